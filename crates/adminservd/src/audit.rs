@@ -0,0 +1,353 @@
+//! Audit log of admin API actions
+//!
+//! Every request that changes state is recorded with the admin user, the
+//! action, its target, the answered status and a before/after view: the
+//! target's state looked up beforehand where an RPC for it exists, and the
+//! request body (or query) with secrets redacted. Requests denied for lack
+//! of a role are recorded too. Entries are sent to domainservd as
+//! [`AuditEventMessage`]s and stored in its append-only `audit_log`
+//! collection.
+
+use std::collections::HashMap;
+
+use axum::body::Body;
+use axum::extract::{FromRequestParts, MatchedPath, Query, RawPathParams, Request, State};
+use axum::http::Method;
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::Utc;
+use oxifed::messaging::AuditEventMessage;
+use serde_json::{Map, Value};
+
+use crate::AppState;
+use crate::auth::{AuthenticatedUser, Role};
+use crate::error::ApiError;
+use crate::messaging;
+
+/// Largest request body recorded, the default limit of axum's `Json`
+const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Body fields naming the target of requests without a path parameter
+const TARGET_FIELDS: &[&str] = &["domain", "subject", "author", "actor", "id"];
+
+/// Parts of field names whose values are never recorded
+const SECRET_FIELDS: &[&str] = &["password", "private_key", "secret", "token"];
+
+/// Action name of a route, such as `domain.update`
+fn action_name(method: &Method, route: &str) -> String {
+    let name = match (method.as_str(), route) {
+        ("POST", "/api/v1/domains") => "domain.create",
+        ("POST", "/api/v1/domains/batch") => "domain.create.batch",
+        ("PUT", "/api/v1/domains/{name}") => "domain.update",
+        ("DELETE", "/api/v1/domains/{name}") => "domain.delete",
+        ("POST", "/api/v1/domains/{name}/relays") => "domain.relay.add",
+        ("DELETE", "/api/v1/domains/{name}/relays") => "domain.relay.remove",
+        ("POST", "/api/v1/domains/{name}/webhooks") => "domain.webhook.add",
+        ("DELETE", "/api/v1/domains/{name}/webhooks/{id}") => "domain.webhook.remove",
+        ("POST", "/api/v1/domains/{name}/invites") => "domain.invite.create",
+        ("POST", "/api/v1/users") => "user.create",
+        ("PUT", "/api/v1/users/{username}/password") => "user.password.set",
+        ("POST", "/api/v1/users/{username}/password-reset") => "user.password.reset",
+        ("DELETE", "/api/v1/users/{username}/sessions") => "user.sessions.revoke",
+        ("POST", "/api/v1/registrations/{id}/approve") => "registration.approve",
+        ("POST", "/api/v1/registrations/{id}/reject") => "registration.reject",
+        ("POST", "/api/v1/persons") => "person.create",
+        ("POST", "/api/v1/persons/batch") => "person.create.batch",
+        ("POST", "/api/v1/persons/moderation") => "person.moderate",
+        ("PUT", "/api/v1/persons/{id}") => "person.update",
+        ("DELETE", "/api/v1/persons/{id}") => "person.delete",
+        ("POST", "/api/v1/persons/{id}/aliases") => "person.alias",
+        ("POST", "/api/v1/persons/{id}/export") => "person.export",
+        ("POST", "/api/v1/persons/{id}/import") => "person.import",
+        ("POST", "/api/v1/groups") => "group.create",
+        ("POST", "/api/v1/groups/{id}/bans") => "group.ban",
+        ("POST", "/api/v1/notes") => "note.create",
+        ("DELETE", "/api/v1/notes/scheduled") => "note.scheduled.cancel",
+        ("PUT", "/api/v1/notes/{id}") => "note.update",
+        ("DELETE", "/api/v1/notes/{id}") => "note.delete",
+        ("POST", "/api/v1/activities/follow") => "activity.follow",
+        ("POST", "/api/v1/activities/like") => "activity.like",
+        ("POST", "/api/v1/activities/announce") => "activity.announce",
+        ("POST", "/api/v1/keys/generate") => "key.generate",
+        ("POST", "/api/v1/keys/rotate") => "key.rotate",
+        ("POST", "/api/v1/keys/import") => "key.import",
+        ("POST", "/api/v1/keys/revoke") => "key.revoke",
+        ("POST", "/api/v1/keys/verify") => "key.verify.start",
+        ("POST", "/api/v1/keys/verify/complete") => "key.verify.complete",
+        ("POST", "/api/v1/keys/sign") => "key.sign",
+        ("POST", "/api/v1/reports/{id}/resolve") => "report.resolve",
+        ("POST", "/api/v1/quarantine/{id}/release") => "quarantine.release",
+        ("POST", "/api/v1/quarantine/{id}/discard") => "quarantine.discard",
+        ("POST", "/api/v1/dlq/retry") | ("POST", "/api/v1/dlq/{id}/retry") => "dlq.retry",
+        ("DELETE", "/api/v1/dlq") | ("DELETE", "/api/v1/dlq/{id}") => "dlq.purge",
+        ("PUT", "/api/v1/system/delivery-limits") => "system.delivery_limits.update",
+        ("POST", "/api/v1/system/replay") => "system.replay",
+        _ => return format!("{} {}", method, route),
+    };
+    name.to_string()
+}
+
+/// Middleware recording requests that change state
+///
+/// Runs inside [`crate::auth::require_role`], which provides the user.
+pub async fn record_mutations(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        return Ok(next.run(request).await);
+    }
+    let (mut parts, body) = request.into_parts();
+    let Some(user) = parts.extensions.get::<AuthenticatedUser>().cloned() else {
+        return Ok(next.run(Request::from_parts(parts, body)).await);
+    };
+
+    let bytes = axum::body::to_bytes(body, BODY_LIMIT)
+        .await
+        .map_err(|_| ApiError::BadRequest(format!("Request body exceeds {} bytes", BODY_LIMIT)))?;
+    let after = if bytes.is_empty() {
+        query_params(&parts)
+    } else {
+        serde_json::from_slice(&bytes).ok()
+    }
+    .map(redact);
+
+    let route = route_of(&parts);
+    let target = path_target(&mut parts)
+        .await
+        .or_else(|| after.as_ref().and_then(body_target));
+    let before = snapshot(&state, &route, target.as_deref()).await;
+
+    let mut event = event(&user, &parts, &route, target);
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    event.status = response.status().as_u16();
+    event.changes = changed_fields(before.as_ref(), after.as_ref());
+    event.before = before;
+    event.after = after;
+    publish(&state, &event).await;
+    Ok(response)
+}
+
+/// Record a request denied because the user lacks the `required` role
+pub async fn record_denial(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    parts: &mut Parts,
+    required: Role,
+) {
+    let route = route_of(parts);
+    let target = path_target(parts).await;
+    let mut event = event(user, parts, &route, target);
+    event.status = axum::http::StatusCode::FORBIDDEN.as_u16();
+    event.after = Some(serde_json::json!({ "required_role": required }));
+    publish(state, &event).await;
+}
+
+fn event(
+    user: &AuthenticatedUser,
+    parts: &Parts,
+    route: &str,
+    target: Option<String>,
+) -> AuditEventMessage {
+    AuditEventMessage {
+        actor: user.sub.clone(),
+        role: user.role.map(|role| role.to_string()),
+        action: action_name(&parts.method, route),
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        target,
+        status: 0,
+        before: None,
+        after: None,
+        changes: Vec::new(),
+        timestamp: Utc::now(),
+    }
+}
+
+/// Send an event to domainservd; failures are logged, not answered
+async fn publish(state: &AppState, event: &AuditEventMessage) {
+    tracing::info!(
+        target: "audit",
+        actor = %event.actor,
+        action = %event.action,
+        target = event.target.as_deref().unwrap_or("-"),
+        status = event.status,
+        "Admin action"
+    );
+    if let Err(e) = messaging::publish_message(&state.mq_pool, event).await {
+        tracing::error!("Failed to record audit event {}: {}", event.action, e);
+    }
+}
+
+fn route_of(parts: &Parts) -> String {
+    parts
+        .extensions
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| parts.uri.path().to_string())
+}
+
+/// First path parameter, such as the domain of `/api/v1/domains/{name}`
+async fn path_target(parts: &mut Parts) -> Option<String> {
+    let params = RawPathParams::from_request_parts(parts, &()).await.ok()?;
+    params.iter().next().map(|(_, value)| value.to_string())
+}
+
+/// Target named in a request body, `username@domain` for users
+fn body_target(body: &Value) -> Option<String> {
+    if let (Some(username), Some(domain)) = (body["username"].as_str(), body["domain"].as_str()) {
+        return Some(format!("{}@{}", username, domain));
+    }
+    TARGET_FIELDS
+        .iter()
+        .find_map(|field| body[*field].as_str().map(str::to_string))
+}
+
+fn query_params(parts: &Parts) -> Option<Value> {
+    let Query(params) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri).ok()?;
+    if params.is_empty() {
+        return None;
+    }
+    Some(Value::Object(
+        params
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect(),
+    ))
+}
+
+/// Replace the values of secret fields, at any depth
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| {
+                    let lower = key.to_ascii_lowercase();
+                    if SECRET_FIELDS.iter().any(|secret| lower.contains(secret)) {
+                        (key, Value::String("[redacted]".to_string()))
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+/// State of the target before the request, for routes that can look it up
+async fn snapshot(state: &AppState, route: &str, target: Option<&str>) -> Option<Value> {
+    let target = target?;
+    let pool = &state.mq_pool;
+    let result = match route {
+        "/api/v1/domains/{name}" | "/api/v1/domains/{name}/relays" => {
+            messaging::get_domain(pool, target)
+                .await
+                .map(|domain| serde_json::to_value(domain).ok())
+        }
+        "/api/v1/users/{username}/password"
+        | "/api/v1/users/{username}/password-reset"
+        | "/api/v1/users/{username}/sessions" => messaging::get_user(pool, target)
+            .await
+            .map(|user| serde_json::to_value(user).ok()),
+        "/api/v1/reports/{id}/resolve" => messaging::get_report(pool, target)
+            .await
+            .map(|report| serde_json::to_value(report).ok()),
+        "/api/v1/quarantine/{id}/release" | "/api/v1/quarantine/{id}/discard" => {
+            messaging::get_quarantined(pool, target)
+                .await
+                .map(|item| serde_json::to_value(item).ok())
+        }
+        _ => return None,
+    };
+    match result {
+        Ok(value) => value.filter(|value| !value.is_null()).map(redact),
+        Err(e) => {
+            tracing::warn!("Failed to look up {} before auditing it: {}", target, e);
+            None
+        }
+    }
+}
+
+/// Top-level fields of `after` that `before` lacks or holds another value in
+fn changed_fields(before: Option<&Value>, after: Option<&Value>) -> Vec<String> {
+    let Some(Value::Object(after)) = after else {
+        return Vec::new();
+    };
+    let before = before.and_then(Value::as_object);
+    after
+        .iter()
+        .filter(|(key, value)| before.and_then(|before| before.get(*key)) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact() {
+        let body = json!({
+            "username": "alice",
+            "password": "hunter2",
+            "NewPassword": "hunter3",
+            "keys": [{ "id": "k1", "private_key_pem": "-----BEGIN" }],
+            "oauth": { "client_secret": "s", "access_token": "t", "scopes": ["read"] },
+        });
+        assert_eq!(
+            redact(body),
+            json!({
+                "username": "alice",
+                "password": "[redacted]",
+                "NewPassword": "[redacted]",
+                "keys": [{ "id": "k1", "private_key_pem": "[redacted]" }],
+                "oauth": { "client_secret": "[redacted]", "access_token": "[redacted]", "scopes": ["read"] },
+            })
+        );
+        assert_eq!(redact(json!("password")), json!("password"));
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let before = json!({ "name": "a", "open": true });
+        let after = json!({ "name": "b", "open": true, "icon": "x" });
+        let mut changed = changed_fields(Some(&before), Some(&after));
+        changed.sort();
+        assert_eq!(changed, vec!["icon", "name"]);
+        assert_eq!(changed_fields(None, Some(&json!({ "a": 1 }))), vec!["a"]);
+        assert!(changed_fields(Some(&before), None).is_empty());
+    }
+
+    #[test]
+    fn test_body_target() {
+        assert_eq!(
+            body_target(&json!({ "username": "alice", "domain": "example.com" })),
+            Some("alice@example.com".to_string())
+        );
+        assert_eq!(
+            body_target(&json!({ "domain": "example.com" })),
+            Some("example.com".to_string())
+        );
+        assert_eq!(body_target(&json!({ "name": "x" })), None);
+    }
+
+    #[test]
+    fn test_action_name() {
+        assert_eq!(
+            action_name(&Method::PUT, "/api/v1/domains/{name}"),
+            "domain.update"
+        );
+        assert_eq!(
+            action_name(&Method::POST, "/api/v1/persons/batch"),
+            "person.create.batch"
+        );
+    }
+}
@@ -1,9 +1,14 @@
-use axum::extract::FromRequestParts;
+use axum::extract::{FromRequestParts, Request, State};
 use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
 use jsonwebtoken::jwk::JwkSet;
 use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::time::Instant;
@@ -18,6 +23,85 @@ pub struct OidcConfig {
     pub audience: String,
     pub jwks_uri: String,
     pub userinfo_endpoint: String,
+    /// Claim holding the user's roles, dot-separated for nested claims
+    pub roles_claim: String,
+    /// Role of users whose token carries none
+    pub default_role: Option<Role>,
+}
+
+/// Role of an admin API user
+///
+/// Roles are ordered by power; each one may do everything the ones below
+/// it may:
+///
+/// - `support` reads domains, users and health, and signs users out or
+///   sends them password reset links
+/// - `moderator` handles reports and the spam quarantine, deletes notes
+///   and bans group members
+/// - `admin` manages domains, users, persons, groups, notes, activities
+///   and the dead-letter queue
+/// - `owner` also deletes domains and manages keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Support,
+    Moderator,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Support => "support",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "support" => Ok(Role::Support),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!(
+                "unknown role '{}'; expected owner, admin, moderator or support",
+                s
+            )),
+        }
+    }
+}
+
+/// Most powerful role listed in the claim at `path`
+///
+/// The claim may be a single role or an array of them; other values, like
+/// roles of other applications, are ignored.
+fn role_from_claims(claims: &Map<String, Value>, path: &str) -> Option<Role> {
+    let mut segments = path.split('.');
+    let mut value = claims.get(segments.next()?)?;
+    for segment in segments {
+        value = value.get(segment)?;
+    }
+    match value {
+        Value::String(role) => role.parse().ok(),
+        Value::Array(roles) => roles
+            .iter()
+            .filter_map(|role| role.as_str()?.parse().ok())
+            .max(),
+        _ => None,
+    }
 }
 
 /// Cached JWKS keys
@@ -126,6 +210,9 @@ pub struct Claims {
     pub exp: usize,
     #[serde(default)]
     pub iat: Option<usize>,
+    /// Remaining claims, among them the roles
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
 }
 
 /// Audience can be a single string or array of strings
@@ -138,25 +225,35 @@ pub enum ClaimAudience {
 
 /// Authenticated user extracted from a valid JWT
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct AuthenticatedUser {
     pub sub: String,
+    /// Most powerful role of the user, if any
+    pub role: Option<Role>,
+}
+
+impl AuthenticatedUser {
+    fn new(sub: String, claims: &Map<String, Value>, oidc_config: &OidcConfig) -> Self {
+        let role = role_from_claims(claims, &oidc_config.roles_claim).or(oidc_config.default_role);
+        Self { sub, role }
+    }
 }
 
 /// Userinfo response (subset of fields we need)
 #[derive(Deserialize)]
 struct UserinfoResponse {
     sub: String,
+    #[serde(flatten)]
+    other: Map<String, Value>,
 }
 
 /// Validate an opaque token by calling the OIDC userinfo endpoint.
 async fn validate_opaque_token(
-    userinfo_endpoint: &str,
+    oidc_config: &OidcConfig,
     token: &str,
 ) -> Result<AuthenticatedUser, ApiError> {
     let client = reqwest::Client::new();
     let response = client
-        .get(userinfo_endpoint)
+        .get(&oidc_config.userinfo_endpoint)
         .bearer_auth(token)
         .send()
         .await
@@ -174,7 +271,7 @@ async fn validate_opaque_token(
         .await
         .map_err(|e| ApiError::InvalidToken(format!("Failed to parse userinfo response: {}", e)))?;
 
-    Ok(AuthenticatedUser { sub: info.sub })
+    Ok(AuthenticatedUser::new(info.sub, &info.other, oidc_config))
 }
 
 impl FromRequestParts<AppState> for AuthenticatedUser {
@@ -184,6 +281,11 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
+        // Already validated by require_role
+        if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(user.clone());
+        }
+
         // Extract Authorization header
         let auth_header = parts
             .headers
@@ -201,7 +303,7 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
             Err(_jwt_err) => {
                 // Token is not a valid JWT — try opaque token via userinfo
                 tracing::debug!("JWT validation failed, trying userinfo introspection");
-                validate_opaque_token(&state.oidc_config.userinfo_endpoint, token).await
+                validate_opaque_token(&state.oidc_config, token).await
             }
         }
     }
@@ -259,7 +361,85 @@ async fn validate_jwt(token: &str, state: &AppState) -> Result<AuthenticatedUser
     let token_data = decode::<Claims>(token, &decoding_key, &validation)
         .map_err(|e| ApiError::InvalidToken(format!("Token validation failed: {}", e)))?;
 
-    Ok(AuthenticatedUser {
-        sub: token_data.claims.sub,
-    })
+    Ok(AuthenticatedUser::new(
+        token_data.claims.sub,
+        &token_data.claims.other,
+        &state.oidc_config,
+    ))
+}
+
+/// Middleware rejecting users without at least the given role
+///
+/// Denials are logged to the `audit` target with the user, their role and
+/// the request.
+pub async fn require_role(
+    State((state, required)): State<(AppState, Role)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let (mut parts, body) = request.into_parts();
+    let user = AuthenticatedUser::from_request_parts(&mut parts, &state).await?;
+    if user.role.is_none_or(|role| role < required) {
+        tracing::warn!(
+            target: "audit",
+            sub = %user.sub,
+            role = user.role.map(|role| role.as_str()).unwrap_or("none"),
+            required = %required,
+            method = %parts.method,
+            path = %parts.uri.path(),
+            "Permission denied"
+        );
+        crate::audit::record_denial(&state, &user, &mut parts, required).await;
+        return Err(ApiError::Forbidden(format!(
+            "The {} role is required",
+            required
+        )));
+    }
+    parts.extensions.insert(user);
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_role_from_claims() {
+        let claims = claims(json!({
+            "role": "Admin",
+            "roles": ["support", "billing", "owner", "moderator"],
+            "realm_access": { "roles": ["moderator"] },
+            "groups": 3,
+        }));
+        assert_eq!(role_from_claims(&claims, "role"), Some(Role::Admin));
+        assert_eq!(role_from_claims(&claims, "roles"), Some(Role::Owner));
+        assert_eq!(
+            role_from_claims(&claims, "realm_access.roles"),
+            Some(Role::Moderator)
+        );
+        assert_eq!(role_from_claims(&claims, "groups"), None);
+        assert_eq!(role_from_claims(&claims, "missing"), None);
+        assert_eq!(role_from_claims(&claims, "realm_access.missing"), None);
+    }
+
+    #[test]
+    fn test_role_from_claims_ignores_unknown_roles() {
+        let claims = claims(json!({ "role": "superuser", "roles": ["billing"] }));
+        assert_eq!(role_from_claims(&claims, "role"), None);
+        assert_eq!(role_from_claims(&claims, "roles"), None);
+    }
+
+    #[test]
+    fn test_role_order() {
+        assert!(Role::Owner > Role::Admin);
+        assert!(Role::Admin > Role::Moderator);
+        assert!(Role::Moderator > Role::Support);
+        assert_eq!("OWNER".parse::<Role>(), Ok(Role::Owner));
+        assert!("root".parse::<Role>().is_err());
+    }
 }
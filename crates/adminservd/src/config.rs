@@ -0,0 +1,89 @@
+//! adminservd configuration
+//!
+//! Loaded with [`oxifed::config`] from the files passed with `--config` and
+//! the environment variables listed in the README.
+
+use std::net::SocketAddr;
+
+use oxifed::config::{AmqpConfig, Config, ConfigError, Env};
+use serde::Deserialize;
+
+use crate::auth::Role;
+
+/// adminservd configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Address the HTTP server listens on
+    pub bind_address: String,
+    pub amqp: AmqpConfig,
+    pub oidc: OidcSettings,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:8081".to_string(),
+            amqp: AmqpConfig::default(),
+            oidc: OidcSettings::default(),
+        }
+    }
+}
+
+/// OIDC provider that issues admin API tokens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcSettings {
+    /// Issuer URL; required
+    pub issuer_url: String,
+    /// Audience expected in access tokens
+    pub audience: String,
+    /// Claim listing the admin roles of a user, dot-separated for nested
+    /// claims such as Keycloak's `realm_access.roles`
+    pub roles_claim: String,
+    /// Role of users whose token lists none; unset denies them everything
+    pub default_role: Option<Role>,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        Self {
+            issuer_url: String::new(),
+            audience: "oxifed-admin".to_string(),
+            roles_claim: "roles".to_string(),
+            default_role: None,
+        }
+    }
+}
+
+impl Config for AdminConfig {
+    fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("BIND_ADDRESS", &mut self.bind_address)?;
+        self.amqp.apply_env(env)?;
+        env.set("OIDC_ISSUER_URL", &mut self.oidc.issuer_url)?;
+        env.set("OIDC_AUDIENCE", &mut self.oidc.audience)?;
+        env.set("OIDC_ROLES_CLAIM", &mut self.oidc.roles_claim)?;
+        env.set_opt("OIDC_DEFAULT_ROLE", &mut self.oidc.default_role)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.bind_address
+            .parse::<SocketAddr>()
+            .map_err(|e| ConfigError::invalid("bind_address", e.to_string()))?;
+        if self.oidc.issuer_url.is_empty() {
+            return Err(ConfigError::invalid(
+                "oidc.issuer_url",
+                "is required; set it in the config file or via OIDC_ISSUER_URL",
+            ));
+        }
+        reqwest::Url::parse(&self.oidc.issuer_url)
+            .map_err(|e| ConfigError::invalid("oidc.issuer_url", e.to_string()))?;
+        if self.oidc.roles_claim.split('.').any(str::is_empty) {
+            return Err(ConfigError::invalid(
+                "oidc.roles_claim",
+                "must be a claim name or a dot-separated path",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -12,6 +12,9 @@ pub enum ApiError {
     #[error("Invalid token: {0}")]
     InvalidToken(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
@@ -21,6 +24,9 @@ pub enum ApiError {
     #[error("Internal error: {0}")]
     Internal(String),
 
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
     #[error("OIDC error: {0}")]
     OidcError(String),
 }
@@ -30,9 +36,11 @@ impl IntoResponse for ApiError {
         let (status, message) = match &self {
             ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
             ApiError::InvalidToken(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            ApiError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
             ApiError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            ApiError::Timeout(_) => (StatusCode::GATEWAY_TIMEOUT, self.to_string()),
             ApiError::OidcError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
 
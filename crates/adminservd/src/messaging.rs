@@ -3,7 +3,9 @@ use futures::StreamExt;
 use lapin::options::*;
 use lapin::protocol::basic::AMQPProperties;
 use lapin::types::FieldTable;
+use oxifed::health::HealthReport;
 use oxifed::messaging::*;
+use oxifed::pki::{DomainVerificationChallenge, TrustLevel, VerificationMethod};
 use serde::Serialize;
 use thiserror::Error;
 use tokio::time::{Duration, timeout};
@@ -31,72 +33,90 @@ pub enum MessagingError {
 
 impl From<MessagingError> for ApiError {
     fn from(err: MessagingError) -> Self {
-        ApiError::Internal(err.to_string())
+        match err {
+            MessagingError::Timeout => ApiError::Timeout(err.to_string()),
+            _ => ApiError::Internal(err.to_string()),
+        }
     }
 }
 
-/// Publish a message to the internal exchange
-pub async fn publish_message<T: Message + Serialize>(
-    pool: &Pool,
-    message: &T,
-) -> Result<(), MessagingError> {
-    let conn = pool.get().await?;
-    let channel = conn.create_channel().await?;
-
-    let payload = serde_json::to_vec(&message.to_message())?;
+/// Request of an RPC answered on a reply queue
+trait RpcRequest: Message + Serialize {
+    /// Routing key on [`EXCHANGE_RPC_REQUEST`] of the daemon answering it
+    const ROUTING_KEY: &'static str;
 
-    channel
-        .basic_publish(
-            EXCHANGE_INTERNAL_PUBLISH,
-            "",
-            BasicPublishOptions::default(),
-            &payload,
-            AMQPProperties::default(),
-        )
-        .await?;
+    type Response: RpcResponse;
+}
 
-    Ok(())
+/// Response of an RPC, carried in one [`MessageEnum`] variant
+trait RpcResponse: Sized {
+    fn from_message(message: MessageEnum) -> Option<Self>;
 }
 
-/// Initialize AMQP exchanges needed by adminservd
-pub async fn init_exchanges(pool: &Pool) -> Result<(), MessagingError> {
-    let conn = pool.get().await?;
-    let channel = conn.create_channel().await?;
+macro_rules! rpc {
+    ($($request:ident => $response:ident, $routing_key:literal;)*) => {
+        $(
+            impl RpcRequest for $request {
+                const ROUTING_KEY: &'static str = $routing_key;
 
-    // Declare the internal publish exchange
-    channel
-        .exchange_declare(
-            EXCHANGE_INTERNAL_PUBLISH,
-            lapin::ExchangeKind::Fanout,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await?;
+                type Response = $response;
+            }
 
-    // Declare the RPC request exchange
-    channel
-        .exchange_declare(
-            EXCHANGE_RPC_REQUEST,
-            lapin::ExchangeKind::Direct,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await?;
+            impl RpcResponse for $response {
+                fn from_message(message: MessageEnum) -> Option<Self> {
+                    match message {
+                        MessageEnum::$response(response) => Some(response),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
 
-    Ok(())
+rpc! {
+    DomainRpcRequest => DomainRpcResponse, "domain";
+    UserRpcRequest => UserRpcResponse, "user";
+    ActorRpcRequest => ActorRpcResponse, "actor";
+    FollowRpcRequest => FollowRpcResponse, "follow";
+    NoteRpcRequest => NoteRpcResponse, "note";
+    AuditRpcRequest => AuditRpcResponse, "audit";
+    KeyRpcRequest => KeyRpcResponse, "key";
+    ModerationRpcRequest => ModerationRpcResponse, "moderation";
+    SpamFilterRpcRequest => SpamFilterRpcResponse, "spam_filter";
+    DlqRpcRequest => DlqRpcResponse, "dlq";
+    DeliveryLimitsRpcRequest => DeliveryLimitsRpcResponse, "delivery_limits";
+    ReplayRpcRequest => ReplayRpcResponse, "replay";
+    DeliveryStatusRpcRequest => DeliveryStatusRpcResponse, "delivery_status";
+    InstanceRpcRequest => InstanceRpcResponse, "instance";
+    SignRpcRequest => SignRpcResponse, "sign";
 }
 
-/// Send a domain RPC request and wait for a response
-async fn send_domain_rpc(
+impl RpcResponse for CommandResponse {
+    fn from_message(message: MessageEnum) -> Option<Self> {
+        match message {
+            MessageEnum::CommandResponse(response) => Some(response),
+            _ => None,
+        }
+    }
+}
+
+/// Send an RPC request and wait for its response
+async fn rpc_call<Req: RpcRequest>(
     pool: &Pool,
-    request: DomainRpcRequest,
-) -> Result<DomainRpcResponse, MessagingError> {
+    request: &Req,
+) -> Result<Req::Response, MessagingError> {
+    request_reply(pool, EXCHANGE_RPC_REQUEST, Req::ROUTING_KEY, request).await
+}
+
+/// Publish `request` with a reply queue and wait up to 30 seconds for the
+/// response carrying its correlation ID
+async fn request_reply<Req: Message + Serialize, Resp: RpcResponse>(
+    pool: &Pool,
+    exchange: &str,
+    routing_key: &str,
+    request: &Req,
+) -> Result<Resp, MessagingError> {
     let conn = pool.get().await?;
     let channel = conn.create_channel().await?;
 
@@ -115,7 +135,6 @@ async fn send_domain_rpc(
         .name()
         .to_string();
 
-    // Setup consumer for the reply queue
     let mut consumer = channel
         .basic_consume(
             &reply_queue,
@@ -125,8 +144,8 @@ async fn send_domain_rpc(
         )
         .await?;
 
-    let request_data = serde_json::to_vec(&request.to_message())?;
-    let correlation_id = request.request_id.clone();
+    let payload = serde_json::to_vec(&request.to_message())?;
+    let correlation_id = Uuid::new_v4().to_string();
 
     let properties = AMQPProperties::default()
         .with_reply_to(reply_queue.into())
@@ -134,10 +153,10 @@ async fn send_domain_rpc(
 
     channel
         .basic_publish(
-            EXCHANGE_RPC_REQUEST,
-            "domain",
+            exchange,
+            routing_key,
             BasicPublishOptions::default(),
-            &request_data,
+            &payload,
             properties,
         )
         .await?;
@@ -146,23 +165,17 @@ async fn send_domain_rpc(
 
     match timeout(response_timeout, async {
         while let Some(delivery) = consumer.next().await {
-            match delivery {
-                Ok(delivery) => {
-                    if let Some(corr_id) = delivery.properties.correlation_id()
-                        && corr_id.as_str() == correlation_id
-                    {
-                        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                            tracing::warn!("Failed to ack RPC response: {}", e);
-                        }
-
-                        let message: MessageEnum = serde_json::from_slice(&delivery.data)?;
-                        if let MessageEnum::DomainRpcResponse(response) = message {
-                            return Ok(response);
-                        }
-                    }
+            let delivery = delivery?;
+            if let Some(corr_id) = delivery.properties.correlation_id()
+                && corr_id.as_str() == correlation_id
+            {
+                if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                    tracing::warn!("Failed to ack RPC response: {}", e);
                 }
-                Err(e) => {
-                    return Err(MessagingError::Amqp(e));
+
+                let message: MessageEnum = serde_json::from_slice(&delivery.data)?;
+                if let Some(response) = Resp::from_message(message) {
+                    return Ok(response);
                 }
             }
         }
@@ -175,92 +188,154 @@ async fn send_domain_rpc(
     }
 }
 
-/// Send a user RPC request and wait for a response
-async fn send_user_rpc(
+/// Publish a message to the internal exchange
+pub async fn publish_message<T: Message + Serialize>(
     pool: &Pool,
-    request: UserRpcRequest,
-) -> Result<UserRpcResponse, MessagingError> {
+    message: &T,
+) -> Result<(), MessagingError> {
+    publish_to_exchange(pool, EXCHANGE_INTERNAL_PUBLISH, message).await
+}
+
+/// Publish a message to an exchange without waiting for it to be processed
+pub async fn publish_to_exchange<T: Message + Serialize>(
+    pool: &Pool,
+    exchange: &str,
+    message: &T,
+) -> Result<(), MessagingError> {
     let conn = pool.get().await?;
     let channel = conn.create_channel().await?;
 
-    let reply_queue = channel
-        .queue_declare(
+    let payload = serde_json::to_vec(&message.to_message())?;
+
+    channel
+        .basic_publish(
+            exchange,
             "",
-            QueueDeclareOptions {
-                exclusive: true,
-                auto_delete: true,
+            BasicPublishOptions::default(),
+            &payload,
+            AMQPProperties::default(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Publish a batch of messages on one channel and wait for the broker's confirms
+///
+/// The publishes are pipelined; the result holds the indices of the
+/// messages the broker refused.
+pub async fn publish_batch<T: Message + Serialize>(
+    pool: &Pool,
+    exchange: &str,
+    messages: &[T],
+) -> Result<Vec<usize>, MessagingError> {
+    let conn = pool.get().await?;
+    let channel = conn.create_channel().await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let mut confirms = Vec::with_capacity(messages.len());
+    for message in messages {
+        let payload = serde_json::to_vec(&message.to_message())?;
+        let confirm = channel
+            .basic_publish(
+                exchange,
+                "",
+                BasicPublishOptions::default(),
+                &payload,
+                AMQPProperties::default().with_delivery_mode(2),
+            )
+            .await?;
+        confirms.push(confirm);
+    }
+
+    let mut rejected = Vec::new();
+    for (index, confirm) in confirms.into_iter().enumerate() {
+        if confirm.await?.is_nack() {
+            rejected.push(index);
+        }
+    }
+    Ok(rejected)
+}
+
+/// Publish a command and wait for the [`CommandResponse`] of its consumer
+///
+/// Commands go to [`EXCHANGE_INTERNAL_PUBLISH`], or to [`EXCHANGE_PKI`] for
+/// key operations; their consumers answer on the reply queue.
+pub async fn send_command<T: Message + Serialize>(
+    pool: &Pool,
+    exchange: &str,
+    message: &T,
+) -> Result<CommandResponse, MessagingError> {
+    request_reply(pool, exchange, "", message).await
+}
+
+/// Initialize AMQP exchanges needed by adminservd
+pub async fn init_exchanges(pool: &Pool) -> Result<(), MessagingError> {
+    let conn = pool.get().await?;
+    let channel = conn.create_channel().await?;
+
+    // Declare the internal publish exchange
+    channel
+        .exchange_declare(
+            EXCHANGE_INTERNAL_PUBLISH,
+            lapin::ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
                 ..Default::default()
             },
             FieldTable::default(),
         )
-        .await?
-        .name()
-        .to_string();
+        .await?;
 
-    let mut consumer = channel
-        .basic_consume(
-            &reply_queue,
-            "",
-            BasicConsumeOptions::default(),
+    // Declare the PKI exchange for key operations
+    channel
+        .exchange_declare(
+            EXCHANGE_PKI,
+            lapin::ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
             FieldTable::default(),
         )
         .await?;
 
-    let request_data = serde_json::to_vec(&request.to_message())?;
-    let correlation_id = request.request_id.clone();
-
-    let properties = AMQPProperties::default()
-        .with_reply_to(reply_queue.into())
-        .with_correlation_id(correlation_id.clone().into());
-
+    // Declare the RPC request exchange
     channel
-        .basic_publish(
+        .exchange_declare(
             EXCHANGE_RPC_REQUEST,
-            "user",
-            BasicPublishOptions::default(),
-            &request_data,
-            properties,
+            lapin::ExchangeKind::Direct,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
         )
         .await?;
 
-    let response_timeout = Duration::from_secs(30);
+    // Declare the health exchange every daemon answers on
+    channel
+        .exchange_declare(
+            EXCHANGE_HEALTH,
+            lapin::ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
 
-    match timeout(response_timeout, async {
-        while let Some(delivery) = consumer.next().await {
-            match delivery {
-                Ok(delivery) => {
-                    if let Some(corr_id) = delivery.properties.correlation_id()
-                        && corr_id.as_str() == correlation_id
-                    {
-                        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                            tracing::warn!("Failed to ack user RPC response: {}", e);
-                        }
-
-                        let message: MessageEnum = serde_json::from_slice(&delivery.data)?;
-                        if let MessageEnum::UserRpcResponse(response) = message {
-                            return Ok(response);
-                        }
-                    }
-                }
-                Err(e) => {
-                    return Err(MessagingError::Amqp(e));
-                }
-            }
-        }
-        Err(MessagingError::Timeout)
-    })
-    .await
-    {
-        Ok(result) => result,
-        Err(_) => Err(MessagingError::Timeout),
-    }
+    Ok(())
 }
 
 /// List all domains via RPC
 pub async fn list_domains(pool: &Pool) -> Result<Vec<DomainInfo>, MessagingError> {
     let request_id = Uuid::new_v4().to_string();
     let request = DomainRpcRequest::list_domains(request_id);
-    let response = send_domain_rpc(pool, request).await?;
+    let response = rpc_call(pool, &request).await?;
 
     match response.result {
         DomainRpcResult::DomainList { domains } => Ok(domains),
@@ -273,7 +348,7 @@ pub async fn list_domains(pool: &Pool) -> Result<Vec<DomainInfo>, MessagingError
 pub async fn get_domain(pool: &Pool, domain: &str) -> Result<Option<DomainInfo>, MessagingError> {
     let request_id = Uuid::new_v4().to_string();
     let request = DomainRpcRequest::get_domain(request_id, domain.to_string());
-    let response = send_domain_rpc(pool, request).await?;
+    let response = rpc_call(pool, &request).await?;
 
     match response.result {
         DomainRpcResult::DomainDetails { domain } => Ok(*domain),
@@ -282,14 +357,49 @@ pub async fn get_domain(pool: &Pool, domain: &str) -> Result<Option<DomainInfo>,
     }
 }
 
-/// List all users via RPC
-pub async fn list_users(pool: &Pool) -> Result<Vec<UserInfo>, MessagingError> {
+/// List the webhooks of a domain via RPC
+pub async fn list_webhooks(pool: &Pool, domain: &str) -> Result<Vec<WebhookInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = DomainRpcRequest::list_webhooks(request_id, domain.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DomainRpcResult::WebhookList { webhooks } => Ok(webhooks),
+        DomainRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List a page of local users, optionally of one domain, via RPC
+pub async fn list_users(
+    pool: &Pool,
+    domain: Option<String>,
+    page: PageRequest,
+) -> Result<Page<UserInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = UserRpcRequest::list_users(request_id, domain, page);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        UserRpcResult::UserList { page } => Ok(page),
+        UserRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List a page of registration applications via RPC
+pub async fn list_applications(
+    pool: &Pool,
+    domain: Option<String>,
+    status: Option<String>,
+    page: PageRequest,
+) -> Result<Page<ApplicationInfo>, MessagingError> {
     let request_id = Uuid::new_v4().to_string();
-    let request = UserRpcRequest::list_users(request_id);
-    let response = send_user_rpc(pool, request).await?;
+    let request = UserRpcRequest::list_applications(request_id, domain, status, page);
+    let response = rpc_call(pool, &request).await?;
 
     match response.result {
-        UserRpcResult::UserList { users } => Ok(users),
+        UserRpcResult::ApplicationList { page } => Ok(page),
         UserRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
         _ => Err(MessagingError::RpcError("Unexpected response type".into())),
     }
@@ -299,7 +409,7 @@ pub async fn list_users(pool: &Pool) -> Result<Vec<UserInfo>, MessagingError> {
 pub async fn get_user(pool: &Pool, username: &str) -> Result<Option<UserInfo>, MessagingError> {
     let request_id = Uuid::new_v4().to_string();
     let request = UserRpcRequest::get_user(request_id, username.to_string());
-    let response = send_user_rpc(pool, request).await?;
+    let response = rpc_call(pool, &request).await?;
 
     match response.result {
         UserRpcResult::UserDetails { user } => Ok(*user),
@@ -308,11 +418,554 @@ pub async fn get_user(pool: &Pool, username: &str) -> Result<Option<UserInfo>, M
     }
 }
 
-/// Send a follow RPC request and wait for a response
-async fn send_follow_rpc(
+/// List a page of local and remote actors via RPC
+pub async fn list_actors(
+    pool: &Pool,
+    domain: Option<String>,
+    local: Option<bool>,
+    status: Option<String>,
+    page: PageRequest,
+) -> Result<Page<ActorInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = ActorRpcRequest::list_actors(request_id, domain, local, status, page);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ActorRpcResult::ActorList { page } => Ok(page),
+        ActorRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get an actor by its ID or user@domain via RPC
+pub async fn get_actor(pool: &Pool, id: &str) -> Result<Option<ActorInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = ActorRpcRequest::get_actor(request_id, id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ActorRpcResult::ActorDetails { actor } => Ok(*actor),
+        ActorRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List follows for an actor (who they follow) via RPC
+pub async fn list_following(pool: &Pool, actor: &str) -> Result<Vec<FollowInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = FollowRpcRequest::list_following(request_id, actor.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        FollowRpcResult::FollowList { follows } => Ok(follows),
+        FollowRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List followers of an actor via RPC
+pub async fn list_followers(pool: &Pool, actor: &str) -> Result<Vec<FollowInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = FollowRpcRequest::list_followers(request_id, actor.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        FollowRpcResult::FollowList { follows } => Ok(follows),
+        FollowRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get a page of an actor's followers or follows via RPC
+pub async fn follow_page(
+    pool: &Pool,
+    actor: String,
+    direction: FollowDirection,
+    status: Option<String>,
+    before: Option<String>,
+    limit: u32,
+) -> Result<FollowPage, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request =
+        FollowRpcRequest::follow_page(request_id, actor, direction, status, before, limit);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        FollowRpcResult::FollowPage { page } => Ok(page),
+        FollowRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List scheduled notes, optionally of one actor, via RPC
+pub async fn list_scheduled_notes(
+    pool: &Pool,
+    actor: Option<String>,
+) -> Result<Vec<ScheduledNoteInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = NoteRpcRequest::list_scheduled(request_id, actor);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        NoteRpcResult::ScheduledList { notes } => Ok(notes),
+        NoteRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List a page of published local posts via RPC
+pub async fn list_notes(
+    pool: &Pool,
+    actor: Option<String>,
+    page: PageRequest,
+) -> Result<Page<NoteInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = NoteRpcRequest::list_notes(request_id, actor, page);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        NoteRpcResult::NoteList { page } => Ok(page),
+        NoteRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get a stored post via RPC
+pub async fn get_note(pool: &Pool, object_id: &str) -> Result<Option<NoteInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = NoteRpcRequest::get_note(request_id, object_id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        NoteRpcResult::NoteDetails { note } => Ok(*note),
+        NoteRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Cancel a scheduled note via RPC, returning false if it is not scheduled
+pub async fn cancel_scheduled_note(pool: &Pool, object_id: &str) -> Result<bool, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = NoteRpcRequest::cancel_scheduled(request_id, object_id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        NoteRpcResult::Cancelled { cancelled } => Ok(cancelled),
+        NoteRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List audit log entries newest first via RPC
+pub async fn list_audit_entries(
+    pool: &Pool,
+    actor: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    before: Option<String>,
+    limit: u32,
+) -> Result<Vec<AuditEntryInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = AuditRpcRequest::list_entries(request_id, actor, action, target, before, limit);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        AuditRpcResult::EntryList { entries } => Ok(entries),
+        AuditRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// Build and verify the trust chain of a key via RPC
+pub async fn get_trust_chain(
+    pool: &Pool,
+    key_id: &str,
+) -> Result<Option<TrustChainReport>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = KeyRpcRequest::trust_chain(request_id, key_id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        KeyRpcResult::TrustChain { report } => Ok(*report),
+        KeyRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List a page of stored keys, optionally of one actor or trust level, via RPC
+pub async fn list_keys(
+    pool: &Pool,
+    actor: Option<String>,
+    trust_level: Option<TrustLevel>,
+    page: PageRequest,
+) -> Result<Page<KeyInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = KeyRpcRequest::list_keys(request_id, actor, trust_level, page);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        KeyRpcResult::KeyList { page } => Ok(page),
+        KeyRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Sign base64 encoded `data` with a stored key via RPC
+///
+/// Returns the base64 encoded signature.
+pub async fn sign_data(
+    pool: &Pool,
+    key_id: &str,
+    algorithm: &str,
+    data: &str,
+) -> Result<String, MessagingError> {
+    let request = SignRpcRequest::new(
+        Uuid::new_v4().to_string(),
+        key_id.to_string(),
+        algorithm.to_string(),
+        data.to_string(),
+    );
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        SignRpcResult::Signature { signature } => Ok(signature),
+        SignRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// Issue a domain verification challenge for the key of an actor via RPC
+pub async fn start_key_verification(
+    pool: &Pool,
+    actor: &str,
+    domain: &str,
+    method: VerificationMethod,
+) -> Result<DomainVerificationChallenge, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = KeyRpcRequest::start_verification(
+        request_id,
+        actor.to_string(),
+        domain.to_string(),
+        method,
+    );
+    verification_result(rpc_call(pool, &request).await?)
+}
+
+/// Check the published challenge of an actor's key via RPC
+pub async fn complete_key_verification(
+    pool: &Pool,
+    actor: &str,
+    domain: &str,
+) -> Result<DomainVerificationChallenge, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request =
+        KeyRpcRequest::complete_verification(request_id, actor.to_string(), domain.to_string());
+    verification_result(rpc_call(pool, &request).await?)
+}
+
+fn verification_result(
+    response: KeyRpcResponse,
+) -> Result<DomainVerificationChallenge, MessagingError> {
+    match response.result {
+        KeyRpcResult::Verification { challenge } => Ok(*challenge),
+        KeyRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List moderation reports via RPC
+pub async fn list_reports(
+    pool: &Pool,
+    status: Option<String>,
+) -> Result<Vec<ReportInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = ModerationRpcRequest::list_reports(request_id, status);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ModerationRpcResult::ReportList { reports } => Ok(reports),
+        ModerationRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get a single moderation report via RPC
+pub async fn get_report(
+    pool: &Pool,
+    report_id: &str,
+) -> Result<Option<ReportInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = ModerationRpcRequest::get_report(request_id, report_id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ModerationRpcResult::ReportDetails { report } => Ok(*report),
+        ModerationRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Resolve a moderation report with an action via RPC
+pub async fn resolve_report(
+    pool: &Pool,
+    report_id: &str,
+    action: ModerationAction,
+    moderator: &str,
+    note: Option<String>,
+) -> Result<Option<ReportInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = ModerationRpcRequest::resolve_report(
+        request_id,
+        report_id.to_string(),
+        action,
+        moderator.to_string(),
+        note,
+    );
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ModerationRpcResult::ReportDetails { report } => Ok(*report),
+        ModerationRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List quarantined content via RPC
+pub async fn list_quarantine(
+    pool: &Pool,
+    status: Option<String>,
+) -> Result<Vec<QuarantineInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = SpamFilterRpcRequest::list_quarantine(request_id, status);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        SpamFilterRpcResult::QuarantineList { items } => Ok(items),
+        SpamFilterRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get a single quarantine entry via RPC
+pub async fn get_quarantined(
+    pool: &Pool,
+    quarantine_id: &str,
+) -> Result<Option<QuarantineInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = SpamFilterRpcRequest::get_quarantined(request_id, quarantine_id.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        SpamFilterRpcResult::QuarantineDetails { item } => Ok(*item),
+        SpamFilterRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Release or discard a quarantine entry via RPC
+pub async fn review_quarantined(
+    pool: &Pool,
+    quarantine_id: &str,
+    moderator: &str,
+    release: bool,
+) -> Result<Option<QuarantineInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = if release {
+        SpamFilterRpcRequest::release_quarantined(
+            request_id,
+            quarantine_id.to_string(),
+            moderator.to_string(),
+        )
+    } else {
+        SpamFilterRpcRequest::discard_quarantined(
+            request_id,
+            quarantine_id.to_string(),
+            moderator.to_string(),
+        )
+    };
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        SpamFilterRpcResult::QuarantineDetails { item } => Ok(*item),
+        SpamFilterRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// List dead letters via RPC
+pub async fn list_dead_letters(
+    pool: &Pool,
+    queue: Option<String>,
+    status: Option<String>,
+) -> Result<Vec<DeadLetterInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = DlqRpcRequest::list_dead_letters(request_id, queue, status);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DlqRpcResult::DeadLetterList { items } => Ok(items),
+        DlqRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Re-inject one or all pending dead letters via RPC
+pub async fn retry_dead_letters(
     pool: &Pool,
-    request: FollowRpcRequest,
-) -> Result<FollowRpcResponse, MessagingError> {
+    dead_letter_id: Option<String>,
+) -> Result<u64, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = DlqRpcRequest::retry_dead_letters(request_id, dead_letter_id);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DlqRpcResult::Affected { count } => Ok(count),
+        DlqRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Delete one or all dead letters via RPC
+pub async fn purge_dead_letters(
+    pool: &Pool,
+    dead_letter_id: Option<String>,
+) -> Result<u64, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = DlqRpcRequest::purge_dead_letters(request_id, dead_letter_id);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DlqRpcResult::Affected { count } => Ok(count),
+        DlqRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Read the delivery limits of publisherd via RPC
+pub async fn get_delivery_limits(pool: &Pool) -> Result<DeliveryLimits, MessagingError> {
+    let request = DeliveryLimitsRpcRequest::get_limits(Uuid::new_v4().to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DeliveryLimitsRpcResult::Limits { limits } => Ok(limits),
+        DeliveryLimitsRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// Change the delivery limits of every running publisherd via RPC
+pub async fn set_delivery_limits(
+    pool: &Pool,
+    max_in_flight: Option<usize>,
+    max_per_host: Option<usize>,
+) -> Result<DeliveryLimits, MessagingError> {
+    let request = DeliveryLimitsRpcRequest::set_limits(
+        Uuid::new_v4().to_string(),
+        max_in_flight,
+        max_per_host,
+    );
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DeliveryLimitsRpcResult::Limits { limits } => Ok(limits),
+        DeliveryLimitsRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// Read the delivery state of an activity via RPC
+///
+/// Returns `None` if no delivery of the activity is recorded.
+pub async fn get_delivery_status(
+    pool: &Pool,
+    activity_id: &str,
+) -> Result<Option<DeliveryStatus>, MessagingError> {
+    let request = DeliveryStatusRpcRequest {
+        request_id: Uuid::new_v4().to_string(),
+        activity_id: activity_id.to_string(),
+    };
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        DeliveryStatusRpcResult::Status { status } => Ok(Some(status)),
+        DeliveryStatusRpcResult::NotFound => Ok(None),
+        DeliveryStatusRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// List a page of known remote instances via RPC
+pub async fn list_instances(
+    pool: &Pool,
+    failing: bool,
+    page: PageRequest,
+) -> Result<Page<InstanceInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = InstanceRpcRequest::list_instances(request_id, failing, page);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        InstanceRpcResult::InstanceList { page } => Ok(page),
+        InstanceRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Get a remote instance by domain via RPC
+pub async fn get_instance(
+    pool: &Pool,
+    domain: &str,
+) -> Result<Option<InstanceInfo>, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = InstanceRpcRequest::get_instance(request_id, domain.to_string());
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        InstanceRpcResult::InstanceDetails { instance } => Ok(*instance),
+        InstanceRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Count the known remote instances by software via RPC
+pub async fn federation_stats(pool: &Pool) -> Result<FederationStats, MessagingError> {
+    let request_id = Uuid::new_v4().to_string();
+    let request = InstanceRpcRequest::get_stats(request_id);
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        InstanceRpcResult::Stats { stats } => Ok(stats),
+        InstanceRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+        _ => Err(MessagingError::RpcError("Unexpected response type".into())),
+    }
+}
+
+/// Replay stored activities through the incoming pipeline via RPC
+pub async fn replay_activities(
+    pool: &Pool,
+    filter: ReplayFilter,
+    limit: u32,
+    dry_run: bool,
+    allow_delivery: bool,
+) -> Result<ReplaySummary, MessagingError> {
+    let request = ReplayRpcRequest {
+        request_id: Uuid::new_v4().to_string(),
+        filter,
+        limit,
+        dry_run,
+        allow_delivery,
+    };
+    let response = rpc_call(pool, &request).await?;
+
+    match response.result {
+        ReplayRpcResult::Replayed { summary } => Ok(summary),
+        ReplayRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
+    }
+}
+
+/// Broadcast a health request and collect the reports that arrive in time
+///
+/// Every running daemon answers, so responses are gathered until `window`
+/// has passed rather than returning on the first one.
+pub async fn collect_health(
+    pool: &Pool,
+    window: Duration,
+) -> Result<Vec<HealthReport>, MessagingError> {
     let conn = pool.get().await?;
     let channel = conn.create_channel().await?;
 
@@ -334,81 +987,44 @@ async fn send_follow_rpc(
         .basic_consume(
             &reply_queue,
             "",
-            BasicConsumeOptions::default(),
+            BasicConsumeOptions {
+                no_ack: true,
+                ..Default::default()
+            },
             FieldTable::default(),
         )
         .await?;
 
+    let request = HealthRpcRequest::new(Uuid::new_v4().to_string());
     let request_data = serde_json::to_vec(&request.to_message())?;
-    let correlation_id = request.request_id.clone();
-
-    let properties = AMQPProperties::default()
-        .with_reply_to(reply_queue.into())
-        .with_correlation_id(correlation_id.clone().into());
 
     channel
         .basic_publish(
-            EXCHANGE_RPC_REQUEST,
-            "follow",
+            EXCHANGE_HEALTH,
+            "",
             BasicPublishOptions::default(),
             &request_data,
-            properties,
+            AMQPProperties::default()
+                .with_reply_to(reply_queue.into())
+                .with_correlation_id(request.request_id.clone().into()),
         )
         .await?;
 
-    let response_timeout = Duration::from_secs(30);
+    let deadline = tokio::time::Instant::now() + window;
+    let mut reports = Vec::new();
 
-    match timeout(response_timeout, async {
-        while let Some(delivery) = consumer.next().await {
-            match delivery {
-                Ok(delivery) => {
-                    if let Some(corr_id) = delivery.properties.correlation_id()
-                        && corr_id.as_str() == correlation_id
-                    {
-                        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                            tracing::warn!("Failed to ack follow RPC response: {}", e);
-                        }
-
-                        let message: MessageEnum = serde_json::from_slice(&delivery.data)?;
-                        if let MessageEnum::FollowRpcResponse(response) = message {
-                            return Ok(response);
-                        }
-                    }
-                }
-                Err(e) => {
-                    return Err(MessagingError::Amqp(e));
-                }
+    while let Ok(Some(delivery)) = tokio::time::timeout_at(deadline, consumer.next()).await {
+        let delivery = delivery?;
+        match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::HealthRpcResponse(response))
+                if response.request_id == request.request_id =>
+            {
+                reports.push(response.report);
             }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to parse health response: {}", e),
         }
-        Err(MessagingError::Timeout)
-    })
-    .await
-    {
-        Ok(result) => result,
-        Err(_) => Err(MessagingError::Timeout),
-    }
-}
-
-/// List follows for an actor (who they follow) via RPC
-pub async fn list_following(pool: &Pool, actor: &str) -> Result<Vec<FollowInfo>, MessagingError> {
-    let request_id = Uuid::new_v4().to_string();
-    let request = FollowRpcRequest::list_following(request_id, actor.to_string());
-    let response = send_follow_rpc(pool, request).await?;
-
-    match response.result {
-        FollowRpcResult::FollowList { follows } => Ok(follows),
-        FollowRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
     }
-}
 
-/// List followers of an actor via RPC
-pub async fn list_followers(pool: &Pool, actor: &str) -> Result<Vec<FollowInfo>, MessagingError> {
-    let request_id = Uuid::new_v4().to_string();
-    let request = FollowRpcRequest::list_followers(request_id, actor.to_string());
-    let response = send_follow_rpc(pool, request).await?;
-
-    match response.result {
-        FollowRpcResult::FollowList { follows } => Ok(follows),
-        FollowRpcResult::Error { message } => Err(MessagingError::RpcError(message)),
-    }
+    Ok(reports)
 }
@@ -1,6 +1,8 @@
 use axum::Json;
 use axum::extract::{Query, State};
-use oxifed::messaging::{AnnounceActivityMessage, FollowActivityMessage, LikeActivityMessage};
+use oxifed::messaging::{
+    AnnounceActivityMessage, DeliveryStatus, FollowActivityMessage, LikeActivityMessage,
+};
 use serde::Deserialize;
 use serde_json::{Value, json};
 
@@ -104,3 +106,21 @@ pub async fn list_followers(
         ApiError::Internal(format!("Serialization error: {}", e))
     })?))
 }
+
+#[derive(Deserialize)]
+pub struct DeliveryStatusQuery {
+    /// ActivityPub ID of the activity
+    pub id: String,
+}
+
+/// Delivery state of an activity for each of its inboxes
+pub async fn delivery_status(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<DeliveryStatusQuery>,
+) -> Result<Json<DeliveryStatus>, ApiError> {
+    messaging::get_delivery_status(&state.mq_pool, &query.id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No deliveries recorded for {}", query.id)))
+}
@@ -0,0 +1,52 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+/// Entries returned when no limit is given
+const DEFAULT_LIMIT: u32 = 50;
+
+/// Most entries returned at once
+const MAX_LIMIT: u32 = 200;
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// ID of the last entry of the previous page
+    pub before: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// List audit log entries newest first
+///
+/// `next` is the `before` of the following page, if there may be one.
+pub async fn list_audit_entries(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let entries = messaging::list_audit_entries(
+        &state.mq_pool,
+        query.actor,
+        query.action,
+        query.target,
+        query.before,
+        limit,
+    )
+    .await
+    .map_err(ApiError::from)?;
+    let next = if entries.len() as u32 >= limit {
+        entries.last().map(|entry| entry.id.clone())
+    } else {
+        None
+    };
+    Ok(Json(json!({ "items": entries, "next": next })))
+}
@@ -0,0 +1,43 @@
+use axum::Json;
+use axum::extract::State;
+use oxifed::messaging::DeliveryLimits;
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+/// Limits to change; absent ones are kept
+#[derive(Deserialize)]
+pub struct DeliveryLimitsUpdate {
+    pub max_in_flight: Option<usize>,
+    pub max_per_host: Option<usize>,
+}
+
+pub async fn get_delivery_limits(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<Json<DeliveryLimits>, ApiError> {
+    let limits = messaging::get_delivery_limits(&state.mq_pool).await?;
+    Ok(Json(limits))
+}
+
+/// Change the delivery limits of every running publisherd
+///
+/// The change lasts until the daemons restart with their configured limits.
+pub async fn set_delivery_limits(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(update): Json<DeliveryLimitsUpdate>,
+) -> Result<Json<DeliveryLimits>, ApiError> {
+    if update.max_in_flight == Some(0) || update.max_per_host == Some(0) {
+        return Err(ApiError::BadRequest(
+            "Delivery limits must be positive".to_string(),
+        ));
+    }
+    let limits =
+        messaging::set_delivery_limits(&state.mq_pool, update.max_in_flight, update.max_per_host)
+            .await?;
+    Ok(Json(limits))
+}
@@ -0,0 +1,76 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    pub queue: Option<String>,
+    pub status: Option<String>,
+}
+
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<DeadLetterQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let items = messaging::list_dead_letters(&state.mq_pool, query.queue, query.status)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(items).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+pub async fn retry_dead_letter(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let count = messaging::retry_dead_letters(&state.mq_pool, Some(id))
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(json!({ "retried": count })))
+}
+
+pub async fn retry_all(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<Json<Value>, ApiError> {
+    let count = messaging::retry_dead_letters(&state.mq_pool, None)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(json!({ "retried": count })))
+}
+
+pub async fn purge_dead_letter(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let count = messaging::purge_dead_letters(&state.mq_pool, Some(id.clone()))
+        .await
+        .map_err(ApiError::from)?;
+    if count == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Dead letter '{}' not found",
+            id
+        )));
+    }
+    Ok(Json(json!({ "purged": count })))
+}
+
+pub async fn purge_all(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<Json<Value>, ApiError> {
+    let count = messaging::purge_dead_letters(&state.mq_pool, None)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(json!({ "purged": count })))
+}
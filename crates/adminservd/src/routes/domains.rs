@@ -1,6 +1,12 @@
 use axum::Json;
 use axum::extract::{Path, Query, State};
-use oxifed::messaging::{DomainCreateMessage, DomainDeleteMessage, DomainUpdateMessage};
+use chrono::{Duration, Utc};
+use oxifed::credentials::{random_token, token_hash};
+use oxifed::messaging::{
+    DomainCreateMessage, DomainDeleteMessage, DomainUpdateMessage, EXCHANGE_INTERNAL_PUBLISH,
+    InviteCreateMessage, RelaySubscribeMessage, RelayUnsubscribeMessage, WebhookCreateMessage,
+    WebhookDeleteMessage, WebhookEvent,
+};
 use serde::Deserialize;
 use serde_json::{Value, json};
 
@@ -8,11 +14,37 @@ use crate::AppState;
 use crate::auth::AuthenticatedUser;
 use crate::error::ApiError;
 use crate::messaging;
+use crate::routes::{CommandQuery, run_batch, run_command};
 
 #[derive(Deserialize)]
 pub struct DeleteQuery {
     #[serde(default)]
     pub force: bool,
+    /// Answer once queued instead of waiting, see [`CommandQuery`]
+    #[serde(default, rename = "async")]
+    pub queue_only: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RelayRequest {
+    pub relay: String,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub secret: String,
+    /// Events to send; all events when left out
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    /// Sign-ups the code allows; unlimited when left out
+    pub max_uses: Option<u32>,
+    /// Days the code is accepted for; forever when left out
+    pub expires_in_days: Option<u32>,
 }
 
 pub async fn list_domains(
@@ -30,15 +62,19 @@ pub async fn list_domains(
 pub async fn create_domain(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
     Json(body): Json<DomainCreateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
-    messaging::publish_message(&state.mq_pool, &body)
-        .await
-        .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+/// Queue the creation of many domains, see [`run_batch`]
+pub async fn create_domains(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<Vec<DomainCreateMessage>>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    run_batch(&state, EXCHANGE_INTERNAL_PUBLISH, &body).await
 }
 
 pub async fn get_domain(
@@ -62,11 +98,38 @@ pub async fn update_domain(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(name): Path<String>,
+    Query(query): Query<CommandQuery>,
     Json(mut body): Json<DomainUpdateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
     // Ensure the domain name in the path matches the body
     body.domain = name;
-    messaging::publish_message(&state.mq_pool, &body)
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+pub async fn delete_domain(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = DomainDeleteMessage::new(name, query.force);
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
+
+pub async fn add_relay(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Json(body): Json<RelayRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = RelaySubscribeMessage::new(name, body.relay);
+    messaging::publish_message(&state.mq_pool, &message)
         .await
         .map_err(ApiError::from)?;
     Ok((
@@ -75,13 +138,13 @@ pub async fn update_domain(
     ))
 }
 
-pub async fn delete_domain(
+pub async fn remove_relay(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(name): Path<String>,
-    Query(query): Query<DeleteQuery>,
+    Query(query): Query<RelayRequest>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
-    let message = DomainDeleteMessage::new(name, query.force);
+    let message = RelayUnsubscribeMessage::new(name, query.relay);
     messaging::publish_message(&state.mq_pool, &message)
         .await
         .map_err(ApiError::from)?;
@@ -90,3 +153,77 @@ pub async fn delete_domain(
         Json(json!({"status": "queued"})),
     ))
 }
+
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let webhooks = messaging::list_webhooks(&state.mq_pool, &name)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(webhooks).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+/// Register a webhook, answering with its ID
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<WebhookRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = WebhookCreateMessage::new(name, body.url, body.secret, body.events);
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path((name, id)): Path<(String, String)>,
+    Query(query): Query<CommandQuery>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = WebhookDeleteMessage::new(name, id);
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
+
+/// Create an invite code for sign-ups on a domain in invite mode
+///
+/// Only the hash of the code is stored; it is answered here once.
+pub async fn create_invite(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<InviteRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let code = random_token();
+    let expires_at = body
+        .expires_in_days
+        .map(|days| Utc::now() + Duration::days(i64::from(days)));
+    let message = InviteCreateMessage::new(name, token_hash(&code), body.max_uses, expires_at);
+    let (status, Json(mut outcome)) = run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await?;
+    outcome["code"] = json!(code);
+    outcome["expires_at"] = json!(expires_at.map(|at| at.to_rfc3339()));
+    Ok((status, Json(outcome)))
+}
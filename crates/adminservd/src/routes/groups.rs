@@ -0,0 +1,45 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use oxifed::messaging::{GroupBanMessage, GroupCreateMessage};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+#[derive(Deserialize)]
+pub struct BanRequest {
+    pub actor: String,
+}
+
+pub async fn create_group(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<GroupCreateMessage>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    messaging::publish_message(&state.mq_pool, &body)
+        .await
+        .map_err(ApiError::from)?;
+    Ok((
+        axum::http::StatusCode::ACCEPTED,
+        Json(json!({"status": "queued"})),
+    ))
+}
+
+pub async fn ban_member(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(body): Json<BanRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = GroupBanMessage::new(id, body.actor);
+    messaging::publish_message(&state.mq_pool, &message)
+        .await
+        .map_err(ApiError::from)?;
+    Ok((
+        axum::http::StatusCode::ACCEPTED,
+        Json(json!({"status": "queued"})),
+    ))
+}
@@ -1,6 +1,75 @@
+use axum::Json;
+use axum::extract::State;
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use oxifed::health::{ComponentHealth, HealthReport, HealthStatus, SystemHealth};
+use serde_json::{Value, json};
+use tokio::time::Duration;
 
-pub async fn health_check() -> impl IntoResponse {
-    (StatusCode::OK, "OK")
+use crate::AppState;
+use crate::auth::{self, AuthenticatedUser};
+use crate::error::ApiError;
+use crate::messaging;
+
+/// Service name used in health reports
+const SERVICE: &str = "adminservd";
+
+/// How long to wait for daemons to answer a health request
+const HEALTH_COLLECT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Liveness: the process is up and serving HTTP
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "service": SERVICE, "status": HealthStatus::Healthy }))
+}
+
+/// Readiness: AMQP is reachable and the JWKS cache can validate tokens
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let report = check(&state).await;
+    let code = match report.status {
+        HealthStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, Json(report))
+}
+
+/// Health of adminservd and every daemon answering within the window
+pub async fn system_health(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<Json<SystemHealth>, ApiError> {
+    let mut reports = messaging::collect_health(&state.mq_pool, HEALTH_COLLECT_WINDOW)
+        .await
+        .map_err(ApiError::from)?;
+    reports.push(check(&state).await);
+    Ok(Json(SystemHealth::new(reports)))
+}
+
+async fn check(state: &AppState) -> HealthReport {
+    let amqp = match state.mq_pool.get().await {
+        Ok(conn) if conn.status().connected() => ComponentHealth::healthy("amqp"),
+        Ok(_) => ComponentHealth::unhealthy("amqp", "connection closed"),
+        Err(e) => ComponentHealth::unhealthy("amqp", e.to_string()),
+    };
+
+    HealthReport::new(SERVICE, vec![amqp, check_jwks(state).await])
+}
+
+/// Refresh a stale JWKS cache; old keys keep working if the provider is down
+async fn check_jwks(state: &AppState) -> ComponentHealth {
+    let (empty, stale) = {
+        let cache = state.jwks_cache.read().await;
+        (cache.keys.is_empty(), cache.is_stale())
+    };
+
+    if !empty && !stale {
+        return ComponentHealth::healthy("jwks");
+    }
+
+    match auth::fetch_jwks(&state.jwks_cache).await {
+        Ok(()) if state.jwks_cache.read().await.keys.is_empty() => {
+            ComponentHealth::unhealthy("jwks", "provider returned no usable keys")
+        }
+        Ok(()) => ComponentHealth::healthy("jwks"),
+        Err(e) if empty => ComponentHealth::unhealthy("jwks", e.to_string()),
+        Err(e) => ComponentHealth::degraded("jwks", format!("serving stale keys: {}", e)),
+    }
 }
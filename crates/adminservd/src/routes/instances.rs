@@ -0,0 +1,51 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use oxifed::messaging::{FederationStats, InstanceInfo, Page};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+use crate::routes::page_request;
+
+#[derive(Deserialize)]
+pub struct InstanceListQuery {
+    /// Only instances whose last deliveries failed, longest streak first
+    #[serde(default)]
+    pub failing: bool,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+/// List the remote instances we federate with
+pub async fn list_instances(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<InstanceListQuery>,
+) -> Result<Json<Page<InstanceInfo>>, ApiError> {
+    let page = page_request(query.offset, query.limit);
+    let instances = messaging::list_instances(&state.mq_pool, query.failing, page).await?;
+    Ok(Json(instances))
+}
+
+/// Get a remote instance by domain
+pub async fn get_instance(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(domain): Path<String>,
+) -> Result<Json<InstanceInfo>, ApiError> {
+    messaging::get_instance(&state.mq_pool, &domain)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Instance '{}' not found", domain)))
+}
+
+/// Count the known instances by the software they run
+pub async fn federation_stats(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<Json<FederationStats>, ApiError> {
+    let stats = messaging::federation_stats(&state.mq_pool).await?;
+    Ok(Json(stats))
+}
@@ -1,6 +1,11 @@
 use axum::Json;
-use axum::extract::State;
-use oxifed::messaging::KeyGenerateMessage;
+use axum::extract::{Query, State};
+use oxifed::httpsignature::SignatureAlgorithm;
+use oxifed::messaging::{
+    EXCHANGE_PKI, KeyGenerateMessage, KeyImportMessage, KeyInfo, KeyRevokeMessage,
+    KeyRotateMessage, KeyRotationType, Page,
+};
+use oxifed::pki::{TrustLevel, VerificationMethod};
 use serde::Deserialize;
 use serde_json::{Value, json};
 
@@ -8,6 +13,7 @@ use crate::AppState;
 use crate::auth::AuthenticatedUser;
 use crate::error::ApiError;
 use crate::messaging;
+use crate::routes::{CommandQuery, page_request, run_command};
 
 #[derive(Deserialize)]
 pub struct KeyGenerateRequest {
@@ -19,14 +25,217 @@ pub struct KeyGenerateRequest {
 pub async fn generate_key(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
     Json(body): Json<KeyGenerateRequest>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
     let message = KeyGenerateMessage::new(body.actor, body.algorithm, body.key_size);
-    messaging::publish_message(&state.mq_pool, &message)
+    run_command(&state, EXCHANGE_PKI, &message, query.queue_only).await
+}
+
+#[derive(Deserialize)]
+pub struct KeyRotateRequest {
+    pub actor: String,
+    pub rotation_type: KeyRotationType,
+    pub algorithm: Option<String>,
+    pub key_size: Option<u32>,
+}
+
+pub async fn rotate_key(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<KeyRotateRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = KeyRotateMessage::new(
+        body.actor,
+        body.rotation_type,
+        body.algorithm,
+        body.key_size,
+    );
+    run_command(&state, EXCHANGE_PKI, &message, query.queue_only).await
+}
+
+#[derive(Deserialize)]
+pub struct KeyImportRequest {
+    pub actor: String,
+    pub algorithm: String,
+    pub public_key_pem: String,
+    pub private_key_pem: String,
+}
+
+pub async fn import_key(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<KeyImportRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = KeyImportMessage::new(
+        body.actor,
+        body.algorithm,
+        body.public_key_pem,
+        body.private_key_pem,
+    );
+    run_command(&state, EXCHANGE_PKI, &message, query.queue_only).await
+}
+
+#[derive(Deserialize)]
+pub struct KeyRevokeRequest {
+    pub actor: String,
+    pub key_id: String,
+}
+
+pub async fn revoke_key(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<KeyRevokeRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = KeyRevokeMessage::new(body.actor, body.key_id);
+    run_command(&state, EXCHANGE_PKI, &message, query.queue_only).await
+}
+
+#[derive(Deserialize)]
+pub struct KeyListQuery {
+    pub actor: Option<String>,
+    /// Trust level such as `unverified` or `domain-verified`
+    pub trust_level: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+pub async fn list_keys(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<KeyListQuery>,
+) -> Result<Json<Page<KeyInfo>>, ApiError> {
+    let trust_level = query
+        .trust_level
+        .map(|level| level.parse::<TrustLevel>())
+        .transpose()
+        .map_err(ApiError::BadRequest)?;
+    let page = page_request(query.offset, query.limit);
+    let keys = messaging::list_keys(&state.mq_pool, query.actor, trust_level, page).await?;
+    Ok(Json(keys))
+}
+
+#[derive(Deserialize)]
+pub struct KeySignRequest {
+    pub actor: String,
+    pub key_id: String,
+    /// Base64 encoded data to sign
+    pub data: String,
+}
+
+/// Sign data with the user key of an actor, for signature diagnostics
+///
+/// Only active `user` keys of the named actor sign; domain and master keys
+/// never sign data given through the API.
+pub async fn sign(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<KeySignRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let keys = messaging::list_keys(
+        &state.mq_pool,
+        Some(body.actor.clone()),
+        None,
+        page_request(None, Some(u32::MAX)),
+    )
+    .await?;
+    let key = keys
+        .items
+        .iter()
+        .find(|key| key.key_id == body.key_id)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Key '{}' of '{}' not found",
+                body.key_id, body.actor
+            ))
+        })?;
+    if key.key_type != "user" || key.status != "active" {
+        return Err(ApiError::BadRequest(format!(
+            "Key '{}' is a {} {} key, only active user keys sign",
+            key.key_id, key.status, key.key_type
+        )));
+    }
+
+    let algorithm = if key.algorithm.to_lowercase().starts_with("ed25519") {
+        SignatureAlgorithm::Ed25519
+    } else {
+        SignatureAlgorithm::RsaSha256
+    };
+    let signature =
+        messaging::sign_data(&state.mq_pool, &key.key_id, algorithm.as_str(), &body.data).await?;
+    Ok(Json(json!({
+        "key_id": key.key_id,
+        "algorithm": algorithm.as_str(),
+        "signature": signature,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct TrustChainQuery {
+    pub key_id: String,
+}
+
+pub async fn get_trust_chain(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<TrustChainQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let report = messaging::get_trust_chain(&state.mq_pool, &query.key_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    match report {
+        Some(report) => {
+            Ok(Json(serde_json::to_value(report).map_err(|e| {
+                ApiError::Internal(format!("Serialization error: {}", e))
+            })?))
+        }
+        None => Err(ApiError::NotFound(format!(
+            "Key '{}' not found",
+            query.key_id
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KeyVerifyRequest {
+    pub actor: String,
+    pub domain: String,
+    pub method: VerificationMethod,
+}
+
+pub async fn start_verification(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<KeyVerifyRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let challenge =
+        messaging::start_key_verification(&state.mq_pool, &body.actor, &body.domain, body.method)
+            .await
+            .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(challenge).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+#[derive(Deserialize)]
+pub struct KeyVerifyCompleteRequest {
+    pub actor: String,
+    pub domain: String,
+}
+
+pub async fn complete_verification(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<KeyVerifyCompleteRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let challenge = messaging::complete_key_verification(&state.mq_pool, &body.actor, &body.domain)
         .await
         .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    Ok(Json(serde_json::to_value(challenge).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
 }
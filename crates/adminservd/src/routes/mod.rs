@@ -1,45 +1,420 @@
 pub mod activities;
+pub mod audit;
+pub mod delivery;
+pub mod dlq;
 pub mod domains;
+pub mod groups;
 pub mod health;
+pub mod instances;
 pub mod keys;
 pub mod notes;
 pub mod persons;
+pub mod quarantine;
+pub mod registrations;
+pub mod replay;
+pub mod reports;
 pub mod users;
 
-use axum::Router;
-use axum::routing::{delete, get, post, put};
+use axum::http::StatusCode;
+use axum::routing::{MethodRouter, delete, get, post, put};
+use axum::{Json, Router, middleware};
+use oxifed::messaging::{CommandErrorKind, CommandResult, Message, PageRequest};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
 
 use crate::AppState;
+use crate::auth::Role::{Admin, Moderator, Owner, Support};
+use crate::auth::{Role, require_role};
+use crate::error::ApiError;
+use crate::messaging::{self, MessagingError};
+
+/// Query of commands whose outcome the API waits for
+#[derive(Deserialize)]
+pub struct CommandQuery {
+    /// Answer once the command is queued instead of waiting for it
+    #[serde(default, rename = "async")]
+    pub queue_only: bool,
+}
+
+/// Items of a listing returned when no limit is given
+const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Most items of a listing returned at once
+const MAX_PAGE_LIMIT: u32 = 200;
+
+/// Window of a listing from its `offset` and `limit` query parameters
+pub(crate) fn page_request(offset: Option<u64>, limit: Option<u32>) -> PageRequest {
+    PageRequest {
+        offset: offset.unwrap_or(0),
+        limit: limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+    }
+}
+
+/// Send a command to `exchange` and answer with its outcome
+///
+/// Answers 200 with `{"status": "done", "id": ...}` once the command was
+/// applied, naming the created object or key, or the error it failed
+/// with. With `queue_only` it answers 202 once the command is queued.
+pub(crate) async fn run_command<T: Message + Serialize>(
+    state: &AppState,
+    exchange: &str,
+    message: &T,
+    queue_only: bool,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    if queue_only {
+        messaging::publish_to_exchange(&state.mq_pool, exchange, message).await?;
+        return Ok((StatusCode::ACCEPTED, Json(json!({"status": "queued"}))));
+    }
+
+    let response = match messaging::send_command(&state.mq_pool, exchange, message).await {
+        Ok(response) => response,
+        Err(MessagingError::Timeout) => {
+            return Err(ApiError::Timeout(
+                "No outcome within 30 seconds, the command may still be applied".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    match response.result {
+        CommandResult::Done { id } => {
+            Ok((StatusCode::OK, Json(json!({"status": "done", "id": id}))))
+        }
+        CommandResult::Error { kind, message } => Err(match kind {
+            CommandErrorKind::NotFound => ApiError::NotFound(message),
+            CommandErrorKind::Rejected => ApiError::BadRequest(message),
+            CommandErrorKind::Failed => ApiError::Internal(message),
+        }),
+    }
+}
+
+/// Largest number of commands in one batch
+const MAX_BATCH: usize = 500;
+
+/// Queue a batch of commands to `exchange`, for bulk imports
+///
+/// Answers 202 with `{"queued": n, "rejected": [...]}` once the broker
+/// confirmed the batch, listing the indices of the commands it refused.
+/// The outcome of each command is not waited for.
+pub(crate) async fn run_batch<T: Message + Serialize>(
+    state: &AppState,
+    exchange: &str,
+    messages: &[T],
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    if messages.is_empty() || messages.len() > MAX_BATCH {
+        return Err(ApiError::BadRequest(format!(
+            "A batch holds 1 to {} commands, got {}",
+            MAX_BATCH,
+            messages.len()
+        )));
+    }
+    let rejected = messaging::publish_batch(&state.mq_pool, exchange, messages).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "queued": messages.len() - rejected.len(),
+            "rejected": rejected,
+        })),
+    ))
+}
+
+/// Routes of the admin API with the role each one requires, see [`Role`]
+pub fn api_router(state: AppState) -> Router<AppState> {
+    // Every route below needs at least the given role; changes are audited
+    let allow = |role: Role, route: MethodRouter<AppState>| {
+        route
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                crate::audit::record_mutations,
+            ))
+            .route_layer(middleware::from_fn_with_state(
+                (state.clone(), role),
+                require_role,
+            ))
+    };
 
-pub fn api_router() -> Router<AppState> {
     Router::new()
-        // Health check (no auth required)
-        .route("/health", get(health::health_check))
+        // Health checks (no auth required)
+        .route("/health", get(health::healthz))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        // System health across all daemons
+        .route(
+            "/api/v1/system/health",
+            allow(Support, get(health::system_health)),
+        )
+        // Delivery limits of publisherd
+        .route(
+            "/api/v1/system/delivery-limits",
+            allow(Support, get(delivery::get_delivery_limits)),
+        )
+        .route(
+            "/api/v1/system/delivery-limits",
+            allow(Admin, put(delivery::set_delivery_limits)),
+        )
+        // Replay of stored activities through the incoming pipeline
+        .route(
+            "/api/v1/system/replay",
+            allow(Admin, post(replay::replay_activities)),
+        )
         // Domains
-        .route("/api/v1/domains", get(domains::list_domains))
-        .route("/api/v1/domains", post(domains::create_domain))
-        .route("/api/v1/domains/{name}", get(domains::get_domain))
-        .route("/api/v1/domains/{name}", put(domains::update_domain))
-        .route("/api/v1/domains/{name}", delete(domains::delete_domain))
+        .route(
+            "/api/v1/domains",
+            allow(Support, get(domains::list_domains)),
+        )
+        .route(
+            "/api/v1/domains",
+            allow(Admin, post(domains::create_domain)),
+        )
+        .route(
+            "/api/v1/domains/batch",
+            allow(Admin, post(domains::create_domains)),
+        )
+        .route(
+            "/api/v1/domains/{name}",
+            allow(Support, get(domains::get_domain)),
+        )
+        .route(
+            "/api/v1/domains/{name}",
+            allow(Admin, put(domains::update_domain)),
+        )
+        .route(
+            "/api/v1/domains/{name}",
+            allow(Owner, delete(domains::delete_domain)),
+        )
+        .route(
+            "/api/v1/domains/{name}/relays",
+            allow(Admin, post(domains::add_relay)),
+        )
+        .route(
+            "/api/v1/domains/{name}/relays",
+            allow(Admin, delete(domains::remove_relay)),
+        )
+        .route(
+            "/api/v1/domains/{name}/webhooks",
+            allow(Admin, get(domains::list_webhooks)),
+        )
+        .route(
+            "/api/v1/domains/{name}/webhooks",
+            allow(Admin, post(domains::create_webhook)),
+        )
+        .route(
+            "/api/v1/domains/{name}/webhooks/{id}",
+            allow(Admin, delete(domains::delete_webhook)),
+        )
+        .route(
+            "/api/v1/domains/{name}/invites",
+            allow(Admin, post(domains::create_invite)),
+        )
         // Users
-        .route("/api/v1/users", get(users::list_users))
-        .route("/api/v1/users", post(users::create_user))
-        .route("/api/v1/users/{username}", get(users::get_user))
+        .route("/api/v1/users", allow(Support, get(users::list_users)))
+        .route("/api/v1/users", allow(Admin, post(users::create_user)))
+        .route(
+            "/api/v1/users/{username}",
+            allow(Support, get(users::get_user)),
+        )
+        .route(
+            "/api/v1/users/{username}/password",
+            allow(Admin, put(users::set_password)),
+        )
+        .route(
+            "/api/v1/users/{username}/password-reset",
+            allow(Support, post(users::reset_password)),
+        )
+        .route(
+            "/api/v1/users/{username}/sessions",
+            allow(Support, delete(users::revoke_sessions)),
+        )
+        // Registration applications
+        .route(
+            "/api/v1/registrations",
+            allow(Moderator, get(registrations::list_applications)),
+        )
+        .route(
+            "/api/v1/registrations/{id}/approve",
+            allow(Moderator, post(registrations::approve)),
+        )
+        .route(
+            "/api/v1/registrations/{id}/reject",
+            allow(Moderator, post(registrations::reject)),
+        )
         // Persons
-        .route("/api/v1/persons", post(persons::create_person))
-        .route("/api/v1/persons/{id}", put(persons::update_person))
-        .route("/api/v1/persons/{id}", delete(persons::delete_person))
+        .route(
+            "/api/v1/persons",
+            allow(Support, get(persons::list_persons)),
+        )
+        .route(
+            "/api/v1/persons",
+            allow(Admin, post(persons::create_person)),
+        )
+        .route(
+            "/api/v1/persons/batch",
+            allow(Admin, post(persons::create_persons)),
+        )
+        .route(
+            "/api/v1/persons/followers",
+            allow(Support, get(persons::list_followers)),
+        )
+        .route(
+            "/api/v1/persons/following",
+            allow(Support, get(persons::list_following)),
+        )
+        .route(
+            "/api/v1/persons/moderation",
+            allow(Moderator, post(persons::moderate_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}",
+            allow(Support, get(persons::get_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}",
+            allow(Admin, put(persons::update_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}",
+            allow(Admin, delete(persons::delete_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}/aliases",
+            allow(Admin, post(persons::alias_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}/export",
+            allow(Admin, post(persons::export_person)),
+        )
+        .route(
+            "/api/v1/persons/{id}/import",
+            allow(Admin, post(persons::import_person)),
+        )
+        // Groups
+        .route("/api/v1/groups", allow(Admin, post(groups::create_group)))
+        .route(
+            "/api/v1/groups/{id}/bans",
+            allow(Moderator, post(groups::ban_member)),
+        )
         // Notes
-        .route("/api/v1/notes", post(notes::create_note))
-        .route("/api/v1/notes/{id}", put(notes::update_note))
-        .route("/api/v1/notes/{id}", delete(notes::delete_note))
+        .route("/api/v1/notes", allow(Support, get(notes::list_notes)))
+        .route("/api/v1/notes", allow(Admin, post(notes::create_note)))
+        .route(
+            "/api/v1/notes/scheduled",
+            allow(Support, get(notes::list_scheduled)),
+        )
+        .route(
+            "/api/v1/notes/scheduled",
+            allow(Admin, delete(notes::cancel_scheduled)),
+        )
+        .route("/api/v1/notes/{id}", allow(Support, get(notes::get_note)))
+        .route("/api/v1/notes/{id}", allow(Admin, put(notes::update_note)))
+        .route(
+            "/api/v1/notes/{id}",
+            allow(Moderator, delete(notes::delete_note)),
+        )
         // Activities
-        .route("/api/v1/activities/follow", post(activities::follow))
-        .route("/api/v1/activities/like", post(activities::like))
-        .route("/api/v1/activities/announce", post(activities::announce))
+        .route(
+            "/api/v1/activities/follow",
+            allow(Admin, post(activities::follow)),
+        )
+        .route(
+            "/api/v1/activities/like",
+            allow(Admin, post(activities::like)),
+        )
+        .route(
+            "/api/v1/activities/announce",
+            allow(Admin, post(activities::announce)),
+        )
+        .route(
+            "/api/v1/activities/status",
+            allow(Support, get(activities::delivery_status)),
+        )
         // Follow relationships
-        .route("/api/v1/following", get(activities::list_following))
-        .route("/api/v1/followers", get(activities::list_followers))
+        .route(
+            "/api/v1/following",
+            allow(Support, get(activities::list_following)),
+        )
+        .route(
+            "/api/v1/followers",
+            allow(Support, get(activities::list_followers)),
+        )
         // Keys
-        .route("/api/v1/keys/generate", post(keys::generate_key))
+        .route(
+            "/api/v1/keys/generate",
+            allow(Owner, post(keys::generate_key)),
+        )
+        .route("/api/v1/keys/rotate", allow(Owner, post(keys::rotate_key)))
+        .route("/api/v1/keys/import", allow(Owner, post(keys::import_key)))
+        .route("/api/v1/keys/revoke", allow(Owner, post(keys::revoke_key)))
+        .route(
+            "/api/v1/keys/verify",
+            allow(Owner, post(keys::start_verification)),
+        )
+        .route(
+            "/api/v1/keys/verify/complete",
+            allow(Owner, post(keys::complete_verification)),
+        )
+        .route("/api/v1/keys", allow(Support, get(keys::list_keys)))
+        .route(
+            "/api/v1/keys/trust-chain",
+            allow(Support, get(keys::get_trust_chain)),
+        )
+        .route("/api/v1/keys/sign", allow(Owner, post(keys::sign)))
+        // Moderation reports
+        .route(
+            "/api/v1/reports",
+            allow(Moderator, get(reports::list_reports)),
+        )
+        .route(
+            "/api/v1/reports/{id}",
+            allow(Moderator, get(reports::get_report)),
+        )
+        .route(
+            "/api/v1/reports/{id}/resolve",
+            allow(Moderator, post(reports::resolve_report)),
+        )
+        // Spam filter quarantine
+        .route(
+            "/api/v1/quarantine",
+            allow(Moderator, get(quarantine::list_quarantine)),
+        )
+        .route(
+            "/api/v1/quarantine/{id}",
+            allow(Moderator, get(quarantine::get_quarantined)),
+        )
+        .route(
+            "/api/v1/quarantine/{id}/release",
+            allow(Moderator, post(quarantine::release_quarantined)),
+        )
+        .route(
+            "/api/v1/quarantine/{id}/discard",
+            allow(Moderator, post(quarantine::discard_quarantined)),
+        )
+        // Remote instances
+        .route(
+            "/api/v1/instances",
+            allow(Support, get(instances::list_instances)),
+        )
+        .route(
+            "/api/v1/instances/stats",
+            allow(Support, get(instances::federation_stats)),
+        )
+        .route(
+            "/api/v1/instances/{domain}",
+            allow(Support, get(instances::get_instance)),
+        )
+        // Audit log
+        .route(
+            "/api/v1/audit",
+            allow(Admin, get(audit::list_audit_entries)),
+        )
+        // Dead-letter queue
+        .route("/api/v1/dlq", allow(Support, get(dlq::list_dead_letters)))
+        .route("/api/v1/dlq", allow(Admin, delete(dlq::purge_all)))
+        .route("/api/v1/dlq/retry", allow(Admin, post(dlq::retry_all)))
+        .route(
+            "/api/v1/dlq/{id}",
+            allow(Admin, delete(dlq::purge_dead_letter)),
+        )
+        .route(
+            "/api/v1/dlq/{id}/retry",
+            allow(Admin, post(dlq::retry_dead_letter)),
+        )
 }
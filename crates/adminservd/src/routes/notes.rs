@@ -1,48 +1,62 @@
 use axum::Json;
 use axum::extract::{Path, Query, State};
-use oxifed::messaging::{NoteCreateMessage, NoteDeleteMessage, NoteUpdateMessage};
+use oxifed::messaging::{
+    EXCHANGE_INTERNAL_PUBLISH, NoteCreateMessage, NoteDeleteMessage, NoteInfo, NoteUpdateMessage,
+    Page,
+};
 use serde::Deserialize;
-use serde_json::{Value, json};
+use serde_json::Value;
 
 use crate::AppState;
 use crate::auth::AuthenticatedUser;
 use crate::error::ApiError;
 use crate::messaging;
+use crate::routes::{CommandQuery, page_request, run_command};
 
 #[derive(Deserialize)]
 pub struct DeleteQuery {
     #[serde(default)]
     pub force: bool,
+    /// Answer once queued instead of waiting, see [`CommandQuery`]
+    #[serde(default, rename = "async")]
+    pub queue_only: bool,
+}
+
+#[derive(Deserialize)]
+pub struct NoteListQuery {
+    pub actor: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ScheduledQuery {
+    pub actor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CancelQuery {
+    pub id: String,
 }
 
 pub async fn create_note(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
     Json(body): Json<NoteCreateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
-    messaging::publish_message(&state.mq_pool, &body)
-        .await
-        .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
 }
 
 pub async fn update_note(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(id): Path<String>,
+    Query(query): Query<CommandQuery>,
     Json(mut body): Json<NoteUpdateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
     body.id = id;
-    messaging::publish_message(&state.mq_pool, &body)
-        .await
-        .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
 }
 
 pub async fn delete_note(
@@ -52,11 +66,65 @@ pub async fn delete_note(
     Query(query): Query<DeleteQuery>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
     let message = NoteDeleteMessage::new(id, query.force);
-    messaging::publish_message(&state.mq_pool, &message)
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
+
+/// List a page of published local posts newest first
+pub async fn list_notes(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<NoteListQuery>,
+) -> Result<Json<Page<NoteInfo>>, ApiError> {
+    let page = page_request(query.offset, query.limit);
+    let notes = messaging::list_notes(&state.mq_pool, query.actor, page).await?;
+    Ok(Json(notes))
+}
+
+/// Get a stored post by its object ID
+pub async fn get_note(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<NoteInfo>, ApiError> {
+    messaging::get_note(&state.mq_pool, &id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Note '{}' not found", id)))
+}
+
+pub async fn list_scheduled(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<ScheduledQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let notes = messaging::list_scheduled_notes(&state.mq_pool, query.actor)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(notes).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+pub async fn cancel_scheduled(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<CancelQuery>,
+) -> Result<axum::http::StatusCode, ApiError> {
+    let cancelled = messaging::cancel_scheduled_note(&state.mq_pool, &query.id)
         .await
         .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    if cancelled {
+        Ok(axum::http::StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!(
+            "No scheduled note '{}'",
+            query.id
+        )))
+    }
 }
@@ -1,6 +1,10 @@
 use axum::Json;
 use axum::extract::{Path, Query, State};
-use oxifed::messaging::{ProfileCreateMessage, ProfileDeleteMessage, ProfileUpdateMessage};
+use oxifed::messaging::{
+    ActorInfo, EXCHANGE_INTERNAL_PUBLISH, FollowDirection, FollowPage, Page, ProfileAliasMessage,
+    ProfileCreateMessage, ProfileDeleteMessage, ProfileExportMessage, ProfileImportMessage,
+    ProfileModerateMessage, ProfileUpdateMessage,
+};
 use serde::Deserialize;
 use serde_json::{Value, json};
 
@@ -8,35 +12,155 @@ use crate::AppState;
 use crate::auth::AuthenticatedUser;
 use crate::error::ApiError;
 use crate::messaging;
+use crate::routes::{CommandQuery, page_request, run_batch, run_command};
+
+/// Follows returned when no limit is given
+const DEFAULT_FOLLOW_LIMIT: u32 = 50;
+
+/// Most follows returned at once
+const MAX_FOLLOW_LIMIT: u32 = 200;
 
 #[derive(Deserialize)]
 pub struct DeleteQuery {
     #[serde(default)]
     pub force: bool,
+    /// Answer once queued instead of waiting, see [`CommandQuery`]
+    #[serde(default, rename = "async")]
+    pub queue_only: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    /// File name of the archive in domainservd's archive directory
+    pub archive: String,
 }
 
 pub async fn create_person(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
     Json(body): Json<ProfileCreateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
-    messaging::publish_message(&state.mq_pool, &body)
-        .await
-        .map_err(ApiError::from)?;
-    Ok((
-        axum::http::StatusCode::ACCEPTED,
-        Json(json!({"status": "queued"})),
-    ))
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+/// Queue the creation of many persons, see [`run_batch`]
+pub async fn create_persons(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(body): Json<Vec<ProfileCreateMessage>>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    run_batch(&state, EXCHANGE_INTERNAL_PUBLISH, &body).await
+}
+
+/// Actor statuses a listing can be limited to
+const ACTOR_STATUSES: [&str; 4] = ["active", "suspended", "deleted", "pending"];
+
+#[derive(Deserialize)]
+pub struct PersonListQuery {
+    pub domain: Option<String>,
+    pub local: Option<bool>,
+    /// Account status (active, suspended, deleted or pending)
+    pub status: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+/// List a page of local and remote actors
+pub async fn list_persons(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<PersonListQuery>,
+) -> Result<Json<Page<ActorInfo>>, ApiError> {
+    if let Some(status) = &query.status
+        && !ACTOR_STATUSES.contains(&status.as_str())
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown status '{}', expected one of {}",
+            status,
+            ACTOR_STATUSES.join(", ")
+        )));
+    }
+    let page = page_request(query.offset, query.limit);
+    let persons = messaging::list_actors(
+        &state.mq_pool,
+        query.domain,
+        query.local,
+        query.status,
+        page,
+    )
+    .await?;
+    Ok(Json(persons))
+}
+
+/// Get an actor by its ID or, for local actors, user@domain
+pub async fn get_person(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<ActorInfo>, ApiError> {
+    messaging::get_actor(&state.mq_pool, &id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Person '{}' not found", id)))
 }
 
 pub async fn update_person(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(id): Path<String>,
+    Query(query): Query<CommandQuery>,
     Json(mut body): Json<ProfileUpdateMessage>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
     body.subject = id;
-    messaging::publish_message(&state.mq_pool, &body)
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+/// Add an alias to a person's `alsoKnownAs`, or remove it
+pub async fn alias_person(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<CommandQuery>,
+    Json(mut body): Json<ProfileAliasMessage>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    body.subject = id;
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+pub async fn delete_person(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = ProfileDeleteMessage::new(id, query.force);
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
+
+/// Suspend, silence or limit an actor, or lift its restriction
+pub async fn moderate_person(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<CommandQuery>,
+    Json(body): Json<ProfileModerateMessage>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    run_command(&state, EXCHANGE_INTERNAL_PUBLISH, &body, query.queue_only).await
+}
+
+pub async fn export_person(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let message = ProfileExportMessage::new(id);
+    messaging::publish_message(&state.mq_pool, &message)
         .await
         .map_err(ApiError::from)?;
     Ok((
@@ -45,13 +169,13 @@ pub async fn update_person(
     ))
 }
 
-pub async fn delete_person(
+pub async fn import_person(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(id): Path<String>,
-    Query(query): Query<DeleteQuery>,
+    Json(body): Json<ImportRequest>,
 ) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
-    let message = ProfileDeleteMessage::new(id, query.force);
+    let message = ProfileImportMessage::new(id, body.archive);
     messaging::publish_message(&state.mq_pool, &message)
         .await
         .map_err(ApiError::from)?;
@@ -60,3 +184,52 @@ pub async fn delete_person(
         Json(json!({"status": "queued"})),
     ))
 }
+
+#[derive(Deserialize)]
+pub struct FollowPageQuery {
+    pub actor: String,
+    /// Follow status such as `accepted` or `pending`
+    pub status: Option<String>,
+    /// `next` of the previous page
+    pub before: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// List the followers of an actor newest first, with their counts
+pub async fn list_followers(
+    state: State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<FollowPageQuery>,
+) -> Result<Json<FollowPage>, ApiError> {
+    follow_page(state, FollowDirection::Followers, query).await
+}
+
+/// List the accounts an actor follows newest first, with their counts
+pub async fn list_following(
+    state: State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<FollowPageQuery>,
+) -> Result<Json<FollowPage>, ApiError> {
+    follow_page(state, FollowDirection::Following, query).await
+}
+
+async fn follow_page(
+    State(state): State<AppState>,
+    direction: FollowDirection,
+    query: FollowPageQuery,
+) -> Result<Json<FollowPage>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_FOLLOW_LIMIT)
+        .clamp(1, MAX_FOLLOW_LIMIT);
+    let page = messaging::follow_page(
+        &state.mq_pool,
+        query.actor,
+        direction,
+        query.status,
+        query.before,
+        limit,
+    )
+    .await?;
+    Ok(Json(page))
+}
@@ -0,0 +1,75 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+#[derive(Deserialize)]
+pub struct QuarantineQuery {
+    pub status: Option<String>,
+}
+
+pub async fn list_quarantine(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<QuarantineQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let items = messaging::list_quarantine(&state.mq_pool, query.status)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(items).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+pub async fn get_quarantined(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let item = messaging::get_quarantined(&state.mq_pool, &id)
+        .await
+        .map_err(ApiError::from)?;
+    quarantine_response(&id, item)
+}
+
+pub async fn release_quarantined(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let item = messaging::review_quarantined(&state.mq_pool, &id, &user.sub, true)
+        .await
+        .map_err(ApiError::from)?;
+    quarantine_response(&id, item)
+}
+
+pub async fn discard_quarantined(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let item = messaging::review_quarantined(&state.mq_pool, &id, &user.sub, false)
+        .await
+        .map_err(ApiError::from)?;
+    quarantine_response(&id, item)
+}
+
+fn quarantine_response(
+    id: &str,
+    item: Option<oxifed::messaging::QuarantineInfo>,
+) -> Result<Json<Value>, ApiError> {
+    match item {
+        Some(i) => Ok(Json(serde_json::to_value(i).map_err(|e| {
+            ApiError::Internal(format!("Serialization error: {}", e))
+        })?)),
+        None => Err(ApiError::NotFound(format!(
+            "Quarantine entry '{}' not found",
+            id
+        ))),
+    }
+}
@@ -0,0 +1,81 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use oxifed::messaging::{
+    ApplicationInfo, EXCHANGE_INTERNAL_PUBLISH, Page, RegistrationDecision,
+    RegistrationReviewMessage,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+use crate::routes::{CommandQuery, page_request, run_command};
+
+#[derive(Deserialize)]
+pub struct ApplicationListQuery {
+    pub domain: Option<String>,
+    /// `pending`, `approved` or `rejected`
+    pub status: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ReviewRequest {
+    /// Reason given for the decision
+    pub note: Option<String>,
+}
+
+/// List a page of registration applications, oldest first
+pub async fn list_applications(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<ApplicationListQuery>,
+) -> Result<Json<Page<ApplicationInfo>>, ApiError> {
+    let page = page_request(query.offset, query.limit);
+    let applications =
+        messaging::list_applications(&state.mq_pool, query.domain, query.status, page).await?;
+    Ok(Json(applications))
+}
+
+/// Approve an application, answering with the ID of the new actor
+pub async fn approve(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<CommandQuery>,
+    body: Option<Json<ReviewRequest>>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    review(state, user, id, RegistrationDecision::Approve, body, query).await
+}
+
+pub async fn reject(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Query(query): Query<CommandQuery>,
+    body: Option<Json<ReviewRequest>>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    review(state, user, id, RegistrationDecision::Reject, body, query).await
+}
+
+async fn review(
+    state: AppState,
+    user: AuthenticatedUser,
+    id: String,
+    decision: RegistrationDecision,
+    body: Option<Json<ReviewRequest>>,
+    query: CommandQuery,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let Json(body) = body.unwrap_or_default();
+    let message = RegistrationReviewMessage::new(id, decision, Some(user.sub), body.note);
+    run_command(
+        &state,
+        EXCHANGE_INTERNAL_PUBLISH,
+        &message,
+        query.queue_only,
+    )
+    .await
+}
@@ -0,0 +1,62 @@
+use axum::Json;
+use axum::extract::State;
+use oxifed::messaging::{ReplayFilter, ReplaySummary};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+/// Activities replayed when no limit is given
+const DEFAULT_REPLAY_LIMIT: u32 = 100;
+
+/// Most activities one replay covers, as enforced by domainservd
+const MAX_REPLAY_LIMIT: u32 = 1000;
+
+/// Stored activities to replay
+#[derive(Deserialize)]
+pub struct ReplayRequest {
+    #[serde(flatten)]
+    pub filter: ReplayFilter,
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub allow_delivery: bool,
+}
+
+/// Replay stored remote activities through the incoming pipeline of a domain
+///
+/// A filter besides the domain is required, so a mistyped request cannot
+/// replay the whole history of an instance.
+pub async fn replay_activities(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Json(request): Json<ReplayRequest>,
+) -> Result<Json<ReplaySummary>, ApiError> {
+    if request.filter.domain.is_empty() {
+        return Err(ApiError::BadRequest("A domain is required".to_string()));
+    }
+    if request.filter.is_unbounded() {
+        return Err(ApiError::BadRequest(
+            "Narrow the replay down by storage ID, actor, type or time".to_string(),
+        ));
+    }
+    let limit = request.limit.unwrap_or(DEFAULT_REPLAY_LIMIT);
+    if limit == 0 || limit > MAX_REPLAY_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "The limit must be between 1 and {}",
+            MAX_REPLAY_LIMIT
+        )));
+    }
+    let summary = messaging::replay_activities(
+        &state.mq_pool,
+        request.filter,
+        limit,
+        request.dry_run,
+        request.allow_delivery,
+    )
+    .await?;
+    Ok(Json(summary))
+}
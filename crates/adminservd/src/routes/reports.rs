@@ -0,0 +1,69 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use oxifed::messaging::ModerationAction;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::AppState;
+use crate::auth::AuthenticatedUser;
+use crate::error::ApiError;
+use crate::messaging;
+
+#[derive(Deserialize)]
+pub struct ReportsQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportActionRequest {
+    pub action: ModerationAction,
+    pub note: Option<String>,
+}
+
+pub async fn list_reports(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<ReportsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let reports = messaging::list_reports(&state.mq_pool, query.status)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(serde_json::to_value(reports).map_err(|e| {
+        ApiError::Internal(format!("Serialization error: {}", e))
+    })?))
+}
+
+pub async fn get_report(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let report = messaging::get_report(&state.mq_pool, &id)
+        .await
+        .map_err(ApiError::from)?;
+
+    match report {
+        Some(r) => Ok(Json(serde_json::to_value(r).map_err(|e| {
+            ApiError::Internal(format!("Serialization error: {}", e))
+        })?)),
+        None => Err(ApiError::NotFound(format!("Report '{}' not found", id))),
+    }
+}
+
+pub async fn resolve_report(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(body): Json<ReportActionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let report = messaging::resolve_report(&state.mq_pool, &id, body.action, &user.sub, body.note)
+        .await
+        .map_err(ApiError::from)?;
+
+    match report {
+        Some(r) => Ok(Json(serde_json::to_value(r).map_err(|e| {
+            ApiError::Internal(format!("Serialization error: {}", e))
+        })?)),
+        None => Err(ApiError::NotFound(format!("Report '{}' not found", id))),
+    }
+}
@@ -1,23 +1,39 @@
 use axum::Json;
-use axum::extract::{Path, State};
-use oxifed::messaging::UserCreateMessage;
+use axum::extract::{Path, Query, State};
+use chrono::{Duration, Utc};
+use oxifed::credentials::{
+    MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH, hash_password, is_acceptable_password, random_token,
+    token_hash,
+};
+use oxifed::messaging::{
+    Page, PasswordChange, UserCreateMessage, UserInfo, UserPasswordMessage,
+    UserSessionsRevokeMessage,
+};
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 use crate::AppState;
 use crate::auth::AuthenticatedUser;
 use crate::error::ApiError;
 use crate::messaging;
+use crate::routes::page_request;
 
+#[derive(Deserialize)]
+pub struct UserListQuery {
+    pub domain: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+/// List a page of local users, optionally of one domain
 pub async fn list_users(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
-) -> Result<Json<Value>, ApiError> {
-    let users = messaging::list_users(&state.mq_pool)
-        .await
-        .map_err(ApiError::from)?;
-    Ok(Json(serde_json::to_value(users).map_err(|e| {
-        ApiError::Internal(format!("Serialization error: {}", e))
-    })?))
+    Query(query): Query<UserListQuery>,
+) -> Result<Json<Page<UserInfo>>, ApiError> {
+    let page = page_request(query.offset, query.limit);
+    let users = messaging::list_users(&state.mq_pool, query.domain, page).await?;
+    Ok(Json(users))
 }
 
 pub async fn create_user(
@@ -50,3 +66,104 @@ pub async fn get_user(
         None => Err(ApiError::NotFound(format!("User '{}' not found", username))),
     }
 }
+
+/// Time a password reset link can be used
+const PASSWORD_RESET_LIFETIME: Duration = Duration::hours(24);
+
+#[derive(Deserialize)]
+pub struct PasswordRequest {
+    pub password: String,
+}
+
+/// Split a `username@domain` path parameter
+fn split_username(username: &str) -> Result<(String, String), ApiError> {
+    match username.split_once('@') {
+        Some((user, domain)) if !user.is_empty() && !domain.is_empty() => {
+            Ok((user.to_string(), domain.to_string()))
+        }
+        _ => Err(ApiError::BadRequest(format!(
+            "Expected username@domain, got '{}'",
+            username
+        ))),
+    }
+}
+
+/// Set the password of a local user, revoking their tokens
+pub async fn set_password(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(username): Path<String>,
+    Json(body): Json<PasswordRequest>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let (username, domain) = split_username(&username)?;
+    if !is_acceptable_password(&body.password) {
+        return Err(ApiError::BadRequest(format!(
+            "Passwords must have {} to {} characters",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        )));
+    }
+    let password_hash = tokio::task::spawn_blocking(move || hash_password(&body.password))
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to hash password: {}", e)))?;
+
+    let message = UserPasswordMessage::new(username, domain, PasswordChange::Set { password_hash });
+    messaging::publish_message(&state.mq_pool, &message)
+        .await
+        .map_err(ApiError::from)?;
+    Ok((
+        axum::http::StatusCode::ACCEPTED,
+        Json(json!({"status": "queued"})),
+    ))
+}
+
+/// Create a link for a local user to choose a new password
+///
+/// The link is answered here and emailed to users who gave an address.
+pub async fn reset_password(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(username): Path<String>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let (username, domain) = split_username(&username)?;
+    let token = random_token();
+    let expires_at = Utc::now() + PASSWORD_RESET_LIFETIME;
+    let reset_url = format!("https://{}/auth/password?token={}", domain, token);
+
+    let message = UserPasswordMessage::new(
+        username,
+        domain,
+        PasswordChange::Reset {
+            token_hash: token_hash(&token),
+            expires_at,
+            reset_url: Some(reset_url.clone()),
+        },
+    );
+    messaging::publish_message(&state.mq_pool, &message)
+        .await
+        .map_err(ApiError::from)?;
+    Ok((
+        axum::http::StatusCode::ACCEPTED,
+        Json(json!({
+            "status": "queued",
+            "reset_url": reset_url,
+            "expires_at": expires_at.to_rfc3339()
+        })),
+    ))
+}
+
+/// Sign a local user out of all applications
+pub async fn revoke_sessions(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(username): Path<String>,
+) -> Result<(axum::http::StatusCode, Json<Value>), ApiError> {
+    let (username, domain) = split_username(&username)?;
+    let message = UserSessionsRevokeMessage::new(username, domain);
+    messaging::publish_message(&state.mq_pool, &message)
+        .await
+        .map_err(ApiError::from)?;
+    Ok((
+        axum::http::StatusCode::ACCEPTED,
+        Json(json!({"status": "queued"})),
+    ))
+}
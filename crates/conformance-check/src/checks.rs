@@ -0,0 +1,736 @@
+//! ActivityPub conformance scenarios
+//!
+//! The scenarios follow the server parts of the W3C ActivityPub test suite
+//! that can be checked from outside: dereferencing an actor with both
+//! ActivityStreams media types, the properties an actor must have, the
+//! collections it links to, dereferencing published objects, and how the
+//! inbox and outbox treat requests they must not accept. Every check names
+//! the section of the specification it tests and whether the section says
+//! MUST or SHOULD.
+
+use std::time::Duration;
+
+use oxifed::client::{ACTIVITY_STREAMS_JSON_LD, ACTIVITYPUB_CONTENT_TYPE};
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde::Serialize;
+use serde_json::{Value, json};
+use url::Url;
+
+/// Namespace every ActivityStreams document has in its context
+const ACTIVITYSTREAMS: &str = "https://www.w3.org/ns/activitystreams";
+
+/// Outbox items whose shape is checked
+const MAX_CHECKED_ITEMS: usize = 20;
+
+/// Requirement level of a check, as worded by the specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Must,
+    Should,
+}
+
+/// Result of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The check could not run, e.g. an empty outbox has no objects
+    Skip,
+}
+
+/// Outcome of one scenario
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    /// Section of the ActivityPub specification tested
+    pub section: &'static str,
+    pub level: Level,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(
+        name: &'static str,
+        section: &'static str,
+        level: Level,
+        passed: bool,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            section,
+            level,
+            outcome: if passed { Outcome::Pass } else { Outcome::Fail },
+            detail: detail.into(),
+        }
+    }
+
+    fn skip(
+        name: &'static str,
+        section: &'static str,
+        level: Level,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            section,
+            level,
+            outcome: Outcome::Skip,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Compliance report of one actor
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub actor: String,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Number of failed checks of `level`
+    pub fn failures(&self, level: Level) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.level == level && check.outcome == Outcome::Fail)
+            .count()
+    }
+}
+
+/// A document answered by the server under test
+struct Fetched {
+    status: StatusCode,
+    content_type: String,
+    document: Option<Value>,
+}
+
+/// Runs the scenarios against one actor
+pub struct Suite {
+    http: reqwest::Client,
+    actor: Url,
+}
+
+impl Suite {
+    /// Suite checking the actor at `actor`, waiting `timeout` per request
+    pub fn new(actor: Url, timeout: Duration) -> reqwest::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("oxifed-conformance/", env!("CARGO_PKG_VERSION")))
+            .timeout(timeout)
+            .build()?;
+        Ok(Self { http, actor })
+    }
+
+    /// Run all scenarios
+    ///
+    /// Scenarios needing the actor document are skipped if it cannot be
+    /// fetched.
+    pub async fn run(&self) -> Report {
+        let mut checks = Vec::new();
+
+        let (check, document) = self
+            .dereference(&self.actor, ACTIVITYPUB_CONTENT_TYPE, "actor activity+json")
+            .await;
+        checks.push(check);
+        let (check, _) = self
+            .dereference(&self.actor, ACTIVITY_STREAMS_JSON_LD, "actor ld+json")
+            .await;
+        checks.push(check);
+
+        let Some(actor) = document else {
+            return Report {
+                actor: self.actor.to_string(),
+                checks,
+            };
+        };
+        checks.extend(actor_properties(&self.actor, &actor));
+
+        let outbox = actor["outbox"]
+            .as_str()
+            .and_then(|url| Url::parse(url).ok());
+        let outbox_document = match &outbox {
+            Some(outbox) => {
+                let (check, document) = self
+                    .collection(outbox, "outbox collection", "5.1", true, false)
+                    .await;
+                checks.push(check);
+                document
+            }
+            None => None,
+        };
+        for (property, name, section, ordered) in [
+            ("inbox", "inbox collection", "5.2", true),
+            ("followers", "followers collection", "5.3", false),
+            ("following", "following collection", "5.4", false),
+        ] {
+            if let Some(url) = actor[property]
+                .as_str()
+                .and_then(|url| Url::parse(url).ok())
+            {
+                let (check, _) = self.collection(&url, name, section, ordered, true).await;
+                checks.push(check);
+            }
+        }
+
+        let items = match &outbox_document {
+            Some(outbox) => self.first_items(outbox).await,
+            None => Vec::new(),
+        };
+        checks.push(outbox_items(&items));
+        checks.push(self.published_object(&items).await);
+
+        match actor["inbox"].as_str().and_then(|url| Url::parse(url).ok()) {
+            Some(inbox) => {
+                checks.push(self.unsigned_delivery(&inbox).await);
+                checks.push(self.wrong_content_type(&inbox).await);
+            }
+            None => {
+                checks.push(Check::skip(
+                    "inbox rejects unsigned",
+                    "B.1",
+                    Level::Should,
+                    "the actor names no inbox",
+                ));
+            }
+        }
+        if let Some(outbox) = &outbox {
+            checks.push(self.unauthenticated_submission(outbox).await);
+        }
+
+        Report {
+            actor: self.actor.to_string(),
+            checks,
+        }
+    }
+
+    /// GET `url` asking for `accept`
+    async fn get(&self, url: &Url, accept: &str) -> Result<Fetched, String> {
+        let response = self
+            .http
+            .get(url.clone())
+            .header(ACCEPT, accept)
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let document = if status.is_success() {
+            response.json().await.ok()
+        } else {
+            None
+        };
+        Ok(Fetched {
+            status,
+            content_type,
+            document,
+        })
+    }
+
+    /// Servers must answer both ActivityStreams media types with an
+    /// ActivityStreams document (section 3.2)
+    async fn dereference(
+        &self,
+        url: &Url,
+        accept: &str,
+        name: &'static str,
+    ) -> (Check, Option<Value>) {
+        let fetched = match self.get(url, accept).await {
+            Ok(fetched) => fetched,
+            Err(e) => return (Check::new(name, "3.2", Level::Must, false, e), None),
+        };
+        if !fetched.status.is_success() {
+            let detail = format!("{} answered {}", url, fetched.status);
+            return (Check::new(name, "3.2", Level::Must, false, detail), None);
+        }
+        if !is_activity_json(&fetched.content_type) {
+            let detail = format!(
+                "answered '{}' instead of an ActivityStreams media type",
+                fetched.content_type
+            );
+            return (Check::new(name, "3.2", Level::Must, false, detail), None);
+        }
+        match fetched.document {
+            Some(document) => (
+                Check::new(
+                    name,
+                    "3.2",
+                    Level::Must,
+                    true,
+                    format!("{} answered {}", fetched.status, fetched.content_type),
+                ),
+                Some(document),
+            ),
+            None => (
+                Check::new(name, "3.2", Level::Must, false, "the body is not JSON"),
+                None,
+            ),
+        }
+    }
+
+    /// Collections linked from the actor are collections, `ordered` ones
+    /// ordered collections (section 5)
+    ///
+    /// The outbox has to be public; the inbox and the social graph may be
+    /// `restricted` to authorized readers and are only recommended.
+    async fn collection(
+        &self,
+        url: &Url,
+        name: &'static str,
+        section: &'static str,
+        ordered: bool,
+        restricted: bool,
+    ) -> (Check, Option<Value>) {
+        let level = if restricted {
+            Level::Should
+        } else {
+            Level::Must
+        };
+        let fetched = match self.get(url, ACTIVITYPUB_CONTENT_TYPE).await {
+            Ok(fetched) => fetched,
+            Err(e) => return (Check::new(name, section, level, false, e), None),
+        };
+        if restricted
+            && matches!(
+                fetched.status,
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            )
+        {
+            let detail = format!("restricted to authorized readers ({})", fetched.status);
+            return (Check::new(name, section, level, true, detail), None);
+        }
+        let Some(document) = fetched.document else {
+            let detail = format!("{} answered {}", url, fetched.status);
+            return (Check::new(name, section, level, false, detail), None);
+        };
+
+        let allowed: &[&str] = if ordered {
+            &["OrderedCollection", "OrderedCollectionPage"]
+        } else {
+            &[
+                "OrderedCollection",
+                "Collection",
+                "OrderedCollectionPage",
+                "CollectionPage",
+            ]
+        };
+        let kind = document["type"].as_str().unwrap_or("");
+        let check = if !is_activity_json(&fetched.content_type) {
+            Check::new(
+                name,
+                section,
+                level,
+                false,
+                format!("answered '{}'", fetched.content_type),
+            )
+        } else if allowed.contains(&kind) {
+            Check::new(name, section, level, true, format!("is a {}", kind))
+        } else {
+            Check::new(
+                name,
+                section,
+                level,
+                false,
+                format!("is a '{}', expected {}", kind, allowed.join(" or ")),
+            )
+        };
+        (check, Some(document))
+    }
+
+    /// Items of the first page of a collection, following `first` if the
+    /// collection does not embed them
+    async fn first_items(&self, collection: &Value) -> Vec<Value> {
+        if let Some(items) = embedded_items(collection) {
+            return items;
+        }
+        match &collection["first"] {
+            Value::String(url) => {
+                let Ok(url) = Url::parse(url) else {
+                    return Vec::new();
+                };
+                match self.get(&url, ACTIVITYPUB_CONTENT_TYPE).await {
+                    Ok(Fetched {
+                        document: Some(page),
+                        ..
+                    }) => embedded_items(&page).unwrap_or_default(),
+                    _ => Vec::new(),
+                }
+            }
+            page @ Value::Object(_) => embedded_items(page).unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Objects the actor published dereference to themselves (section 3.2)
+    async fn published_object(&self, items: &[Value]) -> Check {
+        const NAME: &str = "object dereference";
+        // Activities may be served only embedded, their objects must resolve
+        let id = items.first().and_then(|item| match &item["object"] {
+            Value::String(id) => Some(id.clone()),
+            Value::Object(object) => object.get("id")?.as_str().map(str::to_string),
+            _ => item["id"].as_str().map(str::to_string),
+        });
+        let Some(url) = id.and_then(|id| Url::parse(&id).ok()) else {
+            return Check::skip(NAME, "3.2", Level::Must, "the outbox has no objects");
+        };
+
+        let (check, document) = self.dereference(&url, ACTIVITYPUB_CONTENT_TYPE, NAME).await;
+        match document {
+            Some(document) if document["id"] == url.as_str() => Check {
+                detail: format!("{} dereferences to itself", url),
+                ..check
+            },
+            Some(document) => Check::new(
+                NAME,
+                "3.2",
+                Level::Must,
+                false,
+                format!("{} answered an object with id {}", url, document["id"]),
+            ),
+            None => check,
+        }
+    }
+
+    /// Deliveries without a signature are refused (section B.1)
+    async fn unsigned_delivery(&self, inbox: &Url) -> Check {
+        const NAME: &str = "inbox rejects unsigned";
+        let response = self
+            .http
+            .post(inbox.clone())
+            .header(CONTENT_TYPE, ACTIVITY_STREAMS_JSON_LD)
+            .body(probe_activity(inbox).to_string())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_client_error() => Check::new(
+                NAME,
+                "B.1",
+                Level::Should,
+                true,
+                format!("refused with {}", response.status()),
+            ),
+            Ok(response) => Check::new(
+                NAME,
+                "B.1",
+                Level::Should,
+                false,
+                format!("an unsigned activity was answered {}", response.status()),
+            ),
+            Err(e) => Check::new(NAME, "B.1", Level::Should, false, e.to_string()),
+        }
+    }
+
+    /// Deliveries must be ActivityStreams, others are refused (section 7)
+    async fn wrong_content_type(&self, inbox: &Url) -> Check {
+        const NAME: &str = "inbox content type";
+        let response = self
+            .http
+            .post(inbox.clone())
+            .header(CONTENT_TYPE, "text/plain")
+            .body(probe_activity(inbox).to_string())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_client_error() => Check::new(
+                NAME,
+                "7",
+                Level::Should,
+                true,
+                format!("text/plain refused with {}", response.status()),
+            ),
+            Ok(response) => Check::new(
+                NAME,
+                "7",
+                Level::Should,
+                false,
+                format!("text/plain was answered {}", response.status()),
+            ),
+            Err(e) => Check::new(NAME, "7", Level::Should, false, e.to_string()),
+        }
+    }
+
+    /// Clients must be authorized to post to the outbox (section 6)
+    async fn unauthenticated_submission(&self, outbox: &Url) -> Check {
+        const NAME: &str = "outbox requires auth";
+        let response = self
+            .http
+            .post(outbox.clone())
+            .header(CONTENT_TYPE, ACTIVITY_STREAMS_JSON_LD)
+            .body(
+                json!({
+                    "@context": ACTIVITYSTREAMS,
+                    "type": "Note",
+                    "content": "conformance check, must not be published"
+                })
+                .to_string(),
+            )
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_client_error() => Check::new(
+                NAME,
+                "6",
+                Level::Must,
+                true,
+                format!("refused with {}", response.status()),
+            ),
+            Ok(response) => Check::new(
+                NAME,
+                "6",
+                Level::Must,
+                false,
+                format!(
+                    "an unauthenticated submission was answered {}",
+                    response.status()
+                ),
+            ),
+            Err(e) => Check::new(NAME, "6", Level::Must, false, e.to_string()),
+        }
+    }
+}
+
+/// Properties an actor must or should have (section 4.1)
+pub fn actor_properties(url: &Url, actor: &Value) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let context = match &actor["@context"] {
+        Value::String(context) => context == ACTIVITYSTREAMS,
+        Value::Array(contexts) => contexts.iter().any(|c| c == ACTIVITYSTREAMS),
+        _ => false,
+    };
+    checks.push(Check::new(
+        "actor @context",
+        "3",
+        Level::Must,
+        context,
+        if context {
+            "includes the ActivityStreams namespace".to_string()
+        } else {
+            format!("{} is missing from @context", ACTIVITYSTREAMS)
+        },
+    ));
+
+    let id = actor["id"].as_str();
+    checks.push(Check::new(
+        "actor id",
+        "3.1",
+        Level::Must,
+        id == Some(url.as_str()),
+        match id {
+            Some(id) if id == url.as_str() => "matches the URL it was fetched from".to_string(),
+            Some(id) => format!("{} differs from the URL it was fetched from", id),
+            None => "the actor has no id".to_string(),
+        },
+    ));
+
+    let kind = actor["type"].as_str().unwrap_or("");
+    let actor_type = ["Application", "Group", "Organization", "Person", "Service"].contains(&kind);
+    checks.push(Check::new(
+        "actor type",
+        "4",
+        Level::Should,
+        actor_type,
+        if actor_type {
+            format!("is a {}", kind)
+        } else {
+            format!("'{}' is not an ActivityStreams actor type", kind)
+        },
+    ));
+
+    for (property, level) in [
+        ("inbox", Level::Must),
+        ("outbox", Level::Must),
+        ("following", Level::Should),
+        ("followers", Level::Should),
+    ] {
+        let name = match property {
+            "inbox" => "actor inbox",
+            "outbox" => "actor outbox",
+            "following" => "actor following",
+            _ => "actor followers",
+        };
+        let valid = actor[property]
+            .as_str()
+            .is_some_and(|value| Url::parse(value).is_ok());
+        checks.push(Check::new(
+            name,
+            "4.1",
+            level,
+            valid,
+            if valid {
+                actor[property].as_str().unwrap_or_default().to_string()
+            } else {
+                format!("the actor has no {} URL", property)
+            },
+        ));
+    }
+
+    checks
+}
+
+/// Items are activities or objects with a type, and an id where embedded
+fn outbox_items(items: &[Value]) -> Check {
+    const NAME: &str = "outbox items";
+    if items.is_empty() {
+        return Check::skip(NAME, "5.1", Level::Should, "the outbox is empty");
+    }
+    let invalid = items
+        .iter()
+        .take(MAX_CHECKED_ITEMS)
+        .filter(|item| match item {
+            Value::String(id) => Url::parse(id).is_err(),
+            Value::Object(_) => !item["type"].is_string() || !item["id"].is_string(),
+            _ => true,
+        })
+        .count();
+    let checked = items.len().min(MAX_CHECKED_ITEMS);
+    Check::new(
+        NAME,
+        "5.1",
+        Level::Should,
+        invalid == 0,
+        if invalid == 0 {
+            format!("{} items have an id and type", checked)
+        } else {
+            format!("{} of {} items lack an id or type", invalid, checked)
+        },
+    )
+}
+
+/// `orderedItems` or `items` of a collection or page
+fn embedded_items(collection: &Value) -> Option<Vec<Value>> {
+    collection["orderedItems"]
+        .as_array()
+        .or_else(|| collection["items"].as_array())
+        .cloned()
+}
+
+/// Activity the server can safely ignore, sent to probe the inbox
+fn probe_activity(inbox: &Url) -> Value {
+    let id = format!(
+        "https://conformance.invalid/activities/{}",
+        uuid::Uuid::new_v4()
+    );
+    json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": format!("{}/undo", id),
+        "type": "Undo",
+        "actor": "https://conformance.invalid/users/probe",
+        "object": {
+            "id": id,
+            "type": "Follow",
+            "actor": "https://conformance.invalid/users/probe",
+            "object": inbox.as_str(),
+        },
+    })
+}
+
+/// Whether `content_type` is an ActivityStreams media type
+fn is_activity_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.eq_ignore_ascii_case(ACTIVITYPUB_CONTENT_TYPE)
+        || (mime.eq_ignore_ascii_case("application/ld+json")
+            && content_type.contains(ACTIVITYSTREAMS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::testing::{MockPeer, MockPeerConfig};
+
+    fn outcome(report: &Report, name: &str) -> Outcome {
+        report
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .unwrap_or_else(|| panic!("no check {}", name))
+            .outcome
+    }
+
+    #[test]
+    fn test_actor_properties() {
+        let url = Url::parse("https://example.com/users/alice").unwrap();
+        let actor = json!({
+            "@context": [ACTIVITYSTREAMS, "https://w3id.org/security/v1"],
+            "id": "https://example.com/users/alice",
+            "type": "Person",
+            "inbox": "https://example.com/users/alice/inbox",
+            "outbox": "https://example.com/users/alice/outbox",
+            "followers": "https://example.com/users/alice/followers"
+        });
+
+        let checks = actor_properties(&url, &actor);
+        let failed: Vec<_> = checks
+            .iter()
+            .filter(|check| check.outcome == Outcome::Fail)
+            .map(|check| (check.name, check.level))
+            .collect();
+        assert_eq!(failed, vec![("actor following", Level::Should)]);
+
+        let checks = actor_properties(&url, &json!({ "type": "Note" }));
+        assert_eq!(
+            checks
+                .iter()
+                .filter(|check| check.level == Level::Must && check.outcome == Outcome::Fail)
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_is_activity_json() {
+        assert!(is_activity_json("application/activity+json; charset=utf-8"));
+        assert!(is_activity_json(ACTIVITY_STREAMS_JSON_LD));
+        assert!(!is_activity_json("application/ld+json"));
+        assert!(!is_activity_json("application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_suite_against_mock_peer() {
+        let peer = MockPeer::start_with(MockPeerConfig {
+            require_signatures: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let alice = peer.actor_id("alice");
+        let note = format!("{}/notes/1", alice);
+        peer.add_document(
+            "/users/alice/notes/1",
+            json!({ "@context": ACTIVITYSTREAMS, "id": note, "type": "Note" }),
+        );
+        peer.add_collection(
+            "/users/alice/outbox",
+            vec![json!({
+                "id": format!("{}/activities/1", alice),
+                "type": "Create",
+                "actor": alice,
+                "object": { "id": note, "type": "Note" }
+            })],
+        );
+        peer.add_collection("/users/alice/followers", Vec::new());
+
+        let suite = Suite::new(Url::parse(&alice).unwrap(), Duration::from_secs(5)).unwrap();
+        let report = suite.run().await;
+
+        assert_eq!(outcome(&report, "actor activity+json"), Outcome::Pass);
+        assert_eq!(outcome(&report, "actor ld+json"), Outcome::Pass);
+        assert_eq!(outcome(&report, "outbox collection"), Outcome::Pass);
+        assert_eq!(outcome(&report, "followers collection"), Outcome::Pass);
+        assert_eq!(outcome(&report, "outbox items"), Outcome::Pass);
+        assert_eq!(outcome(&report, "object dereference"), Outcome::Pass);
+        assert_eq!(outcome(&report, "inbox rejects unsigned"), Outcome::Pass);
+        assert_eq!(outcome(&report, "outbox requires auth"), Outcome::Pass);
+        // The mock serves no following collection and no GET on inboxes
+        assert_eq!(outcome(&report, "following collection"), Outcome::Fail);
+        assert_eq!(report.failures(Level::Must), 0);
+    }
+}
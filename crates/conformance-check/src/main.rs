@@ -0,0 +1,142 @@
+//! ActivityPub conformance check
+//!
+//! Runs the server scenarios of the W3C ActivityPub test suite that can be
+//! checked from outside against an actor served by a running domainservd
+//! (or any other server) and prints a compliance report. The exit code is
+//! non-zero if a MUST requirement fails, or with `--strict` any requirement,
+//! so the check can gate CI runs; operators use the table output to see what
+//! remote servers will trip over.
+
+mod checks;
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use checks::{Level, Outcome, Report, Suite};
+use clap::{Parser, ValueEnum};
+use oxifed::egress::EgressPolicy;
+use oxifed::webfinger::WebFingerClient;
+use url::Url;
+
+/// Failures of requirements the run gates on
+const EXIT_NONCONFORMANT: u8 = 1;
+/// The check could not run, e.g. the actor could not be resolved
+const EXIT_ERROR: u8 = 2;
+
+/// Format of the report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Table,
+    Json,
+}
+
+/// Check an ActivityPub server against the specification
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Actor to check, as URL or user@domain
+    actor: String,
+
+    /// Format of the report
+    #[arg(long, short, value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// Fail on SHOULD requirements too
+    #[arg(long)]
+    strict: bool,
+
+    /// Seconds a request may take
+    #[arg(long, default_value_t = 15)]
+    timeout: u64,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Instances under test commonly run on the local network
+    EgressPolicy::allowing_private().install();
+
+    let actor = match resolve(&args.actor).await {
+        Ok(actor) => actor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let suite = match Suite::new(actor, Duration::from_secs(args.timeout)) {
+        Ok(suite) => suite,
+        Err(e) => {
+            eprintln!("Error: failed to create HTTP client: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let report = suite.run().await;
+
+    match args.output {
+        OutputFormat::Table => print_table(&report),
+        OutputFormat::Json => match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        },
+    }
+
+    let failed = report.failures(Level::Must)
+        + if args.strict {
+            report.failures(Level::Should)
+        } else {
+            0
+        };
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_NONCONFORMANT)
+    }
+}
+
+/// Actor URL of a URL or `user@domain`
+async fn resolve(actor: &str) -> Result<Url, String> {
+    if actor.contains("://") {
+        return Url::parse(actor).map_err(|e| format!("invalid actor URL '{}': {}", actor, e));
+    }
+
+    let account = format!("acct:{}", actor.trim_start_matches('@'));
+    let jrd = WebFingerClient::new()
+        .finger(&account, None)
+        .await
+        .map_err(|e| format!("WebFinger lookup of {} failed: {}", actor, e))?;
+    let href = jrd
+        .find_link("self")
+        .and_then(|link| link.href.clone())
+        .ok_or_else(|| format!("WebFinger answer for {} has no self link", actor))?;
+    Url::parse(&href).map_err(|e| format!("invalid actor URL '{}': {}", href, e))
+}
+
+fn print_table(report: &Report) {
+    println!("ActivityPub conformance of {}", report.actor);
+    for check in &report.checks {
+        let mark = match check.outcome {
+            Outcome::Pass => "ok  ",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "skip",
+        };
+        let level = match check.level {
+            Level::Must => "MUST",
+            Level::Should => "SHOULD",
+        };
+        println!(
+            "  [{}] {:<6} §{:<4} {:<22} {}",
+            mark, level, check.section, check.name, check.detail
+        );
+    }
+    println!(
+        "{} MUST and {} SHOULD requirements failed",
+        report.failures(Level::Must),
+        report.failures(Level::Should)
+    );
+}
@@ -4,27 +4,45 @@
 //! actor profiles, inboxes, outboxes, and collections.
 
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
     body::Body,
-    extract::{Path, Query, State},
-    http::{HeaderMap, HeaderValue, StatusCode},
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{get, post, put},
 };
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use oxifed::{
     Activity, ActivityType, ObjectType,
     database::{
-        ActivityDocument, ActivityStatus, ActorDocument, ActorStatus, FollowDocument, FollowStatus,
-        ObjectDocument, VisibilityLevel,
+        ActivityDocument, ActivityStatus, ActorDocument, ActorRestriction, ActorStatus,
+        ApplicationProfile, AttachmentDocument, DatabaseError, DatabaseManager, DirectoryOrder,
+        FollowDocument, FollowStatus, ObjectDocument, ObjectStatus, OutboxMessageDocument,
+        ParticipationStatus, VisibilityLevel, alias_actor_url, articles_url,
     },
+    extensions::{self, Extensions},
+    language,
+    signature_middleware::{VerifiedSigner, accept_signature, require_signature},
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, instrument, warn};
 use url::Url;
 use uuid::Uuid;
 
+use crate::blog;
+use crate::bodylimit::{BodyClass, limit_body};
+use crate::caching::{CacheClass, conditional_get, with_last_modified};
+use crate::delivery;
+use crate::events;
+use crate::group;
+use crate::html;
+use crate::instances;
+use crate::oauth::{activity_scope, authenticated_username, verify_client_authentication};
+use crate::privacy;
+use crate::ratelimit::{EndpointClass, limit_actors, limit_clients};
+use crate::relay;
 use crate::{AppState, extract_domain_from_headers};
 use futures::TryStreamExt;
 
@@ -112,23 +130,144 @@ pub struct ActivityPubCollection {
 }
 
 /// Create ActivityPub router
-pub fn activitypub_router(_state: AppState) -> Router<AppState> {
-    Router::new()
-        // Actor endpoints
-        .route("/users/{username}", get(get_actor))
+///
+/// Requests to the inboxes are only accepted with a valid HTTP signature
+/// and an ActivityStreams content type. Inbox, outbox, C2S and search
+/// requests are rate limited per client IP and per actor; request bodies
+/// are limited by [`limit_body`] instead of axum's default limit.
+pub fn activitypub_router(state: AppState) -> Router<AppState> {
+    let limit = |class| (state.rate_limiter.clone(), class);
+    let body = |class| (state.body_limiter.clone(), class);
+    let cache =
+        |class| middleware::from_fn_with_state((state.http_cache.clone(), class), conditional_get);
+
+    // The client limit and body checks run first so floods are refused
+    // before signatures are verified; the actor limit needs the verified
+    // signer
+    let inboxes = Router::new()
         .route("/users/{username}/inbox", post(post_inbox))
+        .route("/inbox", post(post_shared_inbox))
+        .route_layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::Inbox),
+            limit_actors,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.signatures.clone(),
+            require_signature,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            body(BodyClass::Inbox),
+            limit_body,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::Inbox),
+            limit_clients,
+        ));
+
+    let outboxes = Router::new()
         .route(
             "/users/{username}/outbox",
-            get(get_outbox).post(post_outbox),
+            get(get_outbox)
+                .post(post_outbox)
+                .route_layer(middleware::from_fn_with_state(
+                    body(BodyClass::C2s),
+                    limit_body,
+                )),
+        )
+        .route_layer(cache(CacheClass::Collection))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            privacy::authorized_fetch,
+        ))
+        .route_layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::Outbox),
+            limit_actors,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::Outbox),
+            limit_clients,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            privacy::conceal_accounts,
+        ));
+
+    // C2S endpoints for direct object creation
+    let c2s_body = || middleware::from_fn_with_state(body(BodyClass::C2s), limit_body);
+    let c2s = Router::new()
+        .route(
+            "/users/{username}/notes",
+            post(create_note).route_layer(c2s_body()),
+        )
+        .route("/users/{username}/conversations", get(get_conversations))
+        .route(
+            "/users/{username}/conversations/messages",
+            get(get_conversation_messages),
+        )
+        .route(
+            "/users/{username}/media",
+            post(upload_media).route_layer(middleware::from_fn_with_state(
+                body(BodyClass::Media),
+                limit_body,
+            )),
         )
+        .route_layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::C2s),
+            limit_actors,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::C2s),
+            limit_clients,
+        ));
+    let article_creation = post(create_article)
+        .route_layer(c2s_body())
+        .route_layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::C2s),
+            limit_actors,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::C2s),
+            limit_clients,
+        ));
+    let object_updates = put(update_object)
+        .delete(delete_object)
+        .route_layer(c2s_body())
+        .route_layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::C2s),
+            limit_clients,
+        ));
+
+    let search = Router::new()
+        .route("/search", get(search_content))
+        .route("/directory", get(get_directory))
+        .route("/users", get(get_directory))
+        .route("/api/v1/instance/peers", get(instances::get_peers))
+        .route_layer(middleware::from_fn_with_state(
+            limit(EndpointClass::Search),
+            limit_clients,
+        ));
+
+    // Actor endpoints; unknown and hidden accounts are answered alike
+    let conceal = || middleware::from_fn_with_state(state.clone(), privacy::conceal_accounts);
+    let actors = Router::new()
+        .route("/users/{username}", get(get_actor))
+        .route("/actor", get(relay::get_instance_actor))
+        .route_layer(cache(CacheClass::Actor))
+        .route_layer(conceal());
+
+    // Domains may serve the collections of their actors to signed or
+    // authenticated requests only
+    let collections = Router::new()
         .route("/users/{username}/followers", get(get_followers))
         .route("/users/{username}/following", get(get_following))
+        .route("/users/{username}/moderators", get(group::get_moderators))
         .route("/users/{username}/liked", get(get_liked))
         .route("/users/{username}/featured", get(get_featured))
-        // C2S endpoints for direct object creation
-        .route("/users/{username}/notes", post(create_note))
-        .route("/users/{username}/articles", post(create_article))
-        .route("/users/{username}/media", post(upload_media))
         // Collections with pagination
         .route(
             "/users/{username}/collections/featured",
@@ -138,23 +277,56 @@ pub fn activitypub_router(_state: AppState) -> Router<AppState> {
             "/users/{username}/collections/tags/{tag}",
             get(get_tag_collection),
         )
-        // Object endpoints
+        .route_layer(cache(CacheClass::Collection))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            privacy::authorized_fetch,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.signatures.clone(),
+            accept_signature,
+        ))
+        .route_layer(conceal());
+
+    // Blogs; browsers get pages of them
+    let blogs = Router::new()
+        .route(
+            "/users/{username}/articles",
+            get(blog::get_articles).merge(article_creation),
+        )
         .route(
-            "/objects/{id}",
-            get(get_object).put(update_object).delete(delete_object),
+            "/users/{username}/articles/tagged/{tag}",
+            get(blog::get_tagged_articles),
         )
+        .route("/users/{username}/articles/{slug}", get(blog::get_article))
+        .route_layer(cache(CacheClass::Collection))
+        .route_layer(conceal());
+
+    // Object endpoints; updates and deletions pass the cache layer. Signed
+    // fetches may see the followers-only and direct objects addressed to
+    // their signer.
+    let objects = Router::new()
+        .route("/objects/{id}", get(get_object).merge(object_updates))
+        .route("/objects/{id}/history", get(get_object_history))
+        .route("/objects/{id}/participants", get(get_object_participants))
         .route("/activities/{id}", get(get_activity))
-        // Shared inbox
-        .route("/inbox", post(post_shared_inbox))
-        // Search and discovery
-        .route("/search", get(search_content))
-        .route("/users", get(list_users))
+        .route_layer(cache(CacheClass::Object))
+        .route_layer(middleware::from_fn_with_state(
+            state.signatures.clone(),
+            accept_signature,
+        ));
+
+    Router::new()
+        .merge(inboxes)
+        .merge(outboxes)
+        .merge(c2s)
+        .merge(search)
+        .merge(actors)
+        .merge(collections)
+        .merge(blogs)
+        .merge(objects)
         // Node info
         .route("/nodeinfo/2.0", get(get_nodeinfo))
-        // OAuth endpoints for C2S authentication
-        .route("/oauth/authorize", get(oauth_authorize))
-        .route("/oauth/token", post(oauth_token))
-        .route("/oauth/revoke", post(oauth_revoke))
 }
 
 /// Get actor profile
@@ -181,10 +353,7 @@ async fn get_actor(
         .await
     {
         Ok(Some(actor)) => actor,
-        Ok(None) => {
-            warn!("Actor not found: {}@{}", username, domain);
-            return Err(StatusCode::NOT_FOUND);
-        }
+        Ok(None) => return redirect_alias(&username, &domain, &state).await,
         Err(e) => {
             error!("Database error finding actor: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
@@ -197,37 +366,75 @@ async fn get_actor(
         return Err(StatusCode::GONE);
     }
 
-    // Convert to ActivityPub format
+    let response = if html::prefers_html(&headers) {
+        html::actor_page(&actor_doc, &state)
+    } else {
+        (
+            StatusCode::OK,
+            [("Content-Type", "application/activity+json")],
+            Json(actor_json(&actor_doc)),
+        )
+            .into_response()
+    };
+    Ok(html::vary_accept(with_last_modified(
+        response,
+        actor_doc.updated_at,
+    )))
+}
+
+/// Redirect a handle bound to a local actor to the actor
+async fn redirect_alias(
+    username: &str,
+    domain: &str,
+    state: &AppState,
+) -> Result<Response, StatusCode> {
+    let actor = state
+        .db_manager
+        .find_actor_by_alias(&alias_actor_url(username, domain))
+        .await
+        .map_err(|e| {
+            error!("Database error finding alias: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let Some(actor) = actor else {
+        warn!("Actor not found: {}@{}", username, domain);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    debug!("Redirecting {}@{} to {}", username, domain, actor.actor_id);
+    Ok((
+        StatusCode::PERMANENT_REDIRECT,
+        [(header::LOCATION, actor.actor_id)],
+    )
+        .into_response())
+}
+
+/// ActivityPub representation of a local actor
+///
+/// Also embedded in the actor Update sent after a key rotation.
+pub(crate) fn actor_json(actor_doc: &ActorDocument) -> Value {
+    let mut context = extensions::context();
+    context["oxifed"] = json!("https://oxifed.org/ns#");
+    context["keyChain"] = json!({
+        "@id": "oxifed:keyChain",
+        "@type": "@id"
+    });
+
     let mut actor_json = json!({
         "@context": [
             "https://www.w3.org/ns/activitystreams",
             "https://w3id.org/security/v1",
-            {
-                "manuallyApprovesFollowers": "as:manuallyApprovesFollowers",
-                "toot": "http://joinmastodon.org/ns#",
-                "featured": {
-                    "@id": "toot:featured",
-                    "@type": "@id"
-                },
-                "PropertyValue": "schema:PropertyValue",
-                "value": "schema:value",
-                "oxifed": "https://oxifed.org/ns#",
-                "keyChain": {
-                    "@id": "oxifed:keyChain",
-                    "@type": "@id"
-                }
-            }
+            context
         ],
-        "type": "Person",
+        "type": actor_doc.actor_type,
         "id": actor_doc.actor_id,
         "name": actor_doc.name,
         "preferredUsername": actor_doc.preferred_username,
         "summary": actor_doc.summary,
-        "icon": actor_doc.icon.map(|url| json!({
+        "icon": actor_doc.icon.as_ref().map(|url| json!({
             "type": "Image",
             "url": url
         })),
-        "image": actor_doc.image.map(|url| json!({
+        "image": actor_doc.image.as_ref().map(|url| json!({
             "type": "Image",
             "url": url
         })),
@@ -245,28 +452,39 @@ async fn get_actor(
             "publicKeyPem": pk.public_key_pem
         })),
         "published": actor_doc.created_at.to_rfc3339(),
-        "manuallyApprovesFollowers": false
+        "manuallyApprovesFollowers": false,
+        "discoverable": actor_doc.discoverable(),
+        "indexable": actor_doc.indexable()
     });
 
+    let also_known_as = actor_doc.also_known_as();
+    if !also_known_as.is_empty() {
+        actor_json["alsoKnownAs"] = json!(also_known_as);
+    }
+
+    if actor_doc.actor_type == group::GROUP {
+        actor_json["attributedTo"] = json!(group::moderators_url(actor_doc));
+    }
+
+    // Blogs advertise their article index
+    if actor_doc.application_profile() == ApplicationProfile::Blog {
+        actor_json["streams"] = json!([articles_url(&actor_doc.actor_id)]);
+    }
+
     // Add oxifed:keyChain extension for PKI-aware servers
     if let Some(public_key) = &actor_doc.public_key {
         let key_chain = json!({
             "type": "OxifedPKI",
             "userKey": public_key.id,
-            "domainKey": format!("https://{}/.well-known/oxifed/domain-key", domain),
-            "masterKey": format!("https://{}/.well-known/oxifed/master-key", domain),
+            "domainKey": format!("https://{}/.well-known/oxifed/domain-key", actor_doc.domain),
+            "masterKey": format!("https://{}/.well-known/oxifed/master-key", actor_doc.domain),
             "userKeyCertificate": null,  // TODO: Add actual certificate when available
             "domainKeyCertificate": null // TODO: Add actual certificate when available
         });
         actor_json["oxifed:keyChain"] = key_chain;
     }
 
-    Ok((
-        StatusCode::OK,
-        [("Content-Type", "application/activity+json")],
-        Json(actor_json),
-    )
-        .into_response())
+    actor_json
 }
 
 /// Handle incoming activities to user inbox
@@ -280,23 +498,21 @@ async fn get_actor(
 /// 2. Activity actor URL domain (fallback)
 /// 3. Activity object URL domain (fallback)
 /// 4. Activity object.id URL domain (fallback)
+#[instrument(name = "inbox", skip_all, fields(username = %username))]
 async fn post_inbox(
     Path(username): Path<String>,
     State(state): State<AppState>,
+    signer: Option<Extension<VerifiedSigner>>,
     headers: HeaderMap,
-    Json(activity_json): Json<Value>,
+    Json(mut activity_json): Json<Value>,
 ) -> Result<Response, StatusCode> {
     info!("Received activity for user: {}", username);
     debug!(
         "Activity payload: {}",
         serde_json::to_string_pretty(&activity_json).unwrap_or_default()
     );
-
-    // Verify HTTP signature
-    if let Err(e) = verify_http_signature(&headers, &state).await {
-        warn!("HTTP signature verification failed: {}", e);
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    let signer = signer.map(|Extension(signer)| signer);
+    check_signer(&activity_json, signer.as_ref(), &state).await?;
 
     // Extract domain from Host header with fallback to activity content
     let domain = match extract_domain_from_headers(&headers) {
@@ -337,6 +553,15 @@ async fn post_inbox(
         }
     }
 
+    normalize_for_peer(&mut activity_json, &state).await;
+    let sender = check_sender(&activity_json, &state).await?;
+
+    // Flag activities commonly carry an array of objects, so they are
+    // forwarded to the moderation pipeline before strict deserialization
+    if activity_json.get("type").and_then(|t| t.as_str()) == Some("Flag") {
+        return forward_flag_activity(&activity_json, &state, &domain, Some(&username)).await;
+    }
+
     // Deserialize and validate the activity
     let activity: Activity = match serde_json::from_value::<Activity>(activity_json.clone()) {
         Ok(act) => {
@@ -360,16 +585,67 @@ async fn post_inbox(
         .await
     {
         Ok(Some(actor)) => actor,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        // Deliveries to a handle bound to an actor reach the actor
+        Ok(None) => match state
+            .db_manager
+            .find_actor_by_alias(&alias_actor_url(&username, &domain))
+            .await
+        {
+            Ok(Some(actor)) => actor,
+            Ok(None) => return Err(StatusCode::NOT_FOUND),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        },
         Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
+    let (username, domain) = (
+        actor_doc.preferred_username.clone(),
+        actor_doc.domain.clone(),
+    );
 
     if actor_doc.status != ActorStatus::Active {
         return Err(StatusCode::GONE);
     }
 
+    // Limited actors only reach the local accounts following them
+    if let Some(sender) = &sender
+        && sender.restriction == ActorRestriction::Limited
+        && matches!(
+            activity.activity_type,
+            ActivityType::Create | ActivityType::Announce | ActivityType::Like
+        )
+        && !is_following(&actor_doc.actor_id, &sender.actor_id, &state).await?
+    {
+        info!(
+            "Dropping {:?} of limited actor {} to non-follower {}",
+            activity.activity_type, sender.actor_id, actor_doc.actor_id
+        );
+        return Ok(StatusCode::ACCEPTED.into_response());
+    }
+
+    if actor_doc.actor_type == group::GROUP
+        && let Some(result) =
+            group::handle_inbox(&activity_json, &actor_doc, signer.as_ref(), &state).await
+    {
+        return match result {
+            Ok(()) => Ok(StatusCode::ACCEPTED.into_response()),
+            Err(e) => {
+                error!("Failed to process group activity: {}", e);
+                Err(StatusCode::BAD_REQUEST)
+            }
+        };
+    }
+
     // Process the activity with the parsed struct
-    match process_incoming_activity(&activity, &actor_doc, &state, &domain, &username).await {
+    match process_incoming_activity(
+        &activity,
+        &actor_doc,
+        signer.as_ref(),
+        &state,
+        &domain,
+        &username,
+    )
+    .await
+    {
         Ok(_) => {
             info!(
                 "Successfully processed {} activity for user: {}",
@@ -385,6 +661,115 @@ async fn post_inbox(
     }
 }
 
+/// ID of the actor an incoming activity claims to come from
+fn claimed_sender(activity: &Value) -> Option<&str> {
+    match &activity["actor"] {
+        Value::String(id) => Some(id.as_str()),
+        actor => actor["id"].as_str(),
+    }
+}
+
+/// Refuse activities whose actor is not the signer of the request
+///
+/// The signer must be the activity's actor, or sign with the key stored for
+/// the actor; keys on another origin never stand for it. Unsigned requests
+/// only get here when signatures are not enforced.
+async fn check_signer(
+    activity: &Value,
+    signer: Option<&VerifiedSigner>,
+    state: &AppState,
+) -> Result<(), StatusCode> {
+    let (Some(signer), Some(sender)) = (signer, claimed_sender(activity)) else {
+        return Ok(());
+    };
+    if signer.owner == sender {
+        return Ok(());
+    }
+
+    let same_origin = match (Url::parse(&signer.key_id), Url::parse(sender)) {
+        (Ok(key), Ok(sender)) => key.origin() == sender.origin(),
+        _ => false,
+    };
+    let stored_key = if same_origin {
+        match state.db_manager.find_actor_by_id(sender).await {
+            Ok(actor) => actor.and_then(|actor| actor.public_key).map(|key| key.id),
+            Err(e) => {
+                error!("Database error finding actor {}: {}", sender, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    } else {
+        None
+    };
+    if stored_key.as_deref() == Some(signer.key_id.as_str()) {
+        return Ok(());
+    }
+
+    warn!(
+        "Refusing activity of {} signed by {} ({})",
+        sender, signer.owner, signer.key_id
+    );
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+/// Known actor an incoming activity comes from
+///
+/// Activities of suspended actors are refused with 403.
+async fn check_sender(
+    activity: &Value,
+    state: &AppState,
+) -> Result<Option<ActorDocument>, StatusCode> {
+    let Some(sender) = claimed_sender(activity) else {
+        return Ok(None);
+    };
+    match state.db_manager.find_actor_by_id(sender).await {
+        Ok(Some(actor)) if actor.status == ActorStatus::Suspended => {
+            warn!("Refusing activity of suspended actor {}", sender);
+            Err(StatusCode::FORBIDDEN)
+        }
+        Ok(actor) => {
+            instances::record_sender(state, sender);
+            Ok(actor)
+        }
+        Err(e) => {
+            error!("Database error finding actor {}: {}", sender, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Rewrite the variants of activities the software of the sending peer
+/// uses, such as Pleroma's `EmojiReact`, to what the handlers understand
+async fn normalize_for_peer(activity: &mut Value, state: &AppState) {
+    let sender = claimed_sender(activity).unwrap_or_default();
+    let Some(host) = url::Url::parse(sender)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+    else {
+        return;
+    };
+    state
+        .peer_profiles
+        .for_host(&state.db_manager, &host)
+        .await
+        .normalize_incoming(activity);
+}
+
+/// Whether `follower` follows `following` with an accepted follow
+async fn is_following(
+    follower: &str,
+    following: &str,
+    state: &AppState,
+) -> Result<bool, StatusCode> {
+    match state.db_manager.find_follow(follower, following).await {
+        Ok(follow) => Ok(follow.is_some_and(|follow| follow.status == FollowStatus::Accepted)),
+        Err(e) => {
+            error!("Database error finding follow of {}: {}", following, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 /// Handle shared inbox for server-level activities
 ///
 /// This endpoint receives ActivityPub activities that are server-wide or
@@ -396,22 +781,20 @@ async fn post_inbox(
 /// 1. Extract domain from Host header or activity content
 /// 2. Validate domain exists in our database
 /// 3. Process activity if domain is valid
+#[instrument(name = "shared_inbox", skip_all)]
 async fn post_shared_inbox(
     State(state): State<AppState>,
+    signer: Option<Extension<VerifiedSigner>>,
     headers: HeaderMap,
-    Json(activity_json): Json<Value>,
+    Json(mut activity_json): Json<Value>,
 ) -> Result<Response, StatusCode> {
     info!("Received activity for shared inbox");
     debug!(
         "Activity payload: {}",
         serde_json::to_string_pretty(&activity_json).unwrap_or_default()
     );
-
-    // Verify HTTP signature
-    if let Err(e) = verify_http_signature(&headers, &state).await {
-        warn!("HTTP signature verification failed: {}", e);
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    let signer = signer.map(|Extension(signer)| signer);
+    check_signer(&activity_json, signer.as_ref(), &state).await?;
 
     // Extract domain from Host header with fallback to activity content
     let domain = match extract_domain_from_headers(&headers) {
@@ -437,6 +820,25 @@ async fn post_shared_inbox(
         }
     };
 
+    normalize_for_peer(&mut activity_json, &state).await;
+    check_sender(&activity_json, &state).await?;
+
+    if activity_json.get("type").and_then(|t| t.as_str()) == Some("Flag") {
+        return forward_flag_activity(&activity_json, &state, &domain, None).await;
+    }
+
+    if let Some(result) =
+        relay::handle_shared_inbox(&activity_json, signer.as_ref(), &state, &domain).await
+    {
+        return match result {
+            Ok(()) => Ok(StatusCode::ACCEPTED.into_response()),
+            Err(e) => {
+                error!("Failed to process relay activity: {}", e);
+                Err(StatusCode::BAD_REQUEST)
+            }
+        };
+    }
+
     // Deserialize and validate the activity
     let activity: Activity = match serde_json::from_value::<Activity>(activity_json.clone()) {
         Ok(act) => {
@@ -531,10 +933,13 @@ async fn get_outbox(
                     "id": obj.object_id,
                     "attributedTo": obj.attributed_to,
                     "content": obj.content,
+                    "contentMap": obj.content_map,
                     "summary": obj.summary,
+                    "summaryMap": obj.summary_map,
                     "published": obj.published.unwrap_or(obj.created_at).to_rfc3339(),
                     "to": obj.to,
-                    "cc": obj.cc
+                    "cc": obj.cc,
+                    "attachment": render_attachments(obj.attachment.as_deref(), &domain, &state)
                 }
             })
         })
@@ -571,8 +976,9 @@ async fn post_outbox(
 ) -> Result<Response, StatusCode> {
     info!("Posting to outbox for user: {}", username);
 
-    // Verify authentication via Bearer token or OAuth
-    if !verify_client_authentication(&headers, &username, &state).await {
+    // Following and blocking need the follow scope, everything else write
+    let scope = activity_scope(&activity);
+    if !verify_client_authentication(&headers, &username, scope, &state).await {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
@@ -795,6 +1201,7 @@ async fn get_featured(
 async fn get_object(
     Path(id): Path<String>,
     State(state): State<AppState>,
+    signer: Option<Extension<VerifiedSigner>>,
     headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
     debug!("Getting object: {}", id);
@@ -810,38 +1217,302 @@ async fn get_object(
 
     let object_id = format!("https://{}/objects/{}", domain, id);
 
+    // Scheduled objects do not exist yet for anyone else
     let object_doc = match state.db_manager.find_object_by_id(&object_id).await {
-        Ok(Some(obj)) => obj,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Ok(Some(obj)) if obj.status != ObjectStatus::Scheduled => obj,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
         Err(e) => {
             error!("Failed to get object: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
-    let object_json = json!({
-        "@context": "https://www.w3.org/ns/activitystreams",
+    // Deleted objects leave a Tombstone
+    if object_doc.object_type == ObjectType::Tombstone {
+        let deleted = object_doc.updated.unwrap_or(object_doc.created_at);
+        let former_type = object_doc
+            .additional_properties
+            .as_ref()
+            .and_then(|props| props.get_str("formerType").ok());
+        let tombstone = json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "Tombstone",
+            "id": object_doc.object_id,
+            "formerType": former_type,
+            "deleted": deleted.to_rfc3339()
+        });
+        return Ok((
+            StatusCode::GONE,
+            [("Content-Type", "application/activity+json")],
+            Json(tombstone),
+        )
+            .into_response());
+    }
+
+    // Objects the requester may not see do not exist for them
+    let signer = signer.map(|Extension(signer)| signer);
+    let viewer = viewer_of(signer.as_ref(), &headers, &domain, &state).await;
+    match may_see(&state.db_manager, &object_doc, viewer.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to check access to {}: {}", object_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let is_public = html::is_public(&object_doc);
+    let modified = object_doc
+        .updated
+        .or(object_doc.published)
+        .unwrap_or(object_doc.created_at);
+
+    // Browsers get a page for public objects only
+    if html::prefers_html(&headers) && is_public {
+        let author = match state
+            .db_manager
+            .find_actor_by_id(&object_doc.attributed_to)
+            .await
+        {
+            Ok(author) => author,
+            Err(e) => {
+                warn!("Failed to look up author of {}: {}", object_id, e);
+                None
+            }
+        };
+        let page = html::object_page(&object_doc, author.as_ref(), &domain, &state);
+        return Ok(html::vary_accept(with_last_modified(page, modified)));
+    }
+
+    let mut response = (
+        StatusCode::OK,
+        [("Content-Type", "application/activity+json")],
+        Json(object_json(&object_doc, &domain, &state)),
+    )
+        .into_response();
+    // Shared caches must not keep objects that are not public
+    if !is_public {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    }
+    Ok(html::vary_accept(with_last_modified(response, modified)))
+}
+
+/// ActivityStreams JSON of a stored object, as served to other servers
+///
+/// Media URLs are rewritten to the local media proxy when enabled.
+pub(crate) fn object_json(object_doc: &ObjectDocument, domain: &str, state: &AppState) -> Value {
+    let mut object_json = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams", extensions::context()],
         "type": format!("{:?}", object_doc.object_type),
         "id": object_doc.object_id,
         "attributedTo": object_doc.attributed_to,
+        "name": object_doc.name,
+        "nameMap": object_doc.name_map,
         "content": object_doc.content,
+        "contentMap": object_doc.content_map,
         "summary": object_doc.summary,
+        "summaryMap": object_doc.summary_map,
+        "url": object_doc.url,
         "published": object_doc.published.unwrap_or(object_doc.created_at).to_rfc3339(),
+        "updated": object_doc.updated.map(|updated| updated.to_rfc3339()),
         "to": object_doc.to,
         "cc": object_doc.cc,
         "inReplyTo": object_doc.in_reply_to,
         "conversation": object_doc.conversation,
         "sensitive": object_doc.sensitive,
         "tag": object_doc.tag,
-        "attachment": object_doc.attachment
+        "attachment": render_attachments(object_doc.attachment.as_deref(), domain, state),
+        "image": render_attachments(object_doc.image.as_ref().map(std::slice::from_ref), domain, state)
+            .and_then(|images| images.into_iter().next())
     });
+    object_doc.write_quote_properties(&mut object_json);
+    if let Some(event) = &object_doc.event {
+        event.write_json(&mut object_json);
+    }
+    object_json
+}
 
-    Ok((
+/// Get the earlier versions of an edited object, newest first
+///
+/// The history is visible to whoever may see the object.
+async fn get_object_history(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    signer: Option<Extension<VerifiedSigner>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let domain = extract_domain_from_headers(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let object_id = format!("https://{}/objects/{}", domain, id);
+
+    let object_doc = match state.db_manager.find_object_by_id(&object_id).await {
+        Ok(Some(obj))
+            if obj.status != ObjectStatus::Scheduled
+                && obj.object_type != ObjectType::Tombstone =>
+        {
+            obj
+        }
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to get object: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let signer = signer.map(|Extension(signer)| signer);
+    let viewer = viewer_of(signer.as_ref(), &headers, &domain, &state).await;
+    match may_see(&state.db_manager, &object_doc, viewer.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to check access to {}: {}", object_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let revisions = state
+        .db_manager
+        .find_object_revisions(&object_id, 100)
+        .await
+        .map_err(|e| {
+            error!("Failed to get history of {}: {}", object_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let items: Vec<Value> = revisions
+        .iter()
+        .map(|revision| revision.to_activitypub(&object_doc))
+        .collect();
+
+    let history = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "OrderedCollection",
+        "id": format!("{}/history", object_id),
+        "totalItems": items.len(),
+        "orderedItems": items
+    });
+    let mut response = (
         StatusCode::OK,
         [("Content-Type", "application/activity+json")],
-        Json(object_json),
+        Json(history),
     )
-        .into_response())
+        .into_response();
+    if !html::is_public(&object_doc) {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    }
+    let modified = object_doc
+        .updated
+        .or(object_doc.published)
+        .unwrap_or(object_doc.created_at);
+    Ok(with_last_modified(response, modified))
+}
+
+/// Get the accepted attendees of an event
+///
+/// The collection is visible to whoever may see the event.
+async fn get_object_participants(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    signer: Option<Extension<VerifiedSigner>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let domain = extract_domain_from_headers(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let object_id = format!("https://{}/objects/{}", domain, id);
+
+    let object_doc = match state.db_manager.find_object_by_id(&object_id).await {
+        Ok(Some(obj)) if obj.status != ObjectStatus::Scheduled && obj.event.is_some() => obj,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to get object: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let signer = signer.map(|Extension(signer)| signer);
+    let viewer = viewer_of(signer.as_ref(), &headers, &domain, &state).await;
+    match may_see(&state.db_manager, &object_doc, viewer.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to check access to {}: {}", object_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let participants = state
+        .db_manager
+        .find_participants(&object_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to get participants of {}: {}", object_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let collection = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "OrderedCollection",
+        "id": events::participants_url(&object_id),
+        "totalItems": participants.len(),
+        "orderedItems": participants
+    });
+    let mut response = (
+        StatusCode::OK,
+        [("Content-Type", "application/activity+json")],
+        Json(collection),
+    )
+        .into_response();
+    if !html::is_public(&object_doc) {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    }
+    Ok(response)
+}
+
+/// Actor making a request
+///
+/// That is the signer of a signed request, or the local user of a bearer
+/// token.
+async fn viewer_of(
+    signer: Option<&VerifiedSigner>,
+    headers: &HeaderMap,
+    domain: &str,
+    state: &AppState,
+) -> Option<String> {
+    if let Some(signer) = signer {
+        return Some(signer.owner.clone());
+    }
+    let username = authenticated_username(headers, "read", state).await?;
+    Some(format!("https://{}/users/{}", domain, username))
+}
+
+/// Whether an actor may see an object
+///
+/// Public and unlisted objects are visible to everyone, also without a
+/// `viewer`. Followers-only objects are visible to their recipients and the
+/// accepted followers of their author, direct objects to their recipients
+/// only.
+async fn may_see(
+    db: &DatabaseManager,
+    object: &ObjectDocument,
+    viewer: Option<&str>,
+) -> Result<bool, DatabaseError> {
+    if html::is_public(object) {
+        return Ok(true);
+    }
+    let Some(viewer) = viewer else {
+        return Ok(false);
+    };
+    if viewer == object.attributed_to || object.is_addressed_to(viewer) {
+        return Ok(true);
+    }
+    if object.visibility == VisibilityLevel::Direct {
+        return Ok(false);
+    }
+    let follow = db.find_follow(viewer, &object.attributed_to).await?;
+    Ok(follow.is_some_and(|follow| follow.status == FollowStatus::Accepted))
 }
 
 /// Get individual activity
@@ -872,22 +1543,10 @@ async fn get_activity(
         }
     };
 
-    let activity_json = json!({
-        "@context": "https://www.w3.org/ns/activitystreams",
-        "type": format!("{:?}", activity_doc.activity_type),
-        "id": activity_doc.activity_id,
-        "actor": activity_doc.actor,
-        "object": activity_doc.object,
-        "target": activity_doc.target,
-        "published": activity_doc.published.unwrap_or(activity_doc.created_at).to_rfc3339(),
-        "to": activity_doc.to,
-        "cc": activity_doc.cc
-    });
-
     Ok((
         StatusCode::OK,
         [("Content-Type", "application/activity+json")],
-        Json(activity_json),
+        Json(activity_doc.to_activitypub()),
     )
         .into_response())
 }
@@ -936,41 +1595,70 @@ async fn get_nodeinfo(
         .into_response())
 }
 
-/// Verify HTTP signature (warn-only mode: logs signature presence but accepts all requests)
-async fn verify_http_signature(headers: &HeaderMap, _state: &AppState) -> Result<(), String> {
-    if headers.get("signature").is_some() || headers.get("signature-input").is_some() {
-        warn!("HTTP signature present but verification not yet wired up - accepting request");
-    } else {
-        warn!("No HTTP signature on incoming S2S request - accepting without verification");
-    }
-    Ok(())
-}
-
-/// Process incoming activity for a specific user
-async fn process_incoming_activity(
-    activity: &Activity,
-    actor: &ActorDocument,
+/// Forward a Flag activity to the incoming pipeline for moderation
+async fn forward_flag_activity(
+    activity_json: &Value,
     state: &AppState,
     domain: &str,
-    username: &str,
-) -> Result<(), String> {
-    info!(
-        "Processing {:?} activity for {}",
-        activity.activity_type, actor.actor_id
-    );
-
+    username: Option<&str>,
+) -> Result<Response, StatusCode> {
+    let actor_id = activity_json
+        .get("actor")
+        .and_then(|a| a.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    info!("Received Flag from {} for domain {}", actor_id, domain);
+
+    match crate::rabbitmq::publish_incoming_activity_to_exchange(
+        &state.mq_pool,
+        state.domain_routing,
+        activity_json,
+        "Flag",
+        actor_id,
+        domain,
+        username,
+        None,
+    )
+    .await
+    {
+        Ok(_) => Ok(StatusCode::ACCEPTED.into_response()),
+        Err(e) => {
+            error!("Failed to forward Flag activity: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Process incoming activity for a specific user
+async fn process_incoming_activity(
+    activity: &Activity,
+    actor: &ActorDocument,
+    signer: Option<&VerifiedSigner>,
+    state: &AppState,
+    domain: &str,
+    username: &str,
+) -> Result<(), String> {
+    info!(
+        "Processing {:?} activity for {}",
+        activity.activity_type, actor.actor_id
+    );
+
     match activity.activity_type {
         ActivityType::Follow => handle_follow_activity(activity, actor, state).await,
         ActivityType::Undo => handle_undo_activity(activity, actor, state).await,
         ActivityType::Create => {
             handle_create_activity(activity, actor, state, domain, Some(username)).await
         }
-        ActivityType::Update => handle_update_activity(activity, actor, state).await,
+        ActivityType::Update => handle_update_activity(activity, actor, signer, state).await,
         ActivityType::Delete => handle_delete_activity(activity, actor, state).await,
         ActivityType::Like => handle_like_activity(activity, actor, state).await,
-        ActivityType::Announce => handle_announce_activity(activity, actor, state).await,
+        ActivityType::Announce => handle_announce_activity(activity, actor, state, domain).await,
+        ActivityType::Block => handle_block_activity(activity, actor, state).await,
         ActivityType::Accept => handle_accept_s2s_activity(activity, actor, state).await,
         ActivityType::Reject => handle_reject_s2s_activity(activity, actor, state).await,
+        ActivityType::Join | ActivityType::Leave => {
+            handle_rsvp_activity(activity, state).await.map(|_| ())
+        }
         _ => {
             warn!("Unhandled activity type: {:?}", activity.activity_type);
             Ok(())
@@ -993,10 +1681,25 @@ async fn process_shared_inbox_activity(
     // TODO: Implement proper routing based on activity addressing
     debug!("Processing activity ID: {:?}", activity.id);
 
+    // RSVPs are answered right away, as in the inbox of the organizer
+    handle_rsvp_activity(activity, state).await?;
+
     // Send the activity to the incoming processing exchange instead of storing directly
     let activity_json = serde_json::to_value(activity)
         .map_err(|e| format!("Failed to serialize activity: {}", e))?;
 
+    // storaged records the boost; the boosted post has to reach it too,
+    // as has a quoted post before the post quoting it
+    match activity.activity_type {
+        ActivityType::Announce => resolve_boosted_object(&activity_json, state, domain).await,
+        ActivityType::Create => {
+            if let Some(object) = activity_json.get("object").filter(|o| o.is_object()) {
+                resolve_quoted_object(object, state, domain).await;
+            }
+        }
+        _ => {}
+    }
+
     let actor_id = activity
         .actor
         .as_ref()
@@ -1008,6 +1711,7 @@ async fn process_shared_inbox_activity(
 
     crate::rabbitmq::publish_incoming_activity_to_exchange(
         &state.mq_pool,
+        state.domain_routing,
         &activity_json,
         &format!("{:?}", activity.activity_type),
         actor_id,
@@ -1039,6 +1743,15 @@ async fn handle_follow_activity(
         follower, target_actor.actor_id
     );
 
+    // Remember where to deliver the target's posts to the follower
+    let (follower_inbox, follower_shared_inbox) = match oxifed::client::ActivityPubClient::new() {
+        Ok(client) => delivery::fetch_inboxes(&client, follower).await.unzip(),
+        Err(e) => {
+            warn!("Failed to create client to look up {}: {}", follower, e);
+            (None, None)
+        }
+    };
+
     // Create follow relationship
     let follow_doc = FollowDocument {
         id: None,
@@ -1054,6 +1767,8 @@ async fn handle_follow_activity(
         accept_activity_id: None,
         created_at: Utc::now(),
         responded_at: None,
+        follower_inbox,
+        follower_shared_inbox: follower_shared_inbox.flatten(),
     };
 
     state
@@ -1062,6 +1777,13 @@ async fn handle_follow_activity(
         .await
         .map_err(|e| format!("Failed to store follow: {}", e))?;
 
+    if let Err(e) = state.db_manager.notify_follow(follower, target_actor).await {
+        warn!(
+            "Failed to notify {} of follow: {}",
+            target_actor.actor_id, e
+        );
+    }
+
     // Auto-accept for now (TODO: Check actor preferences)
     // Create Accept activity (convert Activity back to JSON for response)
     let activity_json = serde_json::to_value(activity)
@@ -1100,23 +1822,43 @@ async fn handle_accept_s2s_activity(
     local_actor: &ActorDocument,
     state: &AppState,
 ) -> Result<(), String> {
+    if handle_rsvp_activity(activity, state).await? {
+        return Ok(());
+    }
     let (follower, following) = extract_follow_from_response(activity, local_actor, state).await?;
+    // Only the followed actor answers a follow request
+    if activity_actor(activity)? != following {
+        return Err(format!(
+            "{} cannot answer a follow of {}",
+            activity_actor(activity)?,
+            following
+        ));
+    }
 
     info!(
         "Processing Accept: {} accepted follow from {}",
         following, follower
     );
 
-    state
+    let answered = state
         .db_manager
-        .update_follow_status(&follower, &following, FollowStatus::Accepted)
+        .answer_follow(
+            &follower,
+            &following,
+            FollowStatus::Accepted,
+            activity.id.as_ref().map(Url::as_str),
+        )
         .await
         .map_err(|e| format!("Failed to update follow status to Accepted: {}", e))?;
 
-    info!(
-        "Follow status updated to Accepted for {} -> {}",
-        follower, following
-    );
+    if answered {
+        info!(
+            "Follow status updated to Accepted for {} -> {}",
+            follower, following
+        );
+    } else {
+        warn!("No follow of {} by {} to accept", following, follower);
+    }
 
     Ok(())
 }
@@ -1125,32 +1867,76 @@ async fn handle_accept_s2s_activity(
 ///
 /// When we send a Follow to a remote actor and they reject it,
 /// they send a Reject activity back to our user's inbox.
+/// The local follower is notified of the rejection.
 async fn handle_reject_s2s_activity(
     activity: &Activity,
     local_actor: &ActorDocument,
     state: &AppState,
 ) -> Result<(), String> {
+    if handle_rsvp_activity(activity, state).await? {
+        return Ok(());
+    }
     let (follower, following) = extract_follow_from_response(activity, local_actor, state).await?;
+    // Only the followed actor answers a follow request
+    if activity_actor(activity)? != following {
+        return Err(format!(
+            "{} cannot answer a follow of {}",
+            activity_actor(activity)?,
+            following
+        ));
+    }
 
     info!(
         "Processing Reject: {} rejected follow from {}",
         following, follower
     );
 
-    state
+    let answered = state
         .db_manager
-        .update_follow_status(&follower, &following, FollowStatus::Rejected)
+        .answer_follow(&follower, &following, FollowStatus::Rejected, None)
         .await
         .map_err(|e| format!("Failed to update follow status to Rejected: {}", e))?;
 
-    info!(
-        "Follow status updated to Rejected for {} -> {}",
-        follower, following
-    );
+    if answered {
+        info!(
+            "Follow status updated to Rejected for {} -> {}",
+            follower, following
+        );
+    } else {
+        warn!("No follow of {} by {} to reject", following, follower);
+    }
 
     Ok(())
 }
 
+/// Handle a `Join` or `Leave` of an event, or an `Accept` or `Reject` of
+/// a `Join`
+///
+/// Returns whether the activity was an RSVP; other activities are left
+/// alone.
+async fn handle_rsvp_activity(activity: &Activity, state: &AppState) -> Result<bool, String> {
+    if !matches!(
+        activity.activity_type,
+        ActivityType::Join | ActivityType::Leave | ActivityType::Accept | ActivityType::Reject
+    ) {
+        return Ok(false);
+    }
+    let activity_json = serde_json::to_value(activity)
+        .map_err(|e| format!("Failed to serialize activity: {}", e))?;
+    let db = &state.db_manager;
+    match activity.activity_type {
+        ActivityType::Join => events::join(db, &activity_json).await?,
+        ActivityType::Leave => events::leave(db, &activity_json).await?,
+        ActivityType::Accept => {
+            return events::answer_join(db, &activity_json, ParticipationStatus::Accepted).await;
+        }
+        _ => {
+            return events::answer_join(db, &activity_json, ParticipationStatus::Rejected).await;
+        }
+    }
+    Ok(true)
+}
+
 /// Extract the follower and following actor IDs from an Accept/Reject activity.
 ///
 /// The Accept/Reject object can be either:
@@ -1294,6 +2080,11 @@ async fn handle_undo_activity(
                                 .map_err(|e| format!("Failed to update follow status: {}", e))?;
                         }
                     }
+                    "Like" | "Announce" | "Block" => {
+                        let undone = serde_json::to_value(obj)
+                            .map_err(|e| format!("Failed to serialize undo object: {}", e))?;
+                        undo_interaction(activity_actor(activity)?, &undone, state).await?;
+                    }
                     _ => {
                         warn!("Unhandled undo object type: {}", object_type);
                     }
@@ -1301,8 +2092,7 @@ async fn handle_undo_activity(
             }
         }
         oxifed::ObjectOrLink::Url(url) => {
-            // Handle URL reference - would need to fetch the object
-            warn!("Undo with URL reference not yet implemented: {}", url);
+            undo_interaction(activity_actor(activity)?, &json!(url.as_str()), state).await?;
         }
         _ => {
             warn!("Unhandled undo object format");
@@ -1316,6 +2106,84 @@ async fn handle_undo_activity(
     Ok(())
 }
 
+/// Actor sending an incoming activity
+fn activity_actor(activity: &Activity) -> Result<&str, String> {
+    match activity.actor.as_ref() {
+        Some(oxifed::ObjectOrLink::Url(url)) => Ok(url.as_str()),
+        _ => Err(format!(
+            "{:?} activity must have an actor",
+            activity.activity_type
+        )),
+    }
+}
+
+/// Withdraw the Like, Announce or Block an Undo refers to
+///
+/// `undone` is the embedded activity or the ID of a stored one. Only the
+/// actor who performed an activity may undo it.
+async fn undo_interaction(actor_id: &str, undone: &Value, state: &AppState) -> Result<(), String> {
+    let (activity_type, actor, object) = match undone {
+        Value::String(activity_id) => {
+            let Some(stored) = state
+                .db_manager
+                .find_activity_by_id(activity_id)
+                .await
+                .map_err(|e| format!("Failed to look up activity {}: {}", activity_id, e))?
+            else {
+                warn!("Undo of unknown activity {}", activity_id);
+                return Ok(());
+            };
+            let Some(object) = stored.object else {
+                warn!("Undo of activity {} without object", activity_id);
+                return Ok(());
+            };
+            (stored.activity_type, stored.actor, object)
+        }
+        _ => {
+            let activity_type = undone
+                .get("type")
+                .cloned()
+                .and_then(|t| serde_json::from_value::<ActivityType>(t).ok())
+                .ok_or("Undone activity must have a type")?;
+            // The Undo's own actor stands in when the embedded copy omits it
+            let actor = undone
+                .get("actor")
+                .and_then(Value::as_str)
+                .unwrap_or(actor_id)
+                .to_string();
+            let object = undone
+                .get("object")
+                .and_then(|object| object.as_str().or_else(|| object.get("id")?.as_str()))
+                .ok_or("Undone activity must have an object")?
+                .to_string();
+            (activity_type, actor, object)
+        }
+    };
+
+    if actor != actor_id {
+        return Err(format!("{} cannot undo an activity of {}", actor_id, actor));
+    }
+    if !matches!(
+        activity_type,
+        ActivityType::Like | ActivityType::Announce | ActivityType::Block
+    ) {
+        warn!("Unhandled undo of {:?} activity", activity_type);
+        return Ok(());
+    }
+
+    let removed = state
+        .db_manager
+        .undo_activity(activity_type.clone(), &actor, &object)
+        .await
+        .map_err(|e| format!("Failed to undo {:?}: {}", activity_type, e))?;
+    if removed {
+        info!("{} withdrew {:?} of {}", actor, activity_type, object);
+    } else {
+        debug!("No {:?} of {} by {} to undo", activity_type, object, actor);
+    }
+    Ok(())
+}
+
 /// Handle Create activity
 async fn handle_create_activity(
     activity: &Activity,
@@ -1340,6 +2208,7 @@ async fn handle_create_activity(
                         );
                         let object_json = serde_json::to_value(obj)
                             .map_err(|e| format!("Failed to serialize object: {}", e))?;
+                        resolve_quoted_object(&object_json, state, domain).await;
 
                         let attributed_to = object_json
                             .get("attributedTo")
@@ -1348,6 +2217,7 @@ async fn handle_create_activity(
 
                         crate::rabbitmq::publish_incoming_object_to_exchange(
                             &state.mq_pool,
+                            state.domain_routing,
                             &object_json,
                             object_type,
                             attributed_to,
@@ -1390,6 +2260,7 @@ async fn handle_create_activity(
 
     crate::rabbitmq::publish_incoming_activity_to_exchange(
         &state.mq_pool,
+        state.domain_routing,
         &activity_json,
         &format!("{:?}", activity.activity_type),
         actor_id,
@@ -1402,15 +2273,150 @@ async fn handle_create_activity(
 }
 
 /// Handle Update activity
+///
+/// Updates of an actor refresh its stored profile and keys; updates of a
+/// known object replace its content and keep the previous version. Either
+/// is only applied for the verified signer of the request, who must be the
+/// actor or the author of the object.
 async fn handle_update_activity(
     activity: &Activity,
     actor: &ActorDocument,
+    signer: Option<&VerifiedSigner>,
     state: &AppState,
 ) -> Result<(), String> {
-    info!("Processing update activity from {}", actor.actor_id);
+    info!("Processing update activity for {}", actor.actor_id);
+
+    if let Some(oxifed::ObjectOrLink::Object(obj)) = &activity.object {
+        let Some(signer) = signer else {
+            warn!("Not applying unsigned update {:?}", activity.id);
+            return store_activity_struct(activity, state).await;
+        };
+        let signer = signer.owner.as_str();
+        let object = serde_json::to_value(obj)
+            .map_err(|e| format!("Failed to serialize update object: {}", e))?;
+        let object_type = object
+            .get("type")
+            .cloned()
+            .and_then(|t| serde_json::from_value::<ObjectType>(t).ok())
+            .unwrap_or(ObjectType::Other);
+        match object_type {
+            ObjectType::Person
+            | ObjectType::Service
+            | ObjectType::Application
+            | ObjectType::Group
+            | ObjectType::Organization => refresh_remote_actor(signer, &object, state).await?,
+            ObjectType::Other | ObjectType::Tombstone => {
+                warn!("Unhandled update object type: {:?}", object.get("type"))
+            }
+            object_type => edit_remote_object(signer, &object, object_type, state).await?,
+        }
+    }
+
     store_activity_struct(activity, state).await
 }
 
+/// Store the profile an actor sent in an Update
+///
+/// Only the actor itself, as the verified `signer`, updates its profile.
+/// Cached copies of its keys are dropped so signatures are checked against
+/// the announced ones.
+async fn refresh_remote_actor(
+    signer: &str,
+    object: &Value,
+    state: &AppState,
+) -> Result<(), String> {
+    let mut profile = ActorDocument::from_activitypub(object).ok_or("Invalid actor in update")?;
+    if profile.actor_id != signer {
+        return Err(format!(
+            "{} cannot update actor {}",
+            signer, profile.actor_id
+        ));
+    }
+
+    let stored = state
+        .db_manager
+        .find_actor_by_id(&profile.actor_id)
+        .await
+        .map_err(|e| format!("Failed to look up actor {}: {}", profile.actor_id, e))?;
+    if let Some(stored) = &stored {
+        if stored.local {
+            return Err(format!(
+                "Refusing update of local actor {}",
+                stored.actor_id
+            ));
+        }
+        profile.created_at = stored.created_at;
+        if let Some(key) = &stored.public_key {
+            state.signatures.invalidate_key(&key.id);
+        }
+    }
+    if let Some(key) = &profile.public_key {
+        state.signatures.invalidate_key(&key.id);
+    }
+
+    let actor_id = profile.actor_id.clone();
+    state
+        .db_manager
+        .upsert_remote_actor(profile)
+        .await
+        .map_err(|e| format!("Failed to store actor {}: {}", actor_id, e))?;
+    info!("Refreshed profile of {}", actor_id);
+    Ok(())
+}
+
+/// Apply an edit of a known remote object
+///
+/// Only the stored author, as the verified `signer`, edits an object, and
+/// the edit keeps the author. Edits older than the stored version are
+/// ignored.
+async fn edit_remote_object(
+    signer: &str,
+    object: &Value,
+    object_type: ObjectType,
+    state: &AppState,
+) -> Result<(), String> {
+    let object_id = object
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or("Updated object must have an id")?;
+    let Some(current) = state
+        .db_manager
+        .find_object_by_id(object_id)
+        .await
+        .map_err(|e| format!("Failed to look up object {}: {}", object_id, e))?
+    else {
+        debug!("Update of unknown object {}", object_id);
+        return Ok(());
+    };
+    if current.object_type == ObjectType::Tombstone {
+        debug!("Update of deleted object {}", object_id);
+        return Ok(());
+    }
+    if current.local || current.attributed_to != signer {
+        return Err(format!("{} cannot update object {}", signer, object_id));
+    }
+
+    let edited = ObjectDocument::from_activitypub(object, object_type);
+    if edited.attributed_to != current.attributed_to {
+        return Err(format!("Update of {} cannot change its author", object_id));
+    }
+    let previous = current.updated.or(current.published);
+    if let (Some(edited), Some(previous)) = (edited.updated, previous)
+        && edited <= previous
+    {
+        debug!("Ignoring outdated update of {}", object_id);
+        return Ok(());
+    }
+
+    state
+        .db_manager
+        .revise_object(&current, &edited)
+        .await
+        .map_err(|e| format!("Failed to update object {}: {}", object_id, e))?;
+    info!("Applied edit of {}", object_id);
+    Ok(())
+}
+
 /// Handle Delete activity
 async fn handle_delete_activity(
     activity: &Activity,
@@ -1428,16 +2434,120 @@ async fn handle_like_activity(
     state: &AppState,
 ) -> Result<(), String> {
     info!("Processing like activity from {}", actor.actor_id);
-    store_activity_struct(activity, state).await
+    store_activity_struct(activity, state).await?;
+    if let Some(oxifed::ObjectOrLink::Url(object)) = &activity.object {
+        count_interaction(&ActivityType::Like, object.as_str(), state).await?;
+        notify_favourite(activity_actor(activity)?, object.as_str(), state).await;
+    }
+    Ok(())
 }
 
 /// Handle Announce activity
+///
+/// Records the boost, fetching the boosted post if it is not stored yet.
 async fn handle_announce_activity(
     activity: &Activity,
     actor: &ActorDocument,
     state: &AppState,
+    domain: &str,
 ) -> Result<(), String> {
     info!("Processing announce activity from {}", actor.actor_id);
+    store_activity_struct(activity, state).await?;
+    let announce = serde_json::to_value(activity)
+        .map_err(|e| format!("Failed to serialize activity: {}", e))?;
+    resolve_boosted_object(&announce, state, domain).await;
+    state
+        .db_manager
+        .apply_boost_activity(&announce)
+        .await
+        .map_err(|e| format!("Failed to record boost: {}", e))?;
+    Ok(())
+}
+
+/// Fetch the post an Announce boosts into the incoming pipeline unless it
+/// is stored already
+///
+/// The boost is recorded either way, so failures are only logged; the post
+/// shows up in timelines once stored.
+async fn resolve_boosted_object(announce: &Value, state: &AppState, domain: &str) {
+    let object = announce.get("object");
+    let Some(object_id) =
+        object.and_then(|object| object.as_str().or_else(|| object.get("id")?.as_str()))
+    else {
+        return;
+    };
+    resolve_object(object_id, "boosted", state, domain).await;
+}
+
+/// Fetch the post a created post quotes into the incoming pipeline unless
+/// it is stored already
+///
+/// Resolving it before the quote is published lets storaged check the
+/// quote policy and count the quote when storing the quoting post.
+async fn resolve_quoted_object(object: &Value, state: &AppState, domain: &str) {
+    if let Some(quote) = Extensions::from_json(object).quote {
+        resolve_object(quote.as_str(), "quoted", state, domain).await;
+    }
+}
+
+/// Fetch a post into the incoming pipeline unless it is stored already
+///
+/// `role` names the post in logs; failures are only logged.
+async fn resolve_object(object_id: &str, role: &str, state: &AppState, domain: &str) {
+    match state.db_manager.find_object_by_id(object_id).await {
+        Ok(Some(_)) => return,
+        Ok(None) => {}
+        Err(e) => {
+            warn!("Failed to look up {} object {}: {}", role, object_id, e);
+            return;
+        }
+    }
+
+    let fetched = async {
+        let url = Url::parse(object_id).map_err(|e| e.to_string())?;
+        let client = oxifed::client::ActivityPubClient::new().map_err(|e| e.to_string())?;
+        let object = client
+            .fetch_object(&url)
+            .await
+            .map_err(|e| format!("Failed to fetch: {}", e))?;
+        let object = serde_json::to_value(object).map_err(|e| e.to_string())?;
+        let object_type = object
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or("Object")
+            .to_string();
+        let attributed_to = object
+            .get("attributedTo")
+            .and_then(Value::as_str)
+            .ok_or("Object without author")?
+            .to_string();
+        crate::rabbitmq::publish_incoming_object_to_exchange(
+            &state.mq_pool,
+            state.domain_routing,
+            &object,
+            &object_type,
+            &attributed_to,
+            domain,
+            None,
+            None,
+        )
+        .await
+        .map_err(|e| format!("Failed to publish: {}", e))
+    }
+    .await;
+    match fetched {
+        Ok(()) => debug!("Fetched {} object {}", role, object_id),
+        Err(e) => warn!("Failed to resolve {} object {}: {}", role, object_id, e),
+    }
+}
+
+/// Handle Block activity
+async fn handle_block_activity(
+    activity: &Activity,
+    actor: &ActorDocument,
+    state: &AppState,
+) -> Result<(), String> {
+    info!("Processing block activity for {}", actor.actor_id);
     store_activity_struct(activity, state).await
 }
 
@@ -1495,8 +2605,8 @@ async fn store_activity_struct(activity: &Activity, state: &AppState) -> Result<
     };
 
     state
-        .db_manager
-        .insert_activity(activity_doc)
+        .activity_writer
+        .write(activity_doc)
         .await
         .map_err(|e| format!("Failed to store activity: {}", e))?;
 
@@ -1505,57 +2615,11 @@ async fn store_activity_struct(activity: &Activity, state: &AppState) -> Result<
 
 /// Store activity in database (from JSON Value - legacy)
 async fn store_activity(activity: &Value, state: &AppState) -> Result<(), String> {
-    let activity_doc = ActivityDocument {
-        id: None,
-        activity_id: activity
-            .get("id")
-            .and_then(|id| id.as_str())
-            .unwrap_or(&format!("unknown-{}", Uuid::new_v4()))
-            .to_string(),
-        activity_type: parse_activity_type(activity.get("type")),
-        actor: activity
-            .get("actor")
-            .and_then(|a| a.as_str())
-            .unwrap_or("unknown")
-            .to_string(),
-        object: activity
-            .get("object")
-            .and_then(|o| o.as_str())
-            .map(|s| s.to_string()),
-        target: activity
-            .get("target")
-            .and_then(|t| t.as_str())
-            .map(|s| s.to_string()),
-        name: activity
-            .get("name")
-            .and_then(|n| n.as_str())
-            .map(|s| s.to_string()),
-        summary: activity
-            .get("summary")
-            .and_then(|s| s.as_str())
-            .map(|s| s.to_string()),
-        published: activity
-            .get("published")
-            .and_then(|p| p.as_str())
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc)),
-        updated: None,
-        to: extract_string_array(activity.get("to")),
-        cc: extract_string_array(activity.get("cc")),
-        bto: extract_string_array(activity.get("bto")),
-        bcc: extract_string_array(activity.get("bcc")),
-        additional_properties: None,
-        local: false,
-        status: ActivityStatus::Completed,
-        created_at: Utc::now(),
-        attempts: 0,
-        last_attempt: None,
-        error: None,
-    };
+    let activity_doc = ActivityDocument::from_activitypub(activity);
 
     state
-        .db_manager
-        .insert_activity(activity_doc)
+        .activity_writer
+        .write(activity_doc)
         .await
         .map_err(|e| format!("Failed to store activity: {}", e))?;
 
@@ -1563,372 +2627,140 @@ async fn store_activity(activity: &Value, state: &AppState) -> Result<(), String
 }
 
 /// Store note object in database
-async fn store_note_object(object: &Value, state: &AppState) -> Result<(), String> {
-    let object_doc = ObjectDocument {
-        id: None,
-        object_id: object
-            .get("id")
-            .and_then(|id| id.as_str())
-            .unwrap_or(&format!("unknown-{}", Uuid::new_v4()))
-            .to_string(),
-        object_type: ObjectType::Note,
-        attributed_to: object
-            .get("attributedTo")
-            .and_then(|a| a.as_str())
-            .unwrap_or("unknown")
-            .to_string(),
-        content: object
-            .get("content")
-            .and_then(|c| c.as_str())
-            .map(|s| s.to_string()),
-        summary: object
-            .get("summary")
-            .and_then(|s| s.as_str())
-            .map(|s| s.to_string()),
-        name: object
-            .get("name")
-            .and_then(|n| n.as_str())
-            .map(|s| s.to_string()),
-        media_type: Some("text/html".to_string()),
-        url: object
-            .get("url")
-            .and_then(|u| u.as_str())
-            .map(|s| s.to_string()),
-        published: object
-            .get("published")
-            .and_then(|p| p.as_str())
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc)),
-        updated: object
-            .get("updated")
-            .and_then(|u| u.as_str())
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc)),
-        to: extract_string_array(object.get("to")),
-        cc: extract_string_array(object.get("cc")),
-        bto: extract_string_array(object.get("bto")),
-        bcc: extract_string_array(object.get("bcc")),
-        audience: extract_string_array(object.get("audience")),
-        in_reply_to: object
-            .get("inReplyTo")
-            .and_then(|r| r.as_str())
-            .map(|s| s.to_string()),
-        conversation: object
-            .get("conversation")
-            .and_then(|c| c.as_str())
-            .map(|s| s.to_string()),
-        tag: None,        // TODO: Parse tags
-        attachment: None, // TODO: Parse attachments
-        language: object
-            .get("language")
-            .and_then(|l| l.as_str())
-            .map(|s| s.to_string()),
-        sensitive: object.get("sensitive").and_then(|s| s.as_bool()),
-        additional_properties: None,
-        local: false,
-        visibility: VisibilityLevel::Public, // TODO: Determine visibility
-        created_at: Utc::now(),
-        reply_count: 0,
-        like_count: 0,
-        announce_count: 0,
-    };
+async fn store_note_object(
+    object: &Value,
+    status: ObjectStatus,
+    state: &AppState,
+) -> Result<(), String> {
+    let mut object_doc = ObjectDocument::from_activitypub(object, ObjectType::Note);
+    object_doc.status = status;
 
-    state
-        .db_manager
-        .insert_object(object_doc)
+    let stored = state
+        .object_writer
+        .write(object_doc.clone())
         .await
         .map_err(|e| format!("Failed to store note object: {}", e))?;
 
+    // The recipients of a redelivered post were notified the first time
+    if stored && object_doc.status == ObjectStatus::Published {
+        notify_recipients(&object_doc, state).await;
+    }
     Ok(())
 }
 
 /// Store article object in database
-async fn store_article_object(object: &Value, state: &AppState) -> Result<(), String> {
-    let object_doc = ObjectDocument {
-        id: None,
-        object_id: object
-            .get("id")
-            .and_then(|id| id.as_str())
-            .unwrap_or(&format!("unknown-{}", Uuid::new_v4()))
-            .to_string(),
-        object_type: ObjectType::Article,
-        attributed_to: object
-            .get("attributedTo")
-            .and_then(|a| a.as_str())
-            .unwrap_or("unknown")
-            .to_string(),
-        content: object
-            .get("content")
-            .and_then(|c| c.as_str())
-            .map(|s| s.to_string()),
-        summary: object
-            .get("summary")
-            .and_then(|s| s.as_str())
-            .map(|s| s.to_string()),
-        name: object
-            .get("name")
-            .and_then(|n| n.as_str())
-            .map(|s| s.to_string()),
-        media_type: Some("text/html".to_string()),
-        url: object
-            .get("url")
-            .and_then(|u| u.as_str())
-            .map(|s| s.to_string()),
-        published: object
-            .get("published")
-            .and_then(|p| p.as_str())
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc)),
-        updated: object
-            .get("updated")
-            .and_then(|u| u.as_str())
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc)),
-        to: extract_string_array(object.get("to")),
-        cc: extract_string_array(object.get("cc")),
-        bto: extract_string_array(object.get("bto")),
-        bcc: extract_string_array(object.get("bcc")),
-        audience: extract_string_array(object.get("audience")),
-        in_reply_to: object
-            .get("inReplyTo")
-            .and_then(|r| r.as_str())
-            .map(|s| s.to_string()),
-        conversation: object
-            .get("conversation")
-            .and_then(|c| c.as_str())
-            .map(|s| s.to_string()),
-        tag: None,        // TODO: Parse tags
-        attachment: None, // TODO: Parse attachments
-        language: object
-            .get("language")
-            .and_then(|l| l.as_str())
-            .map(|s| s.to_string()),
-        sensitive: object.get("sensitive").and_then(|s| s.as_bool()),
-        additional_properties: None,
-        local: false,
-        visibility: VisibilityLevel::Public, // TODO: Determine visibility
-        created_at: Utc::now(),
-        reply_count: 0,
-        like_count: 0,
-        announce_count: 0,
-    };
+async fn store_article_object(
+    object: &Value,
+    status: ObjectStatus,
+    state: &AppState,
+) -> Result<(), String> {
+    let mut object_doc = ObjectDocument::from_activitypub(object, ObjectType::Article);
+    object_doc.status = status;
 
-    state
-        .db_manager
-        .insert_object(object_doc)
+    let stored = state
+        .object_writer
+        .write(object_doc.clone())
         .await
         .map_err(|e| format!("Failed to store article object: {}", e))?;
 
+    // The recipients of a redelivered post were notified the first time
+    if stored && object_doc.status == ObjectStatus::Published {
+        notify_recipients(&object_doc, state).await;
+    }
     Ok(())
 }
 
-/// Publish activity to message queue for delivery (from Activity struct)
-#[allow(dead_code)]
-pub async fn publish_activity_message_struct(
-    activity: &Activity,
+/// Store event object in database
+async fn store_event_object(
+    object: &Value,
+    status: ObjectStatus,
     state: &AppState,
 ) -> Result<(), String> {
-    let activity_json =
-        serde_json::to_vec(activity).map_err(|e| format!("Failed to serialize activity: {}", e))?;
+    let mut object_doc = ObjectDocument::from_activitypub(object, ObjectType::Event);
+    object_doc.status = status;
 
-    let conn = state
-        .mq_pool
-        .get()
-        .await
-        .map_err(|e| format!("Failed to get AMQP connection: {}", e))?;
-    let channel = conn
-        .create_channel()
-        .await
-        .map_err(|e| format!("Failed to create AMQP channel: {}", e))?;
-
-    channel
-        .basic_publish(
-            oxifed::messaging::EXCHANGE_ACTIVITYPUB_PUBLISH,
-            "",
-            lapin::options::BasicPublishOptions::default(),
-            &activity_json,
-            lapin::BasicProperties::default(),
-        )
+    let stored = state
+        .object_writer
+        .write(object_doc.clone())
         .await
-        .map_err(|e| format!("Failed to publish activity: {}", e))?;
+        .map_err(|e| format!("Failed to store event object: {}", e))?;
 
-    info!(
-        "Published activity to AMQP exchange: {:?}",
-        activity.activity_type
-    );
+    // The recipients of a redelivered post were notified the first time
+    if stored && object_doc.status == ObjectStatus::Published {
+        notify_recipients(&object_doc, state).await;
+    }
     Ok(())
 }
 
-/// Publish activity to message queue for delivery (legacy JSON version)
-async fn publish_activity_message(activity: &Value, state: &AppState) -> Result<(), String> {
-    let activity_json =
-        serde_json::to_vec(activity).map_err(|e| format!("Failed to serialize activity: {}", e))?;
+/// Notify the local recipients of a new post, logging failures
+async fn notify_recipients(object: &ObjectDocument, state: &AppState) {
+    if let Err(e) = state.db_manager.notify_recipients(object).await {
+        warn!("Failed to notify recipients of {}: {}", object.object_id, e);
+    }
+}
 
-    let conn = state
-        .mq_pool
-        .get()
-        .await
-        .map_err(|e| format!("Failed to get AMQP connection: {}", e))?;
-    let channel = conn
-        .create_channel()
+/// Store an activity and queue it for delivery in one write
+async fn store_and_publish_activity(activity: &Value, state: &AppState) -> Result<(), String> {
+    let activity_doc = ActivityDocument::from_activitypub(activity);
+
+    state
+        .db_manager
+        .insert_activity_with_outbox(activity_doc, vec![activity_outbox_message(activity)?])
         .await
-        .map_err(|e| format!("Failed to create AMQP channel: {}", e))?;
-
-    channel
-        .basic_publish(
-            oxifed::messaging::EXCHANGE_ACTIVITYPUB_PUBLISH,
-            "",
-            lapin::options::BasicPublishOptions::default(),
-            &activity_json,
-            lapin::BasicProperties::default(),
-        )
+        .map_err(|e| format!("Failed to store activity: {}", e))?;
+
+    Ok(())
+}
+
+/// Queue an activity for delivery via the message outbox
+///
+/// The outbox relay publishes it to the delivery exchange.
+async fn publish_activity_message(activity: &Value, state: &AppState) -> Result<(), String> {
+    state
+        .db_manager
+        .insert_outbox_messages(vec![activity_outbox_message(activity)?])
         .await
-        .map_err(|e| format!("Failed to publish activity: {}", e))?;
+        .map_err(|e| format!("Failed to queue activity: {}", e))?;
 
     info!(
-        "Published activity to AMQP exchange: {}",
+        "Queued activity for delivery: {}",
         activity.get("type").unwrap_or(&json!("Unknown"))
     );
     Ok(())
 }
 
-/// Parse ActivityPub activity type
-fn parse_activity_type(type_value: Option<&Value>) -> ActivityType {
-    match type_value.and_then(|t| t.as_str()) {
-        Some("Accept") => ActivityType::Accept,
-        Some("Add") => ActivityType::Add,
-        Some("Announce") => ActivityType::Announce,
-        Some("Arrive") => ActivityType::Arrive,
-        Some("Block") => ActivityType::Block,
-        Some("Create") => ActivityType::Create,
-        Some("Delete") => ActivityType::Delete,
-        Some("Dislike") => ActivityType::Dislike,
-        Some("Flag") => ActivityType::Flag,
-        Some("Follow") => ActivityType::Follow,
-        Some("Ignore") => ActivityType::Ignore,
-        Some("Invite") => ActivityType::Invite,
-        Some("Join") => ActivityType::Join,
-        Some("Leave") => ActivityType::Leave,
-        Some("Like") => ActivityType::Like,
-        Some("Listen") => ActivityType::Listen,
-        Some("Move") => ActivityType::Move,
-        Some("Offer") => ActivityType::Offer,
-        Some("Question") => ActivityType::Question,
-        Some("Reject") => ActivityType::Reject,
-        Some("Read") => ActivityType::Read,
-        Some("Remove") => ActivityType::Remove,
-        Some("TentativeReject") => ActivityType::TentativeReject,
-        Some("TentativeAccept") => ActivityType::TentativeAccept,
-        Some("Travel") => ActivityType::Travel,
-        Some("Undo") => ActivityType::Undo,
-        Some("Update") => ActivityType::Update,
-        Some("View") => ActivityType::View,
-        _ => ActivityType::Other,
-    }
-}
-
-/// Extract string array from JSON value
-fn extract_string_array(value: Option<&Value>) -> Option<Vec<String>> {
-    match value {
-        Some(Value::Array(arr)) => {
-            let strings: Vec<String> = arr
-                .iter()
-                .filter_map(|v| v.as_str())
-                .map(|s| s.to_string())
-                .collect();
-            if strings.is_empty() {
-                None
-            } else {
-                Some(strings)
-            }
-        }
-        Some(Value::String(s)) => Some(vec![s.clone()]),
-        _ => None,
-    }
-}
-
-/// Verify client authentication for C2S API
-///
-/// This function checks if the client is authenticated to post on behalf of the user.
-/// It supports Bearer token authentication and OAuth 2.0.
-async fn verify_client_authentication(
-    headers: &HeaderMap,
-    username: &str,
-    state: &AppState,
-) -> bool {
-    // Check for Authorization header
-    let auth_header = match headers.get("Authorization") {
-        Some(header) => header,
-        None => {
-            debug!("No Authorization header found");
-            return false;
-        }
-    };
-
-    let auth_str = match auth_header.to_str() {
-        Ok(s) => s,
-        Err(_) => {
-            debug!("Invalid Authorization header encoding");
-            return false;
-        }
-    };
-
-    // Parse Bearer token
-    if !auth_str.starts_with("Bearer ") {
-        debug!("Authorization header does not use Bearer scheme");
-        return false;
-    }
-
-    let token = &auth_str[7..]; // Skip "Bearer " prefix
+/// Build the outbox message publishing an activity to the delivery exchange
+fn activity_outbox_message(activity: &Value) -> Result<OutboxMessageDocument, String> {
+    let payload = serde_json::to_string(activity)
+        .map_err(|e| format!("Failed to serialize activity: {}", e))?;
 
-    // Verify token against database
-    match verify_access_token(token, username, state).await {
-        Ok(valid) => valid,
-        Err(e) => {
-            error!("Failed to verify access token: {}", e);
-            false
-        }
-    }
+    Ok(OutboxMessageDocument::new(
+        oxifed::messaging::EXCHANGE_ACTIVITYPUB_DELIVERY,
+        oxifed::messaging::DeliveryPriority::for_activity(activity).routing_key(),
+        Some("application/activity+json"),
+        payload,
+    )
+    .with_trace_context(oxifed_telemetry::current_context()))
 }
 
-/// Verify an access token for a specific user
-async fn verify_access_token(
-    token: &str,
-    username: &str,
+/// Render stored attachments as ActivityStreams JSON
+///
+/// Remote media URLs are rewritten to the local media proxy when enabled.
+fn render_attachments(
+    attachments: Option<&[AttachmentDocument]>,
+    domain: &str,
     state: &AppState,
-) -> Result<bool, String> {
-    // TODO: Implement proper OAuth token verification
-    // For now, check a simple token format: "user:{username}:token:{random}"
-
-    // In production, this should:
-    // 1. Validate the token signature (for JWT)
-    // 2. Check token expiration
-    // 3. Verify the token is associated with the correct user
-    // 4. Check token scopes for required permissions
-
-    // Temporary implementation for development
-    if token.starts_with(&format!("user:{}:token:", username)) {
-        return Ok(true);
-    }
-
-    // Check database for valid tokens
-    let filter = mongodb::bson::doc! {
-        "token": token,
-        "username": username,
-        "expires_at": { "$gt": mongodb::bson::DateTime::now() }
-    };
-
-    match state
-        .db
-        .database()
-        .collection::<mongodb::bson::Document>("access_tokens")
-        .find_one(filter)
-        .await
-    {
-        Ok(Some(_)) => Ok(true),
-        Ok(None) => Ok(false),
-        Err(e) => Err(format!("Database error: {}", e)),
-    }
+) -> Option<Vec<Value>> {
+    attachments.map(|items| {
+        items
+            .iter()
+            .map(|a| {
+                let mut value = a.to_activitypub();
+                value["url"] = json!(state.media_proxy.proxy_url(domain, &a.url));
+                value
+            })
+            .collect()
+    })
 }
 
 /// Process a client activity submitted via C2S API
@@ -1989,6 +2821,10 @@ async fn process_client_activity(
         .and_then(|t| t.as_str())
         .ok_or("Activity must have a type field")?;
 
+    // Posts published in the future are delivered by the scheduler
+    let scheduled =
+        activity_type == "Create" && activity_obj.get("object").is_some_and(is_scheduled);
+
     // Process based on activity type
     match activity_type {
         "Create" => process_create_activity_c2s(&mut activity, username, state).await?,
@@ -1999,24 +2835,66 @@ async fn process_client_activity(
         "Like" => process_like_activity_c2s(&mut activity, username, state).await?,
         "Announce" => process_announce_activity_c2s(&mut activity, username, state).await?,
         "Block" => process_block_activity_c2s(&mut activity, username, state).await?,
+        "Join" | "Leave" => process_rsvp_activity_c2s(&mut activity, username, state).await?,
+        "Accept" | "Reject" => process_join_answer_c2s(&mut activity, username, state).await?,
         _ => {
             warn!("Unsupported activity type for C2S: {}", activity_type);
             return Err(format!("Unsupported activity type: {}", activity_type));
         }
     }
 
-    // Store the activity in the database
-    store_activity(&activity, state).await?;
+    if scheduled {
+        let object_id = activity["object"]["id"].as_str().unwrap_or_default();
+        info!("Scheduled {} for publication", object_id);
+        return Ok(object_id.to_string());
+    }
+
+    // Store the activity and queue it for delivery to followers in one write
+    store_and_publish_activity(&activity, state).await?;
+
+    // Likes count towards their object and Announces record a boost once stored
+    match (activity["type"].as_str(), activity["object"].as_str()) {
+        (Some("Like"), Some(object)) => {
+            count_interaction(&ActivityType::Like, object, state).await?;
+            if let Some(actor) = activity["actor"].as_str() {
+                notify_favourite(actor, object, state).await;
+            }
+        }
+        (Some("Announce"), Some(_)) => {
+            state
+                .db_manager
+                .apply_boost_activity(&activity)
+                .await
+                .map_err(|e| format!("Failed to record boost: {}", e))?;
+        }
+        (Some("Join"), Some(_)) => events::join(&state.db_manager, &activity).await?,
+        (Some("Leave"), Some(_)) => events::leave(&state.db_manager, &activity).await?,
+        (Some("Accept"), Some(_)) => {
+            events::answer_join(&state.db_manager, &activity, ParticipationStatus::Accepted)
+                .await?;
+        }
+        (Some("Reject"), Some(_)) => {
+            events::answer_join(&state.db_manager, &activity, ParticipationStatus::Rejected)
+                .await?;
+        }
+        _ => {}
+    }
 
     // Add to actor's outbox
     add_to_outbox(&activity_id, username, state).await?;
 
-    // Publish for delivery to followers
-    publish_activity_message(&activity, state).await?;
-
     Ok(activity_id)
 }
 
+/// Whether a C2S object is to be published in the future
+fn is_scheduled(object: &Value) -> bool {
+    object
+        .get("published")
+        .and_then(Value::as_str)
+        .and_then(|published| chrono::DateTime::parse_from_rfc3339(published).ok())
+        .is_some_and(|published| published > Utc::now())
+}
+
 /// Process Create activity from C2S API
 async fn process_create_activity_c2s(
     activity: &mut Value,
@@ -2052,18 +2930,77 @@ async fn process_create_activity_c2s(
             obj.insert("attributedTo".to_string(), json!(actor_id));
         }
 
+        let profile = application_profile(username, &domain, state).await?;
+        profile.check_post(object)?;
+        let obj = object.as_object_mut().unwrap();
+
+        // Content is HTML unless the client or the profile says otherwise
+        let media_type = obj.remove("mediaType").filter(|t| !t.is_null());
+        let media_type = match &media_type {
+            Some(media_type) => media_type
+                .as_str()
+                .ok_or_else(|| format!("Invalid mediaType: {}", media_type))?,
+            None => profile.default_media_type(),
+        };
+        match media_type {
+            "text/html" => {}
+            "text/plain" => {
+                if let Some(content) = obj.get("content").and_then(Value::as_str) {
+                    let content = html::text_paragraphs(content);
+                    obj.insert("content".to_string(), json!(content));
+                }
+            }
+            other => return Err(format!("Unsupported mediaType: {}", other)),
+        }
+
+        match obj.get("type").and_then(Value::as_str) {
+            Some("Event") => events::prepare_event(obj)?,
+            Some("Article") => blog::prepare_article(obj, state).await?,
+            _ => {}
+        }
+
         // Add published timestamp if not present
-        if !obj.contains_key("published") {
+        if obj.get("published").is_none_or(Value::is_null) {
             obj.insert("published".to_string(), json!(Utc::now().to_rfc3339()));
         }
 
+        // Clients select the language of the content with `language`
+        if let Some(selected) = obj.remove("language").filter(|l| !l.is_null()) {
+            let tag = selected
+                .as_str()
+                .filter(|tag| language::is_language_tag(tag))
+                .ok_or_else(|| format!("Invalid language tag: {}", selected))?;
+            language::tag_language(obj, tag);
+        }
+
         // Store the object in the database
-        store_object_from_c2s(object, state).await?;
+        let status = if is_scheduled(object) {
+            ObjectStatus::Scheduled
+        } else {
+            ObjectStatus::Published
+        };
+        store_object_from_c2s(object, status, state).await?;
     }
 
     Ok(())
 }
 
+/// Application profile of a local user
+async fn application_profile(
+    username: &str,
+    domain: &str,
+    state: &AppState,
+) -> Result<ApplicationProfile, String> {
+    let actor = state
+        .db_manager
+        .find_actor_by_username(username, domain)
+        .await
+        .map_err(|e| format!("Failed to look up {}: {}", username, e))?;
+    Ok(actor
+        .map(|actor| actor.application_profile())
+        .unwrap_or_default())
+}
+
 /// Process Update activity from C2S API
 async fn process_update_activity_c2s(
     activity: &mut Value,
@@ -2085,21 +3022,162 @@ async fn process_update_activity_c2s(
             .get("id")
             .and_then(|id| id.as_str())
             .ok_or("Object must have an ID")?
-    };
+    }
+    .to_string();
+
+    // Updating the user's own actor edits the profile
+    if activity_obj.get("actor").and_then(Value::as_str) == Some(object_id.as_str()) {
+        return update_profile_c2s(activity_obj, &object_id, state).await;
+    }
 
     // Check that the user owns this object
-    if !verify_object_ownership(object_id, username, state).await? {
+    if !verify_object_ownership(&object_id, username, state).await? {
         return Err("Cannot update object you don't own".to_string());
     }
 
-    // If object is embedded, update it in the database
-    if object.is_object() {
-        store_object_from_c2s(object, state).await?;
+    // Only embedded objects carry an edit
+    let Some(edits) = object.as_object() else {
+        return Ok(());
+    };
+    let current = state
+        .db_manager
+        .find_object_by_id(&object_id)
+        .await
+        .map_err(|e| format!("Failed to look up object {}: {}", object_id, e))?
+        .ok_or("Object not found")?;
+    if current.object_type == ObjectType::Tombstone {
+        return Err("Cannot update a deleted object".to_string());
+    }
+    if current.status == ObjectStatus::Scheduled {
+        return Err("Cannot update a scheduled object".to_string());
+    }
+
+    let edited = edited_object(&current, edits);
+    state
+        .db_manager
+        .revise_object(&current, &edited)
+        .await
+        .map_err(|e| format!("Failed to update object {}: {}", object_id, e))?;
+
+    // Federate the whole edited object with its `updated` time, which
+    // remote servers need to show it as edited
+    activity_obj.insert("object".to_string(), edited.to_activitypub());
+    for (field, recipients) in [
+        ("to", &current.to),
+        ("cc", &current.cc),
+        ("bto", &current.bto),
+        ("bcc", &current.bcc),
+    ] {
+        if let Some(recipients) = recipients {
+            activity_obj
+                .entry(field)
+                .or_insert_with(|| json!(recipients));
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply a C2S Update of the user's own actor
+///
+/// Clients may change the display name, summary and the `discoverable` and
+/// `indexable` flags; other properties are ignored. The Update is
+/// delivered with the whole updated actor.
+async fn update_profile_c2s(
+    activity_obj: &mut serde_json::Map<String, Value>,
+    actor_id: &str,
+    state: &AppState,
+) -> Result<(), String> {
+    let edits = activity_obj
+        .get("object")
+        .filter(|object| object.is_object())
+        .cloned()
+        .ok_or("Profile updates must embed the actor")?;
+    let actor = state
+        .db_manager
+        .find_actor_by_id(actor_id)
+        .await
+        .map_err(|e| format!("Failed to look up actor {}: {}", actor_id, e))?
+        .ok_or("Actor not found")?;
+
+    let mut update = mongodb::bson::Document::new();
+    for field in ["name", "summary"] {
+        if let Some(value) = edits.get(field).and_then(Value::as_str) {
+            update.insert(field, value);
+        }
+    }
+    let extensions = Extensions::from_json(&edits);
+    if extensions.discoverable.is_some() || extensions.indexable.is_some() {
+        let mut properties = actor.additional_properties.clone().unwrap_or_default();
+        for (flag, value) in [
+            ("discoverable", extensions.discoverable),
+            ("indexable", extensions.indexable),
+        ] {
+            if let Some(value) = value {
+                properties.insert(flag, value);
+            }
+        }
+        update.insert("additional_properties", properties);
     }
 
+    let updated = if update.is_empty() {
+        actor
+    } else {
+        state
+            .db_manager
+            .update_actor(actor_id, update)
+            .await
+            .map_err(|e| format!("Failed to update actor {}: {}", actor_id, e))?;
+        state
+            .db_manager
+            .find_actor_by_id(actor_id)
+            .await
+            .map_err(|e| format!("Failed to look up actor {}: {}", actor_id, e))?
+            .ok_or("Actor not found")?
+    };
+
+    activity_obj.insert("object".to_string(), actor_json(&updated));
+    activity_obj
+        .entry("to")
+        .or_insert_with(|| json!(["https://www.w3.org/ns/activitystreams#Public"]));
+    activity_obj
+        .entry("cc")
+        .or_insert_with(|| json!([updated.followers]));
     Ok(())
 }
 
+/// Object resulting from applying the properties of a C2S Update
+///
+/// Properties the client leaves out keep their value. Authorship,
+/// publication time and addressing cannot be changed.
+fn edited_object(
+    current: &ObjectDocument,
+    edits: &serde_json::Map<String, Value>,
+) -> ObjectDocument {
+    let mut merged = current.to_activitypub();
+    for (key, value) in edits {
+        if !matches!(
+            key.as_str(),
+            "id" | "type"
+                | "attributedTo"
+                | "published"
+                | "updated"
+                | "to"
+                | "cc"
+                | "bto"
+                | "bcc"
+                | "audience"
+        ) {
+            merged[key] = value.clone();
+        }
+    }
+    merged["updated"] = json!(Utc::now().to_rfc3339());
+
+    let mut edited = ObjectDocument::from_activitypub(&merged, current.object_type.clone());
+    edited.language = edited.language.or_else(|| current.language.clone());
+    edited
+}
+
 /// Process Delete activity from C2S API
 async fn process_delete_activity_c2s(
     activity: &mut Value,
@@ -2163,6 +3241,11 @@ async fn process_undo_activity_c2s(
     state: &AppState,
 ) -> Result<(), String> {
     let activity_obj = activity.as_object_mut().unwrap();
+    let actor_id = activity_obj
+        .get("actor")
+        .and_then(Value::as_str)
+        .ok_or("Undo activity must have an actor")?
+        .to_string();
 
     // Get the activity being undone
     let undone_activity = activity_obj
@@ -2187,13 +3270,9 @@ async fn process_undo_activity_c2s(
             info!("Processing unfollow from {}", username);
             // Handle unfollow
         }
-        "Like" => {
-            info!("Processing unlike from {}", username);
-            // Handle unlike
-        }
-        "Announce" => {
-            info!("Processing unboost from {}", username);
-            // Handle unboost
+        "Like" | "Announce" | "Block" => {
+            info!("Processing undo of {} from {}", undone_type, username);
+            undo_interaction(&actor_id, undone_activity, state).await?;
         }
         _ => {
             warn!("Unsupported undo type: {}", undone_type);
@@ -2209,15 +3288,8 @@ async fn process_like_activity_c2s(
     username: &str,
     _state: &AppState,
 ) -> Result<(), String> {
-    let activity_obj = activity.as_object_mut().unwrap();
-
-    // Ensure object exists
-    let _object = activity_obj
-        .get("object")
-        .ok_or("Like activity must have an object")?;
-
-    info!("User {} liked an object", username);
-
+    let object = interaction_object_c2s(activity, "Like")?;
+    info!("User {} liked {}", username, object);
     Ok(())
 }
 
@@ -2227,15 +3299,8 @@ async fn process_announce_activity_c2s(
     username: &str,
     _state: &AppState,
 ) -> Result<(), String> {
-    let activity_obj = activity.as_object_mut().unwrap();
-
-    // Ensure object exists
-    let _object = activity_obj
-        .get("object")
-        .ok_or("Announce activity must have an object")?;
-
-    info!("User {} announced an object", username);
-
+    let object = interaction_object_c2s(activity, "Announce")?;
+    info!("User {} announced {}", username, object);
     Ok(())
 }
 
@@ -2245,28 +3310,146 @@ async fn process_block_activity_c2s(
     username: &str,
     _state: &AppState,
 ) -> Result<(), String> {
-    let activity_obj = activity.as_object_mut().unwrap();
+    let target = interaction_object_c2s(activity, "Block")?;
+    if activity.get("actor").and_then(Value::as_str) == Some(target.as_str()) {
+        return Err("Cannot block yourself".to_string());
+    }
+    info!("User {} blocked {}", username, target);
+    Ok(())
+}
 
-    // Ensure object (target actor) exists
-    let _target = activity_obj
+/// Process Join or Leave activity from C2S API
+///
+/// The event is named by ID; the organizer of a stored event is added to
+/// the recipients.
+async fn process_rsvp_activity_c2s(
+    activity: &mut Value,
+    username: &str,
+    state: &AppState,
+) -> Result<(), String> {
+    let activity_type = activity["type"].as_str().unwrap_or("Join").to_string();
+    let event_id = interaction_object_c2s(activity, &activity_type)?;
+    let event = state
+        .db_manager
+        .find_object_by_id(&event_id)
+        .await
+        .map_err(|e| format!("Failed to look up {}: {}", event_id, e))?;
+    if let Some(event) = &event {
+        if event.event.is_none() {
+            return Err(format!("{} is not an event", event_id));
+        }
+        add_recipient(activity, &event.attributed_to);
+    }
+    info!("User {} sent {} of {}", username, activity_type, event_id);
+    Ok(())
+}
+
+/// Process Accept or Reject activity from C2S API
+///
+/// Organizers answer the `Join`s of their events, named by ID; the answer
+/// is delivered to the attendee.
+async fn process_join_answer_c2s(
+    activity: &mut Value,
+    username: &str,
+    state: &AppState,
+) -> Result<(), String> {
+    let activity_type = activity["type"].as_str().unwrap_or("Accept").to_string();
+    let join_id = interaction_object_c2s(activity, &activity_type)?;
+    let participation = state
+        .db_manager
+        .find_participation_by_activity(&join_id)
+        .await
+        .map_err(|e| format!("Failed to look up {}: {}", join_id, e))?
+        .ok_or_else(|| format!("{} is not a Join of an event", join_id))?;
+    if !verify_object_ownership(&participation.event_id, username, state).await? {
+        return Err(format!(
+            "Only the organizer of {} may answer its Joins",
+            participation.event_id
+        ));
+    }
+    add_recipient(activity, &participation.actor);
+    info!(
+        "User {} sent {} of the Join of {} by {}",
+        username, activity_type, participation.event_id, participation.actor
+    );
+    Ok(())
+}
+
+/// Add an actor to the `to` of an activity unless it is addressed already
+fn add_recipient(activity: &mut Value, recipient: &str) {
+    let mut to = match activity.get("to") {
+        Some(Value::Array(to)) => to.clone(),
+        Some(Value::String(to)) => vec![json!(to)],
+        _ => Vec::new(),
+    };
+    if !to
+        .iter()
+        .any(|addressed| addressed.as_str() == Some(recipient))
+    {
+        to.push(json!(recipient));
+    }
+    activity["to"] = json!(to);
+}
+
+/// ID of the object a C2S Like, Announce or Block refers to
+///
+/// An embedded object is replaced by its ID, so the stored activity names
+/// the object and can be found again when it is undone.
+fn interaction_object_c2s(activity: &mut Value, activity_type: &str) -> Result<String, String> {
+    let object = activity
         .get("object")
-        .ok_or("Block activity must have an object")?;
+        .ok_or_else(|| format!("{} activity must have an object", activity_type))?;
+    let object_id = object
+        .as_str()
+        .or_else(|| object.get("id")?.as_str())
+        .ok_or_else(|| format!("{} object must have an ID", activity_type))?
+        .to_string();
+    activity["object"] = json!(object_id);
+    Ok(object_id)
+}
 
-    info!("User {} blocked someone", username);
+/// Count a stored Like on the object it refers to
+async fn count_interaction(
+    activity_type: &ActivityType,
+    object: &str,
+    state: &AppState,
+) -> Result<(), String> {
+    state
+        .db_manager
+        .count_interaction(activity_type, object)
+        .await
+        .map_err(|e| format!("Failed to count {:?} of {}: {}", activity_type, object, e))
+}
 
-    Ok(())
+/// Notify the local author of a liked post
+///
+/// The Like is stored already, so a lost notification is only logged.
+async fn notify_favourite(liker: &str, object: &str, state: &AppState) {
+    let notified = match state.db_manager.find_object_by_id(object).await {
+        Ok(Some(liked)) => state.db_manager.notify_favourite(liker, &liked).await,
+        Ok(None) => Ok(false),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = notified {
+        warn!("Failed to notify the author of {} of a like: {}", object, e);
+    }
 }
 
 /// Store an object from C2S API
-async fn store_object_from_c2s(object: &Value, state: &AppState) -> Result<(), String> {
+async fn store_object_from_c2s(
+    object: &Value,
+    status: ObjectStatus,
+    state: &AppState,
+) -> Result<(), String> {
     let object_type = object
         .get("type")
         .and_then(|t| t.as_str())
         .unwrap_or("Note");
 
     match object_type {
-        "Note" => store_note_object(object, state).await,
-        "Article" => store_article_object(object, state).await,
+        "Note" => store_note_object(object, status, state).await,
+        "Article" => store_article_object(object, status, state).await,
+        "Event" => store_event_object(object, status, state).await,
         _ => {
             warn!("Unsupported object type for storage: {}", object_type);
             Ok(())
@@ -2281,20 +3464,10 @@ async fn verify_object_ownership(
     state: &AppState,
 ) -> Result<bool, String> {
     let domain = std::env::var("OXIFED_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
-    let filter = mongodb::bson::doc! {
-        "id": object_id,
-        "attributedTo": format!("https://{}/users/{}", domain, username)
-    };
+    let owner = format!("https://{}/users/{}", domain, username);
 
-    match state
-        .db
-        .database()
-        .collection::<mongodb::bson::Document>("objects")
-        .find_one(filter)
-        .await
-    {
-        Ok(Some(_)) => Ok(true),
-        Ok(None) => Ok(false),
+    match state.db_manager.find_object_by_id(object_id).await {
+        Ok(object) => Ok(object.is_some_and(|object| object.attributed_to == owner)),
         Err(e) => Err(format!("Database error: {}", e)),
     }
 }
@@ -2342,20 +3515,14 @@ async fn add_to_outbox(activity_id: &str, username: &str, state: &AppState) -> R
 
 /// Fetch activity type from database
 async fn fetch_activity_type(activity_id: &str, state: &AppState) -> Result<String, String> {
-    let filter = mongodb::bson::doc! { "id": activity_id };
-
-    let doc = state
-        .db
-        .database()
-        .collection::<mongodb::bson::Document>("activities")
-        .find_one(filter)
+    let activity = state
+        .db_manager
+        .find_activity_by_id(activity_id)
         .await
         .map_err(|e| format!("Database error: {}", e))?
         .ok_or("Activity not found")?;
 
-    doc.get_str("type")
-        .map(|s| s.to_string())
-        .map_err(|_| "Activity has no type field".to_string())
+    Ok(format!("{:?}", activity.activity_type))
 }
 
 /// Create a note via C2S API
@@ -2368,12 +3535,23 @@ async fn create_note(
     info!("Creating note for user: {}", username);
 
     // Verify authentication
-    if !verify_client_authentication(&headers, &username, &state).await {
+    if !verify_client_authentication(&headers, &username, "write", &state).await {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
     let domain = std::env::var("OXIFED_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
 
+    // The endpoint is disabled for other applications
+    let profile = application_profile(&username, &domain, &state)
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !profile.creates(&ObjectType::Note) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
     // Wrap the note in a Create activity
     let activity = json!({
         "@context": "https://www.w3.org/ns/activitystreams",
@@ -2382,11 +3560,14 @@ async fn create_note(
         "object": {
             "type": "Note",
             "content": note.get("content").cloned().unwrap_or(json!("")),
+            "mediaType": note.get("mediaType").cloned(),
             "to": note.get("to").cloned().unwrap_or(json!(["https://www.w3.org/ns/activitystreams#Public"])),
             "cc": note.get("cc").cloned().unwrap_or(json!([format!("https://{}/users/{}/followers", domain, username)])),
             "inReplyTo": note.get("inReplyTo").cloned(),
-            "sensitive": note.get("sensitive").cloned().unwrap_or(json!(false)),
+            "published": note.get("published").cloned(),
+            "sensitive": Extensions::from_json(&note).sensitive.unwrap_or(false),
             "summary": note.get("summary").cloned(),
+            "language": note.get("language").cloned(),
             "tag": note.get("tag").cloned(),
             "attachment": note.get("attachment").cloned(),
         }
@@ -2421,12 +3602,23 @@ async fn create_article(
     info!("Creating article for user: {}", username);
 
     // Verify authentication
-    if !verify_client_authentication(&headers, &username, &state).await {
+    if !verify_client_authentication(&headers, &username, "write", &state).await {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
     let domain = std::env::var("OXIFED_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
 
+    // The endpoint is disabled for other applications
+    let profile = application_profile(&username, &domain, &state)
+        .await
+        .map_err(|e| {
+            error!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !profile.creates(&ObjectType::Article) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
     // Wrap the article in a Create activity
     let activity = json!({
         "@context": "https://www.w3.org/ns/activitystreams",
@@ -2436,11 +3628,16 @@ async fn create_article(
             "type": "Article",
             "name": article.get("name").cloned().unwrap_or(json!("Untitled")),
             "content": article.get("content").cloned().unwrap_or(json!("")),
+            "mediaType": article.get("mediaType").cloned(),
             "summary": article.get("summary").cloned(),
+            "published": article.get("published").cloned(),
+            "language": article.get("language").cloned(),
             "to": article.get("to").cloned().unwrap_or(json!(["https://www.w3.org/ns/activitystreams#Public"])),
             "cc": article.get("cc").cloned().unwrap_or(json!([format!("https://{}/users/{}/followers", domain, username)])),
             "tag": article.get("tag").cloned(),
             "attachment": article.get("attachment").cloned(),
+            "image": article.get("image").cloned(),
+            "slug": article.get("slug").cloned(),
         }
     });
 
@@ -2473,7 +3670,7 @@ async fn upload_media(
     info!("Uploading media for user: {}", username);
 
     // Verify authentication
-    if !verify_client_authentication(&headers, &username, &state).await {
+    if !verify_client_authentication(&headers, &username, "write", &state).await {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
@@ -2533,14 +3730,15 @@ async fn get_featured_collection(
 
     // Build filter for featured items
     let filter = mongodb::bson::doc! {
-        "actor": format!("https://{}/users/{}", domain, username),
-        "featured": true
+        "attributed_to": format!("https://{}/users/{}", domain, username),
+        "featured": true,
+        "visibility": { "$in": ["public", "unlisted"] }
     };
 
     // Apply pagination
     let limit = query.limit.unwrap_or(20).min(100) as i64;
 
-    let items: Vec<mongodb::bson::Document> = state
+    let items: Vec<ObjectDocument> = state
         .db
         .database()
         .collection("objects")
@@ -2568,7 +3766,7 @@ async fn get_featured_collection(
         ordered_items: Some(
             items
                 .into_iter()
-                .map(|doc| serde_json::to_value(doc).unwrap_or(json!(null)))
+                .map(|object| object.to_activitypub())
                 .collect(),
         ),
         first: None,
@@ -2594,13 +3792,14 @@ async fn get_tag_collection(
     // Build filter for items with this tag
     let filter = mongodb::bson::doc! {
         "actor": format!("https://{}/users/{}", domain, username),
-        "tag.name": &tag
+        "tag.name": &tag,
+        "visibility": "public"
     };
 
     // Apply pagination
     let limit = query.limit.unwrap_or(20).min(100) as i64;
 
-    let items: Vec<mongodb::bson::Document> = state
+    let items: Vec<ObjectDocument> = state
         .db
         .database()
         .collection("objects")
@@ -2631,7 +3830,7 @@ async fn get_tag_collection(
         ordered_items: Some(
             items
                 .into_iter()
-                .map(|doc| serde_json::to_value(doc).unwrap_or(json!(null)))
+                .map(|object| object.to_activitypub())
                 .collect(),
         ),
         first: None,
@@ -2654,15 +3853,10 @@ async fn update_object(
     info!("Updating object: {}", id);
 
     // Extract username from token
-    let username = extract_username_from_headers(&headers, &state)
+    let username = authenticated_username(&headers, "write", &state)
         .await
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    // Verify authentication
-    if !verify_client_authentication(&headers, &username, &state).await {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-
     let domain = std::env::var("OXIFED_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
 
     // Verify ownership
@@ -2706,15 +3900,10 @@ async fn delete_object(
     info!("Deleting object: {}", id);
 
     // Extract username from token
-    let username = extract_username_from_headers(&headers, &state)
+    let username = authenticated_username(&headers, "write", &state)
         .await
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    // Verify authentication
-    if !verify_client_authentication(&headers, &username, &state).await {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-
     let domain = std::env::var("OXIFED_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
 
     // Verify ownership
@@ -2745,20 +3934,169 @@ async fn delete_object(
     Ok(StatusCode::NO_CONTENT.into_response())
 }
 
+/// Query of a conversation's messages
+#[derive(Debug, Deserialize)]
+pub struct ConversationQuery {
+    id: String,
+    limit: Option<u32>,
+}
+
+/// List the direct message conversations of a user (C2S)
+async fn get_conversations(
+    Path(username): Path<String>,
+    Query(query): Query<CollectionQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let actor = conversation_owner(&username, &headers, &state).await?;
+    let limit = query.limit.unwrap_or(20).min(40) as i64;
+
+    let conversations = state
+        .db_manager
+        .find_conversations(&actor.actor_id, limit, 0)
+        .await
+        .map_err(|e| {
+            error!("Failed to list conversations of {}: {}", actor.actor_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut items = Vec::with_capacity(conversations.len());
+    for conversation in conversations {
+        let last_message = state
+            .db_manager
+            .find_object_by_id(&conversation.last_object_id)
+            .await
+            .map_err(|e| {
+                error!("Failed to get {}: {}", conversation.last_object_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        items.push(json!({
+            "id": conversation.conversation_id,
+            "participants": conversation.participants,
+            "updated": conversation.updated_at.to_rfc3339(),
+            "lastMessage": last_message.map(|object| object.to_activitypub())
+        }));
+    }
+
+    let collection = ActivityPubCollection {
+        context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+        collection_type: "OrderedCollection".to_string(),
+        id: format!("{}/conversations", actor.actor_id),
+        total_items: Some(items.len() as u64),
+        items: None,
+        ordered_items: Some(items),
+        first: None,
+        last: None,
+        next: None,
+        prev: None,
+        part_of: None,
+    };
+    Ok(Json(collection).into_response())
+}
+
+/// List the messages of a conversation the user wrote or received (C2S)
+async fn get_conversation_messages(
+    Path(username): Path<String>,
+    Query(query): Query<ConversationQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let actor = conversation_owner(&username, &headers, &state).await?;
+    let limit = query.limit.unwrap_or(40).min(100) as i64;
+
+    let messages = state
+        .db_manager
+        .find_conversation_messages(&actor.actor_id, &query.id, limit)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to list messages of conversation {}: {}",
+                query.id, e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    // Conversations the user is not part of do not exist for them
+    if messages.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let collection = ActivityPubCollection {
+        context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+        collection_type: "OrderedCollection".to_string(),
+        id: query.id,
+        total_items: Some(messages.len() as u64),
+        items: None,
+        ordered_items: Some(
+            messages
+                .iter()
+                .map(ObjectDocument::to_activitypub)
+                .collect(),
+        ),
+        first: None,
+        last: None,
+        next: None,
+        prev: None,
+        part_of: None,
+    };
+    Ok(Json(collection).into_response())
+}
+
+/// Local actor whose conversations are requested by an authenticated client
+async fn conversation_owner(
+    username: &str,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<ActorDocument, StatusCode> {
+    if !verify_client_authentication(headers, username, "read", state).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let domain = extract_domain_from_headers(headers).ok_or(StatusCode::BAD_REQUEST)?;
+    match state
+        .db_manager
+        .find_actor_by_username(username, &domain)
+        .await
+    {
+        Ok(Some(actor)) if actor.status == ActorStatus::Active => Ok(actor),
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to look up {}: {}", username, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 /// Search for content
+///
+/// Anonymous searches find public objects only. Users authenticated with a
+/// bearer token also find the objects they wrote or received.
 async fn search_content(
     Query(params): Query<std::collections::HashMap<String, String>>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
     let query = params.get("q").ok_or(StatusCode::BAD_REQUEST)?;
     info!("Searching for: {}", query);
 
+    let viewer = match extract_domain_from_headers(&headers) {
+        Some(domain) => viewer_of(None, &headers, &domain, &state).await,
+        None => None,
+    };
+    let mut visible = vec![mongodb::bson::doc! { "visibility": "public" }];
+    if let Some(viewer) = &viewer {
+        visible.extend(
+            ["attributed_to", "to", "cc", "bto", "bcc"]
+                .map(|field| mongodb::bson::doc! { field: viewer }),
+        );
+    }
+
     // Build search filter
     let filter = mongodb::bson::doc! {
-        "$text": { "$search": query }
+        "$text": { "$search": query },
+        "$or": visible,
+        "status": { "$ne": "scheduled" }
     };
 
-    let results: Vec<mongodb::bson::Document> = state
+    let results: Vec<ObjectDocument> = state
         .db
         .database()
         .collection("objects")
@@ -2779,195 +4117,383 @@ async fn search_content(
     Ok(Json(json!({
         "type": "Collection",
         "totalItems": results.len(),
-        "items": results
+        "items": results.iter().map(ObjectDocument::to_activitypub).collect::<Vec<_>>()
     }))
     .into_response())
 }
 
-/// List users
-async fn list_users(
-    Query(query): Query<CollectionQuery>,
+/// Query of the account directory
+#[derive(Debug, Deserialize)]
+pub struct DirectoryQuery {
+    #[serde(default)]
+    order: DirectoryOrder,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+/// Account directory of the domain
+///
+/// Lists the local actors that set `discoverable`, most recently posting
+/// or most followed first. Also served at `/users`, unless the domain
+/// hides its directory.
+async fn get_directory(
+    Query(query): Query<DirectoryQuery>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    info!("Listing users");
-
-    let limit = query.limit.unwrap_or(20).min(100) as i64;
-
-    let users: Vec<mongodb::bson::Document> = state
-        .db
-        .database()
-        .collection("actors")
-        .find(mongodb::bson::doc! {})
-        .limit(limit)
-        .sort(mongodb::bson::doc! { "created_at": -1 })
+    let domain = extract_domain_from_headers(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    if privacy::DomainPrivacy::of(&domain, &state)
         .await
-        .map_err(|e| {
-            error!("Failed to list users: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .try_collect()
+        .hides_directory()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100) as i64;
+
+    let entries = state
+        .db_manager
+        .find_directory_actors(
+            &domain,
+            query.order,
+            limit,
+            query.offset.unwrap_or(0) as i64,
+        )
         .await
         .map_err(|e| {
-            error!("Failed to collect users: {}", e);
+            error!("Failed to list directory of {}: {}", domain, e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    Ok(Json(json!({
-        "type": "Collection",
-        "totalItems": users.len(),
-        "items": users
-    }))
-    .into_response())
+    let items: Vec<Value> = entries
+        .iter()
+        .map(|entry| actor_json(&entry.actor))
+        .collect();
+    Ok((
+        StatusCode::OK,
+        [("Content-Type", "application/activity+json")],
+        Json(json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "Collection",
+            "totalItems": items.len(),
+            "items": items
+        })),
+    )
+        .into_response())
 }
 
-/// OAuth authorization endpoint
-async fn oauth_authorize(
-    Query(params): Query<std::collections::HashMap<String, String>>,
-    State(_state): State<AppState>,
-) -> Result<Response, StatusCode> {
-    let client_id = params.get("client_id").ok_or(StatusCode::BAD_REQUEST)?;
-    let redirect_uri = params.get("redirect_uri").ok_or(StatusCode::BAD_REQUEST)?;
-
-    info!("OAuth authorization request from client: {}", client_id);
-
-    // TODO: Implement OAuth authorization flow
-    // For now, return a basic authorization page
-    let html = format!(
-        r#"
-        <html>
-        <body>
-            <h1>Authorize Application</h1>
-            <p>Application {} is requesting access to your account.</p>
-            <form method="post" action="/oauth/authorize">
-                <input type="hidden" name="client_id" value="{}">
-                <input type="hidden" name="redirect_uri" value="{}">
-                <button type="submit" name="action" value="allow">Allow</button>
-                <button type="submit" name="action" value="deny">Deny</button>
-            </form>
-        </body>
-        </html>
-        "#,
-        client_id, client_id, redirect_uri
-    );
-
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "text/html")
-        .body(Body::from(html))
-        .unwrap())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::http::Request;
 
-/// OAuth token endpoint
-async fn oauth_token(
-    State(state): State<AppState>,
-    Json(request): Json<Value>,
-) -> Result<Response, StatusCode> {
-    let grant_type = request
-        .get("grant_type")
-        .and_then(|v| v.as_str())
-        .ok_or(StatusCode::BAD_REQUEST)?;
+    const ALICE: &str = "https://a.example/users/alice";
+    const BOB: &str = "https://b.example/users/bob";
 
-    info!("OAuth token request with grant_type: {}", grant_type);
-
-    match grant_type {
-        "authorization_code" => {
-            // Handle authorization code grant
-            let _code = request
-                .get("code")
-                .and_then(|v| v.as_str())
-                .ok_or(StatusCode::BAD_REQUEST)?;
-
-            // TODO: Validate code and generate access token
-            let token = format!("token:{}", Uuid::new_v4());
-
-            // Store token in database
-            let token_doc = mongodb::bson::doc! {
-                "token": &token,
-                "client_id": request.get("client_id").and_then(|v| v.as_str()).unwrap_or(""),
-                "created_at": mongodb::bson::DateTime::now(),
-                "expires_at": mongodb::bson::DateTime::from_millis(
-                    chrono::Utc::now().timestamp_millis() + 3600000
-                ),
-            };
-
-            state
-                .db
-                .database()
-                .collection::<mongodb::bson::Document>("access_tokens")
-                .insert_one(token_doc)
-                .await
-                .map_err(|e| {
-                    error!("Failed to store access token: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?;
-
-            Ok(Json(json!({
-                "access_token": token,
-                "token_type": "Bearer",
-                "expires_in": 3600
-            }))
-            .into_response())
-        }
-        _ => {
-            error!("Unsupported grant type: {}", grant_type);
-            Err(StatusCode::BAD_REQUEST)
+    fn signed_by(owner: &str) -> VerifiedSigner {
+        VerifiedSigner {
+            key_id: format!("{}#main-key", owner),
+            owner: owner.to_string(),
         }
     }
-}
 
-/// OAuth revoke endpoint
-async fn oauth_revoke(
-    State(state): State<AppState>,
-    Json(request): Json<Value>,
-) -> Result<Response, StatusCode> {
-    let token = request
-        .get("token")
-        .and_then(|v| v.as_str())
-        .ok_or(StatusCode::BAD_REQUEST)?;
+    fn like(actor: &str) -> Value {
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{}/likes/1", actor),
+            "type": "Like",
+            "actor": actor,
+            "object": "https://local.example/objects/1"
+        })
+    }
 
-    info!("Revoking OAuth token");
+    #[tokio::test]
+    async fn test_inbox_refuses_activity_of_another_actor() {
+        for path in ["/users/carol/inbox", "/inbox"] {
+            let router = Router::new()
+                .route("/users/{username}/inbox", post(post_inbox))
+                .route("/inbox", post(post_shared_inbox))
+                .layer(Extension(signed_by(ALICE)))
+                .with_state(testing::state().await);
+            let request = Request::post(path)
+                .header(header::HOST, "local.example")
+                .header(header::CONTENT_TYPE, "application/activity+json")
+                .body(Body::from(like(BOB).to_string()))
+                .unwrap();
+            let (status, _) = testing::send(router, request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+        }
+    }
 
-    // Delete token from database
-    let filter = mongodb::bson::doc! { "token": token };
+    #[tokio::test]
+    async fn test_check_signer() {
+        let state = testing::state().await;
+        let alice = signed_by(ALICE);
+        assert!(
+            check_signer(&like(ALICE), Some(&alice), &state)
+                .await
+                .is_ok()
+        );
+        assert!(check_signer(&like(ALICE), None, &state).await.is_ok());
+        assert_eq!(
+            check_signer(&like(BOB), Some(&alice), &state).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        // A key of the actor's origin stands for it once stored as its key
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let relay_key = VerifiedSigner {
+            key_id: "https://b.example/actor#main-key".to_string(),
+            owner: "https://b.example/actor".to_string(),
+        };
+        assert_eq!(
+            check_signer(&like(BOB), Some(&relay_key), &state).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        let mut bob = ActorDocument::from_activitypub(&json!({
+            "id": BOB,
+            "inbox": format!("{}/inbox", BOB),
+            "publicKey": {
+                "id": relay_key.key_id,
+                "owner": BOB,
+                "publicKeyPem": "-----BEGIN PUBLIC KEY-----"
+            }
+        }))
+        .unwrap();
+        bob.local = false;
+        state.db_manager.upsert_remote_actor(bob).await.unwrap();
+        assert!(
+            check_signer(&like(BOB), Some(&relay_key), &state)
+                .await
+                .is_ok()
+        );
+    }
 
-    state
-        .db
-        .database()
-        .collection::<mongodb::bson::Document>("access_tokens")
-        .delete_one(filter)
-        .await
-        .map_err(|e| {
-            error!("Failed to revoke token: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    const LOCAL_ALICE: &str = "https://local.example/users/alice";
+    const LOCAL_CAROL: &str = "https://local.example/users/carol";
+    const DM: &str = "https://local.example/objects/dm";
+
+    fn direct_message() -> ObjectDocument {
+        let mut message = ObjectDocument::from_activitypub(
+            &json!({
+                "id": DM,
+                "type": "Note",
+                "attributedTo": LOCAL_ALICE,
+                "to": [BOB],
+                "content": "Secret plans",
+                "published": "2024-01-01T00:00:00Z"
+            }),
+            ObjectType::Note,
+        );
+        message.local = true;
+        message
+    }
 
-    Ok(StatusCode::NO_CONTENT.into_response())
-}
+    fn get_request(path: &str, token: Option<&str>) -> Request<Body> {
+        let mut request = Request::get(path).header(header::HOST, "local.example");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request.body(Body::empty()).unwrap()
+    }
 
-/// Extract username from authentication headers
-async fn extract_username_from_headers(headers: &HeaderMap, state: &AppState) -> Option<String> {
-    let auth_header = headers.get("Authorization")?;
-    let auth_str = auth_header.to_str().ok()?;
+    /// Store a read token of a local user
+    async fn store_token(state: &AppState, username: &str) -> String {
+        let token = format!("{}-token", username);
+        let expires_at = mongodb::bson::DateTime::from_millis(
+            (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp_millis(),
+        );
+        let access_token = oxifed::database::AccessTokenDocument {
+            id: None,
+            token_hash: oxifed::credentials::token_hash(&token),
+            username: username.to_string(),
+            domain: "local.example".to_string(),
+            client_id: "client".to_string(),
+            scopes: vec!["read".to_string()],
+            created_at: chrono::Utc::now(),
+            expires_at,
+        };
+        let refresh_token = oxifed::database::RefreshTokenDocument {
+            id: None,
+            token_hash: oxifed::credentials::token_hash(&format!("{}-refresh", username)),
+            access_token_hash: access_token.token_hash.clone(),
+            username: username.to_string(),
+            domain: "local.example".to_string(),
+            client_id: "client".to_string(),
+            scopes: access_token.scopes.clone(),
+            created_at: chrono::Utc::now(),
+            expires_at,
+        };
+        state
+            .db_manager
+            .insert_tokens(access_token, refresh_token)
+            .await
+            .unwrap();
+        token
+    }
 
-    if !auth_str.starts_with("Bearer ") {
-        return None;
+    #[tokio::test]
+    async fn test_direct_messages_are_visible_to_participants_only() {
+        let state = testing::state().await;
+        let message = direct_message();
+        let may_see = |viewer| may_see(&state.db_manager, &message, viewer);
+        assert!(may_see(Some(LOCAL_ALICE)).await.unwrap());
+        assert!(may_see(Some(BOB)).await.unwrap());
+        assert!(!may_see(Some(LOCAL_CAROL)).await.unwrap());
+        assert!(!may_see(None).await.unwrap());
     }
 
-    let token = &auth_str[7..];
+    #[tokio::test]
+    async fn test_conversations_need_the_owners_token() {
+        let router = Router::new()
+            .route("/users/{username}/conversations", get(get_conversations))
+            .route(
+                "/users/{username}/conversations/messages",
+                get(get_conversation_messages),
+            )
+            .with_state(testing::state().await);
+        for path in [
+            "/users/alice/conversations",
+            "/users/alice/conversations/messages?id=https://local.example/objects/dm",
+        ] {
+            let (status, _) = testing::send(router.clone(), get_request(path, None)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+        }
+    }
 
-    // Look up token in database
-    let filter = mongodb::bson::doc! {
-        "token": token,
-        "expires_at": { "$gt": mongodb::bson::DateTime::now() }
-    };
+    #[tokio::test]
+    async fn test_direct_message_access() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        for actor in [LOCAL_ALICE, LOCAL_CAROL] {
+            let mut actor = ActorDocument::from_activitypub(&json!({
+                "id": actor,
+                "type": "Person",
+                "preferredUsername": actor.rsplit('/').next().unwrap(),
+                "inbox": format!("{}/inbox", actor)
+            }))
+            .unwrap();
+            actor.local = true;
+            state.db_manager.insert_actor(actor).await.unwrap();
+        }
+        state
+            .db_manager
+            .insert_object(direct_message())
+            .await
+            .unwrap();
+        let alice = store_token(&state, "alice").await;
+        let carol = store_token(&state, "carol").await;
+
+        let router = Router::new()
+            .route("/objects/{id}", get(get_object))
+            .route("/users/{username}/conversations", get(get_conversations))
+            .route(
+                "/users/{username}/conversations/messages",
+                get(get_conversation_messages),
+            )
+            .route("/search", get(search_content))
+            .with_state(state);
+        let send = |request| testing::send(router.clone(), request);
+
+        // The object: its author and recipients only
+        let (status, body) = send(get_request("/objects/dm", Some(&alice))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], DM);
+        let mut signed = get_request("/objects/dm", None);
+        signed.extensions_mut().insert(signed_by(BOB));
+        assert_eq!(send(signed).await.0, StatusCode::OK);
+        assert_eq!(
+            send(get_request("/objects/dm", Some(&carol))).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(get_request("/objects/dm", None)).await.0,
+            StatusCode::NOT_FOUND
+        );
+
+        // The conversation: listed for the author, hidden from others
+        let (status, body) = send(get_request("/users/alice/conversations", Some(&alice))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ordered_items"][0]["id"], DM);
+        let messages = format!("/users/alice/conversations/messages?id={}", DM);
+        let (status, body) = send(get_request(&messages, Some(&alice))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ordered_items"][0]["id"], DM);
+        assert_eq!(
+            send(get_request(&messages, Some(&carol))).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        let (status, body) = send(get_request("/users/carol/conversations", Some(&carol))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ordered_items"], json!([]));
+        let foreign = format!("/users/carol/conversations/messages?id={}", DM);
+        assert_eq!(
+            send(get_request(&foreign, Some(&carol))).await.0,
+            StatusCode::NOT_FOUND
+        );
+
+        // Search: found by the author only
+        let (status, body) = send(get_request("/search?q=plans", Some(&alice))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["totalItems"], 1);
+        for token in [Some(carol.as_str()), None] {
+            let (status, body) = send(get_request("/search?q=plans", token)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["totalItems"], 0);
+        }
+    }
 
-    let token_doc = state
-        .db
-        .database()
-        .collection::<mongodb::bson::Document>("access_tokens")
-        .find_one(filter)
-        .await
-        .ok()?;
+    #[tokio::test]
+    async fn test_updates_need_the_signer() {
+        let profile = json!({ "id": BOB, "inbox": format!("{}/inbox", BOB), "name": "Mallory" });
+        let state = testing::state().await;
+        assert!(refresh_remote_actor(ALICE, &profile, &state).await.is_err());
 
-    token_doc?.get_str("username").ok().map(|s| s.to_string())
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let note = |content: &str, updated: &str| {
+            json!({
+                "id": "https://b.example/notes/1",
+                "type": "Note",
+                "attributedTo": BOB,
+                "content": content,
+                "published": "2024-01-01T00:00:00Z",
+                "updated": updated
+            })
+        };
+        let mut stored = ObjectDocument::from_activitypub(
+            &note("Hello", "2024-01-01T00:00:00Z"),
+            ObjectType::Note,
+        );
+        stored.local = false;
+        state.db_manager.insert_object(stored).await.unwrap();
+
+        let edit = note("Edited", "2024-01-02T00:00:00Z");
+        assert!(
+            edit_remote_object(ALICE, &edit, ObjectType::Note, &state)
+                .await
+                .is_err()
+        );
+        let mut stolen = edit.clone();
+        stolen["attributedTo"] = json!(ALICE);
+        assert!(
+            edit_remote_object(BOB, &stolen, ObjectType::Note, &state)
+                .await
+                .is_err()
+        );
+        edit_remote_object(BOB, &edit, ObjectType::Note, &state)
+            .await
+            .unwrap();
+        let edited = state
+            .db_manager
+            .find_object_by_id("https://b.example/notes/1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(edited.content.as_deref(), Some("Edited"));
+    }
 }
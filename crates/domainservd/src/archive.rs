@@ -0,0 +1,686 @@
+//! Account export and import
+//!
+//! `oxiadm person export` queues a job writing a Mastodon-compatible ZIP
+//! archive of a local account to the archive directory:
+//!
+//! - `actor.json`: the actor, with `icon` and `image` pointing at
+//!   `avatar.*` and `header.*` in the archive
+//! - `outbox.json`: an `OrderedCollection` of `Create` activities for the
+//!   account's posts, whose attachments point at `media_attachments/`
+//! - `followers.csv` and `following_accounts.csv`: account addresses
+//!
+//! Media that cannot be downloaded keeps its original URL.
+//!
+//! `oxiadm person import` restores an archive as a new account, possibly on
+//! another domain: the profile and posts are recreated under the new actor,
+//! which records the old one in `alsoKnownAs`, and the accounts in
+//! `following_accounts.csv` are followed again. Followers have to move on
+//! their own. Attachments from the archive are dropped, as oxifed does not
+//! host media files.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Utc;
+use oxifed::ObjectType;
+use oxifed::client::ActivityPubClient;
+use oxifed::config::{ConfigError, Env, require_positive};
+use oxifed::database::{ActorDocument, ObjectDocument};
+use oxifed::messaging::{
+    FollowActivityMessage, ProfileCreateMessage, ProfileExportMessage, ProfileImportMessage,
+};
+use oxifed::webfinger::WebFingerClient;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use url::Url;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::activitypub::actor_json;
+use crate::db::MongoDB;
+use crate::rabbitmq::{RabbitMQError, create_person_object, handle_follow, split_subject};
+
+/// Objects read from the database per query while exporting
+const PAGE_SIZE: i64 = 100;
+
+/// Header of Mastodon's following list export
+const FOLLOWING_HEADER: &str = "Account address,Show boosts,Notify on new posts,Languages";
+
+/// Account archive settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Directory exports are written to and imports read from
+    pub dir: String,
+    /// Maximum size of a single media file included in an export, in bytes
+    pub max_media_size: usize,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            dir: "/var/lib/oxifed/archives".to_string(),
+            max_media_size: 50 * 1024 * 1024,
+        }
+    }
+}
+
+impl ArchiveConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("ARCHIVE_DIR", &mut self.dir)?;
+        env.set("ARCHIVE_MAX_MEDIA_SIZE", &mut self.max_media_size)
+    }
+
+    /// Check the settings
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if self.dir.is_empty() {
+            return Err(ConfigError::invalid(
+                format!("{}.dir", key),
+                "must not be empty",
+            ));
+        }
+        require_positive(&format!("{}.max_media_size", key), self.max_media_size)
+    }
+
+    /// Path of an archive, refusing names that leave the directory
+    fn path(&self, name: &str) -> Result<PathBuf, RabbitMQError> {
+        if Path::new(name).file_name().and_then(|n| n.to_str()) != Some(name) {
+            return Err(RabbitMQError::ArchiveError(format!(
+                "Invalid archive name '{}'",
+                name
+            )));
+        }
+        Ok(Path::new(&self.dir).join(name))
+    }
+}
+
+fn archive_error(e: impl std::fmt::Display) -> RabbitMQError {
+    RabbitMQError::ArchiveError(e.to_string())
+}
+
+/// Files of an archive being written, keyed by path
+#[derive(Default)]
+struct Files {
+    entries: Vec<(String, Vec<u8>)>,
+    media: HashMap<String, String>,
+}
+
+impl Files {
+    fn add(&mut self, name: impl Into<String>, data: Vec<u8>) {
+        self.entries.push((name.into(), data));
+    }
+
+    /// Download a media file into the archive, returning its path there
+    async fn add_media(
+        &mut self,
+        client: &ActivityPubClient,
+        config: &ArchiveConfig,
+        url: &str,
+        name: impl FnOnce(&str) -> String,
+    ) -> Option<String> {
+        if let Some(path) = self.media.get(url) {
+            return Some(path.clone());
+        }
+        let parsed = Url::parse(url).ok()?;
+        let (data, content_type) = match client.fetch_media(&parsed, config.max_media_size).await {
+            Ok(media) => media,
+            Err(e) => {
+                warn!("Keeping remote URL of {}: {}", url, e);
+                return None;
+            }
+        };
+        let extension = content_type
+            .as_deref()
+            .and_then(|t| t.split(';').next())
+            .and_then(|t| t.split('/').nth(1))
+            .map(|subtype| subtype.trim().trim_start_matches("x-").to_string())
+            .or_else(|| {
+                let file = parsed.path_segments()?.next_back()?;
+                Some(file.rsplit_once('.')?.1.to_string())
+            })
+            .unwrap_or_else(|| "bin".to_string());
+        let path = name(&extension);
+        self.add(path.clone(), data);
+        self.media.insert(url.to_string(), path.clone());
+        Some(path)
+    }
+
+    /// Write the archive, replacing a partial file only once complete
+    fn write(self, path: &Path) -> Result<(), RabbitMQError> {
+        let partial = path.with_extension("zip.part");
+        let mut zip = ZipWriter::new(std::fs::File::create(&partial).map_err(archive_error)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, data) in self.entries {
+            zip.start_file(name, options).map_err(archive_error)?;
+            zip.write_all(&data).map_err(archive_error)?;
+        }
+        zip.finish().map_err(archive_error)?;
+        std::fs::rename(&partial, path).map_err(archive_error)
+    }
+}
+
+/// `user@domain` of an actor, falling back to its ID
+async fn account_address(db: &Arc<MongoDB>, client: &ActivityPubClient, actor_id: &str) -> String {
+    if let Ok(Some(actor)) = db.find_actor_by_id(actor_id).await {
+        return format!("{}@{}", actor.preferred_username, actor.domain);
+    }
+    let Ok(url) = Url::parse(actor_id) else {
+        return actor_id.to_string();
+    };
+    match client.fetch_actor(&url).await {
+        Ok(actor) => actor
+            .additional_properties
+            .get("preferredUsername")
+            .and_then(Value::as_str)
+            .zip(url.host_str())
+            .map(|(username, host)| format!("{}@{}", username, host))
+            .unwrap_or_else(|| actor_id.to_string()),
+        Err(e) => {
+            warn!("Exporting {} by ID: {}", actor_id, e);
+            actor_id.to_string()
+        }
+    }
+}
+
+/// Write the archive of a local account
+pub async fn export_account(
+    db: &Arc<MongoDB>,
+    config: &ArchiveConfig,
+    message: &ProfileExportMessage,
+) -> Result<(), RabbitMQError> {
+    let (username, domain) = split_subject(&message.subject)?;
+    let actor = db
+        .find_actor_by_username(&username, &domain)
+        .await?
+        .ok_or_else(|| RabbitMQError::ProfileNotFound(message.subject.clone()))?;
+    info!("Exporting {}", actor.actor_id);
+
+    let client = ActivityPubClient::new()?;
+    let mut files = Files::default();
+
+    let mut actor_value = actor_json(&actor);
+    for (key, url, file) in [
+        ("icon", &actor.icon, "avatar"),
+        ("image", &actor.image, "header"),
+    ] {
+        if let Some(url) = url
+            && let Some(path) = files
+                .add_media(&client, config, url, |ext| format!("{}.{}", file, ext))
+                .await
+        {
+            actor_value[key]["url"] = json!(path);
+        }
+    }
+    files.add("actor.json", serde_json::to_vec_pretty(&actor_value)?);
+
+    let mut items = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page = db
+            .manager()
+            .find_objects_by_actor(&actor.actor_id, PAGE_SIZE, offset)
+            .await?;
+        for object in &page {
+            let mut value = object.to_activitypub();
+            if let Some(attachments) = value["attachment"].as_array_mut() {
+                for attachment in attachments {
+                    let Some(url) = attachment["url"].as_str().map(str::to_string) else {
+                        continue;
+                    };
+                    let name = |ext: &str| {
+                        format!(
+                            "media_attachments/files/{}.{}",
+                            Uuid::new_v4().simple(),
+                            ext
+                        )
+                    };
+                    if let Some(path) = files.add_media(&client, config, &url, name).await {
+                        attachment["url"] = json!(format!("/{}", path));
+                    }
+                }
+            }
+            items.push(json!({
+                "id": format!("{}/activity", object.object_id),
+                "type": "Create",
+                "actor": actor.actor_id,
+                "published": value["published"],
+                "to": value["to"],
+                "cc": value["cc"],
+                "object": value
+            }));
+        }
+        if (page.len() as i64) < PAGE_SIZE {
+            break;
+        }
+        offset += PAGE_SIZE;
+    }
+    let outbox = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": "outbox.json",
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items
+    });
+    files.add("outbox.json", serde_json::to_vec_pretty(&outbox)?);
+
+    let mut followers = String::from("Account address\n");
+    for follower in db.manager().get_actor_followers(&actor.actor_id).await? {
+        followers.push_str(&account_address(db, &client, &follower).await);
+        followers.push('\n');
+    }
+    files.add("followers.csv", followers.into_bytes());
+
+    let mut following = format!("{}\n", FOLLOWING_HEADER);
+    for followed in db.manager().get_actor_following(&actor.actor_id).await? {
+        following.push_str(&account_address(db, &client, &followed).await);
+        following.push_str(",true,false,\n");
+    }
+    files.add("following_accounts.csv", following.into_bytes());
+
+    let name = format!(
+        "{}@{}-{}.zip",
+        username,
+        domain,
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    let path = config.path(&name)?;
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .map_err(archive_error)?;
+    tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || files.write(&path)
+    })
+    .await
+    .map_err(archive_error)??;
+
+    info!("Exported {} to {}", actor.actor_id, path.display());
+    Ok(())
+}
+
+/// Read the files of an archive that the import uses
+fn read_archive(data: Vec<u8>) -> Result<HashMap<String, Vec<u8>>, RabbitMQError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(archive_error)?;
+    let mut files = HashMap::new();
+    for name in ["actor.json", "outbox.json", "following_accounts.csv"] {
+        let Ok(mut file) = archive.by_name(name) else {
+            continue;
+        };
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).map_err(archive_error)?;
+        files.insert(name.to_string(), content);
+    }
+    Ok(files)
+}
+
+/// Rewrite the addressing of an imported object to the new actor
+fn readdress(
+    recipients: Option<Vec<String>>,
+    old: &Value,
+    new: &ActorDocument,
+) -> Option<Vec<String>> {
+    let old_followers = old.get("followers").and_then(Value::as_str);
+    recipients.map(|recipients| {
+        recipients
+            .into_iter()
+            .map(|recipient| {
+                if Some(recipient.as_str()) == old_followers {
+                    new.followers.clone()
+                } else {
+                    recipient
+                }
+            })
+            .collect()
+    })
+}
+
+/// Restore an archive as a new local account
+pub async fn import_account(
+    db: &Arc<MongoDB>,
+    config: &ArchiveConfig,
+    message: &ProfileImportMessage,
+) -> Result<(), RabbitMQError> {
+    let path = config.path(&message.archive)?;
+    let data = tokio::fs::read(&path).await.map_err(archive_error)?;
+    let files = tokio::task::spawn_blocking(move || read_archive(data))
+        .await
+        .map_err(archive_error)??;
+
+    let old_actor: Value = files
+        .get("actor.json")
+        .map(|data| serde_json::from_slice(data))
+        .transpose()?
+        .ok_or_else(|| archive_error("Archive contains no actor.json"))?;
+    let old_id = old_actor
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| archive_error("actor.json has no id"))?;
+    info!("Importing {} as {}", old_id, message.subject);
+
+    let summary = old_actor
+        .get("summary")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    create_person_object(
+        db,
+        &ProfileCreateMessage::new(message.subject.clone(), summary, None, None),
+    )
+    .await?;
+
+    let (username, domain) = split_subject(&message.subject)?;
+    let actor = db
+        .find_actor_by_username(&username, &domain)
+        .await?
+        .ok_or_else(|| RabbitMQError::ProfileNotFound(message.subject.clone()))?;
+    let mut update = mongodb::bson::doc! { "additional_properties.alsoKnownAs": [old_id] };
+    if let Some(name) = old_actor.get("name").and_then(Value::as_str) {
+        update.insert("name", name);
+    }
+    db.manager().update_actor(&actor.actor_id, update).await?;
+
+    let outbox: Value = files
+        .get("outbox.json")
+        .map(|data| serde_json::from_slice(data))
+        .transpose()?
+        .unwrap_or(Value::Null);
+    let mut posts = 0;
+    let mut dropped_media = 0;
+    for item in outbox["orderedItems"].as_array().into_iter().flatten() {
+        let object = &item["object"];
+        if item["type"] != "Create" || object["attributedTo"] != old_id {
+            continue;
+        }
+        let object_type = match object["type"].as_str() {
+            Some("Note") => ObjectType::Note,
+            Some("Article") => ObjectType::Article,
+            _ => continue,
+        };
+        let mut object_doc = ObjectDocument::from_activitypub(object, object_type);
+        object_doc.object_id = format!("https://{}/objects/{}", domain, Uuid::new_v4());
+        object_doc.attributed_to = actor.actor_id.clone();
+        object_doc.url = None;
+        object_doc.to = readdress(object_doc.to, &old_actor, &actor);
+        object_doc.cc = readdress(object_doc.cc, &old_actor, &actor);
+        object_doc.local = true;
+        if let Some(attachments) = object_doc.attachment.take() {
+            let (kept, dropped): (Vec<_>, Vec<_>) = attachments
+                .into_iter()
+                .partition(|attachment| Url::parse(&attachment.url).is_ok());
+            dropped_media += dropped.len();
+            object_doc.attachment = (!kept.is_empty()).then_some(kept);
+        }
+        db.manager().insert_object(object_doc).await?;
+        posts += 1;
+    }
+
+    let following = files
+        .get("following_accounts.csv")
+        .map(|data| String::from_utf8_lossy(data).into_owned())
+        .unwrap_or_default();
+    let webfinger = WebFingerClient::new();
+    let mut follows = 0;
+    for line in following.lines().skip(1) {
+        let address = line.split(',').next().unwrap_or_default().trim();
+        if address.is_empty() {
+            continue;
+        }
+        let target = if address.contains("://") {
+            address.to_string()
+        } else {
+            match webfinger.finger(&format!("acct:{}", address), None).await {
+                Ok(jrd) => match jrd.find_link("self").and_then(|link| link.href.clone()) {
+                    Some(href) => href,
+                    None => {
+                        warn!("No actor link for {}", address);
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to resolve {}: {}", address, e);
+                    continue;
+                }
+            }
+        };
+        let follow = FollowActivityMessage::new(actor.actor_id.clone(), target);
+        match handle_follow(db, &follow).await {
+            Ok(()) => follows += 1,
+            Err(e) => warn!("Failed to follow {}: {}", address, e),
+        }
+    }
+
+    info!(
+        "Imported {} as {}: {} posts, {} follows, {} attachments dropped",
+        old_id, actor.actor_id, posts, follows, dropped_media
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use oxifed::database::{DomainDocument, DomainStatus, RegistrationMode};
+
+    const OLD_ID: &str = "https://old.example/users/alice";
+    const OLD_FOLLOWERS: &str = "https://old.example/users/alice/followers";
+
+    /// Config using a fresh temporary directory
+    fn temp_config() -> ArchiveConfig {
+        let dir = std::env::temp_dir().join(format!("oxifed-archive-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        ArchiveConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn actor() -> Value {
+        json!({
+            "id": OLD_ID,
+            "type": "Person",
+            "name": "Alice",
+            "summary": "Bakes bread",
+            "followers": OLD_FOLLOWERS
+        })
+    }
+
+    fn create(id: &str, object_type: &str, author: &str) -> Value {
+        json!({
+            "id": format!("{}/activity", id),
+            "type": "Create",
+            "actor": author,
+            "object": {
+                "id": id,
+                "type": object_type,
+                "attributedTo": author,
+                "content": "Bread",
+                "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                "cc": [OLD_FOLLOWERS]
+            }
+        })
+    }
+
+    /// Write an archive with the given files to the config's directory
+    fn write_archive(config: &ArchiveConfig, name: &str, entries: &[(&str, Vec<u8>)]) {
+        let mut files = Files::default();
+        for (file, data) in entries {
+            files.add(*file, data.clone());
+        }
+        files.write(&config.path(name).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ArchiveConfig::default().validate("archive").is_ok());
+        let no_dir = ArchiveConfig {
+            dir: String::new(),
+            ..Default::default()
+        };
+        assert!(no_dir.validate("archive").is_err());
+        let no_media = ArchiveConfig {
+            max_media_size: 0,
+            ..Default::default()
+        };
+        assert!(no_media.validate("archive").is_err());
+    }
+
+    #[test]
+    fn test_archive_path() {
+        let config = ArchiveConfig::default();
+        assert_eq!(
+            config.path("alice@example.com-20240101000000.zip").unwrap(),
+            Path::new("/var/lib/oxifed/archives/alice@example.com-20240101000000.zip")
+        );
+        for name in [
+            "",
+            ".",
+            "..",
+            "../alice.zip",
+            "export/alice.zip",
+            "/etc/passwd",
+        ] {
+            assert!(
+                matches!(config.path(name), Err(RabbitMQError::ArchiveError(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let config = temp_config();
+        write_archive(
+            &config,
+            "alice.zip",
+            &[
+                ("actor.json", b"{}".to_vec()),
+                ("followers.csv", b"Account address\n".to_vec()),
+                ("following_accounts.csv", FOLLOWING_HEADER.into()),
+            ],
+        );
+        let data = std::fs::read(config.path("alice.zip").unwrap()).unwrap();
+        let files = read_archive(data).unwrap();
+        assert_eq!(files["actor.json"], b"{}");
+        assert_eq!(files["following_accounts.csv"], FOLLOWING_HEADER.as_bytes());
+        assert!(!files.contains_key("followers.csv"));
+        assert!(!files.contains_key("outbox.json"));
+
+        assert!(matches!(
+            read_archive(b"not a zip".to_vec()),
+            Err(RabbitMQError::ArchiveError(_))
+        ));
+        std::fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_refuses_unusable_archives() {
+        let state = testing::state().await;
+        let config = temp_config();
+        let import = |archive: &str| {
+            ProfileImportMessage::new("alice@example.com".to_string(), archive.to_string())
+        };
+
+        // Names outside the directory, missing archives and archives without
+        // an actor fail before anything is created
+        for archive in ["../alice.zip", "missing.zip"] {
+            let result = import_account(&state.db, &config, &import(archive)).await;
+            assert!(matches!(result, Err(RabbitMQError::ArchiveError(_))));
+        }
+        write_archive(&config, "empty.zip", &[("outbox.json", b"{}".to_vec())]);
+        let result = import_account(&state.db, &config, &import("empty.zip")).await;
+        assert!(matches!(result, Err(RabbitMQError::ArchiveError(_))));
+        std::fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_account() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let now = Utc::now();
+        state
+            .db_manager
+            .insert_domain(DomainDocument {
+                id: None,
+                domain: "example.com".to_string(),
+                name: None,
+                description: None,
+                contact_email: None,
+                rules: None,
+                registration_mode: RegistrationMode::Closed,
+                authorized_fetch: false,
+                max_note_length: None,
+                max_file_size: None,
+                allowed_file_types: None,
+                domain_key_id: None,
+                config: None,
+                status: DomainStatus::Active,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        let config = temp_config();
+        let outbox = json!({
+            "type": "OrderedCollection",
+            "orderedItems": [
+                create("https://old.example/notes/1", "Note", OLD_ID),
+                create("https://old.example/notes/2", "Note", "https://old.example/users/bob"),
+                create("https://old.example/notes/3", "Image", OLD_ID)
+            ]
+        });
+        write_archive(
+            &config,
+            "alice.zip",
+            &[
+                ("actor.json", serde_json::to_vec(&actor()).unwrap()),
+                ("outbox.json", serde_json::to_vec(&outbox).unwrap()),
+                ("following_accounts.csv", FOLLOWING_HEADER.into()),
+            ],
+        );
+
+        let message =
+            ProfileImportMessage::new("alice@example.com".to_string(), "alice.zip".to_string());
+        import_account(&state.db, &config, &message).await.unwrap();
+
+        let actor = state
+            .db_manager
+            .find_actor_by_username("alice", "example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(actor.name, "Alice");
+        assert_eq!(actor.summary.as_deref(), Some("Bakes bread"));
+        let also_known_as = actor
+            .additional_properties
+            .as_ref()
+            .and_then(|props| props.get_array("alsoKnownAs").ok())
+            .unwrap();
+        assert_eq!(also_known_as[0].as_str(), Some(OLD_ID));
+
+        // Only the account's own notes are restored, addressed to the new
+        // followers collection
+        let objects = state
+            .db_manager
+            .find_objects_by_actor(&actor.actor_id, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(objects.len(), 1);
+        assert!(
+            objects[0]
+                .object_id
+                .starts_with("https://example.com/objects/")
+        );
+        assert_eq!(objects[0].cc, Some(vec![actor.followers.clone()]));
+
+        // Importing the same account again is refused
+        assert!(import_account(&state.db, &config, &message).await.is_err());
+        std::fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
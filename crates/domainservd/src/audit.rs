@@ -0,0 +1,96 @@
+//! Audit log of administrative actions
+//!
+//! adminservd sends an [`AuditEventMessage`] for every admin API request
+//! that changes state and for every request it denies for lack of a role.
+//! They are appended to the `audit_log` collection, which nothing updates
+//! or deletes, and listed through the audit RPC queue.
+
+use std::sync::Arc;
+
+use mongodb::bson::oid::ObjectId;
+use oxifed::database::{AuditLogDocument, DatabaseManager};
+use oxifed::messaging::{
+    AuditEntryInfo, AuditEventMessage, AuditRpcRequest, AuditRpcRequestType, AuditRpcResponse,
+};
+use tracing::info;
+
+use crate::db::MongoDB;
+use crate::rabbitmq::RabbitMQError;
+
+/// Most entries returned by one listing
+const MAX_LIMIT: u32 = 200;
+
+/// Append an audit event to the log
+pub async fn record(db: &Arc<MongoDB>, event: &AuditEventMessage) -> Result<(), RabbitMQError> {
+    db.manager()
+        .insert_audit_entry(AuditLogDocument::from(event.clone()))
+        .await?;
+    info!(
+        "Audit: {} {} {} ({})",
+        event.actor,
+        event.action,
+        event.target.as_deref().unwrap_or("-"),
+        event.status
+    );
+    Ok(())
+}
+
+/// Answer an audit RPC request
+pub async fn handle_rpc(db: &DatabaseManager, request: AuditRpcRequest) -> AuditRpcResponse {
+    let request_id = request.request_id;
+    match request.request_type {
+        AuditRpcRequestType::ListEntries {
+            actor,
+            action,
+            target,
+            before,
+            limit,
+        } => {
+            let before = match before.as_deref().map(ObjectId::parse_str).transpose() {
+                Ok(before) => before,
+                Err(_) => {
+                    return AuditRpcResponse::error(
+                        request_id,
+                        "Invalid audit entry ID in 'before'".to_string(),
+                    );
+                }
+            };
+            match db
+                .find_audit_entries(
+                    actor.as_deref(),
+                    action.as_deref(),
+                    target.as_deref(),
+                    before,
+                    i64::from(limit.clamp(1, MAX_LIMIT)),
+                )
+                .await
+            {
+                Ok(entries) => AuditRpcResponse::entry_list(
+                    request_id,
+                    entries.into_iter().map(entry_info).collect(),
+                ),
+                Err(e) => AuditRpcResponse::error(
+                    request_id,
+                    format!("Failed to list audit entries: {}", e),
+                ),
+            }
+        }
+    }
+}
+
+fn entry_info(entry: AuditLogDocument) -> AuditEntryInfo {
+    AuditEntryInfo {
+        id: entry.id.map(|id| id.to_hex()).unwrap_or_default(),
+        actor: entry.actor,
+        role: entry.role,
+        action: entry.action,
+        method: entry.method,
+        path: entry.path,
+        target: entry.target,
+        status: entry.status,
+        before: entry.before,
+        after: entry.after,
+        changes: entry.changes,
+        timestamp: entry.timestamp.to_rfc3339(),
+    }
+}
@@ -0,0 +1,234 @@
+//! Blogs
+//!
+//! The articles of a local actor make up their blog. Articles posted
+//! through C2S get a slug and are published at `{actor}/articles/{slug}`,
+//! their `url`; a cover image is given as `image`. The public and unlisted
+//! articles are listed, newest first, in the actor's article index at
+//! `{actor}/articles` and per hashtag at `{actor}/articles/tagged/{tag}`,
+//! ActivityStreams collections paged with `?page=`. Browsers get HTML pages
+//! of the index, the archives and the articles.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use oxifed::database::{
+    ActorDocument, ActorStatus, AttachmentDocument, ObjectStatus, article_url, articles_url,
+    normalize_hashtag,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tracing::error;
+
+use crate::caching::with_last_modified;
+use crate::html;
+use crate::{AppState, extract_domain_from_headers};
+
+/// Articles on a page of an index or archive
+const PAGE_SIZE: u64 = 20;
+
+/// Query of article indexes and archives
+#[derive(Debug, Deserialize)]
+pub struct ArticlesQuery {
+    /// Page to list, starting at 1
+    page: Option<u64>,
+}
+
+/// Give an article posted through C2S its slug and human-readable URL
+///
+/// The slug is made from the client's `slug` or the title. A cover image
+/// given by URL becomes an `Image`.
+pub async fn prepare_article(
+    article: &mut Map<String, Value>,
+    state: &AppState,
+) -> Result<(), String> {
+    let author = article
+        .get("attributedTo")
+        .and_then(Value::as_str)
+        .ok_or("Article must have an author")?
+        .to_string();
+    let requested = article.remove("slug");
+    let title = requested
+        .as_ref()
+        .and_then(Value::as_str)
+        .or_else(|| article.get("name").and_then(Value::as_str))
+        .unwrap_or_default();
+    let slug = state
+        .db_manager
+        .unique_slug(&author, title)
+        .await
+        .map_err(|e| format!("Failed to find a slug for the article: {}", e))?;
+    article.insert("url".to_string(), json!(article_url(&author, &slug)));
+
+    if let Some(image) = article.get("image").filter(|image| !image.is_null()) {
+        let image = AttachmentDocument::parse_image(Some(image))
+            .filter(|image| image.url.starts_with("https://") || image.url.starts_with("http://"))
+            .ok_or_else(|| format!("Invalid cover image: {}", image))?;
+        article.insert("image".to_string(), image.to_activitypub());
+    }
+    Ok(())
+}
+
+/// Active local actor whose blog is requested
+async fn blog_author(
+    username: &str,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<(ActorDocument, String), StatusCode> {
+    let domain = extract_domain_from_headers(headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let author = state
+        .db_manager
+        .find_actor_by_username(username, &domain)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up {}: {}", username, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if author.status != ActorStatus::Active {
+        return Err(StatusCode::GONE);
+    }
+    Ok((author, domain))
+}
+
+/// Get the article index of an actor
+pub async fn get_articles(
+    Path(username): Path<String>,
+    Query(query): Query<ArticlesQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (author, _) = blog_author(&username, &headers, &state).await?;
+    list_articles(&author, None, query, &headers, &state).await
+}
+
+/// Get the archive of an actor's articles with a hashtag
+pub async fn get_tagged_articles(
+    Path((username, tag)): Path<(String, String)>,
+    Query(query): Query<ArticlesQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let hashtag = normalize_hashtag(&tag);
+    if hashtag.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let (author, _) = blog_author(&username, &headers, &state).await?;
+    list_articles(&author, Some(&hashtag), query, &headers, &state).await
+}
+
+/// URL of an actor's archive of articles with a hashtag
+pub fn archive_url(actor_id: &str, hashtag: &str) -> String {
+    format!(
+        "{}/tagged/{}",
+        articles_url(actor_id),
+        normalize_hashtag(hashtag)
+    )
+}
+
+/// Index or archive of articles, as a collection or an HTML page
+///
+/// Without a `page`, the collection only links to its first page.
+async fn list_articles(
+    author: &ActorDocument,
+    hashtag: Option<&str>,
+    query: ArticlesQuery,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<Response, StatusCode> {
+    let collection_id = match hashtag {
+        Some(hashtag) => archive_url(&author.actor_id, hashtag),
+        None => articles_url(&author.actor_id),
+    };
+    let wants_html = html::prefers_html(headers);
+    let page = query.page.filter(|page| *page > 0);
+    let number = page.unwrap_or(1);
+
+    let (articles, total) = state
+        .db_manager
+        .find_articles(
+            &author.actor_id,
+            hashtag,
+            PAGE_SIZE as i64,
+            (number - 1) * PAGE_SIZE,
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to list articles of {}: {}", author.actor_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let has_next = number * PAGE_SIZE < total;
+
+    if wants_html {
+        let page =
+            html::article_index_page(author, hashtag, &articles, number, has_next, &collection_id);
+        return Ok(html::vary_accept(page));
+    }
+
+    let collection = match page {
+        None => json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "OrderedCollection",
+            "id": collection_id,
+            "totalItems": total,
+            "first": format!("{}?page=1", collection_id)
+        }),
+        Some(number) => json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "OrderedCollectionPage",
+            "id": format!("{}?page={}", collection_id, number),
+            "partOf": collection_id,
+            "totalItems": total,
+            "orderedItems": articles.iter().map(|article| article.to_activitypub()).collect::<Vec<_>>(),
+            "prev": (number > 1).then(|| format!("{}?page={}", collection_id, number - 1)),
+            "next": has_next.then(|| format!("{}?page={}", collection_id, number + 1))
+        }),
+    };
+    Ok(html::vary_accept(
+        (
+            StatusCode::OK,
+            [("Content-Type", "application/activity+json")],
+            Json(collection),
+        )
+            .into_response(),
+    ))
+}
+
+/// Get an article at its human-readable URL
+///
+/// Browsers get its page, everyone else its ActivityStreams JSON.
+pub async fn get_article(
+    Path((username, slug)): Path<(String, String)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (author, domain) = blog_author(&username, &headers, &state).await?;
+    let article = state
+        .db_manager
+        .find_article_by_slug(&author.actor_id, &slug)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up article {}: {}", slug, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|article| article.status != ObjectStatus::Scheduled && html::is_public(article))
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let modified = article
+        .updated
+        .or(article.published)
+        .unwrap_or(article.created_at);
+    let response = if html::prefers_html(&headers) {
+        html::object_page(&article, Some(&author), &domain, &state)
+    } else {
+        (
+            StatusCode::OK,
+            [("Content-Type", "application/activity+json")],
+            Json(crate::activitypub::object_json(&article, &domain, &state)),
+        )
+            .into_response()
+    };
+    Ok(html::vary_accept(with_last_modified(response, modified)))
+}
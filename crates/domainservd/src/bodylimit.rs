@@ -0,0 +1,244 @@
+//! Request body limits and content types
+//!
+//! Inbox POSTs must carry an ActivityStreams media type
+//! (`application/activity+json`, or `application/ld+json` with the
+//! ActivityStreams profile) and are otherwise answered with 415. Bodies of
+//! inbox, C2S and media upload requests are limited in size and answered
+//! with 413 beyond the limit.
+//!
+//! A domain can lower or raise the configured limits with a `body_limits`
+//! object in its configuration, for example `{"body_limits": {"inbox": 262144}}`.
+//! Media uploads also honour the domain's `max_file_size`. Inbox bodies are
+//! additionally capped by the signature verifier's `max_body_size`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use oxifed::config::{ConfigError, Env, require_positive};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::domain_config::DomainConfigCache;
+use crate::extract_domain_from_headers;
+
+/// ActivityStreams profile required with `application/ld+json`
+const ACTIVITYSTREAMS_PROFILE: &str = "https://www.w3.org/ns/activitystreams";
+
+/// Requests sharing a body limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyClass {
+    /// Activities delivered to an inbox
+    Inbox,
+    /// JSON objects and activities posted by local clients
+    C2s,
+    /// Media uploads
+    Media,
+}
+
+/// Body size limits, in bytes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BodyLimitConfig {
+    /// Largest activity accepted by an inbox
+    pub inbox: usize,
+    /// Largest object or activity accepted from clients
+    pub c2s: usize,
+    /// Largest media upload
+    pub media: usize,
+}
+
+impl Default for BodyLimitConfig {
+    fn default() -> Self {
+        Self {
+            inbox: 1024 * 1024,
+            c2s: 1024 * 1024,
+            media: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl BodyLimitConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("BODY_LIMIT_INBOX", &mut self.inbox)?;
+        env.set("BODY_LIMIT_C2S", &mut self.c2s)?;
+        env.set("BODY_LIMIT_MEDIA", &mut self.media)
+    }
+
+    /// Check that all limits are positive
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.inbox", key), self.inbox)?;
+        require_positive(&format!("{}.c2s", key), self.c2s)?;
+        require_positive(&format!("{}.media", key), self.media)
+    }
+}
+
+/// The `body_limits` object of a domain configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+struct DomainBodyLimits {
+    inbox: Option<usize>,
+    c2s: Option<usize>,
+    media: Option<usize>,
+}
+
+/// Applies body limits, with the overrides of each domain
+pub struct BodyLimiter {
+    config: BodyLimitConfig,
+    domains: Arc<DomainConfigCache>,
+}
+
+impl BodyLimiter {
+    pub fn new(config: BodyLimitConfig, domains: Arc<DomainConfigCache>) -> Self {
+        Self { config, domains }
+    }
+
+    /// Body limit of a request class on a domain
+    async fn limit(&self, domain: Option<&str>, class: BodyClass) -> usize {
+        let default = match class {
+            BodyClass::Inbox => self.config.inbox,
+            BodyClass::C2s => self.config.c2s,
+            BodyClass::Media => self.config.media,
+        };
+        let Some(domain) = domain else {
+            return default;
+        };
+
+        let overrides: DomainBodyLimits = self.domains.section(domain, "body_limits").await;
+        match class {
+            BodyClass::Inbox => overrides.inbox.unwrap_or(default),
+            BodyClass::C2s => overrides.c2s.unwrap_or(default),
+            BodyClass::Media => match overrides.media {
+                Some(limit) => limit,
+                None => self
+                    .domains
+                    .domain(domain)
+                    .await
+                    .and_then(|domain_doc| domain_doc.max_file_size)
+                    .and_then(|limit| usize::try_from(limit).ok())
+                    .unwrap_or(default),
+            },
+        }
+    }
+}
+
+/// Whether a `Content-Type` is an ActivityStreams media type
+fn is_activitystreams(content_type: &str) -> bool {
+    let mut parts = content_type.split(';').map(str::trim);
+    let essence = parts.next().unwrap_or_default().to_ascii_lowercase();
+    match essence.as_str() {
+        "application/activity+json" => true,
+        "application/ld+json" => parts.any(|param| {
+            param.split_once('=').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("profile")
+                    && value
+                        .trim()
+                        .trim_matches('"')
+                        .split_whitespace()
+                        .any(|profile| profile == ACTIVITYSTREAMS_PROFILE)
+            })
+        }),
+        _ => false,
+    }
+}
+
+/// Enforce the content type and body limit of a request class
+///
+/// Bodies are buffered up to the limit, so the routes this is layered on
+/// should disable axum's default body limit.
+pub async fn limit_body(
+    State((limiter, class)): State<(Arc<BodyLimiter>, BodyClass)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if class == BodyClass::Inbox {
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !is_activitystreams(content_type) {
+            debug!("Refused inbox request with content type {:?}", content_type);
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected application/activity+json",
+            )
+                .into_response();
+        }
+    }
+
+    let domain = extract_domain_from_headers(request.headers());
+    let limit = limiter.limit(domain.as_deref(), class).await;
+
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return payload_too_large(limit);
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, limit).await {
+        Ok(body) => body,
+        Err(_) => return payload_too_large(limit),
+    };
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+fn payload_too_large(limit: usize) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Request body exceeds {} bytes", limit),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_activitystreams() {
+        assert!(is_activitystreams("application/activity+json"));
+        assert!(is_activitystreams(
+            "Application/Activity+JSON; charset=utf-8"
+        ));
+        assert!(is_activitystreams(
+            "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+        ));
+        assert!(is_activitystreams(
+            "application/ld+json;profile=\"https://example.com/p https://www.w3.org/ns/activitystreams\""
+        ));
+        assert!(!is_activitystreams("application/ld+json"));
+        assert!(!is_activitystreams(
+            "application/ld+json; profile=\"https://example.com/profile\""
+        ));
+        assert!(!is_activitystreams("application/json"));
+        assert!(!is_activitystreams(""));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(BodyLimitConfig::default().validate("body_limits").is_ok());
+        let config = BodyLimitConfig {
+            media: 0,
+            ..Default::default()
+        };
+        assert!(config.validate("body_limits").is_err());
+    }
+
+    #[test]
+    fn test_payload_too_large() {
+        assert_eq!(
+            payload_too_large(1024).status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+}
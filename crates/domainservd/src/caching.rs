@@ -0,0 +1,236 @@
+//! HTTP caching of actors, objects and collections
+//!
+//! Remote servers fetch the same actors and objects again and again, so GET
+//! responses of these resources carry an `ETag` computed from the body and a
+//! `Cache-Control` policy per resource class. Handlers add `Last-Modified`
+//! where the documents record an update time. Conditional requests with a
+//! matching `If-None-Match`, or an `If-Modified-Since` no older than the
+//! `Last-Modified` time, are answered with 304.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use oxifed::config::{ConfigError, Env};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+/// Resources sharing a cache policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheClass {
+    Actor,
+    Object,
+    Collection,
+}
+
+/// `Cache-Control` policies of GET responses; an empty policy sends no
+/// header
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpCacheConfig {
+    /// Actor documents
+    pub actor: String,
+    /// Objects and activities
+    pub object: String,
+    /// Outboxes, follower and other collections
+    pub collection: String,
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            actor: "public, max-age=180".to_string(),
+            object: "public, max-age=300".to_string(),
+            collection: "public, max-age=60".to_string(),
+        }
+    }
+}
+
+impl HttpCacheConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("HTTP_CACHE_ACTOR", &mut self.actor)?;
+        env.set("HTTP_CACHE_OBJECT", &mut self.object)?;
+        env.set("HTTP_CACHE_COLLECTION", &mut self.collection)
+    }
+
+    /// Check that the policies are valid header values
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        for (name, policy) in [
+            ("actor", &self.actor),
+            ("object", &self.object),
+            ("collection", &self.collection),
+        ] {
+            HeaderValue::from_str(policy)
+                .map_err(|e| ConfigError::invalid(format!("{}.{}", key, name), e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn policy(&self, class: CacheClass) -> &str {
+        match class {
+            CacheClass::Actor => &self.actor,
+            CacheClass::Object => &self.object,
+            CacheClass::Collection => &self.collection,
+        }
+    }
+}
+
+/// Set the `Last-Modified` header of a response
+pub fn with_last_modified(mut response: Response, modified: DateTime<Utc>) -> Response {
+    let date = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    if let Ok(value) = HeaderValue::from_str(&date) {
+        response.headers_mut().insert(header::LAST_MODIFIED, value);
+    }
+    response
+}
+
+/// Add `ETag` and `Cache-Control` to GET responses and answer conditional
+/// requests
+///
+/// Handlers that set their own `Cache-Control`, for example for
+/// non-public objects, keep it.
+pub async fn conditional_get(
+    State((config, class)): State<(Arc<HttpCacheConfig>, CacheClass)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let request_headers = request.headers().clone();
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to read response body for caching: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(&body)[..16]));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(header::ETAG, value);
+    }
+    let policy = config.policy(class);
+    if !policy.is_empty()
+        && !parts.headers.contains_key(header::CACHE_CONTROL)
+        && let Ok(value) = HeaderValue::from_str(policy)
+    {
+        parts.headers.insert(header::CACHE_CONTROL, value);
+    }
+
+    if is_not_modified(&request_headers, &parts.headers, &etag) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Whether the client's copy is still current
+///
+/// `If-Modified-Since` is only considered without `If-None-Match`.
+fn is_not_modified(request: &HeaderMap, response: &HeaderMap, etag: &str) -> bool {
+    if let Some(if_none_match) = request
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag);
+    }
+
+    let parse = |headers: &HeaderMap, name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+    };
+    match (
+        parse(request, header::IF_MODIFIED_SINCE),
+        parse(response, header::LAST_MODIFIED),
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"0123456789abcdef\"";
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let response = HeaderMap::new();
+        for matching in [
+            ETAG,
+            "*",
+            "\"other\", \"0123456789abcdef\"",
+            "W/\"0123456789abcdef\"",
+        ] {
+            let request = headers(&[(header::IF_NONE_MATCH, matching)]);
+            assert!(is_not_modified(&request, &response, ETAG), "{}", matching);
+        }
+        let request = headers(&[(header::IF_NONE_MATCH, "\"other\"")]);
+        assert!(!is_not_modified(&request, &response, ETAG));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let response = headers(&[(header::LAST_MODIFIED, "Tue, 15 Nov 1994 08:12:31 GMT")]);
+        let current = headers(&[(header::IF_MODIFIED_SINCE, "Tue, 15 Nov 1994 08:12:31 GMT")]);
+        assert!(is_not_modified(&current, &response, ETAG));
+        let stale = headers(&[(header::IF_MODIFIED_SINCE, "Mon, 14 Nov 1994 08:12:31 GMT")]);
+        assert!(!is_not_modified(&stale, &response, ETAG));
+        // Without Last-Modified nothing is known to be unchanged
+        assert!(!is_not_modified(&current, &HeaderMap::new(), ETAG));
+    }
+
+    #[test]
+    fn test_if_none_match_takes_precedence() {
+        let response = headers(&[(header::LAST_MODIFIED, "Tue, 15 Nov 1994 08:12:31 GMT")]);
+        let request = headers(&[
+            (header::IF_NONE_MATCH, "\"other\""),
+            (header::IF_MODIFIED_SINCE, "Tue, 15 Nov 1994 08:12:31 GMT"),
+        ]);
+        assert!(!is_not_modified(&request, &response, ETAG));
+    }
+
+    #[test]
+    fn test_with_last_modified() {
+        let modified = DateTime::parse_from_rfc3339("1994-11-15T08:12:31Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let response = with_last_modified(StatusCode::OK.into_response(), modified);
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Tue, 15 Nov 1994 08:12:31 GMT"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! domainservd configuration
+//!
+//! Loaded with [`oxifed::config`] from the files passed with `--config` and
+//! the environment variables listed in the README.
+
+use std::net::SocketAddr;
+
+use oxifed::backpressure::ConsumerLimits;
+use oxifed::bus::BusConfig;
+use oxifed::client::FetchLimits;
+use oxifed::config::{AmqpConfig, Config, ConfigError, DatabaseConfig, Env, require_positive};
+use oxifed::database::WriteBatchConfig;
+use oxifed::egress::EgressPolicy;
+use oxifed::pki::KeyEncryptionConfig;
+use oxifed::shutdown::DEFAULT_DRAIN_TIMEOUT_SECS;
+use oxifed::signature_middleware::SignatureVerificationConfig;
+use serde::Deserialize;
+
+use crate::archive::ArchiveConfig;
+use crate::bodylimit::BodyLimitConfig;
+use crate::caching::HttpCacheConfig;
+use crate::crawler::CrawlerConfig;
+use crate::dlq::DlqConfig;
+use crate::media::MediaProxyConfig;
+use crate::outbox::OutboxConfig;
+use crate::ratelimit::RateLimitConfig;
+use crate::relay::RelayConfig;
+use crate::retention::RetentionConfig;
+
+/// domainservd configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DomainservdConfig {
+    /// Address the HTTP server listens on
+    pub bind_address: String,
+    pub database: DatabaseConfig,
+    pub amqp: AmqpConfig,
+    /// Bus the outbox relay publishes emails to; everything else uses AMQP
+    pub bus: BusConfig,
+    pub discovery: DiscoveryConfig,
+    pub media_proxy: MediaProxyConfig,
+    pub outbox: OutboxConfig,
+    pub dlq: DlqConfig,
+    /// Verification of inbound HTTP signatures
+    pub signatures: SignatureVerificationConfig,
+    /// Per-IP and per-actor request rate limits
+    pub rate_limits: RateLimitConfig,
+    /// Request body size limits
+    pub body_limits: BodyLimitConfig,
+    /// `Cache-Control` policies of actors, objects and collections
+    pub http_cache: HttpCacheConfig,
+    /// Relay mode of the instance actors
+    pub relay: RelayConfig,
+    /// Account export and import archives
+    pub archives: ArchiveConfig,
+    /// Limits of the activity and RPC consumers
+    pub consumer: ConsumerLimits,
+    /// Batching of incoming activity and post inserts
+    pub write_batch: WriteBatchConfig,
+    /// Pruning of old remote posts and activities
+    pub retention: RetentionConfig,
+    /// NodeInfo crawling of peers
+    pub crawler: CrawlerConfig,
+    /// Hosts outbound fetches and deliveries may reach
+    pub egress: EgressPolicy,
+    /// Timeouts and size limits of fetches and deliveries
+    pub fetch: FetchLimits,
+    /// Encryption of the stored VAPID private keys
+    pub key_encryption: KeyEncryptionConfig,
+    /// Publish deliveries and incoming messages with per-domain routing keys
+    /// so dedicated workers can serve single domains
+    pub domain_routing: bool,
+    /// Time in-flight work gets to finish on shutdown, in seconds
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for DomainservdConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:8080".to_string(),
+            database: DatabaseConfig::default(),
+            amqp: AmqpConfig::default(),
+            bus: BusConfig::default(),
+            discovery: DiscoveryConfig::default(),
+            media_proxy: MediaProxyConfig::default(),
+            outbox: OutboxConfig::default(),
+            dlq: DlqConfig::default(),
+            signatures: SignatureVerificationConfig::default(),
+            rate_limits: RateLimitConfig::default(),
+            body_limits: BodyLimitConfig::default(),
+            http_cache: HttpCacheConfig::default(),
+            relay: RelayConfig::default(),
+            archives: ArchiveConfig::default(),
+            consumer: ConsumerLimits::default(),
+            write_batch: WriteBatchConfig::default(),
+            retention: RetentionConfig::default(),
+            crawler: CrawlerConfig::default(),
+            egress: EgressPolicy::default(),
+            fetch: FetchLimits::default(),
+            key_encryption: KeyEncryptionConfig::default(),
+            domain_routing: false,
+            shutdown_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// URLs advertised via domain-level WebFinger
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    pub admin_api_url: Option<String>,
+    pub oidc_issuer_url: Option<String>,
+    pub oidc_audience: Option<String>,
+}
+
+impl Config for DomainservdConfig {
+    fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("BIND_ADDRESS", &mut self.bind_address)?;
+        self.database.apply_env(env)?;
+        self.amqp.apply_env(env)?;
+        self.bus.apply_env(env)?;
+        env.set_opt("ADMIN_API_URL", &mut self.discovery.admin_api_url)?;
+        env.set_opt("OIDC_ISSUER_URL", &mut self.discovery.oidc_issuer_url)?;
+        env.set_opt("OIDC_AUDIENCE", &mut self.discovery.oidc_audience)?;
+        self.media_proxy.apply_env(env)?;
+        self.outbox.apply_env(env)?;
+        self.dlq.apply_env(env)?;
+        self.signatures.apply_env("SIGNATURE", env)?;
+        self.rate_limits.apply_env(env)?;
+        self.body_limits.apply_env(env)?;
+        self.http_cache.apply_env(env)?;
+        self.relay.apply_env(env)?;
+        self.archives.apply_env(env)?;
+        self.consumer.apply_env("CONSUMER", env)?;
+        self.write_batch.apply_env("WRITE", env)?;
+        self.retention.apply_env(env)?;
+        self.crawler.apply_env(env)?;
+        self.egress.apply_env(env)?;
+        self.fetch.apply_env(env)?;
+        self.key_encryption.apply_env(env)?;
+        env.set("DOMAIN_ROUTING", &mut self.domain_routing)?;
+        env.set("SHUTDOWN_TIMEOUT_SECS", &mut self.shutdown_timeout_secs)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.bind_address
+            .parse::<SocketAddr>()
+            .map_err(|e| ConfigError::invalid("bind_address", e.to_string()))?;
+        for (key, url) in [
+            ("discovery.admin_api_url", &self.discovery.admin_api_url),
+            ("discovery.oidc_issuer_url", &self.discovery.oidc_issuer_url),
+        ] {
+            if let Some(url) = url {
+                url::Url::parse(url).map_err(|e| ConfigError::invalid(key, e.to_string()))?;
+            }
+        }
+        self.database.validate("database")?;
+        require_positive("outbox.poll_interval_ms", self.outbox.poll_interval_ms)?;
+        self.signatures.validate("signatures")?;
+        self.rate_limits.validate("rate_limits")?;
+        self.body_limits.validate("body_limits")?;
+        self.http_cache.validate("http_cache")?;
+        self.archives.validate("archives")?;
+        self.consumer.validate("consumer")?;
+        self.write_batch.validate("write_batch")?;
+        self.retention.validate("retention")?;
+        self.crawler.validate("crawler")?;
+        self.egress.validate("egress")?;
+        self.fetch.validate("fetch")?;
+        self.key_encryption.validate("key_encryption")
+    }
+}
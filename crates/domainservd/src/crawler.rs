@@ -0,0 +1,311 @@
+//! NodeInfo crawling of peers
+//!
+//! The crawler periodically fetches the NodeInfo of the instances in the
+//! registry whose last fetch is older than the recrawl interval, following
+//! `/.well-known/nodeinfo` to the newest schema the instance offers. It
+//! records the software name and version and whether registrations are
+//! open, or the error when the fetch failed, which the instance RPC sums up
+//! into federation statistics. Suspended domains are not contacted.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use oxifed::config::{ConfigError, Env, require_positive};
+use oxifed::database::{DatabaseError, DatabaseManager, InstanceNodeInfo};
+use oxifed::egress::EgressPolicy;
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, error, info, warn};
+
+/// Prefix of the NodeInfo schema relations in `/.well-known/nodeinfo`
+const NODEINFO_REL_PREFIX: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+/// Largest NodeInfo document read
+const MAX_DOCUMENT_BYTES: usize = 256 * 1024;
+
+/// NodeInfo crawling settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlerConfig {
+    /// Whether peers are crawled at all
+    pub enabled: bool,
+    /// Pause between crawling runs, in seconds
+    pub interval_secs: u64,
+    /// Fetch the NodeInfo of an instance again this many hours after the
+    /// last fetch
+    pub recrawl_hours: u32,
+    /// Instances crawled per run
+    pub batch_size: i64,
+    /// Instances fetched at once
+    pub concurrency: usize,
+    /// Timeout of each request, in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 600,
+            recrawl_hours: 24,
+            batch_size: 200,
+            concurrency: 8,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl CrawlerConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("CRAWLER_ENABLED", &mut self.enabled)?;
+        env.set("CRAWLER_INTERVAL_SECS", &mut self.interval_secs)?;
+        env.set("CRAWLER_RECRAWL_HOURS", &mut self.recrawl_hours)?;
+        env.set("CRAWLER_BATCH_SIZE", &mut self.batch_size)?;
+        env.set("CRAWLER_CONCURRENCY", &mut self.concurrency)?;
+        env.set("CRAWLER_TIMEOUT_SECS", &mut self.timeout_secs)
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.interval_secs", key), self.interval_secs)?;
+        require_positive(&format!("{}.concurrency", key), self.concurrency)?;
+        require_positive(&format!("{}.timeout_secs", key), self.timeout_secs)?;
+        if self.batch_size <= 0 {
+            return Err(ConfigError::invalid(
+                format!("{}.batch_size", key),
+                "must be greater than zero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Start crawling the NodeInfo of peers, unless disabled
+pub fn start_crawler(db: Arc<DatabaseManager>, config: CrawlerConfig, shutdown: &Shutdown) {
+    if !config.enabled {
+        debug!("NodeInfo crawling is disabled");
+        return;
+    }
+    let builder = reqwest::Client::builder()
+        .user_agent(oxifed::client::ClientConfig::default().user_agent)
+        .timeout(Duration::from_secs(config.timeout_secs));
+    let egress = EgressPolicy::installed();
+    let client = match egress
+        .configure(builder)
+        .and_then(|builder| builder.build())
+    {
+        Ok(http) => NodeInfoClient { http, egress },
+        Err(e) => {
+            error!(
+                "NodeInfo crawling disabled, failed to build HTTP client: {}",
+                e
+            );
+            return;
+        }
+    };
+    info!(
+        "Starting NodeInfo crawling of peers (every {} hours)",
+        config.recrawl_hours
+    );
+    shutdown.spawn(run_crawler(db, client, config, shutdown.clone()));
+}
+
+/// HTTP client for NodeInfo documents, checking each URL against the egress
+/// policy
+struct NodeInfoClient {
+    http: reqwest::Client,
+    egress: EgressPolicy,
+}
+
+/// Crawl periodically until shutdown begins
+async fn run_crawler(
+    db: Arc<DatabaseManager>,
+    client: NodeInfoClient,
+    config: CrawlerConfig,
+    shutdown: Shutdown,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        match shutdown
+            .unless_triggered(crawl(&db, &client, &config))
+            .await
+        {
+            Some(Ok(0)) => {}
+            Some(Ok(crawled)) => info!("Crawled the NodeInfo of {} instances", crawled),
+            Some(Err(e)) => warn!("NodeInfo crawling failed: {}", e),
+            None => break,
+        }
+    }
+}
+
+/// Fetch the NodeInfo of the instances due, returning how many were crawled
+async fn crawl(
+    db: &DatabaseManager,
+    client: &NodeInfoClient,
+    config: &CrawlerConfig,
+) -> Result<usize, DatabaseError> {
+    let crawled_before = chrono::Utc::now() - chrono::Duration::hours(config.recrawl_hours.into());
+    let domains = db
+        .find_instances_to_crawl(crawled_before, config.batch_size)
+        .await?;
+    let crawled = domains.len();
+
+    let results: Vec<Result<(), DatabaseError>> = futures::stream::iter(domains)
+        .map(|domain| async move {
+            let result = fetch_nodeinfo(client, &domain).await;
+            if let Err(e) = &result {
+                debug!("Failed to fetch NodeInfo of {}: {}", domain, e);
+            }
+            db.record_instance_crawl(&domain, result.as_ref().map_err(String::as_str))
+                .await
+        })
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+    results.into_iter().collect::<Result<(), _>>()?;
+    Ok(crawled)
+}
+
+/// Fetch what `domain` reports in its NodeInfo
+async fn fetch_nodeinfo(client: &NodeInfoClient, domain: &str) -> Result<InstanceNodeInfo, String> {
+    let discovery = get_json(client, &format!("https://{}/.well-known/nodeinfo", domain)).await?;
+    let href = nodeinfo_href(&discovery)
+        .ok_or_else(|| "no NodeInfo 2.x link in /.well-known/nodeinfo".to_string())?;
+    let nodeinfo = get_json(client, &href).await?;
+    Ok(parse_nodeinfo(&nodeinfo))
+}
+
+/// GET a JSON document of at most [`MAX_DOCUMENT_BYTES`] the egress policy
+/// permits
+async fn get_json(client: &NodeInfoClient, url: &str) -> Result<Value, String> {
+    let url = url::Url::parse(url).map_err(|e| format!("invalid URL {}: {}", url, e))?;
+    client
+        .egress
+        .check_url(&url)
+        .map_err(|e| format!("refusing to fetch {}: {}", url, e))?;
+    let mut response = client
+        .http
+        .get(url.clone())
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered HTTP {}", url, response.status()));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > MAX_DOCUMENT_BYTES {
+            return Err(format!(
+                "{} is larger than {} bytes",
+                url, MAX_DOCUMENT_BYTES
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("{} is not valid JSON: {}", url, e))
+}
+
+/// Link to the newest NodeInfo 2.x schema in a `/.well-known/nodeinfo`
+/// document
+fn nodeinfo_href(discovery: &Value) -> Option<String> {
+    discovery["links"]
+        .as_array()?
+        .iter()
+        .filter_map(|link| {
+            let version = link["rel"]
+                .as_str()?
+                .strip_prefix(NODEINFO_REL_PREFIX)?
+                .trim_end_matches('/');
+            if !version.starts_with("2.") {
+                return None;
+            }
+            Some((version, link["href"].as_str()?))
+        })
+        .max_by_key(|(version, _)| *version)
+        .map(|(_, href)| href.to_string())
+}
+
+/// Software and registration status from a NodeInfo document
+fn parse_nodeinfo(nodeinfo: &Value) -> InstanceNodeInfo {
+    let field = |value: &Value| {
+        value
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    InstanceNodeInfo {
+        software: field(&nodeinfo["software"]["name"]).map(|name| name.to_lowercase()),
+        version: field(&nodeinfo["software"]["version"]),
+        open_registrations: nodeinfo["openRegistrations"].as_bool(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_follows_newest_nodeinfo_schema() {
+        let discovery = json!({
+            "links": [
+                {
+                    "rel": "http://nodeinfo.diaspora.software/ns/schema/2.0",
+                    "href": "https://peer.example/nodeinfo/2.0"
+                },
+                {
+                    "rel": "https://www.w3.org/ns/activitystreams#Application",
+                    "href": "https://peer.example/actor"
+                },
+                {
+                    "rel": "http://nodeinfo.diaspora.software/ns/schema/2.1",
+                    "href": "https://peer.example/nodeinfo/2.1"
+                },
+                {
+                    "rel": "http://nodeinfo.diaspora.software/ns/schema/1.0",
+                    "href": "https://peer.example/nodeinfo/1.0"
+                }
+            ]
+        });
+        assert_eq!(
+            nodeinfo_href(&discovery).as_deref(),
+            Some("https://peer.example/nodeinfo/2.1")
+        );
+        assert_eq!(nodeinfo_href(&json!({ "links": [] })), None);
+
+        let nodeinfo = json!({
+            "version": "2.1",
+            "software": { "name": "Mastodon", "version": "4.3.1" },
+            "openRegistrations": true
+        });
+        assert_eq!(
+            parse_nodeinfo(&nodeinfo),
+            InstanceNodeInfo {
+                software: Some("mastodon".to_string()),
+                version: Some("4.3.1".to_string()),
+                open_registrations: Some(true),
+            }
+        );
+        assert_eq!(parse_nodeinfo(&json!({})), InstanceNodeInfo::default());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_urls_outside_the_egress_policy() {
+        let client = NodeInfoClient {
+            http: reqwest::Client::new(),
+            egress: EgressPolicy::default(),
+        };
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/nodeinfo/2.1",
+            "file:///etc/passwd",
+        ] {
+            let error = get_json(&client, url).await.unwrap_err();
+            assert!(error.starts_with("refusing to fetch"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Login credentials of local users
+//!
+//! Users log in with their username and password on the OAuth
+//! authorization page. Administrators set passwords or start password
+//! resets through adminservd, which sends a [`UserPasswordMessage`] with
+//! the password or token already hashed. A reset lets the user choose a new
+//! password at `/auth/password?token=...`; the link is emailed to users
+//! who gave an address. Changing a password revokes all tokens of the user.
+
+use std::sync::{Arc, LazyLock};
+
+use axum::{
+    Form, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::Response,
+    routing::get,
+};
+use oxifed::credentials::{
+    MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH, hash_password, is_acceptable_password, needs_rehash,
+    token_hash, verify_password,
+};
+use oxifed::database::{ActorDocument, ActorStatus, DatabaseError, DatabaseManager};
+use oxifed::messaging::{EmailKind, PasswordChange, UserPasswordMessage};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::AppState;
+use crate::db::MongoDB;
+use crate::html::{escape, form_page};
+use crate::rabbitmq::RabbitMQError;
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+/// Hash checked for unknown users, so they take as long as known ones
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password(""));
+
+/// Routes of the password reset page
+pub fn credentials_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/auth/password", get(reset_page).post(reset_password))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Check the password of a local user
+///
+/// Only active local actors with a password can log in. Hashing runs off
+/// the async runtime as it takes a noticeable time by design.
+pub async fn authenticate(
+    db: &DatabaseManager,
+    username: &str,
+    domain: &str,
+    password: &str,
+) -> Result<bool, DatabaseError> {
+    let active = db
+        .find_actor_by_username(username, domain)
+        .await?
+        .is_some_and(|actor| actor.local && actor.status == ActorStatus::Active);
+    let password_hash = match db.find_credentials(username, domain).await? {
+        Some(credentials) if active => credentials.password_hash,
+        _ => None,
+    };
+
+    let password = password.to_string();
+    let current = password_hash.clone();
+    let (verified, rehashed) = tokio::task::spawn_blocking(move || match password_hash {
+        Some(password_hash) if verify_password(&password, &password_hash) => {
+            // Upgrade hashes of earlier versions or weaker parameters
+            let rehashed = needs_rehash(&password_hash).then(|| hash_password(&password));
+            (true, rehashed)
+        }
+        Some(_) => (false, None),
+        None => {
+            verify_password(&password, &DUMMY_HASH);
+            (false, None)
+        }
+    })
+    .await
+    .unwrap_or((false, None));
+
+    if let (Some(rehashed), Some(current)) = (rehashed, current) {
+        db.rehash_password(username, domain, &current, &rehashed)
+            .await?;
+        info!("Upgraded the password hash of {}@{}", username, domain);
+    }
+    Ok(verified)
+}
+
+/// Apply a password change sent by adminservd
+pub async fn apply_password_message(
+    db: &Arc<MongoDB>,
+    message: &UserPasswordMessage,
+) -> Result<(), RabbitMQError> {
+    let db = db.manager();
+    let actor = db
+        .find_actor_by_username(&message.username, &message.domain)
+        .await?
+        .filter(|actor| actor.local && actor.actor_type == "Person")
+        .ok_or_else(|| {
+            RabbitMQError::ProfileNotFound(format!("{}@{}", message.username, message.domain))
+        })?;
+
+    match &message.change {
+        PasswordChange::Set { password_hash } => {
+            set_password(db, &actor, password_hash).await?;
+            info!("Set the password of {}", actor.actor_id);
+        }
+        PasswordChange::Reset {
+            token_hash,
+            expires_at,
+            reset_url,
+        } => {
+            db.set_password_reset(&actor, token_hash, *expires_at)
+                .await?;
+            info!(
+                "Started a password reset of {}, valid until {}",
+                actor.actor_id, expires_at
+            );
+            if let Some(reset_url) = reset_url {
+                db.queue_user_email(
+                    &message.username,
+                    &message.domain,
+                    EmailKind::PasswordReset,
+                    json!({
+                        "username": message.username,
+                        "reset_url": reset_url,
+                        "expires_at": expires_at.to_rfc3339(),
+                    }),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace a user's password and revoke their tokens
+async fn set_password(
+    db: &DatabaseManager,
+    actor: &ActorDocument,
+    password_hash: &str,
+) -> Result<(), DatabaseError> {
+    db.set_password_hash(actor, password_hash).await?;
+    db.revoke_user_tokens(&actor.preferred_username, &actor.domain, None)
+        .await?;
+    Ok(())
+}
+
+/// Query of the password reset page
+#[derive(Debug, Deserialize)]
+pub struct ResetQuery {
+    #[serde(default)]
+    token: String,
+}
+
+/// Answer of the password reset form
+#[derive(Debug, Deserialize)]
+pub struct ResetForm {
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    confirmation: String,
+}
+
+fn message_page(status: StatusCode, title: &str, message: &str) -> Response {
+    form_page(status, title, &format!("<p>{}</p>\n", escape(message)))
+}
+
+fn invalid_token() -> Response {
+    message_page(
+        StatusCode::BAD_REQUEST,
+        "Password reset failed",
+        "The reset link is invalid or expired. Ask an administrator for a new one.",
+    )
+}
+
+fn internal_error(e: DatabaseError) -> Response {
+    error!("Password reset failed: {}", e);
+    message_page(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Password reset failed",
+        "Internal server error",
+    )
+}
+
+/// Form to choose a new password
+async fn reset_page(State(state): State<AppState>, Query(query): Query<ResetQuery>) -> Response {
+    match state
+        .db_manager
+        .find_credentials_by_reset_token(&token_hash(&query.token))
+        .await
+    {
+        Ok(Some(credentials)) => form_page(
+            StatusCode::OK,
+            "Choose a new password",
+            &format!(
+                "<p>New password for <strong>{}@{}</strong>, {} to {} characters:</p>\n\
+                 <form method=\"post\" action=\"/auth/password\">\n\
+                 <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+                 <label>Password <input name=\"password\" type=\"password\" autocomplete=\"new-password\" required></label>\n\
+                 <label>Confirm password <input name=\"confirmation\" type=\"password\" autocomplete=\"new-password\" required></label>\n\
+                 <button type=\"submit\">Set password</button>\n\
+                 </form>\n",
+                escape(&credentials.username),
+                escape(&credentials.domain),
+                MIN_PASSWORD_LENGTH,
+                MAX_PASSWORD_LENGTH,
+                escape(&query.token)
+            ),
+        ),
+        Ok(None) => invalid_token(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Set the new password of a reset
+async fn reset_password(State(state): State<AppState>, Form(form): Form<ResetForm>) -> Response {
+    let credentials = match state
+        .db_manager
+        .find_credentials_by_reset_token(&token_hash(&form.token))
+        .await
+    {
+        Ok(Some(credentials)) => credentials,
+        Ok(None) => return invalid_token(),
+        Err(e) => return internal_error(e),
+    };
+    if !is_acceptable_password(&form.password) {
+        return message_page(
+            StatusCode::BAD_REQUEST,
+            "Password reset failed",
+            &format!(
+                "The password must have {} to {} characters.",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ),
+        );
+    }
+    if form.password != form.confirmation {
+        return message_page(
+            StatusCode::BAD_REQUEST,
+            "Password reset failed",
+            "The passwords do not match.",
+        );
+    }
+
+    let actor = match state
+        .db_manager
+        .find_actor_by_id(&credentials.actor_id)
+        .await
+    {
+        Ok(Some(actor)) => actor,
+        Ok(None) => {
+            warn!(
+                "Password reset for {} whose actor is gone",
+                credentials.actor_id
+            );
+            return invalid_token();
+        }
+        Err(e) => return internal_error(e),
+    };
+    let password = form.password;
+    let password_hash = match tokio::task::spawn_blocking(move || hash_password(&password)).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            error!("Failed to hash password: {}", e);
+            return message_page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Password reset failed",
+                "Internal server error",
+            );
+        }
+    };
+    if let Err(e) = set_password(&state.db_manager, &actor, &password_hash).await {
+        return internal_error(e);
+    }
+    info!("{} reset their password", actor.actor_id);
+
+    message_page(
+        StatusCode::OK,
+        "Password changed",
+        "Your password was changed and all applications were signed out.",
+    )
+}
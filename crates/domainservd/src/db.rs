@@ -3,11 +3,14 @@
 //! Provides a clean interface to the comprehensive database implementation.
 
 use mongodb::Database;
+use oxifed::config::DatabaseConfig;
 use oxifed::database::{ActorDocument, DatabaseError, DatabaseManager, ObjectDocument};
 use oxifed::webfinger::JrdResource;
 use std::sync::Arc;
 use thiserror::Error;
 
+use crate::webfinger::WebFingerCache;
+
 /// Database errors for domainservd
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -19,23 +22,26 @@ pub enum DbError {
 pub struct MongoDB {
     manager: Arc<DatabaseManager>,
     database: Database,
+    webfinger_cache: WebFingerCache,
 }
 
 impl MongoDB {
     /// Create a new MongoDB connection
-    pub async fn new(connection_string: &str, db_name: &str) -> Result<Self, DbError> {
-        use mongodb::{Client, options::ClientOptions};
-
-        let client_options = ClientOptions::parse(connection_string)
-            .await
-            .map_err(|e| DbError::DatabaseError(DatabaseError::MongoError(e)))?;
-        let client = Client::with_options(client_options)
-            .map_err(|e| DbError::DatabaseError(DatabaseError::MongoError(e)))?;
-        let database = client.database(db_name);
+    pub async fn new(config: &DatabaseConfig) -> Result<Self, DbError> {
+        let manager = Arc::new(DatabaseManager::connect(config).await?);
+        Ok(Self::with_manager(manager))
+    }
 
-        let manager = Arc::new(DatabaseManager::new(database.clone()));
+    /// Wrap a database manager that is already set up
+    pub fn with_manager(manager: Arc<DatabaseManager>) -> Self {
+        let database = manager.database.clone();
+        let webfinger_cache = WebFingerCache::new(manager.clone());
 
-        Ok(Self { manager, database })
+        Self {
+            manager,
+            database,
+            webfinger_cache,
+        }
     }
 
     /// Get the database manager
@@ -43,6 +49,11 @@ impl MongoDB {
         &self.manager
     }
 
+    /// Get a shared handle to the database manager
+    pub fn shared_manager(&self) -> Arc<DatabaseManager> {
+        self.manager.clone()
+    }
+
     /// Get the raw database instance
     pub fn database(&self) -> &Database {
         &self.database
@@ -106,7 +117,7 @@ impl MongoDB {
         offset: i64,
     ) -> Result<Vec<ObjectDocument>, DbError> {
         self.manager
-            .find_objects_by_actor(actor_id, limit, offset)
+            .get_actor_outbox(actor_id, limit, offset)
             .await
             .map_err(Into::into)
     }
@@ -116,6 +127,11 @@ impl MongoDB {
         self.database.collection("webfinger_profiles")
     }
 
+    /// Get the cache of WebFinger profiles, to invalidate when they change
+    pub fn webfinger_cache(&self) -> &WebFingerCache {
+        &self.webfinger_cache
+    }
+
     /// Get actor's activities (for legacy compatibility)
     pub async fn get_actor_activities(
         &self,
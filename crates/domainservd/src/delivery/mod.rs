@@ -34,6 +34,30 @@ pub struct FollowerRecord {
     pub shared_inbox_url: Option<String>,
 }
 
+/// Inbox and shared inbox advertised by an actor
+///
+/// Returns `None` if the actor cannot be fetched or has no inbox.
+pub async fn fetch_inboxes(
+    client: &ActivityPubClient,
+    actor_id: &str,
+) -> Option<(String, Option<String>)> {
+    let url = Url::parse(actor_id).ok()?;
+    let actor = match client.fetch_actor(&url).await {
+        Ok(actor) => actor,
+        Err(e) => {
+            warn!("Failed to fetch inboxes of {}: {}", actor_id, e);
+            return None;
+        }
+    };
+    let inbox = actor.additional_properties.get("inbox")?.as_str()?;
+    let shared_inbox = actor
+        .additional_properties
+        .get("endpoints")
+        .and_then(|endpoints| endpoints.get("sharedInbox"))
+        .and_then(Value::as_str);
+    Some((inbox.to_string(), shared_inbox.map(str::to_string)))
+}
+
 /// Maximum number of concurrent deliveries
 const MAX_CONCURRENT_DELIVERIES: usize = 50;
 
@@ -199,7 +223,8 @@ impl DeliveryManager {
                 .await?;
         }
 
-        // Add followers if explicitly addressed
+        // Add followers if explicitly addressed, keeping their shared inboxes
+        let mut delivery_targets = Vec::new();
         let followers_url = format!("https://{}/u/{}/followers", "localhost", actor_username); // TODO: use actual domain
         if recipients.contains(&followers_url) {
             recipients.remove(&followers_url);
@@ -214,12 +239,12 @@ impl DeliveryManager {
                         .map(|url| Url::parse(url))
                         .transpose()?,
                 };
-                recipients.insert(target.inbox_url.to_string());
+                recipients.remove(target.inbox_url.as_str());
+                delivery_targets.push(target);
             }
         }
 
         // Convert string URLs to DeliveryTargets
-        let mut delivery_targets = Vec::new();
         for recipient_url in recipients {
             if let Ok(inbox_url) = Url::parse(&recipient_url) {
                 // For direct inbox URLs, we don't have actor info, so create minimal target
@@ -313,8 +338,9 @@ impl DeliveryManager {
             // Extract username from URL and get followers from database
             if let Some(username) = self.extract_username_from_url(collection_url) {
                 let followers = self.get_followers(&username).await?;
+                // Followers on one server collapse into its shared inbox
                 for follower in followers.into_iter().take(MAX_COLLECTION_ITEMS) {
-                    recipients.insert(follower.inbox_url);
+                    recipients.insert(follower.shared_inbox_url.unwrap_or(follower.inbox_url));
                 }
             }
         } else {
@@ -378,21 +404,24 @@ impl DeliveryManager {
     /// Get followers from database
     async fn get_followers(&self, username: &str) -> Result<Vec<FollowerRecord>> {
         let actor_id = format!("https://{}/users/{}", "example.com", username); // TODO: get domain from config
-        let follower_ids = self
+        let follows = self
             .db
             .manager()
-            .get_actor_followers(&actor_id)
+            .find_accepted_follows(&actor_id)
             .await
             .map_err(|e| DeliveryError::DatabaseError(e.to_string()))?;
 
-        // Convert follower IDs to FollowerRecord format for compatibility
-        let followers = follower_ids
+        // Follows stored before inboxes were recorded fall back to the
+        // conventional inbox path
+        let followers = follows
             .into_iter()
-            .map(|actor_id| FollowerRecord {
-                actor_id: actor_id.clone(),
-                followed_at: chrono::Utc::now(), // TODO: get actual timestamp from database
-                inbox_url: format!("{}/inbox", actor_id),
-                shared_inbox_url: None,
+            .map(|follow| FollowerRecord {
+                inbox_url: follow
+                    .follower_inbox
+                    .unwrap_or_else(|| format!("{}/inbox", follow.follower)),
+                shared_inbox_url: follow.follower_shared_inbox,
+                followed_at: follow.responded_at.unwrap_or(follow.created_at),
+                actor_id: follow.follower,
             })
             .collect();
 
@@ -469,3 +498,32 @@ impl DeliveryManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::client::ClientConfig;
+    use oxifed::egress::EgressPolicy;
+    use oxifed::testing::{MockPeer, StatusCode};
+
+    #[tokio::test]
+    async fn test_fetch_inboxes() {
+        let peer = MockPeer::start().await.unwrap();
+        let client = ActivityPubClient::with_config(ClientConfig {
+            egress: EgressPolicy::allowing_private(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let (inbox, shared_inbox) = fetch_inboxes(&client, &peer.actor_id("bob")).await.unwrap();
+        assert_eq!(inbox, peer.inbox("bob"));
+        assert_eq!(shared_inbox, Some(peer.shared_inbox()));
+
+        peer.fail_path("/users/bob", StatusCode::NOT_FOUND);
+        assert!(
+            fetch_inboxes(&client, &peer.actor_id("bob"))
+                .await
+                .is_none()
+        );
+    }
+}
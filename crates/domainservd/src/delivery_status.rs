@@ -0,0 +1,108 @@
+//! Delivery status of outgoing activities
+//!
+//! Answers adminservd's questions about the deliveries of an activity from
+//! the `deliveries` collection publisherd keeps up to date.
+
+use std::sync::Arc;
+
+use deadpool_lapin::Pool;
+use futures::StreamExt;
+use lapin::{
+    BasicProperties, Channel,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions},
+    types::FieldTable,
+};
+use oxifed::database::{DatabaseManager, DeliveryDocument};
+use oxifed::messaging::{
+    DeliveryStatus, DeliveryStatusRpcResponse, Message, MessageEnum, QUEUE_RPC_DELIVERY_STATUS,
+};
+use oxifed::shutdown::Shutdown;
+use tracing::{error, info, warn};
+
+use crate::rabbitmq::{RabbitMQError, spawn_channel_task};
+
+pub const DELIVERY_STATUS_RPC_CONSUMER_TAG: &str = "rpc_delivery_status_consumer";
+
+/// Start the delivery status RPC consumer
+pub fn start_delivery_status_consumer(pool: Pool, db: Arc<DatabaseManager>, shutdown: &Shutdown) {
+    spawn_channel_task("delivery status RPC consumer", pool, shutdown, {
+        move |channel, shutdown| run_rpc_consumer(channel, db.clone(), shutdown)
+    });
+}
+
+/// Serve delivery status RPC requests
+async fn run_rpc_consumer(
+    channel: Channel,
+    db: Arc<DatabaseManager>,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_RPC_DELIVERY_STATUS,
+            DELIVERY_STATUS_RPC_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Delivery status RPC consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+            error!("Failed to ack delivery status RPC message: {}", e);
+        }
+
+        let request = match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::DeliveryStatusRpcRequest(request)) => request,
+            Ok(_) => {
+                warn!("Received non-delivery-status message on delivery status RPC queue");
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to parse delivery status RPC message: {}", e);
+                continue;
+            }
+        };
+
+        let response = match db.find_deliveries(&request.activity_id).await {
+            Ok(deliveries) if deliveries.is_empty() => {
+                DeliveryStatusRpcResponse::not_found(request.request_id)
+            }
+            Ok(deliveries) => DeliveryStatusRpcResponse::status(
+                request.request_id,
+                DeliveryStatus::new(
+                    request.activity_id,
+                    deliveries.iter().map(DeliveryDocument::to_info).collect(),
+                ),
+            ),
+            Err(e) => DeliveryStatusRpcResponse::error(request.request_id, e.to_string()),
+        };
+
+        let Some(reply_to) = delivery.properties.reply_to() else {
+            warn!("Delivery status RPC request has no reply_to queue");
+            continue;
+        };
+        let correlation_id = delivery
+            .properties
+            .correlation_id()
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string().into());
+
+        let payload = serde_json::to_vec(&response.to_message())?;
+        if let Err(e) = channel
+            .basic_publish(
+                "",
+                reply_to.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_correlation_id(correlation_id),
+            )
+            .await
+        {
+            error!("Failed to send delivery status RPC response: {}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,582 @@
+//! Dead-letter queue intake and reprocessing
+//!
+//! Everything routed to `oxifed.dlq` is stored in the `dead_letters`
+//! collection together with the failure metadata taken from the broker's
+//! `x-death` header and the rejection headers set by pipeline stages.
+//! Transient failures are re-injected into their original queue with
+//! exponential backoff until `DLQ_MAX_RETRIES` is reached; everything else
+//! waits for an administrator to retry or purge it through the DLQ RPC queue.
+//! Dead letters of queues consumed through the message bus are re-injected
+//! by publishing them to their bus exchange again.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use deadpool_lapin::Pool;
+use futures::StreamExt;
+use lapin::{
+    BasicProperties, Channel,
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions,
+        ConfirmSelectOptions,
+    },
+    types::{AMQPValue, FieldTable},
+};
+use mongodb::bson::{Binary, DateTime as BsonDateTime, spec::BinarySubtype};
+use oxifed::bus::{MessageBus, OutgoingMessage};
+use oxifed::config::{ConfigError, Env};
+use oxifed::database::{DatabaseManager, DeadLetterDocument, DeadLetterStatus};
+use oxifed::messaging::{
+    DeadLetterInfo, DlqRpcRequest, DlqRpcRequestType, DlqRpcResponse, FailureClass,
+    HEADER_FAILURE_CLASS, HEADER_ORIGINAL_QUEUE, HEADER_REJECTION_REASON, HEADER_REJECTION_STAGE,
+    HEADER_RETRY_COUNT, Message, MessageEnum, QUEUE_DEAD_LETTER, QUEUE_RPC_DLQ,
+};
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use tracing::{debug, error, info, warn};
+
+use crate::rabbitmq::{RabbitMQError, spawn_channel_task};
+
+pub const DLQ_CONSUMER_TAG: &str = "dlq_consumer";
+pub const DLQ_RPC_CONSUMER_TAG: &str = "rpc_dlq_consumer";
+
+/// Interval at which due automatic retries are re-injected
+const RETRY_SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of dead letters returned or retried at once
+const BATCH_LIMIT: i64 = 100;
+
+/// Dead-letter reprocessing configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DlqConfig {
+    /// Automatic re-injections of a transient failure before giving up
+    pub max_retries: u32,
+    /// Delay before the first automatic retry, doubled for every attempt
+    pub retry_delay_secs: i64,
+}
+
+impl Default for DlqConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            retry_delay_secs: 60,
+        }
+    }
+}
+
+impl DlqConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("DLQ_MAX_RETRIES", &mut self.max_retries)?;
+        env.set("DLQ_RETRY_DELAY_SECS", &mut self.retry_delay_secs)
+    }
+
+    /// When a dead letter with the given number of previous attempts is retried
+    ///
+    /// Returns `None` for permanent failures and once the retry budget is used up.
+    fn next_retry_at(&self, class: FailureClass, attempts: u32) -> Option<BsonDateTime> {
+        if class != FailureClass::Transient || attempts >= self.max_retries {
+            return None;
+        }
+
+        let delay = self.retry_delay_secs.saturating_mul(1 << attempts.min(16));
+        Some(BsonDateTime::from_millis(
+            (Utc::now() + chrono::Duration::seconds(delay)).timestamp_millis(),
+        ))
+    }
+}
+
+/// Start the dead-letter intake, the retry scheduler and the DLQ RPC consumer
+pub async fn start_dlq_consumers(
+    pool: Pool,
+    bus: Arc<dyn MessageBus>,
+    db: Arc<DatabaseManager>,
+    config: DlqConfig,
+    shutdown: &Shutdown,
+) -> Result<(), RabbitMQError> {
+    info!(
+        "Starting dead-letter consumers (max retries: {}, base delay: {}s)",
+        config.max_retries, config.retry_delay_secs
+    );
+
+    spawn_channel_task("dead-letter intake", pool.clone(), shutdown, {
+        let db = db.clone();
+        move |channel, shutdown| run_intake(channel, db.clone(), config.clone(), shutdown)
+    });
+    spawn_channel_task("dead-letter retry scheduler", pool.clone(), shutdown, {
+        let db = db.clone();
+        let bus = bus.clone();
+        move |channel, shutdown| run_retry_scheduler(channel, bus.clone(), db.clone(), shutdown)
+    });
+    spawn_channel_task(
+        "DLQ RPC consumer",
+        pool,
+        shutdown,
+        move |channel, shutdown| run_rpc_consumer(channel, bus.clone(), db.clone(), shutdown),
+    );
+
+    Ok(())
+}
+
+/// Store every message arriving on the dead-letter queue
+async fn run_intake(
+    channel: Channel,
+    db: Arc<DatabaseManager>,
+    config: DlqConfig,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel.basic_qos(10, BasicQosOptions::default()).await?;
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_DEAD_LETTER,
+            DLQ_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Dead-letter intake ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        let dead_letter = dead_letter_document(&delivery, &config);
+
+        info!(
+            "Dead letter {} from {} ({}, {:?}, attempt {}): {}",
+            dead_letter.dead_letter_id,
+            dead_letter.queue,
+            dead_letter.reason,
+            dead_letter.failure_class,
+            dead_letter.attempts,
+            dead_letter.error.as_deref().unwrap_or("no error reported")
+        );
+        if dead_letter.failure_class == FailureClass::Transient
+            && dead_letter.next_retry_at.is_none()
+        {
+            warn!(
+                "Dead letter {} exhausted its {} automatic retries",
+                dead_letter.dead_letter_id, config.max_retries
+            );
+        }
+
+        // Leave the message on the queue if it cannot be stored
+        db.insert_dead_letter(dead_letter).await?;
+        delivery.ack(BasicAckOptions::default()).await?;
+    }
+
+    Ok(())
+}
+
+/// Build a dead-letter document from a delivery on the dead-letter queue
+fn dead_letter_document(delivery: &Delivery, config: &DlqConfig) -> DeadLetterDocument {
+    let headers = delivery.properties.headers().clone().unwrap_or_default();
+
+    // RabbitMQ prepends the most recent death to the x-death array
+    let death = headers
+        .inner()
+        .get("x-death")
+        .and_then(|value| value.as_array())
+        .and_then(|deaths| deaths.as_slice().first())
+        .and_then(|death| death.as_field_table())
+        .cloned()
+        .unwrap_or_default();
+
+    let stage = header_str(&headers, HEADER_REJECTION_STAGE);
+    let reason = header_str(&death, "reason").unwrap_or_else(|| {
+        if stage.is_some() {
+            "rejected".to_string()
+        } else {
+            "unknown".to_string()
+        }
+    });
+    let failure_class = header_str(&headers, HEADER_FAILURE_CLASS)
+        .and_then(|class| FailureClass::parse(&class))
+        .unwrap_or_else(|| classify_reason(&reason));
+    let attempts = header_int(&headers, HEADER_RETRY_COUNT)
+        .and_then(|count| u32::try_from(count).ok())
+        .unwrap_or(0);
+
+    DeadLetterDocument {
+        id: None,
+        dead_letter_id: uuid::Uuid::new_v4().to_string(),
+        message_id: delivery
+            .properties
+            .message_id()
+            .as_ref()
+            .map(|id| id.to_string()),
+        queue: header_str(&headers, HEADER_ORIGINAL_QUEUE)
+            .or_else(|| header_str(&death, "queue"))
+            .unwrap_or_else(|| "unknown".to_string()),
+        exchange: header_str(&death, "exchange"),
+        routing_key: death
+            .inner()
+            .get("routing-keys")
+            .and_then(|value| value.as_array())
+            .and_then(|keys| keys.as_slice().first())
+            .and_then(amqp_str),
+        reason,
+        stage,
+        error: header_str(&headers, HEADER_REJECTION_REASON),
+        failure_class,
+        attempts,
+        content_type: delivery
+            .properties
+            .content_type()
+            .as_ref()
+            .map(|ct| ct.to_string()),
+        payload: Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: delivery.data.clone(),
+        },
+        status: DeadLetterStatus::Pending,
+        next_retry_at: config.next_retry_at(failure_class, attempts),
+        created_at: Utc::now(),
+        retried_at: None,
+    }
+}
+
+/// Failure class of a broker dead-letter reason
+///
+/// Messages that expired or overflowed a queue were never processed and are
+/// worth another try; a plain rejection without metadata is not.
+fn classify_reason(reason: &str) -> FailureClass {
+    match reason {
+        "expired" | "maxlen" | "delivery_limit" => FailureClass::Transient,
+        _ => FailureClass::Permanent,
+    }
+}
+
+/// Read a string header
+fn header_str(headers: &FieldTable, key: &str) -> Option<String> {
+    headers.inner().get(key).and_then(amqp_str)
+}
+
+/// Read an integer header of any width
+///
+/// Headers that went through the message bus carry numbers as strings.
+fn header_int(headers: &FieldTable, key: &str) -> Option<i64> {
+    match headers.inner().get(key)? {
+        value @ (AMQPValue::ShortString(_) | AMQPValue::LongString(_)) => {
+            amqp_str(value)?.parse().ok()
+        }
+        AMQPValue::ShortShortInt(v) => Some(i64::from(*v)),
+        AMQPValue::ShortShortUInt(v) => Some(i64::from(*v)),
+        AMQPValue::ShortInt(v) => Some(i64::from(*v)),
+        AMQPValue::ShortUInt(v) => Some(i64::from(*v)),
+        AMQPValue::LongInt(v) => Some(i64::from(*v)),
+        AMQPValue::LongUInt(v) => Some(i64::from(*v)),
+        AMQPValue::LongLongInt(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// Convert a short or long AMQP string value
+fn amqp_str(value: &AMQPValue) -> Option<String> {
+    match value {
+        AMQPValue::ShortString(s) => Some(s.to_string()),
+        AMQPValue::LongString(s) => Some(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        _ => None,
+    }
+}
+
+/// Periodically re-inject transient failures whose retry is due
+async fn run_retry_scheduler(
+    channel: Channel,
+    bus: Arc<dyn MessageBus>,
+    db: Arc<DatabaseManager>,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let mut interval = tokio::time::interval(RETRY_SCAN_INTERVAL);
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        let due = db
+            .due_dead_letters(BsonDateTime::now(), BATCH_LIMIT)
+            .await?;
+        for dead_letter in due {
+            debug!(
+                "Automatic retry {} of dead letter {}",
+                dead_letter.attempts + 1,
+                dead_letter.dead_letter_id
+            );
+            retry_dead_letter(&channel, bus.as_ref(), &db, &dead_letter).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish a dead letter back to its original queue and mark it retried
+async fn retry_dead_letter(
+    channel: &Channel,
+    bus: &dyn MessageBus,
+    db: &DatabaseManager,
+    dead_letter: &DeadLetterDocument,
+) -> Result<(), RabbitMQError> {
+    if let Some(exchange) = oxifed::bus::bus_exchange_of_queue(&dead_letter.queue) {
+        retry_through_bus(bus, exchange, dead_letter).await?;
+    } else {
+        retry_through_amqp(channel, dead_letter).await?;
+    }
+
+    db.mark_dead_letter_retried(&dead_letter.dead_letter_id)
+        .await?;
+    info!(
+        "Re-injected dead letter {} into {}",
+        dead_letter.dead_letter_id, dead_letter.queue
+    );
+
+    Ok(())
+}
+
+/// Publish a dead letter to the bus exchange feeding its queue
+async fn retry_through_bus(
+    bus: &dyn MessageBus,
+    exchange: &str,
+    dead_letter: &DeadLetterDocument,
+) -> Result<(), RabbitMQError> {
+    let mut message = OutgoingMessage::new(dead_letter.payload.bytes.clone());
+    message.message_id = dead_letter.message_id.clone();
+    message.content_type = dead_letter.content_type.clone();
+    message.headers.insert(
+        HEADER_RETRY_COUNT.to_string(),
+        (i64::from(dead_letter.attempts) + 1).to_string(),
+    );
+
+    bus.publish(exchange, "", &message).await?;
+    Ok(())
+}
+
+/// Publish a dead letter straight to its original queue
+async fn retry_through_amqp(
+    channel: &Channel,
+    dead_letter: &DeadLetterDocument,
+) -> Result<(), RabbitMQError> {
+    let mut headers = FieldTable::default();
+    headers.insert(
+        HEADER_RETRY_COUNT.into(),
+        AMQPValue::LongLongInt(i64::from(dead_letter.attempts) + 1),
+    );
+
+    let mut properties = BasicProperties::default()
+        .with_delivery_mode(2)
+        .with_headers(headers);
+    if let Some(content_type) = &dead_letter.content_type {
+        properties = properties.with_content_type(content_type.clone().into());
+    }
+    if let Some(message_id) = &dead_letter.message_id {
+        properties = properties.with_message_id(message_id.clone().into());
+    }
+
+    // The default exchange routes directly to the queue the message died in
+    channel
+        .basic_publish(
+            "",
+            &dead_letter.queue,
+            BasicPublishOptions::default(),
+            &dead_letter.payload.bytes,
+            properties,
+        )
+        .await?
+        .await?;
+
+    Ok(())
+}
+
+/// Serve DLQ RPC requests
+async fn run_rpc_consumer(
+    channel: Channel,
+    bus: Arc<dyn MessageBus>,
+    db: Arc<DatabaseManager>,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_RPC_DLQ,
+            DLQ_RPC_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("DLQ RPC consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+            error!("Failed to ack DLQ RPC message: {}", e);
+        }
+
+        let request = match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::DlqRpcRequest(request)) => request,
+            Ok(_) => {
+                warn!("Received non-DLQ message on DLQ RPC queue");
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to parse DLQ RPC message: {}", e);
+                continue;
+            }
+        };
+
+        let response = handle_rpc_request(&db, &channel, bus.as_ref(), request).await;
+
+        let Some(reply_to) = delivery.properties.reply_to() else {
+            warn!("DLQ RPC request has no reply_to queue");
+            continue;
+        };
+        let correlation_id = delivery
+            .properties
+            .correlation_id()
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string().into());
+
+        let payload = serde_json::to_vec(&response.to_message())?;
+        if let Err(e) = channel
+            .basic_publish(
+                "",
+                reply_to.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_correlation_id(correlation_id),
+            )
+            .await
+        {
+            error!("Failed to send DLQ RPC response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Dispatch a DLQ RPC request
+async fn handle_rpc_request(
+    db: &DatabaseManager,
+    channel: &Channel,
+    bus: &dyn MessageBus,
+    request: DlqRpcRequest,
+) -> DlqRpcResponse {
+    info!(
+        "Processing DLQ RPC request: {} (type: {:?})",
+        request.request_id, request.request_type
+    );
+
+    let request_id = request.request_id;
+    match request.request_type {
+        DlqRpcRequestType::ListDeadLetters { queue, status } => {
+            let status = match status.as_deref().map(parse_status) {
+                Some(None) => {
+                    return DlqRpcResponse::error(
+                        request_id,
+                        "Invalid dead letter status".to_string(),
+                    );
+                }
+                Some(status) => status,
+                None => None,
+            };
+
+            match db
+                .list_dead_letters(queue.as_deref(), status, BATCH_LIMIT)
+                .await
+            {
+                Ok(list) => DlqRpcResponse::dead_letter_list(
+                    request_id,
+                    list.into_iter().map(dead_letter_info).collect(),
+                ),
+                Err(e) => DlqRpcResponse::error(request_id, e.to_string()),
+            }
+        }
+        DlqRpcRequestType::RetryDeadLetters { dead_letter_id } => {
+            let dead_letters = match dead_letter_id {
+                Some(id) => match db.find_dead_letter(&id).await {
+                    Ok(Some(dead_letter)) if dead_letter.status == DeadLetterStatus::Pending => {
+                        vec![dead_letter]
+                    }
+                    Ok(Some(_)) => {
+                        return DlqRpcResponse::error(
+                            request_id,
+                            format!("Dead letter already retried: {}", id),
+                        );
+                    }
+                    Ok(None) => {
+                        return DlqRpcResponse::error(
+                            request_id,
+                            format!("Dead letter not found: {}", id),
+                        );
+                    }
+                    Err(e) => return DlqRpcResponse::error(request_id, e.to_string()),
+                },
+                None => match db
+                    .list_dead_letters(None, Some(DeadLetterStatus::Pending), BATCH_LIMIT)
+                    .await
+                {
+                    Ok(list) => list,
+                    Err(e) => return DlqRpcResponse::error(request_id, e.to_string()),
+                },
+            };
+
+            let mut count = 0;
+            for dead_letter in &dead_letters {
+                if let Err(e) = retry_dead_letter(channel, bus, db, dead_letter).await {
+                    return DlqRpcResponse::error(
+                        request_id,
+                        format!("Retried {} dead letters, then failed: {}", count, e),
+                    );
+                }
+                count += 1;
+            }
+            DlqRpcResponse::affected(request_id, count)
+        }
+        DlqRpcRequestType::PurgeDeadLetters { dead_letter_id } => {
+            match db.delete_dead_letters(dead_letter_id.as_deref()).await {
+                Ok(count) => {
+                    info!("Purged {} dead letters", count);
+                    DlqRpcResponse::affected(request_id, count)
+                }
+                Err(e) => DlqRpcResponse::error(request_id, e.to_string()),
+            }
+        }
+    }
+}
+
+/// Convert a dead-letter document into its RPC representation
+fn dead_letter_info(dead_letter: DeadLetterDocument) -> DeadLetterInfo {
+    DeadLetterInfo {
+        dead_letter_id: dead_letter.dead_letter_id,
+        message_id: dead_letter.message_id,
+        queue: dead_letter.queue,
+        reason: dead_letter.reason,
+        stage: dead_letter.stage,
+        error: dead_letter.error,
+        failure_class: dead_letter.failure_class,
+        attempts: dead_letter.attempts,
+        status: match dead_letter.status {
+            DeadLetterStatus::Pending => "pending",
+            DeadLetterStatus::Retried => "retried",
+        }
+        .to_string(),
+        next_retry_at: dead_letter
+            .next_retry_at
+            .and_then(|t| t.try_to_rfc3339_string().ok()),
+        created_at: dead_letter.created_at.to_rfc3339(),
+        retried_at: dead_letter.retried_at.map(|t| t.to_rfc3339()),
+    }
+}
+
+/// Parse a dead letter status filter
+fn parse_status(status: &str) -> Option<DeadLetterStatus> {
+    match status {
+        "pending" => Some(DeadLetterStatus::Pending),
+        "retried" => Some(DeadLetterStatus::Retried),
+        _ => None,
+    }
+}
@@ -0,0 +1,74 @@
+//! Per-domain settings
+//!
+//! Domains can override some request limits with objects in their
+//! `DomainDocument.config`, set through the domain properties. Domain
+//! documents are cached briefly so request middleware does not query MongoDB
+//! on every request.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use moka::sync::Cache;
+use mongodb::bson;
+use oxifed::database::{DatabaseManager, DomainDocument};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+/// How long domain documents are cached
+const DOMAIN_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Briefly cached domain documents
+pub struct DomainConfigCache {
+    db_manager: Arc<DatabaseManager>,
+    domains: Cache<String, Option<Arc<DomainDocument>>>,
+}
+
+impl DomainConfigCache {
+    pub fn new(db_manager: Arc<DatabaseManager>) -> Self {
+        Self {
+            db_manager,
+            domains: Cache::builder()
+                .max_capacity(1_000)
+                .time_to_live(DOMAIN_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Document of a domain, if it is known
+    pub async fn domain(&self, domain: &str) -> Option<Arc<DomainDocument>> {
+        if let Some(domain_doc) = self.domains.get(domain) {
+            return domain_doc;
+        }
+
+        let domain_doc = match self.db_manager.find_domain_by_name(domain).await {
+            Ok(domain_doc) => domain_doc.map(Arc::new),
+            Err(e) => {
+                // Not cached, so the next request tries again
+                warn!("Failed to load settings of domain {}: {}", domain, e);
+                return None;
+            }
+        };
+        self.domains.insert(domain.to_string(), domain_doc.clone());
+        domain_doc
+    }
+
+    /// The `key` object of a domain's config, or the default if the domain
+    /// does not set it
+    pub async fn section<T: DeserializeOwned + Default>(&self, domain: &str, key: &str) -> T {
+        let Some(domain_doc) = self.domain(domain).await else {
+            return T::default();
+        };
+        let Some(section) = domain_doc
+            .config
+            .as_ref()
+            .and_then(|config| config.get_document(key).ok())
+        else {
+            return T::default();
+        };
+
+        bson::from_document(section.clone()).unwrap_or_else(|e| {
+            warn!("Ignoring invalid {} of domain {}: {}", key, domain, e);
+            T::default()
+        })
+    }
+}
@@ -0,0 +1,634 @@
+//! Events and RSVPs
+//!
+//! Local users create `Event`s by posting a `Create` to their outbox.
+//! Actors RSVP with `Join` and `Leave`, which reach the organizer's inbox or
+//! the shared inbox: `Join`s of local events are answered right away
+//! following the event's join mode, with an `Accept` or `Reject` delivered
+//! to the attendee, and the accepted attendees are served as the event's
+//! `participants` collection. The organizer of a restricted event answers
+//! its pending `Join`s by posting an `Accept` or `Reject` of them to their
+//! outbox, just like remote organizers answer the `Join`s local users post
+//! to theirs.
+
+use chrono::{DateTime, Utc};
+use oxifed::database::{
+    ActorDocument, DatabaseError, DatabaseManager, EventDetails, ObjectDocument,
+    ParticipationDocument, ParticipationStatus, PlaceDocument,
+};
+use serde_json::{Map, Value, json};
+use tracing::{debug, info};
+use url::Url;
+
+use crate::relay::{activity_id, queue_activity};
+
+/// URL of the collection of an event's accepted attendees
+pub fn participants_url(event_id: &str) -> String {
+    format!("{}/participants", event_id)
+}
+
+/// Check an `Event` posted through C2S and add its attendee collection
+///
+/// Events need a `startTime` and may not end before they start; their
+/// `location` is a `Place`.
+pub fn prepare_event(event: &mut Map<String, Value>) -> Result<(), String> {
+    let time = |key: &str| -> Result<Option<DateTime<Utc>>, String> {
+        event
+            .get(key)
+            .filter(|time| !time.is_null())
+            .map(|time| {
+                time.as_str()
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.with_timezone(&Utc))
+                    .ok_or_else(|| format!("Invalid {}: {}", key, time))
+            })
+            .transpose()
+    };
+    let start = time("startTime")?.ok_or("Event must have a startTime")?;
+    if time("endTime")?.is_some_and(|end| end < start) {
+        return Err("Event must not end before it starts".to_string());
+    }
+    if let Some(location) = event.get("location").filter(|l| !l.is_null())
+        && PlaceDocument::from_json(location).is_none()
+    {
+        return Err("Event location must be a Place".to_string());
+    }
+    if let Some(mode) = event.get("joinMode")
+        && !matches!(mode.as_str(), Some("free" | "restricted" | "invite"))
+    {
+        return Err(format!("Invalid joinMode: {}", mode));
+    }
+
+    // Attendees are counted by the server
+    let event_id = event
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or("Event must have an ID")?
+        .to_string();
+    event.insert("participantCount".to_string(), json!(0));
+    event.insert(
+        "participants".to_string(),
+        json!(participants_url(&event_id)),
+    );
+    Ok(())
+}
+
+/// Actor, event and ID of a `Join` or `Leave`
+fn rsvp(activity: &Value) -> Option<(&str, &str, Option<&str>)> {
+    let actor = activity.get("actor")?.as_str()?;
+    let object = activity.get("object")?;
+    let event_id = object.as_str().or_else(|| object.get("id")?.as_str())?;
+    Some((actor, event_id, activity.get("id").and_then(Value::as_str)))
+}
+
+/// A stored event of a local organizer, with its details and organizer
+async fn local_event(
+    db: &DatabaseManager,
+    event_id: &str,
+) -> Result<Option<(EventDetails, ActorDocument)>, DatabaseError> {
+    let Some(ObjectDocument {
+        event: Some(details),
+        attributed_to,
+        ..
+    }) = db.find_object_by_id(event_id).await?
+    else {
+        return Ok(None);
+    };
+    let organizer = db
+        .find_actor_by_id(&attributed_to)
+        .await?
+        .filter(|organizer| organizer.local);
+    Ok(organizer.map(|organizer| (details, organizer)))
+}
+
+/// Record a `Join` of an event
+///
+/// `Join`s of local events are answered following the join mode of the
+/// event. Those of remote events by local actors wait for the organizer's
+/// answer; others are ignored.
+pub async fn join(db: &DatabaseManager, join: &Value) -> Result<(), String> {
+    let (actor, event_id, join_id) = rsvp(join).ok_or("Join must have an actor and an event")?;
+    let join_id = join_id.ok_or("Join must have an ID")?;
+    let failed = |e: DatabaseError| format!("Failed to record Join of {}: {}", event_id, e);
+
+    // A local user's Join reaches the inbox of a local organizer after
+    // it was recorded from the outbox
+    if db
+        .find_participation_by_activity(join_id)
+        .await
+        .map_err(failed)?
+        .is_some()
+    {
+        debug!("Join {} already recorded", join_id);
+        return Ok(());
+    }
+    // A repeated Join keeps the place of an accepted attendee, even in a
+    // full event
+    if db
+        .find_participation(event_id, actor)
+        .await
+        .map_err(failed)?
+        .is_some_and(|participation| participation.status == ParticipationStatus::Accepted)
+    {
+        debug!("{} already attends {}", actor, event_id);
+        return Ok(());
+    }
+
+    match local_event(db, event_id).await.map_err(failed)? {
+        Some((details, organizer)) => {
+            let status = details.answer_join();
+            db.record_participation(&ParticipationDocument::new(
+                event_id, actor, join_id, status,
+            ))
+            .await
+            .map_err(failed)?;
+            if status != ParticipationStatus::Pending {
+                send_answer(db, join, status, &organizer)
+                    .await
+                    .map_err(failed)?;
+            }
+            if status == ParticipationStatus::Accepted {
+                db.count_participants(event_id).await.map_err(failed)?;
+            }
+            info!("{} joined {}: {:?}", actor, event_id, status);
+        }
+        None => {
+            let local_actor = db
+                .find_actor_by_id(actor)
+                .await
+                .map_err(failed)?
+                .is_some_and(|actor| actor.local);
+            if !local_actor {
+                debug!("Ignoring Join of unknown event {}", event_id);
+                return Ok(());
+            }
+            db.record_participation(&ParticipationDocument::new(
+                event_id,
+                actor,
+                join_id,
+                ParticipationStatus::Pending,
+            ))
+            .await
+            .map_err(failed)?;
+            info!("{} asked to join {}", actor, event_id);
+        }
+    }
+    Ok(())
+}
+
+/// Remove an actor from the attendees of the event they `Leave`
+pub async fn leave(db: &DatabaseManager, leave: &Value) -> Result<(), String> {
+    let (actor, event_id, _) = rsvp(leave).ok_or("Leave must have an actor and an event")?;
+    let failed = |e: DatabaseError| format!("Failed to record Leave of {}: {}", event_id, e);
+
+    if !db
+        .remove_participation(event_id, actor)
+        .await
+        .map_err(failed)?
+    {
+        debug!("{} left {} without joining it", actor, event_id);
+        return Ok(());
+    }
+    if local_event(db, event_id).await.map_err(failed)?.is_some() {
+        db.count_participants(event_id).await.map_err(failed)?;
+    }
+    info!("{} left {}", actor, event_id);
+    Ok(())
+}
+
+/// Record an organizer's `Accept` or `Reject` of a `Join`
+///
+/// Returns whether `answer` answers a known `Join`. Only the organizer of
+/// the event may answer; the organizer of an event that is not stored has
+/// to be on the event's server. Local events that are full accept no one
+/// else.
+pub async fn answer_join(
+    db: &DatabaseManager,
+    answer: &Value,
+    status: ParticipationStatus,
+) -> Result<bool, String> {
+    let Some(object) = answer.get("object") else {
+        return Ok(false);
+    };
+    let Some(join_id) = object.as_str().or_else(|| object.get("id")?.as_str()) else {
+        return Ok(false);
+    };
+    let failed = |e: DatabaseError| format!("Failed to answer Join {}: {}", join_id, e);
+    let Some(participation) = db
+        .find_participation_by_activity(join_id)
+        .await
+        .map_err(failed)?
+    else {
+        return Ok(false);
+    };
+    let event_id = &participation.event_id;
+
+    let answerer = answer
+        .get("actor")
+        .and_then(Value::as_str)
+        .ok_or("Answer must have an actor")?;
+    let is_organizer = match db.find_object_by_id(event_id).await.map_err(failed)? {
+        Some(event) => event.attributed_to == answerer,
+        None => same_host(answerer, event_id),
+    };
+    if !is_organizer {
+        return Err(format!("{} cannot answer a Join of {}", answerer, event_id));
+    }
+    let local = local_event(db, event_id).await.map_err(failed)?;
+    if status == ParticipationStatus::Accepted
+        && participation.status != ParticipationStatus::Accepted
+        && local.as_ref().is_some_and(|(details, _)| details.is_full())
+    {
+        return Err(format!("{} is full", event_id));
+    }
+
+    db.answer_participation(join_id, status)
+        .await
+        .map_err(failed)?;
+    if local.is_some() {
+        db.count_participants(event_id).await.map_err(failed)?;
+    }
+    info!(
+        "{} answered the Join of {} by {}: {:?}",
+        answerer, event_id, participation.actor, status
+    );
+    Ok(true)
+}
+
+/// Whether two URLs are on the same host
+fn same_host(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.host_str().is_some() && a.host_str() == b.host_str(),
+        _ => false,
+    }
+}
+
+/// Deliver the organizer's answer to a `Join` to the attendee
+async fn send_answer(
+    db: &DatabaseManager,
+    join: &Value,
+    status: ParticipationStatus,
+    organizer: &ActorDocument,
+) -> Result<(), DatabaseError> {
+    let answer_type = match status {
+        ParticipationStatus::Rejected => "Reject",
+        _ => "Accept",
+    };
+    let answer = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": answer_type,
+        "id": activity_id(&organizer.domain),
+        "actor": organizer.actor_id,
+        "object": join,
+        "to": [join.get("actor")],
+        "published": Utc::now().to_rfc3339()
+    });
+    queue_activity(db, &answer).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use oxifed::ObjectType;
+    use oxifed::database::{ActorRestriction, ActorStatus};
+
+    const ORGANIZER: &str = "https://example.com/users/alice";
+    const EVENT: &str = "https://example.com/objects/event";
+    const BOB: &str = "https://remote.example/users/bob";
+    const CAROL: &str = "https://remote.example/users/carol";
+
+    fn organizer() -> ActorDocument {
+        let now = Utc::now();
+        ActorDocument {
+            id: None,
+            actor_id: ORGANIZER.to_string(),
+            name: "Alice".to_string(),
+            preferred_username: "alice".to_string(),
+            domain: "example.com".to_string(),
+            actor_type: "Person".to_string(),
+            summary: None,
+            icon: None,
+            image: None,
+            inbox: format!("{}/inbox", ORGANIZER),
+            outbox: format!("{}/outbox", ORGANIZER),
+            following: format!("{}/following", ORGANIZER),
+            followers: format!("{}/followers", ORGANIZER),
+            liked: None,
+            featured: None,
+            public_key: None,
+            endpoints: None,
+            attachment: None,
+            additional_properties: None,
+            status: ActorStatus::Active,
+            restriction: ActorRestriction::None,
+            created_at: now,
+            updated_at: now,
+            local: true,
+            followers_count: 0,
+            following_count: 0,
+            statuses_count: 0,
+        }
+    }
+
+    /// Store Alice's event with the given join mode and capacity
+    async fn setup_event(db: &DatabaseManager, join_mode: &str, capacity: Option<i64>) {
+        db.insert_actor(organizer()).await.unwrap();
+        let event = ObjectDocument::from_activitypub(
+            &json!({
+                "type": "Event",
+                "id": EVENT,
+                "attributedTo": ORGANIZER,
+                "name": "Meetup",
+                "startTime": "2026-11-01T18:00:00Z",
+                "joinMode": join_mode,
+                "maximumAttendeeCapacity": capacity,
+                "to": ["https://www.w3.org/ns/activitystreams#Public"]
+            }),
+            ObjectType::Event,
+        );
+        db.insert_object(event).await.unwrap();
+    }
+
+    fn rsvp_activity(kind: &str, actor: &str, id: &str) -> Value {
+        json!({
+            "type": kind,
+            "id": format!("{}/activities/{}", actor, id),
+            "actor": actor,
+            "object": EVENT
+        })
+    }
+
+    fn answer(actor: &str, join: &Value) -> Value {
+        json!({
+            "type": "Accept",
+            "id": format!("{}/activities/answer", actor),
+            "actor": actor,
+            "object": join["id"]
+        })
+    }
+
+    async fn status_of(db: &DatabaseManager, actor: &str) -> Option<ParticipationStatus> {
+        db.find_participation(EVENT, actor)
+            .await
+            .unwrap()
+            .map(|participation| participation.status)
+    }
+
+    async fn participant_count(db: &DatabaseManager) -> i64 {
+        let event = db.find_object_by_id(EVENT).await.unwrap().unwrap();
+        event.event.unwrap().participant_count
+    }
+
+    /// Types of the answers Alice sent
+    async fn answers_sent(db: &DatabaseManager) -> Vec<oxifed::ActivityType> {
+        let mut sent: Vec<_> = db
+            .find_activities_by_actor(ORGANIZER, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|activity| activity.activity_type)
+            .collect();
+        sent.sort_by_key(|kind| format!("{:?}", kind));
+        sent
+    }
+
+    #[test]
+    fn test_same_host() {
+        assert!(same_host(BOB, CAROL));
+        assert!(!same_host(BOB, ORGANIZER));
+        assert!(!same_host("urn:uuid:1", "urn:uuid:2"));
+    }
+
+    #[tokio::test]
+    async fn test_rsvps_need_an_actor_and_an_event() {
+        let state = testing::state().await;
+        let db = &state.db_manager;
+        let anonymous =
+            json!({ "type": "Join", "id": "https://remote.example/1", "object": EVENT });
+        assert!(join(db, &anonymous).await.is_err());
+        assert!(leave(db, &anonymous).await.is_err());
+        let unnamed = json!({ "type": "Join", "actor": BOB, "object": EVENT });
+        assert!(join(db, &unnamed).await.is_err());
+        // Answers of anything but a Join are left to other handlers
+        let answer = json!({ "type": "Accept", "actor": BOB });
+        assert_eq!(
+            answer_join(db, &answer, ParticipationStatus::Accepted).await,
+            Ok(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_free_events_accept_until_full() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        setup_event(db, "free", Some(1)).await;
+
+        join(db, &rsvp_activity("Join", BOB, "join")).await.unwrap();
+        assert_eq!(
+            status_of(db, BOB).await,
+            Some(ParticipationStatus::Accepted)
+        );
+        assert_eq!(participant_count(db).await, 1);
+        assert_eq!(db.find_participants(EVENT).await.unwrap(), vec![BOB]);
+
+        join(db, &rsvp_activity("Join", CAROL, "join"))
+            .await
+            .unwrap();
+        assert_eq!(
+            status_of(db, CAROL).await,
+            Some(ParticipationStatus::Rejected)
+        );
+        assert_eq!(participant_count(db).await, 1);
+
+        // Joining again keeps Bob's place in the full event
+        join(db, &rsvp_activity("Join", BOB, "join-again"))
+            .await
+            .unwrap();
+        assert_eq!(
+            status_of(db, BOB).await,
+            Some(ParticipationStatus::Accepted)
+        );
+        assert_eq!(
+            answers_sent(db).await,
+            vec![oxifed::ActivityType::Accept, oxifed::ActivityType::Reject]
+        );
+
+        leave(db, &rsvp_activity("Leave", BOB, "leave"))
+            .await
+            .unwrap();
+        assert_eq!(status_of(db, BOB).await, None);
+        assert_eq!(participant_count(db).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_organizer_answers_restricted_joins() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        setup_event(db, "restricted", Some(1)).await;
+        let bob_join = rsvp_activity("Join", BOB, "join");
+        let carol_join = rsvp_activity("Join", CAROL, "join");
+        join(db, &bob_join).await.unwrap();
+        join(db, &carol_join).await.unwrap();
+        assert_eq!(status_of(db, BOB).await, Some(ParticipationStatus::Pending));
+        assert_eq!(participant_count(db).await, 0);
+        assert!(answers_sent(db).await.is_empty());
+
+        // Only the organizer answers
+        let by_attendee = answer(CAROL, &bob_join);
+        assert!(
+            answer_join(db, &by_attendee, ParticipationStatus::Accepted)
+                .await
+                .is_err()
+        );
+        assert_eq!(status_of(db, BOB).await, Some(ParticipationStatus::Pending));
+
+        let by_organizer = answer(ORGANIZER, &bob_join);
+        assert_eq!(
+            answer_join(db, &by_organizer, ParticipationStatus::Accepted).await,
+            Ok(true)
+        );
+        assert_eq!(
+            status_of(db, BOB).await,
+            Some(ParticipationStatus::Accepted)
+        );
+        assert_eq!(participant_count(db).await, 1);
+
+        // The event is full now
+        let carol_answer = answer(ORGANIZER, &carol_join);
+        assert!(
+            answer_join(db, &carol_answer, ParticipationStatus::Accepted)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            answer_join(db, &carol_answer, ParticipationStatus::Rejected).await,
+            Ok(true)
+        );
+        assert_eq!(
+            status_of(db, CAROL).await,
+            Some(ParticipationStatus::Rejected)
+        );
+        assert_eq!(participant_count(db).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_invite_only_joins_wait() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        setup_event(db, "invite", None).await;
+        join(db, &rsvp_activity("Join", BOB, "join")).await.unwrap();
+        assert_eq!(status_of(db, BOB).await, Some(ParticipationStatus::Pending));
+        assert!(db.find_participants(EVENT).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remote_organizers_answer_local_joins() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        db.insert_actor(organizer()).await.unwrap();
+        let remote_event = "https://events.example/events/1";
+        let join_activity = json!({
+            "type": "Join",
+            "id": format!("{}/activities/join", ORGANIZER),
+            "actor": ORGANIZER,
+            "object": remote_event
+        });
+        join(db, &join_activity).await.unwrap();
+        let pending = db
+            .find_participation(remote_event, ORGANIZER)
+            .await
+            .unwrap();
+        assert_eq!(pending.unwrap().status, ParticipationStatus::Pending);
+
+        // The event is not stored, so the answer must come from its server
+        assert!(
+            answer_join(
+                db,
+                &answer(BOB, &join_activity),
+                ParticipationStatus::Accepted
+            )
+            .await
+            .is_err()
+        );
+        let organizer_answer = answer("https://events.example/@org", &join_activity);
+        assert_eq!(
+            answer_join(db, &organizer_answer, ParticipationStatus::Accepted).await,
+            Ok(true)
+        );
+        let accepted = db
+            .find_participation(remote_event, ORGANIZER)
+            .await
+            .unwrap();
+        assert_eq!(accepted.unwrap().status, ParticipationStatus::Accepted);
+
+        // Joins of remote actors to unknown events are not recorded
+        let unknown = json!({
+            "type": "Join",
+            "id": format!("{}/activities/join", BOB),
+            "actor": BOB,
+            "object": remote_event
+        });
+        join(db, &unknown).await.unwrap();
+        assert!(
+            db.find_participation(remote_event, BOB)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leave_without_join() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        setup_event(db, "free", None).await;
+        join(db, &rsvp_activity("Join", BOB, "join")).await.unwrap();
+
+        leave(db, &rsvp_activity("Leave", CAROL, "leave"))
+            .await
+            .unwrap();
+        assert_eq!(participant_count(db).await, 1);
+        assert_eq!(
+            status_of(db, BOB).await,
+            Some(ParticipationStatus::Accepted)
+        );
+    }
+
+    #[test]
+    fn test_prepare_event() {
+        let mut event = json!({
+            "type": "Event",
+            "id": "https://example.com/objects/1",
+            "startTime": "2026-11-01T18:00:00Z",
+            "endTime": "2026-11-01T21:00:00+01:00",
+            "participantCount": 100,
+            "location": { "type": "Place", "name": "Town hall" }
+        });
+        prepare_event(event.as_object_mut().unwrap()).unwrap();
+        assert_eq!(event["participantCount"], 0);
+        assert_eq!(
+            event["participants"],
+            "https://example.com/objects/1/participants"
+        );
+
+        let mut backwards = json!({
+            "id": "https://example.com/objects/2",
+            "startTime": "2026-11-01T18:00:00Z",
+            "endTime": "2026-11-01T17:00:00Z"
+        });
+        assert!(prepare_event(backwards.as_object_mut().unwrap()).is_err());
+
+        let mut unscheduled = json!({ "id": "https://example.com/objects/3" });
+        assert!(prepare_event(unscheduled.as_object_mut().unwrap()).is_err());
+    }
+}
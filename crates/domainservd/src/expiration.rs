@@ -0,0 +1,305 @@
+//! Automatic deletion of old posts
+//!
+//! Domains set a default policy with an `expiration` object in their
+//! config, and accounts override it with an `expiration` property:
+//! `{"expiration": {"max_age_days": 90, "visibility": ["public"]}}`. An
+//! account's policy replaces its domain's entirely, so `{"expiration": {}}`
+//! keeps all posts of an account on an expiring domain.
+//!
+//! The sweeper replaces expired posts by Tombstones and sends a `Delete` to
+//! everyone the post was addressed to. Posts pinned to the profile, the
+//! ones in its featured collection, are kept unless the policy sets
+//! `keep_pinned = false`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::bson;
+use oxifed::database::{
+    ActorDocument, DatabaseError, DatabaseManager, ObjectDocument, VisibilityLevel,
+};
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::relay::queue_activity;
+
+/// How often expired posts are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Maximum number of posts of one account deleted per sweep
+const BATCH_LIMIT: i64 = 100;
+
+/// Automatic deletion policy of a domain or account
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExpirationPolicy {
+    /// Delete posts this many days after publication; never when unset
+    pub max_age_days: Option<u32>,
+    /// Visibility levels the policy applies to; all when empty
+    pub visibility: Vec<VisibilityLevel>,
+    /// Keep posts pinned to the profile
+    pub keep_pinned: bool,
+}
+
+impl Default for ExpirationPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            visibility: Vec::new(),
+            keep_pinned: true,
+        }
+    }
+}
+
+impl ExpirationPolicy {
+    /// Policy stored under `expiration` in a document, if set and valid
+    fn from_section(section: Option<&bson::Document>, owner: &str) -> Option<Self> {
+        let section = section?.get_document("expiration").ok()?;
+        bson::from_document(section.clone())
+            .inspect_err(|e| warn!("Ignoring invalid expiration policy of {}: {}", owner, e))
+            .ok()
+    }
+}
+
+/// Start deleting expired posts
+pub fn start_sweeper(db: Arc<DatabaseManager>, shutdown: &Shutdown) {
+    info!("Starting post expiration sweeper");
+    shutdown.spawn(run_sweeper(db, shutdown.clone()));
+}
+
+/// Sweep periodically until shutdown begins
+async fn run_sweeper(db: Arc<DatabaseManager>, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        if let Err(e) = sweep(&db, &shutdown).await {
+            warn!("Post expiration sweep failed: {}", e);
+        }
+    }
+}
+
+/// Delete the expired posts of all local accounts
+async fn sweep(db: &DatabaseManager, shutdown: &Shutdown) -> Result<(), DatabaseError> {
+    let mut domain_policies: HashMap<String, ExpirationPolicy> = HashMap::new();
+    for actor in db.find_local_actors().await? {
+        if shutdown.is_triggered() {
+            break;
+        }
+        if !domain_policies.contains_key(&actor.domain) {
+            let domain = db.find_domain_by_name(&actor.domain).await?;
+            let policy = domain
+                .and_then(|domain| {
+                    ExpirationPolicy::from_section(domain.config.as_ref(), &domain.domain)
+                })
+                .unwrap_or_default();
+            domain_policies.insert(actor.domain.clone(), policy);
+        }
+        let policy = policy_of(&actor, &domain_policies[&actor.domain]);
+        let Some(days) = policy.max_age_days else {
+            continue;
+        };
+
+        let before = Utc::now() - chrono::Duration::days(days.into());
+        let expired = db
+            .find_expired_objects(
+                &actor.actor_id,
+                before,
+                &policy.visibility,
+                policy.keep_pinned,
+                BATCH_LIMIT,
+            )
+            .await?;
+        for object in expired {
+            if let Err(e) = expire(db, &object).await {
+                warn!("Failed to delete expired post {}: {}", object.object_id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Policy applying to an account
+fn policy_of(actor: &ActorDocument, domain_policy: &ExpirationPolicy) -> ExpirationPolicy {
+    ExpirationPolicy::from_section(actor.additional_properties.as_ref(), &actor.actor_id)
+        .unwrap_or_else(|| domain_policy.clone())
+}
+
+/// Replace a post by a Tombstone and tell its recipients
+async fn expire(db: &DatabaseManager, object: &ObjectDocument) -> Result<(), DatabaseError> {
+    let deleted = Utc::now();
+    // Another instance may have deleted it first
+    if !db.tombstone_object(object, deleted).await? {
+        return Ok(());
+    }
+
+    let delete = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Delete",
+        "id": format!("{}/delete/{}", object.object_id, deleted.timestamp_millis()),
+        "actor": object.attributed_to,
+        "object": object.tombstone(deleted),
+        "to": object.to,
+        "cc": object.cc,
+        "published": deleted.to_rfc3339()
+    });
+    queue_activity(db, &delete).await?;
+    info!("Deleted expired post {}", object.object_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use oxifed::ObjectType;
+    use oxifed::database::{
+        ActorRestriction, ActorStatus, DomainDocument, DomainStatus, RegistrationMode,
+    };
+
+    const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+    fn actor(username: &str, properties: Option<bson::Document>) -> ActorDocument {
+        let now = Utc::now();
+        let actor_id = format!("https://example.com/users/{}", username);
+        ActorDocument {
+            id: None,
+            actor_id: actor_id.clone(),
+            name: username.to_string(),
+            preferred_username: username.to_string(),
+            domain: "example.com".to_string(),
+            actor_type: "Person".to_string(),
+            summary: None,
+            icon: None,
+            image: None,
+            inbox: format!("{}/inbox", actor_id),
+            outbox: format!("{}/outbox", actor_id),
+            following: format!("{}/following", actor_id),
+            followers: format!("{}/followers", actor_id),
+            liked: None,
+            featured: None,
+            public_key: None,
+            endpoints: None,
+            attachment: None,
+            additional_properties: properties,
+            status: ActorStatus::Active,
+            restriction: ActorRestriction::None,
+            created_at: now,
+            updated_at: now,
+            local: true,
+            followers_count: 0,
+            following_count: 0,
+            statuses_count: 0,
+        }
+    }
+
+    /// Domain deleting posts after 30 days
+    async fn setup_domain(db: &DatabaseManager) {
+        let now = Utc::now();
+        db.insert_domain(DomainDocument {
+            id: None,
+            domain: "example.com".to_string(),
+            name: None,
+            description: None,
+            contact_email: None,
+            rules: None,
+            registration_mode: RegistrationMode::Closed,
+            authorized_fetch: false,
+            max_note_length: None,
+            max_file_size: None,
+            allowed_file_types: None,
+            domain_key_id: None,
+            config: Some(bson::doc! { "expiration": { "max_age_days": 30 } }),
+            status: DomainStatus::Active,
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .unwrap();
+    }
+
+    /// Store a public post of `actor` published 60 days ago
+    async fn store_old_post(db: &DatabaseManager, actor: &ActorDocument) -> String {
+        let mut note = ObjectDocument::from_activitypub(
+            &json!({
+                "id": format!("{}/notes/1", actor.actor_id),
+                "attributedTo": actor.actor_id,
+                "content": "Old news",
+                "to": [PUBLIC],
+                "published": (Utc::now() - chrono::Duration::days(60)).to_rfc3339()
+            }),
+            ObjectType::Note,
+        );
+        note.local = true;
+        db.insert_object(note.clone()).await.unwrap();
+        note.object_id
+    }
+
+    async fn is_tombstone(db: &DatabaseManager, object_id: &str) -> bool {
+        let object = db.find_object_by_id(object_id).await.unwrap().unwrap();
+        object.object_type == ObjectType::Tombstone
+    }
+
+    async fn deletes_sent(db: &DatabaseManager, actor: &ActorDocument) -> usize {
+        db.find_activities_by_actor(&actor.actor_id, 10, 0)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn test_account_policy_replaces_domain_policy() {
+        let domain_policy = ExpirationPolicy {
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        let inherits = actor("alice", None);
+        assert_eq!(policy_of(&inherits, &domain_policy), domain_policy);
+
+        let keeps_all = actor("bob", Some(bson::doc! { "expiration": {} }));
+        assert_eq!(
+            policy_of(&keeps_all, &domain_policy),
+            ExpirationPolicy::default()
+        );
+
+        // An invalid policy is ignored rather than deleting more
+        let invalid = actor("carol", Some(bson::doc! { "expiration": { "days": 1 } }));
+        assert_eq!(policy_of(&invalid, &domain_policy), domain_policy);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_follows_account_policies() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        setup_domain(db).await;
+        let alice = actor("alice", None);
+        let bob = actor("bob", Some(bson::doc! { "expiration": {} }));
+        let carol = actor(
+            "carol",
+            Some(bson::doc! { "expiration": { "max_age_days": 90 } }),
+        );
+        for actor in [&alice, &bob, &carol] {
+            db.insert_actor(actor.clone()).await.unwrap();
+        }
+        let alice_post = store_old_post(db, &alice).await;
+        let bob_post = store_old_post(db, &bob).await;
+        let carol_post = store_old_post(db, &carol).await;
+
+        sweep(db, &Shutdown::new()).await.unwrap();
+        assert!(is_tombstone(db, &alice_post).await);
+        assert_eq!(deletes_sent(db, &alice).await, 1);
+        // `{}` keeps everything, and a longer account policy wins
+        assert!(!is_tombstone(db, &bob_post).await);
+        assert!(!is_tombstone(db, &carol_post).await);
+        assert_eq!(deletes_sent(db, &bob).await, 0);
+        assert_eq!(deletes_sent(db, &carol).await, 0);
+
+        // Nothing is deleted or sent twice
+        sweep(db, &Shutdown::new()).await.unwrap();
+        assert_eq!(deletes_sent(db, &alice).await, 1);
+    }
+}
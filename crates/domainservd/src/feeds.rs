@@ -0,0 +1,282 @@
+//! Followed hashtags, followed instances and the home timeline
+//!
+//! Mastodon-compatible clients follow hashtags at
+//! `/api/v1/tags/{name}/follow` and list them at `/api/v1/followed_tags`.
+//! Remote instances are followed the same way at
+//! `/api/v1/instances/{domain}/follow` and listed at
+//! `/api/v1/followed_instances`, which Mastodon does not have. storaged
+//! adds matching public posts to the follower's home feed, and
+//! `/api/v1/timelines/home` shows them with the posts of followed
+//! accounts, with the user's filters of the `home` context applied.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use oxifed::database::{FeedKind, FeedSubscriptionDocument, FilterContext, normalize_hashtag};
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::AppState;
+use crate::filters::filtered_statuses;
+use crate::mastodon::{ApiError, api_token, page_links, timeline_page, timeline_response};
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+/// Longest hashtag, in characters
+const MAX_HASHTAG_LENGTH: usize = 100;
+
+/// Routes of the feed subscription and home timeline endpoints
+pub fn feeds_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/tags/{name}", get(get_tag))
+        .route("/api/v1/tags/{name}/follow", post(follow_tag))
+        .route("/api/v1/tags/{name}/unfollow", post(unfollow_tag))
+        .route("/api/v1/followed_tags", get(get_followed_tags))
+        .route("/api/v1/instances/{domain}/follow", post(follow_instance))
+        .route(
+            "/api/v1/instances/{domain}/unfollow",
+            post(unfollow_instance),
+        )
+        .route("/api/v1/followed_instances", get(get_followed_instances))
+        .route("/api/v1/timelines/home", get(get_home_timeline))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Hashtag of a request path as followed; letters, digits and underscores
+fn hashtag_param(name: &str) -> Result<String, ApiError> {
+    let name = normalize_hashtag(name);
+    let valid = !name.is_empty()
+        && name.chars().count() <= MAX_HASHTAG_LENGTH
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid {
+        return Err(ApiError::invalid("Validation failed: Name is invalid"));
+    }
+    Ok(name)
+}
+
+/// Host name of an instance in a request path, lowercase
+fn instance_param(domain: &str) -> Result<String, ApiError> {
+    let domain = domain.trim().to_lowercase();
+    match url::Host::parse(&domain) {
+        Ok(url::Host::Domain(host)) if host == domain && host.contains('.') => Ok(domain),
+        _ => Err(ApiError::invalid("Validation failed: Domain is invalid")),
+    }
+}
+
+/// Mastodon tag entity
+fn tag_json(domain: &str, name: &str, following: bool) -> Value {
+    json!({
+        "name": name,
+        "url": format!("https://{}/tags/{}", domain, name),
+        "history": [],
+        "following": following,
+    })
+}
+
+/// Followed instance
+fn instance_json(subscription: &FeedSubscriptionDocument) -> Value {
+    json!({
+        "domain": subscription.name,
+        "following": true,
+        "created_at": subscription.created_at.to_rfc3339(),
+    })
+}
+
+async fn get_tag(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let name = hashtag_param(&name)?;
+        let following = state
+            .db_manager
+            .is_following_feed(&token.username, &token.domain, FeedKind::Hashtag, &name)
+            .await?;
+        Ok::<_, ApiError>(tag_json(&token.domain, &name, following))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn follow_tag(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let name = hashtag_param(&name)?;
+        state
+            .db_manager
+            .follow_feed(&token.username, &token.domain, FeedKind::Hashtag, &name)
+            .await?;
+        info!("{}@{} follows #{}", token.username, token.domain, name);
+        Ok::<_, ApiError>(tag_json(&token.domain, &name, true))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn unfollow_tag(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let name = hashtag_param(&name)?;
+        state
+            .db_manager
+            .unfollow_feed(&token.username, &token.domain, FeedKind::Hashtag, &name)
+            .await?;
+        Ok::<_, ApiError>(tag_json(&token.domain, &name, false))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_followed_tags(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let tags = state
+            .db_manager
+            .find_followed_feeds(&token.username, &token.domain, FeedKind::Hashtag)
+            .await?
+            .iter()
+            .map(|subscription| tag_json(&token.domain, &subscription.name, true))
+            .collect();
+        Ok::<_, ApiError>(Value::Array(tags))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn follow_instance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(domain): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let domain = instance_param(&domain)?;
+        if domain == token.domain {
+            return Err(ApiError::invalid("You cannot follow your own instance"));
+        }
+        let subscription = state
+            .db_manager
+            .follow_feed(&token.username, &token.domain, FeedKind::Instance, &domain)
+            .await?;
+        info!("{}@{} follows {}", token.username, token.domain, domain);
+        Ok(instance_json(&subscription))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn unfollow_instance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(domain): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let domain = instance_param(&domain)?;
+        state
+            .db_manager
+            .unfollow_feed(&token.username, &token.domain, FeedKind::Instance, &domain)
+            .await?;
+        Ok::<_, ApiError>(json!({ "domain": domain, "following": false }))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_followed_instances(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let instances = state
+            .db_manager
+            .find_followed_feeds(&token.username, &token.domain, FeedKind::Instance)
+            .await?
+            .iter()
+            .map(instance_json)
+            .collect();
+        Ok::<_, ApiError>(Value::Array(instances))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Posts and boosts of followed accounts and posts of followed feeds,
+/// newest first
+async fn get_home_timeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let owner = state
+            .db_manager
+            .find_actor_by_username(&token.username, &token.domain)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        let items = state
+            .db_manager
+            .get_home_timeline(
+                &token.username,
+                &token.domain,
+                &owner.actor_id,
+                &timeline_page(&query),
+            )
+            .await?;
+        let url = format!("https://{}/api/v1/timelines/home", token.domain);
+        let links = page_links(&url, &items);
+        let statuses = filtered_statuses(
+            &state.db_manager,
+            &token.username,
+            &token.domain,
+            FilterContext::Home,
+            items,
+        )
+        .await?;
+        Ok((links, statuses))
+    }
+    .await;
+    timeline_response(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashtag_param() {
+        assert_eq!(hashtag_param("#Rust_Lang").unwrap(), "rust_lang");
+        assert_eq!(hashtag_param("Fediverse").unwrap(), "fediverse");
+        assert!(hashtag_param("").is_err());
+        assert!(hashtag_param("two words").is_err());
+    }
+
+    #[test]
+    fn test_instance_param() {
+        assert_eq!(
+            instance_param("Mastodon.Social").unwrap(),
+            "mastodon.social"
+        );
+        assert!(instance_param("localhost").is_err());
+        assert!(instance_param("example.com/users").is_err());
+        assert!(instance_param("").is_err());
+    }
+}
@@ -0,0 +1,998 @@
+//! Content filters
+//!
+//! Users manage their filters with Mastodon's `/api/v2/filters` endpoints:
+//! a filter has a title, the contexts it applies in, an optional expiry,
+//! keywords and individual posts. Posts matching an active filter are left
+//! out of timelines when the filter hides them, or carry the match in their
+//! `filtered` property when it warns. Mentions that a hiding filter of the
+//! `notifications` context matches are not pushed.
+//!
+//! Keywords match case-insensitively anywhere in the text, content warning
+//! and media descriptions of a post; whole-word keywords only between word
+//! boundaries.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use oxifed::database::{
+    DatabaseError, DatabaseManager, FilterAction, FilterContext, FilterDocument, FilterKeyword,
+    FilterStatus, FilterUpdate, ObjectDocument, TimelineItem,
+};
+use regex::{Regex, RegexBuilder};
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::AppState;
+use crate::html::text_content;
+use crate::mastodon::{ApiError, api_token, parse_id, timeline_json};
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+/// Longest filter title or keyword, in characters
+const MAX_TEXT_LENGTH: usize = 256;
+
+/// Routes of the filter endpoints
+pub fn filters_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v2/filters", get(get_filters).post(create_filter))
+        .route(
+            "/api/v2/filters/{id}",
+            get(get_filter).put(update_filter).delete(delete_filter),
+        )
+        .route(
+            "/api/v2/filters/{id}/keywords",
+            get(get_keywords).post(add_keyword),
+        )
+        .route(
+            "/api/v2/filters/keywords/{id}",
+            get(get_keyword).put(update_keyword).delete(delete_entry),
+        )
+        .route(
+            "/api/v2/filters/{id}/statuses",
+            get(get_statuses).post(add_status),
+        )
+        .route(
+            "/api/v2/filters/statuses/{id}",
+            get(get_status).delete(delete_entry),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Active filters of a user in one context, ready to match posts
+#[derive(Debug, Default)]
+pub(crate) struct ActiveFilters {
+    filters: Vec<(FilterDocument, Vec<(String, Regex)>)>,
+}
+
+impl ActiveFilters {
+    pub(crate) async fn load(
+        db: &DatabaseManager,
+        username: &str,
+        domain: &str,
+        context: FilterContext,
+    ) -> Result<Self, DatabaseError> {
+        let filters = db.find_active_filters(username, domain, context).await?;
+        Ok(Self::new(filters))
+    }
+
+    fn new(filters: Vec<FilterDocument>) -> Self {
+        let filters = filters
+            .into_iter()
+            .map(|filter| {
+                let keywords = filter
+                    .keywords
+                    .iter()
+                    .filter_map(|keyword| {
+                        Some((keyword.keyword.clone(), keyword_pattern(keyword)?))
+                    })
+                    .collect();
+                (filter, keywords)
+            })
+            .collect();
+        Self { filters }
+    }
+
+    /// Mastodon filter results of the filters matching a post
+    fn results(&self, object: &ObjectDocument) -> Vec<(FilterAction, Value)> {
+        let mut text: Option<String> = None;
+        self.filters
+            .iter()
+            .filter_map(|(filter, keywords)| {
+                let status_matches: Vec<String> = filter
+                    .statuses
+                    .iter()
+                    .filter(|status| Some(status.status_id) == object.id)
+                    .map(|status| status.status_id.to_hex())
+                    .collect();
+                let keyword_matches: Vec<&String> = if keywords.is_empty() {
+                    Vec::new()
+                } else {
+                    let text = text.get_or_insert_with(|| searchable_text(object));
+                    keywords
+                        .iter()
+                        .filter(|(_, pattern)| pattern.is_match(text))
+                        .map(|(keyword, _)| keyword)
+                        .collect()
+                };
+                if status_matches.is_empty() && keyword_matches.is_empty() {
+                    return None;
+                }
+                Some((
+                    filter.filter_action,
+                    json!({
+                        "filter": filter_json(filter),
+                        "keyword_matches": (!keyword_matches.is_empty()).then_some(keyword_matches),
+                        "status_matches": (!status_matches.is_empty()).then_some(status_matches),
+                    }),
+                ))
+            })
+            .collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Whether a hiding filter matches a post
+    pub(crate) fn hides(&self, object: &ObjectDocument) -> bool {
+        self.results(object)
+            .iter()
+            .any(|(action, _)| *action == FilterAction::Hide)
+    }
+
+    /// Leave out the posts and boosts of posts a hiding filter matches
+    ///
+    /// Returns the remaining items with the results of the warning filters
+    /// matching their posts, by ActivityPub ID.
+    pub(crate) fn apply(
+        &self,
+        items: Vec<TimelineItem>,
+    ) -> (Vec<TimelineItem>, HashMap<String, Vec<Value>>) {
+        if self.is_empty() {
+            return (items, HashMap::new());
+        }
+        let mut warnings = HashMap::new();
+        let kept = items
+            .into_iter()
+            .filter(|item| {
+                let object = item.post();
+                let results = self.results(object);
+                if results
+                    .iter()
+                    .any(|(action, _)| *action == FilterAction::Hide)
+                {
+                    return false;
+                }
+                if !results.is_empty() {
+                    warnings.insert(
+                        object.object_id.clone(),
+                        results.into_iter().map(|(_, result)| result).collect(),
+                    );
+                }
+                true
+            })
+            .collect();
+        (kept, warnings)
+    }
+}
+
+/// Statuses of timeline items with a user's filters of `context` applied
+pub(crate) async fn filtered_statuses(
+    db: &DatabaseManager,
+    username: &str,
+    domain: &str,
+    context: FilterContext,
+    items: Vec<TimelineItem>,
+) -> Result<Vec<Value>, DatabaseError> {
+    let filters = ActiveFilters::load(db, username, domain, context).await?;
+    let (items, warnings) = filters.apply(items);
+    let mut statuses = timeline_json(db, &items).await?;
+    mark_filtered(&mut statuses, &warnings);
+    Ok(statuses)
+}
+
+/// Set the `filtered` property of statuses that warning filters match
+///
+/// A boost carries the results of the post it boosts.
+fn mark_filtered(statuses: &mut [Value], warnings: &HashMap<String, Vec<Value>>) {
+    for status in statuses {
+        let post = status.get("reblog").filter(|reblog| !reblog.is_null());
+        let results = post
+            .unwrap_or(status)
+            .get("uri")
+            .and_then(Value::as_str)
+            .and_then(|uri| warnings.get(uri));
+        if let (Some(results), Some(status)) = (results, status.as_object_mut()) {
+            status.insert("filtered".to_string(), Value::from(results.clone()));
+        }
+    }
+}
+
+/// Pattern of a keyword; `None` if it cannot be compiled
+fn keyword_pattern(keyword: &FilterKeyword) -> Option<Regex> {
+    let escaped = regex::escape(keyword.keyword.trim());
+    if escaped.is_empty() {
+        return None;
+    }
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let pattern = if keyword.whole_word {
+        let text = keyword.keyword.trim();
+        format!(
+            "{}{}{}",
+            if is_word(text.chars().next()) {
+                r"\b"
+            } else {
+                ""
+            },
+            escaped,
+            if is_word(text.chars().last()) {
+                r"\b"
+            } else {
+                ""
+            },
+        )
+    } else {
+        escaped
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+/// Text of a post that keywords are matched against
+fn searchable_text(object: &ObjectDocument) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    parts.extend(object.summary.clone());
+    parts.extend(object.name.clone());
+    parts.extend(object.content.as_deref().map(text_content));
+    parts.extend(
+        object
+            .attachment
+            .iter()
+            .flatten()
+            .filter_map(|attachment| attachment.name.clone()),
+    );
+    parts.join("\n")
+}
+
+/// Mastodon filter entity
+fn filter_json(filter: &FilterDocument) -> Value {
+    json!({
+        "id": filter.id.map(|id| id.to_hex()),
+        "title": filter.title,
+        "context": filter.context,
+        "expires_at": filter.expires_at.map(|expires_at| expires_at.to_rfc3339()),
+        "filter_action": filter.filter_action,
+        "keywords": filter.keywords.iter().map(keyword_json).collect::<Vec<_>>(),
+        "statuses": filter.statuses.iter().map(status_json).collect::<Vec<_>>(),
+    })
+}
+
+fn keyword_json(keyword: &FilterKeyword) -> Value {
+    json!({
+        "id": keyword.id.to_hex(),
+        "keyword": keyword.keyword,
+        "whole_word": keyword.whole_word,
+    })
+}
+
+fn status_json(status: &FilterStatus) -> Value {
+    json!({
+        "id": status.id.to_hex(),
+        "status_id": status.status_id.to_hex(),
+    })
+}
+
+/// Parameters of a form or JSON request body as form pairs
+///
+/// JSON arrays become `name[]` pairs, arrays and maps of objects
+/// `name[index][field]` pairs, as Rails-style clients send them in forms.
+fn body_pairs(headers: &HeaderMap, body: &Bytes) -> Result<Vec<(String, String)>, ApiError> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok(url::form_urlencoded::parse(body).into_owned().collect());
+    }
+
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|e| ApiError::invalid(format!("Invalid JSON body: {}", e)))?;
+    let Value::Object(object) = value else {
+        return Err(ApiError::invalid("Body must be a JSON object"));
+    };
+    let scalar = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in &object {
+        let entries: Vec<(String, &Value)> = match value {
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (index.to_string(), item))
+                .collect(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(index, item)| (index.clone(), item))
+                .collect(),
+            scalar_value => {
+                pairs.push((key.clone(), scalar(scalar_value)));
+                continue;
+            }
+        };
+        for (index, item) in entries {
+            match item {
+                Value::Object(fields) => pairs.extend(fields.iter().map(|(field, value)| {
+                    (format!("{}[{}][{}]", key, index, field), scalar(value))
+                })),
+                item => pairs.push((format!("{}[]", key), scalar(item))),
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Keyword given in `keywords_attributes`
+#[derive(Debug, Default, PartialEq)]
+struct KeywordAttributes {
+    id: Option<String>,
+    keyword: Option<String>,
+    whole_word: Option<bool>,
+    destroy: bool,
+}
+
+impl KeywordAttributes {
+    fn has(&self, field: &str) -> bool {
+        match field {
+            "id" => self.id.is_some(),
+            "keyword" => self.keyword.is_some(),
+            "whole_word" => self.whole_word.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// Settings and keywords of a filter given in a request
+#[derive(Debug, Default, PartialEq)]
+struct FilterParams {
+    title: Option<String>,
+    context: Option<Vec<FilterContext>>,
+    filter_action: Option<FilterAction>,
+    /// `Some(None)` when `expires_in` is given empty
+    expires_in: Option<Option<i64>>,
+    keywords: Vec<KeywordAttributes>,
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, ApiError> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" | "" => Ok(false),
+        _ => Err(ApiError::invalid(format!("{} must be a boolean", name))),
+    }
+}
+
+fn parse_text(name: &str, value: &str) -> Result<String, ApiError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ApiError::invalid(format!(
+            "Validation failed: {} can't be blank",
+            name
+        )));
+    }
+    if value.chars().count() > MAX_TEXT_LENGTH {
+        return Err(ApiError::invalid(format!(
+            "Validation failed: {} is too long",
+            name
+        )));
+    }
+    Ok(value.to_string())
+}
+
+fn parse_enum<T: serde::de::DeserializeOwned>(name: &str, value: &str) -> Result<T, ApiError> {
+    serde_json::from_value(Value::String(value.to_string()))
+        .map_err(|_| ApiError::invalid(format!("Unknown {} {}", name, value)))
+}
+
+fn filter_params(pairs: &[(String, String)]) -> Result<FilterParams, ApiError> {
+    let mut params = FilterParams::default();
+    let mut keyword_indexes: Vec<String> = Vec::new();
+    for (key, value) in pairs {
+        match key.as_str() {
+            "title" => params.title = Some(parse_text("Title", value)?),
+            "context" | "context[]" => params
+                .context
+                .get_or_insert_with(Vec::new)
+                .push(parse_enum("context", value)?),
+            "filter_action" => params.filter_action = Some(parse_enum("filter_action", value)?),
+            "expires_in" if value.is_empty() => params.expires_in = Some(None),
+            "expires_in" => {
+                let secs = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| ApiError::invalid("expires_in must be a number of seconds"))?;
+                params.expires_in = Some(Some(secs));
+            }
+            _ => {
+                let Some((index, field)) = key
+                    .strip_prefix("keywords_attributes[")
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(|rest| rest.split_once("]["))
+                else {
+                    continue;
+                };
+                // Entries without an index start anew when a field repeats
+                let known = if index.is_empty() {
+                    params
+                        .keywords
+                        .last()
+                        .filter(|last| !last.has(field))
+                        .map(|_| params.keywords.len() - 1)
+                } else {
+                    keyword_indexes.iter().position(|known| known == index)
+                };
+                let position = known.unwrap_or_else(|| {
+                    keyword_indexes.push(index.to_string());
+                    params.keywords.push(KeywordAttributes::default());
+                    params.keywords.len() - 1
+                });
+                let entry = &mut params.keywords[position];
+                match field {
+                    "id" => entry.id = Some(value.clone()),
+                    "keyword" => entry.keyword = Some(parse_text("Keyword", value)?),
+                    "whole_word" => entry.whole_word = Some(parse_bool("whole_word", value)?),
+                    "_destroy" => entry.destroy = parse_bool("_destroy", value)?,
+                    _ => {}
+                }
+            }
+        }
+    }
+    if params.context.as_ref().is_some_and(Vec::is_empty) {
+        return Err(ApiError::invalid(
+            "Validation failed: Context can't be blank",
+        ));
+    }
+    Ok(params)
+}
+
+/// Keyword of a keyword request
+fn keyword_params(
+    pairs: &[(String, String)],
+    id: ObjectId,
+    current: Option<&FilterKeyword>,
+) -> Result<FilterKeyword, ApiError> {
+    let param = |name: &str| {
+        pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let keyword = match (param("keyword"), current) {
+        (Some(keyword), _) => parse_text("Keyword", keyword)?,
+        (None, Some(current)) => current.keyword.clone(),
+        (None, None) => {
+            return Err(ApiError::invalid(
+                "Validation failed: Keyword can't be blank",
+            ));
+        }
+    };
+    let whole_word = match param("whole_word") {
+        Some(value) => parse_bool("whole_word", value)?,
+        None => current.is_none_or(|current| current.whole_word),
+    };
+    Ok(FilterKeyword {
+        id,
+        keyword,
+        whole_word,
+    })
+}
+
+async fn get_filters(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let filters = state
+            .db_manager
+            .find_filters(&token.username, &token.domain)
+            .await?;
+        Ok::<_, ApiError>(Value::Array(filters.iter().map(filter_json).collect()))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let filter = state
+            .db_manager
+            .find_filter(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(filter_json(&filter))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn create_filter(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let params = filter_params(&body_pairs(&headers, &body)?)?;
+        let title = params
+            .title
+            .ok_or_else(|| ApiError::invalid("Validation failed: Title can't be blank"))?;
+        let context = params
+            .context
+            .ok_or_else(|| ApiError::invalid("Validation failed: Context can't be blank"))?;
+        let keywords = params
+            .keywords
+            .into_iter()
+            .filter(|attributes| !attributes.destroy)
+            .map(|attributes| {
+                Ok(FilterKeyword {
+                    id: ObjectId::new(),
+                    keyword: attributes.keyword.ok_or_else(|| {
+                        ApiError::invalid("Validation failed: Keyword can't be blank")
+                    })?,
+                    whole_word: attributes.whole_word.unwrap_or(true),
+                })
+            })
+            .collect::<Result<Vec<_>, ApiError>>()?;
+        let now = Utc::now();
+        let filter = state
+            .db_manager
+            .insert_filter(FilterDocument {
+                id: None,
+                username: token.username.clone(),
+                domain: token.domain.clone(),
+                title,
+                context,
+                filter_action: params.filter_action.unwrap_or_default(),
+                expires_at: params
+                    .expires_in
+                    .flatten()
+                    .map(|secs| now + Duration::seconds(secs)),
+                keywords,
+                statuses: Vec::new(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+        info!(
+            "{}@{} created filter {:?}",
+            token.username, token.domain, filter.title
+        );
+        Ok::<_, ApiError>(filter_json(&filter))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Change the settings of a filter and add, change or remove keywords
+async fn update_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let id = parse_id(&id)?;
+        let params = filter_params(&body_pairs(&headers, &body)?)?;
+        let db = &state.db_manager;
+        let filter = db
+            .find_filter(&token.username, &token.domain, id)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+
+        for attributes in params.keywords {
+            let current = match &attributes.id {
+                Some(keyword_id) => {
+                    let keyword_id = parse_id(keyword_id)?;
+                    let current = filter
+                        .keywords
+                        .iter()
+                        .find(|keyword| keyword.id == keyword_id)
+                        .ok_or_else(ApiError::not_found)?;
+                    Some(current)
+                }
+                None => None,
+            };
+            match current {
+                Some(current) if attributes.destroy => {
+                    db.remove_filter_entry(&token.username, &token.domain, current.id)
+                        .await?;
+                }
+                Some(current) => {
+                    let keyword = FilterKeyword {
+                        id: current.id,
+                        keyword: attributes
+                            .keyword
+                            .unwrap_or_else(|| current.keyword.clone()),
+                        whole_word: attributes.whole_word.unwrap_or(current.whole_word),
+                    };
+                    db.update_filter_keyword(&token.username, &token.domain, &keyword)
+                        .await?;
+                }
+                None if attributes.destroy => {}
+                None => {
+                    let keyword = FilterKeyword {
+                        id: ObjectId::new(),
+                        keyword: attributes.keyword.ok_or_else(|| {
+                            ApiError::invalid("Validation failed: Keyword can't be blank")
+                        })?,
+                        whole_word: attributes.whole_word.unwrap_or(true),
+                    };
+                    db.add_filter_keyword(&token.username, &token.domain, id, &keyword)
+                        .await?;
+                }
+            }
+        }
+
+        let update = FilterUpdate {
+            title: params.title,
+            context: params.context,
+            filter_action: params.filter_action,
+            expires_at: params
+                .expires_in
+                .map(|secs| secs.map(|secs| Utc::now() + Duration::seconds(secs))),
+        };
+        let filter = db
+            .update_filter(&token.username, &token.domain, id, update)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(filter_json(&filter))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn delete_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let deleted = state
+            .db_manager
+            .delete_filter(&token.username, &token.domain, parse_id(&id)?)
+            .await?;
+        if !deleted {
+            return Err(ApiError::not_found());
+        }
+        Ok(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_keywords(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let filter = state
+            .db_manager
+            .find_filter(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(Value::Array(
+            filter.keywords.iter().map(keyword_json).collect(),
+        ))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn add_keyword(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let keyword = keyword_params(&body_pairs(&headers, &body)?, ObjectId::new(), None)?;
+        state
+            .db_manager
+            .add_filter_keyword(&token.username, &token.domain, parse_id(&id)?, &keyword)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(keyword_json(&keyword))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Filter of a user holding the keyword or post `id`
+async fn find_entry(
+    state: &AppState,
+    username: &str,
+    domain: &str,
+    id: ObjectId,
+) -> Result<FilterDocument, ApiError> {
+    state
+        .db_manager
+        .find_filter_by_entry(username, domain, id)
+        .await?
+        .ok_or_else(ApiError::not_found)
+}
+
+async fn get_keyword(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let id = parse_id(&id)?;
+        let filter = find_entry(&state, &token.username, &token.domain, id).await?;
+        let keyword = filter
+            .keywords
+            .iter()
+            .find(|keyword| keyword.id == id)
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(keyword_json(keyword))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn update_keyword(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let id = parse_id(&id)?;
+        let filter = find_entry(&state, &token.username, &token.domain, id).await?;
+        let current = filter
+            .keywords
+            .iter()
+            .find(|keyword| keyword.id == id)
+            .ok_or_else(ApiError::not_found)?;
+        let keyword = keyword_params(&body_pairs(&headers, &body)?, id, Some(current))?;
+        state
+            .db_manager
+            .update_filter_keyword(&token.username, &token.domain, &keyword)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(keyword_json(&keyword))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Remove a keyword or post from its filter
+async fn delete_entry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let removed = state
+            .db_manager
+            .remove_filter_entry(&token.username, &token.domain, parse_id(&id)?)
+            .await?;
+        if !removed {
+            return Err(ApiError::not_found());
+        }
+        Ok(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_statuses(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let filter = state
+            .db_manager
+            .find_filter(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(Value::Array(
+            filter.statuses.iter().map(status_json).collect(),
+        ))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn add_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let pairs = body_pairs(&headers, &body)?;
+        let status_id = pairs
+            .iter()
+            .find(|(key, _)| key == "status_id")
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| ApiError::invalid("status_id is required"))?;
+        let status = FilterStatus {
+            id: ObjectId::new(),
+            status_id: parse_id(status_id)?,
+        };
+        state
+            .db_manager
+            .add_filter_status(&token.username, &token.domain, parse_id(&id)?, &status)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(status_json(&status))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let id = parse_id(&id)?;
+        let filter = find_entry(&state, &token.username, &token.domain, id).await?;
+        let status = filter
+            .statuses
+            .iter()
+            .find(|status| status.id == id)
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(status_json(status))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::ObjectType;
+
+    fn filter(action: FilterAction, keyword: &str, whole_word: bool) -> FilterDocument {
+        let now = Utc::now();
+        FilterDocument {
+            id: Some(ObjectId::new()),
+            username: "alice".to_string(),
+            domain: "example.com".to_string(),
+            title: keyword.to_string(),
+            context: vec![FilterContext::Home],
+            filter_action: action,
+            expires_at: None,
+            keywords: vec![FilterKeyword {
+                id: ObjectId::new(),
+                keyword: keyword.to_string(),
+                whole_word,
+            }],
+            statuses: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn note(id: &str, content: &str) -> ObjectDocument {
+        ObjectDocument::from_activitypub(
+            &json!({
+                "id": id,
+                "attributedTo": "https://remote.example/users/bob",
+                "content": content,
+            }),
+            ObjectType::Note,
+        )
+    }
+
+    #[test]
+    fn test_keywords_match_text() {
+        let filters = ActiveFilters::new(vec![
+            filter(FilterAction::Hide, "spoiler", true),
+            filter(FilterAction::Warn, "#tv", false),
+        ]);
+        let items = vec![
+            note("https://remote.example/notes/1", "<p>No SPOILER here</p>"),
+            note("https://remote.example/notes/2", "<p>spoilers ahead</p>"),
+            note(
+                "https://remote.example/notes/3",
+                "<p>Tonight on <a>#TV</a></p>",
+            ),
+        ]
+        .into_iter()
+        .map(TimelineItem::Post)
+        .collect();
+        let (kept, warnings) = filters.apply(items);
+        let kept: Vec<&str> = kept
+            .iter()
+            .map(|item| item.post().object_id.as_str())
+            .collect();
+        // A whole-word keyword does not match within a word
+        assert_eq!(
+            kept,
+            vec![
+                "https://remote.example/notes/2",
+                "https://remote.example/notes/3"
+            ]
+        );
+        let results = &warnings["https://remote.example/notes/3"];
+        assert_eq!(results[0]["keyword_matches"], json!(["#tv"]));
+        assert_eq!(results[0]["filter"]["filter_action"], "warn");
+    }
+
+    #[test]
+    fn test_filter_params_from_form() {
+        let pairs: Vec<(String, String)> = url::form_urlencoded::parse(
+            b"title=TV&context[]=home&context[]=public&filter_action=hide&expires_in=3600\
+              &keywords_attributes[][keyword]=spoiler&keywords_attributes[][whole_word]=false\
+              &keywords_attributes[][keyword]=finale",
+        )
+        .into_owned()
+        .collect();
+        let params = filter_params(&pairs).unwrap();
+        assert_eq!(params.title.as_deref(), Some("TV"));
+        assert_eq!(
+            params.context,
+            Some(vec![FilterContext::Home, FilterContext::Public])
+        );
+        assert_eq!(params.filter_action, Some(FilterAction::Hide));
+        assert_eq!(params.expires_in, Some(Some(3600)));
+        assert_eq!(params.keywords.len(), 2);
+        assert_eq!(params.keywords[0].whole_word, Some(false));
+        assert_eq!(params.keywords[1].keyword.as_deref(), Some("finale"));
+    }
+
+    #[test]
+    fn test_filter_params_from_json() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let body = Bytes::from(
+            r#"{"title": "TV", "context": ["home"], "expires_in": null,
+                "keywords_attributes": [{"id": "abc", "_destroy": true}]}"#,
+        );
+        let params = filter_params(&body_pairs(&headers, &body).unwrap()).unwrap();
+        assert_eq!(params.context, Some(vec![FilterContext::Home]));
+        assert_eq!(params.expires_in, Some(None));
+        assert_eq!(
+            params.keywords,
+            vec![KeywordAttributes {
+                id: Some("abc".to_string()),
+                destroy: true,
+                ..KeywordAttributes::default()
+            }]
+        );
+
+        let unknown = [("context[]".to_string(), "everywhere".to_string())];
+        assert!(filter_params(&unknown).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+//! Group actors
+//!
+//! Groups are communities following FEP-1b12: actors join by following the
+//! group, and the group announces the `Create`, `Update` and `Delete`
+//! activities its members address to it to all members, embedding the
+//! original activity. Members that are banned, by a moderator's `Block`
+//! sent to the group or by `oxiadm group ban`, can no longer post to the
+//! group or rejoin it.
+//!
+//! Moderators are stored in the `moderators` additional property of the
+//! group and served as the collection the group's `attributedTo` points
+//! to. Besides banning members they may delete any post of the group.
+
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use oxifed::builder::PUBLIC;
+use oxifed::database::{
+    ActorDocument, ActorRestriction, ActorStatus, DatabaseManager, FollowDocument, FollowStatus,
+};
+use oxifed::messaging::{GroupBanMessage, GroupCreateMessage};
+use oxifed::signature_middleware::VerifiedSigner;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use crate::AppState;
+use crate::db::MongoDB;
+use crate::extract_domain_from_headers;
+use crate::rabbitmq::{
+    RabbitMQError, create_webfinger_profile, does_domain_exist, queue_key_generation, split_subject,
+};
+use crate::relay::{activity_id, queue_activity};
+
+/// Actor type of groups
+pub const GROUP: &str = "Group";
+
+/// Moderators of a group
+pub fn moderators(group: &ActorDocument) -> Vec<String> {
+    group
+        .additional_properties
+        .as_ref()
+        .and_then(|props| props.get_array("moderators").ok())
+        .map(|moderators| {
+            moderators
+                .iter()
+                .filter_map(|m| m.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// URL of a group's moderators collection
+pub fn moderators_url(group: &ActorDocument) -> String {
+    format!("{}/moderators", group.actor_id)
+}
+
+/// Create a Group actor
+pub async fn create_group(
+    db: &Arc<MongoDB>,
+    message: &GroupCreateMessage,
+) -> Result<(), RabbitMQError> {
+    let (name, domain) = split_subject(&message.subject)?;
+    if !does_domain_exist(&domain, db).await {
+        return Err(RabbitMQError::DomainNotFound(domain));
+    }
+
+    let actor_id = format!("https://{}/users/{}", domain, name);
+    if db.find_actor_by_id(&actor_id).await?.is_some() {
+        return Err(RabbitMQError::ConstraintError(format!(
+            "Actor with ID '{}' already exists",
+            actor_id
+        )));
+    }
+
+    let now = Utc::now();
+    let shared_inbox = format!("https://{}/inbox", domain);
+    let actor_doc = ActorDocument {
+        id: None,
+        actor_id: actor_id.clone(),
+        name: message.name.clone().unwrap_or_else(|| name.clone()),
+        preferred_username: name.clone(),
+        domain: domain.clone(),
+        actor_type: GROUP.to_string(),
+        summary: message.summary.clone(),
+        icon: None,
+        image: None,
+        inbox: format!("{}/inbox", actor_id),
+        outbox: format!("{}/outbox", actor_id),
+        following: format!("{}/following", actor_id),
+        followers: format!("{}/followers", actor_id),
+        liked: None,
+        featured: Some(format!("{}/featured", actor_id)),
+        public_key: None,
+        endpoints: Some(mongodb::bson::doc! { "sharedInbox": shared_inbox }),
+        attachment: None,
+        additional_properties: Some(mongodb::bson::doc! {
+            "moderators": &message.moderators,
+        }),
+        status: ActorStatus::Active,
+        restriction: ActorRestriction::None,
+        created_at: now,
+        updated_at: now,
+        local: true,
+        followers_count: 0,
+        following_count: 0,
+        statuses_count: 0,
+    };
+    db.manager().insert_actor(actor_doc).await?;
+    info!("Created group {}", actor_id);
+
+    queue_key_generation(db, &actor_id).await?;
+    let aliases = vec![format!("https://{}/@{}", domain, name)];
+    create_webfinger_profile(db, &message.subject, &actor_id, Some(aliases), None).await
+}
+
+/// Ban a member from a group on behalf of the group itself
+pub async fn ban_member(db: &Arc<MongoDB>, message: &GroupBanMessage) -> Result<(), RabbitMQError> {
+    let (name, domain) = split_subject(&message.group)?;
+    let group = db
+        .find_actor_by_username(&name, &domain)
+        .await?
+        .filter(|actor| actor.actor_type == GROUP)
+        .ok_or_else(|| RabbitMQError::ProfileNotFound(message.group.clone()))?;
+
+    ban(db.manager(), &group, &group.actor_id, &message.actor, None)
+        .await
+        .map_err(RabbitMQError::ConstraintError)
+}
+
+/// Mark a member as banned and tell the members
+///
+/// `block` is the moderator's Block to announce; without one the group
+/// sends its own.
+async fn ban(
+    db: &DatabaseManager,
+    group: &ActorDocument,
+    moderator: &str,
+    member: &str,
+    block: Option<&Value>,
+) -> Result<(), String> {
+    let stored = match db.find_follow(member, &group.actor_id).await {
+        Ok(Some(_)) => db
+            .update_follow_status(member, &group.actor_id, FollowStatus::Rejected)
+            .await
+            .map(|_| ()),
+        Ok(None) => db
+            .insert_follow(FollowDocument {
+                id: None,
+                follower: member.to_string(),
+                following: group.actor_id.clone(),
+                status: FollowStatus::Rejected,
+                activity_id: String::new(),
+                accept_activity_id: None,
+                created_at: Utc::now(),
+                responded_at: Some(Utc::now()),
+                follower_inbox: None,
+                follower_shared_inbox: None,
+            })
+            .await
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+    stored.map_err(|e| format!("Failed to ban {}: {}", member, e))?;
+    info!("{} banned {} from {}", moderator, member, group.actor_id);
+
+    let mut recipients = members(db, group, None).await?;
+    recipients.push(member.to_string());
+    let activity = match block {
+        Some(block) => announce(group, block, recipients),
+        None => json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "Block",
+            "id": activity_id(&group.domain),
+            "actor": group.actor_id,
+            "object": member,
+            "target": group.actor_id,
+            "to": recipients,
+            "published": Utc::now().to_rfc3339()
+        }),
+    };
+    queue_activity(db, &activity)
+        .await
+        .map_err(|e| format!("Failed to queue ban of {}: {}", member, e))
+}
+
+/// Accepted members of a group except `except`
+async fn members(
+    db: &DatabaseManager,
+    group: &ActorDocument,
+    except: Option<&str>,
+) -> Result<Vec<String>, String> {
+    Ok(db
+        .get_actor_followers(&group.actor_id)
+        .await
+        .map_err(|e| format!("Failed to list members: {}", e))?
+        .into_iter()
+        .filter(|member| Some(member.as_str()) != except)
+        .collect())
+}
+
+/// Announce of an activity by the group
+fn announce(group: &ActorDocument, activity: &Value, members: Vec<String>) -> Value {
+    json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Announce",
+        "id": activity_id(&group.domain),
+        "actor": group.actor_id,
+        "object": activity,
+        "to": [PUBLIC],
+        "cc": members,
+        "published": Utc::now().to_rfc3339()
+    })
+}
+
+/// ID of an object or link value
+fn id_of(value: Option<&Value>) -> Option<&str> {
+    match value? {
+        Value::String(id) => Some(id),
+        object => object.get("id")?.as_str(),
+    }
+}
+
+/// Whether an object is addressed to the group
+fn addresses(object: &Value, group: &str) -> bool {
+    ["to", "cc", "audience"]
+        .iter()
+        .any(|key| match object.get(*key) {
+            Some(Value::String(recipient)) => recipient == group,
+            Some(Value::Array(recipients)) => recipients.iter().any(|r| r.as_str() == Some(group)),
+            _ => false,
+        })
+}
+
+/// Handle the group side of an activity sent to a group's inbox
+///
+/// Returns `None` for activities that take the regular inbox path, which
+/// includes the posts the group announces so they are also stored.
+pub async fn handle_inbox(
+    activity: &Value,
+    group: &ActorDocument,
+    signer: Option<&VerifiedSigner>,
+    state: &AppState,
+) -> Option<Result<(), String>> {
+    let actor = activity.get("actor").and_then(Value::as_str)?;
+    // Only announce what the author signed, not forwarded copies
+    if signer.is_some_and(|signer| signer.owner != actor) {
+        return None;
+    }
+    let db = &state.db_manager;
+    let membership = db
+        .find_follow(actor, &group.actor_id)
+        .await
+        .ok()
+        .flatten()
+        .map(|follow| follow.status);
+    let is_moderator = moderators(group).iter().any(|m| m == actor);
+
+    match activity.get("type").and_then(Value::as_str)? {
+        "Follow" if membership == Some(FollowStatus::Rejected) => {
+            info!("Rejecting banned {} from {}", actor, group.actor_id);
+            let reject = json!({
+                "@context": "https://www.w3.org/ns/activitystreams",
+                "type": "Reject",
+                "id": activity_id(&group.domain),
+                "actor": group.actor_id,
+                "object": activity,
+                "to": [actor],
+                "published": Utc::now().to_rfc3339()
+            });
+            Some(
+                queue_activity(db, &reject)
+                    .await
+                    .map_err(|e| format!("Failed to queue Reject: {}", e)),
+            )
+        }
+        "Block" if is_moderator && id_of(activity.get("target")) == Some(&group.actor_id) => {
+            let member = id_of(activity.get("object"))?;
+            Some(ban(db, group, actor, member, Some(activity)).await)
+        }
+        kind @ ("Create" | "Update" | "Delete") => {
+            if membership != Some(FollowStatus::Accepted) && !is_moderator {
+                return None;
+            }
+            let object = activity.get("object")?;
+            let is_author = author(db, object).await.as_deref() == Some(actor);
+            let allowed = if kind == "Delete" {
+                is_author || is_moderator
+            } else {
+                is_author && addresses(object, &group.actor_id)
+            };
+            if allowed {
+                let members = match members(db, group, Some(actor)).await {
+                    Ok(members) => members,
+                    Err(e) => return Some(Err(e)),
+                };
+                if let Err(e) = queue_activity(db, &announce(group, activity, members)).await {
+                    warn!("Failed to announce {} to {}: {}", kind, group.actor_id, e);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Author of an object, from the object or the stored copy
+async fn author(db: &DatabaseManager, object: &Value) -> Option<String> {
+    if let Some(author) = object.get("attributedTo").and_then(Value::as_str) {
+        return Some(author.to_string());
+    }
+    let object = db.find_object_by_id(id_of(Some(object))?).await.ok()??;
+    Some(object.attributed_to)
+}
+
+/// Serve the moderators collection of a group
+pub async fn get_moderators(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let domain = extract_domain_from_headers(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let group = state
+        .db_manager
+        .find_actor_by_username(&username, &domain)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|actor| actor.actor_type == GROUP)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let moderators = moderators(&group);
+    Ok((
+        StatusCode::OK,
+        [("Content-Type", "application/activity+json")],
+        Json(json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "OrderedCollection",
+            "id": moderators_url(&group),
+            "totalItems": moderators.len(),
+            "orderedItems": moderators
+        })),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use oxifed::ActivityType;
+
+    const GROUP_ID: &str = "https://example.com/users/cooking";
+    const MODERATOR: &str = "https://example.com/users/mod";
+    const MEMBER: &str = "https://remote.example/users/alice";
+    const STRANGER: &str = "https://remote.example/users/mallory";
+
+    fn group() -> ActorDocument {
+        let now = Utc::now();
+        ActorDocument {
+            id: None,
+            actor_id: GROUP_ID.to_string(),
+            name: "Cooking".to_string(),
+            preferred_username: "cooking".to_string(),
+            domain: "example.com".to_string(),
+            actor_type: GROUP.to_string(),
+            summary: None,
+            icon: None,
+            image: None,
+            inbox: format!("{}/inbox", GROUP_ID),
+            outbox: format!("{}/outbox", GROUP_ID),
+            following: format!("{}/following", GROUP_ID),
+            followers: format!("{}/followers", GROUP_ID),
+            liked: None,
+            featured: None,
+            public_key: None,
+            endpoints: None,
+            attachment: None,
+            additional_properties: Some(mongodb::bson::doc! { "moderators": [MODERATOR] }),
+            status: ActorStatus::Active,
+            restriction: ActorRestriction::None,
+            created_at: now,
+            updated_at: now,
+            local: true,
+            followers_count: 0,
+            following_count: 0,
+            statuses_count: 0,
+        }
+    }
+
+    fn post(actor: &str, to: &str) -> Value {
+        json!({
+            "type": "Create",
+            "id": format!("{}/activities/1", actor),
+            "actor": actor,
+            "object": {
+                "type": "Note",
+                "id": format!("{}/notes/1", actor),
+                "attributedTo": actor,
+                "to": [to],
+                "content": "Bread"
+            }
+        })
+    }
+
+    fn follow(follower: &str, status: FollowStatus) -> FollowDocument {
+        FollowDocument {
+            id: None,
+            follower: follower.to_string(),
+            following: GROUP_ID.to_string(),
+            status,
+            activity_id: String::new(),
+            accept_activity_id: None,
+            created_at: Utc::now(),
+            responded_at: None,
+            follower_inbox: None,
+            follower_shared_inbox: None,
+        }
+    }
+
+    async fn sent_by_group(db: &DatabaseManager) -> Vec<ActivityType> {
+        db.find_activities_by_actor(GROUP_ID, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|activity| activity.activity_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_moderators() {
+        assert_eq!(moderators(&group()), vec![MODERATOR.to_string()]);
+        assert_eq!(moderators_url(&group()), format!("{}/moderators", GROUP_ID));
+        let unmoderated = ActorDocument {
+            additional_properties: None,
+            ..group()
+        };
+        assert!(moderators(&unmoderated).is_empty());
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert!(addresses(&json!({ "to": GROUP_ID }), GROUP_ID));
+        assert!(addresses(&json!({ "cc": [PUBLIC, GROUP_ID] }), GROUP_ID));
+        assert!(addresses(&json!({ "audience": GROUP_ID }), GROUP_ID));
+        assert!(!addresses(&json!({ "to": [PUBLIC] }), GROUP_ID));
+        assert!(!addresses(&json!({ "bto": [GROUP_ID] }), GROUP_ID));
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_and_unauthorized_activities_are_ignored() {
+        let state = testing::state().await;
+        let group = group();
+
+        // A copy forwarded by someone other than the author
+        let forwarder = VerifiedSigner {
+            key_id: format!("{}#main-key", STRANGER),
+            owner: STRANGER.to_string(),
+        };
+        let activity = post(MEMBER, GROUP_ID);
+        assert!(
+            handle_inbox(&activity, &group, Some(&forwarder), &state)
+                .await
+                .is_none()
+        );
+
+        // A Block by someone who is not a moderator
+        let block = json!({
+            "type": "Block",
+            "actor": STRANGER,
+            "object": MEMBER,
+            "target": GROUP_ID
+        });
+        assert!(handle_inbox(&block, &group, None, &state).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_members_posts_are_announced() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        let group = group();
+        db.insert_follow(follow(MEMBER, FollowStatus::Accepted))
+            .await
+            .unwrap();
+
+        // Posts by non-members and posts not addressed to the group stay put
+        let stranger_post = post(STRANGER, GROUP_ID);
+        assert!(
+            handle_inbox(&stranger_post, &group, None, &state)
+                .await
+                .is_none()
+        );
+        let private_post = post(MEMBER, MEMBER);
+        assert!(
+            handle_inbox(&private_post, &group, None, &state)
+                .await
+                .is_none()
+        );
+        assert!(sent_by_group(db).await.is_empty());
+
+        let member_post = post(MEMBER, GROUP_ID);
+        assert!(
+            handle_inbox(&member_post, &group, None, &state)
+                .await
+                .is_none()
+        );
+        assert_eq!(sent_by_group(db).await, vec![ActivityType::Announce]);
+    }
+
+    #[tokio::test]
+    async fn test_banned_members_cannot_rejoin() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        let group = group();
+        db.insert_follow(follow(MEMBER, FollowStatus::Accepted))
+            .await
+            .unwrap();
+
+        let block = json!({
+            "type": "Block",
+            "id": format!("{}/activities/block", MODERATOR),
+            "actor": MODERATOR,
+            "object": MEMBER,
+            "target": GROUP_ID
+        });
+        assert_eq!(
+            handle_inbox(&block, &group, None, &state).await,
+            Some(Ok(()))
+        );
+        let banned = db.find_follow(MEMBER, GROUP_ID).await.unwrap().unwrap();
+        assert_eq!(banned.status, FollowStatus::Rejected);
+
+        let rejoin = json!({
+            "type": "Follow",
+            "id": format!("{}/activities/follow", MEMBER),
+            "actor": MEMBER,
+            "object": GROUP_ID
+        });
+        assert_eq!(
+            handle_inbox(&rejoin, &group, None, &state).await,
+            Some(Ok(()))
+        );
+        let member_post = post(MEMBER, GROUP_ID);
+        assert!(
+            handle_inbox(&member_post, &group, None, &state)
+                .await
+                .is_none()
+        );
+        let mut sent = sent_by_group(db).await;
+        sent.sort_by_key(|kind| format!("{:?}", kind));
+        assert_eq!(sent, vec![ActivityType::Announce, ActivityType::Reject]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Liveness and readiness checks
+//!
+//! `/healthz` only tells whether the process is serving HTTP. `/readyz`
+//! checks MongoDB, the AMQP connection and, with the media proxy enabled,
+//! the free space left for cached media, and answers 503 while domainservd
+//! cannot serve requests. The same checks answer health requests broadcast
+//! by adminservd.
+
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::StatusCode};
+use deadpool_lapin::Pool;
+use oxifed::database::DatabaseManager;
+use oxifed::health::{ComponentHealth, HealthReport, HealthStatus};
+use oxifed::shutdown::Shutdown;
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::rabbitmq::spawn_channel_task;
+use crate::retention::RetentionStatus;
+
+/// Service name used in health reports
+const SERVICE: &str = "domainservd";
+
+/// Free share of the media storage below which domainservd reports degraded
+const MIN_FREE_MEDIA_STORAGE: f64 = 0.1;
+
+/// Dependencies checked for readiness
+#[derive(Clone)]
+pub struct HealthChecker {
+    db_manager: Arc<DatabaseManager>,
+    mq_pool: Pool,
+    media_proxy_enabled: bool,
+    retention: RetentionStatus,
+}
+
+impl HealthChecker {
+    pub fn new(
+        db_manager: Arc<DatabaseManager>,
+        mq_pool: Pool,
+        media_proxy_enabled: bool,
+        retention: RetentionStatus,
+    ) -> Self {
+        Self {
+            db_manager,
+            mq_pool,
+            media_proxy_enabled,
+            retention,
+        }
+    }
+
+    /// Check every dependency
+    pub async fn check(&self) -> Vec<ComponentHealth> {
+        let mut components = vec![self.check_amqp().await];
+        components.extend(self.check_storage().await);
+        components
+    }
+
+    /// Check MongoDB and the media storage
+    async fn check_storage(&self) -> Vec<ComponentHealth> {
+        let mut components = vec![self.db_manager.health().await];
+
+        if self.media_proxy_enabled {
+            components.push(match self.db_manager.filesystem_usage().await {
+                Ok(Some((used, total))) if total > 0.0 => {
+                    let free = 1.0 - used / total;
+                    let detail = format!("{:.0}% free", free * 100.0);
+                    if free < MIN_FREE_MEDIA_STORAGE {
+                        ComponentHealth::degraded("media_storage", detail)
+                    } else {
+                        ComponentHealth {
+                            detail: Some(detail),
+                            ..ComponentHealth::healthy("media_storage")
+                        }
+                    }
+                }
+                Ok(_) => ComponentHealth::healthy("media_storage"),
+                Err(e) => ComponentHealth::unhealthy("media_storage", e.to_string()),
+            });
+        }
+        components.extend(self.retention.health());
+
+        components
+    }
+
+    async fn check_amqp(&self) -> ComponentHealth {
+        match self.mq_pool.get().await {
+            Ok(conn) if conn.status().connected() => ComponentHealth::healthy("amqp"),
+            Ok(_) => ComponentHealth::unhealthy("amqp", "connection closed"),
+            Err(e) => ComponentHealth::unhealthy("amqp", e.to_string()),
+        }
+    }
+}
+
+/// Answer health requests broadcast over AMQP
+pub fn start_health_responder(pool: Pool, checker: HealthChecker, shutdown: &Shutdown) {
+    spawn_channel_task(
+        "health responder",
+        pool,
+        shutdown,
+        move |channel, shutdown| {
+            let checker = checker.clone();
+            async move {
+                // Requests arrive over AMQP, so only storage needs checking
+                let responder =
+                    oxifed_telemetry::health::serve_health_rpc(channel, SERVICE, move || {
+                        let checker = checker.clone();
+                        async move { checker.check_storage().await }
+                    });
+                if let Some(result) = shutdown.unless_triggered(responder).await {
+                    result?;
+                }
+                Ok(())
+            }
+        },
+    );
+}
+
+/// Liveness: the process is up and serving HTTP
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "service": SERVICE, "status": HealthStatus::Healthy }))
+}
+
+/// Readiness: all dependencies are usable
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let report = HealthReport::new(SERVICE, state.health.check().await);
+    let code = match report.status {
+        HealthStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, Json(report))
+}
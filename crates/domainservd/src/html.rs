@@ -0,0 +1,464 @@
+//! HTML pages for browsers
+//!
+//! Actor and object URLs are also what people share, so requests that
+//! prefer `text/html` over ActivityStreams JSON get a minimal server-rendered
+//! page instead. Pages link back to the JSON with `<link rel="alternate">`.
+//!
+//! Stored content is HTML from clients and remote servers and is not
+//! sanitized, so it is reduced to escaped text here rather than embedded.
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use oxifed::database::{ActorDocument, AttachmentDocument, ObjectDocument, VisibilityLevel};
+
+use crate::AppState;
+use crate::blog;
+
+/// Media types served as ActivityStreams JSON
+const JSON_TYPES: &[&str] = &[
+    "application/activity+json",
+    "application/ld+json",
+    "application/json",
+];
+
+/// Content security policy of rendered pages; they need no scripts
+const CONTENT_SECURITY_POLICY: &str =
+    "default-src 'none'; img-src https: data:; style-src 'unsafe-inline'";
+
+/// Content security policy of form pages
+const FORM_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'; frame-ancestors 'none'";
+
+/// Whether a request prefers HTML over ActivityStreams JSON
+///
+/// Compares the highest quality values of the HTML and JSON media ranges in
+/// the `Accept` header; ties, wildcards and a missing header mean JSON, so
+/// federation fetches are never answered with HTML.
+pub fn prefers_html(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut html: f32 = 0.0;
+    let mut json: f32 = 0.0;
+    for range in accept.split(',') {
+        let mut params = range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|q| q.parse().ok())
+            .unwrap_or(1.0);
+
+        if media_type == "text/html" || media_type == "application/xhtml+xml" {
+            html = html.max(quality);
+        } else if JSON_TYPES.contains(&media_type.as_str()) {
+            json = json.max(quality);
+        }
+    }
+
+    html > 0.0 && html > json
+}
+
+/// Add `Vary: Accept` to a response of a negotiated resource
+pub fn vary_accept(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("Accept"));
+    response
+}
+
+/// Profile page of a local actor
+pub fn actor_page(actor: &ActorDocument, state: &AppState) -> Response {
+    let handle = format!("@{}@{}", actor.preferred_username, actor.domain);
+    let mut body = String::new();
+
+    if let Some(icon) = actor
+        .icon
+        .as_ref()
+        .map(|icon| state.media_proxy.proxy_url(&actor.domain, icon))
+        .filter(|icon| is_http(icon))
+    {
+        body.push_str(&format!(
+            "<img class=\"avatar\" src=\"{}\" alt=\"\" width=\"96\" height=\"96\">\n",
+            escape(&icon)
+        ));
+    }
+    body.push_str(&format!(
+        "<h1>{}</h1>\n<p class=\"handle\">{}</p>\n",
+        escape(display_name(actor)),
+        escape(&handle)
+    ));
+    if let Some(summary) = &actor.summary {
+        body.push_str(&paragraphs(summary));
+    }
+
+    let fields: Vec<(String, String)> = actor
+        .attachment
+        .iter()
+        .flatten()
+        .filter(|field| field.get_str("type").ok() == Some("PropertyValue"))
+        .filter_map(|field| {
+            Some((
+                field.get_str("name").ok()?.to_string(),
+                field.get_str("value").ok()?.to_string(),
+            ))
+        })
+        .collect();
+    if !fields.is_empty() {
+        body.push_str("<dl>\n");
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "<dt>{}</dt><dd>{}</dd>\n",
+                escape(&name),
+                escape(&text_content(&value))
+            ));
+        }
+        body.push_str("</dl>\n");
+    }
+
+    page(
+        &format!("{} ({})", display_name(actor), handle),
+        &actor.actor_id,
+        None,
+        &body,
+    )
+}
+
+/// Whether an object may be seen by everyone
+pub fn is_public(object: &ObjectDocument) -> bool {
+    matches!(
+        object.visibility,
+        VisibilityLevel::Public | VisibilityLevel::Unlisted
+    )
+}
+
+/// Page of a public object
+///
+/// `author` is the attributed actor if it is known locally.
+pub fn object_page(
+    object: &ObjectDocument,
+    author: Option<&ActorDocument>,
+    domain: &str,
+    state: &AppState,
+) -> Response {
+    let author_name = author
+        .map(|actor| {
+            format!(
+                "{} (@{}@{})",
+                display_name(actor),
+                actor.preferred_username,
+                actor.domain
+            )
+        })
+        .unwrap_or_else(|| object.attributed_to.clone());
+    let mut body = if is_http(&object.attributed_to) {
+        format!(
+            "<p class=\"author\"><a href=\"{}\">{}</a></p>\n",
+            escape(&object.attributed_to),
+            escape(&author_name)
+        )
+    } else {
+        format!("<p class=\"author\">{}</p>\n", escape(&author_name))
+    };
+
+    if let Some(image) = &object.image {
+        let url = state.media_proxy.proxy_url(domain, &image.url);
+        if is_http(&url) {
+            body.push_str(&format!(
+                "<img class=\"cover\" src=\"{}\" alt=\"{}\">\n",
+                escape(&url),
+                escape(image.name.as_deref().unwrap_or_default())
+            ));
+        }
+    }
+    if let Some(name) = &object.name {
+        body.push_str(&format!("<h1>{}</h1>\n", escape(name)));
+    }
+    if let Some(summary) = &object.summary {
+        body.push_str(&format!(
+            "<p class=\"summary\"><strong>{}</strong></p>\n",
+            escape(&text_content(summary))
+        ));
+    }
+    if let Some(content) = &object.content {
+        body.push_str(&paragraphs(content));
+    }
+    for attachment in object.attachment.iter().flatten() {
+        body.push_str(&attachment_html(attachment, domain, state));
+    }
+
+    // Hashtags of local articles lead to the archives of their blog
+    let slug = object.article_slug();
+    if slug.is_some() {
+        let hashtags: Vec<String> = object
+            .tag
+            .iter()
+            .flatten()
+            .filter(|tag| tag.tag_type == "Hashtag")
+            .map(|tag| {
+                format!(
+                    "<a href=\"{}\">#{}</a>",
+                    escape(&blog::archive_url(&object.attributed_to, &tag.name)),
+                    escape(tag.name.trim_start_matches('#'))
+                )
+            })
+            .collect();
+        if !hashtags.is_empty() {
+            body.push_str(&format!("<p class=\"tags\">{}</p>\n", hashtags.join(" ")));
+        }
+    }
+
+    body.push_str(&published_html(object));
+
+    let title = object.name.as_deref().unwrap_or(&author_name);
+    let canonical = slug.and(object.url.as_deref());
+    page(title, &object.object_id, canonical, &body)
+}
+
+/// Blog index of a local actor, or their archive of a hashtag
+///
+/// Lists the title, publication time and summary of the articles on page
+/// `number`, linking to the neighbouring pages.
+pub fn article_index_page(
+    author: &ActorDocument,
+    hashtag: Option<&str>,
+    articles: &[ObjectDocument],
+    number: u64,
+    has_next: bool,
+    collection_id: &str,
+) -> Response {
+    let title = match hashtag {
+        Some(hashtag) => format!("#{} – {}", hashtag, display_name(author)),
+        None => display_name(author).to_string(),
+    };
+    let mut body = format!(
+        "<h1>{}</h1>\n<p class=\"handle\"><a href=\"{}\">@{}@{}</a></p>\n",
+        escape(&title),
+        escape(&author.actor_id),
+        escape(&author.preferred_username),
+        escape(&author.domain)
+    );
+    if articles.is_empty() {
+        body.push_str("<p>No articles yet.</p>\n");
+    }
+    for article in articles {
+        let href = article.url.as_deref().unwrap_or(&article.object_id);
+        body.push_str(&format!(
+            "<article>\n<h2><a href=\"{}\">{}</a></h2>\n",
+            escape(href),
+            escape(article.name.as_deref().unwrap_or("Untitled"))
+        ));
+        body.push_str(&published_html(article));
+        if let Some(summary) = &article.summary {
+            body.push_str(&format!(
+                "<p class=\"summary\">{}</p>\n",
+                escape(&text_content(summary))
+            ));
+        }
+        body.push_str("</article>\n");
+    }
+
+    let mut pages = Vec::new();
+    if number > 1 {
+        pages.push(format!("<a href=\"?page={}\">Newer</a>", number - 1));
+    }
+    if has_next {
+        pages.push(format!("<a href=\"?page={}\">Older</a>", number + 1));
+    }
+    if !pages.is_empty() {
+        body.push_str(&format!("<nav>{}</nav>\n", pages.join(" ")));
+    }
+
+    page(&title, collection_id, None, &body)
+}
+
+/// Publication time of an object
+fn published_html(object: &ObjectDocument) -> String {
+    let published = object.published.unwrap_or(object.created_at);
+    format!(
+        "<p class=\"published\"><time datetime=\"{}\">{}</time></p>\n",
+        published.to_rfc3339(),
+        published.format("%Y-%m-%d %H:%M UTC")
+    )
+}
+
+fn attachment_html(attachment: &AttachmentDocument, domain: &str, state: &AppState) -> String {
+    let url = state.media_proxy.proxy_url(domain, &attachment.url);
+    if !is_http(&url) {
+        return String::new();
+    }
+    let url = escape(&url);
+    let description = escape(attachment.name.as_deref().unwrap_or_default());
+    let is_image = attachment
+        .media_type
+        .as_deref()
+        .is_some_and(|media_type| media_type.starts_with("image/"));
+
+    if is_image {
+        format!("<p><a href=\"{url}\"><img src=\"{url}\" alt=\"{description}\"></a></p>\n")
+    } else {
+        let label = if description.is_empty() {
+            url.clone()
+        } else {
+            description
+        };
+        format!("<p><a href=\"{url}\">{label}</a></p>\n")
+    }
+}
+
+/// Whether a URL is safe to link to
+fn is_http(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Name an actor is shown with
+pub(crate) fn display_name(actor: &ActorDocument) -> &str {
+    if actor.name.is_empty() {
+        &actor.preferred_username
+    } else {
+        &actor.name
+    }
+}
+
+/// Complete HTML page linking back to the JSON representation at `id`
+///
+/// Pages with several URLs name the one to share as `canonical`.
+fn page(title: &str, id: &str, canonical: Option<&str>, body: &str) -> Response {
+    let canonical = canonical
+        .map(|url| format!("<link rel=\"canonical\" href=\"{}\">\n", escape(url)))
+        .unwrap_or_default();
+    let html = format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n\
+         <link rel=\"alternate\" type=\"application/activity+json\" href=\"{id}\">\n\
+         {canonical}\
+         <style>body {{ max-width: 40em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; line-height: 1.5; }} img {{ max-width: 100%; }} .avatar {{ border-radius: 8px; }} .handle, .published {{ color: #666; }}</style>\n\
+         </head>\n\
+         <body>\n\
+         {body}\
+         </body>\n\
+         </html>\n",
+        title = escape(title),
+        id = escape(id),
+        canonical = canonical,
+        body = body,
+    );
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY),
+        ],
+        html,
+    )
+        .into_response()
+}
+
+/// Complete HTML page of a form, such as the OAuth authorization page
+///
+/// Forms may only be submitted to this server and the page must not be
+/// framed, so other sites cannot trick users into submitting it.
+pub fn form_page(status: StatusCode, title: &str, body: &str) -> Response {
+    let html = format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n\
+         <style>body {{ max-width: 40em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; line-height: 1.5; }} label {{ display: block; margin: 1em 0; }}</style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>{title}</h1>\n\
+         {body}\
+         </body>\n\
+         </html>\n",
+        title = escape(title),
+        body = body,
+    );
+
+    (
+        status,
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (
+                header::CONTENT_SECURITY_POLICY,
+                FORM_CONTENT_SECURITY_POLICY,
+            ),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        html,
+    )
+        .into_response()
+}
+
+/// Render HTML content as escaped paragraphs
+fn paragraphs(html: &str) -> String {
+    text_paragraphs(&text_content(html))
+}
+
+/// Render plain text as escaped paragraphs, split at blank lines
+pub(crate) fn text_paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>\n", escape(paragraph).replace('\n', "<br>")))
+        .collect()
+}
+
+/// Text of an HTML fragment
+///
+/// Drops all tags, keeping paragraph and line breaks as newlines, and
+/// decodes the common character references.
+pub(crate) fn text_content(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_ascii_lowercase();
+        if tag == "/p" {
+            text.push_str("\n\n");
+        } else if tag.starts_with("br") {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Escape text for HTML element content and attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
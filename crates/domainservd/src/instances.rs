@@ -0,0 +1,178 @@
+//! Registry of remote instances
+//!
+//! Inbox requests mark the host of their sender as seen in the `instances`
+//! collection, which publisherd also updates with the outcome of every
+//! delivery. The registry is served as the peer list of the instance and,
+//! through the instance RPC, to the federation views of the admin API.
+
+use std::time::Duration;
+
+use axum::{Json, extract::State, http::StatusCode};
+use moka::sync::Cache;
+use oxifed::database::{DatabaseManager, DomainBlockDocument, InstanceDocument};
+use oxifed::messaging::{
+    InstanceInfo, InstanceRpcRequest, InstanceRpcRequestType, InstanceRpcResponse, Page,
+    PageRequest,
+};
+use tracing::{error, warn};
+
+use crate::AppState;
+
+/// Most instances returned by one listing
+const MAX_LIMIT: u32 = 200;
+
+/// How long after recording a host as seen further activities of it are not
+/// recorded again
+const SEEN_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Hosts recently recorded as seen
+#[derive(Clone)]
+pub struct SeenInstances {
+    hosts: Cache<String, ()>,
+}
+
+impl Default for SeenInstances {
+    fn default() -> Self {
+        Self {
+            hosts: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(SEEN_INTERVAL)
+                .build(),
+        }
+    }
+}
+
+/// Record the host of `sender` as seen, at most once per [`SEEN_INTERVAL`]
+///
+/// Hosts served by this instance are left out. Recording happens in the
+/// background so it does not hold up the inbox request.
+pub fn record_sender(state: &AppState, sender: &str) {
+    let Some(host) = url::Url::parse(sender)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+    else {
+        return;
+    };
+    if state.seen_instances.hosts.contains_key(&host) {
+        return;
+    }
+    state.seen_instances.hosts.insert(host.clone(), ());
+
+    let db = state.db_manager.clone();
+    tokio::spawn(async move {
+        match db.find_domain_by_name(&host).await {
+            Ok(Some(_)) => return,
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Failed to look up domain {}: {}", host, e);
+                return;
+            }
+        }
+        if let Err(e) = db.record_instance_seen(&host).await {
+            error!("Failed to record instance {} as seen: {}", host, e);
+        }
+    });
+}
+
+/// Serve the domains of the instances we federate with
+///
+/// Suspended domains are left out, like Mastodon does for its
+/// `/api/v1/instance/peers`.
+pub async fn get_peers(State(state): State<AppState>) -> Result<Json<Vec<String>>, StatusCode> {
+    match state.db_manager.find_peer_domains().await {
+        Ok(domains) => Ok(Json(domains)),
+        Err(e) => {
+            error!("Database error listing peers: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Answer an instance RPC request
+pub async fn handle_rpc(db: &DatabaseManager, request: InstanceRpcRequest) -> InstanceRpcResponse {
+    let request_id = request.request_id;
+    match request.request_type {
+        InstanceRpcRequestType::ListInstances { failing, page } => {
+            let page = PageRequest {
+                limit: page.limit.clamp(1, MAX_LIMIT),
+                ..page
+            };
+            let (instances, total) = match db
+                .find_instances_page(failing, page.offset, i64::from(page.limit))
+                .await
+            {
+                Ok(found) => found,
+                Err(e) => {
+                    return InstanceRpcResponse::error(
+                        request_id,
+                        format!("Failed to list instances: {}", e),
+                    );
+                }
+            };
+            let domains: Vec<String> = instances.iter().map(|i| i.domain.clone()).collect();
+            match db.find_domain_blocks(&domains).await {
+                Ok(blocks) => InstanceRpcResponse::instance_list(
+                    request_id,
+                    Page::new(instances, total, page).map(|instance| {
+                        let block = blocks.get(&instance.domain);
+                        instance_info(instance, block)
+                    }),
+                ),
+                Err(e) => InstanceRpcResponse::error(
+                    request_id,
+                    format!("Failed to list domain blocks: {}", e),
+                ),
+            }
+        }
+        InstanceRpcRequestType::GetInstance { domain } => {
+            let domain = domain.to_lowercase();
+            let instance = match db.find_instance(&domain).await {
+                Ok(instance) => instance,
+                Err(e) => {
+                    return InstanceRpcResponse::error(
+                        request_id,
+                        format!("Failed to find instance: {}", e),
+                    );
+                }
+            };
+            match db.find_domain_blocks(std::slice::from_ref(&domain)).await {
+                Ok(blocks) => InstanceRpcResponse::instance_details(
+                    request_id,
+                    instance.map(|instance| instance_info(instance, blocks.get(&domain))),
+                ),
+                Err(e) => InstanceRpcResponse::error(
+                    request_id,
+                    format!("Failed to find domain block: {}", e),
+                ),
+            }
+        }
+        InstanceRpcRequestType::GetStats => match db.federation_stats().await {
+            Ok(stats) => InstanceRpcResponse::stats(request_id, stats),
+            Err(e) => {
+                InstanceRpcResponse::error(request_id, format!("Failed to count instances: {}", e))
+            }
+        },
+    }
+}
+
+fn instance_info(instance: InstanceDocument, block: Option<&DomainBlockDocument>) -> InstanceInfo {
+    InstanceInfo {
+        domain: instance.domain,
+        first_seen_at: instance.first_seen_at.to_rfc3339(),
+        last_seen_at: instance.last_seen_at.map(|at| at.to_rfc3339()),
+        software: instance.software,
+        version: instance.version,
+        open_registrations: instance.open_registrations,
+        crawled_at: instance.crawled_at.map(|at| at.to_rfc3339()),
+        crawl_error: instance.crawl_error,
+        last_delivery_at: instance.last_delivery_at.map(|at| at.to_rfc3339()),
+        last_failure_at: instance.last_failure_at.map(|at| at.to_rfc3339()),
+        last_error: instance.last_error,
+        consecutive_failures: instance.consecutive_failures,
+        block: block.and_then(|block| {
+            serde_json::to_value(&block.severity)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+        }),
+    }
+}
@@ -0,0 +1,481 @@
+//! Lists of followed accounts
+//!
+//! Mastodon-compatible clients manage a user's lists at `/api/v1/lists`
+//! and read the posts of a list's members at
+//! `/api/v1/timelines/list/{id}`. Only accounts the user follows can be
+//! added; reading needs the `read` scope, changes need `write`. The user's
+//! filters of the `home` context apply to list timelines. Lists are
+//! stored in the `lists` collection; members of `exclusive` lists are kept
+//! off the home timeline.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, Query, RawQuery, State},
+    http::HeaderMap,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::Utc;
+use oxifed::database::{
+    ActorDocument, FilterContext, FollowStatus, ListDocument, ListRepliesPolicy, TimelineItem,
+};
+use serde_json::{Value, json};
+use tracing::info;
+
+use crate::AppState;
+use crate::filters::filtered_statuses;
+use crate::mastodon::{
+    ApiError, account_json, api_token, page_links, parse_id, timeline_page, timeline_response,
+};
+use crate::oauth::request_params;
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+/// Longest list title, in characters
+const MAX_TITLE_LENGTH: usize = 256;
+
+/// Routes of the list endpoints
+pub fn lists_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/lists", get(get_lists).post(create_list))
+        .route(
+            "/api/v1/lists/{id}",
+            get(get_list).put(update_list).delete(delete_list),
+        )
+        .route(
+            "/api/v1/lists/{id}/accounts",
+            get(get_list_accounts)
+                .post(add_list_accounts)
+                .delete(remove_list_accounts),
+        )
+        .route("/api/v1/accounts/{id}/lists", get(get_account_lists))
+        .route("/api/v1/timelines/list/{id}", get(get_list_timeline))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Mastodon list entity
+fn list_json(list: &ListDocument) -> Value {
+    json!({
+        "id": list.id.map(|id| id.to_hex()),
+        "title": list.title,
+        "replies_policy": list.replies_policy,
+        "exclusive": list.exclusive,
+    })
+}
+
+/// Settings of a list given in a request body
+#[derive(Debug, Default, PartialEq)]
+struct ListParams {
+    title: Option<String>,
+    replies_policy: Option<ListRepliesPolicy>,
+    exclusive: Option<bool>,
+}
+
+fn list_params(params: &HashMap<String, String>) -> Result<ListParams, ApiError> {
+    let title = match params.get("title").map(|title| title.trim()) {
+        Some("") => return Err(ApiError::invalid("Validation failed: Title can't be blank")),
+        Some(title) if title.chars().count() > MAX_TITLE_LENGTH => {
+            return Err(ApiError::invalid("Validation failed: Title is too long"));
+        }
+        title => title.map(str::to_string),
+    };
+    let replies_policy = params
+        .get("replies_policy")
+        .map(|policy| {
+            serde_json::from_value(Value::String(policy.clone()))
+                .map_err(|_| ApiError::invalid("replies_policy must be followed, list or none"))
+        })
+        .transpose()?;
+    let exclusive = params
+        .get("exclusive")
+        .map(|exclusive| match exclusive.as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(ApiError::invalid("exclusive must be a boolean")),
+        })
+        .transpose()?;
+    Ok(ListParams {
+        title,
+        replies_policy,
+        exclusive,
+    })
+}
+
+/// Account IDs given as `account_ids[]` in the query or a form body, or as
+/// an `account_ids` array in a JSON body
+fn account_ids_param(query: Option<&str>, headers: &HeaderMap, body: &Bytes) -> Vec<String> {
+    let is_account_ids = |key: &str| key == "account_ids[]" || key == "account_ids";
+    let mut ids: Vec<String> = query
+        .map(str::as_bytes)
+        .into_iter()
+        .chain(std::iter::once(body.as_ref()))
+        .flat_map(url::form_urlencoded::parse)
+        .filter(|(key, _)| is_account_ids(key))
+        .map(|(_, value)| value.into_owned())
+        .collect();
+    if ids.is_empty()
+        && let Ok(params) = request_params(headers, body)
+        && let Some(joined) = params.get("account_ids")
+    {
+        ids = joined.split_whitespace().map(str::to_string).collect();
+    }
+    ids
+}
+
+/// Actor of the requesting user
+async fn owner(state: &AppState, username: &str, domain: &str) -> Result<ActorDocument, ApiError> {
+    state
+        .db_manager
+        .find_actor_by_username(username, domain)
+        .await?
+        .ok_or_else(ApiError::not_found)
+}
+
+async fn get_lists(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let lists = state
+            .db_manager
+            .find_lists(&token.username, &token.domain)
+            .await?;
+        Ok::<_, ApiError>(Value::Array(lists.iter().map(list_json).collect()))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn get_list(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let list = state
+            .db_manager
+            .find_list(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(list_json(&list))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn create_list(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let params = request_params(&headers, &body).map_err(ApiError::invalid)?;
+        let ListParams {
+            title,
+            replies_policy,
+            exclusive,
+        } = list_params(&params)?;
+        let title =
+            title.ok_or_else(|| ApiError::invalid("Validation failed: Title can't be blank"))?;
+        let now = Utc::now();
+        let list = state
+            .db_manager
+            .insert_list(ListDocument {
+                id: None,
+                username: token.username.clone(),
+                domain: token.domain.clone(),
+                title,
+                replies_policy: replies_policy.unwrap_or_default(),
+                exclusive: exclusive.unwrap_or(false),
+                accounts: Vec::new(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+        info!(
+            "{}@{} created list {:?}",
+            token.username, token.domain, list.title
+        );
+        Ok::<_, ApiError>(list_json(&list))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn update_list(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let params = request_params(&headers, &body).map_err(ApiError::invalid)?;
+        let settings = list_params(&params)?;
+        let list = state
+            .db_manager
+            .update_list(
+                &token.username,
+                &token.domain,
+                parse_id(&id)?,
+                settings.title.as_deref(),
+                settings.replies_policy,
+                settings.exclusive,
+            )
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        Ok::<_, ApiError>(list_json(&list))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn delete_list(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let deleted = state
+            .db_manager
+            .delete_list(&token.username, &token.domain, parse_id(&id)?)
+            .await?;
+        if !deleted {
+            return Err(ApiError::not_found());
+        }
+        Ok(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Members of a list that are stored, in the order they were added
+async fn get_list_accounts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let list = state
+            .db_manager
+            .find_list(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        let actors: HashMap<String, ActorDocument> = state
+            .db_manager
+            .find_actors_by_ids(&list.accounts)
+            .await?
+            .into_iter()
+            .map(|actor| (actor.actor_id.clone(), actor))
+            .collect();
+        let accounts = list
+            .accounts
+            .iter()
+            .filter_map(|account| actors.get(account))
+            .map(account_json)
+            .collect();
+        Ok::<_, ApiError>(Value::Array(accounts))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Add followed accounts to a list
+async fn add_list_accounts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let id = parse_id(&id)?;
+        let actors = requested_accounts(&state, query.as_deref(), &headers, &body).await?;
+        let owner = owner(&state, &token.username, &token.domain).await?;
+        for actor in &actors {
+            let followed = state
+                .db_manager
+                .find_follow(&owner.actor_id, &actor.actor_id)
+                .await?
+                .is_some_and(|follow| follow.status == FollowStatus::Accepted);
+            if !followed {
+                return Err(ApiError::invalid(format!(
+                    "You must follow {} to add them to a list",
+                    actor.actor_id
+                )));
+            }
+        }
+
+        let actor_ids: Vec<String> = actors.into_iter().map(|actor| actor.actor_id).collect();
+        if !state
+            .db_manager
+            .add_list_accounts(&token.username, &token.domain, id, &actor_ids)
+            .await?
+        {
+            return Err(ApiError::not_found());
+        }
+        Ok(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Remove accounts from a list
+async fn remove_list_accounts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "write", &state).await?;
+        let id = parse_id(&id)?;
+        let actors = requested_accounts(&state, query.as_deref(), &headers, &body).await?;
+        let actor_ids: Vec<String> = actors.into_iter().map(|actor| actor.actor_id).collect();
+        if !state
+            .db_manager
+            .remove_list_accounts(&token.username, &token.domain, id, &actor_ids)
+            .await?
+        {
+            return Err(ApiError::not_found());
+        }
+        Ok(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Actors of the account IDs of a request, all of which must be stored
+async fn requested_accounts(
+    state: &AppState,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<Vec<ActorDocument>, ApiError> {
+    let ids = account_ids_param(query, headers, body)
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    if ids.is_empty() {
+        return Err(ApiError::invalid("account_ids is required"));
+    }
+    let actors = state.db_manager.find_actors_by_storage_ids(&ids).await?;
+    if actors.len() < ids.len() {
+        return Err(ApiError::not_found());
+    }
+    Ok(actors)
+}
+
+/// Lists of the requesting user that contain an account
+async fn get_account_lists(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let actor = state
+            .db_manager
+            .find_actors_by_storage_ids(&[parse_id(&id)?])
+            .await?
+            .pop()
+            .ok_or_else(ApiError::not_found)?;
+        let lists = state
+            .db_manager
+            .find_lists_with_account(&token.username, &token.domain, &actor.actor_id)
+            .await?;
+        Ok::<_, ApiError>(Value::Array(lists.iter().map(list_json).collect()))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Posts of a list's members, newest first
+async fn get_list_timeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let result = async {
+        let token = api_token(&headers, "read", &state).await?;
+        let list = state
+            .db_manager
+            .find_list(&token.username, &token.domain, parse_id(&id)?)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        let owner = owner(&state, &token.username, &token.domain).await?;
+        let items: Vec<TimelineItem> = state
+            .db_manager
+            .get_list_timeline(&list, &owner.actor_id, &timeline_page(&query))
+            .await?
+            .into_iter()
+            .map(TimelineItem::Post)
+            .collect();
+        let url = format!("https://{}/api/v1/timelines/list/{}", token.domain, id);
+        let links = page_links(&url, &items);
+        let statuses = filtered_statuses(
+            &state.db_manager,
+            &token.username,
+            &token.domain,
+            FilterContext::Home,
+            items,
+        )
+        .await?;
+        Ok((links, statuses))
+    }
+    .await;
+    timeline_response(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    #[test]
+    fn test_account_ids_param() {
+        let form = Bytes::from("account_ids[]=a1&account_ids[]=b2&title=x");
+        assert_eq!(
+            account_ids_param(None, &HeaderMap::new(), &form),
+            vec!["a1", "b2"]
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let json = Bytes::from(r#"{"account_ids": ["a1", "b2"]}"#);
+        assert_eq!(account_ids_param(None, &headers, &json), vec!["a1", "b2"]);
+
+        assert_eq!(
+            account_ids_param(Some("account_ids[]=c3"), &HeaderMap::new(), &Bytes::new()),
+            vec!["c3"]
+        );
+    }
+
+    #[test]
+    fn test_list_params() {
+        let params = HashMap::from([
+            ("title".to_string(), " Friends ".to_string()),
+            ("replies_policy".to_string(), "followed".to_string()),
+        ]);
+        assert_eq!(
+            list_params(&params).unwrap(),
+            ListParams {
+                title: Some("Friends".to_string()),
+                replies_policy: Some(ListRepliesPolicy::Followed),
+                exclusive: None,
+            }
+        );
+
+        let blank = HashMap::from([("title".to_string(), "  ".to_string())]);
+        assert!(list_params(&blank).is_err());
+        let policy = HashMap::from([("replies_policy".to_string(), "all".to_string())]);
+        assert!(list_params(&policy).is_err());
+    }
+}
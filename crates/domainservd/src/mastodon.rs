@@ -0,0 +1,529 @@
+//! Entities of the Mastodon client API
+//!
+//! Mastodon-compatible clients read accounts and statuses in Mastodon's
+//! JSON shapes. Their IDs are the storage IDs of the actor, object and
+//! boost documents, which sort by creation like Mastodon's own IDs, so timelines
+//! page with `max_id`, `since_id` and `min_id` and announce the neighbouring
+//! pages in a `Link` header.
+
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use mongodb::bson::{Document, oid::ObjectId};
+use oxifed::ObjectType;
+use oxifed::database::{
+    AccessTokenDocument, ActorDocument, AttachmentDocument, BoostDocument, DatabaseError,
+    DatabaseManager, ObjectDocument, TimelineItem, TimelinePage, VisibilityLevel,
+};
+use serde_json::{Value, json};
+use tracing::error;
+
+use crate::AppState;
+use crate::html::display_name;
+use crate::oauth::authenticated_token;
+
+/// Statuses in a timeline page unless the client asks for fewer
+const DEFAULT_PAGE_LIMIT: i64 = 20;
+
+/// Most statuses in a timeline page
+const MAX_PAGE_LIMIT: i64 = 40;
+
+/// Client API failure answered as `{"error": ...}`
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    pub(crate) fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "Record not found")
+    }
+
+    pub(crate) fn server_error(e: impl std::fmt::Display) -> Self {
+        error!("Client API request failed: {}", e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(e: DatabaseError) -> Self {
+        Self::server_error(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Token of the request if it grants `scope` on the requested domain
+pub(crate) async fn api_token(
+    headers: &HeaderMap,
+    scope: &str,
+    state: &AppState,
+) -> Result<AccessTokenDocument, ApiError> {
+    authenticated_token(headers, scope, state)
+        .await
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "The access token is invalid"))
+}
+
+/// Storage ID of an entity in a request path; unknown when malformed
+pub(crate) fn parse_id(id: &str) -> Result<ObjectId, ApiError> {
+    ObjectId::parse_str(id).map_err(|_| ApiError::not_found())
+}
+
+/// Timeline page requested by the `max_id`, `since_id`, `min_id` and
+/// `limit` query parameters
+///
+/// Malformed IDs are ignored; the limit is capped at 40.
+pub(crate) fn timeline_page(query: &HashMap<String, String>) -> TimelinePage {
+    let id = |name: &str| query.get(name).and_then(|id| ObjectId::parse_str(id).ok());
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT);
+    TimelinePage {
+        max_id: id("max_id"),
+        since_id: id("since_id"),
+        min_id: id("min_id"),
+        limit,
+    }
+}
+
+/// `Link` header pointing to the older (`next`) and newer (`prev`) pages
+/// around timeline items listed newest first
+pub(crate) fn page_links(url: &str, items: &[TimelineItem]) -> Option<HeaderValue> {
+    let newest = items.first()?.id()?;
+    let oldest = items.last()?.id()?;
+    HeaderValue::from_str(&format!(
+        "<{url}?max_id={oldest}>; rel=\"next\", <{url}?min_id={newest}>; rel=\"prev\""
+    ))
+    .ok()
+}
+
+/// Statuses of a timeline page, with the `Link` header to its neighbours
+pub(crate) fn timeline_response(
+    result: Result<(Option<HeaderValue>, Vec<Value>), ApiError>,
+) -> Response {
+    match result {
+        Ok((Some(links), statuses)) => {
+            ([(header::LINK, links)], Json(Value::Array(statuses))).into_response()
+        }
+        Ok((None, statuses)) => Json(Value::Array(statuses)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Mastodon account of an actor
+pub(crate) fn account_json(actor: &ActorDocument) -> Value {
+    let acct = if actor.local {
+        actor.preferred_username.clone()
+    } else {
+        format!("{}@{}", actor.preferred_username, actor.domain)
+    };
+    let fields: Vec<Value> = actor
+        .attachment
+        .iter()
+        .flatten()
+        .filter(|field| field.get_str("type") == Ok("PropertyValue"))
+        .filter_map(|field| {
+            Some(json!({
+                "name": field.get_str("name").ok()?,
+                "value": field.get_str("value").ok()?,
+                "verified_at": null,
+            }))
+        })
+        .collect();
+    let avatar = actor.icon.clone().unwrap_or_default();
+    let header = actor.image.clone().unwrap_or_default();
+    json!({
+        "id": actor.id.map(|id| id.to_hex()),
+        "username": actor.preferred_username,
+        "acct": acct,
+        "display_name": display_name(actor),
+        "locked": actor
+            .additional_properties
+            .as_ref()
+            .and_then(|props| props.get_bool("manuallyApprovesFollowers").ok())
+            .unwrap_or(false),
+        "bot": matches!(actor.actor_type.as_str(), "Service" | "Application"),
+        "group": actor.actor_type == "Group",
+        "discoverable": actor.discoverable(),
+        "indexable": actor.indexable(),
+        "created_at": actor.created_at.to_rfc3339(),
+        "note": actor.summary.clone().unwrap_or_default(),
+        "url": actor.actor_id,
+        "uri": actor.actor_id,
+        "avatar": avatar,
+        "avatar_static": avatar,
+        "header": header,
+        "header_static": header,
+        "followers_count": actor.followers_count,
+        "following_count": actor.following_count,
+        "statuses_count": actor.statuses_count,
+        "emojis": [],
+        "fields": fields,
+    })
+}
+
+/// Mastodon statuses of objects, in the same order
+///
+/// Authors, mentioned accounts, replied-to and quoted posts are looked up
+/// once for all objects; posts of authors that are not stored are left out.
+pub(crate) async fn statuses_json(
+    db: &DatabaseManager,
+    objects: &[ObjectDocument],
+) -> Result<Vec<Value>, DatabaseError> {
+    let parents = objects_by_id(
+        db,
+        objects
+            .iter()
+            .filter_map(|object| object.in_reply_to.clone()),
+    )
+    .await?;
+    let quoted =
+        objects_by_id(db, objects.iter().filter_map(|object| object.quote.clone())).await?;
+
+    let mut actor_ids: Vec<String> = objects
+        .iter()
+        .flat_map(|object| {
+            let mentions = object
+                .tag
+                .iter()
+                .flatten()
+                .filter(|tag| tag.tag_type == "Mention")
+                .filter_map(|tag| tag.href.clone());
+            std::iter::once(object.attributed_to.clone()).chain(mentions)
+        })
+        .chain(parents.values().map(|parent| parent.attributed_to.clone()))
+        .chain(quoted.values().map(|quoted| quoted.attributed_to.clone()))
+        .collect();
+    actor_ids.sort();
+    actor_ids.dedup();
+    let actors: HashMap<String, ActorDocument> = db
+        .find_actors_by_ids(&actor_ids)
+        .await?
+        .into_iter()
+        .map(|actor| (actor.actor_id.clone(), actor))
+        .collect();
+
+    Ok(objects
+        .iter()
+        .filter_map(|object| {
+            let author = actors.get(&object.attributed_to)?;
+            let parent = object.in_reply_to.as_ref().and_then(|id| parents.get(id));
+            let mut status = status_json(object, author, parent, &actors);
+            if let Some(quoted) = object.quote.as_ref().and_then(|id| quoted.get(id)) {
+                status["quote"] = quote_json(quoted, &parents, &actors);
+            }
+            Some(status)
+        })
+        .collect())
+}
+
+/// Stored objects with the given IDs, by ID
+async fn objects_by_id(
+    db: &DatabaseManager,
+    ids: impl Iterator<Item = String>,
+) -> Result<HashMap<String, ObjectDocument>, DatabaseError> {
+    let ids: Vec<String> = ids.collect();
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    Ok(db
+        .find_objects_by_ids(&ids)
+        .await?
+        .into_iter()
+        .map(|object| (object.object_id.clone(), object))
+        .collect())
+}
+
+/// Mastodon quote of a quoted post
+///
+/// Only public and unlisted posts are embedded, since the quote is shown to
+/// whoever may see the quoting post.
+fn quote_json(
+    quoted: &ObjectDocument,
+    parents: &HashMap<String, ObjectDocument>,
+    actors: &HashMap<String, ActorDocument>,
+) -> Value {
+    if quoted.object_type == ObjectType::Tombstone {
+        return json!({ "state": "deleted", "quoted_status": null });
+    }
+    let author = actors.get(&quoted.attributed_to);
+    match author {
+        Some(author)
+            if matches!(
+                quoted.visibility,
+                VisibilityLevel::Public | VisibilityLevel::Unlisted
+            ) =>
+        {
+            let parent = quoted.in_reply_to.as_ref().and_then(|id| parents.get(id));
+            json!({
+                "state": "accepted",
+                "quoted_status": status_json(quoted, author, parent, actors)
+            })
+        }
+        _ => json!({ "state": "unauthorized", "quoted_status": null }),
+    }
+}
+
+/// Mastodon statuses of timeline items, in the same order
+///
+/// A boost is a status of the booster whose `reblog` is the boosted post.
+pub(crate) async fn timeline_json(
+    db: &DatabaseManager,
+    items: &[TimelineItem],
+) -> Result<Vec<Value>, DatabaseError> {
+    let posts: Vec<ObjectDocument> = items.iter().map(|item| item.post().clone()).collect();
+    let statuses: HashMap<String, Value> = statuses_json(db, &posts)
+        .await?
+        .into_iter()
+        .filter_map(|status| Some((status.get("uri")?.as_str()?.to_string(), status)))
+        .collect();
+
+    let booster_ids: Vec<String> = items
+        .iter()
+        .filter_map(|item| match item {
+            TimelineItem::Boost { boost, .. } => Some(boost.actor.clone()),
+            TimelineItem::Post(_) => None,
+        })
+        .collect();
+    let boosters: HashMap<String, ActorDocument> = if booster_ids.is_empty() {
+        HashMap::new()
+    } else {
+        db.find_actors_by_ids(&booster_ids)
+            .await?
+            .into_iter()
+            .map(|actor| (actor.actor_id.clone(), actor))
+            .collect()
+    };
+
+    Ok(items
+        .iter()
+        .filter_map(|item| {
+            let status = statuses.get(&item.post().object_id)?;
+            match item {
+                TimelineItem::Post(_) => Some(status.clone()),
+                TimelineItem::Boost { boost, .. } => {
+                    Some(reblog_json(boost, boosters.get(&boost.actor)?, status))
+                }
+            }
+        })
+        .collect())
+}
+
+/// Status of a boost wrapping the boosted status
+fn reblog_json(boost: &BoostDocument, booster: &ActorDocument, status: &Value) -> Value {
+    json!({
+        "id": boost.id.map(|id| id.to_hex()),
+        "uri": boost.activity_id,
+        "url": boost.activity_id,
+        "created_at": boost.published.to_rfc3339(),
+        "edited_at": null,
+        "account": account_json(booster),
+        "content": "",
+        "visibility": visibility_name(&boost.visibility),
+        "sensitive": false,
+        "spoiler_text": "",
+        "media_attachments": [],
+        "mentions": [],
+        "tags": [],
+        "emojis": [],
+        "in_reply_to_id": null,
+        "in_reply_to_account_id": null,
+        "language": null,
+        "replies_count": 0,
+        "reblogs_count": 0,
+        "favourites_count": 0,
+        "quotes_count": 0,
+        "quote": null,
+        "reblog": status,
+        "poll": null,
+        "card": null,
+    })
+}
+
+fn status_json(
+    object: &ObjectDocument,
+    author: &ActorDocument,
+    parent: Option<&ObjectDocument>,
+    actors: &HashMap<String, ActorDocument>,
+) -> Value {
+    let storage_id = |id: Option<ObjectId>| id.map(|id| id.to_hex());
+    let tags = object.tag.iter().flatten();
+    let mentions: Vec<Value> = tags
+        .clone()
+        .filter(|tag| tag.tag_type == "Mention")
+        .filter_map(|tag| {
+            let actor = actors.get(tag.href.as_ref()?)?;
+            let account = account_json(actor);
+            Some(json!({
+                "id": account["id"],
+                "username": account["username"],
+                "acct": account["acct"],
+                "url": account["url"],
+            }))
+        })
+        .collect();
+    let hashtags: Vec<Value> = tags
+        .filter(|tag| tag.tag_type == "Hashtag")
+        .map(|tag| {
+            let name = tag.name.trim_start_matches('#');
+            json!({ "name": name, "url": tag.href.clone().unwrap_or_default() })
+        })
+        .collect();
+    let media: Vec<Value> = object
+        .attachment
+        .iter()
+        .flatten()
+        .map(attachment_json)
+        .collect();
+
+    json!({
+        "id": storage_id(object.id),
+        "uri": object.object_id,
+        "url": object.url.clone().unwrap_or_else(|| object.object_id.clone()),
+        "created_at": object.published.unwrap_or(object.created_at).to_rfc3339(),
+        "edited_at": object.updated.map(|updated| updated.to_rfc3339()),
+        "account": account_json(author),
+        "content": object.content.clone().unwrap_or_default(),
+        "visibility": visibility_name(&object.visibility),
+        "sensitive": object.sensitive.unwrap_or(false),
+        "spoiler_text": object.summary.clone().unwrap_or_default(),
+        "media_attachments": media,
+        "mentions": mentions,
+        "tags": hashtags,
+        "emojis": [],
+        "in_reply_to_id": parent.and_then(|parent| storage_id(parent.id)),
+        "in_reply_to_account_id": parent
+            .and_then(|parent| actors.get(&parent.attributed_to))
+            .and_then(|actor| storage_id(actor.id)),
+        "language": object.language,
+        "replies_count": object.reply_count,
+        "reblogs_count": object.announce_count,
+        "favourites_count": object.like_count,
+        "quotes_count": object.quote_count,
+        "quote": null,
+        "reblog": null,
+        "poll": null,
+        "card": null,
+    })
+}
+
+fn attachment_json(attachment: &AttachmentDocument) -> Value {
+    let media_type = attachment.media_type.as_deref().unwrap_or_default();
+    let kind = match media_type.split('/').next() {
+        Some("image") if media_type == "image/gif" => "gifv",
+        Some("image") => "image",
+        Some("video") => "video",
+        Some("audio") => "audio",
+        _ => match attachment.attachment_type.as_str() {
+            "Image" => "image",
+            "Video" => "video",
+            "Audio" => "audio",
+            _ => "unknown",
+        },
+    };
+    let mut meta = Document::new();
+    if let (Some(width), Some(height)) = (attachment.width, attachment.height) {
+        meta.insert(
+            "original",
+            mongodb::bson::doc! { "width": width, "height": height },
+        );
+    }
+    json!({
+        "type": kind,
+        "url": attachment.url,
+        "preview_url": attachment.url,
+        "remote_url": attachment.url,
+        "description": attachment.name,
+        "blurhash": attachment.blurhash,
+        "meta": meta,
+    })
+}
+
+/// Mastodon name of a visibility level
+fn visibility_name(visibility: &VisibilityLevel) -> &'static str {
+    match visibility {
+        VisibilityLevel::Public => "public",
+        VisibilityLevel::Unlisted => "unlisted",
+        VisibilityLevel::Followers => "private",
+        VisibilityLevel::Direct => "direct",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_page() {
+        let id = ObjectId::new();
+        let query = HashMap::from([
+            ("max_id".to_string(), id.to_hex()),
+            ("since_id".to_string(), "not-an-id".to_string()),
+            ("limit".to_string(), "100".to_string()),
+        ]);
+        let page = timeline_page(&query);
+        assert_eq!(page.max_id, Some(id));
+        assert_eq!(page.since_id, None);
+        assert_eq!(page.limit, MAX_PAGE_LIMIT);
+        assert_eq!(timeline_page(&HashMap::new()).limit, DEFAULT_PAGE_LIMIT);
+    }
+
+    #[test]
+    fn test_quote_embeds_visible_posts_only() {
+        let author = ActorDocument::from_activitypub(&json!({
+            "type": "Person",
+            "id": "https://remote.example/users/bob",
+            "preferredUsername": "bob",
+            "inbox": "https://remote.example/users/bob/inbox"
+        }))
+        .unwrap();
+        let actors = HashMap::from([(author.actor_id.clone(), author)]);
+        let quoted = |to: &str| {
+            ObjectDocument::from_activitypub(
+                &json!({
+                    "type": "Note",
+                    "id": "https://remote.example/notes/1",
+                    "attributedTo": "https://remote.example/users/bob",
+                    "content": "Quoted",
+                    "to": [to]
+                }),
+                ObjectType::Note,
+            )
+        };
+
+        let public = quote_json(&quoted(oxifed::builder::PUBLIC), &HashMap::new(), &actors);
+        assert_eq!(public["state"], "accepted");
+        assert_eq!(public["quoted_status"]["content"], "Quoted");
+
+        let private = quote_json(
+            &quoted("https://remote.example/users/bob/followers"),
+            &HashMap::new(),
+            &actors,
+        );
+        assert_eq!(private["state"], "unauthorized");
+        assert!(private["quoted_status"].is_null());
+    }
+}
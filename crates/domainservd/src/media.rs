@@ -0,0 +1,284 @@
+//! Remote media proxy and cache
+//!
+//! Remote attachment URLs are rewritten to `/media/proxy?url=...` when objects
+//! are served, so clients never contact remote instances directly. Proxied
+//! media is cached in MongoDB with a refresh TTL and a size cap; stale copies
+//! are kept for a grace period and served when the remote instance is down.
+//!
+//! The proxy only fetches media of stored objects and actors, only from
+//! public addresses and within the fetch timeout, and only serves images,
+//! video and audio, sandboxed so that remote content cannot run scripts on
+//! this origin.
+
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{Duration, Utc};
+use mongodb::bson::{Binary, DateTime as BsonDateTime, spec::BinarySubtype};
+use oxifed::client::{ActivityPubClient, ClientError};
+use oxifed::config::{ConfigError, Env};
+use oxifed::database::MediaCacheDocument;
+use serde::Deserialize;
+use tracing::{debug, error, warn};
+use url::Url;
+
+use crate::AppState;
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+/// Content types the proxy serves
+const ALLOWED_MEDIA_TYPES: &[&str] = &["image/", "video/", "audio/"];
+
+/// Media proxy configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaProxyConfig {
+    /// Rewrite remote attachment URLs to the proxy
+    pub enabled: bool,
+    /// How long a cached copy is considered fresh, in seconds
+    pub ttl_secs: i64,
+    /// How long a stale copy is kept as a fallback, in seconds
+    pub grace_secs: i64,
+    /// Maximum size of a single cached media file in bytes
+    pub max_size: usize,
+}
+
+impl Default for MediaProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 86_400,
+            grace_secs: 604_800,
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl MediaProxyConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("MEDIA_PROXY_ENABLED", &mut self.enabled)?;
+        env.set("MEDIA_PROXY_TTL_SECS", &mut self.ttl_secs)?;
+        env.set("MEDIA_PROXY_GRACE_SECS", &mut self.grace_secs)?;
+        env.set("MEDIA_PROXY_MAX_SIZE", &mut self.max_size)
+    }
+
+    /// Rewrite a remote media URL to the local proxy endpoint
+    ///
+    /// URLs on the serving domain and non-HTTP URLs are returned unchanged.
+    pub fn proxy_url(&self, domain: &str, media_url: &str) -> String {
+        if !self.enabled {
+            return media_url.to_string();
+        }
+
+        match Url::parse(media_url) {
+            Ok(url)
+                if matches!(url.scheme(), "http" | "https")
+                    && url.host_str().is_some_and(|host| host != domain) =>
+            {
+                let encoded: String =
+                    url::form_urlencoded::byte_serialize(media_url.as_bytes()).collect();
+                format!("https://{}/media/proxy?url={}", domain, encoded)
+            }
+            _ => media_url.to_string(),
+        }
+    }
+}
+
+/// Media proxy query parameters
+#[derive(Debug, Deserialize)]
+pub struct MediaProxyQuery {
+    /// Remote media URL to fetch
+    pub url: String,
+}
+
+/// Create the media proxy router
+pub fn media_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/media/proxy", get(proxy_media))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::Media),
+            limit_clients,
+        ))
+}
+
+/// Serve remote media from the cache, fetching it on demand
+async fn proxy_media(
+    Query(query): Query<MediaProxyQuery>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let config = &state.media_proxy;
+
+    let url = Url::parse(&query.url).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match state.db_manager.is_known_media_url(url.as_str()).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to look up media {}: {}", url, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let cached = match state.db_manager.find_cached_media(url.as_str()).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            error!("Failed to look up cached media {}: {}", url, e);
+            None
+        }
+    };
+
+    if let Some(media) = &cached
+        && media.expires_at > Utc::now()
+    {
+        debug!("Serving cached media for {}", url);
+        return media_response(media, config);
+    }
+
+    let client = ActivityPubClient::new().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match client.fetch_media(&url, config.max_size).await {
+        Ok((_, content_type)) if !is_allowed_media_type(content_type.as_deref()) => {
+            warn!(
+                "Refusing media {} of type {}",
+                url,
+                content_type.as_deref().unwrap_or("unknown")
+            );
+            Err(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+        }
+        Ok((body, content_type)) => {
+            let now = Utc::now();
+            let media = MediaCacheDocument {
+                id: None,
+                url: url.to_string(),
+                content_type,
+                size: body.len() as i64,
+                data: Binary {
+                    subtype: BinarySubtype::Generic,
+                    bytes: body,
+                },
+                fetched_at: now,
+                expires_at: now + Duration::seconds(config.ttl_secs),
+                purge_at: BsonDateTime::from_millis(
+                    (now + Duration::seconds(config.ttl_secs + config.grace_secs))
+                        .timestamp_millis(),
+                ),
+            };
+
+            if let Err(e) = state.db_manager.upsert_cached_media(media.clone()).await {
+                error!("Failed to cache media {}: {}", url, e);
+            }
+
+            media_response(&media, config)
+        }
+        Err(e) => {
+            if let Some(media) = &cached {
+                warn!("Failed to refresh media {}, serving stale copy: {}", url, e);
+                return media_response(media, config);
+            }
+
+            warn!("Failed to fetch media {}: {}", url, e);
+            match e {
+                ClientError::ResponseTooLarge(_) => Err(StatusCode::PAYLOAD_TOO_LARGE),
+                ClientError::Egress(_) => Err(StatusCode::FORBIDDEN),
+                ClientError::RequestFailed(e) if e.is_timeout() => Err(StatusCode::GATEWAY_TIMEOUT),
+                ClientError::StatusError(status) if status.as_u16() == 404 => {
+                    Err(StatusCode::NOT_FOUND)
+                }
+                _ => Err(StatusCode::BAD_GATEWAY),
+            }
+        }
+    }
+}
+
+/// Whether the proxy serves media of `content_type`
+fn is_allowed_media_type(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        ALLOWED_MEDIA_TYPES
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix))
+    })
+}
+
+/// Build the HTTP response for a cached media entry
+///
+/// Entries cached before their type was checked are refused like new ones.
+fn media_response(
+    media: &MediaCacheDocument,
+    config: &MediaProxyConfig,
+) -> Result<Response, StatusCode> {
+    let Some(content_type) = media
+        .content_type
+        .clone()
+        .filter(|content_type| is_allowed_media_type(Some(content_type)))
+    else {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    };
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                header::CACHE_CONTROL,
+                format!("public, max-age={}", config.ttl_secs),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        ],
+        media.data.bytes.clone(),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed_media_type() {
+        assert!(is_allowed_media_type(Some("image/png")));
+        assert!(is_allowed_media_type(Some("Video/MP4")));
+        assert!(is_allowed_media_type(Some("audio/ogg; codecs=opus")));
+        assert!(!is_allowed_media_type(Some("text/html")));
+        assert!(!is_allowed_media_type(Some("application/javascript")));
+        assert!(!is_allowed_media_type(None));
+    }
+
+    #[test]
+    fn test_media_response_headers() {
+        let now = Utc::now();
+        let mut media = MediaCacheDocument {
+            id: None,
+            url: "https://remote.example/cat.png".to_string(),
+            content_type: Some("image/png".to_string()),
+            size: 3,
+            data: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: vec![1, 2, 3],
+            },
+            fetched_at: now,
+            expires_at: now,
+            purge_at: BsonDateTime::now(),
+        };
+        let config = MediaProxyConfig::default();
+
+        let response = media_response(&media, &config).unwrap();
+        let headers = response.headers();
+        assert_eq!(headers[header::CONTENT_SECURITY_POLICY], "sandbox");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+        media.content_type = Some("text/html".to_string());
+        assert_eq!(
+            media_response(&media, &config).unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
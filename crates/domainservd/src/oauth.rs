@@ -0,0 +1,1526 @@
+//! OAuth 2.0 for client-to-server access
+//!
+//! Applications register at `/api/v1/apps`, send the user to
+//! `/oauth/authorize` to log in with their password (see
+//! [`crate::credentials`]) and exchange the code they get back at `/oauth/token`
+//! for an access token and a refresh token. Public clients prove with PKCE
+//! (`S256`) that they started the authorization; confidential clients
+//! authenticate with their secret instead.
+//!
+//! Tokens carry the scopes `read`, `write`, `follow` and `push`, which the
+//! C2S handlers check with [`verify_client_authentication`]. Secrets, codes
+//! and tokens are only stored as SHA-256 hashes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    Form, Json, Router,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use mongodb::bson::{DateTime as BsonDateTime, oid::ObjectId};
+use oxifed::credentials::{random_token, token_hash};
+use oxifed::database::{
+    AccessTokenDocument, AuthorizationCodeDocument, DatabaseError, OAuthAppDocument,
+    RefreshTokenDocument,
+};
+use oxifed::messaging::UserSessionsRevokeMessage;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+use url::Url;
+
+use crate::credentials;
+use crate::db::MongoDB;
+use crate::html::{escape, form_page};
+use crate::rabbitmq::RabbitMQError;
+use crate::ratelimit::{EndpointClass, limit_clients};
+use crate::{AppState, extract_domain_from_headers};
+
+/// Scopes applications may request
+pub const SCOPES: [&str; 4] = ["read", "write", "follow", "push"];
+
+/// Redirect URI of clients that show the code to the user instead
+const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// Time an authorization code can be exchanged
+const CODE_LIFETIME: Duration = Duration::minutes(10);
+
+/// Time an access token is accepted
+const ACCESS_TOKEN_LIFETIME: Duration = Duration::hours(2);
+
+/// Time a refresh token can be used
+const REFRESH_TOKEN_LIFETIME: Duration = Duration::days(30);
+
+/// Routes of application registration and the OAuth endpoints
+pub fn oauth_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/apps", post(register_app))
+        .route("/oauth/authorize", get(authorize_page).post(authorize))
+        .route("/oauth/token", post(token))
+        .route("/oauth/revoke", post(revoke))
+        .route("/oauth/introspect", post(introspect))
+        .route(
+            "/api/v1/sessions",
+            get(list_sessions).delete(revoke_all_sessions),
+        )
+        .route("/api/v1/sessions/{id}", delete(revoke_session))
+        .route("/api/v1/authorized_apps", get(list_authorized_apps))
+        .route(
+            "/api/v1/authorized_apps/{client_id}",
+            delete(revoke_authorized_app),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// OAuth error answered as `{"error", "error_description"}`
+#[derive(Debug)]
+pub struct OAuthError {
+    status: StatusCode,
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            status,
+            error,
+            description: description.into(),
+        }
+    }
+
+    fn invalid_request(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", description)
+    }
+
+    fn invalid_client() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "Client authentication failed",
+        )
+    }
+
+    fn invalid_grant(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_grant", description)
+    }
+
+    fn invalid_scope(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_scope", description)
+    }
+
+    fn server_error(e: impl std::fmt::Display) -> Self {
+        error!("OAuth request failed: {}", e);
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            "Internal server error",
+        )
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "error": self.error,
+            "error_description": self.description
+        }));
+        if self.status == StatusCode::UNAUTHORIZED {
+            (
+                self.status,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"oauth\"")],
+                body,
+            )
+                .into_response()
+        } else {
+            (self.status, body).into_response()
+        }
+    }
+}
+
+/// Parse a space separated scope list
+///
+/// `None` or an empty list stands for `read`.
+fn parse_scopes(scope: Option<&str>) -> Result<Vec<String>, OAuthError> {
+    let mut scopes: Vec<String> = Vec::new();
+    for scope in scope.unwrap_or_default().split([' ', '+']) {
+        if scope.is_empty() || scopes.iter().any(|s| s == scope) {
+            continue;
+        }
+        if !SCOPES.contains(&scope) {
+            return Err(OAuthError::invalid_scope(format!(
+                "Unknown scope {}",
+                scope
+            )));
+        }
+        scopes.push(scope.to_string());
+    }
+    if scopes.is_empty() {
+        scopes.push("read".to_string());
+    }
+    Ok(scopes)
+}
+
+/// Whether granted scopes include `required`
+pub(crate) fn grants(scopes: &[String], required: &str) -> bool {
+    scopes.iter().any(|scope| scope == required)
+}
+
+/// Scope a C2S activity needs
+///
+/// Following and blocking, and undoing either, need `follow`; everything
+/// else `write`.
+pub(crate) fn activity_scope(activity: &Value) -> &'static str {
+    let is_relationship = |value: &Value| {
+        matches!(
+            value.get("type").and_then(Value::as_str),
+            Some("Follow" | "Block")
+        )
+    };
+    let undoes_relationship = activity.get("type").and_then(Value::as_str) == Some("Undo")
+        && activity.get("object").is_some_and(is_relationship);
+    if is_relationship(activity) || undoes_relationship {
+        "follow"
+    } else {
+        "write"
+    }
+}
+
+/// Whether a PKCE verifier answers an `S256` challenge
+fn pkce_verifies(verifier: &str, challenge: &str) -> bool {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+/// Parameters of a form or JSON request body
+fn body_params(headers: &HeaderMap, body: &Bytes) -> Result<HashMap<String, String>, OAuthError> {
+    request_params(headers, body).map_err(OAuthError::invalid_request)
+}
+
+/// Parameters of a form or JSON request body, or why it cannot be read
+///
+/// JSON arrays are joined with spaces, like the scope and redirect URI
+/// lists of form requests.
+pub(crate) fn request_params(
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<HashMap<String, String>, String> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok(url::form_urlencoded::parse(body).into_owned().collect());
+    }
+
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("Invalid JSON body: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "Body must be a JSON object".to_string())?;
+    Ok(object
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Array(items) => items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Value::Null => return None,
+                other => other.to_string(),
+            };
+            Some((key.clone(), value))
+        })
+        .collect())
+}
+
+/// Client ID and secret from HTTP Basic authentication or the body
+fn client_credentials(
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> (Option<String>, Option<String>) {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|decoded| {
+            let (id, secret) = decoded.split_once(':')?;
+            let decode = |s: &str| {
+                url::form_urlencoded::parse(format!("v={}", s).as_bytes())
+                    .next()
+                    .map(|(_, v)| v.into_owned())
+            };
+            Some((decode(id)?, decode(secret)?))
+        });
+    match basic {
+        Some((id, secret)) => (Some(id), Some(secret)),
+        None => (
+            params.get("client_id").cloned(),
+            params.get("client_secret").cloned(),
+        ),
+    }
+}
+
+/// Look up the requesting application
+///
+/// With `require_secret`, the client has to authenticate with its secret;
+/// otherwise a given secret still has to be right.
+async fn find_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    require_secret: bool,
+) -> Result<OAuthAppDocument, OAuthError> {
+    let (client_id, secret) = client_credentials(headers, params);
+    let client_id = client_id.ok_or_else(OAuthError::invalid_client)?;
+    let app = state
+        .db_manager
+        .find_oauth_app(&client_id)
+        .await
+        .map_err(OAuthError::server_error)?
+        .ok_or_else(OAuthError::invalid_client)?;
+    match secret {
+        Some(secret) if token_hash(&secret) != app.client_secret_hash => {
+            Err(OAuthError::invalid_client())
+        }
+        None if require_secret => Err(OAuthError::invalid_client()),
+        _ => Ok(app),
+    }
+}
+
+/// Register an application
+///
+/// Takes `client_name`, `redirect_uris` (space or newline separated),
+/// `scopes` and `website`, and answers the client ID and the secret, which
+/// is not shown again.
+async fn register_app(headers: HeaderMap, State(state): State<AppState>, body: Bytes) -> Response {
+    match create_app(&headers, &state, &body).await {
+        Ok(app) => Json(app).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn create_app(
+    headers: &HeaderMap,
+    state: &AppState,
+    body: &Bytes,
+) -> Result<Value, OAuthError> {
+    let params = body_params(headers, body)?;
+    let name = params
+        .get("client_name")
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| OAuthError::invalid_request("client_name is required"))?;
+    let redirect_uris: Vec<String> = params
+        .get("redirect_uris")
+        .map(|uris| uris.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    if redirect_uris.is_empty() {
+        return Err(OAuthError::invalid_request("redirect_uris is required"));
+    }
+    if let Some(uri) = redirect_uris
+        .iter()
+        .find(|uri| *uri != OOB_REDIRECT_URI && Url::parse(uri).is_err())
+    {
+        return Err(OAuthError::invalid_request(format!(
+            "Invalid redirect URI {}",
+            uri
+        )));
+    }
+    let scopes = parse_scopes(params.get("scopes").map(String::as_str))?;
+
+    let client_id = random_token();
+    let client_secret = random_token();
+    let app = OAuthAppDocument {
+        id: None,
+        client_id: client_id.clone(),
+        client_secret_hash: token_hash(&client_secret),
+        name: name.to_string(),
+        website: params.get("website").filter(|w| !w.is_empty()).cloned(),
+        redirect_uris,
+        scopes,
+        created_at: Utc::now(),
+    };
+    state
+        .db_manager
+        .insert_oauth_app(app.clone())
+        .await
+        .map_err(OAuthError::server_error)?;
+    info!("Registered OAuth application {} ({})", app.name, client_id);
+
+    Ok(json!({
+        "id": client_id,
+        "name": app.name,
+        "website": app.website,
+        "redirect_uri": app.redirect_uris.join("\n"),
+        "redirect_uris": app.redirect_uris,
+        "scopes": app.scopes,
+        "client_id": client_id,
+        "client_secret": client_secret
+    }))
+}
+
+/// Parameters of an authorization request
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorizeParams {
+    #[serde(default)]
+    response_type: String,
+    #[serde(default)]
+    client_id: String,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+/// Answer of the authorization form
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeForm {
+    #[serde(flatten)]
+    params: AuthorizeParams,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    decision: String,
+}
+
+/// A checked authorization request
+struct Authorization {
+    app: OAuthAppDocument,
+    redirect_uri: String,
+    scopes: Vec<String>,
+}
+
+/// Failed authorization request
+#[derive(Debug)]
+enum AuthorizeError {
+    /// Shown to the user, when the redirect URI cannot be trusted
+    Page(StatusCode, String),
+    /// Sent to the client's redirect URI
+    Redirect {
+        redirect_uri: String,
+        error: &'static str,
+        description: String,
+        state: Option<String>,
+    },
+}
+
+impl AuthorizeError {
+    fn page(status: StatusCode, message: impl Into<String>) -> Self {
+        Self::Page(status, message.into())
+    }
+
+    fn server_error(e: impl std::fmt::Display) -> Self {
+        error!("OAuth authorization failed: {}", e);
+        Self::page(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    }
+}
+
+impl IntoResponse for AuthorizeError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Page(status, message) => error_page(status, &message),
+            Self::Redirect {
+                redirect_uri,
+                error,
+                description,
+                state,
+            } => redirect_with(
+                &redirect_uri,
+                &[
+                    ("error", Some(error)),
+                    ("error_description", Some(&description)),
+                    ("state", state.as_deref()),
+                ],
+            ),
+        }
+    }
+}
+
+/// Check an authorization request
+///
+/// Errors before the redirect URI is known to belong to the client are
+/// shown to the user; later ones are sent to the client.
+async fn check_authorization(
+    state: &AppState,
+    params: &AuthorizeParams,
+) -> Result<Authorization, AuthorizeError> {
+    let app = state
+        .db_manager
+        .find_oauth_app(&params.client_id)
+        .await
+        .map_err(AuthorizeError::server_error)?
+        .ok_or_else(|| AuthorizeError::page(StatusCode::BAD_REQUEST, "Unknown application"))?;
+    check_request(app, params)
+}
+
+/// Check an authorization request against the application it names
+fn check_request(
+    app: OAuthAppDocument,
+    params: &AuthorizeParams,
+) -> Result<Authorization, AuthorizeError> {
+    let redirect_uri = match &params.redirect_uri {
+        Some(uri) if app.redirect_uris.contains(uri) => uri.clone(),
+        None if app.redirect_uris.len() == 1 => app.redirect_uris[0].clone(),
+        _ => {
+            return Err(AuthorizeError::page(
+                StatusCode::BAD_REQUEST,
+                "The redirect URI is not registered for this application",
+            ));
+        }
+    };
+
+    let fail = |error: &'static str, description: &str| AuthorizeError::Redirect {
+        redirect_uri: redirect_uri.clone(),
+        error,
+        description: description.to_string(),
+        state: params.state.clone(),
+    };
+    if params.response_type != "code" {
+        return Err(fail(
+            "unsupported_response_type",
+            "Only the code response type is supported",
+        ));
+    }
+    if params.code_challenge.is_some()
+        && params.code_challenge_method.as_deref().unwrap_or("plain") != "S256"
+    {
+        return Err(fail(
+            "invalid_request",
+            "Only the S256 code challenge method is supported",
+        ));
+    }
+    let scopes = match parse_scopes(params.scope.as_deref()) {
+        Ok(scopes) if scopes.iter().all(|scope| app.scopes.contains(scope)) => scopes,
+        Ok(_) => {
+            return Err(fail(
+                "invalid_scope",
+                "The application did not register all requested scopes",
+            ));
+        }
+        Err(e) => return Err(fail(e.error, &e.description)),
+    };
+
+    Ok(Authorization {
+        app,
+        redirect_uri,
+        scopes,
+    })
+}
+
+/// Redirect to a client with query parameters
+fn redirect_with(redirect_uri: &str, params: &[(&str, Option<&str>)]) -> Response {
+    let Ok(mut url) = Url::parse(redirect_uri) else {
+        return error_page(StatusCode::BAD_REQUEST, "Invalid redirect URI");
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        for (key, value) in params {
+            if let Some(value) = value {
+                query.append_pair(key, value);
+            }
+        }
+    }
+    Redirect::to(url.as_str()).into_response()
+}
+
+fn error_page(status: StatusCode, message: &str) -> Response {
+    form_page(
+        status,
+        "Authorization failed",
+        &format!("<p>{}</p>\n", escape(message)),
+    )
+}
+
+/// Page asking the user to authorize an application
+async fn authorize_page(
+    Query(params): Query<AuthorizeParams>,
+    State(state): State<AppState>,
+) -> Result<Response, AuthorizeError> {
+    let authorization = check_authorization(&state, &params).await?;
+
+    let hidden = [
+        ("response_type", Some(params.response_type.as_str())),
+        ("client_id", Some(params.client_id.as_str())),
+        ("redirect_uri", Some(authorization.redirect_uri.as_str())),
+        ("scope", Some(&*authorization.scopes.join(" "))),
+        ("state", params.state.as_deref()),
+        ("code_challenge", params.code_challenge.as_deref()),
+        (
+            "code_challenge_method",
+            params.code_challenge_method.as_deref(),
+        ),
+    ]
+    .iter()
+    .filter_map(|(name, value)| {
+        value.map(|value| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name,
+                escape(value)
+            )
+        })
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+    let body = format!(
+        "<p><strong>{}</strong> asks for access to your account with the scopes: {}.</p>\n\
+         <form method=\"post\" action=\"/oauth/authorize\">\n{}\n\
+         <label>Username <input name=\"username\" autocomplete=\"username\" required></label>\n\
+         <label>Password <input name=\"password\" type=\"password\" autocomplete=\"current-password\" required></label>\n\
+         <button type=\"submit\" name=\"decision\" value=\"allow\">Allow</button>\n\
+         <button type=\"submit\" name=\"decision\" value=\"deny\">Deny</button>\n\
+         </form>",
+        escape(&authorization.app.name),
+        escape(&authorization.scopes.join(", ")),
+        hidden
+    );
+    Ok(form_page(StatusCode::OK, "Authorize application", &body))
+}
+
+/// Answer of the authorization form
+///
+/// Sends an authorization code to the client's redirect URI, or shows it
+/// to clients using the out-of-band URI.
+async fn authorize(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<AuthorizeForm>,
+) -> Result<Response, AuthorizeError> {
+    let domain = extract_domain_from_headers(&headers)
+        .ok_or_else(|| AuthorizeError::page(StatusCode::BAD_REQUEST, "Missing Host header"))?;
+    let params = &form.params;
+    let authorization = check_authorization(&state, params).await?;
+
+    if form.decision != "allow" {
+        return Err(AuthorizeError::Redirect {
+            redirect_uri: authorization.redirect_uri,
+            error: "access_denied",
+            description: "The user denied access".to_string(),
+            state: params.state.clone(),
+        });
+    }
+    let authenticated =
+        credentials::authenticate(&state.db_manager, &form.username, &domain, &form.password)
+            .await
+            .map_err(AuthorizeError::server_error)?;
+    if !authenticated {
+        warn!(
+            "Failed authorization of {} for {}@{}",
+            authorization.app.client_id, form.username, domain
+        );
+        return Err(AuthorizeError::page(
+            StatusCode::UNAUTHORIZED,
+            "Invalid username or password",
+        ));
+    }
+
+    let code = random_token();
+    let now = Utc::now();
+    let document = AuthorizationCodeDocument {
+        id: None,
+        code_hash: token_hash(&code),
+        client_id: authorization.app.client_id.clone(),
+        username: form.username.clone(),
+        domain,
+        redirect_uri: authorization.redirect_uri.clone(),
+        scopes: authorization.scopes,
+        code_challenge: params.code_challenge.clone(),
+        created_at: now,
+        expires_at: BsonDateTime::from_millis((now + CODE_LIFETIME).timestamp_millis()),
+    };
+    state
+        .db_manager
+        .insert_authorization_code(document)
+        .await
+        .map_err(AuthorizeError::server_error)?;
+    info!(
+        "{} authorized application {}",
+        form.username, authorization.app.client_id
+    );
+
+    if authorization.redirect_uri == OOB_REDIRECT_URI {
+        let body = format!(
+            "<p>Copy this code into {}:</p>\n<p><code>{}</code></p>",
+            escape(&authorization.app.name),
+            escape(&code)
+        );
+        return Ok(form_page(StatusCode::OK, "Authorization code", &body));
+    }
+    Ok(redirect_with(
+        &authorization.redirect_uri,
+        &[("code", Some(&code)), ("state", params.state.as_deref())],
+    ))
+}
+
+/// Token endpoint for the `authorization_code` and `refresh_token` grants
+async fn token(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let result = async {
+        let params = body_params(&headers, &body)?;
+        match params.get("grant_type").map(String::as_str) {
+            Some("authorization_code") => exchange_code(&state, &headers, &params).await,
+            Some("refresh_token") => refresh(&state, &headers, &params).await,
+            Some(other) => Err(OAuthError::new(
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+                format!("Unsupported grant type {}", other),
+            )),
+            None => Err(OAuthError::invalid_request("grant_type is required")),
+        }
+    }
+    .await;
+
+    match result {
+        Ok(tokens) => (
+            [
+                (header::CACHE_CONTROL, "no-store"),
+                (header::PRAGMA, "no-cache"),
+            ],
+            Json(tokens),
+        )
+            .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Exchange an authorization code
+///
+/// Codes issued with a PKCE challenge need the verifier, others the client
+/// secret.
+async fn exchange_code(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<Value, OAuthError> {
+    let code = params
+        .get("code")
+        .ok_or_else(|| OAuthError::invalid_request("code is required"))?;
+    let verifier = params.get("code_verifier");
+    let app = find_client(state, headers, params, verifier.is_none()).await?;
+
+    let code = state
+        .db_manager
+        .take_authorization_code(&token_hash(code))
+        .await
+        .map_err(OAuthError::server_error)?
+        .ok_or_else(|| OAuthError::invalid_grant("Invalid or expired code"))?;
+    check_code(&code, &app, params.get("redirect_uri"), verifier)?;
+
+    issue_tokens(state, &app, &code.username, &code.domain, code.scopes, None).await
+}
+
+/// Check that a code may be exchanged by `app` with the given redirect URI
+/// and PKCE verifier
+fn check_code(
+    code: &AuthorizationCodeDocument,
+    app: &OAuthAppDocument,
+    redirect_uri: Option<&String>,
+    verifier: Option<&String>,
+) -> Result<(), OAuthError> {
+    if code.client_id != app.client_id {
+        return Err(OAuthError::invalid_grant(
+            "Code was issued to another client",
+        ));
+    }
+    if redirect_uri.unwrap_or(&code.redirect_uri) != &code.redirect_uri {
+        return Err(OAuthError::invalid_grant("Redirect URI does not match"));
+    }
+    match (&code.code_challenge, verifier) {
+        (Some(challenge), Some(verifier)) if pkce_verifies(verifier, challenge) => {}
+        (Some(_), _) => return Err(OAuthError::invalid_grant("Invalid code verifier")),
+        (None, Some(_)) => {
+            return Err(OAuthError::invalid_grant(
+                "Code was issued without a code challenge",
+            ));
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Exchange a refresh token for new tokens
+///
+/// The refresh token and the access token issued with it are revoked. A
+/// `scope` parameter may narrow the granted scopes.
+async fn refresh(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<Value, OAuthError> {
+    let refresh_token = params
+        .get("refresh_token")
+        .ok_or_else(|| OAuthError::invalid_request("refresh_token is required"))?;
+    let app = find_client(state, headers, params, false).await?;
+
+    let refresh_token_hash = token_hash(refresh_token);
+    let refresh_token = state
+        .db_manager
+        .find_refresh_token(&refresh_token_hash)
+        .await
+        .map_err(OAuthError::server_error)?
+        .filter(|token| token.client_id == app.client_id)
+        .ok_or_else(|| OAuthError::invalid_grant("Invalid or expired refresh token"))?;
+    let scopes = match params.get("scope") {
+        Some(scope) => {
+            let scopes = parse_scopes(Some(scope))?;
+            if !scopes
+                .iter()
+                .all(|scope| grants(&refresh_token.scopes, scope))
+            {
+                return Err(OAuthError::invalid_scope(
+                    "Refreshed tokens cannot gain scopes",
+                ));
+            }
+            scopes
+        }
+        None => refresh_token.scopes.clone(),
+    };
+
+    // Only the first of concurrent refreshes gets new tokens
+    let revoked = state
+        .db_manager
+        .revoke_token(&refresh_token_hash, &app.client_id)
+        .await
+        .map_err(OAuthError::server_error)?;
+    if !revoked {
+        return Err(OAuthError::invalid_grant("Refresh token was already used"));
+    }
+
+    issue_tokens(
+        state,
+        &app,
+        &refresh_token.username,
+        &refresh_token.domain,
+        scopes,
+        Some(&refresh_token.access_token_hash),
+    )
+    .await
+}
+
+/// Issue an access token and a refresh token
+///
+/// Tokens refreshing the ones issued with `previous_access_token_hash`
+/// take over their push subscription.
+async fn issue_tokens(
+    state: &AppState,
+    app: &OAuthAppDocument,
+    username: &str,
+    domain: &str,
+    scopes: Vec<String>,
+    previous_access_token_hash: Option<&str>,
+) -> Result<Value, OAuthError> {
+    let access_token = random_token();
+    let refresh_token = random_token();
+    let now = Utc::now();
+    let expires_at =
+        |lifetime: Duration| BsonDateTime::from_millis((now + lifetime).timestamp_millis());
+
+    state
+        .db_manager
+        .insert_tokens(
+            AccessTokenDocument {
+                id: None,
+                token_hash: token_hash(&access_token),
+                username: username.to_string(),
+                domain: domain.to_string(),
+                client_id: app.client_id.clone(),
+                scopes: scopes.clone(),
+                created_at: now,
+                expires_at: expires_at(ACCESS_TOKEN_LIFETIME),
+            },
+            RefreshTokenDocument {
+                id: None,
+                token_hash: token_hash(&refresh_token),
+                access_token_hash: token_hash(&access_token),
+                username: username.to_string(),
+                domain: domain.to_string(),
+                client_id: app.client_id.clone(),
+                scopes: scopes.clone(),
+                created_at: now,
+                expires_at: expires_at(REFRESH_TOKEN_LIFETIME),
+            },
+        )
+        .await
+        .map_err(OAuthError::server_error)?;
+
+    if let Some(previous) = previous_access_token_hash {
+        state
+            .db_manager
+            .move_push_subscription(previous, &token_hash(&access_token))
+            .await
+            .map_err(OAuthError::server_error)?;
+    }
+
+    Ok(json!({
+        "access_token": access_token,
+        "token_type": "Bearer",
+        "expires_in": ACCESS_TOKEN_LIFETIME.num_seconds(),
+        "refresh_token": refresh_token,
+        "scope": scopes.join(" "),
+        "created_at": now.timestamp()
+    }))
+}
+
+/// Revoke an access or refresh token of the requesting client (RFC 7009)
+async fn revoke(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let result = async {
+        let params = body_params(&headers, &body)?;
+        let token = params
+            .get("token")
+            .ok_or_else(|| OAuthError::invalid_request("token is required"))?;
+        let app = find_client(&state, &headers, &params, true).await?;
+        state
+            .db_manager
+            .revoke_token(&token_hash(token), &app.client_id)
+            .await
+            .map_err(OAuthError::server_error)
+    }
+    .await;
+
+    // Unknown tokens are not an error
+    match result {
+        Ok(_) => Json(json!({})).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Describe an access token to an authenticated client (RFC 7662)
+async fn introspect(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let result = async {
+        let params = body_params(&headers, &body)?;
+        let token = params
+            .get("token")
+            .ok_or_else(|| OAuthError::invalid_request("token is required"))?;
+        find_client(&state, &headers, &params, true).await?;
+        state
+            .db_manager
+            .find_access_token(&token_hash(token))
+            .await
+            .map_err(OAuthError::server_error)
+    }
+    .await;
+
+    match result {
+        Ok(Some(token)) => Json(json!({
+            "active": true,
+            "scope": token.scopes.join(" "),
+            "client_id": token.client_id,
+            "username": token.username,
+            "sub": format!("https://{}/users/{}", token.domain, token.username),
+            "token_type": "Bearer",
+            "iat": token.created_at.timestamp(),
+            "exp": token.expires_at.timestamp_millis() / 1000
+        }))
+        .into_response(),
+        Ok(None) => Json(json!({ "active": false })).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Token of the request's `Authorization: Bearer` header
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Valid access token of the request for the requested domain
+async fn request_token(headers: &HeaderMap, state: &AppState) -> Option<AccessTokenDocument> {
+    let token = bearer_token(headers)?;
+    let domain = extract_domain_from_headers(headers)?;
+    match state.db_manager.find_access_token(&token_hash(token)).await {
+        Ok(token) => token.filter(|token| token.domain == domain),
+        Err(e) => {
+            error!("Failed to look up access token: {}", e);
+            None
+        }
+    }
+}
+
+/// Verify that a C2S request carries a token of `username` granting `scope`
+pub(crate) async fn verify_client_authentication(
+    headers: &HeaderMap,
+    username: &str,
+    scope: &str,
+    state: &AppState,
+) -> bool {
+    request_token(headers, state)
+        .await
+        .is_some_and(|token| token.username == username && grants(&token.scopes, scope))
+}
+
+/// User whose token the request carries, if it grants `scope`
+pub(crate) async fn authenticated_username(
+    headers: &HeaderMap,
+    scope: &str,
+    state: &AppState,
+) -> Option<String> {
+    authenticated_token(headers, scope, state)
+        .await
+        .map(|token| token.username)
+}
+
+/// Token the request carries, if it grants `scope`
+pub(crate) async fn authenticated_token(
+    headers: &HeaderMap,
+    scope: &str,
+    state: &AppState,
+) -> Option<AccessTokenDocument> {
+    request_token(headers, state)
+        .await
+        .filter(|token| grants(&token.scopes, scope))
+}
+
+/// Token of the request if it grants `scope`, for the session endpoints
+async fn session_token(
+    headers: &HeaderMap,
+    scope: &str,
+    state: &AppState,
+) -> Result<AccessTokenDocument, StatusCode> {
+    authenticated_token(headers, scope, state)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+fn session_error(e: DatabaseError) -> StatusCode {
+    error!("Failed to manage sessions: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Sessions of the requesting user, newest first
+///
+/// A session is a refresh token with the access token issued along with
+/// it; `current` marks the one the request was made with.
+async fn list_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    let token = session_token(&headers, "read", &state).await?;
+    let sessions = state
+        .db_manager
+        .find_user_sessions(&token.username, &token.domain)
+        .await
+        .map_err(session_error)?;
+    let apps = session_apps(&state, &sessions).await?;
+
+    let items: Vec<Value> = sessions
+        .iter()
+        .map(|session| {
+            json!({
+                "id": session.id.map(|id| id.to_hex()),
+                "client_id": session.client_id,
+                "application": apps.get(&session.client_id).map(|app| json!({
+                    "name": app.name,
+                    "website": app.website
+                })),
+                "scopes": session.scopes,
+                "created_at": session.created_at.to_rfc3339(),
+                "expires_at": session.expires_at.try_to_rfc3339_string().ok(),
+                "current": session.access_token_hash == token.token_hash
+            })
+        })
+        .collect();
+    Ok(Json(Value::Array(items)))
+}
+
+/// Applications of sessions by client ID
+async fn session_apps(
+    state: &AppState,
+    sessions: &[RefreshTokenDocument],
+) -> Result<HashMap<String, OAuthAppDocument>, StatusCode> {
+    let mut client_ids: Vec<String> = sessions
+        .iter()
+        .map(|session| session.client_id.clone())
+        .collect();
+    client_ids.sort();
+    client_ids.dedup();
+    Ok(state
+        .db_manager
+        .find_oauth_apps(&client_ids)
+        .await
+        .map_err(session_error)?
+        .into_iter()
+        .map(|app| (app.client_id.clone(), app))
+        .collect())
+}
+
+/// Revoke one session of the requesting user
+async fn revoke_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let token = session_token(&headers, "write", &state).await?;
+    let id = ObjectId::parse_str(&id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let revoked = state
+        .db_manager
+        .revoke_session(&token.username, &token.domain, id)
+        .await
+        .map_err(session_error)?;
+    if revoked {
+        info!("{} revoked session {}", token.username, id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Revoke all sessions of the requesting user, including the current one
+async fn revoke_all_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    let token = session_token(&headers, "write", &state).await?;
+    let revoked = state
+        .db_manager
+        .revoke_user_tokens(&token.username, &token.domain, None)
+        .await
+        .map_err(session_error)?;
+    info!("{} revoked all {} sessions", token.username, revoked);
+    Ok(Json(json!({ "revoked": revoked })))
+}
+
+/// Applications the requesting user has sessions with
+async fn list_authorized_apps(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    let token = session_token(&headers, "read", &state).await?;
+    let sessions = state
+        .db_manager
+        .find_user_sessions(&token.username, &token.domain)
+        .await
+        .map_err(session_error)?;
+    let apps = session_apps(&state, &sessions).await?;
+
+    // Sessions are newest first, so the first one of an app is its latest
+    let mut items: Vec<Value> = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for session in &sessions {
+        if let Some(index) = seen.get(session.client_id.as_str()) {
+            let item = &mut items[*index];
+            item["sessions"] = json!(item["sessions"].as_u64().unwrap_or(0) + 1);
+            if let Some(scopes) = item["scopes"].as_array_mut() {
+                for scope in &session.scopes {
+                    if !scopes.iter().any(|s| s == scope) {
+                        scopes.push(json!(scope));
+                    }
+                }
+            }
+            continue;
+        }
+        let app = apps.get(&session.client_id);
+        seen.insert(&session.client_id, items.len());
+        items.push(json!({
+            "client_id": session.client_id,
+            "name": app.map(|app| &app.name),
+            "website": app.and_then(|app| app.website.as_ref()),
+            "scopes": session.scopes,
+            "sessions": 1,
+            "last_authorized_at": session.created_at.to_rfc3339()
+        }));
+    }
+    Ok(Json(Value::Array(items)))
+}
+
+/// Revoke all sessions of the requesting user with an application
+async fn revoke_authorized_app(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(client_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let token = session_token(&headers, "write", &state).await?;
+    let revoked = state
+        .db_manager
+        .revoke_user_tokens(&token.username, &token.domain, Some(&client_id))
+        .await
+        .map_err(session_error)?;
+    info!(
+        "{} revoked {} sessions of {}",
+        token.username, revoked, client_id
+    );
+    Ok(Json(json!({ "revoked": revoked })))
+}
+
+/// Revoke all tokens of a user on behalf of adminservd
+pub async fn apply_sessions_revoke_message(
+    db: &Arc<MongoDB>,
+    message: &UserSessionsRevokeMessage,
+) -> Result<(), RabbitMQError> {
+    let revoked = db
+        .manager()
+        .revoke_user_tokens(&message.username, &message.domain, None)
+        .await?;
+    info!(
+        "Revoked {} sessions of {}@{}",
+        revoked, message.username, message.domain
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::body::Body;
+    use axum::http::Request;
+
+    fn app() -> OAuthAppDocument {
+        OAuthAppDocument {
+            id: None,
+            client_id: "client".to_string(),
+            client_secret_hash: token_hash("secret"),
+            name: "Test".to_string(),
+            website: None,
+            redirect_uris: vec!["https://app.example/callback".to_string()],
+            scopes: vec!["read".to_string(), "write".to_string()],
+            created_at: Utc::now(),
+        }
+    }
+
+    fn params(query: &str) -> AuthorizeParams {
+        serde_json::from_value(Value::Object(
+            url::form_urlencoded::parse(query.as_bytes())
+                .map(|(k, v)| (k.into_owned(), json!(v)))
+                .collect(),
+        ))
+        .unwrap()
+    }
+
+    fn code(code_challenge: Option<&str>) -> AuthorizationCodeDocument {
+        AuthorizationCodeDocument {
+            id: None,
+            code_hash: token_hash("code"),
+            client_id: "client".to_string(),
+            username: "alice".to_string(),
+            domain: "example.com".to_string(),
+            redirect_uri: "https://app.example/callback".to_string(),
+            scopes: vec!["read".to_string()],
+            code_challenge: code_challenge.map(str::to_string),
+            created_at: Utc::now(),
+            expires_at: BsonDateTime::now(),
+        }
+    }
+
+    /// Error code a failed authorization request redirects with
+    fn redirect_error(result: Result<Authorization, AuthorizeError>) -> &'static str {
+        match result {
+            Err(AuthorizeError::Redirect { error, .. }) => error,
+            Err(AuthorizeError::Page(_, message)) => panic!("unexpected page: {}", message),
+            Ok(_) => panic!("request was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_parse_scopes() {
+        assert_eq!(parse_scopes(None).unwrap(), vec!["read"]);
+        assert_eq!(parse_scopes(Some("")).unwrap(), vec!["read"]);
+        assert_eq!(
+            parse_scopes(Some("read write+follow read")).unwrap(),
+            vec!["read", "write", "follow"]
+        );
+        assert_eq!(
+            parse_scopes(Some("admin")).unwrap_err().error,
+            "invalid_scope"
+        );
+    }
+
+    #[test]
+    fn test_activity_scope() {
+        assert_eq!(activity_scope(&json!({ "type": "Create" })), "write");
+        assert_eq!(activity_scope(&json!({ "type": "Like" })), "write");
+        assert_eq!(activity_scope(&json!({ "type": "Follow" })), "follow");
+        assert_eq!(activity_scope(&json!({ "type": "Block" })), "follow");
+        assert_eq!(
+            activity_scope(&json!({ "type": "Undo", "object": { "type": "Follow" } })),
+            "follow"
+        );
+        assert_eq!(
+            activity_scope(&json!({ "type": "Undo", "object": { "type": "Like" } })),
+            "write"
+        );
+        assert!(grants(&["read".to_string(), "write".to_string()], "write"));
+        assert!(!grants(&["read".to_string()], "write"));
+    }
+
+    #[test]
+    fn test_pkce_verifies() {
+        // RFC 7636, appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        assert!(pkce_verifies(verifier, challenge));
+        assert!(!pkce_verifies("another verifier", challenge));
+        assert!(!pkce_verifies(challenge, challenge));
+    }
+
+    #[test]
+    fn test_body_params() {
+        let form = body_params(
+            &HeaderMap::new(),
+            &Bytes::from("grant_type=authorization_code&scope=read+write"),
+        )
+        .unwrap();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["scope"], "read write");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let json = body_params(
+            &headers,
+            &Bytes::from(r#"{"scopes": ["read", "push"], "website": null}"#),
+        )
+        .unwrap();
+        assert_eq!(json["scopes"], "read push");
+        assert!(!json.contains_key("website"));
+        assert!(body_params(&headers, &Bytes::from("[]")).is_err());
+    }
+
+    #[test]
+    fn test_client_credentials() {
+        let params = HashMap::from([
+            ("client_id".to_string(), "body-id".to_string()),
+            ("client_secret".to_string(), "body-secret".to_string()),
+        ]);
+        assert_eq!(
+            client_credentials(&HeaderMap::new(), &params),
+            (Some("body-id".to_string()), Some("body-secret".to_string()))
+        );
+
+        // Basic credentials are form encoded and take precedence
+        let mut headers = HeaderMap::new();
+        let basic = format!("Basic {}", STANDARD.encode("my%20id:s%3Acret"));
+        headers.insert(header::AUTHORIZATION, basic.parse().unwrap());
+        assert_eq!(
+            client_credentials(&headers, &params),
+            (Some("my id".to_string()), Some("s:cret".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_check_request() {
+        let authorization = check_request(
+            app(),
+            &params("response_type=code&client_id=client&scope=read+write&state=xyz"),
+        )
+        .unwrap();
+        assert_eq!(authorization.redirect_uri, "https://app.example/callback");
+        assert_eq!(authorization.scopes, vec!["read", "write"]);
+
+        // An unregistered redirect URI is never redirected to
+        assert!(matches!(
+            check_request(
+                app(),
+                &params("response_type=code&redirect_uri=https://evil.example/")
+            ),
+            Err(AuthorizeError::Page(StatusCode::BAD_REQUEST, _))
+        ));
+
+        assert_eq!(
+            redirect_error(check_request(app(), &params("response_type=token"))),
+            "unsupported_response_type"
+        );
+        assert_eq!(
+            redirect_error(check_request(
+                app(),
+                &params("response_type=code&code_challenge=abc")
+            )),
+            "invalid_request"
+        );
+        assert_eq!(
+            redirect_error(check_request(
+                app(),
+                &params("response_type=code&scope=follow")
+            )),
+            "invalid_scope"
+        );
+        assert_eq!(
+            redirect_error(check_request(
+                app(),
+                &params("response_type=code&scope=admin")
+            )),
+            "invalid_scope"
+        );
+    }
+
+    #[test]
+    fn test_check_code() {
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string();
+        let pkce = code(Some("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"));
+        assert!(check_code(&pkce, &app(), None, Some(&verifier)).is_ok());
+        assert!(check_code(&pkce, &app(), None, None).is_err());
+        assert!(check_code(&pkce, &app(), None, Some(&"wrong".to_string())).is_err());
+
+        let confidential = code(None);
+        assert!(check_code(&confidential, &app(), None, None).is_ok());
+        assert!(check_code(&confidential, &app(), None, Some(&verifier)).is_err());
+        assert!(
+            check_code(
+                &confidential,
+                &app(),
+                Some(&"https://app.example/other".to_string()),
+                None
+            )
+            .is_err()
+        );
+
+        let other = OAuthAppDocument {
+            client_id: "other".to_string(),
+            ..app()
+        };
+        assert!(check_code(&confidential, &other, None, None).is_err());
+    }
+
+    /// Request to the session endpoints with an optional bearer token
+    fn session_request(method: &str, path: &str, token: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::HOST, "example.com");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    /// Log `username` in to the app `client_id`, returning the access token
+    async fn log_in(state: &AppState, username: &str, client_id: &str, scopes: &[&str]) -> String {
+        let app = OAuthAppDocument {
+            client_id: client_id.to_string(),
+            ..app()
+        };
+        let scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        let tokens = issue_tokens(state, &app, username, "example.com", scopes, None)
+            .await
+            .unwrap();
+        tokens["access_token"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_session_endpoints_need_a_token() {
+        let state = testing::state().await;
+        let router = oauth_router(state.clone()).with_state(state);
+        for (method, path) in [
+            ("GET", "/api/v1/sessions"),
+            ("DELETE", "/api/v1/sessions"),
+            ("DELETE", "/api/v1/sessions/65f000000000000000000000"),
+            ("GET", "/api/v1/authorized_apps"),
+            ("DELETE", "/api/v1/authorized_apps/client"),
+        ] {
+            for token in [None, Some("unknown")] {
+                let request = session_request(method, path, token);
+                let (status, _) = testing::send(router.clone(), request).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, path);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_management() {
+        let Some(state) = testing::state_with_db().await else {
+            return;
+        };
+        state.db_manager.insert_oauth_app(app()).await.unwrap();
+        let reader = log_in(&state, "alice", "reader", &["read"]).await;
+        let alice = log_in(&state, "alice", "client", &["read", "write"]).await;
+        let bob = log_in(&state, "bob", "client", &["read", "write"]).await;
+        let router = oauth_router(state.clone()).with_state(state);
+        let send = |method, path: &str, token| {
+            testing::send(router.clone(), session_request(method, path, Some(token)))
+        };
+
+        // Reading needs `read`, and shows the user's own sessions only
+        let (status, sessions) = send("GET", "/api/v1/sessions", &reader).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(sessions.as_array().unwrap().len(), 2);
+        assert!(sessions.as_array().unwrap().iter().any(|session| {
+            session["client_id"] == "client" && session["application"]["name"] == "Test"
+        }));
+        let (status, apps) = send("GET", "/api/v1/authorized_apps", &reader).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(apps.as_array().unwrap().len(), 2);
+
+        // Revoking needs `write`
+        let (_, bob_sessions) = send("GET", "/api/v1/sessions", &bob).await;
+        let bob_session = format!(
+            "/api/v1/sessions/{}",
+            bob_sessions[0]["id"].as_str().unwrap()
+        );
+        for (method, path) in [
+            ("DELETE", "/api/v1/sessions"),
+            ("DELETE", bob_session.as_str()),
+            ("DELETE", "/api/v1/authorized_apps/reader"),
+        ] {
+            let (status, _) = send(method, path, &reader).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, path);
+        }
+
+        // Sessions of other users and malformed IDs are not found
+        assert_eq!(
+            send("DELETE", &bob_session, &alice).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send("DELETE", "/api/v1/sessions/not-an-id", &alice).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send("GET", "/api/v1/sessions", &bob).await.0,
+            StatusCode::OK
+        );
+
+        // Tokens are only valid on their own domain
+        let request = Request::get("/api/v1/sessions")
+            .header(header::HOST, "other.example")
+            .header(header::AUTHORIZATION, format!("Bearer {}", alice))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            testing::send(router.clone(), request).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Revoking an app signs the user out of it alone
+        let (status, body) = send("DELETE", "/api/v1/authorized_apps/reader", &alice).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["revoked"], 1);
+        assert_eq!(
+            send("GET", "/api/v1/sessions", &reader).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Revoking everything includes the current session but no one else's
+        let (status, body) = send("DELETE", "/api/v1/sessions", &alice).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["revoked"], 1);
+        assert_eq!(
+            send("GET", "/api/v1/sessions", &alice).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send("GET", "/api/v1/sessions", &bob).await.0,
+            StatusCode::OK
+        );
+    }
+}
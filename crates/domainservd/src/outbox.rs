@@ -0,0 +1,291 @@
+//! Transactional outbox relay
+//!
+//! Activities are stored together with the AMQP messages that announce them
+//! in the `message_outbox` collection. The relay claims pending messages,
+//! publishes them with publisher confirms and marks them published once the
+//! broker has acknowledged them. Several domainservd replicas can run the
+//! relay at the same time; claims keep them from sending the same message
+//! concurrently, and consumers must tolerate the rare duplicate after a
+//! crash between publish and mark.
+//!
+//! With domain routing enabled, deliveries are published to the domain
+//! delivery exchange with the sending domain in the routing key instead of
+//! the exchange stored with the message; see [`oxifed::routing`].
+//!
+//! Emails, the only messages on an exchange in
+//! [`oxifed::bus::BUS_EXCHANGES`], are published through the configured
+//! message bus instead of the relay's AMQP channel.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use deadpool_lapin::Pool;
+use lapin::{
+    BasicProperties, Channel, options::BasicPublishOptions, options::ConfirmSelectOptions,
+};
+use oxifed::bus::{MessageBus, OutgoingMessage};
+use oxifed::config::{ConfigError, Env};
+use oxifed::database::{DatabaseManager, OutboxMessageDocument};
+use oxifed::messaging::{
+    DeliveryPriority, EXCHANGE_ACTIVITYPUB_DELIVERY, EXCHANGE_DOMAIN_DELIVERY,
+};
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use tracing::{Instrument, debug, info, info_span, warn};
+
+use crate::rabbitmq::{RabbitMQError, spawn_channel_task};
+
+/// How long a claimed message is reserved for one relay
+const CLAIM_LEASE_SECS: i64 = 30;
+
+/// Outbox relay configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboxConfig {
+    /// Pause between polls when the outbox is empty, in milliseconds
+    pub poll_interval_ms: u64,
+    /// How long published messages are kept, in seconds
+    pub retention_secs: i64,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1000,
+            retention_secs: 604_800,
+        }
+    }
+}
+
+impl OutboxConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("OUTBOX_POLL_INTERVAL_MS", &mut self.poll_interval_ms)?;
+        env.set("OUTBOX_RETENTION_SECS", &mut self.retention_secs)
+    }
+}
+
+/// Start the outbox relay
+pub fn start_outbox_relay(
+    pool: Pool,
+    bus: Arc<dyn MessageBus>,
+    db: Arc<DatabaseManager>,
+    config: OutboxConfig,
+    domain_routing: bool,
+    shutdown: &Shutdown,
+) {
+    info!(
+        "Starting outbox relay (poll interval: {}ms)",
+        config.poll_interval_ms
+    );
+
+    spawn_channel_task("outbox relay", pool, shutdown, move |channel, shutdown| {
+        run_relay(
+            channel,
+            bus.clone(),
+            db.clone(),
+            config.clone(),
+            domain_routing,
+            shutdown,
+        )
+    });
+}
+
+/// Publish pending outbox messages until the channel fails or shutdown begins
+async fn run_relay(
+    channel: Channel,
+    bus: Arc<dyn MessageBus>,
+    db: Arc<DatabaseManager>,
+    config: OutboxConfig,
+    domain_routing: bool,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let lease = chrono::Duration::seconds(CLAIM_LEASE_SECS);
+    let retention = chrono::Duration::seconds(config.retention_secs);
+    let poll_interval = Duration::from_millis(config.poll_interval_ms);
+
+    while !shutdown.is_triggered() {
+        // Wait out a database outage instead of failing every poll
+        if let Err(e) = db.available() {
+            debug!("Outbox relay paused: {}", e);
+            shutdown
+                .unless_triggered(tokio::time::sleep(poll_interval))
+                .await;
+            continue;
+        }
+
+        let Some(message) = db.claim_outbox_message(lease).await? else {
+            shutdown
+                .unless_triggered(tokio::time::sleep(poll_interval))
+                .await;
+            continue;
+        };
+
+        match publish(&channel, bus.as_ref(), &message, domain_routing).await {
+            Ok(()) => {
+                db.mark_outbox_published(&message.message_id, retention)
+                    .await?;
+                debug!("Published outbox message {}", message.message_id);
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to publish outbox message {} (attempt {}): {}",
+                    message.message_id,
+                    message.attempts + 1,
+                    e
+                );
+                db.record_outbox_failure(&message.message_id, &e.to_string())
+                    .await?;
+                // Start over on a fresh channel; a nacked publish usually
+                // means the broker is in trouble
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish an outbox message and wait for the broker to confirm it
+///
+/// The publish continues the trace of the write that queued the message.
+async fn publish(
+    channel: &Channel,
+    bus: &dyn MessageBus,
+    message: &OutboxMessageDocument,
+    domain_routing: bool,
+) -> Result<(), RabbitMQError> {
+    let span = info_span!("outbox_publish", message_id = %message.message_id);
+    oxifed_telemetry::set_parent_from_entries(&span, &message.trace_context);
+    let (exchange, routing_key) = route(message, domain_routing);
+    if oxifed::bus::is_bus_exchange(exchange) {
+        publish_to_bus(bus, message, exchange, &routing_key)
+            .instrument(span)
+            .await
+    } else {
+        publish_confirmed(channel, message, exchange, &routing_key)
+            .instrument(span)
+            .await
+    }
+}
+
+async fn publish_to_bus(
+    bus: &dyn MessageBus,
+    message: &OutboxMessageDocument,
+    exchange: &str,
+    routing_key: &str,
+) -> Result<(), RabbitMQError> {
+    let mut outgoing = OutgoingMessage::new(message.payload.clone().into_bytes())
+        .with_message_id(message.message_id.clone())
+        .with_headers(oxifed_telemetry::current_context());
+    outgoing.content_type = message.content_type.clone();
+
+    bus.publish(exchange, routing_key, &outgoing).await?;
+    Ok(())
+}
+
+/// Exchange and routing key an outbox message is published with
+///
+/// Deliveries whose sender cannot be told keep their stored route.
+fn route(message: &OutboxMessageDocument, domain_routing: bool) -> (&str, String) {
+    if domain_routing && message.exchange == EXCHANGE_ACTIVITYPUB_DELIVERY {
+        let domain = serde_json::from_str(&message.payload)
+            .ok()
+            .and_then(|activity| oxifed::routing::sender_domain(&activity));
+        if let Some(domain) = domain {
+            let priority = if message.routing_key == DeliveryPriority::High.routing_key() {
+                DeliveryPriority::High
+            } else {
+                DeliveryPriority::Low
+            };
+            return (
+                EXCHANGE_DOMAIN_DELIVERY,
+                oxifed::routing::delivery_key(priority, &domain),
+            );
+        }
+    }
+    (&message.exchange, message.routing_key.clone())
+}
+
+async fn publish_confirmed(
+    channel: &Channel,
+    message: &OutboxMessageDocument,
+    exchange: &str,
+    routing_key: &str,
+) -> Result<(), RabbitMQError> {
+    let mut properties = BasicProperties::default()
+        .with_delivery_mode(2)
+        .with_message_id(message.message_id.clone().into());
+    if let Some(content_type) = &message.content_type {
+        properties = properties.with_content_type(content_type.clone().into());
+    }
+    let properties = oxifed_telemetry::with_trace_context(properties);
+
+    let confirmation = channel
+        .basic_publish(
+            exchange,
+            routing_key,
+            BasicPublishOptions::default(),
+            message.payload.as_bytes(),
+            properties,
+        )
+        .await?
+        .await?;
+
+    if confirmation.is_nack() {
+        return Err(RabbitMQError::PublishRejected(message.message_id.clone()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::messaging::EXCHANGE_EMAIL;
+
+    fn delivery(routing_key: &str, payload: &str) -> OutboxMessageDocument {
+        OutboxMessageDocument::new(
+            EXCHANGE_ACTIVITYPUB_DELIVERY,
+            routing_key,
+            None,
+            payload.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_route_by_sender_domain() {
+        let message = delivery(
+            "high",
+            r#"{"type":"Follow","actor":"https://big.example/users/alice"}"#,
+        );
+        assert_eq!(
+            route(&message, true),
+            (EXCHANGE_DOMAIN_DELIVERY, "high.big.example".to_string())
+        );
+        assert_eq!(
+            route(&message, false),
+            (EXCHANGE_ACTIVITYPUB_DELIVERY, "high".to_string())
+        );
+
+        // Without a sender the stored route is kept
+        let message = delivery("low", r#"{"type":"Create"}"#);
+        assert_eq!(
+            route(&message, true),
+            (EXCHANGE_ACTIVITYPUB_DELIVERY, "low".to_string())
+        );
+
+        // Other exchanges are not domain routed
+        let message = OutboxMessageDocument::new(
+            EXCHANGE_EMAIL,
+            "",
+            None,
+            r#"{"actor":"https://big.example/users/alice"}"#.to_string(),
+        );
+        assert_eq!(route(&message, true), (EXCHANGE_EMAIL, String::new()));
+    }
+}
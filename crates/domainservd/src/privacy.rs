@@ -0,0 +1,165 @@
+//! Account privacy
+//!
+//! Domains can make it harder to find out which accounts they host with a
+//! `privacy` object in their config:
+//!
+//! - `hide_inactive_accounts` answers requests for suspended, pending and
+//!   deleted accounts with 404 like unknown ones, instead of 410, and does
+//!   not resolve them through WebFinger
+//! - `not_found_delay_ms` holds back 404 answers of actor, collection and
+//!   WebFinger requests until that long after the request arrived, so their
+//!   timing does not tell which lookups found something
+//! - `authorized_fetch_collections` serves follower, following, outbox and
+//!   featured collections only to signed or token-authenticated requests
+//! - `hide_directory` turns off the account directory at `/directory` and
+//!   `/users`, which otherwise lists discoverable accounts only
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use oxifed::signature_middleware::VerifiedSigner;
+use serde::Deserialize;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::{AppState, extract_domain_from_headers};
+
+/// Longest `not_found_delay_ms` honoured
+const MAX_NOT_FOUND_DELAY: Duration = Duration::from_secs(2);
+
+/// The `privacy` object of a domain configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DomainPrivacy {
+    hide_inactive_accounts: bool,
+    not_found_delay_ms: u64,
+    authorized_fetch_collections: bool,
+    hide_directory: bool,
+}
+
+impl DomainPrivacy {
+    /// Privacy settings of the domain a request was made to
+    pub async fn of(domain: &str, state: &AppState) -> Self {
+        state.domain_config.section(domain, "privacy").await
+    }
+
+    /// Whether suspended, pending and deleted accounts look unknown
+    pub fn hides_inactive_accounts(&self) -> bool {
+        self.hide_inactive_accounts
+    }
+
+    /// Whether the account directory is turned off
+    pub fn hides_directory(&self) -> bool {
+        self.hide_directory
+    }
+
+    /// Status answered instead of `status`
+    ///
+    /// Inactive accounts look unknown when they are hidden.
+    fn concealed_status(&self, status: StatusCode) -> StatusCode {
+        if status == StatusCode::GONE && self.hides_inactive_accounts() {
+            StatusCode::NOT_FOUND
+        } else {
+            status
+        }
+    }
+
+    /// Least time before a 404 answer
+    fn not_found_delay(&self) -> Duration {
+        Duration::from_millis(self.not_found_delay_ms).min(MAX_NOT_FOUND_DELAY)
+    }
+}
+
+/// Answer requests for unknown and hidden accounts alike
+///
+/// Such answers are plain 404s, sent no earlier than the domain's
+/// `not_found_delay_ms` after the request arrived.
+pub async fn conceal_accounts(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let arrived = Instant::now();
+    let Some(domain) = extract_domain_from_headers(request.headers()) else {
+        return next.run(request).await;
+    };
+    let privacy = DomainPrivacy::of(&domain, &state).await;
+
+    let response = next.run(request).await;
+    let status = privacy.concealed_status(response.status());
+    if status != StatusCode::NOT_FOUND {
+        return response;
+    }
+    tokio::time::sleep_until(arrived + privacy.not_found_delay()).await;
+    StatusCode::NOT_FOUND.into_response()
+}
+
+/// Refuse anonymous reads of collections on domains requiring authorized
+/// fetches
+///
+/// Reads must be signed, which [`oxifed::signature_middleware::accept_signature`]
+/// checks before, or carry a bearer token with the `read` scope.
+pub async fn authorized_fetch(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !matches!(*request.method(), Method::GET | Method::HEAD)
+        || request.extensions().get::<VerifiedSigner>().is_some()
+    {
+        return next.run(request).await;
+    }
+    let Some(domain) = extract_domain_from_headers(request.headers()) else {
+        return next.run(request).await;
+    };
+    if !DomainPrivacy::of(&domain, &state)
+        .await
+        .authorized_fetch_collections
+    {
+        return next.run(request).await;
+    }
+
+    if crate::oauth::authenticated_username(request.headers(), "read", &state)
+        .await
+        .is_none()
+    {
+        debug!(
+            "Refused unauthorized fetch of {} on {}",
+            request.uri().path(),
+            domain
+        );
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_privacy() {
+        let privacy: DomainPrivacy = mongodb::bson::from_document(mongodb::bson::doc! {
+            "hide_inactive_accounts": true,
+            "not_found_delay_ms": 60_000_i64,
+        })
+        .unwrap();
+        assert_eq!(
+            privacy.concealed_status(StatusCode::GONE),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(privacy.concealed_status(StatusCode::OK), StatusCode::OK);
+        assert_eq!(privacy.not_found_delay(), MAX_NOT_FOUND_DELAY);
+        assert!(!privacy.authorized_fetch_collections);
+        assert!(!privacy.hides_directory());
+
+        let privacy = DomainPrivacy::default();
+        assert_eq!(privacy.concealed_status(StatusCode::GONE), StatusCode::GONE);
+        assert_eq!(privacy.not_found_delay(), Duration::ZERO);
+    }
+}
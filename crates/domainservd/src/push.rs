@@ -0,0 +1,927 @@
+//! Web Push notifications
+//!
+//! Mastodon-compatible clients subscribe at `/api/v1/push/subscription`
+//! with a token granting the `push` scope. A subscription belongs to the
+//! session of the token: it carries the endpoint of the client's push
+//! service, the keys to encrypt for and the alerts the user wants, and
+//! follows the session through token refreshes.
+//!
+//! Notifications of local users are queued through the outbox to
+//! `oxifed.push`. The consumer here sends each to the user's subscriptions
+//! that want it, encrypted per RFC 8291 (`aes128gcm`) and authorized with
+//! the domain's VAPID key (RFC 8292), which is generated on first use and
+//! whose private key is stored encrypted like the signing keys.
+//! Subscriptions the push service no longer knows are removed. Failed
+//! pushes are not retried: a late notification is worth little.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use deadpool_lapin::Pool;
+use futures::StreamExt;
+use hkdf::Hkdf;
+use lapin::{
+    Channel,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicQosOptions},
+    types::FieldTable,
+};
+use oxifed::database::{
+    AccessTokenDocument, DatabaseError, DatabaseManager, FilterContext, FollowStatus, PushAlerts,
+    PushPolicy, PushSubscriptionDocument, VapidKeyDocument,
+};
+use oxifed::egress::EgressPolicy;
+use oxifed::messaging::{NotificationType, PushMessage, QUEUE_PUSH};
+use oxifed::pki::KeyEncryptor;
+use oxifed::shutdown::Shutdown;
+use p256::ecdh::diffie_hellman;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+use crate::AppState;
+use crate::filters::ActiveFilters;
+use crate::html::{display_name, text_content};
+use crate::oauth::{authenticated_token, request_params};
+use crate::rabbitmq::{RabbitMQError, dead_letter_poison, spawn_channel_task, stopped};
+use crate::ratelimit::{EndpointClass, limit_clients};
+
+pub const PUSH_CONSUMER_TAG: &str = "push_consumer";
+
+/// How long a push service may take to answer
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Notifications pushed at once
+const PUSH_PREFETCH: u16 = 10;
+
+/// Time push services keep undelivered notifications, in seconds
+const PUSH_TTL_SECS: u64 = 48 * 3600;
+
+/// Time a VAPID token is valid; RFC 8292 allows at most a day
+const VAPID_TOKEN_LIFETIME: chrono::Duration = chrono::Duration::hours(12);
+
+/// Record size announced in the `aes128gcm` header
+const RECORD_SIZE: u32 = 4096;
+
+/// Longest excerpt of a post in a notification, in characters
+const MAX_EXCERPT_LENGTH: usize = 140;
+
+/// Routes of the push subscription endpoint
+pub fn push_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/push/subscription",
+            get(get_subscription)
+                .post(create_subscription)
+                .put(update_subscription)
+                .delete(delete_subscription),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Push subscription failure answered as `{"error": ...}`
+#[derive(Debug)]
+struct PushError {
+    status: StatusCode,
+    message: String,
+}
+
+impl PushError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "Record not found")
+    }
+
+    fn server_error(e: impl std::fmt::Display) -> Self {
+        error!("Push subscription request failed: {}", e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    }
+}
+
+impl From<DatabaseError> for PushError {
+    fn from(e: DatabaseError) -> Self {
+        Self::server_error(e)
+    }
+}
+
+impl IntoResponse for PushError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Token of the request if it grants `push` on the requested domain
+async fn push_token(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<AccessTokenDocument, PushError> {
+    authenticated_token(headers, "push", state)
+        .await
+        .ok_or_else(|| PushError::new(StatusCode::UNAUTHORIZED, "The access token is invalid"))
+}
+
+async fn get_subscription(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = push_token(&headers, &state).await?;
+        let subscription = state
+            .db_manager
+            .find_push_subscription(&token.token_hash)
+            .await?
+            .ok_or_else(PushError::not_found)?;
+        subscription_json(&state.db_manager, &state.key_encryptor, &subscription).await
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn create_subscription(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = push_token(&headers, &state).await?;
+        let params = push_params(&headers, &body).map_err(PushError::invalid)?;
+        let param = |name: &str| {
+            params
+                .get(name)
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| PushError::invalid(format!("{} is required", name)))
+        };
+
+        let endpoint = param("subscription[endpoint]")?;
+        if Url::parse(endpoint)
+            .ok()
+            .is_none_or(|url| url.scheme() != "https")
+        {
+            return Err(PushError::invalid("The endpoint must be an https URL"));
+        }
+        let p256dh = param("subscription[keys][p256dh]")?;
+        if decode_key(p256dh)
+            .and_then(|key| PublicKey::from_sec1_bytes(&key).ok())
+            .is_none()
+        {
+            return Err(PushError::invalid("p256dh is not a P-256 public key"));
+        }
+        let auth = param("subscription[keys][auth]")?;
+        if decode_key(auth).is_none_or(|auth| auth.len() != 16) {
+            return Err(PushError::invalid("auth is not a 16 byte secret"));
+        }
+
+        let subscription = state
+            .db_manager
+            .upsert_push_subscription(PushSubscriptionDocument {
+                id: None,
+                access_token_hash: token.token_hash.clone(),
+                username: token.username.clone(),
+                domain: token.domain.clone(),
+                endpoint: endpoint.clone(),
+                p256dh: p256dh.clone(),
+                auth: auth.clone(),
+                alerts: alerts_param(&params),
+                policy: policy_param(&params)?.unwrap_or_default(),
+                created_at: Utc::now(),
+            })
+            .await?;
+        info!(
+            "{}@{} subscribed to push notifications",
+            token.username, token.domain
+        );
+        subscription_json(&state.db_manager, &state.key_encryptor, &subscription).await
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn update_subscription(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let result = async {
+        let token = push_token(&headers, &state).await?;
+        let params = push_params(&headers, &body).map_err(PushError::invalid)?;
+        let subscription = state
+            .db_manager
+            .update_push_subscription(
+                &token.token_hash,
+                &alerts_param(&params),
+                policy_param(&params)?,
+            )
+            .await?
+            .ok_or_else(PushError::not_found)?;
+        subscription_json(&state.db_manager, &state.key_encryptor, &subscription).await
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+async fn delete_subscription(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let result = async {
+        let token = push_token(&headers, &state).await?;
+        state
+            .db_manager
+            .delete_push_subscription(&token.token_hash)
+            .await?;
+        Ok::<_, PushError>(json!({}))
+    }
+    .await;
+    result.map(Json).into_response()
+}
+
+/// Parameters of a subscription request
+///
+/// Clients send form data with keys such as `subscription[keys][auth]` or
+/// the same structure as JSON, which is flattened to those keys.
+fn push_params(headers: &HeaderMap, body: &Bytes) -> Result<HashMap<String, String>, String> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json || body.is_empty() {
+        return request_params(headers, body);
+    }
+
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("Invalid JSON body: {}", e))?;
+    let Value::Object(object) = value else {
+        return Err("Body must be a JSON object".to_string());
+    };
+    let mut params = HashMap::new();
+    for (key, value) in object {
+        flatten_param(key, value, &mut params);
+    }
+    Ok(params)
+}
+
+fn flatten_param(key: String, value: Value, params: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(object) => {
+            for (child, value) in object {
+                flatten_param(format!("{}[{}]", key, child), value, params);
+            }
+        }
+        Value::String(s) => {
+            params.insert(key, s);
+        }
+        Value::Null => {}
+        other => {
+            params.insert(key, other.to_string());
+        }
+    }
+}
+
+/// Alerts of `data[alerts]`; those left out are off
+fn alerts_param(params: &HashMap<String, String>) -> PushAlerts {
+    let alerts: serde_json::Map<String, Value> = PushAlerts::NAMES
+        .iter()
+        .map(|name| {
+            let enabled = params
+                .get(&format!("data[alerts][{}]", name))
+                .is_some_and(|value| matches!(value.as_str(), "true" | "1"));
+            (name.to_string(), Value::Bool(enabled))
+        })
+        .collect();
+    serde_json::from_value(Value::Object(alerts)).unwrap_or_default()
+}
+
+/// Policy of `data[policy]`, if given
+fn policy_param(params: &HashMap<String, String>) -> Result<Option<PushPolicy>, PushError> {
+    params
+        .get("data[policy]")
+        .map(|policy| {
+            policy
+                .parse()
+                .map_err(|_| PushError::invalid(format!("Unknown policy {}", policy)))
+        })
+        .transpose()
+}
+
+/// Decode a base64url key, with or without padding
+fn decode_key(key: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(key.trim_end_matches('=')).ok()
+}
+
+/// Subscription as answered to clients, with the domain's VAPID key
+async fn subscription_json(
+    db: &DatabaseManager,
+    encryptor: &KeyEncryptor,
+    subscription: &PushSubscriptionDocument,
+) -> Result<Value, PushError> {
+    let vapid = vapid_key(db, encryptor, &subscription.domain).await?;
+    Ok(json!({
+        "id": subscription.id.map(|id| id.to_hex()),
+        "endpoint": subscription.endpoint,
+        "standard": true,
+        "alerts": subscription.alerts,
+        "policy": subscription.policy.as_str(),
+        "server_key": vapid.public_key,
+    }))
+}
+
+/// Key ID the VAPID private key of a domain is encrypted under
+fn vapid_key_id(domain: &str) -> String {
+    format!("vapid:{}", domain)
+}
+
+/// VAPID key of a domain, generated on first use
+///
+/// When replicas generate one at once, all of them use the key stored
+/// first.
+pub async fn vapid_key(
+    db: &DatabaseManager,
+    encryptor: &KeyEncryptor,
+    domain: &str,
+) -> Result<VapidKeyDocument, DatabaseError> {
+    if let Some(key) = db.find_vapid_key(domain).await? {
+        return Ok(key);
+    }
+
+    let secret = SecretKey::random(&mut OsRng);
+    let private_key = encryptor
+        .encrypt(
+            &vapid_key_id(domain),
+            &URL_SAFE_NO_PAD.encode(secret.to_bytes()),
+        )
+        .await
+        .map_err(|e| DatabaseError::OperationError(e.to_string()))?;
+    let key = db
+        .insert_vapid_key(VapidKeyDocument {
+            id: None,
+            domain: domain.to_string(),
+            private_key,
+            encryption_algorithm: Some(encryptor.algorithm().to_string()),
+            public_key: URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false)),
+            created_at: Utc::now(),
+        })
+        .await?;
+    info!("Generated VAPID key of {}", domain);
+    Ok(key)
+}
+
+/// Decrypted signing key of a VAPID key
+async fn vapid_signing_key(
+    encryptor: &KeyEncryptor,
+    vapid: &VapidKeyDocument,
+) -> Result<SigningKey, String> {
+    let private_key = encryptor
+        .decrypt(
+            &vapid_key_id(&vapid.domain),
+            &vapid.private_key,
+            vapid.encryption_algorithm.as_deref(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    let private_key = decode_key(&private_key).ok_or("Invalid VAPID key")?;
+    SigningKey::from_slice(&private_key).map_err(|e| e.to_string())
+}
+
+/// Start pushing queued notifications
+pub fn start_push_consumer(
+    pool: Pool,
+    db: Arc<DatabaseManager>,
+    encryptor: KeyEncryptor,
+    shutdown: &Shutdown,
+) {
+    let builder = reqwest::Client::builder()
+        .user_agent(oxifed::client::ClientConfig::default().user_agent)
+        .timeout(PUSH_TIMEOUT);
+    let client = match EgressPolicy::installed()
+        .configure(builder)
+        .and_then(|builder| builder.build())
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "Push notifications disabled, failed to build HTTP client: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    spawn_channel_task("push consumer", pool, shutdown, move |channel, shutdown| {
+        run_push_consumer(
+            channel,
+            db.clone(),
+            encryptor.clone(),
+            client.clone(),
+            shutdown,
+        )
+    });
+}
+
+/// Push queued notifications to the subscriptions of their recipients
+async fn run_push_consumer(
+    channel: Channel,
+    db: Arc<DatabaseManager>,
+    encryptor: KeyEncryptor,
+    client: reqwest::Client,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .basic_qos(PUSH_PREFETCH, BasicQosOptions::default())
+        .await?;
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_PUSH,
+            PUSH_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Push consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        match serde_json::from_slice::<PushMessage>(&delivery.data) {
+            Ok(message) => {
+                match oxifed::poison::isolate(push(&db, &encryptor, &client, &message)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!(
+                        "Failed to push notification {}: {}",
+                        message.notification_id, e
+                    ),
+                    Err(panic) => {
+                        dead_letter_poison(&channel, &delivery, QUEUE_PUSH, &panic).await;
+                        continue;
+                    }
+                }
+            }
+            Err(e) => warn!("Dropping invalid push message: {}", e),
+        }
+        delivery.ack(BasicAckOptions::default()).await?;
+    }
+
+    stopped("Push consumer", &shutdown);
+    Ok(())
+}
+
+/// Push a notification to every subscription of its recipient that wants it
+async fn push(
+    db: &DatabaseManager,
+    encryptor: &KeyEncryptor,
+    client: &reqwest::Client,
+    message: &PushMessage,
+) -> Result<(), DatabaseError> {
+    if let Some(object_id) = &message.object_id {
+        let filters = ActiveFilters::load(
+            db,
+            &message.username,
+            &message.domain,
+            FilterContext::Notifications,
+        )
+        .await?;
+        if !filters.is_empty()
+            && let Some(object) = db.find_object_by_id(object_id).await?
+            && filters.hides(&object)
+        {
+            debug!(
+                "Not pushing notification {}, a filter hides {}",
+                message.notification_id, object_id
+            );
+            return Ok(());
+        }
+    }
+
+    let subscriptions = db
+        .find_push_subscriptions(&message.username, &message.domain)
+        .await?;
+    let mut wanted = Vec::new();
+    for subscription in subscriptions {
+        if subscription.alerts.wants(message.notification_type)
+            && allowed_by_policy(db, &subscription.policy, message).await?
+        {
+            wanted.push(subscription);
+        }
+    }
+    if wanted.is_empty() {
+        return Ok(());
+    }
+
+    let vapid = vapid_key(db, encryptor, &message.domain).await?;
+    let signing_key = vapid_signing_key(encryptor, &vapid).await.map_err(|e| {
+        DatabaseError::OperationError(format!("VAPID key of {}: {}", vapid.domain, e))
+    })?;
+    let subject = db
+        .find_domain_by_name(&message.domain)
+        .await?
+        .and_then(|domain| domain.contact_email)
+        .map_or_else(
+            || format!("https://{}", message.domain),
+            |email| format!("mailto:{}", email),
+        );
+    let payload = serde_json::to_vec(&payload(db, message).await?)
+        .map_err(|e| DatabaseError::OperationError(e.to_string()))?;
+
+    for subscription in wanted {
+        match send(
+            client,
+            &vapid,
+            &signing_key,
+            &subject,
+            &subscription,
+            &payload,
+        )
+        .await
+        {
+            Ok(true) => debug!(
+                "Pushed notification {} to {}",
+                message.notification_id, subscription.endpoint
+            ),
+            Ok(false) => {
+                info!(
+                    "Removing expired push subscription {}",
+                    subscription.endpoint
+                );
+                db.delete_push_subscription(&subscription.access_token_hash)
+                    .await?;
+            }
+            Err(e) => warn!("Push to {} failed: {}", subscription.endpoint, e),
+        }
+    }
+    Ok(())
+}
+
+/// Whether the policy of a subscription lets a notification through
+async fn allowed_by_policy(
+    db: &DatabaseManager,
+    policy: &PushPolicy,
+    message: &PushMessage,
+) -> Result<bool, DatabaseError> {
+    let (follower, following) = match policy {
+        PushPolicy::All => return Ok(true),
+        PushPolicy::None => return Ok(false),
+        PushPolicy::Followed => (&message.recipient, &message.account),
+        PushPolicy::Follower => (&message.account, &message.recipient),
+    };
+    Ok(db
+        .find_follow(follower, following)
+        .await?
+        .is_some_and(|follow| follow.status == FollowStatus::Accepted))
+}
+
+/// Notification as Mastodon clients expect it in a push
+async fn payload(db: &DatabaseManager, message: &PushMessage) -> Result<Value, DatabaseError> {
+    let account = db.find_actor_by_id(&message.account).await?;
+    let name = account
+        .as_ref()
+        .map_or(message.account.as_str(), display_name);
+
+    let handle = account.as_ref().map_or(message.account.clone(), |account| {
+        format!("@{}@{}", account.preferred_username, account.domain)
+    });
+    let (title, body) = match message.notification_type {
+        NotificationType::Follow => (format!("{} followed you", name), handle),
+        NotificationType::FollowRejected => {
+            (format!("{} rejected your follow request", name), handle)
+        }
+        NotificationType::Mention | NotificationType::Favourite => {
+            let title = if message.notification_type == NotificationType::Favourite {
+                format!("{} favourited your post", name)
+            } else if message.direct {
+                format!("New direct message from {}", name)
+            } else {
+                format!("{} mentioned you", name)
+            };
+            let content = match &message.object_id {
+                Some(object_id) => db.find_object_by_id(object_id).await?,
+                None => None,
+            };
+            let body = content
+                .and_then(|object| object.summary.filter(|s| !s.is_empty()).or(object.content))
+                .map(|html| excerpt(&text_content(&html)))
+                .unwrap_or_default();
+            (title, body)
+        }
+    };
+
+    Ok(json!({
+        "notification_id": message.notification_id,
+        "notification_type": message.notification_type.as_str(),
+        "preferred_locale": "en",
+        "icon": account.and_then(|account| account.icon).unwrap_or_default(),
+        "title": title,
+        "body": body,
+    }))
+}
+
+/// Text shortened to [`MAX_EXCERPT_LENGTH`] characters
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= MAX_EXCERPT_LENGTH {
+        return text;
+    }
+    let mut short: String = text.chars().take(MAX_EXCERPT_LENGTH - 1).collect();
+    short.push('…');
+    short
+}
+
+/// POST an encrypted notification to a subscription
+///
+/// Returns false when the push service no longer knows the subscription.
+async fn send(
+    client: &reqwest::Client,
+    vapid: &VapidKeyDocument,
+    signing_key: &SigningKey,
+    subject: &str,
+    subscription: &PushSubscriptionDocument,
+    payload: &[u8],
+) -> Result<bool, String> {
+    let ua_public = decode_key(&subscription.p256dh).ok_or("Invalid p256dh key")?;
+    let auth = decode_key(&subscription.auth).ok_or("Invalid auth secret")?;
+    let body = encrypt(payload, &ua_public, &auth)?;
+    let authorization = vapid_authorization(vapid, signing_key, &subscription.endpoint, subject)?;
+    let endpoint = Url::parse(&subscription.endpoint).map_err(|e| e.to_string())?;
+    EgressPolicy::installed()
+        .check_url(&endpoint)
+        .map_err(|e| e.to_string())?;
+
+    let response = client
+        .post(endpoint)
+        .header(header::AUTHORIZATION, authorization)
+        .header(header::CONTENT_ENCODING, "aes128gcm")
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header("TTL", PUSH_TTL_SECS)
+        .header("Urgency", "normal")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::GONE {
+        return Ok(false);
+    }
+    if !status.is_success() {
+        return Err(format!("push service answered {}", status));
+    }
+    Ok(true)
+}
+
+/// Encrypt a push message for a subscription (RFC 8291)
+fn encrypt(payload: &[u8], ua_public: &[u8], auth: &[u8]) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    encrypt_with(
+        payload,
+        ua_public,
+        auth,
+        &SecretKey::random(&mut OsRng),
+        &salt,
+    )
+}
+
+/// Encrypt with a given sender key and salt, as a single `aes128gcm` record
+fn encrypt_with(
+    payload: &[u8],
+    ua_public: &[u8],
+    auth: &[u8],
+    as_secret: &SecretKey,
+    salt: &[u8; 16],
+) -> Result<Vec<u8>, String> {
+    let ua_key = PublicKey::from_sec1_bytes(ua_public).map_err(|e| e.to_string())?;
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared = diffie_hellman(as_secret.to_nonzero_scalar(), ua_key.as_affine());
+
+    // IKM = HKDF(auth, ECDH secret, "WebPush: info" || 0 || ua_public || as_public)
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public);
+    key_info.extend_from_slice(as_public.as_bytes());
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|e| e.to_string())?;
+
+    let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .map_err(|e| e.to_string())?;
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .map_err(|e| e.to_string())?;
+
+    // The only record is the last one, delimited by 2
+    let mut record = payload.to_vec();
+    record.push(2);
+    let ciphertext = Aes128Gcm::new_from_slice(&cek)
+        .map_err(|e| e.to_string())?
+        .encrypt(Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|e| e.to_string())?;
+
+    // Header: salt, record size, length and value of the sender key
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
+
+/// `Authorization` header for a push service (RFC 8292)
+fn vapid_authorization(
+    vapid: &VapidKeyDocument,
+    signing_key: &SigningKey,
+    endpoint: &str,
+    subject: &str,
+) -> Result<String, String> {
+    let endpoint = Url::parse(endpoint).map_err(|e| e.to_string())?;
+
+    let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(
+        json!({
+            "aud": endpoint.origin().ascii_serialization(),
+            "exp": (Utc::now() + VAPID_TOKEN_LIFETIME).timestamp(),
+            "sub": subject,
+        })
+        .to_string(),
+    );
+    let signing_input = format!("{}.{}", header, claims);
+    let signature: Signature = signing_key.sign(signing_input.as_bytes());
+
+    Ok(format!(
+        "vapid t={}.{}, k={}",
+        signing_input,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+        vapid.public_key
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::VerifyingKey;
+    use p256::ecdsa::signature::Verifier;
+
+    fn b64(s: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(s).unwrap()
+    }
+
+    #[test]
+    fn test_encryption_matches_rfc_8291() {
+        // RFC 8291, Appendix A
+        let as_secret =
+            SecretKey::from_slice(&b64("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw")).unwrap();
+        let salt: [u8; 16] = b64("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+        let body = encrypt_with(
+            b"When I grow up, I want to be a watermelon",
+            &b64(
+                "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+            ),
+            &b64("BTBZMqHH6r4Tts7J_aSIgg"),
+            &as_secret,
+            &salt,
+        )
+        .unwrap();
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vapid_authorization() {
+        let secret = SecretKey::random(&mut OsRng);
+        let vapid = VapidKeyDocument {
+            id: None,
+            domain: "example.com".to_string(),
+            private_key: URL_SAFE_NO_PAD.encode(secret.to_bytes()),
+            encryption_algorithm: None,
+            public_key: URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false)),
+            created_at: Utc::now(),
+        };
+        // Keys stored before encryption are read as plaintext
+        let signing_key = vapid_signing_key(&KeyEncryptor::plaintext(), &vapid)
+            .await
+            .unwrap();
+        let authorization = vapid_authorization(
+            &vapid,
+            &signing_key,
+            "https://push.example.net/send/abc?x=1",
+            "mailto:admin@example.com",
+        )
+        .unwrap();
+
+        let (token, key) = authorization
+            .strip_prefix("vapid t=")
+            .and_then(|rest| rest.split_once(", k="))
+            .unwrap();
+        assert_eq!(key, vapid.public_key);
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+        let claims: Value =
+            serde_json::from_slice(&b64(signing_input.split_once('.').unwrap().1)).unwrap();
+        assert_eq!(claims["aud"], "https://push.example.net");
+        assert_eq!(claims["sub"], "mailto:admin@example.com");
+
+        let signature = Signature::from_slice(&b64(signature)).unwrap();
+        VerifyingKey::from_sec1_bytes(&b64(key))
+            .unwrap()
+            .verify(signing_input.as_bytes(), &signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_vapid_keys_are_stored_encrypted_once() {
+        let Some(state) = crate::testing::state_with_db().await else {
+            return;
+        };
+        let db = &state.db_manager;
+        let encryptor = KeyEncryptor::with_master_key(&[7u8; 32]).unwrap();
+
+        // Replicas generating the key at once end up with the same one
+        let (first, second) = tokio::join!(
+            vapid_key(db, &encryptor, "example.com"),
+            vapid_key(db, &encryptor, "example.com")
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first.public_key, second.public_key);
+        assert_eq!(first.private_key, second.private_key);
+
+        let stored = db.find_vapid_key("example.com").await.unwrap().unwrap();
+        assert_eq!(
+            stored.encryption_algorithm.as_deref(),
+            Some(encryptor.algorithm())
+        );
+        assert!(serde_json::from_str::<Value>(&stored.private_key).is_ok());
+        let signing_key = vapid_signing_key(&encryptor, &stored).await.unwrap();
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(signing_key.verifying_key().to_encoded_point(false)),
+            stored.public_key
+        );
+        // Without the key-encryption key the private key is unusable
+        assert!(
+            vapid_signing_key(&KeyEncryptor::plaintext(), &stored)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_json_params_are_flattened() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let body = Bytes::from(
+            r#"{"subscription": {"endpoint": "https://push.example.net/a", "keys": {"p256dh": "k", "auth": "a"}},
+                "data": {"alerts": {"mention": true, "follow": false}, "policy": "follower"}}"#,
+        );
+        let params = push_params(&headers, &body).unwrap();
+        assert_eq!(params["subscription[keys][auth]"], "a");
+
+        let alerts = alerts_param(&params);
+        assert!(alerts.mention);
+        assert!(!alerts.follow);
+        assert!(!alerts.favourite);
+        assert_eq!(policy_param(&params).unwrap(), Some(PushPolicy::Follower));
+    }
+
+    #[test]
+    fn test_form_params() {
+        let headers = HeaderMap::new();
+        let body = Bytes::from("data%5Balerts%5D%5Bfollow%5D=true&data%5Bpolicy%5D=everyone");
+        let params = push_params(&headers, &body).unwrap();
+        assert!(alerts_param(&params).follow);
+        assert!(policy_param(&params).is_err());
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(excerpt("  short\n text "), "short text");
+        let long = "a".repeat(200);
+        assert_eq!(excerpt(&long).chars().count(), MAX_EXCERPT_LENGTH);
+    }
+}
@@ -0,0 +1,235 @@
+//! Read-only queries of actors and posts for the admin API
+//!
+//! adminservd cannot read the database, so its listings of persons, users
+//! and notes are answered here over the RPC queue, one page at a time.
+
+use oxifed::database::{ActorDocument, DatabaseManager, ObjectDocument};
+use oxifed::messaging::{
+    ActorInfo, ActorRpcRequest, ActorRpcRequestType, ActorRpcResponse, NoteInfo, NoteRpcResponse,
+    Page, PageRequest, UserInfo, UserRpcResponse,
+};
+use serde::Serialize;
+use tracing::error;
+
+/// Most items returned by one page
+const MAX_LIMIT: u32 = 200;
+
+/// Answer an actor RPC request
+pub async fn handle_actor_rpc(db: &DatabaseManager, request: ActorRpcRequest) -> ActorRpcResponse {
+    let request_id = request.request_id;
+    match request.request_type {
+        ActorRpcRequestType::ListActors {
+            domain,
+            local,
+            status,
+            page,
+        } => {
+            let page = clamp(page);
+            match db
+                .find_actors_page(
+                    domain.as_deref(),
+                    local,
+                    status.as_deref(),
+                    page.offset,
+                    i64::from(page.limit),
+                )
+                .await
+            {
+                Ok((actors, total)) => ActorRpcResponse::actor_list(
+                    request_id,
+                    Page::new(actors, total, page).map(actor_info),
+                ),
+                Err(e) => {
+                    error!("Failed to list actors: {}", e);
+                    ActorRpcResponse::error(request_id, format!("Failed to list actors: {}", e))
+                }
+            }
+        }
+        ActorRpcRequestType::GetActor { id } => match find_actor(db, &id).await {
+            Ok(actor) => ActorRpcResponse::actor_details(request_id, actor.map(actor_info)),
+            Err(e) => {
+                error!("Failed to get actor '{}': {}", id, e);
+                ActorRpcResponse::error(request_id, format!("Failed to get actor '{}': {}", id, e))
+            }
+        },
+    }
+}
+
+/// Answer a user list request with a page of local actors
+pub async fn list_users(
+    db: &DatabaseManager,
+    request_id: String,
+    domain: Option<&str>,
+    page: PageRequest,
+) -> UserRpcResponse {
+    let page = clamp(page);
+    match db
+        .find_actors_page(domain, Some(true), None, page.offset, i64::from(page.limit))
+        .await
+    {
+        Ok((actors, total)) => {
+            UserRpcResponse::user_list(request_id, Page::new(actors, total, page).map(user_info))
+        }
+        Err(e) => {
+            error!("Failed to list users: {}", e);
+            UserRpcResponse::error(request_id, format!("Failed to list users: {}", e))
+        }
+    }
+}
+
+/// Answer a note list request with a page of published local posts
+pub async fn list_notes(
+    db: &DatabaseManager,
+    request_id: String,
+    actor: Option<&str>,
+    page: PageRequest,
+) -> NoteRpcResponse {
+    let page = clamp(page);
+    match db
+        .find_local_objects_page(actor, page.offset, i64::from(page.limit))
+        .await
+    {
+        Ok((objects, total)) => {
+            NoteRpcResponse::note_list(request_id, Page::new(objects, total, page).map(note_info))
+        }
+        Err(e) => NoteRpcResponse::error(request_id, format!("Failed to list notes: {}", e)),
+    }
+}
+
+/// Answer a request for a stored post
+pub async fn get_note(
+    db: &DatabaseManager,
+    request_id: String,
+    object_id: &str,
+) -> NoteRpcResponse {
+    match db.find_object_by_id(object_id).await {
+        Ok(object) => NoteRpcResponse::note_details(request_id, object.map(note_info)),
+        Err(e) => NoteRpcResponse::error(
+            request_id,
+            format!("Failed to get note '{}': {}", object_id, e),
+        ),
+    }
+}
+
+/// Actor with an ID, or a local actor given as user@domain
+async fn find_actor(
+    db: &DatabaseManager,
+    id: &str,
+) -> Result<Option<ActorDocument>, oxifed::database::DatabaseError> {
+    match id.split_once('@') {
+        Some((username, domain)) if !id.contains("://") => {
+            db.find_actor_by_username(username.trim_start_matches('@'), domain)
+                .await
+        }
+        _ => db.find_actor_by_id(id).await,
+    }
+}
+
+/// Page request with its limit between 1 and [`MAX_LIMIT`]
+fn clamp(page: PageRequest) -> PageRequest {
+    PageRequest {
+        limit: page.limit.clamp(1, MAX_LIMIT),
+        ..page
+    }
+}
+
+/// Serialized name of a unit enum variant, e.g. `active` for
+/// `ActorStatus::Active`
+fn serde_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn actor_info(actor: ActorDocument) -> ActorInfo {
+    ActorInfo {
+        status: serde_name(&actor.status),
+        restriction: serde_name(&actor.restriction),
+        actor_id: actor.actor_id,
+        actor_type: actor.actor_type,
+        username: actor.preferred_username,
+        display_name: Some(actor.name).filter(|name| !name.is_empty()),
+        domain: actor.domain,
+        local: actor.local,
+        followers_count: actor.followers_count,
+        following_count: actor.following_count,
+        statuses_count: actor.statuses_count,
+        created_at: actor.created_at.to_rfc3339(),
+        updated_at: actor.updated_at.to_rfc3339(),
+    }
+}
+
+pub(crate) fn user_info(actor: ActorDocument) -> UserInfo {
+    let public_key = actor
+        .public_key
+        .as_ref()
+        .map(|pk| pk.public_key_pem.clone());
+    UserInfo {
+        username: actor.preferred_username,
+        display_name: Some(actor.name).filter(|name| !name.is_empty()),
+        domain: actor.domain,
+        actor_id: actor.actor_id,
+        private_key_stored: public_key.is_some(),
+        public_key,
+        created_at: actor.created_at.to_rfc3339(),
+        updated_at: actor.updated_at.to_rfc3339(),
+    }
+}
+
+fn note_info(object: ObjectDocument) -> NoteInfo {
+    NoteInfo {
+        object_type: format!("{:?}", object.object_type),
+        visibility: serde_name(&object.visibility),
+        published: object.published.map(|published| published.to_rfc3339()),
+        object_id: object.object_id,
+        author: object.attributed_to,
+        content: object.content,
+        summary: object.summary,
+        in_reply_to: object.in_reply_to,
+        quote: object.quote,
+        quote_count: object.quote_count,
+        local: object.local,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::database::{ActorRestriction, ActorStatus};
+
+    #[test]
+    fn test_limit_clamped() {
+        let page = clamp(PageRequest {
+            offset: 40,
+            limit: 5000,
+        });
+        assert_eq!(page.offset, 40);
+        assert_eq!(page.limit, MAX_LIMIT);
+        assert_eq!(
+            clamp(PageRequest {
+                offset: 0,
+                limit: 0
+            })
+            .limit,
+            1
+        );
+    }
+
+    #[test]
+    fn test_enum_names() {
+        assert_eq!(serde_name(&ActorStatus::Suspended), "suspended");
+        assert_eq!(serde_name(&ActorRestriction::None), "none");
+    }
+
+    #[test]
+    fn test_next_page_offset() {
+        let request = PageRequest {
+            offset: 20,
+            limit: 10,
+        };
+        assert_eq!(Page::new(vec![0; 10], 45, request).next, Some(30));
+        assert_eq!(Page::new(vec![0; 10], 30, request).next, None);
+        assert_eq!(Page::<u8>::new(vec![], 45, request).next, None);
+    }
+}
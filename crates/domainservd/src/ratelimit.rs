@@ -0,0 +1,509 @@
+//! Request rate limiting
+//!
+//! Inbox, outbox, C2S and search requests are limited with token buckets
+//! per client IP and per actor. The IP limit is checked before any other
+//! work, so inbox floods are refused before their signatures are verified;
+//! the actor limit applies to the verified signer on inboxes and to the
+//! local actor named in the path on outbox and C2S endpoints.
+//!
+//! A domain can override the configured limits with a `rate_limits` object
+//! in its configuration, set through the domain properties, for example
+//! `{"rate_limits": {"inbox": {"per_ip_per_minute": 1200}}}`.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use moka::sync::Cache;
+use oxifed::config::{ConfigError, Env, require_positive};
+use oxifed::signature_middleware::VerifiedSigner;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::domain_config::DomainConfigCache;
+use crate::extract_domain_from_headers;
+
+/// Buckets idle for this long are dropped; they would be full again anyway
+const BUCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Endpoints sharing a set of limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    Inbox,
+    Outbox,
+    C2s,
+    Search,
+    Media,
+}
+
+impl EndpointClass {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EndpointClass::Inbox => "inbox",
+            EndpointClass::Outbox => "outbox",
+            EndpointClass::C2s => "c2s",
+            EndpointClass::Search => "search",
+            EndpointClass::Media => "media",
+        }
+    }
+}
+
+/// Limits of one endpoint class
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndpointLimits {
+    /// Sustained requests per minute from one client IP, 0 for no limit
+    pub per_ip_per_minute: u32,
+    /// Sustained requests per minute by one actor, 0 for no limit
+    pub per_actor_per_minute: u32,
+    /// Requests accepted in a burst before the sustained rate applies
+    pub burst: u32,
+}
+
+impl EndpointLimits {
+    fn new(per_ip_per_minute: u32, per_actor_per_minute: u32, burst: u32) -> Self {
+        Self {
+            per_ip_per_minute,
+            per_actor_per_minute,
+            burst,
+        }
+    }
+
+    /// Apply overrides from environment variables starting with `prefix`
+    pub fn apply_env(&mut self, prefix: &str, env: &Env) -> Result<(), ConfigError> {
+        env.set(
+            &format!("{}_PER_IP_PER_MINUTE", prefix),
+            &mut self.per_ip_per_minute,
+        )?;
+        env.set(
+            &format!("{}_PER_ACTOR_PER_MINUTE", prefix),
+            &mut self.per_actor_per_minute,
+        )?;
+        env.set(&format!("{}_BURST", prefix), &mut self.burst)
+    }
+
+    /// Apply the overrides of a domain
+    fn with_override(mut self, limits: &LimitOverride) -> Self {
+        if let Some(per_ip_per_minute) = limits.per_ip_per_minute {
+            self.per_ip_per_minute = per_ip_per_minute;
+        }
+        if let Some(per_actor_per_minute) = limits.per_actor_per_minute {
+            self.per_actor_per_minute = per_actor_per_minute;
+        }
+        if let Some(burst) = limits.burst {
+            self.burst = burst;
+        }
+        self
+    }
+}
+
+impl Default for EndpointLimits {
+    fn default() -> Self {
+        Self::new(600, 300, 60)
+    }
+}
+
+/// Rate limiting configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Enforce the limits
+    pub enabled: bool,
+    /// Take the client IP from `X-Forwarded-For`; only enable this behind a
+    /// reverse proxy that sets the header
+    pub trust_forwarded_for: bool,
+    /// Reverse proxies in front of domainservd, each appending the address
+    /// it was contacted from to `X-Forwarded-For`; the client IP is this
+    /// many entries from the right, as entries further left are whatever
+    /// the client sent
+    pub trusted_proxies: usize,
+    /// Maximum number of clients and actors tracked at a time
+    pub max_tracked: u64,
+    pub inbox: EndpointLimits,
+    pub outbox: EndpointLimits,
+    pub c2s: EndpointLimits,
+    pub search: EndpointLimits,
+    pub media: EndpointLimits,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trust_forwarded_for: false,
+            trusted_proxies: 1,
+            max_tracked: 100_000,
+            inbox: EndpointLimits::new(600, 300, 100),
+            outbox: EndpointLimits::new(300, 120, 30),
+            c2s: EndpointLimits::new(120, 60, 20),
+            search: EndpointLimits::new(60, 0, 10),
+            media: EndpointLimits::new(300, 0, 60),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("RATE_LIMIT_ENABLED", &mut self.enabled)?;
+        env.set(
+            "RATE_LIMIT_TRUST_FORWARDED_FOR",
+            &mut self.trust_forwarded_for,
+        )?;
+        env.set("RATE_LIMIT_TRUSTED_PROXIES", &mut self.trusted_proxies)?;
+        env.set("RATE_LIMIT_MAX_TRACKED", &mut self.max_tracked)?;
+        self.inbox.apply_env("RATE_LIMIT_INBOX", env)?;
+        self.outbox.apply_env("RATE_LIMIT_OUTBOX", env)?;
+        self.c2s.apply_env("RATE_LIMIT_C2S", env)?;
+        self.search.apply_env("RATE_LIMIT_SEARCH", env)?;
+        self.media.apply_env("RATE_LIMIT_MEDIA", env)
+    }
+
+    /// Check the proxy count, reporting errors under `key`
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.trusted_proxies", key), self.trusted_proxies)
+    }
+
+    fn limits(&self, class: EndpointClass) -> EndpointLimits {
+        match class {
+            EndpointClass::Inbox => self.inbox,
+            EndpointClass::Outbox => self.outbox,
+            EndpointClass::C2s => self.c2s,
+            EndpointClass::Search => self.search,
+            EndpointClass::Media => self.media,
+        }
+    }
+}
+
+/// Limits overridden by a domain
+#[derive(Debug, Clone, Default, Deserialize)]
+struct LimitOverride {
+    per_ip_per_minute: Option<u32>,
+    per_actor_per_minute: Option<u32>,
+    burst: Option<u32>,
+}
+
+/// The `rate_limits` object of a domain configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+struct DomainOverrides {
+    inbox: Option<LimitOverride>,
+    outbox: Option<LimitOverride>,
+    c2s: Option<LimitOverride>,
+    search: Option<LimitOverride>,
+    media: Option<LimitOverride>,
+}
+
+impl DomainOverrides {
+    fn get(&self, class: EndpointClass) -> Option<&LimitOverride> {
+        match class {
+            EndpointClass::Inbox => self.inbox.as_ref(),
+            EndpointClass::Outbox => self.outbox.as_ref(),
+            EndpointClass::C2s => self.c2s.as_ref(),
+            EndpointClass::Search => self.search.as_ref(),
+            EndpointClass::Media => self.media.as_ref(),
+        }
+    }
+}
+
+/// Token bucket refilled at a steady rate
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(capacity: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Take a token, or return how long until the next one is available
+    fn take(&mut self, now: Instant, per_minute: u32, capacity: f64) -> Result<(), Duration> {
+        let rate = f64::from(per_minute) / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// Token buckets of all limited clients and actors
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    domains: Arc<DomainConfigCache>,
+    buckets: Cache<String, Arc<Mutex<TokenBucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, domains: Arc<DomainConfigCache>) -> Self {
+        Self {
+            buckets: Cache::builder()
+                .max_capacity(config.max_tracked)
+                .time_to_idle(BUCKET_IDLE_TIMEOUT)
+                .build(),
+            config,
+            domains,
+        }
+    }
+
+    /// Limits of an endpoint class on a domain
+    async fn limits(&self, domain: Option<&str>, class: EndpointClass) -> EndpointLimits {
+        let limits = self.config.limits(class);
+        let Some(domain) = domain else {
+            return limits;
+        };
+
+        let overrides: DomainOverrides = self.domains.section(domain, "rate_limits").await;
+        match overrides.get(class) {
+            Some(limit_override) => limits.with_override(limit_override),
+            None => limits,
+        }
+    }
+
+    /// Take a token from the bucket of `key`
+    fn take(&self, key: String, per_minute: u32, burst: u32) -> Result<(), Duration> {
+        if per_minute == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let capacity = f64::from(burst.max(1));
+        let bucket = self.buckets.get_with(key, || {
+            Arc::new(Mutex::new(TokenBucket::full(capacity, now)))
+        });
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.take(now, per_minute, capacity)
+    }
+
+    /// IP of the client making a request
+    ///
+    /// Behind trusted proxies, the entry the outermost proxy appended to
+    /// `X-Forwarded-For`; the connection's address when the header has
+    /// fewer entries than there are proxies.
+    fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        if self.config.trust_forwarded_for {
+            let forwarded = request
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .collect::<Vec<_>>();
+            let forwarded = forwarded
+                .len()
+                .checked_sub(self.config.trusted_proxies)
+                .and_then(|index| forwarded[index].trim().parse().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+    }
+}
+
+/// Limit requests per client IP
+pub async fn limit_clients(
+    State((limiter, class)): State<(Arc<RateLimiter>, EndpointClass)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !limiter.config.enabled {
+        return next.run(request).await;
+    }
+    let Some(ip) = limiter.client_ip(&request) else {
+        return next.run(request).await;
+    };
+
+    let domain = extract_domain_from_headers(request.headers());
+    let limits = limiter.limits(domain.as_deref(), class).await;
+    let key = format!("{}:ip:{}", class.as_str(), ip);
+    match limiter.take(key, limits.per_ip_per_minute, limits.burst) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            debug!("Rate limited {} requests from {}", class.as_str(), ip);
+            too_many_requests(retry_after)
+        }
+    }
+}
+
+/// Limit requests per actor
+///
+/// On inboxes this must run after signature verification, which provides
+/// the signing actor.
+pub async fn limit_actors(
+    State((limiter, class)): State<(Arc<RateLimiter>, EndpointClass)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !limiter.config.enabled {
+        return next.run(request).await;
+    }
+
+    let domain = extract_domain_from_headers(request.headers());
+    let Some(actor) = request_actor(&request, domain.as_deref()) else {
+        return next.run(request).await;
+    };
+
+    let limits = limiter.limits(domain.as_deref(), class).await;
+    let key = format!("{}:actor:{}", class.as_str(), actor);
+    match limiter.take(key, limits.per_actor_per_minute, limits.burst) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            debug!("Rate limited {} requests by {}", class.as_str(), actor);
+            too_many_requests(retry_after)
+        }
+    }
+}
+
+/// Actor a request is made by or for
+///
+/// The verified signer if there is one, otherwise the local actor named in
+/// a `/users/{username}/...` path.
+fn request_actor(request: &Request, domain: Option<&str>) -> Option<String> {
+    if let Some(signer) = request.extensions().get::<VerifiedSigner>() {
+        return Some(signer.owner.clone());
+    }
+
+    let mut segments = request.uri().path().trim_start_matches('/').split('/');
+    match (segments.next(), segments.next()) {
+        (Some("users"), Some(username)) if !username.is_empty() => {
+            Some(format!("{}@{}", username, domain.unwrap_or_default()))
+        }
+        _ => None,
+    }
+}
+
+/// 429 response telling the client when to retry
+fn too_many_requests(retry_after: Duration) -> Response {
+    let mut headers = HeaderMap::new();
+    let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    headers.insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    (StatusCode::TOO_MANY_REQUESTS, headers, "Too many requests").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_burst_and_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(3.0, start);
+        for _ in 0..3 {
+            assert!(bucket.take(start, 60, 3.0).is_ok());
+        }
+        // At 60 per minute the next token is a second away
+        assert_eq!(bucket.take(start, 60, 3.0), Err(Duration::from_secs(1)));
+
+        let later = start + Duration::from_secs(1);
+        assert!(bucket.take(later, 60, 3.0).is_ok());
+        assert!(bucket.take(later, 60, 3.0).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_refills_up_to_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(2.0, start);
+        assert!(bucket.take(start, 60, 2.0).is_ok());
+        assert!(bucket.take(start, 60, 2.0).is_ok());
+
+        let idle = start + Duration::from_secs(3600);
+        assert!(bucket.take(idle, 60, 2.0).is_ok());
+        assert!(bucket.take(idle, 60, 2.0).is_ok());
+        assert!(bucket.take(idle, 60, 2.0).is_err());
+    }
+
+    #[test]
+    fn test_limit_override() {
+        let limits = EndpointLimits::new(600, 300, 100).with_override(&LimitOverride {
+            per_ip_per_minute: Some(1200),
+            per_actor_per_minute: None,
+            burst: Some(10),
+        });
+        assert_eq!(limits.per_ip_per_minute, 1200);
+        assert_eq!(limits.per_actor_per_minute, 300);
+        assert_eq!(limits.burst, 10);
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_counts_trusted_proxies_from_the_right() {
+        let domains = crate::testing::state().await.domain_config;
+        let limiter = |trust_forwarded_for, trusted_proxies| {
+            let config = RateLimitConfig {
+                trust_forwarded_for,
+                trusted_proxies,
+                ..Default::default()
+            };
+            RateLimiter::new(config, domains.clone())
+        };
+        let request = |forwarded_for: &[&str]| {
+            let mut request = Request::new(axum::body::Body::empty());
+            for value in forwarded_for {
+                request
+                    .headers_mut()
+                    .append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+            }
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 443))));
+            request
+        };
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        // A client spoofing the leftmost entry is still seen at its address
+        let one_proxy = limiter(true, 1);
+        assert_eq!(
+            one_proxy.client_ip(&request(&["1.2.3.4, 203.0.113.7"])),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            one_proxy.client_ip(&request(&["1.2.3.4", "203.0.113.7"])),
+            ip("203.0.113.7")
+        );
+        assert_eq!(one_proxy.client_ip(&request(&[])), ip("10.0.0.2"));
+
+        let two_proxies = limiter(true, 2);
+        assert_eq!(
+            two_proxies.client_ip(&request(&["1.2.3.4, 203.0.113.7, 10.0.0.1"])),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            two_proxies.client_ip(&request(&["203.0.113.7"])),
+            ip("10.0.0.2")
+        );
+
+        // Without trusting the header, the connection's address counts
+        assert_eq!(
+            limiter(false, 1).client_ip(&request(&["203.0.113.7"])),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_too_many_requests_rounds_retry_after_up() {
+        let response = too_many_requests(Duration::from_millis(1500));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+        let response = too_many_requests(Duration::from_millis(10));
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+}
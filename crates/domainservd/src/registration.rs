@@ -0,0 +1,526 @@
+//! Sign-up of local users
+//!
+//! Users sign up at `POST /api/v1/accounts` on the domain they want an
+//! account on. What happens depends on the domain's registration mode:
+//!
+//! - `open` creates the account at once.
+//! - `approval` stores an application that moderators approve or reject
+//!   through adminservd, which sends a [`RegistrationReviewMessage`].
+//! - `invite` creates the account for a valid invite code. Administrators
+//!   create codes through adminservd, which sends an [`InviteCreateMessage`]
+//!   with the code already hashed.
+//! - `closed` turns every sign-up down.
+//!
+//! Users who give an email address are told by email when their application
+//! is received, their account is active or their application is rejected.
+
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use chrono::Utc;
+use oxifed::credentials::{
+    MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH, hash_password, is_acceptable_password, token_hash,
+};
+use oxifed::database::{
+    ApplicationStatus, DatabaseError, DatabaseManager, DomainStatus, InviteDocument,
+    RegistrationApplicationDocument, RegistrationMode,
+};
+use oxifed::messaging::{
+    ApplicationInfo, EmailKind, EmailMessage, InviteCreateMessage, Page, PageRequest,
+    RegistrationDecision, RegistrationReviewMessage, UserRpcResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::db::MongoDB;
+use crate::rabbitmq::{RabbitMQError, create_local_user, does_domain_exist};
+use crate::ratelimit::{EndpointClass, limit_clients};
+use crate::{AppState, extract_domain_from_headers};
+
+/// Longest username accepted at sign-up
+const MAX_USERNAME_LENGTH: usize = 30;
+
+/// Longest reason for joining accepted with an application
+const MAX_REASON_LENGTH: usize = 1000;
+
+/// Most applications returned by one page
+const MAX_LIMIT: u32 = 200;
+
+/// Routes of the sign-up endpoint
+pub fn registration_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/accounts", post(register))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), EndpointClass::C2s),
+            limit_clients,
+        ))
+}
+
+/// Sign-up form, sent as JSON or form data
+#[derive(Debug, Deserialize)]
+struct RegistrationForm {
+    username: String,
+    password: String,
+    email: Option<String>,
+    /// Reason for joining, shown to moderators in approval mode
+    reason: Option<String>,
+    invite_code: Option<String>,
+}
+
+/// Sign-up failure answered as `{"error": ...}`
+#[derive(Debug)]
+struct RegistrationError {
+    status: StatusCode,
+    message: String,
+}
+
+impl RegistrationError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    fn server_error(e: impl std::fmt::Display) -> Self {
+        error!("Registration failed: {}", e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    }
+}
+
+impl From<DatabaseError> for RegistrationError {
+    fn from(e: DatabaseError) -> Self {
+        Self::server_error(e)
+    }
+}
+
+impl IntoResponse for RegistrationError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Whether a username can be signed up with: 1 to 30 lowercase letters,
+/// digits and underscores
+fn is_valid_username(username: &str) -> bool {
+    (1..=MAX_USERNAME_LENGTH).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Whether an email address looks deliverable
+fn is_valid_email(email: &str) -> bool {
+    email.split_once('@').is_some_and(|(local, host)| {
+        !local.is_empty() && host.contains('.') && !email.contains(char::is_whitespace)
+    })
+}
+
+async fn register(headers: HeaderMap, State(state): State<AppState>, body: Bytes) -> Response {
+    match sign_up(&headers, &state, &body).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn sign_up(
+    headers: &HeaderMap,
+    state: &AppState,
+    body: &Bytes,
+) -> Result<Response, RegistrationError> {
+    let domain = extract_domain_from_headers(headers)
+        .ok_or_else(|| RegistrationError::invalid("Missing Host header"))?;
+    let params = crate::oauth::request_params(headers, body).map_err(RegistrationError::invalid)?;
+    let form: RegistrationForm = serde_json::to_value(params)
+        .and_then(serde_json::from_value)
+        .map_err(|e| RegistrationError::invalid(format!("Invalid sign-up: {}", e)))?;
+
+    let db = &state.db_manager;
+    let domain_doc = db
+        .find_domain_by_name(&domain)
+        .await?
+        .filter(|doc| doc.status == DomainStatus::Active)
+        .ok_or_else(|| {
+            RegistrationError::new(
+                StatusCode::NOT_FOUND,
+                format!("{} does not take sign-ups", domain),
+            )
+        })?;
+    if domain_doc.registration_mode == RegistrationMode::Closed {
+        return Err(RegistrationError::forbidden(format!(
+            "Registrations are closed on {}",
+            domain
+        )));
+    }
+
+    if !is_valid_username(&form.username) {
+        return Err(RegistrationError::invalid(format!(
+            "Usernames have 1 to {} lowercase letters, digits and underscores",
+            MAX_USERNAME_LENGTH
+        )));
+    }
+    if !is_acceptable_password(&form.password) {
+        return Err(RegistrationError::invalid(format!(
+            "Passwords must have {} to {} characters",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        )));
+    }
+    let email = form.email.filter(|email| !email.is_empty());
+    if email.as_deref().is_some_and(|email| !is_valid_email(email)) {
+        return Err(RegistrationError::invalid("Invalid email address"));
+    }
+    if db
+        .find_actor_by_username(&form.username, &domain)
+        .await?
+        .is_some()
+        || db.has_pending_application(&form.username, &domain).await?
+    {
+        return Err(RegistrationError::invalid(format!(
+            "Username {} is taken",
+            form.username
+        )));
+    }
+
+    let password = form.password;
+    let password_hash = tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(RegistrationError::server_error)?;
+
+    match domain_doc.registration_mode {
+        RegistrationMode::Approval => {
+            let reason = form.reason.filter(|reason| !reason.trim().is_empty());
+            if reason
+                .as_ref()
+                .is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH)
+            {
+                return Err(RegistrationError::invalid(format!(
+                    "The reason may have up to {} characters",
+                    MAX_REASON_LENGTH
+                )));
+            }
+            let application_id = uuid::Uuid::new_v4().to_string();
+            db.insert_registration_application(RegistrationApplicationDocument {
+                id: None,
+                application_id: application_id.clone(),
+                username: form.username.clone(),
+                domain: domain.clone(),
+                email: email.clone(),
+                reason,
+                password_hash,
+                status: ApplicationStatus::Pending,
+                reviewed_by: None,
+                review_note: None,
+                created_at: Utc::now(),
+                reviewed_at: None,
+            })
+            .await?;
+            info!(
+                "Application {} for {}@{} awaits review",
+                application_id, form.username, domain
+            );
+            send_registration_email(
+                db,
+                &form.username,
+                &domain,
+                email.as_deref(),
+                ApplicationStatus::Pending,
+                None,
+            )
+            .await;
+            Ok((
+                StatusCode::ACCEPTED,
+                Json(json!({ "id": application_id, "status": "pending" })),
+            )
+                .into_response())
+        }
+        RegistrationMode::Invite => {
+            let code = form
+                .invite_code
+                .filter(|code| !code.is_empty())
+                .ok_or_else(|| {
+                    RegistrationError::forbidden(format!("{} takes sign-ups by invite", domain))
+                })?;
+            if !db.redeem_invite(&token_hash(&code), &domain).await? {
+                return Err(RegistrationError::forbidden(
+                    "The invite code is invalid, expired or used up",
+                ));
+            }
+            activate(state, &form.username, &domain, &password_hash, email).await
+        }
+        RegistrationMode::Open => {
+            activate(state, &form.username, &domain, &password_hash, email).await
+        }
+        RegistrationMode::Closed => unreachable!("closed domains are turned down above"),
+    }
+}
+
+/// Create the account of a sign-up that needs no review
+async fn activate(
+    state: &AppState,
+    username: &str,
+    domain: &str,
+    password_hash: &str,
+    email: Option<String>,
+) -> Result<Response, RegistrationError> {
+    let actor_id = create_account(&state.db, username, domain, password_hash, email.as_deref())
+        .await
+        .map_err(|e| match e {
+            RabbitMQError::ConstraintError(message) => RegistrationError::invalid(message),
+            e => RegistrationError::server_error(e),
+        })?;
+    info!("{} signed up", actor_id);
+    send_registration_email(
+        &state.db_manager,
+        username,
+        domain,
+        email.as_deref(),
+        ApplicationStatus::Approved,
+        None,
+    )
+    .await;
+    Ok(Json(json!({ "id": actor_id, "status": "active" })).into_response())
+}
+
+/// Create a local user with a password and, if given, an email address
+async fn create_account(
+    db: &Arc<MongoDB>,
+    username: &str,
+    domain: &str,
+    password_hash: &str,
+    email: Option<&str>,
+) -> Result<String, RabbitMQError> {
+    let actor_id = create_local_user(db, username, domain, None).await?;
+    let actor = db
+        .manager()
+        .find_actor_by_id(&actor_id)
+        .await?
+        .ok_or_else(|| RabbitMQError::ProfileNotFound(actor_id.clone()))?;
+    db.manager()
+        .set_password_hash(&actor, password_hash)
+        .await?;
+    if let Some(email) = email {
+        db.manager().set_credentials_email(&actor, email).await?;
+    }
+    Ok(actor_id)
+}
+
+/// Tell a user where their sign-up stands, if they gave an email address
+///
+/// A failure is only logged; the sign-up itself has succeeded.
+async fn send_registration_email(
+    db: &DatabaseManager,
+    username: &str,
+    domain: &str,
+    email: Option<&str>,
+    status: ApplicationStatus,
+    note: Option<&str>,
+) {
+    let Some(email) = email else {
+        return;
+    };
+    let status = match status {
+        ApplicationStatus::Pending => "pending",
+        ApplicationStatus::Approved => "active",
+        ApplicationStatus::Rejected => "rejected",
+    };
+    let message = EmailMessage::new(
+        EmailKind::RegistrationConfirmation,
+        domain.to_string(),
+        email.to_string(),
+        json!({
+            "username": username,
+            "status": status,
+            "note": note,
+            "login_url": format!("https://{}/", domain),
+        }),
+    );
+    if let Err(e) = db.queue_email(&message).await {
+        error!(
+            "Failed to queue registration email of {}@{}: {}",
+            username, domain, e
+        );
+    }
+}
+
+/// Approve or reject a pending application
+///
+/// Returns the ID of the actor created for an approved application.
+pub async fn review(
+    db: &Arc<MongoDB>,
+    msg: &RegistrationReviewMessage,
+) -> Result<Option<String>, RabbitMQError> {
+    let application = db
+        .manager()
+        .find_registration_application(&msg.application_id)
+        .await?
+        .filter(|application| application.status == ApplicationStatus::Pending)
+        .ok_or_else(|| {
+            DatabaseError::NotFoundError(format!("Pending application {}", msg.application_id))
+        })?;
+
+    let (status, actor_id) = match msg.decision {
+        RegistrationDecision::Approve => {
+            let actor_id = create_account(
+                db,
+                &application.username,
+                &application.domain,
+                &application.password_hash,
+                application.email.as_deref(),
+            )
+            .await?;
+            (ApplicationStatus::Approved, Some(actor_id))
+        }
+        RegistrationDecision::Reject => (ApplicationStatus::Rejected, None),
+    };
+    db.manager()
+        .review_registration_application(
+            &msg.application_id,
+            status,
+            msg.reviewed_by.as_deref(),
+            msg.note.as_deref(),
+        )
+        .await?;
+
+    info!(
+        "Application {} for {}@{} {:?}",
+        msg.application_id, application.username, application.domain, status
+    );
+    send_registration_email(
+        db.manager(),
+        &application.username,
+        &application.domain,
+        application.email.as_deref(),
+        status,
+        msg.note.as_deref(),
+    )
+    .await;
+    Ok(actor_id)
+}
+
+/// Store an invite code of a domain
+pub async fn create_invite(
+    db: &Arc<MongoDB>,
+    msg: &InviteCreateMessage,
+) -> Result<(), RabbitMQError> {
+    if !does_domain_exist(&msg.domain, db).await {
+        return Err(RabbitMQError::DomainNotFound(msg.domain.clone()));
+    }
+    if msg.max_uses == Some(0) {
+        return Err(RabbitMQError::ConstraintError(
+            "An invite must allow at least one sign-up".to_string(),
+        ));
+    }
+    db.manager()
+        .insert_invite(InviteDocument {
+            id: None,
+            code_hash: msg.code_hash.clone(),
+            domain: msg.domain.clone(),
+            max_uses: msg.max_uses,
+            uses: 0,
+            expires_at: msg.expires_at,
+            created_at: Utc::now(),
+        })
+        .await?;
+    info!("Created an invite for {}", msg.domain);
+    Ok(())
+}
+
+/// Answer a request for a page of registration applications
+pub async fn list_applications(
+    db: &Arc<MongoDB>,
+    request_id: String,
+    domain: Option<&str>,
+    status: Option<&str>,
+    page: PageRequest,
+) -> UserRpcResponse {
+    let status = match status.map(|s| serde_json::from_value(json!(s))).transpose() {
+        Ok(status) => status,
+        Err(_) => {
+            return UserRpcResponse::error(
+                request_id,
+                format!("Invalid application status: {}", status.unwrap_or_default()),
+            );
+        }
+    };
+    let page = PageRequest {
+        limit: page.limit.clamp(1, MAX_LIMIT),
+        ..page
+    };
+    match db
+        .manager()
+        .find_registration_applications_page(domain, status, page.offset, i64::from(page.limit))
+        .await
+    {
+        Ok((applications, total)) => UserRpcResponse::application_list(
+            request_id,
+            Page::new(applications, total, page).map(application_info),
+        ),
+        Err(e) => {
+            error!("Failed to list registration applications: {}", e);
+            UserRpcResponse::error(request_id, format!("Failed to list applications: {}", e))
+        }
+    }
+}
+
+/// Application information for the admin API, leaving out the password
+fn application_info(application: RegistrationApplicationDocument) -> ApplicationInfo {
+    let status = match application.status {
+        ApplicationStatus::Pending => "pending",
+        ApplicationStatus::Approved => "approved",
+        ApplicationStatus::Rejected => "rejected",
+    };
+    ApplicationInfo {
+        application_id: application.application_id,
+        username: application.username,
+        domain: application.domain,
+        email: application.email,
+        reason: application.reason,
+        status: status.to_string(),
+        reviewed_by: application.reviewed_by,
+        review_note: application.review_note,
+        created_at: application.created_at.to_rfc3339(),
+        reviewed_at: application.reviewed_at.map(|at| at.to_rfc3339()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usernames() {
+        assert!(is_valid_username("alice"));
+        assert!(is_valid_username("bob_42"));
+        assert!(!is_valid_username(""));
+        assert!(!is_valid_username("Alice"));
+        assert!(!is_valid_username("alice.smith"));
+        assert!(!is_valid_username("älice"));
+        assert!(!is_valid_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)));
+    }
+
+    #[test]
+    fn test_emails() {
+        assert!(is_valid_email("alice@example.com"));
+        assert!(!is_valid_email("alice"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("alice@localhost"));
+        assert!(!is_valid_email("alice smith@example.com"));
+    }
+}
@@ -0,0 +1,501 @@
+//! Relays
+//!
+//! Every domain has an instance actor, an `Application` at
+//! `https://<domain>/actor` whose inbox is the shared inbox. `oxiadm domain
+//! relay add` makes it follow a relay; once the relay accepts, the
+//! `Announce` activities the relay sends to the shared inbox are unwrapped
+//! and their objects processed like any other incoming object. The relay's
+//! signature does not cover objects it embeds from other servers, so those
+//! are fetched from their origin.
+//!
+//! In relay mode (`RELAY_MODE=true`) the instance actor also accepts
+//! subscriptions, either as a Follow of the instance actor or, as Mastodon
+//! sends it, of the public collection. Public posts a subscriber delivers
+//! to the shared inbox are re-announced to all other subscribers.
+
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use oxifed::client::ActivityPubClient;
+use oxifed::config::{ConfigError, Env};
+use oxifed::database::{
+    ActivityDocument, ActorDocument, ActorRestriction, ActorStatus, DatabaseError, DatabaseManager,
+    FollowDocument, FollowStatus, OutboxMessageDocument,
+};
+use oxifed::messaging::{
+    DeliveryPriority, EXCHANGE_ACTIVITYPUB_DELIVERY, RelaySubscribeMessage, RelayUnsubscribeMessage,
+};
+use oxifed::signature_middleware::VerifiedSigner;
+use oxifed::{Activity, builder::PUBLIC};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{debug, info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::activitypub::actor_json;
+use crate::caching::with_last_modified;
+use crate::db::MongoDB;
+use crate::extract_domain_from_headers;
+use crate::rabbitmq::{
+    RabbitMQError, does_domain_exist, publish_incoming_object_to_exchange, queue_key_generation,
+};
+
+/// Relay settings
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    /// Accept subscriptions and re-announce public posts to subscribers
+    pub mode: bool,
+}
+
+impl RelayConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("RELAY_MODE", &mut self.mode)
+    }
+}
+
+/// ID of a domain's instance actor
+pub fn instance_actor_id(domain: &str) -> String {
+    format!("https://{}/actor", domain)
+}
+
+/// Create the instance actor of a domain unless it exists
+async fn ensure_instance_actor(db: &Arc<MongoDB>, domain: &str) -> Result<(), RabbitMQError> {
+    let actor_id = instance_actor_id(domain);
+    if db.find_actor_by_id(&actor_id).await?.is_some() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let shared_inbox = format!("https://{}/inbox", domain);
+    let actor_doc = ActorDocument {
+        id: None,
+        actor_id: actor_id.clone(),
+        name: domain.to_string(),
+        preferred_username: domain.to_string(),
+        domain: domain.to_string(),
+        actor_type: "Application".to_string(),
+        summary: None,
+        icon: None,
+        image: None,
+        inbox: shared_inbox.clone(),
+        outbox: format!("{}/outbox", actor_id),
+        following: format!("{}/following", actor_id),
+        followers: format!("{}/followers", actor_id),
+        liked: None,
+        featured: None,
+        public_key: None,
+        endpoints: Some(mongodb::bson::doc! { "sharedInbox": shared_inbox }),
+        attachment: None,
+        additional_properties: None,
+        status: ActorStatus::Active,
+        restriction: ActorRestriction::None,
+        created_at: now,
+        updated_at: now,
+        local: true,
+        followers_count: 0,
+        following_count: 0,
+        statuses_count: 0,
+    };
+    db.manager().insert_actor(actor_doc).await?;
+    info!("Created instance actor {}", actor_id);
+
+    queue_key_generation(db, &actor_id).await
+}
+
+/// Store a local activity and queue it for delivery in one write
+pub(crate) async fn queue_activity(
+    db: &DatabaseManager,
+    activity: &Value,
+) -> Result<(), DatabaseError> {
+    let mut activity_doc = ActivityDocument::from_activitypub(activity);
+    activity_doc.local = true;
+    let message = OutboxMessageDocument::new(
+        EXCHANGE_ACTIVITYPUB_DELIVERY,
+        DeliveryPriority::for_activity(activity).routing_key(),
+        Some("application/activity+json"),
+        activity.to_string(),
+    )
+    .with_trace_context(oxifed_telemetry::current_context());
+    db.insert_activity_with_outbox(activity_doc, vec![message])
+        .await?;
+    Ok(())
+}
+
+pub(crate) fn activity_id(domain: &str) -> String {
+    format!("https://{}/activities/{}", domain, Uuid::new_v4())
+}
+
+/// Follow a relay from the instance actor
+pub async fn subscribe(
+    db: &Arc<MongoDB>,
+    msg: &RelaySubscribeMessage,
+) -> Result<(), RabbitMQError> {
+    if !does_domain_exist(&msg.domain, db).await {
+        return Err(RabbitMQError::DomainNotFound(msg.domain.clone()));
+    }
+    Url::parse(&msg.relay)?;
+    ensure_instance_actor(db, &msg.domain).await?;
+
+    let actor_id = instance_actor_id(&msg.domain);
+    let follow = Activity::builder()
+        .follow(&actor_id, &msg.relay)
+        .id(&activity_id(&msg.domain))
+        .published(Utc::now())
+        .to(msg.relay.clone())
+        .with_context()
+        .build()?;
+    let follow_id = follow.id.as_ref().map(Url::to_string).unwrap_or_default();
+
+    // Resubscribing reuses the relationship of an earlier subscription
+    let manager = db.manager();
+    if manager.find_follow(&actor_id, &msg.relay).await?.is_some() {
+        manager
+            .update_follow_status(&actor_id, &msg.relay, FollowStatus::Pending)
+            .await?;
+    } else {
+        manager
+            .insert_follow(FollowDocument {
+                id: None,
+                follower: actor_id.clone(),
+                following: msg.relay.clone(),
+                status: FollowStatus::Pending,
+                activity_id: follow_id,
+                accept_activity_id: None,
+                created_at: Utc::now(),
+                responded_at: None,
+                follower_inbox: None,
+                follower_shared_inbox: None,
+            })
+            .await?;
+    }
+
+    queue_activity(manager, &serde_json::to_value(&follow)?).await?;
+    info!("Subscribing {} to relay {}", msg.domain, msg.relay);
+    Ok(())
+}
+
+/// Undo the instance actor's Follow of a relay
+pub async fn unsubscribe(
+    db: &Arc<MongoDB>,
+    msg: &RelayUnsubscribeMessage,
+) -> Result<(), RabbitMQError> {
+    let actor_id = instance_actor_id(&msg.domain);
+    let manager = db.manager();
+    let Some(follow) = manager.find_follow(&actor_id, &msg.relay).await? else {
+        return Err(RabbitMQError::ConstraintError(format!(
+            "{} is not subscribed to relay {}",
+            msg.domain, msg.relay
+        )));
+    };
+
+    let undo = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Undo",
+        "id": activity_id(&msg.domain),
+        "actor": actor_id,
+        "object": {
+            "type": "Follow",
+            "id": follow.activity_id,
+            "actor": actor_id,
+            "object": msg.relay
+        },
+        "to": [msg.relay],
+        "published": Utc::now().to_rfc3339()
+    });
+    queue_activity(manager, &undo).await?;
+    manager
+        .update_follow_status(&actor_id, &msg.relay, FollowStatus::Cancelled)
+        .await?;
+    info!("Unsubscribed {} from relay {}", msg.domain, msg.relay);
+    Ok(())
+}
+
+/// Serve the instance actor of the requested domain
+pub async fn get_instance_actor(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let domain = extract_domain_from_headers(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let actor_doc = state
+        .db_manager
+        .find_actor_by_id(&instance_actor_id(&domain))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let response = (
+        StatusCode::OK,
+        [("Content-Type", "application/activity+json")],
+        Json(actor_json(&actor_doc)),
+    )
+        .into_response();
+    Ok(with_last_modified(response, actor_doc.updated_at))
+}
+
+/// ID of an object or link value
+fn id_of(value: Option<&Value>) -> Option<&str> {
+    match value? {
+        Value::String(id) => Some(id),
+        object => object.get("id")?.as_str(),
+    }
+}
+
+fn is_public(activity: &Value) -> bool {
+    ["to", "cc"].iter().any(|key| match activity.get(*key) {
+        Some(Value::String(recipient)) => recipient == PUBLIC,
+        Some(Value::Array(recipients)) => recipients.iter().any(|r| r.as_str() == Some(PUBLIC)),
+        _ => false,
+    })
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
+
+/// Handle relay traffic arriving at the shared inbox
+///
+/// Returns `None` for activities that are not relay traffic; they take the
+/// regular shared inbox path. Public posts from relay subscribers are
+/// re-announced and then also take the regular path.
+pub async fn handle_shared_inbox(
+    activity: &Value,
+    signer: Option<&VerifiedSigner>,
+    state: &AppState,
+    domain: &str,
+) -> Option<Result<(), String>> {
+    let actor = activity.get("actor").and_then(Value::as_str)?;
+    if signer.is_some_and(|signer| signer.owner != actor) {
+        return None;
+    }
+    let instance = instance_actor_id(domain);
+    let db = &state.db_manager;
+    let follow_status = |follower: String, following: String| async move {
+        db.find_follow(&follower, &following)
+            .await
+            .ok()
+            .flatten()
+            .map(|follow| follow.status)
+    };
+
+    match activity.get("type").and_then(Value::as_str)? {
+        // A relay answering our subscription
+        "Accept" | "Reject" => {
+            follow_status(instance.clone(), actor.to_string()).await?;
+            let status = if activity["type"] == "Accept" {
+                FollowStatus::Accepted
+            } else {
+                FollowStatus::Rejected
+            };
+            info!("Relay {} answered subscription: {:?}", actor, status);
+            Some(
+                db.update_follow_status(&instance, actor, status)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("Failed to update relay subscription: {}", e)),
+            )
+        }
+        "Announce" => {
+            if follow_status(instance.clone(), actor.to_string()).await
+                != Some(FollowStatus::Accepted)
+            {
+                return None;
+            }
+            Some(accept_relayed(activity, actor, state, domain).await)
+        }
+        "Follow" => {
+            let object = id_of(activity.get("object"))?;
+            if object != instance && object != PUBLIC {
+                return None;
+            }
+            Some(answer_subscription(activity, actor, state, domain).await)
+        }
+        "Undo" => {
+            let follow = activity.get("object")?;
+            let object = id_of(follow.get("object"))?;
+            if follow.get("type").and_then(Value::as_str) != Some("Follow")
+                || (object != instance && object != PUBLIC)
+            {
+                return None;
+            }
+            info!("Relay subscriber {} unsubscribed", actor);
+            Some(
+                db.update_follow_status(actor, &instance, FollowStatus::Cancelled)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("Failed to cancel relay subscription: {}", e)),
+            )
+        }
+        "Create" if state.relay.mode && is_public(activity) => {
+            if follow_status(actor.to_string(), instance.clone()).await
+                == Some(FollowStatus::Accepted)
+                && let Err(e) = reannounce(activity, actor, state, domain).await
+            {
+                warn!("Failed to re-announce post from {}: {}", actor, e);
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Process the object of an Announce sent by a relay
+async fn accept_relayed(
+    activity: &Value,
+    relay: &str,
+    state: &AppState,
+    domain: &str,
+) -> Result<(), String> {
+    let mut object = activity
+        .get("object")
+        .cloned()
+        .ok_or("Relayed Announce without object")?;
+    // Some relays wrap the post in the Create they received
+    if object.get("type").and_then(Value::as_str) == Some("Create") {
+        object = object.get("object").cloned().unwrap_or(Value::Null);
+    }
+    let object_id = id_of(Some(&object))
+        .ok_or("Relayed object without id")?
+        .to_string();
+
+    if object.is_string() || host(&object_id) != host(relay) {
+        debug!("Fetching relayed object {}", object_id);
+        let url = Url::parse(&object_id).map_err(|e| e.to_string())?;
+        let client = ActivityPubClient::new().map_err(|e| e.to_string())?;
+        let fetched = client
+            .fetch_object(&url)
+            .await
+            .map_err(|e| format!("Failed to fetch relayed object {}: {}", object_id, e))?;
+        object = serde_json::to_value(fetched).map_err(|e| e.to_string())?;
+    }
+
+    let object_type = object
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or("Object")
+        .to_string();
+    let attributed_to = object
+        .get("attributedTo")
+        .and_then(Value::as_str)
+        .ok_or("Relayed object without author")?
+        .to_string();
+    publish_incoming_object_to_exchange(
+        &state.mq_pool,
+        state.domain_routing,
+        &object,
+        &object_type,
+        &attributed_to,
+        domain,
+        None,
+        Some(relay),
+    )
+    .await
+    .map_err(|e| format!("Failed to publish relayed object: {}", e))
+}
+
+/// Accept or, outside relay mode, reject a relay subscription
+async fn answer_subscription(
+    follow: &Value,
+    subscriber: &str,
+    state: &AppState,
+    domain: &str,
+) -> Result<(), String> {
+    let instance = instance_actor_id(domain);
+    let (answer, status) = if state.relay.mode {
+        ("Accept", FollowStatus::Accepted)
+    } else {
+        ("Reject", FollowStatus::Rejected)
+    };
+    info!("{}ing relay subscription of {}", answer, subscriber);
+    ensure_instance_actor(&state.db, domain)
+        .await
+        .map_err(|e| format!("Failed to create instance actor: {}", e))?;
+
+    let db = &state.db_manager;
+    let result = if db
+        .find_follow(subscriber, &instance)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        db.update_follow_status(subscriber, &instance, status)
+            .await
+            .map(|_| ())
+    } else {
+        db.insert_follow(FollowDocument {
+            id: None,
+            follower: subscriber.to_string(),
+            following: instance.clone(),
+            status,
+            activity_id: id_of(Some(follow)).unwrap_or("unknown").to_string(),
+            accept_activity_id: None,
+            created_at: Utc::now(),
+            responded_at: Some(Utc::now()),
+            follower_inbox: None,
+            follower_shared_inbox: None,
+        })
+        .await
+        .map(|_| ())
+    };
+    result.map_err(|e| format!("Failed to store relay subscription: {}", e))?;
+
+    let response = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": answer,
+        "id": activity_id(domain),
+        "actor": instance,
+        "object": follow,
+        "to": [subscriber],
+        "published": Utc::now().to_rfc3339()
+    });
+    queue_activity(db, &response)
+        .await
+        .map_err(|e| format!("Failed to queue {}: {}", answer, e))
+}
+
+/// Announce a subscriber's public post to all other subscribers
+async fn reannounce(
+    create: &Value,
+    origin: &str,
+    state: &AppState,
+    domain: &str,
+) -> Result<(), String> {
+    let object_id = id_of(create.get("object")).ok_or("Create without object id")?;
+    let instance = instance_actor_id(domain);
+    let subscribers: Vec<String> = state
+        .db_manager
+        .get_actor_followers(&instance)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|subscriber| subscriber != origin)
+        .collect();
+    if subscribers.is_empty() {
+        return Ok(());
+    }
+
+    let announce = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Announce",
+        "id": activity_id(domain),
+        "actor": instance,
+        "object": object_id,
+        "to": subscribers,
+        "cc": [PUBLIC],
+        "published": Utc::now().to_rfc3339()
+    });
+    queue_activity(&state.db_manager, &announce)
+        .await
+        .map_err(|e| e.to_string())?;
+    debug!("Re-announced {} to relay subscribers", object_id);
+    Ok(())
+}
@@ -0,0 +1,268 @@
+//! Replay of stored activities through the incoming pipeline
+//!
+//! Administrators replay stored remote activities after fixing a bug in a
+//! pipeline stage, selected by storage ID range, actor, type or time
+//! window. Activities are published to the incoming exchange of a domain
+//! the same way the inbox publishes them, the objects of `Create`s as
+//! stored, so they pass every stage again. The inbox handlers themselves
+//! do not run, so nothing is accepted, forwarded or delivered again; the
+//! messages carry a [`ReplayMarker`] and stages leave out webhooks and
+//! notifications unless the replay allows delivery.
+
+use std::sync::Arc;
+
+use deadpool_lapin::Pool;
+use futures::StreamExt;
+use lapin::{
+    BasicProperties, Channel,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions},
+    types::FieldTable,
+};
+use oxifed::ActivityType;
+use oxifed::database::{ActivityDocument, DatabaseManager};
+use oxifed::messaging::{
+    IncomingActivityMessage, IncomingObjectMessage, Message, MessageEnum, QUEUE_RPC_REPLAY,
+    ReplayMarker, ReplayRpcRequest, ReplayRpcResponse, ReplaySummary, ReplayedActivity,
+};
+use oxifed::shutdown::Shutdown;
+use tracing::{error, info, warn};
+
+use crate::rabbitmq::{RabbitMQError, publish_incoming, spawn_channel_task};
+
+pub const REPLAY_RPC_CONSUMER_TAG: &str = "rpc_replay_consumer";
+
+/// Activities one replay request covers at most
+pub const MAX_REPLAY_ACTIVITIES: u32 = 1000;
+
+/// Start the replay RPC consumer
+pub fn start_replay_consumer(
+    pool: Pool,
+    db: Arc<DatabaseManager>,
+    domain_routing: bool,
+    shutdown: &Shutdown,
+) {
+    spawn_channel_task("replay RPC consumer", pool.clone(), shutdown, {
+        move |channel, shutdown| {
+            run_rpc_consumer(channel, pool.clone(), db.clone(), domain_routing, shutdown)
+        }
+    });
+}
+
+/// Serve replay RPC requests
+async fn run_rpc_consumer(
+    channel: Channel,
+    pool: Pool,
+    db: Arc<DatabaseManager>,
+    domain_routing: bool,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_RPC_REPLAY,
+            REPLAY_RPC_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Replay RPC consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+            error!("Failed to ack replay RPC message: {}", e);
+        }
+
+        let request = match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::ReplayRpcRequest(request)) => request,
+            Ok(_) => {
+                warn!("Received non-replay message on replay RPC queue");
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to parse replay RPC message: {}", e);
+                continue;
+            }
+        };
+
+        let request_id = request.request_id.clone();
+        let response = match replay(&pool, &db, domain_routing, request).await {
+            Ok(summary) => ReplayRpcResponse::replayed(request_id, summary),
+            Err(message) => ReplayRpcResponse::error(request_id, message),
+        };
+
+        let Some(reply_to) = delivery.properties.reply_to() else {
+            warn!("Replay RPC request has no reply_to queue");
+            continue;
+        };
+        let correlation_id = delivery
+            .properties
+            .correlation_id()
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string().into());
+
+        let payload = serde_json::to_vec(&response.to_message())?;
+        if let Err(e) = channel
+            .basic_publish(
+                "",
+                reply_to.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_correlation_id(correlation_id),
+            )
+            .await
+        {
+            error!("Failed to send replay RPC response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Select the activities of a request and, unless it is a dry run, publish
+/// them to the pipeline oldest first
+async fn replay(
+    pool: &Pool,
+    db: &DatabaseManager,
+    domain_routing: bool,
+    request: ReplayRpcRequest,
+) -> Result<ReplaySummary, String> {
+    let filter = &request.filter;
+    if filter.domain.is_empty() {
+        return Err("A replay needs the domain whose pipeline to use".to_string());
+    }
+    if filter.is_unbounded() {
+        return Err(
+            "Refusing to replay every stored activity, narrow the replay down by storage ID, \
+             actor, type or time"
+                .to_string(),
+        );
+    }
+    if request.limit == 0 || request.limit > MAX_REPLAY_ACTIVITIES {
+        return Err(format!(
+            "The limit must be between 1 and {}",
+            MAX_REPLAY_ACTIVITIES
+        ));
+    }
+
+    // One more than the limit tells where a following replay continues
+    let mut activities = db
+        .find_replay_activities(filter, i64::from(request.limit) + 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    let next_from_id = activities
+        .get(request.limit as usize)
+        .and_then(|next| next.id.map(|id| id.to_hex()));
+    activities.truncate(request.limit as usize);
+
+    let marker = ReplayMarker {
+        replay_id: uuid::Uuid::new_v4().to_string(),
+        allow_delivery: request.allow_delivery,
+    };
+    let mut published = 0;
+    if !request.dry_run {
+        info!(
+            "Replay {} of {} activities to {} (delivery {})",
+            marker.replay_id,
+            activities.len(),
+            filter.domain,
+            if marker.allow_delivery {
+                "allowed"
+            } else {
+                "suppressed"
+            }
+        );
+        for activity in &activities {
+            published +=
+                publish_activity(pool, db, domain_routing, &filter.domain, activity, &marker)
+                    .await
+                    .map_err(|e| {
+                        format!(
+                            "Replay {} stopped at {} after {} messages: {}",
+                            marker.replay_id, activity.activity_id, published, e
+                        )
+                    })?;
+        }
+    }
+
+    Ok(ReplaySummary {
+        replay_id: marker.replay_id,
+        dry_run: request.dry_run,
+        published,
+        activities: activities.iter().map(replayed_activity).collect(),
+        next_from_id,
+    })
+}
+
+/// Publish a stored activity, and the stored object of a `Create`, to the
+/// pipeline of `domain`, returning the number of messages published
+async fn publish_activity(
+    pool: &Pool,
+    db: &DatabaseManager,
+    domain_routing: bool,
+    domain: &str,
+    activity: &ActivityDocument,
+    marker: &ReplayMarker,
+) -> Result<u64, String> {
+    let received_at = chrono::Utc::now().to_rfc3339();
+    let source = Some(format!("replay:{}", marker.replay_id));
+    let mut published = 0;
+
+    if activity.activity_type == ActivityType::Create
+        && let Some(object_id) = &activity.object
+    {
+        match db
+            .find_object_by_id(object_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            Some(object) => {
+                let object_json = object.to_activitypub();
+                let message = IncomingObjectMessage {
+                    object: object_json.clone(),
+                    object_type: format!("{:?}", object.object_type),
+                    attributed_to: object.attributed_to.clone(),
+                    target_domain: domain.to_string(),
+                    target_username: None,
+                    received_at: received_at.clone(),
+                    source: source.clone(),
+                    replay: Some(marker.clone()),
+                };
+                publish_incoming(pool, domain_routing, &object_json, domain, &message)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                published += 1;
+            }
+            None => warn!(
+                "Object {} of replayed {} is not stored, replaying the activity only",
+                object_id, activity.activity_id
+            ),
+        }
+    }
+
+    let activity_json = activity.to_activitypub();
+    let message = IncomingActivityMessage {
+        activity: activity_json.clone(),
+        activity_type: format!("{:?}", activity.activity_type),
+        actor: activity.actor.clone(),
+        target_domain: domain.to_string(),
+        target_username: None,
+        received_at,
+        source,
+        replay: Some(marker.clone()),
+    };
+    publish_incoming(pool, domain_routing, &activity_json, domain, &message)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(published + 1)
+}
+
+fn replayed_activity(activity: &ActivityDocument) -> ReplayedActivity {
+    ReplayedActivity {
+        storage_id: activity.id.map(|id| id.to_hex()).unwrap_or_default(),
+        activity_id: activity.activity_id.clone(),
+        activity_type: format!("{:?}", activity.activity_type),
+        actor: activity.actor.clone(),
+        created_at: activity.created_at.to_rfc3339(),
+    }
+}
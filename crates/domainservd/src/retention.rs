@@ -0,0 +1,307 @@
+//! Retention of remote content
+//!
+//! Remote posts and activities are copies of what their home servers keep.
+//! With a maximum age configured, the pruning job deletes remote posts
+//! published longer ago than that, unless a local account interacted with
+//! them: posts a local account favourited (liked), announced or replied to,
+//! and posts mentioning a local account, are kept. By default only public
+//! posts are pruned. Remote activities are pruned by the age of their
+//! record, except the Follows, Likes, Announces and Blocks a later `Undo`
+//! may refer to.
+//!
+//! Each run walks the candidates in storage order in batches, so one run
+//! finishes even when posts are kept. Its progress is logged and reported
+//! as the `remote_retention` component of domainservd's health report.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use oxifed::config::{ConfigError, Env, require_positive};
+use oxifed::database::{DatabaseError, DatabaseManager, VisibilityLevel};
+use oxifed::health::ComponentHealth;
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+/// Name of the health component reporting the pruning job
+const COMPONENT: &str = "remote_retention";
+
+/// Retention policy of remote content
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Prune remote posts published this many days ago; never when unset
+    pub remote_post_max_age_days: Option<u32>,
+    /// Visibility levels of the posts pruned; all when empty
+    pub visibility: Vec<VisibilityLevel>,
+    /// Prune remote activities stored this many days ago; never when unset
+    pub remote_activity_max_age_days: Option<u32>,
+    /// Pause between pruning runs, in seconds
+    pub interval_secs: u64,
+    /// Posts or activities handled per step
+    pub batch_size: i64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            remote_post_max_age_days: None,
+            visibility: vec![VisibilityLevel::Public],
+            remote_activity_max_age_days: None,
+            interval_secs: 3600,
+            batch_size: 500,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Apply overrides from environment variables
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set_opt(
+            "RETENTION_REMOTE_POST_MAX_AGE_DAYS",
+            &mut self.remote_post_max_age_days,
+        )?;
+        env.set_opt(
+            "RETENTION_REMOTE_ACTIVITY_MAX_AGE_DAYS",
+            &mut self.remote_activity_max_age_days,
+        )?;
+        env.set("RETENTION_INTERVAL_SECS", &mut self.interval_secs)?;
+        env.set("RETENTION_BATCH_SIZE", &mut self.batch_size)
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.interval_secs", key), self.interval_secs)?;
+        if self.batch_size <= 0 {
+            return Err(ConfigError::invalid(
+                format!("{}.batch_size", key),
+                "must be greater than zero",
+            ));
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.remote_post_max_age_days.is_some() || self.remote_activity_max_age_days.is_some()
+    }
+}
+
+/// Progress of the current or last pruning run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PruneProgress {
+    pub running: bool,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Remote posts old enough to be pruned
+    pub posts_examined: u64,
+    pub posts_pruned: u64,
+    /// Posts kept for their local interactions
+    pub posts_kept: u64,
+    pub activities_pruned: u64,
+}
+
+/// Shared view of the pruning job's progress
+#[derive(Debug, Clone, Default)]
+pub struct RetentionStatus {
+    enabled: bool,
+    progress: Arc<Mutex<PruneProgress>>,
+}
+
+impl RetentionStatus {
+    pub fn new(config: &RetentionConfig) -> Self {
+        Self {
+            enabled: config.is_enabled(),
+            progress: Arc::default(),
+        }
+    }
+
+    pub fn progress(&self) -> PruneProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Health component reporting the last run
+    ///
+    /// `None` while retention is disabled. A failed run is reported as
+    /// degraded; remote content then keeps growing until the next run.
+    pub fn health(&self) -> Option<ComponentHealth> {
+        if !self.enabled {
+            return None;
+        }
+        let progress = self.progress();
+        let summary = format!(
+            "{} posts and {} activities pruned, {} posts kept",
+            progress.posts_pruned, progress.activities_pruned, progress.posts_kept
+        );
+        Some(match (&progress.error, progress.running) {
+            (Some(error), _) => {
+                ComponentHealth::degraded(COMPONENT, format!("last run failed: {}", error))
+            }
+            (None, true) => ComponentHealth {
+                detail: Some(format!("running: {}", summary)),
+                ..ComponentHealth::healthy(COMPONENT)
+            },
+            (None, false) => ComponentHealth {
+                detail: progress
+                    .finished_at
+                    .map(|finished| format!("{} at {}", summary, finished.to_rfc3339())),
+                ..ComponentHealth::healthy(COMPONENT)
+            },
+        })
+    }
+
+    fn update(&self, change: impl FnOnce(&mut PruneProgress)) {
+        change(&mut self.progress.lock().unwrap());
+    }
+}
+
+/// Start pruning remote content, if a maximum age is configured
+pub fn start_pruner(
+    db: Arc<DatabaseManager>,
+    config: RetentionConfig,
+    status: RetentionStatus,
+    shutdown: &Shutdown,
+) {
+    if !config.is_enabled() {
+        debug!("Remote content retention is not configured");
+        return;
+    }
+    info!(
+        "Starting remote content pruning (posts: {:?} days, activities: {:?} days)",
+        config.remote_post_max_age_days, config.remote_activity_max_age_days
+    );
+    shutdown.spawn(run_pruner(db, config, status, shutdown.clone()));
+}
+
+/// Prune periodically until shutdown begins
+async fn run_pruner(
+    db: Arc<DatabaseManager>,
+    config: RetentionConfig,
+    status: RetentionStatus,
+    shutdown: Shutdown,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        status.update(|progress| {
+            *progress = PruneProgress {
+                running: true,
+                started_at: Some(Utc::now()),
+                ..PruneProgress::default()
+            }
+        });
+        let result = prune(&db, &config, &status, &shutdown).await;
+        status.update(|progress| {
+            progress.running = false;
+            progress.finished_at = Some(Utc::now());
+            progress.error = result.as_ref().err().map(ToString::to_string);
+        });
+
+        let progress = status.progress();
+        match result {
+            Ok(()) => info!(
+                "Pruned {} remote posts ({} kept for local interactions) and {} remote activities",
+                progress.posts_pruned, progress.posts_kept, progress.activities_pruned
+            ),
+            Err(e) => warn!(
+                "Remote content pruning failed after {} posts and {} activities: {}",
+                progress.posts_pruned, progress.activities_pruned, e
+            ),
+        }
+    }
+}
+
+/// Run one pass over the remote posts and activities
+async fn prune(
+    db: &DatabaseManager,
+    config: &RetentionConfig,
+    status: &RetentionStatus,
+    shutdown: &Shutdown,
+) -> Result<(), DatabaseError> {
+    if let Some(days) = config.remote_post_max_age_days {
+        let before = Utc::now() - chrono::Duration::days(days.into());
+        let mut after = None;
+        while !shutdown.is_triggered() {
+            let batch = db
+                .find_remote_objects_before(before, &config.visibility, after, config.batch_size)
+                .await?;
+            let Some((last, _)) = batch.last() else {
+                break;
+            };
+            after = Some(*last);
+
+            let object_ids: Vec<String> = batch.into_iter().map(|(_, id)| id).collect();
+            let referenced = db.locally_referenced_objects(&object_ids).await?;
+            let prunable: Vec<String> = object_ids
+                .iter()
+                .filter(|id| !referenced.contains(*id))
+                .cloned()
+                .collect();
+            let pruned = db.delete_remote_objects(&prunable).await?;
+            status.update(|progress| {
+                progress.posts_examined += object_ids.len() as u64;
+                progress.posts_pruned += pruned;
+                progress.posts_kept += referenced.len() as u64;
+            });
+            debug!("Pruned {} of {} remote posts", pruned, object_ids.len());
+        }
+    }
+
+    if let Some(days) = config.remote_activity_max_age_days {
+        let before = Utc::now() - chrono::Duration::days(days.into());
+        while !shutdown.is_triggered() {
+            let pruned = db
+                .delete_remote_activities_before(before, config.batch_size)
+                .await?;
+            status.update(|progress| progress.activities_pruned += pruned);
+            if pruned == 0 {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_by_default() {
+        let config = RetentionConfig::default();
+        assert!(!config.is_enabled());
+        assert_eq!(RetentionStatus::new(&config).health(), None);
+
+        let env = Env::from_vars([("RETENTION_REMOTE_POST_MAX_AGE_DAYS", "30")]);
+        let mut config = RetentionConfig::default();
+        config.apply_env(&env).unwrap();
+        assert_eq!(config.remote_post_max_age_days, Some(30));
+        assert!(config.is_enabled());
+    }
+
+    #[test]
+    fn test_health_reports_last_run() {
+        let status = RetentionStatus::new(&RetentionConfig {
+            remote_post_max_age_days: Some(30),
+            ..RetentionConfig::default()
+        });
+        status.update(|progress| {
+            progress.posts_pruned = 12;
+            progress.posts_kept = 3;
+            progress.finished_at = Some(Utc::now());
+        });
+        let health = status.health().unwrap();
+        assert_eq!(health.status, oxifed::health::HealthStatus::Healthy);
+        assert!(
+            health
+                .detail
+                .unwrap()
+                .starts_with("12 posts and 0 activities pruned")
+        );
+
+        status.update(|progress| progress.error = Some("timed out".to_string()));
+        assert_eq!(
+            status.health().unwrap().status,
+            oxifed::health::HealthStatus::Degraded
+        );
+    }
+}
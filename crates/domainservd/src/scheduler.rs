@@ -0,0 +1,144 @@
+//! Scheduled posts
+//!
+//! Notes created with `oxiadm note create --scheduled-at` and C2S objects
+//! whose `published` time lies in the future are stored with the
+//! `Scheduled` status: they are not served or listed and nothing is
+//! delivered yet. The scheduler publishes them once their time has come by
+//! marking them published and queueing their `Create` activity. Scheduled
+//! posts are listed and cancelled through the note RPC queue.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use oxifed::database::{DatabaseManager, ObjectDocument};
+use oxifed::messaging::{NoteRpcRequest, NoteRpcRequestType, NoteRpcResponse, ScheduledNoteInfo};
+use oxifed::shutdown::Shutdown;
+use serde_json::{Value, json};
+use tracing::{error, info, warn};
+
+use crate::relay::queue_activity;
+
+/// How often due posts are looked for
+const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of posts published per scan
+const BATCH_LIMIT: i64 = 100;
+
+/// Start publishing scheduled posts
+pub fn start_scheduler(db: Arc<DatabaseManager>, shutdown: &Shutdown) {
+    info!("Starting post scheduler");
+    shutdown.spawn(run_scheduler(db, shutdown.clone()));
+}
+
+/// Publish due posts until shutdown begins
+async fn run_scheduler(db: Arc<DatabaseManager>, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(SCAN_INTERVAL);
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        let due = match db.due_scheduled_objects(Utc::now(), BATCH_LIMIT).await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to look up scheduled posts: {}", e);
+                continue;
+            }
+        };
+        for object in due {
+            if let Err(e) = publish(&db, &object).await {
+                error!("Failed to publish {}: {}", object.object_id, e);
+            }
+        }
+    }
+}
+
+/// Mark a post published and queue its delivery
+///
+/// A post another instance already published is skipped; one whose
+/// activity cannot be queued goes back to the schedule.
+async fn publish(db: &DatabaseManager, object: &ObjectDocument) -> Result<(), String> {
+    let claimed = db
+        .publish_scheduled_object(&object.object_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !claimed {
+        return Ok(());
+    }
+
+    if let Err(e) = queue_activity(db, &create_activity(object)).await {
+        if let Err(e) = db.reschedule_object(&object.object_id).await {
+            warn!("Failed to reschedule {}: {}", object.object_id, e);
+        }
+        return Err(e.to_string());
+    }
+    if let Err(e) = db.notify_recipients(object).await {
+        warn!("Failed to notify recipients of {}: {}", object.object_id, e);
+    }
+    info!("Published scheduled post {}", object.object_id);
+    Ok(())
+}
+
+/// `Create` activity of a post being published
+fn create_activity(object: &ObjectDocument) -> Value {
+    json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Create",
+        "id": format!("{}/activity", object.object_id),
+        "actor": object.attributed_to,
+        "object": object.to_activitypub(),
+        "to": object.to,
+        "cc": object.cc,
+        "bto": object.bto,
+        "bcc": object.bcc,
+        "published": Utc::now().to_rfc3339()
+    })
+}
+
+/// Answer a note RPC request
+pub async fn handle_rpc(db: &DatabaseManager, request: NoteRpcRequest) -> NoteRpcResponse {
+    let request_id = request.request_id;
+    match request.request_type {
+        NoteRpcRequestType::ListScheduled { actor } => {
+            match db.find_scheduled_objects(actor.as_deref()).await {
+                Ok(objects) => {
+                    let notes = objects
+                        .into_iter()
+                        .map(|object| ScheduledNoteInfo {
+                            scheduled_at: object
+                                .published
+                                .unwrap_or(object.created_at)
+                                .to_rfc3339(),
+                            object_id: object.object_id,
+                            object_type: format!("{:?}", object.object_type),
+                            author: object.attributed_to,
+                            content: object.content,
+                        })
+                        .collect();
+                    NoteRpcResponse::scheduled_list(request_id, notes)
+                }
+                Err(e) => NoteRpcResponse::error(
+                    request_id,
+                    format!("Failed to list scheduled notes: {}", e),
+                ),
+            }
+        }
+        NoteRpcRequestType::ListNotes { actor, page } => {
+            crate::query::list_notes(db, request_id, actor.as_deref(), page).await
+        }
+        NoteRpcRequestType::GetNote { object_id } => {
+            crate::query::get_note(db, request_id, &object_id).await
+        }
+        NoteRpcRequestType::CancelScheduled { object_id } => {
+            match db.cancel_scheduled_object(&object_id).await {
+                Ok(cancelled) => {
+                    if cancelled {
+                        info!("Cancelled scheduled post {}", object_id);
+                    }
+                    NoteRpcResponse::cancelled(request_id, cancelled)
+                }
+                Err(e) => NoteRpcResponse::error(
+                    request_id,
+                    format!("Failed to cancel scheduled note: {}", e),
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Key lookup for inbound HTTP signatures
+//!
+//! Keys of local actors are read from the key store, which also records
+//! revocations, so a revoked key is refused even while remote copies of the
+//! actor still list it. All other keys are fetched from the remote actor and
+//! cached.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures::future::BoxFuture;
+use oxifed::database::DatabaseManager;
+use oxifed::httpsignature::SignatureError;
+use oxifed::signature_middleware::{
+    ActorKey, CachedKeyFetcher, HttpKeyFetcher, KeyFetcher, SignatureVerificationConfig,
+};
+
+/// Looks up stored keys before fetching remote ones
+pub struct StoredKeyFetcher {
+    db_manager: Arc<DatabaseManager>,
+    remote: CachedKeyFetcher<HttpKeyFetcher>,
+}
+
+impl StoredKeyFetcher {
+    pub fn new(
+        db_manager: Arc<DatabaseManager>,
+        config: &SignatureVerificationConfig,
+    ) -> Result<Self, SignatureError> {
+        Ok(Self {
+            db_manager,
+            remote: config.cached(HttpKeyFetcher::new()?),
+        })
+    }
+}
+
+impl KeyFetcher for StoredKeyFetcher {
+    fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>> {
+        Box::pin(async move {
+            let stored = self
+                .db_manager
+                .find_key_by_id(key_id)
+                .await
+                .map_err(|e| SignatureError::KeyNotFound(format!("{}: {}", key_id, e)))?;
+
+            match stored {
+                Some(key) if key.is_revoked(Utc::now()) => {
+                    Err(SignatureError::KeyRevoked(key.key_id))
+                }
+                Some(key) => Ok(ActorKey {
+                    id: key.key_id,
+                    owner: key.actor_id,
+                    public_key_pem: key.public_key_pem,
+                }),
+                None => self.remote.fetch(key_id).await,
+            }
+        })
+    }
+
+    fn invalidate(&self, key_id: &str) {
+        self.remote.invalidate(key_id);
+    }
+}
@@ -0,0 +1,120 @@
+//! Application state for handler tests
+//!
+//! [`state`] connects nowhere: handlers answering before they use the
+//! database or the broker can be tested without either, and requests that
+//! reach them fail quickly. [`state_with_db`] uses MongoDB at
+//! `TEST_MONGODB_URI` and is `None` without it, so tests needing stored data
+//! are skipped.
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use oxifed::database::{DatabaseManager, WriteBatchConfig};
+use oxifed::signature_middleware::{SignatureVerificationConfig, SignatureVerifier};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::db::MongoDB;
+use crate::{
+    AppState, bodylimit, caching, domain_config, health, instances, media, rabbitmq, ratelimit,
+    relay, retention, signatures,
+};
+
+/// Database URI nothing listens on
+const UNREACHABLE_MONGODB_URI: &str = "mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100";
+
+/// State whose database and broker are unreachable
+pub async fn state() -> AppState {
+    let client = mongodb::Client::with_uri_str(UNREACHABLE_MONGODB_URI)
+        .await
+        .unwrap();
+    state_for(DatabaseManager::new(client.database("oxifed_test")))
+}
+
+/// State on a fresh, initialized test database, or `None` without MongoDB
+pub async fn state_with_db() -> Option<AppState> {
+    let mongo_uri = std::env::var("TEST_MONGODB_URI")
+        .unwrap_or_else(|_| "mongodb://localhost:27017/?serverSelectionTimeoutMS=2000".to_string());
+    let client = match mongodb::Client::with_uri_str(&mongo_uri).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Skipping test - MongoDB not available: {}", e);
+            return None;
+        }
+    };
+    let db = DatabaseManager::new(client.database(&format!("test_oxifed_{}", Uuid::new_v4())));
+    if let Err(e) = db.ping().await {
+        eprintln!("Skipping test - MongoDB not available: {}", e);
+        return None;
+    }
+    db.initialize().await.unwrap();
+    Some(state_for(db))
+}
+
+fn state_for(db_manager: DatabaseManager) -> AppState {
+    let db_manager = Arc::new(db_manager);
+    let mq_pool = rabbitmq::create_connection_pool("amqp://127.0.0.1:1");
+    let domain_config = Arc::new(domain_config::DomainConfigCache::new(db_manager.clone()));
+    let signature_config = SignatureVerificationConfig::default();
+    let key_fetcher =
+        signatures::StoredKeyFetcher::new(db_manager.clone(), &signature_config).unwrap();
+
+    AppState {
+        db: Arc::new(MongoDB::with_manager(db_manager.clone())),
+        mq_pool: mq_pool.clone(),
+        db_manager: db_manager.clone(),
+        admin_api_url: None,
+        oidc_issuer_url: None,
+        oidc_audience: None,
+        media_proxy: media::MediaProxyConfig::default(),
+        health: health::HealthChecker::new(
+            db_manager.clone(),
+            mq_pool,
+            false,
+            retention::RetentionStatus::new(&Default::default()),
+        ),
+        signatures: Arc::new(SignatureVerifier::new(
+            Arc::new(key_fetcher),
+            &signature_config,
+        )),
+        rate_limiter: Arc::new(ratelimit::RateLimiter::new(
+            Default::default(),
+            domain_config.clone(),
+        )),
+        body_limiter: Arc::new(bodylimit::BodyLimiter::new(
+            Default::default(),
+            domain_config.clone(),
+        )),
+        domain_config,
+        http_cache: Arc::new(caching::HttpCacheConfig::default()),
+        relay: Arc::new(relay::RelayConfig::default()),
+        activity_writer: oxifed::database::WriteBatcher::spawn(
+            db_manager.clone(),
+            WriteBatchConfig::default(),
+        ),
+        object_writer: oxifed::database::WriteBatcher::spawn(
+            db_manager,
+            WriteBatchConfig::default(),
+        ),
+        domain_routing: false,
+        seen_instances: instances::SeenInstances::default(),
+        peer_profiles: oxifed::compat::PeerProfiles::default(),
+        key_encryptor: oxifed::pki::KeyEncryptor::plaintext(),
+    }
+}
+
+/// Send `request` to `router` and return the status with the JSON body, if
+/// any
+pub async fn send(router: Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let response = router.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+    )
+}
@@ -3,22 +3,114 @@
 //! This module implements the WebFinger protocol as specified in
 //! RFC 7033 (https://datatracker.ietf.org/doc/html/rfc7033).
 //! It provides functionality to serve webfinger resources from disk in JSON format.
+//!
+//! Profiles are cached briefly, and resources without a profile are
+//! remembered as unknown so enumeration scans do not reach MongoDB with
+//! every guess. Creating, deleting and aliasing actors invalidates the
+//! cached entries of their resources in this process; other instances of
+//! the service catch up when the entries expire.
+
+use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
     Json, Router,
     extract::{Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
     routing::get,
 };
-use mongodb::bson::doc;
+use moka::sync::Cache;
+use oxifed::database::{ActorStatus, DatabaseError, DatabaseManager};
 use oxifed::webfinger::{JrdResource, Link};
 use serde::Deserialize;
 use thiserror::Error;
 use tracing::debug;
 use url::Url;
 
-use crate::AppState;
+use crate::privacy::DomainPrivacy;
+use crate::{AppState, extract_domain_from_headers};
+
+/// How long WebFinger profiles are cached
+const PROFILE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How long resources without a profile are remembered as unknown
+const UNKNOWN_CACHE_TTL: Duration = Duration::from_secs(120);
+
+/// Most profiles and unknown resources cached
+const CACHE_SIZE: u64 = 10_000;
+
+/// Briefly cached WebFinger profiles by resource
+pub struct WebFingerCache {
+    db_manager: Arc<DatabaseManager>,
+    profiles: Cache<String, Arc<JrdResource>>,
+    unknown: Cache<String, ()>,
+}
+
+impl WebFingerCache {
+    pub fn new(db_manager: Arc<DatabaseManager>) -> Self {
+        Self {
+            db_manager,
+            profiles: Cache::builder()
+                .max_capacity(CACHE_SIZE)
+                .time_to_live(PROFILE_CACHE_TTL)
+                .build(),
+            unknown: Cache::builder()
+                .max_capacity(CACHE_SIZE)
+                .time_to_live(UNKNOWN_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Profile whose subject or one of whose aliases is `resource`
+    pub async fn profile(&self, resource: &str) -> Result<Option<Arc<JrdResource>>, DatabaseError> {
+        if let Some(profile) = self.profiles.get(resource) {
+            return Ok(Some(profile));
+        }
+        if self.unknown.contains_key(resource) {
+            return Ok(None);
+        }
+
+        // Errors are not cached, so the next request tries again
+        match self.db_manager.find_webfinger_profile(resource).await? {
+            Some(profile) => {
+                let profile = Arc::new(profile);
+                self.profiles.insert(resource.to_string(), profile.clone());
+                Ok(Some(profile))
+            }
+            None => {
+                self.unknown.insert(resource.to_string(), ());
+                Ok(None)
+            }
+        }
+    }
+
+    /// Forget what is cached about `resources`
+    pub fn invalidate<S: AsRef<str>>(&self, resources: &[S]) {
+        for resource in resources {
+            self.profiles.invalidate(resource.as_ref());
+            self.unknown.invalidate(resource.as_ref());
+        }
+    }
+
+    /// Forget what is cached about the resources of `profile`: its subject,
+    /// its aliases and the actor it links to
+    pub fn invalidate_profile(&self, profile: &JrdResource) {
+        let links = profile
+            .find_links("self")
+            .into_iter()
+            .filter_map(|link| link.href.clone());
+        let resources: Vec<String> = profile
+            .subject
+            .iter()
+            .chain(profile.aliases.iter().flatten())
+            .cloned()
+            .chain(links)
+            .collect();
+        self.invalidate(&resources);
+    }
+}
 
 /// WebFinger request parameters as defined in RFC 7033
 #[derive(Debug, Deserialize)]
@@ -43,6 +135,9 @@ pub enum WebfingerError {
     #[error("Database error: {0}")]
     DbError(#[from] mongodb::error::Error),
 
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatabaseError),
+
     #[error("JSON parsing error: {0}")]
     JsonError(#[from] serde_json::Error),
 }
@@ -63,6 +158,7 @@ impl IntoResponse for WebfingerError {
 async fn handle_webfinger(
     Query(query): Query<WebfingerQuery>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Json<JrdResource>, WebfingerError> {
     // Validate the resource format
     if !query.resource.starts_with("acct:")
@@ -99,18 +195,25 @@ async fn handle_webfinger(
     // Use the full resource as the subject for lookup
     let subject = query.resource.replace("act:", "acct:").clone();
 
-    // Query MongoDB for the JrdResource
-    let profiles_collection = state.db.webfinger_profiles_collection();
-    let filter = doc! { "subject": subject.clone() };
-
-    // Attempt to find the resource in MongoDB
-    let jrd_result = profiles_collection.find_one(filter).await?;
+    // Aliases of an actor resolve to its profile, with the canonical subject
+    let jrd_result = state.db.webfinger_cache().profile(&subject).await?;
 
     // Return 404 if not found
-    let mut jrd = jrd_result.ok_or_else(|| {
-        debug!("Webfinger resource not found in database: {}", subject);
+    let jrd = jrd_result.ok_or_else(|| {
+        debug!("Webfinger resource not found: {}", subject);
         WebfingerError::ResourceNotFound(subject)
     })?;
+    let mut jrd = JrdResource::clone(&jrd);
+
+    // Domains hiding inactive accounts do not resolve them either
+    if let Some(domain) = extract_domain_from_headers(&headers)
+        && DomainPrivacy::of(&domain, &state)
+            .await
+            .hides_inactive_accounts()
+        && !is_active(&jrd, &state).await?
+    {
+        return Err(WebfingerError::ResourceNotFound(query.resource));
+    }
 
     // Ensure a "self" link exists — older profiles may have been stored without one.
     // Derive the actor URL from the alias (/@user → /users/user).
@@ -146,6 +249,17 @@ async fn handle_webfinger(
     Ok(Json(jrd))
 }
 
+/// Whether the actor a profile links to is active
+///
+/// Profiles without a stored actor count as active.
+async fn is_active(jrd: &JrdResource, state: &AppState) -> Result<bool, DatabaseError> {
+    let Some(actor_id) = jrd.find_link("self").and_then(|link| link.href.as_deref()) else {
+        return Ok(true);
+    };
+    let actor = state.db_manager.find_actor_by_id(actor_id).await?;
+    Ok(actor.is_none_or(|actor| actor.status == ActorStatus::Active))
+}
+
 /// Build a synthetic JRD for a domain-level WebFinger query.
 ///
 /// Advertises the admin API URL and OIDC issuer when configured.
@@ -200,6 +314,13 @@ fn build_domain_jrd(
 }
 
 /// Creates a router for webfinger endpoints
-pub fn webfinger_router(_state: AppState) -> Router<AppState> {
-    Router::new().route("/.well-known/webfinger", get(handle_webfinger))
+///
+/// Unknown resources are answered like those of hidden accounts.
+pub fn webfinger_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/.well-known/webfinger", get(handle_webfinger))
+        .route_layer(middleware::from_fn_with_state(
+            state,
+            crate::privacy::conceal_accounts,
+        ))
 }
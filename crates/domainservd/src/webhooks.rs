@@ -0,0 +1,501 @@
+//! Webhooks
+//!
+//! Administrators register webhooks per domain with a URL, a shared secret
+//! and the events they want. Services queue events through the outbox to
+//! `oxifed.webhooks`; the event consumer here expands each event into one
+//! delivery per subscribed webhook, and the delivery consumer POSTs it as
+//! JSON signed with the webhook's secret.
+//!
+//! Failed callbacks go to the dead-letter queue: network errors, rate
+//! limits and server errors as transient failures, which the DLQ retries
+//! with backoff, other rejections as permanent ones. Callbacks follow the
+//! egress policy; webhooks on the local network need their host in
+//! `allowed_hosts`.
+//!
+//! Receivers verify the `X-Oxifed-Signature` header, `t=<unix time>,v1=<hex>`
+//! where the hex digest is HMAC-SHA256 over `<unix time>.<body>` keyed with
+//! the secret.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use deadpool_lapin::Pool;
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use lapin::{
+    BasicProperties, Channel,
+    message::Delivery,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions},
+    types::{AMQPValue, FieldTable},
+};
+use oxifed::database::{DatabaseError, DatabaseManager, WebhookDocument};
+use oxifed::egress::EgressPolicy;
+use oxifed::messaging::{
+    DomainRpcResponse, EXCHANGE_DEAD_LETTER, EXCHANGE_WEBHOOKS, FailureClass, HEADER_FAILURE_CLASS,
+    HEADER_ORIGINAL_QUEUE, HEADER_REJECTION_REASON, HEADER_REJECTION_STAGE,
+    QUEUE_WEBHOOK_DELIVERIES, QUEUE_WEBHOOK_EVENTS, ROUTING_KEY_WEBHOOK_DELIVERY,
+    WebhookCreateMessage, WebhookDeleteMessage, WebhookDeliveryMessage, WebhookEvent,
+    WebhookEventMessage, WebhookInfo,
+};
+use oxifed::shutdown::Shutdown;
+use serde_json::json;
+use sha2::Sha256;
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+use crate::db::MongoDB;
+use crate::rabbitmq::{RabbitMQError, does_domain_exist, requeue, spawn_channel_task, stopped};
+
+pub const WEBHOOK_EVENTS_CONSUMER_TAG: &str = "webhook_events_consumer";
+pub const WEBHOOK_DELIVERIES_CONSUMER_TAG: &str = "webhook_deliveries_consumer";
+
+/// Header carrying the signature of a callback
+pub const HEADER_SIGNATURE: &str = "X-Oxifed-Signature";
+/// Header carrying the event name of a callback
+pub const HEADER_EVENT: &str = "X-Oxifed-Event";
+/// Header carrying the event ID, for receivers to drop duplicates
+pub const HEADER_DELIVERY: &str = "X-Oxifed-Delivery";
+
+/// How long a webhook endpoint may take to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Callbacks sent at once by one delivery consumer
+const DELIVERY_PREFETCH: u16 = 10;
+
+/// Register a webhook, returning its ID
+pub async fn create(
+    db: &Arc<MongoDB>,
+    msg: &WebhookCreateMessage,
+) -> Result<String, RabbitMQError> {
+    if !does_domain_exist(&msg.domain, db).await {
+        return Err(RabbitMQError::DomainNotFound(msg.domain.clone()));
+    }
+    let url = Url::parse(&msg.url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(RabbitMQError::ConstraintError(format!(
+            "Webhook URL must use http or https: {}",
+            msg.url
+        )));
+    }
+    if msg.secret.is_empty() {
+        return Err(RabbitMQError::ConstraintError(
+            "Webhook secret must not be empty".to_string(),
+        ));
+    }
+
+    let webhook_id = uuid::Uuid::new_v4().to_string();
+    db.manager()
+        .insert_webhook(WebhookDocument {
+            id: None,
+            webhook_id: webhook_id.clone(),
+            domain: msg.domain.clone(),
+            url: url.to_string(),
+            secret: msg.secret.clone(),
+            events: msg.events.clone(),
+            created_at: Utc::now(),
+        })
+        .await?;
+
+    info!("Webhook {} of {} sends to {}", webhook_id, msg.domain, url);
+    Ok(webhook_id)
+}
+
+/// Remove a webhook
+pub async fn delete(db: &Arc<MongoDB>, msg: &WebhookDeleteMessage) -> Result<(), RabbitMQError> {
+    if !db
+        .manager()
+        .delete_webhook(&msg.domain, &msg.webhook_id)
+        .await?
+    {
+        return Err(DatabaseError::NotFoundError(format!(
+            "Webhook {} of {}",
+            msg.webhook_id, msg.domain
+        ))
+        .into());
+    }
+    info!("Webhook {} of {} removed", msg.webhook_id, msg.domain);
+    Ok(())
+}
+
+/// Answer a request for the webhooks of a domain
+pub async fn list(db: &Arc<MongoDB>, request_id: String, domain: &str) -> DomainRpcResponse {
+    match db.manager().list_webhooks(domain).await {
+        Ok(webhooks) => DomainRpcResponse::webhook_list(
+            request_id,
+            webhooks.into_iter().map(webhook_info).collect(),
+        ),
+        Err(e) => {
+            error!("Failed to list webhooks of {}: {}", domain, e);
+            DomainRpcResponse::error(request_id, format!("Failed to list webhooks: {}", e))
+        }
+    }
+}
+
+/// Webhook information for the admin API, leaving out the secret
+fn webhook_info(webhook: WebhookDocument) -> WebhookInfo {
+    WebhookInfo {
+        webhook_id: webhook.webhook_id,
+        url: webhook.url,
+        events: webhook.events,
+        created_at: webhook.created_at.to_rfc3339(),
+    }
+}
+
+/// Queue a `user.registered` event for a new local actor
+pub(crate) async fn queue_user_registered(
+    db: &Arc<MongoDB>,
+    actor_id: &str,
+    username: &str,
+    domain: &str,
+) -> Result<(), RabbitMQError> {
+    let event = WebhookEventMessage::new(
+        WebhookEvent::UserRegistered,
+        domain.to_string(),
+        json!({ "actor": actor_id, "username": username }),
+    );
+    db.manager().queue_webhook_event(&event).await?;
+    Ok(())
+}
+
+/// Start the event fanout and the callback delivery consumers
+pub fn start_webhook_consumers(pool: Pool, db: Arc<DatabaseManager>, shutdown: &Shutdown) {
+    let builder = reqwest::Client::builder()
+        .user_agent(oxifed::client::ClientConfig::default().user_agent)
+        .timeout(DELIVERY_TIMEOUT);
+    let client = match EgressPolicy::installed()
+        .configure(builder)
+        .and_then(|builder| builder.build())
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Webhooks disabled, failed to build HTTP client: {}", e);
+            return;
+        }
+    };
+
+    spawn_channel_task("webhook events consumer", pool.clone(), shutdown, {
+        let db = db.clone();
+        move |channel, shutdown| run_event_consumer(channel, db.clone(), shutdown)
+    });
+    spawn_channel_task(
+        "webhook deliveries consumer",
+        pool,
+        shutdown,
+        move |channel, shutdown| {
+            run_delivery_consumer(channel, db.clone(), client.clone(), shutdown)
+        },
+    );
+}
+
+/// Queue one delivery per subscribed webhook for every event
+async fn run_event_consumer(
+    channel: Channel,
+    db: Arc<DatabaseManager>,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .confirm_select(lapin::options::ConfirmSelectOptions::default())
+        .await?;
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_WEBHOOK_EVENTS,
+            WEBHOOK_EVENTS_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Webhook events consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        let event: WebhookEventMessage = match serde_json::from_slice(&delivery.data) {
+            Ok(event) => event,
+            Err(e) => {
+                dead_letter(
+                    &channel,
+                    &delivery,
+                    QUEUE_WEBHOOK_EVENTS,
+                    &e.to_string(),
+                    FailureClass::Permanent,
+                )
+                .await?;
+                delivery.ack(BasicAckOptions::default()).await?;
+                continue;
+            }
+        };
+
+        match oxifed::poison::isolate(fan_out(&channel, &db, &event)).await {
+            Ok(Ok(count)) => {
+                debug!(
+                    "Event {} ({}) queued for {} webhooks",
+                    event.id, event.event, count
+                );
+                delivery.ack(BasicAckOptions::default()).await?;
+            }
+            Ok(Err(e)) => {
+                error!("Failed to queue webhook event {}: {}", event.id, e);
+                requeue(&delivery, "webhook event").await;
+            }
+            Err(panic) => {
+                oxifed::poison::record(QUEUE_WEBHOOK_EVENTS, &panic);
+                dead_letter(
+                    &channel,
+                    &delivery,
+                    QUEUE_WEBHOOK_EVENTS,
+                    &format!("handler panicked: {}", panic),
+                    FailureClass::Permanent,
+                )
+                .await?;
+                delivery.ack(BasicAckOptions::default()).await?;
+            }
+        }
+    }
+
+    stopped("Webhook events consumer", &shutdown);
+    Ok(())
+}
+
+/// Publish a delivery of the event for every webhook subscribed to it
+async fn fan_out(
+    channel: &Channel,
+    db: &DatabaseManager,
+    event: &WebhookEventMessage,
+) -> Result<usize, RabbitMQError> {
+    let webhooks = db
+        .find_webhooks_for_event(&event.domain, event.event)
+        .await?;
+    for webhook in &webhooks {
+        let message = WebhookDeliveryMessage {
+            webhook_id: webhook.webhook_id.clone(),
+            event: event.clone(),
+        };
+        let confirm = channel
+            .basic_publish(
+                EXCHANGE_WEBHOOKS,
+                ROUTING_KEY_WEBHOOK_DELIVERY,
+                BasicPublishOptions::default(),
+                &serde_json::to_vec(&message)?,
+                BasicProperties::default()
+                    .with_delivery_mode(2)
+                    .with_content_type("application/json".into())
+                    .with_message_id(uuid::Uuid::new_v4().to_string().into()),
+            )
+            .await?
+            .await?;
+        if confirm.is_nack() {
+            return Err(RabbitMQError::PublishRejected(format!(
+                "webhook delivery of event {}",
+                event.id
+            )));
+        }
+    }
+    Ok(webhooks.len())
+}
+
+/// Send queued callbacks, dead-lettering the ones that fail
+async fn run_delivery_consumer(
+    channel: Channel,
+    db: Arc<DatabaseManager>,
+    client: reqwest::Client,
+    shutdown: Shutdown,
+) -> Result<(), RabbitMQError> {
+    channel
+        .basic_qos(DELIVERY_PREFETCH, BasicQosOptions::default())
+        .await?;
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_WEBHOOK_DELIVERIES,
+            WEBHOOK_DELIVERIES_CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Webhook deliveries consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        let failure = match serde_json::from_slice::<WebhookDeliveryMessage>(&delivery.data) {
+            Ok(message) => match db.find_webhook(&message.webhook_id).await {
+                Ok(Some(webhook)) => {
+                    match oxifed::poison::isolate(send(&client, &webhook, &message.event)).await {
+                        Ok(sent) => sent.err(),
+                        Err(panic) => {
+                            oxifed::poison::record(QUEUE_WEBHOOK_DELIVERIES, &panic);
+                            Some((
+                                format!("handler panicked: {}", panic),
+                                FailureClass::Permanent,
+                            ))
+                        }
+                    }
+                }
+                Ok(None) => {
+                    debug!(
+                        "Dropping callback of removed webhook {}",
+                        message.webhook_id
+                    );
+                    None
+                }
+                Err(e) => Some((e.to_string(), FailureClass::Transient)),
+            },
+            Err(e) => Some((e.to_string(), FailureClass::Permanent)),
+        };
+
+        if let Some((error, class)) = failure {
+            warn!("Webhook callback failed ({}): {}", class.as_str(), error);
+            dead_letter(&channel, &delivery, QUEUE_WEBHOOK_DELIVERIES, &error, class).await?;
+        }
+        delivery.ack(BasicAckOptions::default()).await?;
+    }
+
+    stopped("Webhook deliveries consumer", &shutdown);
+    Ok(())
+}
+
+/// POST a signed event to a webhook
+///
+/// Returns the error and whether retrying may help.
+async fn send(
+    client: &reqwest::Client,
+    webhook: &WebhookDocument,
+    event: &WebhookEventMessage,
+) -> Result<(), (String, FailureClass)> {
+    let body = serde_json::to_vec(event).map_err(|e| (e.to_string(), FailureClass::Permanent))?;
+    let timestamp = Utc::now().timestamp();
+    let url = Url::parse(&webhook.url).map_err(|e| (e.to_string(), FailureClass::Permanent))?;
+    EgressPolicy::installed()
+        .check_url(&url)
+        .map_err(|e| (e.to_string(), FailureClass::Permanent))?;
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(
+            HEADER_SIGNATURE,
+            signature_header(&webhook.secret, timestamp, &body),
+        )
+        .header(HEADER_EVENT, event.event.as_str())
+        .header(HEADER_DELIVERY, &event.id)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (e.to_string(), FailureClass::Transient))?;
+
+    let status = response.status();
+    if status.is_success() {
+        debug!("Event {} sent to webhook {}", event.id, webhook.webhook_id);
+        return Ok(());
+    }
+    let class = if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        FailureClass::Transient
+    } else {
+        FailureClass::Permanent
+    };
+    Err((format!("{} answered {}", webhook.url, status), class))
+}
+
+/// Value of the signature header of a callback body sent at `timestamp`
+pub fn signature_header(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(body);
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hmac_sha256_hex(secret.as_bytes(), &signed)
+    )
+}
+
+/// Hex-encoded HMAC-SHA256 of a message
+fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Publish a failed message to the dead-letter exchange
+///
+/// The delivery's headers are kept, so the retry count of a re-injected
+/// callback carries over and the DLQ gives up after its retry budget.
+async fn dead_letter(
+    channel: &Channel,
+    delivery: &Delivery,
+    queue: &str,
+    error: &str,
+    class: FailureClass,
+) -> Result<(), RabbitMQError> {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        HEADER_REJECTION_REASON.into(),
+        AMQPValue::LongString(error.into()),
+    );
+    headers.insert(
+        HEADER_REJECTION_STAGE.into(),
+        AMQPValue::LongString("webhook".into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_QUEUE.into(),
+        AMQPValue::LongString(queue.into()),
+    );
+    headers.insert(
+        HEADER_FAILURE_CLASS.into(),
+        AMQPValue::LongString(class.as_str().into()),
+    );
+
+    channel
+        .basic_publish(
+            EXCHANGE_DEAD_LETTER,
+            queue,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            delivery
+                .properties
+                .clone()
+                .with_delivery_mode(2)
+                .with_headers(headers),
+        )
+        .await?
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let header = signature_header("secret", 1700000000, b"{}");
+        assert_eq!(
+            header,
+            format!(
+                "t=1700000000,v1={}",
+                hmac_sha256_hex(b"secret", b"1700000000.{}")
+            )
+        );
+        assert_ne!(header, signature_header("secret", 1700000001, b"{}"));
+        assert_ne!(header, signature_header("other", 1700000000, b"{}"));
+    }
+
+    #[test]
+    fn test_event_names() {
+        for event in WebhookEvent::ALL {
+            assert_eq!(event.as_str().parse::<WebhookEvent>(), Ok(event));
+            assert_eq!(
+                serde_json::to_value(event).unwrap(),
+                serde_json::Value::from(event.as_str())
+            );
+        }
+        assert!("user.deleted".parse::<WebhookEvent>().is_err());
+    }
+}
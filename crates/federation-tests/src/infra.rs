@@ -0,0 +1,124 @@
+//! MongoDB and LavinMQ containers of a test federation
+//!
+//! The instances share one MongoDB server, each with its own database, and
+//! get a broker each, so the fanout exchanges of one instance never reach
+//! the daemons of another.
+
+use std::time::Duration;
+
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage, ImageExt};
+use tracing::info;
+
+use crate::HarnessError;
+
+const MONGODB_USER: &str = "root";
+const MONGODB_PASSWORD: &str = "testpassword";
+const AMQP_USER: &str = "admin";
+const AMQP_PASSWORD: &str = "testpassword";
+
+/// Attempts to connect to a freshly started broker
+const AMQP_CONNECT_ATTEMPTS: u32 = 30;
+
+/// Running MongoDB and LavinMQ containers
+///
+/// The containers are removed when this is dropped.
+pub struct Infrastructure {
+    mongodb: ContainerAsync<GenericImage>,
+    brokers: Vec<ContainerAsync<GenericImage>>,
+    mongodb_uri: String,
+    amqp_urls: Vec<String>,
+}
+
+impl std::fmt::Debug for Infrastructure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Infrastructure")
+            .field("mongodb", &self.mongodb.id())
+            .field("brokers", &self.brokers.len())
+            .field("mongodb_uri", &self.mongodb_uri)
+            .field("amqp_urls", &self.amqp_urls)
+            .finish()
+    }
+}
+
+impl Infrastructure {
+    /// Start MongoDB and one broker per instance
+    pub async fn start(instances: usize) -> Result<Self, HarnessError> {
+        let mongodb = GenericImage::new("mongo", "8")
+            .with_exposed_port(27017.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Waiting for connections"))
+            .with_env_var("MONGO_INITDB_ROOT_USERNAME", MONGODB_USER)
+            .with_env_var("MONGO_INITDB_ROOT_PASSWORD", MONGODB_PASSWORD)
+            .start()
+            .await?;
+        let mongodb_uri = format!(
+            "mongodb://{}:{}@127.0.0.1:{}/?authSource=admin",
+            MONGODB_USER,
+            MONGODB_PASSWORD,
+            mongodb.get_host_port_ipv4(27017).await?
+        );
+        info!("MongoDB running at {}", mongodb_uri);
+
+        let mut brokers = Vec::with_capacity(instances);
+        let mut amqp_urls = Vec::with_capacity(instances);
+        for _ in 0..instances {
+            let broker = GenericImage::new("cloudamqp/lavinmq", "latest")
+                .with_exposed_port(5672.tcp())
+                .with_env_var("LAVINMQ_DEFAULT_USER", AMQP_USER)
+                .with_env_var("LAVINMQ_DEFAULT_PASS", AMQP_PASSWORD)
+                .start()
+                .await?;
+            let url = format!(
+                "amqp://{}:{}@127.0.0.1:{}",
+                AMQP_USER,
+                AMQP_PASSWORD,
+                broker.get_host_port_ipv4(5672).await?
+            );
+            wait_for_amqp(&url).await?;
+            info!("LavinMQ running at {}", url);
+            brokers.push(broker);
+            amqp_urls.push(url);
+        }
+
+        Ok(Self {
+            mongodb,
+            brokers,
+            mongodb_uri,
+            amqp_urls,
+        })
+    }
+
+    /// Connection string of the shared MongoDB server
+    pub fn mongodb_uri(&self) -> &str {
+        &self.mongodb_uri
+    }
+
+    /// Connection string of the broker of instance `index`
+    pub fn amqp_url(&self, index: usize) -> &str {
+        &self.amqp_urls[index]
+    }
+}
+
+/// Wait until the broker at `url` accepts connections
+///
+/// LavinMQ opens its port before it accepts logins, so the container's log
+/// is not a reliable readiness signal.
+async fn wait_for_amqp(url: &str) -> Result<(), HarnessError> {
+    let mut last_error = None;
+    for _ in 0..AMQP_CONNECT_ATTEMPTS {
+        match lapin::Connection::connect(url, lapin::ConnectionProperties::default()).await {
+            Ok(connection) => {
+                connection.close(0, "ready").await.ok();
+                return Ok(());
+            }
+            Err(e) => last_error = Some(e),
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    Err(HarnessError::NotReady(format!(
+        "broker at {}: {}",
+        url,
+        last_error.map(|e| e.to_string()).unwrap_or_default()
+    )))
+}
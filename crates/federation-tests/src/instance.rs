@@ -0,0 +1,346 @@
+//! Oxifed instances run from the workspace binaries
+//!
+//! An [`Instance`] is one domain served by its own domainservd, pkid,
+//! publisherd and storaged processes. It is administered the way adminservd
+//! does it, by publishing commands to the internal exchange and waiting for
+//! their [`CommandResponse`], and inspected through its database.
+
+use std::fs::File;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures::StreamExt;
+use lapin::options::{BasicConsumeOptions, BasicPublishOptions, QueueDeclareOptions};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Connection, ConnectionProperties};
+use oxifed::config::DatabaseConfig;
+use oxifed::database::{DatabaseManager, FollowDocument};
+use oxifed::messaging::{
+    CommandResponse, CommandResult, DomainCreateMessage, EXCHANGE_INTERNAL_PUBLISH,
+    FollowActivityMessage, Message, MessageEnum, NoteCreateMessage, ProfileCreateMessage,
+};
+use serde::Serialize;
+use tokio::process::{Child, Command};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{HarnessError, eventually};
+
+/// Daemons making up an instance, in start order
+const DAEMONS: &[&str] = &["pkid", "domainservd", "publisherd", "storaged"];
+
+/// Time a command may take to be answered
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time domainservd may take to report ready
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Settings shared by the daemons of all instances
+#[derive(Debug, Clone)]
+pub struct InstanceEnv {
+    pub mongodb_uri: String,
+    pub amqp_url: String,
+    /// `HTTPS_PROXY` routing the test domains
+    pub proxy_url: String,
+    /// CA certificate the daemons trust, for `SSL_CERT_FILE`
+    pub ca_file: PathBuf,
+    /// Directory the daemon logs are written to
+    pub log_dir: PathBuf,
+}
+
+/// A running Oxifed instance serving one domain
+pub struct Instance {
+    name: String,
+    domain: String,
+    http_addr: SocketAddr,
+    db: DatabaseManager,
+    amqp: Connection,
+    processes: Vec<Child>,
+}
+
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Instance")
+            .field("name", &self.name)
+            .field("domain", &self.domain)
+            .field("http_addr", &self.http_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Instance {
+    /// Start the daemons of instance `name` serving `domain`
+    ///
+    /// Returns once domainservd is ready and the domain is created.
+    pub async fn start(name: &str, domain: &str, env: &InstanceEnv) -> Result<Self, HarnessError> {
+        let http_addr = free_local_addr()?;
+        let database = format!("oxifed_{}", name);
+
+        let mut processes = Vec::with_capacity(DAEMONS.len());
+        for daemon in DAEMONS {
+            let binary = bin_dir().join(daemon);
+            if !binary.exists() {
+                return Err(HarnessError::MissingBinary(binary));
+            }
+            let log = File::create(env.log_dir.join(format!("{}-{}.log", name, daemon)))?;
+            let child = Command::new(&binary)
+                .env("MONGODB_URI", &env.mongodb_uri)
+                .env("MONGODB_DBNAME", &database)
+                .env("AMQP_URL", &env.amqp_url)
+                .env("BIND_ADDRESS", http_addr.to_string())
+                .env("PIPELINE_STAGES", "storage")
+                .env("HTTPS_PROXY", &env.proxy_url)
+                .env("NO_PROXY", "127.0.0.1,localhost")
+                .env("SSL_CERT_FILE", &env.ca_file)
+                .env(
+                    "RUST_LOG",
+                    std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+                )
+                .stdout(Stdio::from(log.try_clone()?))
+                .stderr(Stdio::from(log))
+                .kill_on_drop(true)
+                .spawn()?;
+            processes.push(child);
+        }
+
+        let db = DatabaseManager::connect(&DatabaseConfig {
+            uri: env.mongodb_uri.clone(),
+            name: database,
+            ..DatabaseConfig::default()
+        })
+        .await?;
+        let amqp = Connection::connect(&env.amqp_url, ConnectionProperties::default()).await?;
+
+        let mut instance = Self {
+            name: name.to_string(),
+            domain: domain.to_string(),
+            http_addr,
+            db,
+            amqp,
+            processes,
+        };
+        instance.wait_until_ready(&env.log_dir).await?;
+        instance.create_domain().await?;
+        info!("Instance {} serving {} on {}", name, domain, http_addr);
+        Ok(instance)
+    }
+
+    /// Domain served by the instance
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Local address domainservd listens on, in plain HTTP
+    pub fn http_addr(&self) -> SocketAddr {
+        self.http_addr
+    }
+
+    /// Database of the instance
+    pub fn db(&self) -> &DatabaseManager {
+        &self.db
+    }
+
+    /// ActivityPub ID of the local actor `username`
+    pub fn actor_id(&self, username: &str) -> String {
+        format!("https://{}/users/{}", self.domain, username)
+    }
+
+    /// Publish a command and wait for its response
+    ///
+    /// Returns the ID of the object the command created, if any.
+    pub async fn command<T: Message + Serialize>(
+        &self,
+        message: &T,
+    ) -> Result<Option<String>, HarnessError> {
+        let channel = self.amqp.create_channel().await?;
+        let reply_queue = channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?
+            .name()
+            .to_string();
+        let mut replies = channel
+            .basic_consume(
+                &reply_queue,
+                "",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        let correlation_id = Uuid::new_v4().to_string();
+        channel
+            .basic_publish(
+                EXCHANGE_INTERNAL_PUBLISH,
+                "",
+                BasicPublishOptions::default(),
+                &serde_json::to_vec(&message.to_message())?,
+                BasicProperties::default()
+                    .with_reply_to(reply_queue.into())
+                    .with_correlation_id(correlation_id.clone().into()),
+            )
+            .await?;
+
+        let response = tokio::time::timeout(COMMAND_TIMEOUT, async {
+            while let Some(delivery) = replies.next().await {
+                let delivery = delivery?;
+                if let MessageEnum::CommandResponse(response) =
+                    serde_json::from_slice(&delivery.data)?
+                    && response.request_id == correlation_id
+                {
+                    return Ok(response);
+                }
+            }
+            Err(HarnessError::Command("reply queue closed".to_string()))
+        })
+        .await
+        .map_err(|_| HarnessError::Timeout(format!("response to command {}", correlation_id)))??;
+        channel.close(0, "done").await.ok();
+
+        match response {
+            CommandResponse {
+                result: CommandResult::Done { id },
+                ..
+            } => Ok(id),
+            CommandResponse {
+                result: CommandResult::Error { kind, message },
+                ..
+            } => Err(HarnessError::Command(format!("{:?}: {}", kind, message))),
+        }
+    }
+
+    /// Create a local person and wait until pkid gave it a key
+    ///
+    /// Returns the actor ID.
+    pub async fn create_person(&self, username: &str) -> Result<String, HarnessError> {
+        self.command(&ProfileCreateMessage::new(
+            format!("{}@{}", username, self.domain),
+            Some(format!("Test account on {}", self.name)),
+            None,
+            None,
+        ))
+        .await?;
+
+        let actor_id = self.actor_id(username);
+        eventually(&format!("key of {}", actor_id), || async {
+            let keys = self.db.find_active_keys_by_actor(&actor_id).await.ok()?;
+            (!keys.is_empty()).then_some(())
+        })
+        .await?;
+        Ok(actor_id)
+    }
+
+    /// Let the local `username` follow the actor `target`
+    pub async fn follow(&self, username: &str, target: &str) -> Result<(), HarnessError> {
+        self.command(&FollowActivityMessage::new(
+            self.actor_id(username),
+            target.to_string(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Publish a public note of the local `username`
+    ///
+    /// Returns the note ID.
+    pub async fn post_note(&self, username: &str, content: &str) -> Result<String, HarnessError> {
+        self.command(&NoteCreateMessage::new(
+            format!("{}@{}", username, self.domain),
+            content.to_string(),
+            None,
+            None,
+            None,
+            None,
+        ))
+        .await?
+        .ok_or_else(|| HarnessError::Command("note creation returned no ID".to_string()))
+    }
+
+    /// The follow of `follower` to `following` as stored by this instance
+    pub async fn find_follow(
+        &self,
+        follower: &str,
+        following: &str,
+    ) -> Result<Option<FollowDocument>, HarnessError> {
+        Ok(self.db.find_follow(follower, following).await?)
+    }
+
+    async fn create_domain(&self) -> Result<(), HarnessError> {
+        self.command(&DomainCreateMessage::new(
+            self.domain.clone(),
+            Some(format!("Federation test instance {}", self.name)),
+            Some("Started by the federation tests".to_string()),
+            Some(format!("admin@{}", self.domain)),
+            None,
+            Some("open".to_string()),
+            Some(false),
+            None,
+            None,
+            None,
+            None,
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Wait for domainservd's `/readyz`, failing early if a daemon exits
+    async fn wait_until_ready(&mut self, log_dir: &Path) -> Result<(), HarnessError> {
+        let client = reqwest::Client::builder().no_proxy().build()?;
+        let url = format!("http://{}/readyz", self.http_addr);
+        let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+
+        while tokio::time::Instant::now() < deadline {
+            for (daemon, process) in DAEMONS.iter().zip(&mut self.processes) {
+                if let Some(status) = process.try_wait()? {
+                    return Err(HarnessError::NotReady(format!(
+                        "{} of {} exited with {}, see {}",
+                        daemon,
+                        self.name,
+                        status,
+                        log_dir
+                            .join(format!("{}-{}.log", self.name, daemon))
+                            .display()
+                    )));
+                }
+            }
+            if let Ok(response) = client.get(&url).send().await
+                && response.status().is_success()
+            {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        Err(HarnessError::NotReady(format!(
+            "domainservd of {} at {}",
+            self.name, self.http_addr
+        )))
+    }
+}
+
+/// Directory holding the daemon binaries
+///
+/// `OXIFED_BIN_DIR`, or the workspace's `target/debug`; build the daemons
+/// with `cargo build --workspace` before running the tests.
+fn bin_dir() -> PathBuf {
+    std::env::var_os("OXIFED_BIN_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target/debug"))
+}
+
+/// A local address no one listens on yet
+fn free_local_addr() -> Result<SocketAddr, HarnessError> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
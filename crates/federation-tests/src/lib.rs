@@ -0,0 +1,215 @@
+//! End-to-end federation test harness
+//!
+//! [`Federation::start`] brings up a small fediverse on one machine: MongoDB
+//! and LavinMQ in containers (testcontainers), two Oxifed instances run from
+//! the workspace binaries, `alpha.test` and `beta.test`, and a
+//! [`MockPeer`](oxifed::testing::MockPeer) standing in for a foreign server
+//! at `remote.test`. The daemons reach each other's `https://` URLs through a
+//! [`HostProxy`](proxy::HostProxy) that terminates TLS with certificates of
+//! a throwaway [`TestCa`](tls::TestCa).
+//!
+//! The tests in `tests/` need Docker and the daemon binaries, so they only
+//! run with `OXIFED_RUN_E2E=1`:
+//!
+//! ```sh
+//! cargo build --workspace
+//! OXIFED_RUN_E2E=1 cargo test -p federation-tests
+//! ```
+//!
+//! Daemon logs are written to `target/federation-tests/<run>/`.
+
+pub mod infra;
+pub mod instance;
+pub mod proxy;
+pub mod tls;
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use infra::Infrastructure;
+use instance::{Instance, InstanceEnv};
+use oxifed::testing::{MockPeer, MockPeerConfig, MockPeerError};
+use proxy::HostProxy;
+use tls::TestCa;
+
+/// Domain of the first Oxifed instance
+pub const ALPHA_DOMAIN: &str = "alpha.test";
+/// Domain of the second Oxifed instance
+pub const BETA_DOMAIN: &str = "beta.test";
+/// Domain of the mock remote server
+pub const REMOTE_DOMAIN: &str = "remote.test";
+
+/// Time [`eventually`] waits for a condition
+pub const EVENTUALLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors setting up or driving a test federation
+#[derive(Debug, thiserror::Error)]
+pub enum HarnessError {
+    #[error("Container error: {0}")]
+    Container(#[from] testcontainers::TestcontainersError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("AMQP error: {0}")]
+    Amqp(#[from] lapin::Error),
+
+    #[error("Database error: {0}")]
+    Database(#[from] oxifed::database::DatabaseError),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Certificate error: {0}")]
+    Certificate(#[from] rcgen::Error),
+
+    #[error("TLS error: {0}")]
+    Tls(#[from] rustls::Error),
+
+    #[error("Mock remote error: {0}")]
+    Remote(#[from] MockPeerError),
+
+    #[error("Proxy error: {0}")]
+    Proxy(String),
+
+    #[error("{} not found, build the daemons with `cargo build --workspace` or set OXIFED_BIN_DIR", .0.display())]
+    MissingBinary(PathBuf),
+
+    #[error("Not ready: {0}")]
+    NotReady(String),
+
+    #[error("Command failed: {0}")]
+    Command(String),
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+}
+
+/// Whether the end-to-end tests should run
+///
+/// They need Docker and the daemon binaries; set `OXIFED_RUN_E2E=1` (or
+/// `true`) to enable them.
+pub fn should_run() -> bool {
+    match std::env::var("OXIFED_RUN_E2E") {
+        Ok(v) => v == "1" || v.eq_ignore_ascii_case("true"),
+        Err(_) => false,
+    }
+}
+
+/// Poll `check` until it returns a value or [`EVENTUALLY_TIMEOUT`] passes
+///
+/// Federation is asynchronous; this is how the tests wait for deliveries.
+pub async fn eventually<T, F, Fut>(what: &str, mut check: F) -> Result<T, HarnessError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let deadline = tokio::time::Instant::now() + EVENTUALLY_TIMEOUT;
+    loop {
+        if let Some(value) = check().await {
+            return Ok(value);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(HarnessError::Timeout(what.to_string()));
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Two Oxifed instances and a mock remote server federating with each other
+///
+/// Everything is stopped when this is dropped: the daemons first, then the
+/// containers.
+#[derive(Debug)]
+pub struct Federation {
+    pub alpha: Instance,
+    pub beta: Instance,
+    pub remote: MockPeer,
+    client: reqwest::Client,
+    log_dir: PathBuf,
+    _proxy: HostProxy,
+    _infra: Infrastructure,
+}
+
+impl Federation {
+    /// Start the containers, both instances and the mock remote
+    pub async fn start() -> Result<Self, HarnessError> {
+        let log_dir = Self::run_dir();
+        std::fs::create_dir_all(&log_dir)?;
+
+        let ca = Arc::new(TestCa::new()?);
+        let ca_file = log_dir.join("ca.pem");
+        ca.write_pem(&ca_file)?;
+        let proxy = HostProxy::start(ca.clone()).await?;
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::https(proxy.url())?)
+            .add_root_certificate(ca.root_certificate()?)
+            .build()?;
+
+        let infra = Infrastructure::start(2).await?;
+        let env = |index: usize| InstanceEnv {
+            mongodb_uri: infra.mongodb_uri().to_string(),
+            amqp_url: infra.amqp_url(index).to_string(),
+            proxy_url: proxy.url(),
+            ca_file: ca_file.clone(),
+            log_dir: log_dir.clone(),
+        };
+
+        let remote = MockPeer::start_with(MockPeerConfig {
+            domain: Some(REMOTE_DOMAIN.to_string()),
+            client: Some(client.clone()),
+            require_signatures: false,
+            ..Default::default()
+        })
+        .await?;
+        proxy.route(REMOTE_DOMAIN, remote.addr());
+        let alpha = Instance::start("alpha", ALPHA_DOMAIN, &env(0)).await?;
+        proxy.route(ALPHA_DOMAIN, alpha.http_addr());
+        let beta = Instance::start("beta", BETA_DOMAIN, &env(1)).await?;
+        proxy.route(BETA_DOMAIN, beta.http_addr());
+
+        Ok(Self {
+            alpha,
+            beta,
+            remote,
+            client,
+            log_dir,
+            _proxy: proxy,
+            _infra: infra,
+        })
+    }
+
+    /// HTTP client reaching the test domains over HTTPS
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Directory holding the daemon logs of this run
+    pub fn log_dir(&self) -> &std::path::Path {
+        &self.log_dir
+    }
+
+    /// Fetch an ActivityPub document from one of the test domains
+    pub async fn fetch(&self, url: &str) -> Result<serde_json::Value, HarnessError> {
+        Ok(self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/activity+json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    fn run_dir() -> PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../target/federation-tests")
+            .join(uuid::Uuid::new_v4().to_string())
+    }
+}
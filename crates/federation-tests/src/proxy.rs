@@ -0,0 +1,145 @@
+//! HTTPS proxy routing the test domains to local servers
+//!
+//! The daemons reach remote servers at `https://<domain>/`, which the test
+//! domains cannot resolve to. They are started with `HTTPS_PROXY` pointing at
+//! a [`HostProxy`] instead: for every `CONNECT <domain>:443` it terminates
+//! TLS with a certificate of the [`TestCa`] and forwards the plain HTTP/1.1
+//! stream to the local port registered for the domain. The `Host` header is
+//! passed through untouched, so domainservd sees the domain it serves.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, warn};
+
+use crate::HarnessError;
+use crate::tls::TestCa;
+
+/// Largest CONNECT request head read from a client
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+type Routes = Arc<RwLock<HashMap<String, SocketAddr>>>;
+
+/// CONNECT proxy terminating TLS for the registered test domains
+#[derive(Debug)]
+pub struct HostProxy {
+    addr: SocketAddr,
+    routes: Routes,
+    task: JoinHandle<()>,
+}
+
+impl HostProxy {
+    /// Listen on a free local port
+    pub async fn start(ca: Arc<TestCa>) -> Result<Self, HarnessError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let routes = Routes::default();
+
+        let task = tokio::spawn({
+            let routes = routes.clone();
+            async move {
+                loop {
+                    let (stream, _) = match listener.accept().await {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Proxy failed to accept a connection: {}", e);
+                            continue;
+                        }
+                    };
+                    let routes = routes.clone();
+                    let ca = ca.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = tunnel(stream, &routes, &ca).await {
+                            debug!("Proxy tunnel closed: {}", e);
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(Self { addr, routes, task })
+    }
+
+    /// URL of the proxy, for `HTTPS_PROXY`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Forward connections to `host` to the plain HTTP server at `upstream`
+    pub fn route(&self, host: &str, upstream: SocketAddr) {
+        self.routes
+            .write()
+            .expect("proxy routes poisoned")
+            .insert(host.to_string(), upstream);
+    }
+}
+
+impl Drop for HostProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer one CONNECT request and relay the decrypted stream
+async fn tunnel(mut stream: TcpStream, routes: &Routes, ca: &TestCa) -> Result<(), HarnessError> {
+    let head = read_request_head(&mut stream).await?;
+    let target = head
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("CONNECT "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string);
+    let Some(target) = target else {
+        stream
+            .write_all(b"HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\n\r\n")
+            .await?;
+        return Ok(());
+    };
+    let host = target
+        .rsplit_once(':')
+        .map_or(target.as_str(), |(host, _)| host);
+
+    let upstream = routes
+        .read()
+        .expect("proxy routes poisoned")
+        .get(host)
+        .copied();
+    let Some(upstream) = upstream else {
+        warn!("Proxy has no route for {}", host);
+        stream
+            .write_all(b"HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\n\r\n")
+            .await?;
+        return Ok(());
+    };
+
+    stream
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    let mut tls = TlsAcceptor::from(ca.server_config(host)?)
+        .accept(stream)
+        .await?;
+    let mut upstream = TcpStream::connect(upstream).await?;
+    tokio::io::copy_bidirectional(&mut tls, &mut upstream).await?;
+    Ok(())
+}
+
+/// Read the request line and headers up to the blank line
+async fn read_request_head(stream: &mut TcpStream) -> Result<String, HarnessError> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    // Byte by byte, so nothing of the TLS handshake after the head is consumed
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_REQUEST_HEAD || stream.read(&mut byte).await? == 0 {
+            return Err(HarnessError::Proxy(
+                "incomplete CONNECT request".to_string(),
+            ));
+        }
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
@@ -0,0 +1,101 @@
+//! Test certificate authority for the fake federation domains
+//!
+//! ActivityPub IDs are `https://` URLs, so every domain of a test federation
+//! needs a certificate its peers trust. [`TestCa`] issues them on demand from
+//! a throwaway CA; the daemons trust it through `SSL_CERT_FILE` and the test
+//! client through [`TestCa::root_certificate`].
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+use crate::HarnessError;
+
+/// Certificate authority issuing server certificates for test domains
+pub struct TestCa {
+    certificate: rcgen::Certificate,
+    key: KeyPair,
+    configs: Mutex<HashMap<String, Arc<ServerConfig>>>,
+}
+
+impl std::fmt::Debug for TestCa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestCa").finish_non_exhaustive()
+    }
+}
+
+impl TestCa {
+    /// Create a new CA with a fresh key
+    pub fn new() -> Result<Self, HarnessError> {
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(Vec::<String>::new())?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Oxifed federation test CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        let certificate = params.self_signed(&key)?;
+
+        Ok(Self {
+            certificate,
+            key,
+            configs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// CA certificate in PEM format
+    pub fn pem(&self) -> String {
+        self.certificate.pem()
+    }
+
+    /// Write the CA certificate to `path`, for `SSL_CERT_FILE`
+    pub fn write_pem(&self, path: &Path) -> Result<(), HarnessError> {
+        std::fs::write(path, self.pem())?;
+        Ok(())
+    }
+
+    /// CA certificate for reqwest clients of the tests
+    pub fn root_certificate(&self) -> Result<reqwest::Certificate, HarnessError> {
+        Ok(reqwest::Certificate::from_pem(self.pem().as_bytes())?)
+    }
+
+    /// TLS configuration serving a certificate for `host`
+    ///
+    /// Certificates are issued on first use and kept for the life of the CA.
+    pub fn server_config(&self, host: &str) -> Result<Arc<ServerConfig>, HarnessError> {
+        let mut configs = self.configs.lock().expect("certificate cache poisoned");
+        if let Some(config) = configs.get(host) {
+            return Ok(config.clone());
+        }
+
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec![host.to_string()])?;
+        params.distinguished_name.push(DnType::CommonName, host);
+        let certificate = params.signed_by(&key, &self.certificate, &self.key)?;
+
+        let chain = vec![
+            certificate.der().clone(),
+            CertificateDer::from(self.certificate.der().to_vec()),
+        ];
+        let private_key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+        let mut config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_single_cert(chain, private_key)?;
+        // The upstream servers are spoken to in plain HTTP/1.1
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let config = Arc::new(config);
+        configs.insert(host.to_string(), config.clone());
+        Ok(config)
+    }
+}
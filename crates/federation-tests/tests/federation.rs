@@ -0,0 +1,233 @@
+//! Federation between two Oxifed instances and a mock remote server
+//!
+//! One federation is started for the whole scenario, as bringing up the
+//! containers and eight daemons takes a while; the steps build on each
+//! other's accounts and follows.
+
+use federation_tests::{Federation, HarnessError, eventually, should_run};
+use oxifed::database::FollowStatus;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_federation_flows() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .try_init()
+        .ok();
+
+    if !should_run() {
+        eprintln!("Skipping federation tests (set OXIFED_RUN_E2E=1 to enable)");
+        return;
+    }
+
+    let federation = Federation::start()
+        .await
+        .expect("Failed to start the test federation");
+    let logs = federation.log_dir().display().to_string();
+
+    let alice = federation
+        .alpha
+        .create_person("alice")
+        .await
+        .expect("Failed to create alice");
+    let bob = federation
+        .beta
+        .create_person("bob")
+        .await
+        .expect("Failed to create bob");
+
+    webfinger_discovery(&federation, &alice)
+        .await
+        .unwrap_or_else(|e| panic!("WebFinger discovery failed ({}, logs in {})", e, logs));
+    follow_and_deliver(&federation, &alice, &bob)
+        .await
+        .unwrap_or_else(|e| panic!("Follow and delivery failed ({}, logs in {})", e, logs));
+    remote_follow_reply_undo(&federation, &alice)
+        .await
+        .unwrap_or_else(|e| panic!("Remote interaction failed ({}, logs in {})", e, logs));
+    unsigned_inbox_post(&federation, &alice)
+        .await
+        .unwrap_or_else(|e| panic!("Unsigned inbox post check failed ({}, logs in {})", e, logs));
+}
+
+/// alice is found through WebFinger and her actor serves a key
+async fn webfinger_discovery(federation: &Federation, alice: &str) -> Result<(), HarnessError> {
+    let resource = format!("acct:alice@{}", federation.alpha.domain());
+    let webfinger: Value = federation
+        .client()
+        .get(format!(
+            "https://{}/.well-known/webfinger",
+            federation.alpha.domain()
+        ))
+        .query(&[("resource", resource.as_str())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    assert_eq!(webfinger["subject"], resource);
+    let self_link = webfinger["links"]
+        .as_array()
+        .and_then(|links| links.iter().find(|link| link["rel"] == "self"))
+        .expect("WebFinger response has no self link");
+    assert_eq!(self_link["href"], alice);
+
+    let actor = federation.fetch(alice).await?;
+    assert_eq!(actor["id"], alice);
+    assert!(actor["inbox"].is_string());
+    assert!(actor["publicKey"]["publicKeyPem"].is_string());
+    Ok(())
+}
+
+/// bob follows alice across instances, is accepted and gets her posts
+async fn follow_and_deliver(
+    federation: &Federation,
+    alice: &str,
+    bob: &str,
+) -> Result<(), HarnessError> {
+    federation.beta.follow("bob", alice).await?;
+
+    eventually("beta to see the follow accepted", || async {
+        let follow = federation.beta.find_follow(bob, alice).await.ok()??;
+        (follow.status == FollowStatus::Accepted).then_some(())
+    })
+    .await?;
+    eventually("alpha to record bob as follower", || async {
+        let followers = federation
+            .alpha
+            .db()
+            .get_actor_followers(alice)
+            .await
+            .ok()?;
+        followers
+            .iter()
+            .any(|follower| follower == bob)
+            .then_some(())
+    })
+    .await?;
+
+    let note = federation
+        .alpha
+        .post_note("alice", "Hello from alpha")
+        .await?;
+    eventually("the note to reach beta", || async {
+        federation.beta.db().find_object_by_id(&note).await.ok()?
+    })
+    .await?;
+    Ok(())
+}
+
+/// A foreign actor follows alice, replies to her and withdraws the follow
+///
+/// Checks signatures both ways: alpha verifies the mock's Ed25519
+/// signatures and the mock verifies the Accept alpha signs.
+async fn remote_follow_reply_undo(
+    federation: &Federation,
+    alice: &str,
+) -> Result<(), HarnessError> {
+    let remote = &federation.remote;
+    let carol = remote.actor_id("carol");
+    let inbox = format!("{}/inbox", alice);
+
+    let follow = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/follows/{}", carol, Uuid::new_v4()),
+        "type": "Follow",
+        "actor": carol,
+        "object": alice
+    });
+    let status = remote.send("carol", &inbox, &follow).await?;
+    assert!(status.is_success(), "Follow answered with {}", status);
+
+    let accept = remote
+        .wait_for_delivery("the Accept of carol's follow", |delivery| {
+            delivery.activity_type() == Some("Accept")
+        })
+        .await?;
+    let signer = accept.signer.expect("Accept signature did not verify");
+    assert_eq!(signer.owner, alice);
+
+    let note = federation
+        .alpha
+        .post_note("alice", "Hello, followers on other servers")
+        .await?;
+    remote
+        .wait_for_delivery("alice's note at the remote", |delivery| {
+            delivery.activity_type() == Some("Create")
+                && delivery.activity["object"]["id"] == note.as_str()
+        })
+        .await?;
+
+    let reply_id = format!("{}/notes/{}", carol, Uuid::new_v4());
+    let reply = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/activity", reply_id),
+        "type": "Create",
+        "actor": carol,
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": [alice],
+        "object": {
+            "id": reply_id,
+            "type": "Note",
+            "attributedTo": carol,
+            "inReplyTo": note,
+            "content": "Hello back from a remote server",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"],
+            "cc": [alice]
+        }
+    });
+    let status = remote.send("carol", &inbox, &reply).await?;
+    assert!(status.is_success(), "Reply answered with {}", status);
+    eventually("alpha to store the reply", || async {
+        federation
+            .alpha
+            .db()
+            .find_object_by_id(&reply_id)
+            .await
+            .ok()?
+    })
+    .await?;
+
+    let undo = json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "id": format!("{}/undo/{}", carol, Uuid::new_v4()),
+        "type": "Undo",
+        "actor": carol,
+        "object": follow
+    });
+    let status = remote.send("carol", &inbox, &undo).await?;
+    assert!(status.is_success(), "Undo answered with {}", status);
+    eventually("alpha to drop carol as follower", || async {
+        let followers = federation
+            .alpha
+            .db()
+            .get_actor_followers(alice)
+            .await
+            .ok()?;
+        (!followers.contains(&carol)).then_some(())
+    })
+    .await?;
+    Ok(())
+}
+
+/// Inbox posts without a signature are refused
+async fn unsigned_inbox_post(federation: &Federation, alice: &str) -> Result<(), HarnessError> {
+    let carol = federation.remote.actor_id("carol");
+    let response = federation
+        .client()
+        .post(format!("{}/inbox", alice))
+        .header(reqwest::header::CONTENT_TYPE, "application/activity+json")
+        .json(&json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{}/follows/{}", carol, Uuid::new_v4()),
+            "type": "Follow",
+            "actor": carol,
+            "object": alice
+        }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    Ok(())
+}
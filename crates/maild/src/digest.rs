@@ -0,0 +1,65 @@
+//! Admin digest
+//!
+//! Periodically emails the contact address of every active domain how
+//! many registration applications and reports wait for review. Domains with
+//! nothing to review get no digest, and domains can turn it off with
+//! `"digest": false` in the `email` object of their config. Run a single
+//! maild replica with digests enabled, or domains get one per replica.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use oxifed::database::{DatabaseError, DatabaseManager};
+use oxifed::messaging::{EmailKind, EmailMessage};
+use oxifed::shutdown::Shutdown;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::mailer::email_settings;
+
+/// Queue digests every `interval` until shutdown begins
+pub async fn run_digest(db: Arc<DatabaseManager>, interval: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes at once; restarts should not send digests
+    interval.tick().await;
+
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        match queue_digests(&db).await {
+            Ok(0) => {}
+            Ok(count) => info!("Queued admin digests of {} domains", count),
+            Err(e) => warn!("Admin digest failed: {}", e),
+        }
+    }
+}
+
+/// Queue the digest of every domain with something to review
+async fn queue_digests(db: &DatabaseManager) -> Result<usize, DatabaseError> {
+    let mut queued = 0;
+    for domain in db.find_domains_with_contact().await? {
+        if !email_settings(&domain).digest.unwrap_or(true) {
+            continue;
+        }
+        let Some(contact) = domain.contact_email.clone() else {
+            continue;
+        };
+
+        let pending_applications = db.count_pending_applications(&domain.domain).await?;
+        let open_reports = db.count_open_reports(&domain.domain).await?;
+        if pending_applications == 0 && open_reports == 0 {
+            continue;
+        }
+
+        db.queue_email(&EmailMessage::new(
+            EmailKind::AdminDigest,
+            domain.domain.clone(),
+            contact,
+            json!({
+                "pending_applications": pending_applications,
+                "open_reports": open_reports,
+            }),
+        ))
+        .await?;
+        queued += 1;
+    }
+    Ok(queued)
+}
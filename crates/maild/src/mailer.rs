@@ -0,0 +1,305 @@
+//! Sending queued emails
+//!
+//! Emails arrive on `oxifed.email` as [`EmailMessage`]s. Each is rendered
+//! from the templates of its kind and sent through the SMTP relay from the
+//! sender of its domain: the `sender` of the `email` object in the domain's
+//! config, or else the configured default sender. Emails that cannot be
+//! sent go to the dead-letter exchange, marked transient when the relay or
+//! the database may recover.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lapin::options::BasicPublishOptions;
+use lapin::types::{AMQPValue, FieldTable, ShortString};
+use lapin::{BasicProperties, Channel};
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use oxifed::bus::{Binding, BusDelivery, MessageBus, Subscription};
+use oxifed::database::{DatabaseManager, DomainDocument};
+use oxifed::messaging::{
+    EXCHANGE_DEAD_LETTER, EXCHANGE_EMAIL, EmailMessage, FailureClass, HEADER_FAILURE_CLASS,
+    HEADER_ORIGINAL_QUEUE, HEADER_REJECTION_REASON, HEADER_REJECTION_STAGE, QUEUE_EMAIL,
+};
+use oxifed::shutdown::Shutdown;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::templates::Templates;
+use crate::{MaildConfig, MaildError};
+
+/// The `email` object of a domain's config
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DomainEmailSettings {
+    /// Sender of the domain's emails, such as `Example <hello@example.com>`
+    pub sender: Option<String>,
+    /// Whether the contact address gets the admin digest; on by default
+    pub digest: Option<bool>,
+}
+
+/// Email settings of a domain, the defaults if it sets none
+pub fn email_settings(domain: &DomainDocument) -> DomainEmailSettings {
+    let Some(section) = domain
+        .config
+        .as_ref()
+        .and_then(|config| config.get_document("email").ok())
+    else {
+        return DomainEmailSettings::default();
+    };
+
+    mongodb::bson::from_document(section.clone()).unwrap_or_else(|e| {
+        warn!(
+            "Ignoring invalid email settings of domain {}: {}",
+            domain.domain, e
+        );
+        DomainEmailSettings::default()
+    })
+}
+
+/// Default sender of a domain, `{domain}` replaced by its name
+pub fn default_sender(template: &str, domain: &str) -> String {
+    template.replace("{domain}", domain)
+}
+
+/// Renders emails and sends them through the SMTP relay
+pub struct Mailer {
+    db: Arc<DatabaseManager>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    templates: Templates,
+    default_sender: String,
+}
+
+impl Mailer {
+    pub fn new(db: Arc<DatabaseManager>, config: &MaildConfig) -> Result<Self, MaildError> {
+        Ok(Self {
+            db,
+            transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(&config.smtp_url)?.build(),
+            templates: Templates::load(config.template_dir.as_deref())?,
+            default_sender: config.default_sender.clone(),
+        })
+    }
+
+    /// Render and send an email
+    ///
+    /// Returns the error and whether retrying may help.
+    pub async fn send(&self, email: &EmailMessage) -> Result<(), (String, FailureClass)> {
+        let (subject, body) = self
+            .templates
+            .render(email)
+            .map_err(|e| (e.to_string(), FailureClass::Permanent))?;
+        let from = self.sender(&email.domain).await?;
+        let to: Mailbox = email.to.parse().map_err(|e| {
+            (
+                format!("Invalid recipient {}: {}", email.to, e),
+                FailureClass::Permanent,
+            )
+        })?;
+
+        let message = lettre::Message::builder()
+            .message_id(Some(format!("<{}@{}>", email.id, email.domain)))
+            .from(from)
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| (e.to_string(), FailureClass::Permanent))?;
+
+        self.transport.send(message).await.map_err(|e| {
+            let class = if e.is_permanent() {
+                FailureClass::Permanent
+            } else {
+                FailureClass::Transient
+            };
+            (e.to_string(), class)
+        })?;
+        debug!("Sent {} email {} to {}", email.kind, email.id, email.to);
+        Ok(())
+    }
+
+    /// Sender of a domain's emails
+    async fn sender(&self, domain: &str) -> Result<Mailbox, (String, FailureClass)> {
+        let domain_doc = self
+            .db
+            .find_domain_by_name(domain)
+            .await
+            .map_err(|e| (e.to_string(), FailureClass::Transient))?;
+        if let Some(sender) = domain_doc
+            .as_ref()
+            .and_then(|doc| email_settings(doc).sender)
+        {
+            match sender.parse() {
+                Ok(sender) => return Ok(sender),
+                Err(e) => warn!("Ignoring invalid sender {} of {}: {}", sender, domain, e),
+            }
+        }
+
+        let sender = default_sender(&self.default_sender, domain);
+        sender.parse().map_err(|e| {
+            (
+                format!("Invalid sender {}: {}", sender, e),
+                FailureClass::Permanent,
+            )
+        })
+    }
+}
+
+/// Send queued emails, dead-lettering the ones that fail
+///
+/// Emails are consumed through the configured message bus; failed ones are
+/// published on `channel`, since the dead-letter queue is consumed over AMQP.
+pub async fn run_email_consumer(
+    bus: Arc<dyn MessageBus>,
+    channel: Channel,
+    mailer: Arc<Mailer>,
+    prefetch: u16,
+    shutdown: Shutdown,
+) -> Result<(), MaildError> {
+    let subscription = Subscription {
+        queue: QUEUE_EMAIL.to_string(),
+        bindings: vec![Binding::fanout(EXCHANGE_EMAIL)],
+        prefetch,
+        durable: true,
+    };
+    let mut consumer = bus.subscribe(&subscription).await?;
+
+    info!("Email consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = delivery?;
+        let failure = match serde_json::from_slice::<EmailMessage>(&delivery.payload) {
+            Ok(email) => match oxifed::poison::isolate(mailer.send(&email)).await {
+                Ok(sent) => sent.err(),
+                Err(panic) => {
+                    oxifed::poison::record(QUEUE_EMAIL, &panic);
+                    Some((
+                        format!("handler panicked: {}", panic),
+                        FailureClass::Permanent,
+                    ))
+                }
+            },
+            Err(e) => Some((e.to_string(), FailureClass::Permanent)),
+        };
+
+        if let Some((error, class)) = failure {
+            warn!("Sending email failed ({}): {}", class.as_str(), error);
+            dead_letter(&channel, &delivery, &error, class).await?;
+        }
+        delivery.ack().await?;
+    }
+
+    if !shutdown.is_triggered() {
+        warn!("Email consumer stopped");
+    }
+    Ok(())
+}
+
+/// Publish a failed email to the dead-letter exchange
+///
+/// The delivery's headers are kept, so the retry count of a re-injected
+/// email carries over.
+async fn dead_letter(
+    channel: &Channel,
+    delivery: &BusDelivery,
+    error: &str,
+    class: FailureClass,
+) -> Result<(), MaildError> {
+    let mut headers = FieldTable::default();
+    for (key, value) in &delivery.headers {
+        headers.insert(
+            ShortString::from(key.clone()),
+            AMQPValue::LongString(value.clone().into()),
+        );
+    }
+    headers.insert(
+        HEADER_REJECTION_REASON.into(),
+        AMQPValue::LongString(error.into()),
+    );
+    headers.insert(
+        HEADER_REJECTION_STAGE.into(),
+        AMQPValue::LongString("email".into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_QUEUE.into(),
+        AMQPValue::LongString(QUEUE_EMAIL.into()),
+    );
+    headers.insert(
+        HEADER_FAILURE_CLASS.into(),
+        AMQPValue::LongString(class.as_str().into()),
+    );
+
+    let mut properties = BasicProperties::default()
+        .with_delivery_mode(2)
+        .with_headers(headers);
+    if let Some(message_id) = &delivery.message_id {
+        properties = properties.with_message_id(message_id.clone().into());
+    }
+    if let Some(content_type) = &delivery.content_type {
+        properties = properties.with_content_type(content_type.clone().into());
+    }
+
+    channel
+        .basic_publish(
+            EXCHANGE_DEAD_LETTER,
+            QUEUE_EMAIL,
+            BasicPublishOptions::default(),
+            &delivery.payload,
+            properties,
+        )
+        .await?
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mongodb::bson::doc;
+    use oxifed::database::{DomainStatus, RegistrationMode};
+
+    fn domain(config: Option<mongodb::bson::Document>) -> DomainDocument {
+        DomainDocument {
+            id: None,
+            domain: "example.com".to_string(),
+            name: None,
+            description: None,
+            contact_email: Some("admin@example.com".to_string()),
+            rules: None,
+            registration_mode: RegistrationMode::Open,
+            authorized_fetch: false,
+            max_note_length: None,
+            max_file_size: None,
+            allowed_file_types: None,
+            domain_key_id: None,
+            config,
+            status: DomainStatus::Active,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_email_settings() {
+        let settings = email_settings(&domain(None));
+        assert!(settings.sender.is_none());
+        assert!(settings.digest.is_none());
+
+        let settings = email_settings(&domain(Some(doc! {
+            "email": { "sender": "Example <hello@example.com>", "digest": false },
+        })));
+        assert_eq!(
+            settings.sender.as_deref(),
+            Some("Example <hello@example.com>")
+        );
+        assert_eq!(settings.digest, Some(false));
+    }
+
+    #[test]
+    fn test_default_sender() {
+        let sender = default_sender("Oxifed <noreply@{domain}>", "example.com");
+        let mailbox: Mailbox = sender.parse().unwrap();
+        assert_eq!(mailbox.email.to_string(), "noreply@example.com");
+    }
+}
@@ -0,0 +1,177 @@
+//! Email templates
+//!
+//! Every [`EmailKind`] has a subject template `<kind>.subject` and a plain
+//! text body template `<kind>.txt`. They are rendered with minijinja from
+//! the email's data plus its `domain`. The built-in templates are compiled
+//! in; files of the same name in the template directory replace them.
+
+use std::path::Path;
+
+use minijinja::Environment;
+use oxifed::messaging::{EmailKind, EmailMessage};
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::MaildError;
+
+/// Built-in templates by name
+const BUILTIN: [(&str, &str); 8] = [
+    (
+        "registration_confirmation.subject",
+        include_str!("../templates/registration_confirmation.subject"),
+    ),
+    (
+        "registration_confirmation.txt",
+        include_str!("../templates/registration_confirmation.txt"),
+    ),
+    (
+        "password_reset.subject",
+        include_str!("../templates/password_reset.subject"),
+    ),
+    (
+        "password_reset.txt",
+        include_str!("../templates/password_reset.txt"),
+    ),
+    (
+        "moderation_notice.subject",
+        include_str!("../templates/moderation_notice.subject"),
+    ),
+    (
+        "moderation_notice.txt",
+        include_str!("../templates/moderation_notice.txt"),
+    ),
+    (
+        "admin_digest.subject",
+        include_str!("../templates/admin_digest.subject"),
+    ),
+    (
+        "admin_digest.txt",
+        include_str!("../templates/admin_digest.txt"),
+    ),
+];
+
+/// Compiled subject and body templates of all email kinds
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Compile the built-in templates, replaced by the files in `dir`
+    pub fn load(dir: Option<&Path>) -> Result<Self, MaildError> {
+        let mut env = Environment::new();
+        for (name, builtin) in BUILTIN {
+            let source = match dir.map(|dir| dir.join(name)).filter(|path| path.is_file()) {
+                Some(path) => {
+                    info!("Using template {}", path.display());
+                    std::fs::read_to_string(path)?
+                }
+                None => builtin.to_string(),
+            };
+            env.add_template_owned(name, source)?;
+        }
+        Ok(Self { env })
+    }
+
+    /// Subject and body of an email
+    pub fn render(&self, email: &EmailMessage) -> Result<(String, String), minijinja::Error> {
+        let mut context = match &email.data {
+            Value::Object(data) => data.clone(),
+            _ => Map::new(),
+        };
+        context.insert("domain".to_string(), Value::from(email.domain.clone()));
+
+        let subject = self.template(email.kind, "subject")?.render(&context)?;
+        let body = self.template(email.kind, "txt")?.render(&context)?;
+        // Subjects are a single header line
+        let subject = subject
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        Ok((subject, body))
+    }
+
+    fn template(
+        &self,
+        kind: EmailKind,
+        extension: &str,
+    ) -> Result<minijinja::Template<'_, '_>, minijinja::Error> {
+        self.env
+            .get_template(&format!("{}.{}", kind.as_str(), extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn email(kind: EmailKind, data: Value) -> EmailMessage {
+        EmailMessage::new(
+            kind,
+            "example.com".to_string(),
+            "alice@example.org".to_string(),
+            data,
+        )
+    }
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let templates = Templates::load(None).unwrap();
+        for kind in EmailKind::ALL {
+            let (subject, body) = templates
+                .render(&email(
+                    kind,
+                    json!({
+                        "username": "alice",
+                        "status": "active",
+                        "state": "suspended",
+                        "login_url": "https://example.com/",
+                        "reset_url": "https://example.com/auth/password?token=t",
+                        "expires_at": "2026-01-01T00:00:00+00:00",
+                        "pending_applications": 2,
+                        "open_reports": 1,
+                    }),
+                ))
+                .unwrap();
+            assert!(subject.contains("example.com"), "{}: {}", kind, subject);
+            assert!(!body.trim().is_empty(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_registration_status() {
+        let templates = Templates::load(None).unwrap();
+        let (subject, body) = templates
+            .render(&email(
+                EmailKind::RegistrationConfirmation,
+                json!({ "username": "alice", "status": "rejected", "note": "No bots" }),
+            ))
+            .unwrap();
+        assert_eq!(subject, "Your application on example.com was declined");
+        assert!(body.contains("No bots"));
+        assert!(!body.contains("Log in"));
+    }
+
+    #[test]
+    fn test_template_dir_overrides() {
+        let dir = std::env::temp_dir().join(format!("maild-templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("password_reset.subject"),
+            "Reset for {{ username }}",
+        )
+        .unwrap();
+
+        let templates = Templates::load(Some(&dir)).unwrap();
+        let (subject, body) = templates
+            .render(&email(
+                EmailKind::PasswordReset,
+                json!({ "username": "alice", "reset_url": "https://example.com/r" }),
+            ))
+            .unwrap();
+        assert_eq!(subject, "Reset for alice");
+        assert!(body.contains("https://example.com/r"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Moderation actions applied when resolving reports
+//!
+//! Deleting local content and suspending local actors emit `Delete`
+//! activities to the ActivityPub publish exchange so that remote servers
+//! drop their copies. Actions against remote actors and domains only change
+//! local state. Suspended local users who gave an email address are told
+//! by email.
+
+use chrono::Utc;
+use lapin::{BasicProperties, Channel, options::BasicPublishOptions};
+use mongodb::bson::doc;
+use oxifed::Activity;
+use oxifed::database::{
+    ActorStatus, DatabaseManager, DomainBlockDocument, DomainBlockSeverity, ReportDocument,
+    ReportStatus,
+};
+use oxifed::messaging::{
+    DeliveryPriority, EXCHANGE_ACTIVITYPUB_DELIVERY, EmailKind, ModerationAction, ModerationState,
+};
+use serde_json::json;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::ModerationError;
+
+const PUBLIC_COLLECTION: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Apply a moderation action to an open report and close it
+pub async fn resolve_report(
+    db: &DatabaseManager,
+    channel: &Channel,
+    report_id: &str,
+    action: ModerationAction,
+    moderator: &str,
+    note: Option<&str>,
+) -> Result<ReportDocument, ModerationError> {
+    let report = db
+        .find_report_by_id(report_id)
+        .await?
+        .ok_or_else(|| ModerationError::ReportNotFound(report_id.to_string()))?;
+
+    if report.status != ReportStatus::Open {
+        return Err(ModerationError::AlreadyResolved(report_id.to_string()));
+    }
+
+    let status = match action {
+        ModerationAction::Dismiss => ReportStatus::Dismissed,
+        ModerationAction::DeleteContent => {
+            for object_id in &report.target_objects {
+                delete_content(db, channel, object_id).await?;
+            }
+            ReportStatus::Resolved
+        }
+        ModerationAction::SuspendActor => {
+            let actor_id = report
+                .target_actor
+                .as_deref()
+                .ok_or(ModerationError::MissingTarget("actor"))?;
+            suspend_actor(db, channel, actor_id).await?;
+            ReportStatus::Resolved
+        }
+        ModerationAction::SilenceDomain => {
+            let actor_id = report
+                .target_actor
+                .as_deref()
+                .ok_or(ModerationError::MissingTarget("actor"))?;
+            silence_domain(db, actor_id, moderator, report.content.clone()).await?;
+            ReportStatus::Resolved
+        }
+    };
+
+    db.resolve_report(report_id, status, action_name(action), moderator, note)
+        .await?;
+    info!(
+        "Report {} resolved by {} with action {}",
+        report_id,
+        moderator,
+        action_name(action)
+    );
+
+    db.find_report_by_id(report_id)
+        .await?
+        .ok_or_else(|| ModerationError::ReportNotFound(report_id.to_string()))
+}
+
+/// Name of an action as stored on the report
+fn action_name(action: ModerationAction) -> &'static str {
+    match action {
+        ModerationAction::Dismiss => "dismiss",
+        ModerationAction::DeleteContent => "delete_content",
+        ModerationAction::SuspendActor => "suspend_actor",
+        ModerationAction::SilenceDomain => "silence_domain",
+    }
+}
+
+/// Delete a reported object, federating the deletion if it is local
+async fn delete_content(
+    db: &DatabaseManager,
+    channel: &Channel,
+    object_id: &str,
+) -> Result<(), ModerationError> {
+    let Some(object) = db.find_object_by_id(object_id).await? else {
+        warn!("Reported object {} no longer exists", object_id);
+        return Ok(());
+    };
+
+    db.delete_object(object_id).await?;
+    info!("Deleted reported object {}", object_id);
+
+    if object.local {
+        let mut audience = object.to.clone().unwrap_or_default();
+        audience.extend(object.cc.clone().unwrap_or_default());
+        let audience = expand_audience(db, &object.attributed_to, audience).await?;
+
+        let activity = delete_activity(&object.attributed_to, object_id, audience)?;
+        publish_activity(channel, &activity).await?;
+    }
+
+    Ok(())
+}
+
+/// Suspend a reported actor, federating a Delete if the actor is local
+async fn suspend_actor(
+    db: &DatabaseManager,
+    channel: &Channel,
+    actor_id: &str,
+) -> Result<(), ModerationError> {
+    let actor = db
+        .find_actor_by_id(actor_id)
+        .await?
+        .ok_or_else(|| ModerationError::ActorNotFound(actor_id.to_string()))?;
+
+    db.update_actor(
+        actor_id,
+        doc! { "status": mongodb::bson::to_bson(&ActorStatus::Suspended)? },
+    )
+    .await?;
+    info!("Suspended actor {}", actor_id);
+
+    if actor.local {
+        let audience = expand_audience(
+            db,
+            actor_id,
+            vec![PUBLIC_COLLECTION.to_string(), actor.followers.clone()],
+        )
+        .await?;
+        let activity = delete_activity(actor_id, actor_id, audience)?;
+        publish_activity(channel, &activity).await?;
+
+        if actor.status != ActorStatus::Suspended {
+            db.queue_user_email(
+                &actor.preferred_username,
+                &actor.domain,
+                EmailKind::ModerationNotice,
+                json!({
+                    "username": actor.preferred_username,
+                    "state": ModerationState::Suspended.to_string(),
+                }),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Silence the domain of a reported actor
+async fn silence_domain(
+    db: &DatabaseManager,
+    actor_id: &str,
+    moderator: &str,
+    reason: Option<String>,
+) -> Result<(), ModerationError> {
+    let domain = Url::parse(actor_id)?
+        .host_str()
+        .map(|host| host.to_string())
+        .ok_or(ModerationError::MissingTarget("domain"))?;
+
+    if db.find_domain_by_name(&domain).await?.is_some() {
+        return Err(ModerationError::LocalDomain(domain));
+    }
+
+    db.upsert_domain_block(DomainBlockDocument {
+        id: None,
+        domain: domain.clone(),
+        severity: DomainBlockSeverity::Silence,
+        reason,
+        created_by: Some(moderator.to_string()),
+        created_at: Utc::now(),
+    })
+    .await?;
+    info!("Silenced domain {}", domain);
+
+    Ok(())
+}
+
+/// Replace an actor's followers collection with the individual follower IDs
+///
+/// publisherd resolves inboxes per recipient, so collection URLs must be
+/// expanded before publishing.
+async fn expand_audience(
+    db: &DatabaseManager,
+    actor_id: &str,
+    audience: Vec<String>,
+) -> Result<Vec<String>, ModerationError> {
+    let followers_url = db
+        .find_actor_by_id(actor_id)
+        .await?
+        .map(|actor| actor.followers);
+
+    let mut expanded = Vec::new();
+    for recipient in audience {
+        if Some(&recipient) == followers_url.as_ref() {
+            expanded.extend(db.get_actor_followers(actor_id).await?);
+        } else {
+            expanded.push(recipient);
+        }
+    }
+    expanded.sort();
+    expanded.dedup();
+
+    Ok(expanded)
+}
+
+/// Build a Delete activity for an object or actor
+fn delete_activity(
+    actor_id: &str,
+    object_id: &str,
+    audience: Vec<String>,
+) -> Result<Activity, ModerationError> {
+    let builder = Activity::builder()
+        .delete(actor_id, object_id)
+        .id(&format!("{}#delete-{}", object_id, uuid::Uuid::new_v4()))
+        .published(Utc::now())
+        .with_context();
+    let activity = audience
+        .into_iter()
+        .fold(builder, |builder, recipient| builder.to(recipient))
+        .build()?;
+    Ok(activity)
+}
+
+/// Publish an activity to the delivery exchange
+async fn publish_activity(channel: &Channel, activity: &Activity) -> Result<(), ModerationError> {
+    let payload = serde_json::to_value(activity)?;
+
+    channel
+        .basic_publish(
+            EXCHANGE_ACTIVITYPUB_DELIVERY,
+            DeliveryPriority::for_activity(&payload).routing_key(),
+            BasicPublishOptions::default(),
+            payload.to_string().as_bytes(),
+            BasicProperties::default()
+                .with_content_type("application/activity+json".into())
+                .with_delivery_mode(2),
+        )
+        .await?
+        .await?;
+
+    info!(
+        "Published {:?} activity {:?}",
+        activity.activity_type, activity.id
+    );
+    Ok(())
+}
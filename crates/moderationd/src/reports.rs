@@ -0,0 +1,170 @@
+//! Report intake from `Flag` activities
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use oxifed::database::{DatabaseManager, ReportDocument, ReportStatus};
+use oxifed::messaging::{IncomingActivityMessage, ReportInfo, WebhookEvent, WebhookEventMessage};
+use oxifed_pipeline::{PipelineEnvelope, Stage, StageError, StageOutcome};
+use serde_json::{Value, json};
+use tracing::{debug, info};
+
+use crate::ModerationError;
+
+/// Pipeline stage turning `Flag` activities into reports
+///
+/// Flags end the pipeline here; everything else continues unchanged.
+pub struct ModerationStage {
+    db: Arc<DatabaseManager>,
+}
+
+impl ModerationStage {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self { db }
+    }
+}
+
+impl Stage for ModerationStage {
+    fn name(&self) -> &'static str {
+        "moderation"
+    }
+
+    async fn process(&self, envelope: &PipelineEnvelope) -> Result<StageOutcome, StageError> {
+        match envelope.activity() {
+            Some(activity) if activity.activity_type == "Flag" => {
+                handle_flag(&self.db, activity, envelope.may_deliver()).await?;
+                Ok(StageOutcome::Stop {
+                    reason: "report recorded".to_string(),
+                })
+            }
+            _ => Ok(StageOutcome::Continue),
+        }
+    }
+}
+
+/// Create a report document from an incoming Flag activity
+///
+/// The `report.created` webhook is only sent if `notify` is set.
+pub async fn handle_flag(
+    db: &DatabaseManager,
+    message: &IncomingActivityMessage,
+    notify: bool,
+) -> Result<(), ModerationError> {
+    let activity = &message.activity;
+    let flag_activity_id = activity
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(|s| s.to_string());
+
+    // Ignore redelivered Flags that were already recorded
+    if let Some(flag_id) = &flag_activity_id
+        && db.find_report_by_flag(flag_id).await?.is_some()
+    {
+        debug!("Report for Flag {} already exists", flag_id);
+        return Ok(());
+    }
+
+    let mut target_actor = None;
+    let mut target_objects = Vec::new();
+
+    // The object of a Flag is the reported actor and/or the reported objects
+    for uri in flag_objects(activity) {
+        if db.find_actor_by_id(&uri).await?.is_some() {
+            target_actor.get_or_insert(uri);
+            continue;
+        }
+
+        if let Some(object) = db.find_object_by_id(&uri).await? {
+            target_actor.get_or_insert(object.attributed_to);
+        }
+        target_objects.push(uri);
+    }
+
+    let report = ReportDocument {
+        id: None,
+        report_id: uuid::Uuid::new_v4().to_string(),
+        reporter: message.actor.clone(),
+        target_actor,
+        target_objects,
+        content: activity
+            .get("content")
+            .and_then(|c| c.as_str())
+            .map(|s| s.to_string()),
+        flag_activity_id,
+        domain: message.target_domain.clone(),
+        status: ReportStatus::Open,
+        action_taken: None,
+        resolved_by: None,
+        resolution_note: None,
+        created_at: Utc::now(),
+        resolved_at: None,
+    };
+
+    info!(
+        "Created report {} from {} against {:?}",
+        report.report_id, report.reporter, report.target_actor
+    );
+    let event = WebhookEventMessage::new(
+        WebhookEvent::ReportCreated,
+        report.domain.clone(),
+        json!({
+            "report_id": report.report_id,
+            "reporter": report.reporter,
+            "target_actor": report.target_actor,
+            "target_objects": report.target_objects,
+        }),
+    );
+    db.insert_report(report).await?;
+    if notify {
+        db.queue_webhook_event(&event).await?;
+    }
+
+    Ok(())
+}
+
+/// Extract the object URIs of a Flag, accepting a single value or an array
+fn flag_objects(activity: &Value) -> Vec<String> {
+    let as_uri = |value: &Value| match value {
+        Value::String(uri) => Some(uri.clone()),
+        Value::Object(obj) => obj.get("id").and_then(|id| id.as_str()).map(String::from),
+        _ => None,
+    };
+
+    match activity.get("object") {
+        Some(Value::Array(items)) => items.iter().filter_map(as_uri).collect(),
+        Some(value) => as_uri(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Convert a report document into its RPC representation
+pub fn report_info(report: ReportDocument) -> ReportInfo {
+    ReportInfo {
+        report_id: report.report_id,
+        reporter: report.reporter,
+        target_actor: report.target_actor,
+        target_objects: report.target_objects,
+        content: report.content,
+        status: match report.status {
+            ReportStatus::Open => "open",
+            ReportStatus::Resolved => "resolved",
+            ReportStatus::Dismissed => "dismissed",
+        }
+        .to_string(),
+        action_taken: report.action_taken,
+        resolved_by: report.resolved_by,
+        resolution_note: report.resolution_note,
+        created_at: report.created_at.to_rfc3339(),
+        resolved_at: report.resolved_at.map(|t| t.to_rfc3339()),
+    }
+}
+
+/// Parse a report status filter
+pub fn parse_status(status: &str) -> Option<ReportStatus> {
+    match status {
+        "open" => Some(ReportStatus::Open),
+        "resolved" => Some(ReportStatus::Resolved),
+        "dismissed" => Some(ReportStatus::Dismissed),
+        _ => None,
+    }
+}
@@ -2,22 +2,97 @@
 //!
 //! Replaces the direct AMQP messaging with authenticated HTTP calls.
 
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use miette::{IntoDiagnostic, Result, miette};
+use oxifed::health::SystemHealth;
 use oxifed::messaging::{
-    AnnounceActivityMessage, DomainCreateMessage, DomainInfo, DomainUpdateMessage,
-    FollowActivityMessage, FollowInfo, KeyGenerateMessage, LikeActivityMessage, NoteCreateMessage,
-    NoteUpdateMessage, ProfileCreateMessage, ProfileUpdateMessage, UserCreateMessage, UserInfo,
+    ActorInfo, AnnounceActivityMessage, ApplicationInfo, AuditEntryInfo, DeadLetterInfo,
+    DeliveryLimits, DeliveryStatus, DomainCreateMessage, DomainInfo, DomainUpdateMessage,
+    FederationStats, FollowActivityMessage, FollowDirection, FollowInfo, FollowPage,
+    GroupCreateMessage, InstanceInfo, KeyGenerateMessage, KeyImportMessage, KeyInfo,
+    KeyRevokeMessage, KeyRotateMessage, KeyRotationType, LikeActivityMessage, NoteCreateMessage,
+    NoteInfo, NoteUpdateMessage, Page, ProfileAliasMessage, ProfileCreateMessage,
+    ProfileModerateMessage, ProfileUpdateMessage, ReplayFilter, ReplaySummary, ScheduledNoteInfo,
+    TrustChainReport, UserCreateMessage, UserInfo, WebhookCreateMessage, WebhookInfo,
 };
+use oxifed::pki::{DomainVerificationChallenge, TrustLevel, VerificationMethod};
 use reqwest::StatusCode;
-use serde::Serialize;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// HTTP client for the admin API
+#[derive(Clone)]
 pub struct AdminApiClient {
     client: reqwest::Client,
     base_url: String,
     access_token: String,
+    queue_only: bool,
+}
+
+/// Answer of the admin API to a command
+#[derive(Debug, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum CommandOutcome {
+    /// The command was applied; `id` names the created object or key
+    Done { id: Option<String> },
+    /// The command was queued without waiting for it, see [`AdminApiClient::queue_only`]
+    Queued,
+}
+
+/// Answer of the admin API to the creation of an invite code
+#[derive(Debug, Deserialize)]
+pub struct InviteOutcome {
+    #[serde(flatten)]
+    pub outcome: CommandOutcome,
+    /// The code, which cannot be read back later
+    pub code: String,
+    pub expires_at: Option<String>,
+}
+
+/// Answer of the admin API to a batch of commands
+#[derive(Debug, Deserialize)]
+pub struct BatchOutcome {
+    /// Commands the broker confirmed
+    pub queued: usize,
+    /// Indices of the commands the broker refused
+    pub rejected: Vec<usize>,
+}
+
+/// Error answered by the admin API
+///
+/// Keeps the HTTP status so `main` can exit with a code telling scripts
+/// what went wrong, see [`crate::output::exit_code`].
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{message}")]
+pub struct ApiError {
+    pub status: StatusCode,
+    message: String,
+    #[help]
+    help: Option<String>,
+}
+
+impl ApiError {
+    /// Error for something the API reported as missing
+    pub fn not_found(message: String) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message,
+            help: None,
+        }
+    }
+
+    /// Whether `report` is an API error with the 404 status
+    fn is_not_found(report: &miette::Report) -> bool {
+        report
+            .downcast_ref::<ApiError>()
+            .is_some_and(|e| e.status == StatusCode::NOT_FOUND)
+    }
+}
+
+/// `id` encoded for use as one path segment, as actor and note IDs are URLs
+fn path_segment(id: &str) -> String {
+    url::form_urlencoded::byte_serialize(id.as_bytes()).collect()
 }
 
 impl AdminApiClient {
@@ -28,9 +103,53 @@ impl AdminApiClient {
             client,
             base_url: base_url.trim_end_matches('/').to_string(),
             access_token,
+            queue_only: false,
         })
     }
 
+    /// Return once commands are queued instead of waiting for their outcome
+    pub fn queue_only(mut self, queue_only: bool) -> Self {
+        self.queue_only = queue_only;
+        self
+    }
+
+    /// Send a command and return its outcome
+    async fn command<B: Serialize>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<CommandOutcome> {
+        self.command_with_answer(method, path, body).await
+    }
+
+    /// Send a command whose answer carries more than its outcome
+    async fn command_with_answer<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self
+            .client
+            .request(method, &url)
+            .bearer_auth(&self.access_token);
+        if self.queue_only {
+            request = request.query(&[("async", "true")]);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .into_diagnostic()
+            .map_err(|e| miette!("HTTP request failed: {}", e))?;
+
+        Self::handle_response(response).await
+    }
+
     /// Send an authenticated GET request and deserialize the JSON response
     async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
         let url = format!("{}{}", self.base_url, path);
@@ -82,8 +201,48 @@ impl AdminApiClient {
         Self::handle_status(response).await
     }
 
-    /// Send an authenticated PUT request with a JSON body
-    async fn put<B: Serialize>(&self, path: &str, body: &B) -> Result<()> {
+    /// Send an authenticated DELETE request with query parameters
+    async fn delete_with_query(&self, path: &str, query: &[(&str, &str)]) -> Result<()> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .client
+            .delete(&url)
+            .bearer_auth(&self.access_token)
+            .query(query)
+            .send()
+            .await
+            .into_diagnostic()
+            .map_err(|e| miette!("HTTP request failed: {}", e))?;
+
+        Self::handle_status(response).await
+    }
+
+    /// Send an authenticated POST request with a JSON body and deserialize the JSON response
+    async fn post_json_for<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(body)
+            .send()
+            .await
+            .into_diagnostic()
+            .map_err(|e| miette!("HTTP request failed: {}", e))?;
+
+        Self::handle_response(response).await
+    }
+
+    /// Send an authenticated PUT request with a JSON body and deserialize the JSON response
+    async fn put_json_for<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
         let url = format!("{}{}", self.base_url, path);
         let response = self
             .client
@@ -95,11 +254,26 @@ impl AdminApiClient {
             .into_diagnostic()
             .map_err(|e| miette!("HTTP request failed: {}", e))?;
 
-        Self::handle_status(response).await
+        Self::handle_response(response).await
+    }
+
+    /// Send an authenticated POST request without a body and deserialize the JSON response
+    async fn post_for<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await
+            .into_diagnostic()
+            .map_err(|e| miette!("HTTP request failed: {}", e))?;
+
+        Self::handle_response(response).await
     }
 
-    /// Send an authenticated DELETE request
-    async fn delete(&self, path: &str) -> Result<()> {
+    /// Send an authenticated DELETE request and deserialize the JSON response
+    async fn delete_for<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
         let url = format!("{}{}", self.base_url, path);
         let response = self
             .client
@@ -110,32 +284,13 @@ impl AdminApiClient {
             .into_diagnostic()
             .map_err(|e| miette!("HTTP request failed: {}", e))?;
 
-        Self::handle_status(response).await
+        Self::handle_response(response).await
     }
 
     /// Handle a response that should be deserialized as JSON
     async fn handle_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
-        let status = response.status();
-
-        if status == StatusCode::UNAUTHORIZED {
-            return Err(miette!(
-                help = "Your token may have expired. Try: oxiadm login --issuer-url <URL>",
-                "Authentication failed (401 Unauthorized)"
-            ));
-        }
-
-        if status == StatusCode::NOT_FOUND {
-            let body: Value = response
-                .json()
-                .await
-                .unwrap_or_else(|_| serde_json::json!({"error": "Not found"}));
-            let msg = body["error"].as_str().unwrap_or("Not found");
-            return Err(miette!("{}", msg));
-        }
-
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(miette!("API request failed ({}): {}", status, body));
+        if !response.status().is_success() {
+            return Err(Self::error(response).await.into());
         }
 
         response
@@ -147,23 +302,46 @@ impl AdminApiClient {
 
     /// Handle a response where we only care about the status
     async fn handle_status(response: reqwest::Response) -> Result<()> {
-        let status = response.status();
-
-        if status == StatusCode::UNAUTHORIZED {
-            return Err(miette!(
-                help = "Your token may have expired. Try: oxiadm login --issuer-url <URL>",
-                "Authentication failed (401 Unauthorized)"
-            ));
-        }
-
-        if !status.is_success() && status != StatusCode::ACCEPTED {
-            let body = response.text().await.unwrap_or_default();
-            return Err(miette!("API request failed ({}): {}", status, body));
+        if !response.status().is_success() {
+            return Err(Self::error(response).await.into());
         }
 
         Ok(())
     }
 
+    /// Error for a response with a failure status
+    ///
+    /// Uses the `error` field of the JSON body when the API sent one.
+    async fn error(response: reqwest::Response) -> ApiError {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let error = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string));
+
+        let (message, help) = match status {
+            StatusCode::UNAUTHORIZED => (
+                "Authentication failed (401 Unauthorized)".to_string(),
+                Some("Your token may have expired. Try: oxiadm login --issuer-url <URL>"),
+            ),
+            StatusCode::FORBIDDEN => (
+                error.unwrap_or_else(|| "Forbidden".to_string()),
+                Some("Ask an owner of the instance to grant your account a role that allows it"),
+            ),
+            StatusCode::NOT_FOUND => (error.unwrap_or_else(|| "Not found".to_string()), None),
+            _ => (
+                format!("API request failed ({}): {}", status, error.unwrap_or(body)),
+                None,
+            ),
+        };
+
+        ApiError {
+            status,
+            message,
+            help: help.map(str::to_string),
+        }
+    }
+
     // --- Domain operations ---
 
     pub async fn list_domains(&self) -> Result<Vec<DomainInfo>> {
@@ -174,40 +352,132 @@ impl AdminApiClient {
         let path = format!("/api/v1/domains/{}", name);
         match self.get::<DomainInfo>(&path).await {
             Ok(d) => Ok(Some(d)),
-            Err(e) if e.to_string().contains("Not found") => Ok(None),
+            Err(e) if ApiError::is_not_found(&e) => Ok(None),
             Err(e) => Err(e),
         }
     }
 
-    pub async fn create_domain(&self, message: &DomainCreateMessage) -> Result<()> {
-        self.post("/api/v1/domains", message).await
+    pub async fn create_domain(&self, message: &DomainCreateMessage) -> Result<CommandOutcome> {
+        self.command(reqwest::Method::POST, "/api/v1/domains", Some(message))
+            .await
     }
 
-    pub async fn update_domain(&self, message: &DomainUpdateMessage) -> Result<()> {
+    pub async fn update_domain(&self, message: &DomainUpdateMessage) -> Result<CommandOutcome> {
         let path = format!("/api/v1/domains/{}", message.domain);
-        self.put(&path, message).await
+        self.command(reqwest::Method::PUT, &path, Some(message))
+            .await
     }
 
-    pub async fn delete_domain(&self, name: &str, force: bool) -> Result<()> {
+    pub async fn delete_domain(&self, name: &str, force: bool) -> Result<CommandOutcome> {
         let path = if force {
             format!("/api/v1/domains/{}?force=true", name)
         } else {
             format!("/api/v1/domains/{}", name)
         };
-        self.delete(&path).await
+        self.command(reqwest::Method::DELETE, &path, None::<&()>)
+            .await
+    }
+
+    pub async fn add_relay(&self, domain: &str, relay: &str) -> Result<()> {
+        let path = format!("/api/v1/domains/{}/relays", domain);
+        self.post(&path, &serde_json::json!({ "relay": relay }))
+            .await
+    }
+
+    pub async fn remove_relay(&self, domain: &str, relay: &str) -> Result<()> {
+        let path = format!("/api/v1/domains/{}/relays", domain);
+        self.delete_with_query(&path, &[("relay", relay)]).await
+    }
+
+    pub async fn list_webhooks(&self, domain: &str) -> Result<Vec<WebhookInfo>> {
+        let path = format!("/api/v1/domains/{}/webhooks", domain);
+        self.get(&path).await
+    }
+
+    pub async fn create_webhook(&self, message: &WebhookCreateMessage) -> Result<CommandOutcome> {
+        let path = format!("/api/v1/domains/{}/webhooks", message.domain);
+        self.command(reqwest::Method::POST, &path, Some(message))
+            .await
+    }
+
+    pub async fn delete_webhook(&self, domain: &str, id: &str) -> Result<CommandOutcome> {
+        let path = format!("/api/v1/domains/{}/webhooks/{}", domain, path_segment(id));
+        self.command(reqwest::Method::DELETE, &path, None::<&()>)
+            .await
+    }
+
+    /// Create an invite code for sign-ups on a domain
+    pub async fn create_invite(
+        &self,
+        domain: &str,
+        max_uses: Option<u32>,
+        expires_in_days: Option<u32>,
+    ) -> Result<InviteOutcome> {
+        let path = format!("/api/v1/domains/{}/invites", domain);
+        let body = serde_json::json!({
+            "max_uses": max_uses,
+            "expires_in_days": expires_in_days,
+        });
+        self.command_with_answer(reqwest::Method::POST, &path, Some(&body))
+            .await
     }
 
     // --- User operations ---
 
-    pub async fn list_users(&self) -> Result<Vec<UserInfo>> {
-        self.get("/api/v1/users").await
+    pub async fn list_users(
+        &self,
+        domain: Option<&str>,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<UserInfo>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(domain) = domain {
+            query.push(("domain", domain));
+        }
+        self.get_with_query("/api/v1/users", &query).await
+    }
+
+    pub async fn list_applications(
+        &self,
+        domain: Option<&str>,
+        status: Option<&str>,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<ApplicationInfo>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(domain) = domain {
+            query.push(("domain", domain));
+        }
+        if let Some(status) = status {
+            query.push(("status", status));
+        }
+        self.get_with_query("/api/v1/registrations", &query).await
+    }
+
+    /// Approve or reject a registration application
+    pub async fn review_application(
+        &self,
+        id: &str,
+        approve: bool,
+        note: Option<&str>,
+    ) -> Result<CommandOutcome> {
+        let path = format!(
+            "/api/v1/registrations/{}/{}",
+            path_segment(id),
+            if approve { "approve" } else { "reject" }
+        );
+        let body = serde_json::json!({ "note": note });
+        self.command(reqwest::Method::POST, &path, Some(&body))
+            .await
     }
 
     pub async fn get_user(&self, username: &str) -> Result<Option<UserInfo>> {
         let path = format!("/api/v1/users/{}", username);
         match self.get::<UserInfo>(&path).await {
             Ok(u) => Ok(Some(u)),
-            Err(e) if e.to_string().contains("Not found") => Ok(None),
+            Err(e) if ApiError::is_not_found(&e) => Ok(None),
             Err(e) => Err(e),
         }
     }
@@ -218,42 +488,169 @@ impl AdminApiClient {
 
     // --- Person operations ---
 
-    pub async fn create_person(&self, message: &ProfileCreateMessage) -> Result<()> {
-        self.post("/api/v1/persons", message).await
+    pub async fn list_persons(
+        &self,
+        domain: Option<&str>,
+        local: Option<bool>,
+        status: Option<&str>,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<ActorInfo>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let local = local.map(|local| local.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(domain) = domain {
+            query.push(("domain", domain));
+        }
+        if let Some(local) = &local {
+            query.push(("local", local.as_str()));
+        }
+        if let Some(status) = status {
+            query.push(("status", status));
+        }
+        self.get_with_query("/api/v1/persons", &query).await
+    }
+
+    pub async fn get_person(&self, id: &str) -> Result<Option<ActorInfo>> {
+        let path = format!("/api/v1/persons/{}", path_segment(id));
+        match self.get::<ActorInfo>(&path).await {
+            Ok(p) => Ok(Some(p)),
+            Err(e) if ApiError::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn create_person(&self, message: &ProfileCreateMessage) -> Result<CommandOutcome> {
+        self.command(reqwest::Method::POST, "/api/v1/persons", Some(message))
+            .await
+    }
+
+    /// Queue the creation of a batch of persons or domains at `path`
+    pub async fn create_batch<T: Serialize>(
+        &self,
+        path: &str,
+        messages: &[T],
+    ) -> Result<BatchOutcome> {
+        self.post_json_for(path, &messages).await
+    }
+
+    pub async fn create_group(&self, message: &GroupCreateMessage) -> Result<()> {
+        self.post("/api/v1/groups", message).await
+    }
+
+    pub async fn ban_group_member(&self, group: &str, actor: &str) -> Result<()> {
+        let path = format!("/api/v1/groups/{}/bans", group);
+        self.post(&path, &serde_json::json!({ "actor": actor }))
+            .await
     }
 
-    pub async fn update_person(&self, message: &ProfileUpdateMessage) -> Result<()> {
+    pub async fn export_person(&self, subject: &str) -> Result<()> {
+        let path = format!("/api/v1/persons/{}/export", subject);
+        self.post(&path, &serde_json::json!({})).await
+    }
+
+    pub async fn import_person(&self, subject: &str, archive: &str) -> Result<()> {
+        let path = format!("/api/v1/persons/{}/import", subject);
+        self.post(&path, &serde_json::json!({ "archive": archive }))
+            .await
+    }
+
+    pub async fn update_person(&self, message: &ProfileUpdateMessage) -> Result<CommandOutcome> {
         let path = format!("/api/v1/persons/{}", message.subject);
-        self.put(&path, message).await
+        self.command(reqwest::Method::PUT, &path, Some(message))
+            .await
     }
 
-    pub async fn delete_person(&self, id: &str, force: bool) -> Result<()> {
+    pub async fn delete_person(&self, id: &str, force: bool) -> Result<CommandOutcome> {
         let path = if force {
             format!("/api/v1/persons/{}?force=true", id)
         } else {
             format!("/api/v1/persons/{}", id)
         };
-        self.delete(&path).await
+        self.command(reqwest::Method::DELETE, &path, None::<&()>)
+            .await
+    }
+
+    pub async fn alias_person(&self, message: &ProfileAliasMessage) -> Result<CommandOutcome> {
+        let path = format!("/api/v1/persons/{}/aliases", message.subject);
+        self.command(reqwest::Method::POST, &path, Some(message))
+            .await
+    }
+
+    pub async fn moderate_person(
+        &self,
+        message: &ProfileModerateMessage,
+    ) -> Result<CommandOutcome> {
+        self.command(
+            reqwest::Method::POST,
+            "/api/v1/persons/moderation",
+            Some(message),
+        )
+        .await
     }
 
     // --- Note operations ---
 
-    pub async fn create_note(&self, message: &NoteCreateMessage) -> Result<()> {
-        self.post("/api/v1/notes", message).await
+    pub async fn create_note(&self, message: &NoteCreateMessage) -> Result<CommandOutcome> {
+        self.command(reqwest::Method::POST, "/api/v1/notes", Some(message))
+            .await
+    }
+
+    pub async fn list_notes(
+        &self,
+        actor: Option<&str>,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<NoteInfo>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(actor) = actor {
+            query.push(("actor", actor));
+        }
+        self.get_with_query("/api/v1/notes", &query).await
     }
 
-    pub async fn update_note(&self, message: &NoteUpdateMessage) -> Result<()> {
+    pub async fn get_note(&self, id: &str) -> Result<Option<NoteInfo>> {
+        let path = format!("/api/v1/notes/{}", path_segment(id));
+        match self.get::<NoteInfo>(&path).await {
+            Ok(n) => Ok(Some(n)),
+            Err(e) if ApiError::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn update_note(&self, message: &NoteUpdateMessage) -> Result<CommandOutcome> {
         let path = format!("/api/v1/notes/{}", message.id);
-        self.put(&path, message).await
+        self.command(reqwest::Method::PUT, &path, Some(message))
+            .await
     }
 
-    pub async fn delete_note(&self, id: &str, force: bool) -> Result<()> {
+    pub async fn delete_note(&self, id: &str, force: bool) -> Result<CommandOutcome> {
         let path = if force {
             format!("/api/v1/notes/{}?force=true", id)
         } else {
             format!("/api/v1/notes/{}", id)
         };
-        self.delete(&path).await
+        self.command(reqwest::Method::DELETE, &path, None::<&()>)
+            .await
+    }
+
+    pub async fn list_scheduled_notes(
+        &self,
+        actor: Option<&str>,
+    ) -> Result<Vec<ScheduledNoteInfo>> {
+        match actor {
+            Some(actor) => {
+                self.get_with_query("/api/v1/notes/scheduled", &[("actor", actor)])
+                    .await
+            }
+            None => self.get("/api/v1/notes/scheduled").await,
+        }
+    }
+
+    pub async fn cancel_scheduled_note(&self, id: &str) -> Result<()> {
+        self.delete_with_query("/api/v1/notes/scheduled", &[("id", id)])
+            .await
     }
 
     // --- Activity operations ---
@@ -279,6 +676,12 @@ impl AdminApiClient {
         self.post("/api/v1/activities/announce", &message).await
     }
 
+    /// Delivery state of an activity for each of its inboxes
+    pub async fn delivery_status(&self, activity_id: &str) -> Result<DeliveryStatus> {
+        self.get_with_query("/api/v1/activities/status", &[("id", activity_id)])
+            .await
+    }
+
     // --- Follow query operations ---
 
     pub async fn list_following(&self, actor: &str) -> Result<Vec<FollowInfo>> {
@@ -291,6 +694,29 @@ impl AdminApiClient {
             .await
     }
 
+    pub async fn follow_page(
+        &self,
+        direction: FollowDirection,
+        actor: &str,
+        status: Option<&str>,
+        before: Option<&str>,
+        limit: u32,
+    ) -> Result<FollowPage> {
+        let path = match direction {
+            FollowDirection::Followers => "/api/v1/persons/followers",
+            FollowDirection::Following => "/api/v1/persons/following",
+        };
+        let limit = limit.to_string();
+        let mut query = vec![("actor", actor), ("limit", limit.as_str())];
+        if let Some(status) = status {
+            query.push(("status", status));
+        }
+        if let Some(before) = before {
+            query.push(("before", before));
+        }
+        self.get_with_query(path, &query).await
+    }
+
     // --- Key operations ---
 
     pub async fn generate_key(
@@ -298,8 +724,258 @@ impl AdminApiClient {
         actor: &str,
         algorithm: &str,
         key_size: Option<u32>,
-    ) -> Result<()> {
+    ) -> Result<CommandOutcome> {
         let message = KeyGenerateMessage::new(actor.to_string(), algorithm.to_string(), key_size);
-        self.post("/api/v1/keys/generate", &message).await
+        self.command(
+            reqwest::Method::POST,
+            "/api/v1/keys/generate",
+            Some(&message),
+        )
+        .await
+    }
+
+    pub async fn rotate_key(
+        &self,
+        actor: &str,
+        rotation_type: KeyRotationType,
+        algorithm: Option<&str>,
+        key_size: Option<u32>,
+    ) -> Result<CommandOutcome> {
+        let message = KeyRotateMessage::new(
+            actor.to_string(),
+            rotation_type,
+            algorithm.map(str::to_string),
+            key_size,
+        );
+        self.command(reqwest::Method::POST, "/api/v1/keys/rotate", Some(&message))
+            .await
+    }
+
+    pub async fn import_key(
+        &self,
+        actor: &str,
+        algorithm: &str,
+        public_key_pem: String,
+        private_key_pem: String,
+    ) -> Result<CommandOutcome> {
+        let message = KeyImportMessage::new(
+            actor.to_string(),
+            algorithm.to_string(),
+            public_key_pem,
+            private_key_pem,
+        );
+        self.command(reqwest::Method::POST, "/api/v1/keys/import", Some(&message))
+            .await
+    }
+
+    pub async fn revoke_key(&self, actor: &str, key_id: &str) -> Result<CommandOutcome> {
+        let message = KeyRevokeMessage::new(actor.to_string(), key_id.to_string());
+        self.command(reqwest::Method::POST, "/api/v1/keys/revoke", Some(&message))
+            .await
+    }
+
+    pub async fn start_key_verification(
+        &self,
+        actor: &str,
+        domain: &str,
+        method: VerificationMethod,
+    ) -> Result<DomainVerificationChallenge> {
+        self.post_json_for(
+            "/api/v1/keys/verify",
+            &serde_json::json!({ "actor": actor, "domain": domain, "method": method }),
+        )
+        .await
+    }
+
+    pub async fn complete_key_verification(
+        &self,
+        actor: &str,
+        domain: &str,
+    ) -> Result<DomainVerificationChallenge> {
+        self.post_json_for(
+            "/api/v1/keys/verify/complete",
+            &serde_json::json!({ "actor": actor, "domain": domain }),
+        )
+        .await
+    }
+
+    pub async fn get_trust_chain(&self, key_id: &str) -> Result<Option<TrustChainReport>> {
+        match self
+            .get_with_query::<TrustChainReport>("/api/v1/keys/trust-chain", &[("key_id", key_id)])
+            .await
+        {
+            Ok(report) => Ok(Some(report)),
+            Err(e) if ApiError::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn list_keys(
+        &self,
+        actor: Option<&str>,
+        trust_level: Option<TrustLevel>,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<KeyInfo>> {
+        let trust_level = trust_level.map(|level| format!("{:?}", level));
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(actor) = actor {
+            query.push(("actor", actor));
+        }
+        if let Some(trust_level) = &trust_level {
+            query.push(("trust_level", trust_level.as_str()));
+        }
+        self.get_with_query("/api/v1/keys", &query).await
+    }
+
+    /// Sign `data` with a user key of `actor`, answering the raw signature
+    pub async fn sign(&self, actor: &str, key_id: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let body: Value = self
+            .post_json_for(
+                "/api/v1/keys/sign",
+                &serde_json::json!({
+                    "actor": actor,
+                    "key_id": key_id,
+                    "data": BASE64.encode(data),
+                }),
+            )
+            .await?;
+        let signature = body["signature"]
+            .as_str()
+            .ok_or_else(|| miette!("The admin API answered no signature"))?;
+        BASE64
+            .decode(signature)
+            .into_diagnostic()
+            .map_err(|e| miette!("The admin API answered an invalid signature: {}", e))
+    }
+
+    // --- Remote instance operations ---
+
+    pub async fn list_instances(
+        &self,
+        failing: bool,
+        offset: u64,
+        limit: u32,
+    ) -> Result<Page<InstanceInfo>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if failing {
+            query.push(("failing", "true"));
+        }
+        self.get_with_query("/api/v1/instances", &query).await
+    }
+
+    pub async fn get_instance(&self, domain: &str) -> Result<InstanceInfo> {
+        self.get(&format!("/api/v1/instances/{}", domain)).await
+    }
+
+    pub async fn federation_stats(&self) -> Result<FederationStats> {
+        self.get("/api/v1/instances/stats").await
+    }
+
+    // --- Dead-letter queue operations ---
+
+    pub async fn list_dead_letters(
+        &self,
+        queue: Option<&str>,
+        status: Option<&str>,
+    ) -> Result<Vec<DeadLetterInfo>> {
+        let mut query = Vec::new();
+        if let Some(queue) = queue {
+            query.push(("queue", queue));
+        }
+        if let Some(status) = status {
+            query.push(("status", status));
+        }
+        self.get_with_query("/api/v1/dlq", &query).await
+    }
+
+    pub async fn retry_dead_letters(&self, id: Option<&str>) -> Result<u64> {
+        let path = match id {
+            Some(id) => format!("/api/v1/dlq/{}/retry", id),
+            None => "/api/v1/dlq/retry".to_string(),
+        };
+        let body: Value = self.post_for(&path).await?;
+        Ok(body["retried"].as_u64().unwrap_or(0))
+    }
+
+    pub async fn purge_dead_letters(&self, id: Option<&str>) -> Result<u64> {
+        let path = match id {
+            Some(id) => format!("/api/v1/dlq/{}", id),
+            None => "/api/v1/dlq".to_string(),
+        };
+        let body: Value = self.delete_for(&path).await?;
+        Ok(body["purged"].as_u64().unwrap_or(0))
+    }
+
+    // --- Audit log operations ---
+
+    /// List audit log entries newest first, with the cursor of the next page
+    pub async fn list_audit_entries(
+        &self,
+        actor: Option<&str>,
+        action: Option<&str>,
+        target: Option<&str>,
+        before: Option<&str>,
+        limit: u32,
+    ) -> Result<(Vec<AuditEntryInfo>, Option<String>)> {
+        let limit = limit.to_string();
+        let mut query = vec![("limit", limit.as_str())];
+        for (name, value) in [
+            ("actor", actor),
+            ("action", action),
+            ("target", target),
+            ("before", before),
+        ] {
+            if let Some(value) = value {
+                query.push((name, value));
+            }
+        }
+        let mut body: Value = self.get_with_query("/api/v1/audit", &query).await?;
+        let items = serde_json::from_value(body["items"].take())
+            .into_diagnostic()
+            .map_err(|e| miette!("Failed to parse audit entries: {}", e))?;
+        let next = body["next"].as_str().map(str::to_string);
+        Ok((items, next))
+    }
+
+    // --- System operations ---
+
+    pub async fn system_health(&self) -> Result<SystemHealth> {
+        self.get("/api/v1/system/health").await
+    }
+
+    pub async fn get_delivery_limits(&self) -> Result<DeliveryLimits> {
+        self.get("/api/v1/system/delivery-limits").await
+    }
+
+    /// Change the delivery limits of every running publisherd
+    pub async fn set_delivery_limits(
+        &self,
+        max_in_flight: Option<usize>,
+        max_per_host: Option<usize>,
+    ) -> Result<DeliveryLimits> {
+        let body = serde_json::json!({
+            "max_in_flight": max_in_flight,
+            "max_per_host": max_per_host,
+        });
+        self.put_json_for("/api/v1/system/delivery-limits", &body)
+            .await
+    }
+
+    /// Replay stored remote activities through the incoming pipeline
+    pub async fn replay_activities(
+        &self,
+        filter: &ReplayFilter,
+        limit: u32,
+        dry_run: bool,
+        allow_delivery: bool,
+    ) -> Result<ReplaySummary> {
+        let mut body = serde_json::to_value(filter).into_diagnostic()?;
+        body["limit"] = limit.into();
+        body["dry_run"] = dry_run.into();
+        body["allow_delivery"] = allow_delivery.into();
+        self.post_json_for("/api/v1/system/replay", &body).await
     }
 }
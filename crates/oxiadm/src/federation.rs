@@ -0,0 +1,796 @@
+//! Federation diagnostics
+//!
+//! `oxiadm test federation` walks through what a remote server does when it
+//! federates with a local actor: resolving names, fetching actors with and
+//! without signatures and accepting a signed activity in its inbox. Each
+//! step is reported with a diagnosis of why it failed.
+//!
+//! `oxiadm test signatures` shows how a single request is signed and whether
+//! it verifies against the key the actor publishes.
+
+use std::collections::BTreeMap;
+use std::error::Error as _;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use oxifed::client::{ACTIVITY_STREAMS_JSON_LD, ACTIVITYPUB_CONTENT_TYPE};
+use oxifed::httpsignature::{
+    ComponentIdentifier, HttpSignature, LegacySignature, SignatureAlgorithm, SignatureConfig,
+    SignatureError, SignatureParameters, Signer, VerificationConfig, digest_header,
+    public_key_from_pem,
+};
+use oxifed::signature_middleware::key_from_document;
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, DATE, HOST, HeaderValue};
+use serde::Serialize;
+use serde_json::{Value, json};
+use url::Url;
+
+use crate::client::AdminApiClient;
+use crate::output::{self, OutputFormat};
+use crate::resolve;
+
+/// Time a remote server gets to answer one request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Signer asking the admin API to sign with a user key of an actor
+///
+/// oxiadm never holds private keys; signatures are made by the PKI daemon.
+pub struct ApiSigner {
+    client: AdminApiClient,
+    actor: String,
+    key_id: String,
+    algorithm: SignatureAlgorithm,
+}
+
+impl ApiSigner {
+    /// Signer using the newest active user key of `actor`
+    pub async fn for_actor(client: &AdminApiClient, actor: &str) -> Result<Self> {
+        let keys = client.list_keys(Some(actor), None, 0, 200).await?;
+        let key = keys
+            .items
+            .into_iter()
+            .filter(|key| key.key_type == "user" && key.status == "active")
+            .max_by(|a, b| a.created_at.cmp(&b.created_at))
+            .ok_or_else(|| {
+                miette!(
+                    help = "Generate one with `oxiadm keys generate`",
+                    "'{}' has no active user key",
+                    actor
+                )
+            })?;
+        let algorithm = if key.algorithm.to_lowercase().starts_with("ed25519") {
+            SignatureAlgorithm::Ed25519
+        } else {
+            SignatureAlgorithm::RsaSha256
+        };
+        Ok(Self {
+            client: client.clone(),
+            actor: actor.to_string(),
+            key_id: key.key_id,
+            algorithm,
+        })
+    }
+
+    /// ID of the key the signatures are made with
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
+impl fmt::Debug for ApiSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiSigner")
+            .field("actor", &self.actor)
+            .field("key_id", &self.key_id)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl Signer for ApiSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm.clone()
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, SignatureError>> {
+        Box::pin(async move {
+            self.client
+                .sign(&self.actor, &self.key_id, data)
+                .await
+                .map_err(|e| SignatureError::SignerUnavailable(e.to_string()))
+        })
+    }
+}
+
+/// Why a check failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    /// The host name does not resolve
+    Dns,
+    /// The TLS handshake failed, e.g. an expired or untrusted certificate
+    Tls,
+    /// The server refused or dropped the connection
+    Connect,
+    /// The server did not answer in time
+    Timeout,
+    /// The server rejected or could not verify the signature
+    Signature,
+    /// The server answered something other than ActivityStreams JSON
+    ContentType,
+    /// The server answered with an unexpected status
+    Status,
+    /// The answer lacked something federation needs
+    Document,
+}
+
+impl Failure {
+    /// What to look at to fix the failure
+    fn hint(self) -> &'static str {
+        match self {
+            Failure::Dns => "check the DNS records of the domain",
+            Failure::Tls => "check the certificate chain and its expiry date",
+            Failure::Connect => "check that the server is up and reachable on port 443",
+            Failure::Timeout => "the server is overloaded or a firewall drops packets",
+            Failure::Signature => {
+                "the remote could not verify our signature: check that our actor \
+                 publishes the signing key and that the clocks are in sync"
+            }
+            Failure::ContentType => "the URL is not an ActivityPub actor or is served by a proxy",
+            Failure::Status => "see the status for details",
+            Failure::Document => "the actor document is incomplete",
+        }
+    }
+}
+
+/// Outcome of one step of the diagnosis
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<Failure>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: true,
+            detail: detail.into(),
+            failure: None,
+        }
+    }
+
+    fn fail(name: &'static str, failure: Failure, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: false,
+            detail: detail.into(),
+            failure: Some(failure),
+        }
+    }
+}
+
+/// Outcome of `oxiadm test federation`
+#[derive(Debug, Serialize)]
+pub struct FederationReport {
+    pub actor: String,
+    pub remote_actor: String,
+    pub key_id: String,
+    pub checks: Vec<Check>,
+}
+
+/// Check that `actor` and `remote_actor` can federate, printing the report
+///
+/// Fails if any check failed, after printing all of them.
+pub async fn run(
+    client: &AdminApiClient,
+    actor: &str,
+    remote_actor: &str,
+    output: OutputFormat,
+) -> Result<()> {
+    let diagnosis = Diagnosis::new(client, actor).await?;
+    let report = FederationReport {
+        actor: diagnosis.actor.clone(),
+        remote_actor: remote_actor.to_string(),
+        key_id: diagnosis.signer.key_id().to_string(),
+        checks: diagnosis.run(remote_actor).await,
+    };
+
+    output::print(output, &report, |report| {
+        println!(
+            "Federation from {} to {} (key {})",
+            report.actor, report.remote_actor, report.key_id
+        );
+        for check in &report.checks {
+            let mark = if check.passed { "ok  " } else { "FAIL" };
+            println!("  [{}] {:<16} {}", mark, check.name, check.detail);
+            if let Some(failure) = check.failure {
+                println!("         {:<16} {:?}: {}", "", failure, failure.hint());
+            }
+        }
+    })?;
+
+    let failed = report.checks.iter().filter(|c| !c.passed).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(miette!(
+            "{} of {} federation checks failed",
+            failed,
+            report.checks.len()
+        ))
+    }
+}
+
+/// Outcome of `oxiadm test signatures`
+#[derive(Debug, Serialize)]
+pub struct SignatureReport {
+    pub actor: String,
+    pub key_id: String,
+    pub algorithm: String,
+    pub method: String,
+    pub url: String,
+    /// String the signature was made over, in draft-cavage format
+    pub signing_string: String,
+    pub headers: BTreeMap<String, String>,
+    pub verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_error: Option<String>,
+    /// Answer of `url` when the request was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<EchoResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EchoResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sign a request as `actor`, verify it locally and print how it was made
+///
+/// The request is a POST of a harmless activity to `target`, by default the
+/// inbox of the actor. It is verified against the key the actor document
+/// publishes, as a remote inbox would. With `send` it is posted to `target`,
+/// e.g. an echo service showing the request as it arrived.
+pub async fn signatures(
+    client: &AdminApiClient,
+    actor: &str,
+    target: Option<&str>,
+    send: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let diagnosis = Diagnosis::new(client, actor).await?;
+    let actor_url = Url::parse(&diagnosis.actor).into_diagnostic()?;
+    let (check, document) = diagnosis.fetch(&actor_url, false).await;
+    let document = document.ok_or_else(|| {
+        miette!(
+            "Failed to fetch the actor {}: {}",
+            diagnosis.actor,
+            check.detail
+        )
+    })?;
+    let key_id = diagnosis.signer.key_id();
+    let key = key_from_document(&document, key_id)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("{} does not publish key {}", diagnosis.actor, key_id))?;
+
+    let url = match target {
+        Some(target) => target.to_string(),
+        None => document["inbox"].as_str().unwrap_or_default().to_string(),
+    };
+    let url = Url::parse(&url)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Invalid target URL '{}'", url))?;
+    let body = diagnosis.probe_activity(url.as_str());
+    let mut request = diagnosis.post(&url, &body).into_diagnostic()?;
+    diagnosis
+        .sign(&mut request, Some(body.as_bytes()))
+        .await
+        .into_diagnostic()
+        .wrap_err("Failed to sign the request")?;
+
+    let signing_string = LegacySignature::from_request(&request)
+        .and_then(|signature| signature.signing_string(&request))
+        .into_diagnostic()?;
+    let algorithm = diagnosis.signer.algorithm();
+    let verification =
+        public_key_from_pem(&key.public_key_pem, &algorithm).and_then(|public_key| {
+            let config = VerificationConfig::new(public_key, algorithm.clone())
+                .with_required_components(signed_components());
+            HttpSignature::verify_request_legacy(&request, &config)
+        });
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect();
+
+    let response = if send {
+        let response = diagnosis
+            .http
+            .execute(request)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to send the request to {}", url))?;
+        Some(EchoResponse {
+            status: response.status().as_u16(),
+            body: response.text().await.unwrap_or_default(),
+        })
+    } else {
+        None
+    };
+
+    let report = SignatureReport {
+        actor: diagnosis.actor.clone(),
+        key_id: key_id.to_string(),
+        algorithm: algorithm.as_str().to_string(),
+        method: "POST".to_string(),
+        url: url.to_string(),
+        signing_string,
+        headers,
+        verified: verification.is_ok(),
+        verification_error: verification.err().map(|e| e.to_string()),
+        response,
+    };
+
+    output::print(output, &report, |report| {
+        println!("{} {}", report.method, report.url);
+        println!("Signed by {} with {}", report.key_id, report.algorithm);
+        println!();
+        println!("Signing string:");
+        for line in report.signing_string.lines() {
+            println!("  {}", line);
+        }
+        println!();
+        println!("Headers:");
+        for (name, value) in &report.headers {
+            println!("  {}: {}", name, value);
+        }
+        println!();
+        match &report.verification_error {
+            None => println!("Verification: ok, against the key the actor publishes"),
+            Some(error) => println!("Verification: FAILED: {}", error),
+        }
+        if let Some(response) = &report.response {
+            println!("Response: {}", response.status);
+            for line in response.body.lines() {
+                println!("  {}", line);
+            }
+        }
+    })?;
+
+    if let Some(error) = &report.verification_error {
+        return Err(miette!("The signature does not verify: {}", error));
+    }
+    match &report.response {
+        Some(response) if !(200..300).contains(&response.status) => {
+            Err(miette!("{} answered {}", report.url, response.status))
+        }
+        _ => Ok(()),
+    }
+}
+
+struct Diagnosis {
+    http: reqwest::Client,
+    actor: String,
+    signer: Arc<ApiSigner>,
+}
+
+impl Diagnosis {
+    /// Diagnosis signing as `actor`, given as user@domain or URL
+    async fn new(client: &AdminApiClient, actor: &str) -> Result<Self> {
+        let actor = resolve::resolve_target(actor).await?;
+        let signer = Arc::new(ApiSigner::for_actor(client, &actor).await?);
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("oxiadm/", env!("CARGO_PKG_VERSION")))
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| miette!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            http,
+            actor,
+            signer,
+        })
+    }
+
+    /// Run the checks in order, stopping where later ones cannot run
+    async fn run(&self, remote_actor: &str) -> Vec<Check> {
+        let mut checks = Vec::new();
+
+        checks.push(self.own_actor().await);
+
+        let host = match remote_host(remote_actor) {
+            Some(host) => host,
+            None => {
+                checks.push(Check::fail(
+                    "remote",
+                    Failure::Document,
+                    format!("'{}' is neither user@domain nor a URL", remote_actor),
+                ));
+                return checks;
+            }
+        };
+        match tokio::net::lookup_host((host.as_str(), 443)).await {
+            Ok(mut addresses) => {
+                let first = addresses.next().map(|a| a.ip().to_string());
+                checks.push(Check::pass(
+                    "dns",
+                    format!("{} resolves to {}", host, first.unwrap_or_default()),
+                ));
+            }
+            Err(e) => {
+                checks.push(Check::fail("dns", Failure::Dns, format!("{}: {}", host, e)));
+                return checks;
+            }
+        }
+
+        let remote_id = if resolve::is_user_at_domain(remote_actor) {
+            match resolve::resolve_target(remote_actor).await {
+                Ok(id) => {
+                    checks.push(Check::pass("webfinger", format!("resolved to {}", id)));
+                    id
+                }
+                Err(e) => {
+                    checks.push(Check::fail("webfinger", Failure::Document, e.to_string()));
+                    return checks;
+                }
+            }
+        } else {
+            remote_actor.to_string()
+        };
+        let Ok(remote_url) = Url::parse(&remote_id) else {
+            checks.push(Check::fail(
+                "webfinger",
+                Failure::Document,
+                format!("'{}' is not a valid URL", remote_id),
+            ));
+            return checks;
+        };
+
+        let (unsigned, unsigned_document) = self.fetch(&remote_url, false).await;
+        let (signed, signed_document) = self.fetch(&remote_url, true).await;
+        // A server enforcing authorized fetch refuses the unsigned GET; that
+        // is only a problem when the signed one fails too
+        let unsigned = match unsigned {
+            Check {
+                failure: Some(Failure::Signature),
+                ..
+            } if signed.passed => Check::pass(
+                "unsigned fetch",
+                "refused, the remote requires signed fetches (authorized fetch)",
+            ),
+            check => check,
+        };
+        checks.push(unsigned);
+        checks.push(signed);
+
+        let inbox = signed_document
+            .or(unsigned_document)
+            .and_then(|document| document["inbox"].as_str().map(str::to_string));
+        match inbox.map(|inbox| Url::parse(&inbox)) {
+            Some(Ok(inbox)) => checks.push(self.deliver(&inbox, &remote_id).await),
+            Some(Err(e)) => checks.push(Check::fail(
+                "inbox delivery",
+                Failure::Document,
+                format!("the inbox of the remote actor is not a valid URL: {}", e),
+            )),
+            None => checks.push(Check::fail(
+                "inbox delivery",
+                Failure::Document,
+                "the remote actor could not be fetched or names no inbox",
+            )),
+        }
+        checks
+    }
+
+    /// Check that our actor is fetchable and publishes the signing key
+    async fn own_actor(&self) -> Check {
+        const NAME: &str = "local actor";
+        let url = match Url::parse(&self.actor) {
+            Ok(url) => url,
+            Err(e) => return Check::fail(NAME, Failure::Document, e.to_string()),
+        };
+        let (check, document) = self.fetch(&url, false).await;
+        let Some(document) = document else {
+            return Check {
+                name: NAME,
+                ..check
+            };
+        };
+        let key_id = self.signer.key_id();
+        match key_from_document(&document, key_id) {
+            Ok(_) => Check::pass(NAME, format!("{} publishes key {}", self.actor, key_id)),
+            Err(e) => Check::fail(
+                NAME,
+                Failure::Signature,
+                format!(
+                    "requests are signed with a key the actor does not publish: {}",
+                    e
+                ),
+            ),
+        }
+    }
+
+    /// GET `url`, answering the check and the fetched document
+    async fn fetch(&self, url: &Url, signed: bool) -> (Check, Option<Value>) {
+        let name = if signed {
+            "signed fetch"
+        } else {
+            "unsigned fetch"
+        };
+        let mut request = match self.http.get(url.clone()).build() {
+            Ok(request) => request,
+            Err(e) => return (Check::fail(name, Failure::Document, e.to_string()), None),
+        };
+        request
+            .headers_mut()
+            .insert(ACCEPT, HeaderValue::from_static(ACTIVITYPUB_CONTENT_TYPE));
+        if signed && let Err(e) = self.sign(&mut request, None).await {
+            return (Check::fail(name, Failure::Signature, e.to_string()), None);
+        }
+
+        let response = match self.http.execute(request).await {
+            Ok(response) => response,
+            Err(e) => return (Check::fail(name, classify(&e), e.to_string()), None),
+        };
+        let status = response.status();
+        if !status.is_success() {
+            return (
+                Check::fail(
+                    name,
+                    status_failure(status),
+                    format!("{} answered {}", url, status),
+                ),
+                None,
+            );
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if !is_activity_json(&content_type) {
+            return (
+                Check::fail(
+                    name,
+                    Failure::ContentType,
+                    format!("{} answered '{}'", url, content_type),
+                ),
+                None,
+            );
+        }
+        match response.json::<Value>().await {
+            Ok(document) if document["inbox"].is_string() => (
+                Check::pass(name, format!("{} answered {}", url, status)),
+                Some(document),
+            ),
+            Ok(_) => (
+                Check::fail(name, Failure::Document, format!("{} names no inbox", url)),
+                None,
+            ),
+            Err(e) => (
+                Check::fail(name, Failure::Document, format!("invalid JSON: {}", e)),
+                None,
+            ),
+        }
+    }
+
+    /// POST a signed activity the remote can safely ignore to `inbox`
+    async fn deliver(&self, inbox: &Url, remote_id: &str) -> Check {
+        const NAME: &str = "inbox delivery";
+        let body = self.probe_activity(remote_id);
+        let mut request = match self.post(inbox, &body) {
+            Ok(request) => request,
+            Err(e) => return Check::fail(NAME, Failure::Document, e.to_string()),
+        };
+        if let Err(e) = self.sign(&mut request, Some(body.as_bytes())).await {
+            return Check::fail(NAME, Failure::Signature, e.to_string());
+        }
+
+        match self.http.execute(request).await {
+            Ok(response) if response.status().is_success() => {
+                Check::pass(NAME, format!("{} answered {}", inbox, response.status()))
+            }
+            Ok(response) => Check::fail(
+                NAME,
+                status_failure(response.status()),
+                format!("{} answered {}", inbox, response.status()),
+            ),
+            Err(e) => Check::fail(NAME, classify(&e), e.to_string()),
+        }
+    }
+
+    /// Activity a server can safely ignore: the Undo of a Follow of
+    /// `object` that was never sent
+    fn probe_activity(&self, object: &str) -> String {
+        let id = format!("{}#diagnostics-{}", self.actor, uuid::Uuid::new_v4());
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{}/undo", id),
+            "type": "Undo",
+            "actor": self.actor,
+            "object": {
+                "id": id,
+                "type": "Follow",
+                "actor": self.actor,
+                "object": object,
+            },
+        })
+        .to_string()
+    }
+
+    /// Unsigned POST of `body` to an inbox
+    fn post(&self, url: &Url, body: &str) -> reqwest::Result<reqwest::Request> {
+        self.http
+            .post(url.clone())
+            .header(CONTENT_TYPE, ACTIVITY_STREAMS_JSON_LD)
+            .body(body.to_string())
+            .build()
+    }
+
+    /// Sign `request` the way publisherd signs deliveries
+    async fn sign(
+        &self,
+        request: &mut reqwest::Request,
+        body: Option<&[u8]>,
+    ) -> Result<(), SignatureError> {
+        let url = request.url().clone();
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(SignatureError::InvalidHeader("no host".to_string())),
+        };
+        let date = chrono::Utc::now()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let headers = request.headers_mut();
+        let value = |v: &str| {
+            HeaderValue::from_str(v).map_err(|e| SignatureError::InvalidHeader(e.to_string()))
+        };
+        headers.insert(HOST, value(&host)?);
+        headers.insert(DATE, value(&date)?);
+        if let Some(body) = body {
+            headers.insert("digest", value(&digest_header(body))?);
+        }
+
+        let config = SignatureConfig {
+            parameters: SignatureParameters::new(),
+            key_id: self.signer.key_id().to_string(),
+            components: signed_components(),
+            signer: self.signer.clone(),
+        };
+        HttpSignature::sign_request_legacy(request, &config).await
+    }
+}
+
+/// Components signed on every request, as publisherd signs them
+fn signed_components() -> Vec<ComponentIdentifier> {
+    vec![
+        ComponentIdentifier::RequestTarget,
+        ComponentIdentifier::Header("host".to_string()),
+        ComponentIdentifier::Header("date".to_string()),
+        ComponentIdentifier::Digest,
+    ]
+}
+
+/// Host of a `user@domain` name or an actor URL
+fn remote_host(remote_actor: &str) -> Option<String> {
+    if resolve::is_user_at_domain(remote_actor) {
+        let (_, domain) = remote_actor.trim_start_matches('@').rsplit_once('@')?;
+        (!domain.is_empty()).then(|| domain.to_string())
+    } else {
+        Url::parse(remote_actor)
+            .ok()?
+            .host_str()
+            .map(str::to_string)
+    }
+}
+
+/// Whether `content_type` is one ActivityPub servers accept for actors
+fn is_activity_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.eq_ignore_ascii_case(ACTIVITYPUB_CONTENT_TYPE)
+        || (mime.eq_ignore_ascii_case("application/ld+json")
+            && content_type.contains("https://www.w3.org/ns/activitystreams"))
+}
+
+/// Failure an unexpected status points to
+fn status_failure(status: StatusCode) -> Failure {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Failure::Signature,
+        StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => Failure::Timeout,
+        _ => Failure::Status,
+    }
+}
+
+/// Failure a request error points to
+fn classify(error: &reqwest::Error) -> Failure {
+    if error.is_timeout() {
+        return Failure::Timeout;
+    }
+    // reqwest does not expose the cause, the messages of the chain name it
+    let mut causes = String::new();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        causes.push_str(&cause.to_string().to_lowercase());
+        causes.push('\n');
+        source = cause.source();
+    }
+    classify_causes(&causes, error.is_connect())
+}
+
+fn classify_causes(causes: &str, connect: bool) -> Failure {
+    if causes.contains("dns error") || causes.contains("failed to lookup address") {
+        Failure::Dns
+    } else if ["certificate", "tls", "ssl", "handshake"]
+        .iter()
+        .any(|word| causes.contains(word))
+    {
+        Failure::Tls
+    } else if connect {
+        Failure::Connect
+    } else {
+        Failure::Status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_host() {
+        assert_eq!(remote_host("alice@example.com").unwrap(), "example.com");
+        assert_eq!(remote_host("@alice@example.com").unwrap(), "example.com");
+        assert_eq!(
+            remote_host("https://social.example/users/alice").unwrap(),
+            "social.example"
+        );
+        assert!(remote_host("alice").is_none());
+    }
+
+    #[test]
+    fn test_is_activity_json() {
+        assert!(is_activity_json("application/activity+json"));
+        assert!(is_activity_json("application/activity+json; charset=utf-8"));
+        assert!(is_activity_json(ACTIVITY_STREAMS_JSON_LD));
+        assert!(!is_activity_json("application/ld+json"));
+        assert!(!is_activity_json("text/html; charset=utf-8"));
+    }
+
+    #[test]
+    fn test_failure_classification() {
+        assert_eq!(status_failure(StatusCode::UNAUTHORIZED), Failure::Signature);
+        assert_eq!(status_failure(StatusCode::FORBIDDEN), Failure::Signature);
+        assert_eq!(
+            status_failure(StatusCode::GATEWAY_TIMEOUT),
+            Failure::Timeout
+        );
+        assert_eq!(status_failure(StatusCode::NOT_FOUND), Failure::Status);
+
+        assert_eq!(
+            classify_causes("dns error: failed to lookup address information", true),
+            Failure::Dns
+        );
+        assert_eq!(
+            classify_causes("invalid peer certificate: expired", true),
+            Failure::Tls
+        );
+        assert_eq!(
+            classify_causes("connection refused", true),
+            Failure::Connect
+        );
+    }
+}
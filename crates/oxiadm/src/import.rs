@@ -0,0 +1,316 @@
+//! Bulk import of persons and domains
+//!
+//! Reads the accounts of a CSV file or the domains of a JSON file, checks
+//! every record before anything is sent, and queues them in batches through
+//! the admin API, which publishes each batch with publisher confirms.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use oxifed::messaging::{DomainCreateMessage, ProfileCreateMessage};
+use serde::Serialize;
+
+use crate::client::AdminApiClient;
+use crate::output::{self, OutputFormat};
+
+/// Registration modes a domain accepts
+const REGISTRATION_MODES: &[&str] = &["open", "approval", "invite", "closed"];
+
+/// A record that can be created in bulk
+pub trait Importable: Serialize {
+    /// Batch endpoint of the admin API
+    const PATH: &'static str;
+    /// Plural name used in progress messages
+    const NOUN: &'static str;
+
+    /// Identifier naming the record in messages and duplicate checks
+    fn key(&self) -> &str;
+}
+
+impl Importable for ProfileCreateMessage {
+    const PATH: &'static str = "/api/v1/persons/batch";
+    const NOUN: &'static str = "persons";
+
+    fn key(&self) -> &str {
+        &self.subject
+    }
+}
+
+impl Importable for DomainCreateMessage {
+    const PATH: &'static str = "/api/v1/domains/batch";
+    const NOUN: &'static str = "domains";
+
+    fn key(&self) -> &str {
+        &self.domain
+    }
+}
+
+/// Outcome of an import
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub total: usize,
+    pub queued: usize,
+    /// Records the broker refused
+    pub rejected: Vec<String>,
+    pub dry_run: bool,
+}
+
+/// Read persons from a CSV file
+///
+/// The header names the columns: `subject` is required, `summary`, `icon`
+/// and `properties` (a JSON object) are optional. Other columns are ignored.
+pub fn read_persons(path: &Path) -> Result<Vec<ProfileCreateMessage>> {
+    let text = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let mut rows = parse_csv(&text)?.into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| miette!("{} is empty", path.display()))?;
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let subject =
+        column("subject").ok_or_else(|| miette!("{} has no 'subject' column", path.display()))?;
+    let (summary, icon, properties) = (column("summary"), column("icon"), column("properties"));
+
+    let mut persons = Vec::new();
+    for (index, row) in rows.enumerate() {
+        // The header is line 1
+        let line = index + 2;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| row.get(c))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        let name =
+            field(Some(subject)).ok_or_else(|| miette!("Line {}: the subject is empty", line))?;
+        let properties = field(properties)
+            .map(serde_json::from_str)
+            .transpose()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Line {}: invalid properties JSON", line))?;
+        persons.push(ProfileCreateMessage::new(
+            subject_of(name).wrap_err_with(|| format!("Line {}", line))?,
+            field(summary).map(str::to_string),
+            field(icon).map(str::to_string),
+            properties,
+        ));
+    }
+    check_duplicates(&persons)?;
+    Ok(persons)
+}
+
+/// Read domains from a JSON array of domain creation requests
+pub fn read_domains(path: &Path) -> Result<Vec<DomainCreateMessage>> {
+    let text = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let domains: Vec<DomainCreateMessage> = serde_json::from_str(&text)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("{} is not a JSON array of domains", path.display()))?;
+
+    for (index, domain) in domains.iter().enumerate() {
+        let name = &domain.domain;
+        if name.is_empty()
+            || !name.contains('.')
+            || name.contains(|c: char| c.is_whitespace() || c == '/' || c == '@')
+        {
+            return Err(miette!("Domain {}: invalid name '{}'", index + 1, name));
+        }
+        if let Some(mode) = &domain.registration_mode
+            && !REGISTRATION_MODES.contains(&mode.as_str())
+        {
+            return Err(miette!(
+                "Domain '{}': unknown registration mode '{}', expected one of {}",
+                name,
+                mode,
+                REGISTRATION_MODES.join(", ")
+            ));
+        }
+    }
+    check_duplicates(&domains)?;
+    Ok(domains)
+}
+
+/// Report that `records` are valid without sending them
+pub fn dry_run<T: Importable>(records: &[T], output: OutputFormat) -> Result<()> {
+    let summary = ImportSummary {
+        total: records.len(),
+        queued: 0,
+        rejected: Vec::new(),
+        dry_run: true,
+    };
+    output::print(output, &summary, |summary| {
+        println!(
+            "Dry run: {} {} are valid, nothing was imported",
+            summary.total,
+            T::NOUN
+        )
+    })
+}
+
+/// Queue `records` in batches of `batch_size`, reporting progress on stderr
+pub async fn run<T: Importable>(
+    client: &AdminApiClient,
+    records: &[T],
+    batch_size: usize,
+    output: OutputFormat,
+) -> Result<()> {
+    let mut summary = ImportSummary {
+        total: records.len(),
+        queued: 0,
+        rejected: Vec::new(),
+        dry_run: false,
+    };
+
+    for batch in records.chunks(batch_size) {
+        let outcome = client
+            .create_batch(T::PATH, batch)
+            .await
+            .wrap_err_with(|| {
+                format!(
+                    "Import stopped after {} of {} {}",
+                    summary.queued + summary.rejected.len(),
+                    records.len(),
+                    T::NOUN
+                )
+            })?;
+        summary.queued += outcome.queued;
+        summary.rejected.extend(
+            outcome
+                .rejected
+                .iter()
+                .filter_map(|&i| batch.get(i))
+                .map(|record| record.key().to_string()),
+        );
+        eprintln!(
+            "Queued {}/{} {}",
+            summary.queued + summary.rejected.len(),
+            records.len(),
+            T::NOUN
+        );
+    }
+
+    output::print(output, &summary, |summary| {
+        println!("{} of {} {} queued", summary.queued, summary.total, T::NOUN);
+        for key in &summary.rejected {
+            println!("  refused by the broker: {}", key);
+        }
+    })?;
+
+    if summary.rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(miette!(
+            "The broker refused {} {}, import them again",
+            summary.rejected.len(),
+            T::NOUN
+        ))
+    }
+}
+
+/// Subject of a person given as `name@domain`
+fn subject_of(name: &str) -> Result<String> {
+    let bare = name.trim_start_matches("acct:");
+    match bare.split_once('@') {
+        Some((user, domain)) if !user.is_empty() && domain.contains('.') => {
+            Ok(format!("acct:{}", bare))
+        }
+        _ => Err(miette!("Invalid subject '{}', expected name@domain", name)),
+    }
+}
+
+fn check_duplicates<T: Importable>(records: &[T]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for record in records {
+        if !seen.insert(record.key()) {
+            return Err(miette!("'{}' is listed more than once", record.key()));
+        }
+    }
+    Ok(())
+}
+
+/// Split CSV text into rows of fields
+///
+/// Follows RFC 4180: fields may be quoted, quoted fields may hold commas,
+/// line breaks and doubled quotes. Blank lines are skipped.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(miette!("Unterminated quoted field in CSV"));
+    }
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let rows = parse_csv("subject,summary\r\nalice@example.com,\"Hi, \"\"there\"\"\"\n\nbob@example.com,\"two\nlines\"").unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["subject", "summary"],
+                vec!["alice@example.com", "Hi, \"there\""],
+                vec!["bob@example.com", "two\nlines"],
+            ]
+        );
+        assert!(parse_csv("a,\"b").is_err());
+    }
+
+    #[test]
+    fn test_subject_of() {
+        assert_eq!(
+            subject_of("alice@example.com").unwrap(),
+            "acct:alice@example.com"
+        );
+        assert_eq!(
+            subject_of("acct:alice@example.com").unwrap(),
+            "acct:alice@example.com"
+        );
+        assert!(subject_of("alice").is_err());
+        assert!(subject_of("@example.com").is_err());
+    }
+
+    #[test]
+    fn test_check_duplicates() {
+        let person = |s: &str| ProfileCreateMessage::new(s.to_string(), None, None, None);
+        assert!(check_duplicates(&[person("acct:a@x.org"), person("acct:b@x.org")]).is_ok());
+        assert!(check_duplicates(&[person("acct:a@x.org"), person("acct:a@x.org")]).is_err());
+    }
+}
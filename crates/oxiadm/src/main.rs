@@ -1,11 +1,23 @@
 mod auth;
 mod client;
 mod context;
+mod federation;
+mod import;
+mod output;
 mod resolve;
 
+use std::process::ExitCode;
+
 use clap::{Parser, Subcommand};
-use client::AdminApiClient;
+use client::{AdminApiClient, ApiError, CommandOutcome};
 use miette::{Context, IntoDiagnostic, Result};
+use output::OutputFormat;
+use oxifed::egress::EgressPolicy;
+use oxifed::messaging::{
+    FollowCounts, FollowDirection, KeyRotationType, ModerationState, Page, ProfileAliasMessage,
+    ProfileModerateMessage, ReplayFilter,
+};
+use oxifed::pki::{KEY_ROTATION_OVERLAP_DAYS, TrustLevel, VerificationMethod, VerificationStatus};
 
 /// Oxifed Admin CLI tool for managing profiles
 #[derive(Parser)]
@@ -16,6 +28,14 @@ struct Cli {
     #[arg(long, env = "OXIADM_API_URL")]
     api_url: Option<String>,
 
+    /// Return once commands are queued instead of waiting for their outcome
+    #[arg(long = "async", global = true)]
+    queue_only: bool,
+
+    /// Output format of query commands
+    #[arg(long, short, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -28,6 +48,12 @@ enum Commands {
         command: PersonCommands,
     },
 
+    /// Create or manage Group actors (communities)
+    Group {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+
     /// Create or manage Note objects
     Note {
         #[command(subcommand)]
@@ -150,6 +176,18 @@ enum PersonCommands {
         /// Custom properties to update in JSON format
         #[arg(long)]
         properties: Option<String>,
+
+        /// List the person in the account directory and account searches
+        #[arg(long)]
+        discoverable: Option<bool>,
+
+        /// Let searches index the person's public posts
+        #[arg(long)]
+        indexable: Option<bool>,
+
+        /// Application the person uses the account with
+        #[arg(long, value_parser = ["microblog", "blog", "gallery"])]
+        application_profile: Option<String>,
     },
 
     /// Delete a Person actor
@@ -161,6 +199,168 @@ enum PersonCommands {
         #[arg(long)]
         force: bool,
     },
+
+    /// List local and remote actors
+    List {
+        /// Only list actors of this domain
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Only list local (true) or remote (false) actors
+        #[arg(long)]
+        local: Option<bool>,
+
+        /// Only list actors with this status
+        #[arg(long, value_parser = ["active", "suspended", "deleted", "pending"])]
+        status: Option<String>,
+
+        /// Number of actors to skip, as printed at the end of a page
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of actors
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Show an actor with its status and counts
+    Show {
+        /// Actor (user@domain or URL)
+        id: String,
+    },
+
+    /// Suspend, silence or limit an actor, local or remote, or lift it
+    ///
+    /// Suspended actors answer 410 and their activities are refused;
+    /// silenced actors are left out of public timelines; limited ones are
+    /// also only delivered to local accounts following them.
+    Moderate {
+        /// Actor (user@domain or URL)
+        id: String,
+
+        /// New state of the actor
+        #[arg(value_parser = ["active", "silenced", "limited", "suspended"])]
+        state: String,
+    },
+
+    /// Add another identity to a person's alsoKnownAs, or remove it
+    ///
+    /// Handles on domains of this server are bound to the person: their
+    /// actor URL redirects to the person and WebFinger resolves them to it.
+    Alias {
+        /// Subject identifier of the person (format: user@domain.org)
+        subject: String,
+
+        /// Actor URL of another account, or a handle on a local domain
+        alias: String,
+
+        /// Remove the alias
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Export an account to an archive in domainservd's archive directory
+    Export {
+        /// Subject identifier of the account (format: user@domain.org)
+        subject: String,
+    },
+
+    /// Restore an account from an archive as a new account, or create
+    /// many accounts from a CSV file with --csv
+    Import {
+        /// Subject identifier of the new account (format: user@domain.org)
+        #[arg(required_unless_present = "csv")]
+        subject: Option<String>,
+
+        /// File name of the archive in domainservd's archive directory
+        #[arg(required_unless_present = "csv")]
+        archive: Option<String>,
+
+        /// CSV file with a `subject` column and optional `summary`, `icon` and `properties` columns
+        #[arg(long, conflicts_with_all = ["subject", "archive"])]
+        csv: Option<std::path::PathBuf>,
+
+        /// Check the CSV file without creating anything
+        #[arg(long, requires = "csv")]
+        dry_run: bool,
+
+        /// Number of accounts queued per request
+        #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u16).range(1..=500))]
+        batch_size: u16,
+    },
+
+    /// List the followers of an actor with their follow status
+    Followers {
+        /// Actor identifier (URL or user@domain.com)
+        actor: String,
+
+        /// Only show follows with this status (accepted, pending, rejected or cancelled)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Continue after this follow ID, as printed at the end of a page
+        #[arg(long)]
+        before: Option<String>,
+
+        /// Maximum number of followers
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// List the accounts an actor follows with the follow status
+    Following {
+        /// Actor identifier (URL or user@domain.com)
+        actor: String,
+
+        /// Only show follows with this status (accepted, pending, rejected or cancelled)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Continue after this follow ID, as printed at the end of a page
+        #[arg(long)]
+        before: Option<String>,
+
+        /// Maximum number of follows
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+}
+
+/// Commands for working with Group actors
+#[derive(Subcommand)]
+enum GroupCommands {
+    /// Create a new Group actor
+    Create {
+        /// Subject identifier for the group (format: group@domain.org)
+        subject: String,
+
+        /// Display name of the group
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Description of the group
+        #[arg(long)]
+        summary: Option<String>,
+
+        /// Actor ID of a moderator (can be specified multiple times)
+        #[arg(long = "moderator")]
+        moderators: Vec<String>,
+    },
+
+    /// List the members of a group
+    Members {
+        /// Subject identifier for the group (format: group@domain.org)
+        subject: String,
+    },
+
+    /// Ban a member from a group
+    Ban {
+        /// Subject identifier for the group (format: group@domain.org)
+        subject: String,
+
+        /// Actor ID of the member
+        actor: String,
+    },
 }
 
 /// Commands for working with Note objects
@@ -190,6 +390,18 @@ enum NoteCommands {
         /// Custom properties in JSON format
         #[arg(long)]
         properties: Option<String>,
+
+        /// Publish the note at this time (RFC 3339) instead of immediately
+        #[arg(long)]
+        scheduled_at: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Who may see the note; direct notes go to the mentioned users only
+        #[arg(long, value_parser = ["public", "unlisted", "followers", "direct"])]
+        visibility: Option<String>,
+
+        /// ID of a post to quote; its quote policy must allow the author
+        #[arg(long)]
+        quote: Option<String>,
     },
 
     /// Update a Note
@@ -212,6 +424,14 @@ enum NoteCommands {
         /// Custom properties to update in JSON format
         #[arg(long)]
         properties: Option<String>,
+
+        /// Pin the note to its author's profile
+        #[arg(long, conflicts_with = "unpin")]
+        pin: bool,
+
+        /// Unpin the note from its author's profile
+        #[arg(long)]
+        unpin: bool,
     },
 
     /// Delete a Note
@@ -223,6 +443,40 @@ enum NoteCommands {
         #[arg(long)]
         force: bool,
     },
+
+    /// List published local posts newest first
+    List {
+        /// Only list posts of this actor
+        #[arg(long)]
+        actor: Option<String>,
+
+        /// Number of posts to skip, as printed at the end of a page
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of posts
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Show a stored post
+    Show {
+        /// Note ID
+        id: String,
+    },
+
+    /// List notes waiting for their scheduled publication
+    Scheduled {
+        /// Only list notes of this actor
+        #[arg(long)]
+        actor: Option<String>,
+    },
+
+    /// Cancel a scheduled note before it is published
+    Cancel {
+        /// Note ID
+        id: String,
+    },
 }
 
 /// Commands for working with ActivityPub activities
@@ -279,6 +533,12 @@ enum ActivityCommands {
         #[arg(long)]
         cc: Option<String>,
     },
+
+    /// Show which inboxes an activity was delivered to and which failed
+    Status {
+        /// ActivityPub ID of the activity
+        id: String,
+    },
 }
 
 /// Commands for managing cryptographic keys
@@ -327,9 +587,13 @@ enum KeyCommands {
         /// Domain to verify
         #[arg(long)]
         domain: String,
+
+        /// Where the challenge is published (dns or well-known)
+        #[arg(long, default_value = "dns")]
+        method: String,
     },
 
-    /// Complete domain verification with challenge response
+    /// Complete domain verification once the challenge is published
     VerifyComplete {
         /// Actor identifier
         #[arg(long)]
@@ -338,21 +602,36 @@ enum KeyCommands {
         /// Domain being verified
         #[arg(long)]
         domain: String,
-
-        /// Path to signed challenge response
-        #[arg(long)]
-        challenge_response: String,
     },
 
     /// Rotate a key
     Rotate {
-        /// Actor identifier
+        /// Actor identifier (URL or user@domain.com)
         #[arg(long)]
         actor: String,
 
         /// Rotation type (scheduled or emergency)
         #[arg(long)]
         rotation_type: String,
+
+        /// Algorithm of the new key (rsa or ed25519), defaults to the current one
+        #[arg(long)]
+        algorithm: Option<String>,
+
+        /// Key size for RSA (2048, 4096)
+        #[arg(long)]
+        key_size: Option<u32>,
+    },
+
+    /// Revoke a key that was replaced by a scheduled rotation
+    Revoke {
+        /// Actor identifier (URL or user@domain.com)
+        #[arg(long)]
+        actor: String,
+
+        /// Key ID URL
+        #[arg(long)]
+        key_id: String,
     },
 
     /// View trust chain for a key
@@ -362,11 +641,23 @@ enum KeyCommands {
         key_id: String,
     },
 
-    /// List keys by trust level
+    /// List keys, optionally of one actor or trust level
     List {
-        /// Trust level filter
+        /// Actor identifier (URL or user@domain.com)
+        #[arg(long)]
+        actor: Option<String>,
+
+        /// Trust level filter (unverified, domain-verified, master-signed or instance-actor)
         #[arg(long)]
         trust_level: Option<String>,
+
+        /// Number of keys to skip, as printed at the end of a page
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of keys
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
     },
 }
 
@@ -483,29 +774,204 @@ enum SystemCommands {
         #[arg(long)]
         max_file_size: Option<String>,
     },
+
+    /// Show or change how many deliveries publisherd runs at once
+    ///
+    /// Changes apply to every running publisherd until it restarts.
+    DeliveryLimits {
+        /// Requests to remote inboxes running at once
+        #[arg(long)]
+        max_in_flight: Option<usize>,
+
+        /// Requests to one remote host running at once
+        #[arg(long)]
+        max_per_host: Option<usize>,
+    },
+
+    /// Replay stored remote activities through the incoming pipeline
+    ///
+    /// Activities run through spam filtering, moderation and storage again,
+    /// oldest first; webhooks and notifications are left out unless
+    /// --allow-delivery is given. At least one filter is required.
+    Replay {
+        /// Domain whose pipeline the activities are routed to
+        #[arg(long)]
+        domain: String,
+
+        /// Only activities stored at or after this storage ID
+        #[arg(long)]
+        from_id: Option<String>,
+
+        /// Only activities stored at or before this storage ID
+        #[arg(long)]
+        to_id: Option<String>,
+
+        /// Only activities of this actor
+        #[arg(long)]
+        actor: Option<String>,
+
+        /// Only activities of this type, e.g. Create
+        #[arg(long = "type")]
+        activity_type: Option<String>,
+
+        /// Only activities stored at or after this RFC 3339 time
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only activities stored before this RFC 3339 time
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Maximum number of activities
+        #[arg(long, default_value_t = 100)]
+        limit: u32,
+
+        /// Only list the activities that would be replayed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Let stages send webhooks and notifications again
+        #[arg(long)]
+        allow_delivery: bool,
+    },
+
+    /// Inspect and reprocess dead-lettered messages
+    Dlq {
+        #[command(subcommand)]
+        command: DlqCommands,
+    },
+
+    /// Inspect the remote instances we federate with
+    Instances {
+        #[command(subcommand)]
+        command: InstanceCommands,
+    },
+
+    /// Show the audit log of administrative actions, newest first
+    Audit {
+        /// Only show actions of this admin user (token subject)
+        #[arg(long)]
+        actor: Option<String>,
+
+        /// Only show this action, e.g. domain.update
+        #[arg(long)]
+        action: Option<String>,
+
+        /// Only show actions on this domain, user or other resource
+        #[arg(long)]
+        target: Option<String>,
+
+        /// Continue after this entry ID, as printed at the end of a page
+        #[arg(long)]
+        before: Option<String>,
+
+        /// Maximum number of entries
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+
+        /// Show the state before and after each action
+        #[arg(long)]
+        details: bool,
+    },
+}
+
+/// Commands for the registry of remote instances
+#[derive(Subcommand)]
+enum InstanceCommands {
+    /// List known instances by domain
+    List {
+        /// Only instances whose last deliveries failed, longest streak first
+        #[arg(long)]
+        failing: bool,
+
+        /// Number of instances to skip
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of instances
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Show an instance
+    Show {
+        /// Domain of the instance
+        domain: String,
+    },
+
+    /// Count the known instances by the software they run
+    Stats,
+}
+
+/// Commands for the dead-letter queue
+#[derive(Subcommand)]
+enum DlqCommands {
+    /// List dead-lettered messages
+    List {
+        /// Only show messages that failed in this queue
+        #[arg(long)]
+        queue: Option<String>,
+
+        /// Filter by status (pending, retried)
+        #[arg(long)]
+        status: Option<String>,
+    },
+
+    /// Re-inject dead-lettered messages into their original queue
+    Retry {
+        /// Dead letter ID
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+
+        /// Retry all pending dead letters
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+
+    /// Delete dead-lettered messages
+    Purge {
+        /// Dead letter ID
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+
+        /// Delete all dead letters
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
 }
 
 /// Commands for testing federation
 #[derive(Subcommand)]
 enum TestCommands {
-    /// Test HTTP signature generation and verification
+    /// Sign a request as an actor and verify it locally
+    ///
+    /// Prints the signing string, the headers and whether the signature
+    /// verifies against the key the actor document publishes.
     Signatures {
-        /// Actor identifier
+        /// Local actor (user@domain or URL), signing with its newest user key
         #[arg(long)]
         actor: String,
 
-        /// Target URL
+        /// URL the request is addressed to, by default the actor's inbox
         #[arg(long)]
-        target: String,
+        target: Option<String>,
+
+        /// POST the request to the target, e.g. an echo service, and print the answer
+        #[arg(long)]
+        send: bool,
     },
 
-    /// Verify federation connectivity
+    /// Diagnose federation between a local and a remote actor
+    ///
+    /// Resolves the remote actor, fetches it with and without a signature,
+    /// delivers a harmless signed activity to its inbox and checks that the
+    /// local actor is fetchable. Exits non-zero if any check fails.
     Federation {
-        /// Local actor
+        /// Local actor (user@domain or URL), signing with its newest user key
         #[arg(long)]
         actor: String,
 
-        /// Remote actor
+        /// Remote actor (user@domain or URL)
         #[arg(long)]
         remote_actor: String,
     },
@@ -627,6 +1093,21 @@ enum DomainCommands {
         force: bool,
     },
 
+    /// Create many domains from a JSON array of domains, as sent by `domain create`
+    Import {
+        /// JSON file with the domains
+        #[arg(long)]
+        file: std::path::PathBuf,
+
+        /// Check the file without creating anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Number of domains queued per request
+        #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u16).range(1..=500))]
+        batch_size: u16,
+    },
+
     /// List all domains
     List,
 
@@ -635,33 +1116,176 @@ enum DomainCommands {
         /// Domain name
         domain: String,
     },
-}
 
-/// Commands for managing users
-#[derive(Subcommand)]
-enum UserCommands {
-    /// Create a new user
-    Create {
-        /// Username (required)
-        #[arg(long, short = 'u')]
-        username: String,
+    /// Manage relay subscriptions of a domain's instance actor
+    Relay {
+        #[command(subcommand)]
+        command: RelayCommands,
+    },
 
-        /// Display name (optional, defaults to username)
-        #[arg(long, short = 'd')]
-        display_name: Option<String>,
+    /// Create an invite code for sign-ups on a domain in invite mode
+    Invite {
+        /// Domain name
+        domain: String,
 
-        /// Domain the user belongs to (required)
+        /// Sign-ups the code allows (unlimited if omitted)
         #[arg(long)]
-        domain: String,
-    },
+        max_uses: Option<u32>,
 
-    /// List existing users
-    List,
+        /// Days the code is accepted for (forever if omitted)
+        #[arg(long)]
+        expires_in_days: Option<u32>,
+    },
 
-    /// Show user details including public key
-    Show {
-        /// Username to show
-        username: String,
+    /// Manage webhooks receiving a domain's admin and federation events
+    Webhook {
+        #[command(subcommand)]
+        command: WebhookCommands,
+    },
+}
+
+/// Commands for managing webhooks
+#[derive(Subcommand)]
+enum WebhookCommands {
+    /// Register a webhook
+    Add {
+        /// Domain name
+        domain: String,
+
+        /// Endpoint the signed callbacks are POSTed to
+        url: String,
+
+        /// Shared secret the callbacks are signed with (generated if omitted)
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// Event to send (user.registered, report.created, delivery.failing,
+        /// key.rotated); repeat for several, all events if omitted
+        #[arg(long = "event")]
+        events: Vec<oxifed::messaging::WebhookEvent>,
+    },
+
+    /// Remove a webhook
+    Remove {
+        /// Domain name
+        domain: String,
+
+        /// Webhook ID
+        id: String,
+    },
+
+    /// List the webhooks of a domain
+    List {
+        /// Domain name
+        domain: String,
+    },
+}
+
+/// Commands for managing relays
+#[derive(Subcommand)]
+enum RelayCommands {
+    /// Subscribe to a relay
+    Add {
+        /// Domain name
+        domain: String,
+
+        /// Actor ID of the relay (e.g. https://relay.example/actor)
+        relay: String,
+    },
+
+    /// Unsubscribe from a relay
+    Remove {
+        /// Domain name
+        domain: String,
+
+        /// Actor ID of the relay
+        relay: String,
+    },
+
+    /// List relay subscriptions and, in relay mode, subscribers
+    List {
+        /// Domain name
+        domain: String,
+    },
+}
+
+/// Commands for managing users
+#[derive(Subcommand)]
+enum UserCommands {
+    /// Create a new user
+    Create {
+        /// Username (required)
+        #[arg(long, short = 'u')]
+        username: String,
+
+        /// Display name (optional, defaults to username)
+        #[arg(long, short = 'd')]
+        display_name: Option<String>,
+
+        /// Domain the user belongs to (required)
+        #[arg(long)]
+        domain: String,
+    },
+
+    /// List existing users
+    List {
+        /// Only list users of this domain
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Number of users to skip, as printed at the end of a page
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of users
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Show user details including public key
+    Show {
+        /// Username to show
+        username: String,
+    },
+
+    /// List registration applications of domains in approval mode
+    Applications {
+        /// Only list applications for this domain
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Only list applications with this status (pending, approved,
+        /// rejected)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Number of applications to skip, as printed at the end of a page
+        #[arg(long, default_value_t = 0)]
+        offset: u64,
+
+        /// Maximum number of applications
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+    },
+
+    /// Approve a registration application, creating the account
+    Approve {
+        /// Application ID
+        id: String,
+
+        /// Reason for the decision
+        #[arg(long)]
+        note: Option<String>,
+    },
+
+    /// Reject a registration application
+    Reject {
+        /// Application ID
+        id: String,
+
+        /// Reason for the decision
+        #[arg(long)]
+        note: Option<String>,
     },
 }
 
@@ -685,12 +1309,26 @@ enum ContextCommands {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     // Initialize logging
     tracing_subscriber::fmt().with_env_filter("info").init();
 
     let cli = Cli::parse();
 
+    // Administered instances may well be on the local network
+    EgressPolicy::allowing_private().install();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) => {
+            eprintln!("Error: {:?}", report);
+            ExitCode::from(output::exit_code(&report))
+        }
+    }
+}
+
+/// Run a command, see [`output::exit_code`] for how failures are reported
+async fn run(cli: Cli) -> Result<()> {
     // Handle commands that don't need network / API client
     match &cli.command {
         Commands::Context { command } => return handle_context_command(command),
@@ -703,6 +1341,30 @@ async fn main() -> Result<()> {
             hostname,
             client_id,
         } => return handle_add_server(hostname, client_id.as_deref()).await,
+        Commands::Person {
+            command:
+                PersonCommands::Import {
+                    csv: Some(path),
+                    dry_run: true,
+                    ..
+                },
+        }
+        | Commands::Profile {
+            command:
+                PersonCommands::Import {
+                    csv: Some(path),
+                    dry_run: true,
+                    ..
+                },
+        } => return import::dry_run(&import::read_persons(path)?, cli.output),
+        Commands::Domain {
+            command:
+                DomainCommands::Import {
+                    file,
+                    dry_run: true,
+                    ..
+                },
+        } => return import::dry_run(&import::read_domains(file)?, cli.output),
         _ => {}
     }
 
@@ -715,11 +1377,11 @@ async fn main() -> Result<()> {
     // Refresh token if needed, then create API client
     auth::refresh_token_if_needed().await?;
     let access_token = context::get_access_token()?;
-    let api_client = AdminApiClient::new(&api_url, access_token).await?;
-
-    handle_command(&api_client, &cli.command).await?;
+    let api_client = AdminApiClient::new(&api_url, access_token)
+        .await?
+        .queue_only(cli.queue_only);
 
-    Ok(())
+    handle_command(&api_client, &cli.command, cli.output).await
 }
 
 /// Handle the `add-server` command: WebFinger discovery + OIDC login
@@ -928,34 +1590,41 @@ fn handle_context_command(command: &ContextCommands) -> Result<()> {
 }
 
 /// Handle all commands that require the API client
-async fn handle_command(client: &AdminApiClient, command: &Commands) -> Result<()> {
+async fn handle_command(
+    client: &AdminApiClient,
+    command: &Commands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
         Commands::Person { command } | Commands::Profile { command } => {
-            handle_person_command(client, command).await?;
+            handle_person_command(client, command, output).await?;
+        }
+        Commands::Group { command } => {
+            handle_group_command(client, command, output).await?;
         }
         Commands::Note { command } => {
-            handle_note_command(client, command).await?;
+            handle_note_command(client, command, output).await?;
         }
         Commands::Activity { command } => {
-            handle_activity_command(client, command).await?;
+            handle_activity_command(client, command, output).await?;
         }
         Commands::Keys { command } => {
-            handle_key_command(client, command).await?;
+            handle_key_command(client, command, output).await?;
         }
         Commands::Pki { command } => {
             handle_pki_command(command)?;
         }
         Commands::System { command } => {
-            handle_system_command(command)?;
+            handle_system_command(client, command, output).await?;
         }
         Commands::Test { command } => {
-            handle_test_command(command)?;
+            handle_test_command(client, command, output).await?;
         }
         Commands::Domain { command } => {
-            handle_domain_command(client, command).await?;
+            handle_domain_command(client, command, output).await?;
         }
         Commands::User { command } => {
-            handle_user_command(client, command).await?;
+            handle_user_command(client, command, output).await?;
         }
         Commands::Context { .. }
         | Commands::Login { .. }
@@ -967,8 +1636,24 @@ async fn handle_command(client: &AdminApiClient, command: &Commands) -> Result<(
     Ok(())
 }
 
+/// Print the outcome of a command
+///
+/// `done` describes the applied command and is followed by the ID of what
+/// it created; `queued` is printed for commands sent with `--async`.
+fn print_outcome(outcome: &CommandOutcome, done: &str, queued: &str) {
+    match outcome {
+        CommandOutcome::Done { id: Some(id) } => println!("{}: {}", done, id),
+        CommandOutcome::Done { id: None } => println!("{}", done),
+        CommandOutcome::Queued => println!("{}", queued),
+    }
+}
+
 /// Handle Person actor commands
-async fn handle_person_command(client: &AdminApiClient, command: &PersonCommands) -> Result<()> {
+async fn handle_person_command(
+    client: &AdminApiClient,
+    command: &PersonCommands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
         PersonCommands::Create {
             subject,
@@ -995,8 +1680,12 @@ async fn handle_person_command(client: &AdminApiClient, command: &PersonCommands
                 props,
             );
 
-            client.create_person(&message).await?;
-            println!("Person creation request for '{}' sent", formatted_subject);
+            let outcome = client.create_person(&message).await?;
+            print_outcome(
+                &outcome,
+                &format!("Person '{}' created", formatted_subject),
+                &format!("Person creation request for '{}' queued", formatted_subject),
+            );
         }
 
         PersonCommands::Update {
@@ -1004,6 +1693,9 @@ async fn handle_person_command(client: &AdminApiClient, command: &PersonCommands
             summary,
             icon,
             properties,
+            discoverable,
+            indexable,
+            application_profile,
         } => {
             let props = if let Some(props_json) = properties {
                 Some(
@@ -1015,31 +1707,269 @@ async fn handle_person_command(client: &AdminApiClient, command: &PersonCommands
                 None
             };
 
-            let message = oxifed::messaging::ProfileUpdateMessage::new(
+            let mut message = oxifed::messaging::ProfileUpdateMessage::new(
                 id.clone(),
                 summary.clone(),
                 icon.clone(),
                 props,
             );
-
-            client.update_person(&message).await?;
-            println!("Person update request for ID '{}' sent", id);
+            message.discoverable = *discoverable;
+            message.indexable = *indexable;
+            message.application_profile = application_profile
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|e: String| miette::miette!(e))?;
+
+            let outcome = client.update_person(&message).await?;
+            print_outcome(
+                &outcome,
+                &format!("Person '{}' updated", id),
+                &format!("Person update request for ID '{}' queued", id),
+            );
         }
 
         PersonCommands::Delete { id, force } => {
-            client.delete_person(id, *force).await?;
-            println!("Person deletion request for ID '{}' sent", id);
+            let outcome = client.delete_person(id, *force).await?;
+            print_outcome(
+                &outcome,
+                &format!("Person '{}' deleted", id),
+                &format!("Person deletion request for ID '{}' queued", id),
+            );
             if *force {
                 println!("Forced deletion requested");
             }
         }
+
+        PersonCommands::List {
+            domain,
+            local,
+            status,
+            offset,
+            limit,
+        } => {
+            let page = client
+                .list_persons(
+                    domain.as_deref(),
+                    *local,
+                    status.as_deref(),
+                    *offset,
+                    *limit,
+                )
+                .await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No persons found");
+                    return;
+                }
+                println!("Persons ({} of {}):", page.items.len(), page.total);
+                for person in &page.items {
+                    let mut state = person.status.clone();
+                    if person.restriction != "none" {
+                        state = format!("{}, {}", state, person.restriction);
+                    }
+                    println!(
+                        "  {}@{} [{}] {} ({})",
+                        person.username, person.domain, state, person.actor_type, person.actor_id
+                    );
+                }
+                print_next_page(page);
+            })?;
+        }
+
+        PersonCommands::Show { id } => {
+            let actor = resolve::resolve_target(id).await?;
+            let person = client
+                .get_person(&actor)
+                .await?
+                .ok_or_else(|| ApiError::not_found(format!("Person '{}' not found", actor)))?;
+            output::print(output, &person, |p| {
+                println!("Actor: {}", p.actor_id);
+                println!("Username: {}@{}", p.username, p.domain);
+                if let Some(display_name) = &p.display_name {
+                    println!("Display Name: {}", display_name);
+                }
+                println!("Type: {}", p.actor_type);
+                println!("Local: {}", p.local);
+                println!("Status: {}", p.status);
+                println!("Restriction: {}", p.restriction);
+                println!(
+                    "Followers: {}, following: {}, posts: {}",
+                    p.followers_count, p.following_count, p.statuses_count
+                );
+                println!("Created: {}", p.created_at);
+                println!("Updated: {}", p.updated_at);
+            })?;
+        }
+
+        PersonCommands::Moderate { id, state } => {
+            let state = state
+                .parse::<ModerationState>()
+                .map_err(|e| miette::miette!("{}", e))?;
+            let actor = resolve::resolve_target(id).await?;
+            let outcome = client
+                .moderate_person(&ProfileModerateMessage::new(actor.clone(), state))
+                .await?;
+            print_outcome(
+                &outcome,
+                &format!("'{}' is now {}", actor, state),
+                &format!("Moderation of '{}' queued", actor),
+            );
+        }
+
+        PersonCommands::Alias {
+            subject,
+            alias,
+            remove,
+        } => {
+            let alias = format_subject(alias);
+            let message = ProfileAliasMessage::new(format_subject(subject), alias.clone(), *remove);
+            let outcome = client.alias_person(&message).await?;
+            let done = if *remove { "removed from" } else { "added to" };
+            print_outcome(
+                &outcome,
+                &format!("Alias '{}' {} '{}'", alias, done, subject),
+                &format!("Alias change of '{}' queued", subject),
+            );
+        }
+
+        PersonCommands::Export { subject } => {
+            client.export_person(&format_subject(subject)).await?;
+            println!(
+                "Export of '{}' queued; the archive is written to domainservd's archive directory",
+                subject
+            );
+        }
+
+        // Dry runs are answered by `run` without an API client
+        PersonCommands::Import {
+            csv: Some(path),
+            batch_size,
+            ..
+        } => {
+            let persons = import::read_persons(path)?;
+            import::run(client, &persons, usize::from(*batch_size), output).await?;
+        }
+
+        PersonCommands::Import {
+            subject: Some(subject),
+            archive: Some(archive),
+            ..
+        } => {
+            client
+                .import_person(&format_subject(subject), archive)
+                .await?;
+            println!("Import of '{}' as '{}' queued", archive, subject);
+        }
+
+        PersonCommands::Import { .. } => {
+            return Err(miette::miette!(
+                "Give a subject and an archive, or --csv with a file"
+            ));
+        }
+
+        PersonCommands::Followers {
+            actor,
+            status,
+            before,
+            limit,
+        } => {
+            let resolved_actor = resolve::resolve_target(actor).await?;
+            let page = client
+                .follow_page(
+                    FollowDirection::Followers,
+                    &resolved_actor,
+                    status.as_deref(),
+                    before.as_deref(),
+                    *limit,
+                )
+                .await?;
+            output::print(output, &page, |page| {
+                println!(
+                    "Followers of {}: {}",
+                    resolved_actor,
+                    format_follow_counts(&page.counts)
+                );
+                for f in &page.items {
+                    println!(
+                        "  {} {} (since {})",
+                        follow_status_indicator(&f.status),
+                        f.follower,
+                        f.created_at
+                    );
+                }
+                if let Some(next) = &page.next {
+                    println!("More followers with --before {}", next);
+                }
+            })?;
+        }
+
+        PersonCommands::Following {
+            actor,
+            status,
+            before,
+            limit,
+        } => {
+            let resolved_actor = resolve::resolve_target(actor).await?;
+            let page = client
+                .follow_page(
+                    FollowDirection::Following,
+                    &resolved_actor,
+                    status.as_deref(),
+                    before.as_deref(),
+                    *limit,
+                )
+                .await?;
+            output::print(output, &page, |page| {
+                println!(
+                    "{} follows: {}",
+                    resolved_actor,
+                    format_follow_counts(&page.counts)
+                );
+                for f in &page.items {
+                    println!(
+                        "  {} {} (since {})",
+                        follow_status_indicator(&f.status),
+                        f.following,
+                        f.created_at
+                    );
+                }
+                if let Some(next) = &page.next {
+                    println!("More follows with --before {}", next);
+                }
+            })?;
+        }
     }
 
     Ok(())
 }
 
+/// Hint at the `--offset` of the next page, if there is one
+fn print_next_page<T>(page: &Page<T>) {
+    if let Some(next) = page.next {
+        println!("More with --offset {}", next);
+    }
+}
+
+/// Summary of follow counts such as `12 (10 accepted, 2 pending, 0 rejected)`
+fn format_follow_counts(counts: &FollowCounts) -> String {
+    let mut summary = format!(
+        "{} ({} accepted, {} pending, {} rejected",
+        counts.total, counts.accepted, counts.pending, counts.rejected
+    );
+    if counts.cancelled > 0 {
+        summary.push_str(&format!(", {} cancelled", counts.cancelled));
+    }
+    summary.push(')');
+    summary
+}
+
 /// Handle Note object commands
-async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) -> Result<()> {
+async fn handle_note_command(
+    client: &AdminApiClient,
+    command: &NoteCommands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
         NoteCommands::Create {
             author,
@@ -1048,8 +1978,11 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
             mentions,
             tags,
             properties,
+            scheduled_at,
+            visibility,
+            quote,
         } => {
-            let props = if let Some(props_json) = properties {
+            let mut props: Option<serde_json::Value> = if let Some(props_json) = properties {
                 Some(
                     serde_json::from_str(props_json)
                         .into_diagnostic()
@@ -1058,8 +1991,18 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
             } else {
                 None
             };
+            for (key, value) in [("visibility", visibility), ("quote", quote)] {
+                let Some(value) = value else {
+                    continue;
+                };
+                let props = props.get_or_insert_with(|| serde_json::json!({}));
+                let Some(props) = props.as_object_mut() else {
+                    return Err(miette::miette!("Custom properties must be a JSON object"));
+                };
+                props.insert(key.to_string(), value.clone().into());
+            }
 
-            let message = oxifed::messaging::NoteCreateMessage::new(
+            let mut message = oxifed::messaging::NoteCreateMessage::new(
                 author.clone(),
                 content.clone(),
                 summary.clone(),
@@ -1067,9 +2010,23 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
                 tags.clone(),
                 props,
             );
+            if let Some(at) = scheduled_at {
+                message = message.with_scheduled_at(*at);
+            }
 
-            client.create_note(&message).await?;
-            println!("Note creation request by '{}' sent", author);
+            let outcome = client.create_note(&message).await?;
+            match scheduled_at {
+                Some(at) => print_outcome(
+                    &outcome,
+                    &format!("Note by '{}' scheduled for {}", author, at.to_rfc3339()),
+                    &format!("Note by '{}' queued for {}", author, at.to_rfc3339()),
+                ),
+                None => print_outcome(
+                    &outcome,
+                    &format!("Note by '{}' created", author),
+                    &format!("Note creation request by '{}' queued", author),
+                ),
+            }
         }
 
         NoteCommands::Update {
@@ -1078,6 +2035,8 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
             summary,
             tags,
             properties,
+            pin,
+            unpin,
         } => {
             let props = if let Some(props_json) = properties {
                 Some(
@@ -1089,25 +2048,134 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
                 None
             };
 
-            let message = oxifed::messaging::NoteUpdateMessage::new(
+            let mut message = oxifed::messaging::NoteUpdateMessage::new(
                 id.clone(),
                 content.clone(),
                 summary.clone(),
                 tags.clone(),
                 props,
             );
+            if *pin || *unpin {
+                message = message.with_featured(*pin);
+            }
 
-            client.update_note(&message).await?;
-            println!("Note update request for ID '{}' sent", id);
+            let outcome = client.update_note(&message).await?;
+            print_outcome(
+                &outcome,
+                &format!("Note '{}' updated", id),
+                &format!("Note update request for ID '{}' queued", id),
+            );
         }
 
         NoteCommands::Delete { id, force } => {
-            client.delete_note(id, *force).await?;
-            println!("Note deletion request for ID '{}' sent", id);
+            let outcome = client.delete_note(id, *force).await?;
+            print_outcome(
+                &outcome,
+                &format!("Note '{}' deleted", id),
+                &format!("Note deletion request for ID '{}' queued", id),
+            );
             if *force {
                 println!("Forced deletion requested");
             }
         }
+
+        NoteCommands::List {
+            actor,
+            offset,
+            limit,
+        } => {
+            let resolved_actor = match actor {
+                Some(actor) => Some(resolve::resolve_actor(Some(actor)).await?),
+                None => None,
+            };
+
+            let page = client
+                .list_notes(resolved_actor.as_deref(), *offset, *limit)
+                .await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No notes found");
+                    return;
+                }
+                println!("Notes ({} of {}):", page.items.len(), page.total);
+                for note in &page.items {
+                    println!(
+                        "  {} {} by {} [{}]",
+                        note.published.as_deref().unwrap_or("-"),
+                        note.object_id,
+                        note.author,
+                        note.visibility
+                    );
+                    if let Some(content) = &note.content {
+                        println!("    {}", content);
+                    }
+                }
+                print_next_page(page);
+            })?;
+        }
+
+        NoteCommands::Show { id } => {
+            let note = client
+                .get_note(id)
+                .await?
+                .ok_or_else(|| ApiError::not_found(format!("Note '{}' not found", id)))?;
+            output::print(output, &note, |n| {
+                println!("Note: {}", n.object_id);
+                println!("Type: {}", n.object_type);
+                println!("Author: {}", n.author);
+                println!("Visibility: {}", n.visibility);
+                if let Some(published) = &n.published {
+                    println!("Published: {}", published);
+                }
+                if let Some(in_reply_to) = &n.in_reply_to {
+                    println!("In reply to: {}", in_reply_to);
+                }
+                if let Some(quote) = &n.quote {
+                    println!("Quotes: {}", quote);
+                }
+                if n.quote_count > 0 {
+                    println!("Quoted: {} times", n.quote_count);
+                }
+                if let Some(summary) = &n.summary {
+                    println!("Summary: {}", summary);
+                }
+                if let Some(content) = &n.content {
+                    println!("Content: {}", content);
+                }
+            })?;
+        }
+
+        NoteCommands::Scheduled { actor } => {
+            let resolved_actor = match actor {
+                Some(actor) => Some(resolve::resolve_actor(Some(actor)).await?),
+                None => None,
+            };
+
+            let notes = client
+                .list_scheduled_notes(resolved_actor.as_deref())
+                .await?;
+            output::print(output, &notes, |notes| {
+                if notes.is_empty() {
+                    println!("No scheduled notes");
+                    return;
+                }
+                println!("Scheduled notes ({}):", notes.len());
+                for note in notes {
+                    println!(
+                        "  {} {} by {}",
+                        note.scheduled_at, note.object_id, note.author
+                    );
+                    if let Some(content) = &note.content {
+                        println!("    {}", content);
+                    }
+                }
+            })?;
+        }
+
+        NoteCommands::Cancel { id } => {
+            client.cancel_scheduled_note(id).await?;
+            println!("Scheduled note '{}' cancelled", id);
+        }
     }
 
     Ok(())
@@ -1117,6 +2185,7 @@ async fn handle_note_command(client: &AdminApiClient, command: &NoteCommands) ->
 async fn handle_activity_command(
     client: &AdminApiClient,
     command: &ActivityCommands,
+    output: OutputFormat,
 ) -> Result<()> {
     match command {
         ActivityCommands::Follow { actor, object } => {
@@ -1145,46 +2214,42 @@ async fn handle_activity_command(
             let resolved_actor = resolve::resolve_actor(actor.as_deref()).await?;
 
             let follows = client.list_following(&resolved_actor).await?;
-            if follows.is_empty() {
-                println!("{} is not following anyone", resolved_actor);
-            } else {
+            output::print(output, &follows, |follows| {
+                if follows.is_empty() {
+                    println!("{} is not following anyone", resolved_actor);
+                    return;
+                }
                 println!("Following ({}):", follows.len());
-                for f in &follows {
-                    let status_indicator = match f.status.as_str() {
-                        "accepted" => "[accepted]",
-                        "pending" => "[pending] ",
-                        "rejected" => "[rejected]",
-                        _ => &f.status,
-                    };
+                for f in follows {
                     println!(
                         "  {} {} (since {})",
-                        status_indicator, f.following, f.created_at
+                        follow_status_indicator(&f.status),
+                        f.following,
+                        f.created_at
                     );
                 }
-            }
+            })?;
         }
 
         ActivityCommands::Followers { actor } => {
             let resolved_actor = resolve::resolve_actor(actor.as_deref()).await?;
 
             let follows = client.list_followers(&resolved_actor).await?;
-            if follows.is_empty() {
-                println!("{} has no followers", resolved_actor);
-            } else {
+            output::print(output, &follows, |follows| {
+                if follows.is_empty() {
+                    println!("{} has no followers", resolved_actor);
+                    return;
+                }
                 println!("Followers ({}):", follows.len());
-                for f in &follows {
-                    let status_indicator = match f.status.as_str() {
-                        "accepted" => "[accepted]",
-                        "pending" => "[pending] ",
-                        "rejected" => "[rejected]",
-                        _ => &f.status,
-                    };
+                for f in follows {
                     println!(
                         "  {} {} (since {})",
-                        status_indicator, f.follower, f.created_at
+                        follow_status_indicator(&f.status),
+                        f.follower,
+                        f.created_at
                     );
                 }
-            }
+            })?;
         }
 
         ActivityCommands::Announce {
@@ -1204,13 +2269,53 @@ async fn handle_activity_command(
                 resolved_actor, resolved_object
             );
         }
+
+        ActivityCommands::Status { id } => {
+            let status = client.delivery_status(id).await?;
+            output::print(output, &status, |status| {
+                println!("Activity: {}", status.activity_id);
+                println!(
+                    "Delivered: {}, retrying: {}, failed: {}",
+                    status.delivered, status.retrying, status.failed
+                );
+                for inbox in &status.inboxes {
+                    println!(
+                        "  [{}] {} ({} attempts, updated {})",
+                        inbox.state.as_str(),
+                        inbox.inbox,
+                        inbox.attempts,
+                        inbox.updated_at
+                    );
+                    if let Some(error) = &inbox.last_error {
+                        println!("      last error: {}", error);
+                    }
+                    if let Some(next) = &inbox.next_retry_at {
+                        println!("      next retry: {}", next);
+                    }
+                }
+            })?;
+        }
     }
 
     Ok(())
 }
 
+/// Status of a follow relationship, padded to line up in lists
+fn follow_status_indicator(status: &str) -> &str {
+    match status {
+        "accepted" => "[accepted]",
+        "pending" => "[pending] ",
+        "rejected" => "[rejected]",
+        _ => status,
+    }
+}
+
 /// Handle Key commands
-async fn handle_key_command(client: &AdminApiClient, command: &KeyCommands) -> Result<()> {
+async fn handle_key_command(
+    client: &AdminApiClient,
+    command: &KeyCommands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
         KeyCommands::Generate {
             actor,
@@ -1222,8 +2327,8 @@ async fn handle_key_command(client: &AdminApiClient, command: &KeyCommands) -> R
                 println!("Key size: {}", size);
             }
 
-            client.generate_key(actor, algorithm, *key_size).await?;
-            println!("Key generation request sent");
+            let outcome = client.generate_key(actor, algorithm, *key_size).await?;
+            print_outcome(&outcome, "Key generated", "Key generation request queued");
         }
 
         KeyCommands::Import {
@@ -1232,53 +2337,180 @@ async fn handle_key_command(client: &AdminApiClient, command: &KeyCommands) -> R
             private_key,
             algorithm,
         } => {
-            println!("Importing {} key for '{}'", algorithm, actor);
-            println!("Public key: {}", public_key);
-            println!("Private key: {}", private_key);
-            println!("Key import request sent to PKI service");
-        }
-
-        KeyCommands::Verify { actor, domain } => {
+            let public_key_pem = std::fs::read_to_string(public_key)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read public key file '{}'", public_key))?;
+            let private_key_pem = std::fs::read_to_string(private_key)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read private key file '{}'", private_key))?;
+            let resolved_actor = resolve::resolve_target(actor).await?;
+
+            println!("Importing {} key for '{}'", algorithm, resolved_actor);
+            let outcome = client
+                .import_key(&resolved_actor, algorithm, public_key_pem, private_key_pem)
+                .await?;
+            print_outcome(&outcome, "Key imported", "Key import request queued");
             println!(
-                "Initiating domain verification for '{}' on domain '{}'",
-                actor, domain
+                "The imported key is unverified; run 'oxiadm keys verify' to prove control of the domain"
             );
-            println!("Domain verification request sent to PKI service");
         }
 
-        KeyCommands::VerifyComplete {
+        KeyCommands::Verify {
             actor,
             domain,
-            challenge_response,
+            method,
         } => {
+            let method = method
+                .parse::<VerificationMethod>()
+                .map_err(|e| miette::miette!("{}", e))?;
+            let resolved_actor = resolve::resolve_target(actor).await?;
+
+            let challenge = client
+                .start_key_verification(&resolved_actor, domain, method)
+                .await?;
+            println!("Verification challenge issued for key {}", challenge.key_id);
+            match challenge.method {
+                VerificationMethod::Dns => {
+                    println!("Publish this TXT record:");
+                    println!("  {} TXT \"{}\"", challenge.location(), challenge.token);
+                }
+                VerificationMethod::WellKnown => {
+                    println!("Serve this token as plain text:");
+                    println!("  URL:   {}", challenge.location());
+                    println!("  Token: {}", challenge.token);
+                }
+            }
+            println!("The challenge expires at {}", challenge.expires_at);
             println!(
-                "Completing domain verification for '{}' on domain '{}'",
+                "Then run 'oxiadm keys verify-complete --actor {} --domain {}'",
                 actor, domain
             );
-            println!("Challenge response file: {}", challenge_response);
-            println!("Verification completion request sent to PKI service");
         }
 
-        KeyCommands::Rotate {
-            actor,
+        KeyCommands::VerifyComplete { actor, domain } => {
+            let resolved_actor = resolve::resolve_target(actor).await?;
+
+            let challenge = client
+                .complete_key_verification(&resolved_actor, domain)
+                .await?;
+            match challenge.status {
+                VerificationStatus::Verified => println!(
+                    "Key {} is now domain verified for '{}'",
+                    challenge.key_id, domain
+                ),
+                VerificationStatus::Pending => {
+                    println!("Verification of key {} failed", challenge.key_id);
+                    if let Some(failure) = &challenge.failure {
+                        println!("  {}", failure);
+                    }
+                    println!(
+                        "Check that the token is published at {} and try again",
+                        challenge.location()
+                    );
+                }
+                VerificationStatus::Expired => println!(
+                    "The challenge for key {} expired, run 'oxiadm keys verify' again",
+                    challenge.key_id
+                ),
+            }
+        }
+
+        KeyCommands::Rotate {
+            actor,
             rotation_type,
+            algorithm,
+            key_size,
         } => {
-            println!("Rotating key for '{}' with type '{}'", actor, rotation_type);
-            println!("Key rotation request sent to PKI service");
+            let rotation = rotation_type
+                .parse::<KeyRotationType>()
+                .map_err(|e| miette::miette!("{}", e))?;
+            let resolved_actor = resolve::resolve_target(actor).await?;
+
+            println!(
+                "Rotating key for '{}' with type '{}'",
+                resolved_actor, rotation_type
+            );
+            let outcome = client
+                .rotate_key(&resolved_actor, rotation, algorithm.as_deref(), *key_size)
+                .await?;
+            print_outcome(&outcome, "Key rotated", "Key rotation request queued");
+            match rotation {
+                KeyRotationType::Scheduled => println!(
+                    "The old key stays valid for {} days",
+                    KEY_ROTATION_OVERLAP_DAYS
+                ),
+                KeyRotationType::Emergency => println!("The old key is revoked immediately"),
+            }
+        }
+
+        KeyCommands::Revoke { actor, key_id } => {
+            let resolved_actor = resolve::resolve_target(actor).await?;
+
+            println!("Revoking key '{}' of '{}'", key_id, resolved_actor);
+            let outcome = client.revoke_key(&resolved_actor, key_id).await?;
+            print_outcome(&outcome, "Key revoked", "Key revocation request queued");
         }
 
         KeyCommands::TrustChain { key_id } => {
-            println!("Viewing trust chain for key: {}", key_id);
-            println!("Trust chain request sent to PKI service");
+            let report = client
+                .get_trust_chain(key_id)
+                .await?
+                .ok_or_else(|| ApiError::not_found(format!("Key '{}' not found", key_id)))?;
+            output::print(output, &report, |report| {
+                println!("Key: {}", report.chain.key_id);
+                println!("Status: {}", report.status);
+                println!("Trust Level: {:?}", report.chain.trust_level);
+                println!("Chain:");
+                for link in &report.chain.verification_chain {
+                    let signer = match &link.signed_by {
+                        Some(signer) => format!("signed by {}", signer),
+                        None => "self-signed".to_string(),
+                    };
+                    let revoked = if link.revoked { " [revoked]" } else { "" };
+                    println!("  {}: {} ({}){}", link.level, link.key_id, signer, revoked);
+                }
+                match &report.error {
+                    None => println!("Verification: valid"),
+                    Some(error) => println!("Verification: failed ({})", error),
+                }
+            })?;
         }
 
-        KeyCommands::List { trust_level } => {
-            if let Some(level) = trust_level {
-                println!("Listing keys with trust level: {}", level);
-            } else {
-                println!("Listing all keys");
-            }
-            println!("Key list request sent to PKI service");
+        KeyCommands::List {
+            actor,
+            trust_level,
+            offset,
+            limit,
+        } => {
+            let trust_level = trust_level
+                .as_deref()
+                .map(str::parse::<TrustLevel>)
+                .transpose()
+                .map_err(|e| miette::miette!("{}", e))?;
+            let actor = match actor {
+                Some(actor) => Some(resolve::resolve_target(actor).await?),
+                None => None,
+            };
+
+            let page = client
+                .list_keys(actor.as_deref(), trust_level, *offset, *limit)
+                .await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No keys found");
+                    return;
+                }
+                println!("Keys ({} of {}):", page.items.len(), page.total);
+                for key in &page.items {
+                    println!(
+                        "  {} [{}] {} {:?} ({})",
+                        key.key_id, key.status, key.algorithm, key.trust_level, key.actor_id
+                    );
+                    if let Some(expires_at) = &key.expires_at {
+                        println!("    Expires: {}", expires_at);
+                    }
+                }
+            })?;
         }
     }
 
@@ -1347,11 +2579,39 @@ fn handle_pki_command(command: &PkiCommands) -> Result<()> {
 }
 
 /// Handle System commands (mostly stubs for now)
-fn handle_system_command(command: &SystemCommands) -> Result<()> {
+async fn handle_system_command(
+    client: &AdminApiClient,
+    command: &SystemCommands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
         SystemCommands::Health => {
-            println!("Checking system health");
-            println!("Health check request sent to system service");
+            let health = client.system_health().await?;
+            output::print(output, &health, |health| {
+                println!("System health: {}", health.status.as_str());
+                for report in &health.services {
+                    println!(
+                        "  {} ({}): {} - checked {}",
+                        report.service,
+                        report.instance,
+                        report.status.as_str(),
+                        report.checked_at
+                    );
+                    for component in &report.components {
+                        match &component.detail {
+                            Some(detail) => println!(
+                                "    {}: {} ({})",
+                                component.name,
+                                component.status.as_str(),
+                                detail
+                            ),
+                            None => {
+                                println!("    {}: {}", component.name, component.status.as_str())
+                            }
+                        }
+                    }
+                }
+            })?;
         }
 
         SystemCommands::PkiStatus => {
@@ -1392,31 +2652,308 @@ fn handle_system_command(command: &SystemCommands) -> Result<()> {
             }
             println!("Instance configuration request sent to system service");
         }
+
+        SystemCommands::DeliveryLimits {
+            max_in_flight,
+            max_per_host,
+        } => {
+            let limits = if max_in_flight.is_none() && max_per_host.is_none() {
+                client.get_delivery_limits().await?
+            } else {
+                client
+                    .set_delivery_limits(*max_in_flight, *max_per_host)
+                    .await?
+            };
+            output::print(output, &limits, |limits| {
+                println!("Deliveries in flight: {}", limits.max_in_flight);
+                println!("Deliveries per host: {}", limits.max_per_host);
+            })?;
+        }
+
+        SystemCommands::Replay {
+            domain,
+            from_id,
+            to_id,
+            actor,
+            activity_type,
+            since,
+            until,
+            limit,
+            dry_run,
+            allow_delivery,
+        } => {
+            let filter = ReplayFilter {
+                domain: domain.clone(),
+                from_id: from_id.clone(),
+                to_id: to_id.clone(),
+                actor: actor.clone(),
+                activity_type: activity_type.clone(),
+                since: since.clone(),
+                until: until.clone(),
+            };
+            let summary = client
+                .replay_activities(&filter, *limit, *dry_run, *allow_delivery)
+                .await?;
+            output::print(output, &summary, |summary| {
+                for activity in &summary.activities {
+                    println!(
+                        "{}  {}  {}  {}  {}",
+                        activity.storage_id,
+                        activity.created_at,
+                        activity.activity_type,
+                        activity.actor,
+                        activity.activity_id
+                    );
+                }
+                if summary.dry_run {
+                    println!("{} activities would be replayed", summary.activities.len());
+                } else {
+                    println!(
+                        "Replay {}: {} activities, {} messages published",
+                        summary.replay_id,
+                        summary.activities.len(),
+                        summary.published
+                    );
+                }
+                if let Some(next) = &summary.next_from_id {
+                    println!("More activities match; continue with --from-id {}", next);
+                }
+            })?;
+        }
+
+        SystemCommands::Dlq { command } => {
+            handle_dlq_command(client, command, output).await?;
+        }
+
+        SystemCommands::Instances { command } => {
+            handle_instance_command(client, command, output).await?;
+        }
+
+        SystemCommands::Audit {
+            actor,
+            action,
+            target,
+            before,
+            limit,
+            details,
+        } => {
+            let (entries, next) = client
+                .list_audit_entries(
+                    actor.as_deref(),
+                    action.as_deref(),
+                    target.as_deref(),
+                    before.as_deref(),
+                    *limit,
+                )
+                .await?;
+            let page = serde_json::json!({ "items": entries, "next": next });
+            output::print(output, &page, |_| {
+                if entries.is_empty() {
+                    println!("No audit entries");
+                }
+                for entry in &entries {
+                    println!(
+                        "{} {} {} ({}) {} -> {}",
+                        entry.timestamp,
+                        entry.actor,
+                        entry.action,
+                        entry.role.as_deref().unwrap_or("no role"),
+                        entry.target.as_deref().unwrap_or("-"),
+                        entry.status
+                    );
+                    if !entry.changes.is_empty() {
+                        println!("    Changed: {}", entry.changes.join(", "));
+                    }
+                    if *details {
+                        if let Some(before) = &entry.before {
+                            println!("    Before: {}", before);
+                        }
+                        if let Some(after) = &entry.after {
+                            println!("    After: {}", after);
+                        }
+                    }
+                }
+                if let Some(next) = &next {
+                    println!("More entries with --before {}", next);
+                }
+            })?;
+        }
     }
 
     Ok(())
 }
 
-/// Handle Test commands (stubs)
-fn handle_test_command(command: &TestCommands) -> Result<()> {
+/// Handle dead-letter queue commands
+async fn handle_instance_command(
+    client: &AdminApiClient,
+    command: &InstanceCommands,
+    output: OutputFormat,
+) -> Result<()> {
     match command {
-        TestCommands::Signatures { actor, target } => {
-            println!(
-                "Testing HTTP signatures for '{}' to target: {}",
-                actor, target
-            );
-            println!("Signature test request sent to federation service");
+        InstanceCommands::List {
+            failing,
+            offset,
+            limit,
+        } => {
+            let page = client.list_instances(*failing, *offset, *limit).await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No instances found");
+                    return;
+                }
+                println!("Instances ({} of {}):", page.items.len(), page.total);
+                for instance in &page.items {
+                    let software = match (&instance.software, &instance.version) {
+                        (Some(software), Some(version)) => format!("{} {}", software, version),
+                        (Some(software), None) => software.clone(),
+                        _ => "unknown".to_string(),
+                    };
+                    print!("  {} ({})", instance.domain, software);
+                    if let Some(block) = &instance.block {
+                        print!(" [{}]", block);
+                    }
+                    if instance.consecutive_failures > 0 {
+                        print!(" {} failed deliveries", instance.consecutive_failures);
+                    }
+                    println!();
+                }
+            })?;
+        }
+
+        InstanceCommands::Show { domain } => {
+            let instance = client.get_instance(domain).await?;
+            output::print(output, &instance, |instance| {
+                println!("Instance: {}", instance.domain);
+                println!(
+                    "  Software: {} {}",
+                    instance.software.as_deref().unwrap_or("unknown"),
+                    instance.version.as_deref().unwrap_or("")
+                );
+                if let Some(open) = instance.open_registrations {
+                    println!("  Registrations: {}", if open { "open" } else { "closed" });
+                }
+                println!("  First seen: {}", instance.first_seen_at);
+                if let Some(at) = &instance.last_seen_at {
+                    println!("  Last seen: {}", at);
+                }
+                if let Some(at) = &instance.last_delivery_at {
+                    println!("  Last delivery: {}", at);
+                }
+                if let Some(at) = &instance.last_failure_at {
+                    println!(
+                        "  Last failure: {} ({})",
+                        at,
+                        instance.last_error.as_deref().unwrap_or("unknown error")
+                    );
+                }
+                println!(
+                    "  Failed deliveries in a row: {}",
+                    instance.consecutive_failures
+                );
+                println!("  Block: {}", instance.block.as_deref().unwrap_or("none"));
+                if let Some(at) = &instance.crawled_at {
+                    match &instance.crawl_error {
+                        Some(error) => println!("  NodeInfo fetched: {} (failed: {})", at, error),
+                        None => println!("  NodeInfo fetched: {}", at),
+                    }
+                }
+            })?;
+        }
+
+        InstanceCommands::Stats => {
+            let stats = client.federation_stats().await?;
+            output::print(output, &stats, |stats| {
+                println!(
+                    "Instances: {} ({} unknown software, {} unreachable)",
+                    stats.instances, stats.unknown, stats.unreachable
+                );
+                for software in &stats.software {
+                    println!(
+                        "  {}: {} ({} open for registrations)",
+                        software.name, software.instances, software.open_registrations
+                    );
+                    for version in &software.versions {
+                        println!("    {}: {}", version.version, version.instances);
+                    }
+                }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_dlq_command(
+    client: &AdminApiClient,
+    command: &DlqCommands,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        DlqCommands::List { queue, status } => {
+            let items = client
+                .list_dead_letters(queue.as_deref(), status.as_deref())
+                .await?;
+            output::print(output, &items, |items| {
+                if items.is_empty() {
+                    println!("No dead letters");
+                    return;
+                }
+                println!("Dead letters:");
+                for item in items {
+                    println!(
+                        "  {} [{}] {} ({}, {:?}, {} attempts) - {}",
+                        item.dead_letter_id,
+                        item.status,
+                        item.queue,
+                        item.reason,
+                        item.failure_class,
+                        item.attempts,
+                        item.created_at
+                    );
+                    if let Some(error) = &item.error {
+                        println!("    Error: {}", error);
+                    }
+                    if let Some(next_retry_at) = &item.next_retry_at {
+                        println!("    Next retry: {}", next_retry_at);
+                    }
+                }
+            })?;
+        }
+
+        DlqCommands::Retry { id, .. } => {
+            let count = client.retry_dead_letters(id.as_deref()).await?;
+            println!("Re-injected {} dead letter(s)", count);
+        }
+
+        DlqCommands::Purge { id, .. } => {
+            let count = client.purge_dead_letters(id.as_deref()).await?;
+            println!("Purged {} dead letter(s)", count);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle Test commands
+async fn handle_test_command(
+    client: &AdminApiClient,
+    command: &TestCommands,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        TestCommands::Signatures {
+            actor,
+            target,
+            send,
+        } => {
+            federation::signatures(client, actor, target.as_deref(), *send, output).await?;
         }
 
         TestCommands::Federation {
             actor,
             remote_actor,
         } => {
-            println!(
-                "Testing federation connectivity from '{}' to '{}'",
-                actor, remote_actor
-            );
-            println!("Federation test request sent to federation service");
+            federation::run(client, actor, remote_actor, output).await?;
         }
 
         TestCommands::AuthorizedFetch { actor, target } => {
@@ -1431,8 +2968,68 @@ fn handle_test_command(command: &TestCommands) -> Result<()> {
     Ok(())
 }
 
+/// Handle Group commands
+async fn handle_group_command(
+    client: &AdminApiClient,
+    command: &GroupCommands,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        GroupCommands::Create {
+            subject,
+            name,
+            summary,
+            moderators,
+        } => {
+            let formatted_subject = format_subject(subject);
+            let message = oxifed::messaging::GroupCreateMessage::new(
+                formatted_subject.clone(),
+                name.clone(),
+                summary.clone(),
+                moderators.clone(),
+            );
+
+            client.create_group(&message).await?;
+            println!("Group creation request for '{}' sent", formatted_subject);
+        }
+
+        GroupCommands::Members { subject } => {
+            let group_id = actor_id_from_subject(subject)?;
+            let members = client.list_followers(&group_id).await?;
+            output::print(output, &members, |members| {
+                if members.is_empty() {
+                    println!("{} has no members", subject);
+                    return;
+                }
+                println!("Members of {}:", subject);
+                for member in members {
+                    let status = if member.status == "rejected" {
+                        "banned"
+                    } else {
+                        &member.status
+                    };
+                    println!("  {} ({})", member.follower, status);
+                }
+            })?;
+        }
+
+        GroupCommands::Ban { subject, actor } => {
+            client
+                .ban_group_member(&format_subject(subject), actor)
+                .await?;
+            println!("Ban of {} from {} requested", actor, subject);
+        }
+    }
+
+    Ok(())
+}
+
 /// Handle Domain commands
-async fn handle_domain_command(client: &AdminApiClient, command: &DomainCommands) -> Result<()> {
+async fn handle_domain_command(
+    client: &AdminApiClient,
+    command: &DomainCommands,
+    output: OutputFormat,
+) -> Result<()> {
     use oxifed::messaging::{DomainCreateMessage, DomainUpdateMessage};
 
     match command {
@@ -1472,8 +3069,12 @@ async fn handle_domain_command(client: &AdminApiClient, command: &DomainCommands
                 props,
             );
 
-            client.create_domain(&message).await?;
-            println!("Domain creation request sent for: {}", domain);
+            let outcome = client.create_domain(&message).await?;
+            print_outcome(
+                &outcome,
+                "Domain created",
+                &format!("Domain creation request queued for: {}", domain),
+            );
         }
 
         DomainCommands::Update {
@@ -1512,76 +3113,246 @@ async fn handle_domain_command(client: &AdminApiClient, command: &DomainCommands
                 props,
             );
 
-            client.update_domain(&message).await?;
-            println!("Domain update request sent for: {}", domain);
+            let outcome = client.update_domain(&message).await?;
+            print_outcome(
+                &outcome,
+                &format!("Domain '{}' updated", domain),
+                &format!("Domain update request queued for: {}", domain),
+            );
         }
 
         DomainCommands::Delete { domain, force } => {
-            client.delete_domain(domain, *force).await?;
-            println!("Domain deletion request sent for: {}", domain);
+            let outcome = client.delete_domain(domain, *force).await?;
+            print_outcome(
+                &outcome,
+                &format!("Domain '{}' deleted", domain),
+                &format!("Domain deletion request queued for: {}", domain),
+            );
             if *force {
                 println!("Force deletion enabled — domain will be deleted without confirmation");
             }
         }
 
+        DomainCommands::Import {
+            file, batch_size, ..
+        } => {
+            let domains = import::read_domains(file)?;
+            import::run(client, &domains, usize::from(*batch_size), output).await?;
+        }
+
         DomainCommands::List => {
             let domains = client.list_domains().await?;
-            if domains.is_empty() {
-                println!("No domains registered");
-            } else {
+            output::print(output, &domains, |domains| {
+                if domains.is_empty() {
+                    println!("No domains registered");
+                    return;
+                }
                 println!("Registered domains:");
                 for domain in domains {
                     println!(
                         "  {} - {} ({})",
                         domain.domain,
-                        domain.name.unwrap_or_else(|| "No name".to_string()),
+                        domain.name.as_deref().unwrap_or("No name"),
                         domain.status
                     );
                 }
-            }
+            })?;
         }
 
         DomainCommands::Show { domain } => {
-            let domain_info = client.get_domain(domain).await?;
-            match domain_info {
-                Some(d) => {
-                    println!("Domain: {}", d.domain);
-                    if let Some(name) = &d.name {
-                        println!("Name: {}", name);
-                    }
-                    if let Some(description) = &d.description {
-                        println!("Description: {}", description);
-                    }
-                    if let Some(contact_email) = &d.contact_email {
-                        println!("Contact Email: {}", contact_email);
-                    }
-                    println!("Registration Mode: {}", d.registration_mode);
-                    println!("Authorized Fetch: {}", d.authorized_fetch);
-                    if let Some(max_note_length) = d.max_note_length {
-                        println!("Max Note Length: {}", max_note_length);
-                    }
-                    if let Some(max_file_size) = d.max_file_size {
-                        println!("Max File Size: {} bytes", max_file_size);
-                    }
-                    if let Some(allowed_file_types) = &d.allowed_file_types {
-                        println!("Allowed File Types: {}", allowed_file_types.join(", "));
+            let domain_info = client
+                .get_domain(domain)
+                .await?
+                .ok_or_else(|| ApiError::not_found(format!("Domain '{}' not found", domain)))?;
+            output::print(output, &domain_info, |d| {
+                println!("Domain: {}", d.domain);
+                if let Some(name) = &d.name {
+                    println!("Name: {}", name);
+                }
+                if let Some(description) = &d.description {
+                    println!("Description: {}", description);
+                }
+                if let Some(contact_email) = &d.contact_email {
+                    println!("Contact Email: {}", contact_email);
+                }
+                println!("Registration Mode: {}", d.registration_mode);
+                println!("Authorized Fetch: {}", d.authorized_fetch);
+                if let Some(max_note_length) = d.max_note_length {
+                    println!("Max Note Length: {}", max_note_length);
+                }
+                if let Some(max_file_size) = d.max_file_size {
+                    println!("Max File Size: {} bytes", max_file_size);
+                }
+                if let Some(allowed_file_types) = &d.allowed_file_types {
+                    println!("Allowed File Types: {}", allowed_file_types.join(", "));
+                }
+                println!("Status: {}", d.status);
+                println!("Created: {}", d.created_at);
+                println!("Updated: {}", d.updated_at);
+            })?;
+        }
+
+        DomainCommands::Relay { command } => handle_relay_command(client, command, output).await?,
+        DomainCommands::Invite {
+            domain,
+            max_uses,
+            expires_in_days,
+        } => {
+            let invite = client
+                .create_invite(domain, *max_uses, *expires_in_days)
+                .await?;
+            print_outcome(
+                &invite.outcome,
+                "Invite created",
+                &format!("Invite creation request queued for: {}", domain),
+            );
+            // The code cannot be read back later
+            println!("Code: {}", invite.code);
+            if let Some(expires_at) = &invite.expires_at {
+                println!("Expires: {}", expires_at);
+            }
+        }
+
+        DomainCommands::Webhook { command } => {
+            handle_webhook_command(client, command, output).await?
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle Relay commands
+async fn handle_relay_command(
+    client: &AdminApiClient,
+    command: &RelayCommands,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        RelayCommands::Add { domain, relay } => {
+            client.add_relay(domain, relay).await?;
+            println!(
+                "Relay subscription request sent for {} to {}",
+                domain, relay
+            );
+        }
+
+        RelayCommands::Remove { domain, relay } => {
+            client.remove_relay(domain, relay).await?;
+            println!(
+                "Relay unsubscription request sent for {} from {}",
+                domain, relay
+            );
+        }
+
+        RelayCommands::List { domain } => {
+            let instance_actor = format!("https://{}/actor", domain);
+            let subscriptions = client.list_following(&instance_actor).await?;
+            let subscribers = client.list_followers(&instance_actor).await?;
+            let relays = serde_json::json!({
+                "subscriptions": subscriptions,
+                "subscribers": subscribers,
+            });
+            output::print(output, &relays, |_| {
+                if subscriptions.is_empty() {
+                    println!("No relay subscriptions for {}", domain);
+                } else {
+                    println!("Relays of {}:", domain);
+                    for follow in &subscriptions {
+                        println!("  {} ({})", follow.following, follow.status);
                     }
-                    println!("Status: {}", d.status);
-                    println!("Created: {}", d.created_at);
-                    println!("Updated: {}", d.updated_at);
                 }
-                None => {
-                    println!("Domain '{}' not found", domain);
+
+                if !subscribers.is_empty() {
+                    println!("Relay subscribers of {}:", domain);
+                    for follow in &subscribers {
+                        println!("  {} ({})", follow.follower, follow.status);
+                    }
                 }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle Webhook commands
+async fn handle_webhook_command(
+    client: &AdminApiClient,
+    command: &WebhookCommands,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        WebhookCommands::Add {
+            domain,
+            url,
+            secret,
+            events,
+        } => {
+            let generated = secret.is_none();
+            let secret = secret
+                .clone()
+                .unwrap_or_else(oxifed::credentials::random_token);
+            let message = oxifed::messaging::WebhookCreateMessage::new(
+                domain.clone(),
+                url.clone(),
+                secret.clone(),
+                events.clone(),
+            );
+            let outcome = client.create_webhook(&message).await?;
+            print_outcome(
+                &outcome,
+                "Webhook created",
+                &format!("Webhook creation request queued for: {}", domain),
+            );
+            // The secret cannot be read back later
+            if generated {
+                println!("Secret: {}", secret);
             }
         }
+
+        WebhookCommands::Remove { domain, id } => {
+            let outcome = client.delete_webhook(domain, id).await?;
+            print_outcome(
+                &outcome,
+                "Webhook removed",
+                &format!("Webhook removal request queued for: {}", id),
+            );
+        }
+
+        WebhookCommands::List { domain } => {
+            let webhooks = client.list_webhooks(domain).await?;
+            output::print(output, &webhooks, |webhooks| {
+                if webhooks.is_empty() {
+                    println!("No webhooks for {}", domain);
+                    return;
+                }
+                println!("Webhooks of {}:", domain);
+                for webhook in webhooks {
+                    let events = if webhook.events.is_empty() {
+                        "all events".to_string()
+                    } else {
+                        webhook
+                            .events
+                            .iter()
+                            .map(|event| event.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    println!("  {} {} ({})", webhook.webhook_id, webhook.url, events);
+                }
+            })?;
+        }
     }
 
     Ok(())
 }
 
 /// Handle User commands
-async fn handle_user_command(client: &AdminApiClient, command: &UserCommands) -> Result<()> {
+async fn handle_user_command(
+    client: &AdminApiClient,
+    command: &UserCommands,
+    output: OutputFormat,
+) -> Result<()> {
     use oxifed::messaging::UserCreateMessage;
 
     match command {
@@ -1600,46 +3371,110 @@ async fn handle_user_command(client: &AdminApiClient, command: &UserCommands) ->
             }
         }
 
-        UserCommands::List => {
-            let users = client.list_users().await?;
-            if users.is_empty() {
-                println!("No users found");
-            } else {
-                println!("Registered users:");
-                for user in users {
+        UserCommands::List {
+            domain,
+            offset,
+            limit,
+        } => {
+            let page = client
+                .list_users(domain.as_deref(), *offset, *limit)
+                .await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No users found");
+                    return;
+                }
+                println!("Registered users ({} of {}):", page.items.len(), page.total);
+                for user in &page.items {
                     println!(
                         "  {}@{} - {} ({})",
                         user.username,
                         user.domain,
-                        user.display_name
-                            .unwrap_or_else(|| "No display name".to_string()),
+                        user.display_name.as_deref().unwrap_or("No display name"),
                         user.actor_id
                     );
                 }
-            }
+            })?;
         }
 
         UserCommands::Show { username } => {
-            let user_info = client.get_user(username).await?;
-            match user_info {
-                Some(u) => {
-                    println!("Username: {}", u.username);
-                    if let Some(display_name) = &u.display_name {
-                        println!("Display Name: {}", display_name);
+            let user_info = client
+                .get_user(username)
+                .await?
+                .ok_or_else(|| ApiError::not_found(format!("User '{}' not found", username)))?;
+            output::print(output, &user_info, |u| {
+                println!("Username: {}", u.username);
+                if let Some(display_name) = &u.display_name {
+                    println!("Display Name: {}", display_name);
+                }
+                println!("Domain: {}", u.domain);
+                println!("Actor ID: {}", u.actor_id);
+                if let Some(public_key) = &u.public_key {
+                    println!("Public Key: {}", public_key);
+                }
+                println!("Private Key Stored: {}", u.private_key_stored);
+                println!("Created: {}", u.created_at);
+                println!("Updated: {}", u.updated_at);
+            })?;
+        }
+
+        UserCommands::Applications {
+            domain,
+            status,
+            offset,
+            limit,
+        } => {
+            let page = client
+                .list_applications(domain.as_deref(), status.as_deref(), *offset, *limit)
+                .await?;
+            output::print(output, &page, |page| {
+                if page.items.is_empty() {
+                    println!("No registration applications found");
+                    return;
+                }
+                println!(
+                    "Registration applications ({} of {}):",
+                    page.items.len(),
+                    page.total
+                );
+                for application in &page.items {
+                    println!(
+                        "  {} {}@{} [{}] {}",
+                        application.application_id,
+                        application.username,
+                        application.domain,
+                        application.status,
+                        application.created_at
+                    );
+                    if let Some(email) = &application.email {
+                        println!("    Email: {}", email);
                     }
-                    println!("Domain: {}", u.domain);
-                    println!("Actor ID: {}", u.actor_id);
-                    if let Some(public_key) = &u.public_key {
-                        println!("Public Key: {}", public_key);
+                    if let Some(reason) = &application.reason {
+                        println!("    Reason: {}", reason);
                     }
-                    println!("Private Key Stored: {}", u.private_key_stored);
-                    println!("Created: {}", u.created_at);
-                    println!("Updated: {}", u.updated_at);
-                }
-                None => {
-                    println!("User '{}' not found", username);
                 }
-            }
+                print_next_page(page);
+            })?;
+        }
+
+        UserCommands::Approve { id, note } => {
+            let outcome = client.review_application(id, true, note.as_deref()).await?;
+            print_outcome(
+                &outcome,
+                "Application approved, created",
+                &format!("Approval of application {} queued", id),
+            );
+        }
+
+        UserCommands::Reject { id, note } => {
+            let outcome = client
+                .review_application(id, false, note.as_deref())
+                .await?;
+            print_outcome(
+                &outcome,
+                "Application rejected",
+                &format!("Rejection of application {} queued", id),
+            );
         }
     }
 
@@ -1653,3 +3488,12 @@ fn format_subject(subject: &str) -> String {
     }
     format!("acct:{}", subject)
 }
+
+/// Actor ID of a local actor given as `name@domain`
+fn actor_id_from_subject(subject: &str) -> Result<String> {
+    let (name, domain) = subject
+        .trim_start_matches("acct:")
+        .split_once('@')
+        .ok_or_else(|| miette::miette!("Invalid subject '{}', expected name@domain", subject))?;
+    Ok(format!("https://{}/users/{}", domain, name))
+}
@@ -0,0 +1,67 @@
+//! Output formats and exit codes
+//!
+//! Query commands print tables for people by default; `--output json` and
+//! `--output yaml` print the data the admin API answered instead, so
+//! scripts can consume it. Failures end oxiadm with an exit code telling
+//! what went wrong.
+
+use clap::ValueEnum;
+use miette::{IntoDiagnostic, Result};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::client::ApiError;
+
+/// Any error without a more specific code, such as an unreachable API
+pub const EXIT_FAILURE: u8 = 1;
+/// The domain, actor, key or object asked for does not exist
+pub const EXIT_NOT_FOUND: u8 = 3;
+/// Not logged in, or the user's role does not allow the request
+pub const EXIT_DENIED: u8 = 4;
+/// The API rejected the request as invalid
+pub const EXIT_REJECTED: u8 = 5;
+/// The daemon handling the request failed to apply or answer it
+pub const EXIT_FAILED: u8 = 6;
+/// No daemon answered the request in time
+pub const EXIT_TIMEOUT: u8 = 7;
+
+/// Format of the output of query commands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+/// Print `value` in `format`, using `table` for the table format
+pub fn print<T: Serialize + ?Sized>(
+    format: OutputFormat,
+    value: &T,
+    table: impl FnOnce(&T),
+) -> Result<()> {
+    match format {
+        OutputFormat::Table => table(value),
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(value).into_diagnostic()?);
+        }
+        OutputFormat::Yaml => print!("{}", serde_norway::to_string(value).into_diagnostic()?),
+    }
+    Ok(())
+}
+
+/// Exit code for a failed command
+pub fn exit_code(report: &miette::Report) -> u8 {
+    let Some(error) = report.downcast_ref::<ApiError>() else {
+        return EXIT_FAILURE;
+    };
+    match error.status {
+        StatusCode::NOT_FOUND => EXIT_NOT_FOUND,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => EXIT_DENIED,
+        StatusCode::GATEWAY_TIMEOUT => EXIT_TIMEOUT,
+        status if status.is_client_error() => EXIT_REJECTED,
+        status if status.is_server_error() => EXIT_FAILED,
+        _ => EXIT_FAILURE,
+    }
+}
@@ -0,0 +1,379 @@
+//! Actor custom resource
+//!
+//! An `Actor` declares a local account, typically a bot or service account,
+//! on a `Domain` of the same namespace. The operator keeps its key pair in
+//! the `<name>-keys` Secret and reconciles it into an [`ActorDocument`]
+//! with its key and WebFinger profile.
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Patch, PatchParams, PostParams};
+use kube::runtime::controller::Action;
+use kube::{Api, CustomResource, Resource, ResourceExt};
+use mongodb::bson::{doc, to_bson};
+use oxifed::database::{
+    ActorDocument, ActorRestriction, ActorStatus as DbActorStatus, DatabaseManager, KeyDocument,
+    KeyStatus, KeyType, PublicKeyDocument,
+};
+use oxifed::pki::{KeyAlgorithm, KeyPair, TrustLevel};
+use oxifed::webfinger::{JrdResource, Link};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::{Context, Domain, Error, Result};
+
+/// Spec for the Actor CRD
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(group = "oxifed.io", version = "v1alpha1", kind = "Actor", namespaced)]
+#[kube(status = "ActorStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct ActorSpec {
+    /// Local part of the account, `username@hostname`
+    pub username: String,
+    /// Name of the Domain resource the actor belongs to
+    pub domain_ref: String,
+    pub display_name: Option<String>,
+    pub summary: Option<String>,
+    /// ActivityStreams actor type
+    #[serde(default = "default_actor_type")]
+    pub actor_type: String,
+    /// Algorithm of the generated key; an existing key is kept when it
+    /// changes
+    #[serde(default)]
+    pub key_algorithm: ManagedKeyAlgorithm,
+}
+
+fn default_actor_type() -> String {
+    "Service".to_string()
+}
+
+/// Algorithm of a key generated by the operator
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub enum ManagedKeyAlgorithm {
+    #[default]
+    #[serde(rename = "rsa-2048")]
+    Rsa2048,
+    #[serde(rename = "rsa-4096")]
+    Rsa4096,
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+impl From<ManagedKeyAlgorithm> for KeyAlgorithm {
+    fn from(algorithm: ManagedKeyAlgorithm) -> Self {
+        match algorithm {
+            ManagedKeyAlgorithm::Rsa2048 => KeyAlgorithm::Rsa { key_size: 2048 },
+            ManagedKeyAlgorithm::Rsa4096 => KeyAlgorithm::Rsa { key_size: 4096 },
+            ManagedKeyAlgorithm::Ed25519 => KeyAlgorithm::Ed25519,
+        }
+    }
+}
+
+/// Status for the Actor CRD
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorStatus {
+    pub initialized: bool,
+    pub actor_id: Option<String>,
+    pub key_id: Option<String>,
+    pub last_reconciled: Option<DateTime<Utc>>,
+}
+
+pub async fn reconcile(actor: Arc<Actor>, ctx: Arc<Context>) -> Result<Action> {
+    let reference = actor.object_ref(&());
+    let result = apply(actor, ctx.clone()).await;
+    if let Err(ref e) = result {
+        crate::publish_failure(&ctx, &reference, e).await;
+    }
+    result
+}
+
+async fn apply(actor: Arc<Actor>, ctx: Arc<Context>) -> Result<Action> {
+    if actor.metadata.deletion_timestamp.is_some() {
+        return Ok(Action::await_change());
+    }
+
+    let ns = actor.namespace().unwrap();
+    let actors: Api<Actor> = Api::namespaced(ctx.client.clone(), &ns);
+    let domains: Api<Domain> = Api::namespaced(ctx.client.clone(), &ns);
+
+    tracing::info!("Reconciling Actor: {}", actor.name_any());
+
+    // 1. Resolve the domain the actor lives on
+    let domain = domains
+        .get_opt(&actor.spec.domain_ref)
+        .await
+        .map_err(Error::KubeError)?
+        .ok_or_else(|| Error::DomainNotFound(format!("{}/{}", ns, actor.spec.domain_ref)))?;
+    let hostname = domain.spec.hostname.clone();
+    let actor_id = format!("https://{}/users/{}", hostname, actor.spec.username);
+    let key_id = format!("{}#main-key", actor_id);
+
+    // 2. Load or generate the key pair
+    let key_pair = ensure_key_secret(&ctx, &ns, &actor).await?;
+
+    // 3. Update MongoDB with the actor, its key and WebFinger profile
+    if let Some(ref db_manager) = ctx.db_manager {
+        tracing::info!("Updating MongoDB for Actor: {}", actor.name_any());
+        upsert_key(db_manager, &ctx, &actor_id, &key_id, &hostname, &key_pair).await?;
+        upsert_actor(db_manager, &actor, &actor_id, &key_id, &hostname, &key_pair).await?;
+
+        let subject = format!("acct:{}@{}", actor.spec.username, hostname);
+        db_manager
+            .upsert_webfinger_profile(JrdResource {
+                subject: Some(subject),
+                aliases: Some(vec![format!(
+                    "https://{}/@{}",
+                    hostname, actor.spec.username
+                )]),
+                properties: None,
+                links: Some(vec![Link {
+                    rel: "self".to_string(),
+                    type_: Some("application/activity+json".to_string()),
+                    href: Some(actor_id.clone()),
+                    titles: None,
+                    properties: None,
+                }]),
+            })
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    } else {
+        tracing::warn!("MongoDB manager not initialized, skipping database update");
+    }
+
+    // 4. Update the status
+    let new_status = ActorStatus {
+        initialized: true,
+        actor_id: Some(actor_id),
+        key_id: Some(key_id),
+        last_reconciled: Some(Utc::now()),
+    };
+
+    let patch = serde_json::json!({
+        "status": new_status
+    });
+
+    match actors
+        .patch_status(
+            &actor.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        Ok(_) => Ok(Action::requeue(Duration::from_secs(3600))),
+        Err(kube::Error::Api(e)) if e.code == 404 => {
+            tracing::warn!(
+                "Actor {} not found during status patch, it might have been deleted",
+                actor.name_any()
+            );
+            Ok(Action::await_change())
+        }
+        Err(e) => Err(Error::KubeError(e)),
+    }
+}
+
+/// Read the actor's key pair from its Secret, creating both if missing
+async fn ensure_key_secret(ctx: &Context, ns: &str, actor: &Actor) -> Result<KeyPair> {
+    let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), ns);
+    let secret_name = format!("{}-keys", actor.name_any());
+
+    if let Some(secret) = secrets
+        .get_opt(&secret_name)
+        .await
+        .map_err(Error::KubeError)?
+    {
+        tracing::debug!("Secret {} already exists", secret_name);
+        let data = secret.data.unwrap_or_default();
+        let pem = |name: &str| {
+            data.get(name)
+                .map(|value| String::from_utf8_lossy(&value.0).to_string())
+                .ok_or_else(|| Error::PkiError(format!("Secret {} has no {}", secret_name, name)))
+        };
+        return KeyPair::import(&pem("public_key.pem")?, &pem("private_key.pem")?)
+            .map_err(|e| Error::PkiError(e.to_string()));
+    }
+
+    tracing::info!("Generating keys for Actor: {}", actor.name_any());
+    let key_pair = KeyPair::generate(actor.spec.key_algorithm.into())
+        .map_err(|e| Error::PkiError(e.to_string()))?;
+
+    let mut data = BTreeMap::new();
+    data.insert(
+        "public_key.pem".to_string(),
+        ByteString(key_pair.public_key.pem_data.as_bytes().to_vec()),
+    );
+    data.insert(
+        "private_key.pem".to_string(),
+        ByteString(key_pair.private_key.encrypted_pem.as_bytes().to_vec()),
+    );
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(secret_name),
+            namespace: Some(ns.to_string()),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+
+    secrets
+        .create(&PostParams::default(), &secret)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(key_pair)
+}
+
+/// Store the actor's key with its private key encrypted
+async fn upsert_key(
+    db_manager: &DatabaseManager,
+    ctx: &Context,
+    actor_id: &str,
+    key_id: &str,
+    hostname: &str,
+    key_pair: &KeyPair,
+) -> Result<()> {
+    let (algorithm, key_size) = algorithm_name(&key_pair.public_key.algorithm);
+    let mut key_doc = KeyDocument {
+        id: None,
+        key_id: key_id.to_string(),
+        actor_id: actor_id.to_string(),
+        key_type: KeyType::User,
+        algorithm,
+        key_size,
+        public_key_pem: key_pair.public_key.pem_data.clone(),
+        private_key_pem: Some(key_pair.private_key.encrypted_pem.clone()),
+        encryption_algorithm: None,
+        fingerprint: key_pair.public_key.fingerprint.clone(),
+        trust_level: TrustLevel::Unverified,
+        domain_signature: None,
+        master_signature: None,
+        usage: vec!["signing".to_string()],
+        status: KeyStatus::Active,
+        created_at: Utc::now(),
+        expires_at: None,
+        rotation_policy: None,
+        domain: Some(hostname.to_string()),
+        verification: None,
+    };
+    key_doc
+        .encrypt_private_key(&ctx.key_encryptor)
+        .await
+        .map_err(|e| Error::PkiError(e.to_string()))?;
+    db_manager
+        .upsert_key(key_doc)
+        .await
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    Ok(())
+}
+
+/// Create the actor, or bring its profile in line with the spec
+///
+/// The key of an existing actor is only set if it has none, so keys
+/// rotated by pkid are not replaced.
+async fn upsert_actor(
+    db_manager: &DatabaseManager,
+    actor: &Actor,
+    actor_id: &str,
+    key_id: &str,
+    hostname: &str,
+    key_pair: &KeyPair,
+) -> Result<()> {
+    let (algorithm, key_size) = algorithm_name(&key_pair.public_key.algorithm);
+    let public_key = PublicKeyDocument {
+        id: key_id.to_string(),
+        owner: actor_id.to_string(),
+        public_key_pem: key_pair.public_key.pem_data.clone(),
+        algorithm,
+        key_size,
+        fingerprint: key_pair.public_key.fingerprint.clone(),
+        created_at: Utc::now(),
+    };
+    let username = &actor.spec.username;
+    let name = actor
+        .spec
+        .display_name
+        .clone()
+        .unwrap_or_else(|| username.clone());
+
+    let existing = db_manager
+        .find_actor_by_id(actor_id)
+        .await
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    match existing {
+        Some(existing) => {
+            let mut update = doc! {
+                "name": name,
+                "summary": actor.spec.summary.clone(),
+                "actor_type": &actor.spec.actor_type,
+            };
+            if existing.public_key.is_none() {
+                update.insert(
+                    "public_key",
+                    to_bson(&public_key).map_err(|e| Error::DatabaseError(e.to_string()))?,
+                );
+            }
+            db_manager
+                .update_actor(actor_id, update)
+                .await
+                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        }
+        None => {
+            let mut endpoints = HashMap::new();
+            endpoints.insert(
+                "sharedInbox".to_string(),
+                format!("https://{}/sharedInbox", hostname),
+            );
+            let now = Utc::now();
+            let actor_doc = ActorDocument {
+                id: None,
+                actor_id: actor_id.to_string(),
+                name,
+                preferred_username: username.clone(),
+                domain: hostname.to_string(),
+                actor_type: actor.spec.actor_type.clone(),
+                summary: actor.spec.summary.clone(),
+                icon: None,
+                image: None,
+                inbox: format!("{}/inbox", actor_id),
+                outbox: format!("{}/outbox", actor_id),
+                following: format!("{}/following", actor_id),
+                followers: format!("{}/followers", actor_id),
+                liked: Some(format!("{}/liked", actor_id)),
+                featured: Some(format!("{}/featured", actor_id)),
+                public_key: Some(public_key),
+                endpoints: Some(mongodb::bson::to_document(&endpoints).unwrap_or_default()),
+                attachment: None,
+                additional_properties: None,
+                status: DbActorStatus::Active,
+                restriction: ActorRestriction::None,
+                created_at: now,
+                updated_at: now,
+                local: true,
+                followers_count: 0,
+                following_count: 0,
+                statuses_count: 0,
+            };
+            db_manager
+                .insert_actor(actor_doc)
+                .await
+                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            tracing::info!("Created actor {}", actor_id);
+        }
+    }
+    Ok(())
+}
+
+/// Stored algorithm name and key size of a key
+pub(crate) fn algorithm_name(algorithm: &KeyAlgorithm) -> (String, Option<u32>) {
+    match algorithm {
+        KeyAlgorithm::Rsa { key_size } => (format!("rsa-{}", key_size), Some(*key_size)),
+        KeyAlgorithm::Ed25519 => ("ed25519".to_string(), None),
+    }
+}
@@ -0,0 +1,622 @@
+//! Backup custom resource
+//!
+//! A `Backup` dumps the oxifed database with `mongodump` on a cron schedule
+//! and uploads the archive to an S3 bucket. Media lives in the database as
+//! well, so the archive holds everything an instance needs to come back.
+//! The operator runs the schedule as a CronJob whose pods dump into a
+//! shared volume before the AWS CLI uploads the archive.
+//!
+//! Setting `restore` runs a Job that downloads the named archive and loads
+//! it with `mongorestore`. The schedule is suspended while it runs, so a
+//! backup never captures a half-restored database.
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::batch::v1::{CronJob, Job};
+use k8s_openapi::api::core::v1::ResourceRequirements;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::{Patch, PatchParams};
+use kube::runtime::controller::Action;
+use kube::{Api, CustomResource, Resource, ResourceExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::conditions::{self, Condition, ConditionStatus};
+use crate::{Context, Error, Result};
+
+/// Field manager of the applied resources
+const FIELD_MANAGER: &str = "oxifed-operator";
+
+/// Volume the dump and transfer containers share
+const BACKUP_VOLUME: &str = "backup";
+
+/// Dump the database, naming the archive after the time it was taken
+const DUMP_SCRIPT: &str = r#"set -e
+date -u +%Y%m%dT%H%M%SZ > /backup/name
+set -- --uri="$MONGODB_URI" --db="$MONGODB_DBNAME" --archive=/backup/oxifed.archive.gz --gzip
+for collection in $EXCLUDE_COLLECTIONS; do
+  set -- "$@" --excludeCollection="$collection"
+done
+mongodump "$@"
+"#;
+
+const UPLOAD_SCRIPT: &str = r#"set -e
+aws s3 cp /backup/oxifed.archive.gz "s3://$BACKUP_BUCKET/$BACKUP_PREFIX/$(cat /backup/name).archive.gz"
+"#;
+
+const DOWNLOAD_SCRIPT: &str = r#"set -e
+aws s3 cp "s3://$BACKUP_BUCKET/$BACKUP_PREFIX/$BACKUP_ARCHIVE" /backup/oxifed.archive.gz
+"#;
+
+const RESTORE_SCRIPT: &str = r#"set -e
+mongorestore --uri="$MONGODB_URI" --archive=/backup/oxifed.archive.gz --gzip \
+  --nsInclude="$MONGODB_DBNAME.*" ${RESTORE_DROP:+--drop}
+"#;
+
+/// Spec for the Backup CRD
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(group = "oxifed.io", version = "v1alpha1", kind = "Backup", namespaced)]
+#[kube(status = "BackupStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSpec {
+    /// Cron schedule of the backups, such as `0 3 * * *`
+    pub schedule: String,
+    /// Stop taking backups without removing the schedule
+    #[serde(default)]
+    pub suspend: bool,
+    pub mongodb: MongoDbSource,
+    pub s3: S3Target,
+    /// Image with `mongodump` and `mongorestore`
+    #[serde(default = "default_mongodb_image")]
+    pub mongodb_image: String,
+    /// Image with the AWS CLI
+    #[serde(default = "default_aws_cli_image")]
+    pub aws_cli_image: String,
+    pub resources: Option<ResourceRequirements>,
+    /// Load an archive of the bucket into the database
+    pub restore: Option<RestoreSpec>,
+}
+
+fn default_mongodb_image() -> String {
+    "mongo:7".to_string()
+}
+
+fn default_aws_cli_image() -> String {
+    "amazon/aws-cli:latest".to_string()
+}
+
+/// Database to back up
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MongoDbSource {
+    /// Secret key holding the connection URI
+    pub uri_secret_ref: SecretKeyRef,
+    #[serde(default = "default_database")]
+    pub database: String,
+    /// Collections left out of the backups, such as caches
+    #[serde(default)]
+    pub exclude_collections: Vec<String>,
+}
+
+fn default_database() -> String {
+    "domainservd".to_string()
+}
+
+/// Key of a Secret in the namespace of the Backup
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct SecretKeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+/// Bucket the archives are stored in
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Target {
+    pub bucket: String,
+    /// Key prefix of the archives
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    pub region: Option<String>,
+    /// Endpoint of S3 compatible storage other than AWS
+    pub endpoint: Option<String>,
+    /// Secret with `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    pub credentials_secret: String,
+}
+
+fn default_prefix() -> String {
+    "oxifed".to_string()
+}
+
+/// Archive to restore
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreSpec {
+    /// Name of the archive below the prefix, such as
+    /// `20260101T030000Z.archive.gz`
+    pub archive: String,
+    /// Drop each collection before restoring it
+    #[serde(default)]
+    pub drop: bool,
+}
+
+/// Status for the Backup CRD
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupStatus {
+    /// Scheduled, and Restored while a restore is requested
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub last_schedule_time: Option<DateTime<Utc>>,
+    pub last_successful_time: Option<DateTime<Utc>>,
+    pub restore: Option<RestoreStatus>,
+    pub observed_generation: Option<i64>,
+    pub last_reconciled: Option<DateTime<Utc>>,
+}
+
+/// Progress of a restore
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreStatus {
+    pub archive: String,
+    pub job: String,
+    pub phase: RestorePhase,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub enum RestorePhase {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+pub async fn reconcile(backup: Arc<Backup>, ctx: Arc<Context>) -> Result<Action> {
+    let reference = backup.object_ref(&());
+    let result = apply(backup, ctx.clone()).await;
+    if let Err(ref e) = result {
+        crate::publish_failure(&ctx, &reference, e).await;
+    }
+    result
+}
+
+async fn apply(backup: Arc<Backup>, ctx: Arc<Context>) -> Result<Action> {
+    if backup.metadata.deletion_timestamp.is_some() {
+        return Ok(Action::await_change());
+    }
+
+    let ns = backup.namespace().unwrap();
+    let name = backup.name_any();
+    let backups: Api<Backup> = Api::namespaced(ctx.client.clone(), &ns);
+    let cron_jobs: Api<CronJob> = Api::namespaced(ctx.client.clone(), &ns);
+    let jobs: Api<Job> = Api::namespaced(ctx.client.clone(), &ns);
+    let owner_ref = backup.controller_owner_ref(&()).unwrap();
+    let generation = backup.metadata.generation;
+
+    tracing::info!("Reconciling Backup: {}", name);
+
+    let mut conditions = Vec::new();
+
+    // 1. Restore, creating its Job once per archive
+    let restore = match &backup.spec.restore {
+        Some(restore) => {
+            let job_name = restore_job_name(&name, &restore.archive);
+            let job = match jobs.get_opt(&job_name).await.map_err(Error::KubeError)? {
+                Some(job) => job,
+                None => {
+                    tracing::info!("Restoring {} for Backup {}", restore.archive, name);
+                    // Jobs cannot change once created, so an existing one
+                    // is never applied again
+                    jobs.patch(
+                        &job_name,
+                        &PatchParams::apply(FIELD_MANAGER),
+                        &Patch::Apply(restore_job_manifest(
+                            &backup, restore, &job_name, &owner_ref,
+                        )),
+                    )
+                    .await
+                    .map_err(Error::KubeError)?
+                }
+            };
+            let status = job.status.unwrap_or_default();
+            let phase = if status.succeeded.unwrap_or_default() > 0 {
+                RestorePhase::Succeeded
+            } else if job_failed(&status) {
+                RestorePhase::Failed
+            } else {
+                RestorePhase::Running
+            };
+            conditions.push(match phase {
+                RestorePhase::Succeeded => Condition::new(
+                    conditions::RESTORED,
+                    ConditionStatus::True,
+                    "RestoreCompleted",
+                    None,
+                    generation,
+                ),
+                RestorePhase::Failed => Condition::new(
+                    conditions::RESTORED,
+                    ConditionStatus::False,
+                    "RestoreFailed",
+                    Some(format!("Job {} failed", job_name)),
+                    generation,
+                ),
+                RestorePhase::Running => Condition::new(
+                    conditions::RESTORED,
+                    ConditionStatus::Unknown,
+                    "Restoring",
+                    None,
+                    generation,
+                ),
+            });
+            Some(RestoreStatus {
+                archive: restore.archive.clone(),
+                job: job_name,
+                phase,
+            })
+        }
+        None => None,
+    };
+    let restoring = restore
+        .as_ref()
+        .is_some_and(|restore| restore.phase == RestorePhase::Running);
+
+    // 2. Schedule
+    let cron_job = cron_jobs
+        .patch(
+            &name,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(cron_job_manifest(&backup, restoring, &owner_ref)),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    conditions.push(if restoring {
+        Condition::new(
+            conditions::SCHEDULED,
+            ConditionStatus::False,
+            "SuspendedForRestore",
+            None,
+            generation,
+        )
+    } else if backup.spec.suspend {
+        Condition::new(
+            conditions::SCHEDULED,
+            ConditionStatus::False,
+            "Suspended",
+            None,
+            generation,
+        )
+    } else {
+        Condition::new(
+            conditions::SCHEDULED,
+            ConditionStatus::True,
+            "CronJobReady",
+            None,
+            generation,
+        )
+    });
+
+    // 3. Update the status
+    let cron_status = cron_job.status.unwrap_or_default();
+    let previous = backup
+        .status
+        .as_ref()
+        .map(|status| status.conditions.as_slice())
+        .unwrap_or_default();
+    let new_status = BackupStatus {
+        conditions: conditions::merge(previous, conditions),
+        last_schedule_time: cron_status.last_schedule_time.map(|time| time.0),
+        last_successful_time: cron_status.last_successful_time.map(|time| time.0),
+        restore,
+        observed_generation: generation,
+        last_reconciled: Some(Utc::now()),
+    };
+
+    let patch = json!({
+        "status": new_status
+    });
+
+    match backups
+        .patch_status(&name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        // Jobs of the schedule belong to the CronJob, so its status is
+        // only seen when looking again
+        Ok(_) => Ok(Action::requeue(Duration::from_secs(if restoring {
+            30
+        } else {
+            600
+        }))),
+        Err(kube::Error::Api(e)) if e.code == 404 => {
+            tracing::warn!(
+                "Backup {} not found during status patch, it might have been deleted",
+                name
+            );
+            Ok(Action::await_change())
+        }
+        Err(e) => Err(Error::KubeError(e)),
+    }
+}
+
+/// Whether a Job gave up
+fn job_failed(status: &k8s_openapi::api::batch::v1::JobStatus) -> bool {
+    status
+        .conditions
+        .iter()
+        .flatten()
+        .any(|condition| condition.type_ == "Failed" && condition.status == "True")
+}
+
+/// Name of the Job restoring an archive, the same for every reconcile
+fn restore_job_name(backup: &str, archive: &str) -> String {
+    let archive = archive
+        .trim_end_matches(".archive.gz")
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    let name = format!("{}-restore-{}", backup, archive);
+    name.chars()
+        .take(63)
+        .collect::<String>()
+        .trim_end_matches('-')
+        .to_string()
+}
+
+fn labels(backup: &str) -> serde_json::Value {
+    json!({
+        "app.kubernetes.io/name": "oxifed-backup",
+        "app.kubernetes.io/instance": backup,
+        "app.kubernetes.io/managed-by": FIELD_MANAGER,
+    })
+}
+
+/// Environment of the dump and restore containers
+fn mongodb_env(spec: &BackupSpec) -> serde_json::Value {
+    json!([
+        {
+            "name": "MONGODB_URI",
+            "valueFrom": { "secretKeyRef": {
+                "name": spec.mongodb.uri_secret_ref.name,
+                "key": spec.mongodb.uri_secret_ref.key,
+            } }
+        },
+        { "name": "MONGODB_DBNAME", "value": spec.mongodb.database },
+        {
+            "name": "EXCLUDE_COLLECTIONS",
+            "value": spec.mongodb.exclude_collections.join(" "),
+        },
+    ])
+}
+
+/// Environment of the transfer containers
+fn s3_env(spec: &BackupSpec) -> serde_json::Value {
+    let mut env = vec![
+        json!({ "name": "BACKUP_BUCKET", "value": spec.s3.bucket }),
+        json!({ "name": "BACKUP_PREFIX", "value": spec.s3.prefix.trim_matches('/') }),
+    ];
+    if let Some(region) = &spec.s3.region {
+        env.push(json!({ "name": "AWS_DEFAULT_REGION", "value": region }));
+    }
+    if let Some(endpoint) = &spec.s3.endpoint {
+        env.push(json!({ "name": "AWS_ENDPOINT_URL", "value": endpoint }));
+    }
+    json!(env)
+}
+
+/// Container running a shell script against the shared volume
+fn container(
+    spec: &BackupSpec,
+    name: &str,
+    image: &str,
+    script: &str,
+    env: serde_json::Value,
+) -> serde_json::Value {
+    let mut container = json!({
+        "name": name,
+        "image": image,
+        "command": ["/bin/sh", "-c", script],
+        "env": env,
+        "volumeMounts": [{ "name": BACKUP_VOLUME, "mountPath": "/backup" }],
+    });
+    if let Some(resources) = &spec.resources {
+        container["resources"] = json!(resources);
+    }
+    container
+}
+
+/// Container moving the archive between the shared volume and the bucket
+fn transfer_container(
+    spec: &BackupSpec,
+    name: &str,
+    script: &str,
+    env: serde_json::Value,
+) -> serde_json::Value {
+    let mut container = container(spec, name, &spec.aws_cli_image, script, env);
+    container["envFrom"] = json!([{ "secretRef": { "name": spec.s3.credentials_secret } }]);
+    container
+}
+
+/// Pod running `first` as init container and then `second`
+fn pod_spec(first: serde_json::Value, second: serde_json::Value) -> serde_json::Value {
+    json!({
+        "restartPolicy": "OnFailure",
+        "initContainers": [first],
+        "containers": [second],
+        "volumes": [{ "name": BACKUP_VOLUME, "emptyDir": {} }],
+    })
+}
+
+/// CronJob taking the backups
+fn cron_job_manifest(
+    backup: &Backup,
+    restoring: bool,
+    owner_ref: &OwnerReference,
+) -> serde_json::Value {
+    let spec = &backup.spec;
+    let name = backup.name_any();
+    let dump = container(
+        spec,
+        "dump",
+        &spec.mongodb_image,
+        DUMP_SCRIPT,
+        mongodb_env(spec),
+    );
+    let upload = transfer_container(spec, "upload", UPLOAD_SCRIPT, s3_env(spec));
+
+    json!({
+        "apiVersion": "batch/v1",
+        "kind": "CronJob",
+        "metadata": {
+            "name": name,
+            "labels": labels(&name),
+            "ownerReferences": [owner_ref],
+        },
+        "spec": {
+            "schedule": spec.schedule,
+            "suspend": spec.suspend || restoring,
+            "concurrencyPolicy": "Forbid",
+            "jobTemplate": {
+                "metadata": { "labels": labels(&name) },
+                "spec": {
+                    "backoffLimit": 2,
+                    "template": {
+                        "metadata": { "labels": labels(&name) },
+                        "spec": pod_spec(dump, upload),
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Job restoring an archive
+fn restore_job_manifest(
+    backup: &Backup,
+    restore: &RestoreSpec,
+    job_name: &str,
+    owner_ref: &OwnerReference,
+) -> serde_json::Value {
+    let spec = &backup.spec;
+    let name = backup.name_any();
+    let mut download_env = s3_env(spec);
+    download_env
+        .as_array_mut()
+        .unwrap()
+        .push(json!({ "name": "BACKUP_ARCHIVE", "value": restore.archive }));
+    let mut restore_env = mongodb_env(spec);
+    if restore.drop {
+        restore_env
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "name": "RESTORE_DROP", "value": "true" }));
+    }
+    let download = transfer_container(spec, "download", DOWNLOAD_SCRIPT, download_env);
+    let restore = container(
+        spec,
+        "restore",
+        &spec.mongodb_image,
+        RESTORE_SCRIPT,
+        restore_env,
+    );
+
+    json!({
+        "apiVersion": "batch/v1",
+        "kind": "Job",
+        "metadata": {
+            "name": job_name,
+            "labels": labels(&name),
+            "ownerReferences": [owner_ref],
+        },
+        "spec": {
+            "backoffLimit": 2,
+            "template": {
+                "metadata": { "labels": labels(&name) },
+                "spec": pod_spec(download, restore),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(spec: serde_json::Value) -> Backup {
+        let mut backup = Backup::new("nightly", serde_json::from_value(spec).unwrap());
+        backup.metadata.namespace = Some("oxifed".to_string());
+        backup
+    }
+
+    fn owner_ref() -> OwnerReference {
+        OwnerReference {
+            api_version: "oxifed.io/v1alpha1".to_string(),
+            kind: "Backup".to_string(),
+            name: "nightly".to_string(),
+            uid: "uid".to_string(),
+            controller: Some(true),
+            block_owner_deletion: Some(true),
+        }
+    }
+
+    fn env_value<'a>(container: &'a serde_json::Value, name: &str) -> Option<&'a str> {
+        container["env"]
+            .as_array()?
+            .iter()
+            .find(|var| var["name"] == name)?["value"]
+            .as_str()
+    }
+
+    #[test]
+    fn test_restore_job_name() {
+        assert_eq!(
+            restore_job_name("nightly", "20260101T030000Z.archive.gz"),
+            "nightly-restore-20260101t030000z"
+        );
+        let long = restore_job_name("nightly", &"x".repeat(100));
+        assert_eq!(long.len(), 63);
+    }
+
+    #[test]
+    fn test_cron_job_manifest() {
+        let backup = backup(json!({
+            "schedule": "0 3 * * *",
+            "mongodb": {
+                "uriSecretRef": { "name": "oxifed-config", "key": "MONGODB_URI" },
+                "excludeCollections": ["cached_media", "remote_actors"]
+            },
+            "s3": {
+                "bucket": "backups",
+                "prefix": "/oxifed/",
+                "endpoint": "https://s3.example.com",
+                "credentialsSecret": "backup-s3"
+            }
+        }));
+        let manifest = cron_job_manifest(&backup, false, &owner_ref());
+        assert_eq!(manifest["spec"]["schedule"], "0 3 * * *");
+        assert_eq!(manifest["spec"]["suspend"], false);
+
+        let pod = &manifest["spec"]["jobTemplate"]["spec"]["template"]["spec"];
+        let dump = &pod["initContainers"][0];
+        assert_eq!(dump["image"], "mongo:7");
+        assert_eq!(env_value(dump, "MONGODB_DBNAME"), Some("domainservd"));
+        assert_eq!(
+            env_value(dump, "EXCLUDE_COLLECTIONS"),
+            Some("cached_media remote_actors")
+        );
+        assert!(dump["envFrom"].is_null());
+
+        let upload = &pod["containers"][0];
+        assert_eq!(env_value(upload, "BACKUP_PREFIX"), Some("oxifed"));
+        assert_eq!(
+            env_value(upload, "AWS_ENDPOINT_URL"),
+            Some("https://s3.example.com")
+        );
+        assert_eq!(upload["envFrom"][0]["secretRef"]["name"], "backup-s3");
+
+        // No backups while a restore runs
+        let manifest = cron_job_manifest(&backup, true, &owner_ref());
+        assert_eq!(manifest["spec"]["suspend"], true);
+    }
+}
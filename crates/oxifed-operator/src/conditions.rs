@@ -0,0 +1,237 @@
+//! Status conditions
+//!
+//! Conditions follow the Kubernetes convention: a `type`, a `status` of
+//! `True`, `False` or `Unknown`, a CamelCase `reason`, a human readable
+//! `message` and the time the status last changed.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Key pair in its Secret and in MongoDB
+pub const KEYS_READY: &str = "KeysReady";
+/// Domain document up to date in MongoDB
+pub const DATABASE_SYNCED: &str = "DatabaseSynced";
+/// HTTPRoute accepted by the Gateway
+pub const ROUTING_READY: &str = "RoutingReady";
+/// TLS certificate issued by cert-manager
+pub const CERTIFICATE_READY: &str = "CertificateReady";
+/// Every daemon of an OxifedInstance has all its replicas ready
+pub const AVAILABLE: &str = "Available";
+/// CronJob of a Backup applied and not suspended
+pub const SCHEDULED: &str = "Scheduled";
+/// Restore Job of a Backup completed
+pub const RESTORED: &str = "Restored";
+
+/// Status of a condition
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ConditionStatus {
+    True,
+    False,
+    Unknown,
+}
+
+impl ConditionStatus {
+    fn parse(status: &str) -> Self {
+        match status {
+            "True" => Self::True,
+            "False" => Self::False,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Condition of a resource
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: ConditionStatus,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub last_transition_time: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+}
+
+impl Condition {
+    pub fn new(
+        type_: &str,
+        status: ConditionStatus,
+        reason: &str,
+        message: Option<String>,
+        observed_generation: Option<i64>,
+    ) -> Self {
+        Self {
+            type_: type_.to_string(),
+            status,
+            reason: reason.to_string(),
+            message,
+            last_transition_time: Utc::now(),
+            observed_generation,
+        }
+    }
+
+    /// Condition of a step that either succeeded or failed with an error
+    pub fn from_result<T, E: std::fmt::Display>(
+        type_: &str,
+        result: &Result<T, E>,
+        ready_reason: &str,
+        failed_reason: &str,
+        observed_generation: Option<i64>,
+    ) -> Self {
+        match result {
+            Ok(_) => Self::new(
+                type_,
+                ConditionStatus::True,
+                ready_reason,
+                None,
+                observed_generation,
+            ),
+            Err(e) => Self::failed(type_, failed_reason, e, observed_generation),
+        }
+    }
+
+    /// Condition of a step that failed
+    pub fn failed(
+        type_: &str,
+        reason: &str,
+        error: &impl std::fmt::Display,
+        observed_generation: Option<i64>,
+    ) -> Self {
+        Self::new(
+            type_,
+            ConditionStatus::False,
+            reason,
+            Some(error.to_string()),
+            observed_generation,
+        )
+    }
+
+    /// Copy a condition of another controller's resource, such as the
+    /// `Ready` condition of a cert-manager Certificate
+    ///
+    /// `conditions` is the JSON array of that resource's status. Returns
+    /// `None` if it has no condition of type `source_type`.
+    pub fn from_foreign(
+        type_: &str,
+        conditions: &serde_json::Value,
+        source_type: &str,
+        observed_generation: Option<i64>,
+    ) -> Option<Self> {
+        let condition = conditions
+            .as_array()?
+            .iter()
+            .find(|condition| condition["type"] == source_type)?;
+        let field = |name: &str| condition[name].as_str().filter(|value| !value.is_empty());
+        Some(Self::new(
+            type_,
+            ConditionStatus::parse(field("status").unwrap_or_default()),
+            field("reason").unwrap_or(source_type),
+            field("message").map(str::to_string),
+            observed_generation,
+        ))
+    }
+}
+
+/// Conditions with the transition times of unchanged ones kept from
+/// `previous`
+pub fn merge(previous: &[Condition], current: Vec<Condition>) -> Vec<Condition> {
+    current
+        .into_iter()
+        .map(|mut condition| {
+            if let Some(old) = previous
+                .iter()
+                .find(|old| old.type_ == condition.type_ && old.status == condition.status)
+            {
+                condition.last_transition_time = old.last_transition_time;
+            }
+            condition
+        })
+        .collect()
+}
+
+/// Whether every condition is `True`
+pub fn all_true(conditions: &[Condition]) -> bool {
+    conditions
+        .iter()
+        .all(|condition| condition.status == ConditionStatus::True)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_keeps_unchanged_transition_times() {
+        let earlier = Utc::now() - chrono::Duration::hours(1);
+        let mut keys = Condition::new(
+            KEYS_READY,
+            ConditionStatus::True,
+            "KeysAvailable",
+            None,
+            None,
+        );
+        keys.last_transition_time = earlier;
+        let mut routing = Condition::new(
+            ROUTING_READY,
+            ConditionStatus::Unknown,
+            "Pending",
+            None,
+            None,
+        );
+        routing.last_transition_time = earlier;
+
+        let merged = merge(
+            &[keys, routing],
+            vec![
+                Condition::new(
+                    KEYS_READY,
+                    ConditionStatus::True,
+                    "KeysAvailable",
+                    None,
+                    Some(2),
+                ),
+                Condition::new(
+                    ROUTING_READY,
+                    ConditionStatus::True,
+                    "Accepted",
+                    None,
+                    Some(2),
+                ),
+            ],
+        );
+        assert_eq!(merged[0].last_transition_time, earlier);
+        assert_eq!(merged[0].observed_generation, Some(2));
+        assert!(merged[1].last_transition_time > earlier);
+        assert!(all_true(&merged));
+    }
+
+    #[test]
+    fn test_from_foreign() {
+        let conditions = json!([
+            { "type": "Issuing", "status": "False" },
+            {
+                "type": "Ready",
+                "status": "False",
+                "reason": "DoesNotExist",
+                "message": "Issuing certificate as Secret does not exist"
+            }
+        ]);
+        let condition =
+            Condition::from_foreign(CERTIFICATE_READY, &conditions, "Ready", Some(1)).unwrap();
+        assert_eq!(condition.type_, CERTIFICATE_READY);
+        assert_eq!(condition.status, ConditionStatus::False);
+        assert_eq!(condition.reason, "DoesNotExist");
+        assert_eq!(
+            condition.message.as_deref(),
+            Some("Issuing certificate as Secret does not exist")
+        );
+
+        assert!(Condition::from_foreign(CERTIFICATE_READY, &json!([]), "Ready", None).is_none());
+        assert!(Condition::from_foreign(CERTIFICATE_READY, &json!(null), "Ready", None).is_none());
+    }
+}
@@ -0,0 +1,568 @@
+//! OxifedInstance custom resource
+//!
+//! An `OxifedInstance` declares the daemons of an installation: the image
+//! they run, configuration shared by all of them, and the replicas,
+//! resources and autoscaling of each. The operator applies a Deployment
+//! per daemon, a Service for daemons serving HTTP and a
+//! HorizontalPodAutoscaler where autoscaling is set. Resources of daemons
+//! dropped from the spec are removed. They are named after the daemon, so
+//! a namespace holds one instance.
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use k8s_openapi::api::core::v1::{EnvFromSource, EnvVar, ResourceRequirements, Service};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::{Patch, PatchParams};
+use kube::runtime::controller::Action;
+use kube::{Api, CustomResource, Resource, ResourceExt};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::conditions::{self, Condition, ConditionStatus};
+use crate::{Context, Error, Result};
+
+/// Field manager of the applied resources
+const FIELD_MANAGER: &str = "oxifed-operator";
+
+/// Spec for the OxifedInstance CRD
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "oxifed.io",
+    version = "v1alpha1",
+    kind = "OxifedInstance",
+    namespaced
+)]
+#[kube(status = "OxifedInstanceStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct OxifedInstanceSpec {
+    /// Registry path of the daemon images, which are `<image>/<daemon>:<tag>`
+    #[serde(default = "default_image")]
+    pub image: String,
+    #[serde(default = "default_tag")]
+    pub tag: String,
+    pub image_pull_policy: Option<String>,
+    /// Environment of every daemon, such as `MONGODB_URI` and `AMQP_URL`
+    #[serde(default)]
+    pub env: Vec<EnvVar>,
+    /// ConfigMaps and Secrets whose keys become environment variables of
+    /// every daemon
+    #[serde(default)]
+    pub env_from: Vec<EnvFromSource>,
+    /// Daemons to run; those left out are not deployed
+    #[serde(default)]
+    pub daemons: Daemons,
+}
+
+fn default_image() -> String {
+    "ghcr.io/toasterson/oxifed".to_string()
+}
+
+fn default_tag() -> String {
+    "latest".to_string()
+}
+
+/// Settings of the daemons to run
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct Daemons {
+    pub domainservd: Option<DaemonSpec>,
+    pub publisherd: Option<DaemonSpec>,
+    pub pkid: Option<DaemonSpec>,
+    pub moderationd: Option<DaemonSpec>,
+    pub spamfilterd: Option<DaemonSpec>,
+    pub storaged: Option<DaemonSpec>,
+    pub searchd: Option<DaemonSpec>,
+    pub maild: Option<DaemonSpec>,
+}
+
+impl Daemons {
+    /// Settings of a daemon, `None` if it is not to run
+    fn get(&self, name: &str) -> Option<&DaemonSpec> {
+        match name {
+            "domainservd" => self.domainservd.as_ref(),
+            "publisherd" => self.publisherd.as_ref(),
+            "pkid" => self.pkid.as_ref(),
+            "moderationd" => self.moderationd.as_ref(),
+            "spamfilterd" => self.spamfilterd.as_ref(),
+            "storaged" => self.storaged.as_ref(),
+            "searchd" => self.searchd.as_ref(),
+            "maild" => self.maild.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Settings of one daemon
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonSpec {
+    /// Replicas without autoscaling
+    #[serde(default = "default_replicas")]
+    pub replicas: i32,
+    pub resources: Option<ResourceRequirements>,
+    pub autoscaling: Option<AutoscalingSpec>,
+    /// Environment of this daemon, replacing shared variables of the same
+    /// name
+    #[serde(default)]
+    pub env: Vec<EnvVar>,
+}
+
+fn default_replicas() -> i32 {
+    1
+}
+
+/// CPU based autoscaling of a daemon
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoscalingSpec {
+    #[serde(default = "default_replicas")]
+    pub min_replicas: i32,
+    pub max_replicas: i32,
+    /// Average CPU utilization to keep, in percent of the CPU request
+    #[serde(default = "default_target_cpu")]
+    pub target_cpu_utilization_percentage: i32,
+}
+
+fn default_target_cpu() -> i32 {
+    80
+}
+
+/// Status for the OxifedInstance CRD
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OxifedInstanceStatus {
+    /// Available once every daemon has all its replicas ready
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub daemons: Vec<DaemonStatus>,
+    pub observed_generation: Option<i64>,
+    pub last_reconciled: Option<DateTime<Utc>>,
+}
+
+/// Replicas of a deployed daemon
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonStatus {
+    pub name: String,
+    pub replicas: i32,
+    pub ready_replicas: i32,
+    pub autoscaled: bool,
+}
+
+/// A daemon the operator can deploy
+struct Daemon {
+    name: &'static str,
+    /// Container port of its HTTP endpoints and the port of its Service
+    http: Option<(i32, i32)>,
+    /// Whether it answers `/healthz` and `/readyz`
+    probes: bool,
+}
+
+const DAEMONS: [Daemon; 8] = [
+    Daemon {
+        name: "domainservd",
+        http: Some((8080, 80)),
+        probes: true,
+    },
+    Daemon {
+        name: "publisherd",
+        http: None,
+        probes: false,
+    },
+    Daemon {
+        name: "pkid",
+        http: None,
+        probes: false,
+    },
+    Daemon {
+        name: "moderationd",
+        http: None,
+        probes: false,
+    },
+    Daemon {
+        name: "spamfilterd",
+        http: None,
+        probes: false,
+    },
+    Daemon {
+        name: "storaged",
+        http: None,
+        probes: false,
+    },
+    Daemon {
+        name: "searchd",
+        http: Some((8090, 8090)),
+        probes: false,
+    },
+    Daemon {
+        name: "maild",
+        http: None,
+        probes: false,
+    },
+];
+
+pub async fn reconcile(instance: Arc<OxifedInstance>, ctx: Arc<Context>) -> Result<Action> {
+    let reference = instance.object_ref(&());
+    let result = apply(instance, ctx.clone()).await;
+    if let Err(ref e) = result {
+        crate::publish_failure(&ctx, &reference, e).await;
+    }
+    result
+}
+
+async fn apply(instance: Arc<OxifedInstance>, ctx: Arc<Context>) -> Result<Action> {
+    if instance.metadata.deletion_timestamp.is_some() {
+        return Ok(Action::await_change());
+    }
+
+    let ns = instance.namespace().unwrap();
+    let name = instance.name_any();
+    let instances: Api<OxifedInstance> = Api::namespaced(ctx.client.clone(), &ns);
+    let deployments: Api<Deployment> = Api::namespaced(ctx.client.clone(), &ns);
+    let services: Api<Service> = Api::namespaced(ctx.client.clone(), &ns);
+    let autoscalers: Api<HorizontalPodAutoscaler> = Api::namespaced(ctx.client.clone(), &ns);
+    let owner_ref = instance.controller_owner_ref(&()).unwrap();
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+
+    tracing::info!("Reconciling OxifedInstance: {}", name);
+
+    let mut daemons = Vec::new();
+    for daemon in &DAEMONS {
+        let Some(spec) = instance.spec.daemons.get(daemon.name) else {
+            delete_owned(&autoscalers, daemon.name, &name).await?;
+            delete_owned(&services, daemon.name, &name).await?;
+            delete_owned(&deployments, daemon.name, &name).await?;
+            continue;
+        };
+
+        let deployment = deployments
+            .patch(
+                daemon.name,
+                &params,
+                &Patch::Apply(deployment_manifest(&instance, daemon, spec, &owner_ref)),
+            )
+            .await
+            .map_err(Error::KubeError)?;
+
+        match daemon.http {
+            Some((container_port, port)) => {
+                services
+                    .patch(
+                        daemon.name,
+                        &params,
+                        &Patch::Apply(json!({
+                            "apiVersion": "v1",
+                            "kind": "Service",
+                            "metadata": metadata(daemon.name, &name, &owner_ref),
+                            "spec": {
+                                "selector": { "app": daemon.name },
+                                "ports": [{ "port": port, "targetPort": container_port }],
+                            }
+                        })),
+                    )
+                    .await
+                    .map_err(Error::KubeError)?;
+            }
+            None => delete_owned(&services, daemon.name, &name).await?,
+        }
+
+        match &spec.autoscaling {
+            Some(autoscaling) => {
+                autoscalers
+                    .patch(
+                        daemon.name,
+                        &params,
+                        &Patch::Apply(json!({
+                            "apiVersion": "autoscaling/v2",
+                            "kind": "HorizontalPodAutoscaler",
+                            "metadata": metadata(daemon.name, &name, &owner_ref),
+                            "spec": {
+                                "scaleTargetRef": {
+                                    "apiVersion": "apps/v1",
+                                    "kind": "Deployment",
+                                    "name": daemon.name,
+                                },
+                                "minReplicas": autoscaling.min_replicas,
+                                "maxReplicas": autoscaling.max_replicas,
+                                "metrics": [{
+                                    "type": "Resource",
+                                    "resource": {
+                                        "name": "cpu",
+                                        "target": {
+                                            "type": "Utilization",
+                                            "averageUtilization":
+                                                autoscaling.target_cpu_utilization_percentage,
+                                        }
+                                    }
+                                }]
+                            }
+                        })),
+                    )
+                    .await
+                    .map_err(Error::KubeError)?;
+            }
+            None => delete_owned(&autoscalers, daemon.name, &name).await?,
+        }
+
+        let status = deployment.status.unwrap_or_default();
+        daemons.push(DaemonStatus {
+            name: daemon.name.to_string(),
+            replicas: status.replicas.unwrap_or_default(),
+            ready_replicas: status.ready_replicas.unwrap_or_default(),
+            autoscaled: spec.autoscaling.is_some(),
+        });
+    }
+
+    // Replicas are only known once the Deployments have rolled out, which
+    // the watch on them reports
+    let generation = instance.metadata.generation;
+    let not_ready: Vec<&str> = daemons
+        .iter()
+        .filter(|daemon| daemon.replicas == 0 || daemon.ready_replicas < daemon.replicas)
+        .map(|daemon| daemon.name.as_str())
+        .collect();
+    let available = if not_ready.is_empty() {
+        Condition::new(
+            conditions::AVAILABLE,
+            ConditionStatus::True,
+            "AllReplicasReady",
+            None,
+            generation,
+        )
+    } else {
+        Condition::new(
+            conditions::AVAILABLE,
+            ConditionStatus::False,
+            "ReplicasNotReady",
+            Some(format!("Waiting for {}", not_ready.join(", "))),
+            generation,
+        )
+    };
+    let previous = instance
+        .status
+        .as_ref()
+        .map(|status| status.conditions.as_slice())
+        .unwrap_or_default();
+
+    let new_status = OxifedInstanceStatus {
+        conditions: conditions::merge(previous, vec![available]),
+        daemons,
+        observed_generation: generation,
+        last_reconciled: Some(Utc::now()),
+    };
+
+    let patch = json!({
+        "status": new_status
+    });
+
+    match instances
+        .patch_status(&name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        Ok(_) => Ok(Action::requeue(Duration::from_secs(3600))),
+        Err(kube::Error::Api(e)) if e.code == 404 => {
+            tracing::warn!(
+                "OxifedInstance {} not found during status patch, it might have been deleted",
+                name
+            );
+            Ok(Action::await_change())
+        }
+        Err(e) => Err(Error::KubeError(e)),
+    }
+}
+
+/// Metadata of a resource the instance owns
+fn metadata(daemon: &str, instance: &str, owner_ref: &OwnerReference) -> serde_json::Value {
+    json!({
+        "name": daemon,
+        "labels": labels(daemon, instance),
+        "ownerReferences": [owner_ref],
+    })
+}
+
+fn labels(daemon: &str, instance: &str) -> serde_json::Value {
+    json!({
+        "app": daemon,
+        "app.kubernetes.io/name": daemon,
+        "app.kubernetes.io/instance": instance,
+        "app.kubernetes.io/managed-by": FIELD_MANAGER,
+    })
+}
+
+/// Deployment of a daemon
+///
+/// Replicas are left to the HorizontalPodAutoscaler when autoscaling is on.
+fn deployment_manifest(
+    instance: &OxifedInstance,
+    daemon: &Daemon,
+    spec: &DaemonSpec,
+    owner_ref: &OwnerReference,
+) -> serde_json::Value {
+    let name = instance.name_any();
+    let mut container = json!({
+        "name": daemon.name,
+        "image": format!("{}/{}:{}", instance.spec.image, daemon.name, instance.spec.tag),
+        "env": merge_env(&instance.spec.env, &spec.env),
+        "envFrom": instance.spec.env_from,
+    });
+    if let Some(policy) = &instance.spec.image_pull_policy {
+        container["imagePullPolicy"] = json!(policy);
+    }
+    if let Some(resources) = &spec.resources {
+        container["resources"] = json!(resources);
+    }
+    if let Some((container_port, _)) = daemon.http {
+        container["ports"] = json!([{ "containerPort": container_port }]);
+        if daemon.probes {
+            let probe = |path: &str| {
+                json!({
+                    "httpGet": { "path": path, "port": container_port },
+                    "initialDelaySeconds": 5,
+                    "periodSeconds": 10,
+                })
+            };
+            container["livenessProbe"] = probe("/healthz");
+            container["readinessProbe"] = probe("/readyz");
+        }
+    }
+
+    let mut deployment = json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "metadata": metadata(daemon.name, &name, owner_ref),
+        "spec": {
+            "selector": { "matchLabels": { "app": daemon.name } },
+            "template": {
+                "metadata": { "labels": labels(daemon.name, &name) },
+                "spec": { "containers": [container] },
+            }
+        }
+    });
+    if spec.autoscaling.is_none() {
+        deployment["spec"]["replicas"] = json!(spec.replicas);
+    }
+    deployment
+}
+
+/// Shared variables followed by the daemon's, which replace shared ones of
+/// the same name
+fn merge_env(shared: &[EnvVar], daemon: &[EnvVar]) -> Vec<EnvVar> {
+    shared
+        .iter()
+        .filter(|var| !daemon.iter().any(|own| own.name == var.name))
+        .chain(daemon)
+        .cloned()
+        .collect()
+}
+
+/// Delete a resource of a daemon that is no longer deployed
+///
+/// Resources the instance did not create, such as those of the plain
+/// manifests, are left alone.
+async fn delete_owned<K>(api: &Api<K>, name: &str, instance: &str) -> Result<()>
+where
+    K: Resource + Clone + DeserializeOwned + Debug,
+{
+    let Some(existing) = api.get_opt(name).await.map_err(Error::KubeError)? else {
+        return Ok(());
+    };
+    if existing
+        .labels()
+        .get("app.kubernetes.io/instance")
+        .map(String::as_str)
+        != Some(instance)
+    {
+        return Ok(());
+    }
+    match api.delete(name, &Default::default()).await {
+        Ok(_) => {
+            tracing::info!("Deleted {} of instance {}", name, instance);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => Err(Error::KubeError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str, value: &str) -> EnvVar {
+        EnvVar {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            value_from: None,
+        }
+    }
+
+    fn instance(spec: serde_json::Value) -> OxifedInstance {
+        let mut instance = OxifedInstance::new("oxifed", serde_json::from_value(spec).unwrap());
+        instance.metadata.uid = Some("uid".to_string());
+        instance
+    }
+
+    #[test]
+    fn test_merge_env() {
+        let merged = merge_env(
+            &[var("RUST_LOG", "info"), var("AMQP_URL", "amqp://lavinmq")],
+            &[var("RUST_LOG", "debug"), var("PUBLISHER_WORKERS", "8")],
+        );
+        let names: Vec<_> = merged.iter().map(|var| var.name.as_str()).collect();
+        assert_eq!(names, ["AMQP_URL", "RUST_LOG", "PUBLISHER_WORKERS"]);
+        assert_eq!(merged[1].value.as_deref(), Some("debug"));
+    }
+
+    #[test]
+    fn test_deployment_manifest() {
+        let instance = instance(json!({
+            "tag": "v0.3.22",
+            "env": [{ "name": "MONGODB_URI", "value": "mongodb://mongodb:27017" }],
+            "daemons": {
+                "domainservd": { "replicas": 2 },
+                "publisherd": {
+                    "autoscaling": { "maxReplicas": 6 },
+                    "resources": { "requests": { "cpu": "250m" } }
+                }
+            }
+        }));
+        let owner_ref = instance.controller_owner_ref(&()).unwrap();
+
+        let domainservd = deployment_manifest(
+            &instance,
+            &DAEMONS[0],
+            instance.spec.daemons.domainservd.as_ref().unwrap(),
+            &owner_ref,
+        );
+        assert_eq!(domainservd["spec"]["replicas"], 2);
+        let container = &domainservd["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(
+            container["image"],
+            "ghcr.io/toasterson/oxifed/domainservd:v0.3.22"
+        );
+        assert_eq!(container["env"][0]["name"], "MONGODB_URI");
+        assert_eq!(container["ports"][0]["containerPort"], 8080);
+        assert_eq!(container["readinessProbe"]["httpGet"]["path"], "/readyz");
+
+        let publisherd = deployment_manifest(
+            &instance,
+            &DAEMONS[1],
+            instance.spec.daemons.publisherd.as_ref().unwrap(),
+            &owner_ref,
+        );
+        assert!(publisherd["spec"].get("replicas").is_none());
+        let container = &publisherd["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(container["resources"]["requests"]["cpu"], "250m");
+        assert!(container.get("ports").is_none());
+        assert_eq!(
+            publisherd["metadata"]["labels"]["app.kubernetes.io/instance"],
+            "oxifed"
+        );
+    }
+}
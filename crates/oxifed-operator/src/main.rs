@@ -1,21 +1,35 @@
+mod actor;
+mod backup;
+mod conditions;
+mod instance;
+mod rotation;
+
 use chrono::{DateTime, Utc};
+use clap::Parser;
+use conditions::{Condition, ConditionStatus};
 use futures::StreamExt;
 use k8s_openapi::ByteString;
-use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::api::core::v1::{ObjectReference, Secret};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
 use kube::api::{DynamicObject, Patch, PatchParams};
 use kube::runtime::Controller;
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::runtime::finalizer::{Event as Finalizer, finalizer};
 use kube::{Api, Client};
-use kube::{CustomResource, ResourceExt, runtime::controller::Action};
+use kube::{CustomResource, Resource, ResourceExt, runtime::controller::Action};
 use mongodb::bson::doc;
+use oxifed::config::{Config, ConfigError, DatabaseConfig, Env};
 use oxifed::database::{
-    DatabaseManager, DomainDocument, DomainStatus as DbDomainStatus, KeyDocument, KeyStatus,
-    KeyType, RegistrationMode,
+    DatabaseManager, DomainDocument, DomainStats, DomainStatus as DbDomainStatus, KeyDocument,
+    KeyStatus, KeyType, OutboxMessageDocument, RegistrationMode,
 };
-use oxifed::pki::{KeyAlgorithm, KeyPair, TrustLevel};
+use oxifed::messaging::{EXCHANGE_INTERNAL_PUBLISH, Message, ProfileDeleteMessage};
+use oxifed::pki::{KeyAlgorithm, KeyEncryptionConfig, KeyEncryptor, KeyPair, TrustLevel};
+use rotation::{DomainKeyStatus, RotationPolicy};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::time::Duration;
 
@@ -23,17 +37,64 @@ use tokio::time::Duration;
 #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
 #[kube(group = "oxifed.io", version = "v1alpha1", kind = "Domain", namespaced)]
 #[kube(status = "DomainStatus")]
+#[serde(rename_all = "camelCase")]
 pub struct DomainSpec {
     pub hostname: String,
     pub description: Option<String>,
     pub admin_email: Option<String>,
+    /// What happens to the domain's local actors when the Domain is deleted
+    #[serde(default)]
+    pub actor_deletion_policy: ActorDeletionPolicy,
+    /// Scheduled replacement of the domain key
+    pub rotation_policy: Option<RotationPolicy>,
+}
+
+/// Fate of a domain's local actors when the Domain is deleted
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub enum ActorDeletionPolicy {
+    /// Keep the actors; they stay reachable until deleted some other way
+    #[default]
+    Retain,
+    /// Queue the deletion of every actor, which federates a `Delete`
+    Delete,
 }
 
 /// Status for the Domain CRD
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct DomainStatus {
-    pub initialized: bool,
+    /// KeysReady, DatabaseSynced, RoutingReady and CertificateReady
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub observed_generation: Option<i64>,
     pub last_reconciled: Option<DateTime<Utc>>,
+    /// Federation statistics from MongoDB
+    pub federation: Option<FederationStatus>,
+    /// Current domain key
+    pub key: Option<DomainKeyStatus>,
+}
+
+/// Federation statistics of a domain
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FederationStatus {
+    pub actors: u64,
+    pub posts: u64,
+    pub remote_followers: u64,
+    pub remote_following: u64,
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+impl From<DomainStats> for FederationStatus {
+    fn from(stats: DomainStats) -> Self {
+        Self {
+            actors: stats.actors,
+            posts: stats.posts,
+            remote_followers: stats.remote_followers,
+            remote_following: stats.remote_following,
+            last_activity: stats.last_activity,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -44,12 +105,50 @@ pub enum Error {
     DatabaseError(String),
     #[error("PKI Error: {0}")]
     PkiError(String),
+    #[error("Domain not found: {0}")]
+    DomainNotFound(String),
+    #[error("Finalizer Error: {0}")]
+    FinalizerError(#[source] Box<kube::runtime::finalizer::Error<Error>>),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Gateway/TLS configuration loaded from environment
-#[derive(Clone)]
+/// Operator configuration
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OperatorConfig {
+    /// Database to provision domains in; the operator only manages
+    /// Kubernetes resources without it
+    database: Option<DatabaseConfig>,
+    gateway: GatewayConfig,
+    /// Encryption of the domain private keys stored in the database
+    key_encryption: KeyEncryptionConfig,
+}
+
+impl Config for OperatorConfig {
+    fn apply_env(&mut self, env: &Env) -> std::result::Result<(), ConfigError> {
+        DatabaseConfig::apply_env_optional(&mut self.database, env)?;
+        self.gateway.apply_env(env)?;
+        self.key_encryption.apply_env(env)
+    }
+
+    fn validate(&self) -> std::result::Result<(), ConfigError> {
+        if !(1..=65535).contains(&self.gateway.domainservd_service_port) {
+            return Err(ConfigError::invalid(
+                "gateway.domainservd_service_port",
+                "must be a port number between 1 and 65535",
+            ));
+        }
+        if let Some(database) = &self.database {
+            database.validate("database")?;
+        }
+        self.key_encryption.validate("key_encryption")
+    }
+}
+
+/// Gateway/TLS configuration
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 struct GatewayConfig {
     gateway_name: String,
     gateway_namespace: String,
@@ -73,160 +172,113 @@ impl Default for GatewayConfig {
 }
 
 impl GatewayConfig {
-    fn from_env() -> Self {
-        Self {
-            gateway_name: std::env::var("GATEWAY_NAME").unwrap_or_else(|_| "external".to_string()),
-            gateway_namespace: std::env::var("GATEWAY_NAMESPACE")
-                .unwrap_or_else(|_| "envoy-gateway-system".to_string()),
-            cert_issuer_name: std::env::var("CERT_ISSUER_NAME")
-                .unwrap_or_else(|_| "letsencrypt".to_string()),
-            cert_issuer_kind: std::env::var("CERT_ISSUER_KIND")
-                .unwrap_or_else(|_| "ClusterIssuer".to_string()),
-            domainservd_service_name: std::env::var("DOMAINSERVD_SERVICE_NAME")
-                .unwrap_or_else(|_| "domainservd".to_string()),
-            domainservd_service_port: std::env::var("DOMAINSERVD_SERVICE_PORT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(80),
-        }
+    fn apply_env(&mut self, env: &Env) -> std::result::Result<(), ConfigError> {
+        env.set("GATEWAY_NAME", &mut self.gateway_name)?;
+        env.set("GATEWAY_NAMESPACE", &mut self.gateway_namespace)?;
+        env.set("CERT_ISSUER_NAME", &mut self.cert_issuer_name)?;
+        env.set("CERT_ISSUER_KIND", &mut self.cert_issuer_kind)?;
+        env.set(
+            "DOMAINSERVD_SERVICE_NAME",
+            &mut self.domainservd_service_name,
+        )?;
+        env.set(
+            "DOMAINSERVD_SERVICE_PORT",
+            &mut self.domainservd_service_port,
+        )
     }
 }
 
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[command(version, about = "Oxifed Kubernetes operator")]
+struct Args {
+    /// Configuration file (TOML, YAML or JSON); may be repeated, later files
+    /// override earlier ones
+    #[arg(long = "config", value_name = "PATH")]
+    config: Vec<PathBuf>,
+}
+
 struct Context {
     client: Client,
     db_manager: Option<DatabaseManager>,
+    key_encryptor: KeyEncryptor,
     gateway_config: GatewayConfig,
+    recorder: Recorder,
 }
 
-async fn reconcile(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
-    if domain.metadata.deletion_timestamp.is_some() {
-        return Ok(Action::await_change());
+/// Publish a Warning event for a failed reconcile on the resource
+async fn publish_failure(ctx: &Context, reference: &ObjectReference, error: &Error) {
+    let event = Event {
+        type_: EventType::Warning,
+        reason: "ReconcileFailed".to_string(),
+        note: Some(error.to_string()),
+        action: "Reconcile".to_string(),
+        secondary: None,
+    };
+    if let Err(e) = ctx.recorder.publish(&event, reference).await {
+        tracing::warn!("Failed to publish event: {}", e);
     }
+}
+
+/// Finalizer that cleans up after a deleted Domain
+const DOMAIN_FINALIZER: &str = "oxifed.io/domain-cleanup";
 
+async fn reconcile(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
     let ns = domain.namespace().unwrap();
     let domains: Api<Domain> = Api::namespaced(ctx.client.clone(), &ns);
-    let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), &ns);
-
-    tracing::info!("Reconciling Domain: {}", domain.name_any());
-
-    // 1. Generate Ed25519 keys if not present
-    let secret_name = format!("{}-keys", domain.name_any());
+    let reference = domain.object_ref(&());
 
-    match secrets
-        .get_opt(&secret_name)
-        .await
-        .map_err(Error::KubeError)?
-    {
-        Some(secret) => {
-            tracing::debug!("Secret {} already exists", secret_name);
-            // Even if secret exists, ensure key is in MongoDB
-            if let Some(ref db_manager) = ctx.db_manager
-                && let Some(data) = secret.data
-                && let (Some(pub_key), Some(priv_key)) =
-                    (data.get("public_key.pem"), data.get("private_key.pem"))
-            {
-                let pub_key_str = String::from_utf8_lossy(&pub_key.0).to_string();
-                let priv_key_str = String::from_utf8_lossy(&priv_key.0).to_string();
-
-                // Calculate fingerprint from the public key
-                let fingerprint = {
-                    use sha2::{Digest, Sha256};
-                    let mut hasher = Sha256::new();
-                    hasher.update(pub_key_str.as_bytes());
-                    let result = hasher.finalize();
-                    format!("sha256:{}", hex::encode(result))
-                };
-
-                let key_doc = KeyDocument {
-                    id: None,
-                    key_id: secret_name.clone(),
-                    actor_id: format!("https://{}/actor", domain.spec.hostname),
-                    key_type: KeyType::Domain,
-                    algorithm: "Ed25519".to_string(),
-                    key_size: None,
-                    public_key_pem: pub_key_str,
-                    private_key_pem: Some(priv_key_str),
-                    encryption_algorithm: None,
-                    fingerprint,
-                    trust_level: TrustLevel::MasterSigned,
-                    domain_signature: None,
-                    master_signature: None,
-                    usage: vec!["signing".to_string()],
-                    status: KeyStatus::Active,
-                    created_at: Utc::now(),
-                    expires_at: None,
-                    rotation_policy: None,
-                    domain: Some(domain.spec.hostname.clone()),
-                };
-                db_manager
-                    .upsert_key(key_doc)
-                    .await
-                    .map_err(|e| Error::DatabaseError(e.to_string()))?;
-            }
+    let result = finalizer(&domains, DOMAIN_FINALIZER, domain, |event| async {
+        match event {
+            Finalizer::Apply(domain) => apply(domain, ctx.clone()).await,
+            Finalizer::Cleanup(domain) => cleanup(domain, ctx.clone()).await,
         }
-        None => {
-            tracing::info!("Generating keys for Domain: {}", domain.name_any());
-            let key_pair = KeyPair::generate(KeyAlgorithm::Ed25519)
-                .map_err(|e| Error::PkiError(e.to_string()))?;
-
-            let pub_key_pem = key_pair.public_key.pem_data.clone();
-            let priv_key_pem = key_pair.private_key.encrypted_pem.clone();
-            let fingerprint = key_pair.public_key.fingerprint.clone();
+    })
+    .await
+    .map_err(|e| Error::FinalizerError(Box::new(e)));
+    if let Err(ref e) = result {
+        publish_failure(&ctx, &reference, e).await;
+    }
+    result
+}
 
-            let mut data = BTreeMap::new();
-            data.insert(
-                "public_key.pem".to_string(),
-                ByteString(pub_key_pem.as_bytes().to_vec()),
-            );
-            data.insert(
-                "private_key.pem".to_string(),
-                ByteString(priv_key_pem.as_bytes().to_vec()),
-            );
+async fn apply(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
+    let ns = domain.namespace().unwrap();
+    let domains: Api<Domain> = Api::namespaced(ctx.client.clone(), &ns);
+    let generation = domain.metadata.generation;
 
-            let secret = Secret {
-                metadata: ObjectMeta {
-                    name: Some(secret_name.clone()),
-                    namespace: Some(ns.clone()),
-                    ..Default::default()
-                },
-                data: Some(data),
-                ..Default::default()
-            };
+    tracing::info!("Reconciling Domain: {}", domain.name_any());
 
-            secrets
-                .create(&kube::api::PostParams::default(), &secret)
-                .await
-                .map_err(Error::KubeError)?;
+    // Every step runs and reports a condition; the first failure is
+    // returned once the status is written
+    let mut conditions = Vec::new();
+    let mut failures = Vec::new();
 
-            if let Some(ref db_manager) = ctx.db_manager {
-                let key_doc = KeyDocument {
-                    id: None,
-                    key_id: secret_name.clone(),
-                    actor_id: format!("https://{}/actor", domain.spec.hostname),
-                    key_type: KeyType::Domain,
-                    algorithm: "Ed25519".to_string(),
-                    key_size: None,
-                    public_key_pem: pub_key_pem,
-                    private_key_pem: Some(priv_key_pem),
-                    encryption_algorithm: None,
-                    fingerprint,
-                    trust_level: TrustLevel::MasterSigned,
-                    domain_signature: None,
-                    master_signature: None,
-                    usage: vec!["signing".to_string()],
-                    status: KeyStatus::Active,
-                    created_at: Utc::now(),
-                    expires_at: None,
-                    rotation_policy: None,
-                    domain: Some(domain.spec.hostname.clone()),
-                };
-                db_manager
-                    .upsert_key(key_doc)
-                    .await
-                    .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    // 1. Keys, replaced once the rotation policy says they are due
+    let secret_name = format!("{}-keys", domain.name_any());
+    let result = match ensure_keys(&domain, &ctx, &ns, &secret_name).await {
+        Ok(key) => match domain.spec.rotation_policy {
+            Some(ref policy) => {
+                rotation::rotate_if_due(&domain, &ctx, &ns, &secret_name, key, policy).await
             }
+            None => Ok(key),
+        },
+        Err(e) => Err(e),
+    };
+    conditions.push(Condition::from_result(
+        conditions::KEYS_READY,
+        &result,
+        "KeysAvailable",
+        "KeyGenerationFailed",
+        generation,
+    ));
+    let key = match result {
+        Ok(key) => Some(key),
+        Err(e) => {
+            failures.push(e);
+            None
         }
-    }
+    };
 
     // 2. Ensure networking resources (Certificate, ReferenceGrant, HTTPRoute)
     let domain_resource_name = domain.name_any();
@@ -243,7 +295,7 @@ async fn reconcile(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
     };
 
     // 2a. cert-manager Certificate
-    ensure_certificate(
+    let result = ensure_certificate(
         &ctx.client,
         &ns,
         &domain_resource_name,
@@ -251,57 +303,117 @@ async fn reconcile(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
         gw,
         &owner_ref,
     )
-    .await?;
+    .await;
+    conditions.push(match result {
+        Ok(status) => Condition::from_foreign(
+            conditions::CERTIFICATE_READY,
+            &status["conditions"],
+            "Ready",
+            generation,
+        )
+        .unwrap_or_else(|| pending(conditions::CERTIFICATE_READY, generation)),
+        Err(e) => {
+            let condition =
+                Condition::failed(conditions::CERTIFICATE_READY, "ApplyFailed", &e, generation);
+            failures.push(e);
+            condition
+        }
+    });
 
     // 2b. ReferenceGrant (allows Gateway namespace to reference our cert secret)
-    ensure_reference_grant(&ctx.client, &ns, &domain_resource_name, gw, &owner_ref).await?;
-
     // 2c. HTTPRoute
-    ensure_httproute(
-        &ctx.client,
-        &ns,
-        &domain_resource_name,
-        hostname,
-        gw,
-        &owner_ref,
-    )
-    .await?;
+    let result =
+        match ensure_reference_grant(&ctx.client, &ns, &domain_resource_name, gw, &owner_ref).await
+        {
+            Ok(()) => {
+                ensure_httproute(
+                    &ctx.client,
+                    &ns,
+                    &domain_resource_name,
+                    hostname,
+                    gw,
+                    &owner_ref,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+    conditions.push(match result {
+        // A route reports its state per Gateway it is attached to
+        Ok(status) => status["parents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|parent| {
+                Condition::from_foreign(
+                    conditions::ROUTING_READY,
+                    &parent["conditions"],
+                    "Accepted",
+                    generation,
+                )
+            })
+            .max_by_key(|condition| condition.status == ConditionStatus::True)
+            .unwrap_or_else(|| pending(conditions::ROUTING_READY, generation)),
+        Err(e) => {
+            let condition =
+                Condition::failed(conditions::ROUTING_READY, "ApplyFailed", &e, generation);
+            failures.push(e);
+            condition
+        }
+    });
 
-    // 3. Update MongoDB with the domain configuration
+    // 3. Update MongoDB with the domain configuration and read its
+    //    federation statistics
+    let mut federation = None;
     if let Some(ref db_manager) = ctx.db_manager {
-        tracing::info!("Updating MongoDB for Domain: {}", domain.name_any());
-
-        let db_domain = DomainDocument {
-            id: None,
-            domain: domain.spec.hostname.clone(),
-            name: Some(domain.name_any()),
-            description: domain.spec.description.clone(),
-            contact_email: domain.spec.admin_email.clone(),
-            rules: None,
-            registration_mode: RegistrationMode::Closed,
-            authorized_fetch: true,
-            max_note_length: Some(500),
-            max_file_size: Some(10 * 1024 * 1024),
-            allowed_file_types: Some(vec!["image/jpeg".to_string(), "image/png".to_string()]),
-            domain_key_id: Some(secret_name),
-            config: None,
-            status: DbDomainStatus::Active,
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-        };
+        let key_id = key.as_ref().map_or(secret_name.as_str(), |key| &key.key_id);
+        let result = sync_database(&domain, db_manager, key_id).await;
+        conditions.push(Condition::from_result(
+            conditions::DATABASE_SYNCED,
+            &result,
+            "Synced",
+            "SyncFailed",
+            generation,
+        ));
+        failures.extend(result.err());
 
-        db_manager
-            .upsert_domain(db_domain)
-            .await
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        match db_manager.get_domain_stats(hostname).await {
+            Ok(stats) => federation = Some(FederationStatus::from(stats)),
+            Err(e) => tracing::warn!("Failed to read statistics of {}: {}", hostname, e),
+        }
     } else {
         tracing::warn!("MongoDB manager not initialized, skipping database update");
+        conditions.push(Condition::new(
+            conditions::DATABASE_SYNCED,
+            ConditionStatus::Unknown,
+            "DatabaseNotConfigured",
+            Some("The operator runs without a database".to_string()),
+            generation,
+        ));
     }
 
-    // 3. Update the status
+    // 4. Update the status
+    let previous = domain
+        .status
+        .as_ref()
+        .map(|status| status.conditions.as_slice())
+        .unwrap_or_default();
+    let conditions = conditions::merge(previous, conditions);
+    let ready = conditions::all_true(&conditions);
     let new_status = DomainStatus {
-        initialized: true,
+        conditions,
+        observed_generation: generation,
         last_reconciled: Some(Utc::now()),
+        federation,
+        key: key.map(|key| DomainKeyStatus {
+            next_rotation: domain
+                .spec
+                .rotation_policy
+                .as_ref()
+                .map(|policy| key.created_at + policy.interval()),
+            key_id: key.key_id,
+            created_at: key.created_at,
+        }),
     };
 
     let patch = serde_json::json!({
@@ -316,18 +428,356 @@ async fn reconcile(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
         )
         .await
     {
-        Ok(_) => Ok(Action::requeue(Duration::from_secs(3600))),
+        Ok(_) => {}
         Err(kube::Error::Api(e)) if e.code == 404 => {
             tracing::warn!(
                 "Domain {} not found during status patch, it might have been deleted",
                 domain.name_any()
             );
-            Ok(Action::await_change())
+            return Ok(Action::await_change());
+        }
+        Err(e) => return Err(Error::KubeError(e)),
+    }
+
+    if let Some(error) = failures.into_iter().next() {
+        return Err(error);
+    }
+    // Look again soon while cert-manager or the Gateway are still working
+    Ok(Action::requeue(Duration::from_secs(if ready {
+        3600
+    } else {
+        60
+    })))
+}
+
+/// Condition of a resource its controller has not reported on yet
+fn pending(type_: &str, generation: Option<i64>) -> Condition {
+    Condition::new(type_, ConditionStatus::Unknown, "Pending", None, generation)
+}
+
+/// Annotation on a key Secret with the time its key was generated
+const KEY_CREATED_AT_ANNOTATION: &str = "oxifed.io/key-created-at";
+
+/// Signing key of a domain, as kept in its Secret
+struct DomainKey {
+    key_id: String,
+    key_pair: KeyPair,
+    /// Start of the key's rotation interval
+    created_at: DateTime<Utc>,
+}
+
+/// Read the domain's key from its Secret, generating an Ed25519 key if
+/// missing, and store it in MongoDB
+async fn ensure_keys(
+    domain: &Domain,
+    ctx: &Context,
+    ns: &str,
+    secret_name: &str,
+) -> Result<DomainKey> {
+    let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), ns);
+
+    let key = match secrets
+        .get_opt(secret_name)
+        .await
+        .map_err(Error::KubeError)?
+    {
+        Some(secret) => {
+            tracing::debug!("Secret {} already exists", secret_name);
+            read_key_secret(&secret, secret_name)?
+        }
+        None => {
+            tracing::info!("Generating keys for Domain: {}", domain.name_any());
+            let key = DomainKey {
+                // Keys generated before rotation are named after their Secret
+                key_id: secret_name.to_string(),
+                key_pair: KeyPair::generate(KeyAlgorithm::Ed25519)
+                    .map_err(|e| Error::PkiError(e.to_string()))?,
+                created_at: Utc::now(),
+            };
+            secrets
+                .create(
+                    &kube::api::PostParams::default(),
+                    &key_secret(ns, secret_name, &key),
+                )
+                .await
+                .map_err(Error::KubeError)?;
+            key
         }
+    };
+
+    if let Some(ref db_manager) = ctx.db_manager {
+        let mut key_doc = domain_key_document(&domain.spec.hostname, &key);
+        key_doc
+            .encrypt_private_key(&ctx.key_encryptor)
+            .await
+            .map_err(|e| Error::PkiError(e.to_string()))?;
+        db_manager
+            .upsert_key(key_doc)
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    }
+    Ok(key)
+}
+
+/// Load the domain key from its Secret
+///
+/// Secrets written before key rotation lack the key ID and creation time;
+/// their key is named after the Secret and dates from its creation.
+fn read_key_secret(secret: &Secret, secret_name: &str) -> Result<DomainKey> {
+    let data = secret.data.clone().unwrap_or_default();
+    let field = |name: &str| {
+        data.get(name)
+            .map(|value| String::from_utf8_lossy(&value.0).to_string())
+    };
+    let pem = |name: &str| {
+        field(name)
+            .ok_or_else(|| Error::PkiError(format!("Secret {} has no {}", secret_name, name)))
+    };
+    let key_pair = KeyPair::import(&pem("public_key.pem")?, &pem("private_key.pem")?)
+        .map_err(|e| Error::PkiError(e.to_string()))?;
+
+    let created_at = secret
+        .annotations()
+        .get(KEY_CREATED_AT_ANNOTATION)
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|value| value.with_timezone(&Utc))
+        .or_else(|| secret.creation_timestamp().map(|time| time.0))
+        .unwrap_or_else(Utc::now);
+
+    Ok(DomainKey {
+        key_id: field("key_id").unwrap_or_else(|| secret_name.to_string()),
+        key_pair,
+        created_at,
+    })
+}
+
+/// Secret holding a domain key
+fn key_secret(ns: &str, secret_name: &str, key: &DomainKey) -> Secret {
+    let mut data = BTreeMap::new();
+    data.insert(
+        "public_key.pem".to_string(),
+        ByteString(key.key_pair.public_key.pem_data.as_bytes().to_vec()),
+    );
+    data.insert(
+        "private_key.pem".to_string(),
+        ByteString(key.key_pair.private_key.encrypted_pem.as_bytes().to_vec()),
+    );
+    data.insert(
+        "key_id".to_string(),
+        ByteString(key.key_id.as_bytes().to_vec()),
+    );
+
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(secret_name.to_string()),
+            namespace: Some(ns.to_string()),
+            annotations: Some(BTreeMap::from([(
+                KEY_CREATED_AT_ANNOTATION.to_string(),
+                key.created_at.to_rfc3339(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+/// KeyDocument of an active domain key, with its private key in plaintext
+fn domain_key_document(hostname: &str, key: &DomainKey) -> KeyDocument {
+    let (algorithm, key_size) = actor::algorithm_name(&key.key_pair.public_key.algorithm);
+    KeyDocument {
+        id: None,
+        key_id: key.key_id.clone(),
+        actor_id: format!("https://{}/actor", hostname),
+        key_type: KeyType::Domain,
+        algorithm,
+        key_size,
+        public_key_pem: key.key_pair.public_key.pem_data.clone(),
+        private_key_pem: Some(key.key_pair.private_key.encrypted_pem.clone()),
+        encryption_algorithm: None,
+        fingerprint: key.key_pair.public_key.fingerprint.clone(),
+        trust_level: TrustLevel::MasterSigned,
+        domain_signature: None,
+        master_signature: None,
+        usage: vec!["signing".to_string()],
+        status: KeyStatus::Active,
+        created_at: key.created_at,
+        expires_at: None,
+        rotation_policy: None,
+        domain: Some(hostname.to_string()),
+        verification: None,
+    }
+}
+
+/// Update MongoDB with the domain configuration
+async fn sync_database(
+    domain: &Domain,
+    db_manager: &DatabaseManager,
+    domain_key_id: &str,
+) -> Result<()> {
+    tracing::info!("Updating MongoDB for Domain: {}", domain.name_any());
+
+    let db_domain = DomainDocument {
+        id: None,
+        domain: domain.spec.hostname.clone(),
+        name: Some(domain.name_any()),
+        description: domain.spec.description.clone(),
+        contact_email: domain.spec.admin_email.clone(),
+        rules: None,
+        registration_mode: RegistrationMode::Closed,
+        authorized_fetch: true,
+        max_note_length: Some(500),
+        max_file_size: Some(10 * 1024 * 1024),
+        allowed_file_types: Some(vec!["image/jpeg".to_string(), "image/png".to_string()]),
+        domain_key_id: Some(domain_key_id.to_string()),
+        config: None,
+        status: DbDomainStatus::Active,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+
+    db_manager
+        .upsert_domain(db_domain)
+        .await
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    Ok(())
+}
+
+/// Remove what a Domain left behind before the resource goes away
+///
+/// Optionally queues the deletion of the domain's actors, marks the domain
+/// deleted and revokes its keys in MongoDB, then deletes the networking
+/// resources and the key Secret.
+async fn cleanup(domain: Arc<Domain>, ctx: Arc<Context>) -> Result<Action> {
+    let ns = domain.namespace().unwrap();
+    let name = domain.name_any();
+    let hostname = &domain.spec.hostname;
+
+    tracing::info!("Cleaning up Domain: {}", name);
+
+    if let Some(ref db_manager) = ctx.db_manager {
+        if domain.spec.actor_deletion_policy == ActorDeletionPolicy::Delete {
+            let actors = db_manager
+                .find_local_actors_by_domain(hostname)
+                .await
+                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            let messages = actors
+                .iter()
+                .map(|actor| {
+                    let request = ProfileDeleteMessage::new(
+                        format!("{}@{}", actor.preferred_username, hostname),
+                        false,
+                    );
+                    serde_json::to_string(&request.to_message()).map(|payload| {
+                        OutboxMessageDocument::new(
+                            EXCHANGE_INTERNAL_PUBLISH,
+                            "",
+                            Some("application/json"),
+                            payload,
+                        )
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            if !messages.is_empty() {
+                db_manager
+                    .insert_outbox_messages(messages)
+                    .await
+                    .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            }
+            tracing::info!("Queued deletion of {} actors of {}", actors.len(), hostname);
+        }
+
+        db_manager
+            .tombstone_domain(hostname)
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        let revoked = db_manager
+            .revoke_domain_keys(hostname)
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        tracing::info!("Marked {} deleted and revoked {} keys", hostname, revoked);
+    } else {
+        tracing::warn!("MongoDB manager not initialized, skipping database cleanup");
+    }
+
+    delete_dynamic(&ctx.client, &ns, &httproute_resource(), &name).await?;
+    delete_dynamic(
+        &ctx.client,
+        &ns,
+        &reference_grant_resource(),
+        &format!("{}-tls-grant", name),
+    )
+    .await?;
+    delete_dynamic(
+        &ctx.client,
+        &ns,
+        &certificate_resource(),
+        &format!("{}-tls", name),
+    )
+    .await?;
+
+    let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), &ns);
+    match secrets
+        .delete(&format!("{}-keys", name), &Default::default())
+        .await
+    {
+        Ok(_) => {}
+        Err(kube::Error::Api(e)) if e.code == 404 => {}
+        Err(e) => return Err(Error::KubeError(e)),
+    }
+
+    Ok(Action::await_change())
+}
+
+/// Delete a resource the operator created, if it still exists
+async fn delete_dynamic(
+    client: &Client,
+    ns: &str,
+    api_resource: &kube::api::ApiResource,
+    name: &str,
+) -> Result<()> {
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, api_resource);
+    match api.delete(name, &Default::default()).await {
+        Ok(_) => {
+            tracing::info!("Deleted {}: {}", api_resource.kind, name);
+            Ok(())
+        }
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
         Err(e) => Err(Error::KubeError(e)),
     }
 }
 
+fn certificate_resource() -> kube::api::ApiResource {
+    kube::api::ApiResource {
+        group: "cert-manager.io".to_string(),
+        version: "v1".to_string(),
+        api_version: "cert-manager.io/v1".to_string(),
+        kind: "Certificate".to_string(),
+        plural: "certificates".to_string(),
+    }
+}
+
+fn reference_grant_resource() -> kube::api::ApiResource {
+    kube::api::ApiResource {
+        group: "gateway.networking.k8s.io".to_string(),
+        version: "v1beta1".to_string(),
+        api_version: "gateway.networking.k8s.io/v1beta1".to_string(),
+        kind: "ReferenceGrant".to_string(),
+        plural: "referencegrants".to_string(),
+    }
+}
+
+fn httproute_resource() -> kube::api::ApiResource {
+    kube::api::ApiResource {
+        group: "gateway.networking.k8s.io".to_string(),
+        version: "v1".to_string(),
+        api_version: "gateway.networking.k8s.io/v1".to_string(),
+        kind: "HTTPRoute".to_string(),
+        plural: "httproutes".to_string(),
+    }
+}
+
 /// Ensure a cert-manager Certificate exists for the domain
 async fn ensure_certificate(
     client: &Client,
@@ -336,7 +786,7 @@ async fn ensure_certificate(
     hostname: &str,
     gw: &GatewayConfig,
     owner_ref: &OwnerReference,
-) -> Result<()> {
+) -> Result<serde_json::Value> {
     let cert_name = format!("{}-tls", domain_resource_name);
     let cert_json = serde_json::json!({
         "apiVersion": "cert-manager.io/v1",
@@ -357,28 +807,22 @@ async fn ensure_certificate(
         }
     });
 
-    let api_resource = kube::api::ApiResource {
-        group: "cert-manager.io".to_string(),
-        version: "v1".to_string(),
-        api_version: "cert-manager.io/v1".to_string(),
-        kind: "Certificate".to_string(),
-        plural: "certificates".to_string(),
-    };
-    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, &api_resource);
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, &certificate_resource());
 
     let obj: DynamicObject = serde_json::from_value(cert_json)
         .map_err(|e| Error::DatabaseError(format!("Failed to build Certificate JSON: {}", e)))?;
 
-    api.patch(
-        &cert_name,
-        &PatchParams::apply("oxifed-operator").force(),
-        &Patch::Apply(&obj),
-    )
-    .await
-    .map_err(Error::KubeError)?;
+    let applied = api
+        .patch(
+            &cert_name,
+            &PatchParams::apply("oxifed-operator").force(),
+            &Patch::Apply(&obj),
+        )
+        .await
+        .map_err(Error::KubeError)?;
 
     tracing::info!("Ensured Certificate: {}", cert_name);
-    Ok(())
+    Ok(applied.data["status"].clone())
 }
 
 /// Ensure a ReferenceGrant exists allowing the Gateway namespace to reference our cert secret
@@ -413,14 +857,8 @@ async fn ensure_reference_grant(
         }
     });
 
-    let api_resource = kube::api::ApiResource {
-        group: "gateway.networking.k8s.io".to_string(),
-        version: "v1beta1".to_string(),
-        api_version: "gateway.networking.k8s.io/v1beta1".to_string(),
-        kind: "ReferenceGrant".to_string(),
-        plural: "referencegrants".to_string(),
-    };
-    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, &api_resource);
+    let api: Api<DynamicObject> =
+        Api::namespaced_with(client.clone(), ns, &reference_grant_resource());
 
     let obj: DynamicObject = serde_json::from_value(grant_json)
         .map_err(|e| Error::DatabaseError(format!("Failed to build ReferenceGrant JSON: {}", e)))?;
@@ -445,7 +883,7 @@ async fn ensure_httproute(
     hostname: &str,
     gw: &GatewayConfig,
     owner_ref: &OwnerReference,
-) -> Result<()> {
+) -> Result<serde_json::Value> {
     let route_name = domain_resource_name.to_string();
     let route_json = serde_json::json!({
         "apiVersion": "gateway.networking.k8s.io/v1",
@@ -476,87 +914,135 @@ async fn ensure_httproute(
         }
     });
 
-    let api_resource = kube::api::ApiResource {
-        group: "gateway.networking.k8s.io".to_string(),
-        version: "v1".to_string(),
-        api_version: "gateway.networking.k8s.io/v1".to_string(),
-        kind: "HTTPRoute".to_string(),
-        plural: "httproutes".to_string(),
-    };
-    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, &api_resource);
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), ns, &httproute_resource());
 
     let obj: DynamicObject = serde_json::from_value(route_json)
         .map_err(|e| Error::DatabaseError(format!("Failed to build HTTPRoute JSON: {}", e)))?;
 
-    api.patch(
-        &route_name,
-        &PatchParams::apply("oxifed-operator").force(),
-        &Patch::Apply(&obj),
-    )
-    .await
-    .map_err(Error::KubeError)?;
+    let applied = api
+        .patch(
+            &route_name,
+            &PatchParams::apply("oxifed-operator").force(),
+            &Patch::Apply(&obj),
+        )
+        .await
+        .map_err(Error::KubeError)?;
 
     tracing::info!("Ensured HTTPRoute: {}", route_name);
-    Ok(())
+    Ok(applied.data["status"].clone())
 }
 
-fn error_policy(_domain: Arc<Domain>, error: &Error, _ctx: Arc<Context>) -> Action {
+fn error_policy<K>(_resource: Arc<K>, error: &Error, _ctx: Arc<Context>) -> Action {
     tracing::error!("Reconciliation error: {:?}", error);
     Action::requeue(Duration::from_secs(60))
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
     tracing_subscriber::fmt::init();
 
+    let config: OperatorConfig = oxifed::config::load_or_exit(&args.config);
+
     let client = Client::try_default().await.map_err(Error::KubeError)?;
     let domains: Api<Domain> = Api::all(client.clone());
+    let actors: Api<actor::Actor> = Api::all(client.clone());
+    let instances: Api<instance::OxifedInstance> = Api::all(client.clone());
+    let backups: Api<backup::Backup> = Api::all(client.clone());
 
-    let mongodb_uri = std::env::var("MONGODB_URI").ok();
-    let db_manager = if let Some(uri) = mongodb_uri {
+    let db_manager = if let Some(database) = &config.database {
         tracing::info!("Connecting to MongoDB");
-        let client_options = mongodb::options::ClientOptions::parse(&uri)
+        let manager = DatabaseManager::connect(database)
             .await
             .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        let mongo_client = mongodb::Client::with_options(client_options)
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        let db_name = std::env::var("MONGODB_DBNAME").unwrap_or_else(|_| "domainservd".to_string());
-        let database = mongo_client.database(&db_name);
-        let manager = DatabaseManager::new(database);
         manager
             .initialize()
             .await
             .map_err(|e| Error::DatabaseError(e.to_string()))?;
         Some(manager)
     } else {
-        tracing::warn!("MONGODB_URI not set, operator will run without database integration");
+        tracing::warn!("No database configured, operator will run without database integration");
         None
     };
 
-    let gateway_config = GatewayConfig::from_env();
+    let key_encryptor = KeyEncryptor::from_config(&config.key_encryption)
+        .map_err(|e| Error::PkiError(e.to_string()))?;
+
+    let gateway_config = config.gateway;
     tracing::info!(
         "Gateway config: {} in namespace {}",
         gateway_config.gateway_name,
         gateway_config.gateway_namespace
     );
 
+    // Pods are named by their hostname
+    let reporter = Reporter {
+        controller: "oxifed-operator".to_string(),
+        instance: std::env::var("HOSTNAME").ok(),
+    };
+
     let context = Arc::new(Context {
         client: client.clone(),
         db_manager,
+        key_encryptor,
         gateway_config,
+        recorder: Recorder::new(client.clone(), reporter),
     });
 
-    tracing::info!("Starting Domain Operator");
+    tracing::info!("Starting Domain, Actor and OxifedInstance Operator");
 
-    Controller::new(domains, kube::runtime::watcher::Config::default())
-        .run(reconcile, error_policy, context)
+    let domain_controller = Controller::new(domains, kube::runtime::watcher::Config::default())
+        .run(reconcile, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => tracing::info!("Reconciled {:?}", o),
+                Err(e) => tracing::error!("Reconcile failed: {:?}", e),
+            }
+        });
+    let actor_controller = Controller::new(actors, kube::runtime::watcher::Config::default())
+        .run(actor::reconcile, error_policy, context.clone())
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => tracing::info!("Reconciled {:?}", o),
+                Err(e) => tracing::error!("Reconcile failed: {:?}", e),
+            }
+        });
+    // Rollouts of the Deployments update the instance status
+    let instance_controller = Controller::new(instances, kube::runtime::watcher::Config::default())
+        .owns(
+            Api::<k8s_openapi::api::apps::v1::Deployment>::all(client.clone()),
+            kube::runtime::watcher::Config::default(),
+        )
+        .run(instance::reconcile, error_policy, context.clone())
         .for_each(|res| async move {
             match res {
                 Ok(o) => tracing::info!("Reconciled {:?}", o),
                 Err(e) => tracing::error!("Reconcile failed: {:?}", e),
             }
-        })
-        .await;
+        });
+    // Restore Jobs report their progress to their Backup
+    let backup_controller = Controller::new(backups, kube::runtime::watcher::Config::default())
+        .owns(
+            Api::<k8s_openapi::api::batch::v1::CronJob>::all(client.clone()),
+            kube::runtime::watcher::Config::default(),
+        )
+        .owns(
+            Api::<k8s_openapi::api::batch::v1::Job>::all(client.clone()),
+            kube::runtime::watcher::Config::default(),
+        )
+        .run(backup::reconcile, error_policy, context)
+        .for_each(|res| async move {
+            match res {
+                Ok(o) => tracing::info!("Reconciled {:?}", o),
+                Err(e) => tracing::error!("Reconcile failed: {:?}", e),
+            }
+        });
+    futures::join!(
+        domain_controller,
+        actor_controller,
+        instance_controller,
+        backup_controller
+    );
 
     Ok(())
 }
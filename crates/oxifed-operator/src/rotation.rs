@@ -0,0 +1,236 @@
+//! Scheduled domain key rotation
+//!
+//! A Domain with a `rotationPolicy` gets a new key once its key is older
+//! than the policy's interval. The new key becomes the domain's active key
+//! in MongoDB before it replaces the key in the Secret, so the next
+//! reconcile completes a rotation interrupted halfway. Previous keys are
+//! marked rotated and keep verifying for the grace period. Actor keys the
+//! domain signed are signed again with the new key and announced with a
+//! [`KeyChangedMessage`], on which domainservd sends the actor's Update.
+
+use chrono::{DateTime, Duration, Utc};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, ResourceExt};
+use mongodb::bson::doc;
+use oxifed::database::{DatabaseManager, OutboxMessageDocument, domain_signature_document};
+use oxifed::messaging::{EXCHANGE_KEY_EVENTS, KeyChangedMessage, Message};
+use oxifed::pki::{
+    DomainKeyInfo, KEY_ROTATION_OVERLAP_DAYS, KeyAlgorithm, KeyPair, KeyUsage, TrustLevel,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::actor::ManagedKeyAlgorithm;
+use crate::{Context, Domain, DomainKey, Error, Result};
+
+/// Scheduled replacement of a domain key
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationPolicy {
+    /// Days a key is used before it is replaced
+    pub interval_days: u32,
+    /// Algorithm of new keys; that of the current key if unset
+    pub algorithm: Option<ManagedKeyAlgorithm>,
+    /// Days a replaced key keeps verifying
+    #[serde(default = "default_grace_period_days")]
+    pub grace_period_days: u32,
+}
+
+fn default_grace_period_days() -> u32 {
+    KEY_ROTATION_OVERLAP_DAYS as u32
+}
+
+impl RotationPolicy {
+    pub fn interval(&self) -> Duration {
+        Duration::days(self.interval_days.into())
+    }
+
+    fn grace_period(&self) -> Duration {
+        Duration::days(self.grace_period_days.into())
+    }
+}
+
+/// Current key of a domain
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainKeyStatus {
+    pub key_id: String,
+    pub created_at: DateTime<Utc>,
+    /// When the rotation policy replaces the key
+    pub next_rotation: Option<DateTime<Utc>>,
+}
+
+/// Replace the domain key if it is older than the policy's interval
+///
+/// Returns the key in use afterwards.
+pub(crate) async fn rotate_if_due(
+    domain: &Domain,
+    ctx: &Context,
+    ns: &str,
+    secret_name: &str,
+    key: DomainKey,
+    policy: &RotationPolicy,
+) -> Result<DomainKey> {
+    let now = Utc::now();
+    if now < key.created_at + policy.interval() {
+        return Ok(key);
+    }
+    let hostname = &domain.spec.hostname;
+    tracing::info!(
+        "Rotating key {} of Domain {}",
+        key.key_id,
+        domain.name_any()
+    );
+
+    let algorithm = policy
+        .algorithm
+        .map_or(key.key_pair.public_key.algorithm, KeyAlgorithm::from);
+    let new_key = DomainKey {
+        key_id: format!("{}-{}", secret_name, now.timestamp()),
+        key_pair: KeyPair::generate(algorithm).map_err(|e| Error::PkiError(e.to_string()))?,
+        created_at: now,
+    };
+
+    if let Some(ref db_manager) = ctx.db_manager {
+        let mut key_doc = crate::domain_key_document(hostname, &new_key);
+        key_doc
+            .encrypt_private_key(&ctx.key_encryptor)
+            .await
+            .map_err(|e| Error::PkiError(e.to_string()))?;
+        db_manager
+            .upsert_key(key_doc)
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+        let retired = db_manager
+            .retire_domain_keys(hostname, &new_key.key_id, now + policy.grace_period())
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        tracing::info!("Retired {} previous keys of {}", retired, hostname);
+
+        resign_actor_keys(db_manager, hostname, &new_key).await?;
+    }
+
+    let secrets: Api<Secret> = Api::namespaced(ctx.client.clone(), ns);
+    secrets
+        .patch(
+            secret_name,
+            &PatchParams::default(),
+            &Patch::Merge(&crate::key_secret(ns, secret_name, &new_key)),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    tracing::info!("Key of {} rotated to {}", hostname, new_key.key_id);
+    Ok(new_key)
+}
+
+/// Sign the actor keys of the domain with its new key and announce them
+async fn resign_actor_keys(
+    db_manager: &DatabaseManager,
+    hostname: &str,
+    key: &DomainKey,
+) -> Result<()> {
+    let domain_key = DomainKeyInfo {
+        domain: hostname.to_string(),
+        key_id: key.key_id.clone(),
+        public_key: key.key_pair.public_key.clone(),
+        private_key: key.key_pair.private_key.clone(),
+        created_at: key.created_at,
+        expires_at: None,
+        master_signature: None,
+        usage: vec![KeyUsage::DomainSigning],
+    };
+    let user_keys = db_manager
+        .find_keys_signed_by_other_domain_key(hostname, &key.key_id)
+        .await
+        .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+    let mut messages = Vec::new();
+    for user_key in &user_keys {
+        let signature = domain_key
+            .sign_key(&user_key.key_id, &user_key.fingerprint)
+            .map_err(|e| Error::PkiError(e.to_string()))?;
+        db_manager
+            .update_key(
+                &user_key.key_id,
+                doc! {
+                    "domain_signature": domain_signature_document(&signature),
+                    "trust_level": mongodb::bson::to_bson(&TrustLevel::DomainVerified)
+                        .map_err(|e| Error::DatabaseError(e.to_string()))?,
+                },
+            )
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+        let notice = KeyChangedMessage::new(user_key.actor_id.clone(), user_key.key_id.clone());
+        let payload = serde_json::to_string(&notice.to_message())
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        messages.push(OutboxMessageDocument::new(
+            EXCHANGE_KEY_EVENTS,
+            "",
+            Some("application/json"),
+            payload,
+        ));
+    }
+    if !messages.is_empty() {
+        db_manager
+            .insert_outbox_messages(messages)
+            .await
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+    }
+    tracing::info!(
+        "Signed {} actor keys of {} with {}",
+        user_keys.len(),
+        hostname,
+        key.key_id
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_policy_defaults() {
+        let policy: RotationPolicy = serde_json::from_value(json!({ "intervalDays": 90 })).unwrap();
+        assert_eq!(policy.interval(), Duration::days(90));
+        assert_eq!(
+            policy.grace_period(),
+            Duration::days(KEY_ROTATION_OVERLAP_DAYS)
+        );
+        assert!(policy.algorithm.is_none());
+    }
+
+    #[test]
+    fn test_key_secret_round_trip() {
+        let key = DomainKey {
+            key_id: "example-keys-1700000000".to_string(),
+            key_pair: KeyPair::generate(KeyAlgorithm::Ed25519).unwrap(),
+            created_at: DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        };
+        let secret = crate::key_secret("default", "example-keys", &key);
+        let read = crate::read_key_secret(&secret, "example-keys").unwrap();
+        assert_eq!(read.key_id, key.key_id);
+        assert_eq!(read.created_at, key.created_at);
+        assert_eq!(
+            read.key_pair.public_key.fingerprint,
+            key.key_pair.public_key.fingerprint
+        );
+
+        // Secrets from before rotation name the key after themselves
+        let mut legacy = secret;
+        legacy.metadata.annotations = None;
+        legacy.data.as_mut().unwrap().remove("key_id");
+        assert_eq!(
+            crate::read_key_secret(&legacy, "example-keys")
+                .unwrap()
+                .key_id,
+            "example-keys"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Message envelopes passed between pipeline stages
+
+use chrono::{DateTime, Utc};
+use oxifed::messaging::{IncomingActivityMessage, IncomingObjectMessage, ReplayMarker};
+use serde::{Deserialize, Serialize};
+
+/// A message travelling through the pipeline together with its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineEnvelope {
+    /// Unique envelope identifier, stable across stages
+    pub envelope_id: String,
+    /// The incoming object or activity
+    pub payload: PipelinePayload,
+    /// Stages that have processed the message so far
+    #[serde(default)]
+    pub history: Vec<StageRecord>,
+}
+
+/// Content carried by a pipeline envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum PipelinePayload {
+    Object(IncomingObjectMessage),
+    Activity(IncomingActivityMessage),
+}
+
+/// One entry of the processing history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageRecord {
+    pub stage: String,
+    pub status: StageStatus,
+    pub detail: Option<String>,
+    pub processed_at: DateTime<Utc>,
+}
+
+/// Result of a stage as recorded in the history
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Passed,
+    Stopped,
+}
+
+impl PipelineEnvelope {
+    /// Wrap an incoming message in a new envelope
+    pub fn new(payload: PipelinePayload) -> Self {
+        Self {
+            envelope_id: uuid::Uuid::new_v4().to_string(),
+            payload,
+            history: Vec::new(),
+        }
+    }
+
+    /// Parse a delivery body
+    ///
+    /// Accepts envelopes from earlier stages as well as the bare
+    /// `IncomingObjectMessage`/`IncomingActivityMessage` JSON published by
+    /// domainservd, which is wrapped in a new envelope.
+    pub fn from_slice(data: &[u8]) -> Result<Self, serde_json::Error> {
+        if let Ok(envelope) = serde_json::from_slice::<Self>(data) {
+            return Ok(envelope);
+        }
+        if let Ok(object) = serde_json::from_slice::<IncomingObjectMessage>(data) {
+            return Ok(Self::new(PipelinePayload::Object(object)));
+        }
+        serde_json::from_slice::<IncomingActivityMessage>(data)
+            .map(|activity| Self::new(PipelinePayload::Activity(activity)))
+    }
+
+    /// The incoming object, if this envelope carries one
+    pub fn object(&self) -> Option<&IncomingObjectMessage> {
+        match &self.payload {
+            PipelinePayload::Object(object) => Some(object),
+            PipelinePayload::Activity(_) => None,
+        }
+    }
+
+    /// The incoming activity, if this envelope carries one
+    pub fn activity(&self) -> Option<&IncomingActivityMessage> {
+        match &self.payload {
+            PipelinePayload::Activity(activity) => Some(activity),
+            PipelinePayload::Object(_) => None,
+        }
+    }
+
+    /// Marker of a replay, if the message is replayed from storage
+    pub fn replay(&self) -> Option<&ReplayMarker> {
+        match &self.payload {
+            PipelinePayload::Object(object) => object.replay.as_ref(),
+            PipelinePayload::Activity(activity) => activity.replay.as_ref(),
+        }
+    }
+
+    /// Whether stages may notify anyone outside the instance about the
+    /// message, which replays only allow if asked to
+    pub fn may_deliver(&self) -> bool {
+        self.replay().is_none_or(|replay| replay.allow_delivery)
+    }
+
+    /// Append an entry to the processing history
+    pub fn record(&mut self, stage: &str, status: StageStatus, detail: Option<String>) {
+        self.history.push(StageRecord {
+            stage: stage.to_string(),
+            status,
+            detail,
+            processed_at: Utc::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn incoming_object() -> serde_json::Value {
+        json!({
+            "object": { "id": "https://remote.example/notes/1", "type": "Note" },
+            "object_type": "Note",
+            "attributed_to": "https://remote.example/users/bob",
+            "target_domain": "example.com",
+            "target_username": null,
+            "received_at": "2024-01-01T00:00:00Z",
+            "source": null
+        })
+    }
+
+    #[test]
+    fn test_wraps_bare_incoming_messages() {
+        let data = serde_json::to_vec(&incoming_object()).unwrap();
+        let envelope = PipelineEnvelope::from_slice(&data).unwrap();
+        assert_eq!(
+            envelope.object().unwrap().attributed_to,
+            "https://remote.example/users/bob"
+        );
+        assert!(envelope.history.is_empty());
+
+        let activity = json!({
+            "activity": { "type": "Flag" },
+            "activity_type": "Flag",
+            "actor": "https://remote.example/users/bob",
+            "target_domain": "example.com",
+            "target_username": null,
+            "received_at": "2024-01-01T00:00:00Z",
+            "source": null
+        });
+        let data = serde_json::to_vec(&activity).unwrap();
+        let envelope = PipelineEnvelope::from_slice(&data).unwrap();
+        assert_eq!(envelope.activity().unwrap().activity_type, "Flag");
+    }
+
+    #[test]
+    fn test_envelope_roundtrip_keeps_history() {
+        let data = serde_json::to_vec(&incoming_object()).unwrap();
+        let mut envelope = PipelineEnvelope::from_slice(&data).unwrap();
+        envelope.record("spam_filter", StageStatus::Passed, None);
+
+        let data = serde_json::to_vec(&envelope).unwrap();
+        let parsed = PipelineEnvelope::from_slice(&data).unwrap();
+        assert_eq!(parsed.envelope_id, envelope.envelope_id);
+        assert_eq!(parsed.history.len(), 1);
+        assert_eq!(parsed.history[0].status, StageStatus::Passed);
+    }
+
+    #[test]
+    fn test_replays_may_deliver_only_if_allowed() {
+        let data = serde_json::to_vec(&incoming_object()).unwrap();
+        let envelope = PipelineEnvelope::from_slice(&data).unwrap();
+        assert!(envelope.replay().is_none());
+        assert!(envelope.may_deliver());
+
+        for allow_delivery in [false, true] {
+            let mut object = incoming_object();
+            object["replay"] = json!({ "replay_id": "r1", "allow_delivery": allow_delivery });
+            let data = serde_json::to_vec(&object).unwrap();
+            let envelope = PipelineEnvelope::from_slice(&data).unwrap();
+            assert_eq!(envelope.replay().unwrap().replay_id, "r1");
+            assert_eq!(envelope.may_deliver(), allow_delivery);
+        }
+    }
+
+    #[test]
+    fn test_rejects_unknown_messages() {
+        assert!(PipelineEnvelope::from_slice(br#"{"foo": 1}"#).is_err());
+    }
+}
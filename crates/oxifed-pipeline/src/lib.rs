@@ -0,0 +1,68 @@
+//! Incoming processing pipeline framework
+//!
+//! domainservd publishes every received object and activity to the
+//! `oxifed.incoming.process` fanout exchange. Only the first configured stage
+//! is bound to that exchange; every later stage receives messages from the
+//! `oxifed.pipeline` direct exchange, routed by stage name, after the
+//! previous stage let them through. Messages travel between stages wrapped
+//! in a [`PipelineEnvelope`] that records the processing history.
+//!
+//! Stage daemons implement [`Stage`] and hand it to [`run_stage`].
+//!
+//! Messages a stage fails on are published to the `oxifed.dlx` dead-letter
+//! exchange with the failing stage, the error and a [`FailureClass`] in the
+//! message headers. Stage errors are treated as transient unless the stage
+//! returns a [`PermanentError`].
+
+mod envelope;
+mod runner;
+
+pub use envelope::{PipelineEnvelope, PipelinePayload, StageRecord, StageStatus};
+pub use runner::{PipelineConfig, declare_stage, publish_to_stage, run_stage};
+
+use std::future::Future;
+
+pub use oxifed::messaging::FailureClass;
+use thiserror::Error;
+
+/// Pipeline framework errors
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("AMQP error: {0}")]
+    AmqpError(#[from] lapin::Error),
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Stage {0} is not part of the configured pipeline")]
+    UnknownStage(String),
+}
+
+/// Error returned by a stage implementation
+pub type StageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Stage error that retrying the message will not fix
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct PermanentError(pub String);
+
+/// Decision of a stage about a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageOutcome {
+    /// Pass the message on to the next stage
+    Continue,
+    /// Stop processing; the stage has fully handled or rejected the message
+    Stop { reason: String },
+}
+
+/// A single processing stage of the incoming pipeline
+pub trait Stage: Send + Sync + 'static {
+    /// Stage name; the stage consumes the `oxifed.incoming.<name>` queue
+    fn name(&self) -> &'static str;
+
+    /// Process a message and decide whether it continues down the pipeline
+    fn process(
+        &self,
+        envelope: &PipelineEnvelope,
+    ) -> impl Future<Output = Result<StageOutcome, StageError>> + Send;
+}
@@ -0,0 +1,533 @@
+//! Stage topology and consumer loop
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lapin::{
+    BasicProperties, Channel, ExchangeKind,
+    message::Delivery,
+    options::*,
+    types::{AMQPValue, FieldTable},
+};
+use oxifed::messaging::{
+    EXCHANGE_DEAD_LETTER, EXCHANGE_DOMAIN_INCOMING, EXCHANGE_INCOMING_PROCESS, EXCHANGE_PIPELINE,
+    FailureClass, HEADER_FAILURE_CLASS, HEADER_ORIGINAL_QUEUE, HEADER_REJECTED_BY,
+    HEADER_REJECTION_REASON, HEADER_REJECTION_STAGE, QUEUE_DEAD_LETTER,
+};
+use oxifed::shutdown::Shutdown;
+use tracing::{Instrument, debug, error, info, info_span, warn};
+
+use crate::{PermanentError, PipelineEnvelope, PipelineError, Stage, StageOutcome, StageStatus};
+
+/// Stages run when `PIPELINE_STAGES` is not set
+const DEFAULT_STAGES: &[&str] = &["spam_filter", "moderation", "storage"];
+
+/// Order of the pipeline stages and the domains they are dedicated to
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub stages: Vec<String>,
+    /// Domains whose incoming messages this pipeline processes on its own
+    /// queues; empty for the shared pipeline
+    pub domains: Vec<String>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            stages: DEFAULT_STAGES.iter().map(|s| s.to_string()).collect(),
+            domains: Vec::new(),
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Load the stage order from the comma-separated `PIPELINE_STAGES` variable
+    /// and the dedicated domains from `PIPELINE_DOMAINS`
+    ///
+    /// All stage daemons of a pipeline must be started with the same values.
+    /// Dedicated domains only take effect when domainservd runs with
+    /// `DOMAIN_ROUTING`.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(stages) = std::env::var("PIPELINE_STAGES") {
+            config.stages = split_list(&stages);
+        }
+        if let Ok(domains) = std::env::var("PIPELINE_DOMAINS") {
+            config.domains = split_list(&domains);
+        }
+        config
+    }
+
+    /// Queue consumed by a stage
+    pub fn queue_name(&self, stage: &str) -> String {
+        match self.lane() {
+            Some(lane) => format!("oxifed.incoming.{}.{}", lane, stage),
+            None => format!("oxifed.incoming.{}", stage),
+        }
+    }
+
+    /// Routing key of a stage on the pipeline and dead-letter exchanges
+    pub fn stage_key(&self, stage: &str) -> String {
+        match self.lane() {
+            Some(lane) => format!("{}.{}", lane, stage),
+            None => stage.to_string(),
+        }
+    }
+
+    /// Name of the dedicated pipeline, if this is one
+    fn lane(&self) -> Option<String> {
+        (!self.domains.is_empty()).then(|| oxifed::routing::lane_name(&self.domains))
+    }
+
+    /// Whether a stage receives messages directly from domainservd
+    pub fn is_entry(&self, stage: &str) -> bool {
+        self.stages.first().is_some_and(|first| first == stage)
+    }
+
+    /// Stage that follows the given one, if any
+    pub fn next_stage(&self, stage: &str) -> Option<&str> {
+        let position = self.stages.iter().position(|s| s == stage)?;
+        self.stages.get(position + 1).map(|s| s.as_str())
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Declare the exchanges, queue and bindings for a stage
+pub async fn declare_stage(
+    channel: &Channel,
+    config: &PipelineConfig,
+    stage: &str,
+) -> Result<(), PipelineError> {
+    if !config.stages.iter().any(|s| s == stage) {
+        return Err(PipelineError::UnknownStage(stage.to_string()));
+    }
+
+    // Also declares the incoming processing exchange
+    oxifed::routing::declare_exchanges(channel).await?;
+
+    channel
+        .exchange_declare(
+            EXCHANGE_PIPELINE,
+            ExchangeKind::Direct,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    // Arguments must match the pipeline queue declaration in domainservd
+    let mut args = FieldTable::default();
+    args.insert(
+        "x-queue-type".into(),
+        AMQPValue::LongString("quorum".into()),
+    );
+    args.insert("x-message-ttl".into(), AMQPValue::LongLongInt(1800000));
+    args.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(EXCHANGE_DEAD_LETTER.into()),
+    );
+
+    let queue = config.queue_name(stage);
+    let stage_key = config.stage_key(stage);
+    channel
+        .queue_declare(
+            &queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            args,
+        )
+        .await?;
+
+    // Only the entry stage sees every incoming message; later stages must
+    // not keep a binding from an earlier pipeline order. The entry stage of
+    // a dedicated pipeline only sees the messages of its domains.
+    let incoming_keys: Vec<String> = config
+        .domains
+        .iter()
+        .map(|domain| oxifed::routing::incoming_key(domain))
+        .collect();
+    let incoming_bindings = if config.domains.is_empty() {
+        vec![(EXCHANGE_INCOMING_PROCESS, String::new())]
+    } else {
+        incoming_keys
+            .iter()
+            .map(|key| (EXCHANGE_DOMAIN_INCOMING, key.clone()))
+            .collect()
+    };
+    for (exchange, key) in &incoming_bindings {
+        if config.is_entry(stage) {
+            channel
+                .queue_bind(
+                    &queue,
+                    exchange,
+                    key,
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+        } else {
+            channel
+                .queue_unbind(&queue, exchange, key, FieldTable::default())
+                .await?;
+        }
+    }
+
+    channel
+        .queue_bind(
+            &queue,
+            EXCHANGE_PIPELINE,
+            &stage_key,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    // Messages dead-lettered from this stage keep the stage key as routing
+    // key, so the dead-letter queue needs a binding for it; messages the
+    // broker dead-letters from a dedicated entry queue keep their domain key
+    channel
+        .exchange_declare(
+            EXCHANGE_DEAD_LETTER,
+            ExchangeKind::Direct,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_declare(
+            QUEUE_DEAD_LETTER,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    let mut dead_letter_keys = vec![stage_key];
+    if config.is_entry(stage) {
+        dead_letter_keys.extend(incoming_keys);
+    }
+    for key in &dead_letter_keys {
+        channel
+            .queue_bind(
+                QUEUE_DEAD_LETTER,
+                EXCHANGE_DEAD_LETTER,
+                key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Publish an envelope to a stage and wait for the broker confirmation
+pub async fn publish_to_stage(
+    channel: &Channel,
+    config: &PipelineConfig,
+    stage: &str,
+    envelope: &PipelineEnvelope,
+) -> Result<(), PipelineError> {
+    let payload = serde_json::to_vec(envelope)?;
+
+    channel
+        .basic_publish(
+            EXCHANGE_PIPELINE,
+            &config.stage_key(stage),
+            BasicPublishOptions::default(),
+            &payload,
+            oxifed_telemetry::with_trace_context(
+                BasicProperties::default()
+                    .with_delivery_mode(2)
+                    .with_message_id(envelope.envelope_id.clone().into()),
+            ),
+        )
+        .await?
+        .await?;
+
+    Ok(())
+}
+
+/// Publish a failed message to the dead-letter exchange with failure metadata
+///
+/// The original properties and headers are kept so that the message can be
+/// re-injected unchanged.
+async fn dead_letter(
+    channel: &Channel,
+    config: &PipelineConfig,
+    stage: &str,
+    delivery: &Delivery,
+    error: &str,
+    class: FailureClass,
+) -> Result<(), PipelineError> {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        HEADER_REJECTED_BY.into(),
+        AMQPValue::LongString(process_name().into()),
+    );
+    headers.insert(
+        HEADER_REJECTION_REASON.into(),
+        AMQPValue::LongString(error.into()),
+    );
+    headers.insert(
+        HEADER_REJECTION_STAGE.into(),
+        AMQPValue::LongString(stage.into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_QUEUE.into(),
+        AMQPValue::LongString(config.queue_name(stage).into()),
+    );
+    headers.insert(
+        HEADER_FAILURE_CLASS.into(),
+        AMQPValue::LongString(class.as_str().into()),
+    );
+
+    channel
+        .basic_publish(
+            EXCHANGE_DEAD_LETTER,
+            &config.stage_key(stage),
+            BasicPublishOptions::default(),
+            &delivery.data,
+            delivery
+                .properties
+                .clone()
+                .with_delivery_mode(2)
+                .with_headers(headers),
+        )
+        .await?
+        .await?;
+
+    Ok(())
+}
+
+/// Name of the running daemon, used as the rejecting consumer
+fn process_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Declare a stage and consume its queue until the channel closes or the
+/// daemon shuts down
+///
+/// Messages the stage lets through are forwarded to the next configured
+/// stage. Messages that fail to parse or that the stage fails or panics on
+/// are published to the dead-letter exchange; if that fails they are rejected
+/// and the broker dead-letters them without failure metadata. A message
+/// still being processed when the shutdown drain deadline passes is
+/// requeued.
+///
+/// Each delivery is handled in a `pipeline_stage` span that continues the
+/// trace carried in the message headers.
+pub async fn run_stage<S: Stage>(
+    channel: Channel,
+    config: PipelineConfig,
+    stage: Arc<S>,
+    shutdown: Shutdown,
+) -> Result<(), PipelineError> {
+    let name = stage.name();
+    declare_stage(&channel, &config, name).await?;
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    let mut consumer = channel
+        .basic_consume(
+            &config.queue_name(name),
+            &format!("{}_stage", name),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!(
+        "Pipeline stage {} ready (next: {})",
+        name,
+        config.next_stage(name).unwrap_or("none")
+    );
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                error!("Stage {} failed to receive message: {}", name, e);
+                continue;
+            }
+        };
+
+        let span = info_span!("pipeline_stage", stage = name);
+        oxifed_telemetry::set_parent_from_properties(&span, &delivery.properties);
+        let handled = shutdown
+            .unless_abandoned(handle_delivery(
+                &channel,
+                &config,
+                stage.as_ref(),
+                &delivery,
+            ))
+            .instrument(span)
+            .await;
+
+        if handled.is_none() {
+            warn!("Stage {} requeueing unfinished message", name);
+            if let Err(e) = delivery
+                .nack(BasicNackOptions {
+                    requeue: true,
+                    ..Default::default()
+                })
+                .await
+            {
+                error!("Stage {} failed to requeue message: {}", name, e);
+            }
+        }
+    }
+
+    if shutdown.is_triggered() {
+        info!("Pipeline stage {} stopped for shutdown", name);
+    } else {
+        warn!("Pipeline stage {} stopped", name);
+    }
+    Ok(())
+}
+
+/// Run a stage on one delivery and forward, dead-letter or ack it
+async fn handle_delivery<S: Stage>(
+    channel: &Channel,
+    config: &PipelineConfig,
+    stage: &S,
+    delivery: &Delivery,
+) {
+    let name = stage.name();
+    let forwarded = match PipelineEnvelope::from_slice(&delivery.data) {
+        Ok(mut envelope) => match oxifed::poison::isolate(stage.process(&envelope)).await {
+            Ok(Ok(StageOutcome::Continue)) => {
+                envelope.record(name, StageStatus::Passed, None);
+                match config.next_stage(name) {
+                    Some(next) => publish_to_stage(channel, config, next, &envelope)
+                        .await
+                        .map_err(|e| (e.to_string(), FailureClass::Transient)),
+                    None => Ok(()),
+                }
+            }
+            Ok(Ok(StageOutcome::Stop { reason })) => {
+                debug!(
+                    "Stage {} stopped envelope {}: {}",
+                    name, envelope.envelope_id, reason
+                );
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                let class = if e.downcast_ref::<PermanentError>().is_some() {
+                    FailureClass::Permanent
+                } else {
+                    FailureClass::Transient
+                };
+                Err((e.to_string(), class))
+            }
+            Err(panic) => {
+                oxifed::poison::record(&config.queue_name(name), &panic);
+                Err((
+                    format!("handler panicked: {}", panic),
+                    FailureClass::Permanent,
+                ))
+            }
+        },
+        Err(e) => Err((
+            format!("unparseable message: {}", e),
+            FailureClass::Permanent,
+        )),
+    };
+
+    match forwarded {
+        Ok(()) => {
+            if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                error!("Stage {} failed to ack message: {}", name, e);
+            }
+        }
+        Err((reason, class)) => {
+            warn!(
+                "Stage {} failed on message ({}): {}",
+                name,
+                class.as_str(),
+                reason
+            );
+            match dead_letter(channel, config, name, delivery, &reason, class).await {
+                Ok(()) => {
+                    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                        error!("Stage {} failed to ack message: {}", name, e);
+                    }
+                    return;
+                }
+                Err(e) => {
+                    error!("Stage {} failed to dead-letter message: {}", name, e);
+                }
+            }
+
+            if let Err(e) = delivery
+                .nack(BasicNackOptions {
+                    requeue: false,
+                    ..Default::default()
+                })
+                .await
+            {
+                error!("Stage {} failed to nack message: {}", name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_order() {
+        let config = PipelineConfig::default();
+        assert!(config.is_entry("spam_filter"));
+        assert!(!config.is_entry("storage"));
+        assert_eq!(config.next_stage("spam_filter"), Some("moderation"));
+        assert_eq!(config.next_stage("moderation"), Some("storage"));
+        assert_eq!(config.next_stage("storage"), None);
+        assert_eq!(config.next_stage("validation"), None);
+    }
+
+    #[test]
+    fn test_dedicated_queues() {
+        let shared = PipelineConfig::default();
+        assert_eq!(
+            shared.queue_name("moderation"),
+            "oxifed.incoming.moderation"
+        );
+        assert_eq!(shared.stage_key("moderation"), "moderation");
+
+        let dedicated = PipelineConfig {
+            domains: vec!["big.example".to_string(), "huge.example".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            dedicated.queue_name("moderation"),
+            "oxifed.incoming.big.example+huge.example.moderation"
+        );
+        assert_eq!(
+            dedicated.stage_key("moderation"),
+            "big.example+huge.example.moderation"
+        );
+    }
+}
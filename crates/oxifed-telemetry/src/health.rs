@@ -0,0 +1,164 @@
+//! Health RPC responder
+//!
+//! Every daemon binds its own exclusive queue to the `oxifed.health` fanout
+//! exchange and answers each broadcast health request with a report of its
+//! dependencies. adminservd collects the answers to show the health of the
+//! whole deployment.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lapin::{BasicProperties, Channel, ExchangeKind, options::*, types::FieldTable};
+use oxifed::database::DatabaseManager;
+use oxifed::health::{ComponentHealth, HealthReport};
+use oxifed::messaging::{EXCHANGE_HEALTH, HealthRpcResponse, Message, MessageEnum};
+use tracing::{debug, info, warn};
+
+/// Declare the health exchange
+pub async fn declare_health_exchange(channel: &Channel) -> Result<(), lapin::Error> {
+    channel
+        .exchange_declare(
+            EXCHANGE_HEALTH,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+}
+
+/// Answer health requests with the components returned by `check`
+///
+/// Runs until the channel closes. The AMQP connection is implicitly healthy
+/// while requests arrive, so `check` only needs to cover other dependencies.
+/// Poison messages of the process are always reported as well.
+pub async fn serve_health_rpc<F, Fut>(
+    channel: Channel,
+    service: &'static str,
+    check: F,
+) -> Result<(), lapin::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Vec<ComponentHealth>>,
+{
+    declare_health_exchange(&channel).await?;
+
+    let queue = channel
+        .queue_declare(
+            "",
+            QueueDeclareOptions {
+                exclusive: true,
+                auto_delete: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            queue.name().as_str(),
+            EXCHANGE_HEALTH,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    let mut consumer = channel
+        .basic_consume(
+            queue.name().as_str(),
+            &format!("{}_health", service),
+            BasicConsumeOptions {
+                no_ack: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Health responder for {} ready", service);
+
+    while let Some(delivery) = consumer.next().await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                warn!("Failed to receive health request: {}", e);
+                continue;
+            }
+        };
+
+        let request = match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::HealthRpcRequest(request)) => request,
+            Ok(_) => {
+                warn!("Received non-health message on health queue");
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to parse health request: {}", e);
+                continue;
+            }
+        };
+
+        let Some(reply_to) = delivery.properties.reply_to() else {
+            debug!(
+                "Health request {} has no reply_to queue",
+                request.request_id
+            );
+            continue;
+        };
+
+        let mut components = check().await;
+        components.push(oxifed::poison::health());
+        let response = HealthRpcResponse {
+            request_id: request.request_id.clone(),
+            report: HealthReport::new(service, components),
+        };
+
+        let payload = match serde_json::to_vec(&response.to_message()) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize health response: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = channel
+            .basic_publish(
+                "",
+                reply_to.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_correlation_id(request.request_id.into()),
+            )
+            .await
+        {
+            warn!("Failed to send health response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer health requests with the state of MongoDB
+///
+/// For daemons whose only dependency besides AMQP is the database.
+pub async fn serve_database_health(
+    channel: Channel,
+    service: &'static str,
+    db: Option<Arc<DatabaseManager>>,
+) -> Result<(), lapin::Error> {
+    serve_health_rpc(channel, service, move || {
+        let db = db.clone();
+        async move {
+            match db {
+                Some(db) => vec![db.health().await],
+                None => Vec::new(),
+            }
+        }
+    })
+    .await
+}
@@ -0,0 +1,210 @@
+//! Logging, distributed tracing and health reporting for Oxifed daemons
+//!
+//! [`init`] installs the tracing subscriber every daemon logs through. When
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also exported over
+//! OTLP/HTTP so an operator can follow a single activity from inbox receipt
+//! through the pipeline stages to its final delivery.
+//!
+//! Trace context travels between daemons as W3C `traceparent`/`tracestate`
+//! entries in AMQP message headers; see [`with_trace_context`] and
+//! [`set_parent_from_properties`]. Messages that pass through storage first,
+//! such as the message outbox, carry the same entries as a string map from
+//! [`current_context`].
+//!
+//! The [`health`] module answers the health requests adminservd broadcasts
+//! to all daemons.
+
+pub mod health;
+
+use std::collections::HashMap;
+
+use lapin::BasicProperties;
+use lapin::types::{AMQPValue, FieldTable, LongString};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Context, global};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Flushes exported spans when dropped
+///
+/// Keep it alive for the lifetime of the daemon.
+#[must_use = "spans are only flushed when the guard is dropped"]
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Install logging and, if configured, OTLP trace export
+///
+/// Log levels come from `RUST_LOG` (default `info`). The OTLP exporter is
+/// configured with the standard `OTEL_EXPORTER_OTLP_*` variables; the
+/// service name defaults to `service_name` unless `OTEL_SERVICE_NAME` is set.
+pub fn init(service_name: &str) -> TelemetryGuard {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(_) => match build_provider(service_name) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                eprintln!("Failed to set up OTLP trace export: {}", e);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name.to_string()))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    if provider.is_some() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        tracing::info!("Exporting traces via OTLP");
+    }
+
+    TelemetryGuard { provider }
+}
+
+fn build_provider(service_name: &str) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    let mut resource = Resource::builder();
+    if std::env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name(service_name.to_string());
+    }
+
+    Ok(SdkTracerProvider::builder()
+        .with_resource(resource.build())
+        .with_batch_exporter(exporter)
+        .build())
+}
+
+/// Trace context of the current span as propagation entries
+pub fn current_context() -> HashMap<String, String> {
+    let mut entries = HashMap::new();
+    let cx = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut entries));
+    entries
+}
+
+/// Add the current span's trace context to the message headers
+pub fn with_trace_context(properties: BasicProperties) -> BasicProperties {
+    with_context_entries(properties, &current_context())
+}
+
+/// Add stored propagation entries to the message headers
+pub fn with_context_entries(
+    properties: BasicProperties,
+    entries: &HashMap<String, String>,
+) -> BasicProperties {
+    if entries.is_empty() {
+        return properties;
+    }
+
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    let mut injector = HeaderInjector(&mut headers);
+    for (key, value) in entries {
+        injector.set(key, value.clone());
+    }
+    properties.with_headers(headers)
+}
+
+/// Continue the trace of a received message in `span`
+pub fn set_parent_from_properties(span: &Span, properties: &BasicProperties) {
+    if let Some(headers) = properties.headers() {
+        let cx = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        set_parent(span, cx);
+    }
+}
+
+/// Continue a trace from stored propagation entries in `span`
+pub fn set_parent_from_entries(span: &Span, entries: &HashMap<String, String>) {
+    if !entries.is_empty() {
+        let cx = global::get_text_map_propagator(|propagator| propagator.extract(entries));
+        set_parent(span, cx);
+    }
+}
+
+fn set_parent(span: &Span, cx: Context) {
+    // Fails only when no OpenTelemetry layer is installed
+    let _ = span.set_parent(cx);
+}
+
+struct HeaderInjector<'a>(&'a mut FieldTable);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0
+            .insert(key.into(), AMQPValue::LongString(LongString::from(value)));
+    }
+}
+
+struct HeaderExtractor<'a>(&'a FieldTable);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        match self.0.inner().get(key) {
+            Some(AMQPValue::LongString(value)) => std::str::from_utf8(value.as_bytes()).ok(),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.inner().keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_entries_round_trip_through_headers() {
+        let entries = HashMap::from([(
+            "traceparent".to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string(),
+        )]);
+
+        let properties = with_context_entries(BasicProperties::default(), &entries);
+        let headers = properties.headers().as_ref().unwrap();
+        let extractor = HeaderExtractor(headers);
+
+        assert_eq!(extractor.keys(), vec!["traceparent"]);
+        assert_eq!(
+            extractor.get("traceparent"),
+            entries.get("traceparent").map(String::as_str)
+        );
+    }
+
+    #[test]
+    fn test_empty_context_leaves_headers_untouched() {
+        let properties = with_context_entries(BasicProperties::default(), &HashMap::new());
+        assert!(properties.headers().is_none());
+    }
+}
@@ -0,0 +1,400 @@
+//! Load streams
+//!
+//! Every stream sends requests at a fixed rate for the duration of the run,
+//! open loop: a slow target does not slow the generator down, requests that
+//! find all of the stream's slots taken are counted as dropped instead.
+//! Inbox deliveries and follow churn come from the actors of a
+//! [`MockPeer`], which signs them and serves the actors and keys the target
+//! verifies the signatures with. Posts go to the target actor's outbox with
+//! a bearer token.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use oxifed::client::ACTIVITYPUB_CONTENT_TYPE;
+use oxifed::testing::{MockPeer, MockPeerError};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::{Value, json};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+
+use crate::stats::{Recorder, StreamReport};
+
+const ACTIVITYSTREAMS: &str = "https://www.w3.org/ns/activitystreams";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Kind of requests a stream sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Signed `Create` activities posted to the target's inbox
+    Deliveries,
+    /// C2S `Create` activities posted to the target's outbox
+    Posts,
+    /// Signed `Follow` activities and their `Undo`, alternating per sender
+    Follows,
+}
+
+impl Stream {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Deliveries => "deliveries",
+            Stream::Posts => "posts",
+            Stream::Follows => "follows",
+        }
+    }
+}
+
+/// The actor load is generated against
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub actor: String,
+    pub inbox: String,
+    pub outbox: String,
+    /// Bearer token of the actor, needed for posts
+    pub token: Option<String>,
+}
+
+/// Shape of a load run
+#[derive(Debug, Clone)]
+pub struct Plan {
+    /// Requests per second of every stream, streams at zero are not run
+    pub rates: Vec<(Stream, f64)>,
+    pub duration: Duration,
+    /// Requests of a stream in flight at most
+    pub concurrency: usize,
+    /// Mock actors deliveries and follows are spread over
+    pub senders: usize,
+}
+
+/// Sends the streams of a plan against a target
+pub struct Generator {
+    peer: MockPeer,
+    http: reqwest::Client,
+    target: Target,
+    plan: Plan,
+    /// Follow IDs of the senders currently following the target
+    follows: Mutex<HashMap<usize, String>>,
+}
+
+impl Generator {
+    pub fn new(peer: MockPeer, http: reqwest::Client, target: Target, plan: Plan) -> Self {
+        Self {
+            peer,
+            http,
+            target,
+            plan,
+            follows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run all streams of the plan concurrently
+    pub async fn run(self: Arc<Self>) -> Vec<StreamReport> {
+        let mut streams = JoinSet::new();
+        for (index, (stream, rate)) in self.plan.rates.iter().copied().enumerate() {
+            if rate > 0.0 {
+                let generator = self.clone();
+                streams.spawn(async move { (index, generator.drive(stream, rate).await) });
+            }
+        }
+        let mut reports: Vec<_> = streams.join_all().await;
+        reports.sort_by_key(|(index, _)| *index);
+        let reports: Vec<_> = reports.into_iter().map(|(_, report)| report).collect();
+
+        // Leave the target as it was found
+        self.unfollow_all().await;
+        reports
+    }
+
+    /// Send `stream` at `rate` requests per second until the run is over
+    async fn drive(self: Arc<Self>, stream: Stream, rate: f64) -> StreamReport {
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
+        let slots = Arc::new(Semaphore::new(self.plan.concurrency.max(1)));
+        let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        let start = Instant::now();
+        let mut requests = JoinSet::new();
+        let mut sequence = 0u64;
+        loop {
+            ticks.tick().await;
+            if start.elapsed() >= self.plan.duration {
+                break;
+            }
+            let Ok(slot) = slots.clone().try_acquire_owned() else {
+                recorder.lock().expect("recorder poisoned").drop_request();
+                continue;
+            };
+            let generator = self.clone();
+            let recorder = recorder.clone();
+            requests.spawn(async move {
+                let sent = Instant::now();
+                let result = generator.request(stream, sequence).await;
+                let latency = sent.elapsed();
+                let mut recorder = recorder.lock().expect("recorder poisoned");
+                match result {
+                    Ok(()) => recorder.success(latency),
+                    Err(e) => recorder.failure(latency, e),
+                }
+                drop(slot);
+            });
+            sequence += 1;
+        }
+        requests.join_all().await;
+
+        let elapsed = start.elapsed();
+        recorder
+            .lock()
+            .expect("recorder poisoned")
+            .report(stream.name(), elapsed)
+    }
+
+    /// Send request number `sequence` of `stream`
+    ///
+    /// Errors are reported as the status or a short error class, so they
+    /// group in the report.
+    async fn request(&self, stream: Stream, sequence: u64) -> Result<(), String> {
+        let sender = (sequence % self.plan.senders.max(1) as u64) as usize;
+        match stream {
+            Stream::Deliveries => {
+                let activity = note_delivery(&self.peer.actor_id(&username(sender)), &self.target);
+                self.deliver(sender, &activity).await
+            }
+            Stream::Follows => {
+                let activity = self.follow_churn(sender);
+                self.deliver(sender, &activity).await
+            }
+            Stream::Posts => self.post(sequence).await,
+        }
+    }
+
+    async fn deliver(&self, sender: usize, activity: &Value) -> Result<(), String> {
+        let status = self
+            .peer
+            .send(&username(sender), &self.target.inbox, activity)
+            .await
+            .map_err(|e| match e {
+                MockPeerError::Http(e) => error_class(e),
+                e => e.to_string(),
+            })?;
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(status.to_string())
+        }
+    }
+
+    async fn post(&self, sequence: u64) -> Result<(), String> {
+        let mut request = self
+            .http
+            .post(&self.target.outbox)
+            .header(CONTENT_TYPE, ACTIVITYPUB_CONTENT_TYPE)
+            .body(
+                json!({
+                    "@context": ACTIVITYSTREAMS,
+                    "type": "Create",
+                    "to": [PUBLIC],
+                    "object": {
+                        "type": "Note",
+                        "to": [PUBLIC],
+                        "content": format!("oxiload post {}", sequence)
+                    }
+                })
+                .to_string(),
+            );
+        if let Some(token) = &self.target.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = request.send().await.map_err(error_class)?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(response.status().to_string())
+        }
+    }
+
+    /// Next activity of `sender`'s follow churn: a `Follow` if it does not
+    /// follow the target, an `Undo` of its follow if it does
+    fn follow_churn(&self, sender: usize) -> Value {
+        let actor = self.peer.actor_id(&username(sender));
+        let mut follows = self.follows.lock().expect("follows poisoned");
+        match follows.remove(&sender) {
+            Some(follow) => undo_follow(&actor, &follow, &self.target.actor),
+            None => {
+                let activity = follow(&actor, &self.target.actor);
+                follows.insert(sender, activity["id"].as_str().unwrap_or("").to_string());
+                activity
+            }
+        }
+    }
+
+    /// Undo the follows the churn left behind
+    async fn unfollow_all(&self) {
+        let follows: Vec<_> = self
+            .follows
+            .lock()
+            .expect("follows poisoned")
+            .drain()
+            .collect();
+        for (sender, follow) in follows {
+            let actor = self.peer.actor_id(&username(sender));
+            let undo = undo_follow(&actor, &follow, &self.target.actor);
+            let _ = self.deliver(sender, &undo).await;
+        }
+    }
+}
+
+/// Username of mock sender `index`
+fn username(index: usize) -> String {
+    format!("load{}", index)
+}
+
+/// Public note of `actor` mentioning the target, as delivered to its inbox
+fn note_delivery(actor: &str, target: &Target) -> Value {
+    let id = uuid::Uuid::new_v4();
+    json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": format!("{}/activities/{}", actor, id),
+        "type": "Create",
+        "actor": actor,
+        "to": [PUBLIC],
+        "cc": [target.actor],
+        "object": {
+            "id": format!("{}/notes/{}", actor, id),
+            "type": "Note",
+            "attributedTo": actor,
+            "to": [PUBLIC],
+            "cc": [target.actor],
+            "content": "<p>oxiload delivery</p>",
+            "published": Utc::now().to_rfc3339()
+        }
+    })
+}
+
+fn follow(actor: &str, object: &str) -> Value {
+    json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": format!("{}/follows/{}", actor, uuid::Uuid::new_v4()),
+        "type": "Follow",
+        "actor": actor,
+        "object": object
+    })
+}
+
+fn undo_follow(actor: &str, follow: &str, object: &str) -> Value {
+    json!({
+        "@context": ACTIVITYSTREAMS,
+        "id": format!("{}#undo", follow),
+        "type": "Undo",
+        "actor": actor,
+        "object": {
+            "id": follow,
+            "type": "Follow",
+            "actor": actor,
+            "object": object
+        }
+    })
+}
+
+/// Short class of a transport error
+fn error_class(e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        "timeout".to_string()
+    } else if e.is_connect() {
+        "connection failed".to_string()
+    } else {
+        "request failed".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxifed::testing::{MockPeerConfig, StatusCode};
+
+    #[tokio::test]
+    async fn test_follow_churn_alternates() {
+        let target = Target {
+            actor: "https://example.com/users/alice".into(),
+            inbox: "https://example.com/users/alice/inbox".into(),
+            outbox: "https://example.com/users/alice/outbox".into(),
+            token: None,
+        };
+        let generator = Generator::new(
+            MockPeer::start().await.unwrap(),
+            reqwest::Client::new(),
+            target,
+            Plan {
+                rates: Vec::new(),
+                duration: Duration::ZERO,
+                concurrency: 1,
+                senders: 2,
+            },
+        );
+
+        let first = generator.follow_churn(0);
+        assert_eq!(first["type"], "Follow");
+        assert_eq!(generator.follow_churn(1)["type"], "Follow");
+        let undo = generator.follow_churn(0);
+        assert_eq!(undo["type"], "Undo");
+        assert_eq!(undo["object"]["id"], first["id"]);
+        assert_eq!(generator.follow_churn(0)["type"], "Follow");
+    }
+
+    #[tokio::test]
+    async fn test_run_against_mock_peer() {
+        let target_peer = MockPeer::start_with(MockPeerConfig {
+            require_signatures: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        target_peer.fail_path("/users/alice/outbox", StatusCode::UNAUTHORIZED);
+        let target = Target {
+            actor: target_peer.actor_id("alice"),
+            inbox: target_peer.inbox("alice"),
+            outbox: format!("{}/outbox", target_peer.actor_id("alice")),
+            token: None,
+        };
+
+        let generator = Arc::new(Generator::new(
+            MockPeer::start().await.unwrap(),
+            reqwest::Client::new(),
+            target,
+            Plan {
+                rates: vec![
+                    (Stream::Deliveries, 20.0),
+                    (Stream::Posts, 10.0),
+                    (Stream::Follows, 10.0),
+                ],
+                duration: Duration::from_millis(500),
+                concurrency: 4,
+                senders: 3,
+            },
+        ));
+        let reports = generator.run().await;
+
+        let names: Vec<_> = reports.iter().map(|r| r.stream).collect();
+        assert_eq!(names, vec!["deliveries", "posts", "follows"]);
+        assert!(reports[0].sent > 0);
+        assert_eq!(reports[0].failed, 0);
+        assert_eq!(reports[1].failed, reports[1].sent);
+        assert!(reports[1].errors.contains_key("401 Unauthorized"));
+        assert_eq!(reports[2].failed, 0);
+
+        // Every follow left over at the end of the run was undone
+        let deliveries = target_peer.deliveries();
+        let follows = deliveries
+            .iter()
+            .filter(|d| d.activity_type() == Some("Follow"))
+            .count();
+        let undos = deliveries
+            .iter()
+            .filter(|d| d.activity_type() == Some("Undo"))
+            .count();
+        assert_eq!(follows, undos);
+        assert!(deliveries.iter().all(|d| d.signer.is_some()));
+    }
+}
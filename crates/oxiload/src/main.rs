@@ -0,0 +1,268 @@
+//! Synthetic load generator
+//!
+//! Sends configurable streams of signed inbox deliveries, C2S posts and
+//! follow churn against an actor of a running instance and reports the
+//! latency percentiles and errors of every stream, so regressions in the
+//! incoming pipeline and publisherd show up before a release. The senders
+//! of deliveries and follows are served by a built-in mock instance the
+//! target has to reach to verify their signatures; against a remote target
+//! give it a domain routed to `--listen`.
+
+mod load;
+mod stats;
+
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use load::{Generator, Plan, Stream, Target};
+use oxifed::client::ACTIVITYPUB_CONTENT_TYPE;
+use oxifed::egress::EgressPolicy;
+use oxifed::testing::{MockPeer, MockPeerConfig};
+use oxifed::webfinger::WebFingerClient;
+use reqwest::header::ACCEPT;
+use serde_json::Value;
+use stats::StreamReport;
+
+/// A stream exceeded the error rate or latency it was allowed
+const EXIT_THRESHOLD: u8 = 1;
+/// The run could not start, e.g. the actor could not be resolved
+const EXIT_ERROR: u8 = 2;
+
+/// Format of the report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Table,
+    Json,
+}
+
+/// Generate synthetic load against an Oxifed instance
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Actor to load, as URL or user@domain
+    actor: String,
+
+    /// Signed inbox deliveries per second
+    #[arg(long, default_value_t = 10.0)]
+    deliveries: f64,
+
+    /// C2S posts per second, needs --token
+    #[arg(long, default_value_t = 0.0)]
+    posts: f64,
+
+    /// Follows and unfollows per second
+    #[arg(long, default_value_t = 0.0)]
+    follows: f64,
+
+    /// Seconds to generate load for
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+
+    /// Requests of a stream in flight at most
+    #[arg(long, default_value_t = 64)]
+    concurrency: usize,
+
+    /// Mock actors deliveries and follows are spread over
+    #[arg(long, default_value_t = 10)]
+    senders: usize,
+
+    /// Bearer token of the actor for C2S posts
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Domain the mock senders are served under, over https
+    ///
+    /// Needed if the target refuses to fetch keys from local addresses,
+    /// which production instances do.
+    #[arg(long)]
+    peer_domain: Option<String>,
+
+    /// Address the mock senders are served on
+    #[arg(long)]
+    listen: Option<SocketAddr>,
+
+    /// Seconds a request may take
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Fail if more than this percentage of a stream's requests fail
+    #[arg(long)]
+    max_error_rate: Option<f64>,
+
+    /// Fail if a stream's 99th latency percentile exceeds this many
+    /// milliseconds
+    #[arg(long)]
+    max_p99_ms: Option<f64>,
+
+    /// Format of the report
+    #[arg(long, short, value_enum, default_value_t)]
+    output: OutputFormat,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Instances under load commonly run on the local network
+    EgressPolicy::allowing_private().install();
+
+    if args.posts > 0.0 && args.token.is_none() {
+        eprintln!("Error: --posts needs the actor's --token");
+        return ExitCode::from(EXIT_ERROR);
+    }
+
+    let http = match reqwest::Client::builder()
+        .user_agent(concat!("oxiload/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(args.timeout))
+        .build()
+    {
+        Ok(http) => http,
+        Err(e) => {
+            eprintln!("Error: failed to create HTTP client: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let target = match resolve(&http, &args.actor, args.token.clone()).await {
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let peer = match MockPeer::start_with(MockPeerConfig {
+        domain: args.peer_domain.clone(),
+        client: Some(http.clone()),
+        listen: args.listen,
+        ..Default::default()
+    })
+    .await
+    {
+        Ok(peer) => peer,
+        Err(e) => {
+            eprintln!("Error: failed to serve the mock senders: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    eprintln!(
+        "Loading {} for {}s, senders at {}",
+        target.actor,
+        args.duration,
+        peer.base_url()
+    );
+
+    let plan = Plan {
+        rates: vec![
+            (Stream::Deliveries, args.deliveries),
+            (Stream::Posts, args.posts),
+            (Stream::Follows, args.follows),
+        ],
+        duration: Duration::from_secs(args.duration),
+        concurrency: args.concurrency,
+        senders: args.senders,
+    };
+    let reports = Arc::new(Generator::new(peer, http, target, plan))
+        .run()
+        .await;
+
+    match args.output {
+        OutputFormat::Table => print_table(&reports),
+        OutputFormat::Json => match serde_json::to_string_pretty(&reports) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        },
+    }
+
+    let exceeded = reports.iter().any(|report| {
+        let error_rate = if report.sent == 0 {
+            0.0
+        } else {
+            report.failed as f64 * 100.0 / report.sent as f64
+        };
+        args.max_error_rate.is_some_and(|max| error_rate > max)
+            || args
+                .max_p99_ms
+                .zip(report.latency.p99_ms)
+                .is_some_and(|(max, p99)| p99 > max)
+    });
+    if exceeded {
+        ExitCode::from(EXIT_THRESHOLD)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Inbox and outbox of the actor at a URL or `user@domain`
+async fn resolve(
+    http: &reqwest::Client,
+    actor: &str,
+    token: Option<String>,
+) -> Result<Target, String> {
+    let url = if actor.contains("://") {
+        actor.to_string()
+    } else {
+        let account = format!("acct:{}", actor.trim_start_matches('@'));
+        let jrd = WebFingerClient::new()
+            .finger(&account, None)
+            .await
+            .map_err(|e| format!("WebFinger lookup of {} failed: {}", actor, e))?;
+        jrd.find_link("self")
+            .and_then(|link| link.href.clone())
+            .ok_or_else(|| format!("WebFinger answer for {} has no self link", actor))?
+    };
+
+    let document: Value = http
+        .get(&url)
+        .header(ACCEPT, ACTIVITYPUB_CONTENT_TYPE)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("failed to fetch actor {}: {}", url, e))?
+        .json()
+        .await
+        .map_err(|e| format!("actor {} is not JSON: {}", url, e))?;
+    let property = |name: &str| {
+        document[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("actor {} has no {}", url, name))
+    };
+    Ok(Target {
+        actor: property("id")?,
+        inbox: property("inbox")?,
+        outbox: property("outbox")?,
+        token,
+    })
+}
+
+fn print_table(reports: &[StreamReport]) {
+    println!(
+        "{:<11} {:>7} {:>7} {:>7} {:>7} {:>8} {:>8} {:>8} {:>8}",
+        "stream", "sent", "failed", "dropped", "req/s", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    for report in reports {
+        let ms = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.1}", v));
+        println!(
+            "{:<11} {:>7} {:>7} {:>7} {:>7.1} {:>8} {:>8} {:>8} {:>8}",
+            report.stream,
+            report.sent,
+            report.failed,
+            report.dropped,
+            report.throughput,
+            ms(report.latency.p50_ms),
+            ms(report.latency.p90_ms),
+            ms(report.latency.p99_ms),
+            ms(report.latency.max_ms)
+        );
+        for (error, count) in &report.errors {
+            println!("  {:>7} x {}", count, error);
+        }
+    }
+}
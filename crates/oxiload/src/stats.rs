@@ -0,0 +1,139 @@
+//! Latency and error statistics of a load run
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Results of the requests of one stream
+#[derive(Debug, Default)]
+pub struct Recorder {
+    latencies: Vec<Duration>,
+    failed: u64,
+    /// Ticks without a free request slot, the target fell behind the rate
+    dropped: u64,
+    errors: BTreeMap<String, u64>,
+}
+
+impl Recorder {
+    /// Record a request answered with success after `latency`
+    pub fn success(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    /// Record a request that failed after `latency` with `error`
+    ///
+    /// Failed requests count towards the latencies as well, a slow error
+    /// is as much a regression as a slow success.
+    pub fn failure(&mut self, latency: Duration, error: String) {
+        self.latencies.push(latency);
+        self.failed += 1;
+        *self.errors.entry(error).or_default() += 1;
+    }
+
+    /// Record a request not sent because all slots were in use
+    pub fn drop_request(&mut self) {
+        self.dropped += 1;
+    }
+
+    /// Summary of the stream over a run of `elapsed`
+    pub fn report(&self, stream: &'static str, elapsed: Duration) -> StreamReport {
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        let sent = sorted.len() as u64;
+        let seconds = elapsed.as_secs_f64();
+        StreamReport {
+            stream,
+            sent,
+            succeeded: sent - self.failed,
+            failed: self.failed,
+            dropped: self.dropped,
+            throughput: if seconds > 0.0 {
+                sent as f64 / seconds
+            } else {
+                0.0
+            },
+            latency: Latency {
+                p50_ms: millis(percentile(&sorted, 50.0)),
+                p90_ms: millis(percentile(&sorted, 90.0)),
+                p99_ms: millis(percentile(&sorted, 99.0)),
+                max_ms: millis(sorted.last().copied()),
+            },
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+/// Latency percentiles in milliseconds, absent without requests
+#[derive(Debug, Clone, Serialize)]
+pub struct Latency {
+    pub p50_ms: Option<f64>,
+    pub p90_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// Summary of one stream of a load run
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamReport {
+    pub stream: &'static str,
+    pub sent: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    /// Requests sent per second
+    pub throughput: f64,
+    pub latency: Latency,
+    /// Failed requests by status or error
+    pub errors: BTreeMap<String, u64>,
+}
+
+/// Nearest-rank percentile `p` of `sorted`
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn millis(duration: Option<Duration>) -> Option<f64> {
+    duration.map(|d| d.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(Duration::from_millis(50)));
+        assert_eq!(percentile(&sorted, 99.0), Some(Duration::from_millis(99)));
+        assert_eq!(percentile(&sorted, 100.0), Some(Duration::from_millis(100)));
+        assert_eq!(
+            percentile(&[Duration::from_millis(7)], 90.0),
+            Some(Duration::from_millis(7))
+        );
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut recorder = Recorder::default();
+        recorder.success(Duration::from_millis(10));
+        recorder.success(Duration::from_millis(30));
+        recorder.failure(Duration::from_millis(20), "503 Service Unavailable".into());
+        recorder.drop_request();
+
+        let report = recorder.report("posts", Duration::from_secs(2));
+        assert_eq!(report.sent, 3);
+        assert_eq!(report.succeeded, 2);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.dropped, 1);
+        assert_eq!(report.throughput, 1.5);
+        assert_eq!(report.latency.p50_ms, Some(20.0));
+        assert_eq!(report.latency.max_ms, Some(30.0));
+        assert_eq!(report.errors["503 Service Unavailable"], 1);
+    }
+}
@@ -0,0 +1,276 @@
+//! Stored keys and their conversions to the PKI
+//!
+//! Keys live in the `keys` collection as [`KeyDocument`]s. To verify a
+//! key, the part of the hierarchy it depends on is loaded into a
+//! [`PkiManager`], together with the revocation state of those keys.
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::Document;
+use mongodb::bson::oid::ObjectId;
+use oxifed::database::{
+    DatabaseError, DatabaseManager, KeyDocument, KeyStatus, KeyType, domain_signature_document,
+};
+use oxifed::messaging::{KeyInfo, TrustChainReport};
+use oxifed::pki::{
+    DomainKeyInfo, DomainSignature, KeyAlgorithm, KeyEncryptor, KeyPair, KeyUsage, PkiError,
+    PkiManager, PublicKey, RotationPolicy, UserKeyInfo,
+};
+
+use crate::PkidError;
+
+/// Key collection together with the encryptor of its private keys
+pub struct KeyStore {
+    db: Arc<DatabaseManager>,
+    encryptor: KeyEncryptor,
+}
+
+impl KeyStore {
+    pub fn new(db: Arc<DatabaseManager>, encryptor: KeyEncryptor) -> Self {
+        Self { db, encryptor }
+    }
+
+    pub fn manager(&self) -> &DatabaseManager {
+        &self.db
+    }
+
+    /// Encryptor for private keys at rest
+    pub fn encryptor(&self) -> &KeyEncryptor {
+        &self.encryptor
+    }
+
+    /// Insert a key, encrypting its private key first
+    pub async fn insert_key(&self, mut key: KeyDocument) -> Result<ObjectId, PkidError> {
+        key.encrypt_private_key(&self.encryptor).await?;
+        Ok(self.db.insert_key(key).await?)
+    }
+}
+
+/// Algorithm of a stored key
+pub(crate) fn stored_algorithm(key: &KeyDocument) -> KeyAlgorithm {
+    if key.algorithm.to_lowercase().starts_with("ed25519") {
+        KeyAlgorithm::Ed25519
+    } else {
+        KeyAlgorithm::Rsa {
+            key_size: key.key_size.unwrap_or(2048),
+        }
+    }
+}
+
+/// Create the KeyDocument storing a user key
+pub(crate) fn user_key_document(user_key: &UserKeyInfo) -> KeyDocument {
+    KeyDocument {
+        id: None,
+        key_id: user_key.key_id.clone(),
+        actor_id: user_key.actor_id.clone(),
+        key_type: KeyType::User,
+        algorithm: format!("{:?}", user_key.public_key.algorithm).to_lowercase(),
+        key_size: match user_key.public_key.algorithm {
+            KeyAlgorithm::Rsa { key_size } => Some(key_size),
+            _ => None,
+        },
+        public_key_pem: user_key.public_key.pem_data.clone(),
+        private_key_pem: user_key
+            .private_key
+            .as_ref()
+            .map(|pk| pk.encrypted_pem.clone()),
+        encryption_algorithm: user_key
+            .private_key
+            .as_ref()
+            .map(|pk| pk.encryption_algorithm.clone()),
+        fingerprint: user_key.public_key.fingerprint.clone(),
+        trust_level: user_key.trust_level,
+        domain_signature: user_key
+            .domain_signature
+            .as_ref()
+            .map(domain_signature_document),
+        master_signature: None,
+        usage: vec!["signing".to_string()],
+        status: KeyStatus::Active,
+        created_at: user_key.created_at,
+        expires_at: user_key.expires_at,
+        rotation_policy: {
+            let mut doc = Document::new();
+            doc.insert("automatic", user_key.rotation_policy.automatic);
+            if let Some(interval) = user_key.rotation_policy.rotation_interval {
+                doc.insert("rotation_interval", interval.num_seconds());
+            }
+            if let Some(max_age) = user_key.rotation_policy.max_age {
+                doc.insert("max_age", max_age.num_seconds());
+            }
+            if let Some(notify_before) = user_key.rotation_policy.notify_before {
+                doc.insert("notify_before", notify_before.num_seconds());
+            }
+            Some(doc)
+        },
+        domain: None,
+        verification: None,
+    }
+}
+
+/// Load a stored user key without its private key
+pub(crate) fn user_key_info(key: &KeyDocument) -> Result<UserKeyInfo, PkiError> {
+    Ok(UserKeyInfo {
+        actor_id: key.actor_id.clone(),
+        key_id: key.key_id.clone(),
+        public_key: PublicKey::from_pem(stored_algorithm(key), key.public_key_pem.clone())?,
+        private_key: None,
+        domain_signature: key
+            .domain_signature
+            .as_ref()
+            .map(domain_signature)
+            .transpose()?,
+        trust_level: key.trust_level,
+        created_at: key.created_at,
+        expires_at: key.expires_at,
+        rotation_policy: RotationPolicy::default(),
+    })
+}
+
+/// Parse the domain signature stored with a user key
+fn domain_signature(doc: &Document) -> Result<DomainSignature, PkiError> {
+    let invalid = |e: mongodb::bson::document::ValueAccessError| {
+        PkiError::TrustChainError(format!("Invalid domain signature: {}", e))
+    };
+    let signed_at: SystemTime = doc
+        .get_datetime("signed_at")
+        .map_err(invalid)?
+        .to_system_time();
+
+    Ok(DomainSignature {
+        domain: doc.get_str("domain").map_err(invalid)?.to_string(),
+        signature: doc.get_str("signature").map_err(invalid)?.to_string(),
+        signed_at: DateTime::<Utc>::from(signed_at),
+        domain_key_id: doc.get_str("domain_key_id").map_err(invalid)?.to_string(),
+        verification_chain: doc
+            .get_array("verification_chain")
+            .map(|chain| {
+                chain
+                    .iter()
+                    .filter_map(|key_id| key_id.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+/// Load a domain signing key stored by the operator
+pub(crate) async fn domain_key_info(
+    key: KeyDocument,
+    encryptor: &KeyEncryptor,
+) -> Result<DomainKeyInfo, PkiError> {
+    let algorithm = stored_algorithm(&key);
+    let private_pem = key.decrypt_private_key(encryptor).await?.ok_or_else(|| {
+        PkiError::KeyNotFoundError(format!("Private key of domain key {}", key.key_id))
+    })?;
+    let key_pair = KeyPair::from_pem(algorithm, key.public_key_pem, private_pem)?;
+
+    Ok(DomainKeyInfo {
+        domain: key.domain.unwrap_or_default(),
+        key_id: key.key_id,
+        public_key: key_pair.public_key,
+        private_key: key_pair.private_key,
+        created_at: key.created_at,
+        expires_at: key.expires_at,
+        master_signature: None,
+        usage: vec![KeyUsage::DomainSigning],
+    })
+}
+
+/// Build and verify the trust chain of a stored user key
+///
+/// Returns `None` if the key is unknown. Problems with the chain are
+/// reported in the result rather than as an error.
+pub(crate) async fn trust_chain_report(
+    db: &DatabaseManager,
+    encryptor: &KeyEncryptor,
+    key_id: &str,
+) -> Result<Option<TrustChainReport>, DatabaseError> {
+    let Some(key) = db.find_key_by_id(key_id).await? else {
+        return Ok(None);
+    };
+    let now = Utc::now();
+    let revoked = key.is_revoked(now);
+    let status = format!("{:?}", key.status).to_lowercase();
+
+    let mut pki_manager = PkiManager::new();
+    match user_key_info(&key) {
+        Ok(user_key) => {
+            pki_manager.user_keys.insert(key.actor_id.clone(), user_key);
+        }
+        Err(e) => return Ok(Some(unverifiable(key_id, &key, status, revoked, e))),
+    }
+    if revoked {
+        pki_manager.revoke_key(&key.key_id, key.expires_at.unwrap_or(now));
+    }
+
+    let signed_by = key
+        .domain_signature
+        .as_ref()
+        .and_then(|ds| ds.get_str("domain_key_id").ok());
+    if let Some(domain_key_id) = signed_by
+        && let Some(domain_key) = db.find_key_by_id(domain_key_id).await?
+    {
+        if domain_key.is_revoked(now) {
+            pki_manager.revoke_key(&domain_key.key_id, domain_key.expires_at.unwrap_or(now));
+        }
+        let domain = domain_key.domain.clone().unwrap_or_default();
+        match domain_key_info(domain_key, encryptor).await {
+            Ok(domain_key) => {
+                pki_manager.domain_keys.insert(domain, domain_key);
+            }
+            Err(e) => return Ok(Some(unverifiable(key_id, &key, status, revoked, e))),
+        }
+    }
+
+    let error = pki_manager.verify_trust_chain(key_id).err();
+    let report = match pki_manager.build_trust_chain(key_id) {
+        Ok(chain) => TrustChainReport {
+            chain,
+            status,
+            revoked,
+            error: error.map(|e| e.to_string()),
+        },
+        Err(e) => unverifiable(key_id, &key, status, revoked, e),
+    };
+    Ok(Some(report))
+}
+
+/// Summary of a stored key for key list responses
+pub(crate) fn key_info(key: &KeyDocument) -> KeyInfo {
+    KeyInfo {
+        key_id: key.key_id.clone(),
+        actor_id: key.actor_id.clone(),
+        key_type: format!("{:?}", key.key_type).to_lowercase(),
+        algorithm: key.algorithm.clone(),
+        key_size: key.key_size,
+        fingerprint: key.fingerprint.clone(),
+        trust_level: key.trust_level,
+        status: format!("{:?}", key.status).to_lowercase(),
+        created_at: key.created_at.to_rfc3339(),
+        expires_at: key.expires_at.map(|at| at.to_rfc3339()),
+    }
+}
+
+/// Report for a key whose chain could not be loaded
+fn unverifiable(
+    key_id: &str,
+    key: &KeyDocument,
+    status: String,
+    revoked: bool,
+    error: PkiError,
+) -> TrustChainReport {
+    TrustChainReport {
+        chain: oxifed::pki::TrustChain {
+            key_id: key_id.to_string(),
+            trust_level: key.trust_level,
+            verification_chain: Vec::new(),
+            verified_at: Utc::now(),
+        },
+        status,
+        revoked,
+        error: Some(error.to_string()),
+    }
+}
@@ -0,0 +1,266 @@
+//! PKI Daemon
+//!
+//! This daemon owns all operations on private key material: it generates,
+//! imports, rotates and revokes the keys of local actors, signs data for
+//! services that do not hold private keys themselves and answers the key RPC
+//! requests used by adminservd. It is the only service that needs the
+//! key-encryption master key; domainservd never loads a private key.
+
+mod keys;
+mod operations;
+mod rpc;
+mod verification;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use lapin::{
+    Channel, Connection, ConnectionProperties, ExchangeKind, options::*, types::FieldTable,
+};
+use oxifed::config::{AmqpConfig, Config, ConfigError, DatabaseConfig, Env};
+use oxifed::database::{DatabaseError, DatabaseManager};
+use oxifed::egress::EgressPolicy;
+use oxifed::messaging::{
+    CommandErrorKind, EXCHANGE_KEY_EVENTS, EXCHANGE_PKI, EXCHANGE_RPC_REQUEST, QUEUE_PKI,
+    QUEUE_RPC_PKI, ROUTING_KEY_SIGN,
+};
+use oxifed::pki::{KeyEncryptionConfig, KeyEncryptor};
+use oxifed::shutdown::{DEFAULT_DRAIN_TIMEOUT_SECS, Shutdown};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::info;
+
+use crate::keys::KeyStore;
+
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[command(version, about = "Oxifed PKI daemon")]
+struct Args {
+    /// Configuration file (TOML, YAML or JSON); may be repeated, later files
+    /// override earlier ones
+    #[arg(long = "config", value_name = "PATH")]
+    config: Vec<PathBuf>,
+}
+
+/// PKI daemon errors
+#[derive(Error, Debug)]
+pub enum PkidError {
+    #[error("AMQP error: {0}")]
+    AmqpError(#[from] lapin::Error),
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatabaseError),
+
+    #[error("MongoDB error: {0}")]
+    MongoError(#[from] mongodb::error::Error),
+
+    #[error("BSON serialization error: {0}")]
+    BsonError(#[from] mongodb::bson::ser::Error),
+
+    #[error("PKI error: {0}")]
+    PkiError(#[from] oxifed::pki::PkiError),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Actor not found: {0}")]
+    ActorNotFound(String),
+
+    #[error("Constraint error: {0}")]
+    ConstraintError(String),
+
+    #[error("Handler panicked: {0}")]
+    HandlerPanicked(String),
+}
+
+impl PkidError {
+    /// Kind of failure reported to the sender of a key operation
+    fn command_error_kind(&self) -> CommandErrorKind {
+        match self {
+            PkidError::ActorNotFound(_)
+            | PkidError::DatabaseError(DatabaseError::NotFoundError(_)) => {
+                CommandErrorKind::NotFound
+            }
+            PkidError::JsonError(_) | PkidError::PkiError(_) | PkidError::ConstraintError(_) => {
+                CommandErrorKind::Rejected
+            }
+            _ => CommandErrorKind::Failed,
+        }
+    }
+}
+
+/// PKI daemon configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PkidConfig {
+    pub database: DatabaseConfig,
+    pub amqp: AmqpConfig,
+    /// Encryption of stored private keys
+    pub key_encryption: KeyEncryptionConfig,
+    /// Time in-flight requests get to finish on shutdown, in seconds
+    pub shutdown_timeout_secs: u64,
+    /// Hosts fetches of well-known verification challenges may reach
+    pub egress: EgressPolicy,
+}
+
+impl Default for PkidConfig {
+    fn default() -> Self {
+        Self {
+            database: DatabaseConfig::default(),
+            amqp: AmqpConfig::default(),
+            key_encryption: KeyEncryptionConfig::default(),
+            shutdown_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+            egress: EgressPolicy::default(),
+        }
+    }
+}
+
+impl Config for PkidConfig {
+    fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        self.database.apply_env(env)?;
+        self.amqp.apply_env(env)?;
+        self.key_encryption.apply_env(env)?;
+        self.egress.apply_env(env)?;
+        env.set("SHUTDOWN_TIMEOUT_SECS", &mut self.shutdown_timeout_secs)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.database.validate("database")?;
+        self.key_encryption.validate("key_encryption")?;
+        self.egress.validate("egress")
+    }
+}
+
+/// Declare the exchanges and queues consumed by pkid
+async fn declare_topology(channel: &Channel) -> Result<(), PkidError> {
+    for (exchange, kind) in [
+        (EXCHANGE_PKI, ExchangeKind::Fanout),
+        (EXCHANGE_KEY_EVENTS, ExchangeKind::Fanout),
+        (EXCHANGE_RPC_REQUEST, ExchangeKind::Direct),
+    ] {
+        channel
+            .exchange_declare(
+                exchange,
+                kind,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    for queue in [QUEUE_PKI, QUEUE_RPC_PKI] {
+        channel
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    channel
+        .queue_bind(
+            QUEUE_PKI,
+            EXCHANGE_PKI,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    // Key management and signing requests share one RPC queue
+    for routing_key in ["key", ROUTING_KEY_SIGN] {
+        channel
+            .queue_bind(
+                QUEUE_RPC_PKI,
+                EXCHANGE_RPC_REQUEST,
+                routing_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), PkidError> {
+    let args = Args::parse();
+
+    let _telemetry = oxifed_telemetry::init("pkid");
+
+    info!("Starting PKI Daemon");
+
+    let config: PkidConfig = oxifed::config::load_or_exit(&args.config);
+    config.egress.clone().install();
+
+    info!("Connecting to MongoDB at {}", config.database.uri);
+    let db = Arc::new(DatabaseManager::connect(&config.database).await?);
+    db.initialize().await?;
+
+    // Bring stored private keys up to the configured key-encryption backend
+    let encryptor = KeyEncryptor::from_config(&config.key_encryption)?;
+    let reencrypted = db.reencrypt_private_keys(&encryptor).await?;
+    if reencrypted > 0 {
+        info!(
+            "Re-encrypted {} private key(s) with {}",
+            reencrypted,
+            encryptor.algorithm()
+        );
+    }
+    let store = Arc::new(KeyStore::new(db.clone(), encryptor));
+
+    info!("Connecting to AMQP server: {}", config.amqp.url);
+    let connection = Connection::connect(&config.amqp.url, ConnectionProperties::default()).await?;
+
+    let setup_channel = connection.create_channel().await?;
+    declare_topology(&setup_channel).await?;
+
+    let shutdown = Shutdown::new();
+
+    let operations_channel = connection.create_channel().await?;
+    shutdown.spawn(operations::run_operations_consumer(
+        operations_channel,
+        store.clone(),
+        shutdown.clone(),
+    ));
+
+    let rpc_channel = connection.create_channel().await?;
+    shutdown.spawn(rpc::run_rpc_consumer(
+        rpc_channel,
+        store.clone(),
+        shutdown.clone(),
+    ));
+
+    let health_channel = connection.create_channel().await?;
+    let health = tokio::spawn(oxifed_telemetry::health::serve_database_health(
+        health_channel,
+        "pkid",
+        Some(db.clone()),
+    ));
+
+    shutdown.wait_for_signal().await?;
+    info!("Stopping PKI daemon");
+
+    shutdown
+        .drain(Duration::from_secs(config.shutdown_timeout_secs))
+        .await;
+    health.abort();
+    connection.close(200, "shutdown").await?;
+    db.close().await;
+
+    info!("PKI daemon stopped");
+    Ok(())
+}
@@ -0,0 +1,405 @@
+//! Key operations requested through the PKI exchange
+//!
+//! adminservd and domainservd publish key generation, rotation, import and
+//! revocation requests to the PKI exchange. They are processed one at a time
+//! so two requests for the same actor cannot race each other. Every change
+//! of an actor's key is announced with a [`KeyChangedMessage`] on the key
+//! events exchange; domainservd sends the actor Update to followers when it
+//! sees the notice. Requests sent with a `reply_to` queue are answered
+//! with a [`CommandResponse`] carrying the ID of the key.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures::StreamExt;
+use lapin::{Channel, options::*, types::FieldTable};
+use mongodb::bson::{doc, to_bson};
+use oxifed::database::{
+    ActorDocument, KeyDocument, KeyStatus, OutboxMessageDocument, PublicKeyDocument,
+};
+use oxifed::messaging::{
+    CommandResponse, EXCHANGE_KEY_EVENTS, KeyChangedMessage, KeyGenerateMessage, KeyImportMessage,
+    KeyRevokeMessage, KeyRotateMessage, KeyRotationType, Message, MessageEnum, QUEUE_PKI,
+    WebhookEvent, WebhookEventMessage,
+};
+use oxifed::pki::{KEY_ROTATION_OVERLAP_DAYS, KeyAlgorithm, KeyPair, PkiManager, UserKeyInfo};
+use oxifed::shutdown::Shutdown;
+use tracing::{error, info, warn};
+
+use crate::keys::{self, KeyStore};
+use crate::{PkidError, rpc};
+
+/// Consume key operation requests until shutdown
+pub async fn run_operations_consumer(
+    channel: Channel,
+    store: Arc<KeyStore>,
+    shutdown: Shutdown,
+) -> Result<(), PkidError> {
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE_PKI,
+            "pkid_operations",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Key operations consumer ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                error!("Failed to receive key operation: {}", e);
+                continue;
+            }
+        };
+
+        // Failed operations are still acknowledged; retrying them would
+        // fail the same way
+        let result = match oxifed::poison::isolate(process_message(&store, &delivery.data)).await {
+            Ok(result) => result,
+            Err(panic) => Err(PkidError::HandlerPanicked(panic)),
+        };
+        if let Err(e) = &result {
+            error!("Failed to process key operation: {}", e);
+        }
+        if delivery.properties.reply_to().is_some() {
+            let request_id = delivery
+                .properties
+                .correlation_id()
+                .as_ref()
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            let response = match &result {
+                Ok(key_id) => CommandResponse::done(request_id, key_id.clone()),
+                Err(e) => CommandResponse::error(request_id, e.command_error_kind(), e.to_string()),
+            };
+            rpc::reply(&channel, &delivery.properties, &response.to_message()).await;
+        }
+        if let Err(PkidError::HandlerPanicked(panic)) = &result {
+            rpc::dead_letter_poison(&channel, &delivery, QUEUE_PKI, panic).await;
+        } else if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+            error!("Failed to ack key operation: {}", e);
+        }
+    }
+
+    if !shutdown.is_triggered() {
+        warn!("Key operations consumer stopped");
+    }
+    Ok(())
+}
+
+/// Apply a key operation, returning the ID of the key it created or revoked
+async fn process_message(store: &KeyStore, data: &[u8]) -> Result<Option<String>, PkidError> {
+    let key_id = match serde_json::from_slice::<MessageEnum>(data)? {
+        MessageEnum::KeyGenerateMessage(msg) => handle_key_generate(store, &msg).await?,
+        MessageEnum::KeyRotateMessage(msg) => handle_key_rotate(store, &msg).await?,
+        MessageEnum::KeyImportMessage(msg) => handle_key_import(store, &msg).await?,
+        MessageEnum::KeyRevokeMessage(msg) => handle_key_revoke(store, &msg).await?,
+        _ => {
+            warn!("Received non-key message on PKI queue");
+            return Ok(None);
+        }
+    };
+    Ok(Some(key_id))
+}
+
+/// Handle key generation request
+///
+/// The new key becomes the actor's key if the actor has none yet, which is
+/// the case for actors domainservd has just created.
+async fn handle_key_generate(
+    store: &KeyStore,
+    msg: &KeyGenerateMessage,
+) -> Result<String, PkidError> {
+    info!("Generating key for actor: {}", msg.actor);
+
+    let algorithm = parse_key_algorithm(&msg.algorithm, msg.key_size)?;
+    let user_key = PkiManager::new()
+        .generate_user_key(msg.actor.clone(), algorithm)
+        .map_err(|e| PkidError::ConstraintError(format!("Failed to generate key: {}", e)))?;
+    info!(
+        "Key generated successfully for actor: {}, key ID: {}",
+        msg.actor, user_key.key_id
+    );
+
+    let key_id = store.insert_key(keys::user_key_document(&user_key)).await?;
+    info!("Key saved to database with ID: {}", key_id);
+
+    if let Some(actor) = store.manager().find_actor_by_id(&msg.actor).await?
+        && actor.public_key.is_none()
+    {
+        set_actor_key(store, &actor, &user_key).await?;
+    }
+
+    queue_key_changed(store, &user_key.actor_id, &user_key.key_id).await?;
+    Ok(user_key.key_id)
+}
+
+/// Parse the algorithm name of a key request
+fn parse_key_algorithm(name: &str, key_size: Option<u32>) -> Result<KeyAlgorithm, PkidError> {
+    match name.to_lowercase().as_str() {
+        "rsa" => {
+            let key_size = key_size.unwrap_or(2048);
+            info!("Using RSA algorithm with key size: {}", key_size);
+            Ok(KeyAlgorithm::Rsa { key_size })
+        }
+        "ed25519" => {
+            info!("Using Ed25519 algorithm");
+            Ok(KeyAlgorithm::Ed25519)
+        }
+        _ => {
+            error!("Unsupported algorithm: {}", name);
+            Err(PkidError::ConstraintError(format!(
+                "Unsupported algorithm: {}",
+                name
+            )))
+        }
+    }
+}
+
+/// Handle key rotation request
+///
+/// Generates a replacement key signed by the domain key and installs it
+/// with [`install_user_key`].
+async fn handle_key_rotate(store: &KeyStore, msg: &KeyRotateMessage) -> Result<String, PkidError> {
+    info!(
+        "Rotating key for actor: {} ({:?})",
+        msg.actor, msg.rotation_type
+    );
+
+    let actor = store
+        .manager()
+        .find_actor_by_id(&msg.actor)
+        .await?
+        .ok_or_else(|| PkidError::ActorNotFound(msg.actor.clone()))?;
+    let old_keys = store
+        .manager()
+        .find_active_keys_by_actor(&msg.actor)
+        .await?;
+
+    // Keep the algorithm of the current key unless the request asks for another
+    let algorithm = match (&msg.algorithm, old_keys.first()) {
+        (Some(name), _) => parse_key_algorithm(name, msg.key_size)?,
+        (None, Some(old_key)) => keys::stored_algorithm(old_key),
+        (None, None) => KeyAlgorithm::Rsa { key_size: 2048 },
+    };
+
+    let mut pki_manager = PkiManager::new();
+    match store.manager().find_domain_key(&actor.domain).await? {
+        Some(domain_key) => {
+            pki_manager.domain_keys.insert(
+                actor.domain.clone(),
+                keys::domain_key_info(domain_key, store.encryptor())
+                    .await
+                    .map_err(|e| {
+                        PkidError::ConstraintError(format!("Invalid domain key: {}", e))
+                    })?,
+            );
+        }
+        None => warn!(
+            "No domain key for {}, new key of {} stays unverified",
+            actor.domain, msg.actor
+        ),
+    }
+
+    let user_key = pki_manager
+        .rotate_user_key(&msg.actor, &actor.domain, algorithm)
+        .map_err(|e| PkidError::ConstraintError(format!("Failed to rotate key: {}", e)))?;
+    info!("Generated key {} for actor {}", user_key.key_id, msg.actor);
+
+    install_user_key(store, &actor, &user_key, &old_keys, msg.rotation_type).await?;
+
+    let event = WebhookEventMessage::new(
+        WebhookEvent::KeyRotated,
+        actor.domain.clone(),
+        serde_json::json!({
+            "actor": msg.actor,
+            "key_id": user_key.key_id,
+            "rotation_type": msg.rotation_type,
+            "previous_keys": old_keys.iter().map(|key| &key.key_id).collect::<Vec<_>>(),
+        }),
+    );
+    store.manager().queue_webhook_event(&event).await?;
+    Ok(user_key.key_id)
+}
+
+/// Handle key import request (BYOK)
+///
+/// The imported key replaces the actor's current keys like a scheduled
+/// rotation. It stays unverified until the actor completes domain
+/// verification.
+async fn handle_key_import(store: &KeyStore, msg: &KeyImportMessage) -> Result<String, PkidError> {
+    info!("Importing {} key for actor: {}", msg.algorithm, msg.actor);
+
+    let actor = store
+        .manager()
+        .find_actor_by_id(&msg.actor)
+        .await?
+        .ok_or_else(|| PkidError::ActorNotFound(msg.actor.clone()))?;
+
+    let key_pair = KeyPair::import(&msg.public_key_pem, &msg.private_key_pem)
+        .map_err(|e| PkidError::ConstraintError(format!("Invalid key pair: {}", e)))?;
+    let algorithm_matches = match msg.algorithm.to_lowercase().as_str() {
+        "rsa" => matches!(key_pair.public_key.algorithm, KeyAlgorithm::Rsa { .. }),
+        "ed25519" => key_pair.public_key.algorithm == KeyAlgorithm::Ed25519,
+        _ => {
+            return Err(PkidError::ConstraintError(format!(
+                "Unsupported algorithm: {}",
+                msg.algorithm
+            )));
+        }
+    };
+    if !algorithm_matches {
+        return Err(PkidError::ConstraintError(format!(
+            "Imported key is not a {} key",
+            msg.algorithm
+        )));
+    }
+
+    let user_key = PkiManager::new()
+        .import_user_key(msg.actor.clone(), key_pair)
+        .map_err(|e| PkidError::ConstraintError(format!("Failed to import key: {}", e)))?;
+    info!(
+        "Imported key {} for actor {} (fingerprint {})",
+        user_key.key_id, msg.actor, user_key.public_key.fingerprint
+    );
+
+    let old_keys = store
+        .manager()
+        .find_active_keys_by_actor(&msg.actor)
+        .await?;
+    install_user_key(
+        store,
+        &actor,
+        &user_key,
+        &old_keys,
+        KeyRotationType::Scheduled,
+    )
+    .await?;
+    Ok(user_key.key_id)
+}
+
+/// Handle key revocation request
+///
+/// Revokes a key that is no longer the actor's key, such as one still
+/// valid for the overlap after a scheduled rotation. The current key can
+/// only be replaced by an emergency rotation, which revokes it as well.
+async fn handle_key_revoke(store: &KeyStore, msg: &KeyRevokeMessage) -> Result<String, PkidError> {
+    info!("Revoking key {} of actor {}", msg.key_id, msg.actor);
+
+    let key = store
+        .manager()
+        .find_key_by_id(&msg.key_id)
+        .await?
+        .filter(|key| key.actor_id == msg.actor)
+        .ok_or_else(|| {
+            PkidError::ConstraintError(format!("Actor {} has no key {}", msg.actor, msg.key_id))
+        })?;
+
+    let actor = store
+        .manager()
+        .find_actor_by_id(&msg.actor)
+        .await?
+        .ok_or_else(|| PkidError::ActorNotFound(msg.actor.clone()))?;
+    if actor.public_key.as_ref().map(|pk| pk.id.as_str()) == Some(key.key_id.as_str()) {
+        return Err(PkidError::ConstraintError(format!(
+            "Key {} is the current key of {}, use an emergency rotation to replace it",
+            key.key_id, msg.actor
+        )));
+    }
+
+    store
+        .manager()
+        .retire_key(&key.key_id, KeyStatus::Revoked, Utc::now())
+        .await?;
+    info!("Key {} revoked", key.key_id);
+
+    queue_key_changed(store, &msg.actor, &key.key_id).await?;
+    Ok(key.key_id)
+}
+
+/// Make a new key the actor's key and retire the previous ones
+///
+/// Stores the key and points the actor's `publicKey` at it. A scheduled
+/// replacement keeps the old keys valid for [`KEY_ROTATION_OVERLAP_DAYS`];
+/// an emergency one revokes them at once.
+async fn install_user_key(
+    store: &KeyStore,
+    actor: &ActorDocument,
+    user_key: &UserKeyInfo,
+    old_keys: &[KeyDocument],
+    rotation_type: KeyRotationType,
+) -> Result<(), PkidError> {
+    store.insert_key(keys::user_key_document(user_key)).await?;
+
+    let (status, expires_at) = match rotation_type {
+        KeyRotationType::Scheduled => (
+            KeyStatus::Rotated,
+            Utc::now() + chrono::Duration::days(KEY_ROTATION_OVERLAP_DAYS),
+        ),
+        KeyRotationType::Emergency => (KeyStatus::Revoked, Utc::now()),
+    };
+    for old_key in old_keys {
+        store
+            .manager()
+            .retire_key(&old_key.key_id, status.clone(), expires_at)
+            .await?;
+        info!("Key {} marked {:?}", old_key.key_id, status);
+    }
+
+    set_actor_key(store, actor, user_key).await?;
+    queue_key_changed(store, &actor.actor_id, &user_key.key_id).await
+}
+
+/// Point the actor's `publicKey` at a key
+async fn set_actor_key(
+    store: &KeyStore,
+    actor: &ActorDocument,
+    user_key: &UserKeyInfo,
+) -> Result<(), PkidError> {
+    let public_key = PublicKeyDocument {
+        id: user_key.key_id.clone(),
+        owner: actor.actor_id.clone(),
+        public_key_pem: user_key.public_key.pem_data.clone(),
+        algorithm: match user_key.public_key.algorithm {
+            KeyAlgorithm::Rsa { key_size } => format!("rsa-{}", key_size),
+            KeyAlgorithm::Ed25519 => "ed25519".to_string(),
+        },
+        key_size: match user_key.public_key.algorithm {
+            KeyAlgorithm::Rsa { key_size } => Some(key_size),
+            KeyAlgorithm::Ed25519 => None,
+        },
+        fingerprint: user_key.public_key.fingerprint.clone(),
+        created_at: user_key.created_at,
+    };
+    store
+        .manager()
+        .update_actor(
+            &actor.actor_id,
+            doc! { "public_key": to_bson(&public_key)? },
+        )
+        .await?;
+    Ok(())
+}
+
+/// Tell services caching or publishing the actor's keys about the change
+async fn queue_key_changed(
+    store: &KeyStore,
+    actor_id: &str,
+    key_id: &str,
+) -> Result<(), PkidError> {
+    let notice = KeyChangedMessage::new(actor_id.to_string(), key_id.to_string());
+    let message = OutboxMessageDocument::new(
+        EXCHANGE_KEY_EVENTS,
+        "",
+        Some("application/json"),
+        serde_json::to_string(&notice.to_message())?,
+    );
+    store
+        .manager()
+        .insert_outbox_messages(vec![message])
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Domain verification of imported keys
+//!
+//! Imported keys start out unverified. `keys verify` issues a challenge
+//! whose token the owner of the domain publishes in DNS or at a well-known
+//! URL; `keys verify-complete` looks the token up and, if it is there, has
+//! the domain key sign the actor's key. Well-known URLs are fetched under
+//! the egress policy pkid installs.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use hickory_resolver::TokioResolver;
+use hickory_resolver::proto::rr::RData;
+use mongodb::bson::{doc, to_bson};
+use oxifed::database::{KeyDocument, domain_signature_document};
+use oxifed::egress::EgressPolicy;
+use oxifed::pki::{
+    DomainVerificationChallenge, PkiError, PkiManager, VerificationMethod, VerificationStatus,
+};
+use tracing::{info, warn};
+
+use crate::PkidError;
+use crate::keys::{self, KeyStore};
+
+/// Time fetching a well-known challenge file may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Issue a verification challenge for the current key of an actor
+pub(crate) async fn start(
+    store: &KeyStore,
+    actor_id: &str,
+    domain: &str,
+    method: VerificationMethod,
+) -> Result<DomainVerificationChallenge, PkidError> {
+    let key = current_key(store, actor_id, domain).await?;
+    let pki_manager = load_pki(store, &key, domain).await?;
+    let challenge = pki_manager
+        .issue_verification_challenge(actor_id, domain, method)
+        .map_err(constraint)?;
+
+    store
+        .manager()
+        .update_key(&key.key_id, doc! { "verification": to_bson(&challenge)? })
+        .await?;
+    info!(
+        "Issued {} verification challenge for key {} on {}",
+        method, key.key_id, domain
+    );
+    Ok(challenge)
+}
+
+/// Check the published token and sign the actor's key if it is there
+///
+/// A failed check is recorded on the challenge and returned like a
+/// successful one, so the caller can show why it failed and retry.
+pub(crate) async fn complete(
+    store: &KeyStore,
+    actor_id: &str,
+    domain: &str,
+) -> Result<DomainVerificationChallenge, PkidError> {
+    let key = current_key(store, actor_id, domain).await?;
+    let mut challenge = key
+        .verification
+        .clone()
+        .filter(|challenge| challenge.domain == domain && challenge.key_id == key.key_id)
+        .ok_or_else(|| {
+            PkidError::ConstraintError(format!(
+                "No verification challenge for key {} on {}, run 'oxiadm keys verify' first",
+                key.key_id, domain
+            ))
+        })?;
+    if challenge.status == VerificationStatus::Verified {
+        return Ok(challenge);
+    }
+
+    let mut pki_manager = load_pki(store, &key, domain).await?;
+    let result = match published_values(&challenge).await {
+        Ok(values) => pki_manager.complete_verification(actor_id, &mut challenge, &values),
+        Err(e) => {
+            challenge.checked_at = Some(Utc::now());
+            Err(PkiError::DomainVerificationError(format!(
+                "Failed to look up {}: {}",
+                challenge.location(),
+                e
+            )))
+        }
+    };
+
+    let mut update = doc! {};
+    match result {
+        Ok(()) => {
+            if let Some(user_key) = pki_manager.get_user_key(actor_id) {
+                update.insert("trust_level", to_bson(&user_key.trust_level)?);
+                update.insert(
+                    "domain_signature",
+                    user_key
+                        .domain_signature
+                        .as_ref()
+                        .map(domain_signature_document),
+                );
+            }
+            info!("Key {} verified for domain {}", key.key_id, domain);
+        }
+        Err(e) => {
+            warn!("Verification of key {} failed: {}", key.key_id, e);
+            challenge.failure = Some(e.to_string());
+        }
+    }
+    update.insert("verification", to_bson(&challenge)?);
+    store.manager().update_key(&key.key_id, update).await?;
+
+    Ok(challenge)
+}
+
+/// Current signing key of an actor on `domain`
+async fn current_key(
+    store: &KeyStore,
+    actor_id: &str,
+    domain: &str,
+) -> Result<KeyDocument, PkidError> {
+    let actor = store
+        .manager()
+        .find_actor_by_id(actor_id)
+        .await?
+        .ok_or_else(|| PkidError::ActorNotFound(actor_id.to_string()))?;
+    if actor.domain != domain {
+        return Err(PkidError::ConstraintError(format!(
+            "Actor {} does not belong to domain {}",
+            actor_id, domain
+        )));
+    }
+
+    store
+        .manager()
+        .find_active_keys_by_actor(actor_id)
+        .await?
+        .into_iter()
+        .max_by_key(|key| key.created_at)
+        .ok_or_else(|| PkidError::ConstraintError(format!("Actor {} has no active key", actor_id)))
+}
+
+/// PKI holding the actor's key and the signing key of `domain`
+async fn load_pki(
+    store: &KeyStore,
+    key: &KeyDocument,
+    domain: &str,
+) -> Result<PkiManager, PkidError> {
+    let domain_key = store
+        .manager()
+        .find_domain_key(domain)
+        .await?
+        .ok_or_else(|| PkidError::ConstraintError(format!("No domain key for {}", domain)))?;
+
+    let mut pki_manager = PkiManager::new();
+    pki_manager.domain_keys.insert(
+        domain.to_string(),
+        keys::domain_key_info(domain_key, store.encryptor())
+            .await
+            .map_err(constraint)?,
+    );
+    pki_manager.user_keys.insert(
+        key.actor_id.clone(),
+        keys::user_key_info(key).map_err(constraint)?,
+    );
+    Ok(pki_manager)
+}
+
+fn constraint(e: PkiError) -> PkidError {
+    PkidError::ConstraintError(e.to_string())
+}
+
+/// Values published at the location of a challenge
+async fn published_values(challenge: &DomainVerificationChallenge) -> Result<Vec<String>, String> {
+    let location = challenge.location();
+    match challenge.method {
+        VerificationMethod::Dns => {
+            let resolver = TokioResolver::builder_tokio()
+                .and_then(|builder| builder.build())
+                .map_err(|e| e.to_string())?;
+            let lookup = resolver
+                .txt_lookup(location.as_str())
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(lookup
+                .answers()
+                .iter()
+                .filter_map(|record| match &record.data {
+                    RData::TXT(txt) => Some(txt.to_string()),
+                    _ => None,
+                })
+                .collect())
+        }
+        VerificationMethod::WellKnown => {
+            let egress = EgressPolicy::installed();
+            let url = reqwest::Url::parse(&location).map_err(|e| e.to_string())?;
+            egress.check_url(&url).map_err(|e| e.to_string())?;
+            let client = egress
+                .configure(reqwest::Client::builder().timeout(FETCH_TIMEOUT))
+                .and_then(|builder| builder.build())
+                .map_err(|e| e.to_string())?;
+            let body = client
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.to_string())?
+                .text()
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(body.lines().map(str::to_string).collect())
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Consecutive delivery failures per remote host
+//!
+//! Once deliveries to a host have failed `threshold` times in a row, a
+//! `delivery.failing` webhook event is queued for the domain of the sender.
+//! The event fires once per streak; a successful delivery starts over.
+//!
+//! Every outcome is also recorded in the instance registry, whose failure
+//! streaks all publisherd instances share. A host that failed
+//! [`CircuitBreaker::threshold`] deliveries in a row is skipped until
+//! [`CircuitBreaker::cooldown`] after its last failure; the next delivery
+//! then tries it again, and another failure opens the circuit once more.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use moka::sync::Cache;
+use oxifed::database::DatabaseManager;
+use oxifed::messaging::{WebhookEvent, WebhookEventMessage};
+use serde_json::json;
+use tracing::{error, warn};
+use url::Url;
+
+/// How long the circuit state of a host read from the registry is used
+const CIRCUIT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Hosts whose circuit state is cached
+const CIRCUIT_CACHE_SIZE: u64 = 10_000;
+
+/// When deliveries to failing hosts are skipped
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreaker {
+    /// Failed deliveries in a row that open the circuit; 0 never opens it
+    pub threshold: u32,
+    /// How long the circuit stays open after the last failure
+    pub cooldown: Duration,
+}
+
+/// Failure streaks of remote hosts
+#[derive(Debug)]
+pub struct DeliveryFailures {
+    /// Failures in a row that raise the event; 0 never raises it
+    threshold: u32,
+    streaks: Mutex<HashMap<String, u32>>,
+    circuit: CircuitBreaker,
+    /// End of the open circuit of hosts, if any, as last read
+    open_until: Cache<String, Option<DateTime<Utc>>>,
+}
+
+impl DeliveryFailures {
+    pub fn new(threshold: u32, circuit: CircuitBreaker) -> Self {
+        Self {
+            threshold,
+            streaks: Mutex::new(HashMap::new()),
+            circuit,
+            open_until: Cache::builder()
+                .max_capacity(CIRCUIT_CACHE_SIZE)
+                .time_to_live(CIRCUIT_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Until when deliveries to the host of `inbox` are skipped, if its
+    /// circuit is open
+    pub async fn circuit_open_until(
+        &self,
+        db: Option<&DatabaseManager>,
+        inbox: &Url,
+    ) -> Option<DateTime<Utc>> {
+        let (Some(db), Some(host)) = (db, inbox.host_str()) else {
+            return None;
+        };
+        if self.circuit.threshold == 0 {
+            return None;
+        }
+        if let Some(until) = self.open_until.get(host) {
+            return until.filter(|until| *until > Utc::now());
+        }
+        let cooldown = chrono::Duration::from_std(self.circuit.cooldown).unwrap_or_default();
+        let until = match db.find_instance(host).await {
+            Ok(instance) => instance
+                .and_then(|instance| instance.circuit_open_until(self.circuit.threshold, cooldown)),
+            Err(e) => {
+                error!("Failed to read the delivery state of {}: {}", host, e);
+                return None;
+            }
+        };
+        self.open_until.insert(host.to_string(), until);
+        until
+    }
+
+    /// Record the outcome of a delivery to a host
+    ///
+    /// Returns the length of the failure streak when it reaches the threshold.
+    pub fn record(&self, host: &str, delivered: bool) -> Option<u32> {
+        let mut streaks = self.streaks.lock().unwrap_or_else(|e| e.into_inner());
+        if delivered {
+            streaks.remove(host);
+            return None;
+        }
+        let streak = streaks.entry(host.to_string()).or_default();
+        *streak += 1;
+        (self.threshold > 0 && *streak == self.threshold).then_some(*streak)
+    }
+
+    /// Record a delivery to an inbox and queue the event when the threshold
+    /// is reached
+    pub async fn record_delivery(
+        &self,
+        db: Option<&DatabaseManager>,
+        sender: Option<&str>,
+        inbox: &Url,
+        result: Result<(), &str>,
+    ) {
+        let Some(host) = inbox.host_str() else {
+            return;
+        };
+        if let Some(db) = db {
+            if let Err(e) = db.record_instance_delivery(host, result).await {
+                error!(
+                    "Failed to record delivery to {} in the registry: {}",
+                    host, e
+                );
+            }
+            // The next delivery reads whether this failure opened the circuit
+            self.open_until.invalidate(host);
+        }
+        let Some(streak) = self.record(host, result.is_ok()) else {
+            return;
+        };
+        warn!("{} deliveries to {} failed in a row", streak, host);
+
+        let Some(domain) = sender
+            .and_then(|sender| Url::parse(sender).ok())
+            .and_then(|sender| sender.host_str().map(str::to_string))
+        else {
+            return;
+        };
+        let Some(db) = db else {
+            warn!("No database configured - not raising the delivery failure event");
+            return;
+        };
+        let event = WebhookEventMessage::new(
+            WebhookEvent::DeliveryFailing,
+            domain,
+            json!({ "host": host, "inbox": inbox.as_str(), "consecutive_failures": streak }),
+        );
+        if let Err(e) = db.queue_webhook_event(&event).await {
+            error!("Failed to queue delivery failure event for {}: {}", host, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_CIRCUIT: CircuitBreaker = CircuitBreaker {
+        threshold: 0,
+        cooldown: Duration::ZERO,
+    };
+
+    #[test]
+    fn test_threshold_reached_once_per_streak() {
+        let failures = DeliveryFailures::new(3, NO_CIRCUIT);
+        assert_eq!(failures.record("a.example", false), None);
+        assert_eq!(failures.record("b.example", false), None);
+        assert_eq!(failures.record("a.example", false), None);
+        assert_eq!(failures.record("a.example", false), Some(3));
+        assert_eq!(failures.record("a.example", false), None);
+
+        failures.record("a.example", true);
+        assert_eq!(failures.record("a.example", false), None);
+        assert_eq!(failures.record("a.example", false), None);
+        assert_eq!(failures.record("a.example", false), Some(3));
+    }
+
+    #[test]
+    fn test_zero_threshold_disables() {
+        let failures = DeliveryFailures::new(0, NO_CIRCUIT);
+        assert!((0..10).all(|_| failures.record("a.example", false).is_none()));
+    }
+}
@@ -0,0 +1,429 @@
+//! Fair scheduling of deliveries
+//!
+//! Every POST to a remote inbox waits for a slot first. Slots are limited
+//! in total and per destination host, so one large fanout can neither use
+//! up every connection nor flood a single server. Waiting deliveries are
+//! queued per source domain and freed slots go to the domains in turn, so
+//! a domain delivering to thousands of followers does not hold back the
+//! posts of the others. The limits can be read and changed at runtime with
+//! a [`DeliveryLimitsRpcRequest`], which every running publisherd receives.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+use lapin::{BasicProperties, Channel, ExchangeKind, options::*, types::FieldTable};
+use oxifed::messaging::{
+    DeliveryLimits, DeliveryLimitsRpcRequest, DeliveryLimitsRpcRequestType,
+    DeliveryLimitsRpcResponse, EXCHANGE_RPC_REQUEST, Message, MessageEnum,
+    ROUTING_KEY_DELIVERY_LIMITS,
+};
+use oxifed::shutdown::Shutdown;
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, warn};
+
+/// Slots of deliveries shared by all workers of the daemon
+#[derive(Debug)]
+pub struct DeliveryScheduler {
+    state: Mutex<SchedulerState>,
+}
+
+/// Slot held by a delivery while its request runs
+///
+/// Dropping the slot hands it to the next waiting delivery.
+#[derive(Debug)]
+pub struct DeliverySlot {
+    scheduler: Arc<DeliveryScheduler>,
+    host: String,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    limits: DeliveryLimits,
+    in_flight: usize,
+    /// Deliveries in flight per destination host
+    per_host: HashMap<String, usize>,
+    /// Source domains with waiting deliveries, next in turn first
+    turns: VecDeque<String>,
+    /// Waiting deliveries per source domain, oldest first
+    waiting: HashMap<String, VecDeque<Waiter>>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    host: String,
+    grant: oneshot::Sender<DeliverySlot>,
+}
+
+impl DeliveryScheduler {
+    pub fn new(limits: DeliveryLimits) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(SchedulerState {
+                limits,
+                in_flight: 0,
+                per_host: HashMap::new(),
+                turns: VecDeque::new(),
+                waiting: HashMap::new(),
+            }),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for a slot to deliver from `source` domain to `host`
+    pub async fn acquire(self: &Arc<Self>, source: &str, host: &str) -> DeliverySlot {
+        let (grant, granted) = oneshot::channel();
+        {
+            let mut state = self.lock();
+            let waiter = Waiter {
+                host: host.to_string(),
+                grant,
+            };
+            match state.waiting.get_mut(source) {
+                Some(queue) => queue.push_back(waiter),
+                None => {
+                    state
+                        .waiting
+                        .insert(source.to_string(), VecDeque::from([waiter]));
+                    state.turns.push_back(source.to_string());
+                }
+            }
+        }
+        self.dispatch();
+        granted
+            .await
+            .expect("waiting deliveries are granted or kept")
+    }
+
+    /// Limits in effect
+    pub fn limits(&self) -> DeliveryLimits {
+        self.lock().limits
+    }
+
+    /// Change the given limits and return the limits in effect
+    ///
+    /// Deliveries already running above a lowered limit finish; new ones
+    /// wait until the count is below it.
+    pub fn set_limits(
+        self: &Arc<Self>,
+        max_in_flight: Option<usize>,
+        max_per_host: Option<usize>,
+    ) -> Result<DeliveryLimits, String> {
+        if max_in_flight == Some(0) || max_per_host == Some(0) {
+            return Err("Delivery limits must be positive".to_string());
+        }
+        let limits = {
+            let mut state = self.lock();
+            if let Some(max_in_flight) = max_in_flight {
+                state.limits.max_in_flight = max_in_flight;
+            }
+            if let Some(max_per_host) = max_per_host {
+                state.limits.max_per_host = max_per_host;
+            }
+            state.limits
+        };
+        info!(
+            "Delivery limits set to {} in flight, {} per host",
+            limits.max_in_flight, limits.max_per_host
+        );
+        self.dispatch();
+        Ok(limits)
+    }
+
+    /// Hand free slots to waiting deliveries
+    ///
+    /// Grants are sent after the lock is released: a slot whose delivery
+    /// stopped waiting is dropped, which releases it again.
+    fn dispatch(self: &Arc<Self>) {
+        let grants = self.lock().next_grants();
+        for waiter in grants {
+            let slot = DeliverySlot {
+                scheduler: self.clone(),
+                host: waiter.host,
+            };
+            let _ = waiter.grant.send(slot);
+        }
+    }
+
+    fn release(self: &Arc<Self>, host: &str) {
+        {
+            let mut state = self.lock();
+            state.in_flight -= 1;
+            if let Some(count) = state.per_host.get_mut(host) {
+                *count -= 1;
+                if *count == 0 {
+                    state.per_host.remove(host);
+                }
+            }
+        }
+        self.dispatch();
+    }
+}
+
+impl Drop for DeliverySlot {
+    fn drop(&mut self) {
+        self.scheduler.clone().release(&self.host);
+    }
+}
+
+impl SchedulerState {
+    /// Take the waiters that get a slot now, one source domain at a time
+    ///
+    /// Each domain in turn gets its oldest delivery to a host below the
+    /// per-host limit. Domains whose deliveries all wait for busy hosts
+    /// are passed over; the round ends when every domain was passed over
+    /// or no slot is left.
+    fn next_grants(&mut self) -> Vec<Waiter> {
+        let mut grants = Vec::new();
+        let mut passed = 0;
+        while self.in_flight < self.limits.max_in_flight && passed < self.turns.len() {
+            let Some(source) = self.turns.pop_front() else {
+                break;
+            };
+            let Some(queue) = self.waiting.get_mut(&source) else {
+                continue;
+            };
+            queue.retain(|waiter| !waiter.grant.is_closed());
+            let free = queue.iter().position(|waiter| {
+                self.per_host.get(&waiter.host).copied().unwrap_or(0) < self.limits.max_per_host
+            });
+            let granted = free.and_then(|index| queue.remove(index));
+            let empty = queue.is_empty();
+
+            match granted {
+                Some(waiter) => {
+                    *self.per_host.entry(waiter.host.clone()).or_default() += 1;
+                    self.in_flight += 1;
+                    grants.push(waiter);
+                    passed = 0;
+                }
+                None if !empty => passed += 1,
+                None => {}
+            }
+            if empty {
+                self.waiting.remove(&source);
+            } else {
+                self.turns.push_back(source);
+            }
+        }
+        grants
+    }
+}
+
+/// Answer delivery limit requests until shutdown
+///
+/// Each daemon binds its own exclusive queue, so a change reaches every
+/// running instance.
+pub async fn serve_limits_rpc(
+    channel: Channel,
+    scheduler: Arc<DeliveryScheduler>,
+    shutdown: Shutdown,
+) -> Result<(), lapin::Error> {
+    channel
+        .exchange_declare(
+            EXCHANGE_RPC_REQUEST,
+            ExchangeKind::Direct,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    let queue = channel
+        .queue_declare(
+            "",
+            QueueDeclareOptions {
+                exclusive: true,
+                auto_delete: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            queue.name().as_str(),
+            EXCHANGE_RPC_REQUEST,
+            ROUTING_KEY_DELIVERY_LIMITS,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    let mut consumer = channel
+        .basic_consume(
+            queue.name().as_str(),
+            "publisherd_delivery_limits",
+            BasicConsumeOptions {
+                no_ack: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    info!("Delivery limits responder ready");
+
+    while let Some(Some(delivery)) = shutdown.unless_triggered(consumer.next()).await {
+        let delivery = match delivery {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                warn!("Failed to receive delivery limits request: {}", e);
+                continue;
+            }
+        };
+
+        let request = match serde_json::from_slice::<MessageEnum>(&delivery.data) {
+            Ok(MessageEnum::DeliveryLimitsRpcRequest(request)) => request,
+            Ok(_) => {
+                warn!("Received unexpected message on delivery limits queue");
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to parse delivery limits request: {}", e);
+                continue;
+            }
+        };
+
+        let response = handle_request(&scheduler, request);
+        let Some(reply_to) = delivery.properties.reply_to() else {
+            debug!("Delivery limits request has no reply_to queue");
+            continue;
+        };
+        let payload = match serde_json::to_vec(&response.to_message()) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize delivery limits response: {}", e);
+                continue;
+            }
+        };
+        let correlation_id = delivery
+            .properties
+            .correlation_id()
+            .clone()
+            .unwrap_or_else(|| response.request_id.clone().into());
+        if let Err(e) = channel
+            .basic_publish(
+                "",
+                reply_to.as_str(),
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_correlation_id(correlation_id),
+            )
+            .await
+        {
+            error!("Failed to send delivery limits response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_request(
+    scheduler: &Arc<DeliveryScheduler>,
+    request: DeliveryLimitsRpcRequest,
+) -> DeliveryLimitsRpcResponse {
+    let request_id = request.request_id;
+    match request.request_type {
+        DeliveryLimitsRpcRequestType::GetLimits => {
+            DeliveryLimitsRpcResponse::limits(request_id, scheduler.limits())
+        }
+        DeliveryLimitsRpcRequestType::SetLimits {
+            max_in_flight,
+            max_per_host,
+        } => match scheduler.set_limits(max_in_flight, max_per_host) {
+            Ok(limits) => DeliveryLimitsRpcResponse::limits(request_id, limits),
+            Err(message) => DeliveryLimitsRpcResponse::error(request_id, message),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits(max_in_flight: usize, max_per_host: usize) -> DeliveryLimits {
+        DeliveryLimits {
+            max_in_flight,
+            max_per_host,
+        }
+    }
+
+    /// Whether a delivery is still waiting after the scheduler had a chance
+    /// to grant it
+    async fn waits<F: Future + Unpin>(future: &mut F) -> bool {
+        tokio::time::timeout(Duration::from_millis(20), future)
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn test_per_host_and_global_limits() {
+        let scheduler = DeliveryScheduler::new(limits(3, 2));
+        let first = scheduler.acquire("a.example", "big.example").await;
+        let _second = scheduler.acquire("a.example", "big.example").await;
+
+        // The host is at its limit, other hosts are not
+        let third = scheduler.acquire("a.example", "big.example");
+        tokio::pin!(third);
+        assert!(waits(&mut third).await);
+        let _other = scheduler.acquire("a.example", "small.example").await;
+
+        // The global limit is reached as well
+        let fourth = scheduler.acquire("b.example", "other.example");
+        tokio::pin!(fourth);
+        assert!(waits(&mut fourth).await);
+
+        drop(first);
+        let _third = third.await;
+        assert!(waits(&mut fourth).await);
+
+        assert!(scheduler.set_limits(Some(0), None).is_err());
+        assert_eq!(scheduler.set_limits(Some(4), None).unwrap(), limits(4, 2));
+        let _fourth = fourth.await;
+    }
+
+    #[tokio::test]
+    async fn test_freed_slots_go_to_domains_in_turn() {
+        let scheduler = DeliveryScheduler::new(limits(1, 10));
+        let slot = scheduler.acquire("big.example", "x.example").await;
+
+        let mut fanout: Vec<_> = (0..3)
+            .map(|_| Box::pin(scheduler.acquire("big.example", "x.example")))
+            .collect();
+        for delivery in &mut fanout {
+            assert!(waits(delivery).await);
+        }
+        let small = scheduler.acquire("small.example", "y.example");
+        tokio::pin!(small);
+        assert!(waits(&mut small).await);
+
+        // The small domain gets the second slot although the fanout queued first
+        drop(slot);
+        let slot = fanout.remove(0).await;
+        assert!(waits(&mut small).await);
+        drop(slot);
+        let _small = small.await;
+        assert!(waits(&mut fanout[0]).await);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_wait_frees_slot() {
+        let scheduler = DeliveryScheduler::new(limits(1, 1));
+        let slot = scheduler.acquire("a.example", "x.example").await;
+        {
+            let abandoned = scheduler.acquire("a.example", "x.example");
+            tokio::pin!(abandoned);
+            assert!(waits(&mut abandoned).await);
+        }
+        drop(slot);
+        let _slot = scheduler.acquire("b.example", "x.example").await;
+        assert_eq!(scheduler.lock().in_flight, 1);
+    }
+}
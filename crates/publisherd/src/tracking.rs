@@ -0,0 +1,88 @@
+//! Persistent delivery state
+//!
+//! Every attempt to deliver an activity to an inbox updates the activity's
+//! record for that inbox in the `deliveries` collection, which adminservd
+//! serves as the delivery status of the activity. Without a database, or
+//! for activities without an ID, nothing is recorded.
+
+use std::time::Duration;
+
+use oxifed::database::{DatabaseManager, DeliveryAttempt};
+use oxifed::messaging::DeliveryState;
+use tracing::error;
+
+/// Records the attempts to deliver an activity to one inbox
+pub struct DeliveryTracker<'a> {
+    db: &'a DatabaseManager,
+    activity_id: &'a str,
+    inbox: &'a str,
+    recipients: Vec<String>,
+}
+
+impl<'a> DeliveryTracker<'a> {
+    pub fn new(
+        db: Option<&'a DatabaseManager>,
+        activity_id: Option<&'a str>,
+        inbox: &'a str,
+        recipients: Vec<String>,
+    ) -> Option<Self> {
+        Some(Self {
+            db: db?,
+            activity_id: activity_id?,
+            inbox,
+            recipients,
+        })
+    }
+
+    /// Record a successful attempt
+    pub async fn delivered(&self, attempts: usize) {
+        self.record(DeliveryState::Delivered, attempts, None, None)
+            .await;
+    }
+
+    /// Record a failed attempt that is retried after `delay`
+    pub async fn retrying(&self, attempts: usize, error: String, delay: Duration) {
+        let next_retry_at = chrono::Duration::from_std(delay)
+            .ok()
+            .map(|delay| chrono::Utc::now() + delay);
+        self.record(
+            DeliveryState::Retrying,
+            attempts,
+            Some(error),
+            next_retry_at,
+        )
+        .await;
+    }
+
+    /// Record a failed attempt after which the delivery is given up
+    pub async fn failed(&self, attempts: usize, error: String) {
+        self.record(DeliveryState::Failed, attempts, Some(error), None)
+            .await;
+    }
+
+    async fn record(
+        &self,
+        state: DeliveryState,
+        attempts: usize,
+        error: Option<String>,
+        next_retry_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) {
+        let attempt = DeliveryAttempt {
+            activity_id: self.activity_id,
+            inbox: self.inbox,
+            recipients: &self.recipients,
+            state,
+            attempts: attempts as u32,
+            error,
+            next_retry_at,
+        };
+        // The state is informational; a failure to record it must not
+        // affect the delivery
+        if let Err(e) = self.db.record_delivery_attempt(&attempt).await {
+            error!(
+                "Failed to record delivery of {} to {}: {}",
+                self.activity_id, self.inbox, e
+            );
+        }
+    }
+}
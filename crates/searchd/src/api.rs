@@ -0,0 +1,173 @@
+//! Search endpoints
+//!
+//! `GET /search/accounts`, `/search/hashtags` and `/search/statuses` take
+//! the query in `q` and page with `limit` and `offset`. Results are answered
+//! as ActivityStreams collections.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use oxifed::database::{ActorDocument, DatabaseManager};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::error;
+
+use crate::backend::{SearchBackend, SearchKind};
+use crate::indexer::{account_indexable, status_indexable};
+
+/// Largest page of results
+const MAX_LIMIT: usize = 40;
+
+/// State shared by the endpoints
+#[derive(Clone)]
+pub struct ApiState {
+    pub db: Arc<DatabaseManager>,
+    pub backend: Arc<dyn SearchBackend>,
+}
+
+/// Query of a search request
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Routes of the search endpoints
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/search/accounts", get(search_accounts))
+        .route("/search/hashtags", get(search_hashtags))
+        .route("/search/statuses", get(search_statuses))
+        .with_state(state)
+}
+
+/// Look up matches in the index
+async fn matches(
+    state: &ApiState,
+    kind: SearchKind,
+    query: &SearchQuery,
+) -> Result<Vec<String>, StatusCode> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_LIMIT);
+    state
+        .backend
+        .search(kind, q, limit, query.offset.unwrap_or(0))
+        .await
+        .map_err(|e| {
+            error!("Search for {} failed: {}", kind.as_str(), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Collection of search results
+fn collection(items: Vec<Value>) -> Response {
+    Json(json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Collection",
+        "totalItems": items.len(),
+        "items": items
+    }))
+    .into_response()
+}
+
+fn database_error(e: oxifed::database::DatabaseError) -> StatusCode {
+    error!("Failed to load search results: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Find accounts by username, display name or profile text
+async fn search_accounts(
+    State(state): State<ApiState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Response, StatusCode> {
+    let ids = matches(&state, SearchKind::Accounts, &query).await?;
+    let actors: HashMap<String, ActorDocument> = state
+        .db
+        .find_actors_by_ids(&ids)
+        .await
+        .map_err(database_error)?
+        .into_iter()
+        .map(|actor| (actor.actor_id.clone(), actor))
+        .collect();
+
+    let items = ids
+        .iter()
+        .filter_map(|id| actors.get(id))
+        .filter(|actor| account_indexable(actor))
+        .map(|actor| {
+            json!({
+                "id": actor.actor_id,
+                "type": actor.actor_type,
+                "preferredUsername": actor.preferred_username,
+                "name": actor.name,
+                "summary": actor.summary,
+                "icon": actor.icon.as_ref().map(|url| json!({ "type": "Image", "url": url })),
+                "url": actor.actor_id
+            })
+        })
+        .collect();
+    Ok(collection(items))
+}
+
+/// Find hashtags starting with the query
+async fn search_hashtags(
+    State(state): State<ApiState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Response, StatusCode> {
+    let names = matches(&state, SearchKind::Hashtags, &query).await?;
+    let items = names
+        .iter()
+        .map(|name| json!({ "type": "Hashtag", "name": format!("#{}", name) }))
+        .collect();
+    Ok(collection(items))
+}
+
+/// Find public posts by their text
+async fn search_statuses(
+    State(state): State<ApiState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Response, StatusCode> {
+    let ids = matches(&state, SearchKind::Statuses, &query).await?;
+    let objects = state
+        .db
+        .find_objects_by_ids(&ids)
+        .await
+        .map_err(database_error)?;
+    let author_ids: Vec<String> = objects
+        .iter()
+        .map(|object| object.attributed_to.clone())
+        .collect();
+    let authors: HashMap<String, ActorDocument> = state
+        .db
+        .find_actors_by_ids(&author_ids)
+        .await
+        .map_err(database_error)?
+        .into_iter()
+        .map(|actor| (actor.actor_id.clone(), actor))
+        .collect();
+    let objects: HashMap<&str, _> = objects
+        .iter()
+        .map(|object| (object.object_id.as_str(), object))
+        .collect();
+
+    let items = ids
+        .iter()
+        .filter_map(|id| objects.get(id.as_str()))
+        .filter(|object| {
+            authors
+                .get(&object.attributed_to)
+                .is_some_and(|author| status_indexable(object, author))
+        })
+        .map(|object| object.to_activitypub())
+        .collect();
+    Ok(collection(items))
+}
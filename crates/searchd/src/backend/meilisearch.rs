@@ -0,0 +1,160 @@
+//! Search on a Meilisearch server
+//!
+//! Statuses, accounts and hashtags live in three indexes named with a
+//! configurable prefix. Documents are keyed by a hash of their ActivityPub
+//! ID, as Meilisearch restricts document IDs to a few characters.
+
+use futures::future::BoxFuture;
+use serde_json::{Value, json};
+
+use super::{AccountEntry, SearchBackend, SearchKind, StatusEntry, entry_key};
+use crate::SearchError;
+
+/// Backend storing the index on a Meilisearch server
+pub struct MeilisearchBackend {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    index_prefix: String,
+}
+
+impl MeilisearchBackend {
+    pub fn new(url: &str, api_key: Option<String>, index_prefix: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            index_prefix: index_prefix.to_string(),
+        }
+    }
+
+    /// URL of a path below an index
+    fn index_url(&self, kind: SearchKind, path: &str) -> String {
+        format!(
+            "{}/indexes/{}{}/{}",
+            self.url,
+            self.index_prefix,
+            kind.as_str(),
+            path
+        )
+    }
+
+    /// Send a request with the API key and fail on error statuses
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, SearchError> {
+        let request = match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(SearchError::Backend(format!(
+                "Meilisearch answered {}: {}",
+                status, body
+            )));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Add or replace documents of an index
+    async fn add_documents(&self, kind: SearchKind, documents: Value) -> Result<(), SearchError> {
+        self.send(
+            self.client
+                .post(self.index_url(kind, "documents?primaryKey=key"))
+                .json(&documents),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl SearchBackend for MeilisearchBackend {
+    fn index_status<'a>(
+        &'a self,
+        entry: &'a StatusEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async move {
+            let mut document = serde_json::to_value(entry)?;
+            document["key"] = json!(entry_key(SearchKind::Statuses, &entry.id));
+            self.add_documents(SearchKind::Statuses, json!([document]))
+                .await?;
+
+            if !entry.tags.is_empty() {
+                let hashtags: Vec<Value> = entry
+                    .tags
+                    .iter()
+                    .map(|tag| json!({ "key": entry_key(SearchKind::Hashtags, tag), "name": tag }))
+                    .collect();
+                self.add_documents(SearchKind::Hashtags, json!(hashtags))
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn index_account<'a>(
+        &'a self,
+        entry: &'a AccountEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async move {
+            let mut document = serde_json::to_value(entry)?;
+            document["key"] = json!(entry_key(SearchKind::Accounts, &entry.id));
+            self.add_documents(SearchKind::Accounts, json!([document]))
+                .await
+        })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async move {
+            for kind in [SearchKind::Statuses, SearchKind::Accounts] {
+                let path = format!("documents/{}", entry_key(kind, id));
+                self.send(self.client.delete(self.index_url(kind, &path)))
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn search<'a>(
+        &'a self,
+        kind: SearchKind,
+        query: &'a str,
+        limit: usize,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, SearchError>> {
+        Box::pin(async move {
+            let field = match kind {
+                SearchKind::Hashtags => "name",
+                SearchKind::Accounts | SearchKind::Statuses => "id",
+            };
+            let query = match kind {
+                SearchKind::Hashtags => query.trim_start_matches('#'),
+                SearchKind::Accounts | SearchKind::Statuses => query,
+            };
+            let result = self
+                .send(
+                    self.client
+                        .post(self.index_url(kind, "search"))
+                        .json(&json!({
+                            "q": query,
+                            "limit": limit,
+                            "offset": offset,
+                            "attributesToRetrieve": [field],
+                        })),
+                )
+                .await?;
+
+            Ok(result
+                .get("hits")
+                .and_then(Value::as_array)
+                .map(|hits| {
+                    hits.iter()
+                        .filter_map(|hit| hit.get(field).and_then(Value::as_str))
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+}
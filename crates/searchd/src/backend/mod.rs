@@ -0,0 +1,98 @@
+//! Search engines holding the index
+//!
+//! Backends store entries for statuses, accounts and hashtags and answer
+//! queries with the IDs of matching entries. Results are loaded from
+//! MongoDB and checked against the indexing rules again before they are
+//! served, so a stale index never reveals content.
+
+mod meilisearch;
+mod mongo;
+mod tantivy_index;
+
+pub use meilisearch::MeilisearchBackend;
+pub use mongo::MongoBackend;
+pub use tantivy_index::TantivyBackend;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+
+use crate::SearchError;
+
+/// Kind of searched entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    Accounts,
+    Hashtags,
+    Statuses,
+}
+
+impl SearchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchKind::Accounts => "accounts",
+            SearchKind::Hashtags => "hashtags",
+            SearchKind::Statuses => "statuses",
+        }
+    }
+}
+
+/// Indexed text of a status
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusEntry {
+    /// ActivityPub ID of the object
+    pub id: String,
+    /// ActivityPub ID of the author
+    pub author: String,
+    /// Content, summary and name as plain text
+    pub text: String,
+    /// Hashtag names without `#`, lowercased
+    pub tags: Vec<String>,
+    pub language: Option<String>,
+    pub published: DateTime<Utc>,
+}
+
+/// Indexed text of an account
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountEntry {
+    /// ActivityPub ID of the actor
+    pub id: String,
+    /// `username@domain`
+    pub handle: String,
+    pub name: String,
+    /// Profile summary as plain text
+    pub summary: String,
+}
+
+/// Search engine holding the index
+pub trait SearchBackend: Send + Sync {
+    /// Add or replace a status and record its hashtags
+    fn index_status<'a>(&'a self, entry: &'a StatusEntry)
+    -> BoxFuture<'a, Result<(), SearchError>>;
+
+    /// Add or replace an account
+    fn index_account<'a>(
+        &'a self,
+        entry: &'a AccountEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>>;
+
+    /// Remove the status or account with this ActivityPub ID
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), SearchError>>;
+
+    /// IDs of the matching statuses or accounts, or names of the hashtags
+    /// starting with `query`, best matches first
+    fn search<'a>(
+        &'a self,
+        kind: SearchKind,
+        query: &'a str,
+        limit: usize,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, SearchError>>;
+}
+
+/// Key of an entry in engines restricting document IDs to `[A-Za-z0-9_-]`
+fn entry_key(kind: SearchKind, id: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(format!("{}:{}", kind.as_str(), id).as_bytes());
+    hex::encode(digest)
+}
@@ -0,0 +1,78 @@
+//! Search on the MongoDB collections
+//!
+//! Needs no separate index: statuses are found through the `$text` index on
+//! `objects`, accounts and hashtags by prefix and substring matches.
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use oxifed::database::DatabaseManager;
+
+use super::{AccountEntry, SearchBackend, SearchKind, StatusEntry};
+use crate::SearchError;
+
+/// Backend querying the MongoDB collections directly
+pub struct MongoBackend {
+    db: Arc<DatabaseManager>,
+}
+
+impl MongoBackend {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self { db }
+    }
+}
+
+impl SearchBackend for MongoBackend {
+    fn index_status<'a>(
+        &'a self,
+        _entry: &'a StatusEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn index_account<'a>(
+        &'a self,
+        _entry: &'a AccountEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove<'a>(&'a self, _id: &'a str) -> BoxFuture<'a, Result<(), SearchError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn search<'a>(
+        &'a self,
+        kind: SearchKind,
+        query: &'a str,
+        limit: usize,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, SearchError>> {
+        Box::pin(async move {
+            let (limit, offset) = (limit as i64, offset as i64);
+            Ok(match kind {
+                SearchKind::Statuses => self
+                    .db
+                    .text_search_public_objects(query, limit, offset)
+                    .await?
+                    .into_iter()
+                    .map(|object| object.object_id)
+                    .collect(),
+                SearchKind::Accounts => self
+                    .db
+                    .search_actors(query, limit, offset)
+                    .await?
+                    .into_iter()
+                    .map(|actor| actor.actor_id)
+                    .collect(),
+                SearchKind::Hashtags => self
+                    .db
+                    .search_hashtags(query, limit + offset)
+                    .await?
+                    .into_iter()
+                    .skip(offset as usize)
+                    .collect(),
+            })
+        })
+    }
+}
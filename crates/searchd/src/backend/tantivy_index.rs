@@ -0,0 +1,202 @@
+//! Search on an embedded Tantivy index
+//!
+//! The index lives in a local directory owned by a single searchd
+//! instance. Every change is committed right away, so results reflect it
+//! once the reader reloads shortly after.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, STORED, STRING, Schema, TEXT, Value};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term, doc};
+
+use super::{AccountEntry, SearchBackend, SearchKind, StatusEntry, entry_key};
+use crate::SearchError;
+
+/// Memory the index writer may use, in bytes
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Fields of the index schema
+#[derive(Clone, Copy)]
+struct Fields {
+    /// Unique key of the entry
+    key: Field,
+    /// Kind of entry, see [`SearchKind::as_str`]
+    kind: Field,
+    /// ActivityPub ID, or the name of a hashtag
+    id: Field,
+    /// Searched text
+    text: Field,
+}
+
+struct Inner {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+/// Backend storing the index in a local directory
+#[derive(Clone)]
+pub struct TantivyBackend {
+    inner: Arc<Inner>,
+}
+
+impl TantivyBackend {
+    /// Open the index in `dir`, creating it if needed
+    pub fn open(dir: &Path) -> Result<Self, SearchError> {
+        let mut schema = Schema::builder();
+        let fields = Fields {
+            key: schema.add_text_field("key", STRING),
+            kind: schema.add_text_field("kind", STRING),
+            id: schema.add_text_field("id", STRING | STORED),
+            text: schema.add_text_field("text", TEXT),
+        };
+        std::fs::create_dir_all(dir)?;
+        let directory = tantivy::directory::MmapDirectory::open(dir)
+            .map_err(|e| SearchError::Backend(e.to_string()))?;
+        let index = Index::open_or_create(directory, schema.build())?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommitWithDelay)
+            .try_into()?;
+        let writer = index.writer(WRITER_MEMORY)?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                index,
+                reader,
+                writer: Mutex::new(writer),
+                fields,
+            }),
+        })
+    }
+
+    /// Apply changes to the index and commit them off the async runtime
+    async fn write<F>(&self, change: F) -> Result<(), SearchError>
+    where
+        F: FnOnce(&IndexWriter, Fields) -> Result<(), SearchError> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut writer = inner
+                .writer
+                .lock()
+                .map_err(|_| SearchError::Backend("index writer poisoned".to_string()))?;
+            change(&writer, inner.fields)?;
+            writer.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| SearchError::Backend(e.to_string()))?
+    }
+
+    /// Replace the entry with the given key
+    fn upsert(
+        writer: &IndexWriter,
+        fields: Fields,
+        kind: SearchKind,
+        id: &str,
+        text: &str,
+    ) -> Result<(), SearchError> {
+        let key = entry_key(kind, id);
+        writer.delete_term(Term::from_field_text(fields.key, &key));
+        writer.add_document(doc!(
+            fields.key => key,
+            fields.kind => kind.as_str(),
+            fields.id => id,
+            fields.text => text,
+        ))?;
+        Ok(())
+    }
+}
+
+impl SearchBackend for TantivyBackend {
+    fn index_status<'a>(
+        &'a self,
+        entry: &'a StatusEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        let entry = entry.clone();
+        Box::pin(self.write(move |writer, fields| {
+            Self::upsert(writer, fields, SearchKind::Statuses, &entry.id, &entry.text)?;
+            for tag in &entry.tags {
+                Self::upsert(writer, fields, SearchKind::Hashtags, tag, tag)?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn index_account<'a>(
+        &'a self,
+        entry: &'a AccountEntry,
+    ) -> BoxFuture<'a, Result<(), SearchError>> {
+        let id = entry.id.clone();
+        let text = format!("{} {} {}", entry.handle, entry.name, entry.summary);
+        Box::pin(self.write(move |writer, fields| {
+            Self::upsert(writer, fields, SearchKind::Accounts, &id, &text)
+        }))
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), SearchError>> {
+        let id = id.to_string();
+        Box::pin(self.write(move |writer, fields| {
+            for kind in [SearchKind::Statuses, SearchKind::Accounts] {
+                writer.delete_term(Term::from_field_text(fields.key, &entry_key(kind, &id)));
+            }
+            Ok(())
+        }))
+    }
+
+    fn search<'a>(
+        &'a self,
+        kind: SearchKind,
+        query: &'a str,
+        limit: usize,
+        offset: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, SearchError>> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let fields = inner.fields;
+                let matching: Box<dyn Query> = match kind {
+                    // Hashtags are indexed lowercase as a single token
+                    SearchKind::Hashtags => {
+                        let prefix = query.trim_start_matches('#').to_lowercase();
+                        Box::new(RegexQuery::from_pattern(
+                            &format!("{}.*", regex::escape(&prefix)),
+                            fields.text,
+                        )?)
+                    }
+                    SearchKind::Accounts | SearchKind::Statuses => {
+                        let parser = QueryParser::for_index(&inner.index, vec![fields.text]);
+                        parser.parse_query_lenient(&query).0
+                    }
+                };
+                let of_kind = Box::new(TermQuery::new(
+                    Term::from_field_text(fields.kind, kind.as_str()),
+                    IndexRecordOption::Basic,
+                ));
+                let query =
+                    BooleanQuery::new(vec![(Occur::Must, matching), (Occur::Must, of_kind)]);
+
+                let searcher = inner.reader.searcher();
+                let top =
+                    searcher.search(&query, &TopDocs::with_limit(limit).and_offset(offset))?;
+                let mut ids = Vec::with_capacity(top.len());
+                for (_, address) in top {
+                    let document: TantivyDocument = searcher.doc(address)?;
+                    if let Some(id) = document.get_first(fields.id).and_then(|id| id.as_str()) {
+                        ids.push(id.to_string());
+                    }
+                }
+                Ok(ids)
+            })
+            .await
+            .map_err(|e| SearchError::Backend(e.to_string()))?
+        })
+    }
+}
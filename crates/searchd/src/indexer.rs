@@ -0,0 +1,425 @@
+//! Keeping the index in line with stored content
+//!
+//! Remote content reaches the index through the `search` pipeline stage,
+//! which runs after storage; local content is picked up by a periodic sweep
+//! of the objects and actors collections.
+//!
+//! Only public content is indexed. Accounts are indexed when they set
+//! `discoverable: true`. Public posts of local accounts are indexed unless
+//! the account sets `indexable: false`, those of remote accounts only when
+//! it sets `indexable: true`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use oxifed::ObjectType;
+use oxifed::client::ActivityPubClient;
+use oxifed::database::{
+    ActorDocument, ActorStatus, DatabaseManager, ObjectDocument, ObjectStatus, VisibilityLevel,
+};
+use oxifed::shutdown::Shutdown;
+use oxifed_pipeline::{PipelineEnvelope, PipelinePayload, Stage, StageError, StageOutcome};
+use regex::Regex;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::SearchError;
+use crate::backend::{AccountEntry, SearchBackend, StatusEntry};
+
+/// Name of the pipeline stage run by this daemon
+pub const STAGE_NAME: &str = "search";
+
+/// Local objects read per query of a sweep
+const SWEEP_BATCH: i64 = 500;
+
+/// Overlap between sweeps, covering objects written while a sweep ran
+const SWEEP_OVERLAP_SECS: i64 = 60;
+
+/// Whether an account may be found by searches
+pub fn account_indexable(actor: &ActorDocument) -> bool {
+    actor.status == ActorStatus::Active && actor.discoverable()
+}
+
+/// Whether a status may be found by searches
+pub fn status_indexable(object: &ObjectDocument, author: &ActorDocument) -> bool {
+    object.visibility == VisibilityLevel::Public
+        && object.status == ObjectStatus::Published
+        && object.object_type != ObjectType::Tombstone
+        && object.attributed_to == author.actor_id
+        && author.status == ActorStatus::Active
+        && author.indexable()
+}
+
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Text of HTML content without markup
+fn plain_text(html: &str) -> String {
+    let text = TAGS.replace_all(html, " ");
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Index entry of a status
+pub fn status_entry(object: &ObjectDocument) -> StatusEntry {
+    let text = [&object.name, &object.summary, &object.content]
+        .into_iter()
+        .flatten()
+        .map(|part| plain_text(part))
+        .collect::<Vec<_>>()
+        .join(" ");
+    StatusEntry {
+        id: object.object_id.clone(),
+        author: object.attributed_to.clone(),
+        text,
+        tags: object
+            .tag
+            .iter()
+            .flatten()
+            .filter(|tag| tag.tag_type == "Hashtag")
+            .map(|tag| tag.name.trim_start_matches('#').to_lowercase())
+            .collect(),
+        language: object.language.clone(),
+        published: object.published.unwrap_or(object.created_at),
+    }
+}
+
+/// Index entry of an account
+pub fn account_entry(actor: &ActorDocument) -> AccountEntry {
+    AccountEntry {
+        id: actor.actor_id.clone(),
+        handle: format!("{}@{}", actor.preferred_username, actor.domain),
+        name: actor.name.clone(),
+        summary: actor.summary.as_deref().map(plain_text).unwrap_or_default(),
+    }
+}
+
+/// Updates the index from stored content
+pub struct Indexer {
+    db: Arc<DatabaseManager>,
+    backend: Arc<dyn SearchBackend>,
+    client: ActivityPubClient,
+}
+
+impl Indexer {
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        backend: Arc<dyn SearchBackend>,
+    ) -> Result<Self, SearchError> {
+        Ok(Self {
+            db,
+            backend,
+            client: ActivityPubClient::new()?,
+        })
+    }
+
+    /// Index or unindex a status according to the rules
+    async fn sync_status(
+        &self,
+        object: &ObjectDocument,
+        author: Option<&ActorDocument>,
+    ) -> Result<(), SearchError> {
+        match author {
+            Some(author) if status_indexable(object, author) => {
+                self.backend.index_status(&status_entry(object)).await
+            }
+            _ => self.backend.remove(&object.object_id).await,
+        }
+    }
+
+    /// Index or unindex an account according to the rules
+    async fn sync_account(&self, actor: &ActorDocument) -> Result<(), SearchError> {
+        if account_indexable(actor) {
+            self.backend.index_account(&account_entry(actor)).await
+        } else {
+            self.backend.remove(&actor.actor_id).await
+        }
+    }
+
+    /// Stored author of a remote object, fetched and stored when unknown
+    async fn remote_author(&self, actor_id: &str) -> Result<Option<ActorDocument>, SearchError> {
+        if let Some(actor) = self.db.find_actor_by_id(actor_id).await? {
+            return Ok(Some(actor));
+        }
+        let Ok(url) = Url::parse(actor_id) else {
+            return Ok(None);
+        };
+        let fetched = match self.client.fetch_actor(&url).await {
+            Ok(actor) => serde_json::to_value(actor)?,
+            Err(e) => {
+                debug!("Failed to fetch author {}: {}", actor_id, e);
+                return Ok(None);
+            }
+        };
+        let Some(actor) = ActorDocument::from_activitypub(&fetched) else {
+            return Ok(None);
+        };
+        if actor.actor_id != actor_id {
+            return Ok(None);
+        }
+        self.db.upsert_remote_actor(actor.clone()).await?;
+        self.sync_account(&actor).await?;
+        Ok(Some(actor))
+    }
+
+    /// Index a remote object that passed the pipeline
+    async fn index_remote_object(&self, object: &Value) -> Result<(), SearchError> {
+        let Some(object_id) = object.get("id").and_then(Value::as_str) else {
+            return Ok(());
+        };
+        // Storage keeps the first copy; index what was stored
+        let Some(stored) = self.db.find_object_by_id(object_id).await? else {
+            debug!("Object {} was not stored", object_id);
+            return Ok(());
+        };
+        if stored.local {
+            return Ok(());
+        }
+        let author = self.remote_author(&stored.attributed_to).await?;
+        self.sync_status(&stored, author.as_ref()).await
+    }
+
+    /// Apply an incoming activity to the index
+    async fn apply_activity(&self, activity: &Value) -> Result<(), SearchError> {
+        let object = activity.get("object");
+        match activity.get("type").and_then(Value::as_str) {
+            Some("Delete") => {
+                let id = object.and_then(|object| {
+                    object
+                        .as_str()
+                        .or_else(|| object.get("id").and_then(Value::as_str))
+                });
+                if let Some(id) = id {
+                    self.backend.remove(id).await?;
+                }
+            }
+            Some("Update") => {
+                if let Some(object) = object.filter(|object| object.is_object()) {
+                    let actor_types = ["Person", "Service", "Application", "Group", "Organization"];
+                    let object_type = object.get("type").and_then(Value::as_str);
+                    if object_type.is_some_and(|t| actor_types.contains(&t)) {
+                        if let Some(id) = object.get("id").and_then(Value::as_str)
+                            && let Some(actor) = self.db.find_actor_by_id(id).await?
+                        {
+                            self.sync_account(&actor).await?;
+                        }
+                    } else {
+                        self.index_remote_object(object).await?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Index local content created or edited after `since`
+    async fn sweep_objects(
+        &self,
+        since: DateTime<Utc>,
+        authors: &HashMap<String, ActorDocument>,
+    ) -> Result<usize, SearchError> {
+        let mut offset = 0;
+        loop {
+            let objects = self
+                .db
+                .find_local_objects_changed_since(since, SWEEP_BATCH, offset)
+                .await?;
+            for object in &objects {
+                self.sync_status(object, authors.get(&object.attributed_to))
+                    .await?;
+            }
+            offset += objects.len() as i64;
+            if (objects.len() as i64) < SWEEP_BATCH {
+                return Ok(offset as usize);
+            }
+        }
+    }
+
+    /// Re-index all posts of an account whose opt-in changed
+    async fn resync_author(&self, author: &ActorDocument) -> Result<(), SearchError> {
+        let mut offset = 0;
+        loop {
+            let objects = self
+                .db
+                .find_objects_by_actor(&author.actor_id, SWEEP_BATCH, offset)
+                .await?;
+            for object in &objects {
+                self.sync_status(object, Some(author)).await?;
+            }
+            offset += objects.len() as i64;
+            if (objects.len() as i64) < SWEEP_BATCH {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Stage for Indexer {
+    fn name(&self) -> &'static str {
+        STAGE_NAME
+    }
+
+    async fn process(&self, envelope: &PipelineEnvelope) -> Result<StageOutcome, StageError> {
+        match &envelope.payload {
+            PipelinePayload::Object(message) => self.index_remote_object(&message.object).await?,
+            PipelinePayload::Activity(message) => self.apply_activity(&message.activity).await?,
+        }
+        Ok(StageOutcome::Continue)
+    }
+}
+
+/// Start sweeping local content into the index
+pub fn start_sweeper(indexer: Arc<Indexer>, interval: Duration, shutdown: &Shutdown) {
+    info!("Starting local content indexer");
+    shutdown.spawn(run_sweeper(indexer, interval, shutdown.clone()));
+}
+
+/// Sweep periodically until shutdown begins
+///
+/// The first sweep indexes all local content. Accounts are compared with
+/// the previous sweep; when an account's opt-in changes, all its posts
+/// are indexed or removed again.
+async fn run_sweeper(indexer: Arc<Indexer>, interval: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(interval);
+    let mut since = DateTime::<Utc>::UNIX_EPOCH;
+    let mut known: HashMap<String, AccountEntryState> = HashMap::new();
+
+    while shutdown.unless_triggered(interval.tick()).await.is_some() {
+        let started = Utc::now();
+        match sweep(&indexer, since, &mut known).await {
+            Ok(count) => {
+                if count > 0 {
+                    info!("Indexed {} changed local posts", count);
+                }
+                since = started - chrono::Duration::seconds(SWEEP_OVERLAP_SECS);
+            }
+            Err(e) => warn!("Local content sweep failed: {}", e),
+        }
+    }
+}
+
+/// What the index last saw of a local account
+#[derive(Debug, Clone, PartialEq)]
+struct AccountEntryState {
+    entry: Option<AccountEntry>,
+    posts_indexable: bool,
+}
+
+/// One sweep over local accounts and posts
+async fn sweep(
+    indexer: &Indexer,
+    since: DateTime<Utc>,
+    known: &mut HashMap<String, AccountEntryState>,
+) -> Result<usize, SearchError> {
+    let actors = indexer.db.find_local_actors().await?;
+    let mut current = HashMap::with_capacity(actors.len());
+    for actor in &actors {
+        let state = AccountEntryState {
+            entry: account_indexable(actor).then(|| account_entry(actor)),
+            posts_indexable: actor.indexable(),
+        };
+        let previous = known.get(&actor.actor_id);
+        if previous.map(|previous| &previous.entry) != Some(&state.entry) {
+            indexer.sync_account(actor).await?;
+        }
+        if previous.is_some_and(|previous| previous.posts_indexable != state.posts_indexable) {
+            indexer.resync_author(actor).await?;
+        }
+        current.insert(actor.actor_id.clone(), state);
+    }
+    // Accounts no longer active are removed along with their posts
+    for (actor_id, _) in known.iter().filter(|(id, _)| !current.contains_key(*id)) {
+        indexer.backend.remove(actor_id).await?;
+        if let Some(actor) = indexer.db.find_actor_by_id(actor_id).await? {
+            indexer.resync_author(&actor).await?;
+        }
+    }
+    *known = current;
+
+    let authors = actors
+        .into_iter()
+        .map(|actor| (actor.actor_id.clone(), actor))
+        .collect();
+    indexer.sweep_objects(since, &authors).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn actor(local: bool, flags: Option<mongodb::bson::Document>) -> ActorDocument {
+        let mut actor = ActorDocument::from_activitypub(&json!({
+            "id": "https://example.com/users/alice",
+            "preferredUsername": "alice",
+            "inbox": "https://example.com/users/alice/inbox"
+        }))
+        .unwrap();
+        actor.local = local;
+        actor.additional_properties = flags;
+        actor
+    }
+
+    fn note(to: &str) -> ObjectDocument {
+        ObjectDocument::from_activitypub(
+            &json!({
+                "id": "https://example.com/objects/1",
+                "attributedTo": "https://example.com/users/alice",
+                "content": "<p>Hello &amp; <a href=\"https://example.com/tags/rust\">#Rust</a></p>",
+                "tag": [{ "type": "Hashtag", "name": "#Rust" }],
+                "to": [to]
+            }),
+            ObjectType::Note,
+        )
+    }
+
+    #[test]
+    fn test_local_content_indexed_unless_opted_out() {
+        let public = note("https://www.w3.org/ns/activitystreams#Public");
+        assert!(status_indexable(&public, &actor(true, None)));
+        assert!(!account_indexable(&actor(true, None)));
+        assert!(account_indexable(&actor(
+            true,
+            Some(mongodb::bson::doc! { "discoverable": true })
+        )));
+
+        let opted_out = actor(
+            true,
+            Some(mongodb::bson::doc! { "indexable": false, "discoverable": false }),
+        );
+        assert!(!status_indexable(&public, &opted_out));
+        assert!(!account_indexable(&opted_out));
+
+        let followers = note("https://example.com/users/alice/followers");
+        assert!(!status_indexable(&followers, &actor(true, None)));
+    }
+
+    #[test]
+    fn test_remote_content_indexed_when_opted_in() {
+        let public = note("https://www.w3.org/ns/activitystreams#Public");
+        assert!(!status_indexable(&public, &actor(false, None)));
+        assert!(!account_indexable(&actor(false, None)));
+
+        let opted_in = actor(
+            false,
+            Some(mongodb::bson::doc! { "indexable": true, "discoverable": true }),
+        );
+        assert!(status_indexable(&public, &opted_in));
+        assert!(account_indexable(&opted_in));
+    }
+
+    #[test]
+    fn test_status_entry_is_plain_text() {
+        let entry = status_entry(&note("https://www.w3.org/ns/activitystreams#Public"));
+        assert_eq!(entry.text, "Hello & #Rust");
+        assert_eq!(entry.tags, vec!["rust".to_string()]);
+    }
+}
@@ -0,0 +1,338 @@
+//! Spam filter rules and content checks
+//!
+//! Rules are loaded from an optional TOML file. Every check that matches adds
+//! a reason; content with at least one reason is quarantined.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use moka::sync::Cache;
+use oxifed::client::ActivityPubClient;
+use oxifed::database::DatabaseManager;
+use oxifed::messaging::IncomingObjectMessage;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use url::Url;
+
+use crate::SpamFilterError;
+
+/// How long fetched follower/following counts are reused
+const ACTOR_STATS_TTL: Duration = Duration::from_secs(3600);
+
+/// Most actors whose counts are kept at a time
+const ACTOR_STATS_CAPACITY: u64 = 10_000;
+
+/// Cached follower and following counts of an actor, `None` if unavailable
+type ActorStats = Option<(u64, u64)>;
+
+/// Spam filter rules as read from the configuration file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FilterRules {
+    /// Case-insensitive keywords that reject content
+    pub keywords: Vec<String>,
+    /// Regular expressions that reject content
+    pub patterns: Vec<String>,
+    /// Maximum number of links in a post, mentions and hashtags excluded
+    pub max_links: usize,
+    /// Number of identical posts by one author allowed within the duplicate window
+    pub duplicate_threshold: usize,
+    /// Length of the duplicate detection window in seconds
+    pub duplicate_window_secs: i64,
+    /// Minimum followers/following ratio for actors following many accounts
+    pub min_follower_ratio: f64,
+    /// Following count above which the follower ratio is checked
+    pub ratio_min_following: u64,
+}
+
+impl Default for FilterRules {
+    fn default() -> Self {
+        Self {
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            max_links: 5,
+            duplicate_threshold: 3,
+            duplicate_window_secs: 3600,
+            min_follower_ratio: 0.01,
+            ratio_min_following: 500,
+        }
+    }
+}
+
+impl FilterRules {
+    /// Load rules from a TOML file, or use the defaults when no path is given
+    pub fn load(path: Option<&str>) -> Result<Self, SpamFilterError> {
+        match path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)?;
+                toml::from_str(&contents).map_err(|e| SpamFilterError::ConfigError(e.to_string()))
+            }
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Result of running all checks against a piece of content
+#[derive(Debug)]
+pub struct Verdict {
+    /// Reasons the content was rejected; empty if it passed
+    pub reasons: Vec<String>,
+    /// Normalized content hash
+    pub content_hash: String,
+}
+
+/// Compiled spam filter
+pub struct SpamFilter {
+    rules: FilterRules,
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+    anchor_regex: Regex,
+    tag_regex: Regex,
+    url_regex: Regex,
+    client: ActivityPubClient,
+    actor_stats: Cache<String, ActorStats>,
+}
+
+impl SpamFilter {
+    /// Compile the filter rules
+    pub fn new(rules: FilterRules) -> Result<Self, SpamFilterError> {
+        let patterns = rules
+            .patterns
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keywords = rules.keywords.iter().map(|k| k.to_lowercase()).collect();
+
+        Ok(Self {
+            rules,
+            keywords,
+            patterns,
+            anchor_regex: Regex::new(r#"(?i)<a\s[^>]*>"#)?,
+            tag_regex: Regex::new(r"<[^>]*>")?,
+            url_regex: Regex::new(r#"https?://[^\s<>"']+"#)?,
+            client: ActivityPubClient::new()?,
+            actor_stats: Cache::builder()
+                .max_capacity(ACTOR_STATS_CAPACITY)
+                .time_to_live(ACTOR_STATS_TTL)
+                .build(),
+        })
+    }
+
+    /// Run all checks against an incoming object
+    pub async fn check(
+        &self,
+        db: &DatabaseManager,
+        message: &IncomingObjectMessage,
+    ) -> Result<Verdict, SpamFilterError> {
+        let html = object_html(&message.object);
+        let text = self.plain_text(&html);
+        let lowered = text.to_lowercase();
+        let mut reasons = Vec::new();
+
+        for keyword in &self.keywords {
+            if lowered.contains(keyword.as_str()) {
+                reasons.push(format!("keyword: {}", keyword));
+            }
+        }
+
+        for pattern in &self.patterns {
+            if pattern.is_match(&text) {
+                reasons.push(format!("pattern: {}", pattern.as_str()));
+            }
+        }
+
+        let links = self.count_links(&html, &text);
+        if links > self.rules.max_links {
+            reasons.push(format!(
+                "links: {} exceeds limit of {}",
+                links, self.rules.max_links
+            ));
+        }
+
+        let content_hash = content_hash(&lowered);
+        if !lowered.is_empty() {
+            let purge_at = mongodb::bson::DateTime::from_millis(
+                (chrono::Utc::now() + chrono::Duration::seconds(self.rules.duplicate_window_secs))
+                    .timestamp_millis(),
+            );
+            // Objects without an ID cannot be recognized when seen again
+            let object_id = message
+                .object
+                .get("id")
+                .and_then(|id| id.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            let count = db
+                .record_content_hash(&message.attributed_to, &content_hash, &object_id, purge_at)
+                .await?;
+            if count > self.rules.duplicate_threshold {
+                reasons.push(format!("duplicate: seen {} times", count));
+            }
+        }
+
+        if let Some((followers, following)) = self.actor_stats(&message.attributed_to).await
+            && following >= self.rules.ratio_min_following
+        {
+            let ratio = followers as f64 / following as f64;
+            if ratio < self.rules.min_follower_ratio {
+                reasons.push(format!(
+                    "follower ratio: {} followers for {} following",
+                    followers, following
+                ));
+            }
+        }
+
+        Ok(Verdict {
+            reasons,
+            content_hash,
+        })
+    }
+
+    /// Strip markup and collapse whitespace
+    fn plain_text(&self, html: &str) -> String {
+        let stripped = self.tag_regex.replace_all(html, " ");
+        stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Count distinct links, ignoring mention and hashtag anchors
+    fn count_links(&self, html: &str, text: &str) -> usize {
+        let mut links = HashSet::new();
+        let mut ignored = HashSet::new();
+
+        for anchor in self.anchor_regex.find_iter(html) {
+            let anchor = anchor.as_str();
+            let Some(href) = self.url_regex.find(anchor) else {
+                continue;
+            };
+            let href = href.as_str().to_string();
+            if anchor.contains("mention") || anchor.contains("hashtag") {
+                ignored.insert(href);
+            } else {
+                links.insert(href);
+            }
+        }
+
+        for url in self.url_regex.find_iter(text) {
+            let url = url.as_str().to_string();
+            if !ignored.contains(&url) {
+                links.insert(url);
+            }
+        }
+
+        links.len()
+    }
+
+    /// Fetch an actor's follower and following counts, cached for an hour
+    async fn actor_stats(&self, actor_id: &str) -> ActorStats {
+        if let Some(stats) = self.actor_stats.get(actor_id) {
+            return stats;
+        }
+
+        let stats = self.fetch_actor_stats(actor_id).await;
+        self.actor_stats.insert(actor_id.to_string(), stats);
+        stats
+    }
+
+    async fn fetch_actor_stats(&self, actor_id: &str) -> ActorStats {
+        let actor = match self.client.fetch_actor(&Url::parse(actor_id).ok()?).await {
+            Ok(actor) => actor,
+            Err(e) => {
+                debug!("Could not fetch actor {} for ratio check: {}", actor_id, e);
+                return None;
+            }
+        };
+
+        let followers = self
+            .collection_size(actor.additional_properties.get("followers"))
+            .await?;
+        let following = self
+            .collection_size(actor.additional_properties.get("following"))
+            .await?;
+        Some((followers, following))
+    }
+
+    async fn collection_size(&self, collection: Option<&Value>) -> Option<u64> {
+        let url = Url::parse(collection?.as_str()?).ok()?;
+        let collection = self.client.fetch_collection(&url).await.ok()?;
+        collection.total_items.map(|n| n as u64)
+    }
+}
+
+/// Concatenate the human-readable fields of an object
+fn object_html(object: &Value) -> String {
+    ["name", "summary", "content"]
+        .iter()
+        .filter_map(|field| object.get(*field).and_then(|v| v.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// SHA-256 of normalized content, hex encoded
+fn content_hash(normalized: &str) -> String {
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> SpamFilter {
+        SpamFilter::new(FilterRules::default()).unwrap()
+    }
+
+    #[test]
+    fn test_plain_text_strips_markup() {
+        let filter = filter();
+        assert_eq!(
+            filter.plain_text("<p>Hello <b>world</b></p>\n<p>again</p>"),
+            "Hello world again"
+        );
+    }
+
+    #[test]
+    fn test_count_links_ignores_mentions_and_hashtags() {
+        let filter = filter();
+        let html = concat!(
+            r#"<a href="https://spam.example/a">one</a> "#,
+            r#"<a href="https://remote.example/@bob" class="u-url mention">@bob</a> "#,
+            r#"<a href="https://remote.example/tags/rust" class="hashtag">#rust</a> "#,
+            r#"https://spam.example/b"#,
+        );
+        let text = filter.plain_text(html);
+        assert_eq!(filter.count_links(html, &text), 2);
+    }
+
+    #[test]
+    fn test_content_hash_of_normalized_text() {
+        let filter = filter();
+        let a = filter.plain_text("<p>Buy   NOW</p>").to_lowercase();
+        let b = filter.plain_text("buy now").to_lowercase();
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert_ne!(content_hash(&a), content_hash("buy later"));
+    }
+
+    #[test]
+    fn test_object_html_joins_text_fields() {
+        let object = serde_json::json!({
+            "name": "Title",
+            "summary": "CW",
+            "content": "<p>Body</p>",
+            "url": "https://remote.example/1",
+        });
+        assert_eq!(object_html(&object), "Title\nCW\n<p>Body</p>");
+    }
+
+    #[test]
+    fn test_rules_load_defaults_and_toml() {
+        let rules = FilterRules::load(None).unwrap();
+        assert_eq!(rules.duplicate_threshold, 3);
+
+        let rules: FilterRules = toml::from_str("keywords = [\"casino\"]\nmax_links = 2").unwrap();
+        assert_eq!(rules.keywords, vec!["casino"]);
+        assert_eq!(rules.max_links, 2);
+        assert_eq!(rules.duplicate_window_secs, 3600);
+    }
+}
@@ -0,0 +1,199 @@
+//! Quarantine handling for rejected content
+//!
+//! Rejected envelopes are recorded in the `quarantine` collection and copied
+//! to the quarantine queue. Releasing an entry sends the envelope on to the
+//! pipeline stage after the spam filter.
+
+use chrono::Utc;
+use lapin::{
+    BasicProperties, Channel,
+    options::BasicPublishOptions,
+    types::{AMQPValue, FieldTable},
+};
+use oxifed::database::{DatabaseManager, QuarantineDocument, QuarantineStatus};
+use oxifed::messaging::{IncomingObjectMessage, QUEUE_INCOMING_QUARANTINE, QuarantineInfo};
+use oxifed_pipeline::{PipelineConfig, PipelineEnvelope, StageStatus};
+use tracing::info;
+
+use crate::filters::Verdict;
+use crate::{STAGE_NAME, SpamFilterError};
+
+/// Record rejected content and route it to the quarantine queue
+pub async fn quarantine(
+    db: &DatabaseManager,
+    channel: &Channel,
+    envelope: &PipelineEnvelope,
+    message: &IncomingObjectMessage,
+    verdict: Verdict,
+) -> Result<(), SpamFilterError> {
+    let payload = serde_json::to_string(envelope)?;
+    let entry = QuarantineDocument {
+        id: None,
+        quarantine_id: uuid::Uuid::new_v4().to_string(),
+        object_id: message
+            .object
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(|s| s.to_string()),
+        object_type: message.object_type.clone(),
+        actor: message.attributed_to.clone(),
+        target_domain: message.target_domain.clone(),
+        reasons: verdict.reasons,
+        content_hash: verdict.content_hash,
+        payload: payload.clone(),
+        status: QuarantineStatus::Quarantined,
+        reviewed_by: None,
+        created_at: Utc::now(),
+        reviewed_at: None,
+    };
+
+    let mut headers = FieldTable::default();
+    headers.insert(
+        "x-quarantine-id".into(),
+        AMQPValue::LongString(entry.quarantine_id.clone().into()),
+    );
+    headers.insert(
+        "x-spam-reasons".into(),
+        AMQPValue::LongString(entry.reasons.join("; ").into()),
+    );
+
+    channel
+        .basic_publish(
+            "",
+            QUEUE_INCOMING_QUARANTINE,
+            BasicPublishOptions::default(),
+            payload.as_bytes(),
+            BasicProperties::default()
+                .with_delivery_mode(2)
+                .with_headers(headers),
+        )
+        .await?
+        .await?;
+
+    info!(
+        "Quarantined {} {:?} from {}: {}",
+        entry.object_type,
+        entry.object_id,
+        entry.actor,
+        entry.reasons.join("; ")
+    );
+    db.insert_quarantined(entry).await?;
+
+    Ok(())
+}
+
+/// Override the filter and pass quarantined content on to the next stage
+pub async fn release(
+    db: &DatabaseManager,
+    channel: &Channel,
+    config: &PipelineConfig,
+    quarantine_id: &str,
+    moderator: &str,
+) -> Result<QuarantineDocument, SpamFilterError> {
+    let entry = pending_entry(db, quarantine_id).await?;
+
+    let mut envelope = PipelineEnvelope::from_slice(entry.payload.as_bytes())?;
+    envelope.record(
+        STAGE_NAME,
+        StageStatus::Passed,
+        Some(format!("released by {}", moderator)),
+    );
+    if let Some(next) = config.next_stage(STAGE_NAME) {
+        oxifed_pipeline::publish_to_stage(channel, config, next, &envelope).await?;
+    }
+
+    db.review_quarantined(quarantine_id, QuarantineStatus::Released, moderator)
+        .await?;
+    info!(
+        "Quarantine entry {} released by {}",
+        quarantine_id, moderator
+    );
+
+    reviewed_entry(db, quarantine_id).await
+}
+
+/// Confirm the filter decision and drop quarantined content
+pub async fn discard(
+    db: &DatabaseManager,
+    quarantine_id: &str,
+    moderator: &str,
+) -> Result<QuarantineDocument, SpamFilterError> {
+    pending_entry(db, quarantine_id).await?;
+
+    db.review_quarantined(quarantine_id, QuarantineStatus::Discarded, moderator)
+        .await?;
+    info!(
+        "Quarantine entry {} discarded by {}",
+        quarantine_id, moderator
+    );
+
+    reviewed_entry(db, quarantine_id).await
+}
+
+/// Load an entry that is still awaiting review
+async fn pending_entry(
+    db: &DatabaseManager,
+    quarantine_id: &str,
+) -> Result<QuarantineDocument, SpamFilterError> {
+    let entry = db
+        .find_quarantined(quarantine_id)
+        .await?
+        .ok_or_else(|| SpamFilterError::NotFound(quarantine_id.to_string()))?;
+
+    if entry.status != QuarantineStatus::Quarantined {
+        return Err(SpamFilterError::AlreadyReviewed(quarantine_id.to_string()));
+    }
+    Ok(entry)
+}
+
+async fn reviewed_entry(
+    db: &DatabaseManager,
+    quarantine_id: &str,
+) -> Result<QuarantineDocument, SpamFilterError> {
+    db.find_quarantined(quarantine_id)
+        .await?
+        .ok_or_else(|| SpamFilterError::NotFound(quarantine_id.to_string()))
+}
+
+/// Convert a quarantine document into its RPC representation
+pub fn quarantine_info(entry: QuarantineDocument) -> QuarantineInfo {
+    let content = PipelineEnvelope::from_slice(entry.payload.as_bytes())
+        .ok()
+        .and_then(|envelope| {
+            envelope
+                .object()?
+                .object
+                .get("content")
+                .and_then(|c| c.as_str())
+                .map(|s| s.to_string())
+        });
+
+    QuarantineInfo {
+        quarantine_id: entry.quarantine_id,
+        object_id: entry.object_id,
+        object_type: entry.object_type,
+        actor: entry.actor,
+        target_domain: entry.target_domain,
+        reasons: entry.reasons,
+        content,
+        status: match entry.status {
+            QuarantineStatus::Quarantined => "quarantined",
+            QuarantineStatus::Released => "released",
+            QuarantineStatus::Discarded => "discarded",
+        }
+        .to_string(),
+        reviewed_by: entry.reviewed_by,
+        created_at: entry.created_at.to_rfc3339(),
+        reviewed_at: entry.reviewed_at.map(|t| t.to_rfc3339()),
+    }
+}
+
+/// Parse a quarantine status filter
+pub fn parse_status(status: &str) -> Option<QuarantineStatus> {
+    match status {
+        "quarantined" => Some(QuarantineStatus::Quarantined),
+        "released" => Some(QuarantineStatus::Released),
+        "discarded" => Some(QuarantineStatus::Discarded),
+        _ => None,
+    }
+}
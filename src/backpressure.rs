@@ -0,0 +1,171 @@
+//! Backpressure for message queue consumers
+//!
+//! Consumers take an in-flight slot before pulling the next delivery and
+//! hand it to the task processing that delivery. Once every slot is taken
+//! the consumer stops reading; the broker keeps at most the prefetch window
+//! of unacknowledged messages with it and consumption resumes as soon as a
+//! task finishes.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use crate::config::{ConfigError, Env, require_positive};
+
+/// Prefetch and in-flight limits for a consumer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsumerLimits {
+    /// Unacknowledged messages the broker may push to the consumer
+    pub prefetch: u16,
+    /// Deliveries processed concurrently
+    pub max_in_flight: usize,
+}
+
+impl Default for ConsumerLimits {
+    fn default() -> Self {
+        Self::new(16, 4)
+    }
+}
+
+impl ConsumerLimits {
+    pub fn new(prefetch: u16, max_in_flight: usize) -> Self {
+        Self {
+            prefetch: prefetch.max(1),
+            max_in_flight: max_in_flight.max(1),
+        }
+    }
+
+    /// Apply `<PREFIX>_PREFETCH` and `<PREFIX>_MAX_IN_FLIGHT`
+    pub fn apply_env(&mut self, prefix: &str, env: &Env) -> Result<(), ConfigError> {
+        env.set(&format!("{}_PREFETCH", prefix), &mut self.prefetch)?;
+        env.set(
+            &format!("{}_MAX_IN_FLIGHT", prefix),
+            &mut self.max_in_flight,
+        )
+    }
+
+    /// Reject limits of zero, which would stall the consumer
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.prefetch", key), self.prefetch)?;
+        require_positive(&format!("{}.max_in_flight", key), self.max_in_flight)
+    }
+}
+
+/// Bounded set of in-flight deliveries shared by one or more consumers
+#[derive(Debug, Clone)]
+pub struct InFlightLimiter {
+    name: Arc<str>,
+    max: usize,
+    slots: Arc<Semaphore>,
+    paused: Arc<AtomicBool>,
+}
+
+/// Slot held by a delivery while it is processed
+///
+/// Dropping the slot frees it for the next delivery.
+#[derive(Debug)]
+pub struct InFlightSlot {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl InFlightLimiter {
+    pub fn new(name: impl Into<String>, max_in_flight: usize) -> Self {
+        let max = max_in_flight.max(1);
+        Self {
+            name: name.into().into(),
+            max,
+            slots: Arc::new(Semaphore::new(max)),
+            paused: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Wait for a free slot, pausing consumption while the pool is saturated
+    pub async fn acquire(&self) -> InFlightSlot {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return InFlightSlot { _permit: permit };
+        }
+
+        if !self.paused.swap(true, Ordering::Relaxed) {
+            warn!(
+                "{} saturated with {} deliveries in flight, pausing consumption",
+                self.name, self.max
+            );
+        }
+
+        let permit = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("in-flight semaphore is never closed");
+
+        if self.paused.swap(false, Ordering::Relaxed) {
+            info!("{} resumed consumption", self.name);
+        }
+
+        InFlightSlot { _permit: permit }
+    }
+
+    /// Number of deliveries currently being processed
+    pub fn in_flight(&self) -> usize {
+        self.max - self.slots.available_permits()
+    }
+
+    /// Maximum number of deliveries processed at once
+    pub fn capacity(&self) -> usize {
+        self.max
+    }
+
+    /// Whether consumption is paused waiting for a free slot
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_limits_are_at_least_one() {
+        let limits = ConsumerLimits::new(0, 0);
+        assert_eq!(limits.prefetch, 1);
+        assert_eq!(limits.max_in_flight, 1);
+    }
+
+    #[test]
+    fn test_limits_from_env() {
+        let env = Env::from_vars([("CONSUMER_PREFETCH", "32"), ("CONSUMER_MAX_IN_FLIGHT", "0")]);
+        let mut limits = ConsumerLimits::default();
+        limits.apply_env("CONSUMER", &env).unwrap();
+        assert_eq!(limits.prefetch, 32);
+        assert!(limits.validate("consumer").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limiter_pauses_until_slot_is_released() {
+        let limiter = InFlightLimiter::new("test consumer", 2);
+
+        let first = limiter.acquire().await;
+        let _second = limiter.acquire().await;
+        assert_eq!(limiter.in_flight(), 2);
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(limiter.is_paused());
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        let _third = waiting.await.unwrap();
+        assert!(!limiter.is_paused());
+        assert_eq!(limiter.in_flight(), 2);
+    }
+}
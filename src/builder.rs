@@ -0,0 +1,630 @@
+//! Builders for the core ActivityPub types
+//!
+//! [`Activity::builder`] and [`Object::builder`] assemble activities and
+//! objects without spelling out every optional field or the addressing
+//! properties kept in `additional_properties`. URLs are taken as strings and
+//! parsed by `build`, which also checks the fields the specification
+//! requires:
+//!
+//! ```
+//! use oxifed::{Activity, Object, ObjectType};
+//!
+//! let note = Object::builder(ObjectType::Note)
+//!     .id("https://example.com/objects/1")
+//!     .attributed_to("https://example.com/users/alice")
+//!     .content("<p>Hello</p>")
+//!     .public()
+//!     .build()
+//!     .unwrap();
+//!
+//! let create = Activity::builder()
+//!     .create("https://example.com/users/alice", note)
+//!     .to("https://www.w3.org/ns/activitystreams#Public")
+//!     .build()
+//!     .unwrap();
+//! # assert!(create.object.is_some());
+//! ```
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use thiserror::Error;
+use url::Url;
+
+use crate::extensions::Extensions;
+use crate::language::{self, LanguageMap};
+use crate::{Activity, ActivityType, Object, ObjectOrLink, ObjectType};
+
+/// Addressing collection of public objects
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Errors building an activity or object
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("Missing required field: {0}")]
+    MissingField(&'static str),
+
+    #[error("Invalid language tag: {0}")]
+    InvalidLanguage(String),
+
+    #[error("Invalid URL in {field}: {value}: {source}")]
+    InvalidUrl {
+        field: &'static str,
+        value: String,
+        source: url::ParseError,
+    },
+}
+
+fn parse_url(field: &'static str, value: &str) -> Result<Url, BuildError> {
+    Url::parse(value).map_err(|source| BuildError::InvalidUrl {
+        field,
+        value: value.to_string(),
+        source,
+    })
+}
+
+/// Reference to an object: a URL to parse or an embedded object
+enum Reference {
+    Url(String),
+    Object(Box<Object>),
+}
+
+impl Reference {
+    fn resolve(self, field: &'static str) -> Result<ObjectOrLink, BuildError> {
+        match self {
+            Reference::Url(url) => Ok(ObjectOrLink::Url(parse_url(field, &url)?)),
+            Reference::Object(object) => Ok(ObjectOrLink::Object(object)),
+        }
+    }
+}
+
+/// Addressing and other properties shared by both builders
+#[derive(Default)]
+struct Properties {
+    context: bool,
+    addressing: Vec<(&'static str, String)>,
+    extra: HashMap<String, Value>,
+}
+
+impl Properties {
+    fn address(&mut self, field: &'static str, recipient: impl Into<String>) {
+        self.addressing.push((field, recipient.into()));
+    }
+
+    /// Validate the recipients and collect the additional properties
+    fn build(self) -> Result<HashMap<String, Value>, BuildError> {
+        let mut properties = self.extra;
+        if self.context {
+            properties.insert(
+                "@context".to_string(),
+                json!("https://www.w3.org/ns/activitystreams"),
+            );
+        }
+
+        for (field, recipient) in self.addressing {
+            parse_url(field, &recipient)?;
+            let recipients = properties
+                .entry(field.to_string())
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(recipients) = recipients
+                && !recipients.iter().any(|r| r.as_str() == Some(&recipient))
+            {
+                recipients.push(Value::String(recipient));
+            }
+        }
+        Ok(properties)
+    }
+}
+
+/// Builder for [`Activity`]
+///
+/// `build` requires a type and an actor, and an object for all types other
+/// than the intransitive `Arrive`, `Travel` and `Question`.
+#[must_use]
+#[derive(Default)]
+pub struct ActivityBuilder {
+    activity_type: Option<ActivityType>,
+    id: Option<String>,
+    name: Option<String>,
+    summary: Option<String>,
+    actor: Option<String>,
+    object: Option<Reference>,
+    target: Option<Reference>,
+    published: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    properties: Properties,
+}
+
+impl Activity {
+    /// Start building an activity
+    pub fn builder() -> ActivityBuilder {
+        ActivityBuilder::default()
+    }
+}
+
+impl ActivityBuilder {
+    /// Set the type, actor and object URL in one go
+    fn typed(mut self, activity_type: ActivityType, actor: &str, object: Reference) -> Self {
+        self.activity_type = Some(activity_type);
+        self.actor = Some(actor.to_string());
+        self.object = Some(object);
+        self
+    }
+
+    /// `actor` follows the actor at `object`
+    pub fn follow(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Follow, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` likes `object`
+    pub fn like(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Like, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` boosts `object`
+    pub fn announce(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Announce, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` blocks the actor at `object`
+    pub fn block(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Block, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` deletes `object`
+    pub fn delete(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Delete, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` creates `object`
+    pub fn create(self, actor: &str, object: Object) -> Self {
+        self.typed(
+            ActivityType::Create,
+            actor,
+            Reference::Object(Box::new(object)),
+        )
+    }
+
+    /// `actor` updates `object`
+    pub fn update(self, actor: &str, object: Object) -> Self {
+        self.typed(
+            ActivityType::Update,
+            actor,
+            Reference::Object(Box::new(object)),
+        )
+    }
+
+    /// `actor` accepts the activity at `object`, usually a Follow
+    pub fn accept(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Accept, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` rejects the activity at `object`, usually a Follow
+    pub fn reject(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Reject, actor, Reference::Url(object.into()))
+    }
+
+    /// `actor` undoes its earlier activity at `object`
+    pub fn undo(self, actor: &str, object: &str) -> Self {
+        self.typed(ActivityType::Undo, actor, Reference::Url(object.into()))
+    }
+
+    /// Set the activity type
+    pub fn activity_type(mut self, activity_type: ActivityType) -> Self {
+        self.activity_type = Some(activity_type);
+        self
+    }
+
+    /// Set the actor performing the activity
+    pub fn actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    /// Set the object by URL
+    pub fn object_url(mut self, object: &str) -> Self {
+        self.object = Some(Reference::Url(object.to_string()));
+        self
+    }
+
+    /// Embed the object
+    pub fn object(mut self, object: Object) -> Self {
+        self.object = Some(Reference::Object(Box::new(object)));
+        self
+    }
+
+    /// Set the target by URL
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(Reference::Url(target.to_string()));
+        self
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    pub fn published(mut self, published: DateTime<Utc>) -> Self {
+        self.published = Some(published);
+        self
+    }
+
+    pub fn updated(mut self, updated: DateTime<Utc>) -> Self {
+        self.updated = Some(updated);
+        self
+    }
+
+    /// Add a primary recipient
+    pub fn to(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("to", recipient);
+        self
+    }
+
+    /// Add a secondary recipient
+    pub fn cc(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("cc", recipient);
+        self
+    }
+
+    /// Add a hidden primary recipient
+    pub fn bto(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("bto", recipient);
+        self
+    }
+
+    /// Add a hidden secondary recipient
+    pub fn bcc(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("bcc", recipient);
+        self
+    }
+
+    /// Add the ActivityStreams `@context`, for activities sent on their own
+    pub fn with_context(mut self) -> Self {
+        self.properties.context = true;
+        self
+    }
+
+    /// Set any other property
+    pub fn property(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.properties.extra.insert(key.into(), value);
+        self
+    }
+
+    /// Validate the fields and build the activity
+    pub fn build(self) -> Result<Activity, BuildError> {
+        let activity_type = self.activity_type.ok_or(BuildError::MissingField("type"))?;
+        let actor = self.actor.ok_or(BuildError::MissingField("actor"))?;
+        let intransitive = matches!(
+            activity_type,
+            ActivityType::Arrive | ActivityType::Travel | ActivityType::Question
+        );
+        if !intransitive && self.object.is_none() {
+            return Err(BuildError::MissingField("object"));
+        }
+
+        Ok(Activity {
+            activity_type,
+            id: self.id.map(|id| parse_url("id", &id)).transpose()?,
+            name: self.name,
+            summary: self.summary,
+            actor: Some(ObjectOrLink::Url(parse_url("actor", &actor)?)),
+            object: self
+                .object
+                .map(|object| object.resolve("object"))
+                .transpose()?,
+            target: self
+                .target
+                .map(|target| target.resolve("target"))
+                .transpose()?,
+            published: self.published,
+            updated: self.updated,
+            additional_properties: self.properties.build()?,
+        })
+    }
+}
+
+/// Builder for [`Object`]
+///
+/// `build` checks that all URLs parse; objects embedded in activities need
+/// no other fields.
+#[must_use]
+pub struct ObjectBuilder {
+    object_type: ObjectType,
+    id: Option<String>,
+    name: Option<String>,
+    summary: Option<String>,
+    content: Option<String>,
+    url: Option<String>,
+    published: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    attributed_to: Option<String>,
+    in_reply_to: Option<String>,
+    language: Option<String>,
+    extensions: Option<Extensions>,
+    properties: Properties,
+}
+
+impl Object {
+    /// Start building an object of the given type
+    pub fn builder(object_type: ObjectType) -> ObjectBuilder {
+        ObjectBuilder {
+            object_type,
+            id: None,
+            name: None,
+            summary: None,
+            content: None,
+            url: None,
+            published: None,
+            updated: None,
+            attributed_to: None,
+            in_reply_to: None,
+            language: None,
+            extensions: None,
+            properties: Properties::default(),
+        }
+    }
+}
+
+impl ObjectBuilder {
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the summary, shown as content warning by most software
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// Set the HTML content
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    pub fn published(mut self, published: DateTime<Utc>) -> Self {
+        self.published = Some(published);
+        self
+    }
+
+    pub fn updated(mut self, updated: DateTime<Utc>) -> Self {
+        self.updated = Some(updated);
+        self
+    }
+
+    /// Set the author
+    pub fn attributed_to(mut self, actor: &str) -> Self {
+        self.attributed_to = Some(actor.to_string());
+        self
+    }
+
+    /// Set the object this one replies to
+    pub fn in_reply_to(mut self, object: &str) -> Self {
+        self.in_reply_to = Some(object.to_string());
+        self
+    }
+
+    /// Set the language of the name, summary and content
+    ///
+    /// They are also added to `nameMap`, `summaryMap` and `contentMap`.
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Set extension properties such as `sensitive` and hashtags
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = Some(extensions);
+        self
+    }
+
+    /// Address the object to the public
+    pub fn public(self) -> Self {
+        self.to(PUBLIC)
+    }
+
+    /// Add a primary recipient
+    pub fn to(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("to", recipient);
+        self
+    }
+
+    /// Add a secondary recipient
+    pub fn cc(mut self, recipient: impl Into<String>) -> Self {
+        self.properties.address("cc", recipient);
+        self
+    }
+
+    /// Add the ActivityStreams `@context`, for objects served on their own
+    pub fn with_context(mut self) -> Self {
+        self.properties.context = true;
+        self
+    }
+
+    /// Set any other property
+    pub fn property(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.properties.extra.insert(key.into(), value);
+        self
+    }
+
+    /// Validate the fields and build the object
+    pub fn build(self) -> Result<Object, BuildError> {
+        let mut additional_properties = self.properties.build()?;
+        if let Some(in_reply_to) = self.in_reply_to {
+            parse_url("inReplyTo", &in_reply_to)?;
+            additional_properties.insert("inReplyTo".to_string(), Value::String(in_reply_to));
+        }
+        if let Some(extensions) = &self.extensions {
+            extensions.write_properties(&mut additional_properties);
+        }
+
+        let mut name_map = None;
+        let mut summary_map = None;
+        let mut content_map = None;
+        if let Some(language) = self.language {
+            if !language::is_language_tag(&language) {
+                return Err(BuildError::InvalidLanguage(language));
+            }
+            let map_of = |text: &Option<String>| {
+                text.as_ref()
+                    .map(|text| LanguageMap::from([(language.clone(), text.clone())]))
+            };
+            name_map = map_of(&self.name);
+            summary_map = map_of(&self.summary);
+            content_map = map_of(&self.content);
+        }
+
+        Ok(Object {
+            object_type: self.object_type,
+            id: self.id.map(|id| parse_url("id", &id)).transpose()?,
+            name: self.name,
+            name_map,
+            summary: self.summary,
+            summary_map,
+            content: self.content,
+            content_map,
+            url: self.url.map(|url| parse_url("url", &url)).transpose()?,
+            published: self.published,
+            updated: self.updated,
+            attributed_to: self
+                .attributed_to
+                .map(|actor| parse_url("attributedTo", &actor).map(ObjectOrLink::Url))
+                .transpose()?,
+            additional_properties,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_follow() {
+        let follow = Activity::builder()
+            .follow(
+                "https://example.com/users/alice",
+                "https://remote.example/users/bob",
+            )
+            .id("https://example.com/activities/1")
+            .to("https://remote.example/users/bob")
+            .with_context()
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&follow).unwrap();
+        assert_eq!(json["type"], "Follow");
+        assert_eq!(json["actor"], "https://example.com/users/alice");
+        assert_eq!(json["object"], "https://remote.example/users/bob");
+        assert_eq!(json["to"], json!(["https://remote.example/users/bob"]));
+        assert_eq!(json["@context"], "https://www.w3.org/ns/activitystreams");
+    }
+
+    #[test]
+    fn test_build_requires_actor_and_object() {
+        let missing_actor = Activity::builder()
+            .activity_type(ActivityType::Like)
+            .object_url("https://example.com/objects/1")
+            .build();
+        assert!(matches!(
+            missing_actor,
+            Err(BuildError::MissingField("actor"))
+        ));
+
+        let missing_object = Activity::builder()
+            .activity_type(ActivityType::Like)
+            .actor("https://example.com/users/alice")
+            .build();
+        assert!(matches!(
+            missing_object,
+            Err(BuildError::MissingField("object"))
+        ));
+
+        let arrive = Activity::builder()
+            .activity_type(ActivityType::Arrive)
+            .actor("https://example.com/users/alice")
+            .build();
+        assert!(arrive.is_ok());
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_urls() {
+        let result = Activity::builder()
+            .like("https://example.com/users/alice", "not a url")
+            .build();
+        assert!(matches!(
+            result,
+            Err(BuildError::InvalidUrl {
+                field: "object",
+                ..
+            })
+        ));
+
+        let result = Object::builder(ObjectType::Note).cc("alice").build();
+        assert!(matches!(
+            result,
+            Err(BuildError::InvalidUrl { field: "cc", .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_note_in_create() {
+        let note = Object::builder(ObjectType::Note)
+            .attributed_to("https://example.com/users/alice")
+            .content("<p>Hi</p>")
+            .language("en")
+            .in_reply_to("https://remote.example/objects/9")
+            .public()
+            .cc("https://example.com/users/alice/followers")
+            .extensions(Extensions {
+                sensitive: Some(true),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let create = Activity::builder()
+            .create("https://example.com/users/alice", note)
+            .to(PUBLIC)
+            .cc("https://example.com/users/alice/followers")
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(&create).unwrap();
+        assert_eq!(json["object"]["type"], "Note");
+        assert_eq!(json["object"]["to"], json!([PUBLIC]));
+        assert_eq!(json["object"]["sensitive"], true);
+        assert_eq!(json["object"]["contentMap"], json!({ "en": "<p>Hi</p>" }));
+        assert_eq!(
+            json["object"]["inReplyTo"],
+            "https://remote.example/objects/9"
+        );
+        assert_eq!(json["to"], json!([PUBLIC]));
+        assert_eq!(
+            json["cc"],
+            json!(["https://example.com/users/alice/followers"])
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Message bus abstraction
+//!
+//! [`MessageBus`] offers publishing to an exchange with a routing key,
+//! consuming a queue bound to exchanges, and request/reply RPC.
+//! [`AmqpBus`] runs it on LavinMQ/RabbitMQ through lapin, [`NatsBus`] on
+//! NATS JetStream. [`connect`] picks the backend named in [`BusConfig`].
+//!
+//! Only email goes through the bus: the exchanges in [`BUS_EXCHANGES`],
+//! which is `oxifed.email` alone, published by the domainservd outbox relay
+//! and retried from its DLQ, and consumed by maild. Every other exchange,
+//! RPC included, is used over AMQP directly, so the AMQP broker stays
+//! required with either backend and NATS only replaces it for email.
+//!
+//! Exchanges and routing keys keep their AMQP names on every backend; see
+//! [`NatsBus`] for how they map onto streams and subjects.
+
+mod amqp;
+mod nats;
+
+pub use amqp::AmqpBus;
+pub use nats::NatsBus;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::{AmqpConfig, ConfigError, Env};
+use crate::messaging::{EXCHANGE_EMAIL, QUEUE_EMAIL};
+
+/// Default NATS connection string for local development
+pub const DEFAULT_NATS_URL: &str = "nats://localhost:4222";
+
+/// Exchanges carried by the configured bus instead of plain AMQP, with the
+/// durable queue consuming each: email only
+pub const BUS_EXCHANGES: &[(&str, &str)] = &[(EXCHANGE_EMAIL, QUEUE_EMAIL)];
+
+/// Whether messages on an exchange go through the configured bus
+pub fn is_bus_exchange(exchange: &str) -> bool {
+    BUS_EXCHANGES.iter().any(|(name, _)| *name == exchange)
+}
+
+/// Bus exchange feeding a queue, if the queue is consumed through the bus
+pub fn bus_exchange_of_queue(queue: &str) -> Option<&'static str> {
+    BUS_EXCHANGES
+        .iter()
+        .find(|(_, name)| *name == queue)
+        .map(|(exchange, _)| *exchange)
+}
+
+/// Message bus errors
+#[derive(Error, Debug)]
+pub enum BusError {
+    #[error("AMQP error: {0}")]
+    Amqp(#[from] lapin::Error),
+
+    #[error("NATS error: {0}")]
+    Nats(String),
+
+    #[error("Broker rejected the message")]
+    Rejected,
+
+    #[error("No reply within {0:?}")]
+    Timeout(Duration),
+
+    #[error("Invalid binding {0:?}: {1}")]
+    InvalidBinding(String, String),
+
+    #[error("Message cannot be replied to")]
+    NoReplyTo,
+
+    #[error("Connection closed")]
+    Closed,
+}
+
+impl BusError {
+    pub(crate) fn nats(error: impl fmt::Display) -> Self {
+        BusError::Nats(error.to_string())
+    }
+}
+
+/// Messaging backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BusBackend {
+    /// LavinMQ or RabbitMQ
+    #[default]
+    Amqp,
+    /// NATS with JetStream enabled
+    Nats,
+}
+
+impl FromStr for BusBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "amqp" => Ok(BusBackend::Amqp),
+            "nats" => Ok(BusBackend::Nats),
+            other => Err(format!("unknown message bus {:?}", other)),
+        }
+    }
+}
+
+/// Message bus settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BusConfig {
+    pub backend: BusBackend,
+    /// NATS server, used with the `nats` backend
+    pub nats_url: String,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            backend: BusBackend::default(),
+            nats_url: DEFAULT_NATS_URL.to_string(),
+        }
+    }
+}
+
+impl BusConfig {
+    /// Apply `MESSAGE_BUS` and `NATS_URL`
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("MESSAGE_BUS", &mut self.backend)?;
+        env.set("NATS_URL", &mut self.nats_url)
+    }
+}
+
+/// Connect to the configured backend
+///
+/// The AMQP backend opens a connection of its own to `amqp.url`.
+pub async fn connect(
+    config: &BusConfig,
+    amqp: &AmqpConfig,
+) -> Result<Arc<dyn MessageBus>, BusError> {
+    Ok(match config.backend {
+        BusBackend::Amqp => Arc::new(AmqpBus::connect(&amqp.url).await?),
+        BusBackend::Nats => Arc::new(NatsBus::connect(&config.nats_url).await?),
+    })
+}
+
+/// How an exchange routes messages to the queues bound to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExchangeType {
+    /// Every bound queue gets every message
+    Fanout,
+    /// Queues get messages whose routing key equals their binding key
+    Direct,
+    /// Binding keys are patterns where `*` matches one dot-separated word
+    /// and `#` any number of them
+    Topic,
+}
+
+/// Binding of a queue to an exchange
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub exchange: String,
+    pub kind: ExchangeType,
+    /// Binding key, ignored for fanout exchanges
+    pub routing_key: String,
+}
+
+impl Binding {
+    /// Binding to a fanout exchange
+    pub fn fanout(exchange: &str) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+            kind: ExchangeType::Fanout,
+            routing_key: String::new(),
+        }
+    }
+}
+
+/// Queue to consume and the bindings that feed it
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    /// Queue name; consumers of the same queue compete for its messages.
+    /// May be empty for a non-durable queue of this consumer only.
+    pub queue: String,
+    pub bindings: Vec<Binding>,
+    /// Unacknowledged messages delivered at once
+    pub prefetch: u16,
+    /// Whether messages wait in the queue while no consumer is running
+    pub durable: bool,
+}
+
+/// Message to publish
+#[derive(Debug, Clone, Default)]
+pub struct OutgoingMessage {
+    pub payload: Vec<u8>,
+    pub message_id: Option<String>,
+    pub content_type: Option<String>,
+    /// Application headers, including trace propagation entries
+    pub headers: HashMap<String, String>,
+}
+
+impl OutgoingMessage {
+    pub fn new(payload: Vec<u8>) -> Self {
+        Self {
+            payload,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_message_id(mut self, message_id: impl Into<String>) -> Self {
+        self.message_id = Some(message_id.into());
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+}
+
+/// Settles a delivery with the broker it came from
+pub(crate) trait Acker: Send + Sync {
+    fn ack(&self) -> BoxFuture<'_, Result<(), BusError>>;
+    fn nack(&self, requeue: bool) -> BoxFuture<'_, Result<(), BusError>>;
+    fn reply(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<(), BusError>>;
+}
+
+/// A consumed message
+pub struct BusDelivery {
+    pub payload: Vec<u8>,
+    pub message_id: Option<String>,
+    pub content_type: Option<String>,
+    pub headers: HashMap<String, String>,
+    acker: Box<dyn Acker>,
+}
+
+impl fmt::Debug for BusDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BusDelivery")
+            .field("message_id", &self.message_id)
+            .field("content_type", &self.content_type)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BusDelivery {
+    /// Mark the message processed
+    pub async fn ack(&self) -> Result<(), BusError> {
+        self.acker.ack().await
+    }
+
+    /// Give the message back for redelivery, or drop it
+    pub async fn nack(&self, requeue: bool) -> Result<(), BusError> {
+        self.acker.nack(requeue).await
+    }
+
+    /// Answer an RPC request
+    pub async fn reply(&self, payload: Vec<u8>) -> Result<(), BusError> {
+        self.acker.reply(payload).await
+    }
+}
+
+/// Messages of a subscription, until the connection closes
+pub type BusConsumer = BoxStream<'static, Result<BusDelivery, BusError>>;
+
+/// Publishing, consuming and RPC over a message broker
+pub trait MessageBus: Send + Sync {
+    /// Publish a message and wait until the broker has stored it
+    fn publish<'a>(
+        &'a self,
+        exchange: &'a str,
+        routing_key: &'a str,
+        message: &'a OutgoingMessage,
+    ) -> BoxFuture<'a, Result<(), BusError>>;
+
+    /// Set up a queue with its bindings and consume it
+    fn subscribe<'a>(
+        &'a self,
+        subscription: &'a Subscription,
+    ) -> BoxFuture<'a, Result<BusConsumer, BusError>>;
+
+    /// Send an RPC request on the RPC request exchange and wait for the reply
+    fn request<'a>(
+        &'a self,
+        routing_key: &'a str,
+        payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<Vec<u8>, BusError>>;
+
+    /// Close the connection
+    fn close(&self) -> BoxFuture<'_, Result<(), BusError>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_config() {
+        let mut config = BusConfig::default();
+        assert_eq!(config.backend, BusBackend::Amqp);
+
+        let env = Env::from_vars([("MESSAGE_BUS", "NATS"), ("NATS_URL", "nats://nats:4222")]);
+        config.apply_env(&env).unwrap();
+        assert_eq!(config.backend, BusBackend::Nats);
+        assert_eq!(config.nats_url, "nats://nats:4222");
+
+        let env = Env::from_vars([("MESSAGE_BUS", "kafka")]);
+        assert!(config.apply_env(&env).is_err());
+    }
+
+    #[test]
+    fn test_bus_exchanges() {
+        assert!(is_bus_exchange(EXCHANGE_EMAIL));
+        assert!(!is_bus_exchange(
+            crate::messaging::EXCHANGE_ACTIVITYPUB_DELIVERY
+        ));
+        assert_eq!(bus_exchange_of_queue(QUEUE_EMAIL), Some(EXCHANGE_EMAIL));
+        assert_eq!(bus_exchange_of_queue("oxifed.push"), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! LavinMQ/RabbitMQ backend
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use lapin::{
+    BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind,
+    acker::Acker as LapinAcker,
+    message::Delivery,
+    options::*,
+    types::{AMQPValue, FieldTable, ShortString},
+};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{
+    Acker, BusConsumer, BusDelivery, BusError, ExchangeType, MessageBus, OutgoingMessage,
+    Subscription,
+};
+use crate::messaging::EXCHANGE_RPC_REQUEST;
+
+/// Message bus on an AMQP 0.9.1 broker
+///
+/// Publishes go through one channel with publisher confirms, reopened after
+/// a failure. Each subscription consumes on a channel of its own. Exchanges
+/// and queues are declared durable without arguments.
+pub struct AmqpBus {
+    connection: Connection,
+    publisher: Mutex<Option<Channel>>,
+}
+
+impl AmqpBus {
+    pub async fn connect(url: &str) -> Result<Self, BusError> {
+        let connection = Connection::connect(url, ConnectionProperties::default()).await?;
+        Ok(Self {
+            connection,
+            publisher: Mutex::new(None),
+        })
+    }
+
+    async fn publish_confirmed(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        message: &OutgoingMessage,
+    ) -> Result<(), BusError> {
+        let mut publisher = self.publisher.lock().await;
+        let channel = match publisher.as_ref() {
+            Some(channel) if channel.status().connected() => channel.clone(),
+            _ => {
+                let channel = self.connection.create_channel().await?;
+                channel
+                    .confirm_select(ConfirmSelectOptions::default())
+                    .await?;
+                *publisher = Some(channel.clone());
+                channel
+            }
+        };
+
+        let confirmation = channel
+            .basic_publish(
+                exchange,
+                routing_key,
+                BasicPublishOptions::default(),
+                &message.payload,
+                properties(message),
+            )
+            .await;
+        let confirmation = match confirmation {
+            Ok(pending) => pending.await,
+            Err(e) => Err(e),
+        };
+        match confirmation {
+            Ok(confirmation) if confirmation.is_nack() => Err(BusError::Rejected),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                *publisher = None;
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn consume(&self, subscription: &Subscription) -> Result<BusConsumer, BusError> {
+        let channel = self.connection.create_channel().await?;
+        channel
+            .basic_qos(subscription.prefetch, BasicQosOptions::default())
+            .await?;
+
+        let queue = channel
+            .queue_declare(
+                &subscription.queue,
+                QueueDeclareOptions {
+                    durable: subscription.durable,
+                    exclusive: !subscription.durable,
+                    auto_delete: !subscription.durable,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?
+            .name()
+            .to_string();
+
+        for binding in &subscription.bindings {
+            let kind = match binding.kind {
+                ExchangeType::Fanout => ExchangeKind::Fanout,
+                ExchangeType::Direct => ExchangeKind::Direct,
+                ExchangeType::Topic => ExchangeKind::Topic,
+            };
+            channel
+                .exchange_declare(
+                    &binding.exchange,
+                    kind,
+                    ExchangeDeclareOptions {
+                        durable: true,
+                        ..Default::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await?;
+            channel
+                .queue_bind(
+                    &queue,
+                    &binding.exchange,
+                    &binding.routing_key,
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+        }
+
+        let consumer = channel
+            .basic_consume(
+                &queue,
+                "",
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        Ok(consumer
+            .map(move |delivery| {
+                delivery
+                    .map(|delivery| to_bus_delivery(channel.clone(), delivery))
+                    .map_err(BusError::from)
+            })
+            .boxed())
+    }
+
+    async fn request_reply(
+        &self,
+        routing_key: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, BusError> {
+        let channel = self.connection.create_channel().await?;
+        let reply_queue = channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?
+            .name()
+            .to_string();
+        let mut consumer = channel
+            .basic_consume(
+                &reply_queue,
+                "",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        let correlation_id = Uuid::new_v4().to_string();
+        channel
+            .basic_publish(
+                EXCHANGE_RPC_REQUEST,
+                routing_key,
+                BasicPublishOptions::default(),
+                payload,
+                BasicProperties::default()
+                    .with_reply_to(reply_queue.into())
+                    .with_correlation_id(correlation_id.clone().into()),
+            )
+            .await?;
+
+        let reply = tokio::time::timeout(timeout, async {
+            while let Some(delivery) = consumer.next().await {
+                let delivery = delivery?;
+                if delivery
+                    .properties
+                    .correlation_id()
+                    .as_ref()
+                    .is_some_and(|id| id.as_str() == correlation_id)
+                {
+                    return Ok(Some(delivery.data));
+                }
+            }
+            Ok::<_, BusError>(None)
+        })
+        .await;
+        if let Err(e) = channel.close(200, "reply received").await {
+            tracing::debug!("Failed to close RPC channel: {}", e);
+        }
+
+        match reply {
+            Ok(Ok(Some(data))) => Ok(data),
+            Ok(Ok(None)) => Err(BusError::Closed),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(BusError::Timeout(timeout)),
+        }
+    }
+}
+
+impl MessageBus for AmqpBus {
+    fn publish<'a>(
+        &'a self,
+        exchange: &'a str,
+        routing_key: &'a str,
+        message: &'a OutgoingMessage,
+    ) -> BoxFuture<'a, Result<(), BusError>> {
+        Box::pin(self.publish_confirmed(exchange, routing_key, message))
+    }
+
+    fn subscribe<'a>(
+        &'a self,
+        subscription: &'a Subscription,
+    ) -> BoxFuture<'a, Result<BusConsumer, BusError>> {
+        Box::pin(self.consume(subscription))
+    }
+
+    fn request<'a>(
+        &'a self,
+        routing_key: &'a str,
+        payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<Vec<u8>, BusError>> {
+        Box::pin(self.request_reply(routing_key, payload, timeout))
+    }
+
+    fn close(&self) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move {
+            self.connection.close(200, "shutdown").await?;
+            Ok(())
+        })
+    }
+}
+
+/// Persistent message properties carrying the message's metadata
+fn properties(message: &OutgoingMessage) -> BasicProperties {
+    let mut headers = FieldTable::default();
+    for (key, value) in &message.headers {
+        headers.insert(
+            ShortString::from(key.clone()),
+            AMQPValue::LongString(value.clone().into()),
+        );
+    }
+
+    let mut properties = BasicProperties::default()
+        .with_delivery_mode(2)
+        .with_headers(headers);
+    if let Some(message_id) = &message.message_id {
+        properties = properties.with_message_id(message_id.clone().into());
+    }
+    if let Some(content_type) = &message.content_type {
+        properties = properties.with_content_type(content_type.clone().into());
+    }
+    properties
+}
+
+fn to_bus_delivery(channel: Channel, delivery: Delivery) -> BusDelivery {
+    let properties = &delivery.properties;
+    let headers = properties
+        .headers()
+        .as_ref()
+        .map(|headers| {
+            headers
+                .inner()
+                .iter()
+                .filter_map(|(key, value)| Some((key.to_string(), header_string(value)?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    BusDelivery {
+        message_id: properties.message_id().as_ref().map(|id| id.to_string()),
+        content_type: properties.content_type().as_ref().map(|ct| ct.to_string()),
+        headers,
+        acker: Box::new(AmqpAcker {
+            channel,
+            reply_to: properties.reply_to().clone(),
+            correlation_id: properties.correlation_id().clone(),
+            acker: delivery.acker,
+        }),
+        payload: delivery.data,
+    }
+}
+
+/// Text of a string, number or boolean header
+fn header_string(value: &AMQPValue) -> Option<String> {
+    Some(match value {
+        AMQPValue::ShortString(s) => s.to_string(),
+        AMQPValue::LongString(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        AMQPValue::Boolean(v) => v.to_string(),
+        AMQPValue::ShortShortInt(v) => v.to_string(),
+        AMQPValue::ShortShortUInt(v) => v.to_string(),
+        AMQPValue::ShortInt(v) => v.to_string(),
+        AMQPValue::ShortUInt(v) => v.to_string(),
+        AMQPValue::LongInt(v) => v.to_string(),
+        AMQPValue::LongUInt(v) => v.to_string(),
+        AMQPValue::LongLongInt(v) => v.to_string(),
+        _ => return None,
+    })
+}
+
+struct AmqpAcker {
+    channel: Channel,
+    reply_to: Option<ShortString>,
+    correlation_id: Option<ShortString>,
+    acker: LapinAcker,
+}
+
+impl Acker for AmqpAcker {
+    fn ack(&self) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move {
+            self.acker.ack(BasicAckOptions::default()).await?;
+            Ok(())
+        })
+    }
+
+    fn nack(&self, requeue: bool) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move {
+            self.acker
+                .nack(BasicNackOptions {
+                    requeue,
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn reply(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move {
+            let reply_to = self.reply_to.as_ref().ok_or(BusError::NoReplyTo)?;
+            let mut properties = BasicProperties::default();
+            if let Some(correlation_id) = &self.correlation_id {
+                properties = properties.with_correlation_id(correlation_id.clone());
+            }
+            self.channel
+                .basic_publish(
+                    "",
+                    reply_to.as_str(),
+                    BasicPublishOptions::default(),
+                    &payload,
+                    properties,
+                )
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_properties_carry_metadata() {
+        let message = OutgoingMessage::new(b"{}".to_vec())
+            .with_message_id("m-1")
+            .with_content_type("application/json")
+            .with_headers(HashMap::from([(
+                "traceparent".to_string(),
+                "00-abc-def-01".to_string(),
+            )]));
+        let properties = properties(&message);
+
+        assert_eq!(properties.delivery_mode(), &Some(2));
+        assert_eq!(
+            properties.message_id().as_ref().map(|id| id.as_str()),
+            Some("m-1")
+        );
+        let headers = properties.headers().as_ref().unwrap();
+        assert_eq!(
+            headers.inner().get("traceparent").and_then(header_string),
+            Some("00-abc-def-01".to_string())
+        );
+        assert_eq!(
+            header_string(&AMQPValue::LongLongInt(3)),
+            Some("3".to_string())
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! NATS JetStream backend
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_nats::jetstream::{self, AckKind, consumer::pull, stream};
+use async_nats::{Client, ConnectOptions, HeaderMap, Subject};
+use futures::future::BoxFuture;
+use futures::stream::{self as stream_ext, StreamExt};
+
+use super::{
+    Acker, Binding, BusConsumer, BusDelivery, BusError, ExchangeType, MessageBus, OutgoingMessage,
+    Subscription,
+};
+use crate::messaging::EXCHANGE_RPC_REQUEST;
+
+/// How long a stream keeps messages, consumed or not
+const STREAM_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Header JetStream deduplicates published messages by
+const HEADER_MESSAGE_ID: &str = "Nats-Msg-Id";
+
+const HEADER_CONTENT_TYPE: &str = "Content-Type";
+
+/// Message bus on NATS with JetStream
+///
+/// Every exchange is a stream named after it with the dots replaced by
+/// underscores. A message published with routing key `key` gets the
+/// subject `<exchange>.msg.key`, or `<exchange>.msg` without a key. Durable
+/// queues are durable pull consumers named after the queue, one per stream
+/// they are bound to, whose filter subjects follow the bindings: fanout
+/// bindings take the whole stream, direct bindings their key, and topic
+/// bindings their pattern with a trailing `#` turned into `>`. Streams keep
+/// messages for a week, so a queue created late still receives what was
+/// published before.
+///
+/// Non-durable queues and RPC use core NATS subscriptions and requests
+/// instead, which see only messages published while they run.
+pub struct NatsBus {
+    client: Client,
+    jetstream: jetstream::Context,
+    /// Streams known to exist
+    streams: Mutex<HashSet<String>>,
+}
+
+impl NatsBus {
+    pub async fn connect(url: &str) -> Result<Self, BusError> {
+        // Requests time out through `MessageBus::request` instead
+        let client = ConnectOptions::new()
+            .request_timeout(None)
+            .connect(url)
+            .await
+            .map_err(BusError::nats)?;
+        Ok(Self {
+            jetstream: jetstream::new(client.clone()),
+            client,
+            streams: Mutex::new(HashSet::new()),
+        })
+    }
+
+    async fn stream(&self, exchange: &str) -> Result<stream::Stream, BusError> {
+        let name = stream_name(exchange);
+        let stream = self
+            .jetstream
+            .get_or_create_stream(stream::Config {
+                name: name.clone(),
+                subjects: exchange_subjects(exchange),
+                max_age: STREAM_MAX_AGE,
+                ..Default::default()
+            })
+            .await
+            .map_err(BusError::nats)?;
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name);
+        Ok(stream)
+    }
+
+    async fn publish_acked(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        message: &OutgoingMessage,
+    ) -> Result<(), BusError> {
+        let known = self
+            .streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&stream_name(exchange));
+        if !known {
+            self.stream(exchange).await?;
+        }
+
+        let ack = self
+            .jetstream
+            .publish_with_headers(
+                subject(exchange, routing_key),
+                headers(message),
+                message.payload.clone().into(),
+            )
+            .await
+            .map_err(BusError::nats)?;
+        ack.await.map_err(BusError::nats)?;
+        Ok(())
+    }
+
+    async fn consume(&self, subscription: &Subscription) -> Result<BusConsumer, BusError> {
+        let mut filters: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for binding in &subscription.bindings {
+            filters
+                .entry(&binding.exchange)
+                .or_default()
+                .extend(filter_subjects(binding)?);
+        }
+
+        if !subscription.durable {
+            return self.consume_core(subscription, filters).await;
+        }
+
+        let name = stream_name(&subscription.queue);
+        let mut consumers = Vec::new();
+        for (exchange, filter_subjects) in filters {
+            let consumer = self
+                .stream(exchange)
+                .await?
+                .get_or_create_consumer(
+                    &name,
+                    pull::Config {
+                        durable_name: Some(name.clone()),
+                        filter_subjects,
+                        ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                        max_ack_pending: i64::from(subscription.prefetch),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map_err(BusError::nats)?;
+            let messages = consumer.messages().await.map_err(BusError::nats)?;
+            consumers.push(
+                messages
+                    .map(|message| {
+                        message
+                            .map(|message| {
+                                let (message_id, content_type, headers) =
+                                    split_headers(message.message.headers.as_ref());
+                                BusDelivery {
+                                    payload: message.message.payload.to_vec(),
+                                    message_id,
+                                    content_type,
+                                    headers,
+                                    acker: Box::new(JetStreamAcker { message }),
+                                }
+                            })
+                            .map_err(BusError::nats)
+                    })
+                    .boxed(),
+            );
+        }
+
+        Ok(stream_ext::select_all(consumers).boxed())
+    }
+
+    async fn consume_core(
+        &self,
+        subscription: &Subscription,
+        filters: BTreeMap<&str, Vec<String>>,
+    ) -> Result<BusConsumer, BusError> {
+        let mut subscribers = Vec::new();
+        for subject in filters.into_values().flatten() {
+            let subscriber = if subscription.queue.is_empty() {
+                self.client.subscribe(subject).await
+            } else {
+                self.client
+                    .queue_subscribe(subject, subscription.queue.clone())
+                    .await
+            }
+            .map_err(BusError::nats)?;
+
+            let client = self.client.clone();
+            subscribers.push(
+                subscriber
+                    .map(move |message| {
+                        let (message_id, content_type, headers) =
+                            split_headers(message.headers.as_ref());
+                        Ok(BusDelivery {
+                            payload: message.payload.to_vec(),
+                            message_id,
+                            content_type,
+                            headers,
+                            acker: Box::new(CoreAcker {
+                                client: client.clone(),
+                                reply: message.reply,
+                            }),
+                        })
+                    })
+                    .boxed(),
+            );
+        }
+
+        Ok(stream_ext::select_all(subscribers).boxed())
+    }
+
+    async fn request_reply(
+        &self,
+        routing_key: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, BusError> {
+        let request = self.client.request(
+            subject(EXCHANGE_RPC_REQUEST, routing_key),
+            payload.to_vec().into(),
+        );
+        match tokio::time::timeout(timeout, request).await {
+            Ok(reply) => Ok(reply.map_err(BusError::nats)?.payload.to_vec()),
+            Err(_) => Err(BusError::Timeout(timeout)),
+        }
+    }
+}
+
+impl MessageBus for NatsBus {
+    fn publish<'a>(
+        &'a self,
+        exchange: &'a str,
+        routing_key: &'a str,
+        message: &'a OutgoingMessage,
+    ) -> BoxFuture<'a, Result<(), BusError>> {
+        Box::pin(self.publish_acked(exchange, routing_key, message))
+    }
+
+    fn subscribe<'a>(
+        &'a self,
+        subscription: &'a Subscription,
+    ) -> BoxFuture<'a, Result<BusConsumer, BusError>> {
+        Box::pin(self.consume(subscription))
+    }
+
+    fn request<'a>(
+        &'a self,
+        routing_key: &'a str,
+        payload: &'a [u8],
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<Vec<u8>, BusError>> {
+        Box::pin(self.request_reply(routing_key, payload, timeout))
+    }
+
+    fn close(&self) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move { self.client.drain().await.map_err(BusError::nats) })
+    }
+}
+
+/// Stream of an exchange, or durable consumer of a queue
+fn stream_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '.' | '*' | '>' | '/' | '\\' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Subject of a message published with a routing key
+fn subject(exchange: &str, routing_key: &str) -> String {
+    if routing_key.is_empty() {
+        format!("{}.msg", exchange)
+    } else {
+        format!("{}.msg.{}", exchange, routing_key)
+    }
+}
+
+/// Subjects of the stream of an exchange
+///
+/// The `msg` token keeps the subjects of `a.b` apart from those of an
+/// exchange named `a.b.c`, since streams may not overlap.
+fn exchange_subjects(exchange: &str) -> Vec<String> {
+    vec![format!("{}.msg", exchange), format!("{}.msg.>", exchange)]
+}
+
+/// Filter subjects of a binding
+fn filter_subjects(binding: &Binding) -> Result<Vec<String>, BusError> {
+    let exchange = &binding.exchange;
+    let key = &binding.routing_key;
+    match binding.kind {
+        ExchangeType::Fanout => Ok(exchange_subjects(exchange)),
+        ExchangeType::Direct => Ok(vec![subject(exchange, key)]),
+        ExchangeType::Topic => {
+            let words: Vec<&str> = key.split('.').collect();
+            match words.iter().position(|word| *word == "#") {
+                None => Ok(vec![subject(exchange, key)]),
+                Some(position) if position == words.len() - 1 => {
+                    // `#` also matches no words at all
+                    let prefix = subject(exchange, &words[..position].join("."));
+                    Ok(vec![prefix.clone(), format!("{}.>", prefix)])
+                }
+                Some(_) => Err(BusError::InvalidBinding(
+                    key.clone(),
+                    "`#` is only supported as the last word".to_string(),
+                )),
+            }
+        }
+    }
+}
+
+fn headers(message: &OutgoingMessage) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &message.headers {
+        headers.insert(key.as_str(), value.as_str());
+    }
+    if let Some(message_id) = &message.message_id {
+        headers.insert(HEADER_MESSAGE_ID, message_id.as_str());
+    }
+    if let Some(content_type) = &message.content_type {
+        headers.insert(HEADER_CONTENT_TYPE, content_type.as_str());
+    }
+    headers
+}
+
+/// Message ID, content type and application headers of a message
+fn split_headers(
+    headers: Option<&HeaderMap>,
+) -> (Option<String>, Option<String>, HashMap<String, String>) {
+    let mut message_id = None;
+    let mut content_type = None;
+    let mut entries = HashMap::new();
+    for (name, values) in headers.into_iter().flat_map(HeaderMap::iter) {
+        let Some(value) = values.first() else {
+            continue;
+        };
+        let value = value.to_string();
+        match name.as_ref() {
+            HEADER_MESSAGE_ID => message_id = Some(value),
+            HEADER_CONTENT_TYPE => content_type = Some(value),
+            name => {
+                entries.insert(name.to_string(), value);
+            }
+        }
+    }
+    (message_id, content_type, entries)
+}
+
+struct JetStreamAcker {
+    message: jetstream::Message,
+}
+
+impl Acker for JetStreamAcker {
+    fn ack(&self) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move { self.message.ack().await.map_err(BusError::nats) })
+    }
+
+    fn nack(&self, requeue: bool) -> BoxFuture<'_, Result<(), BusError>> {
+        let kind = if requeue {
+            AckKind::Nak(None)
+        } else {
+            AckKind::Term
+        };
+        Box::pin(async move { self.message.ack_with(kind).await.map_err(BusError::nats) })
+    }
+
+    fn reply(&self, _payload: Vec<u8>) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async { Err(BusError::NoReplyTo) })
+    }
+}
+
+/// Core NATS messages are not acknowledged
+struct CoreAcker {
+    client: Client,
+    reply: Option<Subject>,
+}
+
+impl Acker for CoreAcker {
+    fn ack(&self) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn nack(&self, _requeue: bool) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn reply(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<(), BusError>> {
+        Box::pin(async move {
+            let reply = self.reply.clone().ok_or(BusError::NoReplyTo)?;
+            self.client
+                .publish(reply, payload.into())
+                .await
+                .map_err(BusError::nats)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(kind: ExchangeType, routing_key: &str) -> Binding {
+        Binding {
+            exchange: "oxifed.activitypub.delivery".to_string(),
+            kind,
+            routing_key: routing_key.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_subjects() {
+        assert_eq!(stream_name("oxifed.email"), "oxifed_email");
+        assert_eq!(subject("oxifed.email", ""), "oxifed.email.msg");
+        assert_eq!(
+            subject("oxifed.activitypub.delivery", "high"),
+            "oxifed.activitypub.delivery.msg.high"
+        );
+        assert_eq!(
+            filter_subjects(&Binding::fanout("oxifed.email")).unwrap(),
+            vec!["oxifed.email.msg", "oxifed.email.msg.>"]
+        );
+    }
+
+    #[test]
+    fn test_binding_patterns() {
+        assert_eq!(
+            filter_subjects(&binding(ExchangeType::Direct, "low")).unwrap(),
+            vec!["oxifed.activitypub.delivery.msg.low"]
+        );
+        assert_eq!(
+            filter_subjects(&binding(ExchangeType::Topic, "*.example.com")).unwrap(),
+            vec!["oxifed.activitypub.delivery.msg.*.example.com"]
+        );
+        assert_eq!(
+            filter_subjects(&binding(ExchangeType::Topic, "high.#")).unwrap(),
+            vec![
+                "oxifed.activitypub.delivery.msg.high",
+                "oxifed.activitypub.delivery.msg.high.>"
+            ]
+        );
+        assert_eq!(
+            filter_subjects(&binding(ExchangeType::Topic, "#")).unwrap(),
+            vec![
+                "oxifed.activitypub.delivery.msg",
+                "oxifed.activitypub.delivery.msg.>"
+            ]
+        );
+        assert!(filter_subjects(&binding(ExchangeType::Topic, "#.example.com")).is_err());
+    }
+
+    #[test]
+    fn test_headers_round_trip() {
+        let message = OutgoingMessage::new(Vec::new())
+            .with_message_id("m-1")
+            .with_content_type("application/json")
+            .with_headers(HashMap::from([(
+                "traceparent".to_string(),
+                "00-abc-def-01".to_string(),
+            )]));
+
+        let (message_id, content_type, entries) = split_headers(Some(&headers(&message)));
+        assert_eq!(message_id.as_deref(), Some("m-1"));
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(
+            entries.get("traceparent").map(String::as_str),
+            Some("00-abc-def-01")
+        );
+        assert_eq!(entries.len(), 1);
+    }
+}
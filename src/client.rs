@@ -4,20 +4,43 @@
 //! including fetching objects, collections, actors, and submitting activities to outboxes.
 //! Implementation follows the W3C ActivityPub specification at https://www.w3.org/TR/activitypub/
 
-use crate::httpsignature::{HttpSignature, SignatureConfig, SignatureError};
-use crate::{Activity, ActivityPubEntity, Collection, Object, ObjectOrLink};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{ConfigError, Env, require_positive};
+use crate::egress::{EgressError, EgressPolicy};
+use crate::httpsignature::{HttpSignature, SignatureConfig, SignatureError, digest_header};
+use crate::{Activity, ActivityPubEntity, Collection, Object};
 use reqwest::{
     Client, Response,
-    header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderValue},
+    header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderValue, LOCATION},
+    redirect,
 };
+use serde::Deserialize;
 use url::Url;
 
+pub use crate::egress::is_public_ip;
+
 /// Standard ActivityPub content type for requests
 pub const ACTIVITYPUB_CONTENT_TYPE: &str = "application/activity+json";
 /// JSON-LD ActivityStreams content type profile
 pub const ACTIVITY_STREAMS_JSON_LD: &str =
     "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
 
+/// Redirects followed when fetching media
+const MAX_MEDIA_REDIRECTS: usize = 5;
+
+/// Media types of ActivityPub documents; plain JSON is accepted as some
+/// servers answer with it
+const ACTIVITYPUB_MEDIA_TYPES: &[&str] = &[
+    "application/activity+json",
+    "application/ld+json",
+    "application/json",
+];
+
+/// Limits installed by the daemon
+static INSTALLED_LIMITS: OnceLock<FetchLimits> = OnceLock::new();
+
 /// Error type for ActivityPub client operations
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
@@ -41,11 +64,106 @@ pub enum ClientError {
 
     #[error("Signature error: {0}")]
     SignatureError(#[from] SignatureError),
+
+    #[error("Response exceeds maximum size of {0} bytes")]
+    ResponseTooLarge(usize),
+
+    #[error("Invalid activity: {0}")]
+    BuildError(#[from] crate::builder::BuildError),
+
+    #[error("Refused by egress policy: {0}")]
+    Egress(#[from] EgressError),
+
+    #[error("More than {0} redirects")]
+    TooManyRedirects(usize),
+
+    #[error("Redirect to another origin: {0}")]
+    CrossOriginRedirect(String),
+
+    #[error("Object {0} is not from the origin that served it")]
+    OriginMismatch(String),
+
+    #[error("Unexpected content type: {0}")]
+    UnexpectedContentType(String),
 }
 
 /// Result type for ActivityPub client operations
 pub type Result<T> = std::result::Result<T, ClientError>;
 
+/// Limits of requests for ActivityPub documents, keeping slow or malicious
+/// peers from tying up the fetching worker
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchLimits {
+    /// Time a fetch or delivery may take in total, in seconds
+    pub timeout_secs: u64,
+    /// Time connecting to a host may take, in seconds
+    pub connect_timeout_secs: u64,
+    /// Largest document fetched, in bytes
+    pub max_body_size: usize,
+    /// Redirects followed per fetch, e.g. of actors whose domain was renamed
+    pub max_redirects: usize,
+    /// Follow redirects to another origin; the document fetched must then
+    /// have its ID on the origin it was served from
+    pub cross_origin_redirects: bool,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            max_body_size: 1024 * 1024,
+            max_redirects: 5,
+            cross_origin_redirects: true,
+        }
+    }
+}
+
+impl FetchLimits {
+    /// Make these the limits of clients created from now on
+    ///
+    /// Only the first limits installed take effect.
+    pub fn install(self) {
+        if INSTALLED_LIMITS.set(self).is_err() {
+            tracing::warn!("Fetch limits are already installed, keeping them");
+        }
+    }
+
+    /// The installed limits, or the default ones
+    pub fn installed() -> Self {
+        INSTALLED_LIMITS.get().cloned().unwrap_or_default()
+    }
+
+    /// Apply `FETCH_TIMEOUT_SECS`, `FETCH_CONNECT_TIMEOUT_SECS`,
+    /// `FETCH_MAX_BODY_SIZE`, `FETCH_MAX_REDIRECTS` and
+    /// `FETCH_CROSS_ORIGIN_REDIRECTS`
+    pub fn apply_env(&mut self, env: &Env) -> std::result::Result<(), ConfigError> {
+        env.set("FETCH_TIMEOUT_SECS", &mut self.timeout_secs)?;
+        env.set("FETCH_CONNECT_TIMEOUT_SECS", &mut self.connect_timeout_secs)?;
+        env.set("FETCH_MAX_BODY_SIZE", &mut self.max_body_size)?;
+        env.set("FETCH_MAX_REDIRECTS", &mut self.max_redirects)?;
+        env.set(
+            "FETCH_CROSS_ORIGIN_REDIRECTS",
+            &mut self.cross_origin_redirects,
+        )
+    }
+
+    /// Check the limits, reporting errors under `key`
+    pub fn validate(&self, key: &str) -> std::result::Result<(), ConfigError> {
+        require_positive(&format!("{}.timeout_secs", key), self.timeout_secs)?;
+        require_positive(
+            &format!("{}.connect_timeout_secs", key),
+            self.connect_timeout_secs,
+        )?;
+        require_positive(&format!("{}.max_body_size", key), self.max_body_size)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
 /// Configuration options for ActivityPub client
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
@@ -55,6 +173,10 @@ pub struct ClientConfig {
     pub http_signature_config: Option<SignatureConfig>,
     /// Optional OAuth credentials
     pub oauth_token: Option<String>,
+    /// Hosts requests may reach; the installed policy by default
+    pub egress: EgressPolicy,
+    /// Limits of fetches and deliveries; the installed limits by default
+    pub limits: FetchLimits,
 }
 
 impl Default for ClientConfig {
@@ -63,6 +185,8 @@ impl Default for ClientConfig {
             user_agent: String::from("Oxifed/0.1.0"),
             http_signature_config: None,
             oauth_token: None,
+            egress: EgressPolicy::installed(),
+            limits: FetchLimits::installed(),
         }
     }
 }
@@ -82,11 +206,32 @@ impl ActivityPubClient {
 
     /// Create a new ActivityPub client with the specified configuration
     pub fn with_config(config: ClientConfig) -> Result<Self> {
-        let client = Client::builder().user_agent(&config.user_agent).build()?;
+        // Redirects are followed by hand, signing the request to each host
+        // and checking it against the egress policy
+        let builder = Client::builder()
+            .user_agent(&config.user_agent)
+            .connect_timeout(Duration::from_secs(config.limits.connect_timeout_secs));
+        let client = config
+            .egress
+            .configure(builder)?
+            .redirect(redirect::Policy::none())
+            .build()?;
 
         Ok(Self { client, config })
     }
 
+    /// Send a request the egress policy permits
+    async fn execute(&self, request: reqwest::Request) -> Result<Response> {
+        self.config.egress.check_url(request.url())?;
+        self.client
+            .execute(request)
+            .await
+            .map_err(|e| match crate::egress::refusal(&e) {
+                Some(refusal) => ClientError::Egress(refusal),
+                None => ClientError::RequestFailed(e),
+            })
+    }
+
     /// Get default headers for ActivityPub requests
     fn default_headers(&self) -> Result<HeaderMap> {
         let mut headers = HeaderMap::new();
@@ -104,22 +249,67 @@ impl ActivityPubClient {
     }
 
     /// Sign a request using HTTP Signatures (legacy draft-cavage format for Mastodon compatibility)
-    fn sign_request(&self, request: &mut reqwest::Request) -> Result<()> {
+    async fn sign_request(&self, request: &mut reqwest::Request) -> Result<()> {
         if let Some(config) = &self.config.http_signature_config {
-            HttpSignature::sign_request_legacy(request, config)?;
+            HttpSignature::sign_request_legacy(request, config).await?;
         }
 
         Ok(())
     }
 
     /// Fetch an ActivityPub object from a URL
+    ///
+    /// Redirects are followed with a request signed for the new location,
+    /// so objects moved to another domain stay reachable under their old
+    /// URL. A document reached through a redirect to another origin must
+    /// have its ID on that origin. Documents of other content types and
+    /// larger than the configured limit are refused.
     pub async fn fetch_object(&self, url: &Url) -> Result<ActivityPubEntity> {
+        let limits = &self.config.limits;
+        let origin = url.origin();
+        let mut url = url.clone();
+        for _ in 0..=limits.max_redirects {
+            let mut response = self.get_signed(&url).await?;
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| ClientError::MissingField("Location".into()))?;
+                let target = url.join(location)?;
+                if !matches!(target.scheme(), "http" | "https") {
+                    return Err(ClientError::StatusError(response.status()));
+                }
+                if !limits.cross_origin_redirects && target.origin() != origin {
+                    return Err(ClientError::CrossOriginRedirect(target.to_string()));
+                }
+                tracing::debug!("{} redirects to {}", url, target);
+                url = target;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(ClientError::StatusError(response.status()));
+            }
+            check_content_type(&response)?;
+            let body = read_body(&mut response, limits.max_body_size).await?;
+            let document: serde_json::Value = serde_json::from_slice(&body)?;
+            if url.origin() != origin {
+                check_origin(&document, &url)?;
+            }
+            return Ok(serde_json::from_value(document)?);
+        }
+        Err(ClientError::TooManyRedirects(limits.max_redirects))
+    }
+
+    /// Send a GET request for ActivityPub JSON, signed if configured
+    async fn get_signed(&self, url: &Url) -> Result<Response> {
         tracing::debug!("Fetching ActivityPub object from: {}", url);
 
         let mut request = self
             .client
             .get(url.clone())
             .headers(self.default_headers()?)
+            .timeout(self.config.limits.timeout())
             .build()?;
 
         // Add host and date headers for HTTP signature on GET requests
@@ -141,11 +331,11 @@ impl ActivityPubClient {
         );
 
         // Sign the request if configured
-        self.sign_request(&mut request)?;
+        self.sign_request(&mut request).await?;
 
-        let response = self.client.execute(request).await?;
+        let response = self.execute(request).await?;
         tracing::debug!("Fetch response status: {}", response.status());
-        self.handle_response(response).await
+        Ok(response)
     }
 
     /// Fetch an actor profile
@@ -198,8 +388,26 @@ impl ActivityPubClient {
 
     /// Send an activity to an actor's inbox
     pub async fn send_to_inbox(&self, inbox_url: &Url, activity: &Activity) -> Result<()> {
+        self.send_body_to_inbox(inbox_url, serde_json::to_vec(activity)?)
+            .await
+    }
+
+    /// Send an activity already serialized to JSON to an inbox
+    ///
+    /// Used when the activity was adapted to what the receiving software
+    /// expects, which the [`Activity`] type cannot represent.
+    pub async fn send_json_to_inbox(
+        &self,
+        inbox_url: &Url,
+        activity: &serde_json::Value,
+    ) -> Result<()> {
+        self.send_body_to_inbox(inbox_url, serde_json::to_vec(activity)?)
+            .await
+    }
+
+    async fn send_body_to_inbox(&self, inbox_url: &Url, body_bytes: Vec<u8>) -> Result<()> {
         // Try HTTPS first
-        match self.try_send_to_inbox(inbox_url, activity).await {
+        match self.try_send_to_inbox(inbox_url, &body_bytes).await {
             Ok(()) => Ok(()),
             Err(e) => {
                 // For localhost, try HTTP fallback if HTTPS fails
@@ -215,7 +423,7 @@ impl ActivityPubClient {
                         http_url
                     );
 
-                    return self.try_send_to_inbox(&http_url, activity).await;
+                    return self.try_send_to_inbox(&http_url, &body_bytes).await;
                 }
 
                 Err(e)
@@ -223,18 +431,16 @@ impl ActivityPubClient {
         }
     }
 
-    async fn try_send_to_inbox(&self, inbox_url: &Url, activity: &Activity) -> Result<()> {
+    async fn try_send_to_inbox(&self, inbox_url: &Url, body_bytes: &[u8]) -> Result<()> {
         tracing::debug!("Sending activity to inbox: {}", inbox_url);
 
-        // Serialize the body first so we can compute the digest
-        let body_bytes = serde_json::to_vec(activity)?;
-
         let mut request = self
             .client
             .post(inbox_url.clone())
             .headers(self.default_headers()?)
             .header(CONTENT_TYPE, ACTIVITYPUB_CONTENT_TYPE)
-            .body(body_bytes.clone())
+            .timeout(self.config.limits.timeout())
+            .body(body_bytes.to_vec())
             .build()?;
 
         // Add Host header explicitly (reqwest sets it internally but not in the headers map)
@@ -257,19 +463,16 @@ impl ActivityPubClient {
             HeaderValue::from_str(&date.to_string()).map_err(ClientError::InvalidHeader)?,
         );
 
-        use sha2::{Digest, Sha256};
-        let digest = Sha256::digest(&body_bytes);
-        let digest_b64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, digest);
         request.headers_mut().insert(
             reqwest::header::HeaderName::from_static("digest"),
-            HeaderValue::from_str(&format!("SHA-256={}", digest_b64))
+            HeaderValue::from_str(&digest_header(body_bytes))
                 .map_err(ClientError::InvalidHeader)?,
         );
 
         // Sign the request if configured
-        self.sign_request(&mut request)?;
+        self.sign_request(&mut request).await?;
 
-        let response = self.client.execute(request).await?;
+        let response = self.execute(request).await?;
 
         if response.status().is_success() {
             tracing::debug!("Activity delivered successfully to {}", inbox_url);
@@ -297,13 +500,14 @@ impl ActivityPubClient {
             .post(outbox_url.clone())
             .headers(self.default_headers()?)
             .header(CONTENT_TYPE, ACTIVITYPUB_CONTENT_TYPE)
+            .timeout(self.config.limits.timeout())
             .json(activity)
             .build()?;
 
         // Sign the request if configured
-        self.sign_request(&mut request)?;
+        self.sign_request(&mut request).await?;
 
-        let response = self.client.execute(request).await?;
+        let response = self.execute(request).await?;
 
         if !response.status().is_success() {
             return Err(ClientError::StatusError(response.status()));
@@ -329,42 +533,144 @@ impl ActivityPubClient {
 
         let outbox_url = Url::parse(outbox_url)?;
 
-        // Create a Follow activity
-        let follow_activity = Activity {
-            activity_type: crate::ActivityType::Follow,
-            id: None, // Server will assign an ID
-            name: None,
-            summary: None,
-            actor: Some(ObjectOrLink::Url(actor.id.clone().ok_or_else(|| {
-                ClientError::MissingField("Actor missing id".into())
-            })?)),
-            object: Some(ObjectOrLink::Url(target.clone())),
-            target: None,
-            published: None,
-            updated: None,
-            additional_properties: std::collections::HashMap::new(),
-        };
+        let actor_id = actor
+            .id
+            .as_ref()
+            .ok_or_else(|| ClientError::MissingField("Actor missing id".into()))?;
+
+        // Create a Follow activity; the server assigns its ID
+        let follow_activity = Activity::builder()
+            .follow(actor_id.as_str(), target.as_str())
+            .build()?;
 
         self.post_to_outbox(&outbox_url, &follow_activity).await
     }
 
+    /// Fetch raw media bytes from a URL, returning the body and its content type
+    ///
+    /// Redirects are followed within the egress policy, so remote objects
+    /// cannot make the server fetch from its own network. The download is
+    /// aborted once the body exceeds `max_size` bytes.
+    pub async fn fetch_media(
+        &self,
+        url: &Url,
+        max_size: usize,
+    ) -> Result<(Vec<u8>, Option<String>)> {
+        let mut url = url.clone();
+        for _ in 0..=MAX_MEDIA_REDIRECTS {
+            tracing::debug!("Fetching media from: {}", url);
+
+            let request = self
+                .client
+                .get(url.clone())
+                .timeout(self.config.limits.timeout())
+                .build()?;
+            let mut response = self.execute(request).await?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| ClientError::MissingField("Location".into()))?;
+                url = url.join(location)?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    return Err(ClientError::StatusError(response.status()));
+                }
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(ClientError::StatusError(response.status()));
+            }
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string());
+
+            let body = read_body(&mut response, max_size).await?;
+            return Ok((body, content_type));
+        }
+        Err(ClientError::TooManyRedirects(MAX_MEDIA_REDIRECTS))
+    }
+
     /// Helper method to handle responses and parse them
-    async fn handle_response(&self, response: Response) -> Result<ActivityPubEntity> {
+    async fn handle_response(&self, mut response: Response) -> Result<ActivityPubEntity> {
         if !response.status().is_success() {
             return Err(ClientError::StatusError(response.status()));
         }
 
-        let text = response.text().await?;
-        let entity = crate::parse_activitypub_json(&text)?;
+        let body = read_body(&mut response, self.config.limits.max_body_size).await?;
+        let entity = serde_json::from_slice(&body)?;
 
         Ok(entity)
     }
 }
 
+/// Read a response body, aborting once it exceeds `max_size` bytes
+async fn read_body(response: &mut Response, max_size: usize) -> Result<Vec<u8>> {
+    if let Some(length) = response.content_length()
+        && length > max_size as u64
+    {
+        return Err(ClientError::ResponseTooLarge(max_size));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Err(ClientError::ResponseTooLarge(max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Check that a response is an ActivityPub document
+fn check_content_type(response: &Response) -> Result<()> {
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if ACTIVITYPUB_MEDIA_TYPES.contains(&media_type.as_str()) {
+        Ok(())
+    } else {
+        Err(ClientError::UnexpectedContentType(content_type.to_string()))
+    }
+}
+
+/// Check that a document fetched from `url` has its ID on the same origin
+fn check_origin(document: &serde_json::Value, url: &Url) -> Result<()> {
+    let id = document
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or_else(|| ClientError::MissingField("id".into()))?;
+    match Url::parse(id) {
+        Ok(id_url) if id_url.origin() == url.origin() => Ok(()),
+        _ => Err(ClientError::OriginMismatch(id.to_string())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::httpsignature::{ComponentIdentifier, SignatureAlgorithm};
+    use crate::httpsignature::{ComponentIdentifier, LocalSigner, SignatureAlgorithm};
+
+    /// Client for the mock servers, which listen on the loopback address
+    fn local_client() -> ActivityPubClient {
+        ActivityPubClient::with_config(ClientConfig {
+            egress: EgressPolicy::allowing_private(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
 
     #[tokio::test]
     async fn test_fetch_actor() {
@@ -394,7 +700,7 @@ mod tests {
             .create_async()
             .await;
 
-        let client = ActivityPubClient::new().unwrap();
+        let client = local_client();
         let url = Url::parse(&format!("{}/users/test", url)).unwrap();
 
         let actor = client.fetch_actor(&url).await.unwrap();
@@ -457,7 +763,7 @@ mod tests {
             .create_async()
             .await;
 
-        let client = ActivityPubClient::new().unwrap();
+        let client = local_client();
         let url = Url::parse(&format!("{}/users/test/outbox", url)).unwrap();
 
         let activity: Activity = serde_json::from_str(request_activity).unwrap();
@@ -471,13 +777,295 @@ mod tests {
         m.assert_async().await;
     }
 
+    #[tokio::test]
+    async fn test_send_to_inbox_sets_digest() {
+        let mut server = mockito::Server::new_async().await;
+        let activity: Activity = serde_json::from_str(
+            r#"{"type": "Like", "actor": "https://example.com/users/test", "object": "https://example.org/notes/1"}"#,
+        )
+        .unwrap();
+        let body = serde_json::to_vec(&activity).unwrap();
+
+        let m = server
+            .mock("POST", "/users/bob/inbox")
+            .match_header("digest", digest_header(&body).as_str())
+            .with_status(202)
+            .create_async()
+            .await;
+
+        let client = local_client();
+        let url = Url::parse(&format!("{}/users/bob/inbox", server.url())).unwrap();
+        client.send_to_inbox(&url, &activity).await.unwrap();
+        m.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_media_size_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let m = server
+            .mock("GET", "/media/cat.png")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body(vec![0u8; 64])
+            .expect(2)
+            .create_async()
+            .await;
+
+        let client = local_client();
+        let url = Url::parse(&format!("{}/media/cat.png", url)).unwrap();
+
+        let (body, content_type) = client.fetch_media(&url, 128).await.unwrap();
+        assert_eq!(body.len(), 64);
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+
+        let result = client.fetch_media(&url, 32).await;
+        assert!(matches!(result, Err(ClientError::ResponseTooLarge(32))));
+        m.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_media_refuses_private_addresses() {
+        let mut server = mockito::Server::new_async().await;
+        let m = server
+            .mock("GET", "/media/cat.png")
+            .with_status(200)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let client = ActivityPubClient::new().unwrap();
+        let url = Url::parse(&format!("{}/media/cat.png", server.url())).unwrap();
+        let result = client.fetch_media(&url, 128).await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Egress(EgressError::ForbiddenAddress(_)))
+        ));
+
+        let metadata = Url::parse("http://169.254.169.254/latest/meta-data/").unwrap();
+        let result = client.fetch_media(&metadata, 128).await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Egress(EgressError::ForbiddenAddress(_)))
+        ));
+        m.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_media_and_outbox_posts_time_out() {
+        // A server that accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+
+        let client = ActivityPubClient::with_config(ClientConfig {
+            egress: EgressPolicy::allowing_private(),
+            limits: FetchLimits {
+                timeout_secs: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+        let media = Url::parse(&format!("http://{}/media/cat.png", address)).unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(10), client.fetch_media(&media, 128))
+            .await
+            .expect("fetch_media did not time out");
+        assert!(matches!(result, Err(ClientError::RequestFailed(e)) if e.is_timeout()));
+
+        let outbox = Url::parse(&format!("http://{}/users/bob/outbox", address)).unwrap();
+        let activity: Activity = serde_json::from_str(
+            r#"{"type": "Like", "actor": "https://example.com/users/test", "object": "https://example.org/notes/1"}"#,
+        )
+        .unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            client.post_to_outbox(&outbox, &activity),
+        )
+        .await
+        .expect("post_to_outbox did not time out");
+        assert!(matches!(result, Err(ClientError::RequestFailed(e)) if e.is_timeout()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_media_follows_redirects() {
+        let mut server = mockito::Server::new_async().await;
+        let redirect = server
+            .mock("GET", "/media/old.png")
+            .with_status(302)
+            .with_header("location", "/media/new.png")
+            .create_async()
+            .await;
+        let target = server
+            .mock("GET", "/media/new.png")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body(vec![0u8; 8])
+            .create_async()
+            .await;
+
+        let client = local_client();
+        let url = Url::parse(&format!("{}/media/old.png", server.url())).unwrap();
+        let (body, _) = client.fetch_media(&url, 128).await.unwrap();
+        assert_eq!(body.len(), 8);
+        redirect.assert_async().await;
+        target.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_object_follows_redirects() {
+        let mut server = mockito::Server::new_async().await;
+        let redirect = server
+            .mock("GET", "/users/alice")
+            .with_status(308)
+            .with_header("location", "/users/alice-renamed")
+            .create_async()
+            .await;
+        let actor = format!("{}/users/alice-renamed", server.url());
+        let target = server
+            .mock("GET", "/users/alice-renamed")
+            .match_header("accept", ACTIVITYPUB_CONTENT_TYPE)
+            .with_status(200)
+            .with_header("content-type", ACTIVITYPUB_CONTENT_TYPE)
+            .with_body(
+                serde_json::json!({
+                    "@context": "https://www.w3.org/ns/activitystreams",
+                    "type": "Person",
+                    "id": actor,
+                    "inbox": format!("{}/inbox", actor)
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = local_client();
+        let url = Url::parse(&format!("{}/users/alice", server.url())).unwrap();
+        let fetched = client.fetch_actor(&url).await.unwrap();
+        assert_eq!(fetched.id.map(|id| id.to_string()), Some(actor));
+        redirect.assert_async().await;
+        target.assert_async().await;
+
+        let looping = server
+            .mock("GET", "/users/loop")
+            .with_status(302)
+            .with_header("location", "/users/loop")
+            .expect(FetchLimits::default().max_redirects + 1)
+            .create_async()
+            .await;
+        let url = Url::parse(&format!("{}/users/loop", server.url())).unwrap();
+        assert!(matches!(
+            client.fetch_object(&url).await,
+            Err(ClientError::TooManyRedirects(_))
+        ));
+        looping.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_object_limits() {
+        let mut server = mockito::Server::new_async().await;
+        let html = server
+            .mock("GET", "/users/html")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body("<html></html>")
+            .create_async()
+            .await;
+        let large = server
+            .mock("GET", "/users/large")
+            .with_status(200)
+            .with_header("content-type", ACTIVITY_STREAMS_JSON_LD)
+            .with_body(format!(
+                r#"{{"type": "Note", "content": "{}"}}"#,
+                "a".repeat(256)
+            ))
+            .create_async()
+            .await;
+
+        let client = ActivityPubClient::with_config(ClientConfig {
+            egress: EgressPolicy::allowing_private(),
+            limits: FetchLimits {
+                max_body_size: 128,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let url = Url::parse(&format!("{}/users/html", server.url())).unwrap();
+        assert!(matches!(
+            client.fetch_actor(&url).await,
+            Err(ClientError::UnexpectedContentType(_))
+        ));
+        let url = Url::parse(&format!("{}/users/large", server.url())).unwrap();
+        assert!(matches!(
+            client.fetch_object(&url).await,
+            Err(ClientError::ResponseTooLarge(128))
+        ));
+        html.assert_async().await;
+        large.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_object_cross_origin_redirects() {
+        let mut server = mockito::Server::new_async().await;
+        let mut other = mockito::Server::new_async().await;
+        let redirect = server
+            .mock("GET", "/users/alice")
+            .with_status(301)
+            .with_header("location", &format!("{}/users/alice", other.url()))
+            .expect(2)
+            .create_async()
+            .await;
+        let target = other
+            .mock("GET", "/users/alice")
+            .with_status(200)
+            .with_header("content-type", ACTIVITYPUB_CONTENT_TYPE)
+            .with_body(
+                serde_json::json!({
+                    "type": "Person",
+                    "id": format!("{}/users/alice", server.url())
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let url = Url::parse(&format!("{}/users/alice", server.url())).unwrap();
+        assert!(matches!(
+            local_client().fetch_actor(&url).await,
+            Err(ClientError::OriginMismatch(_))
+        ));
+
+        let client = ActivityPubClient::with_config(ClientConfig {
+            egress: EgressPolicy::allowing_private(),
+            limits: FetchLimits {
+                cross_origin_redirects: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(
+            client.fetch_actor(&url).await,
+            Err(ClientError::CrossOriginRedirect(_))
+        ));
+        redirect.assert_async().await;
+        target.assert_async().await;
+    }
+
     #[tokio::test]
     async fn test_with_http_signature() {
         // This test would require actual keys, so we'll just demonstrate the setup
         let ed25519_key = b"dummy_key_for_demonstration_only";
 
         let signature_config = SignatureConfig {
-            algorithm: SignatureAlgorithm::Ed25519,
             parameters: crate::httpsignature::SignatureParameters::new(),
             key_id: "https://example.com/keys/1".to_string(),
             components: vec![
@@ -487,13 +1075,18 @@ mod tests {
                 ComponentIdentifier::Header("date".to_string()),
                 ComponentIdentifier::Header("content-type".to_string()),
             ],
-            private_key: ed25519_key.to_vec(),
+            signer: std::sync::Arc::new(LocalSigner::new(
+                SignatureAlgorithm::Ed25519,
+                ed25519_key.to_vec(),
+            )),
         };
 
         let client_config = ClientConfig {
             user_agent: "ActivityPub-Client/1.0".to_string(),
             http_signature_config: Some(signature_config),
             oauth_token: None,
+            egress: EgressPolicy::default(),
+            limits: FetchLimits::default(),
         };
 
         // In a real scenario, this client would sign requests with the configured key
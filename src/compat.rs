@@ -0,0 +1,378 @@
+//! Interoperability profiles of fediverse software
+//!
+//! Fediverse software agrees on ActivityPub only up to a point. Pleroma and
+//! Akkoma send emoji reactions as `EmojiReact` activities and Misskey as
+//! `Like`s carrying a `_misskey_reaction`; GoToSocial reads who may like,
+//! reply to or boost a post from its `interactionPolicy`; Mastodon and most
+//! others only verify draft-cavage signatures made with RSA keys. A
+//! [`CompatProfile`] holds these quirks for the software a peer runs, as
+//! recorded in the instance registry by the NodeInfo crawler, and adapts
+//! what is delivered to the peer and what is accepted from it.
+//!
+//! Peers whose software is not known yet get the [`Software::Unknown`]
+//! profile, which sends what Mastodon expects and accepts every variant.
+
+use std::time::Duration;
+
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::database::DatabaseManager;
+
+/// The public collection as addressed in `to` and `cc`
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// GoToSocial extension namespace
+pub const GOTOSOCIAL_NS: &str = "https://gotosocial.org/ns#";
+
+/// How long the profile of a host read from the registry is used
+const PROFILE_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Hosts whose profile is cached
+const PROFILE_CACHE_SIZE: u64 = 10_000;
+
+/// Fediverse software families with known quirks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Software {
+    Oxifed,
+    /// Mastodon and its forks
+    Mastodon,
+    /// Pleroma and Akkoma
+    Pleroma,
+    /// Misskey and its forks
+    Misskey,
+    GoToSocial,
+    Lemmy,
+    /// Software without a profile, or not detected yet
+    Unknown,
+}
+
+impl Software {
+    /// Software family of a NodeInfo `software.name`
+    pub fn from_nodeinfo(name: Option<&str>) -> Self {
+        match name.map(str::to_lowercase).as_deref() {
+            Some("oxifed") => Self::Oxifed,
+            Some("mastodon" | "hometown" | "glitchsoc") => Self::Mastodon,
+            Some("pleroma" | "akkoma") => Self::Pleroma,
+            Some(
+                "misskey" | "sharkey" | "firefish" | "calckey" | "iceshrimp" | "foundkey"
+                | "cherrypick",
+            ) => Self::Misskey,
+            Some("gotosocial") => Self::GoToSocial,
+            Some("lemmy") => Self::Lemmy,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// How emoji reactions (`Like`s with `content`) are delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReactionStyle {
+    /// As a `Like`; software without reactions counts it as a favourite
+    Like,
+    /// As a Pleroma `EmojiReact`
+    EmojiReact,
+    /// As a `Like` with the emoji in `_misskey_reaction`
+    MisskeyLike,
+}
+
+/// Quirks of the software a peer runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompatProfile {
+    pub software: Software,
+    pub reactions: ReactionStyle,
+    /// Whether the peer verifies signatures made with Ed25519 keys; if
+    /// not, deliveries are signed with an RSA key of the actor
+    pub ed25519_signatures: bool,
+    /// Whether delivered posts carry a GoToSocial `interactionPolicy`
+    pub interaction_policy: bool,
+    /// Whether `EmojiReact`s and `_misskey_reaction`s of the peer are
+    /// accepted as `Like`s with `content`
+    pub accepts_reaction_variants: bool,
+}
+
+impl CompatProfile {
+    pub fn for_software(software: Software) -> Self {
+        let base = Self {
+            software,
+            reactions: ReactionStyle::Like,
+            ed25519_signatures: false,
+            interaction_policy: false,
+            accepts_reaction_variants: false,
+        };
+        match software {
+            Software::Oxifed => Self {
+                ed25519_signatures: true,
+                ..base
+            },
+            Software::Pleroma => Self {
+                reactions: ReactionStyle::EmojiReact,
+                accepts_reaction_variants: true,
+                ..base
+            },
+            Software::Misskey => Self {
+                reactions: ReactionStyle::MisskeyLike,
+                accepts_reaction_variants: true,
+                ..base
+            },
+            Software::GoToSocial => Self {
+                interaction_policy: true,
+                ..base
+            },
+            Software::Unknown => Self {
+                accepts_reaction_variants: true,
+                ..base
+            },
+            Software::Mastodon | Software::Lemmy => base,
+        }
+    }
+
+    /// Adapt an activity to be delivered to the peer
+    pub fn adapt_outgoing(&self, activity: &mut Value) {
+        let activity_type = activity["type"].as_str().unwrap_or_default().to_string();
+        if activity_type == "Like"
+            && let Some(reaction) = activity["content"].as_str().map(str::to_string)
+        {
+            match self.reactions {
+                ReactionStyle::Like => {}
+                ReactionStyle::EmojiReact => activity["type"] = json!("EmojiReact"),
+                ReactionStyle::MisskeyLike => activity["_misskey_reaction"] = json!(reaction),
+            }
+        }
+
+        if self.interaction_policy
+            && matches!(activity_type.as_str(), "Create" | "Update")
+            && let Some(object) = activity.get_mut("object").filter(|object| is_post(object))
+            && object.get("interactionPolicy").is_none()
+        {
+            object["interactionPolicy"] = interaction_policy(object);
+            add_context(
+                activity,
+                json!({
+                    "gts": GOTOSOCIAL_NS,
+                    "interactionPolicy": { "@id": "gts:interactionPolicy", "@type": "@id" },
+                    "canLike": { "@id": "gts:canLike", "@type": "@id" },
+                    "canReply": { "@id": "gts:canReply", "@type": "@id" },
+                    "canAnnounce": { "@id": "gts:canAnnounce", "@type": "@id" },
+                    "always": { "@id": "gts:always", "@type": "@id" },
+                    "approvalRequired": { "@id": "gts:approvalRequired", "@type": "@id" }
+                }),
+            );
+        }
+    }
+
+    /// Rewrite the variants of an activity received from the peer to the
+    /// form the handlers understand
+    pub fn normalize_incoming(&self, activity: &mut Value) {
+        if !self.accepts_reaction_variants {
+            return;
+        }
+        match activity["type"].as_str() {
+            Some("EmojiReact") => activity["type"] = json!("Like"),
+            Some("Like") if activity.get("content").is_none() => {
+                if let Some(reaction) = activity["_misskey_reaction"].as_str() {
+                    activity["content"] = json!(reaction);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for CompatProfile {
+    fn default() -> Self {
+        Self::for_software(Software::Unknown)
+    }
+}
+
+/// Profiles of peers by host, read from the instance registry
+#[derive(Clone)]
+pub struct PeerProfiles {
+    profiles: Cache<String, CompatProfile>,
+}
+
+impl Default for PeerProfiles {
+    fn default() -> Self {
+        Self {
+            profiles: Cache::builder()
+                .max_capacity(PROFILE_CACHE_SIZE)
+                .time_to_live(PROFILE_CACHE_TTL)
+                .build(),
+        }
+    }
+}
+
+impl PeerProfiles {
+    /// Profile of the software `host` runs
+    pub async fn for_host(&self, db: &DatabaseManager, host: &str) -> CompatProfile {
+        if let Some(profile) = self.profiles.get(host) {
+            return profile;
+        }
+        let profile = match db.find_instance(host).await {
+            Ok(instance) => CompatProfile::for_software(Software::from_nodeinfo(
+                instance.as_ref().and_then(|i| i.software.as_deref()),
+            )),
+            Err(e) => {
+                warn!("Failed to read the software of {}: {}", host, e);
+                return CompatProfile::default();
+            }
+        };
+        self.profiles.insert(host.to_string(), profile);
+        profile
+    }
+}
+
+/// Whether an object is a post GoToSocial applies interaction policies to
+fn is_post(object: &Value) -> bool {
+    matches!(
+        object["type"].as_str(),
+        Some("Note" | "Article" | "Question" | "Page")
+    )
+}
+
+/// GoToSocial `interactionPolicy` matching the addressing of a post
+///
+/// Everyone may like and reply to a public post and boost it; of a post
+/// that is not public only its audience may like and reply to it, and only
+/// its author boost it.
+fn interaction_policy(object: &Value) -> Value {
+    let addressed: Vec<&str> = ["to", "cc"]
+        .iter()
+        .flat_map(|key| match &object[*key] {
+            Value::String(id) => vec![id.as_str()],
+            Value::Array(ids) => ids.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        })
+        .collect();
+    let public = addressed
+        .iter()
+        .any(|id| matches!(*id, PUBLIC | "as:Public" | "Public"));
+    let author = object["attributedTo"].as_str();
+
+    let (audience, boosters) = if public {
+        (vec![PUBLIC], vec![PUBLIC])
+    } else {
+        let mut audience = addressed;
+        audience.extend(author);
+        (audience, author.into_iter().collect())
+    };
+    json!({
+        "canLike": { "always": audience, "approvalRequired": [] },
+        "canReply": { "always": audience, "approvalRequired": [] },
+        "canAnnounce": { "always": boosters, "approvalRequired": [] }
+    })
+}
+
+/// Append an entry to the `@context` of an activity
+fn add_context(activity: &mut Value, entry: Value) {
+    match activity.get_mut("@context") {
+        Some(Value::Array(context)) => context.push(entry),
+        Some(context) => {
+            let first = context.take();
+            *context = json!([first, entry]);
+        }
+        None => activity["@context"] = json!(["https://www.w3.org/ns/activitystreams", entry]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_software_families() {
+        assert_eq!(Software::from_nodeinfo(Some("akkoma")), Software::Pleroma);
+        assert_eq!(Software::from_nodeinfo(Some("Sharkey")), Software::Misskey);
+        assert_eq!(
+            Software::from_nodeinfo(Some("writefreely")),
+            Software::Unknown
+        );
+        assert_eq!(Software::from_nodeinfo(None), Software::Unknown);
+    }
+
+    #[test]
+    fn test_reactions_follow_peer_style() {
+        let like = json!({
+            "type": "Like",
+            "actor": "https://example.com/users/alice",
+            "object": "https://peer.example/notes/1",
+            "content": "🎉"
+        });
+
+        let mut pleroma = like.clone();
+        CompatProfile::for_software(Software::Pleroma).adapt_outgoing(&mut pleroma);
+        assert_eq!(pleroma["type"], "EmojiReact");
+
+        let mut misskey = like.clone();
+        CompatProfile::for_software(Software::Misskey).adapt_outgoing(&mut misskey);
+        assert_eq!(misskey["type"], "Like");
+        assert_eq!(misskey["_misskey_reaction"], "🎉");
+
+        let mut mastodon = like.clone();
+        CompatProfile::for_software(Software::Mastodon).adapt_outgoing(&mut mastodon);
+        assert_eq!(mastodon, like);
+
+        // Received variants become Likes with content
+        let profile = CompatProfile::for_software(Software::Misskey);
+        let mut received = json!({ "type": "Like", "_misskey_reaction": "👍" });
+        profile.normalize_incoming(&mut received);
+        assert_eq!(received["content"], "👍");
+        let mut received = pleroma;
+        profile.normalize_incoming(&mut received);
+        assert_eq!(received["type"], "Like");
+
+        let mut from_mastodon = json!({ "type": "EmojiReact", "content": "👍" });
+        CompatProfile::for_software(Software::Mastodon).normalize_incoming(&mut from_mastodon);
+        assert_eq!(from_mastodon["type"], "EmojiReact");
+    }
+
+    #[test]
+    fn test_gotosocial_gets_interaction_policy() {
+        let profile = CompatProfile::for_software(Software::GoToSocial);
+        let mut public = json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "type": "Create",
+            "object": {
+                "type": "Note",
+                "attributedTo": "https://example.com/users/alice",
+                "to": [PUBLIC],
+                "cc": ["https://example.com/users/alice/followers"]
+            }
+        });
+        profile.adapt_outgoing(&mut public);
+        assert_eq!(
+            public["object"]["interactionPolicy"]["canReply"]["always"],
+            json!([PUBLIC])
+        );
+        assert_eq!(public["@context"][1]["gts"], GOTOSOCIAL_NS);
+
+        let mut followers_only = json!({
+            "type": "Create",
+            "object": {
+                "type": "Note",
+                "attributedTo": "https://example.com/users/alice",
+                "to": ["https://example.com/users/alice/followers"]
+            }
+        });
+        profile.adapt_outgoing(&mut followers_only);
+        let policy = &followers_only["object"]["interactionPolicy"];
+        assert_eq!(
+            policy["canLike"]["always"],
+            json!([
+                "https://example.com/users/alice/followers",
+                "https://example.com/users/alice"
+            ])
+        );
+        assert_eq!(
+            policy["canAnnounce"]["always"],
+            json!(["https://example.com/users/alice"])
+        );
+
+        let mut note = json!({ "type": "Create", "object": { "type": "Note", "to": [PUBLIC] } });
+        CompatProfile::for_software(Software::Mastodon).adapt_outgoing(&mut note);
+        assert!(note["object"].get("interactionPolicy").is_none());
+    }
+}
@@ -0,0 +1,228 @@
+//! User credentials
+//!
+//! Local users log in with a password to authorize OAuth applications.
+//! Passwords are stored as argon2id PHC strings
+//! (`$argon2id$v=19$m=<memory>,t=<iterations>,p=<lanes>$<salt>$<hash>`),
+//! which carry their parameters, so the cost can be raised without
+//! invalidating stored hashes; [`needs_rehash`] tells when a hash should be
+//! replaced after a successful login. PBKDF2-HMAC-SHA256 hashes of earlier
+//! versions are still verified. Tokens handed out to users, such as password
+//! reset tokens and OAuth tokens, are random and only stored as their SHA-256.
+
+use std::num::NonZeroU32;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD};
+use ring::pbkdf2;
+use sha2::{Digest, Sha256};
+
+/// Identifier of legacy PBKDF2 hashes in PHC strings
+const PBKDF2_ID: &str = "pbkdf2-sha256";
+
+/// Shortest accepted password
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Longest accepted password
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+
+/// Whether a password has an acceptable length
+pub fn is_acceptable_password(password: &str) -> bool {
+    (MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&password.chars().count())
+}
+
+/// Hasher of new passwords: argon2id with the parameters OWASP recommends
+/// (19 MiB of memory, 2 iterations, 1 lane)
+fn hasher() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+}
+
+/// Hash a password with a new random salt
+///
+/// Takes a noticeable time by design; call it off the async runtime.
+pub fn hash_password(password: &str) -> String {
+    hash_with(&hasher(), password)
+}
+
+fn hash_with(hasher: &Argon2, password: &str) -> String {
+    let salt =
+        SaltString::encode_b64(&rand::random::<[u8; 16]>()).expect("16 bytes are a valid salt");
+    hasher
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2 accepts passwords of any acceptable length")
+        .to_string()
+}
+
+/// Whether a password matches a hash of [`hash_password`] or a legacy
+/// PBKDF2 hash
+///
+/// Malformed hashes match no password. The comparison takes constant time.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    if password_hash.starts_with(&format!("${}$", PBKDF2_ID)) {
+        return verify_pbkdf2(password, password_hash);
+    }
+    let Ok(parsed) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    if parsed.algorithm != Algorithm::Argon2id.ident() {
+        return false;
+    }
+    // The parameters of the hash apply, not those of `hasher`
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
+}
+
+/// Whether a verified hash should be replaced by one of [`hash_password`]
+///
+/// True for legacy PBKDF2 hashes and argon2id hashes with other parameters.
+pub fn needs_rehash(password_hash: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+        || !Params::try_from(&parsed).is_ok_and(|params| {
+            let current = Params::default();
+            (params.m_cost(), params.t_cost(), params.p_cost())
+                == (current.m_cost(), current.t_cost(), current.p_cost())
+        })
+}
+
+/// Verify a PBKDF2-HMAC-SHA256 hash (`$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`)
+fn verify_pbkdf2(password: &str, password_hash: &str) -> bool {
+    let mut parts = password_hash.split('$');
+    let (Some(""), Some(PBKDF2_ID), Some(params), Some(salt), Some(hash), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+    let Some(iterations) = params
+        .strip_prefix("i=")
+        .and_then(|i| i.parse().ok())
+        .and_then(NonZeroU32::new)
+    else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (STANDARD_NO_PAD.decode(salt), STANDARD_NO_PAD.decode(hash)) else {
+        return false;
+    };
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+/// New random token of 256 bits, URL-safe base64 encoded
+pub fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// SHA-256 of a token as stored in the database, hex encoded
+pub fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap argon2id hasher so the tests run quickly in debug builds
+    fn test_hasher() -> Argon2<'static> {
+        Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(64, 1, 1, None).unwrap(),
+        )
+    }
+
+    /// PBKDF2 hash as stored by earlier versions
+    fn pbkdf2_hash(password: &str, iterations: u32) -> String {
+        let salt = b"0123456789abcdef";
+        let mut hash = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations).unwrap(),
+            salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+        format!(
+            "${}$i={}${}${}",
+            PBKDF2_ID,
+            iterations,
+            STANDARD_NO_PAD.encode(salt),
+            STANDARD_NO_PAD.encode(hash)
+        )
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_with(&test_hasher(), "correct horse");
+        assert!(hash.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert_ne!(hash, hash_with(&test_hasher(), "correct horse"));
+    }
+
+    #[test]
+    fn test_legacy_pbkdf2_hash() {
+        let hash = pbkdf2_hash("correct horse", 1000);
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        assert!(needs_rehash(&hash_with(&test_hasher(), "secret")));
+        assert!(!needs_rehash(&hash_with(&hasher(), "secret")));
+        assert!(needs_rehash("garbage"));
+    }
+
+    #[test]
+    fn test_malformed_hash() {
+        assert!(!verify_password("", ""));
+        assert!(!verify_password(
+            "secret",
+            "$pbkdf2-sha256$i=0$c2FsdA$aGFzaA"
+        ));
+        assert!(!verify_password(
+            "secret",
+            "$argon2id$v=19$m=65536$c2FsdA$aGFzaA"
+        ));
+        assert!(!verify_password(
+            "secret",
+            "$argon2i$v=19$m=64,t=1,p=1$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA"
+        ));
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_ne!(random_token(), random_token());
+        assert_eq!(random_token().len(), 43);
+        assert_eq!(
+            token_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_password_length() {
+        assert!(!is_acceptable_password("short"));
+        assert!(is_acceptable_password("long enough"));
+        assert!(!is_acceptable_password(
+            &"x".repeat(MAX_PASSWORD_LENGTH + 1)
+        ));
+    }
+}
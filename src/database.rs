@@ -3,20 +3,67 @@
 //! Provides MongoDB schemas and operations for ActivityPub entities,
 //! PKI key management, and system configuration.
 
-use crate::pki::TrustLevel;
+use crate::extensions::Extensions;
+use crate::language::{self, LanguageMap};
+use crate::messaging::{
+    AuditEventMessage, EXCHANGE_EMAIL, EXCHANGE_PUSH, EXCHANGE_WEBHOOKS, EmailKind, EmailMessage,
+    FailureClass, FollowCounts, FollowDirection, NotificationType, PushMessage,
+    ROUTING_KEY_WEBHOOK_EVENT, WebhookEvent, WebhookEventMessage,
+};
+use crate::pki::{
+    DomainSignature, DomainVerificationChallenge, KeyEncryptor, PkiError, TrustLevel,
+};
 use crate::{ActivityType, ObjectType};
 use chrono::{DateTime, Utc};
 use futures::stream::TryStreamExt;
 use mongodb::{
     Collection, Database, IndexModel,
-    bson::{Bson, Document, doc, oid::ObjectId},
-    error::Error as MongoError,
-    options::IndexOptions,
+    bson::{Binary, Bson, DateTime as BsonDateTime, Document, doc, oid::ObjectId},
+    error::{Error as MongoError, ErrorKind as MongoErrorKind},
+    options::{IndexOptions, ReturnDocument},
     results::UpdateResult,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::SystemTime;
 use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+mod aliases;
+mod applications;
+mod articles;
+mod batch;
+mod boosts;
+mod connection;
+mod deliveries;
+mod events;
+mod feeds;
+mod filters;
+mod instances;
+mod lists;
+mod quotes;
+mod replay;
+mod retention;
+
+pub use aliases::{alias_actor_url, alias_resources};
+pub use applications::ApplicationProfile;
+pub use articles::{article_url, articles_url, slugify};
+pub use batch::{BatchDocument, BatchInsert, WriteBatchConfig, WriteBatcher};
+pub use boosts::{BoostDocument, TimelineItem};
+pub use connection::{CircuitState, ConnectionMonitor};
+pub use deliveries::{DELIVERY_RECORD_RETENTION_DAYS, DeliveryAttempt, DeliveryDocument};
+pub use events::{
+    EventDetails, JoinMode, ParticipationDocument, ParticipationStatus, PlaceDocument,
+    PostalAddress,
+};
+pub use feeds::{FeedKind, FeedSubscriptionDocument, HomeFeedEntryDocument, normalize_hashtag};
+pub use filters::{
+    FilterAction, FilterContext, FilterDocument, FilterKeyword, FilterStatus, FilterUpdate,
+};
+pub use instances::{InstanceDocument, InstanceNodeInfo};
+pub use lists::{ListDocument, ListRepliesPolicy};
+pub use quotes::QuoteOutcome;
 
 /// Database-related errors
 #[derive(Error, Debug)]
@@ -41,6 +88,9 @@ pub enum DatabaseError {
 
     #[error("Operation failed: {0}")]
     OperationError(String),
+
+    #[error("Database unavailable: {0}")]
+    Unavailable(String),
 }
 
 /// Actor document in MongoDB
@@ -106,6 +156,10 @@ pub struct ActorDocument {
     /// Account status
     pub status: ActorStatus,
 
+    /// Moderation restriction
+    #[serde(default)]
+    pub restriction: ActorRestriction,
+
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
 
@@ -125,6 +179,106 @@ pub struct ActorDocument {
     pub statuses_count: i64,
 }
 
+impl ActorDocument {
+    /// Build a document for a remote actor from its ActivityStreams JSON
+    ///
+    /// Returns `None` without an `id` URL or `inbox`.
+    pub fn from_activitypub(actor: &serde_json::Value) -> Option<Self> {
+        let actor_id = json_str(actor, "id")?;
+        let domain = url::Url::parse(&actor_id).ok()?.host_str()?.to_string();
+        let preferred_username = json_str(actor, "preferredUsername").unwrap_or_default();
+        let now = Utc::now();
+        Some(Self {
+            id: None,
+            name: json_str(actor, "name").unwrap_or_else(|| preferred_username.clone()),
+            preferred_username,
+            domain,
+            actor_type: json_str(actor, "type").unwrap_or_else(|| "Person".to_string()),
+            summary: json_str(actor, "summary"),
+            icon: json_image_url(actor.get("icon")),
+            image: json_image_url(actor.get("image")),
+            inbox: json_str(actor, "inbox")?,
+            outbox: json_str(actor, "outbox").unwrap_or_default(),
+            following: json_str(actor, "following").unwrap_or_default(),
+            followers: json_str(actor, "followers").unwrap_or_default(),
+            liked: json_str(actor, "liked"),
+            featured: json_str(actor, "featured"),
+            public_key: actor
+                .get("publicKey")
+                .and_then(|key| PublicKeyDocument::from_activitypub(key, &actor_id)),
+            endpoints: actor
+                .get("endpoints")
+                .and_then(|endpoints| mongodb::bson::to_document(endpoints).ok()),
+            attachment: actor
+                .get("attachment")
+                .and_then(serde_json::Value::as_array)
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| mongodb::bson::to_document(item).ok())
+                        .collect()
+                }),
+            additional_properties: remote_actor_flags(actor),
+            status: ActorStatus::Active,
+            restriction: ActorRestriction::None,
+            created_at: now,
+            updated_at: now,
+            local: false,
+            followers_count: 0,
+            following_count: 0,
+            statuses_count: 0,
+            actor_id,
+        })
+    }
+
+    /// Profile flag stored in the additional properties
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.additional_properties
+            .as_ref()
+            .and_then(|props| props.get_bool(name).ok())
+    }
+
+    /// Whether the account chose to be listed in the directory and found by
+    /// account searches
+    pub fn discoverable(&self) -> bool {
+        self.flag("discoverable") == Some(true)
+    }
+
+    /// Whether the public posts of the account may be indexed by searches
+    ///
+    /// Local accounts are indexed unless they opt out, remote ones only when
+    /// they opt in.
+    pub fn indexable(&self) -> bool {
+        match self.flag("indexable") {
+            Some(flag) => flag,
+            None => self.local,
+        }
+    }
+}
+
+/// Order of the account directory
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DirectoryOrder {
+    /// Most recently posting accounts first
+    #[default]
+    Active,
+    /// Most followed accounts first
+    Followers,
+}
+
+/// Account listed in the directory
+#[derive(Debug, Clone, Deserialize)]
+pub struct DirectoryEntry {
+    pub actor: ActorDocument,
+
+    /// Accepted followers
+    pub followers: i64,
+
+    /// Creation time of the latest public post
+    pub last_status_at: Option<DateTime<Utc>>,
+}
+
 /// Public key embedded document
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublicKeyDocument {
@@ -137,6 +291,27 @@ pub struct PublicKeyDocument {
     pub created_at: DateTime<Utc>,
 }
 
+impl PublicKeyDocument {
+    /// Read the `publicKey` of a remote actor
+    fn from_activitypub(key: &serde_json::Value, actor_id: &str) -> Option<Self> {
+        let public_key_pem = json_str(key, "publicKeyPem")?;
+        let ed25519 = crate::httpsignature::public_key_from_pem(
+            &public_key_pem,
+            &crate::httpsignature::SignatureAlgorithm::Ed25519,
+        )
+        .is_ok();
+        Some(Self {
+            id: json_str(key, "id").unwrap_or_else(|| format!("{}#main-key", actor_id)),
+            owner: json_str(key, "owner").unwrap_or_else(|| actor_id.to_string()),
+            algorithm: if ed25519 { "ed25519" } else { "rsa" }.to_string(),
+            key_size: None,
+            fingerprint: crate::pki::PublicKey::calculate_fingerprint(&public_key_pem).ok()?,
+            public_key_pem,
+            created_at: Utc::now(),
+        })
+    }
+}
+
 /// Actor status enumeration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ActorStatus {
@@ -150,6 +325,19 @@ pub enum ActorStatus {
     Pending,
 }
 
+/// Moderation restriction of an active actor
+///
+/// Silenced actors are kept off the public and local timelines; limited
+/// actors additionally only reach local accounts that follow them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActorRestriction {
+    #[default]
+    None,
+    Silenced,
+    Limited,
+}
+
 /// Object document in MongoDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectDocument {
@@ -168,12 +356,24 @@ pub struct ObjectDocument {
     /// Content/body of the object
     pub content: Option<String>,
 
+    /// Content in several languages, keyed by language tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_map: Option<LanguageMap>,
+
     /// Summary or excerpt
     pub summary: Option<String>,
 
+    /// Summary in several languages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_map: Option<LanguageMap>,
+
     /// Display name
     pub name: Option<String>,
 
+    /// Display name in several languages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_map: Option<LanguageMap>,
+
     /// Media type of content
     pub media_type: Option<String>,
 
@@ -204,6 +404,10 @@ pub struct ObjectDocument {
     /// In reply to (for Notes)
     pub in_reply_to: Option<String>,
 
+    /// Object this one quotes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+
     /// Conversation/context
     pub conversation: Option<String>,
 
@@ -213,7 +417,11 @@ pub struct ObjectDocument {
     /// Media attachments
     pub attachment: Option<Vec<AttachmentDocument>>,
 
-    /// Language code
+    /// Cover image of an article
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<AttachmentDocument>,
+
+    /// Language tag of the default content
     pub language: Option<String>,
 
     /// Content warning/sensitive flag
@@ -228,6 +436,14 @@ pub struct ObjectDocument {
     /// Visibility level
     pub visibility: VisibilityLevel,
 
+    /// Publication status
+    #[serde(default)]
+    pub status: ObjectStatus,
+
+    /// Pinned by the author, listing it in their featured collection
+    #[serde(default)]
+    pub featured: bool,
+
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
 
@@ -235,6 +451,249 @@ pub struct ObjectDocument {
     pub reply_count: i64,
     pub like_count: i64,
     pub announce_count: i64,
+    #[serde(default)]
+    pub quote_count: i64,
+
+    /// Schedule, location and attendance of an `Event`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventDetails>,
+}
+
+impl ObjectDocument {
+    /// Build a document for a remote object from its ActivityStreams JSON
+    pub fn from_activitypub(object: &serde_json::Value, object_type: ObjectType) -> Self {
+        let extensions = Extensions::from_json(object);
+        let hashtags: Vec<TagDocument> = extensions
+            .hashtags
+            .into_iter()
+            .map(|hashtag| TagDocument {
+                tag_type: "Hashtag".to_string(),
+                name: hashtag.name,
+                href: hashtag.href.map(String::from),
+            })
+            .collect();
+
+        // Objects may carry their text only in the language maps
+        let content_map = language::read_map(object.get("contentMap"));
+        let content = json_str(object, "content").or_else(|| {
+            let map = content_map.as_ref()?;
+            map.get(&language::primary_language(map, None)?).cloned()
+        });
+        let language = content_map
+            .as_ref()
+            .and_then(|map| language::primary_language(map, content.as_deref()));
+
+        let mut document = Self {
+            id: None,
+            object_id: json_str(object, "id")
+                .unwrap_or_else(|| format!("unknown-{}", uuid::Uuid::new_v4())),
+            object_type,
+            attributed_to: json_str(object, "attributedTo").unwrap_or_else(|| "unknown".into()),
+            content,
+            content_map,
+            summary: json_str(object, "summary"),
+            summary_map: language::read_map(object.get("summaryMap")),
+            name: json_str(object, "name"),
+            name_map: language::read_map(object.get("nameMap")),
+            media_type: Some("text/html".to_string()),
+            url: json_str(object, "url"),
+            published: json_datetime(object, "published"),
+            updated: json_datetime(object, "updated"),
+            to: json_string_array(object.get("to")),
+            cc: json_string_array(object.get("cc")),
+            bto: json_string_array(object.get("bto")),
+            bcc: json_string_array(object.get("bcc")),
+            audience: json_string_array(object.get("audience")),
+            in_reply_to: json_str(object, "inReplyTo"),
+            quote: extensions.quote.map(String::from),
+            conversation: json_str(object, "conversation"),
+            tag: (!hashtags.is_empty()).then_some(hashtags),
+            attachment: AttachmentDocument::parse_list(object.get("attachment")),
+            image: AttachmentDocument::parse_image(object.get("image")),
+            language,
+            sensitive: extensions.sensitive,
+            // Kept to tell who may quote the object
+            additional_properties: object
+                .get("interactionPolicy")
+                .and_then(|policy| mongodb::bson::to_bson(policy).ok())
+                .map(|policy| doc! { "interactionPolicy": policy }),
+            local: false,
+            visibility: VisibilityLevel::Public,
+            status: ObjectStatus::Published,
+            featured: false,
+            created_at: Utc::now(),
+            reply_count: 0,
+            like_count: 0,
+            announce_count: 0,
+            quote_count: 0,
+            event: None,
+        };
+        if document.object_type == ObjectType::Event {
+            document.event = Some(EventDetails::from_activitypub(object));
+        }
+        document.visibility = document.addressed_visibility();
+        document
+    }
+
+    /// Visibility following from the addressing of the object
+    ///
+    /// Objects with the public collection in `to` are public and those
+    /// with it in `cc` unlisted. Objects addressed to a followers
+    /// collection are followers-only, all others direct.
+    pub fn addressed_visibility(&self) -> VisibilityLevel {
+        let is_public = |recipient: &String| {
+            matches!(
+                recipient.as_str(),
+                "https://www.w3.org/ns/activitystreams#Public" | "as:Public" | "Public"
+            )
+        };
+        if self.to.iter().flatten().any(is_public) {
+            return VisibilityLevel::Public;
+        }
+        if self.cc.iter().flatten().any(is_public) {
+            return VisibilityLevel::Unlisted;
+        }
+        let to_followers = [&self.to, &self.cc, &self.bto, &self.bcc, &self.audience]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|recipient| recipient.ends_with("/followers"));
+        if to_followers {
+            VisibilityLevel::Followers
+        } else {
+            VisibilityLevel::Direct
+        }
+    }
+
+    /// Whether the actor is among the recipients of the object
+    pub fn is_addressed_to(&self, actor_id: &str) -> bool {
+        [&self.to, &self.cc, &self.bto, &self.bcc, &self.audience]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|recipient| recipient == actor_id)
+    }
+
+    /// Tombstone standing in for the object once it is deleted
+    pub fn tombstone(&self, deleted: DateTime<Utc>) -> serde_json::Value {
+        serde_json::json!({
+            "type": "Tombstone",
+            "id": self.object_id,
+            "formerType": format!("{:?}", self.object_type),
+            "deleted": deleted.to_rfc3339()
+        })
+    }
+
+    /// Render the object as ActivityStreams JSON, without `@context`
+    pub fn to_activitypub(&self) -> serde_json::Value {
+        let mut object = serde_json::json!({
+            "type": format!("{:?}", self.object_type),
+            "id": self.object_id,
+            "attributedTo": self.attributed_to,
+            "name": self.name,
+            "nameMap": self.name_map,
+            "content": self.content,
+            "contentMap": self.content_map,
+            "summary": self.summary,
+            "summaryMap": self.summary_map,
+            "url": self.url,
+            "published": self.published.unwrap_or(self.created_at).to_rfc3339(),
+            "updated": self.updated.map(|updated| updated.to_rfc3339()),
+            "to": self.to,
+            "cc": self.cc,
+            "inReplyTo": self.in_reply_to,
+            "conversation": self.conversation,
+            "sensitive": self.sensitive,
+            "tag": self.tag,
+            "attachment": self.attachment.as_ref().map(|attachments| {
+                attachments.iter().map(|a| a.to_activitypub()).collect::<Vec<_>>()
+            }),
+            "image": self.image.as_ref().map(AttachmentDocument::to_activitypub)
+        });
+        self.write_quote_properties(&mut object);
+        if let Some(event) = &self.event {
+            event.write_json(&mut object);
+        }
+        object
+    }
+}
+
+/// Earlier version of an edited object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectRevisionDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// ActivityPub ID of the edited object
+    pub object_id: String,
+
+    /// Content of this version
+    pub content: Option<String>,
+
+    /// Content of this version in several languages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_map: Option<LanguageMap>,
+
+    /// Summary or content warning of this version
+    pub summary: Option<String>,
+
+    /// Display name of this version
+    pub name: Option<String>,
+
+    /// Content warning/sensitive flag of this version
+    pub sensitive: Option<bool>,
+
+    /// Tags of this version
+    pub tag: Option<Vec<TagDocument>>,
+
+    /// Media attachments of this version
+    pub attachment: Option<Vec<AttachmentDocument>>,
+
+    /// When this version was published or last edited
+    pub published: DateTime<Utc>,
+
+    /// When this version was replaced by an edit
+    pub replaced_at: DateTime<Utc>,
+}
+
+impl ObjectRevisionDocument {
+    /// Record the current version of an object being replaced
+    pub fn of(object: &ObjectDocument, replaced_at: DateTime<Utc>) -> Self {
+        Self {
+            id: None,
+            object_id: object.object_id.clone(),
+            content: object.content.clone(),
+            content_map: object.content_map.clone(),
+            summary: object.summary.clone(),
+            name: object.name.clone(),
+            sensitive: object.sensitive,
+            tag: object.tag.clone(),
+            attachment: object.attachment.clone(),
+            published: object
+                .updated
+                .or(object.published)
+                .unwrap_or(object.created_at),
+            replaced_at,
+        }
+    }
+
+    /// ActivityStreams representation of this version of `object`
+    pub fn to_activitypub(&self, object: &ObjectDocument) -> serde_json::Value {
+        serde_json::json!({
+            "type": format!("{:?}", object.object_type),
+            "attributedTo": object.attributed_to,
+            "name": self.name,
+            "content": self.content,
+            "contentMap": self.content_map,
+            "summary": self.summary,
+            "sensitive": self.sensitive,
+            "tag": self.tag,
+            "attachment": self.attachment.as_ref().map(|attachments| {
+                attachments.iter().map(|a| a.to_activitypub()).collect::<Vec<_>>()
+            }),
+            "published": self.published.to_rfc3339()
+        })
+    }
 }
 
 /// Tag document for hashtags and mentions
@@ -245,6 +704,27 @@ pub struct TagDocument {
     pub href: Option<String>,
 }
 
+/// Read an attachment duration, converting the seconds stored by older
+/// versions into an xsd:duration
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Duration {
+        Text(String),
+        Seconds(i64),
+    }
+
+    Ok(
+        Option::<Duration>::deserialize(deserializer)?.map(|duration| match duration {
+            Duration::Text(text) => text,
+            Duration::Seconds(seconds) => format!("PT{}S", seconds),
+        }),
+    )
+}
+
 /// Attachment document for media
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttachmentDocument {
@@ -254,10 +734,129 @@ pub struct AttachmentDocument {
     pub name: Option<String>,
     pub width: Option<i32>,
     pub height: Option<i32>,
-    pub duration: Option<i32>,
+    /// Play time as an xsd:duration, such as `PT2M5S`
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub duration: Option<String>,
     pub blurhash: Option<String>,
 }
 
+impl AttachmentDocument {
+    /// Parse an ActivityStreams attachment (Document, Image, Video, Audio)
+    ///
+    /// Returns `None` for entries without a resolvable URL or for non-media
+    /// attachments such as `PropertyValue`.
+    pub fn from_activitypub(value: &serde_json::Value) -> Option<Self> {
+        let attachment_type = value.get("type").and_then(|t| t.as_str())?;
+        if !matches!(attachment_type, "Document" | "Image" | "Video" | "Audio") {
+            return None;
+        }
+
+        // `url` may be a plain string, a Link object or an array of either
+        let url_value = value.get("url")?;
+        let (url, link_media_type) = match url_value {
+            serde_json::Value::Array(items) => items.iter().find_map(Self::parse_url_value)?,
+            other => Self::parse_url_value(other)?,
+        };
+
+        let as_i32 = |key: &str| {
+            value
+                .get(key)
+                .and_then(|v| v.as_i64())
+                .and_then(|v| i32::try_from(v).ok())
+        };
+
+        Some(Self {
+            attachment_type: attachment_type.to_string(),
+            url,
+            media_type: value
+                .get("mediaType")
+                .and_then(|m| m.as_str())
+                .map(|s| s.to_string())
+                .or(link_media_type),
+            name: value
+                .get("name")
+                .and_then(|n| n.as_str())
+                .map(|s| s.to_string()),
+            width: as_i32("width"),
+            height: as_i32("height"),
+            duration: value
+                .get("duration")
+                .and_then(|d| d.as_str())
+                .map(|s| s.to_string()),
+            blurhash: value
+                .get("blurhash")
+                .and_then(|b| b.as_str())
+                .map(|s| s.to_string()),
+        })
+    }
+
+    /// Parse an `attachment` property, accepting a single object or an array
+    pub fn parse_list(value: Option<&serde_json::Value>) -> Option<Vec<Self>> {
+        let attachments: Vec<Self> = match value? {
+            serde_json::Value::Array(items) => {
+                items.iter().filter_map(Self::from_activitypub).collect()
+            }
+            single => Self::from_activitypub(single).into_iter().collect(),
+        };
+
+        if attachments.is_empty() {
+            None
+        } else {
+            Some(attachments)
+        }
+    }
+
+    /// Render the attachment as ActivityStreams JSON
+    pub fn to_activitypub(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "type": self.attachment_type,
+            "url": self.url,
+        });
+
+        let fields = [
+            (
+                "mediaType",
+                self.media_type.clone().map(serde_json::Value::from),
+            ),
+            ("name", self.name.clone().map(serde_json::Value::from)),
+            ("width", self.width.map(serde_json::Value::from)),
+            ("height", self.height.map(serde_json::Value::from)),
+            (
+                "duration",
+                self.duration.clone().map(serde_json::Value::from),
+            ),
+            (
+                "blurhash",
+                self.blurhash.clone().map(serde_json::Value::from),
+            ),
+        ];
+        for (key, field) in fields
+            .into_iter()
+            .filter_map(|(key, field)| field.map(|f| (key, f)))
+        {
+            value[key] = field;
+        }
+
+        value
+    }
+
+    /// Extract the href and optional media type from a `url` entry
+    fn parse_url_value(value: &serde_json::Value) -> Option<(String, Option<String>)> {
+        match value {
+            serde_json::Value::String(url) => Some((url.clone(), None)),
+            serde_json::Value::Object(link) => {
+                let href = link.get("href").and_then(|h| h.as_str())?;
+                let media_type = link
+                    .get("mediaType")
+                    .and_then(|m| m.as_str())
+                    .map(|s| s.to_string());
+                Some((href.to_string(), media_type))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Visibility levels for objects
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum VisibilityLevel {
@@ -271,6 +870,19 @@ pub enum VisibilityLevel {
     Direct,
 }
 
+/// Publication status of objects
+///
+/// Scheduled objects are neither served nor listed until domainservd
+/// publishes them at their `published` time.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ObjectStatus {
+    #[default]
+    #[serde(rename = "published")]
+    Published,
+    #[serde(rename = "scheduled")]
+    Scheduled,
+}
+
 /// Activity document in MongoDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActivityDocument {
@@ -338,6 +950,167 @@ pub struct ActivityDocument {
     pub error: Option<String>,
 }
 
+impl ActivityDocument {
+    /// Build a document for a remote activity from its ActivityStreams JSON
+    pub fn from_activitypub(activity: &serde_json::Value) -> Self {
+        Self {
+            id: None,
+            activity_id: json_str(activity, "id")
+                .unwrap_or_else(|| format!("unknown-{}", uuid::Uuid::new_v4())),
+            activity_type: activity
+                .get("type")
+                .and_then(|t| serde_json::from_value(t.clone()).ok())
+                .unwrap_or(ActivityType::Other),
+            actor: json_str(activity, "actor").unwrap_or_else(|| "unknown".into()),
+            object: json_str(activity, "object"),
+            target: json_str(activity, "target"),
+            name: json_str(activity, "name"),
+            summary: json_str(activity, "summary"),
+            published: json_datetime(activity, "published"),
+            updated: None,
+            to: json_string_array(activity.get("to")),
+            cc: json_string_array(activity.get("cc")),
+            bto: json_string_array(activity.get("bto")),
+            bcc: json_string_array(activity.get("bcc")),
+            additional_properties: None,
+            local: false,
+            status: ActivityStatus::Completed,
+            created_at: Utc::now(),
+            attempts: 0,
+            last_attempt: None,
+            error: None,
+        }
+    }
+}
+
+/// Read a string property from ActivityStreams JSON
+fn json_str(value: &serde_json::Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+/// Timeline filter for objects in `languages` or of unknown language
+///
+/// Matches everything if `languages` is empty.
+fn language_filter(languages: &[String]) -> Document {
+    if languages.is_empty() {
+        return Document::new();
+    }
+    doc! {
+        "$or": [
+            { "language": { "$in": languages } },
+            { "language": null }
+        ]
+    }
+}
+
+/// Page of a timeline, delimited by the storage IDs of its posts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimelinePage {
+    /// Only posts stored before this one
+    pub max_id: Option<ObjectId>,
+    /// Only posts stored after this one, newest first
+    pub since_id: Option<ObjectId>,
+    /// Only posts stored after this one, starting right after it
+    pub min_id: Option<ObjectId>,
+    pub limit: i64,
+}
+
+impl TimelinePage {
+    /// Condition on storage IDs selecting the page, if it is delimited
+    fn id_range(&self) -> Option<Document> {
+        let mut range = Document::new();
+        if let Some(max_id) = self.max_id {
+            range.insert("$lt", max_id);
+        }
+        if let Some(after) = self.min_id.or(self.since_id) {
+            range.insert("$gt", after);
+        }
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// Sort direction of storage IDs while reading the page
+    ///
+    /// With `min_id` the page starts right after it, so posts are read
+    /// oldest first and reversed.
+    fn direction(&self) -> i32 {
+        if self.min_id.is_some() { 1 } else { -1 }
+    }
+}
+
+/// Read an RFC 3339 timestamp property from ActivityStreams JSON
+fn json_datetime(value: &serde_json::Value, key: &str) -> Option<DateTime<Utc>> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Read an addressing property that may be a single string or an array
+fn json_string_array(value: Option<&serde_json::Value>) -> Option<Vec<String>> {
+    match value {
+        Some(serde_json::Value::Array(arr)) => {
+            let strings: Vec<String> = arr
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(|s| s.to_string())
+                .collect();
+            if strings.is_empty() {
+                None
+            } else {
+                Some(strings)
+            }
+        }
+        Some(serde_json::Value::String(s)) => Some(vec![s.clone()]),
+        _ => None,
+    }
+}
+
+/// `discoverable` and `indexable` opt-ins of a remote actor, where given
+fn remote_actor_flags(actor: &serde_json::Value) -> Option<Document> {
+    let extensions = crate::extensions::Extensions::from_json(actor);
+    let mut flags = Document::new();
+    for (flag, value) in [
+        ("discoverable", extensions.discoverable),
+        ("indexable", extensions.indexable),
+    ] {
+        if let Some(value) = value {
+            flags.insert(flag, value);
+        }
+    }
+    (!flags.is_empty()).then_some(flags)
+}
+
+/// URL of an `icon` or `image`, given as a link or an Image object
+fn json_image_url(value: Option<&serde_json::Value>) -> Option<String> {
+    match value? {
+        serde_json::Value::String(url) => Some(url.clone()),
+        serde_json::Value::Array(items) => json_image_url(items.first()),
+        image => json_image_url(image.get("url")),
+    }
+}
+
+/// Object count field an activity of this type is tallied in
+///
+/// Announces are counted by the boosts they record instead.
+fn interaction_count_field(activity_type: &ActivityType) -> Option<&'static str> {
+    match activity_type {
+        ActivityType::Like => Some("like_count"),
+        _ => None,
+    }
+}
+
+/// Filter for the follows of an actor in `direction`
+fn follows_filter(actor_id: &str, direction: FollowDirection) -> Document {
+    match direction {
+        FollowDirection::Followers => doc! { "following": actor_id },
+        FollowDirection::Following => doc! { "follower": actor_id },
+    }
+}
+
 /// Activity processing status
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ActivityStatus {
@@ -412,6 +1185,74 @@ pub struct KeyDocument {
 
     /// Associated domain (for domain keys)
     pub domain: Option<String>,
+
+    /// Latest domain verification challenge (for imported user keys)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<DomainVerificationChallenge>,
+}
+
+/// Store the domain signature of a user key
+pub fn domain_signature_document(ds: &DomainSignature) -> Document {
+    let mut doc = Document::new();
+    doc.insert("domain", ds.domain.clone());
+    doc.insert("signature", ds.signature.clone());
+    let system_time: SystemTime = ds.signed_at.into();
+    doc.insert("signed_at", Bson::DateTime(system_time.into()));
+    doc.insert("domain_key_id", ds.domain_key_id.clone());
+    doc.insert("verification_chain", ds.verification_chain.clone());
+    doc
+}
+
+impl KeyDocument {
+    /// Whether signatures made with this key must be rejected at `now`
+    ///
+    /// Expired keys and rotated keys past their overlap window count as
+    /// revoked.
+    pub fn is_revoked(&self, now: DateTime<Utc>) -> bool {
+        match self.status {
+            KeyStatus::Revoked | KeyStatus::Expired => true,
+            KeyStatus::Active | KeyStatus::Pending | KeyStatus::Rotated => {
+                self.expires_at.is_some_and(|expires_at| expires_at <= now)
+            }
+        }
+    }
+
+    /// Store the private key encrypted with the current key-encryption key
+    ///
+    /// Keys in plaintext or encrypted under another key-encryption key are
+    /// re-encrypted. Returns whether the stored key changed.
+    pub async fn encrypt_private_key(
+        &mut self,
+        encryptor: &KeyEncryptor,
+    ) -> Result<bool, PkiError> {
+        let Some(stored) = &self.private_key_pem else {
+            return Ok(false);
+        };
+        let algorithm = self.encryption_algorithm.as_deref();
+        if encryptor.is_current(stored, algorithm) {
+            return Ok(false);
+        }
+
+        let pem = encryptor.decrypt(&self.key_id, stored, algorithm).await?;
+        self.private_key_pem = Some(encryptor.encrypt(&self.key_id, &pem).await?);
+        self.encryption_algorithm = Some(encryptor.algorithm().to_string());
+        Ok(true)
+    }
+
+    /// Decrypted PEM of the private key, `None` for public-only keys
+    pub async fn decrypt_private_key(
+        &self,
+        encryptor: &KeyEncryptor,
+    ) -> Result<Option<String>, PkiError> {
+        match &self.private_key_pem {
+            Some(stored) => Ok(Some(
+                encryptor
+                    .decrypt(&self.key_id, stored, self.encryption_algorithm.as_deref())
+                    .await?,
+            )),
+            None => Ok(None),
+        }
+    }
 }
 
 /// Key types in the PKI hierarchy
@@ -438,6 +1279,9 @@ pub enum KeyStatus {
     Expired,
     #[serde(rename = "pending")]
     Pending,
+    /// Replaced by a newer key, still accepted until `expires_at`
+    #[serde(rename = "rotated")]
+    Rotated,
 }
 
 /// Domain configuration document
@@ -514,6 +1358,28 @@ pub enum DomainStatus {
     Suspended,
     #[serde(rename = "maintenance")]
     Maintenance,
+    /// Removed by its operator; the record is kept so the name is known
+    #[serde(rename = "deleted")]
+    Deleted,
+}
+
+/// Federation statistics of a domain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DomainStats {
+    /// Active local actors
+    pub actors: u64,
+
+    /// Published notes and articles of local actors
+    pub posts: u64,
+
+    /// Accepted follows of local actors by remote actors
+    pub remote_followers: u64,
+
+    /// Accepted follows of remote actors by local actors
+    pub remote_following: u64,
+
+    /// Creation time of the latest local activity
+    pub last_activity: Option<DateTime<Utc>>,
 }
 
 /// Follow relationship document
@@ -542,6 +1408,14 @@ pub struct FollowDocument {
 
     /// Accept/reject timestamp
     pub responded_at: Option<DateTime<Utc>>,
+
+    /// Inbox of the follower, recorded when the follow is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follower_inbox: Option<String>,
+
+    /// Shared inbox of the follower's server (`endpoints.sharedInbox`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follower_shared_inbox: Option<String>,
 }
 
 /// Follow relationship status
@@ -557,745 +1431,5156 @@ pub enum FollowStatus {
     Cancelled,
 }
 
-/// Database manager for MongoDB operations
-pub struct DatabaseManager {
-    pub database: Database,
-}
+/// Cached copy of remote media served through the media proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaCacheDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
 
-impl DatabaseManager {
-    /// Create a new database manager
-    pub fn new(database: Database) -> Self {
-        Self { database }
-    }
+    /// Original remote media URL
+    pub url: String,
 
-    /// Initialize database collections and indexes
-    pub async fn initialize(&self) -> Result<(), DatabaseError> {
-        self.create_indexes().await?;
-        Ok(())
-    }
+    /// Content type reported by the remote server
+    pub content_type: Option<String>,
 
-    /// Create database indexes for performance
-    async fn create_indexes(&self) -> Result<(), DatabaseError> {
-        // Actor indexes
-        let actors: Collection<ActorDocument> = self.database.collection("actors");
-        actors
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "actor_id": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// Raw media bytes
+    pub data: Binary,
 
-        actors
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "domain": 1, "preferred_username": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// Size of the media in bytes
+    pub size: i64,
 
-        // Object indexes
-        let objects: Collection<ObjectDocument> = self.database.collection("objects");
-        objects
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "object_id": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// When the media was fetched from the remote server
+    pub fetched_at: DateTime<Utc>,
 
-        objects
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "attributed_to": 1, "published": -1 })
-                    .build(),
-            )
-            .await?;
+    /// When the cached copy should be refreshed
+    pub expires_at: DateTime<Utc>,
 
-        // Activity indexes
-        let activities: Collection<ActivityDocument> = self.database.collection("activities");
-        activities
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "activity_id": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// When the cached copy is removed by the TTL index
+    pub purge_at: BsonDateTime,
+}
 
-        activities
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "actor": 1, "published": -1 })
-                    .build(),
-            )
-            .await?;
+/// Direct message conversation of a local actor
+///
+/// Direct objects sharing a `conversation` form one conversation; every
+/// local participant has their own document so each sees the latest
+/// message addressed to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
 
-        // Key indexes
-        let keys: Collection<KeyDocument> = self.database.collection("keys");
-        keys.create_index(
-            IndexModel::builder()
-                .keys(doc! { "key_id": 1 })
-                .options(IndexOptions::builder().unique(true).build())
-                .build(),
-        )
-        .await?;
+    /// Local actor the conversation is listed for
+    pub owner: String,
 
-        keys.create_index(IndexModel::builder().keys(doc! { "actor_id": 1 }).build())
-            .await?;
+    /// Shared `conversation` of the messages
+    pub conversation_id: String,
 
-        // Domain indexes
-        let domains: Collection<DomainDocument> = self.database.collection("domains");
-        domains
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "domain": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// Authors and recipients of the messages, including the owner
+    pub participants: Vec<String>,
 
-        // Follow indexes
-        let follows: Collection<FollowDocument> = self.database.collection("follows");
-        follows
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! { "follower": 1, "following": 1 })
-                    .options(IndexOptions::builder().unique(true).build())
-                    .build(),
-            )
-            .await?;
+    /// Latest message the owner took part in
+    pub last_object_id: String,
 
-        Ok(())
-    }
+    /// Time of the latest message
+    pub updated_at: DateTime<Utc>,
+}
 
-    /// Insert a new actor
-    pub async fn insert_actor(&self, actor: ActorDocument) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<ActorDocument> = self.database.collection("actors");
-        let result = collection.insert_one(actor).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
-    }
+/// Moderation report created from an incoming or local `Flag` activity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
 
-    /// Find actor by ID
-    pub async fn find_actor_by_id(
-        &self,
-        actor_id: &str,
-    ) -> Result<Option<ActorDocument>, DatabaseError> {
-        let collection: Collection<ActorDocument> = self.database.collection("actors");
-        let result = collection.find_one(doc! { "actor_id": actor_id }).await?;
-        Ok(result)
-    }
+    /// Unique report identifier
+    pub report_id: String,
 
-    /// Find actor by username and domain
-    pub async fn find_actor_by_username(
-        &self,
+    /// Actor who filed the report
+    pub reporter: String,
+
+    /// Reported actor, if one could be determined
+    pub target_actor: Option<String>,
+
+    /// Reported objects
+    pub target_objects: Vec<String>,
+
+    /// Reporter's comment
+    pub content: Option<String>,
+
+    /// ID of the originating Flag activity
+    pub flag_activity_id: Option<String>,
+
+    /// Domain the report was received for
+    pub domain: String,
+
+    /// Report status
+    pub status: ReportStatus,
+
+    /// Moderation action applied when resolving
+    pub action_taken: Option<String>,
+
+    /// Moderator who resolved the report
+    pub resolved_by: Option<String>,
+
+    /// Moderator's resolution note
+    pub resolution_note: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Resolution timestamp
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Report status enumeration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ReportStatus {
+    #[serde(rename = "open")]
+    Open,
+    #[serde(rename = "resolved")]
+    Resolved,
+    #[serde(rename = "dismissed")]
+    Dismissed,
+}
+
+/// Moderation policy applied to a remote domain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainBlockDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Remote domain name
+    pub domain: String,
+
+    /// Severity of the block
+    pub severity: DomainBlockSeverity,
+
+    /// Reason shown to moderators
+    pub reason: Option<String>,
+
+    /// Moderator who created the block
+    pub created_by: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Webhook of a domain receiving signed callbacks for its events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique webhook identifier
+    pub webhook_id: String,
+
+    /// Domain whose events are sent
+    pub domain: String,
+
+    /// Endpoint callbacks are POSTed to
+    pub url: String,
+
+    /// Shared secret the callbacks are signed with
+    pub secret: String,
+
+    /// Subscribed events; all events when empty
+    pub events: Vec<WebhookEvent>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Notification of a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique notification identifier
+    pub notification_id: String,
+
+    pub notification_type: NotificationType,
+
+    /// Local actor notified
+    pub recipient: String,
+
+    /// Username and domain of the recipient
+    pub username: String,
+    pub domain: String,
+
+    /// Actor who mentioned, followed, liked or rejected the recipient
+    pub account: String,
+
+    /// Post mentioning the recipient, or the post liked
+    pub object_id: Option<String>,
+
+    /// Whether the post is a direct message
+    #[serde(default)]
+    pub direct: bool,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl NotificationDocument {
+    /// Create a notification of a local actor
+    pub fn new(
+        notification_type: NotificationType,
+        recipient: &ActorDocument,
+        account: &str,
+        object_id: Option<String>,
+        direct: bool,
+    ) -> Self {
+        Self {
+            id: None,
+            notification_id: uuid::Uuid::new_v4().to_string(),
+            notification_type,
+            recipient: recipient.actor_id.clone(),
+            username: recipient.preferred_username.clone(),
+            domain: recipient.domain.clone(),
+            account: account.to_string(),
+            object_id,
+            direct,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Message pushing the notification to the recipient's subscriptions
+    pub fn push_message(&self) -> PushMessage {
+        PushMessage {
+            notification_id: self.notification_id.clone(),
+            notification_type: self.notification_type,
+            recipient: self.recipient.clone(),
+            username: self.username.clone(),
+            domain: self.domain.clone(),
+            account: self.account.clone(),
+            object_id: self.object_id.clone(),
+            direct: self.direct,
+        }
+    }
+}
+
+/// Notifications a push subscription wants, named as in the Mastodon API
+///
+/// Only mentions, follows and favourites are sent so far; rejected follow
+/// requests count as follows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PushAlerts {
+    pub mention: bool,
+    pub follow: bool,
+    pub favourite: bool,
+    pub reblog: bool,
+    pub poll: bool,
+    pub status: bool,
+    pub follow_request: bool,
+    pub update: bool,
+}
+
+impl PushAlerts {
+    pub const NAMES: [&str; 8] = [
+        "mention",
+        "follow",
+        "favourite",
+        "reblog",
+        "poll",
+        "status",
+        "follow_request",
+        "update",
+    ];
+
+    /// Whether notifications of a type are wanted
+    pub fn wants(&self, notification_type: NotificationType) -> bool {
+        match notification_type {
+            NotificationType::Mention => self.mention,
+            NotificationType::Follow => self.follow,
+            NotificationType::Favourite => self.favourite,
+            NotificationType::FollowRejected => self.follow,
+        }
+    }
+}
+
+/// Whose notifications a push subscription receives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushPolicy {
+    /// Everyone's
+    #[default]
+    All,
+    /// Those of actors the user follows
+    Followed,
+    /// Those of the user's followers
+    Follower,
+    /// Nobody's
+    None,
+}
+
+impl PushPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushPolicy::All => "all",
+            PushPolicy::Followed => "followed",
+            PushPolicy::Follower => "follower",
+            PushPolicy::None => "none",
+        }
+    }
+}
+
+impl std::str::FromStr for PushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(PushPolicy::All),
+            "followed" => Ok(PushPolicy::Followed),
+            "follower" => Ok(PushPolicy::Follower),
+            "none" => Ok(PushPolicy::None),
+            _ => Err(format!("Unknown push policy: {}", s)),
+        }
+    }
+}
+
+/// Web Push subscription of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushSubscriptionDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the access token the subscription was made with; updated
+    /// when the session's tokens are refreshed
+    pub access_token_hash: String,
+
+    /// User the subscription belongs to
+    pub username: String,
+    pub domain: String,
+
+    /// Push service endpoint notifications are POSTed to
+    pub endpoint: String,
+
+    /// Client public key (base64url, uncompressed P-256 point)
+    pub p256dh: String,
+
+    /// Client authentication secret (base64url)
+    pub auth: String,
+
+    pub alerts: PushAlerts,
+
+    #[serde(default)]
+    pub policy: PushPolicy,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// VAPID key pair a domain signs its push requests with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VapidKeyDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub domain: String,
+
+    /// P-256 private key (base64url), encrypted with the domain's
+    /// [`KeyEncryptor`]
+    pub private_key: String,
+
+    /// Encryption algorithm of the private key, plaintext when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_algorithm: Option<String>,
+
+    /// P-256 public key (base64url, uncompressed point), the
+    /// `applicationServerKey` of clients
+    pub public_key: String,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Domain block severity
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DomainBlockSeverity {
+    /// Content is accepted but hidden from public timelines
+    #[serde(rename = "silence")]
+    Silence,
+    /// All federation with the domain is refused
+    #[serde(rename = "suspend")]
+    Suspend,
+}
+
+/// Incoming content held back by the spam filter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique quarantine entry identifier
+    pub quarantine_id: String,
+
+    /// ActivityPub ID of the quarantined object
+    pub object_id: Option<String>,
+
+    /// Object type (Note, Article, etc.)
+    pub object_type: String,
+
+    /// Actor the object is attributed to
+    pub actor: String,
+
+    /// Domain the object was received for
+    pub target_domain: String,
+
+    /// Filters that rejected the content
+    pub reasons: Vec<String>,
+
+    /// Normalized content hash used for duplicate detection
+    pub content_hash: String,
+
+    /// Original incoming pipeline message as JSON
+    pub payload: String,
+
+    /// Quarantine status
+    pub status: QuarantineStatus,
+
+    /// Moderator who reviewed the entry
+    pub reviewed_by: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Review timestamp
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// Quarantine status enumeration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum QuarantineStatus {
+    #[serde(rename = "quarantined")]
+    Quarantined,
+    #[serde(rename = "released")]
+    Released,
+    #[serde(rename = "discarded")]
+    Discarded,
+}
+
+/// Occurrence counter for normalized content hashes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentHashDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the normalized content
+    pub hash: String,
+
+    /// Actor the content is attributed to; only an author's own posts count
+    #[serde(default)]
+    pub attributed_to: String,
+
+    /// Distinct objects with the content seen in the current window
+    #[serde(default)]
+    pub object_ids: Vec<String>,
+
+    /// When the content was first seen in the current window
+    pub first_seen: DateTime<Utc>,
+
+    /// When the counter is removed by the TTL index
+    pub purge_at: BsonDateTime,
+}
+
+/// Entry of the append-only audit log of administrative actions
+///
+/// Written from the [`AuditEventMessage`]s adminservd sends; entries are
+/// never updated or deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Subject of the admin user
+    pub actor: String,
+
+    /// Role of the admin user, if any
+    pub role: Option<String>,
+
+    /// Action name, such as `domain.update`
+    pub action: String,
+
+    /// HTTP method of the request
+    pub method: String,
+
+    /// Request path
+    pub path: String,
+
+    /// Resource the action applies to
+    pub target: Option<String>,
+
+    /// HTTP status the request was answered with
+    pub status: u16,
+
+    /// State of the target before the action
+    pub before: Option<serde_json::Value>,
+
+    /// Requested state, with secrets redacted
+    pub after: Option<serde_json::Value>,
+
+    /// Top-level fields that differ between `before` and `after`
+    pub changes: Vec<String>,
+
+    /// When the request was made
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<AuditEventMessage> for AuditLogDocument {
+    fn from(event: AuditEventMessage) -> Self {
+        Self {
+            id: None,
+            actor: event.actor,
+            role: event.role,
+            action: event.action,
+            method: event.method,
+            path: event.path,
+            target: event.target,
+            status: event.status,
+            before: event.before,
+            after: event.after,
+            changes: event.changes,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+/// Message that was dead-lettered by the broker or rejected by a consumer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique dead letter identifier
+    pub dead_letter_id: String,
+
+    /// AMQP message ID of the original message
+    pub message_id: Option<String>,
+
+    /// Queue the message was consumed from when it failed
+    pub queue: String,
+
+    /// Exchange the message was originally published to
+    pub exchange: Option<String>,
+
+    /// Routing key the message was originally published with
+    pub routing_key: Option<String>,
+
+    /// Broker dead-letter reason (rejected, expired, maxlen, ...)
+    pub reason: String,
+
+    /// Pipeline stage that rejected the message
+    pub stage: Option<String>,
+
+    /// Error reported by the rejecting consumer
+    pub error: Option<String>,
+
+    /// Whether the failure is worth retrying automatically
+    pub failure_class: FailureClass,
+
+    /// Number of times the message was re-injected before this failure
+    pub attempts: u32,
+
+    /// Content type of the original message
+    pub content_type: Option<String>,
+
+    /// Original message body
+    pub payload: Binary,
+
+    /// Dead letter status
+    pub status: DeadLetterStatus,
+
+    /// When the message is re-injected automatically, if scheduled
+    pub next_retry_at: Option<BsonDateTime>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Re-injection timestamp
+    pub retried_at: Option<DateTime<Utc>>,
+}
+
+/// Dead letter status enumeration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeadLetterStatus {
+    /// Waiting for an automatic retry or for an administrator
+    #[serde(rename = "pending")]
+    Pending,
+    /// Re-injected into its original queue
+    #[serde(rename = "retried")]
+    Retried,
+}
+
+/// AMQP message written together with the data it announces
+///
+/// The outbox relay in domainservd publishes pending messages with publisher
+/// confirms, so a message is never lost once the write that produced it has
+/// succeeded, and never sent for a write that failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxMessageDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique message identifier, used as the AMQP message ID
+    pub message_id: String,
+
+    /// Exchange to publish to
+    pub exchange: String,
+
+    /// Routing key to publish with
+    pub routing_key: String,
+
+    /// Content type of the payload
+    pub content_type: Option<String>,
+
+    /// Message body
+    pub payload: String,
+
+    /// Outbox status
+    pub status: OutboxStatus,
+
+    /// Number of failed publish attempts
+    pub attempts: i32,
+
+    /// Error of the last failed publish attempt
+    pub last_error: Option<String>,
+
+    /// Until when a relay has claimed the message
+    pub claimed_until: Option<BsonDateTime>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the broker confirmed the message
+    pub published_at: Option<DateTime<Utc>>,
+
+    /// When a published message is removed by the TTL index
+    pub purge_at: Option<BsonDateTime>,
+
+    /// Trace propagation entries of the write that produced the message
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub trace_context: HashMap<String, String>,
+}
+
+impl OutboxMessageDocument {
+    /// Create a pending outbox message
+    pub fn new(
+        exchange: &str,
+        routing_key: &str,
+        content_type: Option<&str>,
+        payload: String,
+    ) -> Self {
+        Self {
+            id: None,
+            message_id: uuid::Uuid::new_v4().to_string(),
+            exchange: exchange.to_string(),
+            routing_key: routing_key.to_string(),
+            content_type: content_type.map(|ct| ct.to_string()),
+            payload,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            claimed_until: None,
+            created_at: Utc::now(),
+            published_at: None,
+            purge_at: None,
+            trace_context: HashMap::new(),
+        }
+    }
+
+    /// Attach the trace context the relay continues when publishing
+    pub fn with_trace_context(mut self, trace_context: HashMap<String, String>) -> Self {
+        self.trace_context = trace_context;
+        self
+    }
+}
+
+/// Outbox message status enumeration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum OutboxStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "published")]
+    Published,
+}
+
+/// Login credentials of a local Person actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Username of the actor
+    pub username: String,
+
+    /// Domain of the actor
+    pub domain: String,
+
+    /// Actor the credentials log in as
+    pub actor_id: String,
+
+    /// Password hash of [`crate::credentials::hash_password`]; users
+    /// without one cannot log in
+    pub password_hash: Option<String>,
+
+    /// Address emails to the user are sent to
+    #[serde(default)]
+    pub email: Option<String>,
+
+    /// SHA-256 of a pending password reset token
+    pub reset_token_hash: Option<String>,
+
+    /// When the password reset token stops being accepted
+    pub reset_expires_at: Option<DateTime<Utc>>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Last update timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Sign-up waiting for review on a domain in approval mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationApplicationDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Unique application identifier
+    pub application_id: String,
+
+    /// Requested username
+    pub username: String,
+
+    /// Domain the account is requested on
+    pub domain: String,
+
+    /// Contact email given by the applicant
+    pub email: Option<String>,
+
+    /// Applicant's reason for joining
+    pub reason: Option<String>,
+
+    /// Password hash the account gets once approved
+    pub password_hash: String,
+
+    /// Review status
+    pub status: ApplicationStatus,
+
+    /// Moderator who reviewed the application
+    pub reviewed_by: Option<String>,
+
+    /// Reason given for the decision
+    pub review_note: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Review timestamp
+    pub reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// Review status of a registration application
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ApplicationStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "approved")]
+    Approved,
+    #[serde(rename = "rejected")]
+    Rejected,
+}
+
+/// Invite code letting users sign up on a domain in invite mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the code
+    pub code_hash: String,
+
+    /// Domain the code is valid on
+    pub domain: String,
+
+    /// Sign-ups the code allows; unlimited when `None`
+    pub max_uses: Option<u32>,
+
+    /// Sign-ups made with the code
+    pub uses: u32,
+
+    /// When the code stops being accepted; never when `None`
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// OAuth client application registered through `/api/v1/apps`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthAppDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Public client identifier
+    pub client_id: String,
+
+    /// SHA-256 of the client secret
+    pub client_secret_hash: String,
+
+    /// Name shown to users on the authorization page
+    pub name: String,
+
+    /// Website of the application
+    pub website: Option<String>,
+
+    /// Redirect URIs authorization codes may be sent to
+    pub redirect_uris: Vec<String>,
+
+    /// Scopes the application may request
+    pub scopes: Vec<String>,
+
+    /// Registration timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Authorization code waiting to be exchanged for tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationCodeDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the code
+    pub code_hash: String,
+
+    /// Application the code was issued to
+    pub client_id: String,
+
+    /// User who authorized the application
+    pub username: String,
+
+    /// Domain of the user
+    pub domain: String,
+
+    /// Redirect URI the code was sent to
+    pub redirect_uri: String,
+
+    /// Granted scopes
+    pub scopes: Vec<String>,
+
+    /// PKCE `S256` challenge the token request has to answer
+    pub code_challenge: Option<String>,
+
+    /// Issue timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the code stops being accepted and is removed by the TTL index
+    pub expires_at: BsonDateTime,
+}
+
+/// Access token of a user for an application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessTokenDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the token
+    pub token_hash: String,
+
+    /// User the token acts for
+    pub username: String,
+
+    /// Domain of the user
+    pub domain: String,
+
+    /// Application the token was issued to
+    pub client_id: String,
+
+    /// Granted scopes
+    pub scopes: Vec<String>,
+
+    /// Issue timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the token stops being accepted and is removed by the TTL index
+    pub expires_at: BsonDateTime,
+}
+
+/// Refresh token issued along with an access token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshTokenDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// SHA-256 of the token
+    pub token_hash: String,
+
+    /// SHA-256 of the access token issued with it
+    pub access_token_hash: String,
+
+    /// User the token acts for
+    pub username: String,
+
+    /// Domain of the user
+    pub domain: String,
+
+    /// Application the token was issued to
+    pub client_id: String,
+
+    /// Granted scopes
+    pub scopes: Vec<String>,
+
+    /// Issue timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the token stops being accepted and is removed by the TTL index
+    pub expires_at: BsonDateTime,
+}
+
+/// An index [`DatabaseManager::initialize`] creates
+#[derive(Debug, Clone)]
+pub struct IndexSpec {
+    pub collection: &'static str,
+    pub keys: Document,
+    pub unique: bool,
+    /// Remove documents this long after the date in the (single) key field
+    pub expire_after: Option<std::time::Duration>,
+    /// Explicit name; MongoDB derives one from the keys otherwise
+    pub name: Option<&'static str>,
+}
+
+impl IndexSpec {
+    fn new(collection: &'static str, keys: Document) -> Self {
+        Self {
+            collection,
+            keys,
+            unique: false,
+            expire_after: None,
+            name: None,
+        }
+    }
+
+    fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// Expire documents once the date in the key field has passed
+    fn expire_at_key(mut self) -> Self {
+        self.expire_after = Some(std::time::Duration::from_secs(0));
+        self
+    }
+
+    fn named(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Name of the index as MongoDB reports it
+    pub fn name(&self) -> String {
+        match self.name {
+            Some(name) => name.to_string(),
+            None => self
+                .keys
+                .iter()
+                .map(|(key, direction)| match direction {
+                    Bson::Int32(d) => format!("{}_{}", key, d),
+                    Bson::String(d) => format!("{}_{}", key, d),
+                    other => format!("{}_{}", key, other),
+                })
+                .collect::<Vec<_>>()
+                .join("_"),
+        }
+    }
+
+    fn model(&self) -> IndexModel {
+        let mut options = IndexOptions::default();
+        options.unique = self.unique.then_some(true);
+        options.expire_after = self.expire_after;
+        options.name = self.name.map(str::to_string);
+        IndexModel::builder()
+            .keys(self.keys.clone())
+            .options(options)
+            .build()
+    }
+}
+
+/// Every index the services rely on, grouped by collection
+///
+/// Queries should be backed by one of these; add the index here when adding
+/// a query on new fields.
+pub fn index_registry() -> Vec<IndexSpec> {
+    vec![
+        // Actor lookups by id and by username within a domain
+        IndexSpec::new("actors", doc! { "actor_id": 1 }).unique(),
+        IndexSpec::new("actors", doc! { "domain": 1, "preferred_username": 1 }).unique(),
+        IndexSpec::new("actors", doc! { "local": 1 }),
+        IndexSpec::new("actors", doc! { "created_at": -1 }),
+        // Handles bound to local actors, see `find_actor_by_alias`
+        IndexSpec::new("actors", doc! { "additional_properties.alsoKnownAs": 1 }),
+        // Media the proxy may fetch, see `is_known_media_url`
+        IndexSpec::new("actors", doc! { "icon": 1 }),
+        IndexSpec::new("actors", doc! { "image": 1 }),
+        IndexSpec::new("objects", doc! { "attachment.url": 1 }),
+        IndexSpec::new("objects", doc! { "image.url": 1 }),
+        // Objects by id, author outboxes, timelines and full-text search
+        IndexSpec::new("objects", doc! { "object_id": 1 }).unique(),
+        IndexSpec::new("objects", doc! { "attributed_to": 1, "published": -1 }),
+        // Articles by their human-readable URL
+        IndexSpec::new("objects", doc! { "url": 1 }),
+        IndexSpec::new(
+            "objects",
+            doc! { "visibility": 1, "object_type": 1, "published": -1 },
+        ),
+        IndexSpec::new(
+            "objects",
+            doc! { "visibility": 1, "language": 1, "published": -1 },
+        ),
+        IndexSpec::new(
+            "objects",
+            doc! { "local": 1, "visibility": 1, "object_type": 1, "published": -1 },
+        ),
+        IndexSpec::new(
+            "objects",
+            doc! { "content": "text", "summary": "text", "name": "text" },
+        )
+        .named("objects_text"),
+        IndexSpec::new("objects", doc! { "status": 1, "published": 1 }),
+        IndexSpec::new("objects", doc! { "conversation": 1, "published": 1 }),
+        // Quotes of a post, recounted when one is deleted
+        IndexSpec::new("objects", doc! { "quote": 1 }),
+        // Direct message conversations of each local actor
+        IndexSpec::new("conversations", doc! { "owner": 1, "conversation_id": 1 }).unique(),
+        IndexSpec::new("conversations", doc! { "owner": 1, "updated_at": -1 }),
+        // Activities by id, actor and type
+        IndexSpec::new("activities", doc! { "activity_id": 1 }).unique(),
+        IndexSpec::new("activities", doc! { "actor": 1, "published": -1 }),
+        IndexSpec::new("activities", doc! { "activity_type": 1, "created_at": -1 }),
+        IndexSpec::new(
+            "activities",
+            doc! { "object": 1, "activity_type": 1, "actor": 1 },
+        ),
+        // Keys by id, the active keys of an actor and domain signing keys
+        IndexSpec::new("keys", doc! { "key_id": 1 }).unique(),
+        IndexSpec::new("keys", doc! { "actor_id": 1, "status": 1 }),
+        IndexSpec::new("keys", doc! { "domain": 1, "key_type": 1 }),
+        IndexSpec::new("domains", doc! { "domain": 1 }).unique(),
+        // Following lists use the prefix of the unique index, followers
+        // lists the reverse one
+        IndexSpec::new("follows", doc! { "follower": 1, "following": 1 }).unique(),
+        IndexSpec::new("follows", doc! { "following": 1, "status": 1 }),
+        IndexSpec::new("webfinger_profiles", doc! { "subject": 1 }).unique(),
+        IndexSpec::new("webfinger_profiles", doc! { "aliases": 1 }),
+        // Login credentials of local users and pending password resets
+        IndexSpec::new("credentials", doc! { "domain": 1, "username": 1 }).unique(),
+        IndexSpec::new("credentials", doc! { "reset_token_hash": 1 }),
+        // Sign-ups waiting for review and invite codes of domains
+        IndexSpec::new("registration_applications", doc! { "application_id": 1 }).unique(),
+        IndexSpec::new(
+            "registration_applications",
+            doc! { "domain": 1, "username": 1, "status": 1 },
+        ),
+        IndexSpec::new(
+            "registration_applications",
+            doc! { "status": 1, "created_at": 1 },
+        ),
+        IndexSpec::new("invites", doc! { "code_hash": 1 }).unique(),
+        // OAuth applications, codes and tokens; codes and tokens are looked
+        // up by the hash of their value and removed when they expire
+        IndexSpec::new("oauth_apps", doc! { "client_id": 1 }).unique(),
+        IndexSpec::new("oauth_codes", doc! { "code_hash": 1 }).unique(),
+        IndexSpec::new("oauth_codes", doc! { "expires_at": 1 }).expire_at_key(),
+        IndexSpec::new("access_tokens", doc! { "token_hash": 1 }).unique(),
+        IndexSpec::new("access_tokens", doc! { "domain": 1, "username": 1 }),
+        IndexSpec::new("access_tokens", doc! { "expires_at": 1 }).expire_at_key(),
+        IndexSpec::new("refresh_tokens", doc! { "token_hash": 1 }).unique(),
+        IndexSpec::new("refresh_tokens", doc! { "domain": 1, "username": 1 }),
+        IndexSpec::new("refresh_tokens", doc! { "access_token_hash": 1 }),
+        IndexSpec::new("refresh_tokens", doc! { "expires_at": 1 }).expire_at_key(),
+        IndexSpec::new("reports", doc! { "report_id": 1 }).unique(),
+        IndexSpec::new("reports", doc! { "status": 1, "created_at": -1 }),
+        IndexSpec::new("domain_blocks", doc! { "domain": 1 }).unique(),
+        IndexSpec::new("webhooks", doc! { "webhook_id": 1 }).unique(),
+        IndexSpec::new("webhooks", doc! { "domain": 1 }),
+        IndexSpec::new("notifications", doc! { "recipient": 1, "created_at": -1 }),
+        IndexSpec::new("push_subscriptions", doc! { "access_token_hash": 1 }).unique(),
+        IndexSpec::new("push_subscriptions", doc! { "domain": 1, "username": 1 }),
+        IndexSpec::new("vapid_keys", doc! { "domain": 1 }).unique(),
+        IndexSpec::new("lists", doc! { "domain": 1, "username": 1 }),
+        IndexSpec::new("filters", doc! { "domain": 1, "username": 1 }),
+        IndexSpec::new(
+            "followed_feeds",
+            doc! { "domain": 1, "username": 1, "kind": 1, "name": 1 },
+        )
+        .unique(),
+        IndexSpec::new("followed_feeds", doc! { "kind": 1, "name": 1 }),
+        IndexSpec::new(
+            "home_feed",
+            doc! { "domain": 1, "username": 1, "post_id": 1 },
+        )
+        .unique(),
+        IndexSpec::new("home_feed", doc! { "object_id": 1 }),
+        IndexSpec::new("boosts", doc! { "actor": 1, "object_id": 1 }).unique(),
+        IndexSpec::new("boosts", doc! { "actor": 1, "_id": -1 }),
+        IndexSpec::new("boosts", doc! { "object_id": 1 }),
+        IndexSpec::new("boosts", doc! { "activity_id": 1 }),
+        // RSVPs by event and actor, attendee collections and answers to Joins
+        IndexSpec::new("participations", doc! { "event_id": 1, "actor": 1 }).unique(),
+        IndexSpec::new(
+            "participations",
+            doc! { "event_id": 1, "status": 1, "created_at": 1 },
+        ),
+        IndexSpec::new("participations", doc! { "activity_id": 1 }),
+        IndexSpec::new("quarantine", doc! { "quarantine_id": 1 }).unique(),
+        IndexSpec::new("quarantine", doc! { "status": 1, "created_at": -1 }),
+        IndexSpec::new("content_hashes", doc! { "attributed_to": 1, "hash": 1 }).unique(),
+        IndexSpec::new("content_hashes", doc! { "purge_at": 1 }).expire_at_key(),
+        IndexSpec::new("dead_letters", doc! { "dead_letter_id": 1 }).unique(),
+        IndexSpec::new("dead_letters", doc! { "status": 1, "next_retry_at": 1 }),
+        IndexSpec::new("audit_log", doc! { "actor": 1, "_id": -1 }),
+        IndexSpec::new("audit_log", doc! { "action": 1, "_id": -1 }),
+        IndexSpec::new("audit_log", doc! { "target": 1, "_id": -1 }),
+        IndexSpec::new("message_outbox", doc! { "message_id": 1 }).unique(),
+        IndexSpec::new("message_outbox", doc! { "status": 1, "created_at": 1 }),
+        IndexSpec::new("message_outbox", doc! { "purge_at": 1 }).expire_at_key(),
+        IndexSpec::new("media_cache", doc! { "url": 1 }).unique(),
+        IndexSpec::new("media_cache", doc! { "purge_at": 1 }).expire_at_key(),
+        IndexSpec::new("deliveries", doc! { "activity_id": 1, "inbox": 1 }).unique(),
+        IndexSpec::new("deliveries", doc! { "purge_at": 1 }).expire_at_key(),
+        // Registry of remote instances, the failing ones among them and
+        // those due for a NodeInfo fetch
+        IndexSpec::new("instances", doc! { "domain": 1 }).unique(),
+        IndexSpec::new(
+            "instances",
+            doc! { "consecutive_failures": -1, "domain": 1 },
+        ),
+        IndexSpec::new("instances", doc! { "crawled_at": 1 }),
+    ]
+}
+
+/// Indexes of earlier versions, as `(collection, name)`, that
+/// [`DatabaseManager::initialize`] drops
+///
+/// They would reject current documents.
+const OBSOLETE_INDEXES: &[(&str, &str)] = &[
+    // Tokens were stored in plain text before only their hashes were
+    ("access_tokens", "token_1"),
+    // Content hashes were counted across all authors
+    ("content_hashes", "hash_1"),
+];
+
+/// Documents of earlier versions, as `(collection, filter)`, that
+/// [`DatabaseManager::initialize`] deletes before creating indexes
+///
+/// They cannot be used any more and would break unique indexes.
+fn obsolete_documents() -> Vec<(&'static str, Document)> {
+    vec![
+        // Tokens stored in plain text have no hash to look them up by, and
+        // their missing hashes collide in the unique `token_hash` index
+        ("access_tokens", doc! { "token_hash": { "$exists": false } }),
+    ]
+}
+
+/// Database manager for MongoDB operations
+pub struct DatabaseManager {
+    pub database: Database,
+    monitor: Arc<ConnectionMonitor>,
+}
+
+impl DatabaseManager {
+    /// Create a new database manager
+    ///
+    /// The connection monitor only learns from pings; use
+    /// [`DatabaseManager::connect`] to follow the driver's heartbeats.
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            monitor: Arc::default(),
+        }
+    }
+
+    /// Close the connections of the underlying client
+    ///
+    /// Call once all work has drained; operations on any handle sharing the
+    /// client fail afterwards. Does not wait for cursors held by abandoned
+    /// tasks.
+    pub async fn close(&self) {
+        self.database
+            .client()
+            .clone()
+            .shutdown()
+            .immediate(true)
+            .await;
+    }
+
+    /// Initialize database collections and indexes
+    pub async fn initialize(&self) -> Result<(), DatabaseError> {
+        self.drop_obsolete_indexes().await?;
+        self.delete_obsolete_documents().await?;
+        self.create_indexes().await?;
+        Ok(())
+    }
+
+    /// Drop the indexes of [`OBSOLETE_INDEXES`] that still exist
+    async fn drop_obsolete_indexes(&self) -> Result<(), DatabaseError> {
+        for (collection, name) in OBSOLETE_INDEXES {
+            let collection = self.database.collection::<Document>(collection);
+            // Listing fails for collections that do not exist yet
+            let Ok(names) = collection.list_index_names().await else {
+                continue;
+            };
+            if names.iter().any(|existing| existing == name) {
+                collection.drop_index(*name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the documents of [`obsolete_documents`]
+    async fn delete_obsolete_documents(&self) -> Result<(), DatabaseError> {
+        for (collection, filter) in obsolete_documents() {
+            let result = self
+                .database
+                .collection::<Document>(collection)
+                .delete_many(filter)
+                .await?;
+            if result.deleted_count > 0 {
+                info!(
+                    "Deleted {} obsolete documents from {}",
+                    result.deleted_count, collection
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the indexes of [`index_registry`]
+    ///
+    /// Creating an index that already exists with the same options is a
+    /// no-op, so this runs on every start.
+    async fn create_indexes(&self) -> Result<(), DatabaseError> {
+        for spec in index_registry() {
+            let collection = self.database.collection::<Document>(spec.collection);
+            collection.create_index(spec.model()).await.map_err(|e| {
+                DatabaseError::OperationError(format!(
+                    "Failed to create index {} on {}: {}",
+                    spec.name(),
+                    spec.collection,
+                    e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Insert a new actor
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_actor(&self, actor: ActorDocument) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let result = collection.insert_one(actor).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find actor by ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actor_by_id(
+        &self,
+        actor_id: &str,
+    ) -> Result<Option<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let result = collection.find_one(doc! { "actor_id": actor_id }).await?;
+        Ok(result)
+    }
+
+    /// Find actor by username and domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actor_by_username(
+        &self,
         username: &str,
         domain: &str,
-    ) -> Result<Option<ActorDocument>, DatabaseError> {
+    ) -> Result<Option<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let result = collection
+            .find_one(doc! { "preferred_username": username, "domain": domain })
+            .await?;
+        Ok(result)
+    }
+
+    /// Update actor
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_actor(
+        &self,
+        actor_id: &str,
+        update: Document,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let result = collection
+            .update_one(
+                doc! { "actor_id": actor_id },
+                doc! { "$set": update, "$currentDate": { "updated_at": true } },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Insert or replace the WebFinger profile with the same subject
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn upsert_webfinger_profile(
+        &self,
+        profile: crate::webfinger::JrdResource,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<crate::webfinger::JrdResource> =
+            self.database.collection("webfinger_profiles");
+        let result = collection
+            .replace_one(doc! { "subject": &profile.subject }, profile)
+            .upsert(true)
+            .await?;
+        Ok(result)
+    }
+
+    /// Insert a new object
+    ///
+    /// Direct objects join the conversation of the object they reply to,
+    /// or start their own, and are recorded in the conversations of their
+    /// local participants.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_object(
+        &self,
+        mut object: ObjectDocument,
+    ) -> Result<ObjectId, DatabaseError> {
+        let direct = object.visibility == VisibilityLevel::Direct;
+        if direct {
+            object.conversation = Some(self.conversation_of(&object).await?);
+        }
+
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection.insert_one(&object).await?;
+        if direct {
+            self.record_direct_message(&object).await?;
+        }
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Conversation a direct object belongs to
+    ///
+    /// Replies stay in the conversation of their parent so threads do not
+    /// split when a server assigns its own `conversation`.
+    async fn conversation_of(&self, object: &ObjectDocument) -> Result<String, DatabaseError> {
+        if let Some(parent) = &object.in_reply_to
+            && let Some(parent) = self.find_object_by_id(parent).await?
+            && let Some(conversation) = parent.conversation
+        {
+            return Ok(conversation);
+        }
+        Ok(object
+            .conversation
+            .clone()
+            .unwrap_or_else(|| object.object_id.clone()))
+    }
+
+    /// Make a direct object the latest message of its local participants
+    async fn record_direct_message(&self, object: &ObjectDocument) -> Result<(), DatabaseError> {
+        let Some(conversation_id) = &object.conversation else {
+            return Ok(());
+        };
+        let mut participants = vec![object.attributed_to.clone()];
+        for recipient in [&object.to, &object.cc, &object.bto, &object.bcc]
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            if !participants.contains(recipient) {
+                participants.push(recipient.clone());
+            }
+        }
+
+        let actors: Collection<ActorDocument> = self.database.collection("actors");
+        let local: Vec<ActorDocument> = actors
+            .find(doc! { "actor_id": { "$in": &participants }, "local": true })
+            .await?
+            .try_collect()
+            .await?;
+
+        let conversations: Collection<ConversationDocument> =
+            self.database.collection("conversations");
+        let updated_at = mongodb::bson::to_bson(&object.published.unwrap_or(object.created_at))?;
+        for owner in local {
+            conversations
+                .update_one(
+                    doc! { "owner": &owner.actor_id, "conversation_id": conversation_id },
+                    doc! {
+                        "$set": {
+                            "last_object_id": &object.object_id,
+                            "updated_at": updated_at.clone(),
+                        },
+                        "$addToSet": { "participants": { "$each": &participants } },
+                    },
+                )
+                .upsert(true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Conversations of a local actor, most recent first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_conversations(
+        &self,
+        owner: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ConversationDocument>, DatabaseError> {
+        let collection: Collection<ConversationDocument> =
+            self.database.collection("conversations");
+        let cursor = collection
+            .find(doc! { "owner": owner })
+            .sort(doc! { "updated_at": -1 })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Messages of a conversation the actor wrote or received, oldest first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_conversation_messages(
+        &self,
+        actor_id: &str,
+        conversation_id: &str,
+        limit: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let cursor = collection
+            .find(doc! {
+                "conversation": conversation_id,
+                "visibility": mongodb::bson::to_bson(&VisibilityLevel::Direct)?,
+                "$or": [
+                    { "attributed_to": actor_id },
+                    { "to": actor_id },
+                    { "cc": actor_id },
+                    { "bto": actor_id },
+                    { "bcc": actor_id },
+                ],
+            })
+            .sort(doc! { "published": 1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Find object by ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_object_by_id(
+        &self,
+        object_id: &str,
+    ) -> Result<Option<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection.find_one(doc! { "object_id": object_id }).await?;
+        Ok(result)
+    }
+
+    /// Insert a new activity
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_activity(
+        &self,
+        activity: ActivityDocument,
+    ) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let result = collection.insert_one(activity).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find activity by ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_activity_by_id(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<ActivityDocument>, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let result = collection
+            .find_one(doc! { "activity_id": activity_id })
+            .await?;
+        Ok(result)
+    }
+
+    /// Upsert a key
+    pub async fn upsert_key(&self, key: KeyDocument) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .replace_one(doc! { "key_id": &key.key_id }, key)
+            .upsert(true)
+            .await?;
+        Ok(result)
+    }
+
+    /// Insert a new key
+    pub async fn insert_key(&self, key: KeyDocument) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection.insert_one(key).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find key by ID
+    pub async fn find_key_by_id(&self, key_id: &str) -> Result<Option<KeyDocument>, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection.find_one(doc! { "key_id": key_id }).await?;
+        Ok(result)
+    }
+
+    /// Find keys by actor ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_keys_by_actor(
+        &self,
+        actor_id: &str,
+    ) -> Result<Vec<KeyDocument>, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let mut cursor = collection.find(doc! { "actor_id": actor_id }).await?;
+        let mut keys = Vec::new();
+
+        while cursor.advance().await? {
+            keys.push(cursor.deserialize_current()?);
+        }
+
+        Ok(keys)
+    }
+
+    /// Upsert a domain
+    pub async fn upsert_domain(
+        &self,
+        domain: DomainDocument,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        let result = collection
+            .replace_one(doc! { "domain": &domain.domain }, domain)
+            .upsert(true)
+            .await?;
+        Ok(result)
+    }
+
+    /// Insert a new domain
+    pub async fn insert_domain(&self, domain: DomainDocument) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        let result = collection.insert_one(domain).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find domain by name
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_domain_by_name(
+        &self,
+        domain_name: &str,
+    ) -> Result<Option<DomainDocument>, DatabaseError> {
+        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        let result = collection.find_one(doc! { "domain": domain_name }).await?;
+        Ok(result)
+    }
+
+    /// Mark a domain as deleted
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn tombstone_domain(&self, domain_name: &str) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        let result = collection
+            .update_one(
+                doc! { "domain": domain_name },
+                doc! {
+                    "$set": { "status": mongodb::bson::to_bson(&DomainStatus::Deleted)? },
+                    "$currentDate": { "updated_at": true },
+                },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Active domains that have a contact email address
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_domains_with_contact(&self) -> Result<Vec<DomainDocument>, DatabaseError> {
+        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        let cursor = collection
+            .find(doc! {
+                "status": mongodb::bson::to_bson(&DomainStatus::Active)?,
+                "contact_email": { "$type": "string", "$ne": "" },
+            })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Insert a new follow relationship
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_follow(&self, follow: FollowDocument) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let result = collection.insert_one(follow).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find follow relationship
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_follow(
+        &self,
+        follower: &str,
+        following: &str,
+    ) -> Result<Option<FollowDocument>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let result = collection
+            .find_one(doc! { "follower": follower, "following": following })
+            .await?;
+        Ok(result)
+    }
+
+    /// Record the answer to a follow request sent by a local actor
+    ///
+    /// Follows sent through the message queue are only stored as `Follow`
+    /// activities until answered; their follow is created here. Accepting
+    /// adds `following` to the follower's following collection, and both
+    /// answers refresh the follower's `following_count`; a rejected
+    /// follower is notified. Returns `false`, changing nothing, if the
+    /// follower never asked or withdrew the request.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn answer_follow(
+        &self,
+        follower: &str,
+        following: &str,
+        status: FollowStatus,
+        answer_id: Option<&str>,
+    ) -> Result<bool, DatabaseError> {
+        let now = Utc::now();
+        let accept_activity_id = answer_id.filter(|_| status == FollowStatus::Accepted);
+        match self.find_follow(follower, following).await? {
+            Some(follow) if follow.status == FollowStatus::Cancelled => return Ok(false),
+            Some(_) => {
+                let collection: Collection<FollowDocument> = self.database.collection("follows");
+                let mut set = doc! {
+                    "status": mongodb::bson::to_bson(&status)?,
+                    "responded_at": mongodb::bson::to_bson(&now)?,
+                };
+                if let Some(id) = accept_activity_id {
+                    set.insert("accept_activity_id", id);
+                }
+                collection
+                    .update_one(
+                        doc! { "follower": follower, "following": following },
+                        doc! { "$set": set },
+                    )
+                    .await?;
+            }
+            None => {
+                let activities: Collection<ActivityDocument> =
+                    self.database.collection("activities");
+                let Some(request) = activities
+                    .find_one(doc! {
+                        "activity_type": mongodb::bson::to_bson(&ActivityType::Follow)?,
+                        "actor": follower,
+                        "object": following,
+                    })
+                    .sort(doc! { "created_at": -1 })
+                    .await?
+                else {
+                    return Ok(false);
+                };
+                self.insert_follow(FollowDocument {
+                    id: None,
+                    follower: follower.to_string(),
+                    following: following.to_string(),
+                    status: status.clone(),
+                    activity_id: request.activity_id,
+                    accept_activity_id: accept_activity_id.map(str::to_string),
+                    created_at: request.created_at,
+                    responded_at: Some(now),
+                    follower_inbox: None,
+                    follower_shared_inbox: None,
+                })
+                .await?;
+            }
+        }
+
+        let counts = self
+            .count_follows(follower, FollowDirection::Following)
+            .await?;
+        self.update_actor_counts(follower, None, Some(counts.accepted as i64), None)
+            .await?;
+        if status == FollowStatus::Rejected
+            && let Some(actor) = self.find_actor_by_id(follower).await?
+        {
+            self.notify_follow_rejected(&actor, following).await?;
+        }
+        Ok(true)
+    }
+
+    /// Update follow status
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_follow_status(
+        &self,
+        follower: &str,
+        following: &str,
+        status: FollowStatus,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let result = collection
+            .update_one(
+                doc! { "follower": follower, "following": following },
+                doc! {
+                    "$set": { "status": mongodb::bson::to_bson(&status)? },
+                    "$currentDate": { "responded_at": true }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Get actor's outbox (recent objects)
+    ///
+    /// Only public and unlisted objects are listed.
+    pub async fn get_actor_outbox(
+        &self,
+        actor_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut cursor = collection
+            .find(doc! {
+                "attributed_to": actor_id,
+                "visibility": { "$in": ["public", "unlisted"] },
+                "status": { "$ne": "scheduled" },
+                "object_type": { "$ne": "Tombstone" },
+            })
+            .sort(doc! { "published": -1 })
+            .limit(limit)
+            .skip(offset as u64)
+            .await?;
+
+        let mut objects = Vec::new();
+        while cursor.advance().await? {
+            objects.push(cursor.deserialize_current()?);
+        }
+
+        Ok(objects)
+    }
+
+    /// Get actor's followers
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn get_actor_followers(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut cursor = collection
+            .find(doc! { "following": actor_id, "status": "accepted" })
+            .await?;
+
+        let mut followers = Vec::new();
+        while cursor.advance().await? {
+            let follow: FollowDocument = cursor.deserialize_current()?;
+            followers.push(follow.follower);
+        }
+
+        Ok(followers)
+    }
+
+    /// Accepted follows of an actor, with the inboxes of the followers
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_accepted_follows(
+        &self,
+        actor_id: &str,
+    ) -> Result<Vec<FollowDocument>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let cursor = collection
+            .find(doc! { "following": actor_id, "status": "accepted" })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Get actor's following
+    pub async fn get_actor_following(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut cursor = collection
+            .find(doc! { "follower": actor_id, "status": "accepted" })
+            .await?;
+
+        let mut following = Vec::new();
+        while cursor.advance().await? {
+            let follow: FollowDocument = cursor.deserialize_current()?;
+            following.push(follow.following);
+        }
+
+        Ok(following)
+    }
+
+    /// Get all follow documents where actor is the follower (all statuses)
+    pub async fn get_actor_following_all(
+        &self,
+        actor_id: &str,
+    ) -> Result<Vec<FollowDocument>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut cursor = collection.find(doc! { "follower": actor_id }).await?;
+
+        let mut follows = Vec::new();
+        while cursor.advance().await? {
+            follows.push(cursor.deserialize_current()?);
+        }
+
+        Ok(follows)
+    }
+
+    /// Get all follow documents where actor is being followed (all statuses)
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn get_actor_followers_all(
+        &self,
+        actor_id: &str,
+    ) -> Result<Vec<FollowDocument>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut cursor = collection.find(doc! { "following": actor_id }).await?;
+
+        let mut follows = Vec::new();
+        while cursor.advance().await? {
+            follows.push(cursor.deserialize_current()?);
+        }
+
+        Ok(follows)
+    }
+
+    /// List follows of an actor newest first
+    ///
+    /// `status` is an exact match on the follow status; `before` continues
+    /// a listing after the follow with that ID.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_follows_page(
+        &self,
+        actor_id: &str,
+        direction: FollowDirection,
+        status: Option<&str>,
+        before: Option<ObjectId>,
+        limit: i64,
+    ) -> Result<Vec<FollowDocument>, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut filter = follows_filter(actor_id, direction);
+        if let Some(status) = status {
+            filter.insert("status", status);
+        }
+        if let Some(before) = before {
+            filter.insert("_id", doc! { "$lt": before });
+        }
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Count the follows of an actor by status
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn count_follows(
+        &self,
+        actor_id: &str,
+        direction: FollowDirection,
+    ) -> Result<FollowCounts, DatabaseError> {
+        let collection: Collection<FollowDocument> = self.database.collection("follows");
+        let mut cursor = collection
+            .aggregate(vec![
+                doc! { "$match": follows_filter(actor_id, direction) },
+                doc! { "$group": { "_id": "$status", "count": { "$sum": 1 } } },
+            ])
+            .await?;
+
+        let mut counts = FollowCounts::default();
+        while let Some(group) = cursor.try_next().await? {
+            let count = match group.get("count") {
+                Some(Bson::Int32(count)) => *count as u64,
+                Some(Bson::Int64(count)) => *count as u64,
+                _ => 0,
+            };
+            match group.get_str("_id") {
+                Ok("accepted") => counts.accepted = count,
+                Ok("pending") => counts.pending = count,
+                Ok("rejected") => counts.rejected = count,
+                Ok("cancelled") => counts.cancelled = count,
+                _ => {}
+            }
+            counts.total += count;
+        }
+        Ok(counts)
+    }
+
+    /// Update an object
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_object(
+        &self,
+        object_id: &str,
+        update: Document,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection
+            .update_one(
+                doc! { "object_id": object_id },
+                doc! { "$set": update, "$currentDate": { "updated_at": true } },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Replace the content of an object by an edited version
+    ///
+    /// The current version is kept in `object_revisions`. Addressing and
+    /// visibility stay as they were.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn revise_object(
+        &self,
+        current: &ObjectDocument,
+        edited: &ObjectDocument,
+    ) -> Result<(), DatabaseError> {
+        let updated = edited.updated.unwrap_or_else(Utc::now);
+        self.insert_object_revision(ObjectRevisionDocument::of(current, updated))
+            .await?;
+
+        self.update_object(
+            &current.object_id,
+            doc! {
+                "content": &edited.content,
+                "content_map": mongodb::bson::to_bson(&edited.content_map)?,
+                "summary": &edited.summary,
+                "summary_map": mongodb::bson::to_bson(&edited.summary_map)?,
+                "name": &edited.name,
+                "name_map": mongodb::bson::to_bson(&edited.name_map)?,
+                "sensitive": edited.sensitive,
+                "tag": mongodb::bson::to_bson(&edited.tag)?,
+                "attachment": mongodb::bson::to_bson(&edited.attachment)?,
+                "language": &edited.language,
+                "updated": mongodb::bson::to_bson(&updated)?,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Keep an earlier version of an object
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_object_revision(
+        &self,
+        revision: ObjectRevisionDocument,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<ObjectRevisionDocument> =
+            self.database.collection("object_revisions");
+        collection.insert_one(revision).await?;
+        Ok(())
+    }
+
+    /// Find the earlier versions of an object, newest first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_object_revisions(
+        &self,
+        object_id: &str,
+        limit: i64,
+    ) -> Result<Vec<ObjectRevisionDocument>, DatabaseError> {
+        let collection: Collection<ObjectRevisionDocument> =
+            self.database.collection("object_revisions");
+        let cursor = collection
+            .find(doc! { "object_id": object_id })
+            .sort(doc! { "published": -1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Insert or refresh the stored profile of a remote actor
+    ///
+    /// Local actors with the same ID are left alone.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn upsert_remote_actor(&self, actor: ActorDocument) -> Result<(), DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        collection
+            .replace_one(doc! { "actor_id": &actor.actor_id, "local": false }, actor)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete an object
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_object(&self, object_id: &str) -> Result<(), DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let quoted = self.quoted_by(doc! { "object_id": object_id }).await?;
+        collection
+            .delete_one(doc! { "object_id": object_id })
+            .await?;
+        self.recount_quotes(&quoted).await
+    }
+
+    /// Scheduled objects whose publication time has passed, oldest first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn due_scheduled_objects(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let cursor = collection
+            .find(doc! {
+                "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled)?,
+                "published": { "$lte": mongodb::bson::to_bson(&now)? },
+            })
+            .sort(doc! { "published": 1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Scheduled objects, optionally of one actor, by publication time
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_scheduled_objects(
+        &self,
+        actor_id: Option<&str>,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut filter = doc! { "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled)? };
+        if let Some(actor_id) = actor_id {
+            filter.insert("attributed_to", actor_id);
+        }
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "published": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Mark a scheduled object published
+    ///
+    /// Returns false when the object is no longer scheduled, because it
+    /// was cancelled or another instance published it.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn publish_scheduled_object(&self, object_id: &str) -> Result<bool, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection
+            .update_one(
+                doc! {
+                    "object_id": object_id,
+                    "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled)?,
+                },
+                doc! { "$set": { "status": mongodb::bson::to_bson(&ObjectStatus::Published)? } },
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    /// Return a published object to the schedule, after its delivery failed
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn reschedule_object(&self, object_id: &str) -> Result<(), DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        collection
+            .update_one(
+                doc! { "object_id": object_id },
+                doc! { "$set": { "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled)? } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a scheduled object, returning false when there is none
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn cancel_scheduled_object(&self, object_id: &str) -> Result<bool, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection
+            .delete_one(doc! {
+                "object_id": object_id,
+                "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled)?,
+            })
+            .await?;
+        Ok(result.deleted_count == 1)
+    }
+
+    /// Local posts of an actor published before `before`, oldest first
+    ///
+    /// With `visibility`, only posts of these levels are returned. With
+    /// `keep_pinned`, posts pinned to the profile are skipped.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_expired_objects(
+        &self,
+        actor_id: &str,
+        before: DateTime<Utc>,
+        visibility: &[VisibilityLevel],
+        keep_pinned: bool,
+        limit: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut filter = doc! {
+            "attributed_to": actor_id,
+            "local": true,
+            "status": { "$ne": "scheduled" },
+            "object_type": { "$in": ["Note", "Article"] },
+            "published": { "$lt": mongodb::bson::to_bson(&before)? },
+        };
+        if !visibility.is_empty() {
+            filter.insert(
+                "visibility",
+                doc! { "$in": mongodb::bson::to_bson(visibility)? },
+            );
+        }
+        if keep_pinned {
+            filter.insert("featured", doc! { "$ne": true });
+        }
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "published": 1 })
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Replace an object by a Tombstone
+    ///
+    /// Returns false when the object is already a Tombstone.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn tombstone_object(
+        &self,
+        object: &ObjectDocument,
+        deleted: DateTime<Utc>,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let result = collection
+            .update_one(
+                doc! {
+                    "object_id": &object.object_id,
+                    "object_type": { "$ne": mongodb::bson::to_bson(&ObjectType::Tombstone)? },
+                },
+                doc! {
+                    "$set": {
+                        "object_type": mongodb::bson::to_bson(&ObjectType::Tombstone)?,
+                        "content": Bson::Null,
+                        "content_map": Bson::Null,
+                        "summary": Bson::Null,
+                        "summary_map": Bson::Null,
+                        "name": Bson::Null,
+                        "name_map": Bson::Null,
+                        "tag": Bson::Null,
+                        "attachment": Bson::Null,
+                        "additional_properties": {
+                            "formerType": format!("{:?}", object.object_type),
+                        },
+                        "updated": mongodb::bson::to_bson(&deleted)?,
+                    }
+                },
+            )
+            .await?;
+        if result.modified_count == 0 {
+            return Ok(false);
+        }
+        if let Some(quote) = &object.quote {
+            self.recount_quotes(std::slice::from_ref(quote)).await?;
+        }
+        Ok(true)
+    }
+
+    /// Update an activity
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_activity(
+        &self,
+        activity_id: &str,
+        update: Document,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let result = collection
+            .update_one(
+                doc! { "activity_id": activity_id },
+                doc! { "$set": update, "$currentDate": { "updated_at": true } },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Delete an activity
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_activity(&self, activity_id: &str) -> Result<(), DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        collection
+            .delete_one(doc! { "activity_id": activity_id })
+            .await?;
+        Ok(())
+    }
+
+    /// Withdraw an actor's Like, Announce or Block of an object
+    ///
+    /// Removes the stored activities and, for Likes, decrements the
+    /// object's count by the number removed; Announces also remove the
+    /// boost. Returns whether anything was removed, so undoing twice leaves
+    /// the count alone.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn undo_activity(
+        &self,
+        activity_type: ActivityType,
+        actor: &str,
+        object: &str,
+    ) -> Result<bool, DatabaseError> {
+        let count_field = interaction_count_field(&activity_type);
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let removed = collection
+            .delete_many(doc! {
+                "activity_type": mongodb::bson::to_bson(&activity_type)?,
+                "actor": actor,
+                "object": object,
+            })
+            .await?;
+        let unboosted = matches!(activity_type, ActivityType::Announce)
+            && self.remove_boost(actor, object).await?;
+        if removed.deleted_count == 0 {
+            return Ok(unboosted);
+        }
+
+        // Every removed record was counted once; the count never drops below 0
+        if let Some(field) = count_field {
+            let removed = removed.deleted_count as i64;
+            let objects: Collection<ObjectDocument> = self.database.collection("objects");
+            objects
+                .update_one(
+                    doc! { "object_id": object, field: { "$gt": 0 } },
+                    vec![doc! {
+                        "$set": {
+                            field: { "$max": [0, { "$subtract": [format!("${}", field), removed] }] }
+                        }
+                    }],
+                )
+                .await?;
+        }
+        Ok(true)
+    }
+
+    /// Whether an actor has a stored activity of a type on an object, such
+    /// as a Like of a post
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn has_interaction(
+        &self,
+        activity_type: ActivityType,
+        actor: &str,
+        object: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        Ok(collection
+            .find_one(doc! {
+                "activity_type": mongodb::bson::to_bson(&activity_type)?,
+                "actor": actor,
+                "object": object,
+            })
+            .await?
+            .is_some())
+    }
+
+    /// Count a stored Like on the object it refers to
+    ///
+    /// Other activity types are not counted; boosts are counted by
+    /// `record_boost`.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn count_interaction(
+        &self,
+        activity_type: &ActivityType,
+        object: &str,
+    ) -> Result<(), DatabaseError> {
+        let Some(field) = interaction_count_field(activity_type) else {
+            return Ok(());
+        };
+        let objects: Collection<ObjectDocument> = self.database.collection("objects");
+        objects
+            .update_one(doc! { "object_id": object }, doc! { "$inc": { field: 1 } })
+            .await?;
+        Ok(())
+    }
+
+    /// Find objects by actor with pagination
+    pub async fn find_objects_by_actor(
+        &self,
+        actor_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut cursor = collection
+            .find(doc! {
+                "attributed_to": actor_id,
+                "status": { "$ne": "scheduled" },
+                "object_type": { "$ne": "Tombstone" },
+            })
+            .sort(doc! { "published": -1 })
+            .limit(limit)
+            .skip(offset as u64)
+            .await?;
+
+        let mut objects = Vec::new();
+        while cursor.advance().await? {
+            objects.push(cursor.deserialize_current()?);
+        }
+
+        Ok(objects)
+    }
+
+    /// Find activities by actor with pagination
+    pub async fn find_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ActivityDocument>, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let mut cursor = collection
+            .find(doc! { "actor": actor_id })
+            .sort(doc! { "published": -1 })
+            .limit(limit)
+            .skip(offset as u64)
+            .await?;
+
+        let mut activities = Vec::new();
+        while cursor.advance().await? {
+            activities.push(cursor.deserialize_current()?);
+        }
+
+        Ok(activities)
+    }
+
+    /// Find activities by type
+    pub async fn find_activities_by_type(
+        &self,
+        activity_type: ActivityType,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ActivityDocument>, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        let mut cursor = collection
+            .find(doc! { "activity_type": mongodb::bson::to_bson(&activity_type)? })
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
+            .skip(offset as u64)
+            .await?;
+
+        let mut activities = Vec::new();
+        while cursor.advance().await? {
+            activities.push(cursor.deserialize_current()?);
+        }
+
+        Ok(activities)
+    }
+
+    /// Count objects by actor
+    pub async fn count_objects_by_actor(&self, actor_id: &str) -> Result<u64, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let count = collection
+            .count_documents(doc! {
+                "attributed_to": actor_id,
+                "status": { "$ne": "scheduled" },
+                "object_type": { "$ne": "Tombstone" },
+            })
+            .await?;
+        Ok(count)
+    }
+
+    /// Update actor counts (followers, following, statuses)
+    pub async fn update_actor_counts(
+        &self,
+        actor_id: &str,
+        followers_count: Option<i64>,
+        following_count: Option<i64>,
+        statuses_count: Option<i64>,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let mut update_doc = doc! {};
+
+        if let Some(count) = followers_count {
+            update_doc.insert("followers_count", count);
+        }
+        if let Some(count) = following_count {
+            update_doc.insert("following_count", count);
+        }
+        if let Some(count) = statuses_count {
+            update_doc.insert("statuses_count", count);
+        }
+
+        if !update_doc.is_empty() {
+            let system_time: SystemTime = chrono::Utc::now().into();
+            update_doc.insert("updated_at", Bson::DateTime(system_time.into()));
+            self.update_actor(actor_id, update_doc).await
+        } else {
+            // Return a minimal successful update result
+            let collection: Collection<ActorDocument> = self.database.collection("actors");
+            let result = collection
+                .update_one(doc! { "actor_id": actor_id }, doc! {})
+                .await?;
+            Ok(result)
+        }
+    }
+
+    /// Mark an actor as deleted, keeping its ID reserved
+    ///
+    /// The profile is cleared, its objects are replaced by Tombstones, its
+    /// activities and follow relationships are removed and its current keys
+    /// expire at `keys_expire`, leaving time to sign the federated `Delete`.
+    /// Rotated keys keep their earlier expiry.
+    /// Returns the number of objects tombstoned.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn tombstone_actor(
+        &self,
+        actor_id: &str,
+        deleted: DateTime<Utc>,
+        keys_expire: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let actors: Collection<ActorDocument> = self.database.collection("actors");
+        actors
+            .update_one(
+                doc! { "actor_id": actor_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&ActorStatus::Deleted)?,
+                        "summary": Bson::Null,
+                        "icon": Bson::Null,
+                        "image": Bson::Null,
+                        "attachment": Bson::Null,
+                        "updated_at": mongodb::bson::to_bson(&deleted)?,
+                    }
+                },
+            )
+            .await?;
+
+        let objects: Collection<ObjectDocument> = self.database.collection("objects");
+        let tombstone = mongodb::bson::to_bson(&ObjectType::Tombstone)?;
+        let quoted = self
+            .quoted_by(doc! { "attributed_to": actor_id, "object_type": { "$ne": &tombstone } })
+            .await?;
+        let result = objects
+            .update_many(
+                doc! {
+                    "attributed_to": actor_id,
+                    "object_type": { "$ne": &tombstone },
+                },
+                vec![doc! {
+                    "$set": {
+                        "object_type": &tombstone,
+                        "content": Bson::Null,
+                        "content_map": Bson::Null,
+                        "summary": Bson::Null,
+                        "summary_map": Bson::Null,
+                        "name": Bson::Null,
+                        "name_map": Bson::Null,
+                        "tag": Bson::Null,
+                        "attachment": Bson::Null,
+                        "additional_properties": { "formerType": "$object_type" },
+                        "updated": mongodb::bson::to_bson(&deleted)?,
+                    }
+                }],
+            )
+            .await?;
+        self.recount_quotes(&quoted).await?;
+
+        let activities: Collection<ActivityDocument> = self.database.collection("activities");
+        activities.delete_many(doc! { "actor": actor_id }).await?;
+
+        let follows: Collection<FollowDocument> = self.database.collection("follows");
+        follows
+            .delete_many(doc! { "$or": [{"follower": actor_id}, {"following": actor_id}] })
+            .await?;
+
+        let keys: Collection<KeyDocument> = self.database.collection("keys");
+        keys.update_many(
+            doc! {
+                "actor_id": actor_id,
+                "status": { "$in": ["active", "pending"] },
+            },
+            doc! { "$set": { "expires_at": mongodb::bson::to_bson(&keys_expire)? } },
+        )
+        .await?;
+
+        Ok(result.modified_count)
+    }
+
+    /// Remote actors to tell about the deletion of `actor_id`, one per host
+    ///
+    /// Covers the hosts of the actor's followers and followed accounts and
+    /// of every remote actor known to the instance. Servers process the
+    /// `Delete` of an actor for the whole instance, so one inbox per host
+    /// suffices.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_peer_actors(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
+        let follows: Collection<FollowDocument> = self.database.collection("follows");
+        let mut candidates: Vec<String> = follows
+            .find(doc! { "$or": [{"follower": actor_id}, {"following": actor_id}] })
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .map(|follow| {
+                if follow.follower == actor_id {
+                    follow.following
+                } else {
+                    follow.follower
+                }
+            })
+            .collect();
+
+        let actors: Collection<Document> = self.database.collection("actors");
+        let mut remote = actors
+            .aggregate(vec![
+                doc! { "$match": { "local": false } },
+                doc! { "$group": { "_id": "$domain", "actor_id": { "$first": "$actor_id" } } },
+            ])
+            .await?;
+        while let Some(group) = remote.try_next().await? {
+            if let Ok(id) = group.get_str("actor_id") {
+                candidates.push(id.to_string());
+            }
+        }
+
+        let host = |id: &str| url::Url::parse(id).ok()?.host_str().map(str::to_string);
+        let own_host = host(actor_id);
+        let mut hosts = std::collections::HashSet::new();
+        Ok(candidates
+            .into_iter()
+            .filter(|id| {
+                let host = host(id);
+                host.is_some() && host != own_host && hosts.insert(host)
+            })
+            .collect())
+    }
+
+    /// Delete actor and all related data
+    pub async fn delete_actor(&self, actor_id: &str) -> Result<(), DatabaseError> {
+        // Delete actor
+        let actors: Collection<ActorDocument> = self.database.collection("actors");
+        actors.delete_one(doc! { "actor_id": actor_id }).await?;
+
+        // Delete actor's objects
+        let objects: Collection<ObjectDocument> = self.database.collection("objects");
+        let quoted = self.quoted_by(doc! { "attributed_to": actor_id }).await?;
+        objects
+            .delete_many(doc! { "attributed_to": actor_id })
+            .await?;
+        self.recount_quotes(&quoted).await?;
+
+        // Delete actor's activities
+        let activities: Collection<ActivityDocument> = self.database.collection("activities");
+        activities.delete_many(doc! { "actor": actor_id }).await?;
+
+        // Delete actor's keys
+        let keys: Collection<KeyDocument> = self.database.collection("keys");
+        keys.delete_many(doc! { "actor_id": actor_id }).await?;
+
+        // Delete follow relationships
+        let follows: Collection<FollowDocument> = self.database.collection("follows");
+        follows
+            .delete_many(doc! { "$or": [{"follower": actor_id}, {"following": actor_id}] })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Active local actors of a domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_local_actors_by_domain(
+        &self,
+        domain: &str,
+    ) -> Result<Vec<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let cursor = collection
+            .find(doc! {
+                "local": true,
+                "domain": domain,
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+            })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// All active local actors
+    pub async fn find_local_actors(&self) -> Result<Vec<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let cursor = collection
+            .find(doc! {
+                "local": true,
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+            })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Page of actors sorted by domain and username, with the number of all
+    /// actors matching `domain`, `local` and `status`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actors_page(
+        &self,
+        domain: Option<&str>,
+        local: Option<bool>,
+        status: Option<&str>,
+        offset: u64,
+        limit: i64,
+    ) -> Result<(Vec<ActorDocument>, u64), DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let mut filter = doc! {};
+        if let Some(domain) = domain {
+            filter.insert("domain", domain);
+        }
+        if let Some(local) = local {
+            filter.insert("local", local);
+        }
+        if let Some(status) = status {
+            filter.insert("status", status);
+        }
+
+        let total = collection.count_documents(filter.clone()).await?;
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "domain": 1, "preferred_username": 1, "actor_id": 1 })
+            .skip(offset)
+            .limit(limit)
+            .await?;
+        Ok((cursor.try_collect().await?, total))
+    }
+
+    /// Page of published local posts newest first, optionally of one
+    /// actor, with the number of all of them
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_local_objects_page(
+        &self,
+        actor_id: Option<&str>,
+        offset: u64,
+        limit: i64,
+    ) -> Result<(Vec<ObjectDocument>, u64), DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut filter = doc! {
+            "local": true,
+            "status": { "$ne": "scheduled" },
+            "object_type": { "$ne": "Tombstone" }
+        };
+        if let Some(actor_id) = actor_id {
+            filter.insert("attributed_to", actor_id);
+        }
+
+        let total = collection.count_documents(filter.clone()).await?;
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "published": -1, "_id": -1 })
+            .skip(offset)
+            .limit(limit)
+            .await?;
+        Ok((cursor.try_collect().await?, total))
+    }
+
+    /// Discoverable local actors of a domain for the account directory
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_directory_actors(
+        &self,
+        domain: &str,
+        order: DirectoryOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<DirectoryEntry>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let sort = match order {
+            DirectoryOrder::Active => doc! { "last_status_at": -1, "followers": -1, "actor_id": 1 },
+            DirectoryOrder::Followers => {
+                doc! { "followers": -1, "last_status_at": -1, "actor_id": 1 }
+            }
+        };
+        let pipeline = vec![
+            doc! { "$match": {
+                "local": true,
+                "domain": domain,
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+                "additional_properties.discoverable": true,
+            }},
+            doc! { "$lookup": {
+                "from": "follows",
+                "let": { "actor": "$actor_id" },
+                "pipeline": [
+                    { "$match": {
+                        "$expr": { "$eq": ["$following", "$$actor"] },
+                        "status": mongodb::bson::to_bson(&FollowStatus::Accepted)?,
+                    }},
+                    { "$count": "count" },
+                ],
+                "as": "follower_count",
+            }},
+            doc! { "$lookup": {
+                "from": "objects",
+                "let": { "actor": "$actor_id" },
+                "pipeline": [
+                    { "$match": {
+                        "$expr": { "$eq": ["$attributed_to", "$$actor"] },
+                        "visibility": "public",
+                        "status": { "$ne": "scheduled" },
+                        "object_type": { "$ne": "Tombstone" },
+                    }},
+                    { "$sort": { "created_at": -1 } },
+                    { "$limit": 1 },
+                    { "$project": { "created_at": 1 } },
+                ],
+                "as": "latest_status",
+            }},
+            doc! { "$project": {
+                "_id": 0,
+                "followers": { "$ifNull": [{ "$first": "$follower_count.count" }, 0] },
+                "last_status_at": { "$first": "$latest_status.created_at" },
+                "actor": "$$ROOT",
+            }},
+            doc! { "$unset": ["actor.follower_count", "actor.latest_status"] },
+            doc! { "$sort": sort },
+            doc! { "$skip": offset },
+            doc! { "$limit": limit },
+        ];
+
+        let mut cursor = collection.aggregate(pipeline).await?;
+        let mut entries = Vec::new();
+        while let Some(entry) = cursor.try_next().await? {
+            entries.push(mongodb::bson::from_document(entry)?);
+        }
+        Ok(entries)
+    }
+
+    /// Get total number of local actors
+    pub async fn count_local_actors(&self) -> Result<u64, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let count = collection.count_documents(doc! { "local": true }).await?;
+        Ok(count)
+    }
+
+    /// Get total number of local posts
+    pub async fn count_local_posts(&self) -> Result<u64, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let count = collection
+            .count_documents(doc! {
+                "local": true,
+                "status": { "$ne": "scheduled" },
+                "object_type": { "$in": ["Note", "Article"] }
+            })
+            .await?;
+        Ok(count)
+    }
+
+    /// Find objects by content search
+    pub async fn search_objects(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let filter = doc! {
+            "visibility": "public",
+            "status": { "$ne": "scheduled" },
+            "$or": [
+                { "content": { "$regex": query, "$options": "i" } },
+                { "summary": { "$regex": query, "$options": "i" } },
+                { "name": { "$regex": query, "$options": "i" } }
+            ]
+        };
+
+        let cursor = collection
+            .find(filter)
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+
+        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
+
+    /// Full-text search of public objects using the `$text` index
+    ///
+    /// Best matches come first.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn text_search_public_objects(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let cursor = collection
+            .find(doc! {
+                "$text": { "$search": query },
+                "visibility": "public",
+                "status": { "$ne": "scheduled" },
+                "object_type": { "$ne": "Tombstone" },
+            })
+            .sort(doc! { "score": { "$meta": "textScore" } })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Find active, discoverable actors whose username or display name
+    /// contains `query`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn search_actors(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let pattern = regex::escape(query);
+        let cursor = collection
+            .find(doc! {
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+                "additional_properties.discoverable": true,
+                "$or": [
+                    { "preferred_username": { "$regex": &pattern, "$options": "i" } },
+                    { "name": { "$regex": &pattern, "$options": "i" } },
+                ],
+            })
+            .sort(doc! { "local": -1, "preferred_username": 1 })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Names of the hashtags of public objects starting with `prefix`, most
+    /// used first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn search_hashtags(
+        &self,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let name = doc! { "$regex": format!("^#?{}", regex::escape(prefix)), "$options": "i" };
+        let mut cursor = collection
+            .aggregate(vec![
+                doc! { "$match": {
+                    "visibility": "public",
+                    "tag": { "$elemMatch": { "tag_type": "Hashtag", "name": name.clone() } },
+                } },
+                doc! { "$unwind": "$tag" },
+                doc! { "$match": { "tag.tag_type": "Hashtag", "tag.name": name } },
+                doc! { "$group": {
+                    "_id": { "$toLower": { "$trim": { "input": "$tag.name", "chars": "#" } } },
+                    "uses": { "$sum": 1 },
+                } },
+                doc! { "$sort": { "uses": -1, "_id": 1 } },
+                doc! { "$limit": limit },
+            ])
+            .await?;
+
+        let mut names = Vec::new();
+        while let Some(group) = cursor.try_next().await? {
+            if let Ok(name) = group.get_str("_id") {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Find objects by their IDs, in no particular order
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_objects_by_ids(
+        &self,
+        object_ids: &[String],
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let cursor = collection
+            .find(doc! { "object_id": { "$in": object_ids } })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Find actors by their IDs, in no particular order
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actors_by_ids(
+        &self,
+        actor_ids: &[String],
+    ) -> Result<Vec<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let cursor = collection
+            .find(doc! { "actor_id": { "$in": actor_ids } })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Find actors by their storage IDs, in no particular order
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actors_by_storage_ids(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<ActorDocument>, DatabaseError> {
         let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let cursor = collection.find(doc! { "_id": { "$in": ids } }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Find local objects created or edited after `since`, oldest first
+    ///
+    /// Scheduled objects are left out; Tombstones of deleted objects are
+    /// included.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_local_objects_changed_since(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let since = mongodb::bson::to_bson(&since)?;
+        let cursor = collection
+            .find(doc! {
+                "local": true,
+                "status": { "$ne": "scheduled" },
+                "$or": [
+                    { "created_at": { "$gt": &since } },
+                    { "updated": { "$gt": &since } },
+                ],
+            })
+            .sort(doc! { "created_at": 1 })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Get recent public activities for timeline
+    ///
+    /// With `languages`, only objects in one of these languages or of
+    /// unknown language are returned.
+    pub async fn get_public_timeline(
+        &self,
+        languages: &[String],
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut filter = doc! {
+            "visibility": "public",
+            "status": { "$ne": "scheduled" },
+            "object_type": { "$in": ["Note", "Article"] }
+        };
+        filter.extend(language_filter(languages));
+        let restricted = self.find_restricted_actor_ids().await?;
+        if !restricted.is_empty() {
+            filter.insert("attributed_to", doc! { "$nin": restricted });
+        }
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "published": -1 })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+
+        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
+
+    /// Get local timeline (only local posts), optionally limited to
+    /// `languages` as in [`DatabaseManager::get_public_timeline`]
+    pub async fn get_local_timeline(
+        &self,
+        languages: &[String],
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let mut filter = doc! {
+            "local": true,
+            "visibility": "public",
+            "status": { "$ne": "scheduled" },
+            "object_type": { "$in": ["Note", "Article"] }
+        };
+        filter.extend(language_filter(languages));
+        let restricted = self.find_restricted_actor_ids().await?;
+        if !restricted.is_empty() {
+            filter.insert("attributed_to", doc! { "$nin": restricted });
+        }
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "published": -1 })
+            .skip(offset as u64)
+            .limit(limit)
+            .await?;
+
+        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
+
+    /// IDs of silenced and limited actors, whose posts stay off the
+    /// public and local timelines
+    pub async fn find_restricted_actor_ids(&self) -> Result<Vec<String>, DatabaseError> {
+        let collection: Collection<Document> = self.database.collection("actors");
+        let ids = collection
+            .distinct(
+                "actor_id",
+                doc! { "restriction": { "$in": ["silenced", "limited"] } },
+            )
+            .await?;
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| id.as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Update key status
+    pub async fn update_key_status(
+        &self,
+        key_id: &str,
+        status: KeyStatus,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let status_str = match status {
+            KeyStatus::Active => "active",
+            KeyStatus::Revoked => "revoked",
+            KeyStatus::Expired => "expired",
+            KeyStatus::Pending => "pending",
+            KeyStatus::Rotated => "rotated",
+        };
         let result = collection
-            .find_one(doc! { "preferred_username": username, "domain": domain })
+            .update_one(
+                doc! { "key_id": key_id },
+                doc! {
+                    "$set": { "status": status_str },
+                    "$currentDate": { "updated_at": true }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Revoke the domain keys of a domain that are still in use
+    ///
+    /// Returns the number of keys revoked.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn revoke_domain_keys(&self, domain: &str) -> Result<u64, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .update_many(
+                doc! {
+                    "domain": domain,
+                    "key_type": mongodb::bson::to_bson(&KeyType::Domain)?,
+                    "status": { "$in": ["active", "pending", "rotated"] },
+                },
+                doc! {
+                    "$set": { "status": mongodb::bson::to_bson(&KeyStatus::Revoked)? },
+                    "$currentDate": { "updated_at": true },
+                },
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    /// Retire the active domain keys of a domain other than `current_key_id`
+    ///
+    /// The retired keys are marked rotated and accepted until `expires_at`.
+    /// Returns the number of keys retired.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn retire_domain_keys(
+        &self,
+        domain: &str,
+        current_key_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .update_many(
+                doc! {
+                    "domain": domain,
+                    "key_type": mongodb::bson::to_bson(&KeyType::Domain)?,
+                    "status": "active",
+                    "key_id": { "$ne": current_key_id },
+                },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&KeyStatus::Rotated)?,
+                        "expires_at": mongodb::bson::to_bson(&expires_at)?,
+                    },
+                    "$currentDate": { "updated_at": true },
+                },
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    /// Find the user keys in use that a domain signed with another key than
+    /// `domain_key_id`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_keys_signed_by_other_domain_key(
+        &self,
+        domain: &str,
+        domain_key_id: &str,
+    ) -> Result<Vec<KeyDocument>, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let keys = collection
+            .find(doc! {
+                "key_type": mongodb::bson::to_bson(&KeyType::User)?,
+                "domain_signature.domain": domain,
+                "domain_signature.domain_key_id": { "$ne": domain_key_id },
+                "status": { "$in": ["active", "rotated"] },
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(keys)
+    }
+
+    /// Update fields of a key
+    pub async fn update_key(
+        &self,
+        key_id: &str,
+        update: Document,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .update_one(
+                doc! { "key_id": key_id },
+                doc! { "$set": update, "$currentDate": { "updated_at": true } },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Re-encrypt private keys not stored under the current key-encryption key
+    ///
+    /// Encrypts keys stored in plaintext and moves keys encrypted with a
+    /// previous master key to the current one. Keys that cannot be decrypted
+    /// are logged and skipped. Returns the number of re-encrypted keys.
+    pub async fn reencrypt_private_keys(
+        &self,
+        encryptor: &KeyEncryptor,
+    ) -> Result<u64, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let mut cursor = collection
+            .find(doc! { "private_key_pem": { "$type": "string" } })
+            .await?;
+
+        let mut reencrypted = 0;
+        while let Some(mut key) = cursor.try_next().await? {
+            match key.encrypt_private_key(encryptor).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    self.update_key(
+                        &key.key_id,
+                        doc! {
+                            "private_key_pem": key.private_key_pem,
+                            "encryption_algorithm": key.encryption_algorithm,
+                        },
+                    )
+                    .await?;
+                    reencrypted += 1;
+                }
+                Err(e) => warn!("Failed to re-encrypt key {}: {}", key.key_id, e),
+            }
+        }
+        Ok(reencrypted)
+    }
+
+    /// Take a key out of service, accepting it until `expires_at`
+    pub async fn retire_key(
+        &self,
+        key_id: &str,
+        status: KeyStatus,
+        expires_at: DateTime<Utc>,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .update_one(
+                doc! { "key_id": key_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&status)?,
+                        "expires_at": mongodb::bson::to_bson(&expires_at)?,
+                    },
+                    "$currentDate": { "updated_at": true }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// Find the active signing key of a domain
+    pub async fn find_domain_key(
+        &self,
+        domain: &str,
+    ) -> Result<Option<KeyDocument>, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let result = collection
+            .find_one(doc! {
+                "key_type": "domain",
+                "domain": domain,
+                "status": "active"
+            })
+            .await?;
+        Ok(result)
+    }
+
+    /// Find active keys by actor
+    pub async fn find_active_keys_by_actor(
+        &self,
+        actor_id: &str,
+    ) -> Result<Vec<KeyDocument>, DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let filter = doc! {
+            "actor_id": actor_id,
+            "status": "active"
+        };
+
+        let cursor = collection.find(filter).await?;
+        let results: Vec<KeyDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
+
+    /// Page of keys, optionally of one actor or with one trust level, with
+    /// the number of all matching keys
+    ///
+    /// Keys are returned by actor, newest first.
+    pub async fn find_keys(
+        &self,
+        actor_id: Option<&str>,
+        trust_level: Option<TrustLevel>,
+        offset: u64,
+        limit: i64,
+    ) -> Result<(Vec<KeyDocument>, u64), DatabaseError> {
+        let collection: Collection<KeyDocument> = self.database.collection("keys");
+        let mut filter = doc! {};
+        if let Some(actor_id) = actor_id {
+            filter.insert("actor_id", actor_id);
+        }
+        if let Some(trust_level) = trust_level {
+            filter.insert("trust_level", mongodb::bson::to_bson(&trust_level)?);
+        }
+
+        let total = collection.count_documents(filter.clone()).await?;
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "actor_id": 1, "created_at": -1 })
+            .skip(offset)
+            .limit(limit)
             .await?;
+        Ok((cursor.try_collect().await?, total))
+    }
+
+    /// Whether `url` is an attachment of a stored object or an actor's icon
+    /// or header image, the only media the media proxy fetches
+    pub async fn is_known_media_url(&self, url: &str) -> Result<bool, DatabaseError> {
+        let objects: Collection<Document> = self.database.collection("objects");
+        let attachment = objects
+            .find_one(doc! { "$or": [{ "attachment.url": url }, { "image.url": url }] })
+            .projection(doc! { "_id": 1 })
+            .await?;
+        if attachment.is_some() {
+            return Ok(true);
+        }
+
+        let actors: Collection<Document> = self.database.collection("actors");
+        let actor = actors
+            .find_one(doc! { "$or": [{ "icon": url }, { "image": url }] })
+            .projection(doc! { "_id": 1 })
+            .await?;
+        Ok(actor.is_some())
+    }
+
+    /// Find cached remote media by its original URL
+    pub async fn find_cached_media(
+        &self,
+        url: &str,
+    ) -> Result<Option<MediaCacheDocument>, DatabaseError> {
+        let collection: Collection<MediaCacheDocument> = self.database.collection("media_cache");
+        let result = collection.find_one(doc! { "url": url }).await?;
         Ok(result)
     }
 
-    /// Update actor
-    pub async fn update_actor(
+    /// Insert or replace a cached remote media entry
+    pub async fn upsert_cached_media(
         &self,
-        actor_id: &str,
-        update: Document,
+        media: MediaCacheDocument,
     ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        let collection: Collection<MediaCacheDocument> = self.database.collection("media_cache");
         let result = collection
-            .update_one(
-                doc! { "actor_id": actor_id },
-                doc! { "$set": update, "$currentDate": { "updated_at": true } },
-            )
+            .replace_one(doc! { "url": &media.url }, media)
+            .upsert(true)
             .await?;
         Ok(result)
     }
 
-    /// Insert a new object
-    pub async fn insert_object(&self, object: ObjectDocument) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let result = collection.insert_one(object).await?;
+    /// Insert a new report
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_report(&self, report: ReportDocument) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
+        let result = collection.insert_one(report).await?;
         Ok(result.inserted_id.as_object_id().unwrap())
     }
 
-    /// Find object by ID
-    pub async fn find_object_by_id(
+    /// Find report by ID
+    pub async fn find_report_by_id(
         &self,
-        object_id: &str,
-    ) -> Result<Option<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let result = collection.find_one(doc! { "object_id": object_id }).await?;
+        report_id: &str,
+    ) -> Result<Option<ReportDocument>, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
+        let result = collection.find_one(doc! { "report_id": report_id }).await?;
         Ok(result)
     }
 
-    /// Insert a new activity
-    pub async fn insert_activity(
+    /// Find report by the ID of its originating Flag activity
+    pub async fn find_report_by_flag(
         &self,
-        activity: ActivityDocument,
-    ) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
-        let result = collection.insert_one(activity).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
+        flag_activity_id: &str,
+    ) -> Result<Option<ReportDocument>, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
+        let result = collection
+            .find_one(doc! { "flag_activity_id": flag_activity_id })
+            .await?;
+        Ok(result)
     }
 
-    /// Find activity by ID
-    pub async fn find_activity_by_id(
+    /// List reports, newest first, optionally filtered by status
+    pub async fn list_reports(
         &self,
-        activity_id: &str,
-    ) -> Result<Option<ActivityDocument>, DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        status: Option<ReportStatus>,
+        limit: i64,
+    ) -> Result<Vec<ReportDocument>, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
+        let filter = match status {
+            Some(status) => doc! { "status": mongodb::bson::to_bson(&status)? },
+            None => doc! {},
+        };
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
+            .await?;
+        let results: Vec<ReportDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
+
+    /// Number of open reports received for a domain
+    pub async fn count_open_reports(&self, domain: &str) -> Result<u64, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
+        Ok(collection
+            .count_documents(doc! {
+                "domain": domain,
+                "status": mongodb::bson::to_bson(&ReportStatus::Open)?,
+            })
+            .await?)
+    }
+
+    /// Close a report with the given status and moderation action
+    pub async fn resolve_report(
+        &self,
+        report_id: &str,
+        status: ReportStatus,
+        action: &str,
+        moderator: &str,
+        note: Option<&str>,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<ReportDocument> = self.database.collection("reports");
         let result = collection
-            .find_one(doc! { "activity_id": activity_id })
+            .update_one(
+                doc! { "report_id": report_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&status)?,
+                        "action_taken": action,
+                        "resolved_by": moderator,
+                        "resolution_note": note,
+                        "resolved_at": mongodb::bson::to_bson(&Utc::now())?,
+                    }
+                },
+            )
             .await?;
         Ok(result)
     }
 
-    /// Upsert a key
-    pub async fn upsert_key(&self, key: KeyDocument) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
+    /// Insert or replace a domain block
+    pub async fn upsert_domain_block(
+        &self,
+        block: DomainBlockDocument,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<DomainBlockDocument> = self.database.collection("domain_blocks");
         let result = collection
-            .replace_one(doc! { "key_id": &key.key_id }, key)
+            .replace_one(doc! { "domain": &block.domain }, block)
             .upsert(true)
             .await?;
         Ok(result)
     }
 
-    /// Insert a new key
-    pub async fn insert_key(&self, key: KeyDocument) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
-        let result = collection.insert_one(key).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
+    /// Find the block for a remote domain
+    pub async fn find_domain_block(
+        &self,
+        domain: &str,
+    ) -> Result<Option<DomainBlockDocument>, DatabaseError> {
+        let collection: Collection<DomainBlockDocument> = self.database.collection("domain_blocks");
+        let result = collection.find_one(doc! { "domain": domain }).await?;
+        Ok(result)
     }
 
-    /// Find key by ID
-    pub async fn find_key_by_id(&self, key_id: &str) -> Result<Option<KeyDocument>, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
-        let result = collection.find_one(doc! { "key_id": key_id }).await?;
-        Ok(result)
+    /// Insert a new webhook
+    pub async fn insert_webhook(&self, webhook: WebhookDocument) -> Result<(), DatabaseError> {
+        let collection: Collection<WebhookDocument> = self.database.collection("webhooks");
+        collection.insert_one(webhook).await?;
+        Ok(())
     }
 
-    /// Find keys by actor ID
-    pub async fn find_keys_by_actor(
+    /// Find webhook by ID
+    pub async fn find_webhook(
         &self,
-        actor_id: &str,
-    ) -> Result<Vec<KeyDocument>, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
-        let mut cursor = collection.find(doc! { "actor_id": actor_id }).await?;
-        let mut keys = Vec::new();
+        webhook_id: &str,
+    ) -> Result<Option<WebhookDocument>, DatabaseError> {
+        let collection: Collection<WebhookDocument> = self.database.collection("webhooks");
+        let result = collection
+            .find_one(doc! { "webhook_id": webhook_id })
+            .await?;
+        Ok(result)
+    }
 
-        while cursor.advance().await? {
-            keys.push(cursor.deserialize_current()?);
-        }
+    /// List the webhooks of a domain, oldest first
+    pub async fn list_webhooks(&self, domain: &str) -> Result<Vec<WebhookDocument>, DatabaseError> {
+        let collection: Collection<WebhookDocument> = self.database.collection("webhooks");
+        let webhooks = collection
+            .find(doc! { "domain": domain })
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(webhooks)
+    }
 
-        Ok(keys)
+    /// Webhooks of a domain subscribed to an event
+    pub async fn find_webhooks_for_event(
+        &self,
+        domain: &str,
+        event: WebhookEvent,
+    ) -> Result<Vec<WebhookDocument>, DatabaseError> {
+        let collection: Collection<WebhookDocument> = self.database.collection("webhooks");
+        let webhooks = collection
+            .find(doc! {
+                "domain": domain,
+                "$or": [{ "events": { "$size": 0 } }, { "events": event.as_str() }],
+            })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(webhooks)
     }
 
-    /// Upsert a domain
-    pub async fn upsert_domain(
+    /// Delete a webhook of a domain, returning whether it existed
+    pub async fn delete_webhook(
         &self,
-        domain: DomainDocument,
-    ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<DomainDocument> = self.database.collection("domains");
+        domain: &str,
+        webhook_id: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<WebhookDocument> = self.database.collection("webhooks");
         let result = collection
-            .replace_one(doc! { "domain": &domain.domain }, domain)
-            .upsert(true)
+            .delete_one(doc! { "domain": domain, "webhook_id": webhook_id })
             .await?;
-        Ok(result)
+        Ok(result.deleted_count > 0)
     }
 
-    /// Insert a new domain
-    pub async fn insert_domain(&self, domain: DomainDocument) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<DomainDocument> = self.database.collection("domains");
-        let result = collection.insert_one(domain).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
+    /// Queue an event for the webhooks of its domain
+    ///
+    /// The event goes through the outbox, so it is sent once the write that
+    /// caused it has succeeded.
+    pub async fn queue_webhook_event(
+        &self,
+        event: &WebhookEventMessage,
+    ) -> Result<(), DatabaseError> {
+        let payload = serde_json::to_string(event)
+            .map_err(|e| DatabaseError::OperationError(format!("Invalid webhook event: {}", e)))?;
+        self.insert_outbox_messages(vec![OutboxMessageDocument::new(
+            EXCHANGE_WEBHOOKS,
+            ROUTING_KEY_WEBHOOK_EVENT,
+            Some("application/json"),
+            payload,
+        )])
+        .await
     }
 
-    /// Find domain by name
-    pub async fn find_domain_by_name(
+    /// Queue an email for maild
+    ///
+    /// Like webhook events, emails go through the outbox.
+    pub async fn queue_email(&self, email: &EmailMessage) -> Result<(), DatabaseError> {
+        let payload = serde_json::to_string(email)
+            .map_err(|e| DatabaseError::OperationError(format!("Invalid email: {}", e)))?;
+        self.insert_outbox_messages(vec![OutboxMessageDocument::new(
+            EXCHANGE_EMAIL,
+            "",
+            Some("application/json"),
+            payload,
+        )])
+        .await
+    }
+
+    /// Queue an email to a local user, if they gave an address
+    ///
+    /// Returns whether an email was queued.
+    pub async fn queue_user_email(
         &self,
-        domain_name: &str,
-    ) -> Result<Option<DomainDocument>, DatabaseError> {
-        let collection: Collection<DomainDocument> = self.database.collection("domains");
-        let result = collection.find_one(doc! { "domain": domain_name }).await?;
-        Ok(result)
+        username: &str,
+        domain: &str,
+        kind: EmailKind,
+        data: serde_json::Value,
+    ) -> Result<bool, DatabaseError> {
+        let Some(to) = self
+            .find_credentials(username, domain)
+            .await?
+            .and_then(|credentials| credentials.email)
+        else {
+            return Ok(false);
+        };
+        self.queue_email(&EmailMessage::new(kind, domain.to_string(), to, data))
+            .await?;
+        Ok(true)
     }
 
-    /// Insert a new follow relationship
-    pub async fn insert_follow(&self, follow: FollowDocument) -> Result<ObjectId, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let result = collection.insert_one(follow).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
+    /// Notify the local actors a post is addressed to
+    ///
+    /// Mentioned actors are among the recipients of a post, so every local,
+    /// active recipient but the author gets a mention. Returns the number
+    /// of notifications.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn notify_recipients(&self, object: &ObjectDocument) -> Result<usize, DatabaseError> {
+        let recipients: Vec<&String> = [&object.to, &object.cc, &object.bto, &object.bcc]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|recipient| **recipient != object.attributed_to)
+            .collect();
+        if recipients.is_empty() {
+            return Ok(0);
+        }
+
+        let actors: Collection<ActorDocument> = self.database.collection("actors");
+        let local: Vec<ActorDocument> = actors
+            .find(doc! {
+                "actor_id": { "$in": recipients },
+                "local": true,
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+            })
+            .await?
+            .try_collect()
+            .await?;
+
+        let direct = object.visibility == VisibilityLevel::Direct;
+        let notifications: Vec<NotificationDocument> = local
+            .iter()
+            .map(|recipient| {
+                NotificationDocument::new(
+                    NotificationType::Mention,
+                    recipient,
+                    &object.attributed_to,
+                    Some(object.object_id.clone()),
+                    direct,
+                )
+            })
+            .collect();
+        let count = notifications.len();
+        self.insert_notifications(notifications).await?;
+        Ok(count)
     }
 
-    /// Find follow relationship
-    pub async fn find_follow(
+    /// Notify a local actor of a new follower
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn notify_follow(
         &self,
         follower: &str,
-        following: &str,
-    ) -> Result<Option<FollowDocument>, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let result = collection
-            .find_one(doc! { "follower": follower, "following": following })
-            .await?;
-        Ok(result)
+        followed: &ActorDocument,
+    ) -> Result<(), DatabaseError> {
+        if !followed.local {
+            return Ok(());
+        }
+        self.insert_notifications(vec![NotificationDocument::new(
+            NotificationType::Follow,
+            followed,
+            follower,
+            None,
+            false,
+        )])
+        .await
     }
 
-    /// Update follow status
-    pub async fn update_follow_status(
+    /// Notify a local actor that their follow request was rejected
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn notify_follow_rejected(
         &self,
-        follower: &str,
-        following: &str,
-        status: FollowStatus,
-    ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let result = collection
-            .update_one(
-                doc! { "follower": follower, "following": following },
-                doc! {
-                    "$set": { "status": mongodb::bson::to_bson(&status)? },
-                    "$currentDate": { "responded_at": true }
-                },
-            )
-            .await?;
-        Ok(result)
+        follower: &ActorDocument,
+        rejected_by: &str,
+    ) -> Result<(), DatabaseError> {
+        if !follower.local {
+            return Ok(());
+        }
+        self.insert_notifications(vec![NotificationDocument::new(
+            NotificationType::FollowRejected,
+            follower,
+            rejected_by,
+            None,
+            false,
+        )])
+        .await
     }
 
-    /// Get actor's outbox (recent objects)
-    pub async fn get_actor_outbox(
+    /// Notify the local author of a post that an actor liked it
+    ///
+    /// Authors are not notified of their own likes. Returns whether a
+    /// notification was stored.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn notify_favourite(
         &self,
-        actor_id: &str,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let mut cursor = collection
-            .find(doc! { "attributed_to": actor_id })
-            .sort(doc! { "published": -1 })
-            .limit(limit)
-            .skip(offset as u64)
-            .await?;
+        liker: &str,
+        object: &ObjectDocument,
+    ) -> Result<bool, DatabaseError> {
+        if !object.local || object.attributed_to == liker {
+            return Ok(false);
+        }
+        let Some(author) = self
+            .find_actor_by_id(&object.attributed_to)
+            .await?
+            .filter(|author| author.local && author.status == ActorStatus::Active)
+        else {
+            return Ok(false);
+        };
+        self.insert_notifications(vec![NotificationDocument::new(
+            NotificationType::Favourite,
+            &author,
+            liker,
+            Some(object.object_id.clone()),
+            false,
+        )])
+        .await?;
+        Ok(true)
+    }
 
-        let mut objects = Vec::new();
-        while cursor.advance().await? {
-            objects.push(cursor.deserialize_current()?);
+    /// Store notifications and queue them for push delivery
+    async fn insert_notifications(
+        &self,
+        notifications: Vec<NotificationDocument>,
+    ) -> Result<(), DatabaseError> {
+        if notifications.is_empty() {
+            return Ok(());
         }
+        let messages = notifications
+            .iter()
+            .map(|notification| {
+                serde_json::to_string(&notification.push_message())
+                    .map(|payload| {
+                        OutboxMessageDocument::new(
+                            EXCHANGE_PUSH,
+                            "",
+                            Some("application/json"),
+                            payload,
+                        )
+                    })
+                    .map_err(|e| {
+                        DatabaseError::OperationError(format!("Invalid push message: {}", e))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(objects)
+        let collection: Collection<NotificationDocument> =
+            self.database.collection("notifications");
+        collection.insert_many(notifications).await?;
+        self.insert_outbox_messages(messages).await
     }
 
-    /// Get actor's followers
-    pub async fn get_actor_followers(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let mut cursor = collection
-            .find(doc! { "following": actor_id, "status": "accepted" })
-            .await?;
+    /// Store the push subscription of a session, replacing its previous one
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn upsert_push_subscription(
+        &self,
+        subscription: PushSubscriptionDocument,
+    ) -> Result<PushSubscriptionDocument, DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        collection
+            .find_one_and_replace(
+                doc! { "access_token_hash": &subscription.access_token_hash },
+                &subscription,
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await?
+            .ok_or_else(|| DatabaseError::OperationError("Push subscription not stored".into()))
+    }
 
-        let mut followers = Vec::new();
-        while cursor.advance().await? {
-            let follow: FollowDocument = cursor.deserialize_current()?;
-            followers.push(follow.follower);
+    /// Push subscription of a session
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_push_subscription(
+        &self,
+        access_token_hash: &str,
+    ) -> Result<Option<PushSubscriptionDocument>, DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        Ok(collection
+            .find_one(doc! { "access_token_hash": access_token_hash })
+            .await?)
+    }
+
+    /// Change the alerts and, if given, the policy of a push subscription
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_push_subscription(
+        &self,
+        access_token_hash: &str,
+        alerts: &PushAlerts,
+        policy: Option<PushPolicy>,
+    ) -> Result<Option<PushSubscriptionDocument>, DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        let mut set = doc! { "alerts": mongodb::bson::to_bson(alerts)? };
+        if let Some(policy) = policy {
+            set.insert("policy", mongodb::bson::to_bson(&policy)?);
         }
+        Ok(collection
+            .find_one_and_update(
+                doc! { "access_token_hash": access_token_hash },
+                doc! { "$set": set },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
 
-        Ok(followers)
+    /// Remove the push subscription of a session, returning whether there
+    /// was one
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_push_subscription(
+        &self,
+        access_token_hash: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        let result = collection
+            .delete_one(doc! { "access_token_hash": access_token_hash })
+            .await?;
+        Ok(result.deleted_count > 0)
     }
 
-    /// Get actor's following
-    pub async fn get_actor_following(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let mut cursor = collection
-            .find(doc! { "follower": actor_id, "status": "accepted" })
+    /// Keep a push subscription when the tokens of its session are refreshed
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn move_push_subscription(
+        &self,
+        old_access_token_hash: &str,
+        new_access_token_hash: &str,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        collection
+            .update_one(
+                doc! { "access_token_hash": old_access_token_hash },
+                doc! { "$set": { "access_token_hash": new_access_token_hash } },
+            )
             .await?;
+        Ok(())
+    }
 
-        let mut following = Vec::new();
-        while cursor.advance().await? {
-            let follow: FollowDocument = cursor.deserialize_current()?;
-            following.push(follow.following);
+    /// Push subscriptions of a user's sessions
+    ///
+    /// Subscriptions whose session ended, because it was revoked or its
+    /// refresh token expired, are removed.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_push_subscriptions(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<Vec<PushSubscriptionDocument>, DatabaseError> {
+        let collection: Collection<PushSubscriptionDocument> =
+            self.database.collection("push_subscriptions");
+        let subscriptions: Vec<PushSubscriptionDocument> = collection
+            .find(doc! { "domain": domain, "username": username })
+            .await?
+            .try_collect()
+            .await?;
+        if subscriptions.is_empty() {
+            return Ok(subscriptions);
         }
 
-        Ok(following)
+        let refresh_tokens: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+        let hashes: Vec<&String> = subscriptions
+            .iter()
+            .map(|subscription| &subscription.access_token_hash)
+            .collect();
+        let live: Vec<RefreshTokenDocument> = refresh_tokens
+            .find(doc! { "access_token_hash": { "$in": &hashes } })
+            .await?
+            .try_collect()
+            .await?;
+
+        let (live, ended): (Vec<_>, Vec<_>) = subscriptions.into_iter().partition(|subscription| {
+            live.iter()
+                .any(|token| token.access_token_hash == subscription.access_token_hash)
+        });
+        if !ended.is_empty() {
+            let ended: Vec<&String> = ended
+                .iter()
+                .map(|subscription| &subscription.access_token_hash)
+                .collect();
+            collection
+                .delete_many(doc! { "access_token_hash": { "$in": ended } })
+                .await?;
+        }
+        Ok(live)
     }
 
-    /// Get all follow documents where actor is the follower (all statuses)
-    pub async fn get_actor_following_all(
+    /// VAPID key of a domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_vapid_key(
         &self,
-        actor_id: &str,
-    ) -> Result<Vec<FollowDocument>, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let mut cursor = collection.find(doc! { "follower": actor_id }).await?;
+        domain: &str,
+    ) -> Result<Option<VapidKeyDocument>, DatabaseError> {
+        let collection: Collection<VapidKeyDocument> = self.database.collection("vapid_keys");
+        Ok(collection.find_one(doc! { "domain": domain }).await?)
+    }
 
-        let mut follows = Vec::new();
-        while cursor.advance().await? {
-            follows.push(cursor.deserialize_current()?);
-        }
+    /// Store the VAPID key of a domain unless it has one
+    ///
+    /// Returns the stored key, which is the existing one when another
+    /// instance stored one first.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_vapid_key(
+        &self,
+        key: VapidKeyDocument,
+    ) -> Result<VapidKeyDocument, DatabaseError> {
+        let collection: Collection<VapidKeyDocument> = self.database.collection("vapid_keys");
+        let stored = match collection
+            .find_one_and_update(
+                doc! { "domain": &key.domain },
+                doc! { "$setOnInsert": mongodb::bson::to_document(&key)? },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+        {
+            Ok(stored) => stored,
+            // Concurrent upserts may both insert; the loser reads the winner's
+            Err(e) if batch::is_duplicate_key(&e) => self.find_vapid_key(&key.domain).await?,
+            Err(e) => return Err(e.into()),
+        };
+        stored.ok_or_else(|| DatabaseError::OperationError("VAPID key not stored".into()))
+    }
 
-        Ok(follows)
+    /// Insert a new quarantine entry
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_quarantined(
+        &self,
+        entry: QuarantineDocument,
+    ) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<QuarantineDocument> = self.database.collection("quarantine");
+        let result = collection.insert_one(entry).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
     }
 
-    /// Get all follow documents where actor is being followed (all statuses)
-    pub async fn get_actor_followers_all(
+    /// Find quarantine entry by ID
+    pub async fn find_quarantined(
         &self,
-        actor_id: &str,
-    ) -> Result<Vec<FollowDocument>, DatabaseError> {
-        let collection: Collection<FollowDocument> = self.database.collection("follows");
-        let mut cursor = collection.find(doc! { "following": actor_id }).await?;
+        quarantine_id: &str,
+    ) -> Result<Option<QuarantineDocument>, DatabaseError> {
+        let collection: Collection<QuarantineDocument> = self.database.collection("quarantine");
+        let result = collection
+            .find_one(doc! { "quarantine_id": quarantine_id })
+            .await?;
+        Ok(result)
+    }
 
-        let mut follows = Vec::new();
-        while cursor.advance().await? {
-            follows.push(cursor.deserialize_current()?);
-        }
+    /// List quarantine entries, newest first, optionally filtered by status
+    pub async fn list_quarantined(
+        &self,
+        status: Option<QuarantineStatus>,
+        limit: i64,
+    ) -> Result<Vec<QuarantineDocument>, DatabaseError> {
+        let collection: Collection<QuarantineDocument> = self.database.collection("quarantine");
+        let filter = match status {
+            Some(status) => doc! { "status": mongodb::bson::to_bson(&status)? },
+            None => doc! {},
+        };
 
-        Ok(follows)
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
+            .await?;
+        let results: Vec<QuarantineDocument> = cursor.try_collect().await?;
+        Ok(results)
     }
 
-    /// Update an object
-    pub async fn update_object(
+    /// Record the review decision for a quarantine entry
+    pub async fn review_quarantined(
         &self,
-        object_id: &str,
-        update: Document,
+        quarantine_id: &str,
+        status: QuarantineStatus,
+        moderator: &str,
     ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let collection: Collection<QuarantineDocument> = self.database.collection("quarantine");
         let result = collection
             .update_one(
-                doc! { "object_id": object_id },
-                doc! { "$set": update, "$currentDate": { "updated_at": true } },
+                doc! { "quarantine_id": quarantine_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&status)?,
+                        "reviewed_by": moderator,
+                        "reviewed_at": mongodb::bson::to_bson(&Utc::now())?,
+                    }
+                },
             )
             .await?;
         Ok(result)
     }
 
-    /// Delete an object
-    pub async fn delete_object(&self, object_id: &str) -> Result<(), DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        collection
-            .delete_one(doc! { "object_id": object_id })
+    /// Record an object of `attributed_to` with a content hash and return
+    /// how many distinct objects of the author had it in the current window
+    ///
+    /// An object delivered more than once, for example to several local
+    /// domains, counts once. The window starts when the hash is first seen
+    /// and ends at `purge_at`, after which the TTL index removes the counter.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_content_hash(
+        &self,
+        attributed_to: &str,
+        hash: &str,
+        object_id: &str,
+        purge_at: BsonDateTime,
+    ) -> Result<usize, DatabaseError> {
+        let collection: Collection<ContentHashDocument> =
+            self.database.collection("content_hashes");
+        let result = collection
+            .find_one_and_update(
+                doc! { "attributed_to": attributed_to, "hash": hash },
+                doc! {
+                    "$addToSet": { "object_ids": object_id },
+                    "$setOnInsert": {
+                        "first_seen": mongodb::bson::to_bson(&Utc::now())?,
+                        "purge_at": purge_at,
+                    }
+                },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
             .await?;
-        Ok(())
+        Ok(result.map(|doc| doc.object_ids.len()).unwrap_or(1))
     }
 
-    /// Update an activity
-    pub async fn update_activity(
+    /// Insert a new dead letter
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_dead_letter(
         &self,
-        activity_id: &str,
-        update: Document,
-    ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        dead_letter: DeadLetterDocument,
+    ) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
+        let result = collection.insert_one(dead_letter).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// Find dead letter by ID
+    pub async fn find_dead_letter(
+        &self,
+        dead_letter_id: &str,
+    ) -> Result<Option<DeadLetterDocument>, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
         let result = collection
-            .update_one(
-                doc! { "activity_id": activity_id },
-                doc! { "$set": update, "$currentDate": { "updated_at": true } },
-            )
+            .find_one(doc! { "dead_letter_id": dead_letter_id })
             .await?;
         Ok(result)
     }
 
-    /// Delete an activity
-    pub async fn delete_activity(&self, activity_id: &str) -> Result<(), DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
-        collection
-            .delete_one(doc! { "activity_id": activity_id })
+    /// List dead letters, newest first, optionally filtered by queue and status
+    pub async fn list_dead_letters(
+        &self,
+        queue: Option<&str>,
+        status: Option<DeadLetterStatus>,
+        limit: i64,
+    ) -> Result<Vec<DeadLetterDocument>, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
+        let mut filter = doc! {};
+        if let Some(queue) = queue {
+            filter.insert("queue", queue);
+        }
+        if let Some(status) = status {
+            filter.insert("status", mongodb::bson::to_bson(&status)?);
+        }
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
             .await?;
-        Ok(())
+        let results: Vec<DeadLetterDocument> = cursor.try_collect().await?;
+        Ok(results)
     }
 
-    /// Find objects by actor with pagination
-    pub async fn find_objects_by_actor(
+    /// List pending dead letters whose automatic retry is due
+    pub async fn due_dead_letters(
         &self,
-        actor_id: &str,
+        now: BsonDateTime,
         limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let mut cursor = collection
-            .find(doc! { "attributed_to": actor_id })
-            .sort(doc! { "published": -1 })
+    ) -> Result<Vec<DeadLetterDocument>, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
+        let cursor = collection
+            .find(doc! {
+                "status": mongodb::bson::to_bson(&DeadLetterStatus::Pending)?,
+                "next_retry_at": { "$lte": now },
+            })
+            .sort(doc! { "next_retry_at": 1 })
             .limit(limit)
-            .skip(offset as u64)
             .await?;
+        let results: Vec<DeadLetterDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
 
-        let mut objects = Vec::new();
-        while cursor.advance().await? {
-            objects.push(cursor.deserialize_current()?);
-        }
+    /// Mark a dead letter as re-injected
+    pub async fn mark_dead_letter_retried(
+        &self,
+        dead_letter_id: &str,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
+        let result = collection
+            .update_one(
+                doc! { "dead_letter_id": dead_letter_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&DeadLetterStatus::Retried)?,
+                        "next_retry_at": Bson::Null,
+                        "retried_at": mongodb::bson::to_bson(&Utc::now())?,
+                    }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
 
-        Ok(objects)
+    /// Delete a dead letter, or all dead letters if no ID is given
+    pub async fn delete_dead_letters(
+        &self,
+        dead_letter_id: Option<&str>,
+    ) -> Result<u64, DatabaseError> {
+        let collection: Collection<DeadLetterDocument> = self.database.collection("dead_letters");
+        let filter = match dead_letter_id {
+            Some(id) => doc! { "dead_letter_id": id },
+            None => doc! {},
+        };
+        let result = collection.delete_many(filter).await?;
+        Ok(result.deleted_count)
     }
 
-    /// Find activities by actor with pagination
-    pub async fn find_activities_by_actor(
+    /// Append an entry to the audit log
+    pub async fn insert_audit_entry(
         &self,
-        actor_id: &str,
+        entry: AuditLogDocument,
+    ) -> Result<ObjectId, DatabaseError> {
+        let collection: Collection<AuditLogDocument> = self.database.collection("audit_log");
+        let result = collection.insert_one(entry).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    /// List audit log entries newest first
+    ///
+    /// Filters on the actor, action and target are exact matches. `before`
+    /// continues a listing after the entry with that ID.
+    pub async fn find_audit_entries(
+        &self,
+        actor: Option<&str>,
+        action: Option<&str>,
+        target: Option<&str>,
+        before: Option<ObjectId>,
         limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ActivityDocument>, DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
-        let mut cursor = collection
-            .find(doc! { "actor": actor_id })
-            .sort(doc! { "published": -1 })
+    ) -> Result<Vec<AuditLogDocument>, DatabaseError> {
+        let collection: Collection<AuditLogDocument> = self.database.collection("audit_log");
+        let mut filter = doc! {};
+        if let Some(actor) = actor {
+            filter.insert("actor", actor);
+        }
+        if let Some(action) = action {
+            filter.insert("action", action);
+        }
+        if let Some(target) = target {
+            filter.insert("target", target);
+        }
+        if let Some(before) = before {
+            filter.insert("_id", doc! { "$lt": before });
+        }
+
+        let cursor = collection
+            .find(filter)
+            .sort(doc! { "_id": -1 })
             .limit(limit)
-            .skip(offset as u64)
             .await?;
+        let results: Vec<AuditLogDocument> = cursor.try_collect().await?;
+        Ok(results)
+    }
 
-        let mut activities = Vec::new();
-        while cursor.advance().await? {
-            activities.push(cursor.deserialize_current()?);
+    /// Insert an activity together with the outbox messages announcing it
+    ///
+    /// Both writes run in one transaction when the deployment supports it
+    /// (replica set or sharded cluster). On a standalone server the outbox
+    /// messages are written right after the activity.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_activity_with_outbox(
+        &self,
+        activity: ActivityDocument,
+        messages: Vec<OutboxMessageDocument>,
+    ) -> Result<ObjectId, DatabaseError> {
+        let activities: Collection<ActivityDocument> = self.database.collection("activities");
+        let outbox: Collection<OutboxMessageDocument> = self.database.collection("message_outbox");
+
+        let mut session = self.database.client().start_session().await?;
+        if let Err(e) = session.start_transaction().await {
+            if !matches!(*e.kind, MongoErrorKind::Transaction { .. }) {
+                return Err(e.into());
+            }
+
+            let id = self.insert_activity(activity).await?;
+            self.insert_outbox_messages(messages).await?;
+            return Ok(id);
         }
 
-        Ok(activities)
+        let result = activities.insert_one(activity).session(&mut session).await;
+        let result = match result {
+            Ok(result) if messages.is_empty() => Ok(result),
+            Ok(result) => outbox
+                .insert_many(messages)
+                .session(&mut session)
+                .await
+                .map(|_| result),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(result) => {
+                session.commit_transaction().await?;
+                Ok(result.inserted_id.as_object_id().unwrap())
+            }
+            Err(e) => {
+                session.abort_transaction().await.ok();
+                Err(e.into())
+            }
+        }
     }
 
-    /// Find activities by type
-    pub async fn find_activities_by_type(
+    /// Insert messages into the outbox
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_outbox_messages(
         &self,
-        activity_type: ActivityType,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ActivityDocument>, DatabaseError> {
-        let collection: Collection<ActivityDocument> = self.database.collection("activities");
-        let mut cursor = collection
-            .find(doc! { "activity_type": mongodb::bson::to_bson(&activity_type)? })
-            .sort(doc! { "created_at": -1 })
-            .limit(limit)
-            .skip(offset as u64)
-            .await?;
-
-        let mut activities = Vec::new();
-        while cursor.advance().await? {
-            activities.push(cursor.deserialize_current()?);
+        messages: Vec<OutboxMessageDocument>,
+    ) -> Result<(), DatabaseError> {
+        if messages.is_empty() {
+            return Ok(());
         }
 
-        Ok(activities)
+        let collection: Collection<OutboxMessageDocument> =
+            self.database.collection("message_outbox");
+        collection.insert_many(messages).await?;
+        Ok(())
     }
 
-    /// Count objects by actor
-    pub async fn count_objects_by_actor(&self, actor_id: &str) -> Result<u64, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let count = collection
-            .count_documents(doc! { "attributed_to": actor_id })
+    /// Claim the oldest pending outbox message for publishing
+    ///
+    /// A claimed message is skipped by other relays until the lease expires.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn claim_outbox_message(
+        &self,
+        lease: chrono::Duration,
+    ) -> Result<Option<OutboxMessageDocument>, DatabaseError> {
+        let collection: Collection<OutboxMessageDocument> =
+            self.database.collection("message_outbox");
+        let now = Utc::now();
+        let result = collection
+            .find_one_and_update(
+                doc! {
+                    "status": mongodb::bson::to_bson(&OutboxStatus::Pending)?,
+                    "$or": [
+                        { "claimed_until": Bson::Null },
+                        { "claimed_until": { "$lte": BsonDateTime::from_millis(now.timestamp_millis()) } },
+                    ],
+                },
+                doc! {
+                    "$set": {
+                        "claimed_until": BsonDateTime::from_millis((now + lease).timestamp_millis()),
+                    }
+                },
+            )
+            .sort(doc! { "created_at": 1 })
+            .return_document(ReturnDocument::After)
             .await?;
-        Ok(count)
+        Ok(result)
     }
 
-    /// Update actor counts (followers, following, statuses)
-    pub async fn update_actor_counts(
+    /// Mark an outbox message as published and schedule its removal
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn mark_outbox_published(
         &self,
-        actor_id: &str,
-        followers_count: Option<i64>,
-        following_count: Option<i64>,
-        statuses_count: Option<i64>,
+        message_id: &str,
+        retention: chrono::Duration,
     ) -> Result<UpdateResult, DatabaseError> {
-        let mut update_doc = doc! {};
+        let collection: Collection<OutboxMessageDocument> =
+            self.database.collection("message_outbox");
+        let now = Utc::now();
+        let result = collection
+            .update_one(
+                doc! { "message_id": message_id },
+                doc! {
+                    "$set": {
+                        "status": mongodb::bson::to_bson(&OutboxStatus::Published)?,
+                        "published_at": mongodb::bson::to_bson(&now)?,
+                        "purge_at": BsonDateTime::from_millis((now + retention).timestamp_millis()),
+                        "claimed_until": Bson::Null,
+                    }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
 
-        if let Some(count) = followers_count {
-            update_doc.insert("followers_count", count);
-        }
-        if let Some(count) = following_count {
-            update_doc.insert("following_count", count);
-        }
-        if let Some(count) = statuses_count {
-            update_doc.insert("statuses_count", count);
-        }
+    /// Record a failed publish attempt and release the claim
+    pub async fn record_outbox_failure(
+        &self,
+        message_id: &str,
+        error: &str,
+    ) -> Result<UpdateResult, DatabaseError> {
+        let collection: Collection<OutboxMessageDocument> =
+            self.database.collection("message_outbox");
+        let result = collection
+            .update_one(
+                doc! { "message_id": message_id },
+                doc! {
+                    "$inc": { "attempts": 1 },
+                    "$set": {
+                        "last_error": error,
+                        "claimed_until": Bson::Null,
+                    }
+                },
+            )
+            .await?;
+        Ok(result)
+    }
 
-        if !update_doc.is_empty() {
-            let system_time: SystemTime = chrono::Utc::now().into();
-            update_doc.insert("updated_at", Bson::DateTime(system_time.into()));
-            self.update_actor(actor_id, update_doc).await
-        } else {
-            // Return a minimal successful update result
-            let collection: Collection<ActorDocument> = self.database.collection("actors");
-            let result = collection
-                .update_one(doc! { "actor_id": actor_id }, doc! {})
-                .await?;
-            Ok(result)
+    /// Check that the database answers commands
+    pub async fn ping(&self) -> Result<(), DatabaseError> {
+        match self.database.run_command(doc! { "ping": 1 }).await {
+            Ok(_) => {
+                self.monitor.record_success();
+                Ok(())
+            }
+            Err(e) => {
+                self.monitor.record_failure(&e.to_string());
+                Err(e.into())
+            }
         }
     }
 
-    /// Delete actor and all related data
-    pub async fn delete_actor(&self, actor_id: &str) -> Result<(), DatabaseError> {
-        // Delete actor
-        let actors: Collection<ActorDocument> = self.database.collection("actors");
-        actors.delete_one(doc! { "actor_id": actor_id }).await?;
+    /// Used and total bytes of the filesystem holding the database
+    ///
+    /// Returns `None` when the server does not report filesystem usage.
+    pub async fn filesystem_usage(&self) -> Result<Option<(f64, f64)>, DatabaseError> {
+        let stats = self.database.run_command(doc! { "dbStats": 1 }).await?;
+        let number = |key: &str| match stats.get(key) {
+            Some(Bson::Double(n)) => Some(*n),
+            Some(Bson::Int64(n)) => Some(*n as f64),
+            Some(Bson::Int32(n)) => Some(*n as f64),
+            _ => None,
+        };
+
+        Ok(number("fsUsedSize").zip(number("fsTotalSize")))
+    }
+
+    /// Find the login credentials of a local user
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_credentials(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<Option<CredentialDocument>, DatabaseError> {
+        let collection: Collection<CredentialDocument> = self.database.collection("credentials");
+        Ok(collection
+            .find_one(doc! { "domain": domain, "username": username })
+            .await?)
+    }
+
+    /// Set the password hash of a local actor
+    ///
+    /// Creates the credentials if needed and ends a pending password reset.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn set_password_hash(
+        &self,
+        actor: &ActorDocument,
+        password_hash: &str,
+    ) -> Result<(), DatabaseError> {
+        self.upsert_credentials(
+            actor,
+            doc! {
+                "password_hash": password_hash,
+                "reset_token_hash": Bson::Null,
+                "reset_expires_at": Bson::Null,
+            },
+            doc! {},
+        )
+        .await
+    }
 
-        // Delete actor's objects
-        let objects: Collection<ObjectDocument> = self.database.collection("objects");
-        objects
-            .delete_many(doc! { "attributed_to": actor_id })
+    /// Replace the password hash of a local user with a stronger hash of
+    /// the same password
+    ///
+    /// Only applies while the stored hash is still `current`, so a password
+    /// changed in the meantime is kept.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn rehash_password(
+        &self,
+        username: &str,
+        domain: &str,
+        current: &str,
+        password_hash: &str,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<CredentialDocument> = self.database.collection("credentials");
+        collection
+            .update_one(
+                doc! { "domain": domain, "username": username, "password_hash": current },
+                doc! { "$set": { "password_hash": password_hash } },
+            )
             .await?;
+        Ok(())
+    }
 
-        // Delete actor's activities
-        let activities: Collection<ActivityDocument> = self.database.collection("activities");
-        activities.delete_many(doc! { "actor": actor_id }).await?;
+    /// Allow a local actor to set a new password with a reset token
+    ///
+    /// Replaces an earlier reset token; the current password stays valid
+    /// until the new one is set.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn set_password_reset(
+        &self,
+        actor: &ActorDocument,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        self.upsert_credentials(
+            actor,
+            doc! {
+                "reset_token_hash": token_hash,
+                "reset_expires_at": mongodb::bson::to_bson(&expires_at)?,
+            },
+            doc! { "password_hash": Bson::Null },
+        )
+        .await
+    }
 
-        // Delete actor's keys
-        let keys: Collection<KeyDocument> = self.database.collection("keys");
-        keys.delete_many(doc! { "actor_id": actor_id }).await?;
+    /// Set the address emails to a local user are sent to
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn set_credentials_email(
+        &self,
+        actor: &ActorDocument,
+        email: &str,
+    ) -> Result<(), DatabaseError> {
+        self.upsert_credentials(actor, doc! { "email": email }, doc! {})
+            .await
+    }
 
-        // Delete follow relationships
-        let follows: Collection<FollowDocument> = self.database.collection("follows");
-        follows
-            .delete_many(doc! { "$or": [{"follower": actor_id}, {"following": actor_id}] })
+    async fn upsert_credentials(
+        &self,
+        actor: &ActorDocument,
+        mut set: Document,
+        mut set_on_insert: Document,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<CredentialDocument> = self.database.collection("credentials");
+        let now = mongodb::bson::to_bson(&Utc::now())?;
+        set.insert("actor_id", &actor.actor_id);
+        set.insert("updated_at", now.clone());
+        set_on_insert.insert("created_at", now);
+        collection
+            .update_one(
+                doc! { "domain": &actor.domain, "username": &actor.preferred_username },
+                doc! { "$set": set, "$setOnInsert": set_on_insert },
+            )
+            .upsert(true)
             .await?;
+        Ok(())
+    }
+
+    /// Find the credentials a password reset token was issued for
+    ///
+    /// Expired tokens are not found.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_credentials_by_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<CredentialDocument>, DatabaseError> {
+        let collection: Collection<CredentialDocument> = self.database.collection("credentials");
+        Ok(collection
+            .find_one(doc! { "reset_token_hash": token_hash })
+            .await?
+            .filter(|credentials| {
+                credentials
+                    .reset_expires_at
+                    .is_some_and(|expires_at| expires_at > Utc::now())
+            }))
+    }
 
+    /// Insert a new registration application
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_registration_application(
+        &self,
+        application: RegistrationApplicationDocument,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
+        collection.insert_one(application).await?;
         Ok(())
     }
 
-    /// Get total number of local actors
-    pub async fn count_local_actors(&self) -> Result<u64, DatabaseError> {
-        let collection: Collection<ActorDocument> = self.database.collection("actors");
-        let count = collection.count_documents(doc! { "local": true }).await?;
-        Ok(count)
+    /// Find registration application by ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_registration_application(
+        &self,
+        application_id: &str,
+    ) -> Result<Option<RegistrationApplicationDocument>, DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
+        Ok(collection
+            .find_one(doc! { "application_id": application_id })
+            .await?)
     }
 
-    /// Get total number of local posts
-    pub async fn count_local_posts(&self) -> Result<u64, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+    /// Whether a username has a pending application on a domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn has_pending_application(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
         let count = collection
             .count_documents(doc! {
-                "local": true,
-                "object_type": { "$in": ["Note", "Article"] }
+                "domain": domain,
+                "username": username,
+                "status": "pending",
             })
             .await?;
-        Ok(count)
+        Ok(count > 0)
     }
 
-    /// Find objects by content search
-    pub async fn search_objects(
+    /// Number of applications on a domain waiting for review
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn count_pending_applications(&self, domain: &str) -> Result<u64, DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
+        Ok(collection
+            .count_documents(doc! { "domain": domain, "status": "pending" })
+            .await?)
+    }
+
+    /// Page of registration applications oldest first, optionally of one
+    /// domain and status, with the number of all of them
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_registration_applications_page(
         &self,
-        query: &str,
+        domain: Option<&str>,
+        status: Option<ApplicationStatus>,
+        offset: u64,
         limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let filter = doc! {
-            "$or": [
-                { "content": { "$regex": query, "$options": "i" } },
-                { "summary": { "$regex": query, "$options": "i" } },
-                { "name": { "$regex": query, "$options": "i" } }
-            ]
-        };
+    ) -> Result<(Vec<RegistrationApplicationDocument>, u64), DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
+        let mut filter = doc! {};
+        if let Some(domain) = domain {
+            filter.insert("domain", domain);
+        }
+        if let Some(status) = status {
+            filter.insert("status", mongodb::bson::to_bson(&status)?);
+        }
 
+        let total = collection.count_documents(filter.clone()).await?;
         let cursor = collection
             .find(filter)
-            .skip(offset as u64)
+            .sort(doc! { "created_at": 1 })
+            .skip(offset)
             .limit(limit)
             .await?;
+        Ok((cursor.try_collect().await?, total))
+    }
 
-        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
-        Ok(results)
+    /// Record the review of a pending registration application
+    ///
+    /// Returns the application as it was before the review, or `None` if it
+    /// does not exist or was already reviewed.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn review_registration_application(
+        &self,
+        application_id: &str,
+        status: ApplicationStatus,
+        reviewed_by: Option<&str>,
+        review_note: Option<&str>,
+    ) -> Result<Option<RegistrationApplicationDocument>, DatabaseError> {
+        let collection: Collection<RegistrationApplicationDocument> =
+            self.database.collection("registration_applications");
+        Ok(collection
+            .find_one_and_update(
+                doc! { "application_id": application_id, "status": "pending" },
+                doc! { "$set": {
+                    "status": mongodb::bson::to_bson(&status)?,
+                    "reviewed_by": reviewed_by,
+                    "review_note": review_note,
+                    "reviewed_at": mongodb::bson::to_bson(&Utc::now())?,
+                } },
+            )
+            .await?)
     }
 
-    /// Get recent public activities for timeline
-    pub async fn get_public_timeline(
+    /// Insert a new invite code
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_invite(&self, invite: InviteDocument) -> Result<(), DatabaseError> {
+        let collection: Collection<InviteDocument> = self.database.collection("invites");
+        collection.insert_one(invite).await?;
+        Ok(())
+    }
+
+    /// Count a sign-up with an invite code of a domain
+    ///
+    /// Returns `false` without counting if the code is unknown, expired or
+    /// used up.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn redeem_invite(
         &self,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let filter = doc! {
-            "visibility": "public",
-            "object_type": { "$in": ["Note", "Article"] }
-        };
+        code_hash: &str,
+        domain: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<InviteDocument> = self.database.collection("invites");
+        let now = mongodb::bson::to_bson(&Utc::now())?;
+        let result = collection
+            .update_one(
+                doc! {
+                    "code_hash": code_hash,
+                    "domain": domain,
+                    "$and": [
+                        { "$or": [{ "expires_at": Bson::Null }, { "expires_at": { "$gt": now } }] },
+                        { "$or": [
+                            { "max_uses": Bson::Null },
+                            { "$expr": { "$lt": ["$uses", "$max_uses"] } },
+                        ] },
+                    ],
+                },
+                doc! { "$inc": { "uses": 1 } },
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Revoke all access and refresh tokens of a user, or only those of
+    /// one application
+    ///
+    /// Returns the number of revoked sessions, that is refresh tokens.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn revoke_user_tokens(
+        &self,
+        username: &str,
+        domain: &str,
+        client_id: Option<&str>,
+    ) -> Result<u64, DatabaseError> {
+        let mut filter = doc! { "domain": domain, "username": username };
+        if let Some(client_id) = client_id {
+            filter.insert("client_id", client_id);
+        }
+        let access_tokens: Collection<AccessTokenDocument> =
+            self.database.collection("access_tokens");
+        let refresh_tokens: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+        let sessions = refresh_tokens.delete_many(filter.clone()).await?;
+        access_tokens.delete_many(filter).await?;
+        Ok(sessions.deleted_count)
+    }
 
+    /// Unexpired refresh tokens of a user, newest first
+    ///
+    /// Each stands for a session of an application, along with the access
+    /// token issued with it.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_user_sessions(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<Vec<RefreshTokenDocument>, DatabaseError> {
+        let collection: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
         let cursor = collection
-            .find(filter)
-            .sort(doc! { "published": -1 })
-            .skip(offset as u64)
-            .limit(limit)
+            .find(doc! {
+                "domain": domain,
+                "username": username,
+                "expires_at": { "$gt": BsonDateTime::now() },
+            })
+            .sort(doc! { "created_at": -1 })
             .await?;
-
-        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
-        Ok(results)
+        Ok(cursor.try_collect().await?)
     }
 
-    /// Get local timeline (only local posts)
-    pub async fn get_local_timeline(
+    /// Revoke a session of a user by the ID of its refresh token
+    ///
+    /// Returns whether the session existed.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn revoke_session(
         &self,
-        limit: i64,
-        offset: i64,
-    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
-        let collection: Collection<ObjectDocument> = self.database.collection("objects");
-        let filter = doc! {
-            "local": true,
-            "visibility": "public",
-            "object_type": { "$in": ["Note", "Article"] }
+        username: &str,
+        domain: &str,
+        session_id: ObjectId,
+    ) -> Result<bool, DatabaseError> {
+        let access_tokens: Collection<AccessTokenDocument> =
+            self.database.collection("access_tokens");
+        let refresh_tokens: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+        let Some(session) = refresh_tokens
+            .find_one_and_delete(doc! {
+                "_id": session_id,
+                "domain": domain,
+                "username": username,
+            })
+            .await?
+        else {
+            return Ok(false);
         };
+        access_tokens
+            .delete_one(doc! { "token_hash": &session.access_token_hash })
+            .await?;
+        Ok(true)
+    }
+
+    /// Register an OAuth application
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_oauth_app(&self, app: OAuthAppDocument) -> Result<(), DatabaseError> {
+        let collection: Collection<OAuthAppDocument> = self.database.collection("oauth_apps");
+        collection.insert_one(app).await?;
+        Ok(())
+    }
 
+    /// Find OAuth applications by client ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_oauth_apps(
+        &self,
+        client_ids: &[String],
+    ) -> Result<Vec<OAuthAppDocument>, DatabaseError> {
+        let collection: Collection<OAuthAppDocument> = self.database.collection("oauth_apps");
         let cursor = collection
-            .find(filter)
-            .sort(doc! { "published": -1 })
-            .skip(offset as u64)
-            .limit(limit)
+            .find(doc! { "client_id": { "$in": client_ids } })
             .await?;
+        Ok(cursor.try_collect().await?)
+    }
 
-        let results: Vec<ObjectDocument> = cursor.try_collect().await?;
-        Ok(results)
+    /// Find an OAuth application by client ID
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_oauth_app(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<OAuthAppDocument>, DatabaseError> {
+        let collection: Collection<OAuthAppDocument> = self.database.collection("oauth_apps");
+        Ok(collection.find_one(doc! { "client_id": client_id }).await?)
     }
 
-    /// Update key status
-    pub async fn update_key_status(
+    /// Store an authorization code
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_authorization_code(
         &self,
-        key_id: &str,
-        status: KeyStatus,
-    ) -> Result<UpdateResult, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
-        let status_str = match status {
-            KeyStatus::Active => "active",
-            KeyStatus::Revoked => "revoked",
-            KeyStatus::Expired => "expired",
-            KeyStatus::Pending => "pending",
-        };
-        let result = collection
-            .update_one(
-                doc! { "key_id": key_id },
-                doc! {
-                    "$set": { "status": status_str },
-                    "$currentDate": { "updated_at": true }
-                },
-            )
-            .await?;
-        Ok(result)
+        code: AuthorizationCodeDocument,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<AuthorizationCodeDocument> =
+            self.database.collection("oauth_codes");
+        collection.insert_one(code).await?;
+        Ok(())
     }
 
-    /// Find active keys by actor
-    pub async fn find_active_keys_by_actor(
+    /// Remove and return an unexpired authorization code
+    ///
+    /// Codes can only be exchanged once.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn take_authorization_code(
         &self,
-        actor_id: &str,
-    ) -> Result<Vec<KeyDocument>, DatabaseError> {
-        let collection: Collection<KeyDocument> = self.database.collection("keys");
-        let filter = doc! {
-            "actor_id": actor_id,
-            "status": "active"
-        };
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCodeDocument>, DatabaseError> {
+        let collection: Collection<AuthorizationCodeDocument> =
+            self.database.collection("oauth_codes");
+        Ok(collection
+            .find_one_and_delete(doc! {
+                "code_hash": code_hash,
+                "expires_at": { "$gt": BsonDateTime::now() },
+            })
+            .await?)
+    }
 
-        let cursor = collection.find(filter).await?;
-        let results: Vec<KeyDocument> = cursor.try_collect().await?;
-        Ok(results)
+    /// Store an access token and the refresh token issued with it
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_tokens(
+        &self,
+        access_token: AccessTokenDocument,
+        refresh_token: RefreshTokenDocument,
+    ) -> Result<(), DatabaseError> {
+        let access_tokens: Collection<AccessTokenDocument> =
+            self.database.collection("access_tokens");
+        let refresh_tokens: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+        access_tokens.insert_one(access_token).await?;
+        refresh_tokens.insert_one(refresh_token).await?;
+        Ok(())
+    }
+
+    /// Find an unexpired access token
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_access_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<AccessTokenDocument>, DatabaseError> {
+        let collection: Collection<AccessTokenDocument> = self.database.collection("access_tokens");
+        Ok(collection
+            .find_one(doc! {
+                "token_hash": token_hash,
+                "expires_at": { "$gt": BsonDateTime::now() },
+            })
+            .await?)
+    }
+
+    /// Find an unexpired refresh token
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshTokenDocument>, DatabaseError> {
+        let collection: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+        Ok(collection
+            .find_one(doc! {
+                "token_hash": token_hash,
+                "expires_at": { "$gt": BsonDateTime::now() },
+            })
+            .await?)
+    }
+
+    /// Revoke an access or refresh token of an application
+    ///
+    /// Revoking either token of a pair revokes both. Returns whether a token
+    /// was found.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn revoke_token(
+        &self,
+        token_hash: &str,
+        client_id: &str,
+    ) -> Result<bool, DatabaseError> {
+        let access_tokens: Collection<AccessTokenDocument> =
+            self.database.collection("access_tokens");
+        let refresh_tokens: Collection<RefreshTokenDocument> =
+            self.database.collection("refresh_tokens");
+
+        let refresh_token = refresh_tokens
+            .find_one_and_delete(doc! { "token_hash": token_hash, "client_id": client_id })
+            .await?;
+        let access_token_hash = refresh_token
+            .as_ref()
+            .map_or(token_hash, |refresh_token| &refresh_token.access_token_hash);
+        let access = access_tokens
+            .delete_one(doc! { "token_hash": access_token_hash, "client_id": client_id })
+            .await?;
+        refresh_tokens
+            .delete_many(doc! { "access_token_hash": access_token_hash, "client_id": client_id })
+            .await?;
+
+        Ok(refresh_token.is_some() || access.deleted_count > 0)
     }
 
-    /// Get domain statistics
-    pub async fn get_domain_stats(&self, domain: &str) -> Result<(u64, u64, u64), DatabaseError> {
-        // Get actor count
+    /// Federation statistics of a domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn get_domain_stats(&self, domain: &str) -> Result<DomainStats, DatabaseError> {
+        let local_id = doc! { "$regex": format!("^https://{}/", regex::escape(domain)) };
+        let remote_id =
+            doc! { "$not": { "$regex": format!("^https://{}/", regex::escape(domain)) } };
+
         let actors: Collection<ActorDocument> = self.database.collection("actors");
-        let actor_count = actors.count_documents(doc! { "domain": domain }).await?;
+        let actor_count = actors
+            .count_documents(doc! {
+                "domain": domain,
+                "local": true,
+                "status": mongodb::bson::to_bson(&ActorStatus::Active)?,
+            })
+            .await?;
 
-        // Get post count
         let objects: Collection<ObjectDocument> = self.database.collection("objects");
         let post_count = objects
             .count_documents(doc! {
+                "attributed_to": &local_id,
+                "status": { "$ne": "scheduled" },
                 "object_type": { "$in": ["Note", "Article"] }
             })
             .await?;
 
-        // Get activity count
+        let follows: Collection<FollowDocument> = self.database.collection("follows");
+        let accepted = mongodb::bson::to_bson(&FollowStatus::Accepted)?;
+        let remote_followers = follows
+            .count_documents(doc! {
+                "following": &local_id,
+                "follower": &remote_id,
+                "status": &accepted,
+            })
+            .await?;
+        let remote_following = follows
+            .count_documents(doc! {
+                "follower": &local_id,
+                "following": &remote_id,
+                "status": &accepted,
+            })
+            .await?;
+
         let activities: Collection<ActivityDocument> = self.database.collection("activities");
-        let activity_count = activities.count_documents(doc! { "local": true }).await?;
+        let last_activity = activities
+            .find_one(doc! { "actor": &local_id, "local": true })
+            .sort(doc! { "created_at": -1 })
+            .await?
+            .map(|activity| activity.created_at);
+
+        Ok(DomainStats {
+            actors: actor_count,
+            posts: post_count,
+            remote_followers,
+            remote_following,
+            last_activity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_remote_actor_from_activitypub() {
+        let actor = ActorDocument::from_activitypub(&json!({
+            "type": "Person",
+            "id": "https://remote.example/users/alice",
+            "preferredUsername": "alice",
+            "name": "Alice",
+            "summary": "<p>Hi</p>",
+            "inbox": "https://remote.example/users/alice/inbox",
+            "icon": { "type": "Image", "url": "https://remote.example/avatar.png" },
+            "endpoints": { "sharedInbox": "https://remote.example/inbox" },
+            "publicKey": {
+                "id": "https://remote.example/users/alice#main-key",
+                "owner": "https://remote.example/users/alice",
+                "publicKeyPem": "-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----"
+            }
+        }))
+        .unwrap();
+        assert_eq!(actor.domain, "remote.example");
+        assert_eq!(actor.name, "Alice");
+        assert_eq!(
+            actor.icon.as_deref(),
+            Some("https://remote.example/avatar.png")
+        );
+        assert!(!actor.local);
+        let key = actor.public_key.unwrap();
+        assert_eq!(key.id, "https://remote.example/users/alice#main-key");
+        assert!(key.fingerprint.starts_with("sha256:"));
+
+        assert!(
+            ActorDocument::from_activitypub(&json!({ "id": "https://remote.example/users/bob" }))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_profile_flags() {
+        let mut actor = ActorDocument::from_activitypub(&json!({
+            "id": "https://remote.example/users/alice",
+            "inbox": "https://remote.example/users/alice/inbox",
+            "indexable": true
+        }))
+        .unwrap();
+        assert!(!actor.discoverable());
+        assert!(actor.indexable());
+
+        // Local posts are indexed unless the account opts out
+        actor.local = true;
+        actor.additional_properties = Some(doc! { "discoverable": true });
+        assert!(actor.discoverable());
+        assert!(actor.indexable());
+        actor.additional_properties = Some(doc! { "indexable": false });
+        assert!(!actor.indexable());
+    }
 
-        Ok((actor_count, post_count, activity_count))
+    #[test]
+    fn test_revision_keeps_replaced_version() {
+        let mut object = ObjectDocument::from_activitypub(
+            &json!({
+                "id": "https://remote.example/notes/1",
+                "attributedTo": "https://remote.example/users/alice",
+                "content": "first",
+                "published": "2024-01-01T00:00:00Z",
+                "to": ["https://www.w3.org/ns/activitystreams#Public"]
+            }),
+            ObjectType::Note,
+        );
+        let replaced_at = Utc::now();
+        let revision = ObjectRevisionDocument::of(&object, replaced_at);
+        assert_eq!(revision.content.as_deref(), Some("first"));
+        assert_eq!(revision.published, object.published.unwrap());
+
+        // Later edits date the replaced version by its last edit
+        object.updated = Some(replaced_at);
+        let revision = ObjectRevisionDocument::of(&object, Utc::now());
+        assert_eq!(revision.published, replaced_at);
+    }
+
+    #[test]
+    fn test_interaction_count_field() {
+        assert_eq!(
+            interaction_count_field(&ActivityType::Like),
+            Some("like_count")
+        );
+        assert_eq!(interaction_count_field(&ActivityType::Announce), None);
+        assert_eq!(interaction_count_field(&ActivityType::Block), None);
+    }
+
+    #[test]
+    fn test_parse_attachment_list() {
+        let value = json!([
+            {
+                "type": "Document",
+                "mediaType": "image/png",
+                "url": "https://remote.example/media/1.png",
+                "name": "A cat",
+                "width": 640,
+                "height": 480,
+                "blurhash": "UBL_:rOpGG-oBUNG,qRj2so|=eE1w^n4S5NH"
+            },
+            {
+                "type": "Video",
+                "url": [
+                    { "type": "Link", "href": "https://remote.example/media/2.mp4", "mediaType": "video/mp4" }
+                ]
+            },
+            { "type": "PropertyValue", "name": "Website", "value": "https://example.com" }
+        ]);
+
+        let attachments = AttachmentDocument::parse_list(Some(&value)).unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].attachment_type, "Document");
+        assert_eq!(attachments[0].media_type.as_deref(), Some("image/png"));
+        assert_eq!(attachments[0].name.as_deref(), Some("A cat"));
+        assert_eq!(attachments[0].width, Some(640));
+        assert_eq!(attachments[1].url, "https://remote.example/media/2.mp4");
+        assert_eq!(attachments[1].media_type.as_deref(), Some("video/mp4"));
+    }
+
+    #[test]
+    fn test_parse_attachment_single_and_empty() {
+        let single = json!({ "type": "Image", "url": "https://remote.example/a.jpg" });
+        let attachments = AttachmentDocument::parse_list(Some(&single)).unwrap();
+        assert_eq!(attachments.len(), 1);
+
+        assert!(AttachmentDocument::parse_list(None).is_none());
+        assert!(AttachmentDocument::parse_list(Some(&json!([]))).is_none());
+        assert!(AttachmentDocument::parse_list(Some(&json!([{ "type": "Image" }]))).is_none());
+    }
+
+    #[test]
+    fn test_object_document_language_maps() {
+        let object = json!({
+            "id": "https://remote.example/notes/1",
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/bob",
+            "contentMap": { "de": "<p>Hallo</p>" },
+            "summaryMap": { "de": "Gruß", "en": "Greeting" }
+        });
+
+        let doc = ObjectDocument::from_activitypub(&object, ObjectType::Note);
+        assert_eq!(doc.content.as_deref(), Some("<p>Hallo</p>"));
+        assert_eq!(doc.language.as_deref(), Some("de"));
+        assert_eq!(doc.summary_map.map(|map| map.len()), Some(2));
+        assert!(doc.name_map.is_none());
+    }
+
+    #[test]
+    fn test_object_status_defaults_to_published() {
+        let object = json!({
+            "id": "https://local.example/notes/1",
+            "type": "Note",
+            "attributedTo": "https://local.example/users/alice",
+            "content": "<p>Hello</p>"
+        });
+        let doc = ObjectDocument::from_activitypub(&object, ObjectType::Note);
+        assert_eq!(doc.status, ObjectStatus::Published);
+
+        // Objects stored before scheduling existed have no status
+        let mut bson = mongodb::bson::to_document(&doc).unwrap();
+        assert_eq!(bson.get_str("status").unwrap(), "published");
+        bson.remove("status");
+        let stored: ObjectDocument = mongodb::bson::from_document(bson).unwrap();
+        assert_eq!(stored.status, ObjectStatus::Published);
+
+        let rendered = stored.to_activitypub();
+        assert_eq!(rendered["id"], object["id"]);
+        assert_eq!(rendered["content"], object["content"]);
+
+        let tombstone = stored.tombstone(Utc::now());
+        assert_eq!(tombstone["type"], "Tombstone");
+        assert_eq!(tombstone["id"], object["id"]);
+        assert_eq!(tombstone["formerType"], "Note");
+    }
+
+    #[test]
+    fn test_visibility_from_addressing() {
+        let visibility = |to: serde_json::Value, cc: serde_json::Value| {
+            let object = json!({
+                "id": "https://remote.example/notes/1",
+                "type": "Note",
+                "to": to,
+                "cc": cc
+            });
+            ObjectDocument::from_activitypub(&object, ObjectType::Note).visibility
+        };
+        let followers = "https://remote.example/users/bob/followers";
+
+        assert_eq!(
+            visibility(
+                json!(["https://www.w3.org/ns/activitystreams#Public"]),
+                json!([followers])
+            ),
+            VisibilityLevel::Public
+        );
+        assert_eq!(
+            visibility(json!([followers]), json!(["as:Public"])),
+            VisibilityLevel::Unlisted
+        );
+        assert_eq!(
+            visibility(json!(followers), json!([])),
+            VisibilityLevel::Followers
+        );
+
+        let direct = json!({
+            "id": "https://remote.example/notes/2",
+            "type": "Note",
+            "to": ["https://local.example/users/alice"],
+            "bcc": ["https://local.example/users/carol"]
+        });
+        let doc = ObjectDocument::from_activitypub(&direct, ObjectType::Note);
+        assert_eq!(doc.visibility, VisibilityLevel::Direct);
+        assert!(doc.is_addressed_to("https://local.example/users/carol"));
+        assert!(!doc.is_addressed_to("https://local.example/users/dave"));
+    }
+
+    #[test]
+    fn test_attachment_roundtrip() {
+        let value = json!({
+            "type": "Image",
+            "mediaType": "image/jpeg",
+            "url": "https://remote.example/a.jpg",
+            "name": "Sunset"
+        });
+
+        let attachment = AttachmentDocument::from_activitypub(&value).unwrap();
+        let rendered = attachment.to_activitypub();
+        assert_eq!(rendered, value);
+    }
+
+    #[test]
+    fn test_attachment_duration() {
+        let value = json!({
+            "type": "Audio",
+            "url": "https://remote.example/a.ogg",
+            "duration": "PT2M5S"
+        });
+
+        let attachment = AttachmentDocument::from_activitypub(&value).unwrap();
+        assert_eq!(attachment.duration.as_deref(), Some("PT2M5S"));
+        assert_eq!(attachment.to_activitypub(), value);
+
+        // Older versions stored the duration in seconds
+        let stored = doc! { "attachment_type": "Audio", "url": "https://remote.example/a.ogg", "duration": 125 };
+        let attachment: AttachmentDocument = mongodb::bson::from_document(stored).unwrap();
+        assert_eq!(attachment.duration.as_deref(), Some("PT125S"));
+    }
+
+    #[test]
+    fn test_outbox_message_starts_pending() {
+        let message = OutboxMessageDocument::new(
+            "oxifed.activitypub.publish",
+            "",
+            Some("application/activity+json"),
+            "{}".to_string(),
+        );
+
+        assert_eq!(message.status, OutboxStatus::Pending);
+        assert_eq!(message.attempts, 0);
+        assert!(message.claimed_until.is_none());
+        assert!(message.purge_at.is_none());
+
+        let bson = mongodb::bson::to_document(&message).unwrap();
+        assert_eq!(bson.get_str("status").unwrap(), "pending");
+        assert!(!bson.contains_key("_id"));
+    }
+
+    #[test]
+    fn test_audit_entry_keeps_json_values() {
+        let entry = AuditLogDocument::from(AuditEventMessage {
+            actor: "alice".to_string(),
+            role: Some("admin".to_string()),
+            action: "domain.update".to_string(),
+            method: "PUT".to_string(),
+            path: "/api/v1/domains/example.com".to_string(),
+            target: Some("example.com".to_string()),
+            status: 202,
+            before: Some(json!({"name": "Example", "max_note_length": 500})),
+            after: Some(json!({"name": "Example", "max_note_length": [1000, null]})),
+            changes: vec!["max_note_length".to_string()],
+            timestamp: Utc::now(),
+        });
+
+        let bson = mongodb::bson::to_document(&entry).unwrap();
+        assert!(!bson.contains_key("_id"));
+        let parsed: AuditLogDocument = mongodb::bson::from_document(bson).unwrap();
+        assert_eq!(parsed.before, entry.before);
+        assert_eq!(parsed.after, entry.after);
+        assert_eq!(parsed.status, 202);
+    }
+
+    #[test]
+    fn test_index_registry_is_consistent() {
+        let registry = index_registry();
+
+        let mut names = std::collections::HashSet::new();
+        for spec in &registry {
+            assert!(
+                names.insert((spec.collection, spec.name())),
+                "duplicate index {} on {}",
+                spec.name(),
+                spec.collection
+            );
+            if spec.expire_after.is_some() {
+                assert_eq!(
+                    spec.keys.len(),
+                    1,
+                    "TTL index {} has several keys",
+                    spec.name()
+                );
+            }
+        }
+
+        // MongoDB allows a single text index per collection
+        let mut text_collections = std::collections::HashSet::new();
+        for spec in &registry {
+            if spec.keys.values().any(|v| v.as_str() == Some("text")) {
+                assert!(text_collections.insert(spec.collection));
+            }
+        }
+
+        for (collection, field) in [
+            ("activities", "activity_id"),
+            ("objects", "object_id"),
+            ("actors", "actor_id"),
+            ("access_tokens", "token_hash"),
+        ] {
+            assert!(
+                registry.iter().any(|spec| spec.collection == collection
+                    && spec.unique
+                    && spec.keys == doc! { field: 1 }),
+                "missing unique index on {}.{}",
+                collection,
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn test_obsolete_documents_cover_unique_indexes() {
+        // Documents missing the key of a unique index collide on null, so
+        // legacy documents without it must be deleted first
+        let registry = index_registry();
+        for (collection, filter) in obsolete_documents() {
+            let field = filter.keys().next().unwrap();
+            assert_eq!(
+                filter.get_document(field).unwrap(),
+                &doc! { "$exists": false }
+            );
+            assert!(registry.iter().any(|spec| spec.collection == collection
+                && spec.unique
+                && spec.keys.contains_key(field)));
+        }
+        assert!(
+            obsolete_documents()
+                .iter()
+                .any(|(collection, _)| *collection == "access_tokens")
+        );
     }
 }
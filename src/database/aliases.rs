@@ -0,0 +1,142 @@
+//! Actor aliases
+//!
+//! An actor's `alsoKnownAs` names its other identities: remote accounts it
+//! moves from, and handles on other local domains bound to it. A bound
+//! handle `user@domain` has the actor URL `https://{domain}/users/{user}`,
+//! which is redirected to the canonical actor, and its WebFinger resources
+//! are aliases in the canonical actor's WebFinger profile, so renamed
+//! domains keep resolving.
+
+use mongodb::Collection;
+use mongodb::bson::doc;
+use tracing::instrument;
+
+use super::{ActorDocument, DatabaseError, DatabaseManager};
+use crate::webfinger::JrdResource;
+
+/// Actor URL of a handle bound to a local actor
+pub fn alias_actor_url(username: &str, domain: &str) -> String {
+    format!("https://{}/users/{}", domain, username)
+}
+
+/// WebFinger resources of a handle bound to a local actor: the handle, its
+/// actor URL and its profile URL
+pub fn alias_resources(username: &str, domain: &str) -> Vec<String> {
+    vec![
+        format!("acct:{}@{}", username, domain),
+        alias_actor_url(username, domain),
+        format!("https://{}/@{}", domain, username),
+    ]
+}
+
+impl ActorDocument {
+    /// Other identities of the actor, its `alsoKnownAs`
+    pub fn also_known_as(&self) -> Vec<String> {
+        self.additional_properties
+            .as_ref()
+            .and_then(|props| props.get_array("alsoKnownAs").ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|alias| alias.as_str().map(String::from))
+            .collect()
+    }
+}
+
+impl DatabaseManager {
+    /// Local actor an actor URL is bound to as an alias
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_actor_by_alias(
+        &self,
+        alias: &str,
+    ) -> Result<Option<ActorDocument>, DatabaseError> {
+        let collection: Collection<ActorDocument> = self.database.collection("actors");
+        Ok(collection
+            .find_one(doc! { "local": true, "additional_properties.alsoKnownAs": alias })
+            .await?)
+    }
+
+    /// Replace the `alsoKnownAs` of an actor
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn set_also_known_as(
+        &self,
+        actor: &ActorDocument,
+        aliases: &[String],
+    ) -> Result<(), DatabaseError> {
+        let mut properties = actor.additional_properties.clone().unwrap_or_default();
+        if aliases.is_empty() {
+            properties.remove("alsoKnownAs");
+        } else {
+            properties.insert("alsoKnownAs", aliases.to_vec());
+        }
+        self.update_actor(
+            &actor.actor_id,
+            doc! { "additional_properties": properties },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// WebFinger profile whose subject or one of whose aliases is `resource`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_webfinger_profile(
+        &self,
+        resource: &str,
+    ) -> Result<Option<JrdResource>, DatabaseError> {
+        let collection: Collection<JrdResource> = self.database.collection("webfinger_profiles");
+        if let Some(profile) = collection.find_one(doc! { "subject": resource }).await? {
+            return Ok(Some(profile));
+        }
+        Ok(collection.find_one(doc! { "aliases": resource }).await?)
+    }
+
+    /// Add aliases to the WebFinger profile of `subject`, or remove them
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_webfinger_aliases(
+        &self,
+        subject: &str,
+        aliases: &[String],
+        remove: bool,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<JrdResource> = self.database.collection("webfinger_profiles");
+        let update = if remove {
+            doc! { "$pull": { "aliases": { "$in": aliases } } }
+        } else {
+            doc! { "$addToSet": { "aliases": { "$each": aliases } } }
+        };
+        collection
+            .update_one(doc! { "subject": subject }, update)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_also_known_as() {
+        let actor = ActorDocument::from_activitypub(&serde_json::json!({
+            "id": "https://example.com/users/alice",
+            "inbox": "https://example.com/users/alice/inbox"
+        }))
+        .unwrap();
+        assert!(actor.also_known_as().is_empty());
+
+        let actor = ActorDocument {
+            additional_properties: Some(doc! {
+                "alsoKnownAs": ["https://old.example/users/alice", 1]
+            }),
+            ..actor
+        };
+        assert_eq!(actor.also_known_as(), ["https://old.example/users/alice"]);
+        assert_eq!(
+            alias_resources("alice", "old.example"),
+            [
+                "acct:alice@old.example",
+                "https://old.example/users/alice",
+                "https://old.example/@alice"
+            ]
+        );
+    }
+}
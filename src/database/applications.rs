@@ -0,0 +1,173 @@
+//! Application profiles
+//!
+//! Accounts are used with one application: a microblog, a blog or a
+//! gallery. The profile decides which posts the account creates through
+//! C2S and how their content is formatted unless the client says otherwise,
+//! and the actor document advertises the collections of the application.
+//! Accounts choose it with an `application_profile` property; it defaults
+//! to `microblog`, which allows all posts.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::ActorDocument;
+use crate::ObjectType;
+
+/// Application an account is used with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplicationProfile {
+    /// Short notes of all kinds
+    #[default]
+    Microblog,
+    /// Articles; notes only answer comments
+    Blog,
+    /// Pictures and videos with captions
+    Gallery,
+}
+
+impl ApplicationProfile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApplicationProfile::Microblog => "microblog",
+            ApplicationProfile::Blog => "blog",
+            ApplicationProfile::Gallery => "gallery",
+        }
+    }
+
+    /// Whether the C2S endpoint creating `object_type`s, `/notes` or
+    /// `/articles`, is enabled
+    pub fn creates(&self, object_type: &ObjectType) -> bool {
+        !matches!(
+            (self, object_type),
+            (ApplicationProfile::Blog, ObjectType::Note)
+                | (ApplicationProfile::Gallery, ObjectType::Article)
+        )
+    }
+
+    /// Check a post created through the C2S outbox
+    ///
+    /// Blogs post notes only as replies, and galleries no articles and no
+    /// posts without media apart from replies.
+    pub fn check_post(&self, object: &Value) -> Result<(), String> {
+        let object_type = object.get("type").and_then(Value::as_str);
+        let is_reply = object
+            .get("inReplyTo")
+            .is_some_and(|reply| !reply.is_null());
+        match (self, object_type) {
+            (ApplicationProfile::Blog, Some("Note")) if !is_reply => {
+                Err("Blogs only post notes as replies".to_string())
+            }
+            (ApplicationProfile::Gallery, Some("Article")) => {
+                Err("Galleries do not post articles".to_string())
+            }
+            (ApplicationProfile::Gallery, _) if !is_reply && !has_media(object) => {
+                Err("Gallery posts need a picture or video".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Media type of C2S content without a `mediaType`
+    ///
+    /// Gallery captions are plain text; microblogs and blogs post HTML.
+    pub fn default_media_type(&self) -> &'static str {
+        match self {
+            ApplicationProfile::Microblog | ApplicationProfile::Blog => "text/html",
+            ApplicationProfile::Gallery => "text/plain",
+        }
+    }
+}
+
+impl FromStr for ApplicationProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "microblog" => Ok(ApplicationProfile::Microblog),
+            "blog" => Ok(ApplicationProfile::Blog),
+            "gallery" => Ok(ApplicationProfile::Gallery),
+            _ => Err(format!("Unknown application profile: {}", s)),
+        }
+    }
+}
+
+/// Whether a post has an image or video attached
+fn has_media(object: &Value) -> bool {
+    let attachments = match object.get("attachment") {
+        Some(Value::Array(attachments)) => attachments.iter().collect(),
+        Some(attachment) if attachment.is_object() => vec![attachment],
+        _ => Vec::new(),
+    };
+    attachments.into_iter().any(|attachment| {
+        let media_type = attachment.get("mediaType").and_then(Value::as_str);
+        let kind = attachment.get("type").and_then(Value::as_str);
+        media_type.is_some_and(|t| t.starts_with("image/") || t.starts_with("video/"))
+            || matches!(kind, Some("Image" | "Video"))
+    })
+}
+
+impl ActorDocument {
+    /// Application profile of the account
+    pub fn application_profile(&self) -> ApplicationProfile {
+        self.additional_properties
+            .as_ref()
+            .and_then(|props| props.get_str("application_profile").ok())
+            .and_then(|profile| profile.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_post() {
+        let note = json!({ "type": "Note", "content": "Hello" });
+        let reply = json!({ "type": "Note", "inReplyTo": "https://example.com/objects/1" });
+        let article = json!({ "type": "Article", "name": "Hello" });
+        let picture = json!({
+            "type": "Note",
+            "attachment": [{ "type": "Document", "mediaType": "image/png", "url": "https://example.com/a.png" }]
+        });
+
+        let microblog = ApplicationProfile::Microblog;
+        for post in [&note, &reply, &article, &picture] {
+            assert!(microblog.check_post(post).is_ok());
+        }
+
+        let blog = ApplicationProfile::Blog;
+        assert!(blog.check_post(&note).is_err());
+        assert!(blog.check_post(&reply).is_ok());
+        assert!(blog.check_post(&article).is_ok());
+        assert!(!blog.creates(&ObjectType::Note));
+
+        let gallery = ApplicationProfile::Gallery;
+        assert!(gallery.check_post(&note).is_err());
+        assert!(gallery.check_post(&reply).is_ok());
+        assert!(gallery.check_post(&article).is_err());
+        assert!(gallery.check_post(&picture).is_ok());
+        assert!(!gallery.creates(&ObjectType::Article));
+        assert!(gallery.creates(&ObjectType::Note));
+    }
+
+    #[test]
+    fn test_application_profile_property() {
+        let mut actor = ActorDocument::from_activitypub(&json!({
+            "id": "https://example.com/users/alice",
+            "inbox": "https://example.com/users/alice/inbox"
+        }))
+        .unwrap();
+        assert_eq!(actor.application_profile(), ApplicationProfile::Microblog);
+
+        actor.additional_properties = Some(mongodb::bson::doc! { "application_profile": "blog" });
+        assert_eq!(actor.application_profile(), ApplicationProfile::Blog);
+
+        actor.additional_properties = Some(mongodb::bson::doc! { "application_profile": "vlog" });
+        assert_eq!(actor.application_profile(), ApplicationProfile::Microblog);
+    }
+}
@@ -0,0 +1,214 @@
+//! Articles
+//!
+//! Local articles are published at a human-readable URL below their
+//! author, `{actor}/articles/{slug}`, which is also their `url`. The slug is
+//! derived from the title unless the author picks one, and is unique among
+//! the author's articles. Articles may have a cover `image`, and are listed
+//! per author, newest first, and per hashtag for tag archives.
+
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc};
+use tracing::instrument;
+
+use super::{AttachmentDocument, DatabaseError, DatabaseManager, ObjectDocument};
+
+/// Longest slug, in characters
+const MAX_SLUG_LENGTH: usize = 80;
+
+/// Slugs that name other resources below an author's articles
+const RESERVED_SLUGS: &[&str] = &["tagged"];
+
+/// URL of the article index of an actor
+pub fn articles_url(actor_id: &str) -> String {
+    format!("{}/articles", actor_id)
+}
+
+/// Human-readable URL of an actor's article
+pub fn article_url(actor_id: &str, slug: &str) -> String {
+    format!("{}/{}", articles_url(actor_id), slug)
+}
+
+/// Slug of a title: lowercase ASCII letters and digits, words joined by
+/// dashes
+///
+/// Titles without any of them get the slug `article`.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            if slug.len() >= MAX_SLUG_LENGTH {
+                break;
+            }
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "article".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+impl AttachmentDocument {
+    /// Cover image of an object, an `Image` or the URL of one
+    pub fn parse_image(value: Option<&serde_json::Value>) -> Option<Self> {
+        match value? {
+            serde_json::Value::String(url) => Some(Self {
+                attachment_type: "Image".to_string(),
+                url: url.clone(),
+                media_type: None,
+                name: None,
+                width: None,
+                height: None,
+                duration: None,
+                blurhash: None,
+            }),
+            serde_json::Value::Array(images) => images
+                .first()
+                .and_then(|image| Self::parse_image(Some(image))),
+            image => Self::from_activitypub(image),
+        }
+    }
+}
+
+impl ObjectDocument {
+    /// Slug of a local article, from its human-readable URL
+    pub fn article_slug(&self) -> Option<&str> {
+        self.url
+            .as_deref()?
+            .strip_prefix(&articles_url(&self.attributed_to))?
+            .strip_prefix('/')
+            .filter(|slug| !slug.is_empty() && !slug.contains('/'))
+    }
+}
+
+impl DatabaseManager {
+    /// Slug for a new article of `author`, made from `title`
+    ///
+    /// Slugs taken by another article of the author get a number appended.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn unique_slug(&self, author: &str, title: &str) -> Result<String, DatabaseError> {
+        let base = slugify(title);
+        let mut slug = base.clone();
+        for n in 2.. {
+            if !RESERVED_SLUGS.contains(&slug.as_str())
+                && self.find_article_by_slug(author, &slug).await?.is_none()
+            {
+                break;
+            }
+            slug = format!("{}-{}", base, n);
+        }
+        Ok(slug)
+    }
+
+    /// Published article of `author` with `slug`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_article_by_slug(
+        &self,
+        author: &str,
+        slug: &str,
+    ) -> Result<Option<ObjectDocument>, DatabaseError> {
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        Ok(collection
+            .find_one(doc! {
+                "attributed_to": author,
+                "object_type": "Article",
+                "url": article_url(author, slug),
+            })
+            .await?)
+    }
+
+    /// Public and unlisted articles of `author`, newest first, with the
+    /// number of them
+    ///
+    /// With a `hashtag`, only articles tagged with it are listed.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_articles(
+        &self,
+        author: &str,
+        hashtag: Option<&str>,
+        limit: i64,
+        offset: u64,
+    ) -> Result<(Vec<ObjectDocument>, u64), DatabaseError> {
+        let mut filter = doc! {
+            "attributed_to": author,
+            "object_type": "Article",
+            "visibility": { "$in": ["public", "unlisted"] },
+            "status": { "$ne": "scheduled" },
+        };
+        if let Some(hashtag) = hashtag {
+            filter.insert("tag", hashtag_match(hashtag));
+        }
+
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let total = collection.count_documents(filter.clone()).await?;
+        let articles = collection
+            .find(filter)
+            .sort(doc! { "published": -1 })
+            .skip(offset)
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok((articles, total))
+    }
+}
+
+/// Filter on `tag` matching a hashtag regardless of case and `#`
+fn hashtag_match(hashtag: &str) -> Document {
+    let name = super::normalize_hashtag(hashtag);
+    doc! { "$elemMatch": {
+        "tag_type": "Hashtag",
+        "name": { "$regex": format!("^#?{}$", regex::escape(&name)), "$options": "i" },
+    } }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  Rust 2024: what's new?  "),
+            "rust-2024-what-s-new"
+        );
+        assert_eq!(slugify("Ünïcödé"), "n-c-d");
+        assert_eq!(slugify("日本語"), "article");
+        assert_eq!(slugify(&"a".repeat(200)).len(), MAX_SLUG_LENGTH);
+    }
+
+    #[test]
+    fn test_article_slug() {
+        let article = ObjectDocument::from_activitypub(
+            &serde_json::json!({
+                "type": "Article",
+                "id": "https://example.com/objects/1",
+                "attributedTo": "https://example.com/users/alice",
+                "url": "https://example.com/users/alice/articles/hello-world",
+                "image": "https://example.com/media/cover.png"
+            }),
+            crate::ObjectType::Article,
+        );
+        assert_eq!(article.article_slug(), Some("hello-world"));
+        assert_eq!(
+            article.image.as_ref().map(|image| image.url.as_str()),
+            Some("https://example.com/media/cover.png")
+        );
+        assert_eq!(
+            article.to_activitypub()["image"]["url"],
+            "https://example.com/media/cover.png"
+        );
+
+        let elsewhere = ObjectDocument {
+            url: Some("https://example.com/@alice/1".to_string()),
+            ..article
+        };
+        assert_eq!(elsewhere.article_slug(), None);
+    }
+}
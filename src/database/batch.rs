@@ -0,0 +1,333 @@
+//! Batched inserts for high-volume writes
+//!
+//! Busy relays receive many activities and posts at once. Instead of one
+//! round trip per document, a [`WriteBatcher`] collects the documents
+//! written concurrently and inserts them with a single unordered
+//! `insert_many` once the batch is full or the flush interval has passed,
+//! whichever comes first. [`WriteBatcher::write`] returns when the batch
+//! holding its document has been written, so callers only acknowledge
+//! messages that are stored.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use mongodb::Collection;
+use mongodb::error::ErrorKind as MongoErrorKind;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+use super::{
+    ActivityDocument, DatabaseError, DatabaseManager, MongoError, ObjectDocument, VisibilityLevel,
+};
+use crate::config::{ConfigError, Env, require_positive};
+
+/// Server error code of a unique index violation
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+/// Result of an unordered batch insert
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BatchInsert {
+    /// Documents written
+    pub inserted: usize,
+    /// Documents skipped because one with the same ID is already stored, by
+    /// position in the batch
+    pub duplicates: Vec<usize>,
+    /// Documents the server rejected, by position in the batch
+    pub rejected: Vec<(usize, String)>,
+}
+
+/// Size and interval at which batched writes are flushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WriteBatchConfig {
+    /// Documents written in one `insert_many`
+    pub max_batch_size: usize,
+    /// Longest time a document waits for its batch to fill, in milliseconds
+    pub flush_interval_ms: u64,
+}
+
+impl Default for WriteBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 100,
+            flush_interval_ms: 20,
+        }
+    }
+}
+
+impl WriteBatchConfig {
+    /// Apply `<PREFIX>_BATCH_SIZE` and `<PREFIX>_BATCH_INTERVAL_MS`
+    pub fn apply_env(&mut self, prefix: &str, env: &Env) -> Result<(), ConfigError> {
+        env.set(&format!("{}_BATCH_SIZE", prefix), &mut self.max_batch_size)?;
+        env.set(
+            &format!("{}_BATCH_INTERVAL_MS", prefix),
+            &mut self.flush_interval_ms,
+        )
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.max_batch_size", key), self.max_batch_size)
+    }
+}
+
+impl DatabaseManager {
+    /// Insert activities in one unordered batch
+    ///
+    /// Activities that are already stored are counted as duplicates.
+    pub async fn insert_activities(
+        &self,
+        activities: Vec<ActivityDocument>,
+    ) -> Result<BatchInsert, DatabaseError> {
+        let collection: Collection<ActivityDocument> = self.database.collection("activities");
+        insert_unordered(&collection, activities).await
+    }
+
+    /// Insert objects in one unordered batch
+    ///
+    /// Direct objects are inserted one by one, as each needs its
+    /// conversation looked up. Objects that are already stored are counted
+    /// as duplicates.
+    pub async fn insert_objects(
+        &self,
+        objects: Vec<ObjectDocument>,
+    ) -> Result<BatchInsert, DatabaseError> {
+        let mut outcome = BatchInsert::default();
+        let mut positions = Vec::new();
+        let mut batch = Vec::new();
+        for (position, object) in objects.into_iter().enumerate() {
+            if object.visibility != VisibilityLevel::Direct {
+                positions.push(position);
+                batch.push(object);
+                continue;
+            }
+            match self.insert_object(object).await {
+                Ok(_) => outcome.inserted += 1,
+                Err(DatabaseError::MongoError(e)) if is_duplicate_key(&e) => {
+                    outcome.duplicates.push(position)
+                }
+                Err(e) => outcome.rejected.push((position, e.to_string())),
+            }
+        }
+
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let batched = insert_unordered(&collection, batch).await?;
+        outcome.inserted += batched.inserted;
+        outcome
+            .duplicates
+            .extend(batched.duplicates.into_iter().map(|index| positions[index]));
+        outcome.rejected.extend(
+            batched
+                .rejected
+                .into_iter()
+                .map(|(index, error)| (positions[index], error)),
+        );
+        Ok(outcome)
+    }
+}
+
+/// Insert documents without stopping at the first rejected one
+pub(super) async fn insert_unordered<T>(
+    collection: &Collection<T>,
+    documents: Vec<T>,
+) -> Result<BatchInsert, DatabaseError>
+where
+    T: Serialize + Send + Sync,
+{
+    let total = documents.len();
+    if total == 0 {
+        return Ok(BatchInsert::default());
+    }
+
+    let e = match collection.insert_many(documents).ordered(false).await {
+        Ok(result) => {
+            return Ok(BatchInsert {
+                inserted: result.inserted_ids.len(),
+                ..BatchInsert::default()
+            });
+        }
+        Err(e) => e,
+    };
+    let MongoErrorKind::InsertMany(failure) = e.kind.as_ref() else {
+        return Err(e.into());
+    };
+    // Without write concern the documents may not be durable
+    if failure.write_concern_error.is_some() {
+        return Err(e.into());
+    }
+
+    let mut outcome = BatchInsert::default();
+    for error in failure.write_errors.iter().flatten() {
+        if error.code == DUPLICATE_KEY_CODE {
+            outcome.duplicates.push(error.index);
+        } else {
+            outcome.rejected.push((error.index, error.message.clone()));
+        }
+    }
+    outcome.inserted = total - outcome.duplicates.len() - outcome.rejected.len();
+    Ok(outcome)
+}
+
+/// Whether an insert or upsert failed because the document is already stored
+///
+/// Inserts report the conflict as a write error, `findAndModify` upserts
+/// as a command error.
+pub(super) fn is_duplicate_key(error: &MongoError) -> bool {
+    match error.kind.as_ref() {
+        MongoErrorKind::Write(mongodb::error::WriteFailure::WriteError(e)) => {
+            e.code == DUPLICATE_KEY_CODE
+        }
+        MongoErrorKind::Command(e) => e.code == DUPLICATE_KEY_CODE,
+        _ => false,
+    }
+}
+
+/// A document type that can be inserted in batches
+pub trait BatchDocument: Send + Sized + 'static {
+    fn insert_batch(
+        db: &DatabaseManager,
+        batch: Vec<Self>,
+    ) -> impl Future<Output = Result<BatchInsert, DatabaseError>> + Send;
+}
+
+impl BatchDocument for ActivityDocument {
+    fn insert_batch(
+        db: &DatabaseManager,
+        batch: Vec<Self>,
+    ) -> impl Future<Output = Result<BatchInsert, DatabaseError>> + Send {
+        db.insert_activities(batch)
+    }
+}
+
+impl BatchDocument for ObjectDocument {
+    fn insert_batch(
+        db: &DatabaseManager,
+        batch: Vec<Self>,
+    ) -> impl Future<Output = Result<BatchInsert, DatabaseError>> + Send {
+        db.insert_objects(batch)
+    }
+}
+
+type PendingWrite<T> = (T, oneshot::Sender<Result<bool, DatabaseError>>);
+
+/// Collects concurrent inserts of one document type into batches
+///
+/// Cloning shares the batch. The background task ends once every clone is
+/// dropped, after writing what is still queued.
+#[derive(Debug)]
+pub struct WriteBatcher<T> {
+    sender: mpsc::Sender<PendingWrite<T>>,
+}
+
+impl<T> Clone for WriteBatcher<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T: BatchDocument> WriteBatcher<T> {
+    /// Start the task writing batches
+    pub fn spawn(db: Arc<DatabaseManager>, config: WriteBatchConfig) -> Self {
+        let max_batch_size = config.max_batch_size.max(1);
+        let (sender, receiver) = mpsc::channel(max_batch_size);
+        tokio::spawn(run_batches(
+            db,
+            receiver,
+            max_batch_size,
+            Duration::from_millis(config.flush_interval_ms),
+        ));
+        Self { sender }
+    }
+
+    /// Queue a document and wait until its batch has been written
+    ///
+    /// Returns `false` if the document was already stored.
+    pub async fn write(&self, document: T) -> Result<bool, DatabaseError> {
+        let stopped = || DatabaseError::OperationError("batch writer stopped".to_string());
+        let (done, written) = oneshot::channel();
+        self.sender
+            .send((document, done))
+            .await
+            .map_err(|_| stopped())?;
+        written.await.map_err(|_| stopped())?
+    }
+}
+
+async fn run_batches<T: BatchDocument>(
+    db: Arc<DatabaseManager>,
+    mut receiver: mpsc::Receiver<PendingWrite<T>>,
+    max_batch_size: usize,
+    flush_interval: Duration,
+) {
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = tokio::time::sleep(flush_interval);
+        tokio::pin!(deadline);
+        while batch.len() < max_batch_size {
+            tokio::select! {
+                pending = receiver.recv() => match pending {
+                    Some(pending) => batch.push(pending),
+                    None => break,
+                },
+                () = &mut deadline => break,
+            }
+        }
+        flush(&db, batch).await;
+    }
+}
+
+/// Write a batch and tell every waiting writer how its document fared
+async fn flush<T: BatchDocument>(db: &DatabaseManager, batch: Vec<PendingWrite<T>>) {
+    let (documents, waiters): (Vec<T>, Vec<_>) = batch.into_iter().unzip();
+    let size = documents.len();
+    match T::insert_batch(db, documents).await {
+        Ok(outcome) => {
+            debug!(
+                "Wrote batch of {}: {} inserted, {} already stored, {} rejected",
+                size,
+                outcome.inserted,
+                outcome.duplicates.len(),
+                outcome.rejected.len()
+            );
+            let mut rejected: HashMap<usize, String> = outcome.rejected.into_iter().collect();
+            for (position, waiter) in waiters.into_iter().enumerate() {
+                let result = match rejected.remove(&position) {
+                    Some(error) => Err(DatabaseError::ConstraintError(error)),
+                    None => Ok(!outcome.duplicates.contains(&position)),
+                };
+                let _ = waiter.send(result);
+            }
+        }
+        Err(e) => {
+            warn!("Batch write of {} documents failed: {}", size, e);
+            let error = e.to_string();
+            for waiter in waiters {
+                let _ = waiter.send(Err(DatabaseError::OperationError(error.clone())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_config_env() {
+        let env = Env::from_vars([
+            ("WRITE_BATCH_SIZE", "500"),
+            ("WRITE_BATCH_INTERVAL_MS", "5"),
+        ]);
+        let mut config = WriteBatchConfig::default();
+        config.apply_env("WRITE", &env).unwrap();
+        assert_eq!(config.max_batch_size, 500);
+        assert_eq!(config.flush_interval_ms, 5);
+
+        config.max_batch_size = 0;
+        assert!(config.validate("write_batch").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Boosts of posts
+//!
+//! An `Announce` of a post is recorded once per actor and post in the
+//! `boosts` collection, which keeps the post's `announce_count`. Followers
+//! of the booster see the boost in their home timeline, attributed to the
+//! booster, for as long as the boosted post is stored and public.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::batch::is_duplicate_key;
+use super::{DatabaseError, DatabaseManager, ObjectDocument, TimelinePage, VisibilityLevel};
+use crate::ActivityType;
+
+/// An actor's boost of a post
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoostDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// ID of the `Announce` activity
+    pub activity_id: String,
+    /// Actor boosting the post
+    pub actor: String,
+    /// ActivityPub ID of the boosted post
+    pub object_id: String,
+
+    /// Public or unlisted following the addressing of the `Announce`;
+    /// followers-only otherwise
+    pub visibility: VisibilityLevel,
+
+    pub published: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BoostDocument {
+    /// Boost recorded by an `Announce` activity, if it has an ID, an actor
+    /// and an object
+    pub fn from_activitypub(activity: &serde_json::Value) -> Option<Self> {
+        let activity_id = activity.get("id")?.as_str()?.to_string();
+        let actor = activity.get("actor")?.as_str()?.to_string();
+        let object = activity.get("object")?;
+        let object_id = object
+            .as_str()
+            .or_else(|| object.get("id")?.as_str())?
+            .to_string();
+
+        let addressed = |key: &str| {
+            super::json_string_array(activity.get(key))
+                .unwrap_or_default()
+                .iter()
+                .any(|recipient| {
+                    matches!(
+                        recipient.as_str(),
+                        "https://www.w3.org/ns/activitystreams#Public" | "as:Public" | "Public"
+                    )
+                })
+        };
+        let visibility = if addressed("to") {
+            VisibilityLevel::Public
+        } else if addressed("cc") {
+            VisibilityLevel::Unlisted
+        } else {
+            VisibilityLevel::Followers
+        };
+
+        let now = Utc::now();
+        Some(Self {
+            id: None,
+            activity_id,
+            actor,
+            object_id,
+            visibility,
+            published: super::json_datetime(activity, "published").unwrap_or(now),
+            created_at: now,
+        })
+    }
+}
+
+/// Entry of a timeline: a post, or a boost of one
+#[derive(Debug, Clone)]
+pub enum TimelineItem {
+    Post(ObjectDocument),
+    Boost {
+        boost: BoostDocument,
+        post: ObjectDocument,
+    },
+}
+
+impl TimelineItem {
+    /// Storage ID the timeline is paged by
+    pub fn id(&self) -> Option<ObjectId> {
+        match self {
+            Self::Post(post) => post.id,
+            Self::Boost { boost, .. } => boost.id,
+        }
+    }
+
+    /// The post shown
+    pub fn post(&self) -> &ObjectDocument {
+        match self {
+            Self::Post(post) | Self::Boost { post, .. } => post,
+        }
+    }
+}
+
+impl DatabaseManager {
+    /// Record a boost and count it on the boosted post
+    ///
+    /// Returns `false` if the actor already boosted the post.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_boost(&self, boost: &BoostDocument) -> Result<bool, DatabaseError> {
+        let collection: Collection<BoostDocument> = self.database.collection("boosts");
+        match collection.insert_one(boost).await {
+            Ok(_) => {}
+            Err(e) if is_duplicate_key(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        self.database
+            .collection::<ObjectDocument>("objects")
+            .update_one(
+                doc! { "object_id": &boost.object_id },
+                doc! { "$inc": { "announce_count": 1 } },
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Remove an actor's boost of a post and uncount it
+    ///
+    /// Returns whether the actor had boosted the post.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn remove_boost(&self, actor: &str, object_id: &str) -> Result<bool, DatabaseError> {
+        let collection: Collection<BoostDocument> = self.database.collection("boosts");
+        let removed = collection
+            .delete_one(doc! { "actor": actor, "object_id": object_id })
+            .await?;
+        if removed.deleted_count == 0 {
+            return Ok(false);
+        }
+        self.database
+            .collection::<ObjectDocument>("objects")
+            .update_one(
+                doc! { "object_id": object_id, "announce_count": { "$gt": 0 } },
+                doc! { "$inc": { "announce_count": -1 } },
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Record the boost of an incoming `Announce`, or withdraw the boost an
+    /// `Undo` refers to
+    ///
+    /// An `Undo` names the `Announce` by ID or embeds it; only the booster
+    /// may undo a boost. Returns whether a boost was recorded or withdrawn;
+    /// other activities are left alone.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn apply_boost_activity(
+        &self,
+        activity: &serde_json::Value,
+    ) -> Result<bool, DatabaseError> {
+        match activity.get("type").and_then(|t| t.as_str()) {
+            Some("Announce") => match BoostDocument::from_activitypub(activity) {
+                Some(boost) => self.record_boost(&boost).await,
+                None => Ok(false),
+            },
+            Some("Undo") => {
+                let Some(actor) = activity.get("actor").and_then(|a| a.as_str()) else {
+                    return Ok(false);
+                };
+                let undone = match activity.get("object") {
+                    Some(serde_json::Value::String(activity_id)) => self
+                        .find_boost_by_activity(activity_id)
+                        .await?
+                        .map(|boost| (boost.actor, boost.object_id)),
+                    Some(undone)
+                        if undone.get("type").and_then(|t| t.as_str()) == Some("Announce") =>
+                    {
+                        let object = undone.get("object");
+                        object
+                            .and_then(|object| {
+                                object.as_str().or_else(|| object.get("id")?.as_str())
+                            })
+                            .map(|object_id| {
+                                let booster = undone.get("actor").and_then(|a| a.as_str());
+                                (booster.unwrap_or(actor).to_string(), object_id.to_string())
+                            })
+                    }
+                    _ => None,
+                };
+                match undone {
+                    Some((booster, object_id)) if booster == actor => {
+                        self.undo_activity(ActivityType::Announce, actor, &object_id)
+                            .await
+                    }
+                    _ => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The boost an `Announce` activity recorded
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_boost_by_activity(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<BoostDocument>, DatabaseError> {
+        let collection: Collection<BoostDocument> = self.database.collection("boosts");
+        Ok(collection
+            .find_one(doc! { "activity_id": activity_id })
+            .await?)
+    }
+
+    /// Boosts by `actors` of stored public and unlisted posts, with the
+    /// posts, in the order of the timeline page
+    pub(super) async fn boosts_timeline(
+        &self,
+        actors: &[&String],
+        page: &TimelinePage,
+    ) -> Result<Vec<TimelineItem>, DatabaseError> {
+        let mut filter = doc! { "actor": { "$in": actors } };
+        if let Some(range) = page.id_range() {
+            filter.insert("_id", range);
+        }
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "_id": page.direction() } },
+            doc! { "$lookup": {
+                "from": "objects",
+                "localField": "object_id",
+                "foreignField": "object_id",
+                "as": "post",
+            } },
+            doc! { "$unwind": "$post" },
+            doc! { "$match": {
+                "post.visibility": { "$in": ["public", "unlisted"] },
+                "post.status": { "$ne": "scheduled" },
+                "post.object_type": { "$in": ["Note", "Article", "Question", "Page"] },
+            } },
+            doc! { "$limit": page.limit },
+        ];
+
+        let collection: Collection<BoostDocument> = self.database.collection("boosts");
+        let documents: Vec<Document> = collection.aggregate(pipeline).await?.try_collect().await?;
+        documents
+            .into_iter()
+            .map(|mut document| {
+                let post = document
+                    .remove("post")
+                    .and_then(|post| post.as_document().cloned())
+                    .unwrap_or_default();
+                Ok(TimelineItem::Boost {
+                    boost: mongodb::bson::from_document(document)?,
+                    post: mongodb::bson::from_document(post)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Newest `limit` items of a page from several sources, or the oldest
+/// ones when the page starts at `min_id`
+///
+/// Boosts of posts shown in the page, or boosted again more recently, are
+/// left out.
+pub(super) fn merge_timeline(
+    mut items: Vec<TimelineItem>,
+    page: &TimelinePage,
+) -> Vec<TimelineItem> {
+    items.sort_by_key(|item| Reverse(item.id()));
+    let shown: HashSet<String> = items
+        .iter()
+        .filter(|item| matches!(item, TimelineItem::Post(_)))
+        .map(|item| item.post().object_id.clone())
+        .collect();
+    let mut boosted = HashSet::new();
+    items.retain(|item| match item {
+        TimelineItem::Post(_) => true,
+        TimelineItem::Boost { post, .. } => {
+            !shown.contains(&post.object_id) && boosted.insert(post.object_id.clone())
+        }
+    });
+
+    let limit = usize::try_from(page.limit).unwrap_or(0);
+    if page.min_id.is_some() {
+        let skip = items.len().saturating_sub(limit);
+        items.split_off(skip)
+    } else {
+        items.truncate(limit);
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_boost_from_activitypub() {
+        let boost = BoostDocument::from_activitypub(&json!({
+            "id": "https://remote.example/activities/1",
+            "type": "Announce",
+            "actor": "https://remote.example/users/bob",
+            "object": { "id": "https://other.example/notes/1", "type": "Note" },
+            "to": ["https://remote.example/users/bob/followers"],
+            "cc": ["https://www.w3.org/ns/activitystreams#Public"]
+        }))
+        .unwrap();
+        assert_eq!(boost.object_id, "https://other.example/notes/1");
+        assert_eq!(boost.visibility, VisibilityLevel::Unlisted);
+
+        assert!(
+            BoostDocument::from_activitypub(&json!({
+                "id": "https://remote.example/activities/2",
+                "type": "Announce",
+                "object": "https://other.example/notes/1"
+            }))
+            .is_none()
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! MongoDB connection setup and health tracking
+//!
+//! [`DatabaseManager::connect`] builds the client from a [`DatabaseConfig`]
+//! and retries the first contact with the server with exponential backoff.
+//! Afterwards the driver reconnects on its own; the [`ConnectionMonitor`]
+//! follows its server heartbeats and opens a circuit after several
+//! consecutive failures. While the circuit is open, work that checks
+//! [`DatabaseManager::available`] backs off instead of piling up operations
+//! that each wait for server selection to time out. Once the reset time has
+//! passed the circuit is half-open, and the next heartbeat or ping decides
+//! whether it closes again.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use mongodb::Client;
+use mongodb::event::EventHandler;
+use mongodb::event::sdam::SdamEvent;
+use mongodb::options::ClientOptions;
+use tracing::{info, warn};
+
+use super::{DatabaseError, DatabaseManager};
+use crate::config::DatabaseConfig;
+use crate::health::ComponentHealth;
+
+/// Wait before the second connection attempt; doubled for each further one
+const INITIAL_CONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between connection attempts
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// State of the circuit breaker guarding the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The database answers
+    Closed,
+    /// The database failed repeatedly; callers should not try it
+    Open,
+    /// The reset time has passed; the next check decides
+    HalfOpen,
+}
+
+/// Tracks whether the database is reachable
+#[derive(Debug)]
+pub struct ConnectionMonitor {
+    failure_threshold: u32,
+    reset_after: Duration,
+    state: Mutex<MonitorState>,
+}
+
+#[derive(Debug, Default)]
+struct MonitorState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl Default for ConnectionMonitor {
+    fn default() -> Self {
+        let defaults = DatabaseConfig::default();
+        Self::new(
+            defaults.circuit_failure_threshold,
+            Duration::from_secs(defaults.circuit_reset_secs),
+        )
+    }
+}
+
+impl ConnectionMonitor {
+    pub fn new(failure_threshold: u32, reset_after: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            reset_after,
+            state: Mutex::new(MonitorState::default()),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.reset_after => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Error of the most recent failed check
+    pub fn last_error(&self) -> Option<String> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.opened_at.take().is_some() {
+            info!("MongoDB connection restored");
+        }
+        state.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.last_error = Some(error.to_string());
+        if state.consecutive_failures < self.failure_threshold {
+            return;
+        }
+        match state.opened_at {
+            // Still open; keep the time the circuit opened
+            Some(opened_at) if opened_at.elapsed() < self.reset_after => {}
+            Some(_) => state.opened_at = Some(Instant::now()),
+            None => {
+                warn!(
+                    "MongoDB unavailable after {} consecutive failures: {}",
+                    state.consecutive_failures, error
+                );
+                state.opened_at = Some(Instant::now());
+            }
+        }
+    }
+
+    fn handle_event(&self, event: SdamEvent) {
+        match event {
+            SdamEvent::ServerHeartbeatSucceeded(_) => self.record_success(),
+            SdamEvent::ServerHeartbeatFailed(event) => {
+                self.record_failure(&format!("{}: {}", event.server_address, event.failure))
+            }
+            _ => {}
+        }
+    }
+}
+
+impl DatabaseManager {
+    /// Connect to the database described by `config`
+    ///
+    /// Waits until the server answers, retrying with exponential backoff up
+    /// to `connect_attempts` times.
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let monitor = Arc::new(ConnectionMonitor::new(
+            config.circuit_failure_threshold,
+            Duration::from_secs(config.circuit_reset_secs),
+        ));
+        let options = client_options(config, monitor.clone()).await?;
+        let client = Client::with_options(options)?;
+        let manager = Self {
+            database: client.database(&config.name),
+            monitor,
+        };
+
+        let mut backoff = INITIAL_CONNECT_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match manager.ping().await {
+                Ok(()) => return Ok(manager),
+                Err(e) if attempt >= config.connect_attempts => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "MongoDB connection attempt {} of {} failed, retrying in {:?}: {}",
+                        attempt, config.connect_attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Monitor of the connection to the database
+    pub fn monitor(&self) -> &ConnectionMonitor {
+        &self.monitor
+    }
+
+    /// Fail fast while the circuit is open
+    pub fn available(&self) -> Result<(), DatabaseError> {
+        match self.monitor.state() {
+            CircuitState::Open => Err(DatabaseError::Unavailable(
+                self.monitor
+                    .last_error()
+                    .unwrap_or_else(|| "circuit open".to_string()),
+            )),
+            CircuitState::Closed | CircuitState::HalfOpen => Ok(()),
+        }
+    }
+
+    /// Health of the connection, as reported by health endpoints
+    ///
+    /// Pings the database unless the circuit is open.
+    pub async fn health(&self) -> ComponentHealth {
+        const NAME: &str = "mongodb";
+        if let Err(e) = self.available() {
+            return ComponentHealth::unhealthy(NAME, e.to_string());
+        }
+        let half_open = self.monitor.state() == CircuitState::HalfOpen;
+        match self.ping().await {
+            Ok(()) if half_open => ComponentHealth::degraded(NAME, "recovering from an outage"),
+            result => ComponentHealth::from_result(NAME, result),
+        }
+    }
+}
+
+/// Client options from the connection string, overridden by `config`
+async fn client_options(
+    config: &DatabaseConfig,
+    monitor: Arc<ConnectionMonitor>,
+) -> Result<ClientOptions, DatabaseError> {
+    let secs = |secs: Option<u64>| secs.map(Duration::from_secs);
+
+    let mut options = ClientOptions::parse(&config.uri).await?;
+    options.max_pool_size = config.max_pool_size.or(options.max_pool_size);
+    options.min_pool_size = config.min_pool_size.or(options.min_pool_size);
+    options.max_idle_time = secs(config.max_idle_time_secs).or(options.max_idle_time);
+    options.connect_timeout = secs(config.connect_timeout_secs).or(options.connect_timeout);
+    options.server_selection_timeout =
+        secs(config.server_selection_timeout_secs).or(options.server_selection_timeout);
+    options.retry_writes = config.retry_writes.or(options.retry_writes);
+    options.retry_reads = config.retry_reads.or(options.retry_reads);
+    options.sdam_event_handler = Some(EventHandler::callback(move |event| {
+        monitor.handle_event(event)
+    }));
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_threshold() {
+        let monitor = ConnectionMonitor::new(2, Duration::from_secs(3600));
+        monitor.record_failure("timeout");
+        assert_eq!(monitor.state(), CircuitState::Closed);
+        monitor.record_failure("timeout");
+        assert_eq!(monitor.state(), CircuitState::Open);
+        assert_eq!(monitor.last_error().as_deref(), Some("timeout"));
+
+        monitor.record_success();
+        assert_eq!(monitor.state(), CircuitState::Closed);
+        // Failures have to be consecutive again
+        monitor.record_failure("timeout");
+        assert_eq!(monitor.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_half_opens_after_reset() {
+        let monitor = ConnectionMonitor::new(1, Duration::ZERO);
+        monitor.record_failure("refused");
+        assert_eq!(monitor.state(), CircuitState::HalfOpen);
+        monitor.record_success();
+        assert_eq!(monitor.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_config_overrides_connection_string() {
+        let config = DatabaseConfig {
+            uri: "mongodb://localhost:27017/?maxPoolSize=50&retryWrites=false".to_string(),
+            min_pool_size: Some(2),
+            server_selection_timeout_secs: Some(5),
+            ..DatabaseConfig::default()
+        };
+        let options = client_options(&config, Arc::default()).await.unwrap();
+        assert_eq!(options.max_pool_size, Some(50));
+        assert_eq!(options.min_pool_size, Some(2));
+        assert_eq!(options.retry_writes, Some(false));
+        assert_eq!(
+            options.server_selection_timeout,
+            Some(Duration::from_secs(5))
+        );
+    }
+}
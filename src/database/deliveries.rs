@@ -0,0 +1,177 @@
+//! Delivery state of outgoing activities
+//!
+//! publisherd records the outcome of every delivery of an activity to an
+//! inbox in the `deliveries` collection, one document per activity and
+//! inbox, so administrators can see which inboxes an activity reached, which
+//! failed with what error and when the next attempt is due. Records are
+//! removed [`DELIVERY_RECORD_RETENTION_DAYS`] after their last update.
+
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{DateTime as BsonDateTime, doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager};
+use crate::messaging::{DeliveryState, InboxDeliveryInfo};
+
+/// Days delivery records are kept after their last update
+pub const DELIVERY_RECORD_RETENTION_DAYS: i64 = 30;
+
+/// Delivery of an activity to one inbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// ActivityPub ID of the delivered activity
+    pub activity_id: String,
+    /// Inbox, or the recipient whose inbox could not be looked up
+    pub inbox: String,
+    /// Recipients reached through the inbox
+    pub recipients: Vec<String>,
+
+    pub state: DeliveryState,
+    /// Attempts made so far
+    pub attempts: u32,
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+    pub next_retry_at: Option<DateTime<Utc>>,
+
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the TTL index removes the record
+    pub purge_at: BsonDateTime,
+}
+
+impl DeliveryDocument {
+    pub fn to_info(&self) -> InboxDeliveryInfo {
+        InboxDeliveryInfo {
+            inbox: self.inbox.clone(),
+            recipients: self.recipients.clone(),
+            state: self.state,
+            attempts: self.attempts,
+            last_error: self.last_error.clone(),
+            next_retry_at: self.next_retry_at.map(|at| at.to_rfc3339()),
+            updated_at: self.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Outcome of a delivery attempt to record
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt<'a> {
+    pub activity_id: &'a str,
+    pub inbox: &'a str,
+    pub recipients: &'a [String],
+    pub state: DeliveryState,
+    /// Attempts made so far, this one included
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub next_retry_at: Option<DateTime<Utc>>,
+}
+
+impl DatabaseManager {
+    /// Record the outcome of a delivery attempt, replacing the earlier state
+    /// of the delivery
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_delivery_attempt(
+        &self,
+        attempt: &DeliveryAttempt<'_>,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<DeliveryDocument> = self.database.collection("deliveries");
+        let now = Utc::now();
+        let purge_at = BsonDateTime::from_millis(
+            (now + Duration::days(DELIVERY_RECORD_RETENTION_DAYS)).timestamp_millis(),
+        );
+        let mut set = doc! {
+            "recipients": attempt.recipients,
+            "state": mongodb::bson::to_bson(&attempt.state)?,
+            "attempts": attempt.attempts,
+            "next_retry_at": mongodb::bson::to_bson(&attempt.next_retry_at)?,
+            "updated_at": mongodb::bson::to_bson(&now)?,
+            "purge_at": purge_at,
+        };
+        // A success keeps the error that preceded it
+        if attempt.error.is_some() {
+            set.insert("last_error", &attempt.error);
+        }
+        collection
+            .update_one(
+                doc! { "activity_id": attempt.activity_id, "inbox": attempt.inbox },
+                doc! {
+                    "$set": set,
+                    "$setOnInsert": { "created_at": mongodb::bson::to_bson(&now)? },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Deliveries of an activity, by inbox
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_deliveries(
+        &self,
+        activity_id: &str,
+    ) -> Result<Vec<DeliveryDocument>, DatabaseError> {
+        let collection: Collection<DeliveryDocument> = self.database.collection("deliveries");
+        let deliveries = collection
+            .find(doc! { "activity_id": activity_id })
+            .sort(doc! { "inbox": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(deliveries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::DeliveryStatus;
+
+    fn delivery(inbox: &str, state: DeliveryState) -> DeliveryDocument {
+        let now = Utc::now();
+        DeliveryDocument {
+            id: None,
+            activity_id: "https://example.com/activities/1".to_string(),
+            inbox: inbox.to_string(),
+            recipients: vec![],
+            state,
+            attempts: 1,
+            last_error: None,
+            next_retry_at: None,
+            created_at: now,
+            updated_at: now,
+            purge_at: BsonDateTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_delivery_status_counts_states() {
+        let mut failed = delivery("https://c.example/inbox", DeliveryState::Failed);
+        failed.last_error = Some("HTTP 410".to_string());
+        let deliveries = [
+            delivery("https://a.example/inbox", DeliveryState::Delivered),
+            delivery("https://b.example/inbox", DeliveryState::Retrying),
+            failed,
+            delivery("https://d.example/inbox", DeliveryState::Delivered),
+        ];
+        let status = DeliveryStatus::new(
+            "https://example.com/activities/1".to_string(),
+            deliveries.iter().map(DeliveryDocument::to_info).collect(),
+        );
+
+        assert_eq!(
+            (status.delivered, status.retrying, status.failed),
+            (2, 1, 1)
+        );
+        assert_eq!(status.inboxes[2].last_error.as_deref(), Some("HTTP 410"));
+        assert_eq!(
+            mongodb::bson::to_bson(&DeliveryState::Retrying).unwrap(),
+            mongodb::bson::Bson::String("retrying".to_string())
+        );
+    }
+}
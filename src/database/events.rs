@@ -0,0 +1,453 @@
+//! Events and RSVPs
+//!
+//! `Event` objects keep their schedule, their `Place` and how actors join
+//! them in `event`, read from and written as the properties Mobilizon
+//! federates. Actors RSVP with a `Join` of the event and withdraw with a
+//! `Leave`; the organizer answers a `Join` with `Accept` or `Reject`. Every
+//! RSVP is kept in the `participations` collection, and the accepted ones
+//! of local events make up their attendee collection and
+//! `participant_count`. Remote events keep the count their server reports.
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager, ObjectDocument};
+
+/// How actors join an event, Mobilizon's `joinMode`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinMode {
+    /// Every `Join` is accepted
+    #[default]
+    Free,
+    /// The organizer accepts or rejects each `Join`
+    Restricted,
+    /// Only invited actors join; their `Join`s wait for the organizer too
+    Invite,
+}
+
+impl JoinMode {
+    fn from_json(value: Option<&Value>) -> Self {
+        match value.and_then(Value::as_str) {
+            Some("restricted") => Self::Restricted,
+            Some("invite") => Self::Invite,
+            _ => Self::Free,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::Restricted => "restricted",
+            Self::Invite => "invite",
+        }
+    }
+}
+
+/// schema.org `PostalAddress` of a place
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PostalAddress {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub street_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locality: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+impl PostalAddress {
+    /// Address of a place, a `PostalAddress` or a plain string
+    fn from_json(value: &Value) -> Option<Self> {
+        if let Some(address) = value.as_str() {
+            return Some(Self {
+                street_address: Some(address.to_string()),
+                ..Default::default()
+            });
+        }
+        let field = |key: &str| super::json_str(value, key);
+        let address = Self {
+            street_address: field("streetAddress"),
+            locality: field("addressLocality"),
+            region: field("addressRegion"),
+            postal_code: field("postalCode"),
+            country: field("addressCountry"),
+        };
+        (address != Self::default()).then_some(address)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "type": "PostalAddress",
+            "streetAddress": self.street_address,
+            "addressLocality": self.locality,
+            "addressRegion": self.region,
+            "postalCode": self.postal_code,
+            "addressCountry": self.country
+        })
+    }
+}
+
+/// Location of an event (`Place`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PlaceDocument {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<PostalAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+}
+
+impl PlaceDocument {
+    /// Place of a `location`, which may be a list of them
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if let Some(places) = value.as_array() {
+            return places.iter().find_map(Self::from_json);
+        }
+        if !value.is_object() {
+            return None;
+        }
+        // Coordinates are numbers, but some software writes strings
+        let coordinate = |key: &str| match value.get(key)? {
+            Value::Number(number) => number.as_f64(),
+            Value::String(number) => number.parse().ok(),
+            _ => None,
+        };
+        let place = Self {
+            name: super::json_str(value, "name"),
+            address: value.get("address").and_then(PostalAddress::from_json),
+            latitude: coordinate("latitude"),
+            longitude: coordinate("longitude"),
+        };
+        (place != Self::default()).then_some(place)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": "Place",
+            "name": self.name,
+            "address": self.address.as_ref().map(PostalAddress::to_json),
+            "latitude": self.latitude,
+            "longitude": self.longitude
+        })
+    }
+}
+
+/// Schedule, location and attendance of an `Event` object
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EventDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<DateTime<Utc>>,
+    /// IANA time zone the event takes place in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<PlaceDocument>,
+    #[serde(default)]
+    pub join_mode: JoinMode,
+    /// Attendees the event admits, unlimited if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum_attendee_capacity: Option<i64>,
+    /// Accepted attendees
+    #[serde(default)]
+    pub participant_count: i64,
+    /// Collection of the accepted attendees
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub participants: Option<String>,
+}
+
+impl EventDetails {
+    /// Read the event properties of an `Event` object
+    pub fn from_activitypub(object: &Value) -> Self {
+        Self {
+            start_time: super::json_datetime(object, "startTime"),
+            end_time: super::json_datetime(object, "endTime"),
+            timezone: super::json_str(object, "timezone"),
+            location: object.get("location").and_then(PlaceDocument::from_json),
+            join_mode: JoinMode::from_json(object.get("joinMode")),
+            maximum_attendee_capacity: object
+                .get("maximumAttendeeCapacity")
+                .and_then(Value::as_i64)
+                .filter(|capacity| *capacity > 0),
+            participant_count: object
+                .get("participantCount")
+                .and_then(Value::as_i64)
+                .unwrap_or(0),
+            participants: super::json_str(object, "participants"),
+        }
+    }
+
+    /// Write the event properties into the ActivityStreams JSON of the event
+    pub fn write_json(&self, object: &mut Value) {
+        object["startTime"] = json!(self.start_time.map(|time| time.to_rfc3339()));
+        object["endTime"] = json!(self.end_time.map(|time| time.to_rfc3339()));
+        object["timezone"] = json!(self.timezone);
+        object["location"] = json!(self.location.as_ref().map(PlaceDocument::to_json));
+        object["joinMode"] = json!(self.join_mode.as_str());
+        object["participantCount"] = json!(self.participant_count);
+        object["maximumAttendeeCapacity"] = json!(self.maximum_attendee_capacity);
+        object["remainingAttendeeCapacity"] = json!(
+            self.maximum_attendee_capacity
+                .map(|capacity| (capacity - self.participant_count).max(0))
+        );
+        object["participants"] = json!(self.participants);
+    }
+
+    /// Whether the event admits no more attendees
+    pub fn is_full(&self) -> bool {
+        self.maximum_attendee_capacity
+            .is_some_and(|capacity| self.participant_count >= capacity)
+    }
+
+    /// Status a new `Join` of the event gets
+    ///
+    /// Joins of full events are rejected; those of restricted and
+    /// invite-only events wait for the organizer.
+    pub fn answer_join(&self) -> ParticipationStatus {
+        match self.join_mode {
+            _ if self.is_full() => ParticipationStatus::Rejected,
+            JoinMode::Free => ParticipationStatus::Accepted,
+            JoinMode::Restricted | JoinMode::Invite => ParticipationStatus::Pending,
+        }
+    }
+}
+
+/// State of an RSVP
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParticipationStatus {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+/// An actor's `Join` of an event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticipationDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// ActivityPub ID of the event
+    pub event_id: String,
+    /// Actor joining the event
+    pub actor: String,
+    pub status: ParticipationStatus,
+    /// ID of the `Join` activity
+    pub activity_id: String,
+
+    pub created_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+impl ParticipationDocument {
+    pub fn new(
+        event_id: &str,
+        actor: &str,
+        activity_id: &str,
+        status: ParticipationStatus,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: None,
+            event_id: event_id.to_string(),
+            actor: actor.to_string(),
+            status,
+            activity_id: activity_id.to_string(),
+            created_at: now,
+            responded_at: (status != ParticipationStatus::Pending).then_some(now),
+        }
+    }
+}
+
+impl DatabaseManager {
+    /// Record an actor's `Join` of an event, replacing an earlier one
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_participation(
+        &self,
+        participation: &ParticipationDocument,
+    ) -> Result<(), DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        collection
+            .replace_one(
+                doc! { "event_id": &participation.event_id, "actor": &participation.actor },
+                participation,
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Answer the `Join` with ID `activity_id`
+    ///
+    /// Returns the answered participation, `None` if the `Join` is unknown.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn answer_participation(
+        &self,
+        activity_id: &str,
+        status: ParticipationStatus,
+    ) -> Result<Option<ParticipationDocument>, DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        Ok(collection
+            .find_one_and_update(
+                doc! { "activity_id": activity_id },
+                doc! { "$set": {
+                    "status": mongodb::bson::to_bson(&status)?,
+                    "responded_at": mongodb::bson::to_bson(&Utc::now())?
+                } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    /// An actor's participation in an event
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_participation(
+        &self,
+        event_id: &str,
+        actor: &str,
+    ) -> Result<Option<ParticipationDocument>, DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        Ok(collection
+            .find_one(doc! { "event_id": event_id, "actor": actor })
+            .await?)
+    }
+
+    /// The `Join` with ID `activity_id`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_participation_by_activity(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<ParticipationDocument>, DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        Ok(collection
+            .find_one(doc! { "activity_id": activity_id })
+            .await?)
+    }
+
+    /// Remove an actor's participation in an event
+    ///
+    /// Returns whether the actor had joined the event.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn remove_participation(
+        &self,
+        event_id: &str,
+        actor: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        let removed = collection
+            .delete_one(doc! { "event_id": event_id, "actor": actor })
+            .await?;
+        Ok(removed.deleted_count > 0)
+    }
+
+    /// Accepted attendees of an event, in the order they joined
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_participants(&self, event_id: &str) -> Result<Vec<String>, DatabaseError> {
+        let collection: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        let participations: Vec<ParticipationDocument> = collection
+            .find(doc! { "event_id": event_id, "status": "accepted" })
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(participations.into_iter().map(|p| p.actor).collect())
+    }
+
+    /// Count the accepted attendees of a local event into its
+    /// `participant_count`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn count_participants(&self, event_id: &str) -> Result<i64, DatabaseError> {
+        let participations: Collection<ParticipationDocument> =
+            self.database.collection("participations");
+        let count = participations
+            .count_documents(doc! { "event_id": event_id, "status": "accepted" })
+            .await? as i64;
+        self.database
+            .collection::<ObjectDocument>("objects")
+            .update_one(
+                doc! { "object_id": event_id, "event": { "$exists": true } },
+                doc! { "$set": { "event.participant_count": count } },
+            )
+            .await?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mobilizon_event_properties() {
+        let event = ObjectDocument::from_activitypub(
+            &json!({
+                "type": "Event",
+                "id": "https://mobilizon.example/events/1",
+                "attributedTo": "https://mobilizon.example/@alice",
+                "name": "Meetup",
+                "startTime": "2026-11-01T18:00:00Z",
+                "endTime": "2026-11-01T21:00:00Z",
+                "timezone": "Europe/Zurich",
+                "joinMode": "restricted",
+                "maximumAttendeeCapacity": 2,
+                "participantCount": 2,
+                "location": {
+                    "type": "Place",
+                    "name": "Town hall",
+                    "latitude": "47.37",
+                    "longitude": 8.54,
+                    "address": {
+                        "type": "PostalAddress",
+                        "addressLocality": "Zürich",
+                        "addressCountry": "Switzerland"
+                    }
+                }
+            }),
+            crate::ObjectType::Event,
+        );
+        let details = event.event.as_ref().expect("event details");
+        assert_eq!(details.join_mode, JoinMode::Restricted);
+        assert_eq!(details.maximum_attendee_capacity, Some(2));
+        let place = details.location.as_ref().unwrap();
+        assert_eq!(place.latitude, Some(47.37));
+        assert_eq!(
+            place.address.as_ref().unwrap().locality.as_deref(),
+            Some("Zürich")
+        );
+        // The event is full
+        assert_eq!(details.answer_join(), ParticipationStatus::Rejected);
+
+        let rendered = event.to_activitypub();
+        assert_eq!(rendered["startTime"], "2026-11-01T18:00:00+00:00");
+        assert_eq!(rendered["joinMode"], "restricted");
+        assert_eq!(rendered["remainingAttendeeCapacity"], 0);
+        assert_eq!(rendered["location"]["type"], "Place");
+        assert_eq!(
+            rendered["location"]["address"]["addressCountry"],
+            "Switzerland"
+        );
+
+        let open = EventDetails::default();
+        assert_eq!(open.answer_join(), ParticipationStatus::Accepted);
+    }
+}
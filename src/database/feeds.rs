@@ -0,0 +1,352 @@
+//! Followed hashtags and instances
+//!
+//! Besides accounts, local users can follow hashtags and remote instances.
+//! Public posts with a followed hashtag or from a followed instance are
+//! added to the user's home feed as they are stored, and the home timeline
+//! shows them next to the posts of followed accounts. Accounts in an
+//! exclusive list are kept off the home timeline.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::boosts::merge_timeline;
+use super::{
+    DatabaseError, DatabaseManager, ObjectDocument, TimelineItem, TimelinePage, VisibilityLevel,
+};
+use crate::ObjectType;
+
+/// What a feed subscription follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedKind {
+    Hashtag,
+    Instance,
+}
+
+/// Hashtag or instance followed by a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedSubscriptionDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// User following the feed
+    pub username: String,
+    pub domain: String,
+
+    pub kind: FeedKind,
+    /// Lowercase hashtag without `#`, or lowercase host name
+    pub name: String,
+
+    pub created_at: DateTime<Utc>,
+}
+
+/// Post added to the home feed of a local user through a subscription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HomeFeedEntryDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub username: String,
+    pub domain: String,
+
+    /// Storage ID of the post, by which the home timeline is paged
+    pub post_id: ObjectId,
+    /// ActivityPub ID of the post
+    pub object_id: String,
+
+    /// Subscription that matched the post
+    pub kind: FeedKind,
+    pub name: String,
+
+    pub created_at: DateTime<Utc>,
+}
+
+/// Hashtag as followed: without `#` and lowercase
+pub fn normalize_hashtag(name: &str) -> String {
+    name.trim().trim_start_matches('#').to_lowercase()
+}
+
+/// Subscriptions a post matches: its hashtags and the host of its author
+fn post_feeds(object: &ObjectDocument) -> Vec<(FeedKind, String)> {
+    let mut feeds: Vec<(FeedKind, String)> = object
+        .tag
+        .iter()
+        .flatten()
+        .filter(|tag| tag.tag_type == "Hashtag")
+        .map(|tag| (FeedKind::Hashtag, normalize_hashtag(&tag.name)))
+        .filter(|(_, name)| !name.is_empty())
+        .collect();
+    if let Some(host) = url::Url::parse(&object.attributed_to)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+    {
+        feeds.push((FeedKind::Instance, host));
+    }
+    feeds.sort();
+    feeds.dedup();
+    feeds
+}
+
+impl DatabaseManager {
+    /// Follow a hashtag or instance; following it again changes nothing
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn follow_feed(
+        &self,
+        username: &str,
+        domain: &str,
+        kind: FeedKind,
+        name: &str,
+    ) -> Result<FeedSubscriptionDocument, DatabaseError> {
+        let collection: Collection<FeedSubscriptionDocument> =
+            self.database.collection("followed_feeds");
+        collection
+            .find_one_and_update(
+                doc! {
+                    "domain": domain,
+                    "username": username,
+                    "kind": mongodb::bson::to_bson(&kind)?,
+                    "name": name,
+                },
+                doc! { "$setOnInsert": { "created_at": mongodb::bson::to_bson(&Utc::now())? } },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await?
+            .ok_or_else(|| DatabaseError::NotFoundError(name.to_string()))
+    }
+
+    /// Stop following a hashtag or instance, returning whether the user did
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn unfollow_feed(
+        &self,
+        username: &str,
+        domain: &str,
+        kind: FeedKind,
+        name: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<FeedSubscriptionDocument> =
+            self.database.collection("followed_feeds");
+        let result = collection
+            .delete_one(doc! {
+                "domain": domain,
+                "username": username,
+                "kind": mongodb::bson::to_bson(&kind)?,
+                "name": name,
+            })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Hashtags or instances a user follows, oldest first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_followed_feeds(
+        &self,
+        username: &str,
+        domain: &str,
+        kind: FeedKind,
+    ) -> Result<Vec<FeedSubscriptionDocument>, DatabaseError> {
+        let collection: Collection<FeedSubscriptionDocument> =
+            self.database.collection("followed_feeds");
+        Ok(collection
+            .find(doc! {
+                "domain": domain,
+                "username": username,
+                "kind": mongodb::bson::to_bson(&kind)?,
+            })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    /// Whether a user follows a hashtag or instance
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn is_following_feed(
+        &self,
+        username: &str,
+        domain: &str,
+        kind: FeedKind,
+        name: &str,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<FeedSubscriptionDocument> =
+            self.database.collection("followed_feeds");
+        Ok(collection
+            .find_one(doc! {
+                "domain": domain,
+                "username": username,
+                "kind": mongodb::bson::to_bson(&kind)?,
+                "name": name,
+            })
+            .await?
+            .is_some())
+    }
+
+    /// Add a stored remote post to the home feeds of the users following
+    /// one of its hashtags or its author's instance
+    ///
+    /// Only public posts are added. Returns the number of home feeds the
+    /// post was added to.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn add_to_followed_feeds(
+        &self,
+        object: &ObjectDocument,
+    ) -> Result<usize, DatabaseError> {
+        let is_post = matches!(
+            object.object_type,
+            ObjectType::Note | ObjectType::Article | ObjectType::Page
+        );
+        if object.local || !is_post || object.visibility != VisibilityLevel::Public {
+            return Ok(0);
+        }
+        let feeds = post_feeds(object);
+        if feeds.is_empty() {
+            return Ok(0);
+        }
+        let matches = feeds
+            .iter()
+            .map(|(kind, name)| Ok(doc! { "kind": mongodb::bson::to_bson(kind)?, "name": name }))
+            .collect::<Result<Vec<Document>, DatabaseError>>()?;
+
+        let subscriptions: Vec<FeedSubscriptionDocument> = self
+            .database
+            .collection::<FeedSubscriptionDocument>("followed_feeds")
+            .find(doc! { "$or": matches })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        if subscriptions.is_empty() {
+            return Ok(0);
+        }
+        // Posts stored in a batch do not carry their storage ID
+        let stored_id = match object.id {
+            Some(id) => Some(id),
+            None => self
+                .find_object_by_id(&object.object_id)
+                .await?
+                .and_then(|stored| stored.id),
+        };
+        let Some(post_id) = stored_id else {
+            return Ok(0);
+        };
+
+        // One entry per user, for the subscription they made first
+        let mut users = HashSet::new();
+        let now = Utc::now();
+        let entries: Vec<HomeFeedEntryDocument> = subscriptions
+            .into_iter()
+            .filter(|subscription| {
+                users.insert((subscription.domain.clone(), subscription.username.clone()))
+            })
+            .map(|subscription| HomeFeedEntryDocument {
+                id: None,
+                username: subscription.username,
+                domain: subscription.domain,
+                post_id,
+                object_id: object.object_id.clone(),
+                kind: subscription.kind,
+                name: subscription.name,
+                created_at: now,
+            })
+            .collect();
+        let collection: Collection<HomeFeedEntryDocument> = self.database.collection("home_feed");
+        let outcome = super::batch::insert_unordered(&collection, entries).await?;
+        if let Some((_, message)) = outcome.rejected.first() {
+            return Err(DatabaseError::OperationError(message.clone()));
+        }
+        Ok(outcome.inserted)
+    }
+
+    /// Home timeline of a local user
+    ///
+    /// Shows the posts and boosts of the user and of the accounts they
+    /// follow, except members of their exclusive lists, with replies to
+    /// those accounts, and the posts added to their home feed.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn get_home_timeline(
+        &self,
+        username: &str,
+        domain: &str,
+        actor_id: &str,
+        page: &TimelinePage,
+    ) -> Result<Vec<TimelineItem>, DatabaseError> {
+        let following = self.get_actor_following(actor_id).await?;
+        let exclusive: HashSet<String> = self
+            .find_lists(username, domain)
+            .await?
+            .into_iter()
+            .filter(|list| list.exclusive)
+            .flat_map(|list| list.accounts)
+            .collect();
+        let owner = actor_id.to_string();
+        let authors: Vec<&String> = following
+            .iter()
+            .filter(|account| !exclusive.contains(*account))
+            .chain(std::iter::once(&owner))
+            .collect();
+        let mut reply_targets: Vec<&String> = following.iter().collect();
+        reply_targets.push(&owner);
+
+        // The page's posts from the home feed are among its newest entries
+        let mut filter = doc! { "domain": domain, "username": username };
+        if let Some(range) = page.id_range() {
+            filter.insert("post_id", range);
+        }
+        let direction = page.direction();
+        let entries: Vec<HomeFeedEntryDocument> = self
+            .database
+            .collection::<HomeFeedEntryDocument>("home_feed")
+            .find(filter)
+            .sort(doc! { "post_id": direction })
+            .limit(page.limit)
+            .await?
+            .try_collect()
+            .await?;
+        let added: Vec<ObjectId> = entries.iter().map(|entry| entry.post_id).collect();
+
+        let mut items: Vec<TimelineItem> = self
+            .posts_timeline(&authors, reply_targets, &added, actor_id, page)
+            .await?
+            .into_iter()
+            .map(TimelineItem::Post)
+            .collect();
+        items.extend(self.boosts_timeline(&authors, page).await?);
+        Ok(merge_timeline(items, page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_post_feeds() {
+        let note = ObjectDocument::from_activitypub(
+            &json!({
+                "id": "https://Remote.example/notes/1",
+                "attributedTo": "https://Remote.example/users/bob",
+                "tag": [
+                    { "type": "Hashtag", "name": "#Rust" },
+                    { "type": "Hashtag", "name": "#rust" },
+                    { "type": "Mention", "name": "@alice", "href": "https://example.com/users/alice" }
+                ]
+            }),
+            ObjectType::Note,
+        );
+        assert_eq!(
+            post_feeds(&note),
+            vec![
+                (FeedKind::Hashtag, "rust".to_string()),
+                (FeedKind::Instance, "remote.example".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Content filters of local users
+//!
+//! A filter holds keywords and individual posts a user does not want to
+//! see in some contexts, until it expires. domainservd's `filters` module
+//! matches posts against them and serves Mastodon's `/api/v2/filters`.
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager};
+
+/// Where a filter applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterContext {
+    /// Home timeline and lists
+    Home,
+    Notifications,
+    /// Public, hashtag and instance timelines
+    Public,
+    /// Conversations and threads
+    Thread,
+    /// Profiles
+    Account,
+}
+
+/// What happens to a matching post
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// Shown behind a warning naming the filter
+    #[default]
+    Warn,
+    /// Left out
+    Hide,
+}
+
+/// Keyword or phrase of a filter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterKeyword {
+    pub id: ObjectId,
+    pub keyword: String,
+    /// Whether the keyword only matches whole words
+    pub whole_word: bool,
+}
+
+/// Post a filter applies to regardless of its content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterStatus {
+    pub id: ObjectId,
+    /// Storage ID of the post
+    pub status_id: ObjectId,
+}
+
+/// Content filter of a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// User the filter belongs to
+    pub username: String,
+    pub domain: String,
+
+    pub title: String,
+    pub context: Vec<FilterContext>,
+    #[serde(default)]
+    pub filter_action: FilterAction,
+
+    /// Time the filter stops applying; never when unset
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub keywords: Vec<FilterKeyword>,
+    #[serde(default)]
+    pub statuses: Vec<FilterStatus>,
+
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FilterDocument {
+    /// Whether the filter still applies at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// Changes to the settings of a filter; fields left `None` are kept
+#[derive(Debug, Clone, Default)]
+pub struct FilterUpdate {
+    pub title: Option<String>,
+    pub context: Option<Vec<FilterContext>>,
+    pub filter_action: Option<FilterAction>,
+    /// `Some(None)` removes the expiry
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+}
+
+impl DatabaseManager {
+    /// Store a new filter
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_filter(
+        &self,
+        mut filter: FilterDocument,
+    ) -> Result<FilterDocument, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        let result = collection.insert_one(&filter).await?;
+        filter.id = result.inserted_id.as_object_id();
+        Ok(filter)
+    }
+
+    /// Filters of a user, oldest first, including expired ones
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_filters(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<Vec<FilterDocument>, DatabaseError> {
+        self.find_filters_matching(doc! { "domain": domain, "username": username })
+            .await
+    }
+
+    /// Filters of a user that apply in `context` at present
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_active_filters(
+        &self,
+        username: &str,
+        domain: &str,
+        context: FilterContext,
+    ) -> Result<Vec<FilterDocument>, DatabaseError> {
+        let filters = self
+            .find_filters_matching(doc! {
+                "domain": domain,
+                "username": username,
+                "context": mongodb::bson::to_bson(&context)?,
+            })
+            .await?;
+        let now = Utc::now();
+        Ok(filters
+            .into_iter()
+            .filter(|filter| filter.is_active(now))
+            .collect())
+    }
+
+    /// A filter of a user
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_filter(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        Ok(collection
+            .find_one(doc! { "_id": id, "domain": domain, "username": username })
+            .await?)
+    }
+
+    /// The filter of a user holding the keyword or post `entry_id`
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_filter_by_entry(
+        &self,
+        username: &str,
+        domain: &str,
+        entry_id: ObjectId,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        Ok(collection
+            .find_one(doc! {
+                "domain": domain,
+                "username": username,
+                "$or": [{ "keywords.id": entry_id }, { "statuses.id": entry_id }],
+            })
+            .await?)
+    }
+
+    /// Change the settings of a filter, returning it as updated
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_filter(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        update: FilterUpdate,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        let mut set = doc! { "updated_at": mongodb::bson::to_bson(&Utc::now())? };
+        if let Some(title) = update.title {
+            set.insert("title", title);
+        }
+        if let Some(context) = update.context {
+            set.insert("context", mongodb::bson::to_bson(&context)?);
+        }
+        if let Some(filter_action) = update.filter_action {
+            set.insert("filter_action", mongodb::bson::to_bson(&filter_action)?);
+        }
+        if let Some(expires_at) = update.expires_at {
+            set.insert("expires_at", mongodb::bson::to_bson(&expires_at)?);
+        }
+        self.update_filter_matching(
+            doc! { "_id": id, "domain": domain, "username": username },
+            doc! { "$set": set },
+        )
+        .await
+    }
+
+    /// Delete a filter, returning whether the user had it
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_filter(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        let result = collection
+            .delete_one(doc! { "_id": id, "domain": domain, "username": username })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Add a keyword to a filter, returning the filter as updated
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn add_filter_keyword(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        keyword: &FilterKeyword,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        self.update_filter_matching(
+            doc! { "_id": id, "domain": domain, "username": username },
+            doc! {
+                "$push": { "keywords": mongodb::bson::to_bson(keyword)? },
+                "$set": { "updated_at": mongodb::bson::to_bson(&Utc::now())? },
+            },
+        )
+        .await
+    }
+
+    /// Replace a keyword of a filter, returning the filter as updated
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_filter_keyword(
+        &self,
+        username: &str,
+        domain: &str,
+        keyword: &FilterKeyword,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        self.update_filter_matching(
+            doc! { "domain": domain, "username": username, "keywords.id": keyword.id },
+            doc! {
+                "$set": {
+                    "keywords.$": mongodb::bson::to_bson(keyword)?,
+                    "updated_at": mongodb::bson::to_bson(&Utc::now())?,
+                },
+            },
+        )
+        .await
+    }
+
+    /// Add a post to a filter, returning the filter as updated
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn add_filter_status(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        status: &FilterStatus,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        self.update_filter_matching(
+            doc! { "_id": id, "domain": domain, "username": username },
+            doc! {
+                "$push": { "statuses": mongodb::bson::to_bson(status)? },
+                "$set": { "updated_at": mongodb::bson::to_bson(&Utc::now())? },
+            },
+        )
+        .await
+    }
+
+    /// Remove the keyword or post `entry_id` from its filter, returning
+    /// whether the user had it
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn remove_filter_entry(
+        &self,
+        username: &str,
+        domain: &str,
+        entry_id: ObjectId,
+    ) -> Result<bool, DatabaseError> {
+        let updated = self
+            .update_filter_matching(
+                doc! {
+                    "domain": domain,
+                    "username": username,
+                    "$or": [{ "keywords.id": entry_id }, { "statuses.id": entry_id }],
+                },
+                doc! {
+                    "$pull": {
+                        "keywords": { "id": entry_id },
+                        "statuses": { "id": entry_id },
+                    },
+                    "$set": { "updated_at": mongodb::bson::to_bson(&Utc::now())? },
+                },
+            )
+            .await?;
+        Ok(updated.is_some())
+    }
+
+    async fn find_filters_matching(
+        &self,
+        filter: Document,
+    ) -> Result<Vec<FilterDocument>, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        Ok(collection
+            .find(filter)
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn update_filter_matching(
+        &self,
+        filter: Document,
+        update: Document,
+    ) -> Result<Option<FilterDocument>, DatabaseError> {
+        let collection: Collection<FilterDocument> = self.database.collection("filters");
+        Ok(collection
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+}
@@ -0,0 +1,440 @@
+//! Registry of remote instances
+//!
+//! Every host we receive activities from or deliver to gets a document in
+//! the `instances` collection recording when it was first and last seen,
+//! the software it runs and how deliveries to it went. publisherd's circuit
+//! breaker reads the failure streak of a host from here, so a host that has
+//! been down for a while is skipped by every publisherd until it recovers.
+//! domainservd's NodeInfo crawler records the software of each host and
+//! whether it is open for registrations.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager, DomainBlockDocument, DomainBlockSeverity};
+use crate::messaging::{FederationStats, SoftwareStats, VersionStats};
+
+/// Remote instance we federate with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// Host name of the instance
+    pub domain: String,
+
+    pub first_seen_at: DateTime<Utc>,
+    /// Last activity received from the instance
+    #[serde(default)]
+    pub last_seen_at: Option<DateTime<Utc>>,
+
+    /// Software name and version the instance reports in its NodeInfo
+    #[serde(default)]
+    pub software: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Whether the instance reports open registrations
+    #[serde(default)]
+    pub open_registrations: Option<bool>,
+    /// Last NodeInfo fetch and its error, if it failed
+    #[serde(default)]
+    pub crawled_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub crawl_error: Option<String>,
+
+    /// Last successful delivery to the instance
+    #[serde(default)]
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    /// Last failed delivery and its error
+    #[serde(default)]
+    pub last_failure_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// Deliveries that failed since the last successful one
+    #[serde(default)]
+    pub consecutive_failures: u32,
+}
+
+/// What an instance reports in its NodeInfo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceNodeInfo {
+    pub software: Option<String>,
+    pub version: Option<String>,
+    pub open_registrations: Option<bool>,
+}
+
+impl InstanceDocument {
+    /// When deliveries to the instance may be tried again, if its failure
+    /// streak of at least `threshold` opened the circuit for `cooldown`
+    pub fn circuit_open_until(
+        &self,
+        threshold: u32,
+        cooldown: chrono::Duration,
+    ) -> Option<DateTime<Utc>> {
+        if threshold == 0 || self.consecutive_failures < threshold {
+            return None;
+        }
+        self.last_failure_at
+            .map(|failed| failed + cooldown)
+            .filter(|until| *until > Utc::now())
+    }
+}
+
+impl DatabaseManager {
+    /// Record that an activity of `domain` was received
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_instance_seen(&self, domain: &str) -> Result<(), DatabaseError> {
+        let now = mongodb::bson::to_bson(&Utc::now())?;
+        self.instances()
+            .update_one(
+                doc! { "domain": domain },
+                doc! {
+                    "$set": { "last_seen_at": &now },
+                    "$setOnInsert": { "first_seen_at": &now, "consecutive_failures": 0 },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the outcome of a delivery to `domain`
+    ///
+    /// A success ends the failure streak of the instance.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_instance_delivery(
+        &self,
+        domain: &str,
+        result: Result<(), &str>,
+    ) -> Result<(), DatabaseError> {
+        let now = mongodb::bson::to_bson(&Utc::now())?;
+        let update = match result {
+            Ok(()) => doc! {
+                "$set": { "last_delivery_at": &now, "consecutive_failures": 0 },
+                "$setOnInsert": { "first_seen_at": &now },
+            },
+            Err(error) => doc! {
+                "$set": { "last_failure_at": &now, "last_error": error },
+                "$inc": { "consecutive_failures": 1 },
+                "$setOnInsert": { "first_seen_at": &now },
+            },
+        };
+        self.instances()
+            .update_one(doc! { "domain": domain }, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the outcome of fetching the NodeInfo of an instance
+    ///
+    /// A failed fetch keeps what the instance reported before.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_instance_crawl(
+        &self,
+        domain: &str,
+        result: Result<&InstanceNodeInfo, &str>,
+    ) -> Result<(), DatabaseError> {
+        let now = mongodb::bson::to_bson(&Utc::now())?;
+        let set = match result {
+            Ok(nodeinfo) => doc! {
+                "crawled_at": now,
+                "crawl_error": null,
+                "software": &nodeinfo.software,
+                "version": &nodeinfo.version,
+                "open_registrations": nodeinfo.open_registrations,
+            },
+            Err(error) => doc! { "crawled_at": now, "crawl_error": error },
+        };
+        self.instances()
+            .update_one(doc! { "domain": domain }, doc! { "$set": set })
+            .await?;
+        Ok(())
+    }
+
+    /// Domains of the instances whose NodeInfo was not fetched since
+    /// `crawled_before`, never fetched ones first, leaving out suspended
+    /// domains
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_instances_to_crawl(
+        &self,
+        crawled_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let suspended = self.suspended_domains().await?;
+        let instances: Vec<InstanceDocument> = self
+            .instances()
+            .find(doc! {
+                "domain": { "$nin": suspended },
+                "$or": [
+                    { "crawled_at": null },
+                    { "crawled_at": { "$lt": mongodb::bson::to_bson(&crawled_before)? } },
+                ],
+            })
+            .sort(doc! { "crawled_at": 1 })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(instances
+            .into_iter()
+            .map(|instance| instance.domain)
+            .collect())
+    }
+
+    /// Known instances by the software and version they run
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn federation_stats(&self) -> Result<FederationStats, DatabaseError> {
+        let collection = self.instances();
+        let unreachable = collection
+            .count_documents(doc! { "crawl_error": { "$type": "string" } })
+            .await?;
+        let mut cursor = collection
+            .aggregate(vec![doc! {
+                "$group": {
+                    "_id": { "software": "$software", "version": "$version" },
+                    "instances": { "$sum": 1 },
+                    "open_registrations": {
+                        "$sum": { "$cond": [{ "$eq": ["$open_registrations", true] }, 1, 0] }
+                    },
+                }
+            }])
+            .await?;
+        let mut groups = Vec::new();
+        while let Some(group) = cursor.try_next().await? {
+            let count = |key: &str| match group.get(key) {
+                Some(mongodb::bson::Bson::Int32(count)) => *count as u64,
+                Some(mongodb::bson::Bson::Int64(count)) => *count as u64,
+                _ => 0,
+            };
+            let id = group.get_document("_id").ok();
+            let field = |key: &str| id.and_then(|id| id.get_str(key).ok()).map(str::to_string);
+            groups.push(SoftwareGroup {
+                software: field("software"),
+                version: field("version"),
+                instances: count("instances"),
+                open_registrations: count("open_registrations"),
+            });
+        }
+        Ok(stats_from_groups(groups, unreachable))
+    }
+
+    /// Find the registry entry of an instance
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_instance(
+        &self,
+        domain: &str,
+    ) -> Result<Option<InstanceDocument>, DatabaseError> {
+        Ok(self.instances().find_one(doc! { "domain": domain }).await?)
+    }
+
+    /// Page of known instances by domain, or with `failing` those whose
+    /// last deliveries failed, longest streak first, with the number of all
+    /// of them
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_instances_page(
+        &self,
+        failing: bool,
+        offset: u64,
+        limit: i64,
+    ) -> Result<(Vec<InstanceDocument>, u64), DatabaseError> {
+        let (filter, sort) = if failing {
+            (
+                doc! { "consecutive_failures": { "$gt": 0 } },
+                doc! { "consecutive_failures": -1, "domain": 1 },
+            )
+        } else {
+            (doc! {}, doc! { "domain": 1 })
+        };
+        let collection = self.instances();
+        let total = collection.count_documents(filter.clone()).await?;
+        let instances = collection
+            .find(filter)
+            .sort(sort)
+            .skip(offset)
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok((instances, total))
+    }
+
+    /// Domains of the known instances that are not suspended
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_peer_domains(&self) -> Result<Vec<String>, DatabaseError> {
+        let suspended = self.suspended_domains().await?;
+        let mut domains: Vec<String> = self
+            .instances()
+            .distinct("domain", doc! { "domain": { "$nin": suspended } })
+            .await?
+            .into_iter()
+            .filter_map(|domain| domain.as_str().map(str::to_string))
+            .collect();
+        domains.sort();
+        Ok(domains)
+    }
+
+    /// Blocks of the given domains, by domain
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_domain_blocks(
+        &self,
+        domains: &[String],
+    ) -> Result<HashMap<String, DomainBlockDocument>, DatabaseError> {
+        let blocks = self
+            .database
+            .collection::<DomainBlockDocument>("domain_blocks")
+            .find(doc! { "domain": { "$in": domains } })
+            .await?
+            .map_ok(|block| (block.domain.clone(), block))
+            .try_collect()
+            .await?;
+        Ok(blocks)
+    }
+
+    async fn suspended_domains(&self) -> Result<Vec<String>, DatabaseError> {
+        Ok(self
+            .database
+            .collection::<DomainBlockDocument>("domain_blocks")
+            .find(doc! { "severity": mongodb::bson::to_bson(&DomainBlockSeverity::Suspend)? })
+            .await?
+            .map_ok(|block| block.domain)
+            .try_collect()
+            .await?)
+    }
+
+    fn instances(&self) -> Collection<InstanceDocument> {
+        self.database.collection("instances")
+    }
+}
+
+/// Instances running one version of a software, as grouped by the database
+struct SoftwareGroup {
+    software: Option<String>,
+    version: Option<String>,
+    instances: u64,
+    open_registrations: u64,
+}
+
+/// Sum up the groups by software, most common software and versions first
+fn stats_from_groups(groups: Vec<SoftwareGroup>, unreachable: u64) -> FederationStats {
+    let mut stats = FederationStats {
+        unreachable,
+        ..FederationStats::default()
+    };
+    let mut software: HashMap<String, SoftwareStats> = HashMap::new();
+    for group in groups {
+        stats.instances += group.instances;
+        let Some(name) = group.software else {
+            stats.unknown += group.instances;
+            continue;
+        };
+        let entry = software
+            .entry(name.to_lowercase())
+            .or_insert_with_key(|name| SoftwareStats {
+                name: name.clone(),
+                ..SoftwareStats::default()
+            });
+        entry.instances += group.instances;
+        entry.open_registrations += group.open_registrations;
+        let version = group.version.unwrap_or_else(|| "unknown".to_string());
+        match entry.versions.iter_mut().find(|v| v.version == version) {
+            Some(existing) => existing.instances += group.instances,
+            None => entry.versions.push(VersionStats {
+                version,
+                instances: group.instances,
+            }),
+        }
+    }
+    stats.software = software.into_values().collect();
+    stats
+        .software
+        .sort_by(|a, b| b.instances.cmp(&a.instances).then(a.name.cmp(&b.name)));
+    for software in &mut stats.software {
+        software.versions.sort_by(|a, b| {
+            b.instances
+                .cmp(&a.instances)
+                .then(a.version.cmp(&b.version))
+        });
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_threshold_until_cooldown() {
+        let cooldown = chrono::Duration::minutes(30);
+        let mut instance = InstanceDocument {
+            id: None,
+            domain: "down.example".to_string(),
+            first_seen_at: Utc::now(),
+            last_seen_at: None,
+            software: None,
+            version: None,
+            open_registrations: None,
+            crawled_at: None,
+            crawl_error: None,
+            last_delivery_at: None,
+            last_failure_at: Some(Utc::now() - chrono::Duration::minutes(10)),
+            last_error: Some("connection refused".to_string()),
+            consecutive_failures: 9,
+        };
+        assert_eq!(instance.circuit_open_until(10, cooldown), None);
+
+        instance.consecutive_failures = 10;
+        assert!(instance.circuit_open_until(10, cooldown).is_some());
+        assert_eq!(instance.circuit_open_until(0, cooldown), None);
+
+        instance.last_failure_at = Some(Utc::now() - chrono::Duration::minutes(31));
+        assert_eq!(instance.circuit_open_until(10, cooldown), None);
+    }
+
+    #[test]
+    fn test_stats_group_by_software_most_common_first() {
+        let group =
+            |software: Option<&str>, version: Option<&str>, instances, open| SoftwareGroup {
+                software: software.map(str::to_string),
+                version: version.map(str::to_string),
+                instances,
+                open_registrations: open,
+            };
+        let stats = stats_from_groups(
+            vec![
+                group(Some("misskey"), Some("2024.1"), 2, 1),
+                group(Some("mastodon"), Some("4.2.0"), 3, 1),
+                group(Some("Mastodon"), Some("4.3.1"), 4, 2),
+                group(Some("mastodon"), None, 1, 0),
+                group(None, None, 5, 0),
+            ],
+            3,
+        );
+
+        assert_eq!(
+            (stats.instances, stats.unknown, stats.unreachable),
+            (15, 5, 3)
+        );
+        assert_eq!(stats.software[0].name, "mastodon");
+        assert_eq!(
+            (
+                stats.software[0].instances,
+                stats.software[0].open_registrations
+            ),
+            (8, 3)
+        );
+        let versions: Vec<&str> = stats.software[0]
+            .versions
+            .iter()
+            .map(|v| v.version.as_str())
+            .collect();
+        assert_eq!(versions, ["4.3.1", "4.2.0", "unknown"]);
+        assert_eq!(stats.software[1].name, "misskey");
+    }
+}
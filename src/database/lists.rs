@@ -0,0 +1,299 @@
+//! Lists of followed accounts
+//!
+//! A list is a named subset of the accounts a local user follows. Its
+//! timeline shows the posts of its members, with replies limited by the
+//! list's `replies_policy`. Members stay in a list after being unfollowed,
+//! but their posts no longer appear in its timeline.
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager, ObjectDocument, TimelinePage};
+
+/// Replies shown in a list's timeline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListRepliesPolicy {
+    /// Replies to any account the user follows
+    Followed,
+    /// Replies to members of the list
+    #[default]
+    List,
+    /// No replies
+    None,
+}
+
+/// A list of accounts followed by a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// User the list belongs to
+    pub username: String,
+    pub domain: String,
+
+    pub title: String,
+
+    #[serde(default)]
+    pub replies_policy: ListRepliesPolicy,
+
+    /// Whether posts of the members are kept off the home timeline
+    #[serde(default)]
+    pub exclusive: bool,
+
+    /// Actor IDs of the members
+    #[serde(default)]
+    pub accounts: Vec<String>,
+
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DatabaseManager {
+    /// Store a new list
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn insert_list(&self, mut list: ListDocument) -> Result<ListDocument, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        let result = collection.insert_one(&list).await?;
+        list.id = result.inserted_id.as_object_id();
+        Ok(list)
+    }
+
+    /// Lists of a user, oldest first
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_lists(
+        &self,
+        username: &str,
+        domain: &str,
+    ) -> Result<Vec<ListDocument>, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        Ok(collection
+            .find(doc! { "domain": domain, "username": username })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    /// A list of a user
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_list(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+    ) -> Result<Option<ListDocument>, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        Ok(collection
+            .find_one(doc! { "_id": id, "domain": domain, "username": username })
+            .await?)
+    }
+
+    /// Lists of a user that `actor_id` is a member of
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_lists_with_account(
+        &self,
+        username: &str,
+        domain: &str,
+        actor_id: &str,
+    ) -> Result<Vec<ListDocument>, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        Ok(collection
+            .find(doc! { "domain": domain, "username": username, "accounts": actor_id })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    /// Change the title and settings of a list
+    ///
+    /// Returns the updated list, or `None` if the user has no such list.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn update_list(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        title: Option<&str>,
+        replies_policy: Option<ListRepliesPolicy>,
+        exclusive: Option<bool>,
+    ) -> Result<Option<ListDocument>, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        let mut set = doc! { "updated_at": mongodb::bson::to_bson(&Utc::now())? };
+        if let Some(title) = title {
+            set.insert("title", title);
+        }
+        if let Some(replies_policy) = replies_policy {
+            set.insert("replies_policy", mongodb::bson::to_bson(&replies_policy)?);
+        }
+        if let Some(exclusive) = exclusive {
+            set.insert("exclusive", exclusive);
+        }
+        Ok(collection
+            .find_one_and_update(
+                doc! { "_id": id, "domain": domain, "username": username },
+                doc! { "$set": set },
+            )
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    /// Delete a list, returning whether the user had it
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_list(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        let result = collection
+            .delete_one(doc! { "_id": id, "domain": domain, "username": username })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Add members to a list, returning whether the user has the list
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn add_list_accounts(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        actor_ids: &[String],
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        let result = collection
+            .update_one(
+                doc! { "_id": id, "domain": domain, "username": username },
+                doc! {
+                    "$addToSet": { "accounts": { "$each": actor_ids } },
+                    "$set": { "updated_at": mongodb::bson::to_bson(&Utc::now())? },
+                },
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Remove members from a list, returning whether the user has the list
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn remove_list_accounts(
+        &self,
+        username: &str,
+        domain: &str,
+        id: ObjectId,
+        actor_ids: &[String],
+    ) -> Result<bool, DatabaseError> {
+        let collection: Collection<ListDocument> = self.database.collection("lists");
+        let result = collection
+            .update_one(
+                doc! { "_id": id, "domain": domain, "username": username },
+                doc! {
+                    "$pull": { "accounts": { "$in": actor_ids } },
+                    "$set": { "updated_at": mongodb::bson::to_bson(&Utc::now())? },
+                },
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Posts of the members of a list that `actor_id`, its owner, still
+    /// follows, newest first
+    ///
+    /// Followers-only posts are included, direct ones only when addressed
+    /// to the owner. Replies are included as the list's `replies_policy`
+    /// allows; a member's replies to themselves and to the owner always are.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn get_list_timeline(
+        &self,
+        list: &ListDocument,
+        actor_id: &str,
+        page: &TimelinePage,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let following = self.get_actor_following(actor_id).await?;
+        let members: Vec<&String> = list
+            .accounts
+            .iter()
+            .filter(|account| following.contains(account))
+            .collect();
+        if members.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut reply_targets: Vec<&String> = match list.replies_policy {
+            ListRepliesPolicy::Followed => following.iter().collect(),
+            ListRepliesPolicy::List => members.clone(),
+            ListRepliesPolicy::None => Vec::new(),
+        };
+        let owner = actor_id.to_string();
+        reply_targets.push(&owner);
+        self.posts_timeline(&members, reply_targets, &[], actor_id, page)
+            .await
+    }
+
+    /// Posts of `authors` and the posts `added`, as `actor_id` may see them
+    ///
+    /// Replies are left out unless they answer the author's own posts or
+    /// `reply_targets`, or were added.
+    pub(super) async fn posts_timeline(
+        &self,
+        authors: &[&String],
+        reply_targets: Vec<&String>,
+        added: &[ObjectId],
+        actor_id: &str,
+        page: &TimelinePage,
+    ) -> Result<Vec<ObjectDocument>, DatabaseError> {
+        let mut filter = doc! {
+            "status": { "$ne": "scheduled" },
+            "object_type": { "$in": ["Note", "Article", "Question", "Page"] },
+            "$or": [
+                { "attributed_to": { "$in": authors },
+                  "visibility": { "$in": ["public", "unlisted", "followers"] } },
+                { "attributed_to": { "$in": authors }, "visibility": "direct", "to": actor_id },
+                { "attributed_to": { "$in": authors }, "visibility": "direct", "cc": actor_id },
+                { "_id": { "$in": added } },
+            ],
+        };
+        if let Some(range) = page.id_range() {
+            filter.insert("_id", range);
+        }
+        let direction = page.direction();
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "_id": direction } },
+            doc! { "$lookup": {
+                "from": "objects",
+                "localField": "in_reply_to",
+                "foreignField": "object_id",
+                "as": "reply_parent",
+            } },
+            doc! { "$match": { "$or": [
+                { "in_reply_to": null },
+                { "$expr": { "$in": ["$attributed_to", "$reply_parent.attributed_to"] } },
+                { "reply_parent.attributed_to": { "$in": reply_targets } },
+                { "_id": { "$in": added } },
+            ] } },
+            doc! { "$limit": page.limit },
+            doc! { "$project": { "reply_parent": 0 } },
+        ];
+
+        let collection: Collection<ObjectDocument> = self.database.collection("objects");
+        let documents: Vec<Document> = collection.aggregate(pipeline).await?.try_collect().await?;
+        let mut objects = documents
+            .into_iter()
+            .map(mongodb::bson::from_document)
+            .collect::<Result<Vec<ObjectDocument>, _>>()?;
+        if direction == 1 {
+            objects.reverse();
+        }
+        Ok(objects)
+    }
+}
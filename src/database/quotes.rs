@@ -0,0 +1,247 @@
+//! Quote posts
+//!
+//! An object quoting another names it in its `quote` field. Who may quote a
+//! post is its FEP-044f `interactionPolicy.canQuote`: local posts advertise
+//! one following their visibility unless their author set their own in the
+//! post's properties, and the policies of remote posts are kept when they
+//! are stored. Posts without a policy may be quoted if they are public or
+//! unlisted, as Mastodon does. Quotes needing manual approval are not
+//! supported and treated as refused.
+//!
+//! Stored quotes are counted in the `quote_count` of the quoted object;
+//! a quote its author was not allowed to make loses its `quote`, so it is
+//! shown without the quoted post. Deleting or tombstoning a quote recounts
+//! the quotes of the post it quoted.
+
+use mongodb::Collection;
+use mongodb::bson::{Document, doc};
+use serde_json::{Value, json};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager, FollowStatus, ObjectDocument, VisibilityLevel};
+use crate::builder::PUBLIC;
+use crate::extensions::Extensions;
+
+/// Outcome of recording a stored quote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteOutcome {
+    /// The quote was counted on the quoted object
+    Counted,
+    /// The quoted object is not stored
+    Unknown,
+    /// The author may not quote the object; the quote was removed
+    Refused,
+}
+
+impl ObjectDocument {
+    /// FEP-044f `canQuote` policy of the object
+    pub fn quote_policy(&self) -> Value {
+        let stored = self
+            .additional_properties
+            .as_ref()
+            .and_then(|properties| properties.get_document("interactionPolicy").ok())
+            .and_then(|policy| mongodb::bson::from_document::<Value>(policy.clone()).ok())
+            .and_then(|policy| policy.get("canQuote").cloned());
+        stored.unwrap_or_else(|| {
+            let approved = match self.visibility {
+                VisibilityLevel::Public | VisibilityLevel::Unlisted => PUBLIC,
+                VisibilityLevel::Followers | VisibilityLevel::Direct => &self.attributed_to,
+            };
+            json!({ "automaticApproval": [approved] })
+        })
+    }
+
+    /// Write the quoted object and the quote policy into the ActivityStreams
+    /// JSON of the object
+    pub fn write_quote_properties(&self, object: &mut Value) {
+        Extensions {
+            quote: self.quote.as_deref().and_then(|url| url.parse().ok()),
+            ..Default::default()
+        }
+        .write_json(object);
+        object["interactionPolicy"] = json!({ "canQuote": self.quote_policy() });
+    }
+}
+
+impl DatabaseManager {
+    /// Whether `actor_id` may quote `quoted` without approval
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn may_quote(
+        &self,
+        quoted: &ObjectDocument,
+        actor_id: &str,
+    ) -> Result<bool, DatabaseError> {
+        if quoted.attributed_to == actor_id {
+            return Ok(true);
+        }
+        let policy = quoted.quote_policy();
+        let approved = automatic_approval(&policy);
+        if approved
+            .iter()
+            .any(|id| *id == actor_id || matches!(*id, PUBLIC | "as:Public" | "Public"))
+        {
+            return Ok(true);
+        }
+        if approved.is_empty() {
+            return Ok(false);
+        }
+
+        // Followers of the author are approved through their collection
+        let Some(author) = self.find_actor_by_id(&quoted.attributed_to).await? else {
+            return Ok(false);
+        };
+        if !approved.contains(&author.followers.as_str()) {
+            return Ok(false);
+        }
+        Ok(self
+            .find_follow(actor_id, &author.actor_id)
+            .await?
+            .is_some_and(|follow| follow.status == FollowStatus::Accepted))
+    }
+
+    /// Count a stored quote on the object it quotes, or remove the quote if
+    /// its author may not quote the object
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn record_quote(
+        &self,
+        object: &ObjectDocument,
+    ) -> Result<QuoteOutcome, DatabaseError> {
+        let Some(quote) = &object.quote else {
+            return Ok(QuoteOutcome::Unknown);
+        };
+        let Some(quoted) = self.find_object_by_id(quote).await? else {
+            return Ok(QuoteOutcome::Unknown);
+        };
+
+        let objects: Collection<ObjectDocument> = self.database.collection("objects");
+        if self.may_quote(&quoted, &object.attributed_to).await? {
+            objects
+                .update_one(
+                    doc! { "object_id": quote },
+                    doc! { "$inc": { "quote_count": 1 } },
+                )
+                .await?;
+            Ok(QuoteOutcome::Counted)
+        } else {
+            objects
+                .update_one(
+                    doc! { "object_id": &object.object_id },
+                    doc! { "$unset": { "quote": "" } },
+                )
+                .await?;
+            Ok(QuoteOutcome::Refused)
+        }
+    }
+}
+
+impl DatabaseManager {
+    /// Posts quoted by the objects matching `filter`
+    pub(super) async fn quoted_by(
+        &self,
+        mut filter: Document,
+    ) -> Result<Vec<String>, DatabaseError> {
+        filter.insert("quote", doc! { "$type": "string" });
+        let quoted = self
+            .database
+            .collection::<Document>("objects")
+            .distinct("quote", filter)
+            .await?;
+        Ok(quoted
+            .into_iter()
+            .filter_map(|quote| quote.as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Set the `quote_count` of posts to the number of their stored quotes
+    /// that are not Tombstones
+    pub(super) async fn recount_quotes(&self, quoted: &[String]) -> Result<(), DatabaseError> {
+        let objects: Collection<ObjectDocument> = self.database.collection("objects");
+        for quote in quoted {
+            let count = objects
+                .count_documents(doc! {
+                    "quote": quote,
+                    "object_type": { "$ne": "Tombstone" },
+                })
+                .await?;
+            objects
+                .update_one(
+                    doc! { "object_id": quote },
+                    doc! { "$set": { "quote_count": count as i64 } },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Actors and collections a `canQuote` policy approves automatically
+///
+/// GoToSocial names the list `always`, FEP-044f `automaticApproval`.
+fn automatic_approval(policy: &Value) -> Vec<&str> {
+    ["automaticApproval", "always"]
+        .iter()
+        .flat_map(|key| match &policy[*key] {
+            Value::String(id) => vec![id.as_str()],
+            Value::Array(ids) => ids.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(visibility: &str) -> ObjectDocument {
+        ObjectDocument::from_activitypub(
+            &json!({
+                "type": "Note",
+                "id": "https://example.com/notes/1",
+                "attributedTo": "https://example.com/users/alice",
+                "to": [visibility]
+            }),
+            crate::ObjectType::Note,
+        )
+    }
+
+    #[test]
+    fn test_quote_policy_follows_visibility() {
+        let public = note(PUBLIC);
+        assert_eq!(automatic_approval(&public.quote_policy()), vec![PUBLIC]);
+        let private = note("https://example.com/users/alice/followers");
+        assert_eq!(
+            automatic_approval(&private.quote_policy()),
+            vec!["https://example.com/users/alice"]
+        );
+
+        // A remote policy replaces the default
+        let restricted = ObjectDocument::from_activitypub(
+            &json!({
+                "type": "Note",
+                "id": "https://remote.example/notes/1",
+                "attributedTo": "https://remote.example/users/bob",
+                "to": [PUBLIC],
+                "interactionPolicy": {
+                    "canQuote": { "always": ["https://remote.example/users/bob/followers"] }
+                },
+                "quoteUrl": "https://example.com/notes/1"
+            }),
+            crate::ObjectType::Note,
+        );
+        assert_eq!(
+            automatic_approval(&restricted.quote_policy()),
+            vec!["https://remote.example/users/bob/followers"]
+        );
+        assert_eq!(
+            restricted.quote.as_deref(),
+            Some("https://example.com/notes/1")
+        );
+
+        let rendered = restricted.to_activitypub();
+        assert_eq!(rendered["quote"], "https://example.com/notes/1");
+        assert_eq!(
+            rendered["interactionPolicy"]["canQuote"]["always"][0],
+            "https://remote.example/users/bob/followers"
+        );
+    }
+}
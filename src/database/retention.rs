@@ -0,0 +1,188 @@
+//! Pruning of remote content
+//!
+//! Remote posts and activities are copies of what their servers keep, so
+//! old ones can be dropped as long as no local account depends on them.
+//! The queries here find candidates in storage order and tell which of
+//! them local accounts interacted with; domainservd's `retention` module
+//! decides what to prune.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{Document, doc, oid::ObjectId};
+use tracing::instrument;
+
+use super::{DatabaseError, DatabaseManager, VisibilityLevel};
+use crate::{ActivityType, ObjectType};
+
+/// Activities kept regardless of age because an `Undo` may refer to them
+const UNDOABLE_ACTIVITY_TYPES: [ActivityType; 4] = [
+    ActivityType::Follow,
+    ActivityType::Like,
+    ActivityType::Announce,
+    ActivityType::Block,
+];
+
+/// Local activities that keep the remote post they refer to
+const INTERACTION_ACTIVITY_TYPES: [ActivityType; 2] = [ActivityType::Like, ActivityType::Announce];
+
+impl DatabaseManager {
+    /// Remote posts published before `before`, in storage order after `after`
+    ///
+    /// Posts without a publication date count from when they were stored.
+    /// Returns the storage IDs with the ActivityPub IDs.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn find_remote_objects_before(
+        &self,
+        before: DateTime<Utc>,
+        visibility: &[VisibilityLevel],
+        after: Option<ObjectId>,
+        limit: i64,
+    ) -> Result<Vec<(ObjectId, String)>, DatabaseError> {
+        let before = mongodb::bson::to_bson(&before)?;
+        let mut filter = doc! {
+            "local": false,
+            "object_type": { "$ne": mongodb::bson::to_bson(&ObjectType::Tombstone)? },
+            "$or": [
+                { "published": { "$lt": &before } },
+                { "published": null, "created_at": { "$lt": &before } },
+            ],
+        };
+        if !visibility.is_empty() {
+            filter.insert(
+                "visibility",
+                doc! { "$in": mongodb::bson::to_bson(visibility)? },
+            );
+        }
+        if let Some(after) = after {
+            filter.insert("_id", doc! { "$gt": after });
+        }
+
+        let collection: Collection<Document> = self.database.collection("objects");
+        let documents: Vec<Document> = collection
+            .find(filter)
+            .projection(doc! { "_id": 1, "object_id": 1 })
+            .sort(doc! { "_id": 1 })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(documents
+            .iter()
+            .filter_map(|document| {
+                Some((
+                    document.get_object_id("_id").ok()?,
+                    document.get_str("object_id").ok()?.to_string(),
+                ))
+            })
+            .collect())
+    }
+
+    /// The objects among `object_ids` that local accounts interacted with
+    ///
+    /// A post counts as interacted with when a local account liked,
+    /// announced or replied to it, or was mentioned in it.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn locally_referenced_objects(
+        &self,
+        object_ids: &[String],
+    ) -> Result<HashSet<String>, DatabaseError> {
+        let mut referenced = HashSet::new();
+        if object_ids.is_empty() {
+            return Ok(referenced);
+        }
+
+        for (collection, field, filter) in [
+            (
+                "activities",
+                "object",
+                doc! {
+                    "local": true,
+                    "activity_type": { "$in": mongodb::bson::to_bson(&INTERACTION_ACTIVITY_TYPES)? },
+                    "object": { "$in": object_ids },
+                },
+            ),
+            (
+                "objects",
+                "in_reply_to",
+                doc! { "local": true, "in_reply_to": { "$in": object_ids } },
+            ),
+            (
+                "notifications",
+                "object_id",
+                doc! { "object_id": { "$in": object_ids } },
+            ),
+        ] {
+            let values = self
+                .database
+                .collection::<Document>(collection)
+                .distinct(field, filter)
+                .await?;
+            referenced.extend(
+                values
+                    .into_iter()
+                    .filter_map(|value| value.as_str().map(str::to_string)),
+            );
+        }
+        Ok(referenced)
+    }
+
+    /// Delete remote objects with their revisions, home feed entries and
+    /// boosts, recounting the quotes of the posts they quoted
+    ///
+    /// Returns the number of objects deleted.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_remote_objects(&self, object_ids: &[String]) -> Result<u64, DatabaseError> {
+        if object_ids.is_empty() {
+            return Ok(0);
+        }
+        let objects: Collection<Document> = self.database.collection("objects");
+        let filter = doc! { "local": false, "object_id": { "$in": object_ids } };
+        let quoted = self.quoted_by(filter.clone()).await?;
+        let deleted = objects.delete_many(filter).await?;
+        self.recount_quotes(&quoted).await?;
+        for collection in ["object_revisions", "home_feed", "boosts"] {
+            self.database
+                .collection::<Document>(collection)
+                .delete_many(doc! { "object_id": { "$in": object_ids } })
+                .await?;
+        }
+        Ok(deleted.deleted_count)
+    }
+
+    /// Delete up to `limit` remote activities stored before `before`
+    ///
+    /// Follows, Likes, Announces and Blocks are kept so that a later `Undo`
+    /// still finds them. Returns the number of activities deleted.
+    #[instrument(skip_all, fields(db.system = "mongodb"))]
+    pub async fn delete_remote_activities_before(
+        &self,
+        before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<u64, DatabaseError> {
+        let collection: Collection<Document> = self.database.collection("activities");
+        let ids: Vec<ObjectId> = collection
+            .find(doc! {
+                "local": false,
+                "activity_type": { "$nin": mongodb::bson::to_bson(&UNDOABLE_ACTIVITY_TYPES)? },
+                "created_at": { "$lt": mongodb::bson::to_bson(&before)? },
+            })
+            .projection(doc! { "_id": 1 })
+            .limit(limit)
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .iter()
+            .filter_map(|document| document.get_object_id("_id").ok())
+            .collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let deleted = collection
+            .delete_many(doc! { "_id": { "$in": ids } })
+            .await?;
+        Ok(deleted.deleted_count)
+    }
+}
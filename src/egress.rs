@@ -0,0 +1,495 @@
+//! Egress policy for outbound requests
+//!
+//! Servers fetch URLs that remote parties choose: actor and object IDs,
+//! inboxes, media, WebFinger hosts. The egress policy keeps such requests
+//! off the server's own network. Hosts resolving to loopback, private,
+//! link-local and other non-public addresses are refused unless allowed,
+//! and hosts can be denied outright.
+//!
+//! Addresses are checked by the DNS resolver of the HTTP client, so the
+//! addresses checked are the ones connected to; a host cannot pass the
+//! check with a public address and then rebind to a private one. IP
+//! literals are checked before the request is sent and on every redirect.
+//!
+//! Requests can go through an HTTP or SOCKS5 proxy. The proxy resolves the
+//! hosts it connects to, so only IP literals and the host lists are checked
+//! here; the proxy is expected to enforce its own egress rules.
+//!
+//! Daemons [`install`](EgressPolicy::install) their configured policy at
+//! startup, and clients created afterwards follow it.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{ClientBuilder, Proxy, redirect};
+use serde::Deserialize;
+use url::{Host, Url};
+
+use crate::config::{Config, ConfigError, Env};
+
+/// Redirects followed per request, as by reqwest's default policy
+const MAX_REDIRECTS: usize = 10;
+
+/// Policy installed by the daemon
+static INSTALLED: OnceLock<EgressPolicy> = OnceLock::new();
+
+/// Refusal of an outbound request
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum EgressError {
+    #[error("{0} is denied by the egress policy")]
+    DeniedHost(String),
+
+    #[error("{0} resolves to a non-public address")]
+    ForbiddenAddress(String),
+
+    #[error("Unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+
+    #[error("Failed to resolve {0}")]
+    ResolveFailed(String),
+}
+
+/// Which hosts outbound requests may reach, and through which proxy
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EgressPolicy {
+    /// Contact hosts on non-public addresses, e.g. for local development
+    pub allow_private: bool,
+    /// Hosts contacted even on non-public addresses, with their subdomains
+    pub allowed_hosts: Vec<String>,
+    /// Hosts never contacted, with their subdomains
+    pub denied_hosts: Vec<String>,
+    /// Proxy for all outbound requests: an `http`, `https`, `socks5` or
+    /// `socks5h` URL
+    pub proxy: Option<String>,
+}
+
+impl EgressPolicy {
+    /// Make this the policy of clients created from now on
+    ///
+    /// Only the first policy installed takes effect.
+    pub fn install(self) {
+        if INSTALLED.set(self).is_err() {
+            tracing::warn!("An egress policy is already installed, keeping it");
+        }
+    }
+
+    /// The installed policy, or the default one that refuses non-public
+    /// addresses
+    pub fn installed() -> Self {
+        INSTALLED.get().cloned().unwrap_or_default()
+    }
+
+    /// Policy that also reaches non-public addresses, for tools run against
+    /// local instances and for tests
+    pub fn allowing_private() -> Self {
+        Self {
+            allow_private: true,
+            ..Default::default()
+        }
+    }
+
+    /// Apply `EGRESS_ALLOW_PRIVATE`, `EGRESS_ALLOWED_HOSTS`,
+    /// `EGRESS_DENIED_HOSTS` (comma-separated) and `EGRESS_PROXY`
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("EGRESS_ALLOW_PRIVATE", &mut self.allow_private)?;
+        for (name, hosts) in [
+            ("EGRESS_ALLOWED_HOSTS", &mut self.allowed_hosts),
+            ("EGRESS_DENIED_HOSTS", &mut self.denied_hosts),
+        ] {
+            if let Some(value) = env.get(name) {
+                *hosts = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|host| !host.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+        env.set_opt("EGRESS_PROXY", &mut self.proxy)
+    }
+
+    /// Check the proxy URL, reporting errors under `key`
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if let Some(proxy) = &self.proxy {
+            let url = Url::parse(proxy)
+                .map_err(|e| ConfigError::invalid(format!("{}.proxy", key), e.to_string()))?;
+            if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                return Err(ConfigError::invalid(
+                    format!("{}.proxy", key),
+                    "must be an http, https, socks5 or socks5h URL",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Route a client's requests through the proxy and check the addresses
+    /// it connects to
+    ///
+    /// Redirect targets are checked with [`check_url`](Self::check_url)
+    /// before they are followed, so a redirect to an IP literal is refused
+    /// like a request to it. Clients following redirects themselves set
+    /// their own redirect policy after this one.
+    pub fn configure(&self, builder: ClientBuilder) -> reqwest::Result<ClientBuilder> {
+        let policy = self.clone();
+        let mut builder = builder
+            .dns_resolver(Arc::new(PolicyResolver {
+                policy: self.clone(),
+            }))
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if let Err(e) = policy.check_url(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            }));
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        Ok(builder)
+    }
+
+    /// Check a URL before requesting it
+    ///
+    /// Hosts given by name have their addresses checked when they are
+    /// resolved.
+    pub fn check_url(&self, url: &Url) -> Result<(), EgressError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(EgressError::UnsupportedScheme(url.scheme().to_string()));
+        }
+        let host = url.host_str().unwrap_or_default();
+        if self.denies(host) {
+            return Err(EgressError::DeniedHost(host.to_string()));
+        }
+        let ip: IpAddr = match url.host() {
+            Some(Host::Ipv4(ip)) => ip.into(),
+            Some(Host::Ipv6(ip)) => ip.into(),
+            Some(Host::Domain(_)) => return Ok(()),
+            None => return Err(EgressError::ResolveFailed(url.to_string())),
+        };
+        self.check_addrs(host, &[SocketAddr::new(ip, 0)])
+    }
+
+    /// Check the addresses a host resolved to, refusing it unless every
+    /// address is public or the host is allowed
+    fn check_addrs(&self, host: &str, addrs: &[SocketAddr]) -> Result<(), EgressError> {
+        if addrs.is_empty() {
+            return Err(EgressError::ResolveFailed(host.to_string()));
+        }
+        if self.allow_private
+            || matches_any(&self.allowed_hosts, host)
+            || self.is_proxy(host)
+            || addrs.iter().all(|addr| is_public_ip(addr.ip()))
+        {
+            Ok(())
+        } else {
+            Err(EgressError::ForbiddenAddress(host.to_string()))
+        }
+    }
+
+    /// Whether requests to `host` are refused outright
+    fn denies(&self, host: &str) -> bool {
+        matches_any(&self.denied_hosts, host)
+    }
+
+    /// Whether `host` is the proxy, which may well be on the local network
+    fn is_proxy(&self, host: &str) -> bool {
+        self.proxy
+            .as_deref()
+            .and_then(|proxy| Url::parse(proxy).ok())
+            .is_some_and(|proxy| proxy.host_str() == Some(host))
+    }
+}
+
+/// Egress settings of daemons that are configured by environment alone
+impl Config for EgressPolicy {
+    fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        EgressPolicy::apply_env(self, env)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        EgressPolicy::validate(self, "egress")
+    }
+}
+
+/// Whether `host` is one of `hosts` or a subdomain of one
+fn matches_any(hosts: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    hosts.iter().any(|entry| {
+        let entry = entry.trim_start_matches("*.").to_ascii_lowercase();
+        host == entry
+            || host
+                .strip_suffix(entry.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+/// DNS resolver checking the addresses of each host against the policy
+struct PolicyResolver {
+    policy: EgressPolicy,
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            if policy.denies(host) {
+                return Err(EgressError::DeniedHost(host.to_string()).into());
+            }
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|_| EgressError::ResolveFailed(host.to_string()))?
+                .collect();
+            policy.check_addrs(host, &addrs)?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Egress refusal behind a failed request, if that is why it failed
+pub fn refusal(error: &reqwest::Error) -> Option<EgressError> {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        if let Some(refusal) = error.downcast_ref::<EgressError>() {
+            return Some(refusal.clone());
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// Whether `ip` is reachable on the public internet
+///
+/// Loopback, private, link-local (which includes cloud metadata services),
+/// shared, documentation, multicast and reserved ranges are not. IPv6
+/// addresses embedding an IPv4 address are judged by that address.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && b & 0xc0 == 64)
+                || (a == 198 && b & 0xfe == 18))
+        }
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_public_ip(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_multicast()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+                    || first & 0xffc0 == 0xfec0)
+            }
+        },
+    }
+}
+
+/// IPv4 address embedded in an IPv4-mapped (`::ffff:0:0/96`),
+/// IPv4-compatible (`::/96`, which includes `::` and `::1`), NAT64
+/// (`64:ff9b::/96`) or 6to4 (`2002::/16`) address
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [s0, s1, s2, .., s6, s7] = ip.segments();
+    let from = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match s0 {
+        0x2002 => Some(from(s1, s2)),
+        0x0064 if ip.segments()[1..6] == [0xff9b, 0, 0, 0, 0] => Some(from(s6, s7)),
+        _ => ip.to_ipv4(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_reserved_ranges_are_not_public() {
+        for ip in ["240.0.0.1", "254.1.2.3", "255.255.255.255"] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(is_public_ip("223.255.255.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_site_local_is_not_public() {
+        for ip in ["fec0::1", "feff:ffff::1"] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(is_public_ip("2606:4700::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_nat64_is_judged_by_its_ipv4_address() {
+        assert!(!is_public_ip("64:ff9b::7f00:1".parse().unwrap()));
+        assert!(!is_public_ip("64:ff9b::a9fe:a9fe".parse().unwrap()));
+        assert!(is_public_ip("64:ff9b::5db8:d822".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_6to4_is_judged_by_its_ipv4_address() {
+        assert!(!is_public_ip("2002:7f00:1::1".parse().unwrap()));
+        assert!(!is_public_ip("2002:a00:1::".parse().unwrap()));
+        assert!(!is_public_ip("2002:a9fe:a9fe::1".parse().unwrap()));
+        assert!(is_public_ip("2002:5db8:d822::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_ipv4_compatible_is_judged_by_its_ipv4_address() {
+        for ip in [
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "::169.254.169.254",
+            "::",
+            "::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(is_public_ip("::93.184.216.34".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_check_url() {
+        let policy = EgressPolicy {
+            allowed_hosts: vec!["relay.internal".to_string()],
+            denied_hosts: vec!["*.evil.example".to_string()],
+            ..Default::default()
+        };
+        let check = |url: &str| policy.check_url(&Url::parse(url).unwrap());
+
+        assert!(check("https://remote.example/users/bob").is_ok());
+        assert!(matches!(
+            check("http://127.0.0.1:8080/users/bob"),
+            Err(EgressError::ForbiddenAddress(_))
+        ));
+        assert!(matches!(
+            check("http://[::1]/inbox"),
+            Err(EgressError::ForbiddenAddress(_))
+        ));
+        assert!(matches!(
+            check("https://evil.example/users/bob"),
+            Err(EgressError::DeniedHost(_))
+        ));
+        assert!(matches!(
+            check("https://cdn.EVIL.example/media"),
+            Err(EgressError::DeniedHost(_))
+        ));
+        assert!(check("https://notevil.example/users/bob").is_ok());
+        assert!(matches!(
+            check("file:///etc/passwd"),
+            Err(EgressError::UnsupportedScheme(_))
+        ));
+
+        let addrs = [SocketAddr::new("10.0.0.5".parse().unwrap(), 0)];
+        assert!(policy.check_addrs("relay.internal", &addrs).is_ok());
+        assert!(policy.check_addrs("remote.example", &addrs).is_err());
+        assert!(policy.check_addrs("remote.example", &[]).is_err());
+
+        let policy = EgressPolicy {
+            allow_private: true,
+            ..Default::default()
+        };
+        assert!(
+            policy
+                .check_url(&Url::parse("http://127.0.0.1:8080/").unwrap())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_apply_env_and_validate() {
+        let mut policy = EgressPolicy::default();
+        let env = Env::from_vars([
+            ("EGRESS_DENIED_HOSTS", "evil.example, spam.example"),
+            ("EGRESS_PROXY", "socks5h://proxy.internal:1080"),
+        ]);
+        policy.apply_env(&env).unwrap();
+        assert_eq!(policy.denied_hosts, ["evil.example", "spam.example"]);
+        assert!(policy.validate("egress").is_ok());
+        assert!(policy.is_proxy("proxy.internal"));
+
+        policy.proxy = Some("ftp://proxy.internal".to_string());
+        assert!(policy.validate("egress").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_refuses_private_hosts() {
+        let client = EgressPolicy::default()
+            .configure(reqwest::Client::builder())
+            .unwrap()
+            .build()
+            .unwrap();
+        let error = client.get("http://localhost:9/").send().await.unwrap_err();
+        assert!(matches!(
+            refusal(&error),
+            Some(EgressError::ForbiddenAddress(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_checked() {
+        let mut server = mockito::Server::new_async().await;
+        let _redirect = server
+            .mock("GET", "/metadata")
+            .with_status(302)
+            .with_header("location", "http://169.254.169.254/latest/meta-data/")
+            .create_async()
+            .await;
+        let policy = EgressPolicy {
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        };
+        let client = policy
+            .configure(reqwest::Client::builder())
+            .unwrap()
+            .build()
+            .unwrap();
+        let error = client
+            .get(format!("{}/metadata", server.url()))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(error.is_redirect());
+        assert!(matches!(
+            refusal(&error),
+            Some(EgressError::ForbiddenAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,495 @@
+//! Typed extension vocabulary
+//!
+//! Most fediverse software relies on a handful of terms outside the
+//! ActivityStreams core: Mastodon's `toot:` namespace, schema.org's
+//! `PropertyValue` for profile fields and a few `as:` terms that never made
+//! it into the specification. The core types keep such properties in
+//! `additional_properties`; [`Extensions`] reads the widely used ones into
+//! typed fields and writes them back, so handlers don't look them up by
+//! string key.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use url::Url;
+
+use crate::Object;
+
+/// Mastodon extension namespace
+pub const TOOT_NS: &str = "http://joinmastodon.org/ns#";
+
+/// Pleroma and Akkoma extension namespace
+pub const LITEPUB_NS: &str = "http://litepub.social/ns#";
+
+/// schema.org namespace, used for profile fields
+pub const SCHEMA_NS: &str = "http://schema.org#";
+
+/// Fedibird extension namespace, used for `quoteUri`
+pub const FEDIBIRD_NS: &str = "http://fedibird.com/ns#";
+
+/// Misskey extension namespace
+pub const MISSKEY_NS: &str = "https://misskey-hub.net/ns#";
+
+/// Mobilizon extension namespace, used for events
+pub const MOBILIZON_NS: &str = "https://joinmobilizon.org/ns#";
+
+/// Media types of a FEP-e232 `Link` tag pointing to an ActivityStreams object
+const OBJECT_LINK_MEDIA_TYPES: [&str; 2] = [
+    "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+    "application/activity+json",
+];
+
+/// JSON-LD context entry defining the extension terms
+///
+/// Meant to follow `https://www.w3.org/ns/activitystreams` in an `@context`
+/// array.
+pub fn context() -> Value {
+    json!({
+        "toot": TOOT_NS,
+        "litepub": LITEPUB_NS,
+        "schema": SCHEMA_NS,
+        "fedibird": FEDIBIRD_NS,
+        "misskey": MISSKEY_NS,
+        "mz": MOBILIZON_NS,
+        "sensitive": "as:sensitive",
+        "manuallyApprovesFollowers": "as:manuallyApprovesFollowers",
+        "Hashtag": "as:Hashtag",
+        "discoverable": "toot:discoverable",
+        "indexable": "toot:indexable",
+        "featured": {
+            "@id": "toot:featured",
+            "@type": "@id"
+        },
+        "alsoKnownAs": {
+            "@id": "as:alsoKnownAs",
+            "@type": "@id"
+        },
+        "votersCount": "toot:votersCount",
+        "quote": {
+            "@id": "https://w3id.org/fep/044f#quote",
+            "@type": "@id"
+        },
+        "quoteUrl": "as:quoteUrl",
+        "quoteUri": "fedibird:quoteUri",
+        "_misskey_quote": "misskey:_misskey_quote",
+        "gts": crate::compat::GOTOSOCIAL_NS,
+        "interactionPolicy": {
+            "@id": "gts:interactionPolicy",
+            "@type": "@id"
+        },
+        "canQuote": {
+            "@id": "gts:canQuote",
+            "@type": "@id"
+        },
+        "automaticApproval": {
+            "@id": "gts:automaticApproval",
+            "@type": "@id"
+        },
+        "joinMode": "mz:joinMode",
+        "participantCount": "mz:participantCount",
+        "timezone": "mz:timezone",
+        "maximumAttendeeCapacity": "schema:maximumAttendeeCapacity",
+        "remainingAttendeeCapacity": "schema:remainingAttendeeCapacity",
+        "participants": {
+            "@id": "schema:attendee",
+            "@type": "@id"
+        },
+        "PostalAddress": "schema:PostalAddress",
+        "address": {
+            "@id": "schema:address",
+            "@type": "schema:PostalAddress"
+        },
+        "streetAddress": "schema:streetAddress",
+        "addressLocality": "schema:addressLocality",
+        "addressRegion": "schema:addressRegion",
+        "postalCode": "schema:postalCode",
+        "addressCountry": "schema:addressCountry",
+        "PropertyValue": "schema:PropertyValue",
+        "value": "schema:value"
+    })
+}
+
+/// Profile field (`schema:PropertyValue` attachment)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyValue {
+    pub name: String,
+    /// HTML value of the field
+    pub value: String,
+}
+
+impl PropertyValue {
+    fn to_json(&self) -> Value {
+        json!({
+            "type": "PropertyValue",
+            "name": self.name,
+            "value": self.value
+        })
+    }
+}
+
+/// Hashtag (`as:Hashtag` tag)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hashtag {
+    /// Tag name including the leading `#`
+    pub name: String,
+    /// Page listing the tagged objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub href: Option<Url>,
+}
+
+impl Hashtag {
+    fn to_json(&self) -> Value {
+        let mut tag = json!({
+            "type": "Hashtag",
+            "name": self.name
+        });
+        if let Some(href) = &self.href {
+            tag["href"] = json!(href);
+        }
+        tag
+    }
+}
+
+/// Widely used extension properties of an object or actor
+///
+/// Every field is optional; absent or malformed properties are `None` or
+/// empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extensions {
+    /// `as:sensitive`: the content should be hidden behind a warning
+    pub sensitive: Option<bool>,
+    /// `as:manuallyApprovesFollowers`: follow requests need approval
+    pub manually_approves_followers: Option<bool>,
+    /// `toot:discoverable`: the actor may be listed in directories
+    pub discoverable: Option<bool>,
+    /// `toot:indexable`: the actor's public posts may be found by searches
+    pub indexable: Option<bool>,
+    /// `toot:featured`: collection of pinned objects
+    pub featured: Option<Url>,
+    /// `PropertyValue` entries of `attachment`
+    pub property_values: Vec<PropertyValue>,
+    /// `Hashtag` entries of `tag`
+    pub hashtags: Vec<Hashtag>,
+    /// `toot:votersCount`: number of people who voted in a poll
+    pub voters_count: Option<u64>,
+    /// Object quoted by this one, from FEP-044f `quote`, the older
+    /// `quoteUrl`, `quoteUri` and `_misskey_quote`, or a FEP-e232 `Link`
+    /// tag
+    pub quote: Option<Url>,
+}
+
+impl Extensions {
+    /// Read the extensions from an ActivityStreams JSON object
+    pub fn from_json(value: &Value) -> Self {
+        Self::read(|key| value.get(key))
+    }
+
+    /// Read the extensions from the additional properties of a core type
+    pub fn from_properties(properties: &HashMap<String, Value>) -> Self {
+        Self::read(|key| properties.get(key))
+    }
+
+    fn read<'a>(get: impl Fn(&str) -> Option<&'a Value>) -> Self {
+        let bool_of = |key| get(key).and_then(Value::as_bool);
+
+        Self {
+            sensitive: bool_of("sensitive"),
+            manually_approves_followers: bool_of("manuallyApprovesFollowers"),
+            discoverable: bool_of("discoverable"),
+            indexable: bool_of("indexable"),
+            featured: get("featured")
+                .and_then(Value::as_str)
+                .and_then(|url| Url::parse(url).ok()),
+            property_values: entries_of_type(get("attachment"), "PropertyValue")
+                .filter_map(|entry| serde_json::from_value(entry.clone()).ok())
+                .collect(),
+            hashtags: entries_of_type(get("tag"), "Hashtag")
+                .filter_map(|entry| serde_json::from_value(entry.clone()).ok())
+                .collect(),
+            voters_count: get("votersCount").and_then(Value::as_u64),
+            quote: read_quote(&get),
+        }
+    }
+
+    /// Write the extensions into an ActivityStreams JSON object
+    ///
+    /// Set fields replace the existing properties; `PropertyValue`
+    /// attachments and `Hashtag` tags replace the entries of that type and
+    /// keep all others, such as media attachments and mentions. Does
+    /// nothing if `value` is not an object.
+    pub fn write_json(&self, value: &mut Value) {
+        if let Value::Object(map) = value {
+            self.write(map);
+        }
+    }
+
+    /// Write the extensions into the additional properties of a core type
+    ///
+    /// See [`Extensions::write_json`].
+    pub fn write_properties(&self, properties: &mut HashMap<String, Value>) {
+        let mut map: Map<String, Value> = properties.drain().collect();
+        self.write(&mut map);
+        properties.extend(map);
+    }
+
+    fn write(&self, map: &mut Map<String, Value>) {
+        let mut set = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                map.insert(key.to_string(), value);
+            }
+        };
+        set("sensitive", self.sensitive.map(Value::from));
+        set(
+            "manuallyApprovesFollowers",
+            self.manually_approves_followers.map(Value::from),
+        );
+        set("discoverable", self.discoverable.map(Value::from));
+        set("indexable", self.indexable.map(Value::from));
+        set("featured", self.featured.as_ref().map(|url| json!(url)));
+        set("votersCount", self.voters_count.map(Value::from));
+
+        let property_values = self.property_values.iter().map(PropertyValue::to_json);
+        replace_entries(map, "attachment", "PropertyValue", property_values);
+        let hashtags = self.hashtags.iter().map(Hashtag::to_json);
+        replace_entries(map, "tag", "Hashtag", hashtags);
+
+        // Software reads quotes from different properties, so all are set
+        if let Some(quote) = &self.quote {
+            for key in ["quote", "quoteUrl", "quoteUri", "_misskey_quote"] {
+                map.insert(key.to_string(), json!(quote));
+            }
+            let mut tags: Vec<Value> = match map.remove("tag") {
+                Some(Value::Array(items)) => items,
+                Some(Value::Null) | None => Vec::new(),
+                Some(item) => vec![item],
+            };
+            tags.retain(|tag| object_link(tag).is_none());
+            tags.push(json!({
+                "type": "Link",
+                "mediaType": OBJECT_LINK_MEDIA_TYPES[0],
+                "href": quote,
+                "name": format!("RE: {}", quote)
+            }));
+            map.insert("tag".to_string(), Value::Array(tags));
+        }
+    }
+}
+
+/// Quoted object of an object, in order of preference of its properties
+fn read_quote<'a>(get: &impl Fn(&str) -> Option<&'a Value>) -> Option<Url> {
+    let url_of = |value: &Value| {
+        value
+            .as_str()
+            .or_else(|| value.get("id")?.as_str())
+            .and_then(|url| Url::parse(url).ok())
+    };
+    ["quote", "quoteUrl", "quoteUri", "_misskey_quote"]
+        .into_iter()
+        .find_map(|key| get(key).and_then(url_of))
+        .or_else(|| entries_of_type(get("tag"), "Link").find_map(object_link))
+}
+
+/// Target of a FEP-e232 `Link` tag pointing to an ActivityStreams object
+fn object_link(tag: &Value) -> Option<Url> {
+    if tag.get("type").and_then(Value::as_str) != Some("Link") {
+        return None;
+    }
+    let media_type = tag.get("mediaType")?.as_str()?;
+    if !OBJECT_LINK_MEDIA_TYPES.contains(&media_type) {
+        return None;
+    }
+    Url::parse(tag.get("href")?.as_str()?).ok()
+}
+
+impl Object {
+    /// Typed extension properties of this object
+    pub fn extensions(&self) -> Extensions {
+        Extensions::from_properties(&self.additional_properties)
+    }
+
+    /// Store extension properties on this object
+    pub fn set_extensions(&mut self, extensions: &Extensions) {
+        extensions.write_properties(&mut self.additional_properties);
+    }
+}
+
+/// Entries of a single value or array property with the given `type`
+fn entries_of_type<'a>(
+    value: Option<&'a Value>,
+    entry_type: &'a str,
+) -> impl Iterator<Item = &'a Value> {
+    let entries = match value {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(item @ Value::Object(_)) => vec![item],
+        _ => Vec::new(),
+    };
+    entries
+        .into_iter()
+        .filter(move |entry| entry.get("type").and_then(Value::as_str) == Some(entry_type))
+}
+
+/// Replace the entries of `entry_type` in an array property
+fn replace_entries(
+    map: &mut Map<String, Value>,
+    key: &str,
+    entry_type: &str,
+    replacements: impl Iterator<Item = Value>,
+) {
+    let mut entries: Vec<Value> = match map.remove(key) {
+        Some(Value::Array(items)) => items,
+        Some(Value::Null) | None => Vec::new(),
+        Some(item) => vec![item],
+    };
+    entries.retain(|entry| entry.get("type").and_then(Value::as_str) != Some(entry_type));
+    entries.extend(replacements);
+
+    if !entries.is_empty() {
+        map.insert(key.to_string(), Value::Array(entries));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_mastodon_actor() {
+        let actor = json!({
+            "type": "Person",
+            "id": "https://mastodon.example/users/alice",
+            "manuallyApprovesFollowers": true,
+            "discoverable": false,
+            "indexable": true,
+            "featured": "https://mastodon.example/users/alice/collections/featured",
+            "attachment": [
+                { "type": "PropertyValue", "name": "Website", "value": "<a href=\"https://alice.example\">alice.example</a>" },
+                { "type": "Image", "url": "https://mastodon.example/banner.png" }
+            ],
+            "tag": { "type": "Hashtag", "name": "#rust", "href": "https://mastodon.example/tags/rust" }
+        });
+
+        let extensions = Extensions::from_json(&actor);
+        assert_eq!(extensions.manually_approves_followers, Some(true));
+        assert_eq!(extensions.discoverable, Some(false));
+        assert_eq!(extensions.indexable, Some(true));
+        assert_eq!(
+            extensions.featured.as_ref().map(Url::as_str),
+            Some("https://mastodon.example/users/alice/collections/featured")
+        );
+        assert_eq!(extensions.property_values.len(), 1);
+        assert_eq!(extensions.property_values[0].name, "Website");
+        assert_eq!(extensions.hashtags.len(), 1);
+        assert_eq!(extensions.hashtags[0].name, "#rust");
+        assert_eq!(extensions.sensitive, None);
+    }
+
+    #[test]
+    fn test_read_question_voters_count() {
+        let question = json!({
+            "type": "Question",
+            "sensitive": true,
+            "votersCount": 42,
+            "tag": [
+                { "type": "Mention", "href": "https://example.com/users/bob", "name": "@bob" },
+                { "type": "Hashtag", "name": "#poll" }
+            ]
+        });
+
+        let extensions = Extensions::from_json(&question);
+        assert_eq!(extensions.sensitive, Some(true));
+        assert_eq!(extensions.voters_count, Some(42));
+        assert_eq!(
+            extensions.hashtags,
+            vec![Hashtag {
+                name: "#poll".to_string(),
+                href: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_write_keeps_other_entries() {
+        let mut note = json!({
+            "type": "Note",
+            "attachment": [{ "type": "Document", "url": "https://example.com/cat.png" }],
+            "tag": [{ "type": "Mention", "name": "@bob" }, { "type": "Hashtag", "name": "#old" }]
+        });
+
+        let extensions = Extensions {
+            sensitive: Some(true),
+            hashtags: vec![Hashtag {
+                name: "#new".to_string(),
+                href: Some(Url::parse("https://example.com/tags/new").unwrap()),
+            }],
+            ..Default::default()
+        };
+        extensions.write_json(&mut note);
+
+        assert_eq!(note["sensitive"], json!(true));
+        assert_eq!(note["attachment"].as_array().unwrap().len(), 1);
+        let tags = note["tag"].as_array().unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0]["type"], "Mention");
+        assert_eq!(tags[1]["name"], "#new");
+        assert!(note.get("manuallyApprovesFollowers").is_none());
+    }
+
+    #[test]
+    fn test_quote_properties() {
+        let quoted = "https://remote.example/notes/1";
+        let link = json!({
+            "type": "Note",
+            "tag": [{
+                "type": "Link",
+                "mediaType": "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+                "href": quoted
+            }]
+        });
+        let misskey = json!({ "type": "Note", "_misskey_quote": quoted });
+        for note in [link, misskey] {
+            assert_eq!(
+                Extensions::from_json(&note).quote.as_ref().map(Url::as_str),
+                Some(quoted)
+            );
+        }
+
+        let mut note = json!({
+            "type": "Note",
+            "tag": [{ "type": "Link", "mediaType": "text/html", "href": "https://example.com/" }]
+        });
+        Extensions {
+            quote: Some(Url::parse(quoted).unwrap()),
+            ..Default::default()
+        }
+        .write_json(&mut note);
+        assert_eq!(note["quoteUrl"], quoted);
+        assert_eq!(note["_misskey_quote"], quoted);
+        let tags = note["tag"].as_array().unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[1]["href"], quoted);
+    }
+
+    #[test]
+    fn test_object_extensions_round_trip() {
+        let mut object: Object = serde_json::from_value(json!({
+            "type": "Note",
+            "content": "Hello"
+        }))
+        .unwrap();
+
+        let extensions = Extensions {
+            sensitive: Some(false),
+            property_values: vec![PropertyValue {
+                name: "Pronouns".to_string(),
+                value: "they/them".to_string(),
+            }],
+            ..Default::default()
+        };
+        object.set_extensions(&extensions);
+
+        let parsed: Object =
+            serde_json::from_value(serde_json::to_value(&object).unwrap()).unwrap();
+        assert_eq!(parsed.extensions(), extensions);
+    }
+}
@@ -0,0 +1,167 @@
+//! Health reports shared by all services
+//!
+//! Each daemon checks its own dependencies and reports them as components
+//! of a [`HealthReport`]. The overall status of a report is the worst status
+//! of its components.
+
+use serde::{Deserialize, Serialize};
+
+/// Health of a service or one of its dependencies
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    /// Working normally
+    Healthy,
+    /// Working, but needs attention soon
+    Degraded,
+    /// Not able to serve requests
+    Unhealthy,
+}
+
+impl HealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Health of a single dependency
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn healthy(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Healthy,
+            detail: None,
+        }
+    }
+
+    pub fn degraded(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Degraded,
+            detail: Some(detail.into()),
+        }
+    }
+
+    pub fn unhealthy(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Unhealthy,
+            detail: Some(detail.into()),
+        }
+    }
+
+    /// Healthy on success, unhealthy with the error otherwise
+    pub fn from_result<T, E: std::fmt::Display>(name: &str, result: Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self::healthy(name),
+            Err(e) => Self::unhealthy(name, e.to_string()),
+        }
+    }
+}
+
+/// Health of one running service
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthReport {
+    pub service: String,
+    /// Host or pod the service runs on
+    pub instance: String,
+    pub status: HealthStatus,
+    pub components: Vec<ComponentHealth>,
+    pub checked_at: String,
+}
+
+impl HealthReport {
+    pub fn new(service: &str, components: Vec<ComponentHealth>) -> Self {
+        let status = components
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy);
+
+        Self {
+            service: service.to_string(),
+            instance: instance_name(),
+            status,
+            components,
+            checked_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Health of all services that answered a health request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SystemHealth {
+    pub status: HealthStatus,
+    pub services: Vec<HealthReport>,
+}
+
+impl SystemHealth {
+    pub fn new(mut services: Vec<HealthReport>) -> Self {
+        services.sort_by(|a, b| (&a.service, &a.instance).cmp(&(&b.service, &b.instance)));
+        let status = services
+            .iter()
+            .map(|s| s.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy);
+        Self { status, services }
+    }
+}
+
+/// Name of the host the service runs on
+fn instance_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_status_is_worst_component() {
+        let report = HealthReport::new(
+            "test",
+            vec![
+                ComponentHealth::healthy("mongodb"),
+                ComponentHealth::degraded("media_storage", "8% free"),
+            ],
+        );
+        assert_eq!(report.status, HealthStatus::Degraded);
+
+        let report = HealthReport::new(
+            "test",
+            vec![
+                ComponentHealth::degraded("media_storage", "8% free"),
+                ComponentHealth::unhealthy("amqp", "connection closed"),
+            ],
+        );
+        assert_eq!(report.status, HealthStatus::Unhealthy);
+    }
+
+    #[test]
+    fn test_report_without_components_is_healthy() {
+        assert_eq!(
+            HealthReport::new("test", Vec::new()).status,
+            HealthStatus::Healthy
+        );
+    }
+}
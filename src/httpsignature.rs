@@ -4,18 +4,23 @@
 //! according to the specifications in RFC 9421 (https://www.rfc-editor.org/rfc/rfc9421.html).
 //!
 //! The module supports various signing algorithms, key types and signature parameters
-//! as specified in the standard.
+//! as specified in the standard. Signatures are produced by a [`Signer`], so the
+//! private key may live in process ([`LocalSigner`]) or in an external service.
 
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
 use regex::Regex;
 use reqwest::{
     Request,
-    header::{HeaderName, HeaderValue},
+    header::{HeaderMap, HeaderName, HeaderValue},
 };
 use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, RsaKeyPair, UnparsedPublicKey};
+use sha2::{Digest, Sha256, Sha512};
 use std::collections::HashSet;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// Algorithm supported for HTTP signatures
@@ -107,6 +112,65 @@ pub enum SignatureError {
 
     #[error("Key not found: {0}")]
     KeyNotFound(String),
+
+    #[error("Signer unavailable: {0}")]
+    SignerUnavailable(String),
+
+    #[error("Key revoked: {0}")]
+    KeyRevoked(String),
+
+    #[error("Body does not match digest")]
+    DigestMismatch,
+}
+
+/// Produces the signatures of outgoing requests
+///
+/// Implementations decide where the private key lives: [`LocalSigner`] holds
+/// it in memory, [`crate::remote_signer::RemoteSigner`] asks the PKI daemon
+/// over AMQP, and an HSM can be used through a PKCS#11 implementation.
+pub trait Signer: Send + Sync + fmt::Debug {
+    /// Algorithm the signatures are made with
+    fn algorithm(&self) -> SignatureAlgorithm;
+
+    /// Sign `data`, returning the raw signature bytes
+    fn sign<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, SignatureError>>;
+}
+
+/// Signer holding a PKCS#8 DER private key in process
+#[derive(Clone)]
+pub struct LocalSigner {
+    algorithm: SignatureAlgorithm,
+    private_key: Vec<u8>,
+}
+
+impl LocalSigner {
+    pub fn new(algorithm: SignatureAlgorithm, private_key: Vec<u8>) -> Self {
+        Self {
+            algorithm,
+            private_key,
+        }
+    }
+}
+
+impl fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Signer for LocalSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm.clone()
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, SignatureError>> {
+        Box::pin(async move {
+            let rng = ring::rand::SystemRandom::new();
+            HttpSignature::create_signature(data, &self.algorithm, &self.private_key, &rng)
+        })
+    }
 }
 
 /// Configuration for HTTP signature verification
@@ -121,6 +185,9 @@ pub struct VerificationConfig {
     /// Maximum allowed signature age in seconds (None for no check)
     pub max_age: Option<i64>,
 
+    /// Seconds a signature may appear to be created in the future
+    pub clock_skew: i64,
+
     /// Required components that must be included in the signature
     pub required_components: Option<Vec<ComponentIdentifier>>,
 
@@ -135,6 +202,7 @@ impl VerificationConfig {
             public_key,
             algorithm,
             max_age: Some(300), // Default to 5 minutes max age
+            clock_skew: 30,
             required_components: None,
             expected_key_id: None,
         }
@@ -152,6 +220,12 @@ impl VerificationConfig {
         self
     }
 
+    /// Set how far in the future a signature creation time may lie
+    pub fn with_clock_skew(mut self, clock_skew: i64) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
     /// Set required components that must be included in the signature
     pub fn with_required_components(mut self, components: Vec<ComponentIdentifier>) -> Self {
         self.required_components = Some(components);
@@ -235,9 +309,6 @@ impl FromStr for ComponentIdentifier {
 /// Configuration for HTTP signature creation
 #[derive(Debug, Clone)]
 pub struct SignatureConfig {
-    /// Signing algorithm to use
-    pub algorithm: SignatureAlgorithm,
-
     /// Signature parameters to include
     pub parameters: SignatureParameters,
 
@@ -247,8 +318,8 @@ pub struct SignatureConfig {
     /// Components to include in the signature
     pub components: Vec<ComponentIdentifier>,
 
-    /// Private key for signing
-    pub private_key: Vec<u8>,
+    /// Signer producing the signatures; it also determines the algorithm
+    pub signer: Arc<dyn Signer>,
 }
 
 /// Parameters for HTTP signature
@@ -476,6 +547,253 @@ impl Signature {
     }
 }
 
+/// Parsed draft-cavage `Signature` header
+#[derive(Debug)]
+pub struct LegacySignature {
+    /// Key ID naming the signing key
+    pub key_id: String,
+
+    /// Algorithm named in the header; `hs2019` leaves it to the key type
+    pub algorithm: Option<String>,
+
+    /// Signed headers and pseudo-headers, lowercase, in signing order
+    pub headers: Vec<String>,
+
+    /// Signature value (base64 encoded)
+    pub signature: String,
+
+    /// Value of the `(created)` pseudo-header
+    pub created: Option<DateTime<Utc>>,
+
+    /// Value of the `(expires)` pseudo-header
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl LegacySignature {
+    /// Parse the `Signature` header of a request
+    pub fn from_request(req: &Request) -> Result<Self, SignatureError> {
+        let header = req
+            .headers()
+            .get("signature")
+            .ok_or(SignatureError::SignatureNotFound)?
+            .to_str()
+            .map_err(|_| SignatureError::InvalidHeader("Non-ASCII signature header".to_string()))?;
+        Self::parse(header)
+    }
+
+    /// Parse the value of a `Signature` header
+    pub fn parse(header: &str) -> Result<Self, SignatureError> {
+        let field_regex = Regex::new(r#"([a-zA-Z]+)=(?:"([^"]*)"|([^,\s]*))"#).unwrap();
+
+        let mut key_id = None;
+        let mut algorithm = None;
+        let mut headers = None;
+        let mut signature = None;
+        let mut created = None;
+        let mut expires = None;
+
+        for cap in field_regex.captures_iter(header) {
+            let key = cap.get(1).unwrap().as_str();
+            let value = cap.get(2).or_else(|| cap.get(3)).unwrap().as_str();
+
+            match key {
+                "keyId" => key_id = Some(value.to_string()),
+                "algorithm" => algorithm = Some(value.to_lowercase()),
+                "headers" => {
+                    headers = Some(value.split_whitespace().map(str::to_lowercase).collect())
+                }
+                "signature" => signature = Some(value.to_string()),
+                "created" => created = Some(parse_timestamp("created", value)?),
+                "expires" => expires = Some(parse_timestamp("expires", value)?),
+                // Unknown fields are ignored, as the draft requires
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            key_id: key_id.ok_or_else(|| SignatureError::MissingParameter("keyId".to_string()))?,
+            algorithm,
+            // The draft signs only the Date header when no list is given
+            headers: headers.unwrap_or_else(|| vec!["date".to_string()]),
+            signature: signature
+                .ok_or_else(|| SignatureError::MissingParameter("signature".to_string()))?,
+            created,
+            expires,
+        })
+    }
+
+    /// Build the signing string the signature was made over
+    pub fn signing_string(&self, req: &Request) -> Result<String, SignatureError> {
+        let mut lines = Vec::with_capacity(self.headers.len());
+
+        for name in &self.headers {
+            let value = match name.as_str() {
+                "(request-target)" => {
+                    let url = req.url();
+                    let path_and_query = match url.query() {
+                        Some(query) => format!("{}?{}", url.path(), query),
+                        None => url.path().to_string(),
+                    };
+                    format!(
+                        "{} {}",
+                        req.method().as_str().to_lowercase(),
+                        path_and_query
+                    )
+                }
+                "(created)" => self
+                    .created
+                    .ok_or_else(|| SignatureError::MissingParameter("created".to_string()))?
+                    .timestamp()
+                    .to_string(),
+                "(expires)" => self
+                    .expires
+                    .ok_or_else(|| SignatureError::MissingParameter("expires".to_string()))?
+                    .timestamp()
+                    .to_string(),
+                header_name => {
+                    let values = req
+                        .headers()
+                        .get_all(header_name)
+                        .iter()
+                        .map(|value| {
+                            value.to_str().map(str::trim).map_err(|_| {
+                                SignatureError::InvalidHeader(format!(
+                                    "Non-ASCII value in header: {}",
+                                    header_name
+                                ))
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if values.is_empty() {
+                        return Err(SignatureError::InvalidHeader(format!(
+                            "Header not found: {}",
+                            header_name
+                        )));
+                    }
+                    values.join(", ")
+                }
+            };
+            lines.push(format!("{}: {}", name, value));
+        }
+
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Parse a Unix timestamp signature parameter
+fn parse_timestamp(name: &str, value: &str) -> Result<DateTime<Utc>, SignatureError> {
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .ok_or_else(|| {
+            SignatureError::InvalidParameter(format!("Invalid {} timestamp: {}", name, value))
+        })
+}
+
+/// `Digest` header value for a request body
+pub fn digest_header(body: &[u8]) -> String {
+    format!("SHA-256={}", BASE64.encode(Sha256::digest(body)))
+}
+
+/// Check the digest headers of a request against its body
+///
+/// Reads `Digest` (RFC 3230, `SHA-256=<base64>`) and `Content-Digest`
+/// (RFC 9530, `sha-256=:<base64>:`). Every SHA-256 and SHA-512 digest given
+/// has to match; other algorithms are ignored, but at least one supported
+/// digest is required for a non-empty body.
+pub fn verify_digest(headers: &HeaderMap, body: &[u8]) -> Result<(), SignatureError> {
+    if body.is_empty() {
+        return Ok(());
+    }
+
+    let mut found = false;
+    let mut checked = false;
+    for name in ["digest", "content-digest"] {
+        for header in headers.get_all(name) {
+            found = true;
+            let header = header.to_str().map_err(|_| {
+                SignatureError::InvalidHeader(format!("Non-ASCII value in header: {}", name))
+            })?;
+
+            for entry in header.split(',') {
+                let Some((algorithm, value)) = entry.trim().split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches(':');
+                let expected = match algorithm.to_ascii_lowercase().as_str() {
+                    "sha-256" => BASE64.encode(Sha256::digest(body)),
+                    "sha-512" => BASE64.encode(Sha512::digest(body)),
+                    _ => continue,
+                };
+                if value != expected {
+                    return Err(SignatureError::DigestMismatch);
+                }
+                checked = true;
+            }
+        }
+    }
+
+    if !found {
+        return Err(SignatureError::InvalidHeader(
+            "Digest header not found".to_string(),
+        ));
+    }
+    if !checked {
+        return Err(SignatureError::UnsupportedAlgorithm(
+            "No SHA-256 or SHA-512 digest".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Decode a PEM public key into the form `algorithm` signatures are verified with
+///
+/// Accepts SubjectPublicKeyInfo PEM, and PKCS#1 PEM for RSA keys. Fails if
+/// the key is not of the type the algorithm needs.
+pub fn public_key_from_pem(
+    pem: &str,
+    algorithm: &SignatureAlgorithm,
+) -> Result<Vec<u8>, SignatureError> {
+    match algorithm {
+        SignatureAlgorithm::RsaSha256 | SignatureAlgorithm::RsaPssSha512 => {
+            use rsa::pkcs1::{DecodeRsaPublicKey, EncodeRsaPublicKey};
+            use rsa::pkcs8::DecodePublicKey;
+
+            let key = rsa::RsaPublicKey::from_public_key_pem(pem.trim())
+                .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(pem.trim()))
+                .map_err(|e| {
+                    SignatureError::InvalidKeyFormat(format!("Invalid RSA public key: {}", e))
+                })?;
+            let der = key.to_pkcs1_der().map_err(|e| {
+                SignatureError::InvalidKeyFormat(format!("Invalid RSA public key: {}", e))
+            })?;
+            Ok(der.as_bytes().to_vec())
+        }
+        // ring takes the raw key, the tail of the SubjectPublicKeyInfo
+        SignatureAlgorithm::Ed25519 => spki_public_key(pem, 44, 32),
+        SignatureAlgorithm::EcdsaP256Sha256 => spki_public_key(pem, 91, 65),
+    }
+}
+
+/// Raw key of a SubjectPublicKeyInfo PEM of a fixed-size key type
+fn spki_public_key(pem: &str, spki_len: usize, key_len: usize) -> Result<Vec<u8>, SignatureError> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+    let der = BASE64.decode(body)?;
+    if der.len() != spki_len {
+        return Err(SignatureError::InvalidKeyFormat(format!(
+            "Expected a {} byte public key, got {} bytes of DER",
+            key_len,
+            der.len()
+        )));
+    }
+    Ok(der[spki_len - key_len..].to_vec())
+}
+
 /// HTTP Signature implementation
 pub struct HttpSignature;
 
@@ -577,25 +895,20 @@ impl HttpSignature {
     }
 
     /// Create a signature for a request using the given configuration
-    pub fn sign_request(req: &mut Request, config: &SignatureConfig) -> Result<(), SignatureError> {
+    pub async fn sign_request(
+        req: &mut Request,
+        config: &SignatureConfig,
+    ) -> Result<(), SignatureError> {
         // Create signature parameters
         let mut params = SignatureParameters::new();
         params.key_id = Some(config.key_id.clone());
-        params.algorithm = Some(config.algorithm.clone());
+        params.algorithm = Some(config.signer.algorithm());
 
         // Create a signature base
         let signature_base = Self::create_signature_base(req, &config.components, &params)?;
 
-        // Create a secure random number generator
-        let rng = ring::rand::SystemRandom::new();
-
-        // Sign the base with the secure random number generator
-        let signature = Self::create_signature(
-            &signature_base,
-            &config.algorithm,
-            &config.private_key,
-            &rng,
-        )?;
+        // Sign the base
+        let signature = BASE64.encode(config.signer.sign(signature_base.as_bytes()).await?);
 
         // Format signature input header
         let mut signature_input = String::new();
@@ -627,7 +940,7 @@ impl HttpSignature {
     /// This produces a single `Signature` header with `keyId`, `algorithm`, `headers`,
     /// and `signature` fields — the format expected by Mastodon and most ActivityPub
     /// implementations.
-    pub fn sign_request_legacy(
+    pub async fn sign_request_legacy(
         req: &mut Request,
         config: &SignatureConfig,
     ) -> Result<(), SignatureError> {
@@ -683,16 +996,10 @@ impl HttpSignature {
         let signing_string = signing_lines.join("\n");
 
         // Sign the string
-        let rng = ring::rand::SystemRandom::new();
-        let signature = Self::create_signature(
-            &signing_string,
-            &config.algorithm,
-            &config.private_key,
-            &rng,
-        )?;
+        let signature = BASE64.encode(config.signer.sign(signing_string.as_bytes()).await?);
 
         // Map algorithm name to draft-cavage convention
-        let algorithm_name = match &config.algorithm {
+        let algorithm_name = match config.signer.algorithm() {
             SignatureAlgorithm::RsaSha256 => "rsa-sha256",
             SignatureAlgorithm::Ed25519 => "ed25519",
             SignatureAlgorithm::EcdsaP256Sha256 => "ecdsa-sha256",
@@ -731,7 +1038,7 @@ impl HttpSignature {
                 let now = Utc::now();
 
                 // Check if a signature is created in the future (with a small tolerance)
-                if created > now + Duration::seconds(30) {
+                if created > now + Duration::seconds(config.clock_skew) {
                     return Err(SignatureError::SignatureCreatedInFuture);
                 }
 
@@ -789,6 +1096,87 @@ impl HttpSignature {
         Ok(())
     }
 
+    /// Verify a draft-cavage `Signature` header using the given verification configuration
+    ///
+    /// Freshness is checked against the `(created)` pseudo-header or, as most
+    /// ActivityPub servers sign, the `Date` header; either has to be signed.
+    /// Required components are matched against the signed header list.
+    pub fn verify_request_legacy(
+        req: &Request,
+        config: &VerificationConfig,
+    ) -> Result<(), SignatureError> {
+        let signature = LegacySignature::from_request(req)?;
+        let is_signed = |name: &str| signature.headers.iter().any(|header| header == name);
+
+        if let Some(expected_key_id) = &config.expected_key_id
+            && &signature.key_id != expected_key_id
+        {
+            return Err(SignatureError::KeyNotFound(signature.key_id));
+        }
+
+        if let Some(required) = &config.required_components {
+            for component in required {
+                let name = match component {
+                    ComponentIdentifier::RequestTarget => "(request-target)".to_string(),
+                    ComponentIdentifier::Header(name) => name.to_lowercase(),
+                    ComponentIdentifier::Digest => "digest".to_string(),
+                    other => {
+                        return Err(SignatureError::InvalidParameter(format!(
+                            "{} cannot be signed in a draft-cavage signature",
+                            other.canonical_name()
+                        )));
+                    }
+                };
+                if !is_signed(&name) {
+                    return Err(SignatureError::MissingSignatureComponents);
+                }
+            }
+        }
+
+        if let Some(max_age) = config.max_age {
+            let created = if is_signed("(created)") {
+                signature
+                    .created
+                    .ok_or_else(|| SignatureError::MissingParameter("created".to_string()))?
+            } else if is_signed("date") {
+                let date = req
+                    .headers()
+                    .get("date")
+                    .and_then(|date| date.to_str().ok())
+                    .ok_or_else(|| SignatureError::InvalidHeader("Missing Date header".into()))?;
+                DateTime::parse_from_rfc2822(date)
+                    .map_err(|_| {
+                        SignatureError::InvalidHeader(format!("Invalid Date header: {}", date))
+                    })?
+                    .with_timezone(&Utc)
+            } else {
+                return Err(SignatureError::MissingParameter("date".to_string()));
+            };
+
+            let now = Utc::now();
+            if created > now + Duration::seconds(config.clock_skew) {
+                return Err(SignatureError::SignatureCreatedInFuture);
+            }
+            if now.timestamp() - created.timestamp() > max_age {
+                return Err(SignatureError::SignatureExpired);
+            }
+        }
+
+        if let Some(expires) = signature.expires
+            && expires < Utc::now()
+        {
+            return Err(SignatureError::SignatureExpired);
+        }
+
+        let signing_string = signature.signing_string(req)?;
+        Self::verify_signature(
+            &signing_string,
+            &signature.signature,
+            &config.algorithm,
+            &config.public_key,
+        )
+    }
+
     /// Verify a signature using the specified algorithm and public key
     fn verify_signature(
         signature_base: &str,
@@ -847,11 +1235,11 @@ impl HttpSignature {
 
     /// Create the actual signature using the specified algorithm and private key
     fn create_signature(
-        signature_base: &str,
+        data: &[u8],
         algorithm: &SignatureAlgorithm,
         private_key: &[u8],
         rng: &dyn ring::rand::SecureRandom,
-    ) -> Result<String, SignatureError> {
+    ) -> Result<Vec<u8>, SignatureError> {
         let signature = match algorithm {
             SignatureAlgorithm::Ed25519 => {
                 let key_pair =
@@ -859,7 +1247,7 @@ impl HttpSignature {
                         SignatureError::InvalidKeyFormat(format!("Invalid Ed25519 key: {:?}", e))
                     })?;
 
-                let signature = key_pair.sign(signature_base.as_bytes());
+                let signature = key_pair.sign(data);
                 signature.as_ref().to_vec()
             }
             SignatureAlgorithm::EcdsaP256Sha256 => {
@@ -873,7 +1261,7 @@ impl HttpSignature {
                 })?;
 
                 let signature = key_pair
-                    .sign(rng, signature_base.as_bytes())
+                    .sign(rng, data)
                     .map_err(|e| SignatureError::CryptoError(format!("Signing failed: {:?}", e)))?;
 
                 signature.as_ref().to_vec()
@@ -885,12 +1273,7 @@ impl HttpSignature {
 
                 let mut signature = vec![0; key_pair.public().modulus_len()];
                 key_pair
-                    .sign(
-                        &signature::RSA_PKCS1_SHA256,
-                        rng,
-                        signature_base.as_bytes(),
-                        &mut signature,
-                    )
+                    .sign(&signature::RSA_PKCS1_SHA256, rng, data, &mut signature)
                     .map_err(|e| {
                         SignatureError::CryptoError(format!("RSA signing failed: {:?}", e))
                     })?;
@@ -904,12 +1287,7 @@ impl HttpSignature {
 
                 let mut signature = vec![0; key_pair.public().modulus_len()];
                 key_pair
-                    .sign(
-                        &signature::RSA_PSS_SHA512,
-                        rng,
-                        signature_base.as_bytes(),
-                        &mut signature,
-                    )
+                    .sign(&signature::RSA_PSS_SHA512, rng, data, &mut signature)
                     .map_err(|e| {
                         SignatureError::CryptoError(format!("RSA-PSS signing failed: {:?}", e))
                     })?;
@@ -918,7 +1296,7 @@ impl HttpSignature {
             }
         };
 
-        Ok(BASE64.encode(signature))
+        Ok(signature)
     }
 }
 
@@ -992,8 +1370,8 @@ mod tests {
         assert!(base.contains("\"@signature-params\":created=1618884475"));
     }
 
-    #[test]
-    fn test_verify_signature() {
+    #[tokio::test]
+    async fn test_verify_signature() {
         // Use Ed25519 test keys in PEM format
         // Note: ring expects the raw key without PEM headers/footers, so we need to decode the PEM format
         let private_key_pem = include_str!("../test-data/ed25519_test_key.pem");
@@ -1039,7 +1417,6 @@ mod tests {
 
         // Create signature configuration
         let sig_config = SignatureConfig {
-            algorithm: SignatureAlgorithm::Ed25519,
             parameters: SignatureParameters::new(),
             key_id: "test-key-ed25519".to_string(),
             components: vec![
@@ -1050,11 +1427,16 @@ mod tests {
                 ComponentIdentifier::Header("content-type".to_string()),
                 ComponentIdentifier::Header("digest".to_string()),
             ],
-            private_key: private_key.to_vec(),
+            signer: Arc::new(LocalSigner::new(
+                SignatureAlgorithm::Ed25519,
+                private_key.to_vec(),
+            )),
         };
 
         // Sign the request
-        HttpSignature::sign_request(&mut req, &sig_config).unwrap();
+        HttpSignature::sign_request(&mut req, &sig_config)
+            .await
+            .unwrap();
 
         // Verify that the signature header was added
         assert!(req.headers().contains_key("signature"));
@@ -1093,4 +1475,145 @@ mod tests {
                 .with_expected_key_id("wrong-key-id".to_string());
         assert!(HttpSignature::verify_request(&req, &wrong_keyid_config).is_err());
     }
+
+    /// Signer standing in for an external service
+    #[derive(Debug)]
+    struct FixedSigner;
+
+    impl Signer for FixedSigner {
+        fn algorithm(&self) -> SignatureAlgorithm {
+            SignatureAlgorithm::RsaSha256
+        }
+
+        fn sign<'a>(&'a self, _data: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, SignatureError>> {
+            Box::pin(async { Ok(vec![1, 2, 3]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_request_legacy_with_external_signer() {
+        let mut req = Client::new()
+            .post("https://example.com/inbox")
+            .header("host", "example.com")
+            .build()
+            .unwrap();
+        let config = SignatureConfig {
+            parameters: SignatureParameters::new(),
+            key_id: "https://example.com/users/alice#main-key".to_string(),
+            components: vec![
+                ComponentIdentifier::RequestTarget,
+                ComponentIdentifier::Header("host".to_string()),
+            ],
+            signer: Arc::new(FixedSigner),
+        };
+
+        HttpSignature::sign_request_legacy(&mut req, &config)
+            .await
+            .unwrap();
+
+        let header = req.headers()["signature"].to_str().unwrap();
+        assert!(header.contains("algorithm=\"rsa-sha256\""));
+        assert!(header.contains("headers=\"(request-target) host\""));
+        assert!(header.contains(&format!("signature=\"{}\"", BASE64.encode([1, 2, 3]))));
+    }
+
+    #[tokio::test]
+    async fn test_verify_request_legacy() {
+        let private_key_pem = include_str!("../test-data/ed25519_test_key.pem");
+        let public_key_pem = include_str!("../test-data/ed25519_test_public_key.pem");
+        let private_key: String = private_key_pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+
+        let mut req = Client::new()
+            .post("https://example.com/users/bob/inbox")
+            .header("host", "example.com")
+            .header("date", Utc::now().to_rfc2822().replace("+0000", "GMT"))
+            .build()
+            .unwrap();
+        let config = SignatureConfig {
+            parameters: SignatureParameters::new(),
+            key_id: "https://example.org/users/alice#main-key".to_string(),
+            components: vec![
+                ComponentIdentifier::RequestTarget,
+                ComponentIdentifier::Header("host".to_string()),
+                ComponentIdentifier::Header("date".to_string()),
+            ],
+            signer: Arc::new(LocalSigner::new(
+                SignatureAlgorithm::Ed25519,
+                BASE64.decode(private_key).unwrap(),
+            )),
+        };
+        HttpSignature::sign_request_legacy(&mut req, &config)
+            .await
+            .unwrap();
+
+        let public_key = public_key_from_pem(public_key_pem, &SignatureAlgorithm::Ed25519).unwrap();
+        let verify_config = VerificationConfig::new(public_key, SignatureAlgorithm::Ed25519)
+            .with_required_components(vec![ComponentIdentifier::RequestTarget]);
+        HttpSignature::verify_request_legacy(&req, &verify_config).unwrap();
+
+        // A signature over another request target does not verify
+        *req.url_mut() = "https://example.com/users/carol/inbox".parse().unwrap();
+        assert!(matches!(
+            HttpSignature::verify_request_legacy(&req, &verify_config),
+            Err(SignatureError::VerificationFailed)
+        ));
+
+        // The digest has to be signed when it is required
+        let digest_config =
+            verify_config.with_required_components(vec![ComponentIdentifier::Digest]);
+        assert!(matches!(
+            HttpSignature::verify_request_legacy(&req, &digest_config),
+            Err(SignatureError::MissingSignatureComponents)
+        ));
+    }
+
+    #[test]
+    fn test_legacy_signature_parse() {
+        let signature = LegacySignature::parse(
+            r#"keyId="https://example.org/actor#main-key",algorithm="hs2019",headers="(request-target) Host",signature="YWJj==""#,
+        )
+        .unwrap();
+        assert_eq!(signature.key_id, "https://example.org/actor#main-key");
+        assert_eq!(signature.algorithm.as_deref(), Some("hs2019"));
+        assert_eq!(signature.headers, vec!["(request-target)", "host"]);
+        assert_eq!(signature.signature, "YWJj==");
+
+        assert!(LegacySignature::parse(r#"signature="YWJj""#).is_err());
+    }
+
+    #[test]
+    fn test_verify_digest() {
+        let body = br#"{"type":"Like"}"#;
+        let mut headers = HeaderMap::new();
+        assert!(verify_digest(&headers, b"").is_ok());
+        assert!(matches!(
+            verify_digest(&headers, body),
+            Err(SignatureError::InvalidHeader(_))
+        ));
+
+        headers.insert("digest", digest_header(body).parse().unwrap());
+        verify_digest(&headers, body).unwrap();
+        assert!(matches!(
+            verify_digest(&headers, br#"{"type":"Block"}"#),
+            Err(SignatureError::DigestMismatch)
+        ));
+
+        let sha512 = BASE64.encode(Sha512::digest(body));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-digest",
+            format!("sha-512=:{}:", sha512).parse().unwrap(),
+        );
+        verify_digest(&headers, body).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("digest", "MD5=abc".parse().unwrap());
+        assert!(matches!(
+            verify_digest(&headers, body),
+            Err(SignatureError::UnsupportedAlgorithm(_))
+        ));
+    }
 }
@@ -0,0 +1,169 @@
+//! Multi-language content
+//!
+//! ActivityStreams carries translations of `content`, `name` and `summary`
+//! in the `contentMap`, `nameMap` and `summaryMap` properties, keyed by
+//! BCP 47 language tag. The plain properties hold the default variant.
+//! Objects stored by oxifed record the language of that default variant,
+//! which timelines can filter on.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::Object;
+
+/// Natural language values keyed by BCP 47 language tag
+pub type LanguageMap = HashMap<String, String>;
+
+/// Whether `tag` is a well-formed BCP 47 language tag
+///
+/// Checks the syntax only: a primary language subtag of two or three
+/// letters, followed by subtags of one to eight alphanumerics, such as
+/// `en`, `pt-BR` or `zh-Hant-TW`.
+pub fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Read a language map property, dropping malformed tags and values
+pub fn read_map(value: Option<&Value>) -> Option<LanguageMap> {
+    let map: LanguageMap = value?
+        .as_object()?
+        .iter()
+        .filter(|(tag, _)| is_language_tag(tag))
+        .filter_map(|(tag, text)| Some((tag.clone(), text.as_str()?.to_string())))
+        .collect();
+    (!map.is_empty()).then_some(map)
+}
+
+/// Language of the default variant of a map
+///
+/// The language whose value equals `default`, otherwise the only language
+/// of the map, otherwise none.
+pub fn primary_language(map: &LanguageMap, default: Option<&str>) -> Option<String> {
+    if let Some(default) = default
+        && let Some((tag, _)) = map.iter().find(|(_, text)| text.as_str() == default)
+    {
+        return Some(tag.clone());
+    }
+    match map.len() {
+        1 => map.keys().next().cloned(),
+        _ => None,
+    }
+}
+
+/// Mark the natural language properties of an object as being in `language`
+///
+/// Adds the `content`, `name` and `summary` values to their maps under
+/// `language`, keeping variants already present in other languages.
+pub fn tag_language(object: &mut Map<String, Value>, language: &str) {
+    for (key, map_key) in [
+        ("content", "contentMap"),
+        ("name", "nameMap"),
+        ("summary", "summaryMap"),
+    ] {
+        let Some(text) = object.get(key).and_then(Value::as_str).map(str::to_string) else {
+            continue;
+        };
+        let map = object
+            .entry(map_key)
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(map) = map {
+            map.insert(language.to_string(), Value::String(text));
+        }
+    }
+}
+
+impl Object {
+    /// Language of the default `content`, if the content map names it
+    pub fn language(&self) -> Option<String> {
+        primary_language(self.content_map.as_ref()?, self.content.as_deref())
+    }
+
+    /// Content in the first of the preferred languages that has a variant,
+    /// falling back to the default content
+    pub fn content_in(&self, languages: &[&str]) -> Option<&str> {
+        self.content_map
+            .as_ref()
+            .and_then(|map| languages.iter().find_map(|tag| map.get(*tag)))
+            .or(self.content.as_ref())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_language_tags() {
+        for tag in ["en", "deu", "pt-BR", "zh-Hant-TW", "sl-rozaj-biske"] {
+            assert!(is_language_tag(tag), "{}", tag);
+        }
+        for tag in ["", "e", "english", "en_US", "en-", "en.x", "$en"] {
+            assert!(!is_language_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_read_map_and_primary_language() {
+        let object = json!({
+            "content": "<p>Hallo</p>",
+            "contentMap": {
+                "en": "<p>Hello</p>",
+                "de": "<p>Hallo</p>",
+                "not a tag": "dropped",
+                "fr": 42
+            }
+        });
+
+        let map = read_map(object.get("contentMap")).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            primary_language(&map, Some("<p>Hallo</p>")).as_deref(),
+            Some("de")
+        );
+        assert_eq!(primary_language(&map, None), None);
+        assert!(read_map(Some(&json!({ "x": "y" }))).is_none());
+    }
+
+    #[test]
+    fn test_tag_language() {
+        let mut object = json!({
+            "type": "Note",
+            "content": "Bonjour",
+            "contentMap": { "en": "Hello" }
+        });
+        tag_language(object.as_object_mut().unwrap(), "fr");
+
+        assert_eq!(
+            object["contentMap"],
+            json!({ "en": "Hello", "fr": "Bonjour" })
+        );
+        assert!(object.get("summaryMap").is_none());
+    }
+
+    #[test]
+    fn test_object_content_in() {
+        let object: Object = serde_json::from_value(json!({
+            "type": "Note",
+            "content": "Hello",
+            "contentMap": { "en": "Hello", "de": "Hallo" }
+        }))
+        .unwrap();
+
+        assert_eq!(object.language().as_deref(), Some("en"));
+        assert_eq!(object.content_in(&["fr", "de"]), Some("Hallo"));
+        assert_eq!(object.content_in(&["fr"]), Some("Hello"));
+        assert_eq!(
+            serde_json::to_value(&object).unwrap()["contentMap"]["de"],
+            "Hallo"
+        );
+    }
+}
@@ -10,11 +10,27 @@ use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use url::Url;
+pub mod backpressure;
+pub mod builder;
+pub mod bus;
 pub mod client;
+pub mod compat;
+pub mod config;
+pub mod credentials;
 pub mod database;
+pub mod egress;
+pub mod extensions;
+pub mod health;
 pub mod httpsignature;
+pub mod language;
 pub mod messaging;
 pub mod pki;
+pub mod poison;
+pub mod remote_signer;
+pub mod routing;
+pub mod shutdown;
+pub mod signature_middleware;
+pub mod testing;
 pub mod webfinger;
 pub mod well_known;
 
@@ -110,14 +126,26 @@ pub struct Object {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 
+    /// The name in several languages
+    #[serde(rename = "nameMap", skip_serializing_if = "Option::is_none")]
+    pub name_map: Option<language::LanguageMap>,
+
     /// A natural language summarization of the object
     #[serde(skip_serializing_if = "Option::is_none")]
     pub summary: Option<String>,
 
+    /// The summary in several languages
+    #[serde(rename = "summaryMap", skip_serializing_if = "Option::is_none")]
+    pub summary_map: Option<language::LanguageMap>,
+
     /// The content of the object
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
 
+    /// The content in several languages
+    #[serde(rename = "contentMap", skip_serializing_if = "Option::is_none")]
+    pub content_map: Option<language::LanguageMap>,
+
     /// The URL of the object
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<Url>,
@@ -3,20 +3,72 @@
 //! This module defines message structures that are shared between
 //! Oxifed services for communication via message queues.
 
+use crate::database::ApplicationProfile;
+use crate::health::HealthReport;
+use crate::pki::{DomainVerificationChallenge, TrustChain, TrustLevel, VerificationMethod};
 use crate::{Attachment, ImageAttachment};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// Constants for RabbitMQ Exchange names
 pub const EXCHANGE_INTERNAL_PUBLISH: &str = "oxifed.internal.publish";
 pub const EXCHANGE_ACTIVITYPUB_PUBLISH: &str = "oxifed.activitypub.publish";
+pub const EXCHANGE_ACTIVITYPUB_DELIVERY: &str = "oxifed.activitypub.delivery";
 pub const EXCHANGE_INCOMING_PROCESS: &str = "oxifed.incoming.process";
+pub const EXCHANGE_PIPELINE: &str = "oxifed.pipeline";
 pub const EXCHANGE_RPC_REQUEST: &str = "oxifed.rpc.request";
 pub const EXCHANGE_RPC_RESPONSE: &str = "oxifed.rpc.response";
+pub const EXCHANGE_DEAD_LETTER: &str = "oxifed.dlx";
+pub const EXCHANGE_HEALTH: &str = "oxifed.health";
+pub const EXCHANGE_KEY_EVENTS: &str = "oxifed.keys";
+pub const EXCHANGE_PKI: &str = "oxifed.pki";
+pub const EXCHANGE_WEBHOOKS: &str = "oxifed.webhooks";
+pub const EXCHANGE_EMAIL: &str = "oxifed.email";
+pub const EXCHANGE_PUSH: &str = "oxifed.push";
+pub const EXCHANGE_DOMAIN_DELIVERY: &str = "oxifed.activitypub.delivery.domains";
+pub const EXCHANGE_SHARED_DELIVERY: &str = "oxifed.activitypub.delivery.shared";
+pub const EXCHANGE_DOMAIN_INCOMING: &str = "oxifed.incoming.domains";
 
 /// Constants for RabbitMQ Queue names
 pub const QUEUE_RPC_DOMAIN: &str = "oxifed.rpc.domain";
 pub const QUEUE_RPC_FOLLOW: &str = "oxifed.rpc.follow";
+pub const QUEUE_RPC_MODERATION: &str = "oxifed.rpc.moderation";
+pub const QUEUE_RPC_SPAM_FILTER: &str = "oxifed.rpc.spam_filter";
+pub const QUEUE_RPC_DLQ: &str = "oxifed.rpc.dlq";
+pub const QUEUE_RPC_REPLAY: &str = "oxifed.rpc.replay";
+pub const QUEUE_RPC_DELIVERY_STATUS: &str = "oxifed.rpc.delivery_status";
+pub const QUEUE_RPC_PKI: &str = "oxifed.rpc.pki";
+pub const QUEUE_PKI: &str = "oxifed.pki";
+pub const QUEUE_INCOMING_QUARANTINE: &str = "oxifed.incoming.quarantine";
+pub const QUEUE_DEAD_LETTER: &str = "oxifed.dlq";
+pub const QUEUE_WEBHOOK_EVENTS: &str = "oxifed.webhooks.events";
+pub const QUEUE_WEBHOOK_DELIVERIES: &str = "oxifed.webhooks.deliveries";
+pub const QUEUE_EMAIL: &str = "oxifed.email";
+pub const QUEUE_PUSH: &str = "oxifed.push";
+
+/// Routing keys for delivery priorities on the delivery exchange
+pub const ROUTING_KEY_DELIVERY_HIGH: &str = "high";
+pub const ROUTING_KEY_DELIVERY_LOW: &str = "low";
+
+/// Routing key of signing requests on the RPC request exchange
+pub const ROUTING_KEY_SIGN: &str = "sign";
+
+/// Routing key of delivery limit requests on the RPC request exchange
+pub const ROUTING_KEY_DELIVERY_LIMITS: &str = "delivery_limits";
+
+/// Routing keys of events and of the deliveries expanded from them on the
+/// webhook exchange
+pub const ROUTING_KEY_WEBHOOK_EVENT: &str = "event";
+pub const ROUTING_KEY_WEBHOOK_DELIVERY: &str = "delivery";
+
+/// Constants for dead-letter message headers
+pub const HEADER_REJECTED_BY: &str = "x-rejected-by";
+pub const HEADER_REJECTION_REASON: &str = "x-rejection-reason";
+pub const HEADER_REJECTION_STAGE: &str = "x-rejection-stage";
+pub const HEADER_ORIGINAL_QUEUE: &str = "x-original-queue";
+pub const HEADER_FAILURE_CLASS: &str = "x-failure-class";
+pub const HEADER_RETRY_COUNT: &str = "x-oxifed-retries";
 
 /// Message trait that must be implemented by all message types
 pub trait Message {
@@ -29,6 +81,12 @@ pub enum MessageEnum {
     ProfileCreateMessage(ProfileCreateMessage),
     ProfileUpdateMessage(ProfileUpdateMessage),
     ProfileDeleteMessage(ProfileDeleteMessage),
+    ProfileModerateMessage(ProfileModerateMessage),
+    ProfileAliasMessage(ProfileAliasMessage),
+    ProfileExportMessage(ProfileExportMessage),
+    ProfileImportMessage(ProfileImportMessage),
+    GroupCreateMessage(GroupCreateMessage),
+    GroupBanMessage(GroupBanMessage),
     NoteCreateMessage(NoteCreateMessage),
     NoteUpdateMessage(NoteUpdateMessage),
     NoteDeleteMessage(NoteDeleteMessage),
@@ -40,16 +98,56 @@ pub enum MessageEnum {
     DomainCreateMessage(DomainCreateMessage),
     DomainUpdateMessage(DomainUpdateMessage),
     DomainDeleteMessage(DomainDeleteMessage),
+    RelaySubscribeMessage(RelaySubscribeMessage),
+    RelayUnsubscribeMessage(RelayUnsubscribeMessage),
+    WebhookCreateMessage(WebhookCreateMessage),
+    WebhookDeleteMessage(WebhookDeleteMessage),
     DomainRpcRequest(DomainRpcRequest),
     DomainRpcResponse(DomainRpcResponse),
     IncomingObjectMessage(IncomingObjectMessage),
     IncomingActivityMessage(IncomingActivityMessage),
     KeyGenerateMessage(KeyGenerateMessage),
+    KeyChangedMessage(KeyChangedMessage),
+    KeyRotateMessage(KeyRotateMessage),
+    KeyImportMessage(KeyImportMessage),
+    KeyRevokeMessage(KeyRevokeMessage),
     UserCreateMessage(UserCreateMessage),
+    UserPasswordMessage(UserPasswordMessage),
+    UserSessionsRevokeMessage(UserSessionsRevokeMessage),
+    RegistrationReviewMessage(RegistrationReviewMessage),
+    InviteCreateMessage(InviteCreateMessage),
     UserRpcRequest(UserRpcRequest),
     UserRpcResponse(UserRpcResponse),
+    ActorRpcRequest(ActorRpcRequest),
+    ActorRpcResponse(ActorRpcResponse),
     FollowRpcRequest(FollowRpcRequest),
     FollowRpcResponse(FollowRpcResponse),
+    NoteRpcRequest(NoteRpcRequest),
+    NoteRpcResponse(NoteRpcResponse),
+    KeyRpcRequest(KeyRpcRequest),
+    KeyRpcResponse(KeyRpcResponse),
+    ModerationRpcRequest(ModerationRpcRequest),
+    ModerationRpcResponse(ModerationRpcResponse),
+    SpamFilterRpcRequest(SpamFilterRpcRequest),
+    SpamFilterRpcResponse(SpamFilterRpcResponse),
+    DlqRpcRequest(DlqRpcRequest),
+    DlqRpcResponse(DlqRpcResponse),
+    DeliveryLimitsRpcRequest(DeliveryLimitsRpcRequest),
+    DeliveryLimitsRpcResponse(DeliveryLimitsRpcResponse),
+    ReplayRpcRequest(ReplayRpcRequest),
+    ReplayRpcResponse(ReplayRpcResponse),
+    DeliveryStatusRpcRequest(DeliveryStatusRpcRequest),
+    DeliveryStatusRpcResponse(DeliveryStatusRpcResponse),
+    InstanceRpcRequest(InstanceRpcRequest),
+    InstanceRpcResponse(InstanceRpcResponse),
+    AuditEventMessage(AuditEventMessage),
+    AuditRpcRequest(AuditRpcRequest),
+    AuditRpcResponse(AuditRpcResponse),
+    HealthRpcRequest(HealthRpcRequest),
+    HealthRpcResponse(HealthRpcResponse),
+    SignRpcRequest(SignRpcRequest),
+    SignRpcResponse(SignRpcResponse),
+    CommandResponse(CommandResponse),
 }
 
 /// Message format for profile creation requests
@@ -102,6 +200,15 @@ pub struct ProfileUpdateMessage {
     pub attachments: Option<Vec<Attachment>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<Value>,
+    /// List the profile in the account directory and account searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discoverable: Option<bool>,
+    /// Let searches index the profile's public posts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indexable: Option<bool>,
+    /// Application the profile is used with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_profile: Option<ApplicationProfile>,
 }
 
 impl ProfileUpdateMessage {
@@ -124,6 +231,9 @@ impl ProfileUpdateMessage {
             icon: icon_attachment,
             attachments: None,
             properties,
+            discoverable: None,
+            indexable: None,
+            application_profile: None,
         }
     }
 }
@@ -154,6 +264,201 @@ impl Message for ProfileDeleteMessage {
     }
 }
 
+/// Moderation state an administrator puts an actor into
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationState {
+    /// No restrictions
+    Active,
+    /// Kept off the public and local timelines
+    Silenced,
+    /// Silenced, and only reaches local accounts following it
+    Limited,
+    /// Hidden entirely; its activities are refused
+    Suspended,
+}
+
+impl std::str::FromStr for ModerationState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(Self::Active),
+            "silenced" => Ok(Self::Silenced),
+            "limited" => Ok(Self::Limited),
+            "suspended" => Ok(Self::Suspended),
+            other => Err(format!(
+                "Unknown moderation state '{}', expected 'active', 'silenced', 'limited' or 'suspended'",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ModerationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Active => "active",
+            Self::Silenced => "silenced",
+            Self::Limited => "limited",
+            Self::Suspended => "suspended",
+        })
+    }
+}
+
+/// Message for changing the moderation state of an actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileModerateMessage {
+    /// Actor ID of the local or remote actor
+    pub actor: String,
+    pub state: ModerationState,
+}
+
+impl ProfileModerateMessage {
+    /// Create a new profile moderation message
+    pub fn new(actor: String, state: ModerationState) -> Self {
+        Self { actor, state }
+    }
+}
+
+impl Message for ProfileModerateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ProfileModerateMessage(self.clone())
+    }
+}
+
+/// Message for adding an alias to a local actor's `alsoKnownAs`, or
+/// removing one
+///
+/// Aliases are actor URLs of other accounts, or handles on local domains,
+/// which are then bound to the actor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileAliasMessage {
+    /// Subject of the local actor (user@domain)
+    pub subject: String,
+    /// Actor URL or `acct:` handle
+    pub alias: String,
+    /// Remove the alias instead of adding it
+    #[serde(default)]
+    pub remove: bool,
+}
+
+impl ProfileAliasMessage {
+    /// Create a new profile alias message
+    pub fn new(subject: String, alias: String, remove: bool) -> Self {
+        Self {
+            subject,
+            alias,
+            remove,
+        }
+    }
+}
+
+impl Message for ProfileAliasMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ProfileAliasMessage(self.clone())
+    }
+}
+
+/// Message for exporting an account to an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileExportMessage {
+    pub subject: String,
+}
+
+impl ProfileExportMessage {
+    /// Create a new profile export message
+    pub fn new(subject: String) -> Self {
+        Self { subject }
+    }
+}
+
+impl Message for ProfileExportMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ProfileExportMessage(self.clone())
+    }
+}
+
+/// Message for restoring an account from an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileImportMessage {
+    /// Subject of the account to create (user@domain)
+    pub subject: String,
+    /// File name of the archive in the archive directory
+    pub archive: String,
+}
+
+impl ProfileImportMessage {
+    /// Create a new profile import message
+    pub fn new(subject: String, archive: String) -> Self {
+        Self { subject, archive }
+    }
+}
+
+impl Message for ProfileImportMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ProfileImportMessage(self.clone())
+    }
+}
+
+/// Message for creating a Group actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupCreateMessage {
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Actor IDs of the group's moderators
+    #[serde(default)]
+    pub moderators: Vec<String>,
+}
+
+impl GroupCreateMessage {
+    /// Create a new group creation message
+    pub fn new(
+        subject: String,
+        name: Option<String>,
+        summary: Option<String>,
+        moderators: Vec<String>,
+    ) -> Self {
+        Self {
+            subject,
+            name,
+            summary,
+            moderators,
+        }
+    }
+}
+
+impl Message for GroupCreateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::GroupCreateMessage(self.clone())
+    }
+}
+
+/// Message for banning a member from a Group actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupBanMessage {
+    /// Subject of the group (name@domain)
+    pub group: String,
+    /// Actor ID of the member
+    pub actor: String,
+}
+
+impl GroupBanMessage {
+    /// Create a new group ban message
+    pub fn new(group: String, actor: String) -> Self {
+        Self { group, actor }
+    }
+}
+
+impl Message for GroupBanMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::GroupBanMessage(self.clone())
+    }
+}
+
 /// Message for creating a note
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoteCreateMessage {
@@ -167,6 +472,9 @@ pub struct NoteCreateMessage {
     pub tags: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<Value>,
+    /// Publish the note at this time instead of immediately
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled_at: Option<DateTime<Utc>>,
 }
 
 impl NoteCreateMessage {
@@ -186,8 +494,15 @@ impl NoteCreateMessage {
             mentions,
             tags,
             properties,
+            scheduled_at: None,
         }
     }
+
+    /// Schedule the note for publication at `at`
+    pub fn with_scheduled_at(mut self, at: DateTime<Utc>) -> Self {
+        self.scheduled_at = Some(at);
+        self
+    }
 }
 
 impl Message for NoteCreateMessage {
@@ -208,6 +523,9 @@ pub struct NoteUpdateMessage {
     pub tags: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<Value>,
+    /// Pin the note to its author's profile or unpin it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub featured: Option<bool>,
 }
 
 impl NoteUpdateMessage {
@@ -225,8 +543,15 @@ impl NoteUpdateMessage {
             summary,
             tags,
             properties,
+            featured: None,
         }
     }
+
+    /// Pin the note to its author's profile, or unpin it
+    pub fn with_featured(mut self, featured: bool) -> Self {
+        self.featured = Some(featured);
+        self
+    }
 }
 
 impl Message for NoteUpdateMessage {
@@ -535,6 +860,308 @@ impl Message for DomainDeleteMessage {
     }
 }
 
+/// Message for subscribing a domain's instance actor to a relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelaySubscribeMessage {
+    pub domain: String,
+    /// Actor ID of the relay
+    pub relay: String,
+}
+
+impl RelaySubscribeMessage {
+    /// Create a new relay subscription message
+    pub fn new(domain: String, relay: String) -> Self {
+        Self { domain, relay }
+    }
+}
+
+impl Message for RelaySubscribeMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::RelaySubscribeMessage(self.clone())
+    }
+}
+
+/// Message for ending a domain's subscription to a relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayUnsubscribeMessage {
+    pub domain: String,
+    /// Actor ID of the relay
+    pub relay: String,
+}
+
+impl RelayUnsubscribeMessage {
+    /// Create a new relay unsubscription message
+    pub fn new(domain: String, relay: String) -> Self {
+        Self { domain, relay }
+    }
+}
+
+impl Message for RelayUnsubscribeMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::RelayUnsubscribeMessage(self.clone())
+    }
+}
+
+/// Event a webhook can subscribe to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WebhookEvent {
+    /// A local user or person was created
+    #[serde(rename = "user.registered")]
+    UserRegistered,
+    /// A report was filed against an actor or post
+    #[serde(rename = "report.created")]
+    ReportCreated,
+    /// Deliveries to a remote host failed too often in a row
+    #[serde(rename = "delivery.failing")]
+    DeliveryFailing,
+    /// A key of an actor of the domain was rotated
+    #[serde(rename = "key.rotated")]
+    KeyRotated,
+}
+
+impl WebhookEvent {
+    /// Every event, in the order they are documented
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::UserRegistered,
+        WebhookEvent::ReportCreated,
+        WebhookEvent::DeliveryFailing,
+        WebhookEvent::KeyRotated,
+    ];
+
+    /// Name of the event in callbacks and filters
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::UserRegistered => "user.registered",
+            WebhookEvent::ReportCreated => "report.created",
+            WebhookEvent::DeliveryFailing => "delivery.failing",
+            WebhookEvent::KeyRotated => "key.rotated",
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WebhookEvent::ALL
+            .into_iter()
+            .find(|event| event.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = WebhookEvent::ALL.iter().map(|e| e.as_str()).collect();
+                format!(
+                    "Unknown webhook event '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Event published on `EXCHANGE_WEBHOOKS`, and the body of the callback
+/// sent to every webhook of its domain subscribed to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEventMessage {
+    /// Unique event ID, the same in every callback of the event
+    pub id: String,
+    pub event: WebhookEvent,
+    /// Domain whose webhooks receive the event
+    pub domain: String,
+    /// Event details
+    pub data: Value,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl WebhookEventMessage {
+    /// Create an event that occurred now
+    pub fn new(event: WebhookEvent, domain: String, data: Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            event,
+            domain,
+            data,
+            occurred_at: Utc::now(),
+        }
+    }
+}
+
+/// Callback of one event to one webhook, queued by the event fanout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryMessage {
+    pub webhook_id: String,
+    pub event: WebhookEventMessage,
+}
+
+/// Message for registering a webhook of a domain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookCreateMessage {
+    pub domain: String,
+    /// Endpoint callbacks are POSTed to
+    pub url: String,
+    /// Shared secret the callbacks are signed with
+    pub secret: String,
+    /// Events to send; all events when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookCreateMessage {
+    /// Create a new webhook registration message
+    pub fn new(domain: String, url: String, secret: String, events: Vec<WebhookEvent>) -> Self {
+        Self {
+            domain,
+            url,
+            secret,
+            events,
+        }
+    }
+}
+
+impl Message for WebhookCreateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::WebhookCreateMessage(self.clone())
+    }
+}
+
+/// Message for removing a webhook of a domain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeleteMessage {
+    pub domain: String,
+    pub webhook_id: String,
+}
+
+impl WebhookDeleteMessage {
+    /// Create a new webhook removal message
+    pub fn new(domain: String, webhook_id: String) -> Self {
+        Self { domain, webhook_id }
+    }
+}
+
+impl Message for WebhookDeleteMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::WebhookDeleteMessage(self.clone())
+    }
+}
+
+/// Kinds of email sent by maild, each rendered from its own templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailKind {
+    /// Sign-up received, account activated or application rejected
+    RegistrationConfirmation,
+    /// Link to choose a new password
+    PasswordReset,
+    /// Moderation state of the recipient's account changed
+    ModerationNotice,
+    /// Summary of pending reviews sent to a domain's contact address
+    AdminDigest,
+}
+
+impl EmailKind {
+    pub const ALL: [EmailKind; 4] = [
+        EmailKind::RegistrationConfirmation,
+        EmailKind::PasswordReset,
+        EmailKind::ModerationNotice,
+        EmailKind::AdminDigest,
+    ];
+
+    /// Name of the kind's templates
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailKind::RegistrationConfirmation => "registration_confirmation",
+            EmailKind::PasswordReset => "password_reset",
+            EmailKind::ModerationNotice => "moderation_notice",
+            EmailKind::AdminDigest => "admin_digest",
+        }
+    }
+}
+
+impl std::fmt::Display for EmailKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Email published on `EXCHANGE_EMAIL` for maild to render and send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailMessage {
+    /// Unique email ID, used as the Message-ID
+    pub id: String,
+    pub kind: EmailKind,
+    /// Domain the email is sent for, which selects the sender
+    pub domain: String,
+    /// Recipient address
+    pub to: String,
+    /// Values available to the templates
+    pub data: Value,
+    pub queued_at: DateTime<Utc>,
+}
+
+impl EmailMessage {
+    /// Create an email queued now
+    pub fn new(kind: EmailKind, domain: String, to: String, data: Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            domain,
+            to,
+            data,
+            queued_at: Utc::now(),
+        }
+    }
+}
+
+/// Kinds of notifications local users receive
+///
+/// Direct messages are mentions, as in Mastodon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationType {
+    /// A post mentions or is addressed to the user
+    Mention,
+    /// An actor followed the user
+    Follow,
+    /// An actor liked a post of the user
+    Favourite,
+    /// An actor rejected the user's follow request
+    FollowRejected,
+}
+
+impl NotificationType {
+    /// Name of the type in the Mastodon API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationType::Mention => "mention",
+            NotificationType::Follow => "follow",
+            NotificationType::Favourite => "favourite",
+            NotificationType::FollowRejected => "follow_rejected",
+        }
+    }
+}
+
+/// Notification published on `EXCHANGE_PUSH` for the push subscriptions of
+/// its recipient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushMessage {
+    pub notification_id: String,
+    pub notification_type: NotificationType,
+    /// Local actor notified
+    pub recipient: String,
+    pub username: String,
+    pub domain: String,
+    /// Actor who mentioned, followed, liked or rejected the recipient
+    pub account: String,
+    /// Post mentioning the recipient, or the post liked
+    pub object_id: Option<String>,
+    /// Whether the post is a direct message
+    pub direct: bool,
+}
+
 /// RPC request message for domain queries
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DomainRpcRequest {
@@ -547,6 +1174,7 @@ pub struct DomainRpcRequest {
 pub enum DomainRpcRequestType {
     ListDomains,
     GetDomain { domain: String },
+    ListWebhooks { domain: String },
 }
 
 impl DomainRpcRequest {
@@ -565,6 +1193,14 @@ impl DomainRpcRequest {
             request_type: DomainRpcRequestType::GetDomain { domain },
         }
     }
+
+    /// Create a new request for the webhooks of a domain
+    pub fn list_webhooks(request_id: String, domain: String) -> Self {
+        Self {
+            request_id,
+            request_type: DomainRpcRequestType::ListWebhooks { domain },
+        }
+    }
 }
 
 impl Message for DomainRpcRequest {
@@ -585,6 +1221,7 @@ pub struct DomainRpcResponse {
 pub enum DomainRpcResult {
     DomainList { domains: Vec<DomainInfo> },
     DomainDetails { domain: Box<Option<DomainInfo>> },
+    WebhookList { webhooks: Vec<WebhookInfo> },
     Error { message: String },
 }
 
@@ -605,6 +1242,16 @@ pub struct DomainInfo {
     pub updated_at: String,
 }
 
+/// Webhook information for RPC responses, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookInfo {
+    pub webhook_id: String,
+    pub url: String,
+    /// Subscribed events; all events when empty
+    pub events: Vec<WebhookEvent>,
+    pub created_at: String,
+}
+
 impl DomainRpcResponse {
     /// Create a domain list response
     pub fn domain_list(request_id: String, domains: Vec<DomainInfo>) -> Self {
@@ -624,6 +1271,14 @@ impl DomainRpcResponse {
         }
     }
 
+    /// Create a webhook list response
+    pub fn webhook_list(request_id: String, webhooks: Vec<WebhookInfo>) -> Self {
+        Self {
+            request_id,
+            result: DomainRpcResult::WebhookList { webhooks },
+        }
+    }
+
     /// Create an error response
     pub fn error(request_id: String, message: String) -> Self {
         Self {
@@ -660,6 +1315,9 @@ pub struct IncomingObjectMessage {
     pub received_at: String,
     /// Source IP or identifier for tracking
     pub source: Option<String>,
+    /// Set if the object is replayed from storage rather than received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<ReplayMarker>,
 }
 
 impl Message for IncomingObjectMessage {
@@ -689,6 +1347,9 @@ pub struct IncomingActivityMessage {
     pub received_at: String,
     /// Source IP or identifier for tracking
     pub source: Option<String>,
+    /// Set if the activity is replayed from storage rather than received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<ReplayMarker>,
 }
 
 impl Message for IncomingActivityMessage {
@@ -722,64 +1383,408 @@ impl Message for KeyGenerateMessage {
     }
 }
 
-/// Message for creating a user
+/// Notice broadcast on `EXCHANGE_KEY_EVENTS` when an actor's keys change
+///
+/// Services caching signing keys drop the actor's entry when they receive it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UserCreateMessage {
-    pub username: String,
-    pub display_name: Option<String>,
-    pub domain: String,
+pub struct KeyChangedMessage {
+    pub actor_id: String,
+    /// The key that was added or changed status
+    pub key_id: String,
 }
 
-impl UserCreateMessage {
-    /// Create a new user creation message
-    pub fn new(username: String, display_name: Option<String>, domain: String) -> Self {
-        Self {
-            username,
-            display_name,
-            domain,
-        }
+impl KeyChangedMessage {
+    pub fn new(actor_id: String, key_id: String) -> Self {
+        Self { actor_id, key_id }
     }
 }
 
-impl Message for UserCreateMessage {
+impl Message for KeyChangedMessage {
     fn to_message(&self) -> MessageEnum {
-        MessageEnum::UserCreateMessage(self.clone())
+        MessageEnum::KeyChangedMessage(self.clone())
     }
 }
 
-/// RPC request message for user queries
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UserRpcRequest {
-    pub request_id: String,
-    pub request_type: UserRpcRequestType,
+/// How an actor's key is rotated
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotationType {
+    /// The old key stays valid during an overlap window
+    Scheduled,
+    /// The old key is revoked immediately, e.g. after a compromise
+    Emergency,
 }
 
-/// Types of user RPC requests
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum UserRpcRequestType {
-    ListUsers,
-    GetUser { username: String },
-}
+impl std::str::FromStr for KeyRotationType {
+    type Err = String;
 
-impl UserRpcRequest {
-    /// Create a new user list request
-    pub fn list_users(request_id: String) -> Self {
-        Self {
-            request_id,
-            request_type: UserRpcRequestType::ListUsers,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scheduled" => Ok(Self::Scheduled),
+            "emergency" => Ok(Self::Emergency),
+            other => Err(format!(
+                "Unknown rotation type '{}', expected 'scheduled' or 'emergency'",
+                other
+            )),
         }
     }
+}
 
-    /// Create a new user get request
-    pub fn get_user(request_id: String, username: String) -> Self {
+/// Message for rotating an actor's key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotateMessage {
+    pub actor: String,
+    pub rotation_type: KeyRotationType,
+    /// Algorithm of the new key, defaults to that of the current key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_size: Option<u32>,
+}
+
+impl KeyRotateMessage {
+    /// Create a new key rotation message
+    pub fn new(
+        actor: String,
+        rotation_type: KeyRotationType,
+        algorithm: Option<String>,
+        key_size: Option<u32>,
+    ) -> Self {
         Self {
-            request_id,
-            request_type: UserRpcRequestType::GetUser { username },
+            actor,
+            rotation_type,
+            algorithm,
+            key_size,
         }
     }
 }
 
-impl Message for UserRpcRequest {
+impl Message for KeyRotateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::KeyRotateMessage(self.clone())
+    }
+}
+
+/// Message for importing an existing key pair of an actor (BYOK)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyImportMessage {
+    pub actor: String,
+    /// Algorithm the key pair is expected to use (rsa or ed25519)
+    pub algorithm: String,
+    pub public_key_pem: String,
+    pub private_key_pem: String,
+}
+
+impl KeyImportMessage {
+    /// Create a new key import message
+    pub fn new(
+        actor: String,
+        algorithm: String,
+        public_key_pem: String,
+        private_key_pem: String,
+    ) -> Self {
+        Self {
+            actor,
+            algorithm,
+            public_key_pem,
+            private_key_pem,
+        }
+    }
+}
+
+impl Message for KeyImportMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::KeyImportMessage(self.clone())
+    }
+}
+
+/// Message for revoking a retired key of an actor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRevokeMessage {
+    pub actor: String,
+    pub key_id: String,
+}
+
+impl KeyRevokeMessage {
+    /// Create a new key revocation message
+    pub fn new(actor: String, key_id: String) -> Self {
+        Self { actor, key_id }
+    }
+}
+
+impl Message for KeyRevokeMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::KeyRevokeMessage(self.clone())
+    }
+}
+
+/// Message for creating a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserCreateMessage {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub domain: String,
+}
+
+impl UserCreateMessage {
+    /// Create a new user creation message
+    pub fn new(username: String, display_name: Option<String>, domain: String) -> Self {
+        Self {
+            username,
+            display_name,
+            domain,
+        }
+    }
+}
+
+impl Message for UserCreateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::UserCreateMessage(self.clone())
+    }
+}
+
+/// Message setting the password of a local user or starting a reset
+///
+/// Carries hashes only; see [`crate::credentials`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserPasswordMessage {
+    pub username: String,
+    pub domain: String,
+    pub change: PasswordChange,
+}
+
+/// Change of a user's password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PasswordChange {
+    /// Replace the password and revoke the user's tokens
+    Set { password_hash: String },
+    /// Let the user choose a new password with the token of this hash
+    Reset {
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+        /// Link emailed to the user, if they gave an address
+        #[serde(default)]
+        reset_url: Option<String>,
+    },
+}
+
+impl UserPasswordMessage {
+    /// Create a new password message
+    pub fn new(username: String, domain: String, change: PasswordChange) -> Self {
+        Self {
+            username,
+            domain,
+            change,
+        }
+    }
+}
+
+impl Message for UserPasswordMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::UserPasswordMessage(self.clone())
+    }
+}
+
+/// Message revoking all OAuth tokens of a local user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSessionsRevokeMessage {
+    pub username: String,
+    pub domain: String,
+}
+
+impl UserSessionsRevokeMessage {
+    /// Create a new session revocation message
+    pub fn new(username: String, domain: String) -> Self {
+        Self { username, domain }
+    }
+}
+
+impl Message for UserSessionsRevokeMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::UserSessionsRevokeMessage(self.clone())
+    }
+}
+
+/// Decision on a registration application
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationDecision {
+    /// Create the account
+    Approve,
+    /// Turn the applicant down
+    Reject,
+}
+
+/// Message approving or rejecting a pending registration application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationReviewMessage {
+    pub application_id: String,
+    pub decision: RegistrationDecision,
+    /// Moderator who reviewed the application
+    pub reviewed_by: Option<String>,
+    /// Reason given for the decision
+    pub note: Option<String>,
+}
+
+impl RegistrationReviewMessage {
+    /// Create a new registration review message
+    pub fn new(
+        application_id: String,
+        decision: RegistrationDecision,
+        reviewed_by: Option<String>,
+        note: Option<String>,
+    ) -> Self {
+        Self {
+            application_id,
+            decision,
+            reviewed_by,
+            note,
+        }
+    }
+}
+
+impl Message for RegistrationReviewMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::RegistrationReviewMessage(self.clone())
+    }
+}
+
+/// Message creating an invite code of a domain
+///
+/// Carries the hash of the code only; the code itself goes to the
+/// administrator who asked for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteCreateMessage {
+    pub domain: String,
+    pub code_hash: String,
+    /// Sign-ups the code allows; unlimited when `None`
+    pub max_uses: Option<u32>,
+    /// When the code stops being accepted; never when `None`
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl InviteCreateMessage {
+    /// Create a new invite creation message
+    pub fn new(
+        domain: String,
+        code_hash: String,
+        max_uses: Option<u32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            domain,
+            code_hash,
+            max_uses,
+            expires_at,
+        }
+    }
+}
+
+impl Message for InviteCreateMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::InviteCreateMessage(self.clone())
+    }
+}
+
+/// Window of a listing requested over RPC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    /// Number of matching items to skip
+    pub offset: u64,
+    /// Maximum number of items
+    pub limit: u32,
+}
+
+/// Page of a listing with the number of all matching items
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    /// `offset` of the next page, `None` on the last page
+    pub next: Option<u64>,
+}
+
+impl<T> Page<T> {
+    /// Page of the `items` found for `request` out of `total` matches
+    pub fn new(items: Vec<T>, total: u64, request: PageRequest) -> Self {
+        let end = request.offset + items.len() as u64;
+        let next = (!items.is_empty() && end < total).then_some(end);
+        Self { items, total, next }
+    }
+
+    /// Page with each item converted by `f`
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next: self.next,
+        }
+    }
+}
+
+/// RPC request message for user queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRpcRequest {
+    pub request_id: String,
+    pub request_type: UserRpcRequestType,
+}
+
+/// Types of user RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum UserRpcRequestType {
+    /// Page through local users, optionally of one domain
+    ListUsers {
+        domain: Option<String>,
+        page: PageRequest,
+    },
+    GetUser {
+        username: String,
+    },
+    /// Page through registration applications, optionally of one domain
+    /// and status
+    ListApplications {
+        domain: Option<String>,
+        status: Option<String>,
+        page: PageRequest,
+    },
+}
+
+impl UserRpcRequest {
+    /// Create a new user list request
+    pub fn list_users(request_id: String, domain: Option<String>, page: PageRequest) -> Self {
+        Self {
+            request_id,
+            request_type: UserRpcRequestType::ListUsers { domain, page },
+        }
+    }
+
+    /// Create a new user get request
+    pub fn get_user(request_id: String, username: String) -> Self {
+        Self {
+            request_id,
+            request_type: UserRpcRequestType::GetUser { username },
+        }
+    }
+
+    /// Create a new registration application list request
+    pub fn list_applications(
+        request_id: String,
+        domain: Option<String>,
+        status: Option<String>,
+        page: PageRequest,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: UserRpcRequestType::ListApplications {
+                domain,
+                status,
+                page,
+            },
+        }
+    }
+}
+
+impl Message for UserRpcRequest {
     fn to_message(&self) -> MessageEnum {
         MessageEnum::UserRpcRequest(self.clone())
     }
@@ -795,8 +1800,9 @@ pub struct UserRpcResponse {
 /// Results of user RPC requests
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UserRpcResult {
-    UserList { users: Vec<UserInfo> },
+    UserList { page: Page<UserInfo> },
     UserDetails { user: Box<Option<UserInfo>> },
+    ApplicationList { page: Page<ApplicationInfo> },
     Error { message: String },
 }
 
@@ -813,12 +1819,27 @@ pub struct UserInfo {
     pub updated_at: String,
 }
 
+/// Registration application information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationInfo {
+    pub application_id: String,
+    pub username: String,
+    pub domain: String,
+    pub email: Option<String>,
+    pub reason: Option<String>,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub review_note: Option<String>,
+    pub created_at: String,
+    pub reviewed_at: Option<String>,
+}
+
 impl UserRpcResponse {
     /// Create a user list response
-    pub fn user_list(request_id: String, users: Vec<UserInfo>) -> Self {
+    pub fn user_list(request_id: String, page: Page<UserInfo>) -> Self {
         Self {
             request_id,
-            result: UserRpcResult::UserList { users },
+            result: UserRpcResult::UserList { page },
         }
     }
 
@@ -832,6 +1853,14 @@ impl UserRpcResponse {
         }
     }
 
+    /// Create a registration application list response
+    pub fn application_list(request_id: String, page: Page<ApplicationInfo>) -> Self {
+        Self {
+            request_id,
+            result: UserRpcResult::ApplicationList { page },
+        }
+    }
+
     /// Create an error response
     pub fn error(request_id: String, message: String) -> Self {
         Self {
@@ -847,6 +1876,134 @@ impl Message for UserRpcResponse {
     }
 }
 
+/// RPC request message for actor queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorRpcRequest {
+    pub request_id: String,
+    pub request_type: ActorRpcRequestType,
+}
+
+/// Types of actor RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ActorRpcRequestType {
+    /// Page through local and remote actors
+    ///
+    /// `domain`, `local` and `status` (active, suspended, deleted or
+    /// pending) limit the page to matching actors.
+    ListActors {
+        domain: Option<String>,
+        local: Option<bool>,
+        status: Option<String>,
+        page: PageRequest,
+    },
+    /// Get an actor by its ID or, for local actors, user@domain
+    GetActor { id: String },
+}
+
+impl ActorRpcRequest {
+    /// Create an actor list request
+    pub fn list_actors(
+        request_id: String,
+        domain: Option<String>,
+        local: Option<bool>,
+        status: Option<String>,
+        page: PageRequest,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: ActorRpcRequestType::ListActors {
+                domain,
+                local,
+                status,
+                page,
+            },
+        }
+    }
+
+    /// Create an actor get request
+    pub fn get_actor(request_id: String, id: String) -> Self {
+        Self {
+            request_id,
+            request_type: ActorRpcRequestType::GetActor { id },
+        }
+    }
+}
+
+impl Message for ActorRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ActorRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for actor queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorRpcResponse {
+    pub request_id: String,
+    pub result: ActorRpcResult,
+}
+
+/// Results of actor RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ActorRpcResult {
+    ActorList { page: Page<ActorInfo> },
+    ActorDetails { actor: Box<Option<ActorInfo>> },
+    Error { message: String },
+}
+
+/// Actor information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorInfo {
+    pub actor_id: String,
+    pub actor_type: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub domain: String,
+    pub local: bool,
+    /// Account status (active, suspended, deleted or pending)
+    pub status: String,
+    /// Moderation restriction (none, silenced or limited)
+    pub restriction: String,
+    pub followers_count: i64,
+    pub following_count: i64,
+    pub statuses_count: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl ActorRpcResponse {
+    /// Create an actor list response
+    pub fn actor_list(request_id: String, page: Page<ActorInfo>) -> Self {
+        Self {
+            request_id,
+            result: ActorRpcResult::ActorList { page },
+        }
+    }
+
+    /// Create an actor details response
+    pub fn actor_details(request_id: String, actor: Option<ActorInfo>) -> Self {
+        Self {
+            request_id,
+            result: ActorRpcResult::ActorDetails {
+                actor: Box::new(actor),
+            },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: ActorRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for ActorRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ActorRpcResponse(self.clone())
+    }
+}
+
 /// RPC request message for follow queries
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FollowRpcRequest {
@@ -861,6 +2018,27 @@ pub enum FollowRpcRequestType {
     ListFollowing { actor: String },
     /// List accounts that follow the given actor (incoming follows)
     ListFollowers { actor: String },
+    /// Page through the follows of an actor newest first, with counts
+    ///
+    /// `status` limits the page to follows with that status; `before`
+    /// continues after the follow with that ID.
+    FollowPage {
+        actor: String,
+        direction: FollowDirection,
+        status: Option<String>,
+        before: Option<String>,
+        limit: u32,
+    },
+}
+
+/// Which follows of an actor to list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FollowDirection {
+    /// Accounts following the actor
+    Followers,
+    /// Accounts the actor follows
+    Following,
 }
 
 impl FollowRpcRequest {
@@ -879,6 +2057,27 @@ impl FollowRpcRequest {
             request_type: FollowRpcRequestType::ListFollowers { actor },
         }
     }
+
+    /// Create a request for a page of an actor's follows
+    pub fn follow_page(
+        request_id: String,
+        actor: String,
+        direction: FollowDirection,
+        status: Option<String>,
+        before: Option<String>,
+        limit: u32,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: FollowRpcRequestType::FollowPage {
+                actor,
+                direction,
+                status,
+                before,
+                limit,
+            },
+        }
+    }
 }
 
 impl Message for FollowRpcRequest {
@@ -898,9 +2097,29 @@ pub struct FollowRpcResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FollowRpcResult {
     FollowList { follows: Vec<FollowInfo> },
+    FollowPage { page: FollowPage },
     Error { message: String },
 }
 
+/// Page of an actor's follows with the counts of all of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FollowPage {
+    pub items: Vec<FollowInfo>,
+    pub counts: FollowCounts,
+    /// `before` of the next page, `None` on the last page
+    pub next: Option<String>,
+}
+
+/// Number of an actor's follows by status
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FollowCounts {
+    pub total: u64,
+    pub accepted: u64,
+    pub pending: u64,
+    pub rejected: u64,
+    pub cancelled: u64,
+}
+
 /// Follow relationship information for RPC responses
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FollowInfo {
@@ -921,8 +2140,16 @@ impl FollowRpcResponse {
         }
     }
 
-    /// Create an error response
-    pub fn error(request_id: String, message: String) -> Self {
+    /// Create a follow page response
+    pub fn follow_page(request_id: String, page: FollowPage) -> Self {
+        Self {
+            request_id,
+            result: FollowRpcResult::FollowPage { page },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
         Self {
             request_id,
             result: FollowRpcResult::Error { message },
@@ -935,3 +2162,1673 @@ impl Message for FollowRpcResponse {
         MessageEnum::FollowRpcResponse(self.clone())
     }
 }
+
+/// RPC request message for notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRpcRequest {
+    pub request_id: String,
+    pub request_type: NoteRpcRequestType,
+}
+
+/// Types of note RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NoteRpcRequestType {
+    /// List scheduled notes, optionally of one actor
+    ListScheduled { actor: Option<String> },
+    /// Delete a scheduled note before it is published
+    CancelScheduled { object_id: String },
+    /// Page through published local posts newest first, optionally of
+    /// one actor
+    ListNotes {
+        actor: Option<String>,
+        page: PageRequest,
+    },
+    /// Get a stored post
+    GetNote { object_id: String },
+}
+
+impl NoteRpcRequest {
+    /// Create a request to list scheduled notes
+    pub fn list_scheduled(request_id: String, actor: Option<String>) -> Self {
+        Self {
+            request_id,
+            request_type: NoteRpcRequestType::ListScheduled { actor },
+        }
+    }
+
+    /// Create a request to cancel a scheduled note
+    pub fn cancel_scheduled(request_id: String, object_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: NoteRpcRequestType::CancelScheduled { object_id },
+        }
+    }
+
+    /// Create a request for a page of published posts
+    pub fn list_notes(request_id: String, actor: Option<String>, page: PageRequest) -> Self {
+        Self {
+            request_id,
+            request_type: NoteRpcRequestType::ListNotes { actor, page },
+        }
+    }
+
+    /// Create a request for a stored post
+    pub fn get_note(request_id: String, object_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: NoteRpcRequestType::GetNote { object_id },
+        }
+    }
+}
+
+impl Message for NoteRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::NoteRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRpcResponse {
+    pub request_id: String,
+    pub result: NoteRpcResult,
+}
+
+/// Results of note RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NoteRpcResult {
+    ScheduledList { notes: Vec<ScheduledNoteInfo> },
+    Cancelled { cancelled: bool },
+    NoteList { page: Page<NoteInfo> },
+    NoteDetails { note: Box<Option<NoteInfo>> },
+    Error { message: String },
+}
+
+/// Post information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteInfo {
+    pub object_id: String,
+    pub object_type: String,
+    pub author: String,
+    pub content: Option<String>,
+    pub summary: Option<String>,
+    pub in_reply_to: Option<String>,
+    /// Post quoted by this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+    /// Times the post was quoted
+    #[serde(default)]
+    pub quote_count: i64,
+    /// Visibility (public, unlisted, followers, direct, ...)
+    pub visibility: String,
+    pub local: bool,
+    pub published: Option<String>,
+}
+
+/// Scheduled note information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledNoteInfo {
+    pub object_id: String,
+    pub object_type: String,
+    pub author: String,
+    pub content: Option<String>,
+    pub scheduled_at: String,
+}
+
+impl NoteRpcResponse {
+    /// Create a scheduled note list response
+    pub fn scheduled_list(request_id: String, notes: Vec<ScheduledNoteInfo>) -> Self {
+        Self {
+            request_id,
+            result: NoteRpcResult::ScheduledList { notes },
+        }
+    }
+
+    /// Create a cancellation response
+    pub fn cancelled(request_id: String, cancelled: bool) -> Self {
+        Self {
+            request_id,
+            result: NoteRpcResult::Cancelled { cancelled },
+        }
+    }
+
+    /// Create a post list response
+    pub fn note_list(request_id: String, page: Page<NoteInfo>) -> Self {
+        Self {
+            request_id,
+            result: NoteRpcResult::NoteList { page },
+        }
+    }
+
+    /// Create a post details response
+    pub fn note_details(request_id: String, note: Option<NoteInfo>) -> Self {
+        Self {
+            request_id,
+            result: NoteRpcResult::NoteDetails {
+                note: Box::new(note),
+            },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: NoteRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for NoteRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::NoteRpcResponse(self.clone())
+    }
+}
+
+/// RPC request message for key queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRpcRequest {
+    pub request_id: String,
+    pub request_type: KeyRpcRequestType,
+}
+
+/// Types of key RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KeyRpcRequestType {
+    /// Build and verify the trust chain of a key
+    TrustChain { key_id: String },
+    /// Issue a domain verification challenge for the key of an actor
+    StartVerification {
+        actor: String,
+        domain: String,
+        method: VerificationMethod,
+    },
+    /// Check the published challenge and sign the key if it passes
+    CompleteVerification { actor: String, domain: String },
+    /// Page through stored keys, optionally of one actor or trust level
+    ListKeys {
+        actor: Option<String>,
+        trust_level: Option<TrustLevel>,
+        page: PageRequest,
+    },
+}
+
+impl KeyRpcRequest {
+    /// Create a trust chain request
+    pub fn trust_chain(request_id: String, key_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: KeyRpcRequestType::TrustChain { key_id },
+        }
+    }
+
+    /// Create a request starting domain verification
+    pub fn start_verification(
+        request_id: String,
+        actor: String,
+        domain: String,
+        method: VerificationMethod,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: KeyRpcRequestType::StartVerification {
+                actor,
+                domain,
+                method,
+            },
+        }
+    }
+
+    /// Create a request completing domain verification
+    pub fn complete_verification(request_id: String, actor: String, domain: String) -> Self {
+        Self {
+            request_id,
+            request_type: KeyRpcRequestType::CompleteVerification { actor, domain },
+        }
+    }
+
+    /// Create a key list request
+    pub fn list_keys(
+        request_id: String,
+        actor: Option<String>,
+        trust_level: Option<TrustLevel>,
+        page: PageRequest,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: KeyRpcRequestType::ListKeys {
+                actor,
+                trust_level,
+                page,
+            },
+        }
+    }
+}
+
+impl Message for KeyRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::KeyRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for key queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRpcResponse {
+    pub request_id: String,
+    pub result: KeyRpcResult,
+}
+
+/// Results of key RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KeyRpcResult {
+    TrustChain {
+        report: Box<Option<TrustChainReport>>,
+    },
+    /// Current state of a domain verification challenge
+    Verification {
+        challenge: Box<DomainVerificationChallenge>,
+    },
+    KeyList {
+        page: Page<KeyInfo>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Summary of a stored key, without its key material
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub key_id: String,
+    pub actor_id: String,
+    /// Key type (user, domain, master, instance)
+    pub key_type: String,
+    pub algorithm: String,
+    pub key_size: Option<u32>,
+    pub fingerprint: String,
+    pub trust_level: TrustLevel,
+    /// Status of the key (active, rotated, revoked, ...)
+    pub status: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+}
+
+/// Trust chain of a key and the outcome of verifying it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustChainReport {
+    pub chain: TrustChain,
+    /// Status of the key in the key store (active, rotated, revoked, ...)
+    pub status: String,
+    pub revoked: bool,
+    /// Why verification failed, `None` if the chain is valid
+    pub error: Option<String>,
+}
+
+impl KeyRpcResponse {
+    /// Create a trust chain response, `None` if the key is unknown
+    pub fn trust_chain(request_id: String, report: Option<TrustChainReport>) -> Self {
+        Self {
+            request_id,
+            result: KeyRpcResult::TrustChain {
+                report: Box::new(report),
+            },
+        }
+    }
+
+    /// Create a domain verification response
+    pub fn verification(request_id: String, challenge: DomainVerificationChallenge) -> Self {
+        Self {
+            request_id,
+            result: KeyRpcResult::Verification {
+                challenge: Box::new(challenge),
+            },
+        }
+    }
+
+    /// Create a key list response
+    pub fn key_list(request_id: String, page: Page<KeyInfo>) -> Self {
+        Self {
+            request_id,
+            result: KeyRpcResult::KeyList { page },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: KeyRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for KeyRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::KeyRpcResponse(self.clone())
+    }
+}
+
+/// RPC request message for moderation queries and actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationRpcRequest {
+    pub request_id: String,
+    pub request_type: ModerationRpcRequestType,
+}
+
+/// Types of moderation RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ModerationRpcRequestType {
+    /// List reports, optionally filtered by status (open, resolved, dismissed)
+    ListReports { status: Option<String> },
+    /// Get a single report
+    GetReport { report_id: String },
+    /// Apply a moderation action to a report and close it
+    ResolveReport {
+        report_id: String,
+        action: ModerationAction,
+        moderator: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    },
+}
+
+/// Actions a moderator can take on a report
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Close the report without further action
+    Dismiss,
+    /// Delete the reported objects
+    DeleteContent,
+    /// Suspend the reported actor
+    SuspendActor,
+    /// Silence the reported actor's domain
+    SilenceDomain,
+}
+
+impl ModerationRpcRequest {
+    /// Create a new report list request
+    pub fn list_reports(request_id: String, status: Option<String>) -> Self {
+        Self {
+            request_id,
+            request_type: ModerationRpcRequestType::ListReports { status },
+        }
+    }
+
+    /// Create a new report get request
+    pub fn get_report(request_id: String, report_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: ModerationRpcRequestType::GetReport { report_id },
+        }
+    }
+
+    /// Create a new report resolution request
+    pub fn resolve_report(
+        request_id: String,
+        report_id: String,
+        action: ModerationAction,
+        moderator: String,
+        note: Option<String>,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: ModerationRpcRequestType::ResolveReport {
+                report_id,
+                action,
+                moderator,
+                note,
+            },
+        }
+    }
+}
+
+impl Message for ModerationRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ModerationRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for moderation queries and actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationRpcResponse {
+    pub request_id: String,
+    pub result: ModerationRpcResult,
+}
+
+/// Results of moderation RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ModerationRpcResult {
+    ReportList { reports: Vec<ReportInfo> },
+    ReportDetails { report: Box<Option<ReportInfo>> },
+    Error { message: String },
+}
+
+/// Report information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportInfo {
+    pub report_id: String,
+    pub reporter: String,
+    pub target_actor: Option<String>,
+    pub target_objects: Vec<String>,
+    pub content: Option<String>,
+    pub status: String,
+    pub action_taken: Option<String>,
+    pub resolved_by: Option<String>,
+    pub resolution_note: Option<String>,
+    pub created_at: String,
+    pub resolved_at: Option<String>,
+}
+
+impl ModerationRpcResponse {
+    /// Create a report list response
+    pub fn report_list(request_id: String, reports: Vec<ReportInfo>) -> Self {
+        Self {
+            request_id,
+            result: ModerationRpcResult::ReportList { reports },
+        }
+    }
+
+    /// Create a report details response
+    pub fn report_details(request_id: String, report: Option<ReportInfo>) -> Self {
+        Self {
+            request_id,
+            result: ModerationRpcResult::ReportDetails {
+                report: Box::new(report),
+            },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: ModerationRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for ModerationRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ModerationRpcResponse(self.clone())
+    }
+}
+
+/// RPC request message for reviewing quarantined content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamFilterRpcRequest {
+    pub request_id: String,
+    pub request_type: SpamFilterRpcRequestType,
+}
+
+/// Types of spam filter RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SpamFilterRpcRequestType {
+    /// List quarantined content, optionally filtered by status (quarantined, released, discarded)
+    ListQuarantine { status: Option<String> },
+    /// Get a single quarantine entry
+    GetQuarantined { quarantine_id: String },
+    /// Override the filter and pass the content on to storage
+    ReleaseQuarantined {
+        quarantine_id: String,
+        moderator: String,
+    },
+    /// Confirm the filter decision and drop the content
+    DiscardQuarantined {
+        quarantine_id: String,
+        moderator: String,
+    },
+}
+
+impl SpamFilterRpcRequest {
+    /// Create a new quarantine list request
+    pub fn list_quarantine(request_id: String, status: Option<String>) -> Self {
+        Self {
+            request_id,
+            request_type: SpamFilterRpcRequestType::ListQuarantine { status },
+        }
+    }
+
+    /// Create a new quarantine get request
+    pub fn get_quarantined(request_id: String, quarantine_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: SpamFilterRpcRequestType::GetQuarantined { quarantine_id },
+        }
+    }
+
+    /// Create a new quarantine release request
+    pub fn release_quarantined(
+        request_id: String,
+        quarantine_id: String,
+        moderator: String,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: SpamFilterRpcRequestType::ReleaseQuarantined {
+                quarantine_id,
+                moderator,
+            },
+        }
+    }
+
+    /// Create a new quarantine discard request
+    pub fn discard_quarantined(
+        request_id: String,
+        quarantine_id: String,
+        moderator: String,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: SpamFilterRpcRequestType::DiscardQuarantined {
+                quarantine_id,
+                moderator,
+            },
+        }
+    }
+}
+
+impl Message for SpamFilterRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::SpamFilterRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for quarantine review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamFilterRpcResponse {
+    pub request_id: String,
+    pub result: SpamFilterRpcResult,
+}
+
+/// Results of spam filter RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SpamFilterRpcResult {
+    QuarantineList { items: Vec<QuarantineInfo> },
+    QuarantineDetails { item: Box<Option<QuarantineInfo>> },
+    Error { message: String },
+}
+
+/// Quarantine entry information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineInfo {
+    pub quarantine_id: String,
+    pub object_id: Option<String>,
+    pub object_type: String,
+    pub actor: String,
+    pub target_domain: String,
+    pub reasons: Vec<String>,
+    pub content: Option<String>,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub created_at: String,
+    pub reviewed_at: Option<String>,
+}
+
+impl SpamFilterRpcResponse {
+    /// Create a quarantine list response
+    pub fn quarantine_list(request_id: String, items: Vec<QuarantineInfo>) -> Self {
+        Self {
+            request_id,
+            result: SpamFilterRpcResult::QuarantineList { items },
+        }
+    }
+
+    /// Create a quarantine details response
+    pub fn quarantine_details(request_id: String, item: Option<QuarantineInfo>) -> Self {
+        Self {
+            request_id,
+            result: SpamFilterRpcResult::QuarantineDetails {
+                item: Box::new(item),
+            },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: SpamFilterRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for SpamFilterRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::SpamFilterRpcResponse(self.clone())
+    }
+}
+
+/// Delivery priority of an outgoing activity
+///
+/// Interactions between people (follows and their responses, blocks, undos
+/// and direct messages) are delivered ahead of the bulk fanout of public and
+/// followers-only posts.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryPriority {
+    High,
+    Low,
+}
+
+impl DeliveryPriority {
+    /// Classify an activity by its type and addressing
+    pub fn for_activity(activity: &Value) -> Self {
+        let activity_type = activity.get("type").and_then(|t| t.as_str());
+        if matches!(
+            activity_type,
+            Some("Follow" | "Accept" | "Reject" | "Undo" | "Block")
+        ) {
+            return DeliveryPriority::High;
+        }
+
+        if is_direct(activity) {
+            DeliveryPriority::High
+        } else {
+            DeliveryPriority::Low
+        }
+    }
+
+    /// Routing key on the delivery exchange
+    pub fn routing_key(&self) -> &'static str {
+        match self {
+            DeliveryPriority::High => ROUTING_KEY_DELIVERY_HIGH,
+            DeliveryPriority::Low => ROUTING_KEY_DELIVERY_LOW,
+        }
+    }
+}
+
+/// Whether an activity is addressed only to individual actors
+///
+/// Anything addressed to the public collection or to a followers collection
+/// is a fanout, not a direct message.
+fn is_direct(activity: &Value) -> bool {
+    let mut recipients = ["to", "cc", "bto", "bcc"]
+        .iter()
+        .filter_map(|field| activity.get(*field))
+        .flat_map(|value| match value {
+            Value::Array(items) => items.iter().filter_map(|v| v.as_str()).collect(),
+            Value::String(s) => vec![s.as_str()],
+            _ => Vec::new(),
+        })
+        .peekable();
+
+    if recipients.peek().is_none() {
+        return false;
+    }
+
+    recipients.all(|recipient| {
+        recipient != "https://www.w3.org/ns/activitystreams#Public"
+            && recipient != "as:Public"
+            && recipient != "Public"
+            && !recipient.ends_with("/followers")
+    })
+}
+
+/// Whether a failed message is worth retrying automatically
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// The failure may go away on its own (timeouts, unavailable services)
+    Transient,
+    /// Retrying will fail the same way (malformed messages, rejected content)
+    Permanent,
+}
+
+impl FailureClass {
+    /// Header value for the failure class
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::Transient => "transient",
+            FailureClass::Permanent => "permanent",
+        }
+    }
+
+    /// Parse a failure class header value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "transient" => Some(FailureClass::Transient),
+            "permanent" => Some(FailureClass::Permanent),
+            _ => None,
+        }
+    }
+}
+
+/// RPC request message for dead-letter queue management
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlqRpcRequest {
+    pub request_id: String,
+    pub request_type: DlqRpcRequestType,
+}
+
+/// Types of dead-letter RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DlqRpcRequestType {
+    /// List dead letters, optionally filtered by original queue and status (pending, retried)
+    ListDeadLetters {
+        queue: Option<String>,
+        status: Option<String>,
+    },
+    /// Re-inject a dead letter, or all pending ones if no ID is given
+    RetryDeadLetters { dead_letter_id: Option<String> },
+    /// Delete a dead letter, or all of them if no ID is given
+    PurgeDeadLetters { dead_letter_id: Option<String> },
+}
+
+impl DlqRpcRequest {
+    /// Create a new dead-letter list request
+    pub fn list_dead_letters(
+        request_id: String,
+        queue: Option<String>,
+        status: Option<String>,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: DlqRpcRequestType::ListDeadLetters { queue, status },
+        }
+    }
+
+    /// Create a new dead-letter retry request
+    pub fn retry_dead_letters(request_id: String, dead_letter_id: Option<String>) -> Self {
+        Self {
+            request_id,
+            request_type: DlqRpcRequestType::RetryDeadLetters { dead_letter_id },
+        }
+    }
+
+    /// Create a new dead-letter purge request
+    pub fn purge_dead_letters(request_id: String, dead_letter_id: Option<String>) -> Self {
+        Self {
+            request_id,
+            request_type: DlqRpcRequestType::PurgeDeadLetters { dead_letter_id },
+        }
+    }
+}
+
+impl Message for DlqRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DlqRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for dead-letter queue management
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlqRpcResponse {
+    pub request_id: String,
+    pub result: DlqRpcResult,
+}
+
+/// Results of dead-letter RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DlqRpcResult {
+    DeadLetterList { items: Vec<DeadLetterInfo> },
+    Affected { count: u64 },
+    Error { message: String },
+}
+
+/// Dead letter information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterInfo {
+    pub dead_letter_id: String,
+    pub message_id: Option<String>,
+    pub queue: String,
+    pub reason: String,
+    pub stage: Option<String>,
+    pub error: Option<String>,
+    pub failure_class: FailureClass,
+    pub attempts: u32,
+    pub status: String,
+    pub next_retry_at: Option<String>,
+    pub created_at: String,
+    pub retried_at: Option<String>,
+}
+
+impl DlqRpcResponse {
+    /// Create a dead-letter list response
+    pub fn dead_letter_list(request_id: String, items: Vec<DeadLetterInfo>) -> Self {
+        Self {
+            request_id,
+            result: DlqRpcResult::DeadLetterList { items },
+        }
+    }
+
+    /// Create a response reporting the number of affected dead letters
+    pub fn affected(request_id: String, count: u64) -> Self {
+        Self {
+            request_id,
+            result: DlqRpcResult::Affected { count },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: DlqRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for DlqRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DlqRpcResponse(self.clone())
+    }
+}
+
+/// Concurrency limits of the deliveries of a publisher daemon
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeliveryLimits {
+    /// Deliveries running at once across all destinations
+    pub max_in_flight: usize,
+    /// Deliveries running at once to one destination host
+    pub max_per_host: usize,
+}
+
+/// RPC request reading or changing the delivery limits of publisherd
+///
+/// Every running publisherd receives the request and applies a change;
+/// the requester gets the answer of the first one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryLimitsRpcRequest {
+    pub request_id: String,
+    pub request_type: DeliveryLimitsRpcRequestType,
+}
+
+/// Types of delivery limit RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeliveryLimitsRpcRequestType {
+    /// Current limits
+    GetLimits,
+    /// Change the given limits, keeping the others
+    SetLimits {
+        max_in_flight: Option<usize>,
+        max_per_host: Option<usize>,
+    },
+}
+
+impl DeliveryLimitsRpcRequest {
+    /// Create a request for the current limits
+    pub fn get_limits(request_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: DeliveryLimitsRpcRequestType::GetLimits,
+        }
+    }
+
+    /// Create a request changing the limits
+    pub fn set_limits(
+        request_id: String,
+        max_in_flight: Option<usize>,
+        max_per_host: Option<usize>,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: DeliveryLimitsRpcRequestType::SetLimits {
+                max_in_flight,
+                max_per_host,
+            },
+        }
+    }
+}
+
+impl Message for DeliveryLimitsRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DeliveryLimitsRpcRequest(self.clone())
+    }
+}
+
+/// RPC response with the delivery limits in effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryLimitsRpcResponse {
+    pub request_id: String,
+    pub result: DeliveryLimitsRpcResult,
+}
+
+/// Results of delivery limit RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeliveryLimitsRpcResult {
+    Limits { limits: DeliveryLimits },
+    Error { message: String },
+}
+
+impl DeliveryLimitsRpcResponse {
+    /// Create a response with the limits in effect
+    pub fn limits(request_id: String, limits: DeliveryLimits) -> Self {
+        Self {
+            request_id,
+            result: DeliveryLimitsRpcResult::Limits { limits },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: DeliveryLimitsRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for DeliveryLimitsRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DeliveryLimitsRpcResponse(self.clone())
+    }
+}
+
+/// Marks an incoming message replayed through the pipeline by an admin
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplayMarker {
+    /// Identifier of the replay run, for correlating logs
+    pub replay_id: String,
+    /// Whether stages may notify anyone outside the instance again, through
+    /// webhooks, push notifications or deliveries
+    pub allow_delivery: bool,
+}
+
+/// Stored remote activities to replay through the incoming pipeline
+///
+/// Conditions are combined; at least one besides the domain must be given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplayFilter {
+    /// Domain whose pipeline the activities are routed to
+    pub domain: String,
+    /// Only activities stored at or after this storage ID
+    pub from_id: Option<String>,
+    /// Only activities stored at or before this storage ID
+    pub to_id: Option<String>,
+    /// Only activities of this actor
+    pub actor: Option<String>,
+    /// Only activities of this type, e.g. `Create`
+    pub activity_type: Option<String>,
+    /// Only activities stored at or after this RFC 3339 time
+    pub since: Option<String>,
+    /// Only activities stored before this RFC 3339 time
+    pub until: Option<String>,
+}
+
+impl ReplayFilter {
+    /// Whether the filter narrows the activities down at all
+    pub fn is_unbounded(&self) -> bool {
+        self.from_id.is_none()
+            && self.to_id.is_none()
+            && self.actor.is_none()
+            && self.activity_type.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+}
+
+/// RPC request replaying stored activities through the incoming pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayRpcRequest {
+    pub request_id: String,
+    pub filter: ReplayFilter,
+    /// Activities replayed at most, oldest first
+    pub limit: u32,
+    /// Only list the activities that would be replayed
+    pub dry_run: bool,
+    /// Let stages notify remote servers again, see [`ReplayMarker`]
+    pub allow_delivery: bool,
+}
+
+impl Message for ReplayRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ReplayRpcRequest(self.clone())
+    }
+}
+
+/// Stored activity picked by a replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayedActivity {
+    /// Storage ID, usable as bound of a following replay
+    pub storage_id: String,
+    pub activity_id: String,
+    pub activity_type: String,
+    pub actor: String,
+    pub created_at: String,
+}
+
+/// Outcome of a replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySummary {
+    pub replay_id: String,
+    pub dry_run: bool,
+    /// Messages published to the pipeline, objects of `Create` included
+    pub published: u64,
+    pub activities: Vec<ReplayedActivity>,
+    /// Storage ID to continue with if more activities matched than the
+    /// limit allowed
+    pub next_from_id: Option<String>,
+}
+
+/// RPC response of a replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayRpcResponse {
+    pub request_id: String,
+    pub result: ReplayRpcResult,
+}
+
+/// Results of replay RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayRpcResult {
+    Replayed { summary: ReplaySummary },
+    Error { message: String },
+}
+
+impl ReplayRpcResponse {
+    /// Create a response with the outcome of a replay
+    pub fn replayed(request_id: String, summary: ReplaySummary) -> Self {
+        Self {
+            request_id,
+            result: ReplayRpcResult::Replayed { summary },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: ReplayRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for ReplayRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::ReplayRpcResponse(self.clone())
+    }
+}
+
+/// RPC request for the delivery state of an activity sent by publisherd
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryStatusRpcRequest {
+    pub request_id: String,
+    /// ActivityPub ID of the activity
+    pub activity_id: String,
+}
+
+impl Message for DeliveryStatusRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DeliveryStatusRpcRequest(self.clone())
+    }
+}
+
+/// State of the delivery of an activity to one inbox
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// An attempt failed and another one is scheduled
+    Retrying,
+    Delivered,
+    /// Every attempt failed, or the inbox could not be looked up
+    Failed,
+}
+
+impl DeliveryState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryState::Retrying => "retrying",
+            DeliveryState::Delivered => "delivered",
+            DeliveryState::Failed => "failed",
+        }
+    }
+}
+
+/// Delivery of an activity to one inbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxDeliveryInfo {
+    /// Inbox, or the recipient whose inbox could not be looked up
+    pub inbox: String,
+    /// Recipients reached through the inbox
+    pub recipients: Vec<String>,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+    pub next_retry_at: Option<String>,
+    pub updated_at: String,
+}
+
+/// Delivery state of an activity across its inboxes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryStatus {
+    pub activity_id: String,
+    pub delivered: u64,
+    pub retrying: u64,
+    pub failed: u64,
+    pub inboxes: Vec<InboxDeliveryInfo>,
+}
+
+impl DeliveryStatus {
+    /// Summarize the deliveries of an activity
+    pub fn new(activity_id: String, inboxes: Vec<InboxDeliveryInfo>) -> Self {
+        let count = |state| inboxes.iter().filter(|i| i.state == state).count() as u64;
+        Self {
+            activity_id,
+            delivered: count(DeliveryState::Delivered),
+            retrying: count(DeliveryState::Retrying),
+            failed: count(DeliveryState::Failed),
+            inboxes,
+        }
+    }
+}
+
+/// RPC response with the delivery state of an activity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryStatusRpcResponse {
+    pub request_id: String,
+    pub result: DeliveryStatusRpcResult,
+}
+
+/// Results of delivery status RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeliveryStatusRpcResult {
+    Status {
+        status: DeliveryStatus,
+    },
+    /// No delivery of the activity is recorded
+    NotFound,
+    Error {
+        message: String,
+    },
+}
+
+impl DeliveryStatusRpcResponse {
+    /// Create a response with the delivery state of an activity
+    pub fn status(request_id: String, status: DeliveryStatus) -> Self {
+        Self {
+            request_id,
+            result: DeliveryStatusRpcResult::Status { status },
+        }
+    }
+
+    /// Create a response for an activity without recorded deliveries
+    pub fn not_found(request_id: String) -> Self {
+        Self {
+            request_id,
+            result: DeliveryStatusRpcResult::NotFound,
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: DeliveryStatusRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for DeliveryStatusRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::DeliveryStatusRpcResponse(self.clone())
+    }
+}
+
+/// RPC request message for the registry of remote instances
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceRpcRequest {
+    pub request_id: String,
+    pub request_type: InstanceRpcRequestType,
+}
+
+/// Types of instance RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InstanceRpcRequestType {
+    /// Page through known instances by domain, or with `failing` through
+    /// those whose last deliveries failed, longest failure streak first
+    ListInstances { failing: bool, page: PageRequest },
+    /// Get an instance by its domain
+    GetInstance { domain: String },
+    /// Count the known instances by software and version
+    GetStats,
+}
+
+impl InstanceRpcRequest {
+    /// Create an instance list request
+    pub fn list_instances(request_id: String, failing: bool, page: PageRequest) -> Self {
+        Self {
+            request_id,
+            request_type: InstanceRpcRequestType::ListInstances { failing, page },
+        }
+    }
+
+    /// Create an instance get request
+    pub fn get_instance(request_id: String, domain: String) -> Self {
+        Self {
+            request_id,
+            request_type: InstanceRpcRequestType::GetInstance { domain },
+        }
+    }
+
+    /// Create a federation statistics request
+    pub fn get_stats(request_id: String) -> Self {
+        Self {
+            request_id,
+            request_type: InstanceRpcRequestType::GetStats,
+        }
+    }
+}
+
+impl Message for InstanceRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::InstanceRpcRequest(self.clone())
+    }
+}
+
+/// Remote instance information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub domain: String,
+    pub first_seen_at: String,
+    pub last_seen_at: Option<String>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+    pub open_registrations: Option<bool>,
+    /// Last NodeInfo fetch and its error, if it failed
+    pub crawled_at: Option<String>,
+    pub crawl_error: Option<String>,
+    pub last_delivery_at: Option<String>,
+    pub last_failure_at: Option<String>,
+    pub last_error: Option<String>,
+    /// Deliveries that failed since the last successful one
+    pub consecutive_failures: u32,
+    /// Severity of the domain block (silence, suspend), if blocked
+    pub block: Option<String>,
+}
+
+/// Known instances by the software they run, from their NodeInfo
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FederationStats {
+    /// Known instances
+    pub instances: u64,
+    /// Instances whose software is not known yet
+    pub unknown: u64,
+    /// Instances whose last NodeInfo fetch failed
+    pub unreachable: u64,
+    /// Instances by software, most common first
+    pub software: Vec<SoftwareStats>,
+}
+
+/// Instances running one software
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SoftwareStats {
+    /// Software name as reported in NodeInfo, in lower case
+    pub name: String,
+    pub instances: u64,
+    /// Instances reporting open registrations
+    pub open_registrations: u64,
+    /// Instances by version, most common first
+    pub versions: Vec<VersionStats>,
+}
+
+/// Instances running one version of a software
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VersionStats {
+    pub version: String,
+    pub instances: u64,
+}
+
+/// RPC response message for instance queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceRpcResponse {
+    pub request_id: String,
+    pub result: InstanceRpcResult,
+}
+
+/// Results of instance RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InstanceRpcResult {
+    InstanceList { page: Page<InstanceInfo> },
+    InstanceDetails { instance: Box<Option<InstanceInfo>> },
+    Stats { stats: FederationStats },
+    Error { message: String },
+}
+
+impl InstanceRpcResponse {
+    /// Create an instance list response
+    pub fn instance_list(request_id: String, page: Page<InstanceInfo>) -> Self {
+        Self {
+            request_id,
+            result: InstanceRpcResult::InstanceList { page },
+        }
+    }
+
+    /// Create an instance details response
+    pub fn instance_details(request_id: String, instance: Option<InstanceInfo>) -> Self {
+        Self {
+            request_id,
+            result: InstanceRpcResult::InstanceDetails {
+                instance: Box::new(instance),
+            },
+        }
+    }
+
+    /// Create a federation statistics response
+    pub fn stats(request_id: String, stats: FederationStats) -> Self {
+        Self {
+            request_id,
+            result: InstanceRpcResult::Stats { stats },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: InstanceRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for InstanceRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::InstanceRpcResponse(self.clone())
+    }
+}
+
+/// Administrative action recorded in the audit log
+///
+/// Sent by adminservd for every request that changes state and for every
+/// request denied for lack of a role.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEventMessage {
+    /// Subject of the admin user
+    pub actor: String,
+    /// Role of the admin user, if any
+    pub role: Option<String>,
+    /// Action name, such as `domain.update`
+    pub action: String,
+    /// HTTP method of the request
+    pub method: String,
+    /// Request path
+    pub path: String,
+    /// Domain, user or other resource the action applies to
+    pub target: Option<String>,
+    /// HTTP status the request was answered with
+    pub status: u16,
+    /// State of the target before the action, where it can be looked up
+    pub before: Option<Value>,
+    /// Request body, with secrets redacted
+    pub after: Option<Value>,
+    /// Top-level fields whose value differs between `before` and `after`
+    pub changes: Vec<String>,
+    /// When the request was made
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Message for AuditEventMessage {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::AuditEventMessage(self.clone())
+    }
+}
+
+/// RPC request message for audit log queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRpcRequest {
+    pub request_id: String,
+    pub request_type: AuditRpcRequestType,
+}
+
+/// Types of audit RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AuditRpcRequestType {
+    /// List entries newest first, starting after the entry `before`
+    ListEntries {
+        actor: Option<String>,
+        action: Option<String>,
+        target: Option<String>,
+        before: Option<String>,
+        limit: u32,
+    },
+}
+
+impl AuditRpcRequest {
+    /// Create a new audit log list request
+    pub fn list_entries(
+        request_id: String,
+        actor: Option<String>,
+        action: Option<String>,
+        target: Option<String>,
+        before: Option<String>,
+        limit: u32,
+    ) -> Self {
+        Self {
+            request_id,
+            request_type: AuditRpcRequestType::ListEntries {
+                actor,
+                action,
+                target,
+                before,
+                limit,
+            },
+        }
+    }
+}
+
+impl Message for AuditRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::AuditRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for audit log queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRpcResponse {
+    pub request_id: String,
+    pub result: AuditRpcResult,
+}
+
+/// Results of audit RPC requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AuditRpcResult {
+    EntryList { entries: Vec<AuditEntryInfo> },
+    Error { message: String },
+}
+
+/// Audit log entry information for RPC responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntryInfo {
+    pub id: String,
+    pub actor: String,
+    pub role: Option<String>,
+    pub action: String,
+    pub method: String,
+    pub path: String,
+    pub target: Option<String>,
+    pub status: u16,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub changes: Vec<String>,
+    pub timestamp: String,
+}
+
+impl AuditRpcResponse {
+    /// Create an audit log entry list response
+    pub fn entry_list(request_id: String, entries: Vec<AuditEntryInfo>) -> Self {
+        Self {
+            request_id,
+            result: AuditRpcResult::EntryList { entries },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: AuditRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for AuditRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::AuditRpcResponse(self.clone())
+    }
+}
+
+/// Outcome of a command sent with a `reply_to` queue
+///
+/// Commands such as [`ProfileCreateMessage`] or [`KeyRotateMessage`] are
+/// applied asynchronously. A sender that sets `reply_to` and a correlation
+/// ID on the command gets this response once it was applied or failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandResponse {
+    pub request_id: String,
+    pub result: CommandResult,
+}
+
+/// Results of commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CommandResult {
+    /// The command was applied; `id` names the object it created, if any
+    Done { id: Option<String> },
+    Error {
+        kind: CommandErrorKind,
+        message: String,
+    },
+}
+
+/// Why a command failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandErrorKind {
+    /// An actor, domain or object the command refers to does not exist
+    NotFound,
+    /// The command is invalid or conflicts with the current state
+    Rejected,
+    /// The command could not be applied, such as on a database error
+    Failed,
+}
+
+impl CommandResponse {
+    /// Create a response for an applied command
+    pub fn done(request_id: String, id: Option<String>) -> Self {
+        Self {
+            request_id,
+            result: CommandResult::Done { id },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, kind: CommandErrorKind, message: String) -> Self {
+        Self {
+            request_id,
+            result: CommandResult::Error { kind, message },
+        }
+    }
+}
+
+impl Message for CommandResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::CommandResponse(self.clone())
+    }
+}
+
+/// Health request broadcast to every running service
+///
+/// Each service answers with its own report, so a requester collects
+/// responses until its timeout instead of waiting for a single reply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthRpcRequest {
+    pub request_id: String,
+}
+
+impl HealthRpcRequest {
+    pub fn new(request_id: String) -> Self {
+        Self { request_id }
+    }
+}
+
+impl Message for HealthRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::HealthRpcRequest(self.clone())
+    }
+}
+
+/// Health report of one service answering a health request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthRpcResponse {
+    pub request_id: String,
+    pub report: HealthReport,
+}
+
+impl Message for HealthRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::HealthRpcResponse(self.clone())
+    }
+}
+
+/// Request to sign data with a key held by the PKI daemon
+///
+/// Used by [`crate::remote_signer::RemoteSigner`] so that private keys of
+/// high-value keys never leave the daemon holding them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignRpcRequest {
+    pub request_id: String,
+    pub key_id: String,
+    /// RFC 9421 name of the signature algorithm
+    pub algorithm: String,
+    /// Base64 encoded data to sign
+    pub data: String,
+}
+
+impl SignRpcRequest {
+    pub fn new(request_id: String, key_id: String, algorithm: String, data: String) -> Self {
+        Self {
+            request_id,
+            key_id,
+            algorithm,
+            data,
+        }
+    }
+}
+
+impl Message for SignRpcRequest {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::SignRpcRequest(self.clone())
+    }
+}
+
+/// RPC response message for signing requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignRpcResponse {
+    pub request_id: String,
+    pub result: SignRpcResult,
+}
+
+/// Results of signing requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SignRpcResult {
+    /// Base64 encoded signature
+    Signature {
+        signature: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl SignRpcResponse {
+    /// Create a response carrying a signature
+    pub fn signature(request_id: String, signature: String) -> Self {
+        Self {
+            request_id,
+            result: SignRpcResult::Signature { signature },
+        }
+    }
+
+    /// Create an error response
+    pub fn error(request_id: String, message: String) -> Self {
+        Self {
+            request_id,
+            result: SignRpcResult::Error { message },
+        }
+    }
+}
+
+impl Message for SignRpcResponse {
+    fn to_message(&self) -> MessageEnum {
+        MessageEnum::SignRpcResponse(self.clone())
+    }
+}
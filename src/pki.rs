@@ -6,15 +6,31 @@
 //! - User keys (individual identity)
 //! - Instance actor keys (system operations)
 
+use crate::config::{ConfigError, Env};
 use crate::httpsignature::SignatureAlgorithm;
 use base64::{Engine as _, engine::general_purpose};
 use chrono::{DateTime, Utc};
-use ring::signature::{Ed25519KeyPair, KeyPair as RingKeyPair};
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair as RingKeyPair, UnparsedPublicKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
 
+/// Days a rotated key stays valid after a scheduled rotation
+pub const KEY_ROTATION_OVERLAP_DAYS: i64 = 7;
+
+/// Hours a domain verification challenge can be completed in
+pub const VERIFICATION_CHALLENGE_TTL_HOURS: i64 = 48;
+
+/// Smallest RSA key accepted for import
+pub const MIN_RSA_KEY_SIZE: u32 = 2048;
+
+/// SubjectPublicKeyInfo header of an Ed25519 public key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
 /// PKI-related errors
 #[derive(Error, Debug)]
 pub enum PkiError {
@@ -36,6 +52,9 @@ pub enum PkiError {
     #[error("Key not found: {0}")]
     KeyNotFoundError(String),
 
+    #[error("Key revoked: {0}")]
+    KeyRevoked(String),
+
     #[error("Domain verification failed: {0}")]
     DomainVerificationError(String),
 
@@ -50,6 +69,9 @@ pub enum PkiError {
 
     #[error("Unsupported algorithm: {0}")]
     UnsupportedAlgorithm(String),
+
+    #[error("Key encryption failed: {0}")]
+    KeyEncryptionError(String),
 }
 
 /// Trust levels in the PKI hierarchy
@@ -87,6 +109,23 @@ impl TrustLevel {
     }
 }
 
+impl std::str::FromStr for TrustLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "unverified" => Ok(TrustLevel::Unverified),
+            "domain-verified" | "domainverified" => Ok(TrustLevel::DomainVerified),
+            "master-signed" | "mastersigned" => Ok(TrustLevel::MasterSigned),
+            "instance-actor" | "instanceactor" => Ok(TrustLevel::InstanceActor),
+            _ => Err(format!(
+                "Invalid trust level '{}', expected 'unverified', 'domain-verified', 'master-signed' or 'instance-actor'",
+                s
+            )),
+        }
+    }
+}
+
 /// Cryptographic algorithm types
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum KeyAlgorithm {
@@ -126,7 +165,7 @@ impl PublicKey {
     }
 
     /// Calculate SHA-256 fingerprint of the key
-    fn calculate_fingerprint(pem_data: &str) -> Result<String, PkiError> {
+    pub(crate) fn calculate_fingerprint(pem_data: &str) -> Result<String, PkiError> {
         let mut hasher = Sha256::new();
         hasher.update(pem_data.as_bytes());
         let result = hasher.finalize();
@@ -137,6 +176,38 @@ impl PublicKey {
     pub fn key_id(&self, actor_id: &str) -> String {
         format!("{}#main-key", actor_id)
     }
+
+    /// Verify a base64 signature made with the matching private key
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<(), PkiError> {
+        match &self.algorithm {
+            KeyAlgorithm::Ed25519 => {
+                let public_der = pem_to_der(&self.pem_data)?;
+                // The raw key is the trailing 32 bytes of the SubjectPublicKeyInfo
+                if public_der.len() < 32 {
+                    return Err(PkiError::InvalidKeyFormat);
+                }
+                let raw_public = &public_der[public_der.len() - 32..];
+                let signature = general_purpose::STANDARD.decode(signature)?;
+                UnparsedPublicKey::new(&ED25519, raw_public)
+                    .verify(data, &signature)
+                    .map_err(|_| {
+                        PkiError::SignatureVerificationError(format!(
+                            "Signature does not match key {}",
+                            self.fingerprint
+                        ))
+                    })
+            }
+            KeyAlgorithm::Rsa { .. } => Err(PkiError::UnsupportedAlgorithm(
+                "RSA verification not supported in PKI module. Use httpsignature module."
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+/// Data a parent key signs to vouch for a child key
+fn key_signature_data(key_id: &str, fingerprint: &str) -> String {
+    format!("{}:{}", key_id, fingerprint)
 }
 
 /// Private key representation (encrypted)
@@ -185,6 +256,74 @@ impl KeyPair {
         })
     }
 
+    /// Parse and check an imported key pair (BYOK)
+    ///
+    /// Accepts Ed25519 keys in PKCS#8/SPKI form and RSA keys of at least
+    /// [`MIN_RSA_KEY_SIZE`] bits in PKCS#8/SPKI or PKCS#1 form. Both halves
+    /// must belong together. The keys are normalized to PKCS#8 and SPKI PEM,
+    /// which is what signing and publishing expect.
+    pub fn import(public_pem: &str, private_pem: &str) -> Result<Self, PkiError> {
+        let public_der = pem_to_der(public_pem)?;
+        if public_der.len() == 44 && public_der.starts_with(&ED25519_SPKI_PREFIX) {
+            Self::import_ed25519(&public_der[ED25519_SPKI_PREFIX.len()..], private_pem)
+        } else {
+            Self::import_rsa(public_pem, private_pem)
+        }
+    }
+
+    fn import_ed25519(raw_public: &[u8], private_pem: &str) -> Result<Self, PkiError> {
+        let private_der = pem_to_der(private_pem)?;
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&private_der)
+            .map_err(|e| PkiError::KeyParseError(format!("Invalid Ed25519 private key: {}", e)))?;
+        if key_pair.public_key().as_ref() != raw_public {
+            return Err(PkiError::KeyParseError(
+                "Public key does not match private key".to_string(),
+            ));
+        }
+
+        Self::from_pem(
+            KeyAlgorithm::Ed25519,
+            der_to_pem(&encode_ed25519_spki(raw_public), "PUBLIC KEY"),
+            der_to_pem(&private_der, "PRIVATE KEY"),
+        )
+    }
+
+    fn import_rsa(public_pem: &str, private_pem: &str) -> Result<Self, PkiError> {
+        use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
+        use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+        use rsa::traits::PublicKeyParts;
+
+        let public_key = rsa::RsaPublicKey::from_public_key_pem(public_pem.trim())
+            .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(public_pem.trim()))
+            .map_err(|e| PkiError::KeyParseError(format!("Invalid public key: {}", e)))?;
+        let private_key = rsa::RsaPrivateKey::from_pkcs8_pem(private_pem.trim())
+            .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(private_pem.trim()))
+            .map_err(|e| PkiError::KeyParseError(format!("Invalid RSA private key: {}", e)))?;
+        if private_key.to_public_key() != public_key {
+            return Err(PkiError::KeyParseError(
+                "Public key does not match private key".to_string(),
+            ));
+        }
+
+        let key_size = (public_key.size() * 8) as u32;
+        if key_size < MIN_RSA_KEY_SIZE {
+            return Err(PkiError::KeyParseError(format!(
+                "RSA key has {} bits, at least {} required",
+                key_size, MIN_RSA_KEY_SIZE
+            )));
+        }
+
+        let public_pem = public_key
+            .to_public_key_pem(pkcs8::LineEnding::LF)
+            .map_err(|e| PkiError::KeyParseError(format!("Failed to encode public key: {}", e)))?;
+        let private_pem = private_key
+            .to_pkcs8_pem(pkcs8::LineEnding::LF)
+            .map_err(|e| PkiError::KeyParseError(format!("Failed to encode private key: {}", e)))?
+            .to_string();
+
+        Self::from_pem(KeyAlgorithm::Rsa { key_size }, public_pem, private_pem)
+    }
+
     /// Generate a new key pair
     pub fn generate(algorithm: KeyAlgorithm) -> Result<Self, PkiError> {
         match algorithm {
@@ -252,9 +391,10 @@ impl KeyPair {
             KeyAlgorithm::Ed25519 => {
                 let private_der = pem_to_der(&self.private_key.encrypted_pem)
                     .map_err(|e| PkiError::SignatureCreationError(format!("Invalid PEM: {}", e)))?;
-                let key_pair = Ed25519KeyPair::from_pkcs8(&private_der).map_err(|e| {
-                    PkiError::SignatureCreationError(format!("Invalid Ed25519 key: {}", e))
-                })?;
+                let key_pair =
+                    Ed25519KeyPair::from_pkcs8_maybe_unchecked(&private_der).map_err(|e| {
+                        PkiError::SignatureCreationError(format!("Invalid Ed25519 key: {}", e))
+                    })?;
                 let sig = key_pair.sign(data);
                 Ok(general_purpose::STANDARD.encode(sig.as_ref()))
             }
@@ -356,6 +496,13 @@ impl UserKeyInfo {
         self.trust_level = TrustLevel::DomainVerified;
     }
 
+    /// Sign this key with the domain authority and upgrade its trust
+    pub fn sign_with_domain_key(&mut self, domain_key: &DomainKeyInfo) -> Result<(), PkiError> {
+        let signature = domain_key.sign_key(&self.key_id, &self.public_key.fingerprint)?;
+        self.upgrade_trust(signature);
+        Ok(())
+    }
+
     /// Check if key is expired
     pub fn is_expired(&self) -> bool {
         if let Some(expires_at) = self.expires_at {
@@ -366,6 +513,104 @@ impl UserKeyInfo {
     }
 }
 
+/// Where the owner of a domain publishes a verification challenge
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationMethod {
+    /// TXT record at `_oxifed-challenge.<domain>`
+    Dns,
+    /// File served at `https://<domain>/.well-known/oxifed/challenge`
+    WellKnown,
+}
+
+impl VerificationMethod {
+    /// DNS name or URL the challenge token has to be published at
+    pub fn location(&self, domain: &str) -> String {
+        match self {
+            VerificationMethod::Dns => format!("_oxifed-challenge.{}", domain),
+            VerificationMethod::WellKnown => {
+                format!("https://{}/.well-known/oxifed/challenge", domain)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for VerificationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationMethod::Dns => write!(f, "dns"),
+            VerificationMethod::WellKnown => write!(f, "well-known"),
+        }
+    }
+}
+
+impl std::str::FromStr for VerificationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dns" => Ok(VerificationMethod::Dns),
+            "well-known" | "well_known" => Ok(VerificationMethod::WellKnown),
+            _ => Err(format!(
+                "Invalid verification method '{}', expected 'dns' or 'well-known'",
+                s
+            )),
+        }
+    }
+}
+
+/// State of a domain verification challenge
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStatus {
+    Pending,
+    Verified,
+    Expired,
+}
+
+/// Challenge an unverified user key has to pass before the domain key signs it
+///
+/// The challenge is signed with the domain key so a stored challenge cannot
+/// be swapped for another one. The owner of the domain proves control by
+/// publishing `token` at the location of the chosen method.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DomainVerificationChallenge {
+    pub key_id: String,
+    pub fingerprint: String,
+    pub domain: String,
+    pub method: VerificationMethod,
+    pub token: String,
+    pub domain_key_id: String,
+    /// Domain key signature over the challenge
+    pub signature: String,
+    pub status: VerificationStatus,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Last time the published token was checked
+    pub checked_at: Option<DateTime<Utc>>,
+    pub verified_at: Option<DateTime<Utc>>,
+    /// Why the last check failed
+    pub failure: Option<String>,
+}
+
+impl DomainVerificationChallenge {
+    /// DNS name or URL the token has to be published at
+    pub fn location(&self) -> String {
+        self.method.location(&self.domain)
+    }
+
+    fn signature_data(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            self.key_id,
+            self.fingerprint,
+            self.domain,
+            self.token,
+            self.expires_at.timestamp()
+        )
+    }
+}
+
 /// Key rotation policy
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RotationPolicy {
@@ -410,6 +655,25 @@ pub struct DomainKeyInfo {
     pub usage: Vec<KeyUsage>,
 }
 
+impl DomainKeyInfo {
+    /// Sign a user key by its ID and fingerprint
+    pub fn sign_key(&self, key_id: &str, fingerprint: &str) -> Result<DomainSignature, PkiError> {
+        let key_pair = KeyPair {
+            public_key: self.public_key.clone(),
+            private_key: self.private_key.clone(),
+        };
+        let signature = key_pair.sign(key_signature_data(key_id, fingerprint).as_bytes())?;
+
+        Ok(DomainSignature {
+            domain: self.domain.clone(),
+            signature,
+            signed_at: Utc::now(),
+            domain_key_id: self.key_id.clone(),
+            verification_chain: vec![self.key_id.clone()],
+        })
+    }
+}
+
 /// Master signature (used to sign domain keys)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MasterSignature {
@@ -440,6 +704,8 @@ pub struct TrustChainLink {
     pub signed_at: Option<DateTime<Utc>>,
     pub self_signed: bool,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub revoked: bool,
 }
 
 /// Complete trust chain for a key
@@ -457,6 +723,8 @@ pub struct PkiManager {
     pub domain_keys: HashMap<String, DomainKeyInfo>,
     pub user_keys: HashMap<String, UserKeyInfo>,
     pub instance_keys: HashMap<String, UserKeyInfo>,
+    /// Revoked key IDs and when they were revoked
+    pub revoked_keys: HashMap<String, DateTime<Utc>>,
 }
 
 impl PkiManager {
@@ -467,6 +735,7 @@ impl PkiManager {
             domain_keys: HashMap::new(),
             user_keys: HashMap::new(),
             instance_keys: HashMap::new(),
+            revoked_keys: HashMap::new(),
         }
     }
 
@@ -489,12 +758,15 @@ impl PkiManager {
     }
 
     /// Import user key (BYOK - Bring Your Own Key)
+    ///
+    /// The key gets its own key ID and stays unverified until the actor
+    /// completes domain verification.
     pub fn import_user_key(
         &mut self,
         actor_id: String,
         key_pair: KeyPair,
     ) -> Result<UserKeyInfo, PkiError> {
-        let user_key = UserKeyInfo::new_unverified(actor_id.clone(), key_pair);
+        let user_key = Self::replacement_key(&actor_id, key_pair);
         self.user_keys.insert(actor_id, user_key.clone());
         Ok(user_key)
     }
@@ -513,34 +785,171 @@ impl PkiManager {
             PkiError::KeyNotFoundError(format!("User key for {} not found", actor_id))
         })?;
 
-        // Create domain signature
-        let signature_data = format!("{}:{}", user_key.key_id, user_key.public_key.fingerprint);
+        user_key.sign_with_domain_key(domain_key)
+    }
+
+    /// Issue a domain verification challenge for the unverified key of a user
+    pub fn issue_verification_challenge(
+        &self,
+        actor_id: &str,
+        domain: &str,
+        method: VerificationMethod,
+    ) -> Result<DomainVerificationChallenge, PkiError> {
+        let domain_key = self.domain_keys.get(domain).ok_or_else(|| {
+            PkiError::KeyNotFoundError(format!("Domain key for {} not found", domain))
+        })?;
+        let user_key = self.user_keys.get(actor_id).ok_or_else(|| {
+            PkiError::KeyNotFoundError(format!("User key for {} not found", actor_id))
+        })?;
+        if user_key.trust_level != TrustLevel::Unverified {
+            return Err(PkiError::DomainVerificationError(format!(
+                "Key {} is already verified",
+                user_key.key_id
+            )));
+        }
+
+        let mut token = [0u8; 32];
+        ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut token).map_err(
+            |_| PkiError::DomainVerificationError("Failed to generate challenge".to_string()),
+        )?;
+        let issued_at = Utc::now();
+        let mut challenge = DomainVerificationChallenge {
+            key_id: user_key.key_id.clone(),
+            fingerprint: user_key.public_key.fingerprint.clone(),
+            domain: domain.to_string(),
+            method,
+            token: general_purpose::URL_SAFE_NO_PAD.encode(token),
+            domain_key_id: domain_key.key_id.clone(),
+            signature: String::new(),
+            status: VerificationStatus::Pending,
+            issued_at,
+            expires_at: issued_at + chrono::Duration::hours(VERIFICATION_CHALLENGE_TTL_HOURS),
+            checked_at: None,
+            verified_at: None,
+            failure: None,
+        };
         let domain_key_pair = KeyPair {
             public_key: domain_key.public_key.clone(),
             private_key: domain_key.private_key.clone(),
         };
-        let signature = domain_key_pair.sign(signature_data.as_bytes())?;
+        challenge.signature = domain_key_pair.sign(challenge.signature_data().as_bytes())?;
+        Ok(challenge)
+    }
 
-        let domain_signature = DomainSignature {
-            domain: domain.to_string(),
-            signature,
-            signed_at: Utc::now(),
-            domain_key_id: domain_key.key_id.clone(),
-            verification_chain: vec![domain_key.key_id.clone()],
-        };
+    /// Complete a domain verification challenge
+    ///
+    /// `published` holds the values found at the challenge location. If one
+    /// of them is the challenge token, the user key is signed with the
+    /// domain key and the challenge is marked verified.
+    pub fn complete_verification(
+        &mut self,
+        actor_id: &str,
+        challenge: &mut DomainVerificationChallenge,
+        published: &[String],
+    ) -> Result<(), PkiError> {
+        let now = Utc::now();
+        challenge.checked_at = Some(now);
+
+        match challenge.status {
+            VerificationStatus::Pending if challenge.expires_at <= now => {
+                challenge.status = VerificationStatus::Expired;
+                return Err(PkiError::DomainVerificationError(
+                    "Challenge expired, request a new one".to_string(),
+                ));
+            }
+            VerificationStatus::Pending => {}
+            VerificationStatus::Verified => {
+                return Err(PkiError::DomainVerificationError(
+                    "Challenge already completed".to_string(),
+                ));
+            }
+            VerificationStatus::Expired => {
+                return Err(PkiError::DomainVerificationError(
+                    "Challenge expired, request a new one".to_string(),
+                ));
+            }
+        }
+
+        let domain_key = self.domain_keys.get(&challenge.domain).ok_or_else(|| {
+            PkiError::KeyNotFoundError(format!("Domain key for {} not found", challenge.domain))
+        })?;
+        if domain_key.key_id != challenge.domain_key_id {
+            return Err(PkiError::DomainVerificationError(
+                "Challenge was issued by another domain key, request a new one".to_string(),
+            ));
+        }
+        domain_key
+            .public_key
+            .verify(challenge.signature_data().as_bytes(), &challenge.signature)?;
+
+        let user_key = self.user_keys.get_mut(actor_id).ok_or_else(|| {
+            PkiError::KeyNotFoundError(format!("User key for {} not found", actor_id))
+        })?;
+        if user_key.key_id != challenge.key_id
+            || user_key.public_key.fingerprint != challenge.fingerprint
+        {
+            return Err(PkiError::DomainVerificationError(format!(
+                "Challenge was issued for key {}, not {}",
+                challenge.key_id, user_key.key_id
+            )));
+        }
+
+        if !published
+            .iter()
+            .any(|value| value.trim() == challenge.token)
+        {
+            return Err(PkiError::DomainVerificationError(format!(
+                "Challenge token not found at {}",
+                challenge.location()
+            )));
+        }
 
-        user_key.upgrade_trust(domain_signature);
+        user_key.sign_with_domain_key(domain_key)?;
+        challenge.status = VerificationStatus::Verified;
+        challenge.verified_at = Some(now);
+        challenge.failure = None;
         Ok(())
     }
 
+    /// Generate a replacement key for a user
+    ///
+    /// The new key is signed with the domain key if that is loaded;
+    /// otherwise it stays unverified.
+    pub fn rotate_user_key(
+        &mut self,
+        actor_id: &str,
+        domain: &str,
+        algorithm: KeyAlgorithm,
+    ) -> Result<UserKeyInfo, PkiError> {
+        let key_pair = KeyPair::generate(algorithm)?;
+        let mut user_key = Self::replacement_key(actor_id, key_pair);
+
+        if let Some(domain_key) = self.domain_keys.get(domain) {
+            user_key.sign_with_domain_key(domain_key)?;
+        }
+
+        self.user_keys
+            .insert(actor_id.to_string(), user_key.clone());
+        Ok(user_key)
+    }
+
+    /// Unverified key replacing the current key of a user
+    ///
+    /// Replacement keys get a new key ID so remote servers fetch them instead
+    /// of reusing a cached copy of the old key.
+    fn replacement_key(actor_id: &str, key_pair: KeyPair) -> UserKeyInfo {
+        let mut user_key = UserKeyInfo::new_unverified(actor_id.to_string(), key_pair);
+        user_key.key_id = format!(
+            "{}#key-{}",
+            actor_id,
+            user_key.created_at.timestamp_millis()
+        );
+        user_key
+    }
+
     /// Build trust chain for a key
     pub fn build_trust_chain(&self, key_id: &str) -> Result<TrustChain, PkiError> {
-        // Find the key
-        let user_key = self
-            .user_keys
-            .values()
-            .find(|uk| uk.key_id == key_id)
-            .ok_or_else(|| PkiError::KeyNotFoundError(format!("Key {} not found", key_id)))?;
+        let user_key = self.find_user_key(key_id)?;
 
         let mut chain = Vec::new();
 
@@ -555,13 +964,16 @@ impl PkiManager {
             signed_at: user_key.domain_signature.as_ref().map(|ds| ds.signed_at),
             self_signed: user_key.domain_signature.is_none(),
             created_at: user_key.created_at,
+            revoked: self.is_revoked(&user_key.key_id),
         };
         chain.push(user_link);
 
         // Add domain key link if exists
-        if let Some(domain_sig) = &user_key.domain_signature
-            && let Some(domain_key) = self.domain_keys.get(&domain_sig.domain)
-        {
+        let domain_key = user_key
+            .domain_signature
+            .as_ref()
+            .and_then(|ds| self.domain_keys.get(&ds.domain));
+        if let Some(domain_key) = domain_key {
             let domain_link = TrustChainLink {
                 level: "domain".to_string(),
                 key_id: domain_key.key_id.clone(),
@@ -572,12 +984,17 @@ impl PkiManager {
                 signed_at: domain_key.master_signature.as_ref().map(|ms| ms.signed_at),
                 self_signed: domain_key.master_signature.is_none(),
                 created_at: domain_key.created_at,
+                revoked: self.is_revoked(&domain_key.key_id),
             };
             chain.push(domain_link);
         }
 
-        // Add master key link if exists
-        if let Some(master_key) = &self.master_key {
+        // Add master key link if it signed the domain key
+        if let Some(master_key) = &self.master_key
+            && domain_key
+                .and_then(|dk| dk.master_signature.as_ref())
+                .is_some_and(|ms| ms.master_key_id == master_key.key_id)
+        {
             let master_link = TrustChainLink {
                 level: "master".to_string(),
                 key_id: master_key.key_id.clone(),
@@ -585,6 +1002,7 @@ impl PkiManager {
                 signed_at: None,
                 self_signed: true,
                 created_at: master_key.created_at,
+                revoked: self.is_revoked(&master_key.key_id),
             };
             chain.push(master_link);
         }
@@ -597,6 +1015,86 @@ impl PkiManager {
         })
     }
 
+    /// Build the trust chain of a key and verify every link
+    ///
+    /// Checks that no key in the chain is revoked or expired and that the
+    /// domain and master signatures match the keys they vouch for.
+    pub fn verify_trust_chain(&self, key_id: &str) -> Result<TrustChain, PkiError> {
+        let trust_chain = self.build_trust_chain(key_id)?;
+        if let Some(link) = trust_chain.verification_chain.iter().find(|l| l.revoked) {
+            return Err(PkiError::KeyRevoked(link.key_id.clone()));
+        }
+
+        let user_key = self.find_user_key(key_id)?;
+        if user_key.is_expired() {
+            return Err(PkiError::TrustChainError(format!(
+                "Key {} expired",
+                user_key.key_id
+            )));
+        }
+
+        let Some(domain_signature) = &user_key.domain_signature else {
+            return Ok(trust_chain);
+        };
+        let domain_key = self
+            .domain_keys
+            .get(&domain_signature.domain)
+            .filter(|dk| dk.key_id == domain_signature.domain_key_id)
+            .ok_or_else(|| {
+                PkiError::TrustChainError(format!(
+                    "Domain key {} not found",
+                    domain_signature.domain_key_id
+                ))
+            })?;
+        if domain_key.expires_at.is_some_and(|at| Utc::now() > at) {
+            return Err(PkiError::TrustChainError(format!(
+                "Domain key {} expired",
+                domain_key.key_id
+            )));
+        }
+        domain_key.public_key.verify(
+            key_signature_data(&user_key.key_id, &user_key.public_key.fingerprint).as_bytes(),
+            &domain_signature.signature,
+        )?;
+
+        if let Some(master_signature) = &domain_key.master_signature {
+            let master_key = self
+                .master_key
+                .as_ref()
+                .filter(|mk| mk.key_id == master_signature.master_key_id)
+                .ok_or_else(|| {
+                    PkiError::TrustChainError(format!(
+                        "Master key {} not found",
+                        master_signature.master_key_id
+                    ))
+                })?;
+            master_key.public_key.verify(
+                key_signature_data(&domain_key.key_id, &domain_key.public_key.fingerprint)
+                    .as_bytes(),
+                &master_signature.signature,
+            )?;
+        }
+
+        Ok(trust_chain)
+    }
+
+    /// Mark a key as revoked
+    pub fn revoke_key(&mut self, key_id: &str, revoked_at: DateTime<Utc>) {
+        self.revoked_keys.insert(key_id.to_string(), revoked_at);
+    }
+
+    /// Check whether a key has been revoked
+    pub fn is_revoked(&self, key_id: &str) -> bool {
+        self.revoked_keys.contains_key(key_id)
+    }
+
+    fn find_user_key(&self, key_id: &str) -> Result<&UserKeyInfo, PkiError> {
+        self.user_keys
+            .values()
+            .find(|uk| uk.key_id == key_id)
+            .ok_or_else(|| PkiError::KeyNotFoundError(format!("Key {} not found", key_id)))
+    }
+
     /// Get user key by actor ID
     pub fn get_user_key(&self, actor_id: &str) -> Option<&UserKeyInfo> {
         self.user_keys.get(actor_id)
@@ -609,31 +1107,447 @@ impl PkiManager {
 
     /// Validate trust chain for a key
     pub fn validate_trust_chain(&self, key_id: &str) -> Result<TrustLevel, PkiError> {
-        let trust_chain = self.build_trust_chain(key_id)?;
+        Ok(self.verify_trust_chain(key_id)?.trust_level)
+    }
+}
+
+impl Default for PkiManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        // Verify each link in the chain
-        for link in trust_chain.verification_chain.iter() {
-            if !link.self_signed
-                && let Some(signer_key_id) = &link.signed_by
-            {
-                // Verify signature exists and is valid
-                // This would involve cryptographic verification in a real implementation
-                tracing::debug!(
-                    "Verifying signature from {} for {}",
-                    signer_key_id,
-                    link.key_id
-                );
+/// `encryption_algorithm` of envelope-encrypted private keys
+pub const ENVELOPE_ALGORITHM: &str = "aes-256-gcm";
+
+/// Where the key-encryption key of stored private keys comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEncryptionBackend {
+    /// Private keys are stored in plaintext
+    #[default]
+    None,
+    /// Master key read from a file
+    File,
+    /// Data keys are wrapped by the HashiCorp Vault transit engine
+    Vault,
+}
+
+impl std::str::FromStr for KeyEncryptionBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(KeyEncryptionBackend::None),
+            "file" => Ok(KeyEncryptionBackend::File),
+            "vault" => Ok(KeyEncryptionBackend::Vault),
+            _ => Err(format!(
+                "Invalid key encryption backend '{}', expected 'none', 'file' or 'vault'",
+                s
+            )),
+        }
+    }
+}
+
+/// Encryption of private keys at rest
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyEncryptionConfig {
+    pub backend: KeyEncryptionBackend,
+    /// File holding the base64 encoded 32 byte master key
+    pub master_key_file: Option<String>,
+    /// Master key replaced by `master_key_file`, still accepted for
+    /// decrypting keys that have not been re-encrypted yet
+    pub previous_master_key_file: Option<String>,
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    /// Transit key wrapping the data keys
+    pub vault_transit_key: Option<String>,
+}
+
+impl KeyEncryptionConfig {
+    /// Apply `KEY_ENCRYPTION_BACKEND`, `KEY_ENCRYPTION_MASTER_KEY_FILE`,
+    /// `KEY_ENCRYPTION_PREVIOUS_MASTER_KEY_FILE`, `VAULT_ADDR`, `VAULT_TOKEN`
+    /// and `VAULT_TRANSIT_KEY`
+    pub fn apply_env(&mut self, env: &Env) -> Result<(), ConfigError> {
+        env.set("KEY_ENCRYPTION_BACKEND", &mut self.backend)?;
+        env.set_opt("KEY_ENCRYPTION_MASTER_KEY_FILE", &mut self.master_key_file)?;
+        env.set_opt(
+            "KEY_ENCRYPTION_PREVIOUS_MASTER_KEY_FILE",
+            &mut self.previous_master_key_file,
+        )?;
+        env.set_opt("VAULT_ADDR", &mut self.vault_addr)?;
+        env.set_opt("VAULT_TOKEN", &mut self.vault_token)?;
+        env.set_opt("VAULT_TRANSIT_KEY", &mut self.vault_transit_key)
+    }
+
+    /// Check that the selected backend has everything it needs
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        let required: &[(&str, &Option<String>)] = match self.backend {
+            KeyEncryptionBackend::None => &[],
+            KeyEncryptionBackend::File => &[("master_key_file", &self.master_key_file)],
+            KeyEncryptionBackend::Vault => &[
+                ("vault_addr", &self.vault_addr),
+                ("vault_token", &self.vault_token),
+                ("vault_transit_key", &self.vault_transit_key),
+            ],
+        };
+        for (name, value) in required {
+            if value.is_none() {
+                return Err(ConfigError::invalid(
+                    format!("{}.{}", key, name),
+                    format!("required by the {:?} backend", self.backend).to_lowercase(),
+                ));
             }
         }
+        Ok(())
+    }
+}
+
+/// Envelope encryption of private keys at rest
+///
+/// Every private key is sealed with its own AES-256-GCM data key, bound to
+/// the key ID. The data key is wrapped with the key-encryption key of the
+/// configured backend and stored alongside the ciphertext, so rotating the
+/// key-encryption key only means rewrapping data keys. Keys stored before
+/// encryption was enabled are read as plaintext until they are re-encrypted.
+#[derive(Clone, Default)]
+pub struct KeyEncryptor {
+    current: Option<KeyEncryptionKey>,
+    previous: Option<KeyEncryptionKey>,
+}
+
+/// Stored form of an envelope-encrypted private key
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    /// Identifier of the key-encryption key
+    kek: String,
+    wrapped_key: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone)]
+enum KeyEncryptionKey {
+    Local {
+        id: String,
+        key: Arc<ring::aead::LessSafeKey>,
+    },
+    Vault {
+        id: String,
+        client: reqwest::Client,
+        address: String,
+        token: String,
+        transit_key: String,
+    },
+}
+
+impl KeyEncryptor {
+    /// Encryptor that stores private keys in plaintext
+    pub fn plaintext() -> Self {
+        Self::default()
+    }
+
+    /// Encryptor using a local 32 byte master key
+    pub fn with_master_key(master_key: &[u8]) -> Result<Self, PkiError> {
+        Ok(Self {
+            current: Some(KeyEncryptionKey::local(master_key)?),
+            previous: None,
+        })
+    }
+
+    /// Also accept keys encrypted with a previous master key
+    pub fn with_previous_master_key(mut self, master_key: &[u8]) -> Result<Self, PkiError> {
+        self.previous = Some(KeyEncryptionKey::local(master_key)?);
+        Ok(self)
+    }
+
+    /// Create the encryptor selected by the configuration
+    pub fn from_config(config: &KeyEncryptionConfig) -> Result<Self, PkiError> {
+        let missing =
+            |name: &str| PkiError::KeyEncryptionError(format!("{} is not configured", name));
+        let mut encryptor = match config.backend {
+            KeyEncryptionBackend::None => Self::plaintext(),
+            KeyEncryptionBackend::File => {
+                let path = config
+                    .master_key_file
+                    .as_deref()
+                    .ok_or_else(|| missing("master_key_file"))?;
+                Self::with_master_key(&read_master_key(path)?)?
+            }
+            KeyEncryptionBackend::Vault => {
+                let transit_key = config
+                    .vault_transit_key
+                    .clone()
+                    .ok_or_else(|| missing("vault_transit_key"))?;
+                Self {
+                    current: Some(KeyEncryptionKey::Vault {
+                        id: format!("vault:{}", transit_key),
+                        client: reqwest::Client::new(),
+                        address: config
+                            .vault_addr
+                            .clone()
+                            .ok_or_else(|| missing("vault_addr"))?,
+                        token: config
+                            .vault_token
+                            .clone()
+                            .ok_or_else(|| missing("vault_token"))?,
+                        transit_key,
+                    }),
+                    previous: None,
+                }
+            }
+        };
+        if let Some(path) = &config.previous_master_key_file {
+            encryptor = encryptor.with_previous_master_key(&read_master_key(path)?)?;
+        }
+        Ok(encryptor)
+    }
+
+    /// Whether new private keys are encrypted
+    pub fn is_enabled(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// `encryption_algorithm` recorded for keys stored by this encryptor
+    pub fn algorithm(&self) -> &'static str {
+        if self.is_enabled() {
+            ENVELOPE_ALGORITHM
+        } else {
+            "none"
+        }
+    }
+
+    /// Encrypt the PEM of the private key `key_id` for storage
+    ///
+    /// Returns the PEM unchanged if encryption is disabled.
+    pub async fn encrypt(&self, key_id: &str, pem: &str) -> Result<String, PkiError> {
+        let Some(kek) = &self.current else {
+            return Ok(pem.to_string());
+        };
+
+        let rng = ring::rand::SystemRandom::new();
+        let mut data_key = [0u8; 32];
+        let mut nonce = [0u8; ring::aead::NONCE_LEN];
+        ring::rand::SecureRandom::fill(&rng, &mut data_key)
+            .and_then(|_| ring::rand::SecureRandom::fill(&rng, &mut nonce))
+            .map_err(|_| PkiError::KeyEncryptionError("Failed to generate data key".to_string()))?;
+
+        let mut ciphertext = pem.as_bytes().to_vec();
+        aead_key(&data_key)?
+            .seal_in_place_append_tag(
+                ring::aead::Nonce::assume_unique_for_key(nonce),
+                ring::aead::Aad::from(key_id.as_bytes()),
+                &mut ciphertext,
+            )
+            .map_err(|_| PkiError::KeyEncryptionError("Failed to seal key".to_string()))?;
+
+        let envelope = Envelope {
+            kek: kek.id().to_string(),
+            wrapped_key: kek.wrap(&data_key, key_id).await?,
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        };
+        Ok(serde_json::to_string(&envelope)?)
+    }
+
+    /// Decrypt a stored private key to PEM
+    pub async fn decrypt(
+        &self,
+        key_id: &str,
+        stored: &str,
+        encryption_algorithm: Option<&str>,
+    ) -> Result<String, PkiError> {
+        if is_plaintext(encryption_algorithm) {
+            return Ok(stored.to_string());
+        }
+
+        let envelope: Envelope = serde_json::from_str(stored)?;
+        let kek = [&self.current, &self.previous]
+            .into_iter()
+            .flatten()
+            .find(|kek| kek.id() == envelope.kek)
+            .ok_or_else(|| {
+                PkiError::KeyEncryptionError(format!(
+                    "Key {} is encrypted with unknown key-encryption key {}",
+                    key_id, envelope.kek
+                ))
+            })?;
 
-        Ok(trust_chain.trust_level)
+        let data_key = kek.unwrap(&envelope.wrapped_key, key_id).await?;
+        let nonce: [u8; ring::aead::NONCE_LEN] = general_purpose::STANDARD
+            .decode(&envelope.nonce)?
+            .try_into()
+            .map_err(|_| PkiError::KeyEncryptionError("Invalid nonce".to_string()))?;
+        let mut ciphertext = general_purpose::STANDARD.decode(&envelope.ciphertext)?;
+        let pem = aead_key(&data_key)?
+            .open_in_place(
+                ring::aead::Nonce::assume_unique_for_key(nonce),
+                ring::aead::Aad::from(key_id.as_bytes()),
+                &mut ciphertext,
+            )
+            .map_err(|_| {
+                PkiError::KeyEncryptionError(format!("Failed to decrypt key {}", key_id))
+            })?;
+
+        String::from_utf8(pem.to_vec())
+            .map_err(|_| PkiError::KeyEncryptionError(format!("Key {} is not PEM", key_id)))
+    }
+
+    /// Whether a stored key is already in the form this encryptor writes
+    pub fn is_current(&self, stored: &str, encryption_algorithm: Option<&str>) -> bool {
+        match &self.current {
+            None => is_plaintext(encryption_algorithm),
+            Some(kek) => {
+                !is_plaintext(encryption_algorithm)
+                    && serde_json::from_str::<Envelope>(stored)
+                        .is_ok_and(|envelope| envelope.kek == kek.id())
+            }
+        }
     }
 }
 
-impl Default for PkiManager {
-    fn default() -> Self {
-        Self::new()
+impl KeyEncryptionKey {
+    fn local(master_key: &[u8]) -> Result<Self, PkiError> {
+        let digest = Sha256::digest(master_key);
+        Ok(KeyEncryptionKey::Local {
+            id: format!("file:{}", hex::encode(&digest[..4])),
+            key: Arc::new(aead_key(master_key)?),
+        })
     }
+
+    fn id(&self) -> &str {
+        match self {
+            KeyEncryptionKey::Local { id, .. } | KeyEncryptionKey::Vault { id, .. } => id,
+        }
+    }
+
+    /// Wrap the data key of `key_id`
+    async fn wrap(&self, data_key: &[u8], key_id: &str) -> Result<String, PkiError> {
+        match self {
+            KeyEncryptionKey::Local { key, .. } => {
+                let mut nonce = [0u8; ring::aead::NONCE_LEN];
+                ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut nonce)
+                    .map_err(|_| {
+                        PkiError::KeyEncryptionError("Failed to generate nonce".to_string())
+                    })?;
+                let mut wrapped = data_key.to_vec();
+                key.seal_in_place_append_tag(
+                    ring::aead::Nonce::assume_unique_for_key(nonce),
+                    ring::aead::Aad::from(key_id.as_bytes()),
+                    &mut wrapped,
+                )
+                .map_err(|_| PkiError::KeyEncryptionError("Failed to wrap data key".to_string()))?;
+                Ok(general_purpose::STANDARD.encode([nonce.as_slice(), &wrapped].concat()))
+            }
+            KeyEncryptionKey::Vault { .. } => {
+                let response = self
+                    .vault_request(
+                        "encrypt",
+                        serde_json::json!({ "plaintext": general_purpose::STANDARD.encode(data_key) }),
+                    )
+                    .await?;
+                response["data"]["ciphertext"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        PkiError::KeyEncryptionError("Vault returned no ciphertext".to_string())
+                    })
+            }
+        }
+    }
+
+    /// Unwrap the data key of `key_id`
+    async fn unwrap(&self, wrapped: &str, key_id: &str) -> Result<Vec<u8>, PkiError> {
+        match self {
+            KeyEncryptionKey::Local { key, .. } => {
+                let wrapped = general_purpose::STANDARD.decode(wrapped)?;
+                if wrapped.len() < ring::aead::NONCE_LEN {
+                    return Err(PkiError::KeyEncryptionError(
+                        "Invalid wrapped data key".to_string(),
+                    ));
+                }
+                let (nonce, sealed) = wrapped.split_at(ring::aead::NONCE_LEN);
+                let nonce = ring::aead::Nonce::try_assume_unique_for_key(nonce)
+                    .map_err(|_| PkiError::KeyEncryptionError("Invalid nonce".to_string()))?;
+                let mut sealed = sealed.to_vec();
+                let data_key = key
+                    .open_in_place(nonce, ring::aead::Aad::from(key_id.as_bytes()), &mut sealed)
+                    .map_err(|_| {
+                        PkiError::KeyEncryptionError(format!(
+                            "Failed to unwrap data key of {}",
+                            key_id
+                        ))
+                    })?;
+                Ok(data_key.to_vec())
+            }
+            KeyEncryptionKey::Vault { .. } => {
+                let response = self
+                    .vault_request("decrypt", serde_json::json!({ "ciphertext": wrapped }))
+                    .await?;
+                let plaintext = response["data"]["plaintext"].as_str().ok_or_else(|| {
+                    PkiError::KeyEncryptionError("Vault returned no plaintext".to_string())
+                })?;
+                Ok(general_purpose::STANDARD.decode(plaintext)?)
+            }
+        }
+    }
+
+    /// Call an operation of the Vault transit engine
+    async fn vault_request(
+        &self,
+        operation: &str,
+        body: serde_json::Value,
+    ) -> Result<serde_json::Value, PkiError> {
+        let KeyEncryptionKey::Vault {
+            client,
+            address,
+            token,
+            transit_key,
+            ..
+        } = self
+        else {
+            return Err(PkiError::KeyEncryptionError(
+                "Not a Vault key-encryption key".to_string(),
+            ));
+        };
+
+        let url = format!(
+            "{}/v1/transit/{}/{}",
+            address.trim_end_matches('/'),
+            operation,
+            transit_key
+        );
+        client
+            .post(url)
+            .header("X-Vault-Token", token)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| PkiError::KeyEncryptionError(format!("Vault request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| PkiError::KeyEncryptionError(format!("Invalid Vault response: {}", e)))
+    }
+}
+
+fn is_plaintext(encryption_algorithm: Option<&str>) -> bool {
+    matches!(encryption_algorithm, None | Some("none"))
+}
+
+fn aead_key(key: &[u8]) -> Result<ring::aead::LessSafeKey, PkiError> {
+    ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, key)
+        .map(ring::aead::LessSafeKey::new)
+        .map_err(|_| PkiError::KeyEncryptionError("Key must be 32 bytes".to_string()))
+}
+
+/// Read a base64 encoded master key
+fn read_master_key(path: &str) -> Result<Vec<u8>, PkiError> {
+    let encoded = std::fs::read_to_string(path).map_err(|e| {
+        PkiError::KeyEncryptionError(format!("Failed to read master key {}: {}", path, e))
+    })?;
+    Ok(general_purpose::STANDARD.decode(encoded.trim())?)
 }
 
 #[cfg(test)]
@@ -658,6 +1572,273 @@ mod tests {
 
         assert_eq!(user_key.trust_level, TrustLevel::Unverified);
         assert_eq!(user_key.actor_id, actor_id);
+        assert!(
+            user_key
+                .key_id
+                .starts_with("https://example.com/users/alice#key-")
+        );
+    }
+
+    #[test]
+    fn test_import_validates_key_pair() {
+        let ed25519 = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let imported = KeyPair::import(
+            &ed25519.public_key.pem_data,
+            &ed25519.private_key.encrypted_pem,
+        )
+        .unwrap();
+        assert!(matches!(
+            imported.public_key.algorithm,
+            KeyAlgorithm::Ed25519
+        ));
+        assert_eq!(
+            imported.public_key.fingerprint,
+            ed25519.public_key.fingerprint
+        );
+
+        let rsa = KeyPair::generate(KeyAlgorithm::Rsa { key_size: 2048 }).unwrap();
+        let imported =
+            KeyPair::import(&rsa.public_key.pem_data, &rsa.private_key.encrypted_pem).unwrap();
+        assert!(matches!(
+            imported.public_key.algorithm,
+            KeyAlgorithm::Rsa { key_size: 2048 }
+        ));
+
+        // Halves of different key pairs
+        let other = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        assert!(
+            KeyPair::import(
+                &ed25519.public_key.pem_data,
+                &other.private_key.encrypted_pem
+            )
+            .is_err()
+        );
+        assert!(KeyPair::import("not a key", &ed25519.private_key.encrypted_pem).is_err());
+    }
+
+    #[test]
+    fn test_rotate_user_key() {
+        let actor_id = "https://example.com/users/alice";
+        let mut pki_manager = PkiManager::new();
+
+        let unverified = pki_manager
+            .rotate_user_key(actor_id, "example.com", KeyAlgorithm::Ed25519)
+            .unwrap();
+        assert_eq!(unverified.trust_level, TrustLevel::Unverified);
+        assert!(
+            unverified
+                .key_id
+                .starts_with("https://example.com/users/alice#key-")
+        );
+
+        let domain_pair = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        pki_manager.domain_keys.insert(
+            "example.com".to_string(),
+            DomainKeyInfo {
+                domain: "example.com".to_string(),
+                key_id: "example-com-keys".to_string(),
+                public_key: domain_pair.public_key,
+                private_key: domain_pair.private_key,
+                created_at: Utc::now(),
+                expires_at: None,
+                master_signature: None,
+                usage: vec![KeyUsage::DomainSigning],
+            },
+        );
+
+        let verified = pki_manager
+            .rotate_user_key(actor_id, "example.com", KeyAlgorithm::Ed25519)
+            .unwrap();
+        assert_eq!(verified.trust_level, TrustLevel::DomainVerified);
+        assert_eq!(
+            verified.domain_signature.unwrap().domain_key_id,
+            "example-com-keys"
+        );
+        assert_ne!(verified.public_key.pem_data, unverified.public_key.pem_data);
+    }
+
+    #[test]
+    fn test_verify_trust_chain() {
+        let actor_id = "https://example.com/users/alice";
+        let mut pki_manager = PkiManager::new();
+
+        let master_pair = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let master_key_id = "https://example.com/.well-known/oxifed/master-key".to_string();
+        let domain_pair = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let master_signature = master_pair
+            .sign(
+                key_signature_data("example-com-keys", &domain_pair.public_key.fingerprint)
+                    .as_bytes(),
+            )
+            .unwrap();
+        pki_manager.master_key = Some(MasterKeyInfo {
+            key_id: master_key_id.clone(),
+            fingerprint: master_pair.public_key.fingerprint.clone(),
+            public_key: master_pair.public_key,
+            private_key: master_pair.private_key,
+            created_at: Utc::now(),
+            usage: vec![KeyUsage::DomainSigning],
+        });
+        pki_manager.domain_keys.insert(
+            "example.com".to_string(),
+            DomainKeyInfo {
+                domain: "example.com".to_string(),
+                key_id: "example-com-keys".to_string(),
+                public_key: domain_pair.public_key,
+                private_key: domain_pair.private_key,
+                created_at: Utc::now(),
+                expires_at: None,
+                master_signature: Some(MasterSignature {
+                    signature: master_signature,
+                    signed_at: Utc::now(),
+                    master_key_id,
+                }),
+                usage: vec![KeyUsage::DomainSigning],
+            },
+        );
+
+        let user_key = pki_manager
+            .rotate_user_key(actor_id, "example.com", KeyAlgorithm::Ed25519)
+            .unwrap();
+        let chain = pki_manager.verify_trust_chain(&user_key.key_id).unwrap();
+        let levels: Vec<_> = chain
+            .verification_chain
+            .iter()
+            .map(|link| link.level.as_str())
+            .collect();
+        assert_eq!(levels, ["user", "domain", "master"]);
+
+        // A signature over another key must not verify
+        let mut tampered = user_key.clone();
+        tampered.public_key = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap().public_key;
+        pki_manager.user_keys.insert(actor_id.to_string(), tampered);
+        assert!(matches!(
+            pki_manager.verify_trust_chain(&user_key.key_id),
+            Err(PkiError::SignatureVerificationError(_))
+        ));
+
+        pki_manager
+            .user_keys
+            .insert(actor_id.to_string(), user_key.clone());
+        pki_manager.revoke_key("example-com-keys", Utc::now());
+        assert!(matches!(
+            pki_manager.verify_trust_chain(&user_key.key_id),
+            Err(PkiError::KeyRevoked(key_id)) if key_id == "example-com-keys"
+        ));
+    }
+
+    #[test]
+    fn test_domain_verification_challenge() {
+        let actor_id = "https://example.com/users/alice";
+        let mut pki_manager = PkiManager::new();
+        let domain_pair = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        pki_manager.domain_keys.insert(
+            "example.com".to_string(),
+            DomainKeyInfo {
+                domain: "example.com".to_string(),
+                key_id: "example-com-keys".to_string(),
+                public_key: domain_pair.public_key,
+                private_key: domain_pair.private_key,
+                created_at: Utc::now(),
+                expires_at: None,
+                master_signature: None,
+                usage: vec![KeyUsage::DomainSigning],
+            },
+        );
+        let imported = KeyPair::generate(KeyAlgorithm::Ed25519).unwrap();
+        let user_key = pki_manager
+            .import_user_key(actor_id.to_string(), imported)
+            .unwrap();
+
+        let mut challenge = pki_manager
+            .issue_verification_challenge(actor_id, "example.com", VerificationMethod::Dns)
+            .unwrap();
+        assert_eq!(challenge.key_id, user_key.key_id);
+        assert_eq!(challenge.location(), "_oxifed-challenge.example.com");
+
+        // The token has to be published
+        assert!(matches!(
+            pki_manager.complete_verification(actor_id, &mut challenge, &["other".to_string()]),
+            Err(PkiError::DomainVerificationError(_))
+        ));
+        assert_eq!(challenge.status, VerificationStatus::Pending);
+        assert!(challenge.checked_at.is_some());
+
+        // A challenge not signed by the domain key is rejected
+        let mut forged = challenge.clone();
+        forged.key_id = "https://example.com/users/bob#key-1".to_string();
+        let published = vec![format!("{}\n", challenge.token)];
+        assert!(matches!(
+            pki_manager.complete_verification(actor_id, &mut forged, &published),
+            Err(PkiError::SignatureVerificationError(_))
+        ));
+
+        pki_manager
+            .complete_verification(actor_id, &mut challenge, &published)
+            .unwrap();
+        assert_eq!(challenge.status, VerificationStatus::Verified);
+        let verified = pki_manager.get_user_key(actor_id).unwrap();
+        assert_eq!(verified.trust_level, TrustLevel::DomainVerified);
+        pki_manager.verify_trust_chain(&user_key.key_id).unwrap();
+
+        assert!(matches!(
+            pki_manager.issue_verification_challenge(
+                actor_id,
+                "example.com",
+                VerificationMethod::WellKnown
+            ),
+            Err(PkiError::DomainVerificationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_key_encryption_round_trip() {
+        let key_id = "https://example.com/users/alice#key-1";
+        let pem = KeyPair::generate(KeyAlgorithm::Ed25519)
+            .unwrap()
+            .private_key
+            .encrypted_pem;
+
+        let plaintext = KeyEncryptor::plaintext();
+        assert_eq!(plaintext.encrypt(key_id, &pem).await.unwrap(), pem);
+        assert!(plaintext.is_current(&pem, Some("none")));
+
+        let old = KeyEncryptor::with_master_key(&[1u8; 32]).unwrap();
+        let stored = old.encrypt(key_id, &pem).await.unwrap();
+        assert!(!stored.contains("PRIVATE KEY"));
+        assert!(old.is_current(&stored, Some(ENVELOPE_ALGORITHM)));
+        assert!(!old.is_current(&pem, None));
+        assert_eq!(
+            old.decrypt(key_id, &stored, Some(ENVELOPE_ALGORITHM))
+                .await
+                .unwrap(),
+            pem
+        );
+        // Plaintext keys stored before encryption was enabled stay readable
+        assert_eq!(old.decrypt(key_id, &pem, None).await.unwrap(), pem);
+
+        // The ciphertext is bound to its key ID
+        assert!(
+            old.decrypt("other", &stored, Some(ENVELOPE_ALGORITHM))
+                .await
+                .is_err()
+        );
+
+        let new = KeyEncryptor::with_master_key(&[2u8; 32]).unwrap();
+        assert!(
+            new.decrypt(key_id, &stored, Some(ENVELOPE_ALGORITHM))
+                .await
+                .is_err()
+        );
+        let rotated = new.with_previous_master_key(&[1u8; 32]).unwrap();
+        assert!(!rotated.is_current(&stored, Some(ENVELOPE_ALGORITHM)));
+        assert_eq!(
+            rotated
+                .decrypt(key_id, &stored, Some(ENVELOPE_ALGORITHM))
+                .await
+                .unwrap(),
+            pem
+        );
     }
 
     #[test]
@@ -731,4 +1912,21 @@ mod tests {
             chrono::Duration::hours(12)
         );
     }
+
+    #[test]
+    fn test_trust_level_from_str() {
+        assert_eq!(
+            "domain-verified".parse::<TrustLevel>(),
+            Ok(TrustLevel::DomainVerified)
+        );
+        assert_eq!(
+            "MasterSigned".parse::<TrustLevel>(),
+            Ok(TrustLevel::MasterSigned)
+        );
+        assert_eq!(
+            "instance_actor".parse::<TrustLevel>(),
+            Ok(TrustLevel::InstanceActor)
+        );
+        assert!("trusted".parse::<TrustLevel>().is_err());
+    }
 }
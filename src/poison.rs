@@ -0,0 +1,180 @@
+//! Poison messages
+//!
+//! A message whose handler panics, say on a branch nobody implemented yet,
+//! must neither take its consumer down nor come back forever. Consumers run
+//! handlers through [`isolate`], which turns a panic into an error, and
+//! hand the message to [`dead_letter`], which publishes it to the
+//! dead-letter exchange as a permanent failure with the panic message.
+//! Panics are counted per queue; [`health`] reports the counts as the
+//! `poison_messages` component of every daemon's health report.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Duration, Utc};
+use futures::FutureExt;
+use lapin::{Channel, message::Delivery, options::BasicPublishOptions, types::AMQPValue};
+use tracing::error;
+
+use crate::health::ComponentHealth;
+use crate::messaging::{
+    EXCHANGE_DEAD_LETTER, FailureClass, HEADER_FAILURE_CLASS, HEADER_ORIGINAL_QUEUE,
+    HEADER_REJECTION_REASON, HEADER_REJECTION_STAGE,
+};
+
+/// Name of the health component
+pub const COMPONENT: &str = "poison_messages";
+
+/// How long the health component stays degraded after a poison message
+const ALERT_WINDOW: Duration = Duration::minutes(15);
+
+/// Poison messages seen by this process
+#[derive(Debug, Default)]
+struct PoisonStats {
+    /// Count per queue
+    counts: BTreeMap<String, u64>,
+    /// Queue, panic message and time of the latest one
+    last: Option<(String, String, DateTime<Utc>)>,
+}
+
+fn stats() -> &'static Mutex<PoisonStats> {
+    static STATS: OnceLock<Mutex<PoisonStats>> = OnceLock::new();
+    STATS.get_or_init(Mutex::default)
+}
+
+/// Run a message handler, returning the panic message if it panics
+pub async fn isolate<F: Future>(handler: F) -> Result<F::Output, String> {
+    AssertUnwindSafe(handler)
+        .catch_unwind()
+        .await
+        .map_err(|payload| panic_message(payload.as_ref()))
+}
+
+/// Text of a panic payload
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic without message".to_string())
+}
+
+/// Count a message of `queue` whose handler panicked
+pub fn record(queue: &str, message: &str) {
+    error!("Handler of a {} message panicked: {}", queue, message);
+    let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    *stats.counts.entry(queue.to_string()).or_default() += 1;
+    stats.last = Some((queue.to_string(), message.to_string(), Utc::now()));
+}
+
+/// Poison messages seen by this process, per queue
+pub fn counts() -> BTreeMap<String, u64> {
+    let stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    stats.counts.clone()
+}
+
+/// Health component of the poison messages seen by this process
+///
+/// Degraded for a while after each poison message, healthy otherwise.
+pub fn health() -> ComponentHealth {
+    let stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+    let Some((queue, message, at)) = &stats.last else {
+        return ComponentHealth::healthy(COMPONENT);
+    };
+    let counts = stats
+        .counts
+        .iter()
+        .map(|(queue, count)| format!("{}: {}", queue, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let detail = format!(
+        "{}; last from {} at {}: {}",
+        counts,
+        queue,
+        at.to_rfc3339(),
+        message
+    );
+    if Utc::now() - *at < ALERT_WINDOW {
+        ComponentHealth::degraded(COMPONENT, detail)
+    } else {
+        ComponentHealth {
+            detail: Some(detail),
+            ..ComponentHealth::healthy(COMPONENT)
+        }
+    }
+}
+
+/// Publish a message whose handler panicked to the dead-letter exchange
+///
+/// The original properties and headers are kept, so the message can be
+/// retried unchanged once its handler is fixed. The caller acks the
+/// delivery once this succeeds.
+pub async fn dead_letter(
+    channel: &Channel,
+    delivery: &Delivery,
+    queue: &str,
+    message: &str,
+) -> Result<(), lapin::Error> {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        HEADER_REJECTION_REASON.into(),
+        AMQPValue::LongString(format!("handler panicked: {}", message).into()),
+    );
+    headers.insert(
+        HEADER_REJECTION_STAGE.into(),
+        AMQPValue::LongString(queue.into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_QUEUE.into(),
+        AMQPValue::LongString(queue.into()),
+    );
+    headers.insert(
+        HEADER_FAILURE_CLASS.into(),
+        AMQPValue::LongString(FailureClass::Permanent.as_str().into()),
+    );
+
+    channel
+        .basic_publish(
+            EXCHANGE_DEAD_LETTER,
+            "",
+            BasicPublishOptions::default(),
+            &delivery.data,
+            delivery
+                .properties
+                .clone()
+                .with_delivery_mode(2)
+                .with_headers(headers),
+        )
+        .await?
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn handle(activity: &str) -> u32 {
+        match activity {
+            "Create" => 1,
+            other => panic!("not yet implemented: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_isolate_panicking_handler() {
+        assert_eq!(isolate(handle("Create")).await, Ok(1));
+
+        let message = isolate(handle("likes")).await.unwrap_err();
+        assert_eq!(message, "not yet implemented: likes");
+
+        record("oxifed.test", &message);
+        assert_eq!(counts().get("oxifed.test"), Some(&1));
+        let health = health();
+        assert_eq!(health.status, crate::health::HealthStatus::Degraded);
+        assert!(health.detail.unwrap().contains("oxifed.test: 1"));
+    }
+}
@@ -0,0 +1,174 @@
+//! Signing with keys held by the PKI daemon
+//!
+//! [`RemoteSigner`] sends the data to sign as a [`SignRpcRequest`] on the
+//! `sign` routing key of the RPC exchange and waits for the signature, so
+//! high-value domain and master keys never have to be loaded by the daemons
+//! that sign with them.
+
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use lapin::options::{
+    BasicConsumeOptions, BasicPublishOptions, QueueDeclareOptions, QueueDeleteOptions,
+};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Channel};
+use uuid::Uuid;
+
+use crate::httpsignature::{SignatureAlgorithm, SignatureError, Signer};
+use crate::messaging::{
+    EXCHANGE_RPC_REQUEST, Message, MessageEnum, ROUTING_KEY_SIGN, SignRpcRequest, SignRpcResult,
+};
+
+/// Time the PKI daemon gets to answer a signing request
+pub const DEFAULT_SIGN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signer delegating to the PKI daemon over AMQP RPC
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    channel: Channel,
+    key_id: String,
+    algorithm: SignatureAlgorithm,
+    timeout: Duration,
+}
+
+impl RemoteSigner {
+    /// Sign with the key `key_id` held by the PKI daemon
+    pub fn new(channel: Channel, key_id: String, algorithm: SignatureAlgorithm) -> Self {
+        Self {
+            channel,
+            key_id,
+            algorithm,
+            timeout: DEFAULT_SIGN_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn request_signature(&self, data: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        let reply_queue = self
+            .channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(unavailable)?
+            .name()
+            .to_string();
+
+        let result = self.exchange(&reply_queue, data).await;
+
+        // The channel outlives the request, so the reply queue is not
+        // removed by closing it
+        if let Err(e) = self
+            .channel
+            .queue_delete(&reply_queue, QueueDeleteOptions::default())
+            .await
+        {
+            tracing::warn!("Failed to delete signing reply queue: {}", e);
+        }
+        result
+    }
+
+    async fn exchange(&self, reply_queue: &str, data: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        let mut consumer = self
+            .channel
+            .basic_consume(
+                reply_queue,
+                "",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(unavailable)?;
+
+        let request = SignRpcRequest::new(
+            Uuid::new_v4().to_string(),
+            self.key_id.clone(),
+            self.algorithm.as_str().to_string(),
+            BASE64.encode(data),
+        );
+        let payload = serde_json::to_vec(&request.to_message())
+            .map_err(|e| SignatureError::SignerUnavailable(e.to_string()))?;
+        let properties = BasicProperties::default()
+            .with_reply_to(reply_queue.into())
+            .with_correlation_id(request.request_id.clone().into());
+
+        self.channel
+            .basic_publish(
+                EXCHANGE_RPC_REQUEST,
+                ROUTING_KEY_SIGN,
+                BasicPublishOptions::default(),
+                &payload,
+                properties,
+            )
+            .await
+            .map_err(unavailable)?;
+
+        let response = tokio::time::timeout(self.timeout, async {
+            while let Some(delivery) = consumer.next().await {
+                let delivery = delivery.map_err(unavailable)?;
+                let matches = delivery
+                    .properties
+                    .correlation_id()
+                    .as_ref()
+                    .is_some_and(|id| id.as_str() == request.request_id);
+                if !matches {
+                    continue;
+                }
+                if let Ok(MessageEnum::SignRpcResponse(response)) =
+                    serde_json::from_slice(&delivery.data)
+                {
+                    return Ok(response);
+                }
+            }
+            Err(SignatureError::SignerUnavailable(
+                "Reply consumer closed".to_string(),
+            ))
+        })
+        .await
+        .map_err(|_| {
+            SignatureError::SignerUnavailable(format!(
+                "No signature for key {} within {:?}",
+                self.key_id, self.timeout
+            ))
+        })??;
+
+        match response.result {
+            SignRpcResult::Signature { signature } => Ok(BASE64.decode(signature)?),
+            SignRpcResult::Error { message } => Err(SignatureError::CryptoError(message)),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm.clone()
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, SignatureError>> {
+        Box::pin(self.request_signature(data))
+    }
+}
+
+fn unavailable(e: lapin::Error) -> SignatureError {
+    SignatureError::SignerUnavailable(e.to_string())
+}
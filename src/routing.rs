@@ -0,0 +1,146 @@
+//! Domain-scoped message routing
+//!
+//! By default every domain shares the delivery and incoming exchanges, so
+//! one busy tenant can starve the others. With domain routing enabled,
+//! domainservd publishes outgoing activities to [`EXCHANGE_DOMAIN_DELIVERY`]
+//! with `<priority>.<domain>` as routing key and incoming messages to
+//! [`EXCHANGE_DOMAIN_INCOMING`] with the target domain as routing key. Both
+//! are topic exchanges that workers dedicated to a tenant bind their own
+//! queues to.
+//!
+//! Messages of domains without a dedicated queue go to the alternate
+//! exchange of the topic exchange: [`EXCHANGE_SHARED_DELIVERY`], where the
+//! shared publisherd queues are bound with `<priority>.#`, or
+//! [`EXCHANGE_INCOMING_PROCESS`], which feeds the shared pipeline. Adding or
+//! removing a dedicated worker therefore never drops messages.
+
+use lapin::{
+    Channel, ExchangeKind,
+    options::ExchangeDeclareOptions,
+    types::{AMQPValue, FieldTable},
+};
+use serde_json::Value;
+
+use crate::messaging::{
+    DeliveryPriority, EXCHANGE_DOMAIN_DELIVERY, EXCHANGE_DOMAIN_INCOMING,
+    EXCHANGE_INCOMING_PROCESS, EXCHANGE_SHARED_DELIVERY,
+};
+
+/// Routing key of an outgoing activity sent by a domain
+pub fn delivery_key(priority: DeliveryPriority, domain: &str) -> String {
+    format!("{}.{}", priority.routing_key(), domain.to_lowercase())
+}
+
+/// Binding of the shared delivery queue of a priority
+pub fn shared_delivery_pattern(priority: DeliveryPriority) -> String {
+    format!("{}.#", priority.routing_key())
+}
+
+/// Routing key of an incoming message for a domain
+pub fn incoming_key(domain: &str) -> String {
+    domain.to_lowercase()
+}
+
+/// Name of the queues of workers dedicated to a set of domains
+///
+/// All workers serving the same domains must be given them in the same
+/// order to share their queues.
+pub fn lane_name(domains: &[String]) -> String {
+    domains
+        .iter()
+        .map(|domain| domain.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Domain of the actor that sent an activity
+pub fn sender_domain(activity: &Value) -> Option<String> {
+    let actor = activity.get("actor")?;
+    let actor_id = actor
+        .as_str()
+        .or_else(|| actor.get("id").and_then(Value::as_str))?;
+    url::Url::parse(actor_id)
+        .ok()?
+        .host_str()
+        .map(str::to_lowercase)
+}
+
+/// Declare the domain and shared exchanges
+///
+/// Every service using them declares them the same way, since the broker
+/// refuses a declaration whose arguments differ from the existing exchange.
+pub async fn declare_exchanges(channel: &Channel) -> Result<(), lapin::Error> {
+    let options = ExchangeDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+
+    channel
+        .exchange_declare(
+            EXCHANGE_SHARED_DELIVERY,
+            ExchangeKind::Topic,
+            options,
+            FieldTable::default(),
+        )
+        .await?;
+    channel
+        .exchange_declare(
+            EXCHANGE_INCOMING_PROCESS,
+            ExchangeKind::Fanout,
+            options,
+            FieldTable::default(),
+        )
+        .await?;
+
+    for (exchange, fallback) in [
+        (EXCHANGE_DOMAIN_DELIVERY, EXCHANGE_SHARED_DELIVERY),
+        (EXCHANGE_DOMAIN_INCOMING, EXCHANGE_INCOMING_PROCESS),
+    ] {
+        let mut args = FieldTable::default();
+        args.insert(
+            "alternate-exchange".into(),
+            AMQPValue::LongString(fallback.into()),
+        );
+        channel
+            .exchange_declare(exchange, ExchangeKind::Topic, options, args)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_routing_keys() {
+        assert_eq!(
+            delivery_key(DeliveryPriority::High, "Big.Example"),
+            "high.big.example"
+        );
+        assert_eq!(
+            delivery_key(DeliveryPriority::Low, "big.example"),
+            "low.big.example"
+        );
+        assert_eq!(shared_delivery_pattern(DeliveryPriority::Low), "low.#");
+        assert_eq!(incoming_key("Big.Example"), "big.example");
+        assert_eq!(
+            lane_name(&["big.example".to_string(), "Huge.Example".to_string()]),
+            "big.example+huge.example"
+        );
+    }
+
+    #[test]
+    fn test_sender_domain() {
+        let activity = json!({"type": "Create", "actor": "https://Big.Example/users/alice"});
+        assert_eq!(sender_domain(&activity).as_deref(), Some("big.example"));
+
+        let activity = json!({"type": "Create", "actor": {"id": "https://big.example/u/a"}});
+        assert_eq!(sender_domain(&activity).as_deref(), Some("big.example"));
+
+        assert_eq!(sender_domain(&json!({"type": "Create"})), None);
+        assert_eq!(sender_domain(&json!({"actor": "not a url"})), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Graceful shutdown for daemons
+//!
+//! On SIGINT or SIGTERM a daemon triggers its [`Shutdown`]: consumers stop
+//! taking new deliveries, and the daemon drains the deliveries already in
+//! flight for up to a deadline. Deliveries still running when the deadline
+//! passes are abandoned; their tasks nack them with requeue so another
+//! instance picks them up, and the daemon then closes its AMQP and MongoDB
+//! connections.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// How long abandoned tasks get to nack their deliveries
+const ABANDON_GRACE: Duration = Duration::from_secs(5);
+
+/// Default time in-flight deliveries get to finish
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Shutdown coordinator shared by all tasks of a daemon
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    triggered: CancellationToken,
+    abandoned: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin shutting down
+    pub fn trigger(&self) {
+        self.triggered.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.is_cancelled()
+    }
+
+    /// Resolves once shutdown has begun
+    pub async fn triggered(&self) {
+        self.triggered.cancelled().await
+    }
+
+    /// Run `fut` unless shutdown begins first
+    ///
+    /// Consumers wrap waiting for their next delivery in this so they stop
+    /// taking messages as soon as shutdown begins.
+    pub async fn unless_triggered<F: Future>(&self, fut: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.triggered.cancelled() => None,
+            output = fut => Some(output),
+        }
+    }
+
+    /// Run `fut` unless the drain deadline passes first
+    ///
+    /// Returns `None` when the work was abandoned; the caller should then
+    /// requeue the delivery it was processing.
+    pub async fn unless_abandoned<F: Future>(&self, fut: F) -> Option<F::Output> {
+        tokio::select! {
+            output = fut => Some(output),
+            _ = self.abandoned.cancelled() => None,
+        }
+    }
+
+    /// Spawn a task that shutdown waits for while draining
+    pub fn spawn<F>(&self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.spawn(fut)
+    }
+
+    /// Number of tracked tasks still running
+    pub fn in_flight(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Wait for SIGINT or SIGTERM and begin shutting down
+    pub async fn wait_for_signal(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            let mut terminate =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => result?,
+                _ = terminate.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+
+        info!("Shutdown signal received");
+        self.trigger();
+        Ok(())
+    }
+
+    /// Wait for tracked tasks to finish, abandoning them after `timeout`
+    ///
+    /// Triggers shutdown if that has not happened yet. Returns `true` if all
+    /// tasks finished before the deadline.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.trigger();
+        self.tasks.close();
+
+        let pending = self.tasks.len();
+        if pending > 0 {
+            info!(
+                "Draining {} in-flight task(s) for up to {:?}",
+                pending, timeout
+            );
+        }
+
+        if tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .is_ok()
+        {
+            info!("All in-flight work finished");
+            return true;
+        }
+
+        warn!(
+            "Drain deadline passed with {} task(s) still running, requeueing their deliveries",
+            self.tasks.len()
+        );
+        self.abandoned.cancel();
+        if tokio::time::timeout(ABANDON_GRACE, self.tasks.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                "{} task(s) did not stop; the broker requeues their deliveries when the connection closes",
+                self.tasks.len()
+            );
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_tasks() {
+        let shutdown = Shutdown::new();
+        let task = shutdown.spawn(async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            "done"
+        });
+
+        assert!(shutdown.drain(Duration::from_secs(5)).await);
+        assert!(shutdown.is_triggered());
+        assert_eq!(task.await.unwrap(), "done");
+    }
+
+    #[tokio::test]
+    async fn test_drain_abandons_slow_tasks() {
+        let shutdown = Shutdown::new();
+        let task = shutdown.spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown
+                    .unless_abandoned(tokio::time::sleep(Duration::from_secs(60)))
+                    .await
+            }
+        });
+
+        assert!(!shutdown.drain(Duration::from_millis(20)).await);
+        assert_eq!(task.await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_triggered_stops_waiting() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+        let next = shutdown
+            .unless_triggered(std::future::pending::<()>())
+            .await;
+        assert!(next.is_none());
+    }
+}
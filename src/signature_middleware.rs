@@ -0,0 +1,890 @@
+//! Inbound HTTP signature verification for axum services
+//!
+//! [`SignatureVerifier`] checks the signature of incoming requests in either
+//! the draft-cavage `Signature` form most ActivityPub servers send or the
+//! RFC 9421 `Signature-Input`/`Signature` form. Besides the signature itself
+//! it enforces that the request target and, for requests with a body, the
+//! `Digest` or `Content-Digest` header are signed, that the digest matches
+//! the body and that the signature time lies within the allowed clock skew.
+//!
+//! Keys are looked up through a [`KeyFetcher`]. [`HttpKeyFetcher`] fetches
+//! the actor or key document named by the key ID and [`CachedKeyFetcher`]
+//! keeps fetched keys for a while; services put their own lookups, such as
+//! stored keys and revocation checks, in front of them.
+//!
+//! [`require_signature`] runs the verifier as axum middleware:
+//!
+//! ```ignore
+//! let inboxes = Router::new()
+//!     .route("/inbox", post(post_shared_inbox))
+//!     .route_layer(axum::middleware::from_fn_with_state(
+//!         verifier,
+//!         oxifed::signature_middleware::require_signature,
+//!     ));
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode, header, request::Parts};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use moka::sync::Cache;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::config::{ConfigError, Env, require_positive};
+use crate::egress::EgressPolicy;
+use crate::httpsignature::{
+    ComponentIdentifier, HttpSignature, LegacySignature, Signature, SignatureAlgorithm,
+    SignatureError, VerificationConfig, public_key_from_pem, verify_digest,
+};
+
+/// Largest key or actor document accepted from a remote server
+const MAX_KEY_DOCUMENT_SIZE: usize = 1024 * 1024;
+
+/// Public key of a remote or local actor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorKey {
+    /// Key ID URL
+    pub id: String,
+    /// Actor the key belongs to
+    pub owner: String,
+    /// Public key in PEM format
+    pub public_key_pem: String,
+}
+
+/// Signer of a verified request
+///
+/// Inserted into the request extensions by [`require_signature`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedSigner {
+    /// Key ID the request was signed with
+    pub key_id: String,
+    /// Actor owning the key
+    pub owner: String,
+}
+
+/// Looks up the public key named by a signature's key ID
+pub trait KeyFetcher: Send + Sync {
+    /// Fetch the key with the given ID
+    ///
+    /// Fails with [`SignatureError::KeyRevoked`] for keys known to be revoked.
+    fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>>;
+
+    /// Forget a cached copy of a key, so the next fetch sees a rotated key
+    fn invalidate(&self, _key_id: &str) {}
+}
+
+/// Fetches keys from the actor or key document at the key ID URL
+#[derive(Debug, Clone)]
+pub struct HttpKeyFetcher {
+    client: reqwest::Client,
+    egress: EgressPolicy,
+}
+
+impl HttpKeyFetcher {
+    /// Create a fetcher following the installed egress policy
+    pub fn new() -> Result<Self, SignatureError> {
+        Self::with_policy(EgressPolicy::installed())
+    }
+
+    fn with_policy(egress: EgressPolicy) -> Result<Self, SignatureError> {
+        let builder = reqwest::Client::builder()
+            .user_agent(format!("Oxifed/{}", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10));
+        let client = egress
+            .configure(builder)
+            .and_then(|builder| builder.build())
+            .map_err(|e| SignatureError::RequestError(e.to_string()))?;
+        Ok(Self { client, egress })
+    }
+}
+
+impl KeyFetcher for HttpKeyFetcher {
+    fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>> {
+        Box::pin(async move {
+            let mut url = Url::parse(key_id)
+                .map_err(|e| SignatureError::KeyNotFound(format!("{}: {}", key_id, e)))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(SignatureError::KeyNotFound(key_id.to_string()));
+            }
+            url.set_fragment(None);
+            self.egress
+                .check_url(&url)
+                .map_err(|e| SignatureError::RequestError(e.to_string()))?;
+
+            let mut response = self
+                .client
+                .get(url)
+                .header(
+                    header::ACCEPT,
+                    "application/activity+json, application/ld+json",
+                )
+                .send()
+                .await
+                .map_err(|e| SignatureError::RequestError(e.to_string()))?;
+            if !response.status().is_success() {
+                return Err(SignatureError::KeyNotFound(format!(
+                    "{}: HTTP {}",
+                    key_id,
+                    response.status()
+                )));
+            }
+            let too_large =
+                || SignatureError::RequestError(format!("Key document of {} is too large", key_id));
+            if response
+                .content_length()
+                .is_some_and(|len| len > MAX_KEY_DOCUMENT_SIZE as u64)
+            {
+                return Err(too_large());
+            }
+
+            // Read chunk by chunk, so an oversized body without a length is
+            // dropped once it passes the limit rather than buffered whole
+            let mut body = Vec::new();
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| SignatureError::RequestError(e.to_string()))?
+            {
+                if body.len() + chunk.len() > MAX_KEY_DOCUMENT_SIZE {
+                    return Err(too_large());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            let document: Value = serde_json::from_slice(&body).map_err(|e| {
+                SignatureError::RequestError(format!("Invalid key document: {}", e))
+            })?;
+
+            key_from_document(&document, key_id)
+        })
+    }
+}
+
+/// Find a key in an actor document or a standalone key document
+///
+/// The owner has to be on the same host as the key, so a server cannot
+/// publish keys for actors of another server.
+pub fn key_from_document(document: &Value, key_id: &str) -> Result<ActorKey, SignatureError> {
+    let key_object = |value: &Value| {
+        let pem = value.get("publicKeyPem")?.as_str()?;
+        let id = value.get("id")?.as_str()?;
+        (id == key_id).then(|| {
+            let owner = value
+                .get("owner")
+                .or_else(|| value.get("controller"))
+                .and_then(Value::as_str)
+                .or_else(|| document.get("id").and_then(Value::as_str))
+                .unwrap_or_default();
+            (owner.to_string(), pem.to_string())
+        })
+    };
+
+    let found = match document.get("publicKey") {
+        Some(Value::Array(keys)) => keys.iter().find_map(key_object),
+        Some(key) => key_object(key),
+        None => key_object(document),
+    };
+    let (owner, public_key_pem) =
+        found.ok_or_else(|| SignatureError::KeyNotFound(key_id.to_string()))?;
+
+    let host = |url: &str| Url::parse(url).ok()?.host_str().map(str::to_string);
+    if owner.is_empty() || host(&owner) != host(key_id) {
+        return Err(SignatureError::KeyNotFound(format!(
+            "{} is not owned by an actor on its host",
+            key_id
+        )));
+    }
+
+    Ok(ActorKey {
+        id: key_id.to_string(),
+        owner,
+        public_key_pem,
+    })
+}
+
+/// Keeps the keys returned by another fetcher for a while
+pub struct CachedKeyFetcher<F> {
+    inner: F,
+    keys: Cache<String, ActorKey>,
+}
+
+impl<F: KeyFetcher> CachedKeyFetcher<F> {
+    pub fn new(inner: F, capacity: u64, ttl: Duration) -> Self {
+        Self {
+            inner,
+            keys: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+}
+
+impl<F> fmt::Debug for CachedKeyFetcher<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedKeyFetcher")
+            .field("cached", &self.keys.entry_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: KeyFetcher> KeyFetcher for CachedKeyFetcher<F> {
+    fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>> {
+        Box::pin(async move {
+            if let Some(key) = self.keys.get(key_id) {
+                return Ok(key);
+            }
+            let key = self.inner.fetch(key_id).await?;
+            self.keys.insert(key_id.to_string(), key.clone());
+            Ok(key)
+        })
+    }
+
+    fn invalidate(&self, key_id: &str) {
+        self.keys.invalidate(key_id);
+        self.inner.invalidate(key_id);
+    }
+}
+
+/// Inbound signature verification settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignatureVerificationConfig {
+    /// Reject requests without a valid signature; otherwise failures are
+    /// only logged
+    pub enforce: bool,
+    /// Allowed difference between the signature time and the local clock,
+    /// in seconds
+    pub clock_skew_secs: i64,
+    /// Largest request body read to check its digest, in bytes
+    pub max_body_size: usize,
+    /// Remote keys kept in the cache
+    pub key_cache_capacity: u64,
+    /// How long a fetched remote key is used before it is fetched again,
+    /// in seconds
+    pub key_cache_ttl_secs: u64,
+}
+
+impl Default for SignatureVerificationConfig {
+    fn default() -> Self {
+        Self {
+            enforce: true,
+            clock_skew_secs: 300,
+            max_body_size: 1024 * 1024,
+            key_cache_capacity: 10_000,
+            key_cache_ttl_secs: 3600,
+        }
+    }
+}
+
+impl SignatureVerificationConfig {
+    /// Apply `<PREFIX>_ENFORCE`, `<PREFIX>_CLOCK_SKEW_SECS`,
+    /// `<PREFIX>_MAX_BODY_SIZE`, `<PREFIX>_KEY_CACHE_CAPACITY` and
+    /// `<PREFIX>_KEY_CACHE_TTL_SECS`
+    pub fn apply_env(&mut self, prefix: &str, env: &Env) -> Result<(), ConfigError> {
+        env.set(&format!("{}_ENFORCE", prefix), &mut self.enforce)?;
+        env.set(
+            &format!("{}_CLOCK_SKEW_SECS", prefix),
+            &mut self.clock_skew_secs,
+        )?;
+        env.set(
+            &format!("{}_MAX_BODY_SIZE", prefix),
+            &mut self.max_body_size,
+        )?;
+        env.set(
+            &format!("{}_KEY_CACHE_CAPACITY", prefix),
+            &mut self.key_cache_capacity,
+        )?;
+        env.set(
+            &format!("{}_KEY_CACHE_TTL_SECS", prefix),
+            &mut self.key_cache_ttl_secs,
+        )
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        require_positive(&format!("{}.clock_skew_secs", key), self.clock_skew_secs)?;
+        require_positive(&format!("{}.max_body_size", key), self.max_body_size)?;
+        require_positive(
+            &format!("{}.key_cache_capacity", key),
+            self.key_cache_capacity,
+        )
+    }
+
+    /// Cache keys of `fetcher` as configured
+    pub fn cached<F: KeyFetcher>(&self, fetcher: F) -> CachedKeyFetcher<F> {
+        CachedKeyFetcher::new(
+            fetcher,
+            self.key_cache_capacity,
+            Duration::from_secs(self.key_cache_ttl_secs),
+        )
+    }
+}
+
+/// Verifies the HTTP signatures of incoming requests
+pub struct SignatureVerifier {
+    fetcher: Arc<dyn KeyFetcher>,
+    enforce: bool,
+    clock_skew_secs: i64,
+    max_body_size: usize,
+}
+
+impl fmt::Debug for SignatureVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureVerifier")
+            .field("enforce", &self.enforce)
+            .field("clock_skew_secs", &self.clock_skew_secs)
+            .field("max_body_size", &self.max_body_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SignatureVerifier {
+    pub fn new(fetcher: Arc<dyn KeyFetcher>, config: &SignatureVerificationConfig) -> Self {
+        Self {
+            fetcher,
+            enforce: config.enforce,
+            clock_skew_secs: config.clock_skew_secs,
+            max_body_size: config.max_body_size,
+        }
+    }
+
+    /// Whether requests failing verification are rejected
+    pub fn is_enforcing(&self) -> bool {
+        self.enforce
+    }
+
+    /// Forget a cached key, for example after its actor announced a new one
+    pub fn invalidate_key(&self, key_id: &str) {
+        self.fetcher.invalidate(key_id);
+    }
+
+    /// Verify the signature and digest of a request
+    ///
+    /// Draft-cavage and RFC 9421 signatures are both accepted; the form is
+    /// picked by the presence of a `Signature-Input` header. A signature that
+    /// does not verify against a cached key is checked once more against a
+    /// freshly fetched key, as the actor may have rotated it.
+    pub async fn verify(
+        &self,
+        parts: &Parts,
+        body: &[u8],
+    ) -> Result<VerifiedSigner, SignatureError> {
+        verify_digest(&parts.headers, body)?;
+
+        let request = to_reqwest(parts)?;
+        let rfc9421 = parts.headers.contains_key("signature-input");
+        let (key_id, algorithm) = if rfc9421 {
+            let signature = Signature::from_request(&request)?;
+            let has_target = signature.components.iter().any(|component| {
+                matches!(
+                    component,
+                    ComponentIdentifier::RequestTarget
+                        | ComponentIdentifier::TargetUri
+                        | ComponentIdentifier::Path
+                )
+            });
+            if !has_target {
+                return Err(SignatureError::MissingSignatureComponents);
+            }
+            let key_id = signature
+                .parameters
+                .key_id
+                .ok_or_else(|| SignatureError::MissingParameter("keyid".to_string()))?;
+            (key_id, signature.parameters.algorithm)
+        } else {
+            let signature = LegacySignature::from_request(&request)?;
+            let algorithm = match signature.algorithm.as_deref() {
+                None | Some("hs2019") => None,
+                Some("rsa-sha256") => Some(SignatureAlgorithm::RsaSha256),
+                Some("ecdsa-sha256") => Some(SignatureAlgorithm::EcdsaP256Sha256),
+                Some(name) => Some(name.parse()?),
+            };
+            (signature.key_id, algorithm)
+        };
+
+        let mut required = vec![];
+        if !rfc9421 {
+            required.push(ComponentIdentifier::RequestTarget);
+        }
+        if !body.is_empty() {
+            // Either digest header may carry the checked digest; the one
+            // present has to be signed
+            let digest_header = if parts.headers.contains_key("digest") {
+                "digest"
+            } else {
+                "content-digest"
+            };
+            required.push(ComponentIdentifier::Header(digest_header.to_string()));
+        }
+
+        let check = |key: &ActorKey| -> Result<(), SignatureError> {
+            let algorithm = match &algorithm {
+                Some(algorithm) => algorithm.clone(),
+                None => algorithm_of_key(&key.public_key_pem)?,
+            };
+            let config = VerificationConfig::new(
+                public_key_from_pem(&key.public_key_pem, &algorithm)?,
+                algorithm,
+            )
+            .with_max_age(self.clock_skew_secs)
+            .with_clock_skew(self.clock_skew_secs)
+            .with_required_components(required.clone());
+            if rfc9421 {
+                HttpSignature::verify_request(&request, &config)
+            } else {
+                HttpSignature::verify_request_legacy(&request, &config)
+            }
+        };
+
+        let key = self.fetcher.fetch(&key_id).await?;
+        let key = match check(&key) {
+            Ok(()) => key,
+            Err(SignatureError::VerificationFailed) => {
+                self.fetcher.invalidate(&key_id);
+                let refreshed = self.fetcher.fetch(&key_id).await?;
+                if refreshed == key {
+                    return Err(SignatureError::VerificationFailed);
+                }
+                check(&refreshed)?;
+                refreshed
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(VerifiedSigner {
+            key_id,
+            owner: key.owner,
+        })
+    }
+}
+
+/// Algorithm of an `hs2019` signature, which leaves it to the key type
+fn algorithm_of_key(public_key_pem: &str) -> Result<SignatureAlgorithm, SignatureError> {
+    if public_key_from_pem(public_key_pem, &SignatureAlgorithm::Ed25519).is_ok() {
+        Ok(SignatureAlgorithm::Ed25519)
+    } else {
+        // RSA keys sign with PKCS#1 v1.5 and SHA-256 in the fediverse
+        Ok(SignatureAlgorithm::RsaSha256)
+    }
+}
+
+/// Rebuild the incoming request as the request type signatures are checked on
+///
+/// The scheme comes from `X-Forwarded-Proto` and defaults to https, as
+/// federation traffic reaches the services through a TLS-terminating proxy.
+fn to_reqwest(parts: &Parts) -> Result<reqwest::Request, SignatureError> {
+    let host = parts
+        .headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| parts.uri.authority().map(|authority| authority.as_str()))
+        .ok_or_else(|| SignatureError::InvalidHeader("Host header not found".to_string()))?;
+    let scheme = parts
+        .headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .unwrap_or("https");
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+
+    let url = Url::parse(&format!("{}://{}{}", scheme, host, path_and_query))
+        .map_err(|e| SignatureError::RequestError(format!("Invalid request URL: {}", e)))?;
+    let mut request = reqwest::Request::new(parts.method.clone(), url);
+    *request.headers_mut() = parts.headers.clone();
+    Ok(request)
+}
+
+/// Axum middleware verifying the HTTP signature of each request
+///
+/// Verified requests carry a [`VerifiedSigner`] extension. Failures are
+/// answered with 401 Unauthorized, or only logged if the verifier does not
+/// enforce signatures. Bodies not matching their digest are always answered
+/// with 400 Bad Request. Bodies larger than the configured limit are answered
+/// with 413 Payload Too Large.
+pub async fn require_signature(
+    State(verifier): State<Arc<SignatureVerifier>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let body: Bytes = match axum::body::to_bytes(body, verifier.max_body_size).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Rejected request body: {}", e);
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+    };
+
+    match verifier.verify(&parts, &body).await {
+        Ok(signer) => {
+            debug!("Request signed by {} ({})", signer.owner, signer.key_id);
+            parts.extensions.insert(signer);
+        }
+        // A body that does not match its digest was altered or truncated in
+        // transit, whether or not signatures are enforced
+        Err(SignatureError::DigestMismatch) => {
+            warn!(
+                "Rejected request to {}: body does not match digest",
+                parts.uri.path()
+            );
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+        Err(e) if verifier.enforce => {
+            warn!("Rejected request to {}: {}", parts.uri.path(), e);
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Err(e) => warn!(
+            "HTTP signature of request to {} not verified, accepting anyway: {}",
+            parts.uri.path(),
+            e
+        ),
+    }
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// Axum middleware identifying the signer of GET requests that are signed
+///
+/// Unlike [`require_signature`], requests are never rejected: unsigned
+/// requests and those whose signature does not verify are passed on without
+/// a [`VerifiedSigner`], so handlers serve them what anonymous clients may
+/// see.
+pub async fn accept_signature(
+    State(verifier): State<Arc<SignatureVerifier>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let signed = ["signature", "signature-input"]
+        .iter()
+        .any(|name| request.headers().contains_key(*name));
+    if !signed || !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    match verifier.verify(&parts, &[]).await {
+        Ok(signer) => {
+            debug!("Request signed by {} ({})", signer.owner, signer.key_id);
+            parts.extensions.insert(signer);
+        }
+        Err(e) => debug!(
+            "HTTP signature of request to {} not verified, serving it anonymously: {}",
+            parts.uri.path(),
+            e
+        ),
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httpsignature::{LocalSigner, SignatureConfig, SignatureParameters, digest_header};
+    use axum::{Router, routing::post};
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use chrono::Utc;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    const KEY_ID: &str = "https://remote.example/users/alice#main-key";
+
+    /// Fetcher serving the Ed25519 test key and counting lookups
+    #[derive(Default)]
+    struct TestFetcher {
+        fetches: AtomicUsize,
+    }
+
+    impl KeyFetcher for TestFetcher {
+        fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>> {
+            Box::pin(async move {
+                self.fetches.fetch_add(1, Ordering::SeqCst);
+                Ok(ActorKey {
+                    id: key_id.to_string(),
+                    owner: "https://remote.example/users/alice".to_string(),
+                    public_key_pem: include_str!("../test-data/ed25519_test_public_key.pem")
+                        .to_string(),
+                })
+            })
+        }
+    }
+
+    /// Inbox request signed the way publisherd signs deliveries
+    async fn signed_request(body: &str, date: chrono::DateTime<Utc>) -> reqwest::Request {
+        let private_key: String = include_str!("../test-data/ed25519_test_key.pem")
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let mut request = reqwest::Client::new()
+            .post("https://local.example/users/bob/inbox")
+            .header("host", "local.example")
+            .header("date", date.to_rfc2822())
+            .header("content-type", "application/activity+json")
+            .header("digest", digest_header(body.as_bytes()))
+            .body(body.to_string())
+            .build()
+            .unwrap();
+        let config = SignatureConfig {
+            parameters: SignatureParameters::new(),
+            key_id: KEY_ID.to_string(),
+            components: vec![
+                ComponentIdentifier::RequestTarget,
+                ComponentIdentifier::Header("host".to_string()),
+                ComponentIdentifier::Header("date".to_string()),
+                ComponentIdentifier::Digest,
+            ],
+            signer: Arc::new(LocalSigner::new(
+                SignatureAlgorithm::Ed25519,
+                BASE64.decode(private_key).unwrap(),
+            )),
+        };
+        HttpSignature::sign_request_legacy(&mut request, &config)
+            .await
+            .unwrap();
+        request
+    }
+
+    /// Convert a signed client request into the request a server receives
+    fn incoming(request: &reqwest::Request, body: &str) -> Request {
+        let mut builder = axum::http::Request::builder()
+            .method(request.method().clone())
+            .uri(request.url().path());
+        for (name, value) in request.headers() {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    fn verifier(enforce: bool) -> Arc<SignatureVerifier> {
+        let config = SignatureVerificationConfig {
+            enforce,
+            ..Default::default()
+        };
+        Arc::new(SignatureVerifier::new(
+            Arc::new(config.cached(TestFetcher::default())),
+            &config,
+        ))
+    }
+
+    async fn post_inbox(verifier: Arc<SignatureVerifier>, request: Request) -> StatusCode {
+        Router::new()
+            .route(
+                "/users/bob/inbox",
+                post(
+                    |signer: Option<axum::Extension<VerifiedSigner>>| async move {
+                        match signer {
+                            Some(_) => StatusCode::ACCEPTED,
+                            None => StatusCode::OK,
+                        }
+                    },
+                ),
+            )
+            .route_layer(axum::middleware::from_fn_with_state(
+                verifier,
+                require_signature,
+            ))
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_verifies_signed_request() {
+        let body = r#"{"type":"Follow"}"#;
+        let request = signed_request(body, Utc::now()).await;
+        let (parts, _) = incoming(&request, body).into_parts();
+
+        let signer = verifier(true)
+            .verify(&parts, body.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(signer.key_id, KEY_ID);
+        assert_eq!(signer.owner, "https://remote.example/users/alice");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_tampered_body_and_stale_date() {
+        let body = r#"{"type":"Follow"}"#;
+        let request = signed_request(body, Utc::now()).await;
+        let (parts, _) = incoming(&request, body).into_parts();
+        assert!(matches!(
+            verifier(true).verify(&parts, br#"{"type":"Block"}"#).await,
+            Err(SignatureError::DigestMismatch)
+        ));
+
+        let stale = signed_request(body, Utc::now() - chrono::Duration::hours(1)).await;
+        let (parts, _) = incoming(&stale, body).into_parts();
+        assert!(matches!(
+            verifier(true).verify(&parts, body.as_bytes()).await,
+            Err(SignatureError::SignatureExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_enforces_signatures() {
+        let body = r#"{"type":"Follow"}"#;
+        let request = signed_request(body, Utc::now()).await;
+        assert_eq!(
+            post_inbox(verifier(true), incoming(&request, body)).await,
+            StatusCode::ACCEPTED
+        );
+
+        let unsigned = axum::http::Request::post("/users/bob/inbox")
+            .header("host", "local.example")
+            .body(Body::from(body))
+            .unwrap();
+        assert_eq!(
+            post_inbox(verifier(true), unsigned).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let unsigned = axum::http::Request::post("/users/bob/inbox")
+            .header("host", "local.example")
+            .body(Body::from(body))
+            .unwrap();
+        assert_eq!(post_inbox(verifier(false), unsigned).await, StatusCode::OK);
+
+        // Altered bodies are refused even when signatures are not enforced
+        let tampered = incoming(&request, r#"{"type":"Block"}"#);
+        assert_eq!(
+            post_inbox(verifier(false), tampered).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accept_signature_serves_unsigned_requests() {
+        let get_object = |request: Request| {
+            Router::new()
+                .route(
+                    "/objects/1",
+                    axum::routing::get(
+                        |signer: Option<axum::Extension<VerifiedSigner>>| async move {
+                            match signer {
+                                Some(_) => StatusCode::ACCEPTED,
+                                None => StatusCode::OK,
+                            }
+                        },
+                    ),
+                )
+                .route_layer(axum::middleware::from_fn_with_state(
+                    verifier(true),
+                    accept_signature,
+                ))
+                .oneshot(request)
+        };
+
+        let unsigned = axum::http::Request::get("/objects/1")
+            .header("host", "local.example")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(get_object(unsigned).await.unwrap().status(), StatusCode::OK);
+
+        let forged = axum::http::Request::get("/objects/1")
+            .header("host", "local.example")
+            .header(
+                "signature",
+                format!(r#"keyId="{}",headers="host",signature="AAAA""#, KEY_ID),
+            )
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(get_object(forged).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_cached_fetcher_fetches_once() {
+        let fetcher = CachedKeyFetcher::new(TestFetcher::default(), 16, Duration::from_secs(60));
+        fetcher.fetch(KEY_ID).await.unwrap();
+        fetcher.fetch(KEY_ID).await.unwrap();
+        assert_eq!(fetcher.inner.fetches.load(Ordering::SeqCst), 1);
+
+        fetcher.invalidate(KEY_ID);
+        fetcher.fetch(KEY_ID).await.unwrap();
+        assert_eq!(fetcher.inner.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_key_from_actor_document() {
+        let actor = json!({
+            "id": "https://remote.example/users/alice",
+            "type": "Person",
+            "publicKey": {
+                "id": KEY_ID,
+                "owner": "https://remote.example/users/alice",
+                "publicKeyPem": "PEM"
+            }
+        });
+        let key = key_from_document(&actor, KEY_ID).unwrap();
+        assert_eq!(key.owner, "https://remote.example/users/alice");
+        assert_eq!(key.public_key_pem, "PEM");
+
+        assert!(key_from_document(&actor, "https://remote.example/users/bob#main-key").is_err());
+
+        // Keys claiming an owner on another host are not accepted
+        let foreign = json!({
+            "id": KEY_ID,
+            "owner": "https://other.example/users/mallory",
+            "publicKeyPem": "PEM"
+        });
+        assert!(key_from_document(&foreign, KEY_ID).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_key_fetcher_limits_document_size() {
+        let mut server = mockito::Server::new_async().await;
+        let actor = format!("{}/users/alice", server.url());
+        let key_id = format!("{}#main-key", actor);
+        let _actor = server
+            .mock("GET", "/users/alice")
+            .with_status(200)
+            .with_header("content-type", "application/activity+json")
+            .with_body(
+                json!({
+                    "id": actor,
+                    "type": "Person",
+                    "publicKey": { "id": key_id, "owner": actor, "publicKeyPem": "PEM" }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        // Chunked, so the size is only known by reading the body
+        let _huge = server
+            .mock("GET", "/users/huge")
+            .with_status(200)
+            .with_header("content-type", "application/activity+json")
+            .with_chunked_body(|writer| {
+                let chunk = [b' '; 64 * 1024];
+                for _ in 0..(2 * MAX_KEY_DOCUMENT_SIZE / chunk.len()) {
+                    writer.write_all(&chunk)?;
+                }
+                Ok(())
+            })
+            .create_async()
+            .await;
+
+        let fetcher = HttpKeyFetcher::with_policy(EgressPolicy::allowing_private()).unwrap();
+        let key = fetcher.fetch(&key_id).await.unwrap();
+        assert_eq!(key.owner, actor);
+
+        let huge = format!("{}/users/huge#main-key", server.url());
+        match fetcher.fetch(&huge).await {
+            Err(SignatureError::RequestError(message)) => {
+                assert!(message.contains("too large"), "{}", message)
+            }
+            other => panic!("Expected an oversized key document, got {:?}", other),
+        }
+    }
+}
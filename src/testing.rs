@@ -0,0 +1,720 @@
+//! Mock ActivityPub peer for tests
+//!
+//! [`MockPeer`] is a small axum server standing in for a remote instance. It
+//! serves an actor with an Ed25519 key for every username, answers
+//! WebFinger, serves documents and collections registered by the test,
+//! records what is delivered to its inboxes together with the verified
+//! signer, and sends signed activities of its actors. Any path can be made
+//! to fail with a status code, and inbox deliveries can be failed a number
+//! of times to exercise retries.
+//!
+//! ```ignore
+//! let peer = MockPeer::start().await?;
+//! peer.fail_deliveries(1, StatusCode::SERVICE_UNAVAILABLE);
+//! // ... deliver to peer.inbox("bob") ...
+//! let delivery = peer
+//!     .wait_for_delivery("the Create", |d| d.activity_type() == Some("Create"))
+//!     .await?;
+//! ```
+//!
+//! Without a domain the peer is addressed as `http://127.0.0.1:<port>`, so
+//! the code under test reaches it without DNS or TLS.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Router};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use crate::httpsignature::{
+    ComponentIdentifier, HttpSignature, LocalSigner, SignatureAlgorithm, SignatureConfig,
+    SignatureError, SignatureParameters, digest_header,
+};
+use crate::pki::{KeyAlgorithm, KeyPair, PkiError};
+use crate::signature_middleware::{
+    ActorKey, KeyFetcher, SignatureVerificationConfig, SignatureVerifier, VerifiedSigner,
+    key_from_document, require_signature,
+};
+
+/// Status codes the peer answers with, re-exported for crates without axum
+pub use axum::http::StatusCode;
+
+const ACTIVITY_JSON: &str = "application/activity+json";
+
+/// Time [`MockPeer::wait_for_delivery`] waits for a delivery
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors starting or driving a mock peer
+#[derive(Error, Debug)]
+pub enum MockPeerError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid header value: {0}")]
+    Header(#[from] axum::http::header::InvalidHeaderValue),
+
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Key error: {0}")]
+    Key(#[from] PkiError),
+
+    #[error("Invalid key encoding: {0}")]
+    KeyEncoding(#[from] base64::DecodeError),
+
+    #[error("Signature error: {0}")]
+    Signature(#[from] SignatureError),
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+}
+
+/// Settings of a [`MockPeer`]
+#[derive(Debug, Clone, Default)]
+pub struct MockPeerConfig {
+    /// Domain served over `https://`, for setups routing it to the peer
+    ///
+    /// Without one the peer is addressed by its local address over plain
+    /// HTTP.
+    pub domain: Option<String>,
+    /// Client fetching signature keys and sending activities
+    pub client: Option<reqwest::Client>,
+    /// Answer inbox posts without a valid signature with 401 Unauthorized
+    /// instead of recording them unverified
+    pub require_signatures: bool,
+    /// Address to listen on instead of a free local port
+    ///
+    /// Set `domain` as well when listening on an address other than
+    /// loopback, the peer's IDs would carry the unspecified address
+    /// otherwise.
+    pub listen: Option<SocketAddr>,
+}
+
+/// An activity delivered to the mock peer
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    /// Inbox path the activity was posted to
+    pub path: String,
+    pub headers: HeaderMap,
+    pub activity: Value,
+    /// Signer of the request, if its signature verified
+    pub signer: Option<VerifiedSigner>,
+    /// Status the delivery was answered with
+    pub status: StatusCode,
+}
+
+impl Delivery {
+    /// `type` of the delivered activity
+    pub fn activity_type(&self) -> Option<&str> {
+        self.activity.get("type").and_then(Value::as_str)
+    }
+}
+
+/// Inbox deliveries still to be failed
+struct FailingDeliveries {
+    remaining: usize,
+    status: StatusCode,
+}
+
+struct PeerState {
+    base_url: String,
+    host: String,
+    public_key_pem: String,
+    private_key: Vec<u8>,
+    client: reqwest::Client,
+    documents: Mutex<HashMap<String, Value>>,
+    failures: Mutex<HashMap<String, StatusCode>>,
+    failing_deliveries: Mutex<Option<FailingDeliveries>>,
+    deliveries: Mutex<Vec<Delivery>>,
+}
+
+impl PeerState {
+    fn actor_id(&self, username: &str) -> String {
+        format!("{}/users/{}", self.base_url, username)
+    }
+}
+
+/// A fake remote ActivityPub server
+pub struct MockPeer {
+    state: Arc<PeerState>,
+    keys: Arc<Mutex<HashMap<String, ActorKey>>>,
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for MockPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockPeer")
+            .field("base_url", &self.state.base_url)
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockPeer {
+    /// Serve a peer on a free local port, addressed by that port
+    pub async fn start() -> Result<Self, MockPeerError> {
+        Self::start_with(MockPeerConfig::default()).await
+    }
+
+    /// Serve a peer with the given settings
+    pub async fn start_with(config: MockPeerConfig) -> Result<Self, MockPeerError> {
+        let listener = match config.listen {
+            Some(addr) => TcpListener::bind(addr).await?,
+            None => TcpListener::bind("127.0.0.1:0").await?,
+        };
+        let addr = listener.local_addr()?;
+        let (base_url, host) = match config.domain {
+            Some(domain) => (format!("https://{}", domain), domain),
+            None => (format!("http://{}", addr), addr.to_string()),
+        };
+        let client = config.client.unwrap_or_default();
+
+        let key = KeyPair::generate(KeyAlgorithm::Ed25519)?;
+        let state = Arc::new(PeerState {
+            base_url,
+            host,
+            public_key_pem: key.public_key.pem_data.clone(),
+            private_key: pem_to_der(&key.private_key.encrypted_pem)?,
+            client: client.clone(),
+            documents: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            failing_deliveries: Mutex::new(None),
+            deliveries: Mutex::new(Vec::new()),
+        });
+
+        let keys = Arc::new(Mutex::new(HashMap::new()));
+        let verifier = Arc::new(SignatureVerifier::new(
+            Arc::new(PeerKeyFetcher {
+                client,
+                keys: keys.clone(),
+            }),
+            &SignatureVerificationConfig {
+                enforce: config.require_signatures,
+                ..Default::default()
+            },
+        ));
+        let inboxes = Router::new()
+            .route("/inbox", post(receive))
+            .route("/users/{username}/inbox", post(receive))
+            .route_layer(axum::middleware::from_fn_with_state(
+                verifier,
+                require_signature,
+            ));
+        let router = Router::new()
+            .route("/.well-known/webfinger", get(webfinger))
+            .route("/users/{username}", get(actor))
+            .merge(inboxes)
+            .fallback(get(document))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                fail_paths,
+            ))
+            .with_state(state.clone());
+
+        let task = tokio::spawn(async move {
+            axum::serve(listener, router).await.ok();
+        });
+
+        Ok(Self {
+            state,
+            keys,
+            addr,
+            task,
+        })
+    }
+
+    /// Local address the peer listens on, in plain HTTP
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Host the peer is addressed by, with the port if it has no domain
+    pub fn host(&self) -> &str {
+        &self.state.host
+    }
+
+    /// Base URL of the peer's IDs, without trailing slash
+    pub fn base_url(&self) -> &str {
+        &self.state.base_url
+    }
+
+    /// ActivityPub ID of the mock actor `username`
+    pub fn actor_id(&self, username: &str) -> String {
+        self.state.actor_id(username)
+    }
+
+    /// Inbox of the mock actor `username`
+    pub fn inbox(&self, username: &str) -> String {
+        format!("{}/inbox", self.actor_id(username))
+    }
+
+    /// Shared inbox of the peer
+    pub fn shared_inbox(&self) -> String {
+        format!("{}/inbox", self.state.base_url)
+    }
+
+    /// Key ID the mock actor `username` signs with
+    pub fn key_id(&self, username: &str) -> String {
+        format!("{}#main-key", self.actor_id(username))
+    }
+
+    /// Serve `document` at `path`
+    ///
+    /// Actor paths, `/users/{username}`, are always served by the peer.
+    pub fn add_document(&self, path: &str, document: Value) {
+        self.state
+            .documents
+            .lock()
+            .expect("documents poisoned")
+            .insert(path.to_string(), document);
+    }
+
+    /// Serve an `OrderedCollection` of `items` at `path`
+    pub fn add_collection(&self, path: &str, items: Vec<Value>) {
+        let document = json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{}{}", self.state.base_url, path),
+            "type": "OrderedCollection",
+            "totalItems": items.len(),
+            "orderedItems": items
+        });
+        self.add_document(path, document);
+    }
+
+    /// Trust `key` for incoming signatures without fetching it
+    ///
+    /// For signers whose actors the peer cannot reach, such as daemons under
+    /// test that serve no actor documents.
+    pub fn add_key(&self, key: ActorKey) {
+        self.keys
+            .lock()
+            .expect("keys poisoned")
+            .insert(key.id.clone(), key);
+    }
+
+    /// Answer every request to `path` with `status`
+    pub fn fail_path(&self, path: &str, status: StatusCode) {
+        self.state
+            .failures
+            .lock()
+            .expect("failures poisoned")
+            .insert(path.to_string(), status);
+    }
+
+    /// Serve `path` normally again
+    pub fn clear_failure(&self, path: &str) {
+        self.state
+            .failures
+            .lock()
+            .expect("failures poisoned")
+            .remove(path);
+    }
+
+    /// Answer the next `count` inbox deliveries with `status`
+    ///
+    /// The failed deliveries are recorded too, with the status they got.
+    pub fn fail_deliveries(&self, count: usize, status: StatusCode) {
+        *self
+            .state
+            .failing_deliveries
+            .lock()
+            .expect("failing deliveries poisoned") = Some(FailingDeliveries {
+            remaining: count,
+            status,
+        });
+    }
+
+    /// Everything delivered so far, failed deliveries included
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.state
+            .deliveries
+            .lock()
+            .expect("deliveries poisoned")
+            .clone()
+    }
+
+    /// Wait up to [`DELIVERY_TIMEOUT`] for a delivery matching `predicate`
+    pub async fn wait_for_delivery(
+        &self,
+        what: &str,
+        predicate: impl Fn(&Delivery) -> bool,
+    ) -> Result<Delivery, MockPeerError> {
+        let deadline = tokio::time::Instant::now() + DELIVERY_TIMEOUT;
+        loop {
+            if let Some(delivery) = self.deliveries().into_iter().find(|d| predicate(d)) {
+                return Ok(delivery);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(MockPeerError::Timeout(what.to_string()));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Post `activity` to `inbox`, signed by the mock actor `username`
+    ///
+    /// Signs in the draft-cavage form most servers send, covering the
+    /// request target, host, date and digest.
+    pub async fn send(
+        &self,
+        username: &str,
+        inbox: &str,
+        activity: &Value,
+    ) -> Result<StatusCode, MockPeerError> {
+        let body = serde_json::to_vec(activity)?;
+        let url = url::Url::parse(inbox)?;
+        let mut request = self
+            .state
+            .client
+            .post(url.clone())
+            .header(header::CONTENT_TYPE, ACTIVITY_JSON)
+            .header(
+                header::DATE,
+                chrono::Utc::now()
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            )
+            .header("digest", digest_header(&body))
+            .body(body)
+            .build()?;
+        // reqwest adds Host only when sending; it is signed, so set it here
+        if let Some(host) = url.host_str() {
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            request
+                .headers_mut()
+                .insert(header::HOST, HeaderValue::from_str(&host)?);
+        }
+
+        let config = SignatureConfig {
+            parameters: SignatureParameters::new(),
+            key_id: self.key_id(username),
+            components: vec![
+                ComponentIdentifier::RequestTarget,
+                ComponentIdentifier::Header("host".to_string()),
+                ComponentIdentifier::Header("date".to_string()),
+                ComponentIdentifier::Digest,
+            ],
+            signer: Arc::new(LocalSigner::new(
+                SignatureAlgorithm::Ed25519,
+                self.state.private_key.clone(),
+            )),
+        };
+        HttpSignature::sign_request_legacy(&mut request, &config).await?;
+
+        Ok(self.state.client.execute(request).await?.status())
+    }
+}
+
+impl Drop for MockPeer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer requests to failing paths with their status
+async fn fail_paths(
+    State(state): State<Arc<PeerState>>,
+    request: Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let status = state
+        .failures
+        .lock()
+        .expect("failures poisoned")
+        .get(request.uri().path())
+        .copied();
+    match status {
+        Some(status) => status.into_response(),
+        None => next.run(request).await,
+    }
+}
+
+async fn actor(State(state): State<Arc<PeerState>>, Path(username): Path<String>) -> Response {
+    let id = state.actor_id(&username);
+    let document = json!({
+        "@context": [
+            "https://www.w3.org/ns/activitystreams",
+            "https://w3id.org/security/v1"
+        ],
+        "id": id,
+        "type": "Person",
+        "preferredUsername": username,
+        "inbox": format!("{}/inbox", id),
+        "outbox": format!("{}/outbox", id),
+        "followers": format!("{}/followers", id),
+        "following": format!("{}/following", id),
+        "endpoints": { "sharedInbox": format!("{}/inbox", state.base_url) },
+        "publicKey": {
+            "id": format!("{}#main-key", id),
+            "owner": id,
+            "publicKeyPem": state.public_key_pem
+        }
+    });
+    ([(header::CONTENT_TYPE, ACTIVITY_JSON)], Json(document)).into_response()
+}
+
+async fn document(State(state): State<Arc<PeerState>>, request: Request) -> Response {
+    let document = state
+        .documents
+        .lock()
+        .expect("documents poisoned")
+        .get(request.uri().path())
+        .cloned();
+    match document {
+        Some(document) => ([(header::CONTENT_TYPE, ACTIVITY_JSON)], Json(document)).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct WebFingerQuery {
+    resource: String,
+}
+
+async fn webfinger(
+    State(state): State<Arc<PeerState>>,
+    Query(query): Query<WebFingerQuery>,
+) -> Response {
+    let account = query.resource.trim_start_matches("acct:");
+    let Some(username) = account
+        .strip_suffix(&format!("@{}", state.host))
+        .filter(|username| !username.is_empty())
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    Json(json!({
+        "subject": format!("acct:{}", account),
+        "links": [{
+            "rel": "self",
+            "type": ACTIVITY_JSON,
+            "href": state.actor_id(username)
+        }]
+    }))
+    .into_response()
+}
+
+async fn receive(
+    State(state): State<Arc<PeerState>>,
+    signer: Option<Extension<VerifiedSigner>>,
+    request: Request,
+) -> StatusCode {
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let Ok(activity) = serde_json::from_slice(&body) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    let status = {
+        let mut failing = state
+            .failing_deliveries
+            .lock()
+            .expect("failing deliveries poisoned");
+        match failing.as_mut() {
+            Some(failing) if failing.remaining > 0 => {
+                failing.remaining -= 1;
+                failing.status
+            }
+            _ => StatusCode::ACCEPTED,
+        }
+    };
+
+    state
+        .deliveries
+        .lock()
+        .expect("deliveries poisoned")
+        .push(Delivery {
+            path: parts.uri.path().to_string(),
+            headers: parts.headers,
+            activity,
+            signer: signer.map(|Extension(signer)| signer),
+            status,
+        });
+    status
+}
+
+/// Looks up keys registered with the peer before fetching them
+struct PeerKeyFetcher {
+    client: reqwest::Client,
+    keys: Arc<Mutex<HashMap<String, ActorKey>>>,
+}
+
+impl KeyFetcher for PeerKeyFetcher {
+    fn fetch<'a>(&'a self, key_id: &'a str) -> BoxFuture<'a, Result<ActorKey, SignatureError>> {
+        Box::pin(async move {
+            let known = self
+                .keys
+                .lock()
+                .expect("keys poisoned")
+                .get(key_id)
+                .cloned();
+            if let Some(key) = known {
+                return Ok(key);
+            }
+
+            let mut url = url::Url::parse(key_id)
+                .map_err(|e| SignatureError::KeyNotFound(format!("{}: {}", key_id, e)))?;
+            url.set_fragment(None);
+            let document: Value = self
+                .client
+                .get(url)
+                .header(header::ACCEPT, ACTIVITY_JSON)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| SignatureError::RequestError(e.to_string()))?
+                .json()
+                .await
+                .map_err(|e| SignatureError::RequestError(e.to_string()))?;
+            key_from_document(&document, key_id)
+        })
+    }
+}
+
+/// DER body of a PEM document
+fn pem_to_der(pem: &str) -> Result<Vec<u8>, MockPeerError> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .map(str::trim)
+        .collect();
+    Ok(BASE64.decode(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_actors_and_webfinger() {
+        let peer = MockPeer::start().await.unwrap();
+        let client = reqwest::Client::new();
+
+        let actor: Value = client
+            .get(peer.actor_id("carol"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(actor["id"], peer.actor_id("carol"));
+        assert_eq!(actor["inbox"], peer.inbox("carol"));
+        assert_eq!(actor["endpoints"]["sharedInbox"], peer.shared_inbox());
+        assert!(actor["publicKey"]["publicKeyPem"].is_string());
+
+        let webfinger: Value = client
+            .get(format!("{}/.well-known/webfinger", peer.base_url()))
+            .query(&[("resource", format!("acct:carol@{}", peer.host()))])
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(webfinger["links"][0]["href"], peer.actor_id("carol"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_collections_and_failures() {
+        let peer = MockPeer::start().await.unwrap();
+        let client = reqwest::Client::new();
+        peer.add_collection("/users/carol/followers", vec![json!(peer.actor_id("dave"))]);
+
+        let url = format!("{}/users/carol/followers", peer.base_url());
+        let collection: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(collection["type"], "OrderedCollection");
+        assert_eq!(collection["totalItems"], 1);
+
+        peer.fail_path("/users/carol/followers", StatusCode::GONE);
+        assert_eq!(
+            client.get(&url).send().await.unwrap().status(),
+            StatusCode::GONE
+        );
+        peer.clear_failure("/users/carol/followers");
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+
+        let missing = format!("{}/users/carol/outbox", peer.base_url());
+        assert_eq!(
+            client.get(&missing).send().await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_records_verified_deliveries() {
+        let peer = MockPeer::start().await.unwrap();
+        let follow = json!({
+            "type": "Follow",
+            "actor": peer.actor_id("carol"),
+            "object": peer.actor_id("dave")
+        });
+
+        peer.fail_deliveries(1, StatusCode::SERVICE_UNAVAILABLE);
+        let inbox = peer.inbox("dave");
+        assert_eq!(
+            peer.send("carol", &inbox, &follow).await.unwrap(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            peer.send("carol", &inbox, &follow).await.unwrap(),
+            StatusCode::ACCEPTED
+        );
+
+        let deliveries = peer.deliveries();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].status, StatusCode::SERVICE_UNAVAILABLE);
+        let delivery = peer
+            .wait_for_delivery("the Follow", |d| {
+                d.activity_type() == Some("Follow") && d.status.is_success()
+            })
+            .await
+            .unwrap();
+        assert_eq!(delivery.path, "/users/dave/inbox");
+        let signer = delivery.signer.expect("signature did not verify");
+        assert_eq!(signer.owner, peer.actor_id("carol"));
+    }
+
+    #[tokio::test]
+    async fn test_requires_signatures() {
+        let peer = MockPeer::start_with(MockPeerConfig {
+            require_signatures: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let response = reqwest::Client::new()
+            .post(peer.shared_inbox())
+            .json(&json!({ "type": "Follow" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(peer.deliveries().is_empty());
+    }
+}
@@ -3,13 +3,29 @@
 //! This module provides functionality for discovering information about entities
 //! identified by URIs using the WebFinger protocol as specified in RFC 7565:
 //! https://datatracker.ietf.org/doc/html/rfc7565
+//!
+//! Results are cached by the client and its clones, and resources the
+//! server does not know are remembered briefly, so resolving the same
+//! account repeatedly does not query its server every time.
 
-use reqwest::Client;
+use crate::egress::{EgressError, EgressPolicy};
+use moka::sync::Cache;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 use thiserror::Error;
 use url::Url;
 
+/// How long WebFinger results are cached
+const RESULT_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// How long resources unknown to their server are remembered as unknown
+const NOT_FOUND_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Most results and unknown resources cached
+const CACHE_SIZE: u64 = 10_000;
+
 /// Error types specific to WebFinger operations
 #[derive(Debug, Error)]
 pub enum WebFingerError {
@@ -30,6 +46,9 @@ pub enum WebFingerError {
 
     #[error("Host extraction failed for resource: {0}")]
     HostExtractionFailed(String),
+
+    #[error("Refused by egress policy: {0}")]
+    Egress(#[from] EgressError),
 }
 
 /// Result type for WebFinger operations
@@ -100,19 +119,43 @@ impl JrdResource {
 #[derive(Debug, Clone)]
 pub struct WebFingerClient {
     client: Client,
+    /// Hosts lookups may reach
+    egress: EgressPolicy,
+    /// Results by resource and relations
+    results: Cache<String, JrdResource>,
+    /// Statuses of resources their server answered 404 or 410 for
+    not_found: Cache<String, StatusCode>,
 }
 
 impl WebFingerClient {
-    /// Create a new WebFinger client
+    /// Create a new WebFinger client following the installed egress policy
+    ///
+    /// # Panics
+    /// Like [`Client::new`], if the HTTP client cannot be initialized.
     pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+        let client = EgressPolicy::installed()
+            .configure(Client::builder())
+            .and_then(|builder| builder.build())
+            .expect("Failed to initialize the WebFinger HTTP client");
+        Self::with_client(client)
     }
 
     /// Create a new WebFinger client with a custom HTTP client
+    ///
+    /// Lookup URLs are still checked against the installed egress policy.
     pub fn with_client(client: Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            egress: EgressPolicy::installed(),
+            results: Cache::builder()
+                .max_capacity(CACHE_SIZE)
+                .time_to_live(RESULT_CACHE_TTL)
+                .build(),
+            not_found: Cache::builder()
+                .max_capacity(CACHE_SIZE)
+                .time_to_live(NOT_FOUND_CACHE_TTL)
+                .build(),
+        }
     }
 
     /// Perform a WebFinger lookup for a resource
@@ -123,7 +166,8 @@ impl WebFingerClient {
     /// * `rel` - Optional relation types to filter the result links
     ///
     /// # Returns
-    /// A JRD Resource containing information about the requested resource
+    /// A JRD Resource containing information about the requested resource,
+    /// possibly cached
     pub async fn finger(&self, resource: &str, rel: Option<&[&str]>) -> Result<JrdResource> {
         // Validate the resource URI according to RFC 7565
         if !resource.starts_with("acct:")
@@ -136,25 +180,40 @@ impl WebFingerClient {
             )));
         }
 
+        // Answer from the cache when the resource was resolved recently
+        let cache_key = match rel {
+            Some(rel_values) => format!("{} {}", resource, rel_values.join(" ")),
+            None => resource.to_string(),
+        };
+        if let Some(jrd) = self.results.get(&cache_key) {
+            return Ok(jrd);
+        }
+        if let Some(status) = self.not_found.get(&cache_key) {
+            return Err(WebFingerError::HttpError(status));
+        }
+
         // Extract host from the resource URI
         let host = self.extract_host(resource)?;
 
         // Construct the WebFinger URL
         let mut webfinger_url = Url::parse(&format!("https://{}/.well-known/webfinger", host))?;
 
-        // Add query parameters
-        let mut query_pairs = webfinger_url.query_pairs_mut();
-        query_pairs.append_pair("resource", resource);
+        // Add query parameters; scoped so the serializer, which is not
+        // `Send`, does not live across the request
+        {
+            let mut query_pairs = webfinger_url.query_pairs_mut();
+            query_pairs.append_pair("resource", resource);
 
-        // Add optional rel parameter(s)
-        if let Some(rel_values) = rel {
-            for r in rel_values {
-                query_pairs.append_pair("rel", r);
+            // Add optional rel parameter(s)
+            if let Some(rel_values) = rel {
+                for r in rel_values {
+                    query_pairs.append_pair("rel", r);
+                }
             }
         }
-        drop(query_pairs);
 
         // Make the request
+        self.egress.check_url(&webfinger_url)?;
         let response = self
             .client
             .get(webfinger_url)
@@ -162,12 +221,18 @@ impl WebFingerClient {
             .send()
             .await?;
 
-        // Check for success and parse response
-        if !response.status().is_success() {
-            return Err(WebFingerError::HttpError(response.status()));
+        // Check for success and parse response; unknown resources are
+        // remembered, other failures may be transient
+        let status = response.status();
+        if !status.is_success() {
+            if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+                self.not_found.insert(cache_key, status);
+            }
+            return Err(WebFingerError::HttpError(status));
         }
 
         let jrd = response.json::<JrdResource>().await?;
+        self.results.insert(cache_key, jrd.clone());
 
         Ok(jrd)
     }
@@ -342,4 +407,41 @@ mod tests {
 
         m.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn test_finger_answers_from_cache() {
+        let client = WebFingerClient::new();
+        let jrd = JrdResource {
+            subject: Some("acct:user@example.invalid".to_string()),
+            aliases: None,
+            properties: None,
+            links: None,
+        };
+        client
+            .results
+            .insert("acct:user@example.invalid".to_string(), jrd);
+        client
+            .not_found
+            .insert("acct:gone@example.invalid".to_string(), StatusCode::GONE);
+
+        // Neither lookup reaches the unresolvable host
+        let cached = client
+            .finger("acct:user@example.invalid", None)
+            .await
+            .unwrap();
+        assert_eq!(cached.subject.unwrap(), "acct:user@example.invalid");
+        assert!(matches!(
+            client.finger("acct:gone@example.invalid", None).await,
+            Err(WebFingerError::HttpError(StatusCode::GONE))
+        ));
+
+        // Clones share the cache
+        assert!(
+            client
+                .clone()
+                .finger("acct:user@example.invalid", None)
+                .await
+                .is_ok()
+        );
+    }
 }
@@ -115,6 +115,7 @@ pub struct TrustChainLinkResponse {
     pub self_signed: bool,
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
+    pub revoked: bool,
 }
 
 /// Node info 2.0 response
@@ -328,6 +329,7 @@ async fn get_trust_chain(
             signed_at: link.signed_at,
             self_signed: link.self_signed,
             created_at: link.created_at,
+            revoked: link.revoked,
         })
         .collect();
 
@@ -0,0 +1,114 @@
+//! Deletion of local actors
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::{Document, doc};
+use oxifed::database::{FollowDocument, FollowStatus};
+
+const ALICE: &str = "https://local.example/users/alice";
+
+fn follow(follower: &str, following: &str) -> FollowDocument {
+    FollowDocument {
+        id: None,
+        follower: follower.to_string(),
+        following: following.to_string(),
+        status: FollowStatus::Accepted,
+        activity_id: format!("{}#follow", follower),
+        accept_activity_id: None,
+        created_at: Utc::now(),
+        responded_at: None,
+        follower_inbox: None,
+        follower_shared_inbox: None,
+    }
+}
+
+#[tokio::test]
+async fn test_tombstone_actor() {
+    let Some((database, db)) = common::setup_raw_test_db().await else {
+        return;
+    };
+    let raw = |name: &str| database.collection::<Document>(name);
+
+    raw("actors")
+        .insert_many([
+            doc! { "actor_id": ALICE, "domain": "local.example", "local": true,
+            "status": "active", "summary": "Hi" },
+            doc! { "actor_id": "https://a.example/users/x", "domain": "a.example", "local": false },
+            doc! { "actor_id": "https://a.example/users/y", "domain": "a.example", "local": false },
+            doc! { "actor_id": "https://b.example/users/z", "domain": "b.example", "local": false },
+        ])
+        .await
+        .unwrap();
+    db.insert_follow(follow("https://c.example/users/bob", ALICE))
+        .await
+        .unwrap();
+    db.insert_follow(follow(ALICE, "https://a.example/users/y"))
+        .await
+        .unwrap();
+
+    let mut peers = db.find_peer_actors(ALICE).await.unwrap();
+    peers.sort();
+    let hosts: Vec<_> = peers
+        .iter()
+        .map(|id| url::Url::parse(id).unwrap().host_str().unwrap().to_string())
+        .collect();
+    assert_eq!(hosts, ["a.example", "b.example", "c.example"]);
+
+    raw("objects")
+        .insert_many([
+            doc! { "object_id": format!("{}/notes/1", ALICE), "attributed_to": ALICE,
+            "object_type": "Note", "content": "first" },
+            doc! { "object_id": format!("{}/notes/2", ALICE), "attributed_to": ALICE,
+            "object_type": "Tombstone" },
+        ])
+        .await
+        .unwrap();
+    raw("keys")
+        .insert_many([
+            doc! { "key_id": format!("{}#main-key", ALICE), "actor_id": ALICE, "status": "active" },
+            doc! { "key_id": format!("{}#old-key", ALICE), "actor_id": ALICE, "status": "revoked" },
+        ])
+        .await
+        .unwrap();
+
+    let now = Utc::now();
+    let tombstoned = db
+        .tombstone_actor(ALICE, now, now + Duration::days(7))
+        .await
+        .unwrap();
+    assert_eq!(tombstoned, 1);
+
+    let actor = raw("actors")
+        .find_one(doc! { "actor_id": ALICE })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(actor.get_str("status").unwrap(), "deleted");
+    assert!(actor.get("summary").unwrap().as_null().is_some());
+
+    let note = raw("objects")
+        .find_one(doc! { "object_id": format!("{}/notes/1", ALICE) })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.get_str("object_type").unwrap(), "Tombstone");
+    assert_eq!(
+        note.get_document("additional_properties")
+            .unwrap()
+            .get_str("formerType")
+            .unwrap(),
+        "Note"
+    );
+
+    assert_eq!(raw("follows").count_documents(doc! {}).await.unwrap(), 0);
+    let expiring = raw("keys")
+        .count_documents(doc! { "expires_at": { "$exists": true } })
+        .await
+        .unwrap();
+    assert_eq!(expiring, 1);
+
+    database.drop().await.unwrap();
+}
@@ -0,0 +1,87 @@
+//! Moderation states of actors
+//!
+//! The timeline test needs MongoDB at `TEST_MONGODB_URI` and is skipped
+//! without it.
+
+mod common;
+
+use oxifed::ObjectType;
+use oxifed::database::{ActorDocument, ActorRestriction, ObjectDocument};
+use oxifed::messaging::{Message, MessageEnum, ModerationState, ProfileModerateMessage};
+use serde_json::json;
+
+#[test]
+fn test_moderate_message_serialization() {
+    let message = ProfileModerateMessage::new(
+        "https://example.com/users/alice".to_string(),
+        "limited".parse().unwrap(),
+    );
+
+    let json = serde_json::to_value(message.to_message()).unwrap();
+    assert_eq!(json["ProfileModerateMessage"]["state"], "limited");
+    match serde_json::from_value::<MessageEnum>(json).unwrap() {
+        MessageEnum::ProfileModerateMessage(msg) => {
+            assert_eq!(msg.actor, "https://example.com/users/alice");
+            assert_eq!(msg.state, ModerationState::Limited);
+        }
+        _ => panic!("Expected ProfileModerateMessage"),
+    }
+    assert!("banned".parse::<ModerationState>().is_err());
+}
+
+#[test]
+fn test_restriction_defaults_to_none() {
+    let actor = ActorDocument::from_activitypub(&json!({
+        "id": "https://example.com/users/alice",
+        "type": "Person",
+        "preferredUsername": "alice",
+        "inbox": "https://example.com/users/alice/inbox"
+    }))
+    .unwrap();
+    let mut stored = mongodb::bson::to_document(&actor).unwrap();
+    stored.remove("restriction");
+
+    let actor: ActorDocument = mongodb::bson::from_document(stored).unwrap();
+    assert_eq!(actor.restriction, ActorRestriction::None);
+}
+
+#[tokio::test]
+async fn test_restricted_actors_left_off_timelines() {
+    let Some((database, db)) = common::setup_raw_test_db().await else {
+        return;
+    };
+
+    for (name, restriction) in [
+        ("alice", ActorRestriction::None),
+        ("bob", ActorRestriction::Silenced),
+        ("carol", ActorRestriction::Limited),
+    ] {
+        let id = format!("https://example.com/users/{}", name);
+        let mut actor = ActorDocument::from_activitypub(&json!({
+            "id": id,
+            "type": "Person",
+            "preferredUsername": name,
+            "inbox": format!("{}/inbox", id)
+        }))
+        .unwrap();
+        actor.restriction = restriction;
+        db.insert_actor(actor).await.unwrap();
+
+        let note = ObjectDocument::from_activitypub(
+            &json!({
+                "id": format!("{}/notes/1", id),
+                "attributedTo": id,
+                "content": "Hello",
+                "to": ["https://www.w3.org/ns/activitystreams#Public"]
+            }),
+            ObjectType::Note,
+        );
+        db.insert_object(note).await.unwrap();
+    }
+
+    let timeline = db.get_public_timeline(&[], 20, 0).await.unwrap();
+    let authors: Vec<_> = timeline.iter().map(|o| o.attributed_to.as_str()).collect();
+    assert_eq!(authors, ["https://example.com/users/alice"]);
+
+    database.drop().await.unwrap();
+}
@@ -0,0 +1,104 @@
+//! Batched inserts of activities and objects
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use std::sync::Arc;
+
+use oxifed::ObjectType;
+use oxifed::database::{ActivityDocument, ObjectDocument, WriteBatchConfig, WriteBatcher};
+use serde_json::json;
+
+fn activity(id: &str) -> ActivityDocument {
+    ActivityDocument::from_activitypub(&json!({
+        "id": id,
+        "type": "Create",
+        "actor": "https://remote.example/users/alice",
+        "object": "https://remote.example/notes/1",
+    }))
+}
+
+fn note(id: &str) -> ObjectDocument {
+    ObjectDocument::from_activitypub(
+        &json!({
+            "id": id,
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/alice",
+            "content": "Hello",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        }),
+        ObjectType::Note,
+    )
+}
+
+#[tokio::test]
+async fn test_batch_insert_skips_stored_documents() {
+    let Some(db) = common::setup_test_db().await.map(Arc::new) else {
+        return;
+    };
+
+    let first = db
+        .insert_activities(vec![
+            activity("https://remote.example/activities/1"),
+            activity("https://remote.example/activities/2"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(first.inserted, 2);
+
+    let second = db
+        .insert_activities(vec![
+            activity("https://remote.example/activities/2"),
+            activity("https://remote.example/activities/3"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(second.inserted, 1);
+    assert_eq!(second.duplicates, vec![0]);
+    assert!(second.rejected.is_empty());
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_batcher_reports_each_write() {
+    let Some(db) = common::setup_test_db().await.map(Arc::new) else {
+        return;
+    };
+    let batcher = WriteBatcher::spawn(
+        db.clone(),
+        WriteBatchConfig {
+            max_batch_size: 8,
+            flush_interval_ms: 50,
+        },
+    );
+
+    let writes = (0..20).map(|i| {
+        let batcher = batcher.clone();
+        tokio::spawn(async move {
+            batcher
+                .write(note(&format!("https://remote.example/notes/{}", i % 10)))
+                .await
+                .unwrap()
+        })
+    });
+    let mut stored = 0;
+    for write in writes.collect::<Vec<_>>() {
+        if write.await.unwrap() {
+            stored += 1;
+        }
+    }
+    // Every note was written once; the second copy of each is a duplicate
+    assert_eq!(stored, 10);
+    for i in 0..10 {
+        assert!(
+            db.find_object_by_id(&format!("https://remote.example/notes/{}", i))
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    db.database.drop().await.unwrap();
+}
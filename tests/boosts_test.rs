@@ -0,0 +1,149 @@
+//! Boosts and the home timeline
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::Utc;
+use oxifed::ObjectType;
+use oxifed::database::{
+    DatabaseManager, FollowDocument, FollowStatus, ObjectDocument, TimelineItem, TimelinePage,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+const OWNER: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const NOTE: &str = "https://other.example/notes/1";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Alice follows Bob, and a post Bob did not write is stored
+async fn setup_boosted_post(db: &DatabaseManager) {
+    db.insert_follow(FollowDocument {
+        id: None,
+        follower: OWNER.to_string(),
+        following: BOB.to_string(),
+        status: FollowStatus::Accepted,
+        activity_id: format!("{}/follows/{}", OWNER, Uuid::new_v4()),
+        accept_activity_id: None,
+        created_at: Utc::now(),
+        responded_at: None,
+        follower_inbox: None,
+        follower_shared_inbox: None,
+    })
+    .await
+    .unwrap();
+    let note = ObjectDocument::from_activitypub(
+        &json!({
+            "id": NOTE,
+            "attributedTo": "https://other.example/users/carol",
+            "content": "Hello",
+            "to": [PUBLIC]
+        }),
+        ObjectType::Note,
+    );
+    db.insert_object(note).await.unwrap();
+}
+
+fn announce(id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "type": "Announce",
+        "actor": BOB,
+        "object": NOTE,
+        "to": [PUBLIC],
+        "cc": ["https://remote.example/users/bob/followers"]
+    })
+}
+
+fn page() -> TimelinePage {
+    TimelinePage {
+        limit: 20,
+        ..TimelinePage::default()
+    }
+}
+
+async fn announce_count(db: &DatabaseManager) -> i64 {
+    let note = db.find_object_by_id(NOTE).await.unwrap().unwrap();
+    note.announce_count
+}
+
+#[tokio::test]
+async fn test_boost_reaches_home_timeline() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    setup_boosted_post(&db).await;
+
+    assert!(
+        db.apply_boost_activity(&announce("https://remote.example/activities/1"))
+            .await
+            .unwrap()
+    );
+    // A redelivered or repeated Announce is counted once
+    assert!(
+        !db.apply_boost_activity(&announce("https://remote.example/activities/2"))
+            .await
+            .unwrap()
+    );
+    assert_eq!(announce_count(&db).await, 1);
+
+    let timeline = db
+        .get_home_timeline("alice", "example.com", OWNER, &page())
+        .await
+        .unwrap();
+    assert_eq!(timeline.len(), 1);
+    match &timeline[0] {
+        TimelineItem::Boost { boost, post } => {
+            assert_eq!(boost.actor, BOB);
+            assert_eq!(post.object_id, NOTE);
+        }
+        TimelineItem::Post(_) => panic!("expected a boost"),
+    }
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_undo_announce_withdraws_boost() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    setup_boosted_post(&db).await;
+    let announce_id = "https://remote.example/activities/1";
+    db.apply_boost_activity(&announce(announce_id))
+        .await
+        .unwrap();
+
+    // Only the booster can undo the boost
+    let undo = |actor: &str| {
+        json!({
+            "id": format!("{}/undo", announce_id),
+            "type": "Undo",
+            "actor": actor,
+            "object": announce_id
+        })
+    };
+    assert!(
+        !db.apply_boost_activity(&undo("https://remote.example/users/mallory"))
+            .await
+            .unwrap()
+    );
+    assert_eq!(announce_count(&db).await, 1);
+
+    assert!(db.apply_boost_activity(&undo(BOB)).await.unwrap());
+    assert_eq!(announce_count(&db).await, 0);
+    assert!(
+        db.find_boost_by_activity(announce_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let timeline = db
+        .get_home_timeline("alice", "example.com", OWNER, &page())
+        .await
+        .unwrap();
+    assert!(timeline.is_empty());
+
+    db.database.drop().await.unwrap();
+}
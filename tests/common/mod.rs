@@ -0,0 +1,44 @@
+//! Setup shared by the tests needing MongoDB
+//!
+//! Each test gets a database of its own on the server at `TEST_MONGODB_URI`;
+//! without a server the helpers return `None` and the test is skipped.
+
+use mongodb::Database;
+use mongodb::bson::doc;
+use oxifed::database::DatabaseManager;
+use uuid::Uuid;
+
+/// A fresh test database, or `None` without MongoDB
+pub async fn test_database() -> Option<Database> {
+    let mongo_uri = std::env::var("TEST_MONGODB_URI")
+        .unwrap_or_else(|_| "mongodb://localhost:27017/?serverSelectionTimeoutMS=2000".to_string());
+    let client = match mongodb::Client::with_uri_str(&mongo_uri).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Skipping test - MongoDB not available: {}", e);
+            return None;
+        }
+    };
+    let db = client.database(&format!("test_oxifed_{}", Uuid::new_v4()));
+    if let Err(e) = db.run_command(doc! { "ping": 1 }).await {
+        eprintln!("Skipping test - MongoDB not available: {}", e);
+        return None;
+    }
+    Some(db)
+}
+
+/// A fresh, initialized test database, or `None` without MongoDB
+#[allow(dead_code)]
+pub async fn setup_test_db() -> Option<DatabaseManager> {
+    let db = DatabaseManager::new(test_database().await?);
+    db.initialize().await.unwrap();
+    Some(db)
+}
+
+/// A fresh test database without indexes and its manager, for tests that
+/// also read collections directly, or `None` without MongoDB
+#[allow(dead_code)]
+pub async fn setup_raw_test_db() -> Option<(Database, DatabaseManager)> {
+    let db = test_database().await?;
+    Some((db.clone(), DatabaseManager::new(db)))
+}
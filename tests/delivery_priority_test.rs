@@ -0,0 +1,59 @@
+//! Tests for outgoing delivery priority classification
+
+use oxifed::messaging::{DeliveryPriority, ROUTING_KEY_DELIVERY_HIGH, ROUTING_KEY_DELIVERY_LOW};
+use serde_json::json;
+
+#[test]
+fn test_interactions_are_high_priority() {
+    for activity_type in ["Follow", "Accept", "Reject", "Undo", "Block"] {
+        let activity = json!({
+            "type": activity_type,
+            "actor": "https://example.com/users/alice",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        });
+        assert_eq!(
+            DeliveryPriority::for_activity(&activity),
+            DeliveryPriority::High,
+            "{} should be high priority",
+            activity_type
+        );
+    }
+}
+
+#[test]
+fn test_direct_message_is_high_priority() {
+    let activity = json!({
+        "type": "Create",
+        "to": ["https://remote.example/users/bob"],
+        "cc": "https://other.example/users/carol",
+    });
+    assert_eq!(
+        DeliveryPriority::for_activity(&activity),
+        DeliveryPriority::High
+    );
+    assert_eq!(
+        DeliveryPriority::for_activity(&activity).routing_key(),
+        ROUTING_KEY_DELIVERY_HIGH
+    );
+}
+
+#[test]
+fn test_fanout_is_low_priority() {
+    let public = json!({
+        "type": "Create",
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": ["https://example.com/users/alice/followers"],
+    });
+    let followers_only = json!({
+        "type": "Create",
+        "to": ["https://example.com/users/alice/followers"],
+    });
+    let unaddressed = json!({ "type": "Announce" });
+
+    for activity in [public, followers_only, unaddressed] {
+        assert_eq!(
+            DeliveryPriority::for_activity(&activity).routing_key(),
+            ROUTING_KEY_DELIVERY_LOW
+        );
+    }
+}
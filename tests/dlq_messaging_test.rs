@@ -0,0 +1,69 @@
+//! Tests for dead-letter RPC message serialization
+
+use oxifed::messaging::{
+    DeadLetterInfo, DlqRpcRequest, DlqRpcRequestType, DlqRpcResponse, DlqRpcResult, FailureClass,
+    Message, MessageEnum,
+};
+
+#[test]
+fn test_retry_all_request_serialization() {
+    let request = DlqRpcRequest::retry_dead_letters("req-1".to_string(), None);
+
+    let json = serde_json::to_string(&request.to_message()).unwrap();
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::DlqRpcRequest(rpc_req) = deserialized {
+        assert_eq!(rpc_req.request_id, "req-1");
+        match rpc_req.request_type {
+            DlqRpcRequestType::RetryDeadLetters { dead_letter_id } => {
+                assert_eq!(dead_letter_id, None);
+            }
+            _ => panic!("Expected RetryDeadLetters request type"),
+        }
+    } else {
+        panic!("Expected DlqRpcRequest");
+    }
+}
+
+#[test]
+fn test_dead_letter_list_response_serialization() {
+    let item = DeadLetterInfo {
+        dead_letter_id: "dl-1".to_string(),
+        message_id: Some("envelope-1".to_string()),
+        queue: "oxifed.incoming.storage".to_string(),
+        reason: "rejected".to_string(),
+        stage: Some("storage".to_string()),
+        error: Some("MongoDB error: connection refused".to_string()),
+        failure_class: FailureClass::Transient,
+        attempts: 1,
+        status: "pending".to_string(),
+        next_retry_at: Some("2024-01-01T00:02:00+00:00".to_string()),
+        created_at: "2024-01-01T00:00:00+00:00".to_string(),
+        retried_at: None,
+    };
+    let response = DlqRpcResponse::dead_letter_list("req-2".to_string(), vec![item]);
+
+    let json = serde_json::to_string(&response.to_message()).unwrap();
+    assert!(json.contains("\"failure_class\":\"transient\""));
+
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::DlqRpcResponse(rpc_resp) = deserialized {
+        match rpc_resp.result {
+            DlqRpcResult::DeadLetterList { items } => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].stage.as_deref(), Some("storage"));
+                assert_eq!(items[0].failure_class, FailureClass::Transient);
+            }
+            _ => panic!("Expected DeadLetterList result"),
+        }
+    } else {
+        panic!("Expected DlqRpcResponse");
+    }
+}
+
+#[test]
+fn test_failure_class_header_values() {
+    for class in [FailureClass::Transient, FailureClass::Permanent] {
+        assert_eq!(FailureClass::parse(class.as_str()), Some(class));
+    }
+    assert_eq!(FailureClass::parse("unknown"), None);
+}
@@ -5,6 +5,7 @@
 
 use oxifed::messaging::{
     DomainCreateMessage, DomainDeleteMessage, DomainUpdateMessage, Message, MessageEnum,
+    RelaySubscribeMessage, RelayUnsubscribeMessage,
 };
 
 #[test]
@@ -211,6 +212,27 @@ fn test_domain_delete_message_serialization() {
     }
 }
 
+#[test]
+fn test_relay_message_serialization() {
+    let relay = "https://relay.example/actor".to_string();
+    let subscribe = RelaySubscribeMessage::new("example.com".to_string(), relay.clone());
+    let json = serde_json::to_string(&subscribe.to_message()).unwrap();
+    match serde_json::from_str(&json).unwrap() {
+        MessageEnum::RelaySubscribeMessage(msg) => {
+            assert_eq!(msg.domain, "example.com");
+            assert_eq!(msg.relay, relay);
+        }
+        _ => panic!("Expected RelaySubscribeMessage"),
+    }
+
+    let unsubscribe = RelayUnsubscribeMessage::new("example.com".to_string(), relay.clone());
+    let json = serde_json::to_string(&unsubscribe.to_message()).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        MessageEnum::RelayUnsubscribeMessage(msg) if msg.relay == relay
+    ));
+}
+
 #[test]
 fn test_domain_create_message_minimal() {
     // Test with minimal required fields
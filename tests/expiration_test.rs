@@ -0,0 +1,125 @@
+//! Selection and tombstoning of expired posts
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use oxifed::ObjectType;
+use oxifed::database::{DatabaseManager, ObjectDocument, ObjectStatus, VisibilityLevel};
+use serde_json::json;
+
+const ALICE: &str = "https://example.com/users/alice";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Store a local note of Alice's published `days` days ago
+async fn store_note(db: &DatabaseManager, name: &str, days: i64, to: &str) -> ObjectDocument {
+    let mut note = ObjectDocument::from_activitypub(
+        &json!({
+            "id": format!("https://example.com/notes/{}", name),
+            "attributedTo": ALICE,
+            "content": name,
+            "to": [to],
+            "published": (Utc::now() - Duration::days(days)).to_rfc3339()
+        }),
+        ObjectType::Note,
+    );
+    note.local = true;
+    db.insert_object(note.clone()).await.unwrap();
+    note
+}
+
+async fn expired(
+    db: &DatabaseManager,
+    visibility: &[VisibilityLevel],
+    keep_pinned: bool,
+) -> Vec<String> {
+    db.find_expired_objects(
+        ALICE,
+        Utc::now() - Duration::days(30),
+        visibility,
+        keep_pinned,
+        100,
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|object| object.object_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn test_expired_posts_are_selected_by_age_and_visibility() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let old = store_note(&db, "old", 60, PUBLIC).await;
+    store_note(&db, "recent", 1, PUBLIC).await;
+    let private = store_note(
+        &db,
+        "private",
+        90,
+        "https://example.com/users/alice/followers",
+    )
+    .await;
+
+    // Oldest first, and only the levels the policy names
+    assert_eq!(
+        expired(&db, &[], true).await,
+        vec![private.object_id.clone(), old.object_id.clone()]
+    );
+    assert_eq!(
+        expired(&db, &[VisibilityLevel::Public], true).await,
+        vec![old.object_id.clone()]
+    );
+    assert!(
+        expired(&db, &[VisibilityLevel::Direct], true)
+            .await
+            .is_empty()
+    );
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_pinned_and_scheduled_posts_are_kept() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let pinned = store_note(&db, "pinned", 60, PUBLIC).await;
+    db.update_object(&pinned.object_id, mongodb::bson::doc! { "featured": true })
+        .await
+        .unwrap();
+    let scheduled = store_note(&db, "scheduled", 60, PUBLIC).await;
+    db.update_object(
+        &scheduled.object_id,
+        mongodb::bson::doc! { "status": mongodb::bson::to_bson(&ObjectStatus::Scheduled).unwrap() },
+    )
+    .await
+    .unwrap();
+
+    assert!(expired(&db, &[], true).await.is_empty());
+    // Pinned posts go too when the policy does not keep them
+    assert_eq!(expired(&db, &[], false).await, vec![pinned.object_id]);
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tombstoning_happens_once() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let old = store_note(&db, "old", 60, PUBLIC).await;
+
+    assert!(db.tombstone_object(&old, Utc::now()).await.unwrap());
+    let tombstone = db.find_object_by_id(&old.object_id).await.unwrap().unwrap();
+    assert_eq!(tombstone.object_type, ObjectType::Tombstone);
+    assert!(tombstone.content.is_none());
+
+    // Tombstones are not selected again, and a second tombstoning is refused
+    assert!(expired(&db, &[], true).await.is_empty());
+    assert!(!db.tombstone_object(&old, Utc::now()).await.unwrap());
+
+    db.database.drop().await.unwrap();
+}
@@ -220,6 +220,7 @@ impl FederationNetwork {
             target_username: None, // In a real implementation, this would be extracted from the activity
             received_at: chrono::Utc::now().to_rfc3339(),
             source: Some(from_domain.to_string()),
+            replay: None,
         };
 
         // Send the activity to the target server
@@ -256,6 +257,7 @@ impl FederationNetwork {
             target_username: None, // In a real implementation, this would be extracted from the object
             received_at: chrono::Utc::now().to_rfc3339(),
             source: Some(from_domain.to_string()),
+            replay: None,
         };
 
         // Send the object to the target server
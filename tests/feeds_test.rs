@@ -0,0 +1,159 @@
+//! Followed hashtags and instances and the home timeline
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::Utc;
+use oxifed::ObjectType;
+use oxifed::database::{
+    DatabaseManager, FeedKind, FollowDocument, FollowStatus, ListDocument, ListRepliesPolicy,
+    ObjectDocument, TimelineItem, TimelinePage,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+const OWNER: &str = "https://example.com/users/alice";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+async fn follow(db: &DatabaseManager, following: &str) {
+    db.insert_follow(FollowDocument {
+        id: None,
+        follower: OWNER.to_string(),
+        following: following.to_string(),
+        status: FollowStatus::Accepted,
+        activity_id: format!("{}/follows/{}", OWNER, Uuid::new_v4()),
+        accept_activity_id: None,
+        created_at: Utc::now(),
+        responded_at: None,
+        follower_inbox: None,
+        follower_shared_inbox: None,
+    })
+    .await
+    .unwrap();
+}
+
+/// Store a remote post and add it to the followed feeds it matches
+async fn post(db: &DatabaseManager, id: &str, author: &str, hashtag: &str, to: &str) -> usize {
+    let note = ObjectDocument::from_activitypub(
+        &json!({
+            "id": id,
+            "attributedTo": author,
+            "content": "Hello",
+            "tag": [{ "type": "Hashtag", "name": hashtag }],
+            "to": [to]
+        }),
+        ObjectType::Note,
+    );
+    db.insert_object(note.clone()).await.unwrap();
+    db.add_to_followed_feeds(&note).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_followed_feeds_reach_home_timeline() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let bob = "https://remote.example/users/bob";
+    let carol = "https://other.example/users/carol";
+    let dave = "https://third.example/users/dave";
+    follow(&db, bob).await;
+
+    db.follow_feed("alice", "example.com", FeedKind::Hashtag, "rust")
+        .await
+        .unwrap();
+    // Following again keeps the first subscription
+    let first = db
+        .follow_feed("alice", "example.com", FeedKind::Hashtag, "rust")
+        .await
+        .unwrap();
+    db.follow_feed("alice", "example.com", FeedKind::Instance, "other.example")
+        .await
+        .unwrap();
+    let tags = db
+        .find_followed_feeds("alice", "example.com", FeedKind::Hashtag)
+        .await
+        .unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].id, first.id);
+
+    post(&db, "https://remote.example/notes/1", bob, "#Cats", PUBLIC).await;
+    assert_eq!(
+        post(&db, "https://third.example/notes/2", dave, "#Rust", PUBLIC).await,
+        1
+    );
+    assert_eq!(
+        post(&db, "https://other.example/notes/3", carol, "#Cats", PUBLIC).await,
+        1
+    );
+    // Only public posts are added
+    assert_eq!(
+        post(
+            &db,
+            "https://third.example/notes/4",
+            dave,
+            "#Rust",
+            "https://third.example/users/dave/followers"
+        )
+        .await,
+        0
+    );
+
+    let page = TimelinePage {
+        limit: 20,
+        ..TimelinePage::default()
+    };
+    let timeline_ids = |items: Vec<TimelineItem>| -> Vec<String> {
+        items
+            .iter()
+            .map(|item| item.post().object_id.clone())
+            .collect()
+    };
+    let timeline = db
+        .get_home_timeline("alice", "example.com", OWNER, &page)
+        .await
+        .unwrap();
+    assert_eq!(
+        timeline_ids(timeline),
+        vec![
+            "https://other.example/notes/3",
+            "https://third.example/notes/2",
+            "https://remote.example/notes/1"
+        ]
+    );
+
+    // Members of exclusive lists are kept off the home timeline
+    let now = Utc::now();
+    db.insert_list(ListDocument {
+        id: None,
+        username: "alice".to_string(),
+        domain: "example.com".to_string(),
+        title: "Bob".to_string(),
+        replies_policy: ListRepliesPolicy::List,
+        exclusive: true,
+        accounts: vec![bob.to_string()],
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .unwrap();
+    assert!(
+        db.unfollow_feed("alice", "example.com", FeedKind::Instance, "other.example")
+            .await
+            .unwrap()
+    );
+    let timeline = db
+        .get_home_timeline("alice", "example.com", OWNER, &page)
+        .await
+        .unwrap();
+    // Posts already added stay when the instance is unfollowed
+    assert_eq!(
+        timeline_ids(timeline),
+        vec![
+            "https://other.example/notes/3",
+            "https://third.example/notes/2"
+        ]
+    );
+
+    db.database.drop().await.unwrap();
+}
@@ -0,0 +1,155 @@
+//! Content filters of local users
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use oxifed::database::{
+    FilterAction, FilterContext, FilterDocument, FilterKeyword, FilterStatus, FilterUpdate,
+};
+
+fn filter(title: &str, context: Vec<FilterContext>) -> FilterDocument {
+    let now = Utc::now();
+    FilterDocument {
+        id: None,
+        username: "alice".to_string(),
+        domain: "example.com".to_string(),
+        title: title.to_string(),
+        context,
+        filter_action: FilterAction::Warn,
+        expires_at: None,
+        keywords: Vec::new(),
+        statuses: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+#[tokio::test]
+async fn test_active_filters_by_context_and_expiry() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    db.insert_filter(filter("home", vec![FilterContext::Home]))
+        .await
+        .unwrap();
+    db.insert_filter(filter("public", vec![FilterContext::Public]))
+        .await
+        .unwrap();
+    let expired = db
+        .insert_filter(FilterDocument {
+            expires_at: Some(Utc::now() - Duration::minutes(1)),
+            ..filter("expired", vec![FilterContext::Home])
+        })
+        .await
+        .unwrap();
+
+    let titles = |filters: Vec<FilterDocument>| -> Vec<String> {
+        filters.into_iter().map(|filter| filter.title).collect()
+    };
+    let active = db
+        .find_active_filters("alice", "example.com", FilterContext::Home)
+        .await
+        .unwrap();
+    assert_eq!(titles(active), vec!["home"]);
+    // Expired filters are still listed for their owner
+    assert_eq!(
+        db.find_filters("alice", "example.com").await.unwrap().len(),
+        3
+    );
+
+    // Removing the expiry makes the filter apply again
+    db.update_filter(
+        "alice",
+        "example.com",
+        expired.id.unwrap(),
+        FilterUpdate {
+            expires_at: Some(None),
+            ..FilterUpdate::default()
+        },
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let active = db
+        .find_active_filters("alice", "example.com", FilterContext::Home)
+        .await
+        .unwrap();
+    assert_eq!(titles(active), vec!["home", "expired"]);
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_filter_keywords_and_statuses() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let id = db
+        .insert_filter(filter("TV", vec![FilterContext::Home]))
+        .await
+        .unwrap()
+        .id
+        .unwrap();
+
+    let keyword = FilterKeyword {
+        id: ObjectId::new(),
+        keyword: "spoiler".to_string(),
+        whole_word: true,
+    };
+    db.add_filter_keyword("alice", "example.com", id, &keyword)
+        .await
+        .unwrap()
+        .unwrap();
+    let status = FilterStatus {
+        id: ObjectId::new(),
+        status_id: ObjectId::new(),
+    };
+    db.add_filter_status("alice", "example.com", id, &status)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let updated = db
+        .update_filter_keyword(
+            "alice",
+            "example.com",
+            &FilterKeyword {
+                whole_word: false,
+                ..keyword.clone()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!updated.keywords[0].whole_word);
+
+    // Entries are found only through the filters of their owner
+    assert!(
+        db.find_filter_by_entry("bob", "example.com", keyword.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        db.remove_filter_entry("alice", "example.com", keyword.id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        db.remove_filter_entry("alice", "example.com", status.id)
+            .await
+            .unwrap()
+    );
+    let filter = db
+        .find_filter("alice", "example.com", id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(filter.keywords.is_empty());
+    assert!(filter.statuses.is_empty());
+
+    db.database.drop().await.unwrap();
+}
@@ -0,0 +1,101 @@
+//! Accepted and rejected follow requests of local actors
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use mongodb::bson::{Document, doc};
+use oxifed::database::{ActivityDocument, ActorDocument, DatabaseManager, FollowStatus};
+use serde_json::json;
+use uuid::Uuid;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://other.example/users/carol";
+
+/// Store a Follow sent by Alice, as the message queue handler does
+async fn send_follow(db: &DatabaseManager, following: &str) {
+    let mut follow = ActivityDocument::from_activitypub(&json!({
+        "id": format!("https://example.com/activities/{}", Uuid::new_v4()),
+        "type": "Follow",
+        "actor": ALICE,
+        "object": following
+    }));
+    follow.local = true;
+    db.insert_activity(follow).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_answer_follow() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let mut alice = ActorDocument::from_activitypub(&json!({
+        "id": ALICE,
+        "type": "Person",
+        "preferredUsername": "alice",
+        "inbox": format!("{}/inbox", ALICE)
+    }))
+    .unwrap();
+    alice.local = true;
+    db.insert_actor(alice).await.unwrap();
+    send_follow(&db, BOB).await;
+    send_follow(&db, CAROL).await;
+
+    // Alice never asked to follow Bob's neighbour
+    assert!(
+        !db.answer_follow(
+            ALICE,
+            "https://remote.example/users/dave",
+            FollowStatus::Accepted,
+            None
+        )
+        .await
+        .unwrap()
+    );
+
+    assert!(
+        db.answer_follow(
+            ALICE,
+            BOB,
+            FollowStatus::Accepted,
+            Some("https://remote.example/activities/accept")
+        )
+        .await
+        .unwrap()
+    );
+    assert_eq!(db.get_actor_following(ALICE).await.unwrap(), vec![BOB]);
+    let follow = db.find_follow(ALICE, BOB).await.unwrap().unwrap();
+    assert_eq!(
+        follow.accept_activity_id.as_deref(),
+        Some("https://remote.example/activities/accept")
+    );
+    let alice = db.find_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.following_count, 1);
+
+    assert!(
+        db.answer_follow(ALICE, CAROL, FollowStatus::Rejected, None)
+            .await
+            .unwrap()
+    );
+    assert_eq!(db.get_actor_following(ALICE).await.unwrap(), vec![BOB]);
+    let notifications = db
+        .database
+        .collection::<Document>("notifications")
+        .count_documents(doc! { "notification_type": "follow_rejected", "account": CAROL })
+        .await
+        .unwrap();
+    assert_eq!(notifications, 1);
+
+    // Withdrawn requests stay withdrawn
+    db.update_follow_status(ALICE, BOB, FollowStatus::Cancelled)
+        .await
+        .unwrap();
+    assert!(
+        !db.answer_follow(ALICE, BOB, FollowStatus::Accepted, None)
+            .await
+            .unwrap()
+    );
+
+    db.database.drop().await.unwrap();
+}
@@ -0,0 +1,61 @@
+//! Tests for health RPC message serialization
+
+use oxifed::health::{ComponentHealth, HealthReport, HealthStatus, SystemHealth};
+use oxifed::messaging::{HealthRpcRequest, HealthRpcResponse, Message, MessageEnum};
+
+#[test]
+fn test_health_request_serialization() {
+    let request = HealthRpcRequest::new("req-1".to_string());
+
+    let json = serde_json::to_string(&request.to_message()).unwrap();
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::HealthRpcRequest(rpc_req) = deserialized {
+        assert_eq!(rpc_req.request_id, "req-1");
+    } else {
+        panic!("Expected HealthRpcRequest");
+    }
+}
+
+#[test]
+fn test_health_response_serialization() {
+    let report = HealthReport::new(
+        "storaged",
+        vec![
+            ComponentHealth::healthy("amqp"),
+            ComponentHealth::unhealthy("mongodb", "connection refused"),
+        ],
+    );
+    let response = HealthRpcResponse {
+        request_id: "req-2".to_string(),
+        report,
+    };
+
+    let json = serde_json::to_string(&response.to_message()).unwrap();
+    assert!(json.contains("\"status\":\"unhealthy\""));
+
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::HealthRpcResponse(rpc_resp) = deserialized {
+        assert_eq!(rpc_resp.request_id, "req-2");
+        assert_eq!(rpc_resp.report.service, "storaged");
+        assert_eq!(rpc_resp.report.status, HealthStatus::Unhealthy);
+        assert_eq!(rpc_resp.report.components.len(), 2);
+        assert_eq!(rpc_resp.report.components[0].detail, None);
+    } else {
+        panic!("Expected HealthRpcResponse");
+    }
+}
+
+#[test]
+fn test_system_health_is_worst_service() {
+    let healthy = HealthReport::new("storaged", vec![ComponentHealth::healthy("mongodb")]);
+    let degraded = HealthReport::new(
+        "domainservd",
+        vec![ComponentHealth::degraded("media_storage", "8% free")],
+    );
+
+    let health = SystemHealth::new(vec![healthy, degraded]);
+    assert_eq!(health.status, HealthStatus::Degraded);
+    assert_eq!(health.services[0].service, "domainservd");
+
+    assert_eq!(SystemHealth::new(Vec::new()).status, HealthStatus::Healthy);
+}
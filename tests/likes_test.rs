@@ -0,0 +1,73 @@
+//! Likes: duplicate detection and notification of local authors
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use mongodb::bson::{Document, doc};
+use oxifed::database::{ActivityDocument, ActorDocument, ObjectDocument};
+use oxifed::{ActivityType, ObjectType};
+use serde_json::json;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const NOTE: &str = "https://example.com/users/alice/notes/1";
+
+#[tokio::test]
+async fn test_like_notifies_local_author() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let mut alice = ActorDocument::from_activitypub(&json!({
+        "id": ALICE,
+        "type": "Person",
+        "preferredUsername": "alice",
+        "inbox": format!("{}/inbox", ALICE)
+    }))
+    .unwrap();
+    alice.local = true;
+    db.insert_actor(alice).await.unwrap();
+    let mut note = ObjectDocument::from_activitypub(
+        &json!({
+            "id": NOTE,
+            "attributedTo": ALICE,
+            "content": "Hello",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"]
+        }),
+        ObjectType::Note,
+    );
+    note.local = true;
+    db.insert_object(note.clone()).await.unwrap();
+
+    assert!(
+        !db.has_interaction(ActivityType::Like, BOB, NOTE)
+            .await
+            .unwrap()
+    );
+    db.insert_activity(ActivityDocument::from_activitypub(&json!({
+        "id": "https://remote.example/activities/1",
+        "type": "Like",
+        "actor": BOB,
+        "object": NOTE
+    })))
+    .await
+    .unwrap();
+    assert!(
+        db.has_interaction(ActivityType::Like, BOB, NOTE)
+            .await
+            .unwrap()
+    );
+
+    assert!(db.notify_favourite(BOB, &note).await.unwrap());
+    // Authors are not notified of their own likes
+    assert!(!db.notify_favourite(ALICE, &note).await.unwrap());
+    let notifications = db
+        .database
+        .collection::<Document>("notifications")
+        .count_documents(doc! { "notification_type": "favourite", "recipient": ALICE })
+        .await
+        .unwrap();
+    assert_eq!(notifications, 1);
+
+    db.database.drop().await.unwrap();
+}
@@ -0,0 +1,186 @@
+//! Lists of followed accounts and their timelines
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::Utc;
+use oxifed::ObjectType;
+use oxifed::database::{
+    DatabaseManager, FollowDocument, FollowStatus, ListDocument, ListRepliesPolicy, ObjectDocument,
+    TimelinePage,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+const OWNER: &str = "https://example.com/users/alice";
+
+async fn follow(db: &DatabaseManager, following: &str) {
+    db.insert_follow(FollowDocument {
+        id: None,
+        follower: OWNER.to_string(),
+        following: following.to_string(),
+        status: FollowStatus::Accepted,
+        activity_id: format!("{}/follows/{}", OWNER, Uuid::new_v4()),
+        accept_activity_id: None,
+        created_at: Utc::now(),
+        responded_at: None,
+        follower_inbox: None,
+        follower_shared_inbox: None,
+    })
+    .await
+    .unwrap();
+}
+
+async fn post(db: &DatabaseManager, id: &str, author: &str, in_reply_to: Option<&str>) {
+    let note = ObjectDocument::from_activitypub(
+        &json!({
+            "id": id,
+            "attributedTo": author,
+            "content": "Hello",
+            "inReplyTo": in_reply_to,
+            "to": ["https://www.w3.org/ns/activitystreams#Public"]
+        }),
+        ObjectType::Note,
+    );
+    db.insert_object(note).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_list_timeline_follows_replies_policy() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    let bob = "https://remote.example/users/bob";
+    let carol = "https://remote.example/users/carol";
+    let dave = "https://remote.example/users/dave";
+    follow(&db, bob).await;
+    follow(&db, carol).await;
+
+    let now = Utc::now();
+    let list = db
+        .insert_list(ListDocument {
+            id: None,
+            username: "alice".to_string(),
+            domain: "example.com".to_string(),
+            title: "Friends".to_string(),
+            replies_policy: ListRepliesPolicy::List,
+            exclusive: false,
+            accounts: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .unwrap();
+    let id = list.id.unwrap();
+    // Dave is not followed, so his posts stay out of the timeline
+    assert!(
+        db.add_list_accounts(
+            "alice",
+            "example.com",
+            id,
+            &[bob.to_string(), dave.to_string()]
+        )
+        .await
+        .unwrap()
+    );
+
+    post(&db, "https://remote.example/notes/1", bob, None).await;
+    post(&db, "https://remote.example/notes/2", carol, None).await;
+    post(
+        &db,
+        "https://remote.example/notes/3",
+        bob,
+        Some("https://remote.example/notes/1"),
+    )
+    .await;
+    post(
+        &db,
+        "https://remote.example/notes/4",
+        bob,
+        Some("https://remote.example/notes/2"),
+    )
+    .await;
+    post(&db, "https://remote.example/notes/5", dave, None).await;
+
+    let page = TimelinePage {
+        limit: 20,
+        ..TimelinePage::default()
+    };
+    let timeline_ids = |objects: Vec<ObjectDocument>| -> Vec<String> {
+        objects.into_iter().map(|object| object.object_id).collect()
+    };
+
+    let list = db
+        .find_list("alice", "example.com", id)
+        .await
+        .unwrap()
+        .unwrap();
+    let timeline = db.get_list_timeline(&list, OWNER, &page).await.unwrap();
+    // Bob's reply to Carol is left out; Carol is not in the list
+    assert_eq!(
+        timeline_ids(timeline),
+        vec![
+            "https://remote.example/notes/3",
+            "https://remote.example/notes/1"
+        ]
+    );
+
+    let list = db
+        .update_list(
+            "alice",
+            "example.com",
+            id,
+            None,
+            Some(ListRepliesPolicy::Followed),
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let timeline = db.get_list_timeline(&list, OWNER, &page).await.unwrap();
+    assert_eq!(timeline.len(), 3);
+
+    let newest = timeline[0].id;
+    let older = db
+        .get_list_timeline(
+            &list,
+            OWNER,
+            &TimelinePage {
+                max_id: newest,
+                limit: 1,
+                ..TimelinePage::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(timeline_ids(older), vec!["https://remote.example/notes/3"]);
+
+    assert!(
+        db.remove_list_accounts("alice", "example.com", id, &[bob.to_string()])
+            .await
+            .unwrap()
+    );
+    let list = db
+        .find_list("alice", "example.com", id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.get_list_timeline(&list, OWNER, &page)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Lists of other users are not found
+    assert!(
+        db.find_list("bob", "example.com", id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(db.delete_list("alice", "example.com", id).await.unwrap());
+
+    db.database.drop().await.unwrap();
+}
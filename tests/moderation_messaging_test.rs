@@ -0,0 +1,104 @@
+//! Tests for moderation RPC message serialization
+
+use oxifed::messaging::{
+    Message, MessageEnum, ModerationAction, ModerationRpcRequest, ModerationRpcRequestType,
+    ModerationRpcResponse, ModerationRpcResult, ReportInfo,
+};
+
+#[test]
+fn test_resolve_report_request_serialization() {
+    let request = ModerationRpcRequest::resolve_report(
+        "req-1".to_string(),
+        "report-1".to_string(),
+        ModerationAction::SilenceDomain,
+        "moderator-sub".to_string(),
+        Some("Repeated spam".to_string()),
+    );
+
+    let json = serde_json::to_string(&request.to_message()).unwrap();
+    assert!(json.contains("silence_domain"));
+
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::ModerationRpcRequest(rpc_req) = deserialized {
+        assert_eq!(rpc_req.request_id, "req-1");
+        match rpc_req.request_type {
+            ModerationRpcRequestType::ResolveReport {
+                report_id,
+                action,
+                moderator,
+                note,
+            } => {
+                assert_eq!(report_id, "report-1");
+                assert_eq!(action, ModerationAction::SilenceDomain);
+                assert_eq!(moderator, "moderator-sub");
+                assert_eq!(note, Some("Repeated spam".to_string()));
+            }
+            _ => panic!("Expected ResolveReport request type"),
+        }
+    } else {
+        panic!("Expected ModerationRpcRequest");
+    }
+}
+
+#[test]
+fn test_report_list_response_serialization() {
+    let report = ReportInfo {
+        report_id: "report-1".to_string(),
+        reporter: "https://remote.example/users/bob".to_string(),
+        target_actor: Some("https://example.com/users/alice".to_string()),
+        target_objects: vec!["https://example.com/notes/1".to_string()],
+        content: Some("Spam".to_string()),
+        status: "open".to_string(),
+        action_taken: None,
+        resolved_by: None,
+        resolution_note: None,
+        created_at: "2024-01-01T00:00:00+00:00".to_string(),
+        resolved_at: None,
+    };
+    let response = ModerationRpcResponse::report_list("req-2".to_string(), vec![report]);
+
+    let json = serde_json::to_string(&response.to_message()).unwrap();
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::ModerationRpcResponse(rpc_resp) = deserialized {
+        assert_eq!(rpc_resp.request_id, "req-2");
+        match rpc_resp.result {
+            ModerationRpcResult::ReportList { reports } => {
+                assert_eq!(reports.len(), 1);
+                assert_eq!(reports[0].report_id, "report-1");
+                assert_eq!(reports[0].target_objects.len(), 1);
+            }
+            _ => panic!("Expected ReportList result"),
+        }
+    } else {
+        panic!("Expected ModerationRpcResponse");
+    }
+}
+
+#[test]
+fn test_release_quarantined_request_serialization() {
+    use oxifed::messaging::{SpamFilterRpcRequest, SpamFilterRpcRequestType};
+
+    let request = SpamFilterRpcRequest::release_quarantined(
+        "req-3".to_string(),
+        "quarantine-1".to_string(),
+        "moderator-sub".to_string(),
+    );
+
+    let json = serde_json::to_string(&request.to_message()).unwrap();
+    let deserialized: MessageEnum = serde_json::from_str(&json).unwrap();
+    if let MessageEnum::SpamFilterRpcRequest(rpc_req) = deserialized {
+        assert_eq!(rpc_req.request_id, "req-3");
+        match rpc_req.request_type {
+            SpamFilterRpcRequestType::ReleaseQuarantined {
+                quarantine_id,
+                moderator,
+            } => {
+                assert_eq!(quarantine_id, "quarantine-1");
+                assert_eq!(moderator, "moderator-sub");
+            }
+            _ => panic!("Expected ReleaseQuarantined request type"),
+        }
+    } else {
+        panic!("Expected SpamFilterRpcRequest");
+    }
+}
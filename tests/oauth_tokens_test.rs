@@ -0,0 +1,97 @@
+//! Single use of OAuth authorization codes and refresh tokens
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::DateTime as BsonDateTime;
+use oxifed::credentials::token_hash;
+use oxifed::database::{AccessTokenDocument, AuthorizationCodeDocument, RefreshTokenDocument};
+
+fn expires_in(duration: Duration) -> BsonDateTime {
+    BsonDateTime::from_millis((Utc::now() + duration).timestamp_millis())
+}
+
+#[tokio::test]
+async fn test_codes_and_refresh_tokens_are_single_use() {
+    let Some((database, db)) = common::setup_raw_test_db().await else {
+        return;
+    };
+
+    db.insert_authorization_code(AuthorizationCodeDocument {
+        id: None,
+        code_hash: token_hash("code"),
+        client_id: "client".to_string(),
+        username: "alice".to_string(),
+        domain: "example.com".to_string(),
+        redirect_uri: "https://app.example/callback".to_string(),
+        scopes: vec!["read".to_string()],
+        code_challenge: None,
+        created_at: Utc::now(),
+        expires_at: expires_in(Duration::minutes(10)),
+    })
+    .await
+    .unwrap();
+    assert!(
+        db.take_authorization_code(&token_hash("code"))
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        db.take_authorization_code(&token_hash("code"))
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let access = AccessTokenDocument {
+        id: None,
+        token_hash: token_hash("access"),
+        username: "alice".to_string(),
+        domain: "example.com".to_string(),
+        client_id: "client".to_string(),
+        scopes: vec!["read".to_string()],
+        created_at: Utc::now(),
+        expires_at: expires_in(Duration::hours(2)),
+    };
+    let refresh = RefreshTokenDocument {
+        id: None,
+        token_hash: token_hash("refresh"),
+        access_token_hash: token_hash("access"),
+        username: "alice".to_string(),
+        domain: "example.com".to_string(),
+        client_id: "client".to_string(),
+        scopes: vec!["read".to_string()],
+        created_at: Utc::now(),
+        expires_at: expires_in(Duration::days(30)),
+    };
+    db.insert_tokens(access, refresh).await.unwrap();
+
+    // Another client cannot use the refresh token
+    assert!(
+        !db.revoke_token(&token_hash("refresh"), "other")
+            .await
+            .unwrap()
+    );
+    // Refreshing revokes the pair, so the refresh token works once
+    assert!(
+        db.revoke_token(&token_hash("refresh"), "client")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !db.revoke_token(&token_hash("refresh"), "client")
+            .await
+            .unwrap()
+    );
+    assert!(
+        db.find_access_token(&token_hash("access"))
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    database.drop().await.unwrap();
+}
@@ -0,0 +1,101 @@
+//! Counting of quote posts
+//!
+//! Needs MongoDB at `TEST_MONGODB_URI`; the tests are skipped without it.
+
+mod common;
+
+use chrono::Utc;
+use oxifed::ObjectType;
+use oxifed::database::{DatabaseManager, ObjectDocument, QuoteOutcome};
+use serde_json::json;
+
+const QUOTED: &str = "https://example.com/notes/1";
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Store a public post by Bob quoting [`QUOTED`] if `quote` is set, and
+/// by Alice otherwise
+async fn store_post(db: &DatabaseManager, id: &str, quote: bool) -> ObjectDocument {
+    let author = if quote { BOB } else { ALICE };
+    let mut post = json!({
+        "id": id,
+        "attributedTo": author,
+        "content": "Look at this",
+        "to": [PUBLIC]
+    });
+    if quote {
+        post["quote"] = json!(QUOTED);
+    }
+    let document = ObjectDocument::from_activitypub(&post, ObjectType::Note);
+    db.insert_object(document.clone()).await.unwrap();
+    document
+}
+
+async fn quote_count(db: &DatabaseManager) -> i64 {
+    db.find_object_by_id(QUOTED)
+        .await
+        .unwrap()
+        .unwrap()
+        .quote_count
+}
+
+#[tokio::test]
+async fn test_deleted_quotes_are_uncounted() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    store_post(&db, QUOTED, false).await;
+    let first = store_post(&db, "https://remote.example/notes/1", true).await;
+    let second = store_post(&db, "https://remote.example/notes/2", true).await;
+    for quote in [&first, &second] {
+        assert_eq!(db.record_quote(quote).await.unwrap(), QuoteOutcome::Counted);
+    }
+    assert_eq!(quote_count(&db).await, 2);
+
+    db.delete_object(&first.object_id).await.unwrap();
+    assert_eq!(quote_count(&db).await, 1);
+
+    // Tombstoning counts once, however often it is tried
+    assert!(db.tombstone_object(&second, Utc::now()).await.unwrap());
+    assert!(!db.tombstone_object(&second, Utc::now()).await.unwrap());
+    assert_eq!(quote_count(&db).await, 0);
+
+    // Deleting a post that quotes nothing leaves the count alone
+    let third = store_post(&db, "https://remote.example/notes/3", true).await;
+    db.record_quote(&third).await.unwrap();
+    store_post(&db, "https://remote.example/notes/4", false).await;
+    db.delete_object("https://remote.example/notes/4")
+        .await
+        .unwrap();
+    assert_eq!(quote_count(&db).await, 1);
+
+    db.database.drop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_pruned_and_deleted_accounts_are_uncounted() {
+    let Some(db) = common::setup_test_db().await else {
+        return;
+    };
+    store_post(&db, QUOTED, false).await;
+    let quote = store_post(&db, "https://remote.example/notes/1", true).await;
+    db.record_quote(&quote).await.unwrap();
+    assert_eq!(quote_count(&db).await, 1);
+
+    assert_eq!(
+        db.delete_remote_objects(std::slice::from_ref(&quote.object_id))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(quote_count(&db).await, 0);
+
+    let quote = store_post(&db, "https://remote.example/notes/2", true).await;
+    db.record_quote(&quote).await.unwrap();
+    assert_eq!(quote_count(&db).await, 1);
+    db.delete_actor(BOB).await.unwrap();
+    assert_eq!(quote_count(&db).await, 0);
+
+    db.database.drop().await.unwrap();
+}
@@ -4,8 +4,11 @@
 //! when wrapped in MessageEnum, which was the source of the parsing error.
 
 use oxifed::messaging::{
-    DomainInfo, DomainRpcRequest, DomainRpcRequestType, DomainRpcResponse, DomainRpcResult,
-    Message, MessageEnum,
+    CommandErrorKind, CommandResponse, CommandResult, DomainInfo, DomainRpcRequest,
+    DomainRpcRequestType, DomainRpcResponse, DomainRpcResult, FollowCounts, FollowDirection,
+    FollowInfo, FollowPage, FollowRpcRequest, FollowRpcRequestType, FollowRpcResponse,
+    FollowRpcResult, Message, MessageEnum, NoteCreateMessage, NoteRpcRequest, NoteRpcRequestType,
+    NoteRpcResponse, NoteRpcResult, ScheduledNoteInfo,
 };
 use uuid::Uuid;
 
@@ -164,3 +167,169 @@ fn test_domain_details_not_found_serialization() {
         panic!("Expected DomainRpcResponse in MessageEnum");
     }
 }
+
+#[test]
+fn test_scheduled_note_serialization() {
+    let at = "2030-01-01T12:00:00Z".parse().unwrap();
+    let message = NoteCreateMessage::new(
+        "alice@example.com".to_string(),
+        "Happy new year".to_string(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_scheduled_at(at);
+
+    let json = serde_json::to_value(message.to_message()).unwrap();
+    assert_eq!(
+        json["NoteCreateMessage"]["scheduled_at"],
+        "2030-01-01T12:00:00Z"
+    );
+    let MessageEnum::NoteCreateMessage(parsed) = serde_json::from_value(json).unwrap() else {
+        panic!("Expected NoteCreateMessage in MessageEnum");
+    };
+    assert_eq!(parsed.scheduled_at, Some(at));
+
+    // Notes without a schedule are published immediately
+    let json = r#"{"NoteCreateMessage":{"author":"alice@example.com","content":"Hi"}}"#;
+    let MessageEnum::NoteCreateMessage(parsed) = serde_json::from_str(json).unwrap() else {
+        panic!("Expected NoteCreateMessage in MessageEnum");
+    };
+    assert!(parsed.scheduled_at.is_none());
+}
+
+#[test]
+fn test_note_rpc_serialization() {
+    let request_id = Uuid::new_v4().to_string();
+    let request = NoteRpcRequest::cancel_scheduled(
+        request_id.clone(),
+        "https://example.com/u/alice/notes/1".to_string(),
+    );
+    let json_data = serde_json::to_vec(&request.to_message()).unwrap();
+    let MessageEnum::NoteRpcRequest(parsed) = serde_json::from_slice(&json_data).unwrap() else {
+        panic!("Expected NoteRpcRequest in MessageEnum");
+    };
+    assert_eq!(parsed.request_id, request_id);
+    assert!(matches!(
+        parsed.request_type,
+        NoteRpcRequestType::CancelScheduled { object_id } if object_id.ends_with("/notes/1")
+    ));
+
+    let response = NoteRpcResponse::scheduled_list(
+        request_id.clone(),
+        vec![ScheduledNoteInfo {
+            object_id: "https://example.com/u/alice/notes/1".to_string(),
+            object_type: "Note".to_string(),
+            author: "https://example.com/users/alice".to_string(),
+            content: Some("Happy new year".to_string()),
+            scheduled_at: "2030-01-01T12:00:00+00:00".to_string(),
+        }],
+    );
+    let json_data = serde_json::to_vec(&response.to_message()).unwrap();
+    let MessageEnum::NoteRpcResponse(parsed) = serde_json::from_slice(&json_data).unwrap() else {
+        panic!("Expected NoteRpcResponse in MessageEnum");
+    };
+    let NoteRpcResult::ScheduledList { notes } = parsed.result else {
+        panic!("Expected ScheduledList result");
+    };
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].author, "https://example.com/users/alice");
+}
+
+#[test]
+fn test_command_response_serialization() {
+    let request_id = Uuid::new_v4().to_string();
+    let response = CommandResponse::done(
+        request_id.clone(),
+        Some("https://example.com/users/alice".to_string()),
+    );
+    let json_data = serde_json::to_vec(&response.to_message()).unwrap();
+    let MessageEnum::CommandResponse(parsed) = serde_json::from_slice(&json_data).unwrap() else {
+        panic!("Expected CommandResponse in MessageEnum");
+    };
+    assert_eq!(parsed.request_id, request_id);
+    assert!(matches!(
+        parsed.result,
+        CommandResult::Done { id: Some(id) } if id == "https://example.com/users/alice"
+    ));
+
+    let response = CommandResponse::error(
+        request_id,
+        CommandErrorKind::NotFound,
+        "Domain not found: example.com".to_string(),
+    );
+    let json = serde_json::to_value(response.to_message()).unwrap();
+    assert_eq!(
+        json["CommandResponse"]["result"]["Error"]["kind"],
+        "not_found"
+    );
+    let MessageEnum::CommandResponse(parsed) = serde_json::from_value(json).unwrap() else {
+        panic!("Expected CommandResponse in MessageEnum");
+    };
+    assert!(matches!(
+        parsed.result,
+        CommandResult::Error {
+            kind: CommandErrorKind::NotFound,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_follow_page_serialization() {
+    let request_id = Uuid::new_v4().to_string();
+    let request = FollowRpcRequest::follow_page(
+        request_id.clone(),
+        "https://example.com/users/alice".to_string(),
+        FollowDirection::Followers,
+        Some("pending".to_string()),
+        None,
+        50,
+    );
+    let json = serde_json::to_value(request.to_message()).unwrap();
+    assert_eq!(
+        json["FollowRpcRequest"]["request_type"]["FollowPage"]["direction"],
+        "followers"
+    );
+    let MessageEnum::FollowRpcRequest(parsed) = serde_json::from_value(json).unwrap() else {
+        panic!("Expected FollowRpcRequest in MessageEnum");
+    };
+    assert!(matches!(
+        parsed.request_type,
+        FollowRpcRequestType::FollowPage {
+            direction: FollowDirection::Followers,
+            limit: 50,
+            ..
+        }
+    ));
+
+    let page = FollowPage {
+        items: vec![FollowInfo {
+            follower: "https://remote.example/users/bob".to_string(),
+            following: "https://example.com/users/alice".to_string(),
+            status: "pending".to_string(),
+            activity_id: "https://remote.example/follows/1".to_string(),
+            created_at: "2030-01-01T12:00:00+00:00".to_string(),
+            responded_at: None,
+        }],
+        counts: FollowCounts {
+            total: 3,
+            accepted: 2,
+            pending: 1,
+            ..Default::default()
+        },
+        next: None,
+    };
+    let response = FollowRpcResponse::follow_page(request_id, page);
+    let json_data = serde_json::to_vec(&response.to_message()).unwrap();
+    let MessageEnum::FollowRpcResponse(parsed) = serde_json::from_slice(&json_data).unwrap() else {
+        panic!("Expected FollowRpcResponse in MessageEnum");
+    };
+    let FollowRpcResult::FollowPage { page } = parsed.result else {
+        panic!("Expected FollowPage result");
+    };
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.counts.total, 3);
+    assert!(page.next.is_none());
+}
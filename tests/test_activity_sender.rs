@@ -1,25 +1,12 @@
 //! Tests for the Activity Sender Component (C2S API)
 
-use axum::http::{HeaderMap, HeaderValue};
 use futures::TryStreamExt;
 use mongodb::bson::doc;
-use oxifed::database::{ActivityDocument, ActorDocument, ActorStatus};
+use oxifed::database::{ActivityDocument, ActorDocument, ActorRestriction, ActorStatus};
 use serde_json::json;
 
 use uuid::Uuid;
 
-/// Test helper to create an authenticated request header
-#[allow(dead_code)]
-fn create_auth_headers(username: &str) -> HeaderMap {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("Bearer user:{}:token:test123", username)).unwrap(),
-    );
-    headers.insert("Content-Type", HeaderValue::from_static("application/json"));
-    headers
-}
-
 /// Test helper to setup test database
 async fn setup_test_db() -> Option<mongodb::Database> {
     let mongo_uri = std::env::var("TEST_MONGODB_URI")
@@ -58,6 +45,7 @@ async fn setup_test_db() -> Option<mongodb::Database> {
         attachment: None,
         additional_properties: None,
         status: ActorStatus::Active,
+        restriction: ActorRestriction::None,
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         local: true,